@@ -0,0 +1,662 @@
+// src/main.rs
+//! Operator command line for a local Gladix agent.
+//!
+//! ```text
+//! gladix-cli [--config <path>] config fingerprint [--export <file>]
+//! gladix-cli [--config <path>] config diff <other-export.json>
+//! gladix-cli [--config <path>] reprocess <enrichment> [--since <time>]
+//! gladix-cli [--config <path>] reprocess status
+//! gladix-cli [--config <path>] snapshots list
+//! gladix-cli [--config <path>] snapshots restore-info <name>
+//! gladix-cli perfcounters install|uninstall
+//! gladix-cli [--config <path>] schema [<event type>] [--json]
+//! gladix-cli metrics dump
+//! gladix-cli tap
+//! gladix-cli [--config <path>] support-bundle <dir>
+//! gladix-cli [--config <path>] setup [--profile <file> [--apply]]
+//! gladix-cli quarantine list
+//! gladix-cli quarantine export <id> [--out <dir>] [--key <file>]
+//! gladix-cli [--config <path>] quarantine restore <id>
+//! gladix-cli [--config <path>] journal [--since <time>]
+//! gladix-cli [--config <path>] alerts show <id>
+//! gladix-cli [--config <path>] query processes|files|net [<filter>...] [--format table|json]
+//! gladix-cli [--config <path>] query tree --pid <n> [--at <time>] [--depth <n>] [--format table|json]
+//! gladix-cli --features-help
+//! ```
+//!
+//! `--config` defaults to `config.toml` next to the executable, the same file
+//! the service loads. `reprocess` only queues jobs in the agent database; the
+//! running service picks them up.
+
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use anyhow::{bail, Context, Result};
+
+use agent::{
+    actions::quarantine::{self, Dpapi, Quarantine, WrapKey},
+    comms::{
+        schema::{describe_schema, render_text, to_json},
+        tap::{self, TapClient},
+    },
+    config::{
+        canonical::{canonicalize, diff, render_diff, ConfigExport},
+        load,
+        provision::{self, Host, Plan, Profile, SystemHost, LOCAL_GRPC, REMOTE_GRPC},
+        Config,
+    },
+    db::{
+        connection::{db_path, open_db_connection},
+        quarantine::mark_restored,
+        reprocess::{backfill, enqueue, jobs, migrate, parse_since, BACKFILLS},
+        ops_journal::{self, Actor},
+        query::{self, FileQuery, NetQuery, ProcessQuery, Tabular, TreeQuery},
+        snapshots::{self, snapshot_root},
+    },
+    features::features_help,
+    intel::{load_alert, render_context},
+    metrics_history::{self, MetricsHistory},
+    perfcounters,
+};
+
+const USAGE: &str = "\
+usage: gladix-cli [--config <path>] <command>
+
+commands:
+  config fingerprint [--export <file>]   hash of the effective config, per section
+  config diff <other-export.json>        compare with a config exported on another host
+  reprocess <enrichment> [--since <t>]   re-run an enrichment over stored rows;
+                                         <t> is RFC 3339 or a duration ago (7d)
+  reprocess status                       progress of reprocessing jobs
+  snapshots list                         safety exports taken before risky operations
+  snapshots restore-info <name>          contents and checksums of one snapshot
+  perfcounters install|uninstall         register or remove the agent's counter sets
+                                         (elevated; the agent must be in this directory)
+  schema [<event type>] [--json]         event fields, enums, DB columns and
+                                         config-dependent storage
+  metrics dump                           latest metrics snapshot of the agent,
+                                         also when its HTTP listener is off
+  tap                                    follow the agent's events as they arrive
+                                         (needs [communications] tap = true)
+  support-bundle <dir>                   config, logs, crash report and recent
+                                         metrics history for support
+  setup                                  first-run wizard: writes the config,
+                                         sensor GUID and baseline signature
+  setup --profile <file> [--apply]       the same from a .toml or .json profile;
+                                         shows the changes, --apply makes them
+  quarantine list                        quarantined files, oldest first
+  quarantine export <id> [--out <dir>] [--key <file>]
+                                         verified copy of a container plus its
+                                         metadata JSON; --key re-wraps it under
+                                         a 32-byte hex transport key
+  quarantine restore <id>                put the file back where it was; refused
+                                         if a different file is there now
+  journal [--since <t>]                  config applies, watchdog restarts and
+                                         writer pressure (default: last 24h),
+                                         then the event volume drops after them
+  alerts show <id>                       one alert and the events around it,
+                                         oldest first
+  query processes [--image <s>] [--pid <n>] [--user <sid>] [--elevated true|false]
+  query files [--path-contains <s>] [--op <op>] [--pid <n>]
+  query net [--dst-port <n>] [--dst-ip <ip>] [--pid <n>]
+                                         stored events, newest first; all take
+                                         --since <t>, --limit <n> (default 100)
+                                         and --format table|json
+  query tree --pid <n> [--at <t>] [--depth <n>]
+                                         ancestors of the process the pid named
+                                         at --at (default: now), then what it
+                                         started, --depth levels (default 8);
+                                         takes --format table|json
+  --features-help                        cargo features this binary was built with";
+
+fn exe_dir() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn quarantine_store() -> Quarantine {
+    Quarantine::new(exe_dir().join(quarantine::DIR), std::sync::Arc::new(Dpapi))
+}
+
+/// Reads a transport key: 64 hex digits, surrounding whitespace ignored.
+fn transport_key(path: &Path) -> Result<WrapKey> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let bytes = hex::decode(text.trim()).with_context(|| format!("{}: not hex", path.display()))?;
+    WrapKey::from_bytes(&bytes).with_context(|| format!("{}: expected 32 bytes, got {}", path.display(), bytes.len()))
+}
+
+fn load_config(path: &Option<PathBuf>) -> Result<Config> {
+    let path = path.clone().unwrap_or_else(|| exe_dir().join("config.toml"));
+    load(&path).with_context(|| format!("loading {}", path.display()))
+}
+
+fn snapshots_dir(path: &Option<PathBuf>) -> Result<PathBuf> {
+    let cfg = load_config(path)?;
+    Ok(snapshot_root(&exe_dir(), &cfg.database.snapshots))
+}
+
+fn open_db(path: &Option<PathBuf>) -> Result<rusqlite::Connection> {
+    let cfg = load_config(path)?;
+    let path = db_path(&exe_dir(), &cfg.database);
+    let conn = open_db_connection(&path, &cfg.database)
+        .with_context(|| format!("opening {}", path.display()))?;
+    migrate(&conn).context("reprocess_jobs")?;
+    Ok(conn)
+}
+
+/// The telemetry database the service writes, opened read-only.
+fn open_telemetry(path: &Option<PathBuf>) -> Result<rusqlite::Connection> {
+    let cfg = load_config(path)?;
+    let path = db_path(&exe_dir(), &cfg.database);
+    if !path.is_file() {
+        bail!("{} does not exist (has the agent run yet?)", path.display());
+    }
+    query::open_read_only(&path).with_context(|| format!("opening {}", path.display()))
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T> {
+    value.parse().ok().with_context(|| format!("{flag} {value}: expected a number"))
+}
+
+fn render_rows<R: Tabular>(rows: &[R], json: bool) -> Result<String> {
+    if json {
+        return Ok(serde_json::to_string_pretty(rows)? + "\n");
+    }
+    Ok(query::render_table(rows))
+}
+
+/// Copies what support needs into `out`; returns the files written.
+fn support_bundle(config_path: &Option<PathBuf>, out: &Path) -> Result<Vec<PathBuf>> {
+    let config_file = config_path.clone().unwrap_or_else(|| exe_dir().join("config.toml"));
+    let cfg = load_config(config_path)?;
+    fs::create_dir_all(out).with_context(|| format!("creating {}", out.display()))?;
+
+    let mut written = Vec::new();
+    let log_file = exe_dir().join(cfg.logging.file.as_deref().unwrap_or("agent.log"));
+    let crash = exe_dir().join("crash_report.json");
+    for src in [&config_file, &log_file, &crash] {
+        let Some(name) = src.file_name() else { continue };
+        if src.is_file() {
+            let dst = out.join(name);
+            fs::copy(src, &dst).with_context(|| format!("copying {}", src.display()))?;
+            written.push(dst);
+        }
+    }
+
+    let history_dir = exe_dir().join(metrics_history::DIR);
+    let history = MetricsHistory::load_dir(&history_dir, &cfg.metrics.history)
+        .with_context(|| format!("reading {}", history_dir.display()))?;
+    let h = &cfg.metrics.history;
+    written.extend(history.write_bundle(&out.join("metrics"), h.bundle_files, &h.csv_series)?);
+    Ok(written)
+}
+
+fn config_file(path: &Option<PathBuf>) -> PathBuf {
+    path.clone().unwrap_or_else(|| exe_dir().join("config.toml"))
+}
+
+/// One line from stdin, trimmed.
+fn answer(prompt: &str) -> Result<String> {
+    print!("{prompt}: ");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        bail!("setup aborted");
+    }
+    Ok(line.trim().to_owned())
+}
+
+/// Asks `question`; an empty answer keeps `default`.
+fn ask(question: &str, default: &str) -> Result<String> {
+    let line = answer(&format!("{question} [{default}]"))?;
+    Ok(if line.is_empty() { default.to_owned() } else { line })
+}
+
+fn confirm(question: &str, default: bool) -> Result<bool> {
+    let hint = if default { "Y/n" } else { "y/N" };
+    loop {
+        match answer(&format!("{question} [{hint}]"))?.to_lowercase().as_str() {
+            ""          => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no"  => return Ok(false),
+            _           => println!("answer y or n"),
+        }
+    }
+}
+
+/// The interactive front end of `setup`: a profile built from prompts.
+fn wizard(host: &dyn Host, config_dir: &Path) -> Result<Profile> {
+    let mut profile = provision::suggested(host);
+    println!("Directories are separated by ';'. Press Enter to keep the suggestion.");
+    for group in &mut profile.scanner {
+        let answer = ask(&format!("{} risk directories", group.risk), &group.directories.join(";"))?;
+        group.directories = answer.split(';').map(str::trim).filter(|d| !d.is_empty()).map(String::from).collect();
+    }
+    profile.scanner.retain(|g| !g.directories.is_empty());
+
+    loop {
+        profile.database = ask("Database file", &profile.database)?;
+        let db = provision::database_path(config_dir, &profile);
+        match provision::check_free_space(&db, profile.min_free_mb, host) {
+            Ok(()) => break,
+            Err(e) => println!("{e}"),
+        }
+    }
+    profile.metrics_listen = confirm("Serve Prometheus metrics over HTTP?", profile.metrics_listen)?;
+    let remote = confirm("Accept gRPC connections from other hosts?", profile.grpc_bind != LOCAL_GRPC)?;
+    profile.grpc_bind = if remote { REMOTE_GRPC } else { LOCAL_GRPC }.into();
+    profile.prevention = confirm("Enable prevention (response actions)?", profile.prevention)?;
+    profile.baseline = confirm("Record a baseline signature of the config?", true)?;
+    Ok(profile)
+}
+
+fn apply_plan(config_path: &Option<PathBuf>, plan: &Plan) -> Result<()> {
+    provision::apply(plan).context("applying setup")?;
+    print!("{}", plan.report());
+    println!("sensor GUID {}", plan.sensor_guid);
+    journal_config(config_path);
+    Ok(())
+}
+
+/// Records the config just written in the ops journal. A database the
+/// service has not created yet is left alone; the service records the
+/// config itself when it starts.
+fn journal_config(config_path: &Option<PathBuf>) {
+    let recorded = load_config(config_path).and_then(|cfg| {
+        let path = db_path(&exe_dir(), &cfg.database);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let conn = open_db_connection(&path, &cfg.database)?;
+        Ok(ops_journal::record_config(&conn, &canonicalize(&cfg), Actor::Cli)?)
+    });
+    match recorded {
+        Ok(Some(id)) => println!("recorded in the ops journal as {id}"),
+        Ok(None)     => {}
+        Err(e)       => eprintln!("warning: cannot record the change in the ops journal: {e:#}"),
+    }
+}
+
+/// Window around journal entries in which a volume drop is attributed to them.
+const JOURNAL_LOOKBACK: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+/// Fraction by which the event rate must fall to count as a drop.
+const JOURNAL_MIN_DROP: f64 = 0.5;
+
+fn run(mut args: Vec<String>) -> Result<ExitCode> {
+    let mut config_path = None;
+    if let Some(i) = args.iter().position(|a| a == "--config") {
+        if i + 1 >= args.len() {
+            bail!("--config needs a path");
+        }
+        config_path = Some(PathBuf::from(args.remove(i + 1)));
+        args.remove(i);
+    }
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["--features-help"] => {
+            print!("{}", features_help());
+            Ok(ExitCode::SUCCESS)
+        }
+        ["config", "fingerprint", rest @ ..] => {
+            let export = ConfigExport::new(&load_config(&config_path)?);
+            print!("{}", export.fingerprint);
+            match rest {
+                [] => {}
+                ["--export", file] => {
+                    fs::write(file, serde_json::to_string_pretty(&export)?)
+                        .with_context(|| format!("writing {file}"))?;
+                    println!("exported to {file}");
+                }
+                _ => bail!("{USAGE}"),
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        ["config", "diff", other] => {
+            let local = canonicalize(&load_config(&config_path)?);
+            let text = fs::read_to_string(other).with_context(|| format!("reading {other}"))?;
+            let other: ConfigExport = serde_json::from_str(&text).context("parsing export")?;
+            let diffs = diff(&local, &other.config);
+            if diffs.is_empty() {
+                println!("configs match");
+                return Ok(ExitCode::SUCCESS);
+            }
+            print!("{}", render_diff(&diffs));
+            // Like diff(1): 1 means differences were found.
+            Ok(ExitCode::from(1))
+        }
+        ["reprocess", "status"] => {
+            println!("{:<4} {:<20} {:<8} {:>10} {:>10} {:>10}  error", "id", "enrichment", "state", "cursor", "processed", "updated");
+            for job in jobs(&open_db(&config_path)?)? {
+                println!(
+                    "{:<4} {:<20} {:<8} {:>10} {:>10} {:>10}  {}",
+                    job.id, job.enrichment, job.state.as_str(), job.cursor,
+                    job.processed, job.updated, job.error.as_deref().unwrap_or("-"),
+                );
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        ["reprocess", name, rest @ ..] => {
+            let Some(backfill) = backfill(name) else {
+                let known: Vec<_> = BACKFILLS.iter().map(|b| b.name).collect();
+                bail!("unknown enrichment {name}; known: {}", known.join(", "));
+            };
+            let since = match rest {
+                [] => None,
+                ["--since", t] => Some(parse_since(t, chrono::Utc::now())
+                    .with_context(|| format!("--since {t}: expected RFC 3339 or a duration"))?),
+                _ => bail!("{USAGE}"),
+            };
+            let id = enqueue(&open_db(&config_path)?, backfill, since)?;
+            println!("queued job {id} ({name})");
+            Ok(ExitCode::SUCCESS)
+        }
+        ["snapshots", "list"] => {
+            let root = snapshots_dir(&config_path)?;
+            let list = snapshots::list(&root).with_context(|| format!("reading {}", root.display()))?;
+            if list.is_empty() {
+                println!("no snapshots in {}", root.display());
+            }
+            for m in list {
+                let created = chrono::DateTime::from_timestamp_micros(m.created_at).unwrap_or_default();
+                println!(
+                    "{}  {:<18} {}  {} rows, {} bytes{}",
+                    m.name, m.operation, created.to_rfc3339(), m.rows(), m.bytes(),
+                    if m.truncated { " (truncated)" } else { "" },
+                );
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        ["snapshots", "restore-info", name] => {
+            let dir = snapshots_dir(&config_path)?.join(name);
+            let m = snapshots::read_manifest(&dir).with_context(|| format!("reading {}", dir.display()))?;
+            let since = chrono::DateTime::from_timestamp_micros(m.since).unwrap_or_default();
+            println!("snapshot   {}", dir.display());
+            println!("operation  {}", m.operation);
+            println!("source     {}", m.source.display());
+            println!("agent      {} (SQLite {})", m.agent_version, m.sqlite_version);
+            println!("window     since {}{}", since.to_rfc3339(), if m.truncated { ", cut by max_mb" } else { "" });
+            for f in &m.files {
+                println!("  {:<16} {:>8} rows {:>12} bytes  sha256 {}", f.file, f.rows, f.bytes, f.sha256);
+            }
+            println!("Rows are JSON lines, newest first; compressed text is stored decoded.");
+            println!("Stop the agent before importing them into {}.", m.source.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        ["perfcounters", "install"] => {
+            perfcounters::install(&exe_dir()).context("registering performance counters")?;
+            println!("performance counters registered");
+            Ok(ExitCode::SUCCESS)
+        }
+        ["perfcounters", "uninstall"] => {
+            perfcounters::uninstall(&exe_dir()).context("removing performance counters")?;
+            println!("performance counters removed");
+            Ok(ExitCode::SUCCESS)
+        }
+        ["schema", rest @ ..] => {
+            let (json, rest): (bool, Vec<&str>) = match rest.iter().position(|a| *a == "--json") {
+                Some(i) => (true, [&rest[..i], &rest[i + 1..]].concat()),
+                None    => (false, rest.to_vec()),
+            };
+            let only = match rest.as_slice() {
+                [] => None,
+                [name] => Some(*name),
+                _ => bail!("{USAGE}"),
+            };
+            let schema = describe_schema(&load_config(&config_path)?.database, only)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&to_json(&schema))?);
+            } else {
+                print!("{}", render_text(&schema));
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        ["metrics", "dump"] => {
+            let latest = exe_dir().join(metrics_history::DIR).join(metrics_history::LATEST);
+            let text = fs::read_to_string(&latest).with_context(|| {
+                format!("reading {} (is [metrics.history] enabled and the agent running?)", latest.display())
+            })?;
+            if let Some(age) = fs::metadata(&latest).and_then(|m| m.modified()).ok().and_then(|t| t.elapsed().ok()) {
+                eprintln!("snapshot taken {}s ago", age.as_secs());
+            }
+            print!("{text}");
+            Ok(ExitCode::SUCCESS)
+        }
+        ["tap"] => {
+            let mut client = TapClient::connect(tap::PIPE_NAME)
+                .with_context(|| format!("connecting to {} (is [communications] tap enabled?)", tap::PIPE_NAME))?;
+            let mut out = io::stdout().lock();
+            while let Some(ev) = client.recv()? {
+                let ts = ev.ts.and_then(|t| chrono::DateTime::from_timestamp(t.seconds, t.nanos as u32)).unwrap_or_default();
+                let Some(payload) = ev.payload else { continue };
+                // Stop quietly once the reader is gone (`| head`).
+                if writeln!(out, "{}  {payload:?}", ts.to_rfc3339()).is_err() {
+                    break;
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        ["support-bundle", dir] => {
+            let files = support_bundle(&config_path, Path::new(dir))?;
+            for f in &files {
+                println!("{}", f.display());
+            }
+            println!("{} files written to {dir}", files.len());
+            Ok(ExitCode::SUCCESS)
+        }
+        ["quarantine", "list"] => {
+            let store = quarantine_store();
+            let list = store.list().with_context(|| format!("reading {}", store.dir().display()))?;
+            if list.is_empty() {
+                println!("nothing quarantined in {}", store.dir().display());
+            }
+            for h in list {
+                let at = chrono::DateTime::from_timestamp_micros(h.quarantined_at).unwrap_or_default();
+                let alert = h.alert_id.map_or_else(|| "-".into(), |a| a.to_string());
+                println!("{}  {}  alert {:<6} {:>10} bytes  {}", h.id, at.to_rfc3339(), alert, h.size, h.original_path);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        ["quarantine", "export", id, rest @ ..] => {
+            let (mut out, mut key) = (PathBuf::from("."), None);
+            for pair in rest.chunks(2) {
+                match pair {
+                    ["--out", dir] => out = PathBuf::from(dir),
+                    ["--key", file] => key = Some(transport_key(Path::new(file))?),
+                    _ => bail!("{USAGE}"),
+                }
+            }
+            let export = quarantine_store().export(id, &out, key.as_ref())?;
+            println!("{}", export.container.display());
+            println!("{}", export.metadata.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        ["quarantine", "restore", id] => {
+            let target = quarantine_store().restore(id)?;
+            println!("restored {}", target.display());
+            let ts = chrono::Utc::now().timestamp_micros();
+            if let Err(e) = open_db(&config_path).and_then(|conn| Ok(mark_restored(&conn, id, ts)?)) {
+                eprintln!("warning: quarantine table not updated: {e:#}");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        ["journal", rest @ ..] => {
+            let since = match rest {
+                [] => chrono::Utc::now().timestamp_micros() - 24 * 3600 * 1_000_000,
+                ["--since", t] => parse_since(t, chrono::Utc::now())
+                    .with_context(|| format!("--since {t}: expected RFC 3339 or a duration"))?,
+                _ => bail!("{USAGE}"),
+            };
+            let entries = ops_journal::since(&open_db(&config_path)?, since)?;
+            if entries.is_empty() {
+                println!("no journal entries since {}", chrono::DateTime::from_timestamp_micros(since).unwrap_or_default().to_rfc3339());
+            }
+            for e in &entries {
+                println!("{e}");
+            }
+
+            // Without metrics history there is nothing to overlay.
+            let cfg = load_config(&config_path)?;
+            let history = MetricsHistory::load_dir(&exe_dir().join(metrics_history::DIR), &cfg.metrics.history)
+                .map(|h| h.snapshots())
+                .unwrap_or_default();
+            let window: Vec<_> = history.into_iter().filter(|s| s.ts.timestamp_micros() >= since).collect();
+            let drops = ops_journal::volume_drops(&window, &entries, JOURNAL_MIN_DROP, JOURNAL_LOOKBACK);
+            if !drops.is_empty() {
+                println!();
+            }
+            for d in drops {
+                println!("{d}");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        ["alerts", "show", id] => {
+            let id = number("alert id", id)?;
+            let conn = open_db(&config_path)?;
+            let Some(alert) = load_alert(&conn, id)? else { bail!("no alert {id}") };
+            let at = chrono::DateTime::from_timestamp_micros(alert.ts).unwrap_or_default();
+            println!("alert {id}  {}  {}  {}", at.to_rfc3339(), alert.severity, alert.rule_id);
+            let ppid = alert.ppid.map_or_else(|| "-".into(), |p| p.to_string());
+            println!("pid {} ppid {}  {}", alert.pid, ppid, alert.message);
+            let lines = render_context(&conn, id)?;
+            if lines.is_empty() {
+                println!("no context captured");
+            }
+            for line in lines {
+                println!("{line}");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        ["query", kind, rest @ ..] => {
+            let (mut since, mut limit, mut json) = (None, None, false);
+            let mut filters = Vec::new();
+            for pair in rest.chunks(2) {
+                match pair {
+                    ["--since", t] => since = Some(parse_since(t, chrono::Utc::now())
+                        .with_context(|| format!("--since {t}: expected RFC 3339 or a duration"))?),
+                    ["--limit", n] => limit = Some(number("--limit", n)?),
+                    ["--format", "table"] => json = false,
+                    ["--format", "json"] => json = true,
+                    [flag, value] => filters.push((*flag, *value)),
+                    _ => bail!("{USAGE}"),
+                }
+            }
+            let conn = open_telemetry(&config_path)?;
+            let out = match *kind {
+                "processes" => {
+                    let mut q = ProcessQuery { since, limit, ..Default::default() };
+                    for (flag, value) in filters {
+                        match flag {
+                            "--image" => q.image = Some(value.into()),
+                            "--pid" => q.pid = Some(number(flag, value)?),
+                            "--user" => q.user = Some(value.into()),
+                            "--elevated" => q.elevated = Some(value.parse().ok()
+                                .with_context(|| format!("--elevated {value}: expected true or false"))?),
+                            _ => bail!("{USAGE}"),
+                        }
+                    }
+                    render_rows(&query::processes(&conn, &q)?, json)?
+                }
+                "files" => {
+                    let mut q = FileQuery { since, limit, ..Default::default() };
+                    for (flag, value) in filters {
+                        match flag {
+                            "--path-contains" => q.path_contains = Some(value.into()),
+                            "--op" => q.op = Some(query::parse_op(value)
+                                .with_context(|| format!("--op {value}: expected Create, Write, Delete or Rename"))?),
+                            "--pid" => q.pid = Some(number(flag, value)?),
+                            _ => bail!("{USAGE}"),
+                        }
+                    }
+                    render_rows(&query::files(&conn, &q)?, json)?
+                }
+                "tree" => {
+                    if since.is_some() || limit.is_some() {
+                        bail!("{USAGE}");
+                    }
+                    let mut q = TreeQuery::default();
+                    let mut pid = None;
+                    for (flag, value) in filters {
+                        match flag {
+                            "--pid" => pid = Some(number(flag, value)?),
+                            "--at" => q.at = Some(parse_since(value, chrono::Utc::now())
+                                .with_context(|| format!("--at {value}: expected RFC 3339 or a duration"))?),
+                            "--depth" => q.depth = Some(number(flag, value)?),
+                            _ => bail!("{USAGE}"),
+                        }
+                    }
+                    let Some(pid) = pid else { bail!("{USAGE}") };
+                    q.pid = pid;
+                    render_rows(&query::tree(&conn, &q)?, json)?
+                }
+                "net" => {
+                    let mut q = NetQuery { since, limit, ..Default::default() };
+                    for (flag, value) in filters {
+                        match flag {
+                            "--dst-port" => q.dst_port = Some(number(flag, value)?),
+                            "--dst-ip" => q.dst_ip = Some(value.into()),
+                            "--pid" => q.pid = Some(number(flag, value)?),
+                            _ => bail!("{USAGE}"),
+                        }
+                    }
+                    render_rows(&query::network(&conn, &q)?, json)?
+                }
+                _ => bail!("{USAGE}"),
+            };
+            // Stop quietly once the reader is gone (`| head`).
+            let _ = io::stdout().lock().write_all(out.as_bytes());
+            Ok(ExitCode::SUCCESS)
+        }
+        ["setup"] => {
+            let config = config_file(&config_path);
+            let dir = config.parent().unwrap_or(Path::new(".")).to_owned();
+            let profile = wizard(&SystemHost, &dir)?;
+            let plan = provision::plan(&profile, &config, &SystemHost)?;
+            if plan.is_noop() {
+                println!("nothing to change");
+                return Ok(ExitCode::SUCCESS);
+            }
+            print!("{}", plan.report());
+            if confirm("Apply these changes?", true)? {
+                apply_plan(&config_path, &plan)?;
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        ["setup", "--profile", file, rest @ ..] => {
+            let apply = match rest {
+                [] => false,
+                ["--apply"] => true,
+                _ => bail!("{USAGE}"),
+            };
+            let profile = Profile::read(Path::new(file))?;
+            let plan = provision::plan(&profile, &config_file(&config_path), &SystemHost)?;
+            if plan.is_noop() {
+                println!("nothing to change");
+                return Ok(ExitCode::SUCCESS);
+            }
+            if !apply {
+                print!("{}", plan.report());
+                // Like `config diff`: 1 means changes are pending.
+                return Ok(ExitCode::from(1));
+            }
+            apply_plan(&config_path, &plan)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        _ => bail!("{USAGE}"),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{e:#}");
+            ExitCode::from(2)
+        }
+    }
+}
@@ -8,5 +8,16 @@
 //! driver binary (ex. linker flags)
 
 fn main() -> Result<(), wdk_build::ConfigError> {
+    // ObRegisterCallbacks and PsSetCreateProcessNotifyRoutineEx refuse images
+    // without the integrity check flag.
+    println!("cargo:rustc-cdylib-link-arg=/INTEGRITYCHECK");
+    // Reported by IOCTL_GLADIX_GET_VERSION; SOURCE_DATE_EPOCH keeps builds
+    // reproducible.
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    let build_time = std::env::var("SOURCE_DATE_EPOCH").ok().unwrap_or_else(|| {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+        now.map_or(0, |d| d.as_secs()).to_string()
+    });
+    println!("cargo:rustc-env=GLADIX_BUILD_TIME={build_time}");
     wdk_build::configure_wdk_binary_build()
 }
\ No newline at end of file
@@ -0,0 +1,56 @@
+//! `ImageLoadEvent` frames as the user-agent reads them from the image ring.
+//!
+//! Encoded with `crate::frame`, following `ImageLoadEvent` in
+//! `shared/proto/events.proto`. Only `core` is used, so
+//! `tests/image_event.rs` can include this file directly.
+
+use crate::consts::ring_frame_len;
+use crate::frame::{len_tag, utf16_field_len, varint_field_len, varint_tag, write_frame};
+
+/// What a load-image notification reports.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageLoadEvent<'a> {
+    /// Process the image is mapped into; 0 for kernel modules.
+    pub pid:              u32,
+    pub image_base:       u64,
+    pub image_size:       u64,
+    /// `FullImageName` as passed to the routine, UTF-16 without terminator.
+    /// Unpaired surrogates become U+FFFD.
+    pub full_image_name:  &'a [u16],
+    pub is_kernel_module: bool,
+}
+
+const PID: u8 = varint_tag(1);
+const IMAGE_BASE: u8 = varint_tag(2);
+const IMAGE_SIZE: u8 = varint_tag(3);
+const FULL_IMAGE_NAME: u8 = len_tag(4);
+const IS_KERNEL_MODULE: u8 = varint_tag(5);
+
+impl ImageLoadEvent<'_> {
+    /// Size of the protobuf encoding.
+    pub fn encoded_len(&self) -> usize {
+        varint_field_len(self.pid as u64)
+            + varint_field_len(self.image_base)
+            + varint_field_len(self.image_size)
+            + utf16_field_len(self.full_image_name)
+            + varint_field_len(self.is_kernel_module as u64)
+    }
+
+    /// Bytes [`write_frame`](Self::write_frame) needs.
+    pub fn frame_len(&self) -> usize {
+        ring_frame_len(self.encoded_len())
+    }
+
+    /// Writes the prefix of frame number `seq` built at `ts`, the encoding
+    /// and zero padding to the start of `out`. Returns the frame length, or
+    /// `None` if `out` is too short.
+    pub fn write_frame(&self, out: &mut [u8], seq: u64, ts: u64) -> Option<usize> {
+        write_frame(out, seq, ts, self.encoded_len(), |w| {
+            w.varint_field(PID, self.pid as u64)?;
+            w.varint_field(IMAGE_BASE, self.image_base)?;
+            w.varint_field(IMAGE_SIZE, self.image_size)?;
+            w.utf16_field(FULL_IMAGE_NAME, self.full_image_name)?;
+            w.varint_field(IS_KERNEL_MODULE, self.is_kernel_module as u64)
+        })
+    }
+}
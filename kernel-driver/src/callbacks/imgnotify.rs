@@ -0,0 +1,103 @@
+//! Image load notification handler.
+//!
+//! Registers a `PsSetLoadImageNotifyRoutine` callback that reports every
+//! image mapped into a process (EXEs, DLLs) and every driver loaded into
+//! the kernel, as an `ImageLoadEvent` frame for the image ring.
+//!
+//! Key responsibilities:
+//! - Register the routine at driver entry and remove it on unload.
+//! - Capture the image name, base, size and owning process.
+//! - Flag kernel-mode images (`SystemModeImage`) so user space can tell
+//!   driver loads from DLL loads.
+//!
+//! The routine runs at `PASSIVE_LEVEL` in the context of the loading thread.
+
+use core::{
+    slice,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use wdk::println;
+use wdk_sys::{
+    ntddk::{PsRemoveLoadImageNotifyRoutine, PsSetLoadImageNotifyRoutine},
+    HANDLE, NTSTATUS, NT_SUCCESS, PIMAGE_INFO, PUNICODE_STRING,
+};
+
+use super::image_event::ImageLoadEvent;
+use crate::{
+    frame::Sequence,
+    kernel_api::{now_micros, Wdk},
+    ring::{Push, RingSlot},
+    ring_event,
+};
+
+/// `IMAGE_INFO.SystemModeImage`, bit 8 of `Properties`.
+const SYSTEM_MODE_IMAGE: u32 = 1 << 8;
+
+/// Frames that could not be delivered: refused by the ring, or offered
+/// while there is none (see `sections.rs`).
+pub static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Numbers this ring's frames, so user space can count the dropped ones.
+static SEQ: Sequence = Sequence::new();
+
+/// Whether [`register`] succeeded and [`unregister`] is still due.
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// Where the frames go; `sections` installs the ring.
+pub(crate) static RING: RingSlot<Wdk> = RingSlot::new(Wdk);
+
+fn push(len: usize, write: impl FnOnce(&mut [u8]) -> Option<usize>) {
+    match RING.push_with(len, write) {
+        Push::Published { was_empty } => ring_event::pushed(was_empty),
+        Push::Dropped => {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// `PLOAD_IMAGE_NOTIFY_ROUTINE`.
+unsafe extern "C" fn on_image_load(full_image_name: PUNICODE_STRING, process_id: HANDLE, info: PIMAGE_INFO) {
+    let Some(info) = info.as_ref() else { return };
+    // The name may be missing, e.g. for images mapped before the file
+    // system is up.
+    let name: &[u16] = match full_image_name.as_ref() {
+        Some(name) if !name.Buffer.is_null() => slice::from_raw_parts(name.Buffer, name.Length as usize / 2),
+        _ => &[],
+    };
+    let event = ImageLoadEvent {
+        pid:              process_id as usize as u32,
+        image_base:       info.ImageBase as usize as u64,
+        image_size:       info.ImageSize as u64,
+        full_image_name:  name,
+        is_kernel_module: info.__bindgen_anon_1.Properties & SYSTEM_MODE_IMAGE != 0,
+    };
+    let seq = SEQ.next();
+    push(event.frame_len(), |out| event.write_frame(out, seq, now_micros()));
+}
+
+/// Registers the notify routine.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL` from `DriverEntry`.
+pub unsafe fn register() -> NTSTATUS {
+    let status = PsSetLoadImageNotifyRoutine(Some(on_image_load));
+    if !NT_SUCCESS(status) {
+        println!("gladix: PsSetLoadImageNotifyRoutine failed: {status:#x}");
+        return status;
+    }
+    REGISTERED.store(true, Ordering::Release);
+    status
+}
+
+/// Removes the routine if [`register`] succeeded. The system waits for
+/// running invocations first, so what they write to can be freed afterwards.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL`, from the unload routine or a failed
+/// `DriverEntry`.
+pub unsafe fn unregister() {
+    if REGISTERED.swap(false, Ordering::AcqRel) {
+        PsRemoveLoadImageNotifyRoutine(Some(on_image_load));
+    }
+}
@@ -5,7 +5,23 @@
 //! access to protected objects.
 //!
 //! Key responsibilities:
-//! - Use `PsSetCreateProcessNotifyRoutine` for process tracking.
-//! - Register object access callbacks using `ObRegisterCallbacks`.
+//! - Report process creation and exit through
+//!   `PsSetCreateProcessNotifyRoutineEx` (`psnotify`).
+//! - Report image loads through `PsSetLoadImageNotifyRoutine` (`imgnotify`).
+//! - Report handle access to protected processes through
+//!   `ObRegisterCallbacks` (`obcallbacks`).
 //! - Forward relevant events to the user-agent for policy enforcement.
 //! - Deregister callbacks on driver unload.
+//!
+//! The ring each callback pushes to sits in a `ring::RingSlot`, which
+//! publishes it with `ownership::SharedRef`: every push holds a guard, and
+//! unload retires the ring and waits for the guards to go before the
+//! section under it is unmapped, so a callback already past the lookup on
+//! another CPU never touches freed memory.
+
+pub mod image_event;
+pub mod imgnotify;
+pub mod object_event;
+pub mod obcallbacks;
+pub mod process_event;
+pub mod psnotify;
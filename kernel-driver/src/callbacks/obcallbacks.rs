@@ -0,0 +1,162 @@
+//! Process handle callbacks.
+//!
+//! Registers an `ObRegisterCallbacks` pre-operation callback on
+//! `PsProcessType` that reports every handle opened to, or duplicated
+//! for, a protected process with `PROCESS_VM_READ` or `PROCESS_VM_WRITE`
+//! as an `ObjectOpEvent` frame for the object ring.
+//!
+//! Key responsibilities:
+//! - Register the callback at driver entry and remove it on unload; a
+//!   refused registration leaves the driver running without it.
+//! - Keep the protected pids sent by the agent through
+//!   `IOCTL_GLADIX_SET_PROTECTED_PIDS` (initially just its own).
+//! - Monitor only: the requested access is always granted unchanged.
+//!
+//! The callback runs at `PASSIVE_LEVEL` or `APC_LEVEL` in the context of
+//! the thread asking for the handle. `ObRegisterCallbacks` refuses drivers
+//! not linked with `/INTEGRITYCHECK`, which `build.rs` passes.
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicU32, Ordering},
+};
+
+use wdk::println;
+use wdk_sys::{
+    ntddk::{ObRegisterCallbacks, ObUnRegisterCallbacks, PsGetCurrentProcessId, PsGetProcessId},
+    NTSTATUS, NT_SUCCESS, OB_CALLBACK_REGISTRATION, OB_FLT_REGISTRATION_VERSION, OB_OPERATION_HANDLE_CREATE,
+    OB_OPERATION_HANDLE_DUPLICATE, OB_OPERATION_REGISTRATION, OB_PREOP_CALLBACK_STATUS, OB_PREOP_SUCCESS,
+    PEPROCESS, POB_PRE_OPERATION_INFORMATION, PVOID, PsProcessType, UNICODE_STRING,
+};
+
+use super::object_event::{
+    reportable, ObjectOpEvent, ProtectedPids, OPERATION_HANDLE_CREATE, OPERATION_HANDLE_DUPLICATE,
+};
+use crate::{
+    frame::Sequence,
+    kernel_api::{now_micros, Wdk},
+    ring::{Push, RingSlot},
+    ring_event,
+};
+
+/// Altitude among object callbacks; only has to be unique.
+static ALTITUDE: [u16; 6] = [b'3' as u16, b'2' as u16, b'1' as u16, b'4' as u16, b'0' as u16, b'0' as u16];
+
+/// `OB_PRE_OPERATION_INFORMATION.Flags` bit set for kernel handles.
+const KERNEL_HANDLE: u32 = 1;
+
+/// Frames that could not be delivered: refused by the ring, or offered
+/// while there is none (see `sections.rs`).
+pub static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Numbers this ring's frames, so user space can count the dropped ones.
+static SEQ: Sequence = Sequence::new();
+
+/// Processes whose handles are reported.
+pub static PROTECTED: ProtectedPids = ProtectedPids::new();
+
+/// Handle from `ObRegisterCallbacks`; null when not registered.
+static REGISTRATION: AtomicPtr<core::ffi::c_void> = AtomicPtr::new(ptr::null_mut());
+
+/// Where the frames go; `sections` installs the ring.
+pub(crate) static RING: RingSlot<Wdk> = RingSlot::new(Wdk);
+
+fn push(len: usize, write: impl FnOnce(&mut [u8]) -> Option<usize>) {
+    match RING.push_with(len, write) {
+        Push::Published { was_empty } => ring_event::pushed(was_empty),
+        Push::Dropped => {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn process_id(process: PEPROCESS) -> u32 {
+    // SAFETY: `process` is a referenced process object handed to the
+    // callback.
+    unsafe { PsGetProcessId(process) as usize as u32 }
+}
+
+/// `POB_PRE_OPERATION_CALLBACK`.
+unsafe extern "C" fn on_process_handle(
+    _context: PVOID,
+    info: POB_PRE_OPERATION_INFORMATION,
+) -> OB_PREOP_CALLBACK_STATUS {
+    let Some(info) = info.as_ref() else { return OB_PREOP_SUCCESS };
+    // Handles the kernel opens for itself are not a process reaching into
+    // another one.
+    if info.__bindgen_anon_1.Flags & KERNEL_HANDLE != 0 || info.Parameters.is_null() {
+        return OB_PREOP_SUCCESS;
+    }
+    let target_pid = process_id(info.Object.cast());
+    if !PROTECTED.contains(target_pid) {
+        return OB_PREOP_SUCCESS;
+    }
+    let (source_pid, desired_access, operation) = match info.Operation {
+        OB_OPERATION_HANDLE_CREATE => {
+            let params = &(*info.Parameters).CreateHandleInformation;
+            (PsGetCurrentProcessId() as usize as u32, params.OriginalDesiredAccess, OPERATION_HANDLE_CREATE)
+        }
+        OB_OPERATION_HANDLE_DUPLICATE => {
+            // The process receiving the copy is the one gaining access.
+            let params = &(*info.Parameters).DuplicateHandleInformation;
+            (process_id(params.TargetProcess.cast()), params.OriginalDesiredAccess, OPERATION_HANDLE_DUPLICATE)
+        }
+        _ => return OB_PREOP_SUCCESS,
+    };
+    // A protected process opening itself is routine.
+    if source_pid == target_pid || !reportable(desired_access) {
+        return OB_PREOP_SUCCESS;
+    }
+    let event = ObjectOpEvent { source_pid, target_pid, desired_access, operation };
+    let seq = SEQ.next();
+    push(event.frame_len(), |out| event.write_frame(out, seq, now_micros()));
+    OB_PREOP_SUCCESS
+}
+
+/// Registers the callback. A failure is logged and returned; `DriverEntry`
+/// carries on without the object ring.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL` from `DriverEntry`.
+pub unsafe fn register() -> NTSTATUS {
+    let mut operation = OB_OPERATION_REGISTRATION {
+        ObjectType:    PsProcessType,
+        Operations:    OB_OPERATION_HANDLE_CREATE | OB_OPERATION_HANDLE_DUPLICATE,
+        PreOperation:  Some(on_process_handle),
+        PostOperation: None,
+    };
+    let bytes = (ALTITUDE.len() * 2) as u16;
+    let registration = OB_CALLBACK_REGISTRATION {
+        Version:                    OB_FLT_REGISTRATION_VERSION as u16,
+        OperationRegistrationCount: 1,
+        // The kernel copies the altitude and the operations.
+        Altitude:                   UNICODE_STRING {
+            Length:        bytes,
+            MaximumLength: bytes,
+            Buffer:        ALTITUDE.as_ptr().cast_mut(),
+        },
+        RegistrationContext:        ptr::null_mut(),
+        OperationRegistration:      &mut operation,
+    };
+    let mut handle: PVOID = ptr::null_mut();
+    let status = ObRegisterCallbacks(&registration, &mut handle);
+    if !NT_SUCCESS(status) {
+        println!("gladix: ObRegisterCallbacks failed: {status:#x}");
+        return status;
+    }
+    REGISTRATION.store(handle, Ordering::Release);
+    status
+}
+
+/// Removes the callback if [`register`] succeeded. The system waits for
+/// running invocations first, so what they write to can be freed afterwards.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL`, from the unload routine or a failed
+/// `DriverEntry`.
+pub unsafe fn unregister() {
+    let handle = REGISTRATION.swap(ptr::null_mut(), Ordering::AcqRel);
+    if !handle.is_null() {
+        ObUnRegisterCallbacks(handle);
+    }
+}
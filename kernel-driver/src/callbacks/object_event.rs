@@ -0,0 +1,95 @@
+//! `ObjectOpEvent` frames as the user-agent reads them from the object
+//! ring, and the set of processes whose handles are watched.
+//!
+//! Encoded with `crate::frame`, following `ObjectOpEvent` in
+//! `shared/proto/events.proto`. Only `core` is used, so
+//! `tests/object_event.rs` can include this file directly.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::consts::{ring_frame_len, PROTECTED_PIDS_MAX};
+use crate::frame::{varint_field_len, varint_tag, write_frame};
+
+/// `ObjectOpEvent.Operation`.
+pub const OPERATION_HANDLE_CREATE: u32 = 0;
+pub const OPERATION_HANDLE_DUPLICATE: u32 = 1;
+
+/// Process access rights that let the holder read or write the target's
+/// memory (`winnt.h`).
+pub const PROCESS_VM_READ: u32 = 0x0010;
+pub const PROCESS_VM_WRITE: u32 = 0x0020;
+
+/// Whether a handle requested with `desired_access` is worth reporting.
+pub const fn reportable(desired_access: u32) -> bool {
+    desired_access & (PROCESS_VM_READ | PROCESS_VM_WRITE) != 0
+}
+
+/// Processes whose handles are reported, as last set through
+/// `IOCTL_GLADIX_SET_PROTECTED_PIDS`. Read from the pre-operation callback
+/// without locking; a lookup racing a replacement may see either set.
+pub struct ProtectedPids([AtomicU32; PROTECTED_PIDS_MAX]);
+
+impl ProtectedPids {
+    pub const fn new() -> Self {
+        Self([const { AtomicU32::new(0) }; PROTECTED_PIDS_MAX])
+    }
+
+    /// Replaces the set with `pids`, of which at most
+    /// [`PROTECTED_PIDS_MAX`] are kept.
+    pub fn replace(&self, pids: impl IntoIterator<Item = u32>) {
+        let mut pids = pids.into_iter();
+        for slot in &self.0 {
+            slot.store(pids.next().unwrap_or(0), Ordering::Relaxed);
+        }
+    }
+
+    /// Whether `pid` is in the set. 0 never is: it marks free slots.
+    pub fn contains(&self, pid: u32) -> bool {
+        pid != 0 && self.0.iter().any(|slot| slot.load(Ordering::Relaxed) == pid)
+    }
+}
+
+/// What a handle pre-operation callback reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectOpEvent {
+    /// Process opening or duplicating the handle.
+    pub source_pid:     u32,
+    /// Protected process the handle refers to.
+    pub target_pid:     u32,
+    /// `ACCESS_MASK` as requested, before any other callback trimmed it.
+    pub desired_access: u32,
+    /// [`OPERATION_HANDLE_CREATE`] or [`OPERATION_HANDLE_DUPLICATE`].
+    pub operation:      u32,
+}
+
+const SOURCE_PID: u8 = varint_tag(1);
+const TARGET_PID: u8 = varint_tag(2);
+const DESIRED_ACCESS: u8 = varint_tag(3);
+const OPERATION: u8 = varint_tag(4);
+
+impl ObjectOpEvent {
+    /// Size of the protobuf encoding.
+    pub fn encoded_len(&self) -> usize {
+        varint_field_len(self.source_pid as u64)
+            + varint_field_len(self.target_pid as u64)
+            + varint_field_len(self.desired_access as u64)
+            + varint_field_len(self.operation as u64)
+    }
+
+    /// Bytes [`write_frame`](Self::write_frame) needs.
+    pub fn frame_len(&self) -> usize {
+        ring_frame_len(self.encoded_len())
+    }
+
+    /// Writes the prefix of frame number `seq` built at `ts`, the encoding
+    /// and zero padding to the start of `out`. Returns the frame length, or
+    /// `None` if `out` is too short.
+    pub fn write_frame(&self, out: &mut [u8], seq: u64, ts: u64) -> Option<usize> {
+        write_frame(out, seq, ts, self.encoded_len(), |w| {
+            w.varint_field(SOURCE_PID, self.source_pid as u64)?;
+            w.varint_field(TARGET_PID, self.target_pid as u64)?;
+            w.varint_field(DESIRED_ACCESS, self.desired_access as u64)?;
+            w.varint_field(OPERATION, self.operation as u64)
+        })
+    }
+}
@@ -0,0 +1,124 @@
+//! `ProcessEvent` frames as the user-agent reads them from the process ring.
+//!
+//! Encoded with `crate::frame`, following `ProcessEvent` in
+//! `shared/proto/events.proto`. A command line can run to hundreds of KB
+//! (encoded PowerShell) and would waste the ring or not fit it at all, so
+//! the notify routine builds events through [`ProcessEvent::capped`]: image
+//! paths are cut to [`PROCESS_IMAGE_PATH_MAX`] bytes of UTF-16, the command
+//! line to [`PROCESS_CMDLINE_MAX`], and `truncated` says which were. A SID
+//! string has at most 184 characters (15 sub-authorities) and is not cut.
+//! Only `core` is used, so `tests/process_event.rs` can include this file
+//! directly.
+
+use crate::consts::{
+    ring_frame_len, PROCESS_CMDLINE_MAX, PROCESS_IMAGE_PATH_MAX, TRUNCATED_CMDLINE, TRUNCATED_IMAGE_PATH,
+    TRUNCATED_PARENT_IMAGE_PATH,
+};
+use crate::frame::{len_tag, truncate_utf16, utf16_field_len, varint_field_len, varint_tag, write_frame};
+
+/// `ProcessEvent.EventType`.
+pub const EVENT_TYPE_CREATE: u32 = 0;
+pub const EVENT_TYPE_EXIT: u32 = 1;
+
+/// What a process notification reports. Strings are UTF-16 without
+/// terminator; unpaired surrogates become U+FFFD.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessEvent<'a> {
+    pub pid:               u32,
+    pub ppid:              u32,
+    pub image_path:        &'a [u16],
+    pub cmdline:           &'a [u16],
+    pub creator_pid:       u32,
+    pub creator_tid:       u32,
+    pub event_type:        u32,
+    /// NTSTATUS of an exit.
+    pub exit_code:         i32,
+    pub parent_image_path: &'a [u16],
+    /// `TRUNCATED_*` bits, set by [`capped`](Self::capped).
+    pub truncated:         u32,
+    /// String SID of the token user; empty when the token could not be read.
+    pub user_sid:          &'a [u16],
+    pub session_id:        u32,
+    pub elevated:          bool,
+}
+
+const PID: u8 = varint_tag(1);
+const PPID: u8 = varint_tag(2);
+const IMAGE_PATH: u8 = len_tag(3);
+const CMDLINE: u8 = len_tag(4);
+const CREATOR_PID: u8 = varint_tag(5);
+const CREATOR_TID: u8 = varint_tag(6);
+const EVENT_TYPE: u8 = varint_tag(7);
+const EXIT_CODE: u8 = varint_tag(8);
+const PARENT_IMAGE_PATH: u8 = len_tag(9);
+const TRUNCATED: u8 = varint_tag(11);
+const USER_SID: u8 = len_tag(12);
+const SESSION_ID: u8 = varint_tag(13);
+const ELEVATED: u8 = varint_tag(14);
+
+/// An `int32` is encoded sign-extended: negative values take 10 bytes.
+fn int32(value: i32) -> u64 {
+    value as i64 as u64
+}
+
+impl<'a> ProcessEvent<'a> {
+    /// The event with its strings cut to the caps, each cut recorded in
+    /// `truncated`.
+    pub fn capped(mut self) -> Self {
+        for (field, max, bit) in [
+            (&mut self.image_path, PROCESS_IMAGE_PATH_MAX, TRUNCATED_IMAGE_PATH),
+            (&mut self.cmdline, PROCESS_CMDLINE_MAX, TRUNCATED_CMDLINE),
+            (&mut self.parent_image_path, PROCESS_IMAGE_PATH_MAX, TRUNCATED_PARENT_IMAGE_PATH),
+        ] {
+            let (kept, cut) = truncate_utf16(field, max);
+            *field = kept;
+            if cut {
+                self.truncated |= bit;
+            }
+        }
+        self
+    }
+
+    /// Size of the protobuf encoding.
+    pub fn encoded_len(&self) -> usize {
+        varint_field_len(self.pid as u64)
+            + varint_field_len(self.ppid as u64)
+            + utf16_field_len(self.image_path)
+            + utf16_field_len(self.cmdline)
+            + varint_field_len(self.creator_pid as u64)
+            + varint_field_len(self.creator_tid as u64)
+            + varint_field_len(self.event_type as u64)
+            + varint_field_len(int32(self.exit_code))
+            + utf16_field_len(self.parent_image_path)
+            + varint_field_len(self.truncated as u64)
+            + utf16_field_len(self.user_sid)
+            + varint_field_len(self.session_id as u64)
+            + varint_field_len(self.elevated as u64)
+    }
+
+    /// Bytes [`write_frame`](Self::write_frame) needs.
+    pub fn frame_len(&self) -> usize {
+        ring_frame_len(self.encoded_len())
+    }
+
+    /// Writes the prefix of frame number `seq` built at `ts`, the encoding
+    /// and zero padding to the start of `out`. Returns the frame length, or
+    /// `None` if `out` is too short.
+    pub fn write_frame(&self, out: &mut [u8], seq: u64, ts: u64) -> Option<usize> {
+        write_frame(out, seq, ts, self.encoded_len(), |w| {
+            w.varint_field(PID, self.pid as u64)?;
+            w.varint_field(PPID, self.ppid as u64)?;
+            w.utf16_field(IMAGE_PATH, self.image_path)?;
+            w.utf16_field(CMDLINE, self.cmdline)?;
+            w.varint_field(CREATOR_PID, self.creator_pid as u64)?;
+            w.varint_field(CREATOR_TID, self.creator_tid as u64)?;
+            w.varint_field(EVENT_TYPE, self.event_type as u64)?;
+            w.varint_field(EXIT_CODE, int32(self.exit_code))?;
+            w.utf16_field(PARENT_IMAGE_PATH, self.parent_image_path)?;
+            w.varint_field(TRUNCATED, self.truncated as u64)?;
+            w.utf16_field(USER_SID, self.user_sid)?;
+            w.varint_field(SESSION_ID, self.session_id as u64)?;
+            w.varint_field(ELEVATED, self.elevated as u64)
+        })
+    }
+}
@@ -1,11 +1,336 @@
-//! Process creation notification handler.
+//! Process creation and exit notification handler.
 //!
 //! This module focuses on intercepting process creation events to extract
 //! metadata (PID, parent PID, image path, etc.) for correlation and
 //! monitoring purposes. It enables early detection of suspicious process trees.
 //!
 //! Key responsibilities:
-//! - Handle `PsCreateProcessNotifyEx` events.
-//! - Build event structures with parent/child info.
-//! - Track relationships between processes for runtime intelligence.
-//! - Pass data to user space via configured channels.
+//! - Register a `PsSetCreateProcessNotifyRoutineEx` routine at driver entry
+//!   and remove it on unload.
+//! - Handle its events, including exits (null create info), which carry the
+//!   exit status from `PsGetProcessExitStatus`.
+//! - Build `ProcessEvent` frames with parent/child info for the process
+//!   ring.
+//!
+//! Two "parents" are recorded. `ParentProcessId` is the process the child
+//! inherits from, which the caller may choose freely
+//! (`PROC_THREAD_ATTRIBUTE_PARENT_PROCESS`). `CreatingThreadId` is the thread
+//! that actually issued the create call. They differ for brokered creations
+//! (services.exe, WMI, AppInfo) and for parent-PID spoofing; the agent tells
+//! those apart.
+//!
+//! The parent's image path is looked up while its process object can still
+//! be found by id; a parent that already exited leaves it empty. The kernel
+//! offers no routine for another process's command line, so
+//! `parent_cmdline` is left to the agent, which completes both from the
+//! parent's stored creation.
+//!
+//! Who the new process runs as is read from its primary token: the user's
+//! SID as a string, the session and whether the token is elevated. Each is
+//! queried on its own, so one that fails leaves only its field empty; the
+//! token and every block the queries return are released on all paths.
+
+use alloc::vec::Vec;
+use core::{
+    ptr,
+    slice,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use wdk::println;
+use wdk_sys::{
+    ntddk::{
+        ExFreePoolWithTag, ObfDereferenceObject, PsDereferencePrimaryToken, PsGetProcessExitStatus,
+        PsLookupProcessByProcessId, PsReferencePrimaryToken, PsSetCreateProcessNotifyRoutineEx,
+        RtlConvertSidToUnicodeString, RtlFreeUnicodeString, SeLocateProcessImageName, SeQueryInformationToken,
+        SeQuerySessionIdToken,
+    },
+    HANDLE, NTSTATUS, NT_SUCCESS, PACCESS_TOKEN, PCUNICODE_STRING, PEPROCESS, PPS_CREATE_NOTIFY_INFO,
+    PS_CREATE_NOTIFY_INFO, PSID, PUNICODE_STRING, PVOID, TOKEN_ELEVATION, TOKEN_INFORMATION_CLASS, TOKEN_USER,
+    UNICODE_STRING,
+    _TOKEN_INFORMATION_CLASS::{TokenElevation, TokenUser},
+};
+
+use super::process_event::{ProcessEvent, EVENT_TYPE_CREATE, EVENT_TYPE_EXIT};
+use crate::{
+    frame::Sequence,
+    kernel_api::{now_micros, Wdk},
+    ring::{Push, RingSlot},
+    ring_event,
+};
+
+/// Frames that could not be delivered: refused by the ring, or offered
+/// while there is none (see `sections.rs`).
+pub static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Numbers this ring's frames, so user space can count the dropped ones.
+static SEQ: Sequence = Sequence::new();
+
+/// Whether [`register`] succeeded and [`unregister`] is still due.
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// Where the frames go; `sections` installs the ring.
+pub(crate) static RING: RingSlot<Wdk> = RingSlot::new(Wdk);
+
+fn push(len: usize, write: impl FnOnce(&mut [u8]) -> Option<usize>) {
+    match RING.push_with(len, write) {
+        Push::Published { was_empty } => ring_event::pushed(was_empty),
+        Push::Dropped => {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The characters of `s`; empty when it or its buffer is null.
+///
+/// # Safety
+/// `s` must be null or point to a valid `UNICODE_STRING` that outlives the
+/// returned slice.
+unsafe fn utf16<'a>(s: PCUNICODE_STRING) -> &'a [u16] {
+    match unsafe { s.as_ref() } {
+        Some(s) if !s.Buffer.is_null() => unsafe { slice::from_raw_parts(s.Buffer, s.Length as usize / 2) },
+        _ => &[],
+    }
+}
+
+/// Identity fields of a process-create notification, mirroring
+/// `ProcessEvent` in `shared/proto/events.proto`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreateIds {
+    pub pid:         u32,
+    pub ppid:        u32,
+    pub creator_pid: u32,
+    pub creator_tid: u32,
+}
+
+/// Process and thread ids are handles whose value is the id.
+fn handle_id(h: HANDLE) -> u32 {
+    h as usize as u32
+}
+
+/// Ids of the process `process_id` being created as described by `info`,
+/// the non-null create info of a `PCREATE_PROCESS_NOTIFY_ROUTINE_EX` call.
+pub fn create_ids(process_id: HANDLE, info: &PS_CREATE_NOTIFY_INFO) -> CreateIds {
+    CreateIds {
+        pid:         handle_id(process_id),
+        ppid:        handle_id(info.ParentProcessId),
+        creator_pid: handle_id(info.CreatingThreadId.UniqueProcess),
+        creator_tid: handle_id(info.CreatingThreadId.UniqueThread),
+    }
+}
+
+/// Image path of the process `pid`, UTF-16 without terminator; empty if it
+/// is gone or its name cannot be read.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL`, as the notify routine runs.
+pub unsafe fn image_path(pid: HANDLE) -> Vec<u16> {
+    let mut process: PEPROCESS = ptr::null_mut();
+    if !NT_SUCCESS(unsafe { PsLookupProcessByProcessId(pid, &mut process) }) {
+        return Vec::new();
+    }
+    let mut name: PUNICODE_STRING = ptr::null_mut();
+    // SAFETY: the lookup referenced `process`; released right after.
+    let status = unsafe { SeLocateProcessImageName(process, &mut name) };
+    unsafe { ObfDereferenceObject(process.cast()) };
+    if !NT_SUCCESS(status) {
+        return Vec::new();
+    }
+    // SAFETY: on success `name` is a pool block holding the UNICODE_STRING
+    // and its buffer, owned by the caller.
+    let path = unsafe { utf16(name) }.to_vec();
+    if !name.is_null() {
+        unsafe { ExFreePoolWithTag(name.cast(), 0) };
+    }
+    path
+}
+
+/// Who a process runs as, mirroring `user_sid`, `session_id` and
+/// `elevated` of `ProcessEvent`. Whatever could not be read is empty, 0 or
+/// `false`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenContext {
+    /// String SID (`S-1-5-21-...`), UTF-16.
+    pub user_sid:   Vec<u16>,
+    pub session_id: u32,
+    pub elevated:   bool,
+}
+
+/// `SeQueryInformationToken` of `class`: a pool block the caller frees with
+/// `ExFreePoolWithTag(.., 0)`, or null if the query failed.
+///
+/// # Safety
+/// `token` must be referenced; call at `PASSIVE_LEVEL`.
+unsafe fn query_token(token: PACCESS_TOKEN, class: TOKEN_INFORMATION_CLASS) -> PVOID {
+    let mut info: PVOID = ptr::null_mut();
+    if NT_SUCCESS(unsafe { SeQueryInformationToken(token, class, &mut info) }) { info } else { ptr::null_mut() }
+}
+
+/// `sid` as a string, UTF-16 without terminator; empty if it cannot be
+/// converted.
+///
+/// # Safety
+/// `sid` must point to a valid SID; call at `PASSIVE_LEVEL`.
+unsafe fn sid_string(sid: PSID) -> Vec<u16> {
+    let mut text: UNICODE_STRING = unsafe { core::mem::zeroed() };
+    // Allocates the buffer, released by RtlFreeUnicodeString.
+    if !NT_SUCCESS(unsafe { RtlConvertSidToUnicodeString(&mut text, sid, 1) }) {
+        return Vec::new();
+    }
+    let sid = if text.Buffer.is_null() {
+        Vec::new()
+    } else {
+        unsafe { slice::from_raw_parts(text.Buffer, text.Length as usize / 2) }.to_vec()
+    };
+    unsafe { RtlFreeUnicodeString(&mut text) };
+    sid
+}
+
+/// User, session and elevation of `process` from its primary token.
+///
+/// # Safety
+/// `process` must be referenced (the notify routine's argument is); call
+/// at `PASSIVE_LEVEL`.
+pub unsafe fn token_context(process: PEPROCESS) -> TokenContext {
+    let mut context = TokenContext::default();
+    let token = unsafe { PsReferencePrimaryToken(process) };
+    if token.is_null() {
+        return context;
+    }
+
+    let user = unsafe { query_token(token, TokenUser) };
+    if !user.is_null() {
+        // SAFETY: a TOKEN_USER whose SID lives in the same block.
+        context.user_sid = unsafe { sid_string((*user.cast::<TOKEN_USER>()).User.Sid) };
+        unsafe { ExFreePoolWithTag(user, 0) };
+    }
+
+    let mut session: u32 = 0;
+    if NT_SUCCESS(unsafe { SeQuerySessionIdToken(token, &mut session) }) {
+        context.session_id = session;
+    }
+
+    let elevation = unsafe { query_token(token, TokenElevation) };
+    if !elevation.is_null() {
+        context.elevated = unsafe { (*elevation.cast::<TOKEN_ELEVATION>()).TokenIsElevated } != 0;
+        unsafe { ExFreePoolWithTag(elevation, 0) };
+    }
+
+    unsafe { PsDereferencePrimaryToken(token) };
+    context
+}
+
+/// Process creation: ids plus what is known of the parent and the token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreateInfo {
+    pub ids: CreateIds,
+    /// `parent_image_path`, UTF-16; empty when the parent is gone.
+    pub parent_image_path: Vec<u16>,
+    pub token: TokenContext,
+}
+
+/// Process exit, mirroring a `ProcessEvent` with `event_type = EXIT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitInfo {
+    pub pid:       u32,
+    /// NTSTATUS the process exited with.
+    pub exit_code: i32,
+}
+
+/// What a `PCREATE_PROCESS_NOTIFY_ROUTINE_EX` call reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+    Create(CreateInfo),
+    Exit(ExitInfo),
+}
+
+/// Decodes the arguments of a `PCREATE_PROCESS_NOTIFY_ROUTINE_EX` call; a
+/// null `info` means `process` is exiting.
+///
+/// # Safety
+/// `process` and `info` must be the values passed to the notify routine,
+/// which keeps both valid for the duration of the call and runs at
+/// `PASSIVE_LEVEL`.
+pub unsafe fn notification(
+    process: PEPROCESS,
+    process_id: HANDLE,
+    info: *const PS_CREATE_NOTIFY_INFO,
+) -> Notification {
+    match unsafe { info.as_ref() } {
+        Some(info) => Notification::Create(CreateInfo {
+            ids:               create_ids(process_id, info),
+            // SAFETY: PASSIVE_LEVEL in the notify routine.
+            parent_image_path: unsafe { image_path(info.ParentProcessId) },
+            // SAFETY: the process object is referenced for the callback.
+            token:             unsafe { token_context(process) },
+        }),
+        None => Notification::Exit(ExitInfo {
+            pid:       handle_id(process_id),
+            // SAFETY: the process object is referenced for the callback.
+            exit_code: unsafe { PsGetProcessExitStatus(process) },
+        }),
+    }
+}
+
+/// `PCREATE_PROCESS_NOTIFY_ROUTINE_EX`.
+unsafe extern "C" fn on_process_notify(process: PEPROCESS, process_id: HANDLE, info: PPS_CREATE_NOTIFY_INFO) {
+    // SAFETY: the arguments the routine was called with, at PASSIVE_LEVEL.
+    let notification = unsafe { notification(process, process_id, info) };
+    let event = match &notification {
+        Notification::Create(create) => {
+            // SAFETY: not null for a creation; it and its strings are valid
+            // for the call.
+            let info = unsafe { &*info };
+            let (image_path, cmdline) = unsafe { (utf16(info.ImageFileName), utf16(info.CommandLine)) };
+            ProcessEvent {
+                pid: create.ids.pid,
+                ppid: create.ids.ppid,
+                image_path,
+                cmdline,
+                creator_pid: create.ids.creator_pid,
+                creator_tid: create.ids.creator_tid,
+                event_type: EVENT_TYPE_CREATE,
+                parent_image_path: &create.parent_image_path,
+                user_sid: &create.token.user_sid,
+                session_id: create.token.session_id,
+                elevated: create.token.elevated,
+                ..ProcessEvent::default()
+            }
+            .capped()
+        }
+        Notification::Exit(exit) => ProcessEvent {
+            pid: exit.pid,
+            event_type: EVENT_TYPE_EXIT,
+            exit_code: exit.exit_code,
+            ..ProcessEvent::default()
+        },
+    };
+    let seq = SEQ.next();
+    push(event.frame_len(), |out| event.write_frame(out, seq, now_micros()));
+}
+
+/// Registers the notify routine.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL` from `DriverEntry`.
+pub unsafe fn register() -> NTSTATUS {
+    let status = unsafe { PsSetCreateProcessNotifyRoutineEx(Some(on_process_notify), 0) };
+    if !NT_SUCCESS(status) {
+        println!("gladix: PsSetCreateProcessNotifyRoutineEx failed: {status:#x}");
+        return status;
+    }
+    REGISTERED.store(true, Ordering::Release);
+    status
+}
+
+/// Removes the routine if [`register`] succeeded. The system waits for
+/// running invocations first, so what they write to can be freed afterwards.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL`, from the unload routine or a failed
+/// `DriverEntry`.
+pub unsafe fn unregister() {
+    if REGISTERED.swap(false, Ordering::AcqRel) {
+        unsafe { PsSetCreateProcessNotifyRoutineEx(Some(on_process_notify), 1) };
+    }
+}
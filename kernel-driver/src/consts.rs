@@ -0,0 +1,291 @@
+//! Device names, IOCTL codes and reply layouts agreed with the user-agent.
+//!
+//! The user-agent side lives in `shared::constants` and
+//! `shared::ring::RingStats`. The driver cannot depend on `shared` (it pulls
+//! in prost and tonic), so the values are repeated here and pinned by the
+//! const asserts at the bottom. Only `core` is used.
+
+use core::mem::size_of;
+
+/// ASCII `s` as UTF-16 without terminator; `N` must be its length.
+const fn utf16<const N: usize>(s: &str) -> [u16; N] {
+    let bytes = s.as_bytes();
+    assert!(bytes.len() == N);
+    let mut out = [0u16; N];
+    let mut i = 0;
+    while i < N {
+        out[i] = bytes[i] as u16;
+        i += 1;
+    }
+    out
+}
+
+/// NT name of the control device.
+pub const DEVICE_NAME: [u16; 14] = utf16(r"\Device\Gladix");
+/// Win32 alias, opened by the agent as `\\.\Gladix`.
+pub const SYMLINK_NAME: [u16; 10] = utf16(r"\??\Gladix");
+/// Event set when a ring gets frames (`shared::constants::RING_EVENT`),
+/// opened by the agent as `Global\GladixRingEvent`.
+pub const RING_EVENT: &str = "GladixRingEvent";
+/// `shared::constants::KERNEL_OBJECT_DIR`.
+pub const KERNEL_OBJECT_DIR: &str = "\\BaseNamedObjects\\";
+/// [`RING_EVENT`] under [`KERNEL_OBJECT_DIR`], where the driver creates it.
+pub const RING_EVENT_NAME: [u16; 33] = kernel_object_path(RING_EVENT);
+
+/// `name` under [`KERNEL_OBJECT_DIR`] as UTF-16 without terminator, as
+/// `shared::constants::kernel_object_path` spells it; `N` must be its length.
+const fn kernel_object_path<const N: usize>(name: &str) -> [u16; N] {
+    let (dir, name) = (KERNEL_OBJECT_DIR.as_bytes(), name.as_bytes());
+    assert!(dir.len() + name.len() == N);
+    let mut out = [0u16; N];
+    let mut i = 0;
+    while i < N {
+        out[i] = if i < dir.len() { dir[i] } else { name[i - dir.len()] } as u16;
+        i += 1;
+    }
+    out
+}
+
+/// Ring sections (`shared::constants::PROCESS_RING` and the others),
+/// created under [`KERNEL_OBJECT_DIR`].
+pub const PROCESS_RING: &str = "process_ring";
+pub const IMAGE_RING: &str = "image_ring";
+pub const OBJECT_RING: &str = "object_ring";
+pub const NETWORK_RING: &str = "network_ring";
+pub const FILE_RING: &str = "file_ring";
+pub const PROCESS_RING_NAME: [u16; 30] = kernel_object_path(PROCESS_RING);
+pub const IMAGE_RING_NAME: [u16; 28] = kernel_object_path(IMAGE_RING);
+pub const OBJECT_RING_NAME: [u16; 29] = kernel_object_path(OBJECT_RING);
+pub const NETWORK_RING_NAME: [u16; 30] = kernel_object_path(NETWORK_RING);
+pub const FILE_RING_NAME: [u16; 27] = kernel_object_path(FILE_RING);
+
+pub const FILE_DEVICE_UNKNOWN: u32 = 0x22;
+pub const METHOD_BUFFERED: u32 = 0;
+pub const FILE_ANY_ACCESS: u32 = 0;
+pub const FILE_READ_ACCESS: u32 = 1;
+pub const FILE_WRITE_ACCESS: u32 = 2;
+
+/// `CTL_CODE` from `devioctl.h`.
+pub const fn ctl_code(device_type: u32, function: u32, method: u32, access: u32) -> u32 {
+    (device_type << 16) | (access << 14) | (function << 2) | method
+}
+
+/// Replies [`RingStats`].
+pub const IOCTL_GLADIX_GET_RING_STATS: u32 =
+    ctl_code(FILE_DEVICE_UNKNOWN, 0x800, METHOD_BUFFERED, FILE_READ_ACCESS);
+/// Replies [`PING_REPLY`].
+pub const IOCTL_GLADIX_PING: u32 =
+    ctl_code(FILE_DEVICE_UNKNOWN, 0x801, METHOD_BUFFERED, FILE_ANY_ACCESS);
+/// Replies [`VersionInfo`].
+pub const IOCTL_GLADIX_GET_VERSION: u32 =
+    ctl_code(FILE_DEVICE_UNKNOWN, 0x802, METHOD_BUFFERED, FILE_READ_ACCESS);
+/// Takes an array of [`NetRule`], replacing the network policy.
+pub const IOCTL_GLADIX_SET_NET_POLICY: u32 =
+    ctl_code(FILE_DEVICE_UNKNOWN, 0x803, METHOD_BUFFERED, FILE_WRITE_ACCESS);
+/// Takes an array of `u32` pids, replacing the protected processes.
+pub const IOCTL_GLADIX_SET_PROTECTED_PIDS: u32 =
+    ctl_code(FILE_DEVICE_UNKNOWN, 0x804, METHOD_BUFFERED, FILE_WRITE_ACCESS);
+
+/// "Gldx", little-endian.
+pub const PING_REPLY: u32 = u32::from_le_bytes(*b"Gldx");
+/// `major << 16 | minor` (`shared::constants::protocol_version`): the
+/// major is bumped when a code, reply layout or the ring framing changes
+/// incompatibly, the minor when something is only added.
+pub const DRIVER_PROTOCOL_VERSION: u32 = 2 << 16 | 1;
+
+/// Reply of [`IOCTL_GLADIX_GET_VERSION`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionInfo {
+    pub protocol:   u32,
+    pub major:      u16,
+    pub minor:      u16,
+    pub patch:      u16,
+    pub reserved:   [u8; 6],
+    /// UNIX seconds, from `GLADIX_BUILD_TIME` set by `build.rs`; 0 if unset.
+    pub build_time: u64,
+}
+
+const fn parse_u16(s: &str) -> u16 {
+    let bytes = s.as_bytes();
+    let mut value = 0u16;
+    let mut i = 0;
+    while i < bytes.len() {
+        value = value * 10 + (bytes[i] - b'0') as u16;
+        i += 1;
+    }
+    value
+}
+
+const fn parse_u64(s: Option<&str>) -> u64 {
+    let Some(s) = s else { return 0 };
+    let bytes = s.as_bytes();
+    let mut value = 0u64;
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            return 0;
+        }
+        value = value * 10 + (bytes[i] - b'0') as u64;
+        i += 1;
+    }
+    value
+}
+
+impl VersionInfo {
+    /// This build.
+    pub const CURRENT: VersionInfo = VersionInfo {
+        protocol:   DRIVER_PROTOCOL_VERSION,
+        major:      parse_u16(env!("CARGO_PKG_VERSION_MAJOR")),
+        minor:      parse_u16(env!("CARGO_PKG_VERSION_MINOR")),
+        patch:      parse_u16(env!("CARGO_PKG_VERSION_PATCH")),
+        reserved:   [0; 6],
+        build_time: parse_u64(option_env!("GLADIX_BUILD_TIME")),
+    };
+}
+
+/// Reply of [`IOCTL_GLADIX_GET_RING_STATS`]. Agents from before the drop
+/// reasons ask for the first [`RING_STATS_LEGACY_SIZE`] bytes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RingStats {
+    pub head:    u64,
+    pub tail:    u64,
+    pub dropped: u32,
+    /// Size of the data area in bytes.
+    pub size:    u32,
+    pub dropped_full:     u32,
+    pub dropped_oversize: u32,
+    pub max_observed_len: u32,
+    pub reserved:         u32,
+}
+
+pub const RING_STATS_LEGACY_SIZE: usize = 24;
+
+/// Most rules one [`IOCTL_GLADIX_SET_NET_POLICY`] carries.
+pub const NET_POLICY_MAX_RULES: usize = 256;
+pub const NET_RULE_PROCESS_LEN: usize = 120;
+pub const NET_ACTION_ALLOW: u8 = 0;
+pub const NET_ACTION_BLOCK: u8 = 1;
+pub const NET_FAMILY_ANY: u8 = 0;
+pub const NET_FAMILY_V4: u8 = 4;
+pub const NET_FAMILY_V6: u8 = 6;
+
+/// Input of [`IOCTL_GLADIX_SET_NET_POLICY`] (`shared::constants::NetRule`).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetRule {
+    pub addr:        [u8; 16],
+    pub family:      u8,
+    pub prefix:      u8,
+    pub action:      u8,
+    pub process_len: u8,
+    pub port_lo:     u16,
+    pub port_hi:     u16,
+    pub process:     [u8; NET_RULE_PROCESS_LEN],
+}
+
+impl NetRule {
+    /// Decodes one rule from `bytes`, which must hold `size_of::<NetRule>()`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut addr = [0u8; 16];
+        addr.copy_from_slice(&bytes[..16]);
+        let mut process = [0u8; NET_RULE_PROCESS_LEN];
+        process.copy_from_slice(&bytes[24..24 + NET_RULE_PROCESS_LEN]);
+        NetRule {
+            addr,
+            family: bytes[16],
+            prefix: bytes[17],
+            action: bytes[18],
+            process_len: bytes[19],
+            port_lo: u16::from_le_bytes([bytes[20], bytes[21]]),
+            port_hi: u16::from_le_bytes([bytes[22], bytes[23]]),
+            process,
+        }
+    }
+
+    /// Whether every field holds a value the classify path understands.
+    pub fn is_valid(&self) -> bool {
+        let max_prefix = match self.family {
+            NET_FAMILY_ANY => 0,
+            NET_FAMILY_V4 => 32,
+            NET_FAMILY_V6 => 128,
+            _ => return false,
+        };
+        self.prefix <= max_prefix
+            && matches!(self.action, NET_ACTION_ALLOW | NET_ACTION_BLOCK)
+            && self.port_lo <= self.port_hi
+            && self.process_len as usize <= NET_RULE_PROCESS_LEN
+    }
+}
+
+/// Most pids one [`IOCTL_GLADIX_SET_PROTECTED_PIDS`] carries.
+pub const PROTECTED_PIDS_MAX: usize = 16;
+
+/// Layout written to `RingHeader.version` (`shared::ring::VERSION`): the
+/// framing below and a header counting drops by reason.
+pub const RING_VERSION: u32 = 4;
+/// Bytes of the header before a ring's data area
+/// (`shared::ring::HEADER_SIZE`).
+pub const RING_HEADER_SIZE: usize = 40;
+/// Ring frames are [`RING_FRAME_MAGIC`], the payload length, the CRC-32 of
+/// everything after it, the frame number and the time the frame was built,
+/// all little-endian, followed by the payload, padded so the next frame
+/// starts on [`RING_FRAME_ALIGN`].
+pub const RING_FRAME_MAGIC: u16 = u16::from_le_bytes(*b"GX");
+pub const RING_FRAME_PREFIX: usize = 26;
+pub const RING_FRAME_ALIGN: usize = 8;
+
+/// Bytes a frame with `payload_len` bytes occupies, padding included
+/// (`shared::ring::frame_len`).
+pub const fn ring_frame_len(payload_len: usize) -> usize {
+    let total = RING_FRAME_PREFIX + payload_len;
+    total + (RING_FRAME_ALIGN - total % RING_FRAME_ALIGN) % RING_FRAME_ALIGN
+}
+
+/// Bytes of UTF-16 kept of a process image path, its own or the parent's
+/// (`shared::constants::PROCESS_IMAGE_PATH_MAX`).
+pub const PROCESS_IMAGE_PATH_MAX: usize = 2 * 1024;
+/// Bytes of UTF-16 kept of a command line.
+pub const PROCESS_CMDLINE_MAX: usize = 8 * 1024;
+/// Bits of `ProcessEvent.truncated`.
+pub const TRUNCATED_IMAGE_PATH: u32 = 1 << 0;
+pub const TRUNCATED_CMDLINE: u32 = 1 << 1;
+pub const TRUNCATED_PARENT_IMAGE_PATH: u32 = 1 << 2;
+
+/// Subkey of the service key `DriverEntry` receives holding
+/// [`RING_SIZE_VALUE`].
+pub const PARAMETERS_KEY: [u16; 11] = utf16(r"\Parameters");
+/// `REG_DWORD` with the bytes wanted for each ring's data area.
+pub const RING_SIZE_VALUE: [u16; 13] = utf16("RingSizeBytes");
+pub const RING_SIZE_MIN: u32 = 64 * 1024;
+pub const RING_SIZE_MAX: u32 = 16 * 1024 * 1024;
+/// Size without [`RING_SIZE_VALUE`].
+pub const RING_SIZE_DEFAULT: u32 = RING_SIZE_MIN;
+pub const PAGE_SIZE: u32 = 4096;
+
+/// Data area allocated for `requested` bytes: rounded up to a whole page and
+/// clamped to [`RING_SIZE_MIN`]..=[`RING_SIZE_MAX`] (`shared::constants::ring_size`).
+pub const fn ring_size(requested: u32) -> u32 {
+    let pages = (requested as u64).div_ceil(PAGE_SIZE as u64);
+    let bytes = pages * PAGE_SIZE as u64;
+    if bytes < RING_SIZE_MIN as u64 {
+        RING_SIZE_MIN
+    } else if bytes > RING_SIZE_MAX as u64 {
+        RING_SIZE_MAX
+    } else {
+        bytes as u32
+    }
+}
+
+const _: () = assert!(IOCTL_GLADIX_GET_RING_STATS == 0x0022_6000);
+const _: () = assert!(IOCTL_GLADIX_PING == 0x0022_2004);
+const _: () = assert!(IOCTL_GLADIX_GET_VERSION == 0x0022_6008);
+const _: () = assert!(IOCTL_GLADIX_SET_NET_POLICY == 0x0022_a00c);
+const _: () = assert!(IOCTL_GLADIX_SET_PROTECTED_PIDS == 0x0022_a010);
+const _: () = assert!(size_of::<NetRule>() == 144);
+const _: () = assert!(size_of::<VersionInfo>() == 24 && core::mem::offset_of!(VersionInfo, build_time) == 16);
+const _: () = assert!(size_of::<RingStats>() == 40);
+const _: () = assert!(RING_SIZE_MIN == 0x1_0000 && RING_SIZE_MAX == 0x100_0000 && PAGE_SIZE == 0x1000);
+const _: () = assert!(PROCESS_IMAGE_PATH_MAX == 0x800 && PROCESS_CMDLINE_MAX == 0x2000);
+const _: () = assert!(ring_frame_len(0) == 32 && ring_frame_len(6) == 32 && ring_frame_len(7) == 40);
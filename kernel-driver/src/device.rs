@@ -0,0 +1,145 @@
+//! Control device (`\Device\Gladix`, `\\.\Gladix` from user mode) and its
+//! dispatch routines. The IOCTL table itself lives in `ioctl.rs`.
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use wdk::println;
+use wdk_sys::{
+    ntddk::{IoCreateDevice, IoCreateSymbolicLink, IoDeleteDevice, IoDeleteSymbolicLink, IofCompleteRequest},
+    DEVICE_OBJECT, DRIVER_OBJECT, FILE_DEVICE_SECURE_OPEN, FILE_DEVICE_UNKNOWN, IO_NO_INCREMENT, IRP,
+    IRP_MJ_CLOSE, IRP_MJ_CREATE, IRP_MJ_DEVICE_CONTROL, NTSTATUS, NT_SUCCESS, PIO_STACK_LOCATION,
+    STATUS_SUCCESS, UNICODE_STRING,
+};
+
+use crate::{
+    consts::{RingStats, DEVICE_NAME, SYMLINK_NAME},
+    callbacks::obcallbacks,
+    ioctl::{self, IoctlError, IoctlTarget, NetRules, ProtectedPids},
+    sections,
+};
+
+fn unicode(name: &'static [u16]) -> UNICODE_STRING {
+    let bytes = (name.len() * 2) as u16;
+    // The kernel only reads through `Buffer`.
+    UNICODE_STRING { Length: bytes, MaximumLength: bytes, Buffer: name.as_ptr().cast_mut() }
+}
+
+/// Rules in the last network policy received. The ALE callout cannot block
+/// yet, so the rules themselves are not kept; the agent applies the same
+/// policy to the events it stores.
+static NET_POLICY_RULES: AtomicU32 = AtomicU32::new(0);
+
+/// State reported through the IOCTLs.
+struct Driver;
+
+impl IoctlTarget for Driver {
+    fn ring_stats(&self) -> Option<RingStats> {
+        // The ring the agent checks the negotiated size against.
+        sections::PROCESS.stats()
+    }
+
+    fn set_net_policy(&self, rules: NetRules<'_>) -> Result<(), IoctlError> {
+        NET_POLICY_RULES.store(rules.len() as u32, Ordering::Relaxed);
+        println!("gladix: network policy of {} rules received", rules.len());
+        Ok(())
+    }
+
+    fn set_protected_pids(&self, pids: ProtectedPids<'_>) -> Result<(), IoctlError> {
+        obcallbacks::PROTECTED.replace(pids.iter());
+        println!("gladix: {} protected processes received", pids.len());
+        Ok(())
+    }
+}
+
+/// Creates the device and its symbolic link and installs the dispatch
+/// routines.
+///
+/// # Safety
+/// `driver` must be the object passed to `DriverEntry`.
+pub unsafe fn create(driver: &mut DRIVER_OBJECT) -> NTSTATUS {
+    let mut name = unicode(&DEVICE_NAME);
+    let mut link = unicode(&SYMLINK_NAME);
+    let mut device: *mut DEVICE_OBJECT = ptr::null_mut();
+
+    let status = IoCreateDevice(
+        driver,
+        0,
+        &mut name,
+        FILE_DEVICE_UNKNOWN,
+        FILE_DEVICE_SECURE_OPEN,
+        0,
+        &mut device,
+    );
+    if !NT_SUCCESS(status) {
+        println!("gladix: IoCreateDevice failed: {status:#x}");
+        return status;
+    }
+    let status = IoCreateSymbolicLink(&mut link, &mut name);
+    if !NT_SUCCESS(status) {
+        println!("gladix: IoCreateSymbolicLink failed: {status:#x}");
+        IoDeleteDevice(device);
+        return status;
+    }
+
+    driver.MajorFunction[IRP_MJ_CREATE as usize] = Some(dispatch_create_close);
+    driver.MajorFunction[IRP_MJ_CLOSE as usize] = Some(dispatch_create_close);
+    driver.MajorFunction[IRP_MJ_DEVICE_CONTROL as usize] = Some(dispatch_device_control);
+    STATUS_SUCCESS
+}
+
+/// Removes what [`create`] set up.
+///
+/// # Safety
+/// Call once, from the unload routine.
+pub unsafe fn delete(driver: *mut DRIVER_OBJECT) {
+    let mut link = unicode(&SYMLINK_NAME);
+    IoDeleteSymbolicLink(&mut link);
+    let device = (*driver).DeviceObject;
+    if !device.is_null() {
+        IoDeleteDevice(device);
+    }
+}
+
+unsafe fn complete(irp: *mut IRP, status: NTSTATUS, information: usize) -> NTSTATUS {
+    (*irp).IoStatus.__bindgen_anon_1.Status = status;
+    (*irp).IoStatus.Information = information as u64;
+    IofCompleteRequest(irp, IO_NO_INCREMENT as i8);
+    status
+}
+
+/// `IoGetCurrentIrpStackLocation`, an inline function in `wdm.h`.
+unsafe fn current_stack_location(irp: *mut IRP) -> PIO_STACK_LOCATION {
+    (*irp).Tail.Overlay.__bindgen_anon_2.__bindgen_anon_1.CurrentStackLocation
+}
+
+unsafe extern "C" fn dispatch_create_close(_device: *mut DEVICE_OBJECT, irp: *mut IRP) -> NTSTATUS {
+    complete(irp, STATUS_SUCCESS, 0)
+}
+
+unsafe extern "C" fn dispatch_device_control(_device: *mut DEVICE_OBJECT, irp: *mut IRP) -> NTSTATUS {
+    let params = &(*current_stack_location(irp)).Parameters.DeviceIoControl;
+    let code = params.IoControlCode;
+    let input_len = params.InputBufferLength as usize;
+    let output_len = params.OutputBufferLength as usize;
+    let system_buffer = (*irp).AssociatedIrp.SystemBuffer.cast::<u8>();
+
+    let len = input_len.max(output_len);
+    let buffer: &mut [u8] = if len == 0 || system_buffer.is_null() {
+        &mut []
+    } else {
+        // METHOD_BUFFERED: the I/O manager allocated max(input, output)
+        // bytes, and the input has been consumed before anything is written.
+        core::slice::from_raw_parts_mut(system_buffer, len)
+    };
+
+    match ioctl::route(code, input_len, buffer, &Driver) {
+        Ok(written) => complete(irp, STATUS_SUCCESS, written),
+        Err(e) => {
+            println!("gladix: IOCTL {code:#x} rejected: {e:?}");
+            complete(irp, e.status(), 0)
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Hand-written protobuf encoding for event ring frames.
+//!
+//! The driver cannot depend on prost, so each event encodes the few fields
+//! it fills with these helpers, following `shared/proto/events.proto`.
+//! Fields with their default value are left out, as prost does. Only `core`
+//! is used, so the host tests can include this file directly.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::consts::{ring_frame_len, RING_FRAME_MAGIC, RING_FRAME_PREFIX};
+
+/// Where the frame number, the first byte the CRC covers, starts.
+const SEQ_AT: usize = 10;
+/// Where the timestamp starts.
+const TS_AT: usize = 18;
+/// 1601-01-01 to 1970-01-01 in 100 ns units.
+const UNIX_EPOCH_FILETIME: i64 = 116_444_736_000_000_000;
+
+/// Numbers the frames of one ring. Every event takes a number whether or
+/// not its frame reaches the ring, so the reader sees a gap for each one
+/// lost.
+pub struct Sequence(AtomicU64);
+
+impl Sequence {
+    pub const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    /// The next number, starting at 1.
+    pub fn next(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+/// Frame timestamp of a system time in 100 ns units since 1601: microseconds
+/// since the Unix epoch, 0 for earlier times.
+pub fn unix_micros(filetime: i64) -> u64 {
+    if filetime <= UNIX_EPOCH_FILETIME { 0 } else { ((filetime - UNIX_EPOCH_FILETIME) / 10) as u64 }
+}
+
+/// Key of a varint field with a one-byte tag (field numbers up to 15).
+pub const fn varint_tag(field: u8) -> u8 {
+    field << 3
+}
+
+/// Key of a length-delimited field with a one-byte tag.
+pub const fn len_tag(field: u8) -> u8 {
+    field << 3 | 2
+}
+
+pub fn varint_len(mut value: u64) -> usize {
+    let mut len = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+/// Encoded size of a varint field; 0 for the default value.
+pub fn varint_field_len(value: u64) -> usize {
+    if value == 0 { 0 } else { 1 + varint_len(value) }
+}
+
+/// UTF-16 as chars; unpaired surrogates become U+FFFD.
+fn chars(s: &[u16]) -> impl Iterator<Item = char> + '_ {
+    char::decode_utf16(s.iter().copied()).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+}
+
+fn utf8_len(s: &[u16]) -> usize {
+    chars(s).map(char::len_utf8).sum()
+}
+
+/// The start of `s` that fits in `max_bytes` of UTF-16, cut before a
+/// surrogate pair rather than between its halves, and whether anything was
+/// cut.
+pub fn truncate_utf16(s: &[u16], max_bytes: usize) -> (&[u16], bool) {
+    let max = max_bytes / 2;
+    if s.len() <= max {
+        return (s, false);
+    }
+    let end = if max > 0 && (0xD800..0xDC00).contains(&s[max - 1]) { max - 1 } else { max };
+    (&s[..end], true)
+}
+
+/// Encoded size of a string field given as UTF-16; 0 when empty.
+pub fn utf16_field_len(s: &[u16]) -> usize {
+    let len = utf8_len(s);
+    if len == 0 { 0 } else { 1 + varint_len(len as u64) + len }
+}
+
+/// Encoded size of a string field; 0 when empty.
+pub fn str_field_len(s: &str) -> usize {
+    if s.is_empty() { 0 } else { 1 + varint_len(s.len() as u64) + s.len() }
+}
+
+/// Appends to `out[at..]`; `None` once it runs out of room.
+pub struct Writer<'b> {
+    out: &'b mut [u8],
+    at:  usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> Option<()> {
+        self.out.get_mut(self.at..self.at + bytes.len())?.copy_from_slice(bytes);
+        self.at += bytes.len();
+        Some(())
+    }
+
+    fn varint(&mut self, mut value: u64) -> Option<()> {
+        while value >= 0x80 {
+            self.bytes(&[value as u8 | 0x80])?;
+            value >>= 7;
+        }
+        self.bytes(&[value as u8])
+    }
+
+    /// Skipped for the default value.
+    pub fn varint_field(&mut self, tag: u8, value: u64) -> Option<()> {
+        if value == 0 {
+            return Some(());
+        }
+        self.bytes(&[tag])?;
+        self.varint(value)
+    }
+
+    /// Skipped when empty.
+    pub fn str_field(&mut self, tag: u8, s: &str) -> Option<()> {
+        if s.is_empty() {
+            return Some(());
+        }
+        self.bytes(&[tag])?;
+        self.varint(s.len() as u64)?;
+        self.bytes(s.as_bytes())
+    }
+
+    /// Writes `s` as UTF-8; skipped when empty.
+    pub fn utf16_field(&mut self, tag: u8, s: &[u16]) -> Option<()> {
+        let len = utf8_len(s);
+        if len == 0 {
+            return Some(());
+        }
+        self.bytes(&[tag])?;
+        self.varint(len as u64)?;
+        let mut utf8 = [0u8; 4];
+        for c in chars(s) {
+            self.bytes(c.encode_utf8(&mut utf8).as_bytes())?;
+        }
+        Some(())
+    }
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE 802.3) of `bytes`, as `shared::ring::crc32`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &b| CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// Writes the prefix of frame number `seq` built at `ts`, the `payload_len`
+/// bytes `encode` produces and zero padding to the start of `out`. Returns the frame
+/// length, or `None` if `out` is too short.
+pub fn write_frame(
+    out: &mut [u8],
+    seq: u64,
+    ts: u64,
+    payload_len: usize,
+    encode: impl FnOnce(&mut Writer<'_>) -> Option<()>,
+) -> Option<usize> {
+    let frame_len = ring_frame_len(payload_len);
+    let out = out.get_mut(..frame_len)?;
+
+    let mut w = Writer { out, at: RING_FRAME_PREFIX };
+    encode(&mut w)?;
+    debug_assert_eq!(w.at, RING_FRAME_PREFIX + payload_len);
+    w.out[w.at..].fill(0);
+
+    // The CRC covers the number, time and payload, so it goes in last.
+    w.out[SEQ_AT..TS_AT].copy_from_slice(&seq.to_le_bytes());
+    w.out[TS_AT..RING_FRAME_PREFIX].copy_from_slice(&ts.to_le_bytes());
+    let crc = crc32(&w.out[SEQ_AT..w.at]);
+    w.out[..2].copy_from_slice(&RING_FRAME_MAGIC.to_le_bytes());
+    w.out[2..6].copy_from_slice(&(payload_len as u32).to_le_bytes());
+    w.out[6..SEQ_AT].copy_from_slice(&crc.to_le_bytes());
+    Some(frame_len)
+}
@@ -0,0 +1,200 @@
+//! IOCTL routing for the control device.
+//!
+//! Kept free of WDK types so it can be host-tested: `device.rs` pulls the
+//! code and buffers out of the IRP, calls [`route`], and completes the IRP
+//! with [`IoctlError::status`] or the number of bytes written. All codes are
+//! `METHOD_BUFFERED`, so input and output share the system buffer; every
+//! request is validated before anything is written, and the only inputs
+//! taken, the network policy and the protected pids, are handed over
+//! before the reply.
+
+use core::{mem::size_of, ptr, slice};
+
+use crate::consts::{
+    NetRule, RingStats, VersionInfo, IOCTL_GLADIX_GET_RING_STATS, IOCTL_GLADIX_GET_VERSION, IOCTL_GLADIX_PING,
+    IOCTL_GLADIX_SET_NET_POLICY, IOCTL_GLADIX_SET_PROTECTED_PIDS, NET_POLICY_MAX_RULES, PING_REPLY,
+    PROTECTED_PIDS_MAX, RING_STATS_LEGACY_SIZE,
+};
+
+/// NTSTATUS values, as `i32` like `wdk_sys::NTSTATUS`.
+pub const STATUS_INVALID_PARAMETER: i32 = 0xC000_000D_u32 as i32;
+pub const STATUS_INVALID_DEVICE_REQUEST: i32 = 0xC000_0010_u32 as i32;
+pub const STATUS_BUFFER_TOO_SMALL: i32 = 0xC000_0023_u32 as i32;
+pub const STATUS_DEVICE_NOT_READY: i32 = 0xC000_00A3_u32 as i32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoctlError {
+    /// Code not in the table.
+    UnknownCode(u32),
+    /// Input sent to a code that takes none.
+    UnexpectedInput(usize),
+    /// Input that is not a whole number of valid rules or pids, or too
+    /// many.
+    BadInput,
+    /// Output buffer shorter than the reply.
+    BufferTooSmall { needed: usize, got: usize },
+    /// The requested state does not exist yet (e.g. no ring allocated).
+    NotReady,
+}
+
+impl IoctlError {
+    pub const fn status(self) -> i32 {
+        match self {
+            IoctlError::UnknownCode(_) => STATUS_INVALID_DEVICE_REQUEST,
+            IoctlError::UnexpectedInput(_) | IoctlError::BadInput => STATUS_INVALID_PARAMETER,
+            IoctlError::BufferTooSmall { .. } => STATUS_BUFFER_TOO_SMALL,
+            IoctlError::NotReady => STATUS_DEVICE_NOT_READY,
+        }
+    }
+}
+
+/// The rules of an [`IOCTL_GLADIX_SET_NET_POLICY`] input, checked by
+/// [`route`] to be at most [`NET_POLICY_MAX_RULES`] valid ones.
+#[derive(Debug, Clone, Copy)]
+pub struct NetRules<'a>(&'a [u8]);
+
+impl NetRules<'_> {
+    pub fn len(&self) -> usize {
+        self.0.len() / size_of::<NetRule>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = NetRule> + '_ {
+        self.0.chunks_exact(size_of::<NetRule>()).map(NetRule::from_bytes)
+    }
+}
+
+/// The pids of an [`IOCTL_GLADIX_SET_PROTECTED_PIDS`] input, checked by
+/// [`route`] to be at most [`PROTECTED_PIDS_MAX`] non-zero ones.
+#[derive(Debug, Clone, Copy)]
+pub struct ProtectedPids<'a>(&'a [u8]);
+
+impl ProtectedPids<'_> {
+    pub fn len(&self) -> usize {
+        self.0.len() / size_of::<u32>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.0.chunks_exact(size_of::<u32>()).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+/// Driver state the IOCTLs report on.
+pub trait IoctlTarget {
+    /// `None` until the event ring exists.
+    fn ring_stats(&self) -> Option<RingStats>;
+
+    /// Replaces the network policy; the rules are only valid for the call.
+    fn set_net_policy(&self, rules: NetRules<'_>) -> Result<(), IoctlError>;
+
+    /// Replaces the processes whose handles are reported.
+    fn set_protected_pids(&self, pids: ProtectedPids<'_>) -> Result<(), IoctlError>;
+}
+
+/// Handles one request and returns the bytes written to `buffer`.
+///
+/// `buffer` is the system buffer: the input in its first `input_len` bytes,
+/// the reply written over it. Codes that take no input reply into all of it.
+pub fn route(
+    code: u32,
+    input_len: usize,
+    buffer: &mut [u8],
+    target: &impl IoctlTarget,
+) -> Result<usize, IoctlError> {
+    let known = matches!(
+        code,
+        IOCTL_GLADIX_PING
+            | IOCTL_GLADIX_GET_VERSION
+            | IOCTL_GLADIX_GET_RING_STATS
+            | IOCTL_GLADIX_SET_NET_POLICY
+            | IOCTL_GLADIX_SET_PROTECTED_PIDS
+    );
+    if !known {
+        return Err(IoctlError::UnknownCode(code));
+    }
+    if code == IOCTL_GLADIX_SET_NET_POLICY {
+        target.set_net_policy(net_rules(buffer, input_len)?)?;
+        return Ok(0);
+    }
+    if code == IOCTL_GLADIX_SET_PROTECTED_PIDS {
+        target.set_protected_pids(protected_pids(buffer, input_len)?)?;
+        return Ok(0);
+    }
+    // None of the other codes take input.
+    if input_len != 0 {
+        return Err(IoctlError::UnexpectedInput(input_len));
+    }
+    let output = buffer;
+    match code {
+        IOCTL_GLADIX_PING => reply(output, &PING_REPLY),
+        IOCTL_GLADIX_GET_VERSION => reply(output, &VersionInfo::CURRENT),
+        _ => {
+            // Check the length first so a short buffer is reported as such
+            // whether or not the ring exists. Agents from before the drop
+            // reasons get the fields they know.
+            let len = size_of::<RingStats>().min(output.len());
+            if len < RING_STATS_LEGACY_SIZE {
+                return Err(IoctlError::BufferTooSmall { needed: size_of::<RingStats>(), got: output.len() });
+            }
+            let stats = target.ring_stats().ok_or(IoctlError::NotReady)?;
+            reply_prefix(output, &stats, len)
+        }
+    }
+}
+
+fn net_rules(buffer: &[u8], input_len: usize) -> Result<NetRules<'_>, IoctlError> {
+    let input = buffer.get(..input_len).ok_or(IoctlError::BadInput)?;
+    let rules = NetRules(input);
+    if !input_len.is_multiple_of(size_of::<NetRule>())
+        || rules.len() > NET_POLICY_MAX_RULES
+        || !rules.iter().all(|r| r.is_valid())
+    {
+        return Err(IoctlError::BadInput);
+    }
+    Ok(rules)
+}
+
+fn protected_pids(buffer: &[u8], input_len: usize) -> Result<ProtectedPids<'_>, IoctlError> {
+    let input = buffer.get(..input_len).ok_or(IoctlError::BadInput)?;
+    let pids = ProtectedPids(input);
+    if !input_len.is_multiple_of(size_of::<u32>()) || pids.len() > PROTECTED_PIDS_MAX || pids.iter().any(|p| p == 0) {
+        return Err(IoctlError::BadInput);
+    }
+    Ok(pids)
+}
+
+fn check_len<T>(output: &[u8]) -> Result<(), IoctlError> {
+    let needed = size_of::<T>();
+    if output.len() < needed {
+        return Err(IoctlError::BufferTooSmall { needed, got: output.len() });
+    }
+    Ok(())
+}
+
+/// Copies `value` to the start of `output`. Reply types are `repr(C)`
+/// without padding, so every byte copied is initialised.
+fn reply<T: Copy>(output: &mut [u8], value: &T) -> Result<usize, IoctlError> {
+    check_len::<T>(output)?;
+    reply_prefix(output, value, size_of::<T>())
+}
+
+/// Copies the first `len` bytes of `value`, at most all of them, to the
+/// start of `output`, which must hold them.
+fn reply_prefix<T: Copy>(output: &mut [u8], value: &T, len: usize) -> Result<usize, IoctlError> {
+    let len = len.min(size_of::<T>());
+    if output.len() < len {
+        return Err(IoctlError::BufferTooSmall { needed: len, got: output.len() });
+    }
+    // SAFETY: `value` is a live `T` of at least `len` bytes and `output`
+    // holds at least `len`; the two cannot overlap.
+    let bytes = unsafe { slice::from_raw_parts(ptr::from_ref(value).cast::<u8>(), len) };
+    output[..len].copy_from_slice(bytes);
+    Ok(len)
+}
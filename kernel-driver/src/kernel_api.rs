@@ -0,0 +1,66 @@
+//! [`KernelApi`] backed by the real kernel routines.
+
+use core::{arch::asm, ffi::c_void};
+
+use wdk_sys::{
+    ntddk::{
+        ExAllocatePool2, ExFreePoolWithTag, KeDelayExecutionThread, KeQuerySystemTimePrecise, MmUnmapViewInSystemSpace,
+        ObfDereferenceObject,
+    },
+    LARGE_INTEGER,
+    _MODE::KernelMode,
+};
+
+use crate::{frame::unix_micros, ownership::KernelApi};
+
+/// `POOL_FLAG_NON_PAGED`.
+const POOL_FLAG_NON_PAGED: u64 = 0x40;
+/// One millisecond, relative (negative) in 100 ns units.
+const DRAIN_INTERVAL: i64 = -10_000;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Wdk;
+
+// SAFETY: thin forwarding to the documented kernel routines.
+unsafe impl KernelApi for Wdk {
+    fn allocate(self, size: usize, tag: u32) -> *mut u8 {
+        // SAFETY: callable at <= DISPATCH_LEVEL for non-paged pool.
+        unsafe { ExAllocatePool2(POOL_FLAG_NON_PAGED, size as u64, tag).cast() }
+    }
+
+    unsafe fn free(self, block: *mut u8, tag: u32) {
+        ExFreePoolWithTag(block.cast(), tag);
+    }
+
+    unsafe fn unmap_view(self, base: *mut c_void) {
+        MmUnmapViewInSystemSpace(base);
+    }
+
+    unsafe fn dereference(self, object: *mut c_void) {
+        ObfDereferenceObject(object);
+    }
+
+    fn current_irql(self) -> u8 {
+        // KeGetCurrentIrql is an inline reading CR8 on x64.
+        let irql: u64;
+        // SAFETY: reading CR8 is side-effect free in kernel mode.
+        unsafe { asm!("mov {}, cr8", out(reg) irql, options(nomem, nostack, preserves_flags)) };
+        irql as u8
+    }
+
+    fn wait_briefly(self) {
+        let mut interval = LARGE_INTEGER { QuadPart: DRAIN_INTERVAL };
+        // SAFETY: PASSIVE_LEVEL is checked by the caller; the interval lives
+        // across the call.
+        unsafe { KeDelayExecutionThread(KernelMode as _, 0, &mut interval) };
+    }
+}
+
+/// The system time as a ring frame timestamp. Callable at any IRQL.
+pub fn now_micros() -> u64 {
+    let mut now = LARGE_INTEGER { QuadPart: 0 };
+    // SAFETY: writes the current time to `now`.
+    unsafe { KeQuerySystemTimePrecise(&mut now) };
+    // SAFETY: every bit pattern is a valid `QuadPart`.
+    unix_micros(unsafe { now.QuadPart })
+}
@@ -20,12 +20,29 @@ extern crate alloc;
 #[cfg(not(test))]
 extern crate wdk_panic;
 
+mod callbacks;
+pub mod consts;
+mod device;
+mod frame;
+pub mod ioctl;
+pub mod kernel_api;
+#[cfg(feature = "minifilter")]
+mod minifilter;
+pub mod ownership;
+mod registry;
+pub mod ring;
+pub mod ring_event;
+mod ring_signal;
+mod sections;
+#[cfg(feature = "wfp")]
+mod wfp;
+
 use alloc::{ffi::CString, slice, string::String};
 
 use wdk::println;
 #[cfg(not(test))]
 use wdk_alloc::WdkAllocator;
-use wdk_sys::{ntddk::DbgPrint, DRIVER_OBJECT, NTSTATUS, PCUNICODE_STRING, STATUS_SUCCESS};
+use wdk_sys::{ntddk::DbgPrint, DRIVER_OBJECT, NTSTATUS, NT_SUCCESS, PCUNICODE_STRING, STATUS_SUCCESS};
 
 #[cfg(not(test))]
 #[global_allocator]
@@ -51,6 +68,80 @@ pub unsafe extern "system" fn driver_entry(
 
     driver.DriverUnload = Some(driver_exit);
 
+    // Before anything that allocates a ring.
+    unsafe { registry::load(registry_path) };
+
+    let status = unsafe { device::create(driver) };
+    if !NT_SUCCESS(status) {
+        return status;
+    }
+
+    // Without the event the agent polls the rings; not worth failing for.
+    let _ = unsafe { ring_event::create() };
+    // Before the callbacks that fill them; one that fails is left out.
+    unsafe { sections::create_all(registry::ring_bytes()) };
+
+    #[cfg(feature = "minifilter")]
+    {
+        let status = unsafe { minifilter::register(driver) };
+        if !NT_SUCCESS(status) {
+            unsafe {
+                sections::delete_all();
+                ring_event::delete();
+                device::delete(driver);
+            }
+            return status;
+        }
+    }
+
+    let status = unsafe { callbacks::psnotify::register() };
+    if !NT_SUCCESS(status) {
+        unsafe {
+            #[cfg(feature = "minifilter")]
+            minifilter::unregister();
+            sections::delete_all();
+            ring_event::delete();
+            device::delete(driver);
+        }
+        return status;
+    }
+
+    let status = unsafe { callbacks::imgnotify::register() };
+    if !NT_SUCCESS(status) {
+        unsafe {
+            callbacks::psnotify::unregister();
+            #[cfg(feature = "minifilter")]
+            minifilter::unregister();
+            sections::delete_all();
+            ring_event::delete();
+            device::delete(driver);
+        }
+        return status;
+    }
+
+    // Refused when the image signature does not satisfy `/INTEGRITYCHECK`;
+    // the driver runs on without the object ring. `register` logs
+    // the status and `unregister` skips a callback that is not there.
+    let _ = unsafe { callbacks::obcallbacks::register() };
+
+    #[cfg(feature = "wfp")]
+    {
+        let status = unsafe { wfp::register(driver) };
+        if !NT_SUCCESS(status) {
+            unsafe {
+                callbacks::obcallbacks::unregister();
+                callbacks::imgnotify::unregister();
+                callbacks::psnotify::unregister();
+                #[cfg(feature = "minifilter")]
+                minifilter::unregister();
+                sections::delete_all();
+                ring_event::delete();
+                device::delete(driver);
+            }
+            return status;
+        }
+    }
+
     // Translate UTF16 string to rust string
     let registry_path: String = String::from_utf16_lossy(unsafe {
         slice::from_raw_parts(
@@ -68,7 +159,21 @@ pub unsafe extern "system" fn driver_entry(
     STATUS_SUCCESS
 }
 
-extern "C" fn driver_exit(_driver: *mut DRIVER_OBJECT) {
+extern "C" fn driver_exit(driver: *mut DRIVER_OBJECT) {
+    // SAFETY: called once by the I/O manager on unload. Callbacks go
+    // first: they write to state torn down after them.
+    unsafe {
+        #[cfg(feature = "wfp")]
+        wfp::unregister();
+        callbacks::obcallbacks::unregister();
+        callbacks::imgnotify::unregister();
+        callbacks::psnotify::unregister();
+        #[cfg(feature = "minifilter")]
+        minifilter::unregister();
+        sections::delete_all();
+        ring_event::delete();
+        device::delete(driver);
+    }
     println!("Goodbye World!");
     println!("Driver Exit Complete!");
 }
\ No newline at end of file
@@ -0,0 +1,56 @@
+//! `FileEvent` frames as the user-agent reads them from an event ring.
+//!
+//! Encoded with `crate::frame`, following `FileEvent` in
+//! `shared/proto/events.proto`. Only `core` is used, so
+//! `tests/file_event.rs` can include this file directly.
+
+use crate::consts::ring_frame_len;
+use crate::frame::{len_tag, utf16_field_len, varint_field_len, varint_tag, write_frame};
+
+/// `FileEvent.Operation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum FileOp {
+    Create = 0,
+    Write  = 1,
+    Delete = 2,
+    Rename = 3,
+}
+
+/// What a minifilter callback reports about one file operation.
+#[derive(Debug, Clone, Copy)]
+pub struct FileEvent<'a> {
+    pub op:   FileOp,
+    /// The name as the Filter Manager returned it, UTF-16 without terminator.
+    /// Unpaired surrogates become U+FFFD.
+    pub path: &'a [u16],
+    /// Process that requested the operation.
+    pub pid:  u32,
+}
+
+const OP: u8 = varint_tag(1);
+const PATH: u8 = len_tag(2);
+const PID: u8 = varint_tag(4);
+
+impl FileEvent<'_> {
+    /// Size of the protobuf encoding.
+    pub fn encoded_len(&self) -> usize {
+        varint_field_len(self.op as u64) + utf16_field_len(self.path) + varint_field_len(self.pid as u64)
+    }
+
+    /// Bytes [`write_frame`](Self::write_frame) needs.
+    pub fn frame_len(&self) -> usize {
+        ring_frame_len(self.encoded_len())
+    }
+
+    /// Writes the prefix of frame number `seq` built at `ts`, the encoding
+    /// and zero padding to the start of `out`. Returns the frame length, or
+    /// `None` if `out` is too short.
+    pub fn write_frame(&self, out: &mut [u8], seq: u64, ts: u64) -> Option<usize> {
+        write_frame(out, seq, ts, self.encoded_len(), |w| {
+            w.varint_field(OP, self.op as u64)?;
+            w.utf16_field(PATH, self.path)?;
+            w.varint_field(PID, self.pid as u64)
+        })
+    }
+}
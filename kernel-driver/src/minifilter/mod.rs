@@ -7,5 +7,185 @@
 //! Key responsibilities:
 //! - Register the minifilter driver at a high altitude.
 //! - Attach to file system volumes.
-//! - Forward selected I/O events to user space via `FltSendMessage`.
+//! - Forward selected I/O events to user space as `FileEvent` ring frames.
 //! - Serve as an integration point for file-specific filters (pre-create, etc.).
+//!
+//! Built with the `minifilter` feature only. The service must be installed
+//! as a minifilter (an `Instances` key with an altitude) for
+//! `FltRegisterFilter` to succeed. `wdk-sys` does not bind `fltKernel.h`, so
+//! the few declarations used are below.
+
+#![allow(non_camel_case_types, non_snake_case)]
+
+pub mod event;
+pub(crate) mod precreate;
+
+use core::{
+    ffi::c_void,
+    mem::size_of,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use wdk::println;
+use wdk_sys::{DRIVER_OBJECT, NTSTATUS, NT_SUCCESS, PDRIVER_OBJECT, STATUS_SUCCESS, UNICODE_STRING};
+
+pub type PFLT_FILTER = *mut c_void;
+
+/// `FLT_CALLBACK_DATA`; only ever handled by pointer.
+#[repr(C)]
+pub struct FLT_CALLBACK_DATA {
+    _opaque: [u8; 0],
+}
+
+/// `FLT_RELATED_OBJECTS`; only ever handled by pointer.
+#[repr(C)]
+pub struct FLT_RELATED_OBJECTS {
+    _opaque: [u8; 0],
+}
+
+/// Leading fields of `FLT_FILE_NAME_INFORMATION`; the Filter Manager owns
+/// the rest.
+#[repr(C)]
+pub struct FLT_FILE_NAME_INFORMATION {
+    pub Size:        u16,
+    pub NamesParsed: u16,
+    pub Format:      u32,
+    pub Name:        UNICODE_STRING,
+}
+
+pub type FLT_PREOP_CALLBACK_STATUS = i32;
+pub const FLT_PREOP_SUCCESS_NO_CALLBACK: FLT_PREOP_CALLBACK_STATUS = 1;
+
+pub type PFLT_PRE_OPERATION_CALLBACK = unsafe extern "system" fn(
+    data: *mut FLT_CALLBACK_DATA,
+    objects: *const FLT_RELATED_OBJECTS,
+    completion_context: *mut *mut c_void,
+) -> FLT_PREOP_CALLBACK_STATUS;
+
+#[repr(C)]
+struct FLT_OPERATION_REGISTRATION {
+    MajorFunction: u8,
+    Flags:         u32,
+    PreOperation:  Option<PFLT_PRE_OPERATION_CALLBACK>,
+    PostOperation: *const c_void,
+    Reserved1:     *const c_void,
+}
+
+#[repr(C)]
+struct FLT_REGISTRATION {
+    Size:                             u16,
+    Version:                          u16,
+    Flags:                            u32,
+    ContextRegistration:              *const c_void,
+    OperationRegistration:            *const FLT_OPERATION_REGISTRATION,
+    FilterUnloadCallback:             *const c_void,
+    InstanceSetupCallback:            *const c_void,
+    InstanceQueryTeardownCallback:    *const c_void,
+    InstanceTeardownStartCallback:    *const c_void,
+    InstanceTeardownCompleteCallback: *const c_void,
+    GenerateFileNameCallback:         *const c_void,
+    NormalizeNameComponentCallback:   *const c_void,
+    NormalizeContextCleanupCallback:  *const c_void,
+    TransactionNotificationCallback:  *const c_void,
+    NormalizeNameComponentExCallback: *const c_void,
+    SectionNotificationCallback:      *const c_void,
+}
+
+// SAFETY: both tables are immutable and only contain pointers to statics
+// and functions.
+unsafe impl Sync for FLT_OPERATION_REGISTRATION {}
+unsafe impl Sync for FLT_REGISTRATION {}
+
+/// `FLT_REGISTRATION_VERSION_0203`, the layout above.
+const FLT_REGISTRATION_VERSION: u16 = 0x0203;
+const IRP_MJ_CREATE: u8 = 0x00;
+const IRP_MJ_OPERATION_END: u8 = 0x80;
+
+#[link(name = "fltmgr")]
+extern "system" {
+    fn FltRegisterFilter(driver: PDRIVER_OBJECT, registration: *const FLT_REGISTRATION, filter: *mut PFLT_FILTER) -> NTSTATUS;
+    fn FltStartFiltering(filter: PFLT_FILTER) -> NTSTATUS;
+    fn FltUnregisterFilter(filter: PFLT_FILTER);
+    pub fn FltGetFileNameInformation(
+        data: *mut FLT_CALLBACK_DATA,
+        options: u32,
+        info: *mut *mut FLT_FILE_NAME_INFORMATION,
+    ) -> NTSTATUS;
+    pub fn FltReleaseFileNameInformation(info: *mut FLT_FILE_NAME_INFORMATION);
+    pub fn FltGetRequestorProcessId(data: *mut FLT_CALLBACK_DATA) -> u32;
+}
+
+static CALLBACKS: [FLT_OPERATION_REGISTRATION; 2] = [
+    FLT_OPERATION_REGISTRATION {
+        MajorFunction: IRP_MJ_CREATE,
+        Flags:         0,
+        PreOperation:  Some(precreate::pre_create),
+        PostOperation: ptr::null(),
+        Reserved1:     ptr::null(),
+    },
+    FLT_OPERATION_REGISTRATION {
+        MajorFunction: IRP_MJ_OPERATION_END,
+        Flags:         0,
+        PreOperation:  None,
+        PostOperation: ptr::null(),
+        Reserved1:     ptr::null(),
+    },
+];
+
+static REGISTRATION: FLT_REGISTRATION = FLT_REGISTRATION {
+    Size:                             size_of::<FLT_REGISTRATION>() as u16,
+    Version:                          FLT_REGISTRATION_VERSION,
+    Flags:                            0,
+    ContextRegistration:              ptr::null(),
+    OperationRegistration:            CALLBACKS.as_ptr(),
+    // Unloading goes through `DriverUnload`, which calls `unregister`.
+    FilterUnloadCallback:             ptr::null(),
+    InstanceSetupCallback:            ptr::null(),
+    InstanceQueryTeardownCallback:    ptr::null(),
+    InstanceTeardownStartCallback:    ptr::null(),
+    InstanceTeardownCompleteCallback: ptr::null(),
+    GenerateFileNameCallback:         ptr::null(),
+    NormalizeNameComponentCallback:   ptr::null(),
+    NormalizeContextCleanupCallback:  ptr::null(),
+    TransactionNotificationCallback:  ptr::null(),
+    NormalizeNameComponentExCallback: ptr::null(),
+    SectionNotificationCallback:      ptr::null(),
+};
+
+/// Handle returned by `FltRegisterFilter`; null when not registered.
+static FILTER: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+
+/// Registers the filter and starts filtering.
+///
+/// # Safety
+/// `driver` must be the object passed to `DriverEntry`.
+pub unsafe fn register(driver: &mut DRIVER_OBJECT) -> NTSTATUS {
+    let mut filter: PFLT_FILTER = ptr::null_mut();
+    let status = FltRegisterFilter(driver, &REGISTRATION, &mut filter);
+    if !NT_SUCCESS(status) {
+        println!("gladix: FltRegisterFilter failed: {status:#x}");
+        return status;
+    }
+    FILTER.store(filter, Ordering::Release);
+
+    let status = FltStartFiltering(filter);
+    if !NT_SUCCESS(status) {
+        println!("gladix: FltStartFiltering failed: {status:#x}");
+        unregister();
+        return status;
+    }
+    STATUS_SUCCESS
+}
+
+/// Unregisters the filter if [`register`] succeeded. Returns once no
+/// callback is running, so whatever they write to can be freed afterwards.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL`, from the unload routine or a failed `register`.
+pub unsafe fn unregister() {
+    let filter = FILTER.swap(ptr::null_mut(), Ordering::AcqRel);
+    if !filter.is_null() {
+        FltUnregisterFilter(filter);
+    }
+}
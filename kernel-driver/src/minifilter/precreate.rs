@@ -10,3 +10,75 @@
 //! - Apply filtering rules to reduce overhead (e.g., skip harmless files).
 //! - Prepare and format messages for user-agent delivery.
 //! - Return early (`FLT_PREOP_SUCCESS_NO_CALLBACK`) for ignored cases.
+//!
+//! Every create is reported for now, as a `FileEvent` with the normalized
+//! NT name (`\Device\HarddiskVolumeN\...`) and the requesting process.
+
+use core::{
+    ffi::c_void,
+    ptr, slice,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use wdk_sys::NT_SUCCESS;
+
+use super::{
+    event::{FileEvent, FileOp},
+    FltGetFileNameInformation, FltGetRequestorProcessId, FltReleaseFileNameInformation, FLT_CALLBACK_DATA,
+    FLT_FILE_NAME_INFORMATION, FLT_PREOP_CALLBACK_STATUS, FLT_PREOP_SUCCESS_NO_CALLBACK, FLT_RELATED_OBJECTS,
+};
+use crate::{
+    frame::Sequence,
+    kernel_api::{now_micros, Wdk},
+    ring::{Push, RingSlot},
+    ring_event,
+};
+
+const FLT_FILE_NAME_NORMALIZED: u32 = 0x0001;
+const FLT_FILE_NAME_QUERY_DEFAULT: u32 = 0x0100;
+
+/// Frames that could not be delivered: refused by the ring, or offered
+/// while there is none (see `sections.rs`).
+pub static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Numbers this ring's frames, so user space can count the dropped ones.
+static SEQ: Sequence = Sequence::new();
+
+/// Where the frames go; `sections` installs the ring.
+pub(crate) static RING: RingSlot<Wdk> = RingSlot::new(Wdk);
+
+fn push(len: usize, write: impl FnOnce(&mut [u8]) -> Option<usize>) {
+    match RING.push_with(len, write) {
+        Push::Published { was_empty } => ring_event::pushed(was_empty),
+        Push::Dropped => {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// `IRP_MJ_CREATE` pre-operation callback.
+pub unsafe extern "system" fn pre_create(
+    data: *mut FLT_CALLBACK_DATA,
+    _objects: *const FLT_RELATED_OBJECTS,
+    _completion_context: *mut *mut c_void,
+) -> FLT_PREOP_CALLBACK_STATUS {
+    let mut info: *mut FLT_FILE_NAME_INFORMATION = ptr::null_mut();
+    let status = FltGetFileNameInformation(data, FLT_FILE_NAME_NORMALIZED | FLT_FILE_NAME_QUERY_DEFAULT, &mut info);
+    if !NT_SUCCESS(status) {
+        return FLT_PREOP_SUCCESS_NO_CALLBACK;
+    }
+
+    let name = &(*info).Name;
+    let path: &[u16] = if name.Buffer.is_null() {
+        &[]
+    } else {
+        // Length is in bytes.
+        slice::from_raw_parts(name.Buffer, name.Length as usize / 2)
+    };
+    let event = FileEvent { op: FileOp::Create, path, pid: FltGetRequestorProcessId(data) };
+    let seq = SEQ.next();
+    push(event.frame_len(), |out| event.write_frame(out, seq, now_micros()));
+
+    FltReleaseFileNameInformation(info);
+    FLT_PREOP_SUCCESS_NO_CALLBACK
+}
@@ -0,0 +1,285 @@
+//! Typed ownership for kernel pointers.
+//!
+//! Raw pointers held across driver entry points (pool allocations, the
+//! mapped ring section, state read by notify callbacks) are wrapped here so
+//! the release order is enforced by the types instead of by comments:
+//!
+//! - [`KernelBox`] owns a pool allocation made with the driver's tag.
+//! - [`SectionMapping`] owns a system-space view and the referenced section
+//!   object; dropping it unmaps the view *before* dereferencing the object.
+//! - [`SharedRef`] publishes state to callbacks. Readers take a
+//!   [`SharedGuard`] without waiting (valid up to `DISPATCH_LEVEL`);
+//!   [`SharedRef::retire`] unpublishes and waits for readers to drain, which
+//!   is only allowed at `PASSIVE_LEVEL`.
+//!
+//! Every kernel call goes through [`KernelApi`] so the logic runs on the
+//! host against a mock; `kernel_api::Wdk` is the real implementation. This
+//! file uses `core` only, so `tests/ownership.rs` can include it directly.
+
+use core::{
+    ffi::c_void,
+    fmt,
+    marker::PhantomData,
+    mem::{align_of, size_of, ManuallyDrop},
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+/// Pool tag of every driver allocation ("Gldx", little-endian).
+pub const POOL_TAG: u32 = u32::from_le_bytes(*b"Gldx");
+
+pub const PASSIVE_LEVEL: u8 = 0;
+pub const DISPATCH_LEVEL: u8 = 2;
+
+/// Kernel services the wrappers depend on.
+///
+/// # Safety
+/// Implementations must behave like the kernel routines they stand for:
+/// `allocate` returns null or a block of at least `size` bytes aligned for
+/// any type up to 16 bytes, valid until `free` with the same tag.
+pub unsafe trait KernelApi: Copy {
+    /// `ExAllocatePool2(POOL_FLAG_NON_PAGED, size, tag)`.
+    fn allocate(self, size: usize, tag: u32) -> *mut u8;
+    /// `ExFreePoolWithTag`.
+    ///
+    /// # Safety
+    /// `block` came from `allocate` with `tag` and is not used afterwards.
+    unsafe fn free(self, block: *mut u8, tag: u32);
+    /// `MmUnmapViewInSystemSpace`.
+    ///
+    /// # Safety
+    /// `base` is a view mapped by the caller and not used afterwards.
+    unsafe fn unmap_view(self, base: *mut c_void);
+    /// `ObDereferenceObject`.
+    ///
+    /// # Safety
+    /// The caller owns one reference to `object`.
+    unsafe fn dereference(self, object: *mut c_void);
+    fn current_irql(self) -> u8;
+    /// Short wait used while draining readers; `PASSIVE_LEVEL` only.
+    fn wait_briefly(self);
+}
+
+/// Operation attempted above the IRQL it allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrongIrql {
+    pub required: u8,
+    pub current:  u8,
+}
+
+impl fmt::Display for WrongIrql {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "requires IRQL {} but running at {}", self.required, self.current)
+    }
+}
+
+/// Owned, tagged pool allocation holding a `T`.
+pub struct KernelBox<T, A: KernelApi> {
+    ptr: NonNull<T>,
+    api: A,
+}
+
+// SAFETY: a KernelBox owns its `T` like `Box` does.
+unsafe impl<T: Send, A: KernelApi + Send> Send for KernelBox<T, A> {}
+unsafe impl<T: Sync, A: KernelApi + Sync> Sync for KernelBox<T, A> {}
+
+impl<T, A: KernelApi> KernelBox<T, A> {
+    /// Moves `value` into non-paged pool; `None` when the pool is exhausted.
+    pub fn new(api: A, value: T) -> Option<Self> {
+        debug_assert!(align_of::<T>() <= 16, "pool blocks are 16-byte aligned");
+        let ptr = NonNull::new(api.allocate(size_of::<T>().max(1), POOL_TAG).cast::<T>())?;
+        // SAFETY: fresh block large enough for `T`.
+        unsafe { ptr.as_ptr().write(value) };
+        Some(Self { ptr, api })
+    }
+
+    /// Gives up ownership, e.g. to store the pointer in a device extension.
+    pub fn into_raw(self) -> *mut T {
+        ManuallyDrop::new(self).ptr.as_ptr()
+    }
+
+    /// # Safety
+    /// `ptr` came from [`into_raw`](Self::into_raw) with the same `api` and
+    /// is not owned by anything else.
+    pub unsafe fn from_raw(api: A, ptr: *mut T) -> Self {
+        Self { ptr: NonNull::new_unchecked(ptr), api }
+    }
+
+    pub fn as_ptr(&self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+}
+
+impl<T, A: KernelApi> Deref for KernelBox<T, A> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // SAFETY: owned and initialised.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T, A: KernelApi> DerefMut for KernelBox<T, A> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: owned, initialised and borrowed mutably through `self`.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T, A: KernelApi> Drop for KernelBox<T, A> {
+    fn drop(&mut self) {
+        // SAFETY: owned block holding an initialised `T`, released once.
+        unsafe {
+            ptr::drop_in_place(self.ptr.as_ptr());
+            self.api.free(self.ptr.as_ptr().cast(), POOL_TAG);
+        }
+    }
+}
+
+/// A section object mapped into system space.
+pub struct SectionMapping<A: KernelApi> {
+    base:   *mut c_void,
+    len:    usize,
+    object: *mut c_void,
+    api:    A,
+}
+
+// SAFETY: system-space views and object references are not thread-bound.
+unsafe impl<A: KernelApi + Send> Send for SectionMapping<A> {}
+unsafe impl<A: KernelApi + Sync> Sync for SectionMapping<A> {}
+
+impl<A: KernelApi> SectionMapping<A> {
+    /// Takes ownership of a view and of one reference to its section.
+    ///
+    /// # Safety
+    /// `base` is a view of `len` bytes mapped with
+    /// `MmMapViewInSystemSpace` from `object`, which the caller referenced.
+    pub unsafe fn from_raw(api: A, object: *mut c_void, base: *mut c_void, len: usize) -> Self {
+        Self { base, len, object, api }
+    }
+
+    pub fn base(&self) -> *mut u8 {
+        self.base.cast()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        !self.base.is_null()
+    }
+
+    /// Unmaps the view and then drops the section reference. Idempotent;
+    /// also run on drop.
+    pub fn release(&mut self) {
+        let base = core::mem::replace(&mut self.base, ptr::null_mut());
+        if !base.is_null() {
+            // SAFETY: mapped by the owner, released exactly once.
+            unsafe { self.api.unmap_view(base) };
+        }
+        let object = core::mem::replace(&mut self.object, ptr::null_mut());
+        if !object.is_null() {
+            // SAFETY: the reference taken in `from_raw`, released once and
+            // only after the view that depends on it.
+            unsafe { self.api.dereference(object) };
+        }
+        self.len = 0;
+    }
+}
+
+impl<A: KernelApi> Drop for SectionMapping<A> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// State published to callbacks and torn down only once they are done.
+pub struct SharedRef<T, A: KernelApi> {
+    ptr:     AtomicPtr<T>,
+    readers: AtomicUsize,
+    api:     A,
+    _owns:   PhantomData<KernelBox<T, A>>,
+}
+
+// SAFETY: access to `T` goes through guards counted in `readers`.
+unsafe impl<T: Send + Sync, A: KernelApi + Send> Send for SharedRef<T, A> {}
+unsafe impl<T: Send + Sync, A: KernelApi + Sync> Sync for SharedRef<T, A> {}
+
+impl<T, A: KernelApi> SharedRef<T, A> {
+    /// Usable in a `static`.
+    pub const fn empty(api: A) -> Self {
+        Self { ptr: AtomicPtr::new(ptr::null_mut()), readers: AtomicUsize::new(0), api, _owns: PhantomData }
+    }
+
+    /// Publishes `value`. Hands it back if something is already published.
+    pub fn publish(&self, value: KernelBox<T, A>) -> Result<(), KernelBox<T, A>> {
+        let raw = value.into_raw();
+        match self.ptr.compare_exchange(ptr::null_mut(), raw, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => Ok(()),
+            // SAFETY: `raw` was ours a moment ago and was not stored.
+            Err(_) => Err(unsafe { KernelBox::from_raw(self.api, raw) }),
+        }
+    }
+
+    /// Reference for the duration of a callback; `None` before publish or
+    /// after retire. Never waits, so any IRQL up to `DISPATCH_LEVEL` is fine.
+    pub fn acquire(&self) -> Option<SharedGuard<'_, T, A>> {
+        self.readers.fetch_add(1, Ordering::SeqCst);
+        match NonNull::new(self.ptr.load(Ordering::SeqCst)) {
+            Some(ptr) => Some(SharedGuard { owner: self, ptr }),
+            None => {
+                self.readers.fetch_sub(1, Ordering::SeqCst);
+                None
+            }
+        }
+    }
+
+    pub fn active_readers(&self) -> usize {
+        self.readers.load(Ordering::SeqCst)
+    }
+
+    /// Unpublishes the value and waits until no guard refers to it, then
+    /// returns it for the caller to drop. `PASSIVE_LEVEL` only, since it
+    /// waits; returns `Ok(None)` if nothing was published.
+    pub fn retire(&self) -> Result<Option<KernelBox<T, A>>, WrongIrql> {
+        let current = self.api.current_irql();
+        if current != PASSIVE_LEVEL {
+            return Err(WrongIrql { required: PASSIVE_LEVEL, current });
+        }
+        let raw = self.ptr.swap(ptr::null_mut(), Ordering::SeqCst);
+        if raw.is_null() {
+            return Ok(None);
+        }
+        // New readers now see null; wait for the ones that saw `raw`.
+        while self.readers.load(Ordering::SeqCst) != 0 {
+            self.api.wait_briefly();
+        }
+        // SAFETY: published from a KernelBox and unreachable by readers now.
+        Ok(Some(unsafe { KernelBox::from_raw(self.api, raw) }))
+    }
+}
+
+/// Counted reference handed out by [`SharedRef::acquire`].
+pub struct SharedGuard<'a, T, A: KernelApi> {
+    owner: &'a SharedRef<T, A>,
+    ptr:   NonNull<T>,
+}
+
+impl<T, A: KernelApi> Deref for SharedGuard<'_, T, A> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // SAFETY: `retire` does not free while this guard is counted.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T, A: KernelApi> Drop for SharedGuard<'_, T, A> {
+    fn drop(&mut self) {
+        self.owner.readers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,100 @@
+//! Driver parameters under the service key (`<registry path>\Parameters`),
+//! read once from `DriverEntry`.
+//!
+//! Only [`RING_SIZE_VALUE`] is read. A missing key or value, or one of the
+//! wrong type, leaves [`RING_SIZE_DEFAULT`]; whatever is read goes through
+//! [`ring_size`], so the ring is always a whole number of pages within
+//! bounds.
+
+use alloc::vec::Vec;
+use core::{
+    mem::size_of,
+    ptr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use wdk::println;
+use wdk_sys::{
+    ntddk::{ZwClose, ZwOpenKey, ZwQueryValueKey},
+    HANDLE, KEY_READ, KEY_VALUE_PARTIAL_INFORMATION, NT_SUCCESS, OBJECT_ATTRIBUTES, OBJ_CASE_INSENSITIVE,
+    OBJ_KERNEL_HANDLE, PCUNICODE_STRING, REG_DWORD, UNICODE_STRING,
+    _KEY_VALUE_INFORMATION_CLASS::KeyValuePartialInformation,
+};
+
+use crate::consts::{ring_size, PARAMETERS_KEY, RING_SIZE_DEFAULT, RING_SIZE_VALUE};
+
+/// Data area of each ring, as negotiated at load. The producer allocates
+/// this much and reports it in `RingStats::size`.
+static RING_SIZE: AtomicU32 = AtomicU32::new(RING_SIZE_DEFAULT);
+
+pub fn ring_bytes() -> u32 {
+    RING_SIZE.load(Ordering::Relaxed)
+}
+
+fn unicode(s: &[u16]) -> UNICODE_STRING {
+    let bytes = (s.len() * 2) as u16;
+    // The kernel only reads through `Buffer`.
+    UNICODE_STRING { Length: bytes, MaximumLength: bytes, Buffer: s.as_ptr().cast_mut() }
+}
+
+/// Reads the parameters of the service at `registry_path`.
+///
+/// # Safety
+/// `registry_path` must be the one passed to `DriverEntry`; call at
+/// `PASSIVE_LEVEL`.
+pub unsafe fn load(registry_path: PCUNICODE_STRING) {
+    let requested = read_dword(registry_path, &RING_SIZE_VALUE);
+    let size = requested.map_or(RING_SIZE_DEFAULT, ring_size);
+    RING_SIZE.store(size, Ordering::Relaxed);
+    match requested {
+        Some(requested) if requested != size => {
+            println!("gladix: RingSizeBytes {requested} adjusted to {size}");
+        }
+        _ => println!("gladix: ring size {size} bytes"),
+    }
+}
+
+/// A `REG_DWORD` of the `Parameters` key.
+unsafe fn read_dword(registry_path: PCUNICODE_STRING, value: &[u16]) -> Option<u32> {
+    let service = &*registry_path;
+    let units = service.Length as usize / size_of::<u16>();
+    let mut path: Vec<u16> = Vec::with_capacity(units + PARAMETERS_KEY.len());
+    path.extend_from_slice(core::slice::from_raw_parts(service.Buffer, units));
+    path.extend_from_slice(&PARAMETERS_KEY);
+    let mut name = unicode(&path);
+
+    let mut attributes = OBJECT_ATTRIBUTES {
+        Length: size_of::<OBJECT_ATTRIBUTES>() as u32,
+        RootDirectory: ptr::null_mut(),
+        ObjectName: &mut name,
+        Attributes: OBJ_CASE_INSENSITIVE | OBJ_KERNEL_HANDLE,
+        SecurityDescriptor: ptr::null_mut(),
+        SecurityQualityOfService: ptr::null_mut(),
+    };
+    let mut key: HANDLE = ptr::null_mut();
+    if !NT_SUCCESS(ZwOpenKey(&mut key, KEY_READ, &mut attributes)) {
+        return None;
+    }
+
+    let mut value_name = unicode(value);
+    // Header plus the four bytes of a DWORD.
+    let mut info = [0u8; size_of::<KEY_VALUE_PARTIAL_INFORMATION>() + 4];
+    let mut written = 0u32;
+    let status = ZwQueryValueKey(
+        key,
+        &mut value_name,
+        KeyValuePartialInformation,
+        info.as_mut_ptr().cast(),
+        info.len() as u32,
+        &mut written,
+    );
+    ZwClose(key);
+    if !NT_SUCCESS(status) {
+        return None;
+    }
+    let info = &*info.as_ptr().cast::<KEY_VALUE_PARTIAL_INFORMATION>();
+    if info.Type != REG_DWORD || info.DataLength != 4 {
+        return None;
+    }
+    Some(ptr::read_unaligned(info.Data.as_ptr().cast::<u32>()))
+}
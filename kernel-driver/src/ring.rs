@@ -0,0 +1,258 @@
+//! Producer side of the event rings.
+//!
+//! A frame is written straight into the ring: [`EventRing::push_with`]
+//! reserves room for it, hands that slice to the caller's encoder and only
+//! publishes the tail once the encoder filled it exactly. An encoder that
+//! fails leaves the tail where it was, so the reader never sees its bytes,
+//! and nothing is allocated per event, which callbacks running above
+//! `PASSIVE_LEVEL` cannot do with paged pool anyway.
+//!
+//! The layout is `shared::ring` (the driver cannot depend on `shared`):
+//! frames never cross the end of the data area, one that does not fit
+//! before it starts at 0 and the bytes left behind read as padding. Only
+//! `core` and `ownership` are used, so `tests/ring.rs` can include this
+//! file directly.
+
+use core::{
+    hint,
+    ptr,
+    slice,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+};
+
+use crate::{
+    consts::{RingStats, RING_HEADER_SIZE, RING_VERSION},
+    ownership::{KernelApi, KernelBox, SharedRef, WrongIrql},
+};
+
+/// Start of every ring section, as `shared::ring::RingHeader`.
+#[repr(C)]
+pub struct RingHeader {
+    /// Read offset into the data area, advanced by the consumer.
+    pub head:    AtomicU64,
+    /// Write offset into the data area, advanced by the producer.
+    pub tail:    AtomicU64,
+    /// Events discarded, for whatever reason.
+    pub dropped: AtomicU32,
+    pub version: u32,
+    /// Of `dropped`, frames refused by [`reserve`] with [`NoRoom::Full`].
+    pub dropped_full:     AtomicU32,
+    /// Of `dropped`, frames refused with [`NoRoom::Oversize`].
+    pub dropped_oversize: AtomicU32,
+    /// Longest frame offered, in bytes.
+    pub max_observed_len: AtomicU32,
+    pub reserved:         u32,
+}
+
+impl RingHeader {
+    /// An empty ring using the current layout.
+    pub const fn new() -> Self {
+        Self {
+            head:             AtomicU64::new(0),
+            tail:             AtomicU64::new(0),
+            dropped:          AtomicU32::new(0),
+            version:          RING_VERSION,
+            dropped_full:     AtomicU32::new(0),
+            dropped_oversize: AtomicU32::new(0),
+            max_observed_len: AtomicU32::new(0),
+            reserved:         0,
+        }
+    }
+
+    fn refused(&self, reason: NoRoom) -> &AtomicU32 {
+        match reason {
+            NoRoom::Full => &self.dropped_full,
+            NoRoom::Oversize => &self.dropped_oversize,
+        }
+    }
+}
+
+const _: () = assert!(core::mem::size_of::<RingHeader>() == RING_HEADER_SIZE);
+
+/// Room found for a frame by [`reserve`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reserved {
+    /// Where the frame starts in the data area.
+    pub at:   usize,
+    /// The frame did not fit before the end and starts at 0; the bytes from
+    /// the old tail to the end must be zeroed.
+    pub wrap: bool,
+    /// Tail to publish once the frame is written.
+    pub tail: usize,
+}
+
+/// Why [`reserve`] found no room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoRoom {
+    /// Not before the head: the consumer is behind.
+    Full,
+    /// Not even in an empty ring: the frame is as long as the data area or
+    /// longer, which no consumer can help.
+    Oversize,
+}
+
+/// Where a frame of `len` bytes goes in a data area of `size` bytes whose
+/// consumer is at `head` and producer at `tail`. `head == tail` means empty,
+/// so the tail never catches up with the head, and offsets outside the data
+/// area find no room.
+pub fn reserve(head: usize, tail: usize, size: usize, len: usize) -> Result<Reserved, NoRoom> {
+    if len >= size {
+        return Err(NoRoom::Oversize);
+    }
+    if head > size || tail >= size {
+        return Err(NoRoom::Full);
+    }
+    let (at, wrap) = if tail >= head {
+        let end = tail + len;
+        if end < size || (end == size && head != 0) {
+            (tail, false)
+        } else if len < head {
+            (0, true)
+        } else {
+            return Err(NoRoom::Full);
+        }
+    } else if tail + len < head {
+        (tail, false)
+    } else {
+        return Err(NoRoom::Full);
+    };
+    let end = at + len;
+    Ok(Reserved { at, wrap, tail: if end == size { 0 } else { end } })
+}
+
+/// What became of a frame offered to a ring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Push {
+    /// Published; `was_empty` when the ring held nothing before it.
+    Published { was_empty: bool },
+    /// No room, no ring, or the encoder failed.
+    Dropped,
+}
+
+/// A ring section mapped for this driver and the agent.
+pub struct EventRing {
+    header: *const RingHeader,
+    data:   *mut u8,
+    size:   usize,
+    /// Callbacks on several processors push at once; the reservation and
+    /// the write happen under this lock.
+    busy:   AtomicBool,
+}
+
+// SAFETY: the pointers stay valid for the ring's lifetime (see `new`), and
+// the data area is only written under `busy`.
+unsafe impl Send for EventRing {}
+unsafe impl Sync for EventRing {}
+
+impl EventRing {
+    /// # Safety
+    /// `header` and the `size` bytes at `data` must stay mapped, and be
+    /// written by no other producer, for as long as the ring is used.
+    pub const unsafe fn new(header: *const RingHeader, data: *mut u8, size: usize) -> Self {
+        Self { header, data, size, busy: AtomicBool::new(false) }
+    }
+
+    fn header(&self) -> &RingHeader {
+        // SAFETY: valid while the ring is used, per `new`.
+        unsafe { &*self.header }
+    }
+
+    /// Reserves `len` bytes and lets `write` fill them with a whole frame,
+    /// returning the length it wrote. The frame is published only if that is
+    /// `len`; otherwise, or without room, the event is counted in `dropped`,
+    /// and a frame refused for lack of room in the counter of its
+    /// [`NoRoom`]. `len` is measured against `max_observed_len` either way.
+    /// Callable at `IRQL <= DISPATCH_LEVEL`.
+    pub fn push_with(&self, len: usize, write: impl FnOnce(&mut [u8]) -> Option<usize>) -> Push {
+        while self.busy.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            hint::spin_loop();
+        }
+        let pushed = self.push_locked(len, write);
+        self.busy.store(false, Ordering::Release);
+        if pushed == Push::Dropped {
+            self.header().dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        pushed
+    }
+
+    /// The header as `IOCTL_GLADIX_GET_RING_STATS` replies it.
+    pub fn stats(&self) -> RingStats {
+        let header = self.header();
+        RingStats {
+            head:             header.head.load(Ordering::Acquire),
+            tail:             header.tail.load(Ordering::Acquire),
+            dropped:          header.dropped.load(Ordering::Relaxed),
+            size:             self.size as u32,
+            dropped_full:     header.dropped_full.load(Ordering::Relaxed),
+            dropped_oversize: header.dropped_oversize.load(Ordering::Relaxed),
+            max_observed_len: header.max_observed_len.load(Ordering::Relaxed),
+            reserved:         0,
+        }
+    }
+
+    fn push_locked(&self, len: usize, write: impl FnOnce(&mut [u8]) -> Option<usize>) -> Push {
+        let header = self.header();
+        let head = header.head.load(Ordering::Acquire) as usize;
+        let tail = header.tail.load(Ordering::Relaxed) as usize;
+        header.max_observed_len.fetch_max(u32::try_from(len).unwrap_or(u32::MAX), Ordering::Relaxed);
+        let reserved = match reserve(head, tail, self.size, len) {
+            Ok(reserved) => reserved,
+            Err(reason) => {
+                header.refused(reason).fetch_add(1, Ordering::Relaxed);
+                return Push::Dropped;
+            }
+        };
+        // SAFETY: `reserve` keeps both ranges within the data area and clear
+        // of what the consumer has yet to read; `busy` keeps other producers
+        // out.
+        if reserved.wrap {
+            unsafe { ptr::write_bytes(self.data.add(tail), 0, self.size - tail) };
+        }
+        let frame = unsafe { slice::from_raw_parts_mut(self.data.add(reserved.at), len) };
+        if write(frame) != Some(len) {
+            return Push::Dropped;
+        }
+        header.tail.store(reserved.tail as u64, Ordering::Release);
+        Push::Published { was_empty: head == tail }
+    }
+}
+
+/// The ring a callback pushes to, once one is installed.
+///
+/// The ring is published through a [`SharedRef`]: every push holds a guard,
+/// and [`retire`](Self::retire) waits for the pushes in flight, so the view
+/// under a retired ring can be unmapped even if its producer is still
+/// registered. Later pushes find the slot empty and drop their event.
+pub struct RingSlot<A: KernelApi>(SharedRef<EventRing, A>);
+
+impl<A: KernelApi> RingSlot<A> {
+    pub const fn new(api: A) -> Self {
+        Self(SharedRef::empty(api))
+    }
+
+    /// Makes `ring` the one pushed to. Hands it back if a ring is already
+    /// installed.
+    pub fn install(&self, ring: KernelBox<EventRing, A>) -> Result<(), KernelBox<EventRing, A>> {
+        self.0.publish(ring)
+    }
+
+    /// Stops pushing to the installed ring and returns it once no push is
+    /// using it. `PASSIVE_LEVEL` only, since it waits.
+    pub fn retire(&self) -> Result<Option<KernelBox<EventRing, A>>, WrongIrql> {
+        self.0.retire()
+    }
+
+    /// [`EventRing::stats`] of the installed ring.
+    pub fn stats(&self) -> Option<RingStats> {
+        self.0.acquire().map(|ring| ring.stats())
+    }
+
+    /// [`EventRing::push_with`] on the installed ring. Without one the event
+    /// is dropped before `write` runs, so it costs no encoding.
+    pub fn push_with(&self, len: usize, write: impl FnOnce(&mut [u8]) -> Option<usize>) -> Push {
+        match self.0.acquire() {
+            Some(ring) => ring.push_with(len, write),
+            None => Push::Dropped,
+        }
+    }
+}
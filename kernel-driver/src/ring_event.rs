@@ -0,0 +1,123 @@
+//! Named event that wakes the user-agent's ring consumers.
+//!
+//! `\BaseNamedObjects\GladixRingEvent` (`Global\GladixRingEvent` to user
+//! mode) is an auto-reset event the agent opens with `OpenEventW` and waits
+//! on instead of polling the rings. Producers call [`pushed`] after
+//! publishing a frame; [`RingSignal`] decides when the event is actually
+//! set. Agents that cannot open it keep polling, so failing to create it
+//! does not stop the driver. The ring sections (`sections.rs`) get the
+//! same descriptor.
+
+use core::{
+    ffi::c_void,
+    mem::size_of,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use wdk::println;
+use wdk_sys::{
+    ntddk::{
+        KeSetEvent, ObReferenceObjectByHandle, ObfDereferenceObject, RtlCreateSecurityDescriptor,
+        RtlSetDaclSecurityDescriptor, ZwClose, ZwCreateEvent,
+    },
+    ExEventObjectType, EVENT_ALL_ACCESS, EVENT_MODIFY_STATE, HANDLE, IO_NO_INCREMENT, NTSTATUS, NT_SUCCESS,
+    OBJECT_ATTRIBUTES, OBJ_KERNEL_HANDLE, OBJ_OPENIF, PKEVENT, PVOID, SECURITY_DESCRIPTOR,
+    SECURITY_DESCRIPTOR_REVISION, UNICODE_STRING, _EVENT_TYPE::SynchronizationEvent, _MODE::KernelMode,
+};
+
+use crate::{consts::RING_EVENT_NAME, kernel_api::now_micros, ring_signal::RingSignal};
+
+/// Referenced event object; null when there is none.
+static EVENT: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+/// Kernel handle keeping the name alive.
+static HANDLE: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+
+static SIGNAL: RingSignal = RingSignal::new();
+
+/// A descriptor with a NULL DACL, which grants everyone access: the agent
+/// may run as any user.
+pub(crate) unsafe fn world_descriptor(sd: &mut SECURITY_DESCRIPTOR) -> NTSTATUS {
+    let status = RtlCreateSecurityDescriptor((sd as *mut SECURITY_DESCRIPTOR).cast(), SECURITY_DESCRIPTOR_REVISION);
+    if !NT_SUCCESS(status) {
+        return status;
+    }
+    RtlSetDaclSecurityDescriptor((sd as *mut SECURITY_DESCRIPTOR).cast(), 1, ptr::null_mut(), 0)
+}
+
+/// Creates the event. A failure is logged and returned; the caller carries
+/// on without it.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL` from `DriverEntry`.
+pub unsafe fn create() -> NTSTATUS {
+    let mut sd: SECURITY_DESCRIPTOR = core::mem::zeroed();
+    let status = world_descriptor(&mut sd);
+    if !NT_SUCCESS(status) {
+        println!("gladix: ring event security descriptor failed: {status:#x}");
+        return status;
+    }
+    let bytes = (RING_EVENT_NAME.len() * 2) as u16;
+    // The object manager only reads the name.
+    let mut name = UNICODE_STRING { Length: bytes, MaximumLength: bytes, Buffer: RING_EVENT_NAME.as_ptr().cast_mut() };
+    let mut attributes = OBJECT_ATTRIBUTES {
+        Length:                   size_of::<OBJECT_ATTRIBUTES>() as u32,
+        RootDirectory:            ptr::null_mut(),
+        ObjectName:               &mut name,
+        Attributes:               OBJ_KERNEL_HANDLE | OBJ_OPENIF,
+        SecurityDescriptor:       (&mut sd as *mut SECURITY_DESCRIPTOR).cast(),
+        SecurityQualityOfService: ptr::null_mut(),
+    };
+    let mut handle: HANDLE = ptr::null_mut();
+    let status = ZwCreateEvent(&mut handle, EVENT_ALL_ACCESS, &mut attributes, SynchronizationEvent, 0);
+    if !NT_SUCCESS(status) {
+        println!("gladix: ring event not created: {status:#x}");
+        return status;
+    }
+    let mut event: PVOID = ptr::null_mut();
+    let status = ObReferenceObjectByHandle(
+        handle,
+        EVENT_MODIFY_STATE,
+        *ExEventObjectType,
+        KernelMode as _,
+        &mut event,
+        ptr::null_mut(),
+    );
+    if !NT_SUCCESS(status) {
+        println!("gladix: ring event not referenced: {status:#x}");
+        ZwClose(handle);
+        return status;
+    }
+    HANDLE.store(handle, Ordering::Release);
+    EVENT.store(event, Ordering::Release);
+    status
+}
+
+/// Wakes the agent if [`RingSignal`] says so. `was_empty` when the ring
+/// held nothing before the frame just published. Callable at
+/// `IRQL <= DISPATCH_LEVEL`.
+pub fn pushed(was_empty: bool) {
+    let event = EVENT.load(Ordering::Acquire);
+    if event.is_null() || !SIGNAL.pushed(was_empty, now_micros()) {
+        return;
+    }
+    // SAFETY: the object stays referenced until `delete`, which runs after
+    // every producer is gone.
+    unsafe { KeSetEvent(event as PKEVENT, IO_NO_INCREMENT as i32, 0) };
+}
+
+/// Drops what [`create`] made.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL` from the unload routine, after the callbacks
+/// that push frames are removed.
+pub unsafe fn delete() {
+    let event = EVENT.swap(ptr::null_mut(), Ordering::AcqRel);
+    if !event.is_null() {
+        ObfDereferenceObject(event);
+    }
+    let handle = HANDLE.swap(ptr::null_mut(), Ordering::AcqRel);
+    if !handle.is_null() {
+        ZwClose(handle);
+    }
+}
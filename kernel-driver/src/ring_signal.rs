@@ -0,0 +1,51 @@
+//! When the producers set the ring event.
+//!
+//! A push that finds its ring empty marks a wakeup as pending; the pending
+//! wakeup is delivered at most once per [`SIGNAL_INTERVAL_MICROS`], by that
+//! push or the first one after the interval. A busy ring therefore costs no
+//! `KeSetEvent` per frame, and a wakeup held back by the interval is found
+//! by the consumer's wait timeout if no later push delivers it. Only `core`
+//! is used, so `tests/ring_signal.rs` can include this file directly.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Shortest time between two signals, in microseconds.
+pub const SIGNAL_INTERVAL_MICROS: u64 = 1_000;
+
+/// Throttle shared by every ring that signals the same event.
+pub struct RingSignal {
+    /// A push found its ring empty and the consumer has not been woken since.
+    pending: AtomicBool,
+    /// Frame timestamp of the last signal.
+    last:    AtomicU64,
+}
+
+impl RingSignal {
+    pub const fn new() -> Self {
+        Self { pending: AtomicBool::new(false), last: AtomicU64::new(0) }
+    }
+
+    /// Called after a frame is published at `now` (microseconds, as in the
+    /// frame prefix); `was_empty` when the ring held nothing before it.
+    /// Returns whether to set the event.
+    pub fn pushed(&self, was_empty: bool, now: u64) -> bool {
+        if was_empty {
+            self.pending.store(true, Ordering::Relaxed);
+        }
+        if !self.pending.load(Ordering::Relaxed) {
+            return false;
+        }
+        let last = self.last.load(Ordering::Relaxed);
+        if now.saturating_sub(last) < SIGNAL_INTERVAL_MICROS {
+            return false;
+        }
+        // Producers of other rings may get here at once; one signal is enough.
+        if self.last.compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed).is_err() {
+            return false;
+        }
+        // Frames published before this point are visible to the woken
+        // consumer, so clearing after a racing push marked it loses nothing.
+        self.pending.store(false, Ordering::Relaxed);
+        true
+    }
+}
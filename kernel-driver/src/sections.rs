@@ -0,0 +1,233 @@
+//! Sections backing the event rings.
+//!
+//! Each ring is a paging-file section named under `\BaseNamedObjects\`
+//! (`consts::PROCESS_RING_NAME` and the others) with the world-access
+//! descriptor of the ring event, so the agent can map it whatever user it
+//! runs as. It holds the `RingHeader` and the data area negotiated in
+//! `registry`. The driver maps it into system space and installs an
+//! [`EventRing`] over it in the [`RingSlot`] of the callback that fills it.
+//!
+//! A ring that cannot be created is logged and left out: its callback
+//! keeps dropping frames, and the agent, finding no section, reports the
+//! source unavailable. [`delete_all`] retires each ring from its slot, which
+//! waits for the pushes in flight, before unmapping the view: a producer
+//! whose unregistration failed (a WFP callout that stays busy) finds the
+//! slot empty and drops its frame instead of writing to freed memory.
+
+use core::{
+    mem::size_of,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use wdk::println;
+use wdk_sys::{
+    ntddk::{MmMapViewInSystemSpace, ObReferenceObjectByHandle, ObfDereferenceObject, ZwClose, ZwCreateSection},
+    MmSectionObjectType, HANDLE, LARGE_INTEGER, NTSTATUS, NT_SUCCESS, OBJECT_ATTRIBUTES, OBJ_KERNEL_HANDLE,
+    PAGE_READWRITE, PVOID, SECTION_ALL_ACCESS, SECTION_MAP_READ, SECTION_MAP_WRITE, SECURITY_DESCRIPTOR, SEC_COMMIT,
+    SIZE_T, STATUS_INSUFFICIENT_RESOURCES, STATUS_SUCCESS, STATUS_UNSUCCESSFUL, UNICODE_STRING, _MODE::KernelMode,
+};
+
+#[cfg(feature = "minifilter")]
+use crate::{
+    consts::{FILE_RING, FILE_RING_NAME},
+    minifilter::precreate,
+};
+#[cfg(feature = "wfp")]
+use crate::{
+    consts::{NETWORK_RING, NETWORK_RING_NAME},
+    wfp::ale_flow,
+};
+use crate::{
+    callbacks::{imgnotify, obcallbacks, psnotify},
+    consts::{
+        RingStats, IMAGE_RING, IMAGE_RING_NAME, OBJECT_RING, OBJECT_RING_NAME, PROCESS_RING, PROCESS_RING_NAME,
+        RING_HEADER_SIZE,
+    },
+    kernel_api::Wdk,
+    ownership::{KernelBox, SectionMapping},
+    ring::{EventRing, RingHeader, RingSlot},
+    ring_event::world_descriptor,
+};
+
+/// A created ring section: the name and the view the ring writes to.
+struct Section {
+    /// Kernel handle keeping the name alive.
+    handle:  HANDLE,
+    mapping: SectionMapping<Wdk>,
+}
+
+impl Drop for Section {
+    fn drop(&mut self) {
+        // SAFETY: the handle from `ZwCreateSection`, closed once. `mapping`
+        // holds its own reference to the section until the view is gone.
+        unsafe { ZwClose(self.handle) };
+    }
+}
+
+/// One ring section and the slot of its producer.
+pub struct RingSection {
+    /// For the log.
+    label: &'static str,
+    name:  &'static [u16],
+    slot:  &'static RingSlot<Wdk>,
+    live:  AtomicPtr<Section>,
+}
+
+pub static PROCESS: RingSection = RingSection::new(PROCESS_RING, &PROCESS_RING_NAME, &psnotify::RING);
+pub static IMAGE: RingSection = RingSection::new(IMAGE_RING, &IMAGE_RING_NAME, &imgnotify::RING);
+pub static OBJECT: RingSection = RingSection::new(OBJECT_RING, &OBJECT_RING_NAME, &obcallbacks::RING);
+#[cfg(feature = "wfp")]
+pub static NETWORK: RingSection = RingSection::new(NETWORK_RING, &NETWORK_RING_NAME, &ale_flow::RING);
+#[cfg(feature = "minifilter")]
+pub static FILE: RingSection = RingSection::new(FILE_RING, &FILE_RING_NAME, &precreate::RING);
+
+impl RingSection {
+    const fn new(label: &'static str, name: &'static [u16], slot: &'static RingSlot<Wdk>) -> Self {
+        Self { label, name, slot, live: AtomicPtr::new(ptr::null_mut()) }
+    }
+
+    /// The installed ring's counters; `None` when it was not created.
+    pub fn stats(&self) -> Option<RingStats> {
+        self.slot.stats()
+    }
+
+    /// Creates the section with a data area of `size` bytes, maps it and
+    /// installs the ring. A failure is logged and returned.
+    ///
+    /// # Safety
+    /// Call at `PASSIVE_LEVEL` from `DriverEntry`, once.
+    unsafe fn create(&self, size: u32) -> NTSTATUS {
+        let mut sd: SECURITY_DESCRIPTOR = core::mem::zeroed();
+        let status = world_descriptor(&mut sd);
+        if !NT_SUCCESS(status) {
+            println!("gladix: ring section security descriptor failed: {status:#x}");
+            return status;
+        }
+        let bytes = (self.name.len() * 2) as u16;
+        // The object manager only reads the name.
+        let mut name = UNICODE_STRING { Length: bytes, MaximumLength: bytes, Buffer: self.name.as_ptr().cast_mut() };
+        let mut attributes = OBJECT_ATTRIBUTES {
+            Length:                   size_of::<OBJECT_ATTRIBUTES>() as u32,
+            RootDirectory:            ptr::null_mut(),
+            ObjectName:               &mut name,
+            Attributes:               OBJ_KERNEL_HANDLE,
+            SecurityDescriptor:       (&mut sd as *mut SECURITY_DESCRIPTOR).cast(),
+            SecurityQualityOfService: ptr::null_mut(),
+        };
+        let total = RING_HEADER_SIZE + size as usize;
+        let mut maximum = LARGE_INTEGER { QuadPart: total as i64 };
+        let mut handle: HANDLE = ptr::null_mut();
+        // A section left by an agent still mapping the one of a previous
+        // load is not reused: its header belongs to that load.
+        let status = ZwCreateSection(
+            &mut handle,
+            SECTION_ALL_ACCESS,
+            &mut attributes,
+            &mut maximum,
+            PAGE_READWRITE,
+            SEC_COMMIT,
+            ptr::null_mut(),
+        );
+        if !NT_SUCCESS(status) {
+            println!("gladix: ring section {} not created: {status:#x}", self.label);
+            return status;
+        }
+        let mut object: PVOID = ptr::null_mut();
+        let status = ObReferenceObjectByHandle(
+            handle,
+            SECTION_MAP_READ | SECTION_MAP_WRITE,
+            *MmSectionObjectType,
+            KernelMode as _,
+            &mut object,
+            ptr::null_mut(),
+        );
+        if !NT_SUCCESS(status) {
+            println!("gladix: ring section {} not referenced: {status:#x}", self.label);
+            ZwClose(handle);
+            return status;
+        }
+        let mut base: PVOID = ptr::null_mut();
+        let mut view = total as SIZE_T;
+        let status = MmMapViewInSystemSpace(object, &mut base, &mut view);
+        if !NT_SUCCESS(status) {
+            println!("gladix: ring section {} not mapped: {status:#x}", self.label);
+            ObfDereferenceObject(object);
+            ZwClose(handle);
+            return status;
+        }
+
+        // SAFETY: a fresh view of `total` bytes, page-aligned; the ring keeps
+        // to the `size` bytes after the header and the view outlives it.
+        let header = base.cast::<RingHeader>();
+        header.write(RingHeader::new());
+        let ring = EventRing::new(header, base.cast::<u8>().add(RING_HEADER_SIZE), size as usize);
+        let mapping = SectionMapping::from_raw(Wdk, object, base, view as usize);
+        // Without the pool blocks, dropping the section undoes the above.
+        let section = Section { handle, mapping };
+        let (Some(section), Some(ring)) = (KernelBox::new(Wdk, section), KernelBox::new(Wdk, ring)) else {
+            println!("gladix: ring section {} not allocated", self.label);
+            return STATUS_INSUFFICIENT_RESOURCES;
+        };
+        if let Err(ring) = self.slot.install(ring) {
+            println!("gladix: ring section {} installed twice", self.label);
+            drop((ring, section));
+            return STATUS_UNSUCCESSFUL;
+        }
+        // The view is unmapped only by `delete`, once the slot has retired
+        // the ring.
+        self.live.store(section.into_raw(), Ordering::Release);
+        println!("gladix: ring section {} of {size} bytes", self.label);
+        STATUS_SUCCESS
+    }
+
+    /// Retires the ring, waiting for the pushes in flight, and frees what
+    /// [`create`](Self::create) made. A ring that cannot be retired keeps
+    /// its view mapped: it is leaked rather than unmapped under a producer.
+    ///
+    /// # Safety
+    /// Call at `PASSIVE_LEVEL`.
+    unsafe fn delete(&self) {
+        if let Err(error) = self.slot.retire() {
+            println!("gladix: ring section {} kept mapped: {error}", self.label);
+            return;
+        }
+        let section = self.live.swap(ptr::null_mut(), Ordering::AcqRel);
+        if !section.is_null() {
+            drop(KernelBox::from_raw(Wdk, section));
+        }
+    }
+}
+
+/// Every ring [`create_all`] makes.
+fn all() -> impl Iterator<Item = &'static RingSection> {
+    let rings: [&'static RingSection; 3] = [&PROCESS, &IMAGE, &OBJECT];
+    #[cfg(feature = "wfp")]
+    let rings = rings.into_iter().chain([&NETWORK]);
+    #[cfg(feature = "minifilter")]
+    let rings = rings.into_iter().chain([&FILE]);
+    rings.into_iter()
+}
+
+/// Creates every ring with a data area of `size` bytes. One that fails is
+/// logged and left out.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL` from `DriverEntry`, once, before the callbacks
+/// are registered.
+pub unsafe fn create_all(size: u32) {
+    for ring in all() {
+        let _ = ring.create(size);
+    }
+}
+
+/// Frees every ring once no push is using it.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL`, from the unload routine or a failed
+/// `DriverEntry`, after the callbacks are unregistered.
+pub unsafe fn delete_all() {
+    for ring in all() {
+        ring.delete();
+    }
+}
@@ -1,4 +1,4 @@
-//! ALE_FLOW_ESTABLISHED callout for network flow monitoring.
+//! ALE connect callout for network flow monitoring.
 //!
 //! This module implements the core logic of the WFP callout triggered when
 //! new network flows are authorized. It extracts flow metadata (IPs, ports,
@@ -9,3 +9,105 @@
 //! - Extract source/destination and application context.
 //! - Format and send event data to the user-agent component.
 //! - Maintain temporary process-flow mapping to avoid stale PID resolution.
+//!
+//! The classify runs at `FWPM_LAYER_ALE_AUTH_CONNECT_V4`, in the context of
+//! the connecting process and at up to `DISPATCH_LEVEL`. It reports an
+//! outbound `NetworkEvent` per connection and always permits it.
+
+use core::{
+    ffi::c_void,
+    slice,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use super::{
+    net_event::{Direction, NetworkEvent},
+    FWPS_CLASSIFY_OUT0, FWPS_FILTER0, FWPS_INCOMING_METADATA_VALUES0, FWPS_INCOMING_VALUES0, FWP_ACTION_PERMIT,
+    FWP_VALUE0, FWPS_METADATA_FIELD_PROCESS_ID, FWPS_METADATA_FIELD_PROCESS_PATH, FWPS_RIGHT_ACTION_WRITE,
+};
+use crate::{
+    frame::Sequence,
+    kernel_api::{now_micros, Wdk},
+    ring::{Push, RingSlot},
+    ring_event,
+};
+
+/// `FWPS_FIELDS_ALE_AUTH_CONNECT_V4` indices of the values read.
+const IP_LOCAL_ADDRESS: usize = 2;
+const IP_LOCAL_PORT: usize = 4;
+const IP_PROTOCOL: usize = 5;
+const IP_REMOTE_ADDRESS: usize = 6;
+const IP_REMOTE_PORT: usize = 7;
+
+/// Frames that could not be delivered: refused by the ring, or offered
+/// while there is none (see `sections.rs`).
+pub static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Numbers this ring's frames, so user space can count the dropped ones.
+static SEQ: Sequence = Sequence::new();
+
+/// Where the frames go; `sections` installs the ring.
+pub(crate) static RING: RingSlot<Wdk> = RingSlot::new(Wdk);
+
+fn push(len: usize, write: impl FnOnce(&mut [u8]) -> Option<usize>) {
+    match RING.push_with(len, write) {
+        Push::Published { was_empty } => ring_event::pushed(was_empty),
+        Push::Dropped => {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The UTF-16 process path in `metadata`, without its terminator; empty if
+/// the engine did not supply one.
+unsafe fn process_path(metadata: &FWPS_INCOMING_METADATA_VALUES0) -> &[u16] {
+    if metadata.currentMetadataValues & FWPS_METADATA_FIELD_PROCESS_PATH == 0 {
+        return &[];
+    }
+    let Some(blob) = metadata.processPath.as_ref() else { return &[] };
+    if blob.data.is_null() {
+        return &[];
+    }
+    let path = slice::from_raw_parts(blob.data.cast::<u16>(), blob.size as usize / 2);
+    path.strip_suffix(&[0]).unwrap_or(path)
+}
+
+/// `FWPS_CALLOUT_CLASSIFY_FN0` of the connect callout.
+pub unsafe extern "system" fn classify_connect(
+    values: *const FWPS_INCOMING_VALUES0,
+    metadata: *const FWPS_INCOMING_METADATA_VALUES0,
+    _layer_data: *mut c_void,
+    _filter: *const FWPS_FILTER0,
+    _flow_context: u64,
+    classify_out: *mut FWPS_CLASSIFY_OUT0,
+) {
+    if let (Some(values), Some(metadata)) = (values.as_ref(), metadata.as_ref()) {
+        if values.valueCount as usize > IP_REMOTE_PORT {
+            let fields: &[FWP_VALUE0] = slice::from_raw_parts(values.incomingValue, values.valueCount as usize);
+            let pid = if metadata.currentMetadataValues & FWPS_METADATA_FIELD_PROCESS_ID != 0 {
+                metadata.processId as u32
+            } else {
+                0
+            };
+            let event = NetworkEvent {
+                direction: Direction::Outbound,
+                protocol:  fields[IP_PROTOCOL].value as u8,
+                src_ip:    fields[IP_LOCAL_ADDRESS].value as u32,
+                src_port:  fields[IP_LOCAL_PORT].value as u16,
+                dst_ip:    fields[IP_REMOTE_ADDRESS].value as u32,
+                dst_port:  fields[IP_REMOTE_PORT].value as u16,
+                pid,
+                exe_path:  process_path(metadata),
+            };
+            let seq = SEQ.next();
+            push(event.frame_len(), |out| event.write_frame(out, seq, now_micros()));
+        }
+    }
+
+    // Monitor only: the connection goes ahead whatever was reported.
+    if let Some(out) = classify_out.as_mut() {
+        if out.rights & FWPS_RIGHT_ACTION_WRITE != 0 {
+            out.actionType = FWP_ACTION_PERMIT;
+        }
+    }
+}
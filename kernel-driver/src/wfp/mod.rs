@@ -9,3 +9,361 @@
 //! - Correlate network activity with process information.
 //! - Relay selected flow data to user space.
 //! - Manage callout lifecycle (register/unregister).
+//!
+//! Built with the `wfp` feature only. One callout is registered, at
+//! `FWPM_LAYER_ALE_AUTH_CONNECT_V4`, behind a filter without conditions:
+//! outbound IPv4 connections are reported and permitted. `wdk-sys` does not bind
+//! `fwpsk.h` or `fwpmk.h`, so the few declarations used are below.
+
+#![allow(non_camel_case_types, non_snake_case)]
+
+pub(crate) mod ale_flow;
+pub mod net_event;
+
+use core::{
+    ffi::c_void,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, Ordering},
+};
+
+use wdk::println;
+use wdk_sys::{DEVICE_OBJECT, DRIVER_OBJECT, GUID, HANDLE, NTSTATUS, NT_SUCCESS, STATUS_SUCCESS};
+
+/// `FWP_BYTE_BLOB`.
+#[repr(C)]
+pub struct FWP_BYTE_BLOB {
+    pub size: u32,
+    pub data: *mut u8,
+}
+
+/// `FWP_VALUE0`. The union is kept as its widest scalar: integers up to
+/// `UINT32` are its low bytes, as `as` reads them on x64.
+#[repr(C)]
+pub struct FWP_VALUE0 {
+    pub r#type: u32,
+    pub value:  u64,
+}
+
+pub const FWP_EMPTY: u32 = 0;
+
+/// `FWPS_INCOMING_VALUES0`; `incomingValue` points to `valueCount`
+/// `FWPS_INCOMING_VALUE0`, which only wrap a `FWP_VALUE0`.
+#[repr(C)]
+pub struct FWPS_INCOMING_VALUES0 {
+    pub layerId:       u16,
+    pub valueCount:    u32,
+    pub incomingValue: *const FWP_VALUE0,
+}
+
+#[repr(C)]
+pub struct FWPS_DISCARD_METADATA0 {
+    pub discardModule: u32,
+    pub discardReason: u32,
+    pub filterId:      u64,
+}
+
+/// Leading fields of `FWPS_INCOMING_METADATA_VALUES0`; only handled by
+/// pointer, the filter engine owns the rest.
+#[repr(C)]
+pub struct FWPS_INCOMING_METADATA_VALUES0 {
+    pub currentMetadataValues: u32,
+    pub flags:                 u32,
+    pub reserved:              u64,
+    pub discardMetadata:       FWPS_DISCARD_METADATA0,
+    pub flowHandle:            u64,
+    pub ipHeaderSize:          u32,
+    pub transportHeaderSize:   u32,
+    pub processPath:           *const FWP_BYTE_BLOB,
+    pub token:                 u64,
+    pub processId:             u64,
+}
+
+pub const FWPS_METADATA_FIELD_PROCESS_PATH: u32 = 0x0000_0010;
+pub const FWPS_METADATA_FIELD_PROCESS_ID: u32 = 0x0000_0040;
+
+/// `FWPS_FILTER0`; only handled by pointer.
+#[repr(C)]
+pub struct FWPS_FILTER0 {
+    _opaque: [u8; 0],
+}
+
+/// `FWPS_CLASSIFY_OUT0`.
+#[repr(C)]
+pub struct FWPS_CLASSIFY_OUT0 {
+    pub actionType: u32,
+    pub outContext: u64,
+    pub filterId:   u64,
+    pub rights:     u32,
+    pub flags:      u32,
+    pub reserved:   u32,
+}
+
+pub const FWPS_RIGHT_ACTION_WRITE: u32 = 0x0000_0001;
+pub const FWP_ACTION_PERMIT: u32 = 0x0000_1002;
+const FWP_ACTION_CALLOUT_TERMINATING: u32 = 0x0000_5005;
+
+type FWPS_CALLOUT_CLASSIFY_FN0 = unsafe extern "system" fn(
+    values: *const FWPS_INCOMING_VALUES0,
+    metadata: *const FWPS_INCOMING_METADATA_VALUES0,
+    layer_data: *mut c_void,
+    filter: *const FWPS_FILTER0,
+    flow_context: u64,
+    classify_out: *mut FWPS_CLASSIFY_OUT0,
+);
+type FWPS_CALLOUT_NOTIFY_FN0 =
+    unsafe extern "system" fn(notify_type: u32, filter_key: *const GUID, filter: *const FWPS_FILTER0) -> NTSTATUS;
+
+#[repr(C)]
+struct FWPS_CALLOUT0 {
+    calloutKey:   GUID,
+    flags:        u32,
+    classifyFn:   Option<FWPS_CALLOUT_CLASSIFY_FN0>,
+    notifyFn:     Option<FWPS_CALLOUT_NOTIFY_FN0>,
+    flowDeleteFn: *const c_void,
+}
+
+#[repr(C)]
+struct FWPM_DISPLAY_DATA0 {
+    name:        *const u16,
+    description: *const u16,
+}
+
+#[repr(C)]
+struct FWPM_SESSION0 {
+    sessionKey:           GUID,
+    displayData:          FWPM_DISPLAY_DATA0,
+    flags:                u32,
+    txnWaitTimeoutInMSec: u32,
+    processId:            u32,
+    sid:                  *const c_void,
+    username:             *const u16,
+    kernelMode:           i32,
+}
+
+/// Objects added through the session are removed when it closes, so a
+/// crashed driver leaves no filter pointing at a callout that is gone.
+const FWPM_SESSION_FLAG_DYNAMIC: u32 = 0x0000_0001;
+const RPC_C_AUTHN_WINNT: u32 = 10;
+
+#[repr(C)]
+struct FWPM_CALLOUT0 {
+    calloutKey:      GUID,
+    displayData:     FWPM_DISPLAY_DATA0,
+    flags:           u32,
+    providerKey:     *const GUID,
+    providerData:    FWP_BYTE_BLOB,
+    applicableLayer: GUID,
+    calloutId:       u32,
+}
+
+/// `FWPM_ACTION0`, with the union as the callout key.
+#[repr(C)]
+struct FWPM_ACTION0 {
+    r#type:     u32,
+    calloutKey: GUID,
+}
+
+#[repr(C)]
+struct FWPM_FILTER0 {
+    filterKey:           GUID,
+    displayData:         FWPM_DISPLAY_DATA0,
+    flags:               u32,
+    providerKey:         *const GUID,
+    providerData:        FWP_BYTE_BLOB,
+    layerKey:            GUID,
+    subLayerKey:         GUID,
+    weight:              FWP_VALUE0,
+    numFilterConditions: u32,
+    filterCondition:     *const c_void,
+    action:              FWPM_ACTION0,
+    /// Union with `providerContextKey`, hence the size of a GUID.
+    rawContext:          [u64; 2],
+    reserved:            *const GUID,
+    filterId:            u64,
+    effectiveWeight:     FWP_VALUE0,
+}
+
+#[link(name = "fwpkclnt")]
+extern "system" {
+    fn FwpmEngineOpen0(
+        server_name: *const u16,
+        authn_service: u32,
+        auth_identity: *const c_void,
+        session: *const FWPM_SESSION0,
+        engine: *mut HANDLE,
+    ) -> NTSTATUS;
+    fn FwpmEngineClose0(engine: HANDLE) -> NTSTATUS;
+    fn FwpsCalloutRegister0(device: *mut c_void, callout: *const FWPS_CALLOUT0, id: *mut u32) -> NTSTATUS;
+    fn FwpsCalloutUnregisterById0(id: u32) -> NTSTATUS;
+    fn FwpmCalloutAdd0(engine: HANDLE, callout: *const FWPM_CALLOUT0, sd: *const c_void, id: *mut u32) -> NTSTATUS;
+    fn FwpmCalloutDeleteByKey0(engine: HANDLE, key: *const GUID) -> NTSTATUS;
+    fn FwpmFilterAdd0(engine: HANDLE, filter: *const FWPM_FILTER0, sd: *const c_void, id: *mut u64) -> NTSTATUS;
+    fn FwpmFilterDeleteById0(engine: HANDLE, id: u64) -> NTSTATUS;
+}
+
+const fn guid(data1: u32, data2: u16, data3: u16, data4: [u8; 8]) -> GUID {
+    GUID { Data1: data1, Data2: data2, Data3: data3, Data4: data4 }
+}
+
+/// `FWPM_LAYER_ALE_AUTH_CONNECT_V4`.
+const FWPM_LAYER_ALE_AUTH_CONNECT_V4: GUID =
+    guid(0xc38d57d1, 0x05a7, 0x4c33, [0x90, 0x4f, 0x7f, 0xbc, 0xee, 0xe6, 0x0e, 0x82]);
+/// Key of the connect callout, ours.
+const CONNECT_CALLOUT_KEY: GUID =
+    guid(0x5f3c9a2e, 0x6b1d, 0x4e8a, [0x9c, 0x47, 0x1a, 0xd2, 0x3e, 0x80, 0x5b, 0x61]);
+const ZERO_GUID: GUID = guid(0, 0, 0, [0; 8]);
+
+/// NUL-terminated UTF-16 of the ASCII `s`; `N` must be its length plus one.
+const fn wide<const N: usize>(s: &str) -> [u16; N] {
+    let bytes = s.as_bytes();
+    assert!(bytes.len() + 1 == N);
+    let mut out = [0u16; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        out[i] = bytes[i] as u16;
+        i += 1;
+    }
+    out
+}
+
+static CALLOUT_NAME: [u16; 18] = wide("Gladix connect v4");
+static FILTER_NAME: [u16; 25] = wide("Gladix connect v4 filter");
+
+/// Session handle from `FwpmEngineOpen0`; null when not registered.
+static ENGINE: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+/// Run-time callout id; 0 when not registered.
+static CALLOUT_ID: AtomicU32 = AtomicU32::new(0);
+/// Whether `FwpmCalloutAdd0` succeeded.
+static CALLOUT_ADDED: AtomicBool = AtomicBool::new(false);
+/// Filter id; 0 when not added.
+static FILTER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// `FWPS_CALLOUT_NOTIFY_FN0`; filters come and go without state to keep.
+unsafe extern "system" fn notify(
+    _notify_type: u32,
+    _filter_key: *const GUID,
+    _filter: *const FWPS_FILTER0,
+) -> NTSTATUS {
+    STATUS_SUCCESS
+}
+
+/// Registers the connect callout with the filter engine and adds the
+/// filter that invokes it. On failure, whatever was set up is removed.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL` from `DriverEntry`, after the control device
+/// of `driver` was created.
+pub unsafe fn register(driver: &DRIVER_OBJECT) -> NTSTATUS {
+    let status = add_all(driver.DeviceObject);
+    if !NT_SUCCESS(status) {
+        unregister();
+    }
+    status
+}
+
+unsafe fn add_all(device: *mut DEVICE_OBJECT) -> NTSTATUS {
+    let session = FWPM_SESSION0 {
+        sessionKey:           ZERO_GUID,
+        displayData:          FWPM_DISPLAY_DATA0 { name: ptr::null(), description: ptr::null() },
+        flags:                FWPM_SESSION_FLAG_DYNAMIC,
+        txnWaitTimeoutInMSec: 0,
+        processId:            0,
+        sid:                  ptr::null(),
+        username:             ptr::null(),
+        kernelMode:           0,
+    };
+    let mut engine: HANDLE = ptr::null_mut();
+    let status = FwpmEngineOpen0(ptr::null(), RPC_C_AUTHN_WINNT, ptr::null(), &session, &mut engine);
+    if !NT_SUCCESS(status) {
+        println!("gladix: FwpmEngineOpen0 failed: {status:#x}");
+        return status;
+    }
+    ENGINE.store(engine, Ordering::Release);
+
+    let callout = FWPS_CALLOUT0 {
+        calloutKey:   CONNECT_CALLOUT_KEY,
+        flags:        0,
+        classifyFn:   Some(ale_flow::classify_connect),
+        notifyFn:     Some(notify),
+        flowDeleteFn: ptr::null(),
+    };
+    let mut id = 0;
+    let status = FwpsCalloutRegister0(device.cast(), &callout, &mut id);
+    if !NT_SUCCESS(status) {
+        println!("gladix: FwpsCalloutRegister0 failed: {status:#x}");
+        return status;
+    }
+    CALLOUT_ID.store(id, Ordering::Release);
+
+    let callout = FWPM_CALLOUT0 {
+        calloutKey:      CONNECT_CALLOUT_KEY,
+        displayData:     FWPM_DISPLAY_DATA0 { name: CALLOUT_NAME.as_ptr(), description: ptr::null() },
+        flags:           0,
+        providerKey:     ptr::null(),
+        providerData:    FWP_BYTE_BLOB { size: 0, data: ptr::null_mut() },
+        applicableLayer: FWPM_LAYER_ALE_AUTH_CONNECT_V4,
+        calloutId:       0,
+    };
+    let status = FwpmCalloutAdd0(engine, &callout, ptr::null(), ptr::null_mut());
+    if !NT_SUCCESS(status) {
+        println!("gladix: FwpmCalloutAdd0 failed: {status:#x}");
+        return status;
+    }
+    CALLOUT_ADDED.store(true, Ordering::Release);
+
+    let filter = FWPM_FILTER0 {
+        filterKey:           ZERO_GUID,
+        displayData:         FWPM_DISPLAY_DATA0 { name: FILTER_NAME.as_ptr(), description: ptr::null() },
+        flags:               0,
+        providerKey:         ptr::null(),
+        providerData:        FWP_BYTE_BLOB { size: 0, data: ptr::null_mut() },
+        layerKey:            FWPM_LAYER_ALE_AUTH_CONNECT_V4,
+        // The default sublayer, with a weight the engine picks.
+        subLayerKey:         ZERO_GUID,
+        weight:              FWP_VALUE0 { r#type: FWP_EMPTY, value: 0 },
+        numFilterConditions: 0,
+        filterCondition:     ptr::null(),
+        action:              FWPM_ACTION0 { r#type: FWP_ACTION_CALLOUT_TERMINATING, calloutKey: CONNECT_CALLOUT_KEY },
+        rawContext:          [0; 2],
+        reserved:            ptr::null(),
+        filterId:            0,
+        effectiveWeight:     FWP_VALUE0 { r#type: FWP_EMPTY, value: 0 },
+    };
+    let mut filter_id = 0;
+    let status = FwpmFilterAdd0(engine, &filter, ptr::null(), &mut filter_id);
+    if !NT_SUCCESS(status) {
+        println!("gladix: FwpmFilterAdd0 failed: {status:#x}");
+        return status;
+    }
+    FILTER_ID.store(filter_id, Ordering::Release);
+    STATUS_SUCCESS
+}
+
+/// Removes the filter and the callout and closes the engine, undoing as
+/// much of [`register`] as succeeded. The callout is unregistered last:
+/// that waits for running classifies.
+///
+/// # Safety
+/// Call at `PASSIVE_LEVEL`, from the unload routine or a failed `register`.
+pub unsafe fn unregister() {
+    let engine = ENGINE.swap(ptr::null_mut(), Ordering::AcqRel);
+    if !engine.is_null() {
+        let filter_id = FILTER_ID.swap(0, Ordering::AcqRel);
+        if filter_id != 0 {
+            FwpmFilterDeleteById0(engine, filter_id);
+        }
+        if CALLOUT_ADDED.swap(false, Ordering::AcqRel) {
+            FwpmCalloutDeleteByKey0(engine, &CONNECT_CALLOUT_KEY);
+        }
+        FwpmEngineClose0(engine);
+    }
+    let id = CALLOUT_ID.swap(0, Ordering::AcqRel);
+    if id != 0 {
+        let status = FwpsCalloutUnregisterById0(id);
+        // A callout left registered may still classify; `sections` retires
+        // the network ring before unmapping it, so those pushes are dropped.
+        if !NT_SUCCESS(status) {
+            println!("gladix: FwpsCalloutUnregisterById0 failed: {status:#x}");
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! `NetworkEvent` frames as the user-agent reads them from the network ring.
+//!
+//! Encoded with `crate::frame`, following `NetworkEvent` in
+//! `shared/proto/events.proto`. Addresses arrive as WFP hands them over,
+//! host-order `u32`s, and are written in dotted-decimal form. Only `core` is
+//! used, so `tests/net_event.rs` can include this file directly.
+
+use crate::consts::ring_frame_len;
+use crate::frame::{len_tag, str_field_len, utf16_field_len, varint_field_len, varint_tag, write_frame};
+
+/// `NetworkEvent.Direction`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Direction {
+    Inbound  = 0,
+    Outbound = 1,
+}
+
+/// What an ALE classify reports about one connection.
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkEvent<'a> {
+    pub direction: Direction,
+    /// IP protocol number (`IPPROTO_*`).
+    pub protocol:  u8,
+    pub src_ip:    u32,
+    pub src_port:  u16,
+    pub dst_ip:    u32,
+    pub dst_port:  u16,
+    pub pid:       u32,
+    /// Process image as WFP reports it (`\device\harddiskvolumeN\...`),
+    /// UTF-16 without terminator. Unpaired surrogates become U+FFFD.
+    pub exe_path:  &'a [u16],
+}
+
+const DIRECTION: u8 = varint_tag(1);
+const PROTO: u8 = len_tag(2);
+const SRC_IP: u8 = len_tag(3);
+const SRC_PORT: u8 = varint_tag(4);
+const DST_IP: u8 = len_tag(5);
+const DST_PORT: u8 = varint_tag(6);
+const PID: u8 = varint_tag(7);
+const EXE_PATH: u8 = len_tag(8);
+
+/// `NetworkEvent.proto` for an IP protocol number; empty, and so left out,
+/// for those without a name here.
+pub fn proto_name(protocol: u8) -> &'static str {
+    match protocol {
+        1 => "ICMP",
+        6 => "TCP",
+        17 => "UDP",
+        _ => "",
+    }
+}
+
+/// Dotted-decimal text of an IPv4 address, built on the stack.
+pub struct Ipv4Text {
+    buf: [u8; 15],
+    len: usize,
+}
+
+impl Ipv4Text {
+    pub fn new(addr: u32) -> Self {
+        let mut text = Self { buf: [0; 15], len: 0 };
+        for (i, octet) in addr.to_be_bytes().into_iter().enumerate() {
+            if i > 0 {
+                text.push(b'.');
+            }
+            if octet >= 100 {
+                text.push(b'0' + octet / 100);
+            }
+            if octet >= 10 {
+                text.push(b'0' + octet / 10 % 10);
+            }
+            text.push(b'0' + octet % 10);
+        }
+        text
+    }
+
+    fn push(&mut self, b: u8) {
+        self.buf[self.len] = b;
+        self.len += 1;
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ASCII digits and dots are pushed.
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or_default()
+    }
+}
+
+impl NetworkEvent<'_> {
+    /// Size of the protobuf encoding.
+    pub fn encoded_len(&self) -> usize {
+        varint_field_len(self.direction as u64)
+            + str_field_len(proto_name(self.protocol))
+            + str_field_len(Ipv4Text::new(self.src_ip).as_str())
+            + varint_field_len(self.src_port as u64)
+            + str_field_len(Ipv4Text::new(self.dst_ip).as_str())
+            + varint_field_len(self.dst_port as u64)
+            + varint_field_len(self.pid as u64)
+            + utf16_field_len(self.exe_path)
+    }
+
+    /// Bytes [`write_frame`](Self::write_frame) needs.
+    pub fn frame_len(&self) -> usize {
+        ring_frame_len(self.encoded_len())
+    }
+
+    /// Writes the prefix of frame number `seq` built at `ts`, the encoding
+    /// and zero padding to the start of `out`. Returns the frame length, or
+    /// `None` if `out` is too short.
+    pub fn write_frame(&self, out: &mut [u8], seq: u64, ts: u64) -> Option<usize> {
+        write_frame(out, seq, ts, self.encoded_len(), |w| {
+            w.varint_field(DIRECTION, self.direction as u64)?;
+            w.str_field(PROTO, proto_name(self.protocol))?;
+            w.str_field(SRC_IP, Ipv4Text::new(self.src_ip).as_str())?;
+            w.varint_field(SRC_PORT, self.src_port as u64)?;
+            w.str_field(DST_IP, Ipv4Text::new(self.dst_ip).as_str())?;
+            w.varint_field(DST_PORT, self.dst_port as u64)?;
+            w.varint_field(PID, self.pid as u64)?;
+            w.utf16_field(EXE_PATH, self.exe_path)
+        })
+    }
+}
@@ -0,0 +1,93 @@
+//! Host tests for the `FileEvent` frames built in `src/minifilter/event.rs`.
+//!
+//! The expected bytes are the ones `tests/listeners.rs` in the user-agent
+//! feeds through a ring and decodes with prost.
+
+#[path = "../src/consts.rs"]
+#[allow(dead_code)]
+mod consts;
+#[path = "../src/frame.rs"]
+#[allow(dead_code)]
+mod frame;
+#[path = "../src/minifilter/event.rs"]
+#[allow(dead_code)]
+mod event;
+
+use event::{FileEvent, FileOp};
+
+fn utf16(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+#[test]
+fn create_frame_matches_the_hand_encoding() {
+    let path = utf16(r"\Device\HarddiskVolume3\t.txt");
+    let event = FileEvent { op: FileOp::Create, path: &path, pid: 4242 };
+
+    let mut payload = vec![0x12, 29];
+    payload.extend_from_slice(br"\Device\HarddiskVolume3\t.txt");
+    payload.extend_from_slice(&[0x20, 0x92, 0x21]);
+    assert_eq!(event.encoded_len(), payload.len());
+
+    let mut covered = 0x0102_0304_0506_0708u64.to_le_bytes().to_vec();
+    covered.extend_from_slice(&1_760_000_000_000_000u64.to_le_bytes());
+    covered.extend_from_slice(&payload);
+
+    let mut frame = vec![0xAA; 72];
+    assert_eq!(event.write_frame(&mut frame, 0x0102_0304_0506_0708, 1_760_000_000_000_000), Some(64));
+    assert_eq!(frame[..2], *b"GX");
+    assert_eq!(frame[2..6], (payload.len() as u32).to_le_bytes());
+    assert_eq!(frame[6..10], frame::crc32(&covered).to_le_bytes());
+    assert_eq!(frame[10..18], [8, 7, 6, 5, 4, 3, 2, 1]);
+    assert_eq!(frame[18..26], covered[8..16]);
+    assert_eq!(frame[26..26 + payload.len()], payload[..]);
+    assert!(frame[26 + payload.len()..64].iter().all(|&b| b == 0), "padding");
+    assert_eq!(frame[64], 0xAA, "nothing past the frame");
+}
+
+#[test]
+fn defaults_are_left_out_and_other_ops_are_written() {
+    assert_eq!(FileEvent { op: FileOp::Create, path: &[], pid: 0 }.encoded_len(), 0);
+
+    let event = FileEvent { op: FileOp::Rename, path: &[], pid: 0 };
+    let mut frame = [0u8; 32];
+    assert_eq!(event.write_frame(&mut frame, 1, 0), Some(32));
+    assert_eq!(frame[..6], [b'G', b'X', 2, 0, 0, 0]);
+    assert_eq!(frame[26..], [0x08, 3, 0, 0, 0, 0]);
+}
+
+#[test]
+fn crc_is_the_one_the_agent_checks() {
+    // The CRC-32 check value `shared::ring::crc32` is tested against too.
+    assert_eq!(frame::crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(frame::crc32(&[]), 0);
+}
+
+#[test]
+fn timestamps_count_from_the_unix_epoch() {
+    // 2025-10-09T08:53:20Z as a FILETIME.
+    assert_eq!(frame::unix_micros(134_044_736_000_000_000), 1_760_000_000_000_000);
+    assert_eq!(frame::unix_micros(116_444_736_000_000_010), 1);
+    assert_eq!(frame::unix_micros(0), 0);
+}
+
+#[test]
+fn paths_are_utf8_and_lone_surrogates_are_replaced() {
+    let mut path = utf16("C:\\é");
+    path.push(0xD800);
+    let event = FileEvent { op: FileOp::Create, path: &path, pid: 0 };
+
+    let mut frame = vec![0u8; event.frame_len()];
+    event.write_frame(&mut frame, 1, 0).unwrap();
+    let expected = "C:\\é\u{FFFD}".as_bytes();
+    assert_eq!(frame[26..28], [0x12, expected.len() as u8]);
+    assert_eq!(&frame[28..28 + expected.len()], expected);
+}
+
+#[test]
+fn short_buffers_are_refused() {
+    let path = utf16(r"C:\a");
+    let event = FileEvent { op: FileOp::Create, path: &path, pid: 1 };
+    let mut frame = vec![0u8; event.frame_len() - 1];
+    assert_eq!(event.write_frame(&mut frame, 1, 0), None);
+}
@@ -0,0 +1,83 @@
+//! Host tests for the `ImageLoadEvent` frames built in
+//! `src/callbacks/image_event.rs`.
+//!
+//! The expected bytes are the ones `tests/listeners.rs` in the user-agent
+//! feeds through a ring and decodes with prost.
+
+#[path = "../src/consts.rs"]
+#[allow(dead_code)]
+mod consts;
+#[path = "../src/frame.rs"]
+#[allow(dead_code)]
+mod frame;
+#[path = "../src/callbacks/image_event.rs"]
+#[allow(dead_code)]
+mod image_event;
+
+use image_event::ImageLoadEvent;
+
+fn utf16(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+#[test]
+fn dll_load_frame_matches_the_hand_encoding() {
+    let name = utf16(r"\Windows\System32\ntdll.dll");
+    let event = ImageLoadEvent {
+        pid:              4242,
+        image_base:       0x7ffa_0000_0000,
+        image_size:       0x1f_8000,
+        full_image_name:  &name,
+        is_kernel_module: false,
+    };
+
+    let mut payload = vec![0x08, 0x92, 0x21];
+    payload.extend_from_slice(&[0x10, 0x80, 0x80, 0x80, 0x80, 0xa0, 0xff, 0x1f]);
+    payload.extend_from_slice(&[0x18, 0x80, 0x80, 0x7e]);
+    payload.extend_from_slice(&[0x22, 27]);
+    payload.extend_from_slice(br"\Windows\System32\ntdll.dll");
+    assert_eq!(event.encoded_len(), payload.len());
+
+    let mut covered = 7u64.to_le_bytes().to_vec();
+    covered.extend_from_slice(&1_760_000_000_000_000u64.to_le_bytes());
+    covered.extend_from_slice(&payload);
+
+    let mut frame = vec![0xAA; 80];
+    assert_eq!(event.write_frame(&mut frame, 7, 1_760_000_000_000_000), Some(72));
+    assert_eq!(frame[..2], *b"GX");
+    assert_eq!(frame[2..6], (payload.len() as u32).to_le_bytes());
+    assert_eq!(frame[6..10], frame::crc32(&covered).to_le_bytes());
+    assert_eq!(frame[10..26], covered[..16]);
+    assert_eq!(frame[26..26 + payload.len()], payload[..]);
+    assert!(frame[26 + payload.len()..72].iter().all(|&b| b == 0), "padding");
+    assert_eq!(frame[72], 0xAA, "nothing past the frame");
+}
+
+#[test]
+fn kernel_modules_have_no_pid_and_set_the_flag() {
+    let event = ImageLoadEvent {
+        pid:              0,
+        image_base:       0,
+        image_size:       0,
+        full_image_name:  &[],
+        is_kernel_module: true,
+    };
+    let mut frame = [0u8; 32];
+    assert_eq!(event.write_frame(&mut frame, 1, 0), Some(32));
+    assert_eq!(frame[..6], [b'G', b'X', 2, 0, 0, 0]);
+    assert_eq!(frame[26..], [0x28, 1, 0, 0, 0, 0]);
+}
+
+#[test]
+fn short_buffers_are_refused() {
+    let name = utf16(r"\a.sys");
+    let event = ImageLoadEvent {
+        pid:              1,
+        image_base:       1,
+        image_size:       1,
+        full_image_name:  &name,
+        is_kernel_module: true,
+    };
+    let mut frame = vec![0u8; event.frame_len() - 1];
+    assert_eq!(event.write_frame(&mut frame, 1, 0), None);
+}
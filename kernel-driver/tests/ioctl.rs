@@ -0,0 +1,270 @@
+//! Host tests for the IOCTL table in `src/consts.rs` and `src/ioctl.rs`.
+
+#[path = "../src/consts.rs"]
+#[allow(dead_code)]
+mod consts;
+#[path = "../src/ioctl.rs"]
+#[allow(dead_code)]
+mod ioctl;
+
+use std::cell::RefCell;
+
+use consts::{
+    ctl_code, NetRule, RingStats, VersionInfo, FILE_READ_ACCESS, IOCTL_GLADIX_GET_RING_STATS,
+    IOCTL_GLADIX_GET_VERSION, IOCTL_GLADIX_PING, IOCTL_GLADIX_SET_NET_POLICY, IOCTL_GLADIX_SET_PROTECTED_PIDS,
+    METHOD_BUFFERED, NET_ACTION_BLOCK, NET_FAMILY_V4, NET_FAMILY_V6, NET_POLICY_MAX_RULES, NET_RULE_PROCESS_LEN,
+    DRIVER_PROTOCOL_VERSION, FILE_RING_NAME, IMAGE_RING_NAME, NETWORK_RING_NAME, OBJECT_RING_NAME, PING_REPLY,
+    PROCESS_RING_NAME, PROTECTED_PIDS_MAX, RING_EVENT_NAME,
+};
+use ioctl::{
+    route, IoctlError, IoctlTarget, NetRules, ProtectedPids, STATUS_BUFFER_TOO_SMALL, STATUS_INVALID_DEVICE_REQUEST,
+    STATUS_INVALID_PARAMETER,
+};
+
+struct Target(Option<RingStats>);
+
+impl IoctlTarget for Target {
+    fn ring_stats(&self) -> Option<RingStats> {
+        self.0
+    }
+
+    fn set_net_policy(&self, _rules: NetRules<'_>) -> Result<(), IoctlError> {
+        Ok(())
+    }
+
+    fn set_protected_pids(&self, _pids: ProtectedPids<'_>) -> Result<(), IoctlError> {
+        Ok(())
+    }
+}
+
+/// Keeps the last policy it was sent.
+#[derive(Default)]
+struct PolicyTarget(RefCell<Option<Vec<NetRule>>>);
+
+impl IoctlTarget for PolicyTarget {
+    fn ring_stats(&self) -> Option<RingStats> {
+        None
+    }
+
+    fn set_net_policy(&self, rules: NetRules<'_>) -> Result<(), IoctlError> {
+        *self.0.borrow_mut() = Some(rules.iter().collect());
+        Ok(())
+    }
+
+    fn set_protected_pids(&self, _pids: ProtectedPids<'_>) -> Result<(), IoctlError> {
+        Ok(())
+    }
+}
+
+/// Keeps the last protected pids it was sent.
+#[derive(Default)]
+struct PidsTarget(RefCell<Option<Vec<u32>>>);
+
+impl IoctlTarget for PidsTarget {
+    fn ring_stats(&self) -> Option<RingStats> {
+        None
+    }
+
+    fn set_net_policy(&self, _rules: NetRules<'_>) -> Result<(), IoctlError> {
+        Ok(())
+    }
+
+    fn set_protected_pids(&self, pids: ProtectedPids<'_>) -> Result<(), IoctlError> {
+        *self.0.borrow_mut() = Some(pids.iter().collect());
+        Ok(())
+    }
+}
+
+const STATS: RingStats = RingStats {
+    head:             4096,
+    tail:             1024,
+    dropped:          3,
+    size:             1 << 20,
+    dropped_full:     2,
+    dropped_oversize: 1,
+    max_observed_len: 2 << 20,
+    reserved:         0,
+};
+
+#[test]
+fn codes_match_ctl_code_and_are_distinct() {
+    // CTL_CODE(FILE_DEVICE_UNKNOWN, 0x800, METHOD_BUFFERED, FILE_READ_ACCESS)
+    assert_eq!(ctl_code(0x22, 0x800, METHOD_BUFFERED, FILE_READ_ACCESS), 0x0022_6000);
+    assert_eq!(IOCTL_GLADIX_GET_RING_STATS & 3, METHOD_BUFFERED);
+    let codes = [
+        IOCTL_GLADIX_PING,
+        IOCTL_GLADIX_GET_VERSION,
+        IOCTL_GLADIX_GET_RING_STATS,
+        IOCTL_GLADIX_SET_NET_POLICY,
+        IOCTL_GLADIX_SET_PROTECTED_PIDS,
+    ];
+    for (i, a) in codes.iter().enumerate() {
+        assert!(codes[i + 1..].iter().all(|b| a != b));
+    }
+}
+
+#[test]
+fn ping_and_version_reply() {
+    let mut out = [0xffu8; 32];
+    assert_eq!(route(IOCTL_GLADIX_PING, 0, &mut out, &Target(None)), Ok(4));
+    assert_eq!(u32::from_le_bytes(out[..4].try_into().unwrap()), PING_REPLY);
+
+    assert_eq!(route(IOCTL_GLADIX_GET_VERSION, 0, &mut out, &Target(None)), Ok(24));
+    assert_eq!(u32::from_le_bytes(out[..4].try_into().unwrap()), DRIVER_PROTOCOL_VERSION);
+    assert_eq!(out[10..16], [0; 6]);
+    assert_eq!(u64::from_le_bytes(out[16..24].try_into().unwrap()), VersionInfo::CURRENT.build_time);
+    assert_eq!(VersionInfo::CURRENT.major, env!("CARGO_PKG_VERSION_MAJOR").parse::<u16>().unwrap());
+    // Agents from before the build time send 12 bytes.
+    let err = route(IOCTL_GLADIX_GET_VERSION, 0, &mut out[..12], &Target(None));
+    assert_eq!(err, Err(IoctlError::BufferTooSmall { needed: 24, got: 12 }));
+}
+
+#[test]
+fn ring_event_is_created_where_the_agent_opens_it() {
+    // `shared::constants::kernel_object_path(RING_EVENT)`; `Global\` names
+    // resolve under `\BaseNamedObjects\`.
+    assert_eq!(String::from_utf16(&RING_EVENT_NAME).unwrap(), r"\BaseNamedObjects\GladixRingEvent");
+}
+
+#[test]
+fn ring_sections_are_named_after_the_agent_rings() {
+    // `shared::constants::PROCESS_RING` and the others.
+    let names = [&PROCESS_RING_NAME[..], &IMAGE_RING_NAME, &OBJECT_RING_NAME, &NETWORK_RING_NAME, &FILE_RING_NAME]
+        .map(|n| String::from_utf16(n).unwrap());
+    assert_eq!(names, [
+        r"\BaseNamedObjects\process_ring",
+        r"\BaseNamedObjects\image_ring",
+        r"\BaseNamedObjects\object_ring",
+        r"\BaseNamedObjects\network_ring",
+        r"\BaseNamedObjects\file_ring",
+    ]);
+}
+
+#[test]
+fn version_reply_layout_matches_the_agent() {
+    // Offsets `shared::constants::VersionInfo::from_bytes` reads.
+    use std::mem::offset_of;
+    assert_eq!(size_of::<VersionInfo>(), 24);
+    assert_eq!(
+        [
+            offset_of!(VersionInfo, protocol),
+            offset_of!(VersionInfo, major),
+            offset_of!(VersionInfo, minor),
+            offset_of!(VersionInfo, patch),
+            offset_of!(VersionInfo, reserved),
+            offset_of!(VersionInfo, build_time),
+        ],
+        [0, 4, 6, 8, 10, 16]
+    );
+    assert_eq!(DRIVER_PROTOCOL_VERSION >> 16, 2);
+}
+
+#[test]
+fn ring_stats_reply_or_not_ready() {
+    let mut out = [0u8; 40];
+    assert_eq!(route(IOCTL_GLADIX_GET_RING_STATS, 0, &mut out, &Target(Some(STATS))), Ok(40));
+    assert_eq!(u64::from_le_bytes(out[..8].try_into().unwrap()), 4096);
+    assert_eq!(u32::from_le_bytes(out[16..20].try_into().unwrap()), 3);
+    let reasons: Vec<u32> = out[24..36].chunks(4).map(|b| u32::from_le_bytes(b.try_into().unwrap())).collect();
+    assert_eq!(reasons, [2, 1, 2 << 20]);
+
+    // Agents from before the drop reasons ask for the fields they know.
+    let mut legacy = [0xaau8; 24];
+    assert_eq!(route(IOCTL_GLADIX_GET_RING_STATS, 0, &mut legacy, &Target(Some(STATS))), Ok(24));
+    assert_eq!(legacy, out[..24]);
+
+    assert_eq!(route(IOCTL_GLADIX_GET_RING_STATS, 0, &mut out, &Target(None)), Err(IoctlError::NotReady));
+}
+
+#[test]
+fn bad_requests_are_rejected_without_writing() {
+    let mut out = [0xaau8; 23];
+    let err = route(IOCTL_GLADIX_GET_RING_STATS, 0, &mut out, &Target(Some(STATS))).unwrap_err();
+    assert_eq!(err, IoctlError::BufferTooSmall { needed: 40, got: 23 });
+    assert_eq!(err.status(), STATUS_BUFFER_TOO_SMALL);
+    assert!(out.iter().all(|b| *b == 0xaa));
+
+    assert_eq!(route(IOCTL_GLADIX_PING, 8, &mut out, &Target(None)), Err(IoctlError::UnexpectedInput(8)));
+
+    let err = route(0x0022_2ffc, 0, &mut out, &Target(None)).unwrap_err();
+    assert_eq!(err.status(), STATUS_INVALID_DEVICE_REQUEST);
+    assert_eq!(route(IOCTL_GLADIX_PING, 0, &mut [], &Target(None)), Err(IoctlError::BufferTooSmall { needed: 4, got: 0 }));
+}
+
+/// 10.0.0.0/8 port 445, blocked; laid out as `shared::constants::NetRule`.
+fn block_smb() -> Vec<u8> {
+    let mut rule = vec![0u8; size_of::<NetRule>()];
+    rule[0] = 10;
+    rule[16..20].copy_from_slice(&[NET_FAMILY_V4, 8, NET_ACTION_BLOCK, 5]);
+    rule[20..24].copy_from_slice(&[0xbd, 0x01, 0xbd, 0x01]);
+    rule[24..29].copy_from_slice(b"*.exe");
+    rule
+}
+
+#[test]
+fn net_policy_rules_reach_the_target() {
+    let target = PolicyTarget::default();
+    let mut input = block_smb();
+    input.extend(block_smb());
+    assert_eq!(route(IOCTL_GLADIX_SET_NET_POLICY, input.len(), &mut input, &target), Ok(0));
+    let rules = target.0.borrow_mut().take().unwrap();
+    assert_eq!(rules.len(), 2);
+    assert_eq!((rules[0].addr[0], rules[0].prefix, rules[0].port_lo, rules[0].port_hi), (10, 8, 445, 445));
+    assert_eq!(&rules[1].process[..rules[1].process_len as usize], b"*.exe");
+
+    // An empty policy clears the rules.
+    assert_eq!(route(IOCTL_GLADIX_SET_NET_POLICY, 0, &mut [], &target), Ok(0));
+    assert_eq!(target.0.borrow().as_deref(), Some(&[][..]));
+}
+
+#[test]
+fn malformed_net_policies_are_refused() {
+    let target = PolicyTarget::default();
+    let send = |mut input: Vec<u8>| route(IOCTL_GLADIX_SET_NET_POLICY, input.len(), &mut input, &target);
+
+    let mut partial = block_smb();
+    partial.pop();
+    assert_eq!(send(partial), Err(IoctlError::BadInput));
+    assert_eq!(IoctlError::BadInput.status(), STATUS_INVALID_PARAMETER);
+
+    let mut prefix = block_smb();
+    prefix[17] = 33;
+    assert_eq!(send(prefix.clone()), Err(IoctlError::BadInput));
+    prefix[16] = NET_FAMILY_V6;
+    assert_eq!(send(prefix), Ok(0));
+
+    let mut ports = block_smb();
+    ports[20] = 0xbe;
+    assert_eq!(send(ports), Err(IoctlError::BadInput));
+
+    let mut process = block_smb();
+    process[19] = NET_RULE_PROCESS_LEN as u8 + 1;
+    assert_eq!(send(process), Err(IoctlError::BadInput));
+
+    assert_eq!(send(block_smb().repeat(NET_POLICY_MAX_RULES + 1)), Err(IoctlError::BadInput));
+    assert!(target.0.borrow().as_ref().is_some_and(|rules| rules.len() == 1));
+}
+
+#[test]
+fn protected_pids_reach_the_target() {
+    let target = PidsTarget::default();
+    let mut input: Vec<u8> = [4242u32, 7].iter().flat_map(|p| p.to_le_bytes()).collect();
+    assert_eq!(route(IOCTL_GLADIX_SET_PROTECTED_PIDS, input.len(), &mut input, &target), Ok(0));
+    assert_eq!(target.0.borrow_mut().take(), Some(vec![4242, 7]));
+
+    // An empty set stops the reports.
+    assert_eq!(route(IOCTL_GLADIX_SET_PROTECTED_PIDS, 0, &mut [], &target), Ok(0));
+    assert_eq!(target.0.borrow_mut().take(), Some(vec![]));
+}
+
+#[test]
+fn malformed_protected_pids_are_refused() {
+    let target = PidsTarget::default();
+    let send = |mut input: Vec<u8>| route(IOCTL_GLADIX_SET_PROTECTED_PIDS, input.len(), &mut input, &target);
+
+    assert_eq!(send(vec![1, 0, 0]), Err(IoctlError::BadInput));
+    assert_eq!(send([9u32, 0].iter().flat_map(|p| p.to_le_bytes()).collect()), Err(IoctlError::BadInput));
+    assert_eq!(send(1u32.to_le_bytes().repeat(PROTECTED_PIDS_MAX + 1)), Err(IoctlError::BadInput));
+    assert_eq!(target.0.borrow().as_ref(), None);
+    assert_eq!(send(1u32.to_le_bytes().repeat(PROTECTED_PIDS_MAX)), Ok(0));
+}
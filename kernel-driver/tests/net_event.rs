@@ -0,0 +1,108 @@
+//! Host tests for the `NetworkEvent` frames built in `src/wfp/net_event.rs`.
+//!
+//! The captured frame is the fixture `tests/listeners.rs` in the user-agent
+//! feeds through a ring and expects in `network_events`.
+
+#[path = "../src/consts.rs"]
+#[allow(dead_code)]
+mod consts;
+#[path = "../src/frame.rs"]
+#[allow(dead_code)]
+mod frame;
+#[path = "../src/wfp/net_event.rs"]
+#[allow(dead_code)]
+mod net_event;
+
+use net_event::{proto_name, Direction, Ipv4Text, NetworkEvent};
+
+const CAPTURED: &[u8] = include_bytes!("../../user-agent/tests/fixtures/wfp_connect_v4.frame");
+
+fn utf16(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+fn ip(octets: [u8; 4]) -> u32 {
+    u32::from_be_bytes(octets)
+}
+
+#[test]
+fn connect_frame_matches_the_capture() {
+    let exe = utf16(r"\device\harddiskvolume3\windows\system32\curl.exe");
+    let event = NetworkEvent {
+        direction: Direction::Outbound,
+        protocol:  6,
+        src_ip:    ip([10, 0, 0, 1]),
+        src_port:  49752,
+        dst_ip:    ip([93, 184, 216, 34]),
+        dst_port:  443,
+        pid:       4242,
+        exe_path:  &exe,
+    };
+
+    let mut payload = vec![0x08, 0x01, 0x12, 3];
+    payload.extend_from_slice(b"TCP");
+    payload.extend_from_slice(&[0x1a, 8]);
+    payload.extend_from_slice(b"10.0.0.1");
+    payload.extend_from_slice(&[0x20, 0xd8, 0x84, 0x03, 0x2a, 13]);
+    payload.extend_from_slice(b"93.184.216.34");
+    payload.extend_from_slice(&[0x30, 0xbb, 0x03, 0x38, 0x92, 0x21, 0x42, 49]);
+    payload.extend_from_slice(br"\device\harddiskvolume3\windows\system32\curl.exe");
+    assert_eq!(event.encoded_len(), payload.len());
+
+    let mut covered = 1u64.to_le_bytes().to_vec();
+    covered.extend_from_slice(&1_760_000_000_000_000u64.to_le_bytes());
+    covered.extend_from_slice(&payload);
+
+    let mut frame = vec![0xAA; 128];
+    assert_eq!(event.write_frame(&mut frame, 1, 1_760_000_000_000_000), Some(CAPTURED.len()));
+    assert_eq!(frame[..2], *b"GX");
+    assert_eq!(frame[2..6], (payload.len() as u32).to_le_bytes());
+    assert_eq!(frame[6..10], frame::crc32(&covered).to_le_bytes());
+    assert_eq!(frame[10..26], covered[..16]);
+    assert_eq!(frame[26..26 + payload.len()], payload[..]);
+    assert_eq!(frame[..CAPTURED.len()], CAPTURED[..]);
+    assert_eq!(frame[CAPTURED.len()], 0xAA, "nothing past the frame");
+}
+
+#[test]
+fn addresses_are_dotted_decimal() {
+    assert_eq!(Ipv4Text::new(0).as_str(), "0.0.0.0");
+    assert_eq!(Ipv4Text::new(u32::MAX).as_str(), "255.255.255.255");
+    assert_eq!(Ipv4Text::new(ip([192, 168, 10, 7])).as_str(), "192.168.10.7");
+}
+
+#[test]
+fn unnamed_protocols_and_empty_fields_are_left_out() {
+    assert_eq!(proto_name(17), "UDP");
+    let event = NetworkEvent {
+        direction: Direction::Inbound,
+        protocol:  47,
+        src_ip:    0,
+        src_port:  0,
+        dst_ip:    0,
+        dst_port:  0,
+        pid:       0,
+        exe_path:  &[],
+    };
+    // Only the two addresses, which always have text.
+    let mut frame = [0u8; 48];
+    assert_eq!(event.write_frame(&mut frame, 1, 0), Some(48));
+    assert_eq!(frame[26..35], *b"\x1a\x070.0.0.0");
+    assert_eq!(frame[35..44], *b"\x2a\x070.0.0.0");
+}
+
+#[test]
+fn short_buffers_are_refused() {
+    let event = NetworkEvent {
+        direction: Direction::Outbound,
+        protocol:  17,
+        src_ip:    1,
+        src_port:  1,
+        dst_ip:    1,
+        dst_port:  1,
+        pid:       1,
+        exe_path:  &[],
+    };
+    let mut frame = vec![0u8; event.frame_len() - 1];
+    assert_eq!(event.write_frame(&mut frame, 1, 0), None);
+}
@@ -0,0 +1,89 @@
+//! Host tests for the `ObjectOpEvent` frames and the protected pid set in
+//! `src/callbacks/object_event.rs`.
+//!
+//! The expected bytes are the ones `tests/listeners.rs` in the user-agent
+//! feeds through a ring and decodes with prost.
+
+#[path = "../src/consts.rs"]
+#[allow(dead_code)]
+mod consts;
+#[path = "../src/frame.rs"]
+#[allow(dead_code)]
+mod frame;
+#[path = "../src/callbacks/object_event.rs"]
+#[allow(dead_code)]
+mod object_event;
+
+use consts::PROTECTED_PIDS_MAX;
+use object_event::{
+    reportable, ObjectOpEvent, ProtectedPids, OPERATION_HANDLE_CREATE, OPERATION_HANDLE_DUPLICATE, PROCESS_VM_READ,
+    PROCESS_VM_WRITE,
+};
+
+#[test]
+fn duplicate_frame_matches_the_hand_encoding() {
+    let event = ObjectOpEvent {
+        source_pid:     4242,
+        target_pid:     1337,
+        desired_access: 0x1f_ffff,
+        operation:      OPERATION_HANDLE_DUPLICATE,
+    };
+
+    let payload = [0x08, 0x92, 0x21, 0x10, 0xb9, 0x0a, 0x18, 0xff, 0xff, 0x7f, 0x20, 0x01];
+    assert_eq!(event.encoded_len(), payload.len());
+
+    let mut covered = 9u64.to_le_bytes().to_vec();
+    covered.extend_from_slice(&1_760_000_000_000_000u64.to_le_bytes());
+    covered.extend_from_slice(&payload);
+
+    let mut frame = vec![0xAA; 48];
+    assert_eq!(event.write_frame(&mut frame, 9, 1_760_000_000_000_000), Some(40));
+    assert_eq!(frame[..2], *b"GX");
+    assert_eq!(frame[2..6], (payload.len() as u32).to_le_bytes());
+    assert_eq!(frame[6..10], frame::crc32(&covered).to_le_bytes());
+    assert_eq!(frame[10..26], covered[..16]);
+    assert_eq!(frame[26..38], payload);
+    assert_eq!(frame[38..40], [0, 0], "padding");
+    assert_eq!(frame[40], 0xAA, "nothing past the frame");
+}
+
+#[test]
+fn handle_create_is_the_default_and_left_out() {
+    let event = ObjectOpEvent {
+        source_pid:     4,
+        target_pid:     8,
+        desired_access: PROCESS_VM_READ,
+        operation:      OPERATION_HANDLE_CREATE,
+    };
+    let mut frame = [0u8; 32];
+    assert_eq!(event.write_frame(&mut frame, 1, 0), Some(32));
+    assert_eq!(frame[26..], [0x08, 4, 0x10, 8, 0x18, 0x10]);
+    assert_eq!(event.write_frame(&mut frame[..31], 1, 0), None);
+}
+
+#[test]
+fn only_memory_access_is_reported() {
+    assert!(reportable(PROCESS_VM_READ));
+    assert!(reportable(PROCESS_VM_WRITE | 0x0400));
+    // PROCESS_QUERY_LIMITED_INFORMATION | SYNCHRONIZE, what most tools ask for.
+    assert!(!reportable(0x1000 | 0x0010_0000));
+}
+
+#[test]
+fn protected_pids_are_replaced_whole() {
+    let pids = ProtectedPids::new();
+    assert!(!pids.contains(0));
+    assert!(!pids.contains(4242));
+
+    pids.replace([4242, 7]);
+    assert!(pids.contains(4242) && pids.contains(7));
+    assert!(!pids.contains(0), "free slots match nothing");
+
+    pids.replace([8]);
+    assert!(pids.contains(8));
+    assert!(!pids.contains(4242) && !pids.contains(7));
+
+    pids.replace(1..=PROTECTED_PIDS_MAX as u32 + 4);
+    assert!(pids.contains(PROTECTED_PIDS_MAX as u32));
+    assert!(!pids.contains(PROTECTED_PIDS_MAX as u32 + 1));
+}
@@ -0,0 +1,214 @@
+//! Host tests for the kernel pointer wrappers in `src/ownership.rs`.
+//!
+//! The driver crate is a `cdylib` linked against the WDK, so the module is
+//! included by path and driven through a mock [`KernelApi`] that records
+//! every kernel call.
+
+#[path = "../src/ownership.rs"]
+#[allow(dead_code)]
+mod ownership;
+
+use std::{
+    alloc::{alloc, dealloc, Layout},
+    ffi::c_void,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use ownership::{KernelApi, KernelBox, SectionMapping, SharedRef, WrongIrql, DISPATCH_LEVEL, POOL_TAG};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Call {
+    Allocate(usize),
+    Free,
+    Unmap(usize),
+    Dereference(usize),
+}
+
+struct Kernel {
+    calls: Mutex<Vec<Call>>,
+    irql:  AtomicU8,
+}
+
+#[derive(Clone, Copy)]
+struct Mock(&'static Kernel);
+
+impl Mock {
+    fn new() -> Self {
+        Self(Box::leak(Box::new(Kernel { calls: Mutex::default(), irql: AtomicU8::new(0) })))
+    }
+
+    fn calls(self) -> Vec<Call> {
+        self.0.calls.lock().unwrap().clone()
+    }
+
+    fn record(self, call: Call) {
+        self.0.calls.lock().unwrap().push(call);
+    }
+}
+
+/// Size prefix so `free` can rebuild the layout.
+const HEADER: usize = 16;
+
+unsafe impl KernelApi for Mock {
+    fn allocate(self, size: usize, tag: u32) -> *mut u8 {
+        assert_eq!(tag, POOL_TAG);
+        self.record(Call::Allocate(size));
+        unsafe {
+            let block = alloc(Layout::from_size_align(size + HEADER, 16).unwrap());
+            block.cast::<usize>().write(size);
+            block.add(HEADER)
+        }
+    }
+
+    unsafe fn free(self, block: *mut u8, tag: u32) {
+        assert_eq!(tag, POOL_TAG);
+        self.record(Call::Free);
+        let block = block.sub(HEADER);
+        let size = block.cast::<usize>().read();
+        dealloc(block, Layout::from_size_align(size + HEADER, 16).unwrap());
+    }
+
+    unsafe fn unmap_view(self, base: *mut c_void) {
+        self.record(Call::Unmap(base as usize));
+    }
+
+    unsafe fn dereference(self, object: *mut c_void) {
+        self.record(Call::Dereference(object as usize));
+    }
+
+    fn current_irql(self) -> u8 {
+        self.0.irql.load(Ordering::SeqCst)
+    }
+
+    fn wait_briefly(self) {
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Sets a flag when dropped, to check the pool block still held it.
+struct Tracked(Arc<AtomicBool>);
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn kernel_box_drops_value_then_frees_block() {
+    let api = Mock::new();
+    let dropped = Arc::new(AtomicBool::new(false));
+    let b = KernelBox::new(api, Tracked(dropped.clone())).unwrap();
+    let raw = b.into_raw();
+    assert_eq!(api.calls(), [Call::Allocate(size_of::<Tracked>())]);
+
+    drop(unsafe { KernelBox::from_raw(api, raw) });
+    assert!(dropped.load(Ordering::SeqCst));
+    assert_eq!(api.calls().last(), Some(&Call::Free));
+}
+
+#[test]
+fn section_unmaps_before_dereferencing_once() {
+    let api = Mock::new();
+    let mut section = unsafe { SectionMapping::from_raw(api, 0x10 as *mut c_void, 0x2000 as *mut c_void, 4096) };
+    assert!(section.is_mapped());
+    section.release();
+    assert!(!section.is_mapped());
+    section.release();
+    drop(section);
+    assert_eq!(api.calls(), [Call::Unmap(0x2000), Call::Dereference(0x10)]);
+}
+
+#[test]
+fn retire_refuses_above_passive() {
+    let api = Mock::new();
+    let shared = SharedRef::empty(api);
+    shared.publish(KernelBox::new(api, 7u32).unwrap()).ok().unwrap();
+
+    api.0.irql.store(DISPATCH_LEVEL, Ordering::SeqCst);
+    assert_eq!(shared.retire().err(), Some(WrongIrql { required: 0, current: DISPATCH_LEVEL }));
+    assert_eq!(*shared.acquire().unwrap(), 7, "still published");
+
+    api.0.irql.store(0, Ordering::SeqCst);
+    assert_eq!(shared.retire().unwrap().as_deref(), Some(&7));
+    assert!(shared.acquire().is_none());
+    assert!(shared.retire().unwrap().is_none());
+}
+
+#[test]
+fn publish_twice_hands_the_value_back() {
+    let api = Mock::new();
+    let shared = SharedRef::empty(api);
+    shared.publish(KernelBox::new(api, 1u32).unwrap()).ok().unwrap();
+    let rejected = shared.publish(KernelBox::new(api, 2u32).unwrap()).err().unwrap();
+    assert_eq!(*rejected, 2);
+}
+
+#[test]
+fn teardown_waits_for_an_active_reader() {
+    let api = Mock::new();
+    let shared: &'static SharedRef<Tracked, Mock> = Box::leak(Box::new(SharedRef::empty(api)));
+    let dropped = Arc::new(AtomicBool::new(false));
+    shared.publish(KernelBox::new(api, Tracked(dropped.clone())).unwrap()).ok().unwrap();
+
+    let (entered, release) = (mpsc::channel(), mpsc::channel::<()>());
+    let reader = thread::spawn(move || {
+        let guard = shared.acquire().unwrap();
+        entered.0.send(()).unwrap();
+        release.1.recv().unwrap();
+        // Still valid: retire is blocked on this guard.
+        assert!(!guard.0.load(Ordering::SeqCst));
+    });
+    entered.1.recv().unwrap();
+
+    let unload = thread::spawn(move || drop(shared.retire().unwrap()));
+    thread::sleep(Duration::from_millis(20));
+    assert!(!unload.is_finished(), "retire returned with a reader inside");
+    assert!(shared.acquire().is_none(), "new readers see the value retired");
+    assert!(!api.calls().contains(&Call::Free));
+
+    release.0.send(()).unwrap();
+    reader.join().unwrap();
+    unload.join().unwrap();
+    assert!(dropped.load(Ordering::SeqCst));
+    assert_eq!(shared.active_readers(), 0);
+    assert_eq!(api.calls().last(), Some(&Call::Free));
+}
+
+#[test]
+fn callbacks_racing_the_unload_never_see_freed_state() {
+    let api = Mock::new();
+    let shared: &'static SharedRef<Tracked, Mock> = Box::leak(Box::new(SharedRef::empty(api)));
+    let dropped = Arc::new(AtomicBool::new(false));
+    shared.publish(KernelBox::new(api, Tracked(dropped.clone())).unwrap()).ok().unwrap();
+
+    // Callbacks on other CPUs, entering and leaving until they find the
+    // state gone.
+    let callbacks: Vec<_> = (0..8)
+        .map(|_| {
+            thread::spawn(move || {
+                let mut entered = 0u32;
+                while let Some(guard) = shared.acquire() {
+                    assert!(!guard.0.load(Ordering::SeqCst), "state freed under a callback");
+                    entered += 1;
+                    thread::yield_now();
+                }
+                entered
+            })
+        })
+        .collect();
+    thread::sleep(Duration::from_millis(20));
+
+    drop(shared.retire().unwrap());
+    assert!(dropped.load(Ordering::SeqCst));
+    for callback in callbacks {
+        assert!(callback.join().unwrap() > 0);
+    }
+    assert_eq!(shared.active_readers(), 0);
+    assert!(shared.acquire().is_none());
+}
@@ -0,0 +1,111 @@
+//! Host tests for the `ProcessEvent` frames built in
+//! `src/callbacks/process_event.rs` and the caps on their strings.
+
+#[path = "../src/consts.rs"]
+#[allow(dead_code)]
+mod consts;
+#[path = "../src/frame.rs"]
+#[allow(dead_code)]
+mod frame;
+#[path = "../src/callbacks/process_event.rs"]
+#[allow(dead_code)]
+mod process_event;
+
+use consts::{PROCESS_CMDLINE_MAX, PROCESS_IMAGE_PATH_MAX, TRUNCATED_CMDLINE, TRUNCATED_PARENT_IMAGE_PATH};
+use frame::truncate_utf16;
+use process_event::{ProcessEvent, EVENT_TYPE_EXIT};
+
+fn utf16(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+#[test]
+fn an_oversized_cmdline_is_cut_and_flagged() {
+    let image = utf16(r"C:\Windows\System32\WindowsPowerShell\v1.0\powershell.exe");
+    let cmdline = utf16(&format!("powershell -enc {}", "A".repeat(300 * 1024)));
+    let event = ProcessEvent {
+        pid: 4242,
+        ppid: 600,
+        image_path: &image,
+        cmdline: &cmdline,
+        ..ProcessEvent::default()
+    }
+    .capped();
+
+    assert_eq!(event.cmdline.len() * 2, PROCESS_CMDLINE_MAX);
+    assert_eq!(event.cmdline, &cmdline[..PROCESS_CMDLINE_MAX / 2]);
+    assert_eq!(event.image_path, &image[..]);
+    assert_eq!(event.truncated, TRUNCATED_CMDLINE);
+
+    // pid, ppid, the image path, the 4096-char command line (2-byte
+    // length), then `truncated`.
+    let expected = 3 + 3 + 2 + image.len() + 3 + PROCESS_CMDLINE_MAX / 2 + 2;
+    assert_eq!(event.encoded_len(), expected);
+    let mut frame = vec![0u8; event.frame_len()];
+    assert_eq!(event.write_frame(&mut frame, 1, 0), Some(frame.len()));
+    assert_eq!(frame[26 + expected - 2..26 + expected], [0x58, TRUNCATED_CMDLINE as u8]);
+}
+
+#[test]
+fn cuts_fall_on_character_boundaries() {
+    // U+1F600 is a surrogate pair: cutting after its first half would leave
+    // an unpaired surrogate.
+    let s = utf16("ab\u{1F600}cd");
+    assert_eq!(truncate_utf16(&s, 8), (&s[..4], true));
+    assert_eq!(truncate_utf16(&s, 6), (&s[..2], true));
+    assert_eq!(truncate_utf16(&s, 4), (&s[..2], true));
+    assert_eq!(truncate_utf16(&s, 12), (&s[..], false));
+    assert_eq!(truncate_utf16(&s, 0), (&s[..0], true));
+    assert_eq!(truncate_utf16(&[], 0), (&[][..], false));
+}
+
+#[test]
+fn parent_paths_are_capped_and_exits_keep_their_status() {
+    let parent = utf16(&"\\dir".repeat(PROCESS_IMAGE_PATH_MAX));
+    let event = ProcessEvent {
+        pid: 7,
+        parent_image_path: &parent,
+        ..ProcessEvent::default()
+    }
+    .capped();
+    assert_eq!(event.truncated, TRUNCATED_PARENT_IMAGE_PATH);
+    assert_eq!(event.parent_image_path.len(), PROCESS_IMAGE_PATH_MAX / 2);
+
+    let exit = ProcessEvent { pid: 7, event_type: EVENT_TYPE_EXIT, exit_code: -1_073_741_819, ..ProcessEvent::default() }
+        .capped();
+    assert_eq!(exit.truncated, 0);
+    // STATUS_ACCESS_VIOLATION, sign-extended to 10 bytes.
+    let mut payload = vec![0x08, 7, 0x38, 1, 0x40];
+    payload.extend_from_slice(&[0x85, 0x80, 0x80, 0x80, 0xfc, 0xff, 0xff, 0xff, 0xff, 0x01]);
+    assert_eq!(exit.encoded_len(), payload.len());
+    let mut frame = [0u8; 48];
+    assert_eq!(exit.write_frame(&mut frame, 1, 0), Some(48));
+    assert_eq!(frame[26..26 + payload.len()], payload[..]);
+}
+
+#[test]
+fn token_fields_follow_truncated_and_are_left_out_when_unknown() {
+    let image = utf16(r"C:\Windows\System32\cmd.exe");
+    let sid = utf16("S-1-5-21-1004336348-1177238915-682003330-1001");
+    let event = ProcessEvent {
+        pid: 9,
+        image_path: &image,
+        user_sid: &sid,
+        session_id: 1,
+        elevated: true,
+        ..ProcessEvent::default()
+    }
+    .capped();
+    let mut tail = vec![0x62, sid.len() as u8];
+    tail.extend(String::from_utf16(&sid).unwrap().bytes());
+    tail.extend([0x68, 1, 0x70, 1]);
+    let expected = 2 + 2 + image.len() + tail.len();
+    assert_eq!(event.encoded_len(), expected);
+    let mut frame = vec![0u8; event.frame_len()];
+    assert_eq!(event.write_frame(&mut frame, 1, 0), Some(frame.len()));
+    assert_eq!(frame[26 + expected - tail.len()..26 + expected], tail[..]);
+
+    // A token that could not be read, or session 0: nothing is encoded.
+    let unknown = ProcessEvent { pid: 9, image_path: &image, ..ProcessEvent::default() };
+    assert_eq!(unknown.encoded_len(), 2 + 2 + image.len());
+}
@@ -0,0 +1,348 @@
+//! Host tests for the ring producer in `src/ring.rs`: where frames are
+//! reserved, that only frames written whole are published, and that a
+//! retired ring is no longer written to.
+
+#[path = "../src/consts.rs"]
+#[allow(dead_code)]
+mod consts;
+#[path = "../src/frame.rs"]
+#[allow(dead_code)]
+mod frame;
+#[path = "../src/callbacks/image_event.rs"]
+#[allow(dead_code)]
+mod image_event;
+#[path = "../src/ownership.rs"]
+#[allow(dead_code)]
+mod ownership;
+#[path = "../src/ring.rs"]
+#[allow(dead_code)]
+mod ring;
+
+use std::{
+    alloc::{alloc, dealloc, Layout},
+    cell::Cell,
+    ffi::c_void,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
+
+use consts::{ring_frame_len, RING_FRAME_MAGIC, RING_FRAME_PREFIX};
+use image_event::ImageLoadEvent;
+use ownership::{KernelApi, KernelBox, PASSIVE_LEVEL};
+use ring::{reserve, EventRing, NoRoom, Push, RingHeader, RingSlot, Reserved};
+
+const SIZE: usize = 256;
+
+/// Pool on the host heap, always at `PASSIVE_LEVEL`.
+#[derive(Clone, Copy)]
+struct Host;
+
+/// Size prefix so `free` can rebuild the layout.
+const PREFIX: usize = 16;
+
+unsafe impl KernelApi for Host {
+    fn allocate(self, size: usize, _tag: u32) -> *mut u8 {
+        unsafe {
+            let block = alloc(Layout::from_size_align(size + PREFIX, 16).unwrap());
+            block.cast::<usize>().write(size);
+            block.add(PREFIX)
+        }
+    }
+
+    unsafe fn free(self, block: *mut u8, _tag: u32) {
+        let block = block.sub(PREFIX);
+        let size = block.cast::<usize>().read();
+        dealloc(block, Layout::from_size_align(size + PREFIX, 16).unwrap());
+    }
+
+    unsafe fn unmap_view(self, _base: *mut c_void) {
+        unreachable!("a ring maps nothing itself");
+    }
+
+    unsafe fn dereference(self, _object: *mut c_void) {
+        unreachable!("a ring references nothing itself");
+    }
+
+    fn current_irql(self) -> u8 {
+        PASSIVE_LEVEL
+    }
+
+    fn wait_briefly(self) {
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// A slot with a ring over `size` bytes at `data` installed.
+fn installed(header: &'static RingHeader, data: *mut u8, size: usize) -> RingSlot<Host> {
+    let slot = RingSlot::new(Host);
+    let ring = KernelBox::new(Host, unsafe { EventRing::new(header, data, size) }).unwrap();
+    assert!(slot.install(ring).is_ok());
+    slot
+}
+
+fn utf16(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+/// Frame number `seq` occupying `len` bytes (a multiple of 8, at least 32).
+fn write(out: &mut [u8], seq: u64, len: usize) -> Option<usize> {
+    // A string of n bytes encodes in n + 2 while n is below 128.
+    let text = "x".repeat(len - RING_FRAME_PREFIX - 2);
+    let payload_len = frame::str_field_len(&text);
+    assert_eq!(ring_frame_len(payload_len), len);
+    frame::write_frame(out, seq, 0, payload_len, |w| w.str_field(frame::len_tag(1), &text))
+}
+
+/// Sequence numbers of the whole frames in `data[from..to]`.
+fn frames(data: &[u8], from: usize, to: usize) -> Vec<u64> {
+    let mut seqs = Vec::new();
+    let mut at = from;
+    while at < to {
+        let prefix = &data[at..at + RING_FRAME_PREFIX];
+        assert_eq!(u16::from_le_bytes([prefix[0], prefix[1]]), RING_FRAME_MAGIC, "no frame at {at}");
+        let len = u32::from_le_bytes(prefix[2..6].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(prefix[6..10].try_into().unwrap());
+        assert_eq!(frame::crc32(&data[at + 10..at + RING_FRAME_PREFIX + len]), crc, "damaged frame at {at}");
+        seqs.push(u64::from_le_bytes(prefix[10..18].try_into().unwrap()));
+        at += ring_frame_len(len);
+    }
+    seqs
+}
+
+#[test]
+fn a_frame_goes_at_the_tail_while_it_fits_before_the_end() {
+    assert_eq!(reserve(0, 0, SIZE, 32), Ok(Reserved { at: 0, wrap: false, tail: 32 }));
+    assert_eq!(reserve(32, 96, SIZE, 64), Ok(Reserved { at: 96, wrap: false, tail: 160 }));
+    // Ending exactly at the end wraps the tail, unless that meets the head.
+    assert_eq!(reserve(32, 192, SIZE, 64), Ok(Reserved { at: 192, wrap: false, tail: 0 }));
+    assert_eq!(reserve(0, 192, SIZE, 64), Err(NoRoom::Full));
+}
+
+#[test]
+fn a_frame_that_does_not_fit_before_the_end_starts_at_zero() {
+    assert_eq!(reserve(96, 224, SIZE, 64), Ok(Reserved { at: 0, wrap: true, tail: 64 }));
+    // Starting at 0 it would reach the head.
+    assert_eq!(reserve(64, 224, SIZE, 64), Err(NoRoom::Full));
+    assert_eq!(reserve(0, 224, SIZE, 64), Err(NoRoom::Full));
+}
+
+#[test]
+fn behind_the_head_a_frame_must_stop_short_of_it() {
+    assert_eq!(reserve(128, 32, SIZE, 64), Ok(Reserved { at: 32, wrap: false, tail: 96 }));
+    assert_eq!(reserve(128, 64, SIZE, 64), Err(NoRoom::Full));
+    assert_eq!(reserve(128, 96, SIZE, 64), Err(NoRoom::Full));
+}
+
+#[test]
+fn offsets_outside_the_data_area_find_no_room() {
+    assert_eq!(reserve(SIZE + 8, 0, SIZE, 32), Err(NoRoom::Full));
+    assert_eq!(reserve(0, SIZE, SIZE, 32), Err(NoRoom::Full));
+}
+
+#[test]
+fn a_frame_as_long_as_the_data_area_never_fits() {
+    // Not even in an empty ring: the tail would meet the head.
+    assert_eq!(reserve(0, 0, SIZE, SIZE), Err(NoRoom::Oversize));
+    assert_eq!(reserve(128, 128, SIZE, SIZE + 32), Err(NoRoom::Oversize));
+    // Whatever the offsets.
+    assert_eq!(reserve(SIZE + 8, 0, SIZE, SIZE), Err(NoRoom::Oversize));
+    assert_eq!(reserve(0, 0, SIZE, SIZE - 8), Ok(Reserved { at: 0, wrap: false, tail: SIZE - 8 }));
+}
+
+#[test]
+fn an_event_is_encoded_in_place_and_published() {
+    let header = RingHeader::new();
+    let mut data = vec![0xAAu8; SIZE];
+    let ring = unsafe { EventRing::new(&header, data.as_mut_ptr(), SIZE) };
+    let name = utf16(r"\SystemRoot\System32\ntdll.dll");
+    let event = ImageLoadEvent {
+        pid:              4,
+        image_base:       0x7ff8_0000_0000,
+        image_size:       0x1f_0000,
+        full_image_name:  &name,
+        is_kernel_module: false,
+    };
+
+    let pushed = ring.push_with(event.frame_len(), |out| event.write_frame(out, 1, 42));
+    assert_eq!(pushed, Push::Published { was_empty: true });
+    let pushed = ring.push_with(event.frame_len(), |out| event.write_frame(out, 2, 43));
+    assert_eq!(pushed, Push::Published { was_empty: false });
+
+    let len = event.frame_len();
+    assert_eq!(header.tail.load(Ordering::Acquire) as usize, 2 * len);
+    let mut expected = vec![0u8; len];
+    event.write_frame(&mut expected, 1, 42).unwrap();
+    assert_eq!(&data[..len], expected.as_slice());
+    assert_eq!(frames(&data, 0, 2 * len), [1, 2]);
+    assert_eq!(header.dropped.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn a_failed_encoding_is_never_published() {
+    let header = RingHeader::new();
+    let mut data = vec![0u8; SIZE];
+    let ring = unsafe { EventRing::new(&header, data.as_mut_ptr(), SIZE) };
+
+    assert_eq!(ring.push_with(32, |out| write(out, 1, 32)), Push::Published { was_empty: true });
+    // Half written, then given up.
+    let pushed = ring.push_with(64, |out| {
+        out[..RING_FRAME_PREFIX].fill(0xFF);
+        None
+    });
+    assert_eq!(pushed, Push::Dropped);
+    // Written, but shorter than reserved.
+    assert_eq!(ring.push_with(64, |out| write(out, 3, 32)), Push::Dropped);
+
+    assert_eq!(header.tail.load(Ordering::Acquire), 32);
+    assert_eq!(header.dropped.load(Ordering::Relaxed), 2);
+    // There was room: neither reason counts them.
+    assert_eq!(header.dropped_full.load(Ordering::Relaxed), 0);
+    assert_eq!(header.dropped_oversize.load(Ordering::Relaxed), 0);
+    // The next frame takes the abandoned room.
+    assert_eq!(ring.push_with(32, |out| write(out, 4, 32)), Push::Published { was_empty: false });
+    assert_eq!(frames(&data, 0, 64), [1, 4]);
+}
+
+#[test]
+fn a_wrapping_frame_zeroes_what_it_leaves_behind() {
+    let header = RingHeader::new();
+    let mut data = vec![0u8; SIZE];
+    let ring = unsafe { EventRing::new(&header, data.as_mut_ptr(), SIZE) };
+    for seq in 1..=7 {
+        assert!(matches!(ring.push_with(32, |out| write(out, seq, 32)), Push::Published { .. }));
+    }
+    assert_eq!(header.tail.load(Ordering::Acquire), 224);
+    // The consumer read the first three.
+    header.head.store(96, Ordering::Release);
+    data[224..].fill(0xEE);
+
+    assert_eq!(ring.push_with(64, |out| write(out, 8, 64)), Push::Published { was_empty: false });
+    assert_eq!(header.tail.load(Ordering::Acquire), 64);
+    assert!(data[224..].iter().all(|&b| b == 0));
+    assert_eq!(frames(&data, 96, 224), [4, 5, 6, 7]);
+    assert_eq!(frames(&data, 0, 64), [8]);
+}
+
+#[test]
+fn a_full_ring_drops_without_encoding() {
+    let header = RingHeader::new();
+    let mut data = vec![0u8; SIZE];
+    let ring = unsafe { EventRing::new(&header, data.as_mut_ptr(), SIZE) };
+    let mut seq = 0;
+    while let Push::Published { .. } = ring.push_with(32, |out| write(out, seq + 1, 32)) {
+        seq += 1;
+    }
+    // Never fills up completely: head == tail would read as empty.
+    assert_eq!(seq, 7);
+
+    let called = Cell::new(false);
+    assert_eq!(ring.push_with(32, |_| Some(called.replace(true)).map(|_| 32)), Push::Dropped);
+    assert!(!called.get());
+    assert_eq!(header.dropped.load(Ordering::Relaxed), 2);
+    assert_eq!(header.dropped_full.load(Ordering::Relaxed), 2);
+    assert_eq!(header.dropped_oversize.load(Ordering::Relaxed), 0);
+
+    let slot = RingSlot::new(Host);
+    assert_eq!(slot.push_with(32, |_| Some(called.replace(true)).map(|_| 32)), Push::Dropped);
+    assert!(!called.get());
+}
+
+#[test]
+fn an_oversize_frame_is_counted_apart_from_a_full_ring() {
+    let header = RingHeader::new();
+    let mut data = vec![0u8; SIZE];
+    let ring = unsafe { EventRing::new(&header, data.as_mut_ptr(), SIZE) };
+
+    let called = Cell::new(false);
+    assert_eq!(ring.push_with(SIZE, |_| Some(called.replace(true)).map(|_| SIZE)), Push::Dropped);
+    assert_eq!(ring.push_with(4 * SIZE, |_| Some(called.replace(true)).map(|_| SIZE)), Push::Dropped);
+    assert!(!called.get());
+    assert_eq!(ring.push_with(64, |out| write(out, 1, 64)), Push::Published { was_empty: true });
+
+    assert_eq!(header.dropped.load(Ordering::Relaxed), 2);
+    assert_eq!(header.dropped_oversize.load(Ordering::Relaxed), 2);
+    assert_eq!(header.dropped_full.load(Ordering::Relaxed), 0);
+    // The high-water mark counts the frames refused too.
+    assert_eq!(header.max_observed_len.load(Ordering::Relaxed), 4 * SIZE as u32);
+}
+
+#[test]
+fn producers_on_several_threads_each_publish_whole_frames() {
+    const BIG: usize = 64 * 1024;
+    const PER_THREAD: u64 = 400;
+    let header: &'static RingHeader = Box::leak(Box::new(RingHeader::new()));
+    let data = Box::leak(vec![0u8; BIG].into_boxed_slice()).as_mut_ptr();
+    let slot: &'static RingSlot<Host> = Box::leak(Box::new(installed(header, data, BIG)));
+
+    let threads: Vec<_> = (0..4u64)
+        .map(|t| {
+            thread::spawn(move || {
+                for i in 0..PER_THREAD {
+                    let seq = t * PER_THREAD + i + 1;
+                    assert!(matches!(slot.push_with(32, |out| write(out, seq, 32)), Push::Published { .. }));
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let tail = header.tail.load(Ordering::Acquire) as usize;
+    assert_eq!(tail, 4 * PER_THREAD as usize * 32);
+    let mut seqs = frames(unsafe { std::slice::from_raw_parts(data, BIG) }, 0, tail);
+    seqs.sort_unstable();
+    assert_eq!(seqs, (1..=4 * PER_THREAD).collect::<Vec<_>>());
+}
+
+#[test]
+fn a_slot_reports_its_ring_until_retired() {
+    let header: &'static RingHeader = Box::leak(Box::new(RingHeader::new()));
+    let data = Box::leak(vec![0u8; SIZE].into_boxed_slice()).as_mut_ptr();
+    let slot = RingSlot::new(Host);
+    assert_eq!(slot.stats(), None);
+
+    let ring = KernelBox::new(Host, unsafe { EventRing::new(header, data, SIZE) }).unwrap();
+    assert!(slot.install(ring).is_ok());
+    assert_eq!(slot.push_with(64, |out| write(out, 1, 64)), Push::Published { was_empty: true });
+    assert_eq!(slot.push_with(SIZE, |_| None), Push::Dropped);
+    let stats = slot.stats().unwrap();
+    assert_eq!((stats.head, stats.tail, stats.size), (0, 64, SIZE as u32));
+    assert_eq!((stats.dropped, stats.dropped_oversize, stats.max_observed_len), (1, 1, SIZE as u32));
+
+    assert!(matches!(slot.retire(), Ok(Some(_))));
+    assert!(matches!(slot.retire(), Ok(None)));
+    assert_eq!(slot.stats(), None);
+    assert_eq!(slot.push_with(32, |out| write(out, 2, 32)), Push::Dropped);
+    assert_eq!(header.tail.load(Ordering::Acquire), 64);
+}
+
+#[test]
+fn retiring_a_ring_waits_for_the_push_in_flight() {
+    let header: &'static RingHeader = Box::leak(Box::new(RingHeader::new()));
+    let data = Box::leak(vec![0u8; SIZE].into_boxed_slice()).as_mut_ptr();
+    let slot: &'static RingSlot<Host> = Box::leak(Box::new(installed(header, data, SIZE)));
+    let written: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
+
+    let (started, wait) = mpsc::channel();
+    let producer = thread::spawn(move || {
+        slot.push_with(64, |out| {
+            started.send(()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            written.store(true, Ordering::SeqCst);
+            write(out, 1, 64)
+        })
+    });
+    wait.recv().unwrap();
+
+    // The view under the ring may be unmapped as soon as this returns.
+    assert!(matches!(slot.retire(), Ok(Some(_))));
+    assert!(written.load(Ordering::SeqCst));
+    assert_eq!(producer.join().unwrap(), Push::Published { was_empty: true });
+    assert_eq!(slot.push_with(32, |out| write(out, 2, 32)), Push::Dropped);
+    assert_eq!(header.tail.load(Ordering::Acquire), 64);
+}
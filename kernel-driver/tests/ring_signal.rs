@@ -0,0 +1,48 @@
+//! Host tests for the ring event throttle in `src/ring_signal.rs`.
+
+#[path = "../src/ring_signal.rs"]
+mod ring_signal;
+
+use ring_signal::{RingSignal, SIGNAL_INTERVAL_MICROS};
+
+const T0: u64 = 1_700_000_000_000_000;
+
+#[test]
+fn a_push_into_an_empty_ring_signals_and_the_ones_after_it_do_not() {
+    let signal = RingSignal::new();
+    assert!(signal.pushed(true, T0));
+    assert!(!signal.pushed(false, T0 + 1));
+    assert!(!signal.pushed(false, T0 + 10 * SIGNAL_INTERVAL_MICROS));
+}
+
+#[test]
+fn an_empty_ring_refilled_within_the_interval_is_signalled_by_a_later_push() {
+    let signal = RingSignal::new();
+    assert!(signal.pushed(true, T0));
+    // Drained and refilled at once: held back, not forgotten.
+    assert!(!signal.pushed(true, T0 + 10));
+    assert!(!signal.pushed(false, T0 + SIGNAL_INTERVAL_MICROS - 1));
+    assert!(signal.pushed(false, T0 + SIGNAL_INTERVAL_MICROS));
+    assert!(!signal.pushed(false, T0 + 3 * SIGNAL_INTERVAL_MICROS));
+}
+
+#[test]
+fn a_busy_ring_signals_once_per_emptying() {
+    let signal = RingSignal::new();
+    let mut signals = 0;
+    for i in 0..10_000u64 {
+        // Emptied by the consumer every 1000 pushes, one push per 10 µs.
+        if signal.pushed(i % 1000 == 0, T0 + i * 10) {
+            signals += 1;
+        }
+    }
+    assert_eq!(signals, 10);
+}
+
+#[test]
+fn a_clock_going_back_holds_the_signal_until_it_passes_the_last_one() {
+    let signal = RingSignal::new();
+    assert!(signal.pushed(true, T0));
+    assert!(!signal.pushed(true, T0 - 5 * SIGNAL_INTERVAL_MICROS));
+    assert!(signal.pushed(false, T0 + SIGNAL_INTERVAL_MICROS));
+}
@@ -0,0 +1,35 @@
+//! Host tests for the ring size negotiation in `src/consts.rs`: what
+//! `RingSizeBytes` asks for becomes whole pages within bounds.
+
+#[path = "../src/consts.rs"]
+#[allow(dead_code)]
+mod consts;
+
+use consts::{ring_size, PAGE_SIZE, PARAMETERS_KEY, RING_SIZE_DEFAULT, RING_SIZE_MAX, RING_SIZE_MIN, RING_SIZE_VALUE};
+
+const KIB: u32 = 1024;
+const MIB: u32 = 1024 * KIB;
+
+#[test]
+fn sizes_in_bounds_are_rounded_up_to_a_page() {
+    assert_eq!(ring_size(4 * MIB), 4 * MIB);
+    assert_eq!(ring_size(4 * MIB + 1), 4 * MIB + PAGE_SIZE);
+    assert_eq!(ring_size(100 * KIB), 100 * KIB);
+    assert_eq!(ring_size(100 * KIB - 1), 100 * KIB);
+    assert_eq!(ring_size(RING_SIZE_MAX - PAGE_SIZE + 1), RING_SIZE_MAX);
+}
+
+#[test]
+fn sizes_out_of_bounds_are_clamped() {
+    assert_eq!(ring_size(0), RING_SIZE_MIN);
+    assert_eq!(ring_size(RING_SIZE_MIN - 1), RING_SIZE_MIN);
+    assert_eq!(ring_size(RING_SIZE_MAX + 1), RING_SIZE_MAX);
+    assert_eq!(ring_size(u32::MAX), RING_SIZE_MAX);
+    assert_eq!(ring_size(RING_SIZE_DEFAULT), RING_SIZE_DEFAULT);
+}
+
+#[test]
+fn value_is_read_from_the_parameters_key() {
+    assert_eq!(String::from_utf16(&PARAMETERS_KEY).unwrap(), r"\Parameters");
+    assert_eq!(String::from_utf16(&RING_SIZE_VALUE).unwrap(), "RingSizeBytes");
+}
@@ -1,18 +1,27 @@
+use std::{env, path::PathBuf};
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Embedded by `lib.rs` so the agent can describe its schema without
+    // shipping the .proto files.
+    let descriptors = PathBuf::from(env::var("OUT_DIR")?).join("gladix_descriptors.bin");
+
     tonic_build::configure()
         .build_server(true)
         .build_client(true)
         .out_dir("src/proto_gen")
+        .file_descriptor_set_path(&descriptors)
         .compile_protos(
             &[
                 "proto/events.proto",
                 "proto/config.proto",
+                "proto/status.proto",
             ],
             &["proto"],
         )?;
 
     println!("cargo:rerun-if-changed=proto/events.proto");
     println!("cargo:rerun-if-changed=proto/config.proto");
+    println!("cargo:rerun-if-changed=proto/status.proto");
     println!("cargo:rerun-if-changed=build.rs");
 
     Ok(())
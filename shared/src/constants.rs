@@ -0,0 +1,250 @@
+//! Values the driver and the user-agent must agree on.
+//!
+//! The driver repeats these in `kernel-driver/src/consts.rs`; keep both in
+//! step and bump [`DRIVER_PROTOCOL_VERSION`] on any change to them.
+
+/// Win32 path of the driver's control device.
+pub const DEVICE_PATH: &str = r"\\.\Gladix";
+/// Auto-reset event the driver sets when a ring gets frames. Drivers
+/// without it leave the agent polling. The driver creates it at its
+/// [`kernel_object_path`], the agent opens its [`user_object_path`].
+pub const RING_EVENT: &str = "GladixRingEvent";
+
+/// Directory of the global named objects, as the kernel names them.
+pub const KERNEL_OBJECT_DIR: &str = "\\BaseNamedObjects\\";
+/// The same directory from a Win32 process in any session.
+pub const USER_OBJECT_DIR: &str = "Global\\";
+
+/// `\BaseNamedObjects\<name>`: where the driver creates the object `name`.
+pub fn kernel_object_path(name: &str) -> String {
+    format!("{KERNEL_OBJECT_DIR}{name}")
+}
+
+/// `Global\<name>`: where the agent opens the object `name`.
+pub fn user_object_path(name: &str) -> String {
+    format!("{USER_OBJECT_DIR}{name}")
+}
+
+/// Ring sections, by the name the agent maps them from with [`ring_path`].
+pub const PROCESS_RING: &str = "process_ring";
+pub const IMAGE_RING: &str = "image_ring";
+pub const OBJECT_RING: &str = "object_ring";
+pub const NETWORK_RING: &str = "network_ring";
+pub const FILE_RING: &str = "file_ring";
+
+/// `\\Gladix\<ring>`: the path the agent maps the ring section `ring` from.
+pub fn ring_path(ring: &str) -> String {
+    format!(r"\\Gladix\{ring}")
+}
+
+pub const FILE_DEVICE_UNKNOWN: u32 = 0x22;
+pub const METHOD_BUFFERED: u32 = 0;
+pub const FILE_ANY_ACCESS: u32 = 0;
+pub const FILE_READ_ACCESS: u32 = 1;
+pub const FILE_WRITE_ACCESS: u32 = 2;
+
+/// `CTL_CODE` from `devioctl.h`.
+pub const fn ctl_code(device_type: u32, function: u32, method: u32, access: u32) -> u32 {
+    (device_type << 16) | (access << 14) | (function << 2) | method
+}
+
+/// Function number of an IOCTL code (the inverse of [`ctl_code`]).
+pub const fn ctl_function(code: u32) -> u32 {
+    (code >> 2) & 0xfff
+}
+
+/// Returns a [`RingStats`](crate::ring::RingStats) in the output buffer.
+pub const IOCTL_GLADIX_GET_RING_STATS: u32 =
+    ctl_code(FILE_DEVICE_UNKNOWN, 0x800, METHOD_BUFFERED, FILE_READ_ACCESS);
+/// Returns [`PING_REPLY`] as a little-endian `u32`.
+pub const IOCTL_GLADIX_PING: u32 =
+    ctl_code(FILE_DEVICE_UNKNOWN, 0x801, METHOD_BUFFERED, FILE_ANY_ACCESS);
+/// Returns a [`VersionInfo`].
+pub const IOCTL_GLADIX_GET_VERSION: u32 =
+    ctl_code(FILE_DEVICE_UNKNOWN, 0x802, METHOD_BUFFERED, FILE_READ_ACCESS);
+/// Takes an array of [`NetRule`] as input, replacing the driver's network
+/// policy; replies nothing.
+pub const IOCTL_GLADIX_SET_NET_POLICY: u32 =
+    ctl_code(FILE_DEVICE_UNKNOWN, 0x803, METHOD_BUFFERED, FILE_WRITE_ACCESS);
+/// Takes an array of little-endian `u32` process ids as input, replacing
+/// the processes whose memory access the driver reports; replies nothing.
+pub const IOCTL_GLADIX_SET_PROTECTED_PIDS: u32 =
+    ctl_code(FILE_DEVICE_UNKNOWN, 0x804, METHOD_BUFFERED, FILE_WRITE_ACCESS);
+
+/// "Gldx", little-endian.
+pub const PING_REPLY: u32 = u32::from_le_bytes(*b"Gldx");
+/// `major << 16 | minor`. The major changes when a code, reply layout or
+/// the ring framing does and the other side can no longer read it; the
+/// minor when something is added that an older peer can ignore.
+pub const fn protocol_version(major: u16, minor: u16) -> u32 {
+    (major as u32) << 16 | minor as u32
+}
+
+pub const fn protocol_major(version: u32) -> u16 {
+    (version >> 16) as u16
+}
+
+pub const fn protocol_minor(version: u32) -> u16 {
+    version as u16
+}
+
+/// Protocol this agent speaks; see [`VersionInfo::protocol`]. Drivers from
+/// before the major/minor split reply protocol 1, which reads as 0.1.
+/// 2.1 added the drop reasons to the ring header and to [`RingStats`].
+///
+/// [`RingStats`]: crate::ring::RingStats
+pub const DRIVER_PROTOCOL_VERSION: u32 = protocol_version(2, 1);
+
+/// Reply of [`IOCTL_GLADIX_GET_VERSION`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VersionInfo {
+    /// See [`protocol_version`].
+    pub protocol:   u32,
+    /// Driver crate version.
+    pub major:      u16,
+    pub minor:      u16,
+    pub patch:      u16,
+    pub reserved:   [u8; 6],
+    /// When the driver was built, UNIX seconds; 0 if unknown.
+    pub build_time: u64,
+}
+
+impl VersionInfo {
+    pub const SIZE: usize = size_of::<VersionInfo>();
+
+    /// Decodes the reply bytes; `None` if too short.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..Self::SIZE)?;
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        Some(Self {
+            protocol:   u32::from_le_bytes(bytes[..4].try_into().ok()?),
+            major:      u16_at(4),
+            minor:      u16_at(6),
+            patch:      u16_at(8),
+            reserved:   bytes[10..16].try_into().ok()?,
+            build_time: u64::from_le_bytes(bytes[16..24].try_into().ok()?),
+        })
+    }
+
+    /// `major.minor.patch` of the driver.
+    pub fn driver_version(&self) -> String {
+        format!("{}.{}.{}", self.major, self.minor, self.patch)
+    }
+
+    /// `major.minor` of [`protocol`](Self::protocol).
+    pub fn protocol_version(&self) -> String {
+        format!("{}.{}", protocol_major(self.protocol), protocol_minor(self.protocol))
+    }
+}
+
+/// Most rules one [`IOCTL_GLADIX_SET_NET_POLICY`] carries.
+pub const NET_POLICY_MAX_RULES: usize = 256;
+/// Bytes of [`NetRule::process`].
+pub const NET_RULE_PROCESS_LEN: usize = 120;
+
+pub const NET_ACTION_ALLOW: u8 = 0;
+pub const NET_ACTION_BLOCK: u8 = 1;
+
+/// [`NetRule::family`] of rules for any address.
+pub const NET_FAMILY_ANY: u8 = 0;
+pub const NET_FAMILY_V4: u8 = 4;
+pub const NET_FAMILY_V6: u8 = 6;
+
+/// One compiled network policy rule, as sent to the driver. A connection
+/// matches when its remote address is in `addr/prefix`, its remote port in
+/// `port_lo..=port_hi` and its process path matches `process`; the first
+/// matching rule decides.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetRule {
+    /// Network address; IPv4 in the first 4 bytes.
+    pub addr:        [u8; 16],
+    pub family:      u8,
+    pub prefix:      u8,
+    pub action:      u8,
+    /// Bytes of `process` in use; 0 matches any process.
+    pub process_len: u8,
+    pub port_lo:     u16,
+    pub port_hi:     u16,
+    /// Lowercase UTF-8 glob of the process path (`*`, `?`).
+    pub process:     [u8; NET_RULE_PROCESS_LEN],
+}
+
+impl NetRule {
+    pub const SIZE: usize = size_of::<NetRule>();
+
+    /// A rule matching every connection.
+    pub const ANY: NetRule = NetRule {
+        addr:        [0; 16],
+        family:      NET_FAMILY_ANY,
+        prefix:      0,
+        action:      NET_ACTION_ALLOW,
+        process_len: 0,
+        port_lo:     0,
+        port_hi:     u16::MAX,
+        process:     [0; NET_RULE_PROCESS_LEN],
+    };
+
+    /// The rule as the driver reads it.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut out = [0u8; Self::SIZE];
+        out[..16].copy_from_slice(&self.addr);
+        out[16..20].copy_from_slice(&[self.family, self.prefix, self.action, self.process_len]);
+        out[20..22].copy_from_slice(&self.port_lo.to_le_bytes());
+        out[22..24].copy_from_slice(&self.port_hi.to_le_bytes());
+        out[24..].copy_from_slice(&self.process);
+        out
+    }
+}
+
+/// Most pids one [`IOCTL_GLADIX_SET_PROTECTED_PIDS`] carries.
+pub const PROTECTED_PIDS_MAX: usize = 16;
+
+/// Service the driver is installed as (`sc create edr_driver ...`).
+pub const DRIVER_SERVICE: &str = "edr_driver";
+/// `REG_DWORD` under the service's `Parameters` key: bytes wanted for each
+/// ring's data area. Read when the driver loads.
+pub const RING_SIZE_VALUE: &str = "RingSizeBytes";
+pub const RING_SIZE_MIN: u32 = 64 * 1024;
+pub const RING_SIZE_MAX: u32 = 16 * 1024 * 1024;
+/// Size without [`RING_SIZE_VALUE`].
+pub const RING_SIZE_DEFAULT: u32 = RING_SIZE_MIN;
+pub const PAGE_SIZE: u32 = 4096;
+
+/// Bytes of UTF-16 the driver keeps of a process image path (its own and
+/// the parent's); longer ones are cut on a character boundary.
+pub const PROCESS_IMAGE_PATH_MAX: usize = 2 * 1024;
+/// Bytes of UTF-16 the driver keeps of a command line.
+pub const PROCESS_CMDLINE_MAX: usize = 8 * 1024;
+
+/// Bits of `ProcessEvent.truncated`, one per field cut.
+pub const TRUNCATED_IMAGE_PATH: u32 = 1 << 0;
+pub const TRUNCATED_CMDLINE: u32 = 1 << 1;
+pub const TRUNCATED_PARENT_IMAGE_PATH: u32 = 1 << 2;
+
+/// Data area the driver allocates when asked for `requested` bytes: rounded
+/// up to a whole page and clamped to [`RING_SIZE_MIN`]..=[`RING_SIZE_MAX`].
+/// The size in use is the one `RingStats::size` reports.
+pub const fn ring_size(requested: u32) -> u32 {
+    let pages = (requested as u64).div_ceil(PAGE_SIZE as u64);
+    let bytes = pages * PAGE_SIZE as u64;
+    if bytes < RING_SIZE_MIN as u64 {
+        RING_SIZE_MIN
+    } else if bytes > RING_SIZE_MAX as u64 {
+        RING_SIZE_MAX
+    } else {
+        bytes as u32
+    }
+}
+
+const _: () = assert!(IOCTL_GLADIX_GET_RING_STATS == 0x0022_6000);
+const _: () = assert!(IOCTL_GLADIX_PING == 0x0022_2004);
+const _: () = assert!(IOCTL_GLADIX_GET_VERSION == 0x0022_6008);
+const _: () = assert!(IOCTL_GLADIX_SET_NET_POLICY == 0x0022_a00c);
+const _: () = assert!(IOCTL_GLADIX_SET_PROTECTED_PIDS == 0x0022_a010);
+const _: () = assert!(NetRule::SIZE == 144);
+const _: () = assert!(VersionInfo::SIZE == 24);
+const _: () = assert!(core::mem::offset_of!(VersionInfo, build_time) == 16);
+const _: () = assert!(PROCESS_IMAGE_PATH_MAX == 0x800 && PROCESS_CMDLINE_MAX == 0x2000);
+const _: () = assert!(ring_size(0) == RING_SIZE_MIN && ring_size(u32::MAX) == RING_SIZE_MAX);
@@ -1,7 +1,23 @@
 pub mod events {
     include!("proto_gen/events.rs"); // or mod per file
+
+    impl ProcessEvent {
+        /// `true` for a process-exit notification.
+        pub fn is_exit(&self) -> bool {
+            self.event_type == process_event::EventType::Exit as i32
+        }
+    }
 }
 
 pub mod config {
     include!("proto_gen/config.rs"); // or mod per file
-}
\ No newline at end of file
+}
+
+pub mod status {
+    include!("proto_gen/status.rs");
+}
+pub mod constants;
+pub mod ring;
+
+/// Encoded `FileDescriptorSet` of `events.proto`, `config.proto` and `status.proto`.
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/gladix_descriptors.bin"));
@@ -116,6 +116,193 @@ pub struct SetConfigResponse {
     #[prost(string, tag = "2")]
     pub message: ::prost::alloc::string::String,
 }
+/// Request for the event schema; empty event_type describes every type
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DescribeSchemaRequest {
+    /// message name, e.g. "ProcessEvent"
+    #[prost(string, tag = "1")]
+    pub event_type: ::prost::alloc::string::String,
+}
+/// One field of an event message
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FieldSchema {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub number: u32,
+    /// scalar, enum or message type name
+    #[prost(string, tag = "3")]
+    pub r#type: ::prost::alloc::string::String,
+    #[prost(bool, tag = "4")]
+    pub repeated: bool,
+    /// DB columns filled from this field
+    #[prost(string, repeated, tag = "5")]
+    pub columns: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// config-dependent storage effects
+    #[prost(string, repeated, tag = "6")]
+    pub annotations: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EnumValueSchema {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(int32, tag = "2")]
+    pub number: i32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EnumSchema {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "2")]
+    pub values: ::prost::alloc::vec::Vec<EnumValueSchema>,
+}
+/// One event type as stored by the agent
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EventSchema {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    /// empty when the type is not stored
+    #[prost(string, tag = "2")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "3")]
+    pub fields: ::prost::alloc::vec::Vec<FieldSchema>,
+    #[prost(message, repeated, tag = "4")]
+    pub enums: ::prost::alloc::vec::Vec<EnumSchema>,
+    /// columns not filled from a field
+    #[prost(string, repeated, tag = "5")]
+    pub derived_columns: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DescribeSchemaResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub events: ::prost::alloc::vec::Vec<EventSchema>,
+}
+/// Request for the agent's latest heartbeat
+///
+/// empty
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct GetStatusRequest {}
+/// Newest agent_status row; counts are since the heartbeat before it
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetStatusResponse {
+    /// UNIX microseconds
+    #[prost(int64, tag = "1")]
+    pub timestamp_us: i64,
+    #[prost(string, tag = "2")]
+    pub version: ::prost::alloc::string::String,
+    #[prost(double, tag = "3")]
+    pub uptime_seconds: f64,
+    /// events the driver dropped
+    #[prost(uint64, tag = "4")]
+    pub ring_dropped: u64,
+    /// rows stored per table
+    #[prost(map = "string, uint64", tag = "5")]
+    pub processed: ::std::collections::HashMap<::prost::alloc::string::String, u64>,
+    /// database plus WAL
+    #[prost(uint64, tag = "6")]
+    pub db_size_bytes: u64,
+    /// empty when none
+    #[prost(string, tag = "7")]
+    pub last_error: ::prost::alloc::string::String,
+    /// unset while no driver answered
+    #[prost(message, optional, tag = "8")]
+    pub driver: ::core::option::Option<DriverVersion>,
+    /// panicked too often, not restarted
+    #[prost(string, repeated, tag = "9")]
+    pub failed_tasks: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// Reply of IOCTL_GLADIX_GET_VERSION
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DriverVersion {
+    /// major << 16 | minor
+    #[prost(uint32, tag = "1")]
+    pub protocol: u32,
+    /// driver crate, "major.minor.patch"
+    #[prost(string, tag = "2")]
+    pub version: ::prost::alloc::string::String,
+    /// UNIX seconds; 0 if unknown
+    #[prost(uint64, tag = "3")]
+    pub build_time: u64,
+}
+/// Scan outside the schedule: the directories of group `risk`, or `paths`
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TriggerScanRequest {
+    /// "high", "medium", "low" or "special"
+    #[prost(string, tag = "1")]
+    pub risk: ::prost::alloc::string::String,
+    /// instead of risk; no exclusions, xxh64
+    #[prost(string, repeated, tag = "2")]
+    pub paths: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct TriggerScanResponse {
+    /// for GetScanStatus
+    #[prost(uint64, tag = "1")]
+    pub job_id: u64,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct GetScanStatusRequest {
+    #[prost(uint64, tag = "1")]
+    pub job_id: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetScanStatusResponse {
+    #[prost(enumeration = "get_scan_status_response::State", tag = "1")]
+    pub state: i32,
+    /// listed, hashed or not; set when done
+    #[prost(uint64, tag = "2")]
+    pub files: u64,
+    #[prost(uint64, tag = "3")]
+    pub bytes_hashed: u64,
+    /// empty unless FAILED
+    #[prost(string, tag = "4")]
+    pub error: ::prost::alloc::string::String,
+}
+/// Nested message and enum types in `GetScanStatusResponse`.
+pub mod get_scan_status_response {
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration
+    )]
+    #[repr(i32)]
+    pub enum State {
+        Queued = 0,
+        Running = 1,
+        Done = 2,
+        Failed = 3,
+    }
+    impl State {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::Queued => "QUEUED",
+                Self::Running => "RUNNING",
+                Self::Done => "DONE",
+                Self::Failed => "FAILED",
+            }
+        }
+        /// Creates an enum from field names used in the ProtoBuf definition.
+        pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+            match value {
+                "QUEUED" => Some(Self::Queued),
+                "RUNNING" => Some(Self::Running),
+                "DONE" => Some(Self::Done),
+                "FAILED" => Some(Self::Failed),
+                _ => None,
+            }
+        }
+    }
+}
 /// Generated client implementations.
 pub mod config_service_client {
     #![allow(
@@ -258,6 +445,106 @@ pub mod config_service_client {
                 .insert(GrpcMethod::new("config.ConfigService", "SetConfig"));
             self.inner.unary(req, path, codec).await
         }
+        /// Describe event types, their DB mapping and config-dependent storage
+        pub async fn describe_schema(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DescribeSchemaRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::DescribeSchemaResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/config.ConfigService/DescribeSchema",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("config.ConfigService", "DescribeSchema"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Latest heartbeat of the agent
+        pub async fn get_status(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetStatusResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/config.ConfigService/GetStatus",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("config.ConfigService", "GetStatus"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Queue a scan now, on the scanner's workers
+        pub async fn trigger_scan(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TriggerScanRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::TriggerScanResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/config.ConfigService/TriggerScan",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("config.ConfigService", "TriggerScan"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Progress of a scan queued with TriggerScan
+        pub async fn get_scan_status(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetScanStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetScanStatusResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/config.ConfigService/GetScanStatus",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("config.ConfigService", "GetScanStatus"));
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -289,6 +576,38 @@ pub mod config_service_server {
             tonic::Response<super::SetConfigResponse>,
             tonic::Status,
         >;
+        /// Describe event types, their DB mapping and config-dependent storage
+        async fn describe_schema(
+            &self,
+            request: tonic::Request<super::DescribeSchemaRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::DescribeSchemaResponse>,
+            tonic::Status,
+        >;
+        /// Latest heartbeat of the agent
+        async fn get_status(
+            &self,
+            request: tonic::Request<super::GetStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetStatusResponse>,
+            tonic::Status,
+        >;
+        /// Queue a scan now, on the scanner's workers
+        async fn trigger_scan(
+            &self,
+            request: tonic::Request<super::TriggerScanRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::TriggerScanResponse>,
+            tonic::Status,
+        >;
+        /// Progress of a scan queued with TriggerScan
+        async fn get_scan_status(
+            &self,
+            request: tonic::Request<super::GetScanStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetScanStatusResponse>,
+            tonic::Status,
+        >;
     }
     /// Service definition for UI ↔ Agent config RPCs
     #[derive(Debug)]
@@ -457,6 +776,186 @@ pub mod config_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/config.ConfigService/DescribeSchema" => {
+                    #[allow(non_camel_case_types)]
+                    struct DescribeSchemaSvc<T: ConfigService>(pub Arc<T>);
+                    impl<
+                        T: ConfigService,
+                    > tonic::server::UnaryService<super::DescribeSchemaRequest>
+                    for DescribeSchemaSvc<T> {
+                        type Response = super::DescribeSchemaResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DescribeSchemaRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ConfigService>::describe_schema(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = DescribeSchemaSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/config.ConfigService/GetStatus" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetStatusSvc<T: ConfigService>(pub Arc<T>);
+                    impl<
+                        T: ConfigService,
+                    > tonic::server::UnaryService<super::GetStatusRequest>
+                    for GetStatusSvc<T> {
+                        type Response = super::GetStatusResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetStatusRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ConfigService>::get_status(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetStatusSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/config.ConfigService/TriggerScan" => {
+                    #[allow(non_camel_case_types)]
+                    struct TriggerScanSvc<T: ConfigService>(pub Arc<T>);
+                    impl<
+                        T: ConfigService,
+                    > tonic::server::UnaryService<super::TriggerScanRequest>
+                    for TriggerScanSvc<T> {
+                        type Response = super::TriggerScanResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TriggerScanRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ConfigService>::trigger_scan(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = TriggerScanSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/config.ConfigService/GetScanStatus" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetScanStatusSvc<T: ConfigService>(pub Arc<T>);
+                    impl<
+                        T: ConfigService,
+                    > tonic::server::UnaryService<super::GetScanStatusRequest>
+                    for GetScanStatusSvc<T> {
+                        type Response = super::GetScanStatusResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetScanStatusRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ConfigService>::get_scan_status(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetScanStatusSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(empty_body());
@@ -5,7 +5,16 @@ pub struct BaseEvent {
     pub ts: ::core::option::Option<::prost_types::Timestamp>,
     #[prost(string, tag = "2")]
     pub sensor_guid: ::prost::alloc::string::String,
-    #[prost(oneof = "base_event::Payload", tags = "10, 11, 12, 13, 14")]
+    /// Number of the ring frame the event was read from (shared::ring::Frame);
+    /// 0 for events that did not come from a ring.
+    #[prost(uint64, tag = "3")]
+    pub seq: u64,
+    /// Position of the event in the agent's forward stream (comms::forwarder),
+    /// counting from 1 and never reused; collectors drop the ones they already
+    /// have. 0 for events that were not forwarded.
+    #[prost(uint64, tag = "4")]
+    pub forward_seq: u64,
+    #[prost(oneof = "base_event::Payload", tags = "10, 11, 12, 13, 14, 15, 16")]
     pub payload: ::core::option::Option<base_event::Payload>,
 }
 /// Nested message and enum types in `BaseEvent`.
@@ -22,12 +31,17 @@ pub mod base_event {
         ScanResult(super::ScanResult),
         #[prost(message, tag = "14")]
         EtwEvent(super::EtwEvent),
+        #[prost(message, tag = "15")]
+        ImageLoadEvent(super::ImageLoadEvent),
+        #[prost(message, tag = "16")]
+        ObjectOpEvent(super::ObjectOpEvent),
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct FileEvent {
     #[prost(enumeration = "file_event::Operation", tag = "1")]
     pub op: i32,
+    /// Alternate data streams use the Win32 form "C:\dir\file.txt:stream".
     #[prost(string, tag = "2")]
     pub path: ::prost::alloc::string::String,
     #[prost(string, tag = "3")]
@@ -42,6 +56,12 @@ pub struct FileEvent {
     pub sha256: ::prost::alloc::vec::Vec<u8>,
     #[prost(bool, tag = "8")]
     pub success: bool,
+    /// path and new_path as the sensor sent them, set by the agent when it
+    /// rewrote them into their canonical form; empty otherwise.
+    #[prost(string, tag = "9")]
+    pub raw_path: ::prost::alloc::string::String,
+    #[prost(string, tag = "10")]
+    pub raw_new_path: ::prost::alloc::string::String,
 }
 /// Nested message and enum types in `FileEvent`.
 pub mod file_event {
@@ -110,6 +130,10 @@ pub struct NetworkEvent {
     pub bytes: u64,
     #[prost(bool, tag = "10")]
     pub blocked: bool,
+    /// Host name of dst_ip, filled in by the agent from its DNS cache; empty
+    /// while the address has not been looked up or has no name.
+    #[prost(string, tag = "11")]
+    pub dst_hostname: ::prost::alloc::string::String,
 }
 /// Nested message and enum types in `NetworkEvent`.
 pub mod network_event {
@@ -160,6 +184,78 @@ pub struct ProcessEvent {
     pub image_path: ::prost::alloc::string::String,
     #[prost(string, tag = "4")]
     pub cmdline: ::prost::alloc::string::String,
+    /// Thread that issued the create call (PS_CREATE_NOTIFY_INFO.CreatingThreadId).
+    /// Differs from ppid for brokered creations and parent-PID spoofing.
+    #[prost(uint32, tag = "5")]
+    pub creator_pid: u32,
+    #[prost(uint32, tag = "6")]
+    pub creator_tid: u32,
+    #[prost(enumeration = "process_event::EventType", tag = "7")]
+    pub event_type: i32,
+    #[prost(int32, tag = "8")]
+    pub exit_code: i32,
+    /// Parent (ppid) at creation. The driver reports the image path
+    /// (SeLocateProcessImageName); both are empty when it cannot, and the agent
+    /// completes them from the parent's stored creation.
+    #[prost(string, tag = "9")]
+    pub parent_image_path: ::prost::alloc::string::String,
+    #[prost(string, tag = "10")]
+    pub parent_cmdline: ::prost::alloc::string::String,
+    /// TRUNCATED_* bits of shared::constants: fields the driver cut to
+    /// PROCESS_IMAGE_PATH_MAX or PROCESS_CMDLINE_MAX bytes of UTF-16.
+    #[prost(uint32, tag = "11")]
+    pub truncated: u32,
+    /// Primary token of the new process at creation (TokenUser, its session and
+    /// TokenElevation). A field the driver could not read stays empty, as do
+    /// all three on exits; the event is reported either way.
+    ///
+    /// e.g. S-1-5-21-...-1001
+    #[prost(string, tag = "12")]
+    pub user_sid: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "13")]
+    pub session_id: u32,
+    #[prost(bool, tag = "14")]
+    pub elevated: bool,
+}
+/// Nested message and enum types in `ProcessEvent`.
+pub mod process_event {
+    /// Exit events carry only pid and exit_code (PsGetProcessExitStatus).
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration
+    )]
+    #[repr(i32)]
+    pub enum EventType {
+        Create = 0,
+        Exit = 1,
+    }
+    impl EventType {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::Create => "CREATE",
+                Self::Exit => "EXIT",
+            }
+        }
+        /// Creates an enum from field names used in the ProtoBuf definition.
+        pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+            match value {
+                "CREATE" => Some(Self::Create),
+                "EXIT" => Some(Self::Exit),
+                _ => None,
+            }
+        }
+    }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ScanResult {
@@ -171,6 +267,23 @@ pub struct ScanResult {
     pub matches: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
     #[prost(enumeration = "scan_result::Severity", tag = "4")]
     pub severity: i32,
+    /// Filled by the directory scanner for new or changed files. In groups
+    /// running YARA rules a matching file gives one result per rule, with the
+    /// rule in rule_id and its patterns found in matches; otherwise rule_id
+    /// and matches are empty and severity is LOW.
+    #[prost(uint64, tag = "5")]
+    pub size: u64,
+    /// XxHash64 of the content, as in the scan cache
+    #[prost(uint64, tag = "6")]
+    pub hash: u64,
+    /// seconds since the epoch
+    #[prost(uint64, tag = "7")]
+    pub mtime: u64,
+    #[prost(string, tag = "8")]
+    pub risk_group: ::prost::alloc::string::String,
+    /// empty unless the group hashes with SHA-256
+    #[prost(bytes = "vec", tag = "9")]
+    pub sha256: ::prost::alloc::vec::Vec<u8>,
 }
 /// Nested message and enum types in `ScanResult`.
 pub mod scan_result {
@@ -232,3 +345,374 @@ pub struct EtwEvent {
     #[prost(string, tag = "6")]
     pub json_payload: ::prost::alloc::string::String,
 }
+/// PsSetLoadImageNotifyRoutine: an image mapped into a process, or a driver
+/// loaded into the kernel (pid 0, is_kernel_module set).
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ImageLoadEvent {
+    #[prost(uint32, tag = "1")]
+    pub pid: u32,
+    #[prost(uint64, tag = "2")]
+    pub image_base: u64,
+    #[prost(uint64, tag = "3")]
+    pub image_size: u64,
+    #[prost(string, tag = "4")]
+    pub full_image_name: ::prost::alloc::string::String,
+    #[prost(bool, tag = "5")]
+    pub is_kernel_module: bool,
+}
+/// ObRegisterCallbacks: a handle to a protected process opened or duplicated
+/// with PROCESS_VM_READ or PROCESS_VM_WRITE. Reported only; the access is
+/// granted unchanged.
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ObjectOpEvent {
+    #[prost(uint32, tag = "1")]
+    pub source_pid: u32,
+    #[prost(uint32, tag = "2")]
+    pub target_pid: u32,
+    /// ACCESS_MASK as requested
+    #[prost(uint32, tag = "3")]
+    pub desired_access: u32,
+    #[prost(enumeration = "object_op_event::Operation", tag = "4")]
+    pub operation: i32,
+}
+/// Nested message and enum types in `ObjectOpEvent`.
+pub mod object_op_event {
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration
+    )]
+    #[repr(i32)]
+    pub enum Operation {
+        HandleCreate = 0,
+        HandleDuplicate = 1,
+    }
+    impl Operation {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::HandleCreate => "HANDLE_CREATE",
+                Self::HandleDuplicate => "HANDLE_DUPLICATE",
+            }
+        }
+        /// Creates an enum from field names used in the ProtoBuf definition.
+        pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+            match value {
+                "HANDLE_CREATE" => Some(Self::HandleCreate),
+                "HANDLE_DUPLICATE" => Some(Self::HandleDuplicate),
+                _ => None,
+            }
+        }
+    }
+}
+/// Reply to a StreamEvents call once the agent has sent the whole batch.
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct StreamAck {
+    /// events of the stream the collector has stored
+    #[prost(uint64, tag = "1")]
+    pub received: u64,
+}
+/// Generated client implementations.
+pub mod event_collector_client {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    use tonic::codegen::http::Uri;
+    /// Implemented by the central collector; the agent is the client.
+    #[derive(Debug, Clone)]
+    pub struct EventCollectorClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl EventCollectorClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> EventCollectorClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + std::marker::Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + std::marker::Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> EventCollectorClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::BoxBody>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+            >>::Error: Into<StdError> + std::marker::Send + std::marker::Sync,
+        {
+            EventCollectorClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        /// One batch of events, gzip-compressed, retried whole until acknowledged
+        pub async fn stream_events(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<Message = super::BaseEvent>,
+        ) -> std::result::Result<tonic::Response<super::StreamAck>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/events.EventCollector/StreamEvents",
+            );
+            let mut req = request.into_streaming_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("events.EventCollector", "StreamEvents"));
+            self.inner.client_streaming(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod event_collector_server {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with EventCollectorServer.
+    #[async_trait]
+    pub trait EventCollector: std::marker::Send + std::marker::Sync + 'static {
+        /// One batch of events, gzip-compressed, retried whole until acknowledged
+        async fn stream_events(
+            &self,
+            request: tonic::Request<tonic::Streaming<super::BaseEvent>>,
+        ) -> std::result::Result<tonic::Response<super::StreamAck>, tonic::Status>;
+    }
+    /// Implemented by the central collector; the agent is the client.
+    #[derive(Debug)]
+    pub struct EventCollectorServer<T> {
+        inner: Arc<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    impl<T> EventCollectorServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for EventCollectorServer<T>
+    where
+        T: EventCollector,
+        B: Body + std::marker::Send + 'static,
+        B::Error: Into<StdError> + std::marker::Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            match req.uri().path() {
+                "/events.EventCollector/StreamEvents" => {
+                    #[allow(non_camel_case_types)]
+                    struct StreamEventsSvc<T: EventCollector>(pub Arc<T>);
+                    impl<
+                        T: EventCollector,
+                    > tonic::server::ClientStreamingService<super::BaseEvent>
+                    for StreamEventsSvc<T> {
+                        type Response = super::StreamAck;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<tonic::Streaming<super::BaseEvent>>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as EventCollector>::stream_events(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = StreamEventsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.client_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        let mut response = http::Response::new(empty_body());
+                        let headers = response.headers_mut();
+                        headers
+                            .insert(
+                                tonic::Status::GRPC_STATUS,
+                                (tonic::Code::Unimplemented as i32).into(),
+                            );
+                        headers
+                            .insert(
+                                http::header::CONTENT_TYPE,
+                                tonic::metadata::GRPC_CONTENT_TYPE,
+                            );
+                        Ok(response)
+                    })
+                }
+            }
+        }
+    }
+    impl<T> Clone for EventCollectorServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    /// Generated gRPC service name
+    pub const SERVICE_NAME: &str = "events.EventCollector";
+    impl<T> tonic::server::NamedService for EventCollectorServer<T> {
+        const NAME: &'static str = SERVICE_NAME;
+    }
+}
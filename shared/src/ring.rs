@@ -0,0 +1,288 @@
+//! Layout of the shared-memory event rings written by the driver and read by
+//! the user-agent.
+//!
+//! Offsets are fixed-width (`u64`) rather than `usize` so the header is the
+//! same on every architecture the agent ships for (x86_64, ARM64). Only
+//! `core` is used here so the driver can share the definition.
+
+use core::{
+    mem::{align_of, size_of},
+    ops::Range,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+/// Start of every ring section; the data area follows immediately.
+#[repr(C)]
+pub struct RingHeader {
+    /// Read offset into the data area, advanced by the consumer.
+    pub head: AtomicU64,
+    /// Write offset into the data area, advanced by the producer.
+    pub tail: AtomicU64,
+    /// Events the producer discarded: the two counters below plus the
+    /// frames it failed to encode.
+    pub dropped: AtomicU32,
+    /// Layout the producer writes, [`VERSION`]. Rings from before frames
+    /// were checked have zero here, rings of version 3 end the header here
+    /// ([`LEGACY_HEADER_SIZE`]).
+    pub version: u32,
+    /// Of `dropped`, events refused because the consumer had not made room.
+    pub dropped_full: AtomicU32,
+    /// Of `dropped`, frames longer than the whole data area, which no
+    /// consumer could make room for.
+    pub dropped_oversize: AtomicU32,
+    /// Longest frame offered, published or not, in bytes.
+    pub max_observed_len: AtomicU32,
+    pub reserved: u32,
+}
+
+impl RingHeader {
+    /// An empty ring using this framing, as the producer initializes it.
+    pub const fn new() -> Self {
+        Self {
+            head:             AtomicU64::new(0),
+            tail:             AtomicU64::new(0),
+            dropped:          AtomicU32::new(0),
+            version:          VERSION,
+            dropped_full:     AtomicU32::new(0),
+            dropped_oversize: AtomicU32::new(0),
+            max_observed_len: AtomicU32::new(0),
+            reserved:         0,
+        }
+    }
+}
+
+impl Default for RingHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub const HEADER_SIZE: usize = 40;
+pub const HEADER_ALIGN: usize = 8;
+/// Header of a version 3 ring, without the drop reasons.
+pub const LEGACY_HEADER_SIZE: usize = 24;
+/// Current layout: frames are `[u16 magic][u32 len][u32 crc][u64 seq][u64
+/// ts][payload]`, the header counts drops by reason. Version 3 frames the
+/// same way with a shorter header.
+pub const VERSION: u32 = 4;
+
+/// Where the data area of a ring of `version` starts; `None` for a layout
+/// this side cannot read.
+pub const fn header_size(version: u32) -> Option<usize> {
+    match version {
+        3 => Some(LEGACY_HEADER_SIZE),
+        VERSION => Some(HEADER_SIZE),
+        _ => None,
+    }
+}
+
+/// A frame of `len` bytes never fits a data area of `size` bytes: it would
+/// take the tail all the way round to the head.
+pub const fn oversize(size: usize, len: usize) -> bool {
+    len >= size
+}
+/// Little-endian marker every frame starts with; the reader looks for it to
+/// resynchronize after a damaged frame.
+pub const FRAME_MAGIC: u16 = u16::from_le_bytes(*b"GX");
+/// Magic, payload length, CRC-32 of everything after it, the sequence number
+/// and the producer's timestamp, all little-endian.
+pub const FRAME_PREFIX: usize = 26;
+/// Where the bytes the CRC covers, the sequence number first, start.
+const CRC_FROM: usize = 10;
+/// Where the timestamp starts.
+const TS_AT: usize = 18;
+/// Every frame starts on this boundary.
+pub const FRAME_ALIGN: usize = 8;
+
+const _: () = assert!(size_of::<RingHeader>() == HEADER_SIZE);
+const _: () = assert!(align_of::<RingHeader>() == HEADER_ALIGN);
+const _: () = assert!(size_of::<RingStats>() == 40);
+/// Replies of drivers from before the drop reasons stop after `size`.
+pub const LEGACY_STATS_SIZE: usize = 24;
+
+/// Snapshot of a ring header, returned by `IOCTL_GLADIX_GET_RING_STATS`
+/// (see [`crate::constants`]) and read through the mapped view.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RingStats {
+    pub head:    u64,
+    pub tail:    u64,
+    pub dropped: u32,
+    /// Size of the data area in bytes.
+    pub size:    u32,
+    /// Of `dropped`, those refused for lack of room (see [`RingHeader`]).
+    pub dropped_full:     u32,
+    /// Of `dropped`, frames longer than the data area.
+    pub dropped_oversize: u32,
+    /// Longest frame offered, in bytes.
+    pub max_observed_len: u32,
+    pub reserved:         u32,
+}
+
+impl RingStats {
+    /// Reads `header` of a ring whose data area is `size` bytes. A version 3
+    /// header has no drop reasons; they read as 0.
+    pub fn read(header: &RingHeader, size: usize) -> Self {
+        let mut stats = Self {
+            head:    header.head.load(Ordering::Acquire),
+            tail:    header.tail.load(Ordering::Acquire),
+            dropped: header.dropped.load(Ordering::Relaxed),
+            size:    size as u32,
+            ..Self::default()
+        };
+        if header.version >= VERSION {
+            stats.dropped_full = header.dropped_full.load(Ordering::Relaxed);
+            stats.dropped_oversize = header.dropped_oversize.load(Ordering::Relaxed);
+            stats.max_observed_len = header.max_observed_len.load(Ordering::Relaxed);
+        }
+        stats
+    }
+
+    /// Decodes an IOCTL reply; `None` if too short. A reply of
+    /// [`LEGACY_STATS_SIZE`] bytes has no drop reasons.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let u32_at = |at: usize| bytes.get(at..at + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+        let legacy = bytes.get(..LEGACY_STATS_SIZE)?;
+        Some(Self {
+            head:    u64::from_le_bytes(legacy[..8].try_into().ok()?),
+            tail:    u64::from_le_bytes(legacy[8..16].try_into().ok()?),
+            dropped: u32::from_le_bytes(legacy[16..20].try_into().ok()?),
+            size:    u32::from_le_bytes(legacy[20..24].try_into().ok()?),
+            dropped_full:     u32_at(24).unwrap_or(0),
+            dropped_oversize: u32_at(28).unwrap_or(0),
+            max_observed_len: u32_at(32).unwrap_or(0),
+            reserved:         0,
+        })
+    }
+}
+
+/// Bytes a frame with `payload_len` bytes occupies, padding included.
+pub const fn frame_len(payload_len: usize) -> usize {
+    let total = FRAME_PREFIX + payload_len;
+    total + (FRAME_ALIGN - total % FRAME_ALIGN) % FRAME_ALIGN
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE 802.3, as zlib computes it) of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &b| CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// Writes `payload` as frame number `seq` produced at `ts`, padding
+/// included, to the start of `out`. Returns the frame length, or `None` if
+/// `out` is too short.
+pub fn write_frame(out: &mut [u8], seq: u64, ts: u64, payload: &[u8]) -> Option<usize> {
+    let len = frame_len(payload.len());
+    let out = out.get_mut(..len)?;
+    out[..2].copy_from_slice(&FRAME_MAGIC.to_le_bytes());
+    out[2..6].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+    out[CRC_FROM..TS_AT].copy_from_slice(&seq.to_le_bytes());
+    out[TS_AT..FRAME_PREFIX].copy_from_slice(&ts.to_le_bytes());
+    out[FRAME_PREFIX..FRAME_PREFIX + payload.len()].copy_from_slice(payload);
+    out[FRAME_PREFIX + payload.len()..].fill(0);
+    let crc = crc32(&out[CRC_FROM..FRAME_PREFIX + payload.len()]);
+    out[6..CRC_FROM].copy_from_slice(&crc.to_le_bytes());
+    Some(len)
+}
+
+/// A whole frame found by [`check_frame`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Number the producer gave the frame. It counts every push, including
+    /// the ones dropped for lack of room, so a jump reveals lost events.
+    pub seq:     u64,
+    /// When the producer built the frame, in microseconds since the Unix
+    /// epoch; 0 if it could not tell.
+    pub ts:      u64,
+    /// Where the payload lies in the window.
+    pub payload: Range<usize>,
+}
+
+/// The frame at the start of `window`, if one starts there whole: magic in
+/// place, length within `window` and CRC matching.
+pub fn check_frame(window: &[u8]) -> Option<Frame> {
+    let prefix = window.get(..FRAME_PREFIX)?;
+    if u16::from_le_bytes([prefix[0], prefix[1]]) != FRAME_MAGIC {
+        return None;
+    }
+    let len = u32::from_le_bytes([prefix[2], prefix[3], prefix[4], prefix[5]]) as usize;
+    let crc = u32::from_le_bytes([prefix[6], prefix[7], prefix[8], prefix[9]]);
+    let payload = FRAME_PREFIX..FRAME_PREFIX.checked_add(len)?;
+    if crc32(window.get(CRC_FROM..payload.end)?) != crc {
+        return None;
+    }
+    let seq = u64::from_le_bytes(prefix[CRC_FROM..TS_AT].try_into().ok()?);
+    let ts = u64::from_le_bytes(prefix[TS_AT..FRAME_PREFIX].try_into().ok()?);
+    Some(Frame { seq, ts, payload })
+}
+
+/// Offset of the first whole frame in `window` after its start, which
+/// holds none. Frames start on [`FRAME_ALIGN`], so only those offsets are
+/// tried.
+pub fn find_frame(window: &[u8]) -> Option<usize> {
+    (FRAME_ALIGN..window.len()).step_by(FRAME_ALIGN).find(|&at| check_frame(&window[at..]).is_some())
+}
+
+/// Appends `payload` as frame number `seq` produced at `ts` to the ring
+/// whose header is `header` and data area `data`, as the producer does. The
+/// producer takes a new `seq` for every event, whether or not it fits. A
+/// frame never crosses the end of the data area: one that does not fit
+/// before it starts at 0, and the bytes left behind are zeroed so the reader
+/// takes them for padding. The tail is published once the frame is written.
+/// Returns `false`, counting the event in `dropped` and in
+/// `dropped_oversize` or `dropped_full`, when there is no room before the
+/// head. Every frame offered is measured against `max_observed_len`.
+///
+/// `head == tail` means empty, so the tail never catches up with the head.
+/// One producer per ring.
+pub fn push(header: &RingHeader, data: &mut [u8], seq: u64, ts: u64, payload: &[u8]) -> bool {
+    let size = data.len();
+    let head = header.head.load(Ordering::Acquire) as usize;
+    let tail = header.tail.load(Ordering::Relaxed) as usize;
+    let len = frame_len(payload.len());
+    header.max_observed_len.fetch_max(u32::try_from(len).unwrap_or(u32::MAX), Ordering::Relaxed);
+
+    let at = if oversize(size, len) || head > size || tail >= size {
+        None
+    } else if tail >= head {
+        let end = tail + len;
+        if end < size || (end == size && head != 0) {
+            Some(tail)
+        } else if len < head {
+            data[tail..].fill(0);
+            Some(0)
+        } else {
+            None
+        }
+    } else if tail + len < head {
+        Some(tail)
+    } else {
+        None
+    };
+    let Some(at) = at else {
+        header.dropped.fetch_add(1, Ordering::Relaxed);
+        let reason = if oversize(size, len) { &header.dropped_oversize } else { &header.dropped_full };
+        reason.fetch_add(1, Ordering::Relaxed);
+        return false;
+    };
+
+    write_frame(&mut data[at..], seq, ts, payload);
+    let new_tail = if at + len == size { 0 } else { at + len };
+    header.tail.store(new_tail as u64, Ordering::Release);
+    true
+}
@@ -9,8 +9,9 @@
 use shared::config::{
     config_service_server::{ConfigService, ConfigServiceServer},
     config_service_client::ConfigServiceClient,
-    ConfigUpdate, GetConfigRequest, GetConfigResponse, SetConfigRequest, SetConfigResponse,
-    ScannerConfig,
+    ConfigUpdate, DescribeSchemaRequest, DescribeSchemaResponse, GetConfigRequest, GetConfigResponse,
+    GetScanStatusRequest, GetScanStatusResponse, GetStatusRequest, GetStatusResponse, SetConfigRequest,
+    SetConfigResponse, ScannerConfig, TriggerScanRequest, TriggerScanResponse,
 };
 use tonic::{transport::Server, Request, Response, Status};
 use prost::Message;
@@ -49,6 +50,34 @@ impl ConfigService for DummyConfigServer {
             message: "OK".to_string(),
         }))
     }
+
+    async fn describe_schema(
+        &self,
+        _request: Request<DescribeSchemaRequest>,
+    ) -> Result<Response<DescribeSchemaResponse>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+
+    async fn get_status(
+        &self,
+        _request: Request<GetStatusRequest>,
+    ) -> Result<Response<GetStatusResponse>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+
+    async fn trigger_scan(
+        &self,
+        _request: Request<TriggerScanRequest>,
+    ) -> Result<Response<TriggerScanResponse>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+
+    async fn get_scan_status(
+        &self,
+        _request: Request<GetScanStatusRequest>,
+    ) -> Result<Response<GetScanStatusResponse>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
 }
 
 static START_SERVER: OnceCell<()> = OnceCell::const_new();
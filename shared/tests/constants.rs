@@ -0,0 +1,98 @@
+use shared::constants::*;
+use shared::ring::RingStats;
+
+#[test]
+fn test_ctl_code_matches_devioctl() {
+    // CTL_CODE(FILE_DEVICE_UNKNOWN, 0x800, METHOD_BUFFERED, FILE_READ_ACCESS)
+    assert_eq!(ctl_code(FILE_DEVICE_UNKNOWN, 0x800, METHOD_BUFFERED, FILE_READ_ACCESS), 0x0022_6000);
+    // CTL_CODE(FILE_DEVICE_UNKNOWN, 0x801, METHOD_BUFFERED, FILE_ANY_ACCESS)
+    assert_eq!(ctl_code(FILE_DEVICE_UNKNOWN, 0x801, METHOD_BUFFERED, FILE_ANY_ACCESS), 0x0022_2004);
+    assert_eq!(ctl_code(0x12, 0xfff, 3, 3), 0x0012_ffff);
+}
+
+#[test]
+fn test_gladix_codes_are_distinct_and_buffered() {
+    let codes = [
+        IOCTL_GLADIX_PING,
+        IOCTL_GLADIX_GET_VERSION,
+        IOCTL_GLADIX_GET_RING_STATS,
+        IOCTL_GLADIX_SET_NET_POLICY,
+        IOCTL_GLADIX_SET_PROTECTED_PIDS,
+    ];
+    let functions: Vec<u32> = codes.iter().map(|c| ctl_function(*c)).collect();
+    assert_eq!(functions, [0x801, 0x802, 0x800, 0x803, 0x804]);
+    assert!(codes.iter().all(|c| c & 3 == METHOD_BUFFERED && c >> 16 == FILE_DEVICE_UNKNOWN));
+}
+
+#[test]
+fn test_replies_decode() {
+    let mut version = protocol_version(2, 0).to_le_bytes().to_vec();
+    version.extend([0, 0, 3, 0, 7, 0, 0, 0, 0, 0, 0, 0]);
+    version.extend(1_760_000_000u64.to_le_bytes());
+    let decoded = VersionInfo::from_bytes(&version).unwrap();
+    assert_eq!(
+        (decoded.protocol, decoded.major, decoded.minor, decoded.patch, decoded.build_time),
+        (2 << 16, 0, 3, 7, 1_760_000_000)
+    );
+    assert_eq!((decoded.driver_version().as_str(), decoded.protocol_version().as_str()), ("0.3.7", "2.0"));
+    // A reply from before the build time.
+    assert!(VersionInfo::from_bytes(&version[..12]).is_none());
+
+    let mut stats = 4096u64.to_le_bytes().to_vec();
+    stats.extend(1024u64.to_le_bytes());
+    stats.extend(3u32.to_le_bytes());
+    stats.extend((1u32 << 20).to_le_bytes());
+    // A driver from before the drop reasons.
+    let decoded = RingStats::from_bytes(&stats).unwrap();
+    assert_eq!(decoded, RingStats { head: 4096, tail: 1024, dropped: 3, size: 1 << 20, ..RingStats::default() });
+    assert!(RingStats::from_bytes(&stats[..23]).is_none());
+    for reason in [2u32, 1, 2 << 20, 0] {
+        stats.extend(reason.to_le_bytes());
+    }
+    let decoded = RingStats::from_bytes(&stats).unwrap();
+    assert_eq!(
+        (decoded.dropped, decoded.dropped_full, decoded.dropped_oversize, decoded.max_observed_len),
+        (3, 2, 1, 2 << 20)
+    );
+}
+
+#[test]
+fn test_net_rules_encode_little_endian() {
+    let mut rule = NetRule { family: NET_FAMILY_V4, prefix: 8, action: NET_ACTION_BLOCK, port_lo: 445, port_hi: 446, ..NetRule::ANY };
+    rule.addr[0] = 10;
+    rule.process[..5].copy_from_slice(b"*.exe");
+    rule.process_len = 5;
+    let bytes = rule.to_bytes();
+    assert_eq!(bytes.len(), 144);
+    assert_eq!(bytes[..4], [10, 0, 0, 0]);
+    assert_eq!(bytes[16..24], [4, 8, 1, 5, 0xbd, 0x01, 0xbe, 0x01]);
+    assert_eq!(&bytes[24..29], b"*.exe");
+    assert!(bytes[29..].iter().all(|b| *b == 0));
+}
+
+#[test]
+fn test_version_layout_and_protocol_split() {
+    use std::mem::offset_of;
+    // Offsets the driver's copy of `VersionInfo` writes.
+    assert_eq!(VersionInfo::SIZE, 24);
+    assert_eq!(
+        [offset_of!(VersionInfo, major), offset_of!(VersionInfo, patch), offset_of!(VersionInfo, build_time)],
+        [4, 8, 16]
+    );
+    assert_eq!(protocol_version(3, 12), 0x0003_000c);
+    assert_eq!((protocol_major(0x0003_000c), protocol_minor(0x0003_000c)), (3, 12));
+    // Drivers from before the split replied 1.
+    assert_eq!((protocol_major(1), protocol_minor(1)), (0, 1));
+}
+
+#[test]
+fn test_object_names_derive_from_one_logical_name() {
+    // The driver creates under `\BaseNamedObjects\`, which user mode reaches
+    // from any session as `Global\`.
+    assert_eq!(kernel_object_path(RING_EVENT), r"\BaseNamedObjects\GladixRingEvent");
+    assert_eq!(user_object_path(RING_EVENT), r"Global\GladixRingEvent");
+    assert_eq!(
+        [PROCESS_RING, IMAGE_RING, OBJECT_RING, NETWORK_RING].map(ring_path),
+        [r"\\Gladix\process_ring", r"\\Gladix\image_ring", r"\\Gladix\object_ring", r"\\Gladix\network_ring"]
+    );
+}
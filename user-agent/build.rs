@@ -0,0 +1,26 @@
+// build.rs
+//! Records what the agent library was built with, for `features::build_info`.
+//!
+//! Cargo only exposes enabled features to build scripts as
+//! `CARGO_FEATURE_<NAME>` variables, so they are collected here and handed to
+//! the crate as a `gladix_feature` cfg per feature plus one comma-separated
+//! environment variable.
+
+use std::env;
+
+fn main() {
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(k, _)| k.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase().replace('_', "-")))
+        .filter(|f| f != "default")
+        .collect();
+    features.sort();
+
+    println!("cargo::rustc-check-cfg=cfg(gladix_feature, values(any()))");
+    for f in &features {
+        println!("cargo::rustc-cfg=gladix_feature=\"{f}\"");
+    }
+    println!("cargo::rustc-env=GLADIX_FEATURES={}", features.join(","));
+    println!("cargo::rustc-env=GLADIX_TARGET={}", env::var("TARGET").unwrap_or_default());
+    println!("cargo::rustc-env=GLADIX_PROFILE={}", env::var("PROFILE").unwrap_or_default());
+    println!("cargo::rerun-if-changed=build.rs");
+}
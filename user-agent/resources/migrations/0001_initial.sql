@@ -0,0 +1,56 @@
+-- Version 1: the tables the agent has created from SQL since its first
+-- release. Tables declared in Rust (events, probe_results, captures, ...)
+-- are versioned on their own by db::schema_registry.
+
+-- Configuration tables (scanner, process, fs, network, etw)
+CREATE TABLE IF NOT EXISTS scanner_config (
+    id               INTEGER PRIMARY KEY CHECK (id = 1),
+    enabled          BOOLEAN NOT NULL DEFAULT TRUE,
+    interval_seconds INTEGER NOT NULL DEFAULT 600,
+    recursive        BOOLEAN NOT NULL DEFAULT TRUE,
+    file_extensions  TEXT    NOT NULL DEFAULT '.exe,.dll,.bat',
+    paths            TEXT    NOT NULL DEFAULT '[]'
+    );
+
+CREATE TABLE IF NOT EXISTS process_config (
+    id                    INTEGER PRIMARY KEY CHECK (id = 1),
+    enabled               BOOLEAN NOT NULL DEFAULT TRUE,
+    hook_creation         BOOLEAN NOT NULL DEFAULT TRUE,
+    hook_termination      BOOLEAN NOT NULL DEFAULT FALSE,
+    detect_remote_threads BOOLEAN NOT NULL DEFAULT TRUE
+    );
+
+CREATE TABLE IF NOT EXISTS fs_config (
+    id             INTEGER PRIMARY KEY CHECK (id = 1),
+    enabled        BOOLEAN NOT NULL DEFAULT TRUE,
+    filter_mask    INTEGER NOT NULL DEFAULT 0x1F,
+    path_whitelist TEXT    NOT NULL DEFAULT '[]',
+    path_blacklist TEXT    NOT NULL DEFAULT '[]'
+    );
+
+CREATE TABLE IF NOT EXISTS network_config (
+    id             INTEGER PRIMARY KEY CHECK (id = 1),
+    enabled        BOOLEAN NOT NULL DEFAULT TRUE,
+    inspect_dns    BOOLEAN NOT NULL DEFAULT FALSE,
+    include_ports  TEXT    NOT NULL DEFAULT '[]',
+    exclude_ports  TEXT    NOT NULL DEFAULT '[]'
+    );
+
+CREATE TABLE IF NOT EXISTS etw_config (
+    id         INTEGER PRIMARY KEY CHECK (id = 1),
+    enabled    BOOLEAN NOT NULL DEFAULT TRUE,
+    level      INTEGER NOT NULL DEFAULT 4,
+    keywords   INTEGER NOT NULL DEFAULT 0xFFFFFFFF,
+    providers  TEXT    NOT NULL DEFAULT '[]'
+    );
+
+-- Configuration auditing
+CREATE TABLE IF NOT EXISTS config_audit (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    sensor_type TEXT    NOT NULL,
+    changed_at  INTEGER NOT NULL,
+    actor       TEXT    NOT NULL,
+    old_config  TEXT    NOT NULL,
+    new_config  TEXT    NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_config_audit_time ON config_audit(changed_at);
@@ -0,0 +1,35 @@
+-- Version 2: alerts, ring consumer progress and coverage gaps. Databases
+-- older than the migration runner may have some of them already, so every
+-- statement tolerates an existing table or index.
+
+-- Alerts raised by detection; context_event_ids is a JSON array of
+-- {table, event_uid, ts, pid} references captured around the trigger.
+CREATE TABLE IF NOT EXISTS alerts (
+    id                INTEGER PRIMARY KEY,
+    ts                INTEGER NOT NULL,
+    rule_id           TEXT    NOT NULL,
+    severity          TEXT    NOT NULL,
+    pid               INTEGER,
+    ppid              INTEGER,
+    message           TEXT,
+    context_event_ids TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_alerts_ts ON alerts(ts);
+CREATE INDEX IF NOT EXISTS idx_alerts_severity_ts ON alerts(severity, ts);
+
+-- Ring consumer progress: last position whose events were committed
+CREATE TABLE IF NOT EXISTS consumer_state (
+    ring       TEXT    PRIMARY KEY,
+    position   INTEGER NOT NULL,
+    ring_size  INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+-- Telemetry known to be missing (bytes is NULL when unknown)
+CREATE TABLE IF NOT EXISTS coverage_gaps (
+    id    INTEGER PRIMARY KEY,
+    ts    INTEGER NOT NULL,
+    ring  TEXT    NOT NULL,
+    kind  TEXT    NOT NULL,
+    bytes INTEGER
+);
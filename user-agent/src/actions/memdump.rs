@@ -0,0 +1,417 @@
+// src/actions/memdump.rs
+//! Memory capture of the process an alert points at.
+//!
+//! The dump is written with `MiniDumpWriteDump` into the captures directory,
+//! which is restricted to SYSTEM and Administrators before the first file
+//! lands in it. Requests are refused for the agent itself and for
+//! denylisted images, and limited to `per_hour` attempts in any rolling hour.
+//! A dump above `max_mb` (after optional zstd compression) is cut to the cap
+//! and flagged as truncated. Failures such as a process that already exited
+//! or a protected process denying access are recorded once, not retried.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use chrono::Utc;
+use metrics::counter;
+
+use crate::config::model::{DumpType, MemdumpConfig};
+use crate::intel::analytics::image_matches;
+
+const RATE_WINDOW: Duration = Duration::from_secs(3_600);
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureStatus {
+    Captured,
+    /// The dump was attempted and failed (process gone, access denied...).
+    Failed,
+    /// The agent itself or a denylisted image.
+    Refused,
+    RateLimited,
+}
+
+impl CaptureStatus {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            CaptureStatus::Captured    => "captured",
+            CaptureStatus::Failed      => "failed",
+            CaptureStatus::Refused     => "refused",
+            CaptureStatus::RateLimited => "rate_limited",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        [Self::Captured, Self::Failed, Self::Refused, Self::RateLimited]
+            .into_iter()
+            .find(|c| c.as_str() == s)
+    }
+}
+
+impl fmt::Display for CaptureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Outcome of one capture request, as stored in `captures`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    /// UNIX microseconds.
+    pub ts:         i64,
+    pub alert_id:   i64,
+    pub pid:        u32,
+    /// `None` when the process could not be opened.
+    pub image:      Option<String>,
+    pub status:     CaptureStatus,
+    pub path:       Option<PathBuf>,
+    /// Bytes on disk.
+    pub size:       Option<u64>,
+    pub truncated:  bool,
+    pub compressed: bool,
+    pub error:      Option<String>,
+}
+
+/// Reads and dumps other processes. Implemented by [`SystemDumper`] and by
+/// fakes in tests.
+pub trait Dumper: Send + Sync {
+    /// Full image path of `pid`.
+    fn image_path(&self, pid: u32) -> io::Result<String>;
+    /// Writes a dump of `pid` to `out`.
+    fn write_dump(&self, pid: u32, kind: DumpType, out: &File) -> io::Result<()>;
+}
+
+pub struct Memdump {
+    kind:     DumpType,
+    dir:      PathBuf,
+    max:      u64,
+    per_hour: usize,
+    compress: bool,
+    /// Lower-cased.
+    denylist: Vec<String>,
+    dumper:   Arc<dyn Dumper>,
+    attempts: Mutex<VecDeque<Instant>>,
+}
+
+impl Memdump {
+    pub fn new(cfg: &MemdumpConfig, dir: PathBuf, dumper: Arc<dyn Dumper>) -> Self {
+        Self {
+            kind:     cfg.dump_type,
+            dir,
+            max:      cfg.max_mb.saturating_mul(1024 * 1024),
+            per_hour: cfg.per_hour as usize,
+            compress: cfg.compress,
+            denylist: cfg.denylist.iter().map(|d| d.to_lowercase()).collect(),
+            dumper,
+            attempts: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Dumps `pid` for `alert_id`. Never fails: the outcome is in the
+    /// returned record.
+    pub fn capture(&self, alert_id: i64, pid: u32) -> Capture {
+        let mut capture = Capture {
+            ts: Utc::now().timestamp_micros(),
+            alert_id,
+            pid,
+            image: None,
+            status: CaptureStatus::Refused,
+            path: None,
+            size: None,
+            truncated: false,
+            compressed: false,
+            error: None,
+        };
+        let outcome = self.try_capture(&mut capture);
+        if let Err((status, reason)) = outcome {
+            capture.status = status;
+            capture.error = Some(reason);
+        }
+        counter!("captures_total", "status" => capture.status.as_str()).increment(1);
+        match &capture.error {
+            None => log::warn!(
+                "alert {}: captured pid {} to {} ({} bytes{})",
+                alert_id,
+                pid,
+                capture.path.as_deref().unwrap_or(Path::new("?")).display(),
+                capture.size.unwrap_or(0),
+                if capture.truncated { ", truncated" } else { "" },
+            ),
+            Some(e) => log::warn!("alert {}: no capture of pid {}: {} ({})", alert_id, pid, e, capture.status),
+        }
+        capture
+    }
+
+    fn try_capture(&self, c: &mut Capture) -> Result<(), (CaptureStatus, String)> {
+        let failed = |what: &str, e: io::Error| (CaptureStatus::Failed, format!("{what}: {e}"));
+        if c.pid == std::process::id() {
+            return Err((CaptureStatus::Refused, "the agent does not dump itself".into()));
+        }
+        let image = self.dumper.image_path(c.pid).map_err(|e| failed("cannot open process", e))?;
+        c.image = Some(image.clone());
+        if image_matches(&self.denylist, &image) {
+            return Err((CaptureStatus::Refused, "image is denylisted".into()));
+        }
+        if !self.take_slot() {
+            return Err((CaptureStatus::RateLimited, format!("more than {} captures in the last hour", self.per_hour)));
+        }
+
+        fs::create_dir_all(&self.dir).map_err(|e| failed("cannot create captures directory", e))?;
+        sys::restrict_dir(&self.dir).map_err(|e| failed("cannot restrict captures directory", e))?;
+        let stem = image.rsplit(['\\', '/']).next().unwrap_or("process").trim_end_matches(".exe");
+        let name = format!("{}-alert{}-pid{}-{}.dmp", Utc::now().format("%Y%m%dT%H%M%SZ"), c.alert_id, c.pid, stem);
+        let mut path = self.dir.join(name);
+
+        let written = File::create(&path).and_then(|file| self.dumper.write_dump(c.pid, self.kind, &file));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&path);
+            return Err(failed("dump failed", e));
+        }
+        if self.compress {
+            let zst = path.with_extension("dmp.zst");
+            let packed = File::open(&path)
+                .and_then(|raw| zstd::stream::copy_encode(raw, File::create(&zst)?, ZSTD_LEVEL));
+            let _ = fs::remove_file(if packed.is_ok() { &path } else { &zst });
+            match packed {
+                Ok(()) => {
+                    path = zst;
+                    c.compressed = true;
+                }
+                Err(e) => log::warn!("cannot compress {}: {}; keeping it raw", path.display(), e),
+            }
+        }
+
+        let mut size = fs::metadata(&path).map_err(|e| failed("dump vanished", e))?.len();
+        if size > self.max {
+            File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|f| f.set_len(self.max))
+                .map_err(|e| failed("cannot truncate dump", e))?;
+            size = self.max;
+            c.truncated = true;
+        }
+        c.status = CaptureStatus::Captured;
+        c.path = Some(path);
+        c.size = Some(size);
+        Ok(())
+    }
+
+    /// Counts an attempt if fewer than `per_hour` happened in the last hour.
+    fn take_slot(&self) -> bool {
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().unwrap();
+        while attempts.front().is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW) {
+            attempts.pop_front();
+        }
+        if attempts.len() >= self.per_hour {
+            return false;
+        }
+        attempts.push_back(now);
+        true
+    }
+}
+
+/// Restricts `dir` to SYSTEM and Administrators (the owner off Windows).
+pub(crate) use sys::restrict_dir;
+
+/// [`Dumper`] for the local machine; unsupported off Windows.
+#[derive(Debug, Default)]
+pub struct SystemDumper;
+
+impl Dumper for SystemDumper {
+    fn image_path(&self, pid: u32) -> io::Result<String> {
+        sys::image_path(pid)
+    }
+
+    fn write_dump(&self, pid: u32, kind: DumpType, out: &File) -> io::Result<()> {
+        sys::write_dump(pid, kind, out)
+    }
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use std::{fs::File, io, path::Path};
+    use crate::config::model::DumpType;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, "memory capture is only available on Windows")
+    }
+
+    pub fn image_path(_pid: u32) -> io::Result<String> {
+        Err(unsupported())
+    }
+
+    pub fn write_dump(_pid: u32, _kind: DumpType, _out: &File) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    #[cfg(unix)]
+    pub fn restrict_dir(dir: &Path) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+    }
+
+    #[cfg(not(unix))]
+    pub fn restrict_dir(_dir: &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{
+        ffi::c_void,
+        fs::File,
+        io,
+        os::windows::{ffi::OsStrExt, io::AsRawHandle},
+        path::Path,
+        ptr,
+    };
+    use crate::config::model::DumpType;
+
+    const PROCESS_QUERY_INFORMATION: u32 = 0x0400;
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const PROCESS_VM_READ: u32 = 0x0010;
+
+    const MINI_DUMP_WITH_FULL_MEMORY: u32 = 0x0002;
+    const MINI_DUMP_WITH_HANDLE_DATA: u32 = 0x0004;
+    const MINI_DUMP_WITH_UNLOADED_MODULES: u32 = 0x0020;
+    const MINI_DUMP_WITH_FULL_MEMORY_INFO: u32 = 0x0800;
+    const MINI_DUMP_WITH_THREAD_INFO: u32 = 0x1000;
+
+    const SDDL_REVISION_1: u32 = 1;
+    const DACL_SECURITY_INFORMATION: u32 = 0x0000_0004;
+    const PROTECTED_DACL_SECURITY_INFORMATION: u32 = 0x8000_0000;
+    /// Full control for SYSTEM and Administrators, inherited, nothing else.
+    const CAPTURES_SDDL: &str = "D:P(A;OICI;FA;;;SY)(A;OICI;FA;;;BA)";
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+        fn QueryFullProcessImageNameW(process: *mut c_void, flags: u32, name: *mut u16, len: *mut u32) -> i32;
+        fn LocalFree(mem: *mut c_void) -> *mut c_void;
+    }
+
+    #[link(name = "dbghelp")]
+    unsafe extern "system" {
+        fn MiniDumpWriteDump(
+            process: *mut c_void,
+            pid: u32,
+            file: *mut c_void,
+            dump_type: u32,
+            exception: *const c_void,
+            user_stream: *const c_void,
+            callback: *const c_void,
+        ) -> i32;
+    }
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        fn ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            sddl: *const u16,
+            revision: u32,
+            descriptor: *mut *mut c_void,
+            len: *mut u32,
+        ) -> i32;
+        fn SetFileSecurityW(path: *const u16, info: u32, descriptor: *mut c_void) -> i32;
+    }
+
+    /// Process handle closed on drop.
+    struct Process(*mut c_void);
+
+    impl Process {
+        fn open(pid: u32, access: u32) -> io::Result<Self> {
+            // SAFETY: plain call; a null handle is reported below.
+            let handle = unsafe { OpenProcess(access, 0, pid) };
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self(handle))
+        }
+    }
+
+    impl Drop for Process {
+        fn drop(&mut self) {
+            // SAFETY: handle opened by `Process::open` and closed once.
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    fn wide(s: impl AsRef<std::ffi::OsStr>) -> Vec<u16> {
+        s.as_ref().encode_wide().chain(Some(0)).collect()
+    }
+
+    pub fn image_path(pid: u32) -> io::Result<String> {
+        let process = Process::open(pid, PROCESS_QUERY_LIMITED_INFORMATION)?;
+        let mut buf = vec![0u16; 32_768];
+        let mut len = buf.len() as u32;
+        // SAFETY: `buf` holds `len` UTF-16 units.
+        if unsafe { QueryFullProcessImageNameW(process.0, 0, buf.as_mut_ptr(), &mut len) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(String::from_utf16_lossy(&buf[..len as usize]))
+    }
+
+    pub fn write_dump(pid: u32, kind: DumpType, out: &File) -> io::Result<()> {
+        let flags = match kind {
+            DumpType::Mini => MINI_DUMP_WITH_THREAD_INFO | MINI_DUMP_WITH_UNLOADED_MODULES,
+            DumpType::Full => {
+                MINI_DUMP_WITH_FULL_MEMORY
+                    | MINI_DUMP_WITH_FULL_MEMORY_INFO
+                    | MINI_DUMP_WITH_HANDLE_DATA
+                    | MINI_DUMP_WITH_THREAD_INFO
+                    | MINI_DUMP_WITH_UNLOADED_MODULES
+            }
+        };
+        let process = Process::open(pid, PROCESS_QUERY_INFORMATION | PROCESS_VM_READ)?;
+        // SAFETY: both handles stay open for the synchronous call.
+        let ok = unsafe {
+            MiniDumpWriteDump(process.0, pid, out.as_raw_handle(), flags, ptr::null(), ptr::null(), ptr::null())
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn restrict_dir(dir: &Path) -> io::Result<()> {
+        let sddl = wide(CAPTURES_SDDL);
+        let path = wide(dir);
+        let mut descriptor = ptr::null_mut();
+        // SAFETY: NUL-terminated inputs; the descriptor is freed below.
+        unsafe {
+            if ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                ptr::null_mut(),
+            ) == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            let ok = SetFileSecurityW(
+                path.as_ptr(),
+                DACL_SECURITY_INFORMATION | PROTECTED_DACL_SECURITY_INFORMATION,
+                descriptor,
+            );
+            let err = io::Error::last_os_error();
+            LocalFree(descriptor);
+            if ok == 0 {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
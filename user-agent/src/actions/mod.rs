@@ -0,0 +1,95 @@
+// src/actions/mod.rs
+//! Responses run when a rule raises an alert.
+//!
+//! `[actions.rules]` maps rule ids to an action; nothing runs unless the
+//! `[actions]` master switch is on. Actions run after the alert row is
+//! stored, on the blocking pool of the analytic that raised it, and record
+//! their outcome whether or not they succeed.
+
+pub mod memdump;
+pub mod quarantine;
+
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+use rusqlite::Connection;
+
+use crate::config::model::{ActionsConfig, RuleAction};
+use crate::db::{captures::record_capture, quarantine::record_quarantine};
+use crate::intel::Alert;
+
+pub use memdump::{Capture, CaptureStatus, Dumper, Memdump, SystemDumper};
+pub use quarantine::{Dpapi, Protector, Quarantine, QuarantineRecord, QuarantineStatus};
+
+/// What the action of an alert did, as recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    Capture(Capture),
+    Quarantine(QuarantineRecord),
+}
+
+/// Configured actions; cheap to clone into every analytic.
+#[derive(Clone)]
+pub struct Actions {
+    enabled:    bool,
+    rules:      Arc<BTreeMap<String, RuleAction>>,
+    memdump:    Arc<Memdump>,
+    quarantine: Arc<Quarantine>,
+}
+
+impl Actions {
+    pub fn new(cfg: &ActionsConfig, exe_dir: &Path) -> Self {
+        Self::with_dumper(cfg, exe_dir, Arc::new(SystemDumper))
+    }
+
+    pub fn with_dumper(cfg: &ActionsConfig, exe_dir: &Path, dumper: Arc<dyn Dumper>) -> Self {
+        Self::with_backends(cfg, exe_dir, dumper, Arc::new(Dpapi))
+    }
+
+    /// The quarantine store keeps its master key with `protector`.
+    pub fn with_backends(
+        cfg: &ActionsConfig,
+        exe_dir: &Path,
+        dumper: Arc<dyn Dumper>,
+        protector: Arc<dyn Protector>,
+    ) -> Self {
+        let store = exe_dir.join(quarantine::DIR);
+        Self {
+            enabled:    cfg.enabled,
+            rules:      Arc::new(cfg.rules.clone()),
+            memdump:    Arc::new(Memdump::new(&cfg.memdump, exe_dir.join(&cfg.memdump.dir), dumper)),
+            quarantine: Arc::new(Quarantine::with_config(store, protector, &cfg.quarantine)),
+        }
+    }
+
+    /// Runs nothing.
+    pub fn disabled() -> Self {
+        Self::new(&ActionsConfig::default(), Path::new("."))
+    }
+
+    /// Action declared for `rule_id`, if the master switch is on.
+    pub fn action_for(&self, rule_id: &str) -> Option<RuleAction> {
+        self.rules.get(rule_id).copied().filter(|_| self.enabled)
+    }
+
+    /// Runs the action of `alert.rule_id` for the stored alert `alert_id`
+    /// and records the outcome. Blocks for as long as the action takes.
+    /// Quarantine does nothing for an alert without a file.
+    pub fn on_alert(&self, conn: &Connection, alert_id: i64, alert: &Alert) -> rusqlite::Result<Option<Response>> {
+        match self.action_for(&alert.rule_id) {
+            None => Ok(None),
+            Some(RuleAction::Memdump) => {
+                let capture = self.memdump.capture(alert_id, alert.pid);
+                record_capture(conn, &capture)?;
+                Ok(Some(Response::Capture(capture)))
+            }
+            Some(RuleAction::Quarantine) => {
+                let Some(file) = &alert.file else {
+                    log::warn!("alert {}: {} names no file to quarantine", alert_id, alert.rule_id);
+                    return Ok(None);
+                };
+                let record = self.quarantine.quarantine_file(Path::new(file), Some(alert_id), &alert.rule_id);
+                record_quarantine(conn, &record)?;
+                Ok(Some(Response::Quarantine(record)))
+            }
+        }
+    }
+}
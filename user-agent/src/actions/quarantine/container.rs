@@ -0,0 +1,259 @@
+// src/actions/quarantine/container.rs
+//! File format of a quarantined file.
+//!
+//! ```text
+//! magic "GLDXQRNT" | version u16 | header_len u32 | header (JSON)
+//! wrapped key (60)  : nonce (12) + AES-256-GCM of the file key (32 + 16 tag)
+//! nonce (12) | content_len u64 | content: AES-256-GCM of the file, header as AAD
+//! hmac (32)         : HMAC-SHA256 over every byte before it
+//! ```
+//!
+//! Integers are little-endian. Every file gets its own random key, wrapped
+//! by a [`WrapKey`]: the agent's master key on the host, or a transport key
+//! shared with an analysis server for exports (see [`rewrap`]). The HMAC key
+//! is derived from the wrapping key.
+//!
+//! [`open`] checks the parts in file order so a corrupt byte is reported
+//! where it is: framing, header, key block, content, plaintext hash, then
+//! the trailing HMAC. Nothing is returned unless all of them pass.
+
+use std::fmt;
+use aes_gcm::{
+    aead::{Aead, AeadCore, OsRng, Payload},
+    Aes256Gcm, Key, KeyInit as _, Nonce,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{digest::KeyInit, Digest, Sha256};
+use thiserror::Error;
+
+pub const MAGIC: &[u8; 8] = b"GLDXQRNT";
+pub const VERSION: u16 = 1;
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const WRAPPED_LEN: usize = NONCE_LEN + KEY_LEN + TAG_LEN;
+const HMAC_LEN: usize = 32;
+/// Context of the HMAC key derived from a wrapping key.
+const HMAC_CONTEXT: &[u8] = b"gladix quarantine hmac v1";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// What is known about a quarantined file without decrypting it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub id:             String,
+    pub original_path:  String,
+    /// UNIX microseconds.
+    pub quarantined_at: i64,
+    /// Last modification of the original file, UNIX microseconds.
+    pub modified:       Option<i64>,
+    /// Access control of the original file, put back on restore: a
+    /// self-relative security descriptor on Windows, the mode elsewhere.
+    #[serde(with = "hex_bytes")]
+    pub acl:            Vec<u8>,
+    /// Of the plaintext, hex.
+    pub sha256:         String,
+    pub size:           u64,
+    /// Alert that asked for the quarantine, if any.
+    pub alert_id:       Option<i64>,
+    pub agent_version:  String,
+}
+
+/// Key wrapping the per-file keys.
+#[derive(Clone, PartialEq, Eq)]
+pub struct WrapKey([u8; KEY_LEN]);
+
+impl WrapKey {
+    pub fn generate() -> Self {
+        Self(Aes256Gcm::generate_key(OsRng).into())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok().map(Self)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.0))
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        let mut derive = <HmacSha256 as KeyInit>::new_from_slice(&self.0).expect("HMAC takes any key size");
+        derive.update(HMAC_CONTEXT);
+        <HmacSha256 as KeyInit>::new_from_slice(&derive.finalize().into_bytes()).expect("HMAC takes any key size")
+    }
+}
+
+impl fmt::Debug for WrapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WrapKey(..)")
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ContainerError {
+    #[error("not a quarantine container")]
+    BadMagic,
+    #[error("unsupported container version {0}")]
+    Version(u16),
+    #[error("container truncated in the {0}")]
+    Truncated(&'static str),
+    #[error("{0} unexpected bytes after the HMAC")]
+    TrailingBytes(usize),
+    #[error("header is corrupt: {0}")]
+    Header(serde_json::Error),
+    #[error("file key cannot be unwrapped: wrong key or corrupt key block")]
+    KeyUnwrap,
+    #[error("content failed authentication: corrupt header, nonce or content")]
+    Content,
+    #[error("plaintext sha256 {actual} does not match the header's {expected}")]
+    Hash { expected: String, actual: String },
+    #[error("HMAC mismatch: corrupt trailer or wrong key")]
+    Hmac,
+}
+
+/// Byte ranges of a parsed container.
+struct Parts<'a> {
+    header_raw: &'a [u8],
+    header:     Header,
+    wrapped:    &'a [u8],
+    nonce:      &'a [u8],
+    content:    &'a [u8],
+    /// Everything the HMAC covers.
+    signed:     &'a [u8],
+    hmac:       &'a [u8],
+}
+
+/// Reads `n` bytes from the front of `rest`.
+fn take<'a>(rest: &mut &'a [u8], n: usize, what: &'static str) -> Result<&'a [u8], ContainerError> {
+    if rest.len() < n {
+        return Err(ContainerError::Truncated(what));
+    }
+    let (head, tail) = rest.split_at(n);
+    *rest = tail;
+    Ok(head)
+}
+
+fn parse(bytes: &[u8]) -> Result<Parts<'_>, ContainerError> {
+    let mut rest = bytes;
+    if take(&mut rest, MAGIC.len(), "magic").map_err(|_| ContainerError::BadMagic)? != MAGIC {
+        return Err(ContainerError::BadMagic);
+    }
+    let version = u16::from_le_bytes(take(&mut rest, 2, "version")?.try_into().unwrap());
+    if version != VERSION {
+        return Err(ContainerError::Version(version));
+    }
+    let header_len = u32::from_le_bytes(take(&mut rest, 4, "header length")?.try_into().unwrap());
+    let header_raw = take(&mut rest, header_len as usize, "header")?;
+    let header = serde_json::from_slice(header_raw).map_err(ContainerError::Header)?;
+    let wrapped = take(&mut rest, WRAPPED_LEN, "key block")?;
+    let nonce = take(&mut rest, NONCE_LEN, "content nonce")?;
+    let content_len = u64::from_le_bytes(take(&mut rest, 8, "content length")?.try_into().unwrap());
+    let content = take(&mut rest, usize::try_from(content_len).unwrap_or(usize::MAX), "content")?;
+    let signed = &bytes[..bytes.len() - rest.len()];
+    let hmac = take(&mut rest, HMAC_LEN, "HMAC")?;
+    if !rest.is_empty() {
+        return Err(ContainerError::TrailingBytes(rest.len()));
+    }
+    Ok(Parts { header_raw, header, wrapped, nonce, content, signed, hmac })
+}
+
+/// Seals `content` under a new file key wrapped by `key`.
+pub fn seal(header: &Header, content: &[u8], key: &WrapKey) -> Vec<u8> {
+    let header_raw = serde_json::to_vec(header).expect("header serializes");
+    let file_key = Aes256Gcm::generate_key(OsRng);
+    assemble(&header_raw, &wrap(&file_key, key), &file_key, content, key)
+}
+
+fn wrap(file_key: &Key<Aes256Gcm>, key: &WrapKey) -> Vec<u8> {
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let mut wrapped = nonce.to_vec();
+    wrapped.extend(key.cipher().encrypt(&nonce, file_key.as_slice()).expect("AES-GCM encryption"));
+    wrapped
+}
+
+fn assemble(header_raw: &[u8], wrapped: &[u8], file_key: &Key<Aes256Gcm>, content: &[u8], key: &WrapKey) -> Vec<u8> {
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let sealed = Aes256Gcm::new(file_key)
+        .encrypt(&nonce, Payload { msg: content, aad: header_raw })
+        .expect("AES-GCM encryption");
+
+    let mut out = Vec::with_capacity(header_raw.len() + sealed.len() + 128);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(header_raw.len() as u32).to_le_bytes());
+    out.extend_from_slice(header_raw);
+    out.extend_from_slice(wrapped);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&(sealed.len() as u64).to_le_bytes());
+    out.extend_from_slice(&sealed);
+    let mut mac = key.mac();
+    mac.update(&out);
+    out.extend_from_slice(&mac.finalize().into_bytes());
+    out
+}
+
+fn unwrap_key(parts: &Parts<'_>, key: &WrapKey) -> Result<Key<Aes256Gcm>, ContainerError> {
+    let (nonce, sealed) = parts.wrapped.split_at(NONCE_LEN);
+    let plain = key.cipher().decrypt(Nonce::from_slice(nonce), sealed).map_err(|_| ContainerError::KeyUnwrap)?;
+    Ok(*Key::<Aes256Gcm>::from_slice(&plain))
+}
+
+fn verify_hmac(parts: &Parts<'_>, key: &WrapKey) -> Result<(), ContainerError> {
+    let mut mac = key.mac();
+    mac.update(parts.signed);
+    mac.verify_slice(parts.hmac).map_err(|_| ContainerError::Hmac)
+}
+
+/// Header of a container, unauthenticated; for listings.
+pub fn peek(bytes: &[u8]) -> Result<Header, ContainerError> {
+    parse(bytes).map(|p| p.header)
+}
+
+/// Verifies every part of a container and returns its header and plaintext.
+pub fn open(bytes: &[u8], key: &WrapKey) -> Result<(Header, Vec<u8>), ContainerError> {
+    let parts = parse(bytes)?;
+    let file_key = unwrap_key(&parts, key)?;
+    let plain = Aes256Gcm::new(&file_key)
+        .decrypt(Nonce::from_slice(parts.nonce), Payload { msg: parts.content, aad: parts.header_raw })
+        .map_err(|_| ContainerError::Content)?;
+    let actual = hex::encode(Sha256::digest(&plain));
+    if actual != parts.header.sha256 || plain.len() as u64 != parts.header.size {
+        return Err(ContainerError::Hash { expected: parts.header.sha256, actual });
+    }
+    verify_hmac(&parts, key)?;
+    Ok((parts.header, plain))
+}
+
+/// Verifies a container sealed under `from` and wraps its file key under
+/// `to` instead. The header and content bytes are kept.
+pub fn rewrap(bytes: &[u8], from: &WrapKey, to: &WrapKey) -> Result<Vec<u8>, ContainerError> {
+    open(bytes, from)?;
+    let parts = parse(bytes)?;
+    let file_key = unwrap_key(&parts, from)?;
+    let mut out = bytes[..parts.signed.len()].to_vec();
+    let start = MAGIC.len() + 2 + 4 + parts.header_raw.len();
+    out.splice(start..start + WRAPPED_LEN, wrap(&file_key, to));
+    let mut mac = to.mac();
+    mac.update(&out);
+    out.extend_from_slice(&mac.finalize().into_bytes());
+    Ok(out)
+}
+
+/// `Vec<u8>` as a hex string.
+mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        hex::decode(String::deserialize(d)?).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,614 @@
+// src/actions/quarantine/mod.rs
+//! Quarantine store: files moved out of reach in encrypted containers.
+//!
+//! Each file becomes `<dir>/<id>.gqf` (see [`container`]) and the original
+//! is deleted, or kept with `mode = "copy"`. Files above `max_mb` are
+//! refused, and a file another process holds open is retried with backoff
+//! before giving up. The per-file keys are wrapped by a master key that lives in
+//! `<dir>/master.key`, protected with machine-scope DPAPI, and is created on
+//! first use. The directory is restricted to SYSTEM and Administrators like
+//! the captures directory. Restores verify the container completely before
+//! writing anything back; exports copy it, optionally re-wrapped under a
+//! transport key, next to a metadata JSON for upload.
+//!
+//! The `quarantine` rule action goes through [`Quarantine::quarantine_file`],
+//! whose outcome is stored in the `quarantine` table (`db::quarantine`).
+
+pub mod container;
+
+use std::{
+    fmt,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+use chrono::Utc;
+use metrics::counter;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::actions::memdump::restrict_dir;
+use crate::config::model::{QuarantineConfig, QuarantineMode};
+use crate::util::retry::{retry_blocking, RetryError, RetryPolicy};
+pub use container::{ContainerError, Header, WrapKey};
+
+/// Quarantine directory, relative to the agent's directory.
+pub const DIR: &str = "quarantine";
+pub const MASTER_KEY_FILE: &str = "master.key";
+pub const EXTENSION: &str = "gqf";
+
+/// Protects the master key at rest. Implemented by [`Dpapi`] and by fakes
+/// in tests.
+pub trait Protector: Send + Sync {
+    fn protect(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+    fn unprotect(&self, blob: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// Machine-scope DPAPI; unsupported off Windows.
+#[derive(Debug, Default)]
+pub struct Dpapi;
+
+impl Protector for Dpapi {
+    fn protect(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        sys::protect(data)
+    }
+
+    fn unprotect(&self, blob: &[u8]) -> io::Result<Vec<u8>> {
+        sys::unprotect(blob)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum QuarantineError {
+    #[error("no quarantined file {0}")]
+    NotFound(String),
+    #[error("{0} already exists; not overwritten")]
+    Exists(PathBuf),
+    #[error("{path} is {size} bytes, above the {max} byte limit")]
+    TooLarge { path: PathBuf, size: u64, max: u64 },
+    /// Sealed, but the original is still there.
+    #[error("sealed as {}, but {} could not be deleted: {source}", header.id, header.original_path)]
+    Kept { header: Box<Header>, source: io::Error },
+    #[error("quarantined file {id}: {source}")]
+    Container { id: String, source: ContainerError },
+    #[error("master key: {0}")]
+    MasterKey(io::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuarantineStatus {
+    /// Sealed and the original deleted.
+    Quarantined,
+    /// Sealed, the original left in place: `mode = "copy"`, or it could not
+    /// be deleted (see `error`).
+    Copied,
+    /// Above `max_mb`; nothing sealed.
+    TooLarge,
+    /// Nothing sealed: no such file, unreadable, or still locked after the
+    /// retries.
+    Failed,
+    /// Put back by [`Quarantine::restore`].
+    Restored,
+}
+
+impl QuarantineStatus {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            QuarantineStatus::Quarantined => "quarantined",
+            QuarantineStatus::Copied      => "copied",
+            QuarantineStatus::TooLarge    => "too_large",
+            QuarantineStatus::Failed      => "failed",
+            QuarantineStatus::Restored    => "restored",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        [Self::Quarantined, Self::Copied, Self::TooLarge, Self::Failed, Self::Restored]
+            .into_iter()
+            .find(|q| q.as_str() == s)
+    }
+}
+
+impl fmt::Display for QuarantineStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Outcome of one quarantine request, as stored in `quarantine`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuarantineRecord {
+    /// UNIX microseconds.
+    pub ts:       i64,
+    pub alert_id: Option<i64>,
+    pub path:     String,
+    /// Why the file was quarantined, e.g. the rule that alerted.
+    pub reason:   String,
+    pub status:   QuarantineStatus,
+    /// Container id, once sealed.
+    pub id:       Option<String>,
+    /// Of the content, hex.
+    pub sha256:   Option<String>,
+    pub size:     Option<u64>,
+    /// Last modification of the original, UNIX microseconds.
+    pub modified: Option<i64>,
+    pub error:    Option<String>,
+}
+
+/// Files written by [`Quarantine::export`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub container: PathBuf,
+    pub metadata:  PathBuf,
+}
+
+/// Detached metadata of an exported container.
+#[derive(Debug, Serialize)]
+struct ExportMetadata<'a> {
+    #[serde(flatten)]
+    header:           &'a Header,
+    format_version:   u16,
+    /// `master` (opens only on this host) or `transport`.
+    key:              &'static str,
+    container_sha256: String,
+    container_size:   u64,
+    exported_at:      String,
+}
+
+pub struct Quarantine {
+    dir:       PathBuf,
+    protector: Arc<dyn Protector>,
+    mode:      QuarantineMode,
+    /// Bytes.
+    max_size:  u64,
+    /// For files another process holds open.
+    locked:    RetryPolicy,
+}
+
+impl Quarantine {
+    /// A store with the `[actions.quarantine]` defaults.
+    pub fn new(dir: PathBuf, protector: Arc<dyn Protector>) -> Self {
+        Self::with_config(dir, protector, &QuarantineConfig::default())
+    }
+
+    pub fn with_config(dir: PathBuf, protector: Arc<dyn Protector>, cfg: &QuarantineConfig) -> Self {
+        Self {
+            dir,
+            protector,
+            mode:     cfg.mode,
+            max_size: cfg.max_mb.saturating_mul(1024 * 1024),
+            locked:   RetryPolicy::new("quarantine", Duration::from_millis(200))
+                .max_delay(Duration::from_secs(5))
+                .max_attempts(cfg.attempts),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Container of `id`; ids never contain path separators.
+    pub fn path_of(&self, id: &str) -> Result<PathBuf, QuarantineError> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(QuarantineError::NotFound(id.into()));
+        }
+        Ok(self.dir.join(format!("{id}.{EXTENSION}")))
+    }
+
+    fn read(&self, id: &str) -> Result<Vec<u8>, QuarantineError> {
+        match fs::read(self.path_of(id)?) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(QuarantineError::NotFound(id.into())),
+            other => Ok(other?),
+        }
+    }
+
+    /// Loads the master key, creating it on first use.
+    fn master_key(&self) -> Result<WrapKey, QuarantineError> {
+        let path = self.dir.join(MASTER_KEY_FILE);
+        match fs::read(&path) {
+            Ok(blob) => {
+                let plain = self.protector.unprotect(&blob).map_err(QuarantineError::MasterKey)?;
+                WrapKey::from_bytes(&plain).ok_or_else(|| {
+                    QuarantineError::MasterKey(io::Error::new(io::ErrorKind::InvalidData, "wrong key length"))
+                })
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let key = WrapKey::generate();
+                let blob = self.protector.protect(key.as_bytes()).map_err(QuarantineError::MasterKey)?;
+                self.prepare_dir()?;
+                write_atomic(&path, &blob)?;
+                Ok(key)
+            }
+            Err(e) => Err(QuarantineError::MasterKey(e)),
+        }
+    }
+
+    fn prepare_dir(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        restrict_dir(&self.dir)
+    }
+
+    /// Runs `op`, again with backoff while it fails on a file another
+    /// process holds open.
+    fn unlocked<T>(&self, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let outcome = retry_blocking(&self.locked, || match op() {
+            Err(e) if sys::is_locked(&e) => Err(e),
+            other => Ok(other),
+        });
+        match outcome.result {
+            Ok(result) => result,
+            Err(RetryError::GaveUp { last } | RetryError::Cancelled { last }) => {
+                Err(last.unwrap_or_else(|| io::ErrorKind::TimedOut.into()))
+            }
+        }
+    }
+
+    /// Seals `path` into the store and, in `move` mode, deletes it. A file
+    /// above the size limit is refused.
+    pub fn quarantine(&self, path: &Path, alert_id: Option<i64>) -> Result<Header, QuarantineError> {
+        let too_large = |size: u64| QuarantineError::TooLarge { path: path.into(), size, max: self.max_size };
+        let meta = fs::metadata(path)?;
+        if meta.len() > self.max_size {
+            return Err(too_large(meta.len()));
+        }
+        let key = self.master_key()?;
+        let content = self.unlocked(|| fs::read(path))?;
+        if content.len() as u64 > self.max_size {
+            return Err(too_large(content.len() as u64));
+        }
+        let header = Header {
+            id:             uuid::Uuid::new_v4().to_string(),
+            original_path:  path.to_string_lossy().into_owned(),
+            quarantined_at: Utc::now().timestamp_micros(),
+            modified:       meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_micros() as i64),
+            acl:            sys::read_acl(path)?,
+            sha256:         hex::encode(Sha256::digest(&content)),
+            size:           content.len() as u64,
+            alert_id,
+            agent_version:  env!("CARGO_PKG_VERSION").into(),
+        };
+        write_atomic(&self.path_of(&header.id)?, &container::seal(&header, &content, &key))?;
+        if self.mode == QuarantineMode::Move
+            && let Err(source) = self.unlocked(|| fs::remove_file(path))
+        {
+            return Err(QuarantineError::Kept { header: Box::new(header), source });
+        }
+        log::warn!("quarantined {} as {} (sha256 {})", header.original_path, header.id, header.sha256);
+        Ok(header)
+    }
+
+    /// [`quarantine`](Self::quarantine) for a rule action. Never fails: the
+    /// outcome is in the returned record.
+    pub fn quarantine_file(&self, path: &Path, alert_id: Option<i64>, reason: &str) -> QuarantineRecord {
+        let mut record = QuarantineRecord {
+            ts: Utc::now().timestamp_micros(),
+            alert_id,
+            path: path.to_string_lossy().into_owned(),
+            reason: reason.into(),
+            status: QuarantineStatus::Failed,
+            id: None,
+            sha256: None,
+            size: None,
+            modified: None,
+            error: None,
+        };
+        let sealed = match self.quarantine(path, alert_id) {
+            Ok(header) => {
+                record.status = match self.mode {
+                    QuarantineMode::Move => QuarantineStatus::Quarantined,
+                    QuarantineMode::Copy => QuarantineStatus::Copied,
+                };
+                Some(header)
+            }
+            Err(QuarantineError::Kept { header, source }) => {
+                record.status = QuarantineStatus::Copied;
+                record.error = Some(format!("original not deleted: {source}"));
+                Some(*header)
+            }
+            Err(e) => {
+                if let QuarantineError::TooLarge { size, .. } = e {
+                    record.status = QuarantineStatus::TooLarge;
+                    record.size = Some(size);
+                }
+                record.error = Some(e.to_string());
+                None
+            }
+        };
+        if let Some(header) = sealed {
+            record.id = Some(header.id);
+            record.sha256 = Some(header.sha256);
+            record.size = Some(header.size);
+            record.modified = header.modified;
+        }
+        counter!("quarantine_total", "status" => record.status.as_str()).increment(1);
+        if let Some(e) = &record.error {
+            log::warn!("no quarantine of {} ({}): {} ({})", record.path, reason, e, record.status);
+        }
+        record
+    }
+
+    /// Headers of every container, sorted by quarantine time. Unreadable
+    /// files are skipped with a warning.
+    pub fn list(&self) -> Result<Vec<Header>, QuarantineError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            other => other?,
+        };
+        let mut headers = Vec::new();
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.extension().is_some_and(|e| e == EXTENSION) {
+                match fs::read(&path).map_err(QuarantineError::from).and_then(|b| {
+                    container::peek(&b).map_err(|source| QuarantineError::Container { id: path.display().to_string(), source })
+                }) {
+                    Ok(header) => headers.push(header),
+                    Err(e) => log::warn!("skipping {}: {}", path.display(), e),
+                }
+            }
+        }
+        headers.sort_by_key(|h| h.quarantined_at);
+        Ok(headers)
+    }
+
+    /// Verifies `id` and writes it back to its original path with its
+    /// access control; the container is removed afterwards. A different file
+    /// at that path is not overwritten; the same content there (a `copy`
+    /// quarantine) only removes the container.
+    pub fn restore(&self, id: &str) -> Result<PathBuf, QuarantineError> {
+        let bytes = self.read(id)?;
+        let (header, content) = container::open(&bytes, &self.master_key()?)
+            .map_err(|source| QuarantineError::Container { id: id.into(), source })?;
+        let target = PathBuf::from(&header.original_path);
+        if target.exists() {
+            if fs::read(&target).is_ok_and(|present| Sha256::digest(&present) == Sha256::digest(&content)) {
+                fs::remove_file(self.path_of(id)?)?;
+                log::warn!("{} is already in place; dropped {}", target.display(), id);
+                return Ok(target);
+            }
+            return Err(QuarantineError::Exists(target));
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(&target, &content)?;
+        if let Err(e) = sys::write_acl(&target, &header.acl) {
+            log::warn!("restored {} without its access control: {}", target.display(), e);
+        }
+        fs::remove_file(self.path_of(id)?)?;
+        log::warn!("restored {} from {}", target.display(), id);
+        Ok(target)
+    }
+
+    /// Writes `<id>.gqf` and `<id>.json` into `out`. With `transport` the
+    /// copy is re-wrapped so that key opens it instead of the master key.
+    pub fn export(&self, id: &str, out: &Path, transport: Option<&WrapKey>) -> Result<Export, QuarantineError> {
+        let bytes = self.read(id)?;
+        let master = self.master_key()?;
+        let invalid = |source| QuarantineError::Container { id: id.into(), source };
+        let (header, _) = container::open(&bytes, &master).map_err(invalid)?;
+        let bytes = match transport {
+            Some(key) => container::rewrap(&bytes, &master, key).map_err(invalid)?,
+            None => bytes,
+        };
+        let metadata = ExportMetadata {
+            header:           &header,
+            format_version:   container::VERSION,
+            key:              if transport.is_some() { "transport" } else { "master" },
+            container_sha256: hex::encode(Sha256::digest(&bytes)),
+            container_size:   bytes.len() as u64,
+            exported_at:      Utc::now().to_rfc3339(),
+        };
+        fs::create_dir_all(out)?;
+        let export = Export {
+            container: out.join(format!("{id}.{EXTENSION}")),
+            metadata:  out.join(format!("{id}.json")),
+        };
+        fs::write(&export.container, &bytes)?;
+        let json = serde_json::to_vec_pretty(&metadata).map_err(io::Error::from)?;
+        fs::write(&export.metadata, json)?;
+        Ok(export)
+    }
+}
+
+/// Writes through a temporary file so readers never see half a file.
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".gladix-tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use std::{io, path::Path};
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, "DPAPI is only available on Windows")
+    }
+
+    pub fn protect(_data: &[u8]) -> io::Result<Vec<u8>> {
+        Err(unsupported())
+    }
+
+    pub fn unprotect(_blob: &[u8]) -> io::Result<Vec<u8>> {
+        Err(unsupported())
+    }
+
+    /// Files are not locked against other processes here.
+    pub fn is_locked(_e: &io::Error) -> bool {
+        false
+    }
+
+    #[cfg(unix)]
+    pub fn read_acl(path: &Path) -> io::Result<Vec<u8>> {
+        use std::os::unix::fs::PermissionsExt;
+        Ok(std::fs::metadata(path)?.permissions().mode().to_le_bytes().to_vec())
+    }
+
+    #[cfg(unix)]
+    pub fn write_acl(path: &Path, acl: &[u8]) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = acl.try_into().map(u32::from_le_bytes).map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    pub fn read_acl(_path: &Path) -> io::Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    #[cfg(not(unix))]
+    pub fn write_acl(_path: &Path, _acl: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{
+        ffi::c_void,
+        io,
+        os::windows::ffi::OsStrExt,
+        path::Path,
+        ptr,
+    };
+
+    const CRYPTPROTECT_UI_FORBIDDEN: u32 = 0x1;
+    const CRYPTPROTECT_LOCAL_MACHINE: u32 = 0x4;
+    const DACL_SECURITY_INFORMATION: u32 = 0x0000_0004;
+    const ERROR_INSUFFICIENT_BUFFER: i32 = 122;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+
+    #[repr(C)]
+    struct DataBlob {
+        len:  u32,
+        data: *mut u8,
+    }
+
+    #[link(name = "crypt32")]
+    unsafe extern "system" {
+        fn CryptProtectData(
+            input: *const DataBlob,
+            description: *const u16,
+            entropy: *const DataBlob,
+            reserved: *const c_void,
+            prompt: *const c_void,
+            flags: u32,
+            output: *mut DataBlob,
+        ) -> i32;
+        fn CryptUnprotectData(
+            input: *const DataBlob,
+            description: *mut *mut u16,
+            entropy: *const DataBlob,
+            reserved: *const c_void,
+            prompt: *const c_void,
+            flags: u32,
+            output: *mut DataBlob,
+        ) -> i32;
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn LocalFree(mem: *mut c_void) -> *mut c_void;
+    }
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        fn GetFileSecurityW(path: *const u16, info: u32, descriptor: *mut c_void, len: u32, needed: *mut u32) -> i32;
+        fn SetFileSecurityW(path: *const u16, info: u32, descriptor: *mut c_void) -> i32;
+    }
+
+    fn wide(s: impl AsRef<std::ffi::OsStr>) -> Vec<u16> {
+        s.as_ref().encode_wide().chain(Some(0)).collect()
+    }
+
+    /// Another process has the file open without sharing, or a range locked.
+    pub fn is_locked(e: &io::Error) -> bool {
+        matches!(e.raw_os_error(), Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION))
+    }
+
+    /// Copies and frees a blob allocated by DPAPI.
+    fn take(blob: DataBlob) -> Vec<u8> {
+        // SAFETY: DPAPI returned `len` bytes at `data`, freed once here.
+        unsafe {
+            let out = std::slice::from_raw_parts(blob.data, blob.len as usize).to_vec();
+            LocalFree(blob.data.cast());
+            out
+        }
+    }
+
+    pub fn protect(data: &[u8]) -> io::Result<Vec<u8>> {
+        let input = DataBlob { len: data.len() as u32, data: data.as_ptr().cast_mut() };
+        let mut output = DataBlob { len: 0, data: ptr::null_mut() };
+        let flags = CRYPTPROTECT_UI_FORBIDDEN | CRYPTPROTECT_LOCAL_MACHINE;
+        // SAFETY: `input` borrows `data`, which DPAPI only reads.
+        let ok = unsafe {
+            CryptProtectData(&input, ptr::null(), ptr::null(), ptr::null(), ptr::null(), flags, &mut output)
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(take(output))
+    }
+
+    pub fn unprotect(blob: &[u8]) -> io::Result<Vec<u8>> {
+        let input = DataBlob { len: blob.len() as u32, data: blob.as_ptr().cast_mut() };
+        let mut output = DataBlob { len: 0, data: ptr::null_mut() };
+        // SAFETY: as in `protect`.
+        let ok = unsafe {
+            CryptUnprotectData(
+                &input,
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(take(output))
+    }
+
+    pub fn read_acl(path: &Path) -> io::Result<Vec<u8>> {
+        let path = wide(path);
+        let mut needed = 0u32;
+        // SAFETY: a null buffer of length 0 only asks for the size.
+        let ok = unsafe {
+            GetFileSecurityW(path.as_ptr(), DACL_SECURITY_INFORMATION, ptr::null_mut(), 0, &mut needed)
+        };
+        let err = io::Error::last_os_error();
+        if ok == 0 && err.raw_os_error() != Some(ERROR_INSUFFICIENT_BUFFER) {
+            return Err(err);
+        }
+        let mut buf = vec![0u8; needed as usize];
+        // SAFETY: `buf` holds `needed` bytes.
+        if unsafe {
+            GetFileSecurityW(path.as_ptr(), DACL_SECURITY_INFORMATION, buf.as_mut_ptr().cast(), needed, &mut needed)
+        } == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(buf)
+    }
+
+    pub fn write_acl(path: &Path, acl: &[u8]) -> io::Result<()> {
+        if acl.is_empty() {
+            return Ok(());
+        }
+        let path = wide(path);
+        let mut descriptor = acl.to_vec();
+        // SAFETY: `descriptor` is the self-relative descriptor read by `read_acl`.
+        if unsafe { SetFileSecurityW(path.as_ptr(), DACL_SECURITY_INFORMATION, descriptor.as_mut_ptr().cast()) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+// src/admin/mod.rs
+
+//! `install`, `uninstall` and `status`: the driver and the agent as
+//! services, without `sc.exe`.
+//!
+//! ```text
+//! agent install [--driver <path>] [--agent <path>] [--ring-size <bytes>] [--config <path>]
+//! agent uninstall
+//! agent status
+//! ```
+//!
+//! `install` registers the driver ([`DRIVER_SERVICE`], loaded at system
+//! start), asks it for rings of `--ring-size` bytes or `[ring] size_bytes`,
+//! and registers the agent ([`AGENT_SERVICE`], started automatically and
+//! restarted by the SCM after a failure). `uninstall` stops and deletes both,
+//! agent first, and checks that the driver took its control device with it.
+//!
+//! Every step looks before it acts, so both commands can run again: what is
+//! already as asked is left alone. A failed step does not stop the others;
+//! the [`Report`] lists each with its outcome and the command fails if any
+//! did. The SCM sits behind [`ServiceManager`]; see [`scm`] for the real one.
+
+pub mod scm;
+
+use std::{
+    ffi::OsString,
+    fmt, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+use clap::{Args, Subcommand};
+use shared::constants::{DEVICE_PATH, DRIVER_SERVICE};
+
+/// Service the agent runs as.
+pub const AGENT_SERVICE: &str = "Gladix";
+/// Driver image looked for next to the executable.
+pub const DRIVER_FILE: &str = "gladix.sys";
+/// Delays of the SCM's restarts after the first, second and later failures.
+pub const RESTART_DELAYS: [Duration; 3] =
+    [Duration::from_secs(5), Duration::from_secs(30), Duration::from_secs(60)];
+/// Failure-free run after which the SCM counts failures from the first again.
+pub const RESTART_RESET: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Maintenance subcommands of the agent binary.
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Register the driver and the agent as services.
+    Install(InstallArgs),
+    /// Stop and delete both services.
+    Uninstall,
+    /// Show the state of both services.
+    Status,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Args)]
+pub struct InstallArgs {
+    /// Driver image [default: gladix.sys next to the executable].
+    #[arg(long, value_name = "PATH")]
+    pub driver: Option<PathBuf>,
+    /// Agent executable [default: this executable].
+    #[arg(long, value_name = "PATH")]
+    pub agent: Option<PathBuf>,
+    /// Ring size asked of the driver [default: `[ring] size_bytes`, if set].
+    #[arg(long, value_name = "BYTES")]
+    pub ring_size: Option<u32>,
+}
+
+impl InstallArgs {
+    /// What to install, the defaults taken from `exe`, the running
+    /// executable. The agent service is started with `--config <config>`
+    /// when one was given. Paths are made absolute: the SCM starts services
+    /// from `System32`.
+    pub fn plan(&self, exe: &Path, config: Option<&Path>, ring_size: Option<u32>) -> InstallPlan {
+        let dir = exe.parent().unwrap_or(Path::new("."));
+        let driver = self.driver.clone().unwrap_or_else(|| dir.join(DRIVER_FILE));
+        let agent = self.agent.clone().unwrap_or_else(|| exe.to_owned());
+        let args = match config {
+            Some(config) => vec!["--config".into(), absolute(config).into_os_string()],
+            None => Vec::new(),
+        };
+        InstallPlan {
+            driver: ServiceSpec { name: DRIVER_SERVICE, kind: ServiceKind::Driver, binary: absolute(&driver), args: Vec::new() },
+            agent: ServiceSpec { name: AGENT_SERVICE, kind: ServiceKind::Agent, binary: absolute(&agent), args },
+            ring_size: self.ring_size.or(ring_size),
+        }
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_owned())
+}
+
+/// Everything `install` sets up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallPlan {
+    pub driver:    ServiceSpec,
+    pub agent:     ServiceSpec,
+    pub ring_size: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceKind {
+    /// Kernel driver, loaded at system start.
+    Driver,
+    /// Process of its own, started automatically.
+    Agent,
+}
+
+/// A service as `install` registers it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceSpec {
+    pub name:   &'static str,
+    pub kind:   ServiceKind,
+    pub binary: PathBuf,
+    /// Command-line arguments; drivers take none.
+    pub args:   Vec<OsString>,
+}
+
+impl ServiceSpec {
+    pub fn display_name(&self) -> String {
+        match self.kind {
+            ServiceKind::Driver => "Gladix sensor driver".into(),
+            ServiceKind::Agent => "Gladix endpoint agent".into(),
+        }
+    }
+}
+
+/// Run state of a service, as the SCM reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Stopped,
+    Starting,
+    Stopping,
+    Running,
+    Paused,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            State::Stopped => "stopped",
+            State::Starting => "starting",
+            State::Stopping => "stopping",
+            State::Running => "running",
+            State::Paused => "paused",
+        })
+    }
+}
+
+/// A service found in the SCM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Installed {
+    pub state:   State,
+    /// Binary path as registered: the command line of a process, the image
+    /// (maybe as `\??\C:\...`) of a driver.
+    pub command: String,
+}
+
+/// What the commands need of the Service Control Manager.
+pub trait ServiceManager {
+    /// The service `name`; `None` when there is none.
+    fn query(&self, name: &str) -> io::Result<Option<Installed>>;
+    fn create(&self, spec: &ServiceSpec) -> io::Result<()>;
+    /// Points an existing service at `spec` again.
+    fn reconfigure(&self, spec: &ServiceSpec) -> io::Result<()>;
+    /// Restarts after each failure, `delays` apart, the count starting over
+    /// after `reset` without one.
+    fn set_recovery(&self, name: &str, delays: &[Duration], reset: Duration) -> io::Result<()>;
+    /// Stops `name` and waits until it has.
+    fn stop(&self, name: &str) -> io::Result<()>;
+    fn delete(&self, name: &str) -> io::Result<()>;
+    /// Writes the driver's ring size parameter; returns the size it will use.
+    fn set_ring_size(&self, bytes: u32) -> io::Result<u32>;
+    /// Whether the driver's control device ([`DEVICE_PATH`]) still opens.
+    fn device_present(&self) -> bool;
+}
+
+/// What `install` does about a service in `current` state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallAction {
+    Create,
+    /// Registered with another binary.
+    Reconfigure,
+    Keep,
+}
+
+pub fn install_action(current: Option<&Installed>, spec: &ServiceSpec) -> InstallAction {
+    match current {
+        None => InstallAction::Create,
+        Some(installed) if runs(&installed.command, &spec.binary) => InstallAction::Keep,
+        Some(_) => InstallAction::Reconfigure,
+    }
+}
+
+/// Whether the registered `command` starts `binary`. Windows paths:
+/// case aside, without the `\??\` prefix drivers are registered with.
+fn runs(command: &str, binary: &Path) -> bool {
+    let command = command.to_lowercase();
+    let command = command.strip_prefix(r"\??\").unwrap_or(&command);
+    let binary = binary.to_string_lossy().to_lowercase();
+    command == binary || command.starts_with(&format!("\"{binary}\"")) || command.starts_with(&format!("{binary} "))
+}
+
+/// What `uninstall` does about a service in `current` state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoveAction {
+    StopAndDelete,
+    Delete,
+    Nothing,
+}
+
+pub fn remove_action(current: Option<&Installed>) -> RemoveAction {
+    match current.map(|s| s.state) {
+        None => RemoveAction::Nothing,
+        Some(State::Stopped) => RemoveAction::Delete,
+        Some(_) => RemoveAction::StopAndDelete,
+    }
+}
+
+/// How a step ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Changed something; what.
+    Done(String),
+    /// Already as asked, or nothing to do; why.
+    Unchanged(String),
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub name:    String,
+    pub outcome: Outcome,
+}
+
+/// The steps of a command, in the order they ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub steps: Vec<Step>,
+}
+
+impl Report {
+    fn step(&mut self, name: impl Into<String>, run: impl FnOnce() -> io::Result<Outcome>) -> &Outcome {
+        let outcome = run().unwrap_or_else(|e| Outcome::Failed(e.to_string()));
+        self.steps.push(Step { name: name.into(), outcome });
+        &self.steps.last().unwrap().outcome
+    }
+
+    pub fn failed(&self) -> usize {
+        self.steps.iter().filter(|s| matches!(s.outcome, Outcome::Failed(_))).count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (mut done, mut unchanged) = (0, 0);
+        for step in &self.steps {
+            let (tag, text) = match &step.outcome {
+                Outcome::Done(text) => {
+                    done += 1;
+                    ("ok", text)
+                }
+                Outcome::Unchanged(text) => {
+                    unchanged += 1;
+                    ("--", text)
+                }
+                Outcome::Failed(text) => ("FAILED", text),
+            };
+            writeln!(f, "{tag:>6}  {}: {text}", step.name)?;
+        }
+        write!(f, "{done} done, {unchanged} unchanged, {} failed", self.failed())
+    }
+}
+
+/// Registers the services of `plan`, keeping what is already registered as
+/// asked.
+pub fn install(scm: &impl ServiceManager, plan: &InstallPlan) -> Report {
+    let mut report = Report::default();
+    register(scm, &plan.driver, &mut report);
+    report.step("ring size", || match plan.ring_size {
+        Some(bytes) => {
+            let size = scm.set_ring_size(bytes)?;
+            Ok(Outcome::Done(format!("{size} bytes from the next driver start")))
+        }
+        None => Ok(Outcome::Unchanged("not asked; the driver keeps its own".into())),
+    });
+    let agent = register(scm, &plan.agent, &mut report);
+    // Without the service there is nothing to set it on.
+    if !matches!(agent, Outcome::Failed(_)) {
+        report.step(format!("{AGENT_SERVICE} recovery"), || {
+            scm.set_recovery(AGENT_SERVICE, &RESTART_DELAYS, RESTART_RESET)?;
+            Ok(Outcome::Done(format!("restart after {:?}", RESTART_DELAYS)))
+        });
+    }
+    report
+}
+
+fn register(scm: &impl ServiceManager, spec: &ServiceSpec, report: &mut Report) -> Outcome {
+    report
+        .step(format!("{} service", spec.name), || {
+            let current = scm.query(spec.name)?;
+            let binary = spec.binary.display();
+            Ok(match install_action(current.as_ref(), spec) {
+                InstallAction::Create => {
+                    scm.create(spec)?;
+                    Outcome::Done(format!("created for {binary}"))
+                }
+                InstallAction::Reconfigure => {
+                    scm.reconfigure(spec)?;
+                    Outcome::Done(format!("pointed at {binary}"))
+                }
+                InstallAction::Keep => Outcome::Unchanged(format!("already registered for {binary}")),
+            })
+        })
+        .clone()
+}
+
+/// Stops and deletes the agent, then the driver, and checks that the
+/// driver's control device went with it.
+pub fn uninstall(scm: &impl ServiceManager) -> Report {
+    let mut report = Report::default();
+    for name in [AGENT_SERVICE, DRIVER_SERVICE] {
+        report.step(format!("{name} service"), || {
+            Ok(match remove_action(scm.query(name)?.as_ref()) {
+                RemoveAction::StopAndDelete => {
+                    scm.stop(name)?;
+                    scm.delete(name)?;
+                    Outcome::Done("stopped and deleted".into())
+                }
+                RemoveAction::Delete => {
+                    scm.delete(name)?;
+                    Outcome::Done("deleted".into())
+                }
+                RemoveAction::Nothing => Outcome::Unchanged("not installed".into()),
+            })
+        });
+    }
+    // The driver deletes its symbolic link when it unloads.
+    report.step("device link", || {
+        Ok(match scm.device_present() {
+            false => Outcome::Unchanged(format!("{DEVICE_PATH} gone")),
+            true => Outcome::Failed(format!("{DEVICE_PATH} still open; restart Windows to unload the driver")),
+        })
+    });
+    report
+}
+
+/// State of both services, as steps that change nothing.
+pub fn status(scm: &impl ServiceManager) -> Report {
+    let mut report = Report::default();
+    for name in [DRIVER_SERVICE, AGENT_SERVICE] {
+        report.step(name, || {
+            Ok(Outcome::Unchanged(match scm.query(name)? {
+                Some(installed) => format!("{} ({})", installed.state, installed.command),
+                None => "not installed".into(),
+            }))
+        });
+    }
+    report
+}
+
+/// Runs `command` against this machine's SCM and prints the report.
+/// `config` is the agent's `--config`, if given.
+pub fn run(command: &Command, config: Option<&Path>) -> ExitCode {
+    let scm = match scm::connect() {
+        Ok(scm) => scm,
+        Err(e) => {
+            eprintln!("cannot open the service control manager: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let report = match command {
+        Command::Install(args) => {
+            let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("agent.exe"));
+            let config_path = config.map_or_else(|| exe.with_file_name("config.toml"), Path::to_owned);
+            let ring_size = crate::config::load(&config_path).ok().and_then(|cfg| cfg.ring.size_bytes);
+            install(&scm, &args.plan(&exe, config, ring_size))
+        }
+        Command::Uninstall => uninstall(&scm),
+        Command::Status => status(&scm),
+    };
+    println!("{report}");
+    if report.failed() > 0 { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
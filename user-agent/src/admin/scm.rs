@@ -0,0 +1,212 @@
+// src/admin/scm.rs
+
+//! The Service Control Manager of this machine, through `windows-service`.
+//! Off Windows [`connect`] fails with `ErrorKind::Unsupported`.
+
+#[cfg(windows)]
+pub use sys::{connect, Scm};
+
+#[cfg(not(windows))]
+pub use stub::{connect, Scm};
+
+#[cfg(not(windows))]
+mod stub {
+    use std::{io, time::Duration};
+
+    use crate::admin::{Installed, ServiceManager, ServiceSpec};
+
+    /// Never built: there is no SCM to talk to.
+    pub enum Scm {}
+
+    pub fn connect() -> io::Result<Scm> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "services are only available on Windows"))
+    }
+
+    impl ServiceManager for Scm {
+        fn query(&self, _name: &str) -> io::Result<Option<Installed>> {
+            match *self {}
+        }
+
+        fn create(&self, _spec: &ServiceSpec) -> io::Result<()> {
+            match *self {}
+        }
+
+        fn reconfigure(&self, _spec: &ServiceSpec) -> io::Result<()> {
+            match *self {}
+        }
+
+        fn set_recovery(&self, _name: &str, _delays: &[Duration], _reset: Duration) -> io::Result<()> {
+            match *self {}
+        }
+
+        fn stop(&self, _name: &str) -> io::Result<()> {
+            match *self {}
+        }
+
+        fn delete(&self, _name: &str) -> io::Result<()> {
+            match *self {}
+        }
+
+        fn set_ring_size(&self, _bytes: u32) -> io::Result<u32> {
+            match *self {}
+        }
+
+        fn device_present(&self) -> bool {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{
+        io, thread,
+        time::{Duration, Instant},
+    };
+    use windows_service::{
+        service::{
+            Service, ServiceAccess, ServiceAction, ServiceActionType, ServiceErrorControl,
+            ServiceFailureActions, ServiceFailureResetPeriod, ServiceInfo, ServiceStartType,
+            ServiceState, ServiceType,
+        },
+        service_manager::{ServiceManager as Manager, ServiceManagerAccess},
+    };
+
+    use crate::admin::{Installed, ServiceKind, ServiceManager, ServiceSpec, State};
+    use crate::comms::{driver_params::set_ring_size_registry, ioctl::Driver};
+
+    const ERROR_SERVICE_DOES_NOT_EXIST: i32 = 1060;
+    const ERROR_SERVICE_NOT_ACTIVE: i32 = 1062;
+    const ERROR_SERVICE_MARKED_FOR_DELETE: i32 = 1072;
+    /// How long [`ServiceManager::stop`] waits for a service to stop.
+    const STOP_WAIT: Duration = Duration::from_secs(30);
+
+    /// Connection to the local SCM, allowed to create services.
+    pub struct Scm(Manager);
+
+    pub fn connect() -> io::Result<Scm> {
+        let access = ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE;
+        Manager::local_computer(None::<&str>, access).map(Scm).map_err(to_io)
+    }
+
+    fn to_io(e: windows_service::Error) -> io::Error {
+        match e {
+            windows_service::Error::Winapi(e) => e,
+            e => io::Error::other(e),
+        }
+    }
+
+    fn is(e: &windows_service::Error, code: i32) -> bool {
+        matches!(e, windows_service::Error::Winapi(e) if e.raw_os_error() == Some(code))
+    }
+
+    fn info(spec: &ServiceSpec) -> ServiceInfo {
+        let (service_type, start_type) = match spec.kind {
+            ServiceKind::Driver => (ServiceType::KERNEL_DRIVER, ServiceStartType::SystemStart),
+            ServiceKind::Agent => (ServiceType::OWN_PROCESS, ServiceStartType::AutoStart),
+        };
+        ServiceInfo {
+            name: spec.name.into(),
+            display_name: spec.display_name().into(),
+            service_type,
+            start_type,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: spec.binary.clone(),
+            launch_arguments: spec.args.clone(),
+            dependencies: Vec::new(),
+            account_name: None,
+            account_password: None,
+        }
+    }
+
+    fn state(state: ServiceState) -> State {
+        match state {
+            ServiceState::Stopped => State::Stopped,
+            ServiceState::StartPending | ServiceState::ContinuePending => State::Starting,
+            ServiceState::StopPending | ServiceState::PausePending => State::Stopping,
+            ServiceState::Running => State::Running,
+            ServiceState::Paused => State::Paused,
+        }
+    }
+
+    impl Scm {
+        fn open(&self, name: &str, access: ServiceAccess) -> io::Result<Service> {
+            self.0.open_service(name, access).map_err(to_io)
+        }
+    }
+
+    impl ServiceManager for Scm {
+        fn query(&self, name: &str) -> io::Result<Option<Installed>> {
+            let service = match self.0.open_service(name, ServiceAccess::QUERY_STATUS | ServiceAccess::QUERY_CONFIG) {
+                Ok(service) => service,
+                Err(e) if is(&e, ERROR_SERVICE_DOES_NOT_EXIST) => return Ok(None),
+                Err(e) => return Err(to_io(e)),
+            };
+            let status = service.query_status().map_err(to_io)?;
+            let config = service.query_config().map_err(to_io)?;
+            Ok(Some(Installed {
+                state:   state(status.current_state),
+                command: config.executable_path.to_string_lossy().into_owned(),
+            }))
+        }
+
+        fn create(&self, spec: &ServiceSpec) -> io::Result<()> {
+            self.0.create_service(&info(spec), ServiceAccess::QUERY_STATUS).map(drop).map_err(to_io)
+        }
+
+        fn reconfigure(&self, spec: &ServiceSpec) -> io::Result<()> {
+            self.open(spec.name, ServiceAccess::CHANGE_CONFIG)?.change_config(&info(spec)).map_err(to_io)
+        }
+
+        fn set_recovery(&self, name: &str, delays: &[Duration], reset: Duration) -> io::Result<()> {
+            // A restart action needs SERVICE_START as well.
+            let service = self.open(name, ServiceAccess::CHANGE_CONFIG | ServiceAccess::START)?;
+            let actions = delays.iter().map(|&delay| ServiceAction { action_type: ServiceActionType::Restart, delay });
+            service
+                .update_failure_actions(ServiceFailureActions {
+                    reset_period: ServiceFailureResetPeriod::After(reset),
+                    reboot_msg:   None,
+                    command:      None,
+                    actions:      Some(actions.collect()),
+                })
+                .map_err(to_io)?;
+            // Also when the agent exits with an error rather than crashing.
+            service.set_failure_actions_on_non_crash_failures(true).map_err(to_io)
+        }
+
+        fn stop(&self, name: &str) -> io::Result<()> {
+            let service = self.open(name, ServiceAccess::STOP | ServiceAccess::QUERY_STATUS)?;
+            match service.stop() {
+                Ok(_) => {}
+                Err(e) if is(&e, ERROR_SERVICE_NOT_ACTIVE) => return Ok(()),
+                Err(e) => return Err(to_io(e)),
+            }
+            let deadline = Instant::now() + STOP_WAIT;
+            while service.query_status().map_err(to_io)?.current_state != ServiceState::Stopped {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("still stopping after {}s", STOP_WAIT.as_secs()),
+                    ));
+                }
+                thread::sleep(Duration::from_millis(250));
+            }
+            Ok(())
+        }
+
+        fn delete(&self, name: &str) -> io::Result<()> {
+            match self.open(name, ServiceAccess::DELETE)?.delete() {
+                Err(e) if !is(&e, ERROR_SERVICE_MARKED_FOR_DELETE) => Err(to_io(e)),
+                _ => Ok(()),
+            }
+        }
+
+        fn set_ring_size(&self, bytes: u32) -> io::Result<u32> {
+            set_ring_size_registry(bytes)
+        }
+
+        fn device_present(&self) -> bool {
+            Driver::open().is_ok()
+        }
+    }
+}
@@ -0,0 +1,5 @@
+// src/bin/probe_marker.rs
+//! No-op helper spawned by the live probe. The marker travels in its command
+//! line, where the process sensor has to find it; the binary does nothing.
+
+fn main() {}
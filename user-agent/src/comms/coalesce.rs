@@ -0,0 +1,164 @@
+// src/comms/coalesce.rs
+//! Bursts of like events merged before they are stored.
+//!
+//! Editors and compilers write the same file dozens of times a second. A
+//! [`Coalescer`] in front of the database sender keeps the first event of a
+//! key for a window, folds the ones after it in, and releases one event
+//! marked [`Coalesced`] when the window closes. The intel bus is fed before
+//! this stage and still sees every event. Callers pass the current time, so
+//! windows can be driven by a fake clock.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+use metrics::counter;
+use shared::events::FileEvent;
+use tokio::{sync::mpsc, task::JoinHandle, time::{self, MissedTickBehavior}};
+
+use super::{Coalesced, WrappedEvent};
+use crate::db::hub::{AnyEvent, DbSender};
+
+/// Keys with an open window, beyond which the oldest is released early.
+pub const MAX_PENDING: usize = 16_384;
+
+/// Windows are checked this many times per window, so one closes at most a
+/// quarter of it late.
+const CHECKS_PER_WINDOW: u32 = 4;
+
+/// Key of a file event: same process, path and operation.
+pub type FileKey = (u32, String, i32);
+
+struct Pending<E: Clone> {
+    event:  WrappedEvent<E>,
+    opened: Instant,
+}
+
+/// Events of one key within `window` of its first, merged into that one.
+pub struct Coalescer<E: Clone, K> {
+    window:  Duration,
+    key:     fn(&E) -> K,
+    /// Folds the second event into the first.
+    merge:   fn(&mut E, &E),
+    pending: HashMap<K, Pending<E>>,
+    /// Keys by the time their window opened, oldest first.
+    order:   VecDeque<(Instant, K)>,
+}
+
+impl<E: Clone, K: Clone + Eq + Hash> Coalescer<E, K> {
+    pub fn new(window: Duration, key: fn(&E) -> K, merge: fn(&mut E, &E)) -> Self {
+        Self { window, key, merge, pending: HashMap::new(), order: VecDeque::new() }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Keys with an open window.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Merges `ev` into the open window of its key, or opens one. Returns
+    /// the events released to make room (see [`MAX_PENDING`]).
+    pub fn push(&mut self, ev: WrappedEvent<E>, now: Instant) -> Vec<WrappedEvent<E>> {
+        let key = (self.key)(&ev.payload);
+        if let Some(open) = self.pending.get_mut(&key) {
+            (self.merge)(&mut open.event.payload, &ev.payload);
+            let merged = open.event.coalesced.get_or_insert(Coalesced { count: 1, last_ts: open.event.ts });
+            merged.count += 1;
+            merged.last_ts = ev.ts;
+            // The stored row stands for the events up to the last one.
+            open.event.ring_pos = ev.ring_pos.or(open.event.ring_pos);
+            return Vec::new();
+        }
+        let mut released = Vec::new();
+        while self.pending.len() >= MAX_PENDING {
+            let Some((_, oldest)) = self.order.pop_front() else { break };
+            released.extend(self.pending.remove(&oldest).map(|p| p.event));
+        }
+        if !released.is_empty() {
+            counter!("coalesce_released_early_total").increment(released.len() as u64);
+        }
+        self.order.push_back((now, key.clone()));
+        self.pending.insert(key, Pending { event: ev, opened: now });
+        released
+    }
+
+    /// Events whose window closed by `now`, in the order they were opened.
+    pub fn expired(&mut self, now: Instant) -> Vec<WrappedEvent<E>> {
+        let mut closed = Vec::new();
+        while let Some((opened, _)) = self.order.front() {
+            if now.saturating_duration_since(*opened) < self.window {
+                break;
+            }
+            let (_, key) = self.order.pop_front().expect("front exists");
+            closed.extend(self.pending.remove(&key).map(|p| p.event));
+        }
+        closed
+    }
+
+    /// Every open window, closed now.
+    pub fn drain(&mut self) -> Vec<WrappedEvent<E>> {
+        let mut events: Vec<_> = self.pending.drain().map(|(_, p)| p).collect();
+        events.sort_by_key(|p| p.opened);
+        self.order.clear();
+        events.into_iter().map(|p| p.event).collect()
+    }
+}
+
+impl Coalescer<FileEvent, FileKey> {
+    /// File events keyed by process, path and operation; sizes add up, the
+    /// other fields are the first event's.
+    pub fn files(window: Duration) -> Self {
+        Self::new(
+            window,
+            |ev| (ev.pid, ev.path.clone(), ev.op),
+            |first, next| first.size = first.size.saturating_add(next.size),
+        )
+    }
+}
+
+/// Runs `coalescer` in front of `db_tx`; returns the sender to store
+/// through instead. Open windows are released once every clone of the
+/// returned sender is dropped, so the task ends with its producers.
+pub fn spawn_coalescer<E, K>(
+    mut coalescer: Coalescer<E, K>,
+    db_tx: DbSender<E>,
+    capacity: usize,
+) -> (DbSender<E>, JoinHandle<()>)
+where
+    E: Clone + Send + 'static,
+    K: Clone + Eq + Hash + Send + 'static,
+    WrappedEvent<E>: Into<AnyEvent>,
+{
+    let (tx, mut rx) = mpsc::channel::<WrappedEvent<E>>(capacity);
+    let handle = tokio::spawn(async move {
+        let period = (coalescer.window() / CHECKS_PER_WINDOW).max(Duration::from_millis(10));
+        let mut check = time::interval(period);
+        check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            let ready = tokio::select! {
+                maybe = rx.recv() => match maybe {
+                    Some(ev) => coalescer.push(ev, Instant::now()),
+                    None => break,
+                },
+                _ = check.tick() => coalescer.expired(Instant::now()),
+            };
+            for ev in ready {
+                let _ = db_tx.try_send(ev);
+            }
+        }
+        for ev in coalescer.drain() {
+            if db_tx.send(ev).await.is_err() {
+                break;
+            }
+        }
+    });
+    (tx.into(), handle)
+}
@@ -0,0 +1,144 @@
+// src/comms/driver_params.rs
+//! Driver parameters kept under its service key,
+//! `HKLM\SYSTEM\CurrentControlSet\Services\<DRIVER_SERVICE>\Parameters`.
+//!
+//! The driver reads them once, when it loads: a change made here applies
+//! from the next driver start. Writing needs administrator rights. Off
+//! Windows every call fails with `ErrorKind::Unsupported`.
+
+use std::io;
+use shared::constants::{ring_size, DRIVER_SERVICE, RING_SIZE_VALUE};
+
+/// Key of the driver's parameters, relative to `HKEY_LOCAL_MACHINE`.
+pub fn parameters_key() -> String {
+    format!(r"SYSTEM\CurrentControlSet\Services\{DRIVER_SERVICE}\Parameters")
+}
+
+/// Asks the driver for rings of `bytes` from its next start. Returns the
+/// size it will use: `bytes` rounded up to a page and clamped to the bounds
+/// in `shared::constants`.
+pub fn set_ring_size_registry(bytes: u32) -> io::Result<u32> {
+    let size = ring_size(bytes);
+    sys::set_dword(&parameters_key(), RING_SIZE_VALUE, size)?;
+    Ok(size)
+}
+
+/// The size asked for, if any.
+pub fn ring_size_registry() -> io::Result<Option<u32>> {
+    sys::get_dword(&parameters_key(), RING_SIZE_VALUE)
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use std::io;
+
+    pub fn set_dword(_key: &str, _name: &str, _value: u32) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "driver parameters are only available on Windows"))
+    }
+
+    pub fn get_dword(_key: &str, _name: &str) -> io::Result<Option<u32>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "driver parameters are only available on Windows"))
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{ffi::c_void, io, ptr};
+
+    const HKEY_LOCAL_MACHINE: isize = 0x8000_0002_u32 as i32 as isize;
+    const KEY_QUERY_VALUE: u32 = 0x0001;
+    const KEY_SET_VALUE: u32 = 0x0002;
+    const REG_DWORD: u32 = 4;
+    const ERROR_FILE_NOT_FOUND: i32 = 2;
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        fn RegCreateKeyExW(
+            key: isize,
+            subkey: *const u16,
+            reserved: u32,
+            class: *const u16,
+            options: u32,
+            access: u32,
+            security: *const c_void,
+            result: *mut isize,
+            disposition: *mut u32,
+        ) -> i32;
+        fn RegOpenKeyExW(key: isize, subkey: *const u16, options: u32, access: u32, result: *mut isize) -> i32;
+        fn RegSetValueExW(key: isize, name: *const u16, reserved: u32, kind: u32, data: *const u8, len: u32) -> i32;
+        fn RegQueryValueExW(
+            key: isize,
+            name: *const u16,
+            reserved: *mut u32,
+            kind: *mut u32,
+            data: *mut u8,
+            len: *mut u32,
+        ) -> i32;
+        fn RegCloseKey(key: isize) -> i32;
+    }
+
+    /// Registry key closed on drop.
+    struct Key(isize);
+
+    impl Drop for Key {
+        fn drop(&mut self) {
+            // SAFETY: key opened by this module and closed once.
+            unsafe { RegCloseKey(self.0) };
+        }
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn check(status: i32) -> io::Result<()> {
+        match status {
+            0 => Ok(()),
+            e => Err(io::Error::from_raw_os_error(e)),
+        }
+    }
+
+    pub fn set_dword(key: &str, name: &str, value: u32) -> io::Result<()> {
+        let mut handle = 0isize;
+        // SAFETY: NUL-terminated strings; `handle` receives the key.
+        check(unsafe {
+            RegCreateKeyExW(
+                HKEY_LOCAL_MACHINE,
+                wide(key).as_ptr(),
+                0,
+                ptr::null(),
+                0,
+                KEY_SET_VALUE,
+                ptr::null(),
+                &mut handle,
+                ptr::null_mut(),
+            )
+        })?;
+        let key = Key(handle);
+        let data = value.to_le_bytes();
+        // SAFETY: `data` holds the 4 bytes of a REG_DWORD.
+        check(unsafe { RegSetValueExW(key.0, wide(name).as_ptr(), 0, REG_DWORD, data.as_ptr(), data.len() as u32) })
+    }
+
+    pub fn get_dword(key: &str, name: &str) -> io::Result<Option<u32>> {
+        let mut handle = 0isize;
+        // SAFETY: NUL-terminated string; `handle` receives the key.
+        match unsafe { RegOpenKeyExW(HKEY_LOCAL_MACHINE, wide(key).as_ptr(), 0, KEY_QUERY_VALUE, &mut handle) } {
+            ERROR_FILE_NOT_FOUND => return Ok(None),
+            status => check(status)?,
+        }
+        let key = Key(handle);
+        let (mut kind, mut data, mut len) = (0u32, [0u8; 4], 4u32);
+        // SAFETY: `data` holds `len` bytes.
+        match unsafe {
+            RegQueryValueExW(key.0, wide(name).as_ptr(), ptr::null_mut(), &mut kind, data.as_mut_ptr(), &mut len)
+        } {
+            ERROR_FILE_NOT_FOUND => return Ok(None),
+            status => check(status)?,
+        }
+        if kind != REG_DWORD || len != 4 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "value is not a REG_DWORD"));
+        }
+        Ok(Some(u32::from_le_bytes(data)))
+    }
+}
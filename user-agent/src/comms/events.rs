@@ -1,9 +1,9 @@
-// src/scanner/events.rs
+// src/comms/events.rs
 
 //! Unified event model used across the agent.
 //!
 //! Defines the `Event` enum and data structures for filesystem, network,
-//! process creation, scan results, and ETW telemetry.
+//! process, scan result, ETW, image load and process handle telemetry.
 //! Supports JSON (serde) and Protobuf (prost) serialization compatible with `event.proto`.
 
 use serde::{Deserialize, Serialize};
@@ -13,8 +13,14 @@ use shared::events::base_event::Payload;
 
 use shared::events::{
     BaseEvent as ProtoEvent,
+    EtwEvent as ProtoEtwEvent,
     FileEvent as ProtoFileEvent,
-    file_event,
+    ImageLoadEvent as ProtoImageLoadEvent,
+    NetworkEvent as ProtoNetworkEvent,
+    ObjectOpEvent as ProtoObjectOpEvent,
+    ProcessEvent as ProtoProcessEvent,
+    ScanResult as ProtoScanResult,
+    file_event, network_event, object_op_event, process_event, scan_result,
 };
 
 /// Core enum representing all telemetry types in a normalized form.
@@ -27,6 +33,8 @@ pub enum Event {
     Process(ProcessEvent),
     Scan(ScanResult),
     Etw(EtwEvent),
+    Image(ImageLoadEvent),
+    ObjectOp(ObjectOpEvent),
 }
 
 /// File system operations like create, write, delete, rename.
@@ -34,6 +42,9 @@ pub enum Event {
 pub struct FileEvent {
     pub ts: DateTime<Utc>,
     pub sensor_guid: String,
+    /// Ring frame number, for events read from a ring.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
     pub op: FileOperation,
     pub path: String,
     pub new_path: Option<String>,
@@ -42,6 +53,12 @@ pub struct FileEvent {
     pub size: u64,
     pub sha256: Vec<u8>,
     pub success: bool,
+    /// `path` and `new_path` as the sensor sent them, when the agent
+    /// rewrote them (see `intel::paths`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_new_path: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -52,6 +69,8 @@ pub enum FileOperation { Create, Write, Delete, Rename }
 pub struct NetworkEvent {
     pub ts: DateTime<Utc>,
     pub sensor_guid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
     pub direction: Direction,
     pub proto: String,
     pub src_ip: String,
@@ -62,31 +81,62 @@ pub struct NetworkEvent {
     pub exe_path: String,
     pub bytes: u64,
     pub blocked: bool,
+    /// Host name of `dst_ip`, when the DNS cache had one.
+    #[serde(default)]
+    pub dst_hostname: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Direction { Inbound, Outbound }
 
-/// Process creation events for detecting anomalous chains.
+/// Process creation and exit events for detecting anomalous chains.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProcessEvent {
     pub ts: DateTime<Utc>,
     pub sensor_guid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    pub event_type: ProcessEventType,
     pub pid: u32,
     pub ppid: u32,
     pub image_path: String,
     pub cmdline: String,
+    pub creator_pid: u32,
+    pub creator_tid: u32,
+    pub exit_code: i32,
+    pub parent_image_path: String,
+    pub parent_cmdline: String,
+    /// `TRUNCATED_*` bits of `shared::constants`.
+    #[serde(default)]
+    pub truncated: u32,
+    /// Token of a creation; empty when the driver could not read it.
+    #[serde(default)]
+    pub user_sid: String,
+    #[serde(default)]
+    pub session_id: u32,
+    #[serde(default)]
+    pub elevated: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ProcessEventType { Create, Exit }
+
 /// Output of embedded scanner (e.g. YARA), triggers alerts.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ScanResult {
     pub ts: DateTime<Utc>,
     pub sensor_guid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
     pub rule_id: String,
     pub file_path: String,
     pub matches: Vec<String>,
     pub severity: Severity,
+    pub size: u64,
+    pub hash: u64,
+    pub mtime: u64,
+    pub risk_group: String,
+    pub sha256: Vec<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -97,6 +147,8 @@ pub enum Severity { Low, Medium, High, Critical }
 pub struct EtwEvent {
     pub ts: DateTime<Utc>,
     pub sensor_guid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
     pub provider_guid: String,
     pub event_id: u32,
     pub level: u32,
@@ -105,21 +157,53 @@ pub struct EtwEvent {
     pub json_payload: String,
 }
 
+/// Image mapped into a process, or a driver loaded into the kernel.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageLoadEvent {
+    pub ts: DateTime<Utc>,
+    pub sensor_guid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    pub pid: u32,
+    pub image_base: u64,
+    pub image_size: u64,
+    pub full_image_name: String,
+    pub is_kernel_module: bool,
+}
+
+/// Handle to a protected process opened or duplicated with memory access.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ObjectOpEvent {
+    pub ts: DateTime<Utc>,
+    pub sensor_guid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    pub source_pid: u32,
+    pub target_pid: u32,
+    pub desired_access: u32,
+    pub operation: ObjectOperation,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ObjectOperation { HandleCreate, HandleDuplicate }
+
+/// Envelope fields shared by every `BaseEvent`.
+fn envelope(ts: DateTime<Utc>, sensor_guid: String, seq: Option<u64>) -> ProtoEvent {
+    ProtoEvent {
+        // Convert chrono timestamp to prost Timestamp
+        ts: Some(prost_types::Timestamp { seconds: ts.timestamp(), nanos: ts.timestamp_subsec_nanos() as i32 }),
+        sensor_guid,
+        seq: seq.unwrap_or(0),
+        payload: None,
+        forward_seq: 0,
+    }
+}
+
 /// Convert internal `Event` to Protobuf `ProtoEvent` for gRPC/IPC.
-/// Only `File` variant shown; other variants require similar mapping.
 impl From<Event> for ProtoEvent {
     fn from(evt: Event) -> ProtoEvent {
         match evt {
             Event::File(fe) => {
-                let mut base = ProtoEvent::default();
-                // Convert chrono timestamp to prost Timestamp
-                let ts_proto = prost_types::Timestamp {
-                    seconds: fe.ts.timestamp(),
-                    nanos: fe.ts.timestamp_subsec_nanos() as i32,
-                };
-                base.ts = Some(ts_proto);
-                base.sensor_guid = fe.sensor_guid.clone();
-
                 // Map Rust enum into Prost enum values
                 let op_enum = match fe.op {
                     FileOperation::Create => file_event::Operation::Create,
@@ -127,7 +211,7 @@ impl From<Event> for ProtoEvent {
                     FileOperation::Delete => file_event::Operation::Delete,
                     FileOperation::Rename => file_event::Operation::Rename,
                 } as i32;
-
+                let mut base = envelope(fe.ts, fe.sensor_guid, fe.seq);
                 base.payload = Some(Payload::FileEvent(ProtoFileEvent {
                     op: op_enum,
                     path: fe.path,
@@ -137,10 +221,114 @@ impl From<Event> for ProtoEvent {
                     size: fe.size,
                     sha256: fe.sha256,
                     success: fe.success,
+                    raw_path: fe.raw_path.unwrap_or_default(),
+                    raw_new_path: fe.raw_new_path.unwrap_or_default(),
+                }));
+                base
+            }
+            Event::Network(ne) => {
+                let direction = match ne.direction {
+                    Direction::Inbound => network_event::Direction::Inbound,
+                    Direction::Outbound => network_event::Direction::Outbound,
+                } as i32;
+                let mut base = envelope(ne.ts, ne.sensor_guid, ne.seq);
+                base.payload = Some(Payload::NetworkEvent(ProtoNetworkEvent {
+                    direction,
+                    proto: ne.proto,
+                    src_ip: ne.src_ip,
+                    src_port: ne.src_port,
+                    dst_ip: ne.dst_ip,
+                    dst_port: ne.dst_port,
+                    pid: ne.pid,
+                    exe_path: ne.exe_path,
+                    bytes: ne.bytes,
+                    blocked: ne.blocked,
+                    dst_hostname: ne.dst_hostname,
+                }));
+                base
+            }
+            Event::Process(pe) => {
+                let event_type = match pe.event_type {
+                    ProcessEventType::Create => process_event::EventType::Create,
+                    ProcessEventType::Exit => process_event::EventType::Exit,
+                } as i32;
+                let mut base = envelope(pe.ts, pe.sensor_guid, pe.seq);
+                base.payload = Some(Payload::ProcessEvent(ProtoProcessEvent {
+                    pid: pe.pid,
+                    ppid: pe.ppid,
+                    image_path: pe.image_path,
+                    cmdline: pe.cmdline,
+                    creator_pid: pe.creator_pid,
+                    creator_tid: pe.creator_tid,
+                    event_type,
+                    exit_code: pe.exit_code,
+                    parent_image_path: pe.parent_image_path,
+                    parent_cmdline: pe.parent_cmdline,
+                    truncated: pe.truncated,
+                    user_sid: pe.user_sid,
+                    session_id: pe.session_id,
+                    elevated: pe.elevated,
+                }));
+                base
+            }
+            Event::Scan(sr) => {
+                let severity = match sr.severity {
+                    Severity::Low => scan_result::Severity::Low,
+                    Severity::Medium => scan_result::Severity::Medium,
+                    Severity::High => scan_result::Severity::High,
+                    Severity::Critical => scan_result::Severity::Critical,
+                } as i32;
+                let mut base = envelope(sr.ts, sr.sensor_guid, sr.seq);
+                base.payload = Some(Payload::ScanResult(ProtoScanResult {
+                    rule_id: sr.rule_id,
+                    file_path: sr.file_path,
+                    matches: sr.matches,
+                    severity,
+                    size: sr.size,
+                    hash: sr.hash,
+                    mtime: sr.mtime,
+                    risk_group: sr.risk_group,
+                    sha256: sr.sha256,
+                }));
+                base
+            }
+            Event::Etw(ee) => {
+                let mut base = envelope(ee.ts, ee.sensor_guid, ee.seq);
+                base.payload = Some(Payload::EtwEvent(ProtoEtwEvent {
+                    provider_guid: ee.provider_guid,
+                    event_id: ee.event_id,
+                    level: ee.level,
+                    pid: ee.pid,
+                    tid: ee.tid,
+                    json_payload: ee.json_payload,
+                }));
+                base
+            }
+            Event::Image(ie) => {
+                let mut base = envelope(ie.ts, ie.sensor_guid, ie.seq);
+                base.payload = Some(Payload::ImageLoadEvent(ProtoImageLoadEvent {
+                    pid: ie.pid,
+                    image_base: ie.image_base,
+                    image_size: ie.image_size,
+                    full_image_name: ie.full_image_name,
+                    is_kernel_module: ie.is_kernel_module,
+                }));
+                base
+            }
+            Event::ObjectOp(oe) => {
+                let operation = match oe.operation {
+                    ObjectOperation::HandleCreate => object_op_event::Operation::HandleCreate,
+                    ObjectOperation::HandleDuplicate => object_op_event::Operation::HandleDuplicate,
+                } as i32;
+                let mut base = envelope(oe.ts, oe.sensor_guid, oe.seq);
+                base.payload = Some(Payload::ObjectOpEvent(ProtoObjectOpEvent {
+                    source_pid: oe.source_pid,
+                    target_pid: oe.target_pid,
+                    desired_access: oe.desired_access,
+                    operation,
                 }));
                 base
             }
-            _ => unimplemented!("Other Event variants not yet implemented"),
         }
     }
 }
@@ -152,8 +340,10 @@ impl TryFrom<ProtoEvent> for Event {
     fn try_from(pe: ProtoEvent) -> Result<Self, Self::Error> {
         // Ensure timestamp present and valid
         let ts_proto = pe.ts.ok_or_else(|| anyhow!("missing timestamp"))?;
-        let ts = DateTime::from_timestamp(ts_proto.seconds, ts_proto.nanos as u32).expect("invalid timestamp");
-        let sensor = pe.sensor_guid;
+        let ts = DateTime::from_timestamp(ts_proto.seconds, ts_proto.nanos as u32)
+            .ok_or_else(|| anyhow!("invalid timestamp {}.{:09}", ts_proto.seconds, ts_proto.nanos))?;
+        let sensor_guid = pe.sensor_guid;
+        let seq = (pe.seq != 0).then_some(pe.seq);
 
         match pe.payload.ok_or_else(|| anyhow!("no payload"))? {
             Payload::FileEvent(f) => {
@@ -168,7 +358,8 @@ impl TryFrom<ProtoEvent> for Event {
                 };
                 Ok(Event::File(FileEvent {
                     ts,
-                    sensor_guid: sensor,
+                    sensor_guid,
+                    seq,
                     op,
                     path: f.path,
                     new_path: if f.new_path.is_empty() { None } else { Some(f.new_path) },
@@ -177,9 +368,123 @@ impl TryFrom<ProtoEvent> for Event {
                     size: f.size,
                     sha256: f.sha256,
                     success: f.success,
+                    raw_path: if f.raw_path.is_empty() { None } else { Some(f.raw_path) },
+                    raw_new_path: if f.raw_new_path.is_empty() { None } else { Some(f.raw_new_path) },
+                }))
+            }
+            Payload::NetworkEvent(n) => {
+                let direction = match network_event::Direction::try_from(n.direction)
+                    .map_err(|_| anyhow!("invalid network direction {}", n.direction))?
+                {
+                    network_event::Direction::Inbound => Direction::Inbound,
+                    network_event::Direction::Outbound => Direction::Outbound,
+                };
+                Ok(Event::Network(NetworkEvent {
+                    ts,
+                    sensor_guid,
+                    seq,
+                    direction,
+                    proto: n.proto,
+                    src_ip: n.src_ip,
+                    src_port: n.src_port,
+                    dst_ip: n.dst_ip,
+                    dst_port: n.dst_port,
+                    pid: n.pid,
+                    exe_path: n.exe_path,
+                    bytes: n.bytes,
+                    blocked: n.blocked,
+                    dst_hostname: n.dst_hostname,
+                }))
+            }
+            Payload::ProcessEvent(p) => {
+                let event_type = match process_event::EventType::try_from(p.event_type)
+                    .map_err(|_| anyhow!("invalid process event type {}", p.event_type))?
+                {
+                    process_event::EventType::Create => ProcessEventType::Create,
+                    process_event::EventType::Exit => ProcessEventType::Exit,
+                };
+                Ok(Event::Process(ProcessEvent {
+                    ts,
+                    sensor_guid,
+                    seq,
+                    event_type,
+                    pid: p.pid,
+                    ppid: p.ppid,
+                    image_path: p.image_path,
+                    cmdline: p.cmdline,
+                    creator_pid: p.creator_pid,
+                    creator_tid: p.creator_tid,
+                    exit_code: p.exit_code,
+                    parent_image_path: p.parent_image_path,
+                    parent_cmdline: p.parent_cmdline,
+                    truncated: p.truncated,
+                    user_sid: p.user_sid,
+                    session_id: p.session_id,
+                    elevated: p.elevated,
+                }))
+            }
+            Payload::ScanResult(s) => {
+                let severity = match scan_result::Severity::try_from(s.severity)
+                    .map_err(|_| anyhow!("invalid scan severity {}", s.severity))?
+                {
+                    scan_result::Severity::Low => Severity::Low,
+                    scan_result::Severity::Medium => Severity::Medium,
+                    scan_result::Severity::High => Severity::High,
+                    scan_result::Severity::Critical => Severity::Critical,
+                };
+                Ok(Event::Scan(ScanResult {
+                    ts,
+                    sensor_guid,
+                    seq,
+                    rule_id: s.rule_id,
+                    file_path: s.file_path,
+                    matches: s.matches,
+                    severity,
+                    size: s.size,
+                    hash: s.hash,
+                    mtime: s.mtime,
+                    risk_group: s.risk_group,
+                    sha256: s.sha256,
+                }))
+            }
+            Payload::EtwEvent(e) => Ok(Event::Etw(EtwEvent {
+                ts,
+                sensor_guid,
+                seq,
+                provider_guid: e.provider_guid,
+                event_id: e.event_id,
+                level: e.level,
+                pid: e.pid,
+                tid: e.tid,
+                json_payload: e.json_payload,
+            })),
+            Payload::ImageLoadEvent(i) => Ok(Event::Image(ImageLoadEvent {
+                ts,
+                sensor_guid,
+                seq,
+                pid: i.pid,
+                image_base: i.image_base,
+                image_size: i.image_size,
+                full_image_name: i.full_image_name,
+                is_kernel_module: i.is_kernel_module,
+            })),
+            Payload::ObjectOpEvent(o) => {
+                let operation = match object_op_event::Operation::try_from(o.operation)
+                    .map_err(|_| anyhow!("invalid object operation {}", o.operation))?
+                {
+                    object_op_event::Operation::HandleCreate => ObjectOperation::HandleCreate,
+                    object_op_event::Operation::HandleDuplicate => ObjectOperation::HandleDuplicate,
+                };
+                Ok(Event::ObjectOp(ObjectOpEvent {
+                    ts,
+                    sensor_guid,
+                    seq,
+                    source_pid: o.source_pid,
+                    target_pid: o.target_pid,
+                    desired_access: o.desired_access,
+                    operation,
                 }))
             }
-            other => Err(anyhow!("unsupported payload type: {:?}", other)),
         }
     }
 }
@@ -0,0 +1,206 @@
+// src/comms/export.rs
+//! Every event the intel buses carry, appended to a file as one JSON object
+//! per line (NDJSON), for jq or Python without going through SQLite.
+//!
+//! Events are converted as on the tap, into a `BaseEvent`, and from there
+//! into the serde model of [`Event`]. Lines are buffered and flushed every
+//! [`FLUSH_EVERY`]. Once the file reaches its rotation size it is renamed to
+//! `<path>.1`, the older ones moving up to `<path>.<keep>`.
+//!
+//! A slow disk does not hold the buses back: events the exporter falls
+//! behind on, or that do not convert, are counted in
+//! `export_events_dropped_total` and left out.
+
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use metrics::counter;
+use shared::events::BaseEvent;
+use tokio::{
+    runtime::Runtime,
+    sync::{broadcast::{self, error::RecvError}, mpsc},
+    task::JoinHandle,
+    time::interval,
+};
+
+use super::{
+    events::Event,
+    tap::{TapPayload, TapSources},
+    WrappedEvent,
+};
+use crate::{config::model::ExportConfig, util::Shutdown};
+
+/// Longest a written line waits in the buffer.
+pub const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+/// Lines queued for the writer before the buses' backlog takes over.
+const QUEUE: usize = 4_096;
+
+/// The file the exporter writes and when it rotates.
+#[derive(Debug, Clone)]
+pub struct ExportTarget {
+    pub path:         PathBuf,
+    pub rotate_bytes: u64,
+    /// Rotated files kept.
+    pub keep:         usize,
+}
+
+impl ExportTarget {
+    /// The target `cfg` names, with a relative path taken from `base`.
+    pub fn new(base: &Path, cfg: &ExportConfig) -> Self {
+        Self { path: base.join(&cfg.path), rotate_bytes: cfg.rotate_mb << 20, keep: cfg.keep }
+    }
+}
+
+/// Appends the events of `sources` to `target` until `shutdown`. Fails if
+/// the file cannot be opened.
+pub fn spawn_exporter(
+    rt: &Runtime,
+    target: ExportTarget,
+    sources: TapSources,
+    shutdown: Shutdown,
+) -> io::Result<JoinHandle<()>> {
+    let file = open(&target.path)?;
+    let (lines, rx) = mpsc::channel::<String>(QUEUE);
+
+    let TapSources { process, file: files, network, etw, scan, image, object } = sources;
+    forward(rt, process, &lines, &shutdown);
+    forward(rt, files, &lines, &shutdown);
+    forward(rt, network, &lines, &shutdown);
+    forward(rt, etw, &lines, &shutdown);
+    forward(rt, scan, &lines, &shutdown);
+    forward(rt, image, &lines, &shutdown);
+    forward(rt, object, &lines, &shutdown);
+
+    log::info!("exporting events to {}", target.path.display());
+    Ok(rt.spawn(write(rx, target, file, shutdown)))
+}
+
+/// Converts the events of one bus into lines.
+fn forward<E: TapPayload>(
+    rt: &Runtime,
+    source: Option<broadcast::Sender<WrappedEvent<E>>>,
+    lines: &mpsc::Sender<String>,
+    shutdown: &Shutdown,
+) {
+    let Some(source) = source else { return };
+    let (mut rx, lines, shutdown) = (source.subscribe(), lines.clone(), shutdown.clone());
+    rt.spawn(async move {
+        loop {
+            let ev = tokio::select! {
+                ev = rx.recv() => ev,
+                _ = shutdown.triggered() => break,
+            };
+            match ev {
+                Ok(ev) => match line(BaseEvent::from(ev)) {
+                    Ok(line) => {
+                        if lines.send(line).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        log::debug!("export: {} event left out: {}", E::KIND, e);
+                        counter!("export_events_dropped_total", "type" => E::KIND).increment(1);
+                    }
+                },
+                Err(RecvError::Lagged(n)) => {
+                    counter!("export_events_dropped_total", "type" => E::KIND).increment(n);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// `ev` as a line of JSON, without the newline.
+pub fn line(ev: BaseEvent) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(&Event::try_from(ev)?)?)
+}
+
+struct Output {
+    file: BufWriter<File>,
+    size: u64,
+}
+
+fn open(path: &Path) -> io::Result<Output> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok(Output { file: BufWriter::new(file), size })
+}
+
+async fn write(mut lines: mpsc::Receiver<String>, target: ExportTarget, mut out: Output, shutdown: Shutdown) {
+    let mut tick = interval(FLUSH_EVERY);
+    loop {
+        tokio::select! {
+            line = lines.recv() => match line {
+                Some(line) => append(&mut out, &target, line),
+                None => break,
+            },
+            _ = tick.tick() => {
+                if let Err(e) = out.file.flush() {
+                    log::warn!("export: cannot write {}: {}", target.path.display(), e);
+                }
+            }
+            _ = shutdown.triggered() => break,
+        }
+    }
+    // What the buses handed over before stopping.
+    while let Ok(line) = lines.try_recv() {
+        append(&mut out, &target, line);
+    }
+    if let Err(e) = out.file.flush() {
+        log::warn!("export: cannot write {}: {}", target.path.display(), e);
+    }
+}
+
+fn append(out: &mut Output, target: &ExportTarget, mut line: String) {
+    line.push('\n');
+    if let Err(e) = out.file.write_all(line.as_bytes()) {
+        log::warn!("export: cannot write {}: {}", target.path.display(), e);
+        counter!("export_events_dropped_total", "type" => "write").increment(1);
+        return;
+    }
+    counter!("export_events_total").increment(1);
+    out.size += line.len() as u64;
+    if out.size < target.rotate_bytes {
+        return;
+    }
+    let rotated = out.file.flush().and_then(|()| rotate(&target.path, target.keep)).and_then(|()| open(&target.path));
+    match rotated {
+        Ok(fresh) => *out = fresh,
+        Err(e) => log::warn!("export: cannot rotate {}: {}", target.path.display(), e),
+    }
+}
+
+/// `<path>.<n>`.
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{n}"));
+    name.into()
+}
+
+/// Moves `path` to `<path>.1`, shifting older files up and removing the one
+/// past `keep`.
+fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+    match fs::remove_file(numbered(path, keep)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    for n in (1..keep).rev() {
+        let from = numbered(path, n);
+        if from.exists() {
+            fs::rename(from, numbered(path, n + 1))?;
+        }
+    }
+    fs::rename(path, numbered(path, 1))
+}
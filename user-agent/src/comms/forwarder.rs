@@ -0,0 +1,438 @@
+// src/comms/forwarder.rs
+//! Selected events streamed to a remote collector over gRPC.
+//!
+//! The payload types in `forwarder.types` are taken off the intel buses,
+//! converted into `BaseEvent`s as on the tap, numbered with a `forward_seq`
+//! and appended to the [`Spool`] before anything is sent. A sender streams
+//! the oldest ones to `EventCollector.StreamEvents`, at most `batch_size`
+//! per call and gzip-compressed, and lets go of them only once the
+//! collector has acknowledged the whole batch. A batch that fails is sent
+//! again, whole, after an exponential backoff (`util::retry`, site
+//! `forwarder`); the collector drops what it already has by `forward_seq`,
+//! so every event is stored there once.
+//!
+//! The spool follows the overflow file: length-prefixed `BaseEvent`s (the
+//! tap's framing) appended to one file up to a size cap, past which new
+//! events are dropped and counted. The last acknowledged `forward_seq` is
+//! kept next to it in `<spool>.acked`, so after a restart the events not
+//! yet delivered are sent first and numbering goes on where it stopped.
+//!
+//! Counted in `forwarder_events_total` (acknowledged),
+//! `forwarder_dropped_total{type}` and `forwarder_spool_bytes`.
+
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use anyhow::Context;
+use metrics::{counter, gauge};
+use shared::events::{event_collector_client::EventCollectorClient, BaseEvent};
+use tokio::{
+    runtime::Runtime,
+    sync::{broadcast::{self, error::RecvError}, mpsc, Notify},
+    task::{self, JoinHandle},
+    time::interval,
+};
+use tonic::{
+    codec::CompressionEncoding,
+    transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity},
+    Status,
+};
+
+use super::{
+    tap::{frame, read_event, TapPayload, TapSources},
+    WrappedEvent,
+};
+use crate::config::model::ForwarderConfig;
+use crate::util::{retry::{retry_async, RetryError, RetryPolicy}, Shutdown};
+
+/// Longest an event waits for its batch to fill.
+pub const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+/// Events queued for the spool before the buses' backlog takes over.
+const QUEUE: usize = 4_096;
+
+/// Acknowledged bytes at the head of the spool worth rewriting it for.
+const COMPACT_AT: u64 = 8 << 20;
+
+/// Where the forwarder sends events and how.
+#[derive(Debug, Clone)]
+pub struct ForwardTarget {
+    pub endpoint:    Endpoint,
+    /// Payload types forwarded ([`TapPayload::KIND`]).
+    pub types:       Vec<String>,
+    pub batch_size:  usize,
+    pub spool_path:  PathBuf,
+    pub spool_bytes: u64,
+    /// Backoff between the attempts at one batch.
+    pub retry:       RetryPolicy,
+    pub flush_every: Duration,
+}
+
+impl ForwardTarget {
+    /// The target `cfg` names, with a relative spool path taken from `base`.
+    /// Fails if the endpoint does not parse or a certificate cannot be read.
+    pub fn new(base: &Path, cfg: &ForwarderConfig) -> anyhow::Result<Self> {
+        let mut endpoint = Endpoint::from_shared(cfg.endpoint.clone())
+            .with_context(|| format!("endpoint {}", cfg.endpoint))?
+            .connect_timeout(Duration::from_secs(10));
+        if cfg.endpoint.starts_with("https://") {
+            let mut tls = ClientTlsConfig::new();
+            tls = match &cfg.ca_cert {
+                Some(ca) => tls.ca_certificate(Certificate::from_pem(read(ca)?)),
+                None => tls.with_native_roots(),
+            };
+            if let (Some(cert), Some(key)) = (&cfg.client_cert, &cfg.client_key) {
+                tls = tls.identity(Identity::from_pem(read(cert)?, read(key)?));
+            }
+            endpoint = endpoint.tls_config(tls).context("TLS")?;
+        }
+        Ok(Self {
+            endpoint,
+            types:       cfg.types.clone(),
+            batch_size:  cfg.batch_size,
+            spool_path:  base.join(&cfg.spool_path),
+            spool_bytes: cfg.spool_mb << 20,
+            retry:       RetryPolicy::new("forwarder", Duration::from_secs(1)).attempt_timeout(Duration::from_secs(60)),
+            flush_every: FLUSH_EVERY,
+        })
+    }
+}
+
+fn read(path: &Path) -> anyhow::Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("cannot read {}", path.display()))
+}
+
+/// Events not yet acknowledged by the collector, oldest first.
+pub struct Spool {
+    path:      PathBuf,
+    /// `<path>.acked`: the last acknowledged `forward_seq`.
+    acked:     PathBuf,
+    max_bytes: u64,
+    state:     Mutex<SpoolState>,
+}
+
+struct SpoolState {
+    file:     File,
+    len:      u64,
+    /// Offset of the first event not acknowledged.
+    start:    u64,
+    /// Events from `start` on.
+    pending:  usize,
+    next_seq: u64,
+}
+
+/// Events read from the spool, acknowledged together.
+#[derive(Debug, Default)]
+pub struct Batch {
+    pub events: Vec<BaseEvent>,
+    /// Offset just past the last one.
+    end:        u64,
+}
+
+impl Spool {
+    /// Opens or creates the spool at `path`. Events a previous run left
+    /// unacknowledged are sent again; a frame cut short by a crash is cut
+    /// off.
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let path = path.into();
+        let acked_path = suffixed(&path, ".acked");
+        let acked = match fs::read(&acked_path) {
+            Ok(bytes) => bytes.try_into().map(u64::from_le_bytes).unwrap_or(0),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+
+        let file = OpenOptions::new().create(true).read(true).append(true).open(&path)?;
+        let (mut start, mut len, mut pending, mut last) = (0, 0, 0, acked);
+        let mut reader = BufReader::new(&file);
+        loop {
+            match read_event(&mut reader) {
+                Ok(Some(ev)) => {
+                    len = reader.stream_position()?;
+                    if ev.forward_seq <= acked {
+                        start = len;
+                    } else {
+                        pending += 1;
+                    }
+                    last = last.max(ev.forward_seq);
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    log::warn!("{} ends with an unreadable frame: {}", path.display(), e);
+                    break;
+                }
+            }
+        }
+        drop(reader);
+        file.set_len(len)?;
+        let spool = Self {
+            path,
+            acked: acked_path,
+            max_bytes,
+            state: Mutex::new(SpoolState { file, len, start, pending, next_seq: last + 1 }),
+        };
+        spool.trim(&mut spool.state.lock().unwrap())?;
+        Ok(spool)
+    }
+
+    /// Events waiting to be acknowledged.
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().pending
+    }
+
+    /// Bytes of those events.
+    pub fn len(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.len - state.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// Numbers `ev` and appends it. Returns `false`, counting it in
+    /// `forwarder_dropped_total`, if it does not fit or cannot be written.
+    pub fn push(&self, mut ev: BaseEvent, kind: &'static str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        ev.forward_seq = state.next_seq;
+        let frame = frame(&ev);
+        if state.len - state.start + frame.len() as u64 > self.max_bytes {
+            counter!("forwarder_dropped_total", "type" => kind).increment(1);
+            return false;
+        }
+        if let Err(e) = state.file.write_all(&frame) {
+            log::warn!("cannot spool to {}: {}", self.path.display(), e);
+            counter!("forwarder_dropped_total", "type" => kind).increment(1);
+            return false;
+        }
+        state.len += frame.len() as u64;
+        state.pending += 1;
+        state.next_seq += 1;
+        gauge!("forwarder_spool_bytes").set((state.len - state.start) as f64);
+        true
+    }
+
+    /// Up to `max` of the oldest events, left in the spool until
+    /// [`ack`](Self::ack)ed.
+    pub fn peek(&self, max: usize) -> io::Result<Batch> {
+        let (start, len) = {
+            let state = self.state.lock().unwrap();
+            (state.start, state.len)
+        };
+        let mut batch = Batch { events: Vec::new(), end: start };
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        reader.seek(SeekFrom::Start(start))?;
+        while batch.events.len() < max && batch.end < len {
+            let Some(ev) = read_event(&mut reader)? else { break };
+            batch.events.push(ev);
+            batch.end = reader.stream_position()?;
+        }
+        Ok(batch)
+    }
+
+    /// Lets go of the events of `batch`, the collector having stored them.
+    pub fn ack(&self, batch: &Batch) -> io::Result<()> {
+        let Some(last) = batch.events.last() else { return Ok(()) };
+        // Written aside and renamed, so a crash leaves the old value or the
+        // new one.
+        let tmp = suffixed(&self.acked, ".tmp");
+        fs::write(&tmp, last.forward_seq.to_le_bytes())?;
+        fs::rename(&tmp, &self.acked)?;
+
+        let mut state = self.state.lock().unwrap();
+        state.start = batch.end;
+        state.pending -= batch.events.len();
+        counter!("forwarder_events_total").increment(batch.events.len() as u64);
+        self.trim(&mut state)
+    }
+
+    /// Drops the acknowledged events from the file: all of it once nothing
+    /// is pending, by rewriting the rest once they take up most of it.
+    fn trim(&self, state: &mut SpoolState) -> io::Result<()> {
+        if state.start == state.len {
+            state.file.set_len(0)?;
+            (state.start, state.len) = (0, 0);
+        } else if state.start >= COMPACT_AT && state.start * 2 >= state.len {
+            let tmp = suffixed(&self.path, ".tmp");
+            let mut rest = File::open(&self.path)?;
+            rest.seek(SeekFrom::Start(state.start))?;
+            let mut out = File::create(&tmp)?;
+            io::copy(&mut rest, &mut out)?;
+            out.sync_all()?;
+            drop((rest, out));
+            fs::rename(&tmp, &self.path)?;
+            state.file = OpenOptions::new().read(true).append(true).open(&self.path)?;
+            (state.start, state.len) = (0, state.len - state.start);
+        }
+        gauge!("forwarder_spool_bytes").set((state.len - state.start) as f64);
+        Ok(())
+    }
+}
+
+/// `<path><suffix>`.
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    name.into()
+}
+
+/// Forwards the events of `sources` named in `target.types` until
+/// `shutdown`. Fails if the spool cannot be opened. The handle finishes once
+/// the events taken off the buses are in the spool.
+pub fn spawn_forwarder(
+    rt: &Runtime,
+    target: ForwardTarget,
+    sources: TapSources,
+    shutdown: Shutdown,
+) -> io::Result<JoinHandle<()>> {
+    let spool = Arc::new(Spool::open(&target.spool_path, target.spool_bytes)?);
+    let (events, rx) = mpsc::channel(QUEUE);
+
+    let TapSources { process, file, network, etw, scan, image, object } = sources;
+    forward(rt, process, &target.types, &events, &shutdown);
+    forward(rt, file, &target.types, &events, &shutdown);
+    forward(rt, network, &target.types, &events, &shutdown);
+    forward(rt, etw, &target.types, &events, &shutdown);
+    forward(rt, scan, &target.types, &events, &shutdown);
+    forward(rt, image, &target.types, &events, &shutdown);
+    forward(rt, object, &target.types, &events, &shutdown);
+
+    let wake = Arc::new(Notify::new());
+    let spooled = rt.spawn(spool_events(rx, spool.clone(), wake.clone(), target.batch_size, shutdown.clone()));
+    log::info!(
+        "forwarding {} to {} ({} events spooled)",
+        target.types.join(", "),
+        target.endpoint.uri(),
+        spool.pending()
+    );
+    let _guard = rt.enter();
+    let client = EventCollectorClient::new(target.endpoint.connect_lazy()).send_compressed(CompressionEncoding::Gzip);
+    Ok(rt.spawn(async move {
+        send(client, spool, &target, wake, shutdown).await;
+        let _ = spooled.await;
+    }))
+}
+
+/// Hands the events of one bus to the spool, if its type is forwarded.
+fn forward<E: TapPayload>(
+    rt: &Runtime,
+    source: Option<broadcast::Sender<WrappedEvent<E>>>,
+    types: &[String],
+    events: &mpsc::Sender<(BaseEvent, &'static str)>,
+    shutdown: &Shutdown,
+) {
+    let Some(source) = source.filter(|_| types.iter().any(|t| t == E::KIND)) else { return };
+    let (mut rx, events, shutdown) = (source.subscribe(), events.clone(), shutdown.clone());
+    rt.spawn(async move {
+        loop {
+            let ev = tokio::select! {
+                ev = rx.recv() => ev,
+                _ = shutdown.triggered() => break,
+            };
+            match ev {
+                Ok(ev) => {
+                    if events.send((BaseEvent::from(ev), E::KIND)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    counter!("forwarder_dropped_total", "type" => E::KIND).increment(n);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Appends the events to `spool`, waking the sender once a batch is full.
+async fn spool_events(
+    mut rx: mpsc::Receiver<(BaseEvent, &'static str)>,
+    spool: Arc<Spool>,
+    wake: Arc<Notify>,
+    batch_size: usize,
+    shutdown: Shutdown,
+) {
+    loop {
+        tokio::select! {
+            ev = rx.recv() => match ev {
+                Some((ev, kind)) => {
+                    if spool.push(ev, kind) && spool.pending() >= batch_size {
+                        wake.notify_one();
+                    }
+                }
+                None => break,
+            },
+            _ = shutdown.triggered() => break,
+        }
+    }
+    // What the buses handed over before stopping.
+    while let Ok((ev, kind)) = rx.try_recv() {
+        spool.push(ev, kind);
+    }
+}
+
+/// Sends the spool in batches until `shutdown`, each until acknowledged.
+async fn send(
+    client: EventCollectorClient<Channel>,
+    spool: Arc<Spool>,
+    target: &ForwardTarget,
+    wake: Arc<Notify>,
+    shutdown: Shutdown,
+) {
+    let retry = target.retry.clone().cancel_on(shutdown.clone());
+    let mut tick = interval(target.flush_every);
+    loop {
+        if spool.pending() < target.batch_size {
+            tokio::select! {
+                _ = tick.tick() => {}
+                _ = wake.notified() => {}
+                _ = shutdown.triggered() => break,
+            }
+        }
+        let peeked = {
+            let (spool, max) = (spool.clone(), target.batch_size);
+            task::spawn_blocking(move || spool.peek(max)).await
+        };
+        let batch = match peeked {
+            Ok(Ok(batch)) if !batch.events.is_empty() => batch,
+            Ok(Ok(_)) | Err(_) => continue,
+            Ok(Err(e)) => {
+                log::warn!("forwarder: cannot read the spool: {}", e);
+                tick.tick().await;
+                continue;
+            }
+        };
+
+        let outcome = retry_async(&retry, || stream(client.clone(), batch.events.clone())).await;
+        match outcome.result {
+            Ok(()) => {
+                if outcome.attempts > 1 {
+                    log::info!("forwarder: {} delivered after {} attempts", target.endpoint.uri(), outcome.attempts);
+                }
+                let spool = spool.clone();
+                match task::spawn_blocking(move || spool.ack(&batch)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => log::warn!("forwarder: cannot update the spool: {}", e),
+                    Err(_) => {}
+                }
+            }
+            Err(RetryError::Cancelled { .. }) => break,
+            Err(e) => log::warn!("forwarder: {} unreachable: {}", target.endpoint.uri(), e),
+        }
+    }
+}
+
+/// One batch as one `StreamEvents` call; `Ok` once all of it is stored.
+async fn stream(mut client: EventCollectorClient<Channel>, events: Vec<BaseEvent>) -> Result<(), Status> {
+    let sent = events.len() as u64;
+    let ack = client.stream_events(tokio_stream::iter(events)).await?.into_inner();
+    if ack.received < sent {
+        return Err(Status::data_loss(format!("collector stored {} of {} events", ack.received, sent)));
+    }
+    Ok(())
+}
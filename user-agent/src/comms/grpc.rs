@@ -1,10 +1,375 @@
 //! gRPC interface between the GUI and the user-agent.
 //!
-//! This module defines the gRPC service used by the GUI to communicate with
-//! the local user-agent. It enables actions like triggering manual scans,
-//! querying alert history, or adjusting runtime configurations.
-//!
-//! Key responsibilities:
-//! - Define protobuf service interface and message types.
-//! - Handle gRPC server initialization and connection lifecycle.
-//! - Dispatch incoming requests to appropriate agent subsystems.
+//! [`ConfigServer`] implements `config.ConfigService`:
+//! - `GetConfig` returns the scanner settings the engines are following.
+//! - `SetConfig` edits `config.toml` (comments are kept), checks the result
+//!   as [`parse`] checks any config, writes it, hands the groups to the
+//!   running scanner through its [`Schedule`] and records the change in the
+//!   ops journal. A refused change leaves the file untouched.
+//! - `DescribeSchema` is [`describe_schema`].
+//! - `GetStatus` returns the newest heartbeat in `agent_status`; NOT_FOUND
+//!   before the first one, or when the server was not given the database.
+//! - `TriggerScan` queues a scan of a group's directories or of explicit
+//!   paths on the [`Schedule`]'s jobs, which the running engine picks up;
+//!   `GetScanStatus` follows it by job id.
+//!
+//! The proto has a single `ScannerConfig`; it stands for the first
+//! `[[scanner]]` group, the most exposed directories in the layouts setup
+//! writes. Its interval and directories can change; scans are always
+//! recursive over [`EXTENSIONS`]. Process, file system, network and ETW
+//! settings are not served and are refused.
+//!
+//! [`StatusServer`] implements `status.StatusService` from the live
+//! [`AgentStats`] rather than the heartbeat rows: `GetStatus` takes a
+//! snapshot, `Watch` sends one right away and then every
+//! `interval_seconds` until the client leaves or the agent stops.
+//!
+//! Both services listen on `communications.grpc_bind`, moved to 127.0.0.1
+//! unless `allow_remote` is set.
+
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    net::{AddrParseError, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    pin::Pin,
+    sync::Mutex,
+    time::Duration,
+};
+use futures::{Stream, StreamExt};
+use rusqlite::{Connection, OpenFlags};
+use tokio::{net::TcpListener, time};
+use tokio_stream::wrappers::IntervalStream;
+use toml_edit::{Array, DocumentMut, Item, Table};
+use tonic::{
+    transport::{server::TcpIncoming, Server},
+    Request, Response, Status,
+};
+use shared::config::{
+    config_service_server::{ConfigService, ConfigServiceServer},
+    get_scan_status_response::State, DescribeSchemaRequest, DescribeSchemaResponse, DriverVersion,
+    GetConfigRequest, GetConfigResponse, GetScanStatusRequest, GetScanStatusResponse, GetStatusRequest,
+    GetStatusResponse, ScannerConfig, SetConfigRequest, SetConfigResponse, TriggerScanRequest,
+    TriggerScanResponse,
+};
+use shared::status::{
+    status_service_server::{StatusService, StatusServiceServer},
+    GetStatusRequest as StatusRequest, StatusSnapshot, WatchRequest,
+};
+
+use crate::comms::schema::describe_schema;
+use crate::config::{
+    canonical::canonicalize,
+    loader::parse,
+    model::{CommunicationsConfig, ConfigError, DatabaseConfig, DirectoryRisk, RiskGroup},
+    provision,
+};
+use crate::db::{
+    agent_status::latest_status,
+    ops_journal::{Actor, Journal},
+};
+use crate::scanner::{
+    jobs::{JobState, ScanTarget},
+    scheduler::EXTENSIONS,
+    Schedule,
+};
+use crate::status::AgentStats;
+use crate::util::Shutdown;
+
+/// `Watch` interval when the request gives none.
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Why a `SetConfig` was not applied.
+enum Refused {
+    /// Told to the client in the response.
+    Invalid(String),
+    Io(io::Error),
+}
+
+pub struct ConfigServer {
+    /// `config.toml` the agent loaded.
+    path:     PathBuf,
+    schedule: Schedule,
+    /// Storage annotations of `DescribeSchema`.
+    database: DatabaseConfig,
+    journal:  Journal,
+    /// Database `GetStatus` reads the heartbeat from.
+    status:   Option<PathBuf>,
+    /// One `SetConfig` at a time, each editing what the last one wrote.
+    writing:  Mutex<()>,
+}
+
+impl ConfigServer {
+    pub fn new(path: PathBuf, schedule: Schedule, database: DatabaseConfig, journal: Journal) -> Self {
+        Self { path, schedule, database, journal, status: None, writing: Mutex::new(()) }
+    }
+
+    /// Serves `GetStatus` from the heartbeats in `db_path`.
+    pub fn with_status(self, db_path: PathBuf) -> Self {
+        Self { status: Some(db_path), ..self }
+    }
+
+    fn apply(&self, update: &ScannerConfig, actor: Actor) -> Result<(), Refused> {
+        let _writing = self.writing.lock().unwrap();
+        let Some(risk) = self.schedule.groups().first().map(|g| g.risk) else {
+            return Err(Refused::Invalid("no [[scanner]] group".into()));
+        };
+        let text = fs::read_to_string(&self.path).map_err(Refused::Io)?;
+        let edited = edit(&text, risk, update).map_err(Refused::Invalid)?;
+        let cfg = parse(&edited).map_err(|e| Refused::Invalid(e.to_string()))?;
+        provision::write(&self.path, &edited).map_err(Refused::Io)?;
+
+        self.schedule.set(cfg.scanner.clone());
+        log::info!("config: scanner group {} set to {:?} by {}", risk.as_str(), self.schedule.interval(risk), actor);
+        if let Some(id) = self.journal.record_config(&canonicalize(&cfg), actor) {
+            log::info!("config change recorded in the ops journal ({})", id);
+        }
+        Ok(())
+    }
+}
+
+/// Extensions as `ScannerConfig::file_extensions` lists them.
+fn extensions() -> String {
+    EXTENSIONS.iter().map(|e| format!(".{e}")).collect::<Vec<_>>().join(",")
+}
+
+fn scanner_config(group: Option<&RiskGroup>) -> ScannerConfig {
+    let interval = group.and_then(|g| g.interval);
+    ScannerConfig {
+        enabled:          interval.is_some(),
+        interval_seconds: interval.map_or(0, |i| u32::try_from(i.as_secs()).unwrap_or(u32::MAX)),
+        recursive:        true,
+        file_extensions:  extensions(),
+        paths:            group.map_or_else(Vec::new, |g| g.directories.iter().map(|d| d.display().to_string()).collect()),
+    }
+}
+
+/// `text` with `update` applied to the `[[scanner]]` table of `risk`: a
+/// disabled group keeps its directories and loses its interval, and empty
+/// `paths` keep the directories.
+fn edit(text: &str, risk: DirectoryRisk, update: &ScannerConfig) -> Result<String, String> {
+    if !update.recursive {
+        return Err("scans are always recursive".into());
+    }
+    let exts: BTreeSet<String> = update
+        .file_extensions
+        .split(',')
+        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect();
+    if !exts.is_empty() && exts != EXTENSIONS.iter().map(|e| e.to_string()).collect() {
+        return Err(format!("file_extensions: only {} are scanned", extensions()));
+    }
+
+    let mut doc: DocumentMut = text.parse().map_err(|e| format!("config.toml: {e}"))?;
+    let table = doc
+        .get_mut("scanner")
+        .and_then(Item::as_array_of_tables_mut)
+        .and_then(|groups| {
+            groups.iter_mut().find(|t| {
+                t.get("risk").and_then(Item::as_str).is_some_and(|r| r.parse::<DirectoryRisk>().is_ok_and(|r| r == risk))
+            })
+        })
+        .ok_or_else(|| format!("no [[scanner]] group {}", risk.as_str()))?;
+    if update.enabled {
+        set(table, "interval", format!("{}s", update.interval_seconds).into());
+    } else {
+        table.remove("interval");
+    }
+    if !update.paths.is_empty() {
+        set(table, "dirs", update.paths.iter().collect::<Array>().into());
+    }
+    Ok(doc.to_string())
+}
+
+/// Sets `key`, keeping the comments around the old value.
+fn set(table: &mut Table, key: &str, mut new: toml_edit::Value) {
+    if let Some(old) = table.get(key).and_then(Item::as_value) {
+        *new.decor_mut() = old.decor().clone();
+    }
+    table.insert(key, Item::Value(new));
+}
+
+fn refused(message: String) -> Response<SetConfigResponse> {
+    Response::new(SetConfigResponse { success: false, message })
+}
+
+#[tonic::async_trait]
+impl ConfigService for ConfigServer {
+    async fn get_config(&self, _request: Request<GetConfigRequest>) -> Result<Response<GetConfigResponse>, Status> {
+        let groups = self.schedule.groups();
+        Ok(Response::new(GetConfigResponse { scanner: Some(scanner_config(groups.first())), ..Default::default() }))
+    }
+
+    async fn set_config(&self, request: Request<SetConfigRequest>) -> Result<Response<SetConfigResponse>, Status> {
+        let actor = Actor::Grpc(request.remote_addr().map_or_else(|| "unknown".into(), |a| a.to_string()));
+        let Some(update) = request.into_inner().config else {
+            return Ok(refused("no config".into()));
+        };
+        if update.process.is_some() || update.fs.is_some() || update.network.is_some() || update.etw.is_some() {
+            return Ok(refused("only scanner settings can be changed".into()));
+        }
+        let Some(scanner) = update.scanner else {
+            return Ok(refused("no scanner settings".into()));
+        };
+        match self.apply(&scanner, actor.clone()) {
+            Ok(()) => Ok(Response::new(SetConfigResponse { success: true, message: "applied".into() })),
+            Err(Refused::Invalid(reason)) => {
+                log::warn!("config: change from {} refused: {}", actor, reason);
+                Ok(refused(reason))
+            }
+            Err(Refused::Io(e)) => Err(Status::internal(format!("{}: {e}", self.path.display()))),
+        }
+    }
+
+    async fn describe_schema(
+        &self,
+        request: Request<DescribeSchemaRequest>,
+    ) -> Result<Response<DescribeSchemaResponse>, Status> {
+        let only = request.into_inner().event_type;
+        describe_schema(&self.database, Some(only.as_str()).filter(|o| !o.is_empty()))
+            .map(Response::new)
+            .map_err(|e| Status::invalid_argument(format!("{e:#}")))
+    }
+
+    async fn get_status(&self, _request: Request<GetStatusRequest>) -> Result<Response<GetStatusResponse>, Status> {
+        let Some(db_path) = self.status.clone() else {
+            return Err(Status::not_found("no heartbeat"));
+        };
+        let latest = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            latest_status(&conn)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e| Status::internal(e.to_string()))?;
+        let status = latest.ok_or_else(|| Status::not_found("no heartbeat yet"))?;
+        Ok(Response::new(GetStatusResponse {
+            timestamp_us:   status.ts,
+            version:        status.version,
+            uptime_seconds: status.uptime_secs,
+            ring_dropped:   status.ring_dropped,
+            processed:      status.processed.into_iter().collect(),
+            db_size_bytes:  status.db_size_bytes,
+            last_error:     status.last_error.unwrap_or_default(),
+            driver:         status.driver.map(|d| DriverVersion {
+                protocol:   d.protocol,
+                version:    d.driver_version(),
+                build_time: d.build_time,
+            }),
+            failed_tasks:   status.failed_tasks,
+        }))
+    }
+
+    async fn trigger_scan(&self, request: Request<TriggerScanRequest>) -> Result<Response<TriggerScanResponse>, Status> {
+        let actor = Actor::Grpc(request.remote_addr().map_or_else(|| "unknown".into(), |a| a.to_string()));
+        let TriggerScanRequest { risk, paths } = request.into_inner();
+        let target = match (risk.is_empty(), paths.is_empty()) {
+            (false, true) => {
+                let risk: DirectoryRisk = risk.parse().map_err(|e: ConfigError| Status::invalid_argument(e.to_string()))?;
+                if self.schedule.group(risk).is_none() {
+                    return Err(Status::invalid_argument(format!("no [[scanner]] group {}", risk.as_str())));
+                }
+                ScanTarget::Group(risk)
+            }
+            (true, false) => ScanTarget::Paths(paths.into_iter().map(PathBuf::from).collect()),
+            _ => return Err(Status::invalid_argument("give either risk or paths")),
+        };
+        let job_id = self.schedule.jobs().trigger(target.clone()).map_err(|e| Status::resource_exhausted(e.to_string()))?;
+        log::info!("scan job {} queued by {}: {:?}", job_id, actor, target);
+        Ok(Response::new(TriggerScanResponse { job_id }))
+    }
+
+    async fn get_scan_status(
+        &self,
+        request: Request<GetScanStatusRequest>,
+    ) -> Result<Response<GetScanStatusResponse>, Status> {
+        let job_id = request.into_inner().job_id;
+        let job = self.schedule.jobs().status(job_id).ok_or_else(|| Status::not_found(format!("no scan job {job_id}")))?;
+        let state = match job.state {
+            JobState::Queued => State::Queued,
+            JobState::Running => State::Running,
+            JobState::Done => State::Done,
+            JobState::Failed => State::Failed,
+        };
+        Ok(Response::new(GetScanStatusResponse {
+            state:        state.into(),
+            files:        job.files,
+            bytes_hashed: job.bytes,
+            error:        job.error.unwrap_or_default(),
+        }))
+    }
+}
+
+pub struct StatusServer {
+    stats:    AgentStats,
+    /// Database whose size snapshots report.
+    db_path:  Option<PathBuf>,
+    /// Ends the `Watch` streams, which would otherwise hold the server up.
+    shutdown: Shutdown,
+}
+
+impl StatusServer {
+    pub fn new(stats: AgentStats) -> Self {
+        Self { stats, db_path: None, shutdown: Shutdown::new() }
+    }
+
+    /// Reports the size of the database at `db_path`.
+    pub fn with_db(self, db_path: PathBuf) -> Self {
+        Self { db_path: Some(db_path), ..self }
+    }
+}
+
+type SnapshotStream = Pin<Box<dyn Stream<Item = Result<StatusSnapshot, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl StatusService for StatusServer {
+    async fn get_status(&self, _request: Request<StatusRequest>) -> Result<Response<StatusSnapshot>, Status> {
+        Ok(Response::new(self.stats.snapshot(self.db_path.as_deref())))
+    }
+
+    type WatchStream = SnapshotStream;
+
+    async fn watch(&self, request: Request<WatchRequest>) -> Result<Response<Self::WatchStream>, Status> {
+        let period = match request.into_inner().interval_seconds {
+            0 => DEFAULT_WATCH_INTERVAL,
+            secs => Duration::from_secs(secs.into()),
+        };
+        let mut ticker = time::interval(period);
+        ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        let (stats, db_path, shutdown) = (self.stats.clone(), self.db_path.clone(), self.shutdown.clone());
+        let snapshots = IntervalStream::new(ticker)
+            .map(move |_| stats.snapshot(db_path.as_deref()))
+            .take_until(async move { shutdown.triggered().await })
+            .map(Ok);
+        Ok(Response::new(Box::pin(snapshots)))
+    }
+}
+
+/// Where the service listens: `grpc_bind`, or 127.0.0.1 on its port when
+/// that is not a loopback address and `allow_remote` is off.
+pub fn listen_addr(cfg: &CommunicationsConfig) -> Result<SocketAddr, AddrParseError> {
+    let addr: SocketAddr = cfg.grpc_bind.parse()?;
+    if addr.ip().is_loopback() || cfg.allow_remote {
+        return Ok(addr);
+    }
+    log::warn!("grpc_bind {} is not local and allow_remote is off; listening on 127.0.0.1", addr);
+    Ok(SocketAddr::from((Ipv4Addr::LOCALHOST, addr.port())))
+}
+
+/// Serves `config` and `status` on `listener` until `shutdown`.
+pub async fn serve(
+    listener: TcpListener,
+    config: ConfigServer,
+    status: StatusServer,
+    shutdown: Shutdown,
+) -> anyhow::Result<()> {
+    let incoming = TcpIncoming::from_listener(listener, true, None).map_err(|e| anyhow::anyhow!(e))?;
+    let status = StatusServer { shutdown: shutdown.clone(), ..status };
+    Server::builder()
+        .add_service(ConfigServiceServer::new(config))
+        .add_service(StatusServiceServer::new(status))
+        .serve_with_incoming_shutdown(incoming, async move { shutdown.triggered().await })
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,211 @@
+// src/comms/intel_bus.rs
+//! Intel buses that remember their recent events.
+//!
+//! A `broadcast` receiver only sees what is sent after it subscribes, and
+//! one that falls behind gets `RecvError::Lagged`. A [`TokioBuses`] task sits
+//! between the producers' sender and the intel consumers: it keeps the last
+//! events of the bus in a [`RecentBuffer`] and forwards them, so a consumer
+//! that attaches late (the detection engine after a restart) starts from
+//! [`TokioBuses::subscribe_with_replay`]. Lag is handled once, in
+//! [`Subscription::recv`]: logged, counted in
+//! `intel_lagged_events_total{bus}` and skipped.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use metrics::counter;
+use tokio::{
+    runtime::Runtime,
+    sync::broadcast::{self, error::RecvError},
+};
+
+use super::WrappedEvent;
+use crate::config::model::CommunicationsConfig;
+
+/// The last events of a bus: at most `max_events`, none older than
+/// `max_age`. Callers pass the current time.
+pub struct RecentBuffer<E: Clone> {
+    events:     VecDeque<(Instant, WrappedEvent<E>)>,
+    max_events: usize,
+    max_age:    Duration,
+}
+
+impl<E: Clone> RecentBuffer<E> {
+    pub fn new(max_events: usize, max_age: Duration) -> Self {
+        Self { events: VecDeque::new(), max_events, max_age }
+    }
+
+    pub fn push(&mut self, ev: WrappedEvent<E>, now: Instant) {
+        if self.max_events == 0 {
+            return;
+        }
+        while self.events.len() >= self.max_events {
+            self.events.pop_front();
+        }
+        self.events.push_back((now, ev));
+        self.expire(now);
+    }
+
+    /// Forgets the events older than `max_age` at `now`.
+    pub fn expire(&mut self, now: Instant) {
+        while self.events.front().is_some_and(|(at, _)| now.saturating_duration_since(*at) > self.max_age) {
+            self.events.pop_front();
+        }
+    }
+
+    /// The events kept at `now`, oldest first.
+    pub fn events(&mut self, now: Instant) -> VecDeque<WrappedEvent<E>> {
+        self.expire(now);
+        self.events.iter().map(|(_, ev)| ev.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+struct Shared<E: Clone> {
+    recent: RecentBuffer<E>,
+    /// `None` once the producers are gone, which closes the bus.
+    tx:     Option<broadcast::Sender<WrappedEvent<E>>>,
+}
+
+/// One intel bus with the buffer of its recent events. Clones share them.
+pub struct TokioBuses<E: Clone> {
+    name:   &'static str,
+    shared: Arc<Mutex<Shared<E>>>,
+}
+
+impl<E: Clone> Clone for TokioBuses<E> {
+    fn clone(&self) -> Self {
+        Self { name: self.name, shared: Arc::clone(&self.shared) }
+    }
+}
+
+impl<E: Clone + Send + 'static> TokioBuses<E> {
+    /// Follows `input`, the sender the producers of bus `name` publish on,
+    /// keeping `communications.intel_replay_events` events for at most
+    /// `communications.intel_replay_max_age`. Subscribers get up to
+    /// `capacity` events behind before they lag. The bus closes with
+    /// `input`.
+    pub fn spawn(
+        rt: &Runtime,
+        name: &'static str,
+        input: &broadcast::Sender<WrappedEvent<E>>,
+        capacity: usize,
+        cfg: &CommunicationsConfig,
+    ) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        let bus = Self {
+            name,
+            shared: Arc::new(Mutex::new(Shared {
+                recent: RecentBuffer::new(cfg.intel_replay_events, cfg.intel_replay_max_age),
+                tx:     Some(tx),
+            })),
+        };
+        // Subscribed before returning, so nothing sent from here on is missed.
+        let mut rx = Subscription::live(name, input.subscribe());
+        let shared = Arc::clone(&bus.shared);
+        rt.spawn(async move {
+            while let Some(ev) = rx.recv().await {
+                let mut shared = shared.lock().unwrap();
+                // Under the lock: a subscriber sees an event either in its
+                // replay or live, never both or neither.
+                shared.recent.push(ev.clone(), Instant::now());
+                if let Some(tx) = &shared.tx {
+                    let _ = tx.send(ev);
+                }
+            }
+            shared.lock().unwrap().tx = None;
+        });
+        bus
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The events published from now on.
+    pub fn subscribe(&self) -> Subscription<E> {
+        let shared = self.shared.lock().unwrap();
+        Subscription::live(self.name, receiver(shared.tx.as_ref()))
+    }
+
+    /// The events still in the buffer, then the ones published from now on.
+    pub fn subscribe_with_replay(&self) -> Subscription<E> {
+        let mut shared = self.shared.lock().unwrap();
+        let replay = shared.recent.events(Instant::now());
+        let mut sub = Subscription::live(self.name, receiver(shared.tx.as_ref()));
+        sub.replayed = replay.iter().filter_map(|ev| ev.seq).collect();
+        sub.replay = replay;
+        sub
+    }
+
+    /// Events the buffer holds.
+    pub fn recent_len(&self) -> usize {
+        self.shared.lock().unwrap().recent.len()
+    }
+}
+
+/// A receiver of `tx`, or one of a bus already closed.
+fn receiver<E: Clone>(tx: Option<&broadcast::Sender<E>>) -> broadcast::Receiver<E> {
+    match tx {
+        Some(tx) => tx.subscribe(),
+        None => broadcast::channel(1).1,
+    }
+}
+
+/// What a consumer reads from a bus: the replayed events first, then the
+/// live ones.
+pub struct Subscription<E: Clone> {
+    bus:      &'static str,
+    replay:   VecDeque<WrappedEvent<E>>,
+    /// `seq` of the replayed events. Live events with one of them are
+    /// dropped until the first that has none, so an event sent again by its
+    /// producer is not seen twice; a `seq` that restarted (driver reloaded)
+    /// only passes.
+    replayed: HashSet<u64>,
+    rx:       broadcast::Receiver<WrappedEvent<E>>,
+}
+
+impl<E: Clone> Subscription<E> {
+    /// The events `rx` receives, from bus `bus`.
+    pub fn live(bus: &'static str, rx: broadcast::Receiver<WrappedEvent<E>>) -> Self {
+        Self { bus, replay: VecDeque::new(), replayed: HashSet::new(), rx }
+    }
+
+    /// The next event; `None` once the bus is closed. A subscriber that fell
+    /// behind skips the events it lost. Cancel-safe, so it can be raced in
+    /// `tokio::select!`.
+    pub async fn recv(&mut self) -> Option<WrappedEvent<E>> {
+        if let Some(ev) = self.replay.pop_front() {
+            return Some(ev);
+        }
+        loop {
+            match self.rx.recv().await {
+                Ok(ev) if ev.seq.is_some_and(|seq| self.replayed.contains(&seq)) => {}
+                Ok(ev) => {
+                    self.replayed.clear();
+                    return Some(ev);
+                }
+                Err(RecvError::Lagged(n)) => {
+                    log::warn!("intel bus '{}': a subscriber lagged by {} events", self.bus, n);
+                    counter!("intel_lagged_events_total", "bus" => self.bus).increment(n);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl<E: Clone> From<broadcast::Receiver<WrappedEvent<E>>> for Subscription<E> {
+    fn from(rx: broadcast::Receiver<WrappedEvent<E>>) -> Self {
+        Self::live("intel", rx)
+    }
+}
@@ -1,11 +1,276 @@
-//! IOCTL communication interface with the kernel driver.
+// src/comms/ioctl.rs
+//! `DeviceIoControl` access to the driver's control device.
 //!
-//! This module wraps low-level Windows DeviceIoControl operations,
-//! providing a safe Rust interface to send configuration commands
-//! and retrieve data from the kernel-mode EDR driver.
+//! The device is opened as a file at [`DEVICE_PATH`]; every call is
+//! `METHOD_BUFFERED` and replies are decoded from little-endian bytes with
+//! the layouts in `shared`. Off Windows [`Driver::open`] fails with
+//! `ErrorKind::Unsupported`.
 //!
-//! Key responsibilities:
-//! - Open a secure handle to the driver device.
-//! - Send control codes (IOCTLs) and marshal data safely.
-//! - Handle error codes and fallback conditions.
-//! - Abstract over driver protocol versioning (if added).
+//! At startup [`check_driver`] compares the driver's protocol with
+//! [`DRIVER_PROTOCOL_VERSION`]: a different major stops the ring consumer,
+//! a different minor is only warned about.
+
+use std::{fs::File, io};
+use shared::{
+    constants::{
+        protocol_major, protocol_minor, NetRule, VersionInfo, DEVICE_PATH, DRIVER_PROTOCOL_VERSION,
+        IOCTL_GLADIX_GET_RING_STATS, IOCTL_GLADIX_GET_VERSION, IOCTL_GLADIX_PING, IOCTL_GLADIX_SET_NET_POLICY,
+        IOCTL_GLADIX_SET_PROTECTED_PIDS, NET_POLICY_MAX_RULES, PING_REPLY, PROTECTED_PIDS_MAX,
+    },
+    ring::RingStats,
+};
+use thiserror::Error;
+
+use crate::heartbeat::Stats;
+use crate::status::{AgentStats, DriverState};
+
+/// Version reply of protocol 1 drivers, which stops before `build_time`.
+const LEGACY_VERSION_LEN: usize = 12;
+
+/// Open handle to the control device.
+#[derive(Debug)]
+pub struct Driver {
+    file: File,
+}
+
+impl Driver {
+    pub fn open() -> io::Result<Self> {
+        sys::open(DEVICE_PATH).map(|file| Self { file })
+    }
+
+    /// Sends `code` and returns the number of bytes written to `output`.
+    pub fn call(&self, code: u32, input: &[u8], output: &mut [u8]) -> io::Result<usize> {
+        sys::device_io_control(&self.file, code, input, output)
+    }
+
+    /// Checks that the device answers and is ours.
+    pub fn ping(&self) -> io::Result<()> {
+        let mut out = [0u8; 4];
+        let n = self.call(IOCTL_GLADIX_PING, &[], &mut out)?;
+        if n != out.len() || u32::from_le_bytes(out) != PING_REPLY {
+            return Err(invalid("unexpected ping reply"));
+        }
+        Ok(())
+    }
+
+    /// Replies of older drivers decode with the fields they lack zeroed, so
+    /// their protocol can still be compared.
+    pub fn version(&self) -> io::Result<VersionInfo> {
+        let mut out = [0u8; VersionInfo::SIZE];
+        let n = self.call(IOCTL_GLADIX_GET_VERSION, &[], &mut out)?;
+        if n < LEGACY_VERSION_LEN {
+            return Err(invalid("short version reply"));
+        }
+        VersionInfo::from_bytes(&out).ok_or_else(|| invalid("short version reply"))
+    }
+
+    /// Fails with the driver's `STATUS_DEVICE_NOT_READY` (raw OS error 21)
+    /// while the driver has no ring.
+    pub fn ring_stats(&self) -> io::Result<RingStats> {
+        let mut out = [0u8; size_of::<RingStats>()];
+        let n = self.call(IOCTL_GLADIX_GET_RING_STATS, &[], &mut out)?;
+        RingStats::from_bytes(&out[..n]).ok_or_else(|| invalid("short ring stats reply"))
+    }
+
+    /// Replaces the driver's network policy with `rules`.
+    pub fn set_net_policy(&self, rules: &[NetRule]) -> io::Result<()> {
+        if rules.len() > NET_POLICY_MAX_RULES {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many network policy rules"));
+        }
+        let input: Vec<u8> = rules.iter().flat_map(NetRule::to_bytes).collect();
+        self.call(IOCTL_GLADIX_SET_NET_POLICY, &input, &mut [])?;
+        Ok(())
+    }
+
+    /// Replaces the processes whose memory access the driver reports.
+    pub fn set_protected_pids(&self, pids: &[u32]) -> io::Result<()> {
+        if pids.len() > PROTECTED_PIDS_MAX {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many protected processes"));
+        }
+        let input: Vec<u8> = pids.iter().flat_map(|p| p.to_le_bytes()).collect();
+        self.call(IOCTL_GLADIX_SET_PROTECTED_PIDS, &input, &mut [])?;
+        Ok(())
+    }
+}
+
+/// How a driver's protocol relates to the agent's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compatibility {
+    Same,
+    /// One side knows codes or fields the other does not use.
+    MinorDiffers,
+    /// Codes, replies or ring frames are not read the same way.
+    MajorDiffers,
+}
+
+/// Compares two protocols encoded as [`shared::constants::protocol_version`].
+pub fn compatibility(agent: u32, driver: u32) -> Compatibility {
+    if protocol_major(agent) != protocol_major(driver) {
+        Compatibility::MajorDiffers
+    } else if agent != driver {
+        Compatibility::MinorDiffers
+    } else {
+        Compatibility::Same
+    }
+}
+
+/// A driver whose protocol major differs from the agent's.
+#[derive(Debug, Error)]
+#[error(
+    "driver {} speaks protocol {}, agent {} speaks {}.{}; install the driver built with this agent",
+    .driver.driver_version(),
+    .driver.protocol_version(),
+    env!("CARGO_PKG_VERSION"),
+    protocol_major(DRIVER_PROTOCOL_VERSION),
+    protocol_minor(DRIVER_PROTOCOL_VERSION)
+)]
+pub struct IncompatibleDriver {
+    pub driver: VersionInfo,
+}
+
+/// Fails when `driver` speaks another protocol major; warns when only the
+/// minor differs.
+pub fn ensure_compatible(driver: &VersionInfo) -> Result<Compatibility, IncompatibleDriver> {
+    let compat = compatibility(DRIVER_PROTOCOL_VERSION, driver.protocol);
+    match compat {
+        Compatibility::Same => {}
+        Compatibility::MinorDiffers => log::warn!(
+            "driver {} speaks protocol {}, agent expects {}.{}; continuing",
+            driver.driver_version(),
+            driver.protocol_version(),
+            protocol_major(DRIVER_PROTOCOL_VERSION),
+            protocol_minor(DRIVER_PROTOCOL_VERSION),
+        ),
+        Compatibility::MajorDiffers => return Err(IncompatibleDriver { driver: *driver }),
+    }
+    Ok(compat)
+}
+
+/// What the driver answered at startup.
+#[derive(Debug, Clone, Copy)]
+pub struct DriverInfo {
+    pub version: VersionInfo,
+    /// `None` while the driver has no ring.
+    pub ring:    Option<RingStats>,
+}
+
+/// Pings the driver, logs which version it runs, hands it to the heartbeat
+/// and to [`AgentStats`] and checks its protocol with [`ensure_compatible`]. `Ok(None)` when it
+/// does not answer: the ring consumer reports a missing driver on its own.
+pub fn check_driver() -> Result<Option<DriverInfo>, IncompatibleDriver> {
+    let driver = Driver::open().and_then(|d| {
+        d.ping()?;
+        Ok((d.version()?, d.ring_stats()))
+    });
+    let (version, ring) = match driver {
+        Ok(answered) => answered,
+        Err(e) => {
+            log::warn!("driver control device {} unavailable: {}", DEVICE_PATH, e);
+            AgentStats::global().set_driver(DriverState::Unavailable, None);
+            return Ok(None);
+        }
+    };
+    log::info!(
+        "driver {} (protocol {}, built {})",
+        version.driver_version(),
+        version.protocol_version(),
+        version.build_time
+    );
+    Stats::global().set_driver(version);
+    if let Err(e) = ensure_compatible(&version) {
+        AgentStats::global().set_driver(DriverState::Incompatible, Some(version));
+        return Err(e);
+    }
+    AgentStats::global().set_driver(DriverState::Connected, Some(version));
+    let ring = match ring {
+        Ok(stats) => {
+            log::debug!("driver ring: {:?}", stats);
+            Some(stats)
+        }
+        Err(e) => {
+            log::debug!("driver ring stats unavailable: {}", e);
+            None
+        }
+    };
+    Ok(Some(DriverInfo { version, ring }))
+}
+
+/// Asks the driver to report memory access to the agent's own process.
+/// Never fails: without the driver there is nothing to report it.
+pub fn protect_agent() {
+    let pid = std::process::id();
+    match Driver::open().and_then(|d| d.set_protected_pids(&[pid])) {
+        Ok(()) => log::info!("driver reports memory access to the agent (pid {})", pid),
+        Err(e) => log::warn!("agent process not protected by the driver: {}", e),
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use std::{fs::File, io};
+
+    pub fn open(_path: &str) -> io::Result<File> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "the driver is only available on Windows"))
+    }
+
+    pub fn device_io_control(_file: &File, _code: u32, _input: &[u8], _output: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{
+        ffi::c_void,
+        fs::{File, OpenOptions},
+        io,
+        os::windows::io::AsRawHandle,
+        ptr,
+    };
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn DeviceIoControl(
+            device: *mut c_void,
+            code: u32,
+            input: *const c_void,
+            input_len: u32,
+            output: *mut c_void,
+            output_len: u32,
+            returned: *mut u32,
+            overlapped: *mut c_void,
+        ) -> i32;
+    }
+
+    pub fn open(path: &str) -> io::Result<File> {
+        OpenOptions::new().read(true).write(true).open(path)
+    }
+
+    pub fn device_io_control(file: &File, code: u32, input: &[u8], output: &mut [u8]) -> io::Result<usize> {
+        let input_ptr = if input.is_empty() { ptr::null() } else { input.as_ptr().cast() };
+        let output_ptr = if output.is_empty() { ptr::null_mut() } else { output.as_mut_ptr().cast() };
+        let mut returned = 0u32;
+        // SAFETY: both buffers outlive the synchronous call and their
+        // lengths are passed alongside.
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle(),
+                code,
+                input_ptr,
+                input.len() as u32,
+                output_ptr,
+                output.len() as u32,
+                &mut returned,
+                ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(returned as usize)
+    }
+}
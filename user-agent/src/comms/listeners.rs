@@ -1,25 +1,36 @@
 // src/comms/listeners.rs
 
-use std::{marker::PhantomData, sync::Arc, time::SystemTime};
+use std::{marker::PhantomData, sync::Arc, time::{Duration, Instant, SystemTime}};
 use async_trait::async_trait;
+use metrics::{counter, gauge, histogram};
 use prost::Message;
-use tokio::{task, sync::{broadcast, mpsc}};
+use shared::{
+    constants::{TRUNCATED_CMDLINE, TRUNCATED_IMAGE_PATH, TRUNCATED_PARENT_IMAGE_PATH},
+    events::ProcessEvent,
+};
+use tokio::{task::{self, JoinHandle}, sync::{broadcast, mpsc}, time::{self, MissedTickBehavior}};
 
-use super::{WrappedEvent, memory_ring::MemoryRing};
+use super::{WrappedEvent, memory_ring::{DropMonitor, GapMonitor, LagMonitor, MemoryRing, Popped, RingSource}, rate_limit::{BypassKey, RateLimiter}};
+use crate::config::model::{LimitsConfig, PayloadLimit, RingConfig};
+use crate::db::hub::{AnyEvent, DbSender};
+use crate::intel::enrich::Enricher;
+use crate::heartbeat::Stats;
+use crate::status::AgentStats;
+use crate::util::{spawn_supervised, Shutdown, Supervisor};
 
 /// Canales para enviar WrappedEvent<E> a base de datos e inteligencia.
 /// E: Clone + Send + 'static asegura que WrappedEvent<E> sea Clone + Send + 'static.
 #[derive(Clone)]
 pub struct Buses<E: Clone + Send + 'static> {
-    pub db_tx:    mpsc::Sender<WrappedEvent<E>>,
+    pub db_tx:    DbSender<E>,
     pub intel_tx: broadcast::Sender<WrappedEvent<E>>,
 }
 
 impl<E: Clone + Send + 'static> Buses<E> {
     pub fn new(db_capacity: usize, intel_capacity: usize) -> Self {
-        let (db_tx, _)    = mpsc::channel(db_capacity);
+        let (db_tx, _)    = mpsc::channel::<WrappedEvent<E>>(db_capacity);
         let (intel_tx, _) = broadcast::channel(intel_capacity);
-        Self { db_tx, intel_tx }
+        Self { db_tx: db_tx.into(), intel_tx }
     }
 }
 
@@ -33,71 +44,184 @@ pub trait Listener<E: Clone + Send + 'static>: Send + Sync + 'static {
     /// Capacidad del canal interno “raw”.
     fn capacity(&self) -> usize { 16_384 }
 
-    /// Lee del ring, decodifica E y envuelve en WrappedEvent<E>.
-    async fn ingest(self: Arc<Self>, tx: mpsc::Sender<WrappedEvent<E>>);
+    /// Lee del ring, decodifica E y envuelve en WrappedEvent<E>; termina al
+    /// activarse `shutdown`.
+    async fn ingest(self: Arc<Self>, tx: mpsc::Sender<WrappedEvent<E>>, shutdown: Shutdown);
 
     /// Filtro/triage opcional (por defecto pasa todo).
     fn triage(&self, ev: WrappedEvent<E>) -> Option<WrappedEvent<E>> {
         Some(ev)
     }
 
-    /// Helper que lanza ingest + triage → broadcast + db.
-    fn spawn(self: Arc<Self>, buses: Buses<E>) {
+    /// Helper que lanza ingest + triage → broadcast + db. Devuelve ambas
+    /// tareas; triage acaba tras reenviar lo que ingest dejó en el canal.
+    /// Las dos corren bajo un [`Supervisor`]: si una hace panic se vuelve a
+    /// lanzar, con el mismo canal, como `<name>_ingest` o `<name>_triage`.
+    fn spawn(self: Arc<Self>, buses: Buses<E>, shutdown: &Shutdown) -> [JoinHandle<()>; 2]
+    where
+        WrappedEvent<E>: Into<AnyEvent>,
+    {
         let name = self.name();
         let cap  = self.capacity();
-        let (raw_tx, mut raw_rx) = mpsc::channel::<WrappedEvent<E>>(cap);
+        let (raw_tx, raw_rx) = mpsc::channel::<WrappedEvent<E>>(cap);
+        // Compartido para que un triage relanzado siga leyendo el mismo canal.
+        let raw_rx = Arc::new(tokio::sync::Mutex::new(raw_rx));
         let ingest_self = self.clone();
         let triage_self = self;
         let Buses { db_tx, intel_tx } = buses;
+        let shutdown = shutdown.clone();
 
         // Tarea de ingest
-        task::spawn(async move {
-            log::info!("listener '{}' ingest started", name);
-            ingest_self.ingest(raw_tx).await;
-            log::info!("listener '{}' ingest ended", name);
-        });
+        let ingest = task::spawn(Supervisor::new(format!("{name}_ingest")).cancel_on(shutdown.clone()).run(move || {
+            let (ingest_self, raw_tx, shutdown) = (ingest_self.clone(), raw_tx.clone(), shutdown.clone());
+            async move {
+                log::info!("listener '{}' ingest started", name);
+                ingest_self.ingest(raw_tx, shutdown).await;
+                log::info!("listener '{}' ingest ended", name);
+            }
+        }));
 
-        // Tarea de triage + forward
-        task::spawn(async move {
-            log::info!("listener '{}' triage started", name);
-            while let Some(ev) = raw_rx.recv().await {
-                if let Some(ev2) = triage_self.triage(ev) {
-                    // clonamos para intel; el original va a BD
-                    let _ = intel_tx.send(ev2.clone());
-                    let _ = db_tx.send(ev2).await;
+        // Tarea de triage + forward; no se cancela con `shutdown`: acaba al
+        // cerrarse el canal.
+        let triage = spawn_supervised(format!("{name}_triage"), move || {
+            let (triage_self, raw_rx) = (triage_self.clone(), raw_rx.clone());
+            let (db_tx, intel_tx) = (db_tx.clone(), intel_tx.clone());
+            async move {
+                log::info!("listener '{}' triage started", name);
+                let mut raw_rx = raw_rx.lock().await;
+                while let Some(ev) = raw_rx.recv().await {
+                    if let Some(ev2) = triage_self.triage(ev) {
+                        // clonamos para intel; el original va a BD sin esperar:
+                        // si el canal está lleno se descarta (o se vuelca a disco)
+                        let _ = intel_tx.send(ev2.clone());
+                        let _ = db_tx.try_send(ev2);
+                    }
                 }
+                log::info!("listener '{}' triage ended", name);
             }
-            log::info!("listener '{}' triage ended", name);
         });
+        [ingest, triage]
+    }
+}
+
+/// Sets fields of an event before it is published, such as the policy
+/// verdict of a connection.
+pub type Judge<E> = Arc<dyn Fn(&mut E) + Send + Sync>;
+
+/// Judge of process events that leaves them as they are and counts the
+/// strings the driver cut, in `events_truncated_total{field}`.
+pub fn count_truncated(ev: &mut ProcessEvent) {
+    for (bit, field) in [
+        (TRUNCATED_IMAGE_PATH, "image_path"),
+        (TRUNCATED_CMDLINE, "cmdline"),
+        (TRUNCATED_PARENT_IMAGE_PATH, "parent_image_path"),
+    ] {
+        if ev.truncated & bit != 0 {
+            counter!("events_truncated_total", "field" => field).increment(1);
+        }
     }
 }
 
+/// How often the ring's backlog is sampled while it is consumed.
+const LAG_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Listener que lee bytes de un MemoryRing, los decodifica con prost y envuelve.
-pub struct RingListener<E> {
+///
+/// Mientras el anillo no se vacía se leen ráfagas de hasta
+/// `ring.max_events_per_burst` eventos, tras las que la tarea cede el hilo
+/// (`tokio::task::yield_now`) para no retrasar a los writers del mismo
+/// runtime; cada ráfaga se cuenta en `ring_burst_size{ring}`. Los frames de
+/// más de `ring.blocking_decode_bytes` se decodifican en `spawn_blocking`.
+pub struct RingListener<E, R = MemoryRing> {
     name:        &'static str,
-    ring:        MemoryRing,
+    ring:        R,
     sensor_guid: String,
+    drops:       DropMonitor,
+    gaps:        GapMonitor,
+    lag:         LagMonitor,
+    limit:       Option<PayloadLimit>,
+    /// Events kept over the limit the first time their key is seen.
+    new_keys:    Option<(BypassKey<E>, usize)>,
+    judge:       Option<Judge<E>>,
+    enrichers:   Vec<Arc<dyn Enricher<E>>>,
+    max_burst:   usize,
+    /// Frames más largos se decodifican fuera del runtime.
+    blocking_decode: usize,
     _marker:     PhantomData<E>,
 }
 
-impl<E> RingListener<E> {
+impl<E, R: RingSource> RingListener<E, R> {
     /// `name`: p.ej. "network"; `ring`: tu MemoryRing; `sensor_guid`: desde config.
     pub fn new(
         name: &'static str,
-        ring: MemoryRing,
+        ring: R,
         sensor_guid: impl Into<String>
     ) -> Self {
+        gauge!("ring_mapped_bytes", "ring" => name).set(ring.mapped_len() as f64);
+        // Lo que el driver descartó antes de arrancar el agente.
+        let stats = ring.stats();
+        log::info!(
+            "ring '{}': {} events dropped so far ({} with the ring full, {} longer than the ring), longest frame {} bytes",
+            name, stats.dropped, stats.dropped_full, stats.dropped_oversize, stats.max_observed_len,
+        );
         Self {
             name,
             ring,
             sensor_guid: sensor_guid.into(),
+            drops: DropMonitor::default(),
+            gaps: GapMonitor::default(),
+            lag: LagMonitor::new(RingConfig::default().lag_warn_samples),
+            limit: None,
+            new_keys: None,
+            judge: None,
+            enrichers: Vec::new(),
+            max_burst: RingConfig::default().max_events_per_burst,
+            blocking_decode: RingConfig::default().blocking_decode_bytes,
             _marker: PhantomData,
         }
     }
+
+    /// Yields after `max_events` events read in a row
+    /// (`ring.max_events_per_burst`) and decodes frames over
+    /// `blocking_decode` bytes on the blocking pool
+    /// (`ring.blocking_decode_bytes`).
+    pub fn bursts(mut self, max_events: usize, blocking_decode: usize) -> Self {
+        self.max_burst = max_events.max(1);
+        self.blocking_decode = blocking_decode;
+        self
+    }
+
+    /// Drops events over this payload's entry in `limits`. Events for which
+    /// `key` gives a key not seen recently are kept anyway
+    /// (`rate_limit::process_image` for process creations).
+    pub fn limited(mut self, limits: &LimitsConfig, key: Option<BypassKey<E>>) -> Self {
+        self.limit = limits.payloads.get(self.name).copied();
+        self.new_keys = key.filter(|_| limits.new_images > 0).map(|k| (k, limits.new_images));
+        self
+    }
+
+    /// Warns once the ring stays over 80% full for more than `samples`
+    /// seconds (`ring.lag_warn_samples`).
+    pub fn lag_warned_after(mut self, samples: u32) -> Self {
+        self.lag = LagMonitor::new(samples);
+        self
+    }
+
+    /// Passes every event kept to `judge` before it reaches the buses.
+    pub fn judged(mut self, judge: Judge<E>) -> Self {
+        self.judge = Some(judge);
+        self
+    }
+
+    /// Runs `enricher` on every event kept, before the judge sees it.
+    pub fn enriched(mut self, enricher: Arc<dyn Enricher<E>>) -> Self {
+        self.enrichers.push(enricher);
+        self
+    }
 }
 
 #[async_trait]
-impl<E> Listener<E> for RingListener<E>
+impl<E, R: RingSource> Listener<E> for RingListener<E, R>
 where
 // E debe decodificarse con prost, clonarse, enviarse entre hilos y vivir 'static
     E: Message + Default + Clone + Send + Sync + 'static,
@@ -106,26 +230,95 @@ where
         self.name
     }
 
-    async fn ingest(self: Arc<Self>, tx: mpsc::Sender<WrappedEvent<E>>) {
+    async fn ingest(self: Arc<Self>, tx: mpsc::Sender<WrappedEvent<E>>, shutdown: Shutdown) {
+        let mut limiter = self.limit.map(|limit| {
+            RateLimiter::new(self.name, limit, self.new_keys.map_or(0, |(_, n)| n), Instant::now())
+        });
+        let mut sampler = time::interval(LAG_SAMPLE_INTERVAL);
+        sampler.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // Frames leídos desde que el anillo se vació o la tarea cedió el hilo.
+        let mut burst = 0;
         loop {
-            match self.ring.pop().await {
-                Some(bytes) => match E::decode(&*bytes) {
-                    Ok(payload) => {
-                        let wrapped = WrappedEvent {
-                            // SystemTime::now() se convierte a prost_types::Timestamp
-                            ts:          SystemTime::now().into(),
-                            sensor_guid: self.sensor_guid.clone(),
-                            payload,
-                        };
-                        if tx.send(wrapped).await.is_err() {
-                            // receptor cerrado → salimos
-                            break;
+            if burst >= self.max_burst || (burst > 0 && self.ring.backlog() == 0) {
+                histogram!("ring_burst_size", "ring" => self.name).record(burst as f64);
+                if std::mem::take(&mut burst) >= self.max_burst {
+                    // Las sends sólo esperan con el canal lleno: sin esto un
+                    // anillo siempre lleno acapara el hilo.
+                    task::yield_now().await;
+                }
+            }
+            // `pop_frame` only yields before taking a frame, so none is lost here.
+            let frame = tokio::select! {
+                frame = self.ring.pop_frame() => frame,
+                _ = sampler.tick() => {
+                    // Reads the header only; `head` stays with `pop_frame`.
+                    self.lag.sample(self.name, &self.ring);
+                    let stats = self.ring.stats();
+                    self.drops.observe(self.name, &stats);
+                    AgentStats::global().set_ring(self.name, stats);
+                    continue;
+                }
+                _ = shutdown.triggered() => break,
+            };
+            match frame {
+                Some(Popped { data, seq, ts, pos }) => {
+                    // Also for frames that do not decode: they were not lost.
+                    self.gaps.observe(self.name, seq);
+                    burst += 1;
+                    let decoded = if data.len() > self.blocking_decode {
+                        match task::spawn_blocking(move || E::decode(data.as_slice())).await {
+                            Ok(decoded) => decoded,
+                            Err(e) => {
+                                log::error!("listener '{}': decode task failed: {}", self.name, e);
+                                continue;
+                            }
+                        }
+                    } else {
+                        E::decode(&*data)
+                    };
+                    match decoded {
+                        Ok(payload) => {
+                            counter!("events_received_total", "type" => self.name).increment(1);
+                            AgentStats::global().record_events(self.name, 1, Instant::now());
+                            gauge!("ring_fill_ratio", "ring" => self.name).set(self.ring.fill_ratio());
+                            self.drops.observe(self.name, &self.ring.stats());
+                            if let Some(limiter) = &mut limiter {
+                                let key = self.new_keys.and_then(|(key, _)| key(&payload));
+                                if !limiter.admit(key, Instant::now()) {
+                                    continue;
+                                }
+                            }
+                            let mut wrapped = WrappedEvent {
+                                // Hora del driver (KeQuerySystemTimePrecise) guardada en
+                                // la cabecera del frame; el payload no la repite. Sin
+                                // ella, la hora de lectura.
+                                ts:          ts.unwrap_or_else(SystemTime::now).into(),
+                                sensor_guid: self.sensor_guid.clone(),
+                                payload,
+                                ring_pos:    Some(pos),
+                                seq:         Some(seq),
+                                enrichment:  None,
+                                coalesced:   None,
+                            };
+                            for enricher in &self.enrichers {
+                                enricher.enrich(&mut wrapped);
+                            }
+                            if let Some(judge) = &self.judge {
+                                judge(&mut wrapped.payload);
+                            }
+                            if tx.send(wrapped).await.is_err() {
+                                // receptor cerrado → salimos
+                                break;
+                            }
+                        }
+                        Err(err) => {
+                            log::error!("listener '{}': decode error: {:?}", self.name, err);
+                            let error = format!("listener '{}': decode error: {}", self.name, err);
+                            AgentStats::global().record_error(error.clone());
+                            Stats::global().set_error(error);
                         }
                     }
-                    Err(err) => {
-                        log::error!("listener '{}': decode error: {:?}", self.name, err);
-                    }
-                },
+                }
                 None => {
                     // buffer cerrado
                     break;
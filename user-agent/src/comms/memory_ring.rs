@@ -1,19 +1,111 @@
 // src/comms/memory_ring.rs
+use async_trait::async_trait;
 use memmap2::{MmapMut, MmapOptions};
 use std::{
     fs::OpenOptions,
+    io,
+    mem::offset_of,
     path::Path,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tokio::task::yield_now;
+use metrics::{counter, gauge};
+use shared::ring::{self, RingHeader, RingStats};
+use thiserror::Error;
+
+use super::ring_event::RingWait;
+use crate::config::model::ReplayPolicy;
+use crate::heartbeat::Stats;
+
+/// Un frame extraído del anillo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Popped {
+    /// Payload puro.
+    pub data: Vec<u8>,
+    /// Número que el driver dio al frame (ver [`ring::Frame`]).
+    pub seq:  u64,
+    /// Cuándo lo escribió el driver, si lo indica.
+    pub ts:   Option<SystemTime>,
+    /// `head` tras el frame.
+    pub pos:  u64,
+}
+
+/// Momento que indica el timestamp `ts` de un frame; `None` si es 0.
+pub fn frame_time(ts: u64) -> Option<SystemTime> {
+    (ts != 0).then(|| UNIX_EPOCH + Duration::from_micros(ts))
+}
+
+/// Bytes escritos por el driver y aún sin leer en un área de datos de
+/// `size` bytes, con el consumer en `head` y el driver en `tail`. Es la
+/// distancia circular de `head` a `tail`: tras dar la vuelta `tail` queda
+/// por debajo de `head`.
+pub fn backlog_bytes(head: u64, tail: u64, size: u64) -> u64 {
+    if size == 0 {
+        return 0;
+    }
+    (tail % size + size - head % size) % size
+}
+
+/// Por qué no se pudo abrir un anillo.
+#[derive(Debug, Error)]
+pub enum RingOpenError {
+    /// El driver no ha creado la sección: no está cargado o no expone este
+    /// anillo.
+    #[error("ring section not found; is the driver loaded?")]
+    SectionMissing,
+    /// La sección existe pero no se puede abrir para leer y escribir.
+    #[error("access to the ring section denied; the agent must run elevated")]
+    AccessDenied,
+    /// La vista no tiene los bytes que hacen falta: `expected` como mínimo,
+    /// `got` mapeados.
+    #[error("ring view of {got} bytes, {expected} needed")]
+    SizeMismatch { expected: u64, got: u64 },
+    /// La cabecera no es de un formato de frame conocido.
+    #[error("ring framing version {found}, expected {}", ring::VERSION)]
+    BadVersion { found: u32 },
+    /// Cualquier otro fallo al abrir o mapear.
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl From<io::Error> for RingOpenError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => Self::SectionMissing,
+            io::ErrorKind::PermissionDenied => Self::AccessDenied,
+            _ => Self::Io(e),
+        }
+    }
+}
+
+/// Offset del área de datos de un anillo cuya vista, de `view_len` bytes,
+/// empieza por `header`. Falla si la vista no llega a cubrir la cabecera y
+/// algo de datos, o si la versión no es una que sepamos leer.
+pub fn data_offset(header: &[u8], view_len: u64) -> Result<usize, RingOpenError> {
+    const VERSION_AT: usize = offset_of!(RingHeader, version);
+    let got = view_len.min(header.len() as u64);
+    if view_len <= ring::HEADER_SIZE as u64 || got < (VERSION_AT + 4) as u64 {
+        return Err(RingOpenError::SizeMismatch { expected: ring::HEADER_SIZE as u64 + 1, got });
+    }
+    let found = u32::from_ne_bytes(header[VERSION_AT..VERSION_AT + 4].try_into().unwrap());
+    // Los de versión 3 enmarcan igual, con una cabecera sin los motivos
+    // de descarte.
+    ring::header_size(found).ok_or(RingOpenError::BadVersion { found })
+}
 
 /// Un anillo de memoria mapeada por un driver y leído desde user-mode.
 pub struct MemoryRing {
     mmap:        MmapMut,
-    head:        *mut AtomicUsize,
-    tail:        *mut AtomicUsize,
+    header:      *const RingHeader,
+    head:        *const AtomicU64,
+    tail:        *const AtomicU64,
     data_offset: usize,
     buf_size:    usize,
+    replay:      ReplayPolicy,
+    /// `true` hasta que se ha aplicado `replay` a lo que había antes de abrir.
+    backlog:     AtomicBool,
+    /// Qué hacer con el anillo vacío.
+    wait:        RingWait,
 }
 
 // Permitir uso concurrente ya que accesos son atómicos y el mapping es seguro.
@@ -21,57 +113,375 @@ unsafe impl Send for MemoryRing {}
 unsafe impl Sync for MemoryRing {}
 
 impl MemoryRing {
-    /// Abre (y mapea) el fichero de anillo.
-    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+    /// Abre (y mapea) el fichero de anillo, leyendo todo lo pendiente.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, RingOpenError> {
+        Self::open_with_policy(path, ReplayPolicy::default())
+    }
+
+    /// Como [`open`](Self::open), aplicando `replay` a los frames que el
+    /// driver escribió antes de la primera lectura. La posición del header no
+    /// se toca hasta entonces, así que la reconciliación con la posición
+    /// guardada (`comms::progress`) sigue viendo la de antes.
+    ///
+    /// Primero se mapea sólo la cabecera y se valida; la sección entera,
+    /// que puede ser grande, después. La vista se pide de lectura y
+    /// escritura, nunca de ejecución.
+    pub fn open_with_policy<P: AsRef<Path>>(path: P, replay: ReplayPolicy) -> Result<Self, RingOpenError> {
         let file = OpenOptions::new()
             .read(true)
             .write(true)
             .open(path)?;
-        let metadata = file.metadata()?;
-        let len = metadata.len() as usize;
-        let header_bytes = 2 * std::mem::size_of::<AtomicUsize>();
-        if len <= header_bytes {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "file too small for MemoryRing header",
-            ));
+        let len = file.metadata()?.len();
+        // Sin cabecera entera no hay nada que mapear primero.
+        if len <= ring::HEADER_SIZE as u64 {
+            return Err(RingOpenError::SizeMismatch { expected: ring::HEADER_SIZE as u64 + 1, got: len });
         }
+        // Un productor con otro formato de frame no se puede leer.
+        let header_bytes = {
+            let view = unsafe { MmapOptions::new().len(ring::HEADER_SIZE).map(&file)? };
+            data_offset(&view, len)?
+        };
 
         let mmap = unsafe { MmapOptions::new().map_mut(&file)? };
+        // Una vista parcial dejaría el final del área de datos fuera.
+        if (mmap.len() as u64) < len {
+            return Err(RingOpenError::SizeMismatch { expected: len, got: mmap.len() as u64 });
+        }
         // Asumimos alineación de página al inicio
-        let ptr = mmap.as_ptr() as *mut AtomicUsize;
-        let head = ptr;
-        let tail = unsafe { ptr.add(1) };
+        let header = mmap.as_ptr() as *const RingHeader;
+        let buf_size = mmap.len() - header_bytes;
+        let head = unsafe { &raw const (*header).head };
+        let tail = unsafe { &raw const (*header).tail };
+
+        Ok(MemoryRing {
+            mmap,
+            header,
+            head,
+            tail,
+            data_offset: header_bytes,
+            buf_size,
+            replay,
+            backlog: AtomicBool::new(replay != ReplayPolicy::FromTail),
+            wait: RingWait::Poll,
+        })
+    }
+
+    /// Con el anillo vacío espera a `wait` en vez de volver a mirar sin
+    /// pausa (ver `comms::ring_event`).
+    pub fn woken_by(mut self, wait: RingWait) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Offset de lectura actual (consumer).
+    pub fn head(&self) -> u64 {
+        unsafe { (*self.head).load(Ordering::Acquire) }
+    }
 
-        Ok(MemoryRing { mmap, head, tail, data_offset: header_bytes, buf_size: len - header_bytes })
+    /// Offset de escritura actual (driver).
+    pub fn tail(&self) -> u64 {
+        unsafe { (*self.tail).load(Ordering::Acquire) }
+    }
+
+    /// Eventos que el driver ha descartado por no caber.
+    pub fn dropped(&self) -> u32 {
+        unsafe { (*self.header).dropped.load(Ordering::Relaxed) }
+    }
+
+    /// Bytes pendientes de leer (ver [`backlog_bytes`]).
+    pub fn backlog(&self) -> u64 {
+        backlog_bytes(self.head(), self.tail(), self.capacity())
+    }
+
+    /// Tamaño del área de datos en bytes.
+    pub fn capacity(&self) -> u64 {
+        self.buf_size as u64
+    }
+
+    /// Bytes mapeados, cabecera incluida.
+    pub fn mapped_len(&self) -> usize {
+        self.mmap.len()
+    }
+
+    /// Comprueba que la vista cubre la cabecera más los `size` bytes de
+    /// datos que el driver dice usar (`RingStats::size`). El área de datos
+    /// se toma de la vista, no de una constante: el tamaño lo negocia el
+    /// driver al cargar.
+    pub fn expect_size(&self, size: u32) -> Result<(), RingOpenError> {
+        let expected = (self.data_offset + size as usize) as u64;
+        if (self.mmap.len() as u64) < expected {
+            return Err(RingOpenError::SizeMismatch { expected, got: self.mmap.len() as u64 });
+        }
+        Ok(())
+    }
+
+    /// Lectura de la cabecera tal como la devuelve `IOCTL_GLADIX_GET_RING_STATS`,
+    /// con los descartes por motivo (`dropped_full`, `dropped_oversize`) y el
+    /// frame más largo visto (`max_observed_len`); a 0 en un anillo de versión 3.
+    pub fn stats(&self) -> RingStats {
+        RingStats::read(unsafe { &*self.header }, self.buf_size)
+    }
+
+    /// Fracción del área de datos pendiente de leer, entre 0 y 1.
+    pub fn fill_ratio(&self) -> f64 {
+        let cap = self.capacity();
+        if cap == 0 {
+            return 0.0;
+        }
+        self.backlog() as f64 / cap as f64
     }
 
     /// Extrae el siguiente evento (payload puro) si hay datos; espera (async) si está vacío.
     pub async fn pop(&self) -> Option<Vec<u8>> {
+        self.pop_frame().await.map(|frame| frame.data)
+    }
+
+    /// Como [`pop`](Self::pop), devolviendo además el número del frame,
+    /// cuándo se escribió y el `head` tras él.
+    ///
+    /// Un frame dañado (magic, longitud o CRC que no cuadran) no se entrega:
+    /// se salta hasta el siguiente frame íntegro, contando los bytes en
+    /// `ring_skipped_bytes_total`, y se pierde sólo ese frame.
+    pub async fn pop_frame(&self) -> Option<Popped> {
+        if self.replay == ReplayPolicy::SkipBacklog && self.backlog.swap(false, Ordering::Relaxed) {
+            self.skip_backlog();
+        }
         loop {
-            let h = unsafe { (*self.head).load(Ordering::Acquire) };
-            let t = unsafe { (*self.tail).load(Ordering::Acquire) };
+            let h = unsafe { (*self.head).load(Ordering::Acquire) } as usize;
+            let t = unsafe { (*self.tail).load(Ordering::Acquire) } as usize;
             if h == t {
-                yield_now().await;
+                self.wait.idle(|| self.head() == self.tail()).await;
                 continue;
             }
 
-            let off = self.data_offset + h;
-            let len_bytes = &self.mmap[off..off + 4];
-            let payload_len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
-            let start = off + 4;
-            let end = start + payload_len;
-            let data = self.mmap[start..end].to_vec();
+            // Los frames no cruzan el final del área de datos (ver
+            // `ring::push`): si el productor ha dado la vuelta, se lee hasta
+            // el final y luego desde 0.
+            let end = if t > h { t } else { self.buf_size };
+            let window = &self.mmap[self.data_offset + h..self.data_offset + end];
+            let (frame, advance) = match ring::check_frame(window) {
+                Some(frame) => {
+                    let len = frame.payload.len();
+                    (Some((window[frame.payload].to_vec(), frame.seq, frame.ts)), ring::frame_len(len))
+                }
+                None => {
+                    let skip = ring::find_frame(window).unwrap_or(window.len());
+                    // Los ceros al final del área son relleno antes de la vuelta.
+                    if window[..skip].iter().any(|&b| b != 0) {
+                        log::warn!("ring: corrupt frame at offset {}, skipped {} bytes", h, skip);
+                        counter!("ring_skipped_bytes_total").increment(skip as u64);
+                    }
+                    (None, skip)
+                }
+            };
 
-            let total = 4 + payload_len;
-            let pad = (8 - (total % 8)) % 8;
-            let mut new_h = h + total + pad;
+            let mut new_h = h + advance;
             if new_h >= self.buf_size {
                 new_h -= self.buf_size;
             }
-            unsafe { (*self.head).store(new_h, Ordering::Release) };
+            unsafe { (*self.head).store(new_h as u64, Ordering::Release) };
 
-            return Some(data);
+            if let Some((data, seq, ts)) = frame {
+                let ts = frame_time(ts);
+                if self.stale(ts) {
+                    counter!("ring_replay_skipped_total").increment(1);
+                    continue;
+                }
+                return Some(Popped { data, seq, ts, pos: new_h as u64 });
+            }
         }
     }
-}
\ No newline at end of file
+
+    /// Salta todo lo escrito hasta ahora (`ReplayPolicy::SkipBacklog`).
+    fn skip_backlog(&self) {
+        let skipped = self.backlog();
+        if skipped > 0 {
+            log::info!("ring: skipping {} bytes written before the agent attached", skipped);
+        }
+        unsafe { (*self.head).store(self.tail(), Ordering::Release) };
+    }
+
+    /// Si un frame escrito en `ts` se descarta por `ReplayPolicy::MaxAge`.
+    /// Sólo se descartan los del principio: el primero reciente termina el
+    /// backlog. Los frames sin timestamp se entregan.
+    fn stale(&self, ts: Option<SystemTime>) -> bool {
+        let ReplayPolicy::MaxAge(max) = self.replay else { return false };
+        if !self.backlog.load(Ordering::Relaxed) {
+            return false;
+        }
+        let old = ts.is_some_and(|ts| SystemTime::now().duration_since(ts).is_ok_and(|age| age > max));
+        if !old {
+            self.backlog.store(false, Ordering::Relaxed);
+        }
+        old
+    }
+}
+
+/// Origen de frames de un [`RingListener`](super::listeners::RingListener):
+/// un [`MemoryRing`] en el agente, cualquier cola en los tests.
+#[async_trait]
+pub trait RingSource: Send + Sync + 'static {
+    /// Siguiente frame; espera mientras no haya ninguno. `None` si el
+    /// origen se ha cerrado.
+    async fn pop_frame(&self) -> Option<Popped>;
+
+    /// Bytes pendientes de leer.
+    fn backlog(&self) -> u64;
+
+    /// Contadores de la cabecera (ver [`MemoryRing::stats`]).
+    fn stats(&self) -> RingStats;
+
+    /// Tamaño del área de datos en bytes.
+    fn capacity(&self) -> u64 {
+        self.stats().size as u64
+    }
+
+    /// Fracción del área de datos pendiente de leer, entre 0 y 1.
+    fn fill_ratio(&self) -> f64 {
+        match self.capacity() {
+            0 => 0.0,
+            cap => self.backlog() as f64 / cap as f64,
+        }
+    }
+
+    /// Bytes mapeados, cabecera incluida; 0 si no hay vista.
+    fn mapped_len(&self) -> usize {
+        0
+    }
+}
+
+#[async_trait]
+impl RingSource for MemoryRing {
+    async fn pop_frame(&self) -> Option<Popped> {
+        MemoryRing::pop_frame(self).await
+    }
+
+    fn backlog(&self) -> u64 {
+        MemoryRing::backlog(self)
+    }
+
+    fn stats(&self) -> RingStats {
+        MemoryRing::stats(self)
+    }
+
+    fn capacity(&self) -> u64 {
+        MemoryRing::capacity(self)
+    }
+
+    fn fill_ratio(&self) -> f64 {
+        MemoryRing::fill_ratio(self)
+    }
+
+    fn mapped_len(&self) -> usize {
+        MemoryRing::mapped_len(self)
+    }
+}
+
+/// Ocupación por encima de la cual el consumer se da por retrasado.
+pub const LAG_WARN_RATIO: f64 = 0.8;
+
+/// Muestrea cuánto va el consumer de un anillo por detrás del driver:
+/// publica `ring_backlog_bytes{ring}` y `ring_utilization_ratio{ring}`, los
+/// descartes por motivo (`ring_dropped_full{ring}`,
+/// `ring_dropped_oversize{ring}`) y el frame más largo
+/// (`ring_max_frame_bytes{ring}`), y avisa en el log una vez cuando la ocupación pasa de [`LAG_WARN_RATIO`]
+/// durante más de `warn_after` muestras seguidas. Sólo lee la cabecera; el
+/// `head` sigue siendo cosa de [`MemoryRing::pop_frame`].
+#[derive(Debug)]
+pub struct LagMonitor {
+    warn_after: u32,
+    /// Muestras seguidas por encima de [`LAG_WARN_RATIO`].
+    above:      AtomicU32,
+}
+
+impl LagMonitor {
+    pub fn new(warn_after: u32) -> Self {
+        Self { warn_after, above: AtomicU32::new(0) }
+    }
+
+    /// Registra una muestra del anillo `name`; devuelve `true` si es la que
+    /// dispara el aviso.
+    pub fn sample(&self, name: &'static str, ring: &impl RingSource) -> bool {
+        let backlog = ring.backlog();
+        let ratio = ring.fill_ratio();
+        gauge!("ring_backlog_bytes", "ring" => name).set(backlog as f64);
+        gauge!("ring_utilization_ratio", "ring" => name).set(ratio);
+        // Contadores del driver tal cual: vuelven a 0 si se recarga.
+        let stats = ring.stats();
+        gauge!("ring_dropped_full", "ring" => name).set(stats.dropped_full as f64);
+        gauge!("ring_dropped_oversize", "ring" => name).set(stats.dropped_oversize as f64);
+        gauge!("ring_max_frame_bytes", "ring" => name).set(stats.max_observed_len as f64);
+        if ratio <= LAG_WARN_RATIO {
+            let above = self.above.swap(0, Ordering::Relaxed);
+            if above > self.warn_after {
+                log::info!("ring '{}': consumer caught up after {} samples, {:.0}% used", name, above, ratio * 100.0);
+            }
+            return false;
+        }
+        let above = self.above.fetch_add(1, Ordering::Relaxed) + 1;
+        let warn = above == self.warn_after.saturating_add(1);
+        if warn {
+            log::warn!(
+                "ring '{}': {:.0}% used ({} of {} bytes unread) for {} samples, the consumer is falling behind",
+                name, ratio * 100.0, backlog, ring.capacity(), above,
+            );
+        }
+        warn
+    }
+}
+
+/// Sigue el contador `dropped` de un anillo entre lecturas: cada aumento se
+/// avisa en el log y se suma a `ring_dropped_total{ring}` y al heartbeat.
+#[derive(Debug, Default)]
+pub struct DropMonitor {
+    last: AtomicU32,
+}
+
+impl DropMonitor {
+    /// Registra la lectura `stats` del anillo `name`; devuelve los eventos
+    /// perdidos desde la anterior.
+    pub fn observe(&self, name: &'static str, stats: &RingStats) -> u32 {
+        let previous = self.last.swap(stats.dropped, Ordering::Relaxed);
+        let lost = stats.dropped.wrapping_sub(previous);
+        if lost > 0 {
+            log::warn!(
+                "ring '{}': driver dropped {} events ({} in total, {} with the ring full, {} longer than the ring)",
+                name, lost, stats.dropped, stats.dropped_full, stats.dropped_oversize,
+            );
+            counter!("ring_dropped_total", "ring" => name).increment(lost as u64);
+            Stats::global().add_dropped(lost as u64);
+        }
+        lost
+    }
+}
+
+/// Sigue los números de secuencia de los frames de un anillo: los que faltan
+/// entre dos frames leídos (descartados por el driver o saltados al
+/// resincronizar) se suman a `ring_gap_events_total{ring}`.
+#[derive(Debug, Default)]
+pub struct GapMonitor {
+    /// Último número visto; 0 antes del primero.
+    last: AtomicU64,
+}
+
+impl GapMonitor {
+    /// Registra el frame `seq` del anillo `name`; devuelve los eventos que
+    /// faltan desde el anterior. Un número que no avanza es un contador
+    /// reiniciado (driver recargado) y se toma como nuevo punto de partida.
+    pub fn observe(&self, name: &'static str, seq: u64) -> u64 {
+        let last = self.last.swap(seq, Ordering::Relaxed);
+        if last == 0 {
+            return 0;
+        }
+        if seq <= last {
+            log::warn!("ring '{}': sequence went from {} back to {}", name, last, seq);
+            return 0;
+        }
+        let missing = seq - last - 1;
+        if missing > 0 {
+            log::warn!("ring '{}': {} events missing before frame {}", name, missing, seq);
+            counter!("ring_gap_events_total", "ring" => name).increment(missing);
+        }
+        missing
+    }
+}
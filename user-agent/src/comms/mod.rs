@@ -1,14 +1,94 @@
+pub mod driver_params;
+pub mod coalesce;
 pub mod events;
+pub mod export;
+pub mod forwarder;
+pub mod grpc;
+pub mod intel_bus;
+pub mod ioctl;
 pub mod listeners;
 pub mod memory_ring;
+pub mod progress;
+pub mod rate_limit;
+pub mod ring_event;
+pub mod schema;
+pub mod tap;
 
+use prost::Message;
+use std::collections::BTreeMap;
 use prost_types::Timestamp;
+use twox_hash::XxHash64;
+use shared::events::{EtwEvent, FileEvent, ImageLoadEvent, NetworkEvent, ObjectOpEvent, ProcessEvent};
 
 /// Asegúrate de añadir este derive para que luego WrappedEvent<E>: Clone
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct WrappedEvent<E: Clone>  {
     pub ts:          Timestamp,
     pub sensor_guid: String,
     pub payload:     E,
+    /// Ring offset just past the frame this event was read from; `None` for
+    /// events that did not come from a ring.
+    pub ring_pos:    Option<u64>,
+    /// Number of that frame ([`shared::ring::Frame::seq`]); gaps between
+    /// stored events of one ring are events lost before storage.
+    pub seq:         Option<u64>,
+    /// Payload fields filled in after the event was read, each with where
+    /// the value came from (see [`crate::intel::enrich`]); `None` when none
+    /// were.
+    pub enrichment:  Option<BTreeMap<&'static str, &'static str>>,
+    /// Set on an event that stands for several merged by a
+    /// [`Coalescer`](coalesce::Coalescer); `None` for a single event. Lost
+    /// if the event is spilled to the overflow file.
+    pub coalesced:   Option<Coalesced>,
 }
 
+/// How many events a coalesced one stands for; its `ts` is the first's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Coalesced {
+    pub count:   u64,
+    pub last_ts: Timestamp,
+}
+
+impl<E: Message + Clone> WrappedEvent<E> {
+    /// Stable content id of the event, stored in the `event_uid` column and
+    /// used by the in-memory caches so both sides refer to the same event.
+    pub fn event_uid(&self) -> i64 {
+        let mut buf = Vec::with_capacity(12 + self.sensor_guid.len() + self.payload.encoded_len());
+        buf.extend_from_slice(&self.ts.seconds.to_le_bytes());
+        buf.extend_from_slice(&self.ts.nanos.to_le_bytes());
+        buf.extend_from_slice(self.sensor_guid.as_bytes());
+        buf.extend_from_slice(&self.payload.encode_to_vec());
+        XxHash64::oneshot(0, &buf) as i64
+    }
+
+    /// Event time in UNIX microseconds, the unit used by every `ts` column.
+    pub fn ts_micros(&self) -> i64 {
+        self.ts.seconds
+            .saturating_mul(1_000_000)
+            .saturating_add((self.ts.nanos as i64) / 1_000)
+    }
+}
+
+/// Exposes [`WrappedEvent::ring_pos`] to the generic DB writer.
+pub trait RingPosition {
+    fn ring_pos(&self) -> Option<u64>;
+}
+
+impl<E: Clone> RingPosition for WrappedEvent<E> {
+    fn ring_pos(&self) -> Option<u64> {
+        self.ring_pos
+    }
+}
+
+/// Payloads attributable to a process.
+pub trait HasPid {
+    fn pid(&self) -> u32;
+}
+
+impl HasPid for ProcessEvent   { fn pid(&self) -> u32 { self.pid } }
+impl HasPid for FileEvent      { fn pid(&self) -> u32 { self.pid } }
+impl HasPid for NetworkEvent   { fn pid(&self) -> u32 { self.pid } }
+impl HasPid for EtwEvent       { fn pid(&self) -> u32 { self.pid } }
+impl HasPid for ImageLoadEvent { fn pid(&self) -> u32 { self.pid } }
+/// The process asking for the handle, not the protected one.
+impl HasPid for ObjectOpEvent  { fn pid(&self) -> u32 { self.source_pid } }
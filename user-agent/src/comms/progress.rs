@@ -0,0 +1,84 @@
+// src/comms/progress.rs
+//! Reconciles the persisted consumer position with a ring header at startup.
+//!
+//! The writer stores the ring position of every flushed batch (see
+//! [`FlushAck`](crate::db::db_writer::FlushAck)). After a crash the header
+//! `head` may be ahead of it: those bytes were consumed but possibly never
+//! committed, and are reported as a coverage gap.
+
+use metrics::counter;
+use rusqlite::Connection;
+
+use super::memory_ring::MemoryRing;
+use crate::db::consumer_state::{load_position, record_gap, store_position, StoredPosition};
+
+/// Outcome of comparing the stored position with the ring header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reconcile {
+    /// No position recorded yet (first start).
+    FirstRun,
+    /// Everything consumed was committed.
+    Clean,
+    /// `bytes` were consumed but possibly not committed before the restart.
+    Gap { bytes: u64 },
+    /// The header was reset or resized behind our back; what the old ring
+    /// held after `recorded` cannot be accounted for.
+    DriverReloaded { recorded: u64 },
+}
+
+/// Offsets wrap at `ring_size`; a gap is assumed to be shorter than one lap,
+/// which holds because at most one unflushed batch can be lost. A header
+/// back at `head == tail == 0` is read as a driver reload.
+pub fn reconcile(stored: Option<StoredPosition>, head: u64, tail: u64, ring_size: u64) -> Reconcile {
+    let Some(stored) = stored else {
+        return Reconcile::FirstRun;
+    };
+    if stored.ring_size != ring_size || stored.position >= ring_size {
+        return Reconcile::DriverReloaded { recorded: stored.position };
+    }
+    if head == stored.position {
+        return Reconcile::Clean;
+    }
+    if head == 0 && tail == 0 {
+        return Reconcile::DriverReloaded { recorded: stored.position };
+    }
+    Reconcile::Gap { bytes: (head + ring_size - stored.position) % ring_size }
+}
+
+/// Reconciles `ring` against its stored position, records any gap and
+/// re-bases the stored position on the current header.
+pub fn reconcile_ring(conn: &Connection, name: &str, ring: &MemoryRing) -> rusqlite::Result<Reconcile> {
+    let (head, tail, size) = (ring.head(), ring.tail(), ring.capacity());
+    let outcome = reconcile(load_position(conn, name)?, head, tail, size);
+    match outcome {
+        Reconcile::FirstRun | Reconcile::Clean => {
+            log::info!("ring '{}': consumer resumes at {} ({:?})", name, head, outcome);
+        }
+        Reconcile::Gap { bytes } => {
+            log::warn!(
+                "ring '{}': {} bytes consumed but possibly uncommitted before restart",
+                name, bytes
+            );
+            counter!("ring_coverage_gap_bytes_total", "ring" => name.to_string()).increment(bytes);
+            record_gap(conn, name, "uncommitted", Some(bytes))?;
+        }
+        Reconcile::DriverReloaded { recorded } => {
+            log::warn!(
+                "ring '{}': header reset (recorded {}, head {}, tail {}); unflushed events are lost",
+                name, recorded, head, tail
+            );
+            record_gap(conn, name, "driver_reloaded", None)?;
+        }
+    }
+    store_position(conn, name, StoredPosition { position: head, ring_size: size })?;
+    Ok(outcome)
+}
+
+/// [`reconcile_ring`] for a ring the agent runs without: a failure is logged
+/// and counted rather than returned, and the consumer starts all the same.
+pub fn reconcile_optional(conn: &Connection, name: &str, ring: &MemoryRing) {
+    if let Err(e) = reconcile_ring(conn, name, ring) {
+        log::warn!("ring '{}': consumer position not reconciled: {}", name, e);
+        counter!("ring_reconcile_errors_total", "ring" => name.to_string()).increment(1);
+    }
+}
@@ -0,0 +1,138 @@
+// src/comms/rate_limit.rs
+//! Ingest rate limits, one token bucket per ring payload.
+//!
+//! A process spawning thousands of children per second would otherwise fill
+//! the database channel and crowd out every other sensor. Events over the
+//! limit are dropped by the ring consumer as soon as they are decoded.
+//! Callers pass the current time, so the buckets can be driven by a fake
+//! clock.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+use metrics::counter;
+use shared::events::{process_event::EventType, ProcessEvent};
+
+use crate::config::model::PayloadLimit;
+
+/// How often a limiter reports its drops.
+const WARN_EVERY: Duration = Duration::from_secs(1);
+
+/// `per_sec` tokens added per second, at most `burst` kept.
+#[derive(Debug)]
+pub struct TokenBucket {
+    per_sec: f64,
+    burst:   f64,
+    tokens:  f64,
+    last:    Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(limit: PayloadLimit, now: Instant) -> Self {
+        let burst = f64::from(limit.burst.max(1));
+        Self { per_sec: f64::from(limit.per_sec), burst, tokens: burst, last: now }
+    }
+
+    /// Takes one token if there is one.
+    pub fn take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.burst);
+        self.last = now.max(self.last);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The last `capacity` distinct keys seen, least recently seen first out.
+#[derive(Debug)]
+pub struct RecentKeys {
+    capacity: usize,
+    /// Keys by the tick they were last seen at.
+    seen:  HashMap<String, u64>,
+    /// (tick, key) in seen order; stale when the key was seen again since.
+    order: VecDeque<(u64, String)>,
+    tick:  u64,
+}
+
+impl RecentKeys {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, seen: HashMap::new(), order: VecDeque::new(), tick: 0 }
+    }
+
+    /// Records `key`; `true` if it was not among the recent keys.
+    pub fn first_seen(&mut self, key: &str) -> bool {
+        self.tick += 1;
+        let new = self.seen.insert(key.to_owned(), self.tick).is_none();
+        self.order.push_back((self.tick, key.to_owned()));
+        while self.seen.len() > self.capacity {
+            let Some((tick, old)) = self.order.pop_front() else { break };
+            if self.seen.get(&old) == Some(&tick) {
+                self.seen.remove(&old);
+            }
+        }
+        // Stale entries pile up while the same keys repeat.
+        if self.order.len() > 4 * self.capacity.max(1) {
+            let seen = &self.seen;
+            self.order.retain(|(tick, key)| seen.get(key) == Some(tick));
+        }
+        new
+    }
+}
+
+/// Key of the events kept over the limit the first time it is seen.
+pub type BypassKey<E> = fn(&E) -> Option<&str>;
+
+/// Bypass key of process events: the image of each creation.
+pub fn process_image(ev: &ProcessEvent) -> Option<&str> {
+    (ev.event_type == EventType::Create as i32 && !ev.image_path.is_empty()).then_some(ev.image_path.as_str())
+}
+
+/// Decides which events of one payload get through.
+#[derive(Debug)]
+pub struct RateLimiter {
+    payload: &'static str,
+    bucket:  TokenBucket,
+    /// Keys of recent events; an event with a new key passes an empty bucket.
+    recent:  Option<RecentKeys>,
+    dropped: u64,
+    warned:  Option<Instant>,
+}
+
+impl RateLimiter {
+    /// `new_keys`: distinct keys remembered for the bypass, 0 for none.
+    pub fn new(payload: &'static str, limit: PayloadLimit, new_keys: usize, now: Instant) -> Self {
+        Self {
+            payload,
+            bucket: TokenBucket::new(limit, now),
+            recent: (new_keys > 0).then(|| RecentKeys::new(new_keys)),
+            dropped: 0,
+            warned: None,
+        }
+    }
+
+    /// Whether the event arriving at `now` is kept. `key` identifies events
+    /// worth keeping the first of, such as a process image.
+    pub fn admit(&mut self, key: Option<&str>, now: Instant) -> bool {
+        let new = match (&mut self.recent, key) {
+            (Some(recent), Some(key)) => recent.first_seen(key),
+            _ => false,
+        };
+        if self.bucket.take(now) || new {
+            return true;
+        }
+        counter!("events_rate_limited_total", "payload" => self.payload).increment(1);
+        self.dropped += 1;
+        if self.warned.is_none_or(|at| now.saturating_duration_since(at) >= WARN_EVERY) {
+            log::warn!("{} events over the rate limit: {} dropped since the last warning", self.payload, self.dropped);
+            self.dropped = 0;
+            self.warned = Some(now);
+        }
+        false
+    }
+}
@@ -0,0 +1,235 @@
+// src/comms/ring_event.rs
+//! Wakeups for the ring consumers from the driver's event.
+//!
+//! The driver sets [`RING_EVENT`], opened here by its `Global\` name, when
+//! a ring gets frames after being empty, throttled. One blocking task waits
+//! on it and wakes every [`MemoryRing`](super::memory_ring::MemoryRing)
+//! given the same [`RingWait`]; the wait times out after
+//! [`RING_WAIT_TIMEOUT`] so frames whose signal the driver held back are
+//! still found. Without the event (older drivers, or off Windows) the
+//! consumers poll.
+
+use std::{io, sync::Arc, time::Duration};
+use tokio::{sync::Notify, task};
+
+pub use shared::constants::RING_EVENT;
+
+use crate::util::Shutdown;
+
+/// Longest a consumer sleeps on an empty ring without a signal.
+pub const RING_WAIT_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Auto-reset event: a set wakes one wait.
+#[derive(Debug)]
+pub struct RingEvent(sys::Event);
+
+impl RingEvent {
+    /// Opens an existing event, as the agent opens the driver's.
+    pub fn open(name: &str) -> io::Result<Self> {
+        sys::Event::open(name).map(Self)
+    }
+
+    /// Creates an event, named or not; opens it if the name exists.
+    pub fn create(name: Option<&str>) -> io::Result<Self> {
+        sys::Event::create(name).map(Self)
+    }
+
+    pub fn set(&self) -> io::Result<()> {
+        self.0.set()
+    }
+
+    /// Blocks until the event is set or `timeout` passes. Returns whether it
+    /// was set.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        self.0.wait(timeout)
+    }
+}
+
+/// What a ring consumer does on an empty ring.
+#[derive(Debug, Clone, Default)]
+pub enum RingWait {
+    /// Yield and look again.
+    #[default]
+    Poll,
+    /// Sleep until the event is set or its wait times out.
+    Event(Arc<Notify>),
+}
+
+impl RingWait {
+    /// Waits on the event `name`, or polls if it cannot be opened. Must be
+    /// called within a Tokio runtime.
+    pub fn open(name: &str, shutdown: &Shutdown) -> Self {
+        match RingEvent::open(name) {
+            Ok(event) => {
+                log::info!("ring consumers wait on {}", name);
+                Self::event(event, RING_WAIT_TIMEOUT, shutdown)
+            }
+            Err(e) => {
+                log::info!("{} unavailable, ring consumers poll: {}", name, e);
+                Self::Poll
+            }
+        }
+    }
+
+    /// Wakes the consumers whenever `event` is set or `timeout` passes,
+    /// until `shutdown` or every clone of the result is dropped. Must be
+    /// called within a Tokio runtime.
+    pub fn event(event: RingEvent, timeout: Duration, shutdown: &Shutdown) -> Self {
+        let notify = Arc::new(Notify::new());
+        let (wake, shutdown) = (notify.clone(), shutdown.clone());
+        task::spawn_blocking(move || {
+            while !shutdown.is_triggered() && Arc::strong_count(&wake) > 1 {
+                event.wait(timeout);
+                wake.notify_waiters();
+            }
+        });
+        Self::Event(notify)
+    }
+
+    /// Returns once the ring may have frames: at once when polling,
+    /// otherwise after the next wakeup unless `empty` already says no.
+    pub async fn idle(&self, empty: impl Fn() -> bool) {
+        match self {
+            Self::Poll => task::yield_now().await,
+            Self::Event(notify) => {
+                let notified = notify.notified();
+                tokio::pin!(notified);
+                // Registered before looking, so a wakeup in between counts.
+                notified.as_mut().enable();
+                if empty() {
+                    notified.await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{ffi::c_void, io, iter::once, ptr, time::Duration};
+
+    const SYNCHRONIZE: u32 = 0x0010_0000;
+    const WAIT_OBJECT_0: u32 = 0;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn CreateEventW(attributes: *const c_void, manual_reset: i32, initial_state: i32, name: *const u16)
+            -> *mut c_void;
+        fn OpenEventW(access: u32, inherit: i32, name: *const u16) -> *mut c_void;
+        fn SetEvent(event: *mut c_void) -> i32;
+        fn WaitForSingleObject(handle: *mut c_void, millis: u32) -> u32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    #[derive(Debug)]
+    pub struct Event(*mut c_void);
+
+    // SAFETY: the handle is only waited on, set and closed, once, on drop.
+    unsafe impl Send for Event {}
+    unsafe impl Sync for Event {}
+
+    fn wide(name: &str) -> Vec<u16> {
+        name.encode_utf16().chain(once(0)).collect()
+    }
+
+    fn checked(handle: *mut c_void) -> io::Result<Event> {
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Event(handle))
+    }
+
+    impl Event {
+        pub fn open(name: &str) -> io::Result<Self> {
+            let name = wide(name);
+            // SAFETY: `name` is NUL-terminated and outlives the call.
+            checked(unsafe { OpenEventW(SYNCHRONIZE, 0, name.as_ptr()) })
+        }
+
+        pub fn create(name: Option<&str>) -> io::Result<Self> {
+            let name = name.map(wide);
+            let name_ptr = name.as_ref().map_or(ptr::null(), |n| n.as_ptr());
+            // SAFETY: `name_ptr` is null or NUL-terminated and outlives the call.
+            checked(unsafe { CreateEventW(ptr::null(), 0, 0, name_ptr) })
+        }
+
+        pub fn set(&self) -> io::Result<()> {
+            // SAFETY: valid handle until drop.
+            if unsafe { SetEvent(self.0) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        pub fn wait(&self, timeout: Duration) -> bool {
+            let millis = timeout.as_millis().min(u32::MAX as u128 - 1) as u32;
+            // SAFETY: valid handle until drop.
+            unsafe { WaitForSingleObject(self.0, millis) == WAIT_OBJECT_0 }
+        }
+    }
+
+    impl Drop for Event {
+        fn drop(&mut self) {
+            // SAFETY: handle from `CreateEventW` or `OpenEventW`, closed once.
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+}
+
+/// Without named kernel objects the events are shared within this process
+/// only, which is what the tests exercise.
+#[cfg(not(windows))]
+mod sys {
+    use std::{
+        collections::HashMap,
+        io,
+        sync::{Arc, Condvar, LazyLock, Mutex, Weak},
+        time::Duration,
+    };
+
+    static NAMED: LazyLock<Mutex<HashMap<String, Weak<Inner>>>> = LazyLock::new(Default::default);
+
+    #[derive(Debug, Default)]
+    struct Inner {
+        set:  Mutex<bool>,
+        cond: Condvar,
+    }
+
+    #[derive(Debug)]
+    pub struct Event(Arc<Inner>);
+
+    impl Event {
+        pub fn open(name: &str) -> io::Result<Self> {
+            let named = NAMED.lock().unwrap_or_else(|e| e.into_inner());
+            named
+                .get(name)
+                .and_then(Weak::upgrade)
+                .map(Self)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no event named {name}")))
+        }
+
+        pub fn create(name: Option<&str>) -> io::Result<Self> {
+            let Some(name) = name else { return Ok(Self(Arc::default())) };
+            let mut named = NAMED.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(inner) = named.get(name).and_then(Weak::upgrade) {
+                return Ok(Self(inner));
+            }
+            let inner = Arc::<Inner>::default();
+            named.insert(name.to_owned(), Arc::downgrade(&inner));
+            Ok(Self(inner))
+        }
+
+        pub fn set(&self) -> io::Result<()> {
+            *self.0.set.lock().unwrap_or_else(|e| e.into_inner()) = true;
+            self.0.cond.notify_one();
+            Ok(())
+        }
+
+        pub fn wait(&self, timeout: Duration) -> bool {
+            let set = self.0.set.lock().unwrap_or_else(|e| e.into_inner());
+            let (mut set, _) =
+                self.0.cond.wait_timeout_while(set, timeout, |set| !*set).unwrap_or_else(|e| e.into_inner());
+            std::mem::take(&mut *set)
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// src/comms/schema.rs
+//! Event schema description for UIs (`DescribeSchema`, `gladix-cli schema`).
+//!
+//! Field names, types and enum values come from the descriptor set embedded
+//! in `shared`; the table and columns from the `event_types` registration;
+//! annotations from the effective database config.
+
+use anyhow::{bail, Context, Result};
+use prost::Message;
+use prost_types::{field_descriptor_proto::{Label, Type}, DescriptorProto, FileDescriptorSet};
+use serde_json::{json, Value};
+use shared::config::{DescribeSchemaResponse, EnumSchema, EnumValueSchema, EventSchema, FieldSchema};
+
+use crate::config::model::DatabaseConfig;
+use crate::db::event_types::event_type;
+
+/// Envelope whose `payload` oneof lists the event types.
+const ENVELOPE: &str = "BaseEvent";
+
+fn type_name(field: &prost_types::FieldDescriptorProto) -> String {
+    match field.r#type() {
+        Type::Enum | Type::Message => {
+            // ".events.FileEvent.Operation" → "FileEvent.Operation"
+            let name = field.type_name().trim_start_matches('.');
+            name.split_once('.').map_or(name, |(_, rest)| rest).to_owned()
+        }
+        t => t.as_str_name().trim_start_matches("TYPE_").to_lowercase(),
+    }
+}
+
+fn describe_message(msg: &DescriptorProto, cfg: &DatabaseConfig) -> EventSchema {
+    let registration = event_type(msg.name());
+    let table = registration.map_or("", |t| t.table);
+    let fields = msg
+        .field
+        .iter()
+        .map(|f| {
+            let columns: Vec<String> = registration
+                .map(|t| t.columns_of(f.name()).map(String::from).collect())
+                .unwrap_or_default();
+            let annotations = columns
+                .iter()
+                .filter(|c| cfg.compress_columns.contains(&format!("{table}.{c}")))
+                .map(|c| format!("{c}: compressed from {} bytes", cfg.compress_threshold))
+                .collect();
+            FieldSchema {
+                name:     f.name().to_owned(),
+                number:   f.number() as u32,
+                r#type:   type_name(f),
+                repeated: f.label() == Label::Repeated,
+                columns,
+                annotations,
+            }
+        })
+        .collect();
+    let enums = msg
+        .enum_type
+        .iter()
+        .map(|e| EnumSchema {
+            name:   format!("{}.{}", msg.name(), e.name()),
+            values: e.value.iter().map(|v| EnumValueSchema { name: v.name().to_owned(), number: v.number() }).collect(),
+        })
+        .collect();
+    let derived_columns = registration
+        .map(|t| t.columns.iter().filter(|c| c.field.is_none()).map(|c| c.name.to_owned()).collect())
+        .unwrap_or_default();
+    EventSchema { name: msg.name().to_owned(), table: table.to_owned(), fields, enums, derived_columns }
+}
+
+/// Describes every event type, or only `only` (message name).
+pub fn describe_schema(cfg: &DatabaseConfig, only: Option<&str>) -> Result<DescribeSchemaResponse> {
+    let set = FileDescriptorSet::decode(shared::FILE_DESCRIPTOR_SET).context("embedded descriptor set")?;
+    let events = set
+        .file
+        .iter()
+        .find(|f| f.package() == "events")
+        .context("events.proto missing from the descriptor set")?;
+    let message = |name: &str| events.message_type.iter().find(|m| m.name() == name);
+    let envelope = message(ENVELOPE).context("BaseEvent missing")?;
+
+    let mut out = DescribeSchemaResponse::default();
+    for payload in &envelope.field {
+        if payload.oneof_index.is_none() || payload.r#type() != Type::Message {
+            continue;
+        }
+        let name = type_name(payload);
+        if only.is_some_and(|o| o != name) {
+            continue;
+        }
+        let msg = message(&name).with_context(|| format!("{name} missing"))?;
+        out.events.push(describe_message(msg, cfg));
+    }
+    if let Some(o) = only.filter(|_| out.events.is_empty()) {
+        bail!("unknown event type {o}");
+    }
+    Ok(out)
+}
+
+/// Human-readable form.
+pub fn render_text(schema: &DescribeSchemaResponse) -> String {
+    let mut out = String::new();
+    for ev in &schema.events {
+        let table = if ev.table.is_empty() { "not stored" } else { &ev.table };
+        out += &format!("{} ({table})\n", ev.name);
+        for f in &ev.fields {
+            let ty = if f.repeated { format!("repeated {}", f.r#type) } else { f.r#type.clone() };
+            let columns = if f.columns.is_empty() { "-".to_owned() } else { f.columns.join(", ") };
+            out += &format!("  {:>2} {:<16} {:<20} → {}\n", f.number, f.name, ty, columns);
+            for a in &f.annotations {
+                out += &format!("     {:<16} {:<20}   [{a}]\n", "", "");
+            }
+        }
+        if !ev.derived_columns.is_empty() {
+            out += &format!("  envelope columns: {}\n", ev.derived_columns.join(", "));
+        }
+        for e in &ev.enums {
+            let values: Vec<String> = e.values.iter().map(|v| format!("{}={}", v.name, v.number)).collect();
+            out += &format!("  enum {}: {}\n", e.name, values.join(", "));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// JSON form, field for field the same as the RPC response.
+pub fn to_json(schema: &DescribeSchemaResponse) -> Value {
+    Value::Array(
+        schema
+            .events
+            .iter()
+            .map(|ev| {
+                json!({
+                    "name":  ev.name,
+                    "table": ev.table,
+                    "fields": ev.fields.iter().map(|f| json!({
+                        "name":        f.name,
+                        "number":      f.number,
+                        "type":        f.r#type,
+                        "repeated":    f.repeated,
+                        "columns":     f.columns,
+                        "annotations": f.annotations,
+                    })).collect::<Vec<_>>(),
+                    "enums": ev.enums.iter().map(|e| json!({
+                        "name":   e.name,
+                        "values": e.values.iter().map(|v| json!({ "name": v.name, "number": v.number })).collect::<Vec<_>>(),
+                    })).collect::<Vec<_>>(),
+                    "derived_columns": ev.derived_columns,
+                })
+            })
+            .collect(),
+    )
+}
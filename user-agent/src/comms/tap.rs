@@ -0,0 +1,293 @@
+// src/comms/tap.rs
+//! Local stream of decoded events for debugging tools.
+//!
+//! The driver ring has a single consumer: a second reader advancing `tail`
+//! would steal frames from the agent. Tools that want to watch the events
+//! connect to [`PIPE_NAME`] instead and receive what the intel buses carry,
+//! each event as a `BaseEvent` behind a little-endian `u32` length. A pipe
+//! does not tear bytes like shared memory can, so unlike ring frames these
+//! carry no magic or CRC.
+//!
+//! Clients only read. One that falls behind loses frames rather than
+//! slowing the buses; the loss is counted in `tap_frames_dropped_total`.
+//! Off Windows the name is a Unix socket path, which is how the tests run.
+
+use std::{
+    io::{self, Read},
+    sync::Arc,
+};
+use metrics::counter;
+use prost::Message;
+use tokio::{
+    io::AsyncWriteExt,
+    runtime::Runtime,
+    sync::broadcast::{self, error::RecvError},
+    task::JoinHandle,
+};
+use shared::events::{
+    base_event::Payload, BaseEvent, EtwEvent, FileEvent, ImageLoadEvent, NetworkEvent, ObjectOpEvent, ProcessEvent,
+    ScanResult,
+};
+
+use super::WrappedEvent;
+use crate::util::Shutdown;
+
+pub const PIPE_NAME: &str = r"\\.\pipe\GladixTap";
+
+/// Frames buffered per client before it starts losing them.
+const CLIENT_BACKLOG: usize = 4_096;
+
+/// Frames larger than this are refused by [`TapClient`].
+const MAX_FRAME: usize = 16 << 20;
+
+/// Intel broadcasts the tap forwards. Types without a bus are left out.
+#[derive(Clone, Default)]
+pub struct TapSources {
+    pub process: Option<broadcast::Sender<WrappedEvent<ProcessEvent>>>,
+    pub file:    Option<broadcast::Sender<WrappedEvent<FileEvent>>>,
+    pub network: Option<broadcast::Sender<WrappedEvent<NetworkEvent>>>,
+    pub etw:     Option<broadcast::Sender<WrappedEvent<EtwEvent>>>,
+    pub scan:    Option<broadcast::Sender<WrappedEvent<ScanResult>>>,
+    pub image:   Option<broadcast::Sender<WrappedEvent<ImageLoadEvent>>>,
+    pub object:  Option<broadcast::Sender<WrappedEvent<ObjectOpEvent>>>,
+}
+
+/// Payloads with a slot in the `BaseEvent` oneof.
+pub trait TapPayload: Clone + Send + 'static {
+    const KIND: &'static str;
+    fn into_payload(self) -> Payload;
+}
+
+macro_rules! tap_payload {
+    ($($ty:ty => $variant:ident, $kind:literal;)*) => {
+        $(
+            impl TapPayload for $ty {
+                const KIND: &'static str = $kind;
+                fn into_payload(self) -> Payload {
+                    Payload::$variant(self)
+                }
+            }
+        )*
+    };
+}
+
+tap_payload! {
+    ProcessEvent   => ProcessEvent,   "process";
+    FileEvent      => FileEvent,      "file";
+    NetworkEvent   => NetworkEvent,   "network";
+    EtwEvent       => EtwEvent,       "etw";
+    ScanResult     => ScanResult,     "scan";
+    ImageLoadEvent => ImageLoadEvent, "image";
+    ObjectOpEvent  => ObjectOpEvent,  "object";
+}
+
+impl<E: TapPayload> From<WrappedEvent<E>> for BaseEvent {
+    fn from(ev: WrappedEvent<E>) -> Self {
+        BaseEvent {
+            ts:          Some(ev.ts),
+            sensor_guid: ev.sensor_guid,
+            seq:         ev.seq.unwrap_or(0),
+            payload:     Some(ev.payload.into_payload()),
+            forward_seq: 0,
+        }
+    }
+}
+
+/// Length prefix and encoding of `ev`.
+pub fn frame(ev: &BaseEvent) -> Vec<u8> {
+    let len = ev.encoded_len();
+    let mut buf = Vec::with_capacity(4 + len);
+    buf.extend_from_slice(&(len as u32).to_le_bytes());
+    ev.encode(&mut buf).expect("buffer has room");
+    buf
+}
+
+/// Serves the events of `sources` on `name` until `shutdown`. Fails if the
+/// endpoint cannot be created, e.g. because another agent already serves it.
+pub fn spawn_event_tap(
+    rt: &Runtime,
+    name: &str,
+    sources: TapSources,
+    shutdown: Shutdown,
+) -> io::Result<JoinHandle<()>> {
+    let mut listener = {
+        let _guard = rt.enter();
+        sys::Listener::bind(name)?
+    };
+    let (frames, _) = broadcast::channel::<Arc<[u8]>>(CLIENT_BACKLOG);
+
+    let TapSources { process, file, network, etw, scan, image, object } = sources;
+    forward(rt, process, &frames, &shutdown);
+    forward(rt, file, &frames, &shutdown);
+    forward(rt, network, &frames, &shutdown);
+    forward(rt, etw, &frames, &shutdown);
+    forward(rt, scan, &frames, &shutdown);
+    forward(rt, image, &frames, &shutdown);
+    forward(rt, object, &frames, &shutdown);
+
+    log::info!("event tap listening on {}", name);
+    Ok(rt.spawn(async move {
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(client) => {
+                        counter!("tap_clients_total").increment(1);
+                        tokio::spawn(serve(client, frames.subscribe(), shutdown.clone()));
+                    }
+                    Err(e) => {
+                        log::warn!("event tap stopped accepting clients: {}", e);
+                        break;
+                    }
+                },
+                _ = shutdown.triggered() => break,
+            }
+        }
+    }))
+}
+
+/// Encodes the events of one bus while a client is connected.
+fn forward<E: TapPayload>(
+    rt: &Runtime,
+    source: Option<broadcast::Sender<WrappedEvent<E>>>,
+    frames: &broadcast::Sender<Arc<[u8]>>,
+    shutdown: &Shutdown,
+) {
+    let Some(source) = source else { return };
+    let (mut rx, frames, shutdown) = (source.subscribe(), frames.clone(), shutdown.clone());
+    rt.spawn(async move {
+        loop {
+            let ev = tokio::select! {
+                ev = rx.recv() => ev,
+                _ = shutdown.triggered() => break,
+            };
+            match ev {
+                Ok(ev) if frames.receiver_count() > 0 => {
+                    let _ = frames.send(frame(&ev.into()).into());
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(n)) => {
+                    counter!("tap_frames_dropped_total", "type" => E::KIND).increment(n);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+async fn serve<S>(mut client: S, mut frames: broadcast::Receiver<Arc<[u8]>>, shutdown: Shutdown)
+where
+    S: tokio::io::AsyncWrite + Unpin,
+{
+    loop {
+        let frame = tokio::select! {
+            frame = frames.recv() => frame,
+            _ = shutdown.triggered() => break,
+        };
+        match frame {
+            Ok(frame) => {
+                // The client went away.
+                if client.write_all(&frame).await.is_err() {
+                    break;
+                }
+            }
+            Err(RecvError::Lagged(n)) => {
+                counter!("tap_frames_dropped_total", "type" => "client").increment(n);
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Blocking reader side of the tap.
+pub struct TapClient {
+    stream: sys::Stream,
+}
+
+impl TapClient {
+    pub fn connect(name: &str) -> io::Result<Self> {
+        sys::connect(name).map(|stream| Self { stream })
+    }
+
+    /// Next event; `None` once the agent closes the stream.
+    pub fn recv(&mut self) -> io::Result<Option<BaseEvent>> {
+        read_event(&mut self.stream)
+    }
+}
+
+/// Reads one frame written by the tap. `None` on a clean end of stream.
+pub fn read_event(r: &mut impl Read) -> io::Result<Option<BaseEvent>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame of {len} bytes")));
+    }
+    let mut buf = vec![0u8; len];
+    r.read_exact(&mut buf)?;
+    BaseEvent::decode(buf.as_slice())
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use std::{io, os::unix::net::UnixStream};
+    use tokio::net::{UnixListener, UnixStream as AsyncStream};
+
+    pub type Stream = UnixStream;
+
+    pub struct Listener(UnixListener);
+
+    impl Listener {
+        /// A stale socket left by a previous run is replaced.
+        pub fn bind(path: &str) -> io::Result<Self> {
+            let _ = std::fs::remove_file(path);
+            UnixListener::bind(path).map(Self)
+        }
+
+        pub async fn accept(&mut self) -> io::Result<AsyncStream> {
+            self.0.accept().await.map(|(stream, _)| stream)
+        }
+    }
+
+    pub fn connect(path: &str) -> io::Result<Stream> {
+        UnixStream::connect(path)
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{fs::{File, OpenOptions}, io};
+    use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+
+    pub type Stream = File;
+
+    /// The instance waiting for the next client.
+    pub struct Listener {
+        name: String,
+        next: NamedPipeServer,
+    }
+
+    impl Listener {
+        /// Fails if another process already owns the pipe name. Remote
+        /// clients are refused (the `ServerOptions` default).
+        pub fn bind(name: &str) -> io::Result<Self> {
+            let next = ServerOptions::new().first_pipe_instance(true).access_inbound(false).create(name)?;
+            Ok(Self { name: name.to_owned(), next })
+        }
+
+        pub async fn accept(&mut self) -> io::Result<NamedPipeServer> {
+            self.next.connect().await?;
+            let fresh = ServerOptions::new().access_inbound(false).create(&self.name)?;
+            Ok(std::mem::replace(&mut self.next, fresh))
+        }
+    }
+
+    pub fn connect(name: &str) -> io::Result<Stream> {
+        OpenOptions::new().read(true).open(name)
+    }
+}
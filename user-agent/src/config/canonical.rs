@@ -0,0 +1,191 @@
+// src/config/canonical.rs
+//! Canonical form of the effective configuration.
+//!
+//! Fleet comparison (`gladix-cli config fingerprint|diff`) and drift detection
+//! both go through [`canonicalize`], so they always agree on whether two hosts
+//! run the same config. The canonical form is the default-filled effective
+//! config as JSON with sorted keys, scanner groups in a stable order and
+//! volatile keys (see [`metadata`](super::metadata)) removed.
+
+use std::{collections::BTreeMap, fmt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use super::{
+    metadata::{self, Reload},
+    model::Config,
+};
+
+pub fn canonicalize(cfg: &Config) -> Value {
+    let raw = serde_json::to_value(cfg).expect("Config always serialises to JSON");
+    normalize(raw, "")
+}
+
+fn normalize(v: Value, path: &str) -> Value {
+    match v {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            let mut out = Map::new();
+            for (k, v) in entries {
+                let key = if path.is_empty() { k.clone() } else { format!("{path}.{k}") };
+                if !metadata::is_volatile(&key) {
+                    out.insert(k, normalize(v, &key));
+                }
+            }
+            Value::Object(out)
+        }
+        Value::Array(items) => {
+            let mut items: Vec<Value> = items
+                .into_iter()
+                .map(|v| normalize(v, &format!("{path}[]")))
+                .collect();
+            // TOML arrays of tables carry no meaning in their order.
+            if path == "scanner" || path == "notification" {
+                items.sort_by_key(|v| v.to_string());
+            }
+            Value::Array(items)
+        }
+        other => other,
+    }
+}
+
+fn digest(v: &Value) -> String {
+    hex::encode(Sha256::digest(v.to_string().as_bytes()))
+}
+
+/// Hash of the whole canonical config plus one per top-level section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub digest:   String,
+    pub sections: BTreeMap<String, String>,
+}
+
+impl Fingerprint {
+    pub fn of(canonical: &Value) -> Self {
+        let sections = canonical
+            .as_object()
+            .map(|m| m.iter().map(|(k, v)| (k.clone(), digest(v))).collect())
+            .unwrap_or_default();
+        Self { digest: digest(canonical), sections }
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.digest)?;
+        for (section, hash) in &self.sections {
+            writeln!(f, "  {section:<10} {hash}")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn fingerprint(cfg: &Config) -> Fingerprint {
+    Fingerprint::of(&canonicalize(cfg))
+}
+
+/// File exchanged between hosts for `config diff`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigExport {
+    pub fingerprint: Fingerprint,
+    pub config:      Value,
+}
+
+impl ConfigExport {
+    pub fn new(cfg: &Config) -> Self {
+        let config = canonicalize(cfg);
+        Self { fingerprint: Fingerprint::of(&config), config }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Only present in the other config.
+    Added(Value),
+    /// Only present in the local config.
+    Removed(Value),
+    Changed { local: Value, other: Value },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyDiff {
+    pub key:    String,
+    pub change: Change,
+    pub reload: Reload,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectionDiff {
+    pub section: String,
+    pub changes: Vec<KeyDiff>,
+}
+
+fn flatten(v: &Value, path: String, out: &mut BTreeMap<String, Value>) {
+    match v {
+        Value::Object(map) => {
+            for (k, v) in map {
+                let key = if path.is_empty() { k.clone() } else { format!("{path}.{k}") };
+                flatten(v, key, out);
+            }
+        }
+        Value::Array(items) if items.iter().any(|i| i.is_object() || i.is_array()) => {
+            for (i, v) in items.iter().enumerate() {
+                flatten(v, format!("{path}[{i}]"), out);
+            }
+        }
+        leaf => {
+            out.insert(path, leaf.clone());
+        }
+    }
+}
+
+fn section_of(key: &str) -> &str {
+    key.split(['.', '[']).next().unwrap_or(key)
+}
+
+/// Key-level differences between two canonical configs, grouped by section.
+pub fn diff(local: &Value, other: &Value) -> Vec<SectionDiff> {
+    let (mut a, mut b) = (BTreeMap::new(), BTreeMap::new());
+    flatten(local, String::new(), &mut a);
+    flatten(other, String::new(), &mut b);
+
+    let mut sections: BTreeMap<String, Vec<KeyDiff>> = BTreeMap::new();
+    let keys: std::collections::BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    for key in keys {
+        let change = match (a.get(key), b.get(key)) {
+            (Some(l), Some(o)) if l == o => continue,
+            (Some(l), Some(o)) => Change::Changed { local: l.clone(), other: o.clone() },
+            (Some(l), None)    => Change::Removed(l.clone()),
+            (None, Some(o))    => Change::Added(o.clone()),
+            (None, None)       => continue,
+        };
+        sections.entry(section_of(key).to_string()).or_default().push(KeyDiff {
+            key: key.clone(),
+            change,
+            reload: metadata::reload_class(key),
+        });
+    }
+    sections
+        .into_iter()
+        .map(|(section, changes)| SectionDiff { section, changes })
+        .collect()
+}
+
+/// Human-readable rendering of [`diff`]; empty when the configs match.
+pub fn render_diff(diffs: &[SectionDiff]) -> String {
+    let mut out = String::new();
+    for s in diffs {
+        out.push_str(&format!("[{}]\n", s.section));
+        for d in &s.changes {
+            let line = match &d.change {
+                Change::Added(v)                 => format!("  + {} = {}", d.key, v),
+                Change::Removed(v)               => format!("  - {} = {}", d.key, v),
+                Change::Changed { local, other } => format!("  ~ {}: {} -> {}", d.key, local, other),
+            };
+            out.push_str(&format!("{line}  ({})\n", d.reload));
+        }
+    }
+    out
+}
@@ -0,0 +1,183 @@
+// src/config/env.rs
+
+//! `GLADIX_`-prefixed environment variables layered over the TOML.
+//!
+//! `GLADIX_DATABASE__FLUSH_INTERVAL_MS=100` sets `database.flush_interval_ms`:
+//! `__` separates the lower-cased path segments. Every key is in a section,
+//! so a variable without one (`GLADIX_PROFILE`, set by the build) is not an
+//! override. A number picks an element of an array of tables, one past the
+//! end appends it, so `GLADIX_SCANNER__0__DIRS` is the first `[[scanner]]`
+//! group.
+//!
+//! A value replacing one from the file takes its type, and fails with
+//! [`ConfigError::InvalidValue`] if it does not read as one. A key the file
+//! leaves out takes the type the value reads as: `true`/`false`, an integer,
+//! a number with a decimal point, else a string. Lists are `;`-separated; a
+//! trailing `;` makes a list of one.
+
+use std::cmp::Ordering;
+use toml::{Table, Value};
+
+use crate::config::model::ConfigError;
+
+/// What every variable read here starts with.
+pub const PREFIX: &str = "GLADIX_";
+const SEPARATOR: &str = "__";
+const LIST_SEPARATOR: char = ';';
+
+/// One `__`-separated part of a variable name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl Segment {
+    fn parse(s: &str) -> Self {
+        match s.parse() {
+            Ok(i) => Self::Index(i),
+            Err(_) => Self::Key(s.to_ascii_lowercase()),
+        }
+    }
+}
+
+/// Indexes in numeric order, so element 2 is appended before element 10.
+impl Ord for Segment {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Index(a), Self::Index(b)) => a.cmp(b),
+            (Self::Index(_), Self::Key(_)) => Ordering::Less,
+            (Self::Key(_), Self::Index(_)) => Ordering::Greater,
+            (Self::Key(a), Self::Key(b)) => a.cmp(b),
+        }
+    }
+}
+
+impl PartialOrd for Segment {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Dotted key for error messages: `scanner[0].dirs`.
+fn dotted(path: &[Segment]) -> String {
+    let mut key = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(k) if key.is_empty() => key.push_str(k),
+            Segment::Key(k) => {
+                key.push('.');
+                key.push_str(k);
+            }
+            Segment::Index(i) => key.push_str(&format!("[{i}]")),
+        }
+    }
+    key
+}
+
+/// The segments of an override's name, `None` for any other variable.
+fn path(name: &str) -> Option<Vec<Segment>> {
+    let rest = name.strip_prefix(PREFIX)?;
+    rest.contains(SEPARATOR).then(|| rest.split(SEPARATOR).map(Segment::parse).collect())
+}
+
+/// The overrides set in the process environment.
+pub fn vars() -> Vec<(String, String)> {
+    std::env::vars().filter(|(name, _)| path(name).is_some()).collect()
+}
+
+/// Sets every override in `vars` on `table`; other variables are ignored.
+pub fn apply(table: &mut Table, vars: impl IntoIterator<Item = (String, String)>) -> Result<(), ConfigError> {
+    let mut overrides: Vec<_> = vars.into_iter().filter_map(|(name, value)| Some((path(&name)?, name, value))).collect();
+    overrides.sort();
+
+    for (path, name, value) in overrides {
+        let invalid = |reason: String| ConfigError::InvalidValue(dotted(&path), format!("{reason} (from {name})"));
+        let (last, parents) = match path.split_last() {
+            Some((Segment::Key(last), parents)) if !last.is_empty() => (last, parents),
+            _ => return Err(invalid("must end in a key".into())),
+        };
+        let parent = walk(table, parents).map_err(invalid)?;
+        let value = coerce(&value, parent.get(last)).map_err(invalid)?;
+        parent.insert(last.clone(), value);
+    }
+    Ok(())
+}
+
+/// The table at `path`, created as needed.
+fn walk<'t>(table: &'t mut Table, path: &[Segment]) -> Result<&'t mut Table, String> {
+    let Some((first, rest)) = path.split_first() else { return Ok(table) };
+    let Segment::Key(key) = first else { return Err("must start with a key".into()) };
+    let fresh = match rest.first() {
+        Some(Segment::Index(_)) => Value::Array(Vec::new()),
+        _ => Value::Table(Table::new()),
+    };
+    let mut value = table.entry(key.clone()).or_insert(fresh);
+    let mut rest = rest;
+    while let Some(Segment::Index(i)) = rest.first() {
+        let Value::Array(items) = value else { return Err(format!("'{key}' is not a list")) };
+        if *i == items.len() {
+            items.push(Value::Table(Table::new()));
+        }
+        let len = items.len();
+        value = items.get_mut(*i).ok_or_else(|| format!("index {i} is past the end ({len} in '{key}')"))?;
+        rest = &rest[1..];
+    }
+    match value {
+        Value::Table(inner) => walk(inner, rest),
+        _ => Err(format!("'{key}' is not a table")),
+    }
+}
+
+/// `raw` as a value of the kind of `like`, or inferred without one.
+fn coerce(raw: &str, like: Option<&Value>) -> Result<Value, String> {
+    let trimmed = raw.trim();
+    match like {
+        None if raw.contains(LIST_SEPARATOR) => list(raw, None),
+        None => Ok(infer(trimmed)),
+        Some(Value::String(_)) => Ok(Value::String(raw.into())),
+        Some(Value::Integer(_)) => {
+            trimmed.parse().map(Value::Integer).map_err(|_| format!("'{raw}' is not an integer"))
+        }
+        Some(Value::Float(_)) => trimmed.parse().map(Value::Float).map_err(|_| format!("'{raw}' is not a number")),
+        Some(Value::Boolean(_)) => boolean(trimmed).map(Value::Boolean).ok_or_else(|| format!("'{raw}' is not true or false")),
+        Some(Value::Datetime(_)) => {
+            trimmed.parse().map(Value::Datetime).map_err(|_| format!("'{raw}' is not a date-time"))
+        }
+        Some(Value::Array(items)) => list(raw, items.first()),
+        Some(Value::Table(_)) => Err("is a table; set its keys instead".into()),
+    }
+}
+
+fn list(raw: &str, like: Option<&Value>) -> Result<Value, String> {
+    raw.split(LIST_SEPARATOR)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| match like {
+            Some(like) => coerce(item, Some(like)),
+            None => Ok(infer(item)),
+        })
+        .collect::<Result<_, _>>()
+        .map(Value::Array)
+}
+
+fn boolean(s: &str) -> Option<bool> {
+    match s.to_ascii_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn infer(s: &str) -> Value {
+    if let Ok(b) = s.parse() {
+        return Value::Boolean(b);
+    }
+    if let Ok(i) = s.parse() {
+        return Value::Integer(i);
+    }
+    match s.parse() {
+        Ok(f) if s.contains('.') => Value::Float(f),
+        _ => Value::String(s.into()),
+    }
+}
@@ -3,19 +3,50 @@
 //! Reads `config.toml` into our `model::Config`
 
 use crate::config::model::{
-    Config, ConfigError, DatabaseConfig, DirectoryRisk,
-    LoggingConfig, RiskGroup, RiskStub,
+    ActionsConfig, AnalyticsConfig, CommunicationsConfig, Config, ConfigError, DatabaseConfig, DetectionConfig,
+    DirectoryRisk, DnsConfig, EtwConfig, ExportConfig, FileHashConfig, ForwarderConfig, HeartbeatConfig, LimitsConfig, NetPolicyRule,
+    LoggingConfig, MetricsConfig, NotificationChannel, ProbeConfig, ProbeStub, ReportGroup,
+    ReportsConfig, ReportsStub, RingConfig, RiskGroup, RiskStub, ScanningConfig, SchedulingConfig, FORWARD_PAYLOADS, RING_PAYLOADS,
 };
+use crate::db::event_types::{event_table, EVENT_TYPES};
+use crate::etw::Guid;
+use shared::constants::{RING_SIZE_MAX, RING_SIZE_MIN};
+use crate::intel::detection::Detection;
+use crate::policy::NetPolicy;
 use humantime::parse_duration;
-use std::{fs, path::Path, str::FromStr};
+use crate::config::env;
+use std::{collections::HashSet, fs, io, path::Path, str::FromStr, time::Duration};
 
-/// Entry point: read the file, parse, convert, validate.
+/// Entry point: read the file, apply `GLADIX_` overrides, parse, convert,
+/// validate. A missing file reads as empty when overrides are set, so the
+/// environment alone can configure the agent.
 pub fn load(path: &Path) -> Result<Config, ConfigError> {
+    let vars = env::vars();
     // 1. Read file (IO errors become ConfigError::Io)
-    let text = fs::read_to_string(path)?;
+    let text = match fs::read_to_string(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !vars.is_empty() => String::new(),
+        read => read?,
+    };
+    parse_with(&text, vars)
+}
+
+/// Same as [`load`] for TOML already in memory, without the environment.
+pub fn parse(text: &str) -> Result<Config, ConfigError> {
+    parse_with(text, [])
+}
 
+/// [`parse`] with the `GLADIX_` variables in `vars` set over the TOML; see
+/// [`env`] for how names and values are read.
+pub fn parse_with(text: &str, vars: impl IntoIterator<Item = (String, String)>) -> Result<Config, ConfigError> {
     // 2. Raw deserialization (TOML errors become ConfigError::Toml)
-    let raw: Raw = toml::from_str(&text)?;
+    let mut vars = vars.into_iter().peekable();
+    let raw: Raw = if vars.peek().is_none() {
+        toml::from_str(text)?
+    } else {
+        let mut table: toml::Table = toml::from_str(text)?;
+        env::apply(&mut table, vars)?;
+        toml::Value::Table(table).try_into()?
+    };
 
     // 3. Convert to runtime types
     let mut groups = Vec::new();
@@ -40,21 +71,332 @@ pub fn load(path: &Path) -> Result<Config, ConfigError> {
             risk,
             directories,
             interval,
+            hydrate_placeholders: stub.hydrate_placeholders,
+            hash: stub.hash,
+            exclude: stub.exclude,
+            follow_symlinks: stub.follow_symlinks,
+            max_depth: stub.max_depth,
+            yara: stub.yara,
         });
     }
 
-    Ok(Config {
+    // 4. Validate notification routing
+    let mut names = HashSet::new();
+    for ch in &raw.notifications {
+        if !names.insert(ch.name.as_str()) {
+            return Err(ConfigError::InvalidNotification(ch.name.clone(), "duplicate name".into()));
+        }
+        if ch.min_severity > ch.max_severity {
+            return Err(ConfigError::InvalidNotification(
+                ch.name.clone(),
+                format!("min_severity {} is above max_severity {}", ch.min_severity, ch.max_severity),
+            ));
+        }
+    }
+
+    // 5. Probe settings, defaults filled in
+    let probe = probe_config(raw.probe)?;
+
+    // 6. Reports, against the groups and channels above
+    let reports = reports_config(raw.reports, &groups, &names)?;
+
+    let mut config = Config {
         logging:  raw.logging,
         database: raw.database,
         scanner:  groups,
+        notifications: raw.notifications,
+        probe,
+        analytics: raw.analytics,
+        detection: raw.detection,
+        metrics:  raw.metrics,
+        scheduling: raw.scheduling,
+        actions:  raw.actions,
+        reports,
+        scanning: raw.scanning,
+        communications: raw.communications,
+        limits:   raw.limits,
+        ring:     raw.ring,
+        export:   raw.export,
+        forwarder: raw.forwarder,
+        network_policy: raw.network_policy,
+        etw:      raw.etw,
+        heartbeat: raw.heartbeat,
+        file_hash: raw.file_hash,
+        dns:      raw.dns,
+    };
+
+    // 7. Ranges the runtime relies on
+    config.validate()?;
+    Ok(config)
+}
+
+/// Shortest `database.flush_interval_ms` accepted.
+const MIN_FLUSH_INTERVAL_MS: u64 = 10;
+/// Largest `database.batch_size` accepted.
+const MAX_BATCH_SIZE: usize = 100_000;
+/// `PRAGMA synchronous` levels accepted, in canonical spelling.
+const SYNCHRONOUS: [&str; 3] = ["FULL", "NORMAL", "OFF"];
+
+impl Config {
+    /// Rejects values that would panic or misbehave at runtime and
+    /// normalizes `database.synchronous` to upper case. Run by [`parse`].
+    pub fn validate(&mut self) -> Result<(), ConfigError> {
+        let invalid = |field: &str, reason: String| Err(ConfigError::InvalidValue(field.into(), reason));
+        let db = &mut self.database;
+
+        match SYNCHRONOUS.iter().find(|s| s.eq_ignore_ascii_case(db.synchronous.trim())) {
+            Some(s) => db.synchronous = (*s).into(),
+            None => return invalid("database.synchronous", format!("'{}' is not one of {}", db.synchronous, SYNCHRONOUS.join(", "))),
+        }
+        if db.flush_interval_ms < MIN_FLUSH_INTERVAL_MS {
+            return invalid("database.flush_interval_ms", format!("must be at least {MIN_FLUSH_INTERVAL_MS}"));
+        }
+        if db.batch_size == 0 || db.batch_size > MAX_BATCH_SIZE {
+            return invalid("database.batch_size", format!("must be between 1 and {MAX_BATCH_SIZE}"));
+        }
+        if db.channel_capacity == 0 {
+            return invalid("database.channel_capacity", "must be positive".into());
+        }
+        if db.flush_retry_max_ms < db.flush_interval_ms {
+            return invalid("database.flush_retry_max_ms", format!("below database.flush_interval_ms ({})", db.flush_interval_ms));
+        }
+        if db.pending_max_rows < db.batch_size {
+            return invalid("database.pending_max_rows", format!("below database.batch_size ({})", db.batch_size));
+        }
+        if db.checkpoint_seconds == 0 {
+            return invalid("database.checkpoint_seconds", "must be positive".into());
+        }
+        if db.maintenance_hour.is_some_and(|h| h > 23) {
+            return invalid("database.maintenance_hour", "must be between 0 and 23".into());
+        }
+        // 0 turns the TTL off.
+        if db.ttl_seconds != 0 && db.ttl_seconds < db.checkpoint_seconds {
+            return invalid("database.ttl_seconds", format!("below database.checkpoint_seconds ({})", db.checkpoint_seconds));
+        }
+        let tables = || EVENT_TYPES.iter().map(|t| t.table).collect::<Vec<_>>().join(", ");
+        for name in db.retention.events.keys() {
+            if event_table(name).is_none() {
+                return invalid(&format!("database.retention.{name}"), format!("unknown event table; expected one of {}", tables()));
+            }
+        }
+        if let Some(name) = db.purge_on_restart.tables().iter().find(|n| event_table(n).is_none()) {
+            return invalid("database.purge_on_restart", format!("unknown event table '{name}'; expected one of {}", tables()));
+        }
+
+        for g in &self.scanner {
+            let field = |key: &str| format!("scanner.{}.{key}", g.risk.as_str());
+            if g.directories.is_empty() {
+                return invalid(&field("dirs"), "no directories".into());
+            }
+            if g.interval.is_some_and(|i| i.is_zero()) {
+                return invalid(&field("interval"), "must be positive".into());
+            }
+            if let Some(Err(e)) = g.exclude.iter().map(|p| globset::Glob::new(p)).find(Result::is_err) {
+                return invalid(&field("exclude"), e.to_string());
+            }
+            if g.yara && self.scanning.rules_dir.is_none() {
+                return invalid(&field("yara"), "needs scanning.rules_dir".into());
+            }
+        }
+
+        if self.detection.reload_secs == 0 {
+            return invalid("detection.reload_secs", "must be positive".into());
+        }
+        // Compiled again by the engine; a rule that does not compile fails here.
+        Detection::compile(&self.detection)?;
+
+        if let Some(url) = &self.metrics.push_gateway_url
+            && !(url.starts_with("http://") || url.starts_with("https://"))
+        {
+            return invalid("metrics.push_gateway_url", format!("'{url}' is not an http(s) URL"));
+        }
+        if let Err(e) = self.communications.grpc_bind.parse::<std::net::SocketAddr>() {
+            return invalid("communications.grpc_bind", format!("'{}': {e}", self.communications.grpc_bind));
+        }
+        for (payload, limit) in &self.limits.payloads {
+            let field = format!("limits.{payload}");
+            if !RING_PAYLOADS.contains(&payload.as_str()) {
+                return invalid(&field, format!("unknown payload; expected one of {}", RING_PAYLOADS.join(", ")));
+            }
+            if limit.per_sec == 0 || limit.burst == 0 {
+                return invalid(&field, "per_sec and burst must be positive".into());
+            }
+        }
+        if self.export.enable {
+            if self.export.path.trim().is_empty() {
+                return invalid("export.path", "must not be empty".into());
+            }
+            if self.export.rotate_mb == 0 {
+                return invalid("export.rotate_mb", "must be positive".into());
+            }
+        }
+        if self.forwarder.enabled {
+            let forwarder = &self.forwarder;
+            let tls = forwarder.endpoint.starts_with("https://");
+            if !tls && !forwarder.endpoint.starts_with("http://") {
+                return invalid("forwarder.endpoint", format!("'{}' is not an http:// or https:// URL", forwarder.endpoint));
+            }
+            if forwarder.types.is_empty() {
+                return invalid("forwarder.types", "no payload types".into());
+            }
+            if let Some(t) = forwarder.types.iter().find(|t| !FORWARD_PAYLOADS.contains(&t.as_str())) {
+                return invalid("forwarder.types", format!("unknown payload '{t}'; expected one of {}", FORWARD_PAYLOADS.join(", ")));
+            }
+            if forwarder.batch_size == 0 {
+                return invalid("forwarder.batch_size", "must be positive".into());
+            }
+            if forwarder.spool_path.trim().is_empty() {
+                return invalid("forwarder.spool_path", "must not be empty".into());
+            }
+            if forwarder.spool_mb == 0 {
+                return invalid("forwarder.spool_mb", "must be positive".into());
+            }
+            if forwarder.client_cert.is_some() != forwarder.client_key.is_some() {
+                return invalid("forwarder.client_key", "client_cert and client_key go together".into());
+            }
+            if !tls && (forwarder.ca_cert.is_some() || forwarder.client_cert.is_some()) {
+                return invalid("forwarder.endpoint", "certificates need an https:// endpoint".into());
+            }
+        }
+        // Compiled again at startup; a rule that does not compile fails here.
+        NetPolicy::compile(&self.network_policy)?;
+        if self.etw.enabled {
+            if self.etw.session.trim().is_empty() {
+                return invalid("etw.session", "must not be empty".into());
+            }
+            if self.etw.providers.is_empty() {
+                return invalid("etw.providers", "enabled without providers".into());
+            }
+        }
+        for (i, provider) in self.etw.providers.iter().enumerate() {
+            if let Err(e) = provider.guid.parse::<Guid>() {
+                return invalid(&format!("etw.providers[{i}].guid"), e);
+            }
+            if !(1..=5).contains(&provider.level) {
+                return invalid(&format!("etw.providers[{i}].level"), "must be 1 (critical) to 5 (verbose)".into());
+            }
+        }
+        if self.ring.size_bytes.is_some_and(|bytes| !(RING_SIZE_MIN..=RING_SIZE_MAX).contains(&bytes)) {
+            return invalid("ring.size_bytes", format!("must be {RING_SIZE_MIN} to {RING_SIZE_MAX}"));
+        }
+        if self.ring.max_events_per_burst == 0 {
+            return invalid("ring.max_events_per_burst", "must be positive".into());
+        }
+        if self.heartbeat.enabled && self.heartbeat.interval_ms == 0 {
+            return invalid("heartbeat.interval_ms", "must be positive".into());
+        }
+        if self.file_hash.enabled && self.file_hash.threads == 0 {
+            return invalid("file_hash.threads", "must be positive".into());
+        }
+        if self.dns.enabled {
+            if self.dns.cache_entries == 0 {
+                return invalid("dns.cache_entries", "must be positive".into());
+            }
+            if self.dns.lookups_per_sec == 0 {
+                return invalid("dns.lookups_per_sec", "must be positive".into());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn probe_config(stub: ProbeStub) -> Result<ProbeConfig, ConfigError> {
+    let defaults = ProbeConfig::default();
+    let duration = |v: Option<String>, default| {
+        v.map(|s| parse_duration(&s).map_err(|e| ConfigError::InvalidDuration(s.clone(), e)))
+            .transpose()
+            .map(|d| d.unwrap_or(default))
+    };
+    Ok(ProbeConfig {
+        enabled:           stub.enabled,
+        interval:          duration(stub.interval, defaults.interval)?,
+        timeout:           duration(stub.timeout, defaults.timeout)?,
+        failure_threshold: stub.failure_threshold.unwrap_or(defaults.failure_threshold).max(1),
+        helper:            stub.helper.map(Into::into).unwrap_or(defaults.helper),
+        temp_dir:          stub.temp_dir.map(Into::into),
+    })
+}
+
+fn reports_config(
+    stub: ReportsStub,
+    scanner: &[RiskGroup],
+    channels: &HashSet<&str>,
+) -> Result<ReportsConfig, ConfigError> {
+    let defaults = ReportsConfig::default();
+    let mut groups: Vec<ReportGroup> = Vec::new();
+    for g in stub.groups {
+        let risk = DirectoryRisk::from_str(&g.risk)?;
+        let invalid = |msg: String| ConfigError::InvalidReport(g.risk.clone(), msg);
+        if !scanner.iter().any(|s| s.risk == risk) {
+            return Err(invalid("no [[scanner]] group with this risk".into()));
+        }
+        if groups.iter().any(|r| r.risk == risk) {
+            return Err(invalid("duplicate group".into()));
+        }
+        if let Some(ch) = g.channels.iter().find(|c| !channels.contains(c.as_str())) {
+            return Err(invalid(format!("unknown notification channel '{ch}'")));
+        }
+        let interval = match g.interval.as_str() {
+            "daily"  => Duration::from_secs(24 * 3600),
+            "weekly" => Duration::from_secs(7 * 24 * 3600),
+            other    => parse_duration(other).map_err(|e| ConfigError::InvalidDuration(other.into(), e))?,
+        };
+        if interval.is_zero() {
+            return Err(invalid("interval must be positive".into()));
+        }
+        groups.push(ReportGroup { risk, interval, channels: g.channels });
+    }
+    Ok(ReportsConfig {
+        enabled:     stub.enabled,
+        max_entries: stub.max_entries.unwrap_or(defaults.max_entries).max(1),
+        groups,
     })
 }
 
 /// Mirrors the top-level TOML
 #[derive(serde::Deserialize)]
 struct Raw {
+    #[serde(default)]
     pub logging:  LoggingConfig,
     pub database: DatabaseConfig,
     #[serde(rename = "scanner")]
     pub scanner:  Vec<RiskStub>,
+    #[serde(default, rename = "notification")]
+    pub notifications: Vec<NotificationChannel>,
+    #[serde(default)]
+    pub probe:    ProbeStub,
+    #[serde(default)]
+    pub analytics: AnalyticsConfig,
+    #[serde(default)]
+    pub detection: DetectionConfig,
+    #[serde(default)]
+    pub metrics:  MetricsConfig,
+    #[serde(default)]
+    pub scheduling: SchedulingConfig,
+    #[serde(default)]
+    pub actions:  ActionsConfig,
+    #[serde(default)]
+    pub reports:  ReportsStub,
+    #[serde(default)]
+    pub scanning: ScanningConfig,
+    #[serde(default)]
+    pub communications: CommunicationsConfig,
+    #[serde(default)]
+    pub limits:   LimitsConfig,
+    #[serde(default)]
+    pub ring:     RingConfig,
+    #[serde(default)]
+    pub export:   ExportConfig,
+    #[serde(default)]
+    pub forwarder: ForwarderConfig,
+    #[serde(default)]
+    pub network_policy: Vec<NetPolicyRule>,
+    #[serde(default)]
+    pub etw:      EtwConfig,
+    #[serde(default)]
+    pub heartbeat: HeartbeatConfig,
+    #[serde(default)]
+    pub file_hash: FileHashConfig,
+    #[serde(default)]
+    pub dns:      DnsConfig,
 }
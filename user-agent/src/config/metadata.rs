@@ -0,0 +1,121 @@
+// src/config/metadata.rs
+//! Registry of configuration keys: how a change is applied and whether the
+//! value is host-specific.
+//!
+//! Keys are dotted paths into the canonical config (`database.ttl_seconds`).
+//! An entry for a section (`scanner`) covers every key below it.
+
+use std::fmt;
+
+/// How a changed value takes effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reload {
+    /// Picked up by the running agent.
+    Hot,
+    /// Needs a service restart.
+    Restart,
+}
+
+impl fmt::Display for Reload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reload::Hot     => "hot",
+            Reload::Restart => "restart",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct KeyMeta {
+    pub key:      &'static str,
+    pub reload:   Reload,
+    /// Host-specific; excluded from fingerprints and fleet diffs.
+    pub volatile: bool,
+}
+
+const fn meta(key: &'static str, reload: Reload, volatile: bool) -> KeyMeta {
+    KeyMeta { key, reload, volatile }
+}
+
+pub const REGISTRY: &[KeyMeta] = &[
+    meta("logging.enable",              Reload::Restart, false),
+    meta("logging.file",                Reload::Restart, true),
+    meta("logging.level",               Reload::Restart, false),
+    meta("logging.eventlog",            Reload::Restart, false),
+    meta("database.path",               Reload::Restart, true),
+    meta("database.purge_on_restart",   Reload::Restart, false),
+    meta("database.synchronous",        Reload::Restart, false),
+    meta("database.journal_size_limit", Reload::Restart, false),
+    meta("database.checkpoint_seconds", Reload::Restart, false),
+    meta("database.ttl_seconds",        Reload::Restart, false),
+    meta("database.flush_interval_ms",  Reload::Restart, false),
+    meta("database.batch_size",         Reload::Restart, false),
+    meta("database.page_size",          Reload::Restart, false),
+    meta("database.cache_kb",           Reload::Restart, false),
+    meta("database.compress_columns",   Reload::Restart, false),
+    meta("database.compress_threshold", Reload::Restart, false),
+    meta("database.cleanup_interval_seconds", Reload::Restart, false),
+    meta("database.retention",          Reload::Restart, false),
+    meta("database.snapshots",          Reload::Restart, false),
+    meta("database.snapshots.dir",      Reload::Restart, true),
+    meta("database.channel_capacity",   Reload::Restart, false),
+    meta("database.overflow_max_kb",    Reload::Restart, false),
+    meta("database.flush_retry_max_ms", Reload::Restart, false),
+    meta("database.pending_max_rows",   Reload::Restart, false),
+    meta("database.coalesce_window_ms", Reload::Restart, false),
+    meta("database.maintenance_hour",   Reload::Restart, false),
+    meta("database.vacuum_after_deleted_rows", Reload::Restart, false),
+    meta("scanner",                     Reload::Restart, false),
+    meta("scanning",                    Reload::Restart, false),
+    meta("notification",                Reload::Restart, false),
+    meta("probe",                       Reload::Restart, false),
+    meta("probe.temp_dir",              Reload::Restart, true),
+    meta("analytics",                   Reload::Restart, false),
+    meta("detection",                   Reload::Hot,     false),
+    meta("detection.enabled",           Reload::Restart, false),
+    meta("detection.reload_secs",       Reload::Restart, false),
+    meta("metrics",                     Reload::Restart, false),
+    meta("scheduling",                  Reload::Restart, false),
+    meta("actions",                     Reload::Restart, false),
+    meta("actions.memdump.dir",         Reload::Restart, true),
+    meta("reports",                     Reload::Restart, false),
+    meta("communications.tap",          Reload::Restart, false),
+    meta("communications.grpc_bind",    Reload::Restart, true),
+    meta("communications.allow_remote", Reload::Restart, false),
+    meta("communications.intel_replay_events",  Reload::Restart, false),
+    meta("communications.intel_replay_max_age", Reload::Restart, false),
+    meta("limits",                      Reload::Restart, false),
+    meta("ring.replay",                 Reload::Restart, false),
+    meta("export",                      Reload::Restart, false),
+    meta("export.path",                 Reload::Restart, true),
+    meta("forwarder",                   Reload::Restart, false),
+    meta("forwarder.spool_path",        Reload::Restart, true),
+    meta("forwarder.ca_cert",           Reload::Restart, true),
+    meta("forwarder.client_cert",       Reload::Restart, true),
+    meta("forwarder.client_key",        Reload::Restart, true),
+    meta("network_policy",              Reload::Restart, false),
+    meta("etw",                         Reload::Restart, false),
+    meta("heartbeat",                   Reload::Restart, false),
+    meta("file_hash",                   Reload::Restart, false),
+    meta("dns",                         Reload::Restart, false),
+];
+
+/// Most specific registry entry covering `key`.
+pub fn lookup(key: &str) -> Option<&'static KeyMeta> {
+    REGISTRY
+        .iter()
+        .filter(|m| {
+            key == m.key
+                || key.strip_prefix(m.key).is_some_and(|rest| rest.starts_with(['.', '[']))
+        })
+        .max_by_key(|m| m.key.len())
+}
+
+/// Unknown keys are assumed to need a restart.
+pub fn reload_class(key: &str) -> Reload {
+    lookup(key).map_or(Reload::Restart, |m| m.reload)
+}
+
+pub fn is_volatile(key: &str) -> bool {
+    lookup(key).is_some_and(|m| m.volatile)
+}
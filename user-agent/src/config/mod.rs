@@ -1,7 +1,11 @@
 //! Public API for configuration
 
+pub mod canonical;
+pub mod env;
 pub mod loader;
+pub mod metadata;
 pub mod model;
+pub mod provision;
 
 // Re-export the main entrypoints:
 pub use loader::load;
@@ -1,59 +1,1223 @@
 // src/config/model.rs
 
-use serde::Deserialize;
-use std::{path::PathBuf, str::FromStr, time::Duration};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::BTreeMap, fmt, net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
 use thiserror::Error;
+use crate::intel::Severity;
 
 /// Top-level runtime config
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Config {
     pub logging:  LoggingConfig,
     pub database: DatabaseConfig,
     pub scanner:  Vec<RiskGroup>,
+    #[serde(rename = "notification")]
+    pub notifications: Vec<NotificationChannel>,
+    pub probe:    ProbeConfig,
+    pub analytics: AnalyticsConfig,
+    pub detection: DetectionConfig,
+    pub metrics:  MetricsConfig,
+    pub scheduling: SchedulingConfig,
+    pub actions:  ActionsConfig,
+    pub reports:  ReportsConfig,
+    pub scanning: ScanningConfig,
+    pub communications: CommunicationsConfig,
+    pub limits:   LimitsConfig,
+    pub ring:     RingConfig,
+    pub export:   ExportConfig,
+    pub forwarder: ForwarderConfig,
+    pub network_policy: Vec<NetPolicyRule>,
+    pub etw:      EtwConfig,
+    pub heartbeat: HeartbeatConfig,
+    pub file_hash: FileHashConfig,
+    pub dns:      DnsConfig,
 }
 
 /// Mirror of the `[logging]` table
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct LoggingConfig {
     #[serde(default)]            pub enable: bool,
     #[serde(default)]            pub file:   Option<String>,
     #[serde(default = "default_level")] pub level: String,
+    /// Also write WARN and above, and detection alerts, to the Windows
+    /// Event Log (source `Gladix`).
+    #[serde(default)]            pub eventlog: bool,
 }
 fn default_level() -> String { "INFO".into() }
 
-/// Mirror of the `[database]` table — **no defaults**: must be present in TOML
-#[derive(Debug, Deserialize, Clone)]
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self { enable: false, file: None, level: default_level(), eventlog: false }
+    }
+}
+
+/// Mirror of the `[database]` table — **no defaults** except the optional
+/// tuning knobs at the end: everything else must be present in TOML
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct DatabaseConfig {
     pub path:               String,
-    pub purge_on_restart:   bool,
+    /// `true` deletes the database file on start; a list of event tables
+    /// only empties those.
+    pub purge_on_restart:   PurgeOnRestart,
     pub synchronous:        String,
     pub journal_size_limit: u64,
     pub checkpoint_seconds: u64,
     pub ttl_seconds:        u64,
     pub flush_interval_ms:  u64,
     pub batch_size:         usize,
+    /// Page size in bytes; takes effect on new files or via VACUUM.
+    #[serde(default)]
+    pub page_size:          Option<u32>,
+    /// Per-connection page cache in KiB.
+    #[serde(default)]
+    pub cache_kb:           Option<u32>,
+    /// Text columns (`table.column`) stored zstd-compressed when large.
+    #[serde(default)]
+    pub compress_columns:   Vec<String>,
+    /// Minimum value size in bytes before compression is attempted.
+    #[serde(default = "default_compress_threshold")]
+    pub compress_threshold: usize,
+    /// How often TTL cleanup and alert retention run.
+    #[serde(default = "default_cleanup_interval")]
+    pub cleanup_interval_seconds: u64,
+    /// Per-table retention that overrides `ttl_seconds`.
+    #[serde(default)]
+    pub retention:          RetentionConfig,
+    /// Safety exports taken before risky operations.
+    #[serde(default)]
+    pub snapshots:          SnapshotConfig,
+    /// Events queued for the writer before listeners start shedding them.
+    #[serde(default = "default_channel_capacity")]
+    pub channel_capacity:   usize,
+    /// Cap on `overflow.bin`, where shed events wait for the writer, in
+    /// KiB; 0 drops them instead.
+    #[serde(default = "default_overflow_max_kb")]
+    pub overflow_max_kb:    u64,
+    /// Longest wait between retries of a flush that could not commit; the
+    /// first retry waits `flush_interval_ms` and each one doubles it.
+    #[serde(default = "default_flush_retry_max_ms")]
+    pub flush_retry_max_ms: u64,
+    /// Rows a writer keeps while its flushes fail; beyond that the oldest
+    /// are dropped.
+    #[serde(default = "default_pending_max_rows")]
+    pub pending_max_rows:   usize,
+    /// File events of one process, path and operation arriving within this
+    /// window are stored as one row with their `count` (`comms::coalesce`);
+    /// 0 stores each one.
+    #[serde(default = "default_coalesce_window_ms")]
+    pub coalesce_window_ms: u64,
+    /// Local hour (0-23) at which `PRAGMA optimize` runs once a day, with a
+    /// VACUUM when one is due; unset skips both.
+    #[serde(default)]
+    pub maintenance_hour:   Option<u32>,
+    /// Rows deleted by TTL cleanup and alert retention after which the
+    /// maintenance hour also runs VACUUM; 0 never does.
+    #[serde(default)]
+    pub vacuum_after_deleted_rows: u64,
+}
+fn default_compress_threshold() -> usize { 512 }
+fn default_cleanup_interval() -> u64 { 60 }
+fn default_channel_capacity() -> usize { 10_000 }
+fn default_overflow_max_kb() -> u64 { 65_536 }
+fn default_flush_retry_max_ms() -> u64 { 30_000 }
+fn default_pending_max_rows() -> usize { 100_000 }
+fn default_coalesce_window_ms() -> u64 { 2_000 }
+
+/// Mirror of the optional `[metrics]` table
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct MetricsConfig {
+    /// Also publish Windows performance counters.
+    pub perfcounters: bool,
+    /// Serve the Prometheus exposition over HTTP. History is kept either way.
+    pub listen:           bool,
+    /// Where the exposition is served.
+    pub listen_addr:      SocketAddr,
+    /// Push to this Prometheus push gateway instead of listening.
+    pub push_gateway_url: Option<String>,
+    pub history:          MetricsHistoryConfig,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            perfcounters:     false,
+            listen:           true,
+            listen_addr:      SocketAddr::from(([127, 0, 0, 1], 9184)),
+            push_gateway_url: None,
+            history:          MetricsHistoryConfig::default(),
+        }
+    }
+}
+
+/// Mirror of `[metrics.history]`: periodic snapshots of every metric for
+/// support bundles and crash reports.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct MetricsHistoryConfig {
+    pub enabled:       bool,
+    pub interval_secs: u64,
+    /// Snapshots kept; the oldest go first.
+    pub keep:          usize,
+    /// Upper bound on the rendered text kept, in KiB.
+    pub max_kb:        usize,
+    /// Most recent snapshots copied into a bundle or crash report.
+    pub bundle_files:  usize,
+    /// Metrics tabulated over the whole window in `metrics.csv`; empty for
+    /// none.
+    pub csv_series:    Vec<String>,
+}
+
+impl Default for MetricsHistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled:       true,
+            interval_secs: 60,
+            keep:          120,
+            max_kb:        8_192,
+            bundle_files:  5,
+            csv_series:    ["events_received_total", "ring_fill_ratio", "ring_dropped_total", "alerts_raised_total"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+/// Mirror of the optional `[scheduling]` table
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct SchedulingConfig {
+    /// Defer heavy background work while the interactive user is active.
+    pub respect_user_activity: bool,
+    /// Seconds without keyboard/mouse input after which the user is idle.
+    pub idle_threshold_secs:   u64,
+    /// Deferred work runs anyway after waiting this long.
+    pub max_deferral_hours:    f64,
+    pub tasks:                 DeferrableTasks,
+}
+
+impl Default for SchedulingConfig {
+    fn default() -> Self {
+        Self {
+            respect_user_activity: true,
+            idle_threshold_secs:   300,
+            max_deferral_hours:    4.0,
+            tasks:                 DeferrableTasks::default(),
+        }
+    }
+}
+
+/// Mirror of `[scheduling.tasks]`: which tasks wait for idle periods.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, default)]
+pub struct DeferrableTasks {
+    /// Scheduled scanner passes.
+    pub scanner:              bool,
+    pub wal_checkpoint:       bool,
+    pub compression_backfill: bool,
+    pub reprocess:            bool,
+}
+
+impl Default for DeferrableTasks {
+    fn default() -> Self {
+        Self { scanner: true, wal_checkpoint: true, compression_backfill: true, reprocess: true }
+    }
+}
+
+/// Mirror of `[database.snapshots]`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, default)]
+pub struct SnapshotConfig {
+    pub enabled:         bool,
+    /// Relative to the executable directory, like `database.path`.
+    pub dir:             String,
+    /// Export window: the most recent `max_days`, cut at `max_mb`.
+    pub max_days:        u32,
+    pub max_mb:          u64,
+    /// Snapshots kept on disk; older ones are removed.
+    pub keep:            usize,
+    /// Run the operation anyway when the snapshot fails.
+    pub skip_on_failure: bool,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            enabled:         false,
+            dir:             "snapshots".into(),
+            max_days:        7,
+            max_mb:          256,
+            keep:            5,
+            skip_on_failure: false,
+        }
+    }
+}
+
+/// `database.purge_on_restart`: the whole file, or some event tables
+/// named as in [`RetentionConfig::events`].
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum PurgeOnRestart {
+    All(bool),
+    Tables(Vec<String>),
+}
+
+impl From<bool> for PurgeOnRestart {
+    fn from(all: bool) -> Self {
+        Self::All(all)
+    }
+}
+
+impl PurgeOnRestart {
+    /// Whether the database file is deleted.
+    pub fn whole_file(&self) -> bool {
+        matches!(self, Self::All(true))
+    }
+
+    /// Tables emptied instead of deleting the file.
+    pub fn tables(&self) -> &[String] {
+        match self {
+            Self::Tables(tables) => tables,
+            Self::All(_)         => &[],
+        }
+    }
+}
+
+/// Mirror of `[database.retention]`
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct RetentionConfig {
+    #[serde(default)]
+    pub alerts: PerSeverity<Keep>,
+    /// Event table retention overriding `ttl_seconds`, keyed by table name
+    /// with or without its `_events` suffix: `process = "30d"`.
+    #[serde(flatten)]
+    pub events: BTreeMap<String, Keep>,
+}
+
+/// A value with per-severity overrides of `default`, e.g.
+/// `{ default = "30d", info = "7d", critical = "forever" }`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PerSeverity<T> {
+    pub default:  Option<T>,
+    pub info:     Option<T>,
+    pub low:      Option<T>,
+    pub medium:   Option<T>,
+    pub high:     Option<T>,
+    pub critical: Option<T>,
+}
+
+impl<T> Default for PerSeverity<T> {
+    fn default() -> Self {
+        Self { default: None, info: None, low: None, medium: None, high: None, critical: None }
+    }
+}
+
+impl<T> PerSeverity<T> {
+    /// Override for `sev`, else `default`.
+    pub fn get(&self, sev: Severity) -> Option<&T> {
+        let own = match sev {
+            Severity::Info     => &self.info,
+            Severity::Low      => &self.low,
+            Severity::Medium   => &self.medium,
+            Severity::High     => &self.high,
+            Severity::Critical => &self.critical,
+        };
+        own.as_ref().or(self.default.as_ref())
+    }
+}
+
+/// How long rows are kept: `"forever"` or a humantime duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Keep {
+    Forever,
+    For(Duration),
+}
+
+impl TryFrom<String> for Keep {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if s.eq_ignore_ascii_case("forever") {
+            return Ok(Keep::Forever);
+        }
+        humantime::parse_duration(&s)
+            .map(Keep::For)
+            .map_err(|e| format!("invalid retention '{s}': {e}"))
+    }
+}
+
+impl From<Keep> for String {
+    fn from(k: Keep) -> String {
+        match k {
+            Keep::Forever => "forever".into(),
+            Keep::For(d)  => humantime::format_duration(d).to_string(),
+        }
+    }
+}
+
+impl PerSeverity<Keep> {
+    /// Retention of `sev`; kept forever unless configured.
+    pub fn keep(&self, sev: Severity) -> Keep {
+        self.get(sev).copied().unwrap_or(Keep::Forever)
+    }
+
+    /// Whether any severity ever expires.
+    pub fn expires(&self) -> bool {
+        Severity::ALL.into_iter().any(|s| self.keep(s) != Keep::Forever)
+    }
+}
+
+/// Mirror of one `[[notification]]` channel
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationChannel {
+    pub name:         String,
+    /// Lowest severity delivered to this channel.
+    #[serde(default = "default_min_severity")]
+    pub min_severity: Severity,
+    /// Highest severity delivered to this channel.
+    #[serde(default = "default_max_severity")]
+    pub max_severity: Severity,
+    /// Caps per severity band, e.g. `{ high = "5/1m", default = "60/1h" }`.
+    #[serde(default)]
+    pub rate_limit:   PerSeverity<RateLimit>,
+}
+fn default_min_severity() -> Severity { Severity::Info }
+fn default_max_severity() -> Severity { Severity::Critical }
+
+impl NotificationChannel {
+    pub fn accepts(&self, sev: Severity) -> bool {
+        (self.min_severity..=self.max_severity).contains(&sev)
+    }
+}
+
+/// At most `count` notifications per `per`, written `"count/per"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RateLimit {
+    pub count: u32,
+    pub per:   Duration,
+}
+
+impl TryFrom<String> for RateLimit {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid rate limit '{s}', expected e.g. \"10/1m\"");
+        let (count, per) = s.split_once('/').ok_or_else(invalid)?;
+        let count = count.trim().parse::<u32>().map_err(|_| invalid())?;
+        let per   = humantime::parse_duration(per.trim()).map_err(|_| invalid())?;
+        if count == 0 || per.is_zero() {
+            return Err(invalid());
+        }
+        Ok(RateLimit { count, per })
+    }
+}
+
+impl From<RateLimit> for String {
+    fn from(r: RateLimit) -> String {
+        r.to_string()
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.count, humantime::format_duration(self.per))
+    }
 }
 
 /// Holds the raw scanner entries from TOML
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct RiskStub {
     pub risk:        String,
     #[serde(rename = "dirs")]
     pub directories: Vec<String>,
     #[serde(default)]
     pub interval:    Option<String>,
+    #[serde(default)]
+    pub hydrate_placeholders: bool,
+    #[serde(default)]
+    pub hash:        HashAlgorithm,
+    #[serde(default)]
+    pub exclude:     Vec<String>,
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    #[serde(default)]
+    pub max_depth:   Option<usize>,
+    #[serde(default)]
+    pub yara:        bool,
 }
 
 /// Fully-typed scanner group
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct RiskGroup {
     pub risk:        DirectoryRisk,
+    #[serde(rename = "dirs")]
     pub directories: Vec<PathBuf>,
+    #[serde(serialize_with = "serialize_interval")]
     pub interval:    Option<Duration>,
+    /// Read cloud placeholders instead of recording them as skipped.
+    pub hydrate_placeholders: bool,
+    /// Digests computed for each file.
+    pub hash:        HashAlgorithm,
+    /// Globs matched against full paths; matching directories are not
+    /// entered and matching files not listed.
+    pub exclude:     Vec<String>,
+    /// Enter symbolic links and junctions instead of skipping them.
+    pub follow_symlinks: bool,
+    /// Directory levels listed below each of `directories`; 0 lists only
+    /// their own files. Unlimited when `None`.
+    pub max_depth:   Option<usize>,
+    /// Run the YARA rules of `scanning.rules_dir` on new or changed files.
+    pub yara:        bool,
+}
+
+/// Content digests the scanner computes, per group.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// XxHash64 only: fast, used for change detection.
+    #[default]
+    Xxh64,
+    Sha256,
+    Both,
+}
+
+impl HashAlgorithm {
+    /// Name as written in TOML.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Xxh64  => "xxh64",
+            Self::Sha256 => "sha256",
+            Self::Both   => "both",
+        }
+    }
+
+    pub fn xxh64(self) -> bool {
+        matches!(self, Self::Xxh64 | Self::Both)
+    }
+
+    pub fn sha256(self) -> bool {
+        matches!(self, Self::Sha256 | Self::Both)
+    }
+}
+
+/// Writes intervals back in the human-readable form used in TOML.
+fn serialize_interval<S: Serializer>(v: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+    match v {
+        Some(d) => s.serialize_some(&humantime::format_duration(*d).to_string()),
+        None    => s.serialize_none(),
+    }
+}
+
+/// Mirror of the optional `[probe]` table, before duration parsing
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProbeStub {
+    #[serde(default = "default_true")]
+    pub enabled:           bool,
+    #[serde(default)]
+    pub interval:          Option<String>,
+    #[serde(default)]
+    pub timeout:           Option<String>,
+    #[serde(default)]
+    pub failure_threshold: Option<u32>,
+    #[serde(default)]
+    pub helper:            Option<String>,
+    #[serde(default)]
+    pub temp_dir:          Option<String>,
+}
+fn default_true() -> bool { true }
+
+impl Default for ProbeStub {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: None,
+            timeout: None,
+            failure_threshold: None,
+            helper: None,
+            temp_dir: None,
+        }
+    }
+}
+
+/// Live verification probe settings
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeConfig {
+    /// Turn off in high-security deployments where the probe's process,
+    /// file and DNS activity is not acceptable.
+    pub enabled:           bool,
+    #[serde(serialize_with = "serialize_duration")]
+    pub interval:          Duration,
+    /// How long each sensor has to deliver the marker event.
+    #[serde(serialize_with = "serialize_duration")]
+    pub timeout:           Duration,
+    /// Consecutive failures before the sensor is reported degraded.
+    pub failure_threshold: u32,
+    /// Marker helper executable, relative to the agent directory.
+    pub helper:            PathBuf,
+    /// Watched directory the marker file is created in; system temp if unset.
+    pub temp_dir:          Option<PathBuf>,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            enabled:           true,
+            interval:          Duration::from_secs(15 * 60),
+            timeout:           Duration::from_secs(30),
+            failure_threshold: 3,
+            helper:            PathBuf::from("gladix-probe-marker.exe"),
+            temp_dir:          None,
+        }
+    }
+}
+
+fn serialize_duration<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&humantime::format_duration(*d).to_string())
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    let s = String::deserialize(d)?;
+    humantime::parse_duration(&s).map_err(|e| serde::de::Error::custom(format!("invalid duration '{s}': {e}")))
+}
+
+/// Mirror of the `[communications]` table.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct CommunicationsConfig {
+    /// Serve decoded events on `comms::tap::PIPE_NAME` for local tools.
+    pub tap: bool,
+    /// Address of the config service (`comms::grpc`).
+    pub grpc_bind: String,
+    /// Listen on a non-loopback `grpc_bind`; otherwise the service is bound
+    /// to 127.0.0.1 on the same port.
+    pub allow_remote: bool,
+    /// Recent events each intel bus keeps for consumers that subscribe late
+    /// (`comms::intel_bus`); 0 keeps none.
+    pub intel_replay_events: usize,
+    /// Older events are not replayed, as a humantime duration.
+    #[serde(serialize_with = "serialize_duration", deserialize_with = "deserialize_duration")]
+    pub intel_replay_max_age: Duration,
+}
+
+impl Default for CommunicationsConfig {
+    fn default() -> Self {
+        Self {
+            tap: false,
+            grpc_bind: "127.0.0.1:50051".into(),
+            allow_remote: false,
+            intel_replay_events: 512,
+            intel_replay_max_age: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Mirror of the optional `[limits]` table: ingest rate limits per ring
+/// payload (`comms::rate_limit`).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// Process images remembered as recently seen. The creation of an image
+    /// not among them is kept even over the limit; 0 turns this off.
+    pub new_images: usize,
+    /// Buckets by payload (`process`, `image`); payloads not listed are
+    /// not limited.
+    #[serde(flatten)]
+    pub payloads: BTreeMap<String, PayloadLimit>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self { new_images: 256, payloads: BTreeMap::new() }
+    }
+}
+
+/// Payloads read from a ring, the keys of `[limits]`.
+pub const RING_PAYLOADS: [&str; 2] = ["process", "image"];
+
+/// Sustained events per second and the burst above it.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PayloadLimit {
+    pub per_sec: u32,
+    pub burst:   u32,
+}
+
+/// Mirror of the optional `[ring]` table: how the consumer attaches to the
+/// driver rings (`comms::memory_ring`).
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields, default)]
+pub struct RingConfig {
+    /// Events already in a ring when the agent attaches that are read.
+    pub replay:     ReplayPolicy,
+    /// Data area asked of the driver for each ring, applied from its next
+    /// start (`comms::driver_params`); the driver's setting is left alone
+    /// when unset.
+    pub size_bytes: Option<u32>,
+    /// Once-a-second samples a ring may stay over 80% full before the lag
+    /// is logged as a warning (`memory_ring::LagMonitor`).
+    pub lag_warn_samples: u32,
+    /// Events a consumer reads in a row before yielding its runtime thread.
+    pub max_events_per_burst: usize,
+    /// Frames longer than this are decoded on the blocking pool.
+    pub blocking_decode_bytes: usize,
+}
+
+impl Default for RingConfig {
+    fn default() -> Self {
+        Self {
+            replay:                ReplayPolicy::default(),
+            size_bytes:            None,
+            lag_warn_samples:      30,
+            max_events_per_burst:  512,
+            blocking_decode_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// Backlog read on attach: `"all"`, `"none"` or the events younger than a
+/// humantime duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ReplayPolicy {
+    /// Everything from the header's read offset on.
+    #[default]
+    FromTail,
+    /// Nothing written before the first read.
+    SkipBacklog,
+    /// Frames older than this are discarded until the first younger one.
+    MaxAge(Duration),
+}
+
+impl TryFrom<String> for ReplayPolicy {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(ReplayPolicy::FromTail);
+        }
+        if s.eq_ignore_ascii_case("none") {
+            return Ok(ReplayPolicy::SkipBacklog);
+        }
+        humantime::parse_duration(&s)
+            .map(ReplayPolicy::MaxAge)
+            .map_err(|e| format!("invalid replay '{s}': {e}"))
+    }
+}
+
+impl From<ReplayPolicy> for String {
+    fn from(p: ReplayPolicy) -> String {
+        match p {
+            ReplayPolicy::FromTail    => "all".into(),
+            ReplayPolicy::SkipBacklog => "none".into(),
+            ReplayPolicy::MaxAge(d)   => humantime::format_duration(d).to_string(),
+        }
+    }
+}
+
+/// Mirror of the optional `[export]` table: every event as a line of JSON
+/// (`comms::export`).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct ExportConfig {
+    pub enable:    bool,
+    /// Relative to the executable directory, like `database.path`.
+    pub path:      String,
+    /// Size at which the file is rotated to `<path>.1`, in MiB.
+    pub rotate_mb: u64,
+    /// Rotated files kept; older ones are removed.
+    pub keep:      usize,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self { enable: false, path: "events.ndjson".into(), rotate_mb: 100, keep: 5 }
+    }
+}
+
+/// Payload types on the intel buses, the values of `forwarder.types`.
+pub const FORWARD_PAYLOADS: [&str; 7] = ["process", "file", "network", "etw", "scan", "image", "object"];
+
+/// Mirror of the optional `[forwarder]` table: events streamed to a remote
+/// collector over gRPC (`comms::forwarder`).
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct ForwarderConfig {
+    pub enabled:     bool,
+    /// Collector URL; `https://` for TLS.
+    pub endpoint:    String,
+    /// Payload types forwarded, out of [`FORWARD_PAYLOADS`].
+    pub types:       Vec<String>,
+    /// Most events sent in one stream.
+    pub batch_size:  usize,
+    /// Events not yet acknowledged, relative to the executable directory.
+    pub spool_path:  String,
+    /// Size of the spool past which new events are dropped, in MiB.
+    pub spool_mb:    u64,
+    /// PEM certificate the collector's is checked against, instead of the
+    /// system's trusted roots.
+    pub ca_cert:     Option<PathBuf>,
+    /// PEM certificate and key the agent authenticates with; both or neither.
+    pub client_cert: Option<PathBuf>,
+    pub client_key:  Option<PathBuf>,
+}
+
+impl Default for ForwarderConfig {
+    fn default() -> Self {
+        Self {
+            enabled:     false,
+            endpoint:    String::new(),
+            types:       FORWARD_PAYLOADS.map(String::from).into(),
+            batch_size:  500,
+            spool_path:  "forward.spool".into(),
+            spool_mb:    256,
+            ca_cert:     None,
+            client_cert: None,
+            client_key:  None,
+        }
+    }
+}
+
+/// `[[network_policy]]`: connections allowed or blocked, the first matching
+/// rule deciding (see `policy`). Connections no rule matches are allowed.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct NetPolicyRule {
+    pub id:      String,
+    pub action:  PolicyAction,
+    /// Remote range (`10.0.0.0/8`) or address; any when unset.
+    #[serde(default)]
+    pub dst:     Option<String>,
+    /// Remote port (`445`) or range (`8000-8100`); any when unset.
+    #[serde(default)]
+    pub ports:   Option<String>,
+    /// Glob over the process path (`*`, `?`, case-insensitive); any when
+    /// unset.
+    #[serde(default)]
+    pub process: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyAction {
+    Allow,
+    Block,
+}
+
+/// Mirror of the optional `[etw]` table: a real-time trace session over the
+/// listed providers (`etw`). Needs administrator rights.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct EtwConfig {
+    pub enabled:   bool,
+    /// Session name; a session left with this name is stopped and replaced.
+    pub session:   String,
+    pub providers: Vec<EtwProvider>,
+}
+
+impl Default for EtwConfig {
+    fn default() -> Self {
+        Self { enabled: false, session: "GladixAgent".into(), providers: Vec::new() }
+    }
+}
+
+/// One `[[etw.providers]]` entry.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EtwProvider {
+    /// `{22fb2cd6-0e7b-422b-a0c7-2fad1fd0e716}`, braces optional.
+    pub guid:     String,
+    /// Most verbose level delivered, 1 (critical) to 5 (verbose).
+    #[serde(default = "default_etw_level")]
+    pub level:    u8,
+    /// Keyword mask; 0 delivers every event.
+    #[serde(default)]
+    pub keywords: u64,
+}
+fn default_etw_level() -> u8 { 4 }
+
+/// Mirror of the optional `[heartbeat]` table: a row in `agent_status`
+/// every `interval_ms`, also served by `GetStatus`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct HeartbeatConfig {
+    pub enabled:     bool,
+    pub interval_ms: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self { enabled: true, interval_ms: 60_000 }
+    }
+}
+
+/// Mirror of the optional `[file_hash]` table: SHA-256 of small files
+/// filled into file events before they are stored (`intel::file_hash`).
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct FileHashConfig {
+    pub enabled:       bool,
+    /// Larger files are left without a hash.
+    pub max_bytes:     u64,
+    /// Files hashed at once, on the blocking pool.
+    pub threads:       usize,
+    /// Digests remembered by path and modification time.
+    pub cache_entries: usize,
+}
+
+impl Default for FileHashConfig {
+    fn default() -> Self {
+        Self { enabled: true, max_bytes: 10 * 1024 * 1024, threads: 2, cache_entries: 4_096 }
+    }
+}
+
+/// Mirror of the optional `[dns]` table: host names of the remote
+/// addresses of network events, from a reverse-lookup cache (`intel::dns`).
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct DnsConfig {
+    pub enabled:           bool,
+    /// Addresses remembered, with a name or without one.
+    pub cache_entries:     usize,
+    /// How long a name found is kept, in seconds.
+    pub ttl_secs:          u64,
+    /// How long an address without a name is not asked about again, in
+    /// seconds.
+    pub negative_ttl_secs: u64,
+    /// Reverse lookups started per second at most.
+    pub lookups_per_sec:   u32,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self { enabled: true, cache_entries: 4_096, ttl_secs: 3_600, negative_ttl_secs: 300, lookups_per_sec: 10 }
+    }
+}
+
+/// Mirror of the optional `[scanning]` table: how scanner groups are run.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct ScanningConfig {
+    /// Files hashed at once across all groups.
+    pub concurrency: usize,
+    /// Bytes hashed per second across all groups; 0 is unlimited.
+    pub read_bytes_per_sec: u64,
+    /// YARA rule files (`.yar`, `.yara`) run by groups with `yara = true`
+    /// (see `scanner::rules`).
+    pub rules_dir: Option<PathBuf>,
+}
+
+impl Default for ScanningConfig {
+    fn default() -> Self {
+        Self { concurrency: 4, read_bytes_per_sec: 0, rules_dir: None }
+    }
+}
+
+/// Mirror of the optional `[reports]` table, before interval parsing
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ReportsStub {
+    #[serde(default)]
+    pub enabled:     bool,
+    #[serde(default)]
+    pub max_entries: Option<usize>,
+    #[serde(default, rename = "group")]
+    pub groups:      Vec<ReportGroupStub>,
+}
+
+/// Mirror of one `[[reports.group]]` entry
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportGroupStub {
+    pub risk:     String,
+    /// `"daily"`, `"weekly"` or a duration such as `"12h"`.
+    pub interval: String,
+    #[serde(default)]
+    pub channels: Vec<String>,
+}
+
+/// Periodic digests of executable changes per scanner group
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportsConfig {
+    pub enabled:     bool,
+    /// Entries listed per change kind before "and N more".
+    pub max_entries: usize,
+    #[serde(rename = "group")]
+    pub groups:      Vec<ReportGroup>,
+}
+
+impl Default for ReportsConfig {
+    fn default() -> Self {
+        Self { enabled: false, max_entries: 50, groups: Vec::new() }
+    }
+}
+
+/// Report schedule of one scanner group
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportGroup {
+    pub risk:     DirectoryRisk,
+    #[serde(serialize_with = "serialize_duration")]
+    pub interval: Duration,
+    /// `[[notification]]` channels the report is delivered to.
+    pub channels: Vec<String>,
+}
+
+/// Mirror of the optional `[analytics]` table
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AnalyticsConfig {
+    #[serde(default)]
+    pub parent_spoofing: ParentSpoofingConfig,
+    #[serde(default)]
+    pub write_execute:   WriteExecuteConfig,
+}
+
+/// Mirror of `[analytics.parent_spoofing]`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ParentSpoofingConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Creators allowed to set another process as parent: full paths or
+    /// bare file names, case-insensitive.
+    #[serde(default = "default_brokers")]
+    pub brokers: Vec<String>,
+}
+fn default_brokers() -> Vec<String> {
+    ["services.exe", "svchost.exe", "wmiprvse.exe"].map(String::from).to_vec()
+}
+
+impl Default for ParentSpoofingConfig {
+    fn default() -> Self {
+        Self { enabled: true, brokers: default_brokers() }
+    }
+}
+
+/// Mirror of `[analytics.write_execute]`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct WriteExecuteConfig {
+    pub enabled:      bool,
+    /// A process started from a file written this many seconds earlier
+    /// raises an alert.
+    pub window_secs:  u64,
+    /// Written paths remembered at once; least recently written go first.
+    pub max_tracked:  usize,
+    /// Classes of image paths that are watched.
+    pub path_classes: Vec<PathClass>,
+    /// Writers never alerted on (installers, updaters): full paths or bare
+    /// file names, case-insensitive.
+    pub installers:   Vec<String>,
+    /// SHA-256 (hex) of files with a known good reputation.
+    pub known_hashes: Vec<String>,
+}
+
+impl Default for WriteExecuteConfig {
+    fn default() -> Self {
+        Self {
+            enabled:      true,
+            window_secs:  60,
+            max_tracked:  8_192,
+            path_classes: vec![PathClass::UserWritable, PathClass::Other],
+            installers:   ["msiexec.exe", "trustedinstaller.exe", "tiworker.exe"].map(String::from).to_vec(),
+            known_hashes: Vec::new(),
+        }
+    }
+}
+
+/// Where an executable lives, by who can normally write there.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PathClass {
+    /// Profiles, `ProgramData`, temp directories and network shares.
+    UserWritable,
+    /// `C:\Windows`.
+    System,
+    /// `C:\Program Files` and `C:\Program Files (x86)`.
+    ProgramFiles,
+    Other,
+}
+
+/// Mirror of the optional `[detection]` table: rules matched against every
+/// process, file and network event. The rule lists are re-read from
+/// `config.toml` while the agent runs.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct DetectionConfig {
+    pub enabled:     bool,
+    /// How often `config.toml` is checked for changed rules.
+    pub reload_secs: u64,
+    pub process:     Vec<ProcessRule>,
+    pub file:        Vec<FileRule>,
+    pub network:     Vec<NetworkRule>,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self { enabled: true, reload_secs: 10, process: Vec::new(), file: Vec::new(), network: Vec::new() }
+    }
+}
+
+/// `[[detection.process]]`: process creations whose image, command line and
+/// parent image match regular expressions. At least one of them is required.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessRule {
+    /// Alerts carry `detection.<id>` as their rule id.
+    pub id:            String,
+    #[serde(default = "default_rule_severity")]
+    pub severity:      Severity,
+    /// Severity expression (see `intel::severity`) over the event fields;
+    /// `severity` when no clause matches.
+    #[serde(default)]
+    pub severity_when: Option<String>,
+    #[serde(default)]
+    pub image:         Option<String>,
+    #[serde(default)]
+    pub cmdline:       Option<String>,
+    /// Over the parent's image: the one the event carries, else the
+    /// parent's stored creation.
+    #[serde(default)]
+    pub parent_image:  Option<String>,
+}
+
+/// `[[detection.file]]`: file operations on paths matching a glob (`*`, `?`,
+/// case-insensitive).
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FileRule {
+    pub id:            String,
+    #[serde(default = "default_rule_severity")]
+    pub severity:      Severity,
+    #[serde(default)]
+    pub severity_when: Option<String>,
+    pub path:          String,
+    /// `CREATE`, `WRITE`, `DELETE`, `RENAME`; empty matches all of them.
+    #[serde(default)]
+    pub ops:           Vec<String>,
+}
+
+/// `[[detection.network]]`: connections to addresses in CIDR ranges and to
+/// ports. An empty list matches anything.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkRule {
+    pub id:            String,
+    #[serde(default = "default_rule_severity")]
+    pub severity:      Severity,
+    #[serde(default)]
+    pub severity_when: Option<String>,
+    /// Ranges (`10.0.0.0/8`) or single addresses.
+    #[serde(default)]
+    pub dst:           Vec<String>,
+    #[serde(default)]
+    pub ports:         Vec<u16>,
+}
+
+fn default_rule_severity() -> Severity { Severity::Medium }
+
+/// Mirror of the optional `[actions]` table: responses run when a rule
+/// raises an alert.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct ActionsConfig {
+    /// Master switch; no action runs while off, whatever `rules` says.
+    pub enabled:    bool,
+    /// Action per rule id, e.g. `"builtin.parent_pid_spoofing" = "memdump"`.
+    pub rules:      BTreeMap<String, RuleAction>,
+    pub memdump:    MemdumpConfig,
+    pub quarantine: QuarantineConfig,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    /// Capture the memory of the alerted process.
+    Memdump,
+    /// Seal the file the alert is about into the quarantine store.
+    Quarantine,
+}
+
+/// Mirror of `[actions.quarantine]`; the store itself is `quarantine` next
+/// to the executable.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct QuarantineConfig {
+    pub mode:     QuarantineMode,
+    /// Larger files are refused and recorded as `too_large`.
+    pub max_mb:   u64,
+    /// Reads and deletes tried on a file another process holds open, with
+    /// backoff in between.
+    pub attempts: u32,
+}
+
+impl Default for QuarantineConfig {
+    fn default() -> Self {
+        Self { mode: QuarantineMode::Move, max_mb: 256, attempts: 5 }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QuarantineMode {
+    /// Seal the file and delete the original.
+    #[default]
+    Move,
+    /// Seal a copy and leave the original where it is.
+    Copy,
+}
+
+/// Mirror of `[actions.memdump]`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct MemdumpConfig {
+    pub dump_type: DumpType,
+    /// Relative to the executable directory; created readable by SYSTEM and
+    /// Administrators only.
+    pub dir:       String,
+    /// Dumps larger than this are cut and flagged as truncated.
+    pub max_mb:    u64,
+    /// Dumps attempted per rolling hour; further requests are recorded as
+    /// rate limited.
+    pub per_hour:  u32,
+    /// Store dumps zstd-compressed (`.dmp.zst`).
+    pub compress:  bool,
+    /// Images never dumped: full paths or bare file names, case-insensitive.
+    /// The agent itself is always refused.
+    pub denylist:  Vec<String>,
+}
+
+impl Default for MemdumpConfig {
+    fn default() -> Self {
+        Self {
+            dump_type: DumpType::Mini,
+            dir:       "captures".into(),
+            max_mb:    512,
+            per_hour:  4,
+            compress:  false,
+            denylist:  ["system", "smss.exe", "csrss.exe", "wininit.exe", "services.exe", "lsass.exe"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+/// Contents of a memory dump.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DumpType {
+    /// Threads, stacks and module list.
+    Mini,
+    /// Every committed page of the process.
+    Full,
 }
 
 /// Allowed risk levels; add a variant here to support new ones
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum DirectoryRisk {
     Low,
     Medium,
@@ -75,6 +1239,27 @@ pub enum ConfigError {
 
     #[error("TOML parse error: {0}")]
     Toml(#[from] toml::de::Error),
+
+    #[error("notification channel '{0}': {1}")]
+    InvalidNotification(String, String),
+
+    #[error("report group '{0}': {1}")]
+    InvalidReport(String, String),
+
+    #[error("{0}: {1}")]
+    InvalidValue(String, String),
+}
+
+impl DirectoryRisk {
+    /// Lower-case name, as accepted by [`FromStr`].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DirectoryRisk::Low     => "low",
+            DirectoryRisk::Medium  => "medium",
+            DirectoryRisk::High    => "high",
+            DirectoryRisk::Special => "special",
+        }
+    }
 }
 
 /// Allow `"High"` → `DirectoryRisk::High"`
@@ -0,0 +1,586 @@
+// src/config/provision.rs
+//! First-run setup: from a provisioning profile to an installed agent.
+//!
+//! A [`Profile`] holds what a deployment decides (scanner directories,
+//! database location, metrics and gRPC exposure, prevention) and may use
+//! `%NAME%` placeholders that expand on the target host. [`plan`] merges it
+//! into the existing `config.toml` (or the shipped template), validates the
+//! result with the regular loader and compares it with the current state
+//! through [`canonical`](super::canonical); [`apply`] then writes only what
+//! differs. Applying the same profile twice leaves everything untouched.
+//!
+//! `gladix-cli setup` builds a profile interactively from [`suggested`], or
+//! reads one with `--profile` for MDM/GPO distribution.
+
+use std::{
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+use serde::Deserialize;
+use thiserror::Error;
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, Value};
+
+use super::{
+    canonical::{canonicalize, diff, render_diff, Change, ConfigExport, KeyDiff, SectionDiff},
+    loader::parse,
+    metadata::reload_class,
+    model::{Config, ConfigError, HashAlgorithm, RiskStub},
+};
+
+/// Shipped `config.toml`, the base when the host has none yet.
+pub const TEMPLATE: &str = include_str!("../../config.toml");
+
+/// Sensor GUID, next to `config.toml`.
+pub const SENSOR_GUID_FILE: &str = "sensor.guid";
+
+/// Config fingerprint taken at setup, next to `config.toml`; compare later
+/// with `gladix-cli config diff config-baseline.json`.
+pub const BASELINE_FILE: &str = "config-baseline.json";
+
+/// Deployment choices, as read from a `.toml` or `.json` profile.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Profile {
+    /// Same tables as `[[scanner]]` in `config.toml`.
+    pub scanner:        Vec<RiskStub>,
+    /// Database file; relative paths are next to `config.toml`.
+    pub database:       String,
+    /// Free space required where the database goes, in MiB.
+    pub min_free_mb:    u64,
+    /// `[metrics] listen`: serve Prometheus metrics over HTTP.
+    pub metrics_listen: bool,
+    /// `[communications] grpc_bind`.
+    pub grpc_bind:      String,
+    /// `[actions] enabled`: the master switch of response actions.
+    pub prevention:     bool,
+    /// Write a config fingerprint to [`BASELINE_FILE`].
+    pub baseline:       bool,
+    /// Fixed sensor GUID; by default one is generated on first setup and kept.
+    pub sensor_guid:    Option<String>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            scanner:        default_groups(),
+            database:       r"%PROGRAMDATA%\Gladix\telemetry.db".into(),
+            min_free_mb:    1024,
+            metrics_listen: true,
+            grpc_bind:      LOCAL_GRPC.into(),
+            prevention:     false,
+            baseline:       false,
+            sensor_guid:    None,
+        }
+    }
+}
+
+/// gRPC reachable from this host only.
+pub const LOCAL_GRPC: &str = "127.0.0.1:50051";
+/// gRPC reachable from the network.
+pub const REMOTE_GRPC: &str = "0.0.0.0:50051";
+
+fn group(risk: &str, dirs: &[&str], interval: Option<&str>) -> RiskStub {
+    RiskStub {
+        risk:        risk.into(),
+        directories: dirs.iter().map(|d| d.to_string()).collect(),
+        interval:    interval.map(Into::into),
+        hydrate_placeholders: false,
+        hash:        HashAlgorithm::default(),
+        exclude:     Vec::new(),
+        follow_symlinks: false,
+        max_depth:   None,
+        yara:        false,
+    }
+}
+
+/// Where executables usually land on Windows, most exposed first.
+fn default_groups() -> Vec<RiskStub> {
+    vec![
+        group("High", &[r"%USERPROFILE%\Downloads", r"%PUBLIC%", r"%TEMP%"], Some("60s")),
+        group("Medium", &[r"%PROGRAMFILES%", r"%PROGRAMFILES(X86)%", r"%PROGRAMDATA%"], Some("300s")),
+        group("Low", &[r"%SYSTEMROOT%\System32"], None),
+    ]
+}
+
+/// What setup needs from the machine; faked in tests.
+pub trait Host {
+    /// Environment variable, as `%NAME%` expands to.
+    fn var(&self, name: &str) -> Option<String>;
+    /// Free bytes on the volume holding `dir`; `None` when unknown.
+    fn free_bytes(&self, dir: &Path) -> Option<u64>;
+}
+
+/// The machine setup runs on.
+pub struct SystemHost;
+
+impl Host for SystemHost {
+    fn var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+
+    fn free_bytes(&self, dir: &Path) -> Option<u64> {
+        system::free_bytes(dir)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ProvisionError {
+    #[error("unknown placeholder %{1}% in '{0}'")]
+    UnknownPlaceholder(String, String),
+
+    #[error("unterminated placeholder in '{0}'")]
+    Unterminated(String),
+
+    #[error("profile: {0}")]
+    Profile(String),
+
+    #[error("{dir}: {free_mb} MiB free, {min_mb} MiB required")]
+    LowDiskSpace { dir: PathBuf, free_mb: u64, min_mb: u64 },
+
+    #[error("invalid sensor GUID '{0}'")]
+    InvalidGuid(String),
+
+    #[error("resulting config: {0}")]
+    Config(#[from] ConfigError),
+
+    #[error("{0}: {1}")]
+    Toml(PathBuf, toml_edit::TomlError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Expands `%NAME%` from `host`; `%%` is a literal `%`.
+pub fn expand(s: &str, host: &dyn Host) -> Result<String, ProvisionError> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('%') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after.find('%').ok_or_else(|| ProvisionError::Unterminated(s.into()))?;
+        let name = &after[..end];
+        if name.is_empty() {
+            out.push('%');
+        } else {
+            let value = host
+                .var(name)
+                .ok_or_else(|| ProvisionError::UnknownPlaceholder(s.into(), name.into()))?;
+            out.push_str(&value);
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+impl Profile {
+    /// Reads a profile; `.json` files are JSON, anything else TOML.
+    pub fn read(path: &Path) -> Result<Self, ProvisionError> {
+        let text = fs::read_to_string(path)?;
+        let parsed = match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("json") => serde_json::from_str(&text).map_err(|e| e.to_string()),
+            _ => toml::from_str(&text).map_err(|e| e.to_string()),
+        };
+        parsed.map_err(|e| ProvisionError::Profile(format!("{}: {e}", path.display())))
+    }
+
+    /// The profile with every placeholder expanded.
+    pub fn expand(&self, host: &dyn Host) -> Result<Self, ProvisionError> {
+        let mut out = self.clone();
+        for g in &mut out.scanner {
+            for d in &mut g.directories {
+                *d = expand(d, host)?;
+            }
+        }
+        out.database = expand(&self.database, host)?;
+        Ok(out)
+    }
+}
+
+/// Defaults for the wizard, expanded on `host`. Directories whose variables
+/// this host lacks, such as `%PROGRAMFILES(X86)%` on 32-bit Windows, are
+/// left out.
+pub fn suggested(host: &dyn Host) -> Profile {
+    let defaults = Profile::default();
+    let mut groups = Vec::new();
+    for g in defaults.scanner {
+        let directories = g.directories.iter().filter_map(|d| expand(d, host).ok()).collect();
+        groups.push(RiskStub { directories, ..g });
+    }
+    Profile {
+        scanner:  groups,
+        database: expand(&defaults.database, host).unwrap_or_else(|_| "telemetry.db".into()),
+        ..defaults
+    }
+}
+
+/// Database file of `profile` for a config at `config_dir`.
+pub fn database_path(config_dir: &Path, profile: &Profile) -> PathBuf {
+    config_dir.join(&profile.database)
+}
+
+/// Fails when the volume that will hold `db` has less than `min_mb` free.
+/// Unknown free space passes.
+pub fn check_free_space(db: &Path, min_mb: u64, host: &dyn Host) -> Result<(), ProvisionError> {
+    let dir = db.parent().unwrap_or(Path::new("."));
+    // The directory may not exist yet; its volume is the nearest ancestor's.
+    let existing = dir.ancestors().find(|a| a.is_dir()).unwrap_or(dir);
+    let Some(free) = host.free_bytes(existing) else {
+        return Ok(());
+    };
+    let free_mb = free / (1024 * 1024);
+    if free_mb < min_mb {
+        return Err(ProvisionError::LowDiskSpace { dir: dir.to_owned(), free_mb, min_mb });
+    }
+    Ok(())
+}
+
+/// Result of one setup step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Created,
+    /// What changed, one line per key.
+    Changed(String),
+    Unchanged,
+}
+
+/// What a setup step writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Config,
+    DatabaseDir,
+    SensorGuid,
+    Baseline,
+}
+
+impl Target {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Target::Config      => "config",
+            Target::DatabaseDir => "database directory",
+            Target::SensorGuid  => "sensor GUID",
+            Target::Baseline    => "baseline",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub target:  Target,
+    pub path:    PathBuf,
+    pub outcome: Outcome,
+}
+
+/// Everything [`apply`] would write, already validated.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub steps:       Vec<Step>,
+    pub config:      String,
+    pub sensor_guid: String,
+    pub baseline:    Option<String>,
+}
+
+impl Plan {
+    /// Whether applying would leave the host as it is.
+    pub fn is_noop(&self) -> bool {
+        self.steps.iter().all(|s| s.outcome == Outcome::Unchanged)
+    }
+
+    /// One line per step, with the changed keys indented below.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        for s in &self.steps {
+            let state = match &s.outcome {
+                Outcome::Created    => "created",
+                Outcome::Changed(_) => "changed",
+                Outcome::Unchanged  => "unchanged",
+            };
+            out.push_str(&format!("{:<9} {:<18} {}\n", state, s.target.as_str(), s.path.display()));
+            if let Outcome::Changed(detail) = &s.outcome {
+                for line in detail.lines() {
+                    out.push_str(&format!("          {line}\n"));
+                }
+            }
+        }
+        out
+    }
+}
+
+fn file_outcome(path: &Path, content: &str, detail: impl FnOnce(&str) -> String) -> io::Result<Outcome> {
+    match fs::read_to_string(path) {
+        Ok(old) if old == content => Ok(Outcome::Unchanged),
+        Ok(old) => Ok(Outcome::Changed(detail(&old))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Outcome::Created),
+        Err(e) => Err(e),
+    }
+}
+
+/// Works out what applying `profile` to the config at `config_path` would
+/// change. Nothing is written.
+pub fn plan(profile: &Profile, config_path: &Path, host: &dyn Host) -> Result<Plan, ProvisionError> {
+    let profile = profile.expand(host)?;
+    let dir = config_path.parent().unwrap_or(Path::new("."));
+
+    // Config: the profile merged into what is there, comments kept.
+    let current = match fs::read_to_string(config_path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let config = merge(current.as_deref().unwrap_or(TEMPLATE), &profile)
+        .map_err(|e| ProvisionError::Toml(config_path.to_owned(), e))?;
+    let cfg = parse(&config)?;
+    let mut steps = vec![Step {
+        target:  Target::Config,
+        path:    config_path.to_owned(),
+        outcome: file_outcome(config_path, &config, |old| config_changes(old, &config, &cfg))?,
+    }];
+
+    // Database directory, with room for the database.
+    let db = database_path(dir, &profile);
+    check_free_space(&db, profile.min_free_mb, host)?;
+    let db_dir = db.parent().unwrap_or(dir).to_owned();
+    steps.push(Step {
+        target:  Target::DatabaseDir,
+        path:    db_dir.clone(),
+        outcome: if db_dir.is_dir() { Outcome::Unchanged } else { Outcome::Created },
+    });
+
+    // Sensor GUID: kept once generated unless the profile pins one.
+    let guid_path = dir.join(SENSOR_GUID_FILE);
+    let existing = read_sensor_guid(dir);
+    let sensor_guid = match (&profile.sensor_guid, existing) {
+        (Some(g), _) => uuid::Uuid::parse_str(g)
+            .map_err(|_| ProvisionError::InvalidGuid(g.clone()))?
+            .hyphenated()
+            .to_string(),
+        (None, Some(g)) => g,
+        (None, None) => uuid::Uuid::new_v4().hyphenated().to_string(),
+    };
+    steps.push(Step {
+        target:  Target::SensorGuid,
+        path:    guid_path.clone(),
+        outcome: file_outcome(&guid_path, &format!("{sensor_guid}\n"), |old| {
+            format!("{} -> {}", old.trim(), sensor_guid)
+        })?,
+    });
+
+    // Baseline signature of the resulting config.
+    let baseline = if profile.baseline {
+        let path = dir.join(BASELINE_FILE);
+        let export = ConfigExport::new(&cfg);
+        let text = serde_json::to_string_pretty(&export).expect("exports always serialise") + "\n";
+        steps.push(Step {
+            target:  Target::Baseline,
+            path:    path.clone(),
+            outcome: file_outcome(&path, &text, |_| format!("fingerprint {}", export.fingerprint.digest))?,
+        });
+        Some(text)
+    } else {
+        None
+    };
+
+    Ok(Plan { steps, config, sensor_guid, baseline })
+}
+
+/// Writes what `plan` found different. Returns the plan's steps as applied.
+pub fn apply(plan: &Plan) -> Result<Vec<Step>, ProvisionError> {
+    for step in &plan.steps {
+        if step.outcome == Outcome::Unchanged {
+            continue;
+        }
+        match step.target {
+            Target::Config      => write(&step.path, &plan.config)?,
+            Target::DatabaseDir => fs::create_dir_all(&step.path)?,
+            Target::SensorGuid  => write(&step.path, &format!("{}\n", plan.sensor_guid))?,
+            Target::Baseline    => write(&step.path, plan.baseline.as_deref().unwrap_or_default())?,
+        }
+        log::info!("setup: {} {}", step.target.as_str(), step.path.display());
+    }
+    Ok(plan.steps.clone())
+}
+
+/// Writes through a temporary file so a failed write leaves the old one.
+pub(crate) fn write(path: &Path, content: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("setup.tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)
+}
+
+/// Sensor GUID written by setup in `dir`, if any.
+pub fn read_sensor_guid(dir: &Path) -> Option<String> {
+    let text = fs::read_to_string(dir.join(SENSOR_GUID_FILE)).ok()?;
+    uuid::Uuid::parse_str(text.trim()).ok().map(|g| g.hyphenated().to_string())
+}
+
+/// The sensor GUID in `dir`, generating and keeping one there if setup has
+/// not written it, so a host set up by hand still has its own.
+pub fn load_or_create_sensor_guid(dir: &Path) -> io::Result<String> {
+    if let Some(guid) = read_sensor_guid(dir) {
+        return Ok(guid);
+    }
+    let guid = uuid::Uuid::new_v4().hyphenated().to_string();
+    write(&dir.join(SENSOR_GUID_FILE), &format!("{guid}\n"))?;
+    Ok(guid)
+}
+
+/// Keys setup writes that the canonical form leaves out: host-specific or
+/// not loaded by the agent.
+const OUTSIDE_CANONICAL: [(&str, &str); 2] = [("database", "path"), ("communications", "grpc_bind")];
+
+/// Key-level changes from `old` to `new`, via the canonical form plus
+/// [`OUTSIDE_CANONICAL`].
+fn config_changes(old: &str, new: &str, cfg: &Config) -> String {
+    let mut diffs = match parse(old) {
+        Ok(old) => diff(&canonicalize(&old), &canonicalize(cfg)),
+        Err(e) => return format!("previous config did not load: {e}\n"),
+    };
+    let (old_doc, new_doc) = (old.parse::<DocumentMut>().ok(), new.parse::<DocumentMut>().ok());
+    let get = |doc: &Option<DocumentMut>, table: &str, key: &str| {
+        let v = doc.as_ref()?.get(table)?.get(key)?.as_value()?;
+        Some(serde_json::Value::String(v.as_str().map_or_else(|| bare(v), str::to_owned)))
+    };
+    for (table, key) in OUTSIDE_CANONICAL {
+        let change = match (get(&old_doc, table, key), get(&new_doc, table, key)) {
+            (a, b) if a == b      => continue,
+            (Some(l), Some(o))    => Change::Changed { local: l, other: o },
+            (Some(l), None)       => Change::Removed(l),
+            (None, Some(o))       => Change::Added(o),
+            (None, None)          => continue,
+        };
+        let full = format!("{table}.{key}");
+        let d = KeyDiff { reload: reload_class(&full), key: full, change };
+        match diffs.iter_mut().find(|s| s.section == table) {
+            Some(s) => {
+                s.changes.push(d);
+                s.changes.sort_by(|a, b| a.key.cmp(&b.key));
+            }
+            None => diffs.push(SectionDiff { section: table.into(), changes: vec![d] }),
+        }
+    }
+    diffs.sort_by(|a, b| a.section.cmp(&b.section));
+    let out = render_diff(&diffs);
+    if out.is_empty() { "formatting only\n".into() } else { out }
+}
+
+/// `base` with the profile's settings; untouched keys keep their comments.
+fn merge(base: &str, p: &Profile) -> Result<String, toml_edit::TomlError> {
+    let mut doc: DocumentMut = base.parse()?;
+    set(&mut doc, "database", "path", p.database.as_str().into());
+    set(&mut doc, "metrics", "listen", p.metrics_listen.into());
+    set(&mut doc, "communications", "grpc_bind", p.grpc_bind.as_str().into());
+    // The service leaves loopback only with `allow_remote`.
+    let remote = !p.grpc_bind.parse::<SocketAddr>().is_ok_and(|a| a.ip().is_loopback());
+    if remote || doc.get("communications").and_then(|t| t.get("allow_remote")).is_some() {
+        set(&mut doc, "communications", "allow_remote", remote.into());
+    }
+    set(&mut doc, "actions", "enabled", p.prevention.into());
+    set_scanner(&mut doc, &p.scanner);
+    Ok(doc.to_string())
+}
+
+/// Sets `[table] key`, keeping the value's comments; a no-op when equal.
+fn set(doc: &mut DocumentMut, table: &str, key: &str, mut new: Value) {
+    let item = &mut doc[table][key];
+    match item.as_value() {
+        Some(old) if bare(old) == bare(&new) => {}
+        Some(old) => {
+            *new.decor_mut() = old.decor().clone();
+            *item = Item::Value(new);
+        }
+        None => *item = Item::Value(new),
+    }
+}
+
+fn bare(v: &Value) -> String {
+    let mut v = v.clone();
+    v.decor_mut().clear();
+    v.to_string()
+}
+
+/// Replaces `[[scanner]]` unless it already holds `groups`, keeping the
+/// comment above the first table.
+fn set_scanner(doc: &mut DocumentMut, groups: &[RiskStub]) {
+    #[derive(Deserialize)]
+    struct Current {
+        #[serde(default)]
+        scanner: Vec<RiskStub>,
+    }
+    let current = toml::from_str::<Current>(&doc.to_string()).map(|c| c.scanner).unwrap_or_default();
+    if current == groups {
+        return;
+    }
+
+    let old = doc.remove("scanner");
+    let prefix = old
+        .as_ref()
+        .and_then(Item::as_array_of_tables)
+        .and_then(|a| a.get(0))
+        .and_then(|t| t.decor().prefix().cloned());
+
+    let mut tables = ArrayOfTables::new();
+    for (i, g) in groups.iter().enumerate() {
+        let mut t = Table::new();
+        t.insert("risk", toml_edit::value(g.risk.as_str()));
+        t.insert("dirs", toml_edit::value(g.directories.iter().map(String::as_str).collect::<toml_edit::Array>()));
+        if let Some(interval) = &g.interval {
+            t.insert("interval", toml_edit::value(interval.as_str()));
+        }
+        if g.hydrate_placeholders {
+            t.insert("hydrate_placeholders", toml_edit::value(true));
+        }
+        if g.hash != HashAlgorithm::default() {
+            t.insert("hash", toml_edit::value(g.hash.as_str()));
+        }
+        if !g.exclude.is_empty() {
+            t.insert("exclude", toml_edit::value(g.exclude.iter().map(String::as_str).collect::<toml_edit::Array>()));
+        }
+        if g.follow_symlinks {
+            t.insert("follow_symlinks", toml_edit::value(true));
+        }
+        if let Some(depth) = g.max_depth {
+            t.insert("max_depth", toml_edit::value(depth as i64));
+        }
+        if g.yara {
+            t.insert("yara", toml_edit::value(true));
+        }
+        if i == 0 {
+            if let Some(prefix) = &prefix {
+                t.decor_mut().set_prefix(prefix.clone());
+            }
+        } else {
+            t.decor_mut().set_prefix("\n");
+        }
+        tables.push(t);
+    }
+    doc.insert("scanner", Item::ArrayOfTables(tables));
+}
+
+#[cfg(windows)]
+mod system {
+    use std::{os::windows::ffi::OsStrExt, path::Path};
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetDiskFreeSpaceExW(dir: *const u16, avail: *mut u64, total: *mut u64, free: *mut u64) -> i32;
+    }
+
+    pub fn free_bytes(dir: &Path) -> Option<u64> {
+        let wide: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut avail = 0u64;
+        // SAFETY: `wide` is NUL-terminated; the unused outputs may be null.
+        let ok = unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut avail, std::ptr::null_mut(), std::ptr::null_mut()) };
+        (ok != 0).then_some(avail)
+    }
+}
+
+#[cfg(not(windows))]
+mod system {
+    pub fn free_bytes(_dir: &std::path::Path) -> Option<u64> {
+        None
+    }
+}
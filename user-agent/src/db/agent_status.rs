@@ -0,0 +1,136 @@
+// src/db/agent_status.rs
+//! Persistence of the agent heartbeat (see [`crate::heartbeat`]).
+
+use std::collections::BTreeMap;
+use rusqlite::{params, Connection};
+use shared::constants::VersionInfo;
+use crate::db::schema_registry::{ensure_for, table_exists, TableDef};
+
+/// One row per heartbeat; counts are since the previous row. The `driver_`
+/// columns are NULL while no driver answered, `failed_tasks` while no
+/// supervised task was given up on.
+pub const AGENT_STATUS_TABLE: TableDef = TableDef {
+    name:     "agent_status",
+    version:  3,
+    ddl: "\
+CREATE TABLE IF NOT EXISTS agent_status (
+    id                INTEGER PRIMARY KEY,
+    ts                INTEGER NOT NULL,
+    version           TEXT    NOT NULL,
+    uptime_secs       REAL    NOT NULL,
+    ring_dropped      INTEGER NOT NULL,
+    processed         TEXT    NOT NULL,
+    db_size_bytes     INTEGER NOT NULL,
+    last_error        TEXT,
+    driver_protocol   INTEGER,
+    driver_major      INTEGER,
+    driver_minor      INTEGER,
+    driver_patch      INTEGER,
+    driver_build_time INTEGER,
+    failed_tasks      TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_agent_status_ts ON agent_status(ts);",
+    upgrades: &[
+        (2, "\
+ALTER TABLE agent_status ADD COLUMN driver_protocol INTEGER;
+ALTER TABLE agent_status ADD COLUMN driver_major INTEGER;
+ALTER TABLE agent_status ADD COLUMN driver_minor INTEGER;
+ALTER TABLE agent_status ADD COLUMN driver_patch INTEGER;
+ALTER TABLE agent_status ADD COLUMN driver_build_time INTEGER;"),
+        (3, "ALTER TABLE agent_status ADD COLUMN failed_tasks TEXT;"),
+    ],
+};
+
+/// One heartbeat.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentStatus {
+    /// UNIX microseconds.
+    pub ts:            i64,
+    pub version:       String,
+    pub uptime_secs:   f64,
+    /// Events the driver dropped from the rings.
+    pub ring_dropped:  u64,
+    /// Rows stored per table; stored as a JSON object.
+    pub processed:     BTreeMap<String, u64>,
+    pub db_size_bytes: u64,
+    /// Most recent error, if any was reported.
+    pub last_error:    Option<String>,
+    /// What the driver answered to `IOCTL_GLADIX_GET_VERSION` at startup.
+    pub driver:        Option<VersionInfo>,
+    /// Supervised tasks given up on; stored as a JSON array.
+    pub failed_tasks:  Vec<String>,
+}
+
+pub fn record_status(conn: &Connection, status: &AgentStatus) -> rusqlite::Result<()> {
+    ensure_for(conn, &AGENT_STATUS_TABLE)?;
+    let processed = serde_json::to_string(&status.processed).unwrap_or_else(|_| "{}".into());
+    let driver = status.driver.as_ref();
+    let failed_tasks = (!status.failed_tasks.is_empty())
+        .then(|| serde_json::to_string(&status.failed_tasks).unwrap_or_else(|_| "[]".into()));
+    conn.prepare_cached(
+        "INSERT INTO agent_status (ts, version, uptime_secs, ring_dropped, processed, db_size_bytes, last_error, \
+         driver_protocol, driver_major, driver_minor, driver_patch, driver_build_time, failed_tasks) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+    )?
+    .execute(params![
+        status.ts,
+        &status.version,
+        status.uptime_secs,
+        status.ring_dropped as i64,
+        processed,
+        status.db_size_bytes as i64,
+        status.last_error.as_deref(),
+        driver.map(|d| d.protocol),
+        driver.map(|d| d.major),
+        driver.map(|d| d.minor),
+        driver.map(|d| d.patch),
+        driver.map(|d| d.build_time as i64),
+        failed_tasks,
+    ])?;
+    Ok(())
+}
+
+/// Most recent heartbeats, newest first.
+pub fn recent_status(conn: &Connection, limit: usize) -> rusqlite::Result<Vec<AgentStatus>> {
+    if !table_exists(conn, AGENT_STATUS_TABLE.name)? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT ts, version, uptime_secs, ring_dropped, processed, db_size_bytes, last_error, \
+         driver_protocol, driver_major, driver_minor, driver_patch, driver_build_time, failed_tasks \
+         FROM agent_status ORDER BY id DESC LIMIT ?1",
+    )?;
+    let rows = stmt.query_map([limit as i64], |r| {
+        let driver = match r.get::<_, Option<u32>>(7)? {
+            Some(protocol) => Some(VersionInfo {
+                protocol,
+                major:      r.get(8)?,
+                minor:      r.get(9)?,
+                patch:      r.get(10)?,
+                build_time: r.get::<_, i64>(11)? as u64,
+                ..VersionInfo::default()
+            }),
+            None => None,
+        };
+        Ok(AgentStatus {
+            ts:            r.get(0)?,
+            version:       r.get(1)?,
+            uptime_secs:   r.get(2)?,
+            ring_dropped:  r.get::<_, i64>(3)? as u64,
+            processed:     serde_json::from_str(&r.get::<_, String>(4)?).unwrap_or_default(),
+            db_size_bytes: r.get::<_, i64>(5)? as u64,
+            last_error:    r.get(6)?,
+            driver,
+            failed_tasks:  r
+                .get::<_, Option<String>>(12)?
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default(),
+        })
+    })?;
+    rows.collect()
+}
+
+/// The most recent heartbeat, if any was written.
+pub fn latest_status(conn: &Connection) -> rusqlite::Result<Option<AgentStatus>> {
+    Ok(recent_status(conn, 1)?.into_iter().next())
+}
@@ -1,15 +1,29 @@
 // src/db/batch_inserts.rs
 
-use rusqlite::{params, Result as SqlResult, Statement};
+use rusqlite::{params, Connection, Result as SqlResult, Statement};
 use prost_types::Timestamp;
 
 use crate::comms::WrappedEvent;
+use crate::db::codec::Codec;
+use crate::db::event_types::{
+    ETW_EVENTS, FS_EVENTS, IMAGE_LOAD_EVENTS, NETWORK_EVENTS, OBJECT_OP_EVENTS, PROCESS_EVENTS, SCAN_RESULTS,
+};
+use crate::db::schema_registry::TableDef;
+use crate::intel::enrich::{extract_sid, normalize_path, resolve_sid};
+use crate::policy::Verdict;
 use shared::events::{
     FileEvent,
     NetworkEvent,
     EtwEvent,
+    ImageLoadEvent,
+    ObjectOpEvent,
     ProcessEvent,
+    ScanResult,
+    file_event::Operation as FileOperation,
     network_event::Direction as NetDirection,
+    object_op_event::Operation as ObjectOperation,
+    process_event::EventType as ProcessEventType,
+    scan_result::Severity as ScanSeverity,
 };
 
 /// Convierte un prost_types::Timestamp en micros UNIX.
@@ -21,21 +35,37 @@ fn timestamp_micros(ts: &Timestamp) -> i64 {
 
 /// Trait para insertar un registro en SQLite.
 pub trait BatchInsert<T> {
-    /// SQL de inserción para una fila.
+    /// SQL de inserción para una fila, generado en `event_types`.
     fn insert_sql() -> &'static str;
-    /// Vincula los campos de `record` y ejecuta la sentencia.
-    fn bind_and_execute(stmt: &mut Statement<'_>, record: &T) -> SqlResult<()>;
+    /// Tabla destino; el writer la crea en el primer flush si no existe.
+    fn schema() -> &'static TableDef;
+    /// Vincula los campos de `record` y ejecuta la sentencia. Las columnas de
+    /// texto grandes pasan por `codec`.
+    fn bind_and_execute(stmt: &mut Statement<'_>, record: &T, codec: &mut Codec) -> SqlResult<()>;
+    /// Completa, con lo ya guardado, las filas recién insertadas en `conn`
+    /// con los rowid `ids`. Por defecto no hace nada.
+    fn complete(_conn: &Connection, _ids: &[i64]) -> SqlResult<()> {
+        Ok(())
+    }
+}
+
+/// Nombre proto de la operación (`CREATE`, `RENAME`); los valores
+/// desconocidos se guardan como `UNKNOWN(n)`.
+pub fn file_operation(op: i32) -> String {
+    FileOperation::try_from(op).map_or_else(|_| format!("UNKNOWN({op})"), |o| o.as_str_name().to_owned())
 }
 
 /// FS EVENTS: WrappedEvent<FileEvent>
 impl BatchInsert<WrappedEvent<FileEvent>> for WrappedEvent<FileEvent> {
     fn insert_sql() -> &'static str {
-        "INSERT INTO fs_events \
-         (ts, sensor_guid, op, path, new_path, pid, exe_path, size, sha256, result) \
-         VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10)"
+        FS_EVENTS.insert_sql
     }
 
-    fn bind_and_execute(stmt: &mut Statement<'_>, rec: &WrappedEvent<FileEvent>) -> SqlResult<()> {
+    fn schema() -> &'static TableDef {
+        &FS_EVENTS.schema
+    }
+
+    fn bind_and_execute(stmt: &mut Statement<'_>, rec: &WrappedEvent<FileEvent>, _codec: &mut Codec) -> SqlResult<()> {
         let ts     = timestamp_micros(&rec.ts);
         let sensor = &rec.sensor_guid;
         let ev     = &rec.payload;
@@ -43,14 +73,20 @@ impl BatchInsert<WrappedEvent<FileEvent>> for WrappedEvent<FileEvent> {
         stmt.execute(params![
             ts,
             sensor,
-            format!("{:?}", ev.op),
+            file_operation(ev.op),
             &ev.path,
             &ev.new_path,             // ya es String
             ev.pid as i64,
             &ev.exe_path,
             ev.size as i64,
             &ev.sha256,
-            ev.success.to_string(),
+            ev.success,
+            rec.event_uid(),
+            rec.seq.map(|s| s as i64),
+            rec.coalesced.map_or(1, |c| c.count as i64),
+            rec.coalesced.map(|c| timestamp_micros(&c.last_ts)),
+            (!ev.raw_path.is_empty()).then_some(&ev.raw_path),
+            (!ev.raw_new_path.is_empty()).then_some(&ev.raw_new_path),
         ])?;
         Ok(())
     }
@@ -59,13 +95,14 @@ impl BatchInsert<WrappedEvent<FileEvent>> for WrappedEvent<FileEvent> {
 /// NETWORK EVENTS: WrappedEvent<NetworkEvent>
 impl BatchInsert<WrappedEvent<NetworkEvent>> for WrappedEvent<NetworkEvent> {
     fn insert_sql() -> &'static str {
-        "INSERT INTO network_events \
-         (ts, sensor_guid, direction, proto, src_ip, src_port, \
-          dst_ip, dst_port, pid, exe_path, bytes, verdict) \
-         VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10,?11,?12)"
+        NETWORK_EVENTS.insert_sql
     }
 
-    fn bind_and_execute(stmt: &mut Statement<'_>, rec: &WrappedEvent<NetworkEvent>) -> SqlResult<()> {
+    fn schema() -> &'static TableDef {
+        &NETWORK_EVENTS.schema
+    }
+
+    fn bind_and_execute(stmt: &mut Statement<'_>, rec: &WrappedEvent<NetworkEvent>, _codec: &mut Codec) -> SqlResult<()> {
         let ts     = timestamp_micros(&rec.ts);
         let sensor = &rec.sensor_guid;
         let ev     = &rec.payload;
@@ -87,7 +124,10 @@ impl BatchInsert<WrappedEvent<NetworkEvent>> for WrappedEvent<NetworkEvent> {
             ev.pid as i64,
             &ev.exe_path,
             ev.bytes as i64,
-            ev.blocked.to_string(),
+            Verdict::of(ev.blocked).as_str(),
+            rec.event_uid(),
+            rec.seq.map(|s| s as i64),
+            (!ev.dst_hostname.is_empty()).then_some(&ev.dst_hostname),
         ])?;
         Ok(())
     }
@@ -96,15 +136,18 @@ impl BatchInsert<WrappedEvent<NetworkEvent>> for WrappedEvent<NetworkEvent> {
 /// ETW EVENTS: WrappedEvent<EtwEvent>
 impl BatchInsert<WrappedEvent<EtwEvent>> for WrappedEvent<EtwEvent> {
     fn insert_sql() -> &'static str {
-        "INSERT INTO etw_events \
-         (ts, sensor_guid, provider_guid, event_id, level, pid, tid, json_payload) \
-         VALUES (?1,?2,?3,?4,?5,?6,?7,?8)"
+        ETW_EVENTS.insert_sql
     }
 
-    fn bind_and_execute(stmt: &mut Statement<'_>, rec: &WrappedEvent<EtwEvent>) -> SqlResult<()> {
+    fn schema() -> &'static TableDef {
+        &ETW_EVENTS.schema
+    }
+
+    fn bind_and_execute(stmt: &mut Statement<'_>, rec: &WrappedEvent<EtwEvent>, codec: &mut Codec) -> SqlResult<()> {
         let ts     = timestamp_micros(&rec.ts);
         let sensor = &rec.sensor_guid;
         let ev     = &rec.payload;
+        let sid    = extract_sid(&ev.json_payload).unwrap_or_default();
 
         stmt.execute(params![
             ts,
@@ -114,21 +157,47 @@ impl BatchInsert<WrappedEvent<EtwEvent>> for WrappedEvent<EtwEvent> {
             ev.level as i64,
             ev.pid as i64,
             ev.tid as i64,
-            &ev.json_payload,
+            codec.encode("etw_events.json_payload", &ev.json_payload),
+            rec.event_uid(),
+            &sid,
+            resolve_sid(&sid),
+            rec.seq.map(|s| s as i64),
         ])?;
         Ok(())
     }
 }
 
+/// Nombre proto del tipo (`CREATE`, `EXIT`); los valores desconocidos se
+/// guardan como número.
+fn process_event_type(ev: &ProcessEvent) -> String {
+    ProcessEventType::try_from(ev.event_type)
+        .map_or_else(|_| ev.event_type.to_string(), |t| t.as_str_name().to_owned())
+}
+
+/// Rellena los `parent_*` que el driver dejó vacíos en la fila `?1` con la
+/// última creación guardada del `ppid`, o con `''` si no hay ninguna.
+const COMPLETE_PARENT: &str = "
+UPDATE process_events AS c SET
+    parent_image_path = COALESCE(c.parent_image_path, (
+        SELECT p.image_path FROM process_events p
+        WHERE p.pid = c.ppid AND p.event_type = 'CREATE' AND p.id < c.id ORDER BY p.id DESC LIMIT 1), ''),
+    parent_cmdline = COALESCE(c.parent_cmdline, (
+        SELECT p.cmdline FROM process_events p
+        WHERE p.pid = c.ppid AND p.event_type = 'CREATE' AND p.id < c.id ORDER BY p.id DESC LIMIT 1), '')
+WHERE c.id = ?1 AND c.event_type = 'CREATE'
+  AND (c.parent_image_path IS NULL OR c.parent_cmdline IS NULL)";
 
+/// PROCESS EVENTS: WrappedEvent<ProcessEvent>
 impl BatchInsert<WrappedEvent<ProcessEvent>> for WrappedEvent<ProcessEvent> {
     fn insert_sql() -> &'static str {
-        "INSERT INTO etw_events \
-         (ts, sensor_guid, pid, ppid, image_path, cmdline) \
-         VALUES (?1,?2,?3,?4,?5,?6)"
+        PROCESS_EVENTS.insert_sql
+    }
+
+    fn schema() -> &'static TableDef {
+        &PROCESS_EVENTS.schema
     }
 
-    fn bind_and_execute(stmt: &mut Statement<'_>, rec: &WrappedEvent<ProcessEvent>) -> SqlResult<()> {
+    fn bind_and_execute(stmt: &mut Statement<'_>, rec: &WrappedEvent<ProcessEvent>, codec: &mut Codec) -> SqlResult<()> {
         let ts     = timestamp_micros(&rec.ts);
         let sensor = &rec.sensor_guid;
         let ev     = &rec.payload;
@@ -136,12 +205,138 @@ impl BatchInsert<WrappedEvent<ProcessEvent>> for WrappedEvent<ProcessEvent> {
         stmt.execute(params![
             ts,
             sensor,
-            ev.pid,
+            ev.pid as i64,
             ev.ppid as i64,
-            &ev.image_path,
-            &ev.cmdline,
+            codec.encode("process_events.image_path", &ev.image_path),
+            codec.encode("process_events.cmdline", &ev.cmdline),
+            rec.event_uid(),
+            ev.creator_pid as i64,
+            ev.creator_tid as i64,
+            normalize_path(&ev.image_path),
+            process_event_type(ev),
+            ev.is_exit().then_some(ev.exit_code),
+            // NULL hasta que `complete` lo busque.
+            (!ev.parent_image_path.is_empty())
+                .then(|| codec.encode("process_events.parent_image_path", &ev.parent_image_path)),
+            (!ev.parent_cmdline.is_empty()).then(|| codec.encode("process_events.parent_cmdline", &ev.parent_cmdline)),
+            rec.seq.map(|s| s as i64),
+            ev.truncated as i64,
+            (!ev.user_sid.is_empty()).then_some(&ev.user_sid),
+            (!ev.is_exit()).then_some(ev.session_id as i64),
+            (!ev.is_exit()).then_some(ev.elevated),
+        ])?;
+        Ok(())
+    }
+
+    fn complete(conn: &Connection, ids: &[i64]) -> SqlResult<()> {
+        let mut stmt = conn.prepare_cached(COMPLETE_PARENT)?;
+        for id in ids {
+            stmt.execute([id])?;
+        }
+        Ok(())
+    }
+}
+
+/// SCAN RESULTS: WrappedEvent<ScanResult>
+impl BatchInsert<WrappedEvent<ScanResult>> for WrappedEvent<ScanResult> {
+    fn insert_sql() -> &'static str {
+        SCAN_RESULTS.insert_sql
+    }
+
+    fn schema() -> &'static TableDef {
+        &SCAN_RESULTS.schema
+    }
+
+    fn bind_and_execute(stmt: &mut Statement<'_>, rec: &WrappedEvent<ScanResult>, _codec: &mut Codec) -> SqlResult<()> {
+        let ts     = timestamp_micros(&rec.ts);
+        let sensor = &rec.sensor_guid;
+        let ev     = &rec.payload;
+
+        // Severidades desconocidas se guardan como número
+        let severity = ScanSeverity::try_from(ev.severity)
+            .map_or_else(|_| ev.severity.to_string(), |s| s.as_str_name().to_owned());
+
+        stmt.execute(params![
+            ts,
+            sensor,
+            &ev.file_path,
+            ev.size as i64,
+            format!("{:016x}", ev.hash),
+            ev.mtime as i64,
+            &ev.risk_group,
+            &ev.rule_id,
+            ev.matches.join("\n"),
+            severity,
+            rec.event_uid(),
+            // NULL si el grupo no calcula SHA-256
+            (!ev.sha256.is_empty()).then(|| hex::encode(&ev.sha256)),
+            rec.seq.map(|s| s as i64),
+        ])?;
+        Ok(())
+    }
+}
+
+/// IMAGE LOAD EVENTS: WrappedEvent<ImageLoadEvent>
+impl BatchInsert<WrappedEvent<ImageLoadEvent>> for WrappedEvent<ImageLoadEvent> {
+    fn insert_sql() -> &'static str {
+        IMAGE_LOAD_EVENTS.insert_sql
+    }
+
+    fn schema() -> &'static TableDef {
+        &IMAGE_LOAD_EVENTS.schema
+    }
+
+    fn bind_and_execute(stmt: &mut Statement<'_>, rec: &WrappedEvent<ImageLoadEvent>, _codec: &mut Codec) -> SqlResult<()> {
+        let ts     = timestamp_micros(&rec.ts);
+        let sensor = &rec.sensor_guid;
+        let ev     = &rec.payload;
+
+        stmt.execute(params![
+            ts,
+            sensor,
+            ev.pid as i64,
+            format!("{:016x}", ev.image_base),
+            ev.image_size as i64,
+            &ev.full_image_name,
+            ev.is_kernel_module,
+            rec.event_uid(),
+            rec.seq.map(|s| s as i64),
         ])?;
         Ok(())
     }
 }
 
+/// Nombre de la operación sobre el handle; los valores que no conocemos se
+/// guardan igual, como en `file_operation`.
+pub fn object_operation(op: i32) -> String {
+    ObjectOperation::try_from(op).map_or_else(|_| format!("UNKNOWN({op})"), |o| o.as_str_name().to_owned())
+}
+
+/// OBJECT OP EVENTS: WrappedEvent<ObjectOpEvent>
+impl BatchInsert<WrappedEvent<ObjectOpEvent>> for WrappedEvent<ObjectOpEvent> {
+    fn insert_sql() -> &'static str {
+        OBJECT_OP_EVENTS.insert_sql
+    }
+
+    fn schema() -> &'static TableDef {
+        &OBJECT_OP_EVENTS.schema
+    }
+
+    fn bind_and_execute(stmt: &mut Statement<'_>, rec: &WrappedEvent<ObjectOpEvent>, _codec: &mut Codec) -> SqlResult<()> {
+        let ts     = timestamp_micros(&rec.ts);
+        let sensor = &rec.sensor_guid;
+        let ev     = &rec.payload;
+
+        stmt.execute(params![
+            ts,
+            sensor,
+            ev.source_pid as i64,
+            ev.target_pid as i64,
+            ev.desired_access as i64,
+            object_operation(ev.operation),
+            rec.event_uid(),
+            rec.seq.map(|s| s as i64),
+        ])?;
+        Ok(())
+    }
+}
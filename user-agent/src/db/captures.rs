@@ -0,0 +1,79 @@
+// src/db/captures.rs
+//! Persistence of memory captures taken by the memdump action.
+
+use std::path::PathBuf;
+use rusqlite::{params, Connection};
+use crate::actions::{Capture, CaptureStatus};
+use crate::db::schema_registry::{ensure_for, table_exists, TableDef};
+
+/// One row per request, with `path` and `size` NULL unless the status is
+/// `captured`.
+pub const CAPTURES_TABLE: TableDef = TableDef {
+    name:     "captures",
+    version:  1,
+    ddl: "\
+CREATE TABLE IF NOT EXISTS captures (
+    id         INTEGER PRIMARY KEY,
+    ts         INTEGER NOT NULL,
+    alert_id   INTEGER NOT NULL,
+    pid        INTEGER NOT NULL,
+    image      TEXT,
+    status     TEXT    NOT NULL,
+    path       TEXT,
+    size       INTEGER,
+    truncated  BOOLEAN NOT NULL DEFAULT FALSE,
+    compressed BOOLEAN NOT NULL DEFAULT FALSE,
+    error      TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_captures_alert ON captures(alert_id);",
+    upgrades: &[],
+};
+
+/// Stores `c` and returns its row id.
+pub fn record_capture(conn: &Connection, c: &Capture) -> rusqlite::Result<i64> {
+    ensure_for(conn, &CAPTURES_TABLE)?;
+    conn.execute(
+        "INSERT INTO captures (ts, alert_id, pid, image, status, path, size, truncated, compressed, error) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            c.ts,
+            c.alert_id,
+            c.pid as i64,
+            c.image.as_deref(),
+            c.status.as_str(),
+            c.path.as_deref().map(|p| p.to_string_lossy()),
+            c.size.map(|s| s as i64),
+            c.truncated,
+            c.compressed,
+            c.error.as_deref(),
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Captures requested for `alert_id`, oldest first.
+pub fn captures_for_alert(conn: &Connection, alert_id: i64) -> rusqlite::Result<Vec<Capture>> {
+    if !table_exists(conn, CAPTURES_TABLE.name)? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT ts, pid, image, status, path, size, truncated, compressed, error FROM captures \
+         WHERE alert_id = ?1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map([alert_id], |r| {
+        let status: String = r.get(3)?;
+        Ok(Capture {
+            ts:         r.get(0)?,
+            alert_id,
+            pid:        r.get::<_, i64>(1)? as u32,
+            image:      r.get(2)?,
+            status:     CaptureStatus::parse(&status).unwrap_or(CaptureStatus::Failed),
+            path:       r.get::<_, Option<String>>(4)?.map(PathBuf::from),
+            size:       r.get::<_, Option<i64>>(5)?.map(|s| s as u64),
+            truncated:  r.get(6)?,
+            compressed: r.get(7)?,
+            error:      r.get(8)?,
+        })
+    })?;
+    rows.collect()
+}
@@ -0,0 +1,212 @@
+// src/db/codec.rs
+//! Transparent compression of large text columns.
+//!
+//! Values of the columns listed in `database.compress_columns` that reach
+//! `database.compress_threshold` bytes are stored as a tagged BLOB holding a
+//! zstd frame; everything else stays plain TEXT. Readers go through
+//! [`StoredText`], which accepts both forms, so partially migrated tables
+//! read the same. Event ids are hashed from the decoded payload, never from
+//! the stored form, so compression does not change them.
+//!
+//! The embedded dictionary was trained offline with
+//! `zstd --train --maxdict=16384` on ETW JSON payloads (PowerShell script
+//! blocks, Kernel-Process, DNS-Client) and PowerShell command lines. A new
+//! dictionary needs a new kind byte so existing values keep decoding.
+
+use std::{cell::RefCell, collections::HashSet, io, time::Instant};
+use metrics::{counter, histogram};
+use rusqlite::{
+    params,
+    types::{FromSql, FromSqlError, FromSqlResult, Value, ValueRef},
+    Connection,
+};
+use zstd::bulk::{Compressor, Decompressor};
+
+use crate::config::model::DatabaseConfig;
+use crate::db::{db_writer::DbError, schema_registry::table_exists};
+
+/// Columns whose writers route values through the codec (`table.column`).
+pub const COMPRESSIBLE: &[&str] = &[
+    "etw_events.json_payload",
+    "process_events.cmdline",
+    "process_events.image_path",
+    "process_events.parent_cmdline",
+    "process_events.parent_image_path",
+];
+
+const MAGIC: u8 = 0xC5;
+const KIND_PLAIN: u8 = 0;
+const KIND_DICT_V1: u8 = 1;
+static DICT_V1: &[u8] = include_bytes!("../../resources/zstd/text_v1.dict");
+const LEVEL: i32 = 3;
+/// Upper bound for a decoded value; guards readers against corrupt blobs.
+const MAX_TEXT: usize = 64 << 20;
+
+thread_local! {
+    static DICT_DECODER: RefCell<Option<Decompressor<'static>>> =
+        RefCell::new(Decompressor::with_dictionary(DICT_V1).ok());
+}
+
+/// Rejects columns no writer routes through the codec.
+pub fn validate(cfg: &DatabaseConfig) -> Result<(), DbError> {
+    match cfg.compress_columns.iter().find(|c| !COMPRESSIBLE.contains(&c.as_str())) {
+        Some(bad) => Err(DbError::InvalidConfig(format!(
+            "database.compress_columns: '{bad}' is not one of {COMPRESSIBLE:?}"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Per-writer compression state.
+pub struct Codec {
+    threshold: usize,
+    columns:   HashSet<String>,
+    /// `None` if the dictionary cannot be loaded; values are then compressed
+    /// without it.
+    dict:      Option<Compressor<'static>>,
+}
+
+impl Codec {
+    pub fn new(cfg: &DatabaseConfig) -> Result<Self, DbError> {
+        validate(cfg)?;
+        let dict = if cfg.compress_columns.is_empty() {
+            None
+        } else {
+            Compressor::with_dictionary(LEVEL, DICT_V1)
+                .map_err(|e| log::warn!("zstd dictionary unavailable, compressing without: {}", e))
+                .ok()
+        };
+        Ok(Self {
+            threshold: cfg.compress_threshold,
+            columns:   cfg.compress_columns.iter().cloned().collect(),
+            dict,
+        })
+    }
+
+    /// Codec that stores everything as plain text.
+    pub fn disabled() -> Self {
+        Self { threshold: usize::MAX, columns: HashSet::new(), dict: None }
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = &str> {
+        self.columns.iter().map(String::as_str)
+    }
+
+    /// Storage form of `text` for `column` (`table.column`).
+    pub fn encode(&mut self, column: &str, text: &str) -> Value {
+        if text.len() < self.threshold || !self.columns.contains(column) {
+            return Value::Text(text.to_owned());
+        }
+        let start = Instant::now();
+        let packed = self.compress(text.as_bytes());
+        histogram!("db_compress_duration_seconds").record(start.elapsed().as_secs_f64());
+        match packed {
+            Some(blob) if blob.len() < text.len() => {
+                counter!("db_compress_bytes_saved_total").increment((text.len() - blob.len()) as u64);
+                Value::Blob(blob)
+            }
+            _ => Value::Text(text.to_owned()),
+        }
+    }
+
+    fn compress(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        if let Some(frame) = self.dict.as_mut().and_then(|c| c.compress(data).ok()) {
+            return Some(tag(KIND_DICT_V1, frame));
+        }
+        zstd::bulk::compress(data, LEVEL).ok().map(|frame| tag(KIND_PLAIN, frame))
+    }
+}
+
+fn tag(kind: u8, frame: Vec<u8>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(frame.len() + 2);
+    out.extend_from_slice(&[MAGIC, kind]);
+    out.extend_from_slice(&frame);
+    out
+}
+
+pub fn is_compressed(blob: &[u8]) -> bool {
+    blob.len() >= 2 && blob[0] == MAGIC
+}
+
+/// Decodes a BLOB written by [`Codec::encode`].
+pub fn decode(blob: &[u8]) -> io::Result<String> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+    if !is_compressed(blob) {
+        return Err(invalid("not a compressed text value"));
+    }
+    let frame = &blob[2..];
+    let bytes = match blob[1] {
+        KIND_PLAIN   => zstd::bulk::decompress(frame, MAX_TEXT)?,
+        KIND_DICT_V1 => DICT_DECODER.with(|d| match d.borrow_mut().as_mut() {
+            Some(d) => d.decompress(frame, MAX_TEXT),
+            None    => Err(invalid("zstd dictionary unavailable")),
+        })?,
+        _ => return Err(invalid("unknown compression kind")),
+    };
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Text column value that may be stored compressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredText(pub String);
+
+impl FromSql for StoredText {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Text(t) => Ok(StoredText(String::from_utf8_lossy(t).into_owned())),
+            ValueRef::Blob(b) => decode(b).map(StoredText).map_err(|e| FromSqlError::Other(Box::new(e))),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// Progress of one [`backfill_chunk`] call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Backfill {
+    /// Highest row id examined; `None` once nothing is left.
+    pub last_id:     Option<i64>,
+    pub compressed:  usize,
+    pub bytes_saved: u64,
+}
+
+/// Compresses up to `chunk` oversized plain values of `column` with row id
+/// above `after_id`, in one transaction.
+pub fn backfill_chunk(
+    conn: &Connection,
+    codec: &mut Codec,
+    column: &str,
+    after_id: i64,
+    chunk: usize,
+) -> rusqlite::Result<Backfill> {
+    let Some((table, col)) = column.split_once('.').filter(|_| COMPRESSIBLE.contains(&column)) else {
+        return Ok(Backfill::default());
+    };
+    if !table_exists(conn, table)? {
+        return Ok(Backfill::default());
+    }
+    let rows: Vec<(i64, String)> = {
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT id, {col} FROM {table} \
+             WHERE id > ?1 AND typeof({col}) = 'text' AND length(CAST({col} AS BLOB)) >= ?2 \
+             ORDER BY id LIMIT ?3"
+        ))?;
+        let threshold = codec.threshold.min(i64::MAX as usize) as i64;
+        stmt.query_map(params![after_id, threshold, chunk as i64], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?
+    };
+
+    let mut step = Backfill { last_id: rows.last().map(|r| r.0), ..Backfill::default() };
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut update = tx.prepare_cached(&format!("UPDATE {table} SET {col} = ?1 WHERE id = ?2"))?;
+        for (id, text) in rows {
+            if let Value::Blob(blob) = codec.encode(column, &text) {
+                step.bytes_saved += (text.len() - blob.len()) as u64;
+                step.compressed += 1;
+                update.execute(params![blob, id])?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(step)
+}
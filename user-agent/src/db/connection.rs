@@ -1,9 +1,71 @@
 // src/db/connection.rs
 //! Opening and initialising SQLite with runtime parameters.
+//!
+//! Tables created from SQL are migrated as a whole: [`MIGRATIONS`] are the
+//! scripts under `resources/migrations`, and `PRAGMA user_version` is the
+//! last one applied. A schema change is a new script appended to the list.
+//! Tables declared in Rust are versioned per table by `db::schema_registry`.
 
 use std::{fs, path::{Path, PathBuf}, time::Duration};
-use rusqlite::Connection;
+use rusqlite::{Connection, TransactionBehavior};
 use crate::config::model::DatabaseConfig;
+use crate::db::{
+    codec,
+    db_writer::DbError,
+    event_types::event_table,
+    preflight::{self, Requirements},
+    reprocess,
+    schema_registry::{self, Ensured, SchemaRegistry, CORE_TABLES, LAZY_TABLES},
+    snapshots::{self, RiskyOp},
+};
+
+/// How `database.page_size` was honoured by [`init_database`].
+///
+/// SQLite only accepts a new page size before the first table is written or
+/// through a `VACUUM`, and never while the file is in WAL mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSizeOutcome {
+    /// Not configured; the SQLite default is kept.
+    Default,
+    /// The configured size was already in effect.
+    Unchanged,
+    /// Fresh file: applied before any table was created.
+    NewDatabase,
+    /// Existing file: rebuilt with `VACUUM` after leaving WAL temporarily.
+    Vacuumed,
+}
+
+/// `(version, sql)` steps, ascending from 1.
+pub const MIGRATIONS: &[(u32, &str)] = &[
+    (1, include_str!("../../resources/migrations/0001_initial.sql")),
+    (2, include_str!("../../resources/migrations/0002_alerts_consumer_state.sql")),
+];
+
+/// Version [`migrate`] brings a database to.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].0;
+
+/// Last migration applied to `conn`'s database; 0 for a new file or one
+/// older than the migration runner.
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |r| r.get(0))
+}
+
+/// Applies the [`MIGRATIONS`] `conn` lacks, each with its version bump in
+/// one transaction. Refuses a database written by a newer agent. Returns
+/// the version it started from.
+pub fn migrate(conn: &mut Connection) -> Result<u32, DbError> {
+    let from = schema_version(conn)?;
+    if from > SCHEMA_VERSION {
+        return Err(DbError::TooNew { found: from, known: SCHEMA_VERSION });
+    }
+    for (version, sql) in MIGRATIONS.iter().filter(|(v, _)| *v > from) {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+    }
+    Ok(from)
+}
 
 pub fn db_path(exe_dir: &Path, cfg: &DatabaseConfig) -> PathBuf {
     exe_dir.join(&cfg.path)
@@ -11,27 +73,137 @@ pub fn db_path(exe_dir: &Path, cfg: &DatabaseConfig) -> PathBuf {
 
 pub fn open_db_connection(path: &Path, cfg: &DatabaseConfig) -> rusqlite::Result<Connection> {
     let conn = Connection::open(path)?;
+    configure_connection(&conn, cfg)?;
+    Ok(conn)
+}
+
+/// Per-connection pragmas; page cache size and the WAL size limit are not
+/// persisted by SQLite.
+fn configure_connection(conn: &Connection, cfg: &DatabaseConfig) -> rusqlite::Result<()> {
     conn.busy_timeout(Duration::from_millis(1_000))?;
     conn.pragma_update(None, "journal_mode", &"WAL")?;
     conn.pragma_update(None, "synchronous", &cfg.synchronous.as_str())?;
-    Ok(conn)
+    conn.pragma_update(None, "journal_size_limit", cfg.journal_size_limit as i64)?;
+    if let Some(kb) = cfg.cache_kb {
+        // Negative values are interpreted as KiB rather than pages.
+        conn.pragma_update(None, "cache_size", -(kb as i64))?;
+    }
+    Ok(())
+}
+
+/// `true` when [`apply_page_size`] would rebuild an existing file.
+pub fn needs_vacuum(conn: &Connection, page_size: Option<u32>, first_run: bool) -> rusqlite::Result<bool> {
+    let Some(size) = page_size.filter(|_| !first_run) else { return Ok(false) };
+    let current: u32 = conn.query_row("PRAGMA page_size", [], |r| r.get(0))?;
+    Ok(current != size)
+}
+
+/// Applies `page_size` to a connection that is not in WAL mode yet.
+pub fn apply_page_size(
+    conn: &Connection,
+    page_size: Option<u32>,
+    first_run: bool,
+) -> Result<PageSizeOutcome, DbError> {
+    let Some(size) = page_size else {
+        return Ok(PageSizeOutcome::Default);
+    };
+    if !(512..=65_536).contains(&size) || !size.is_power_of_two() {
+        return Err(DbError::InvalidConfig(format!(
+            "database.page_size must be a power of two between 512 and 65536, got {size}"
+        )));
+    }
+
+    if first_run {
+        conn.pragma_update(None, "page_size", size)?;
+        return Ok(PageSizeOutcome::NewDatabase);
+    }
+
+    let current: u32 = conn.query_row("PRAGMA page_size", [], |r| r.get(0))?;
+    if current == size {
+        return Ok(PageSizeOutcome::Unchanged);
+    }
+    conn.pragma_update(None, "journal_mode", "DELETE")?;
+    conn.pragma_update(None, "page_size", size)?;
+    conn.execute_batch("VACUUM;")?;
+    Ok(PageSizeOutcome::Vacuumed)
 }
 
-pub fn init_database(exe_dir: &Path, cfg: &DatabaseConfig) -> rusqlite::Result<Connection> {
+pub fn init_database(exe_dir: &Path, cfg: &DatabaseConfig) -> Result<Connection, DbError> {
     let path = db_path(exe_dir, cfg);
+    let root = snapshots::snapshot_root(exe_dir, &cfg.snapshots);
+    let mut taken = Vec::new();
+    codec::validate(cfg)?;
 
-    if cfg.purge_on_restart && path.exists() {
+    let purged = cfg.purge_on_restart.tables();
+    if path.exists() && (cfg.purge_on_restart.whole_file() || !purged.is_empty()) {
+        taken.extend(snapshots::guard(&path, &root, &cfg.snapshots, RiskyOp::Purge)?);
+    }
+    if cfg.purge_on_restart.whole_file() && path.exists() {
         let _ = fs::remove_file(&path);
     }
     let first_run = !path.exists();
 
-    let conn = open_db_connection(&path, cfg)?;
-    conn.pragma_update(None, "journal_size_limit", &(cfg.journal_size_limit as i64))?;
+    let mut conn = Connection::open(&path)?;
+    // Before anything is written to a file this agent cannot read.
+    let version = schema_version(&conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(DbError::TooNew { found: version, known: SCHEMA_VERSION });
+    }
+    if needs_vacuum(&conn, cfg.page_size, first_run)? {
+        taken.extend(snapshots::guard(&path, &root, &cfg.snapshots, RiskyOp::LayoutConversion)?);
+    }
+    // Must precede WAL: the page size is frozen once the file is in WAL mode.
+    let page = apply_page_size(&conn, cfg.page_size, first_run)?;
 
-    if first_run {
-        let schema = include_str!("../../resources/schema.sql");
-        conn.execute_batch(schema)?;
+    let report = preflight::check(&conn, &Requirements::default())?;
+    if !report.is_ok() {
+        return Err(DbError::Unsupported(Box::new(report)));
+    }
+    log::debug!("{report}");
+
+    configure_connection(&conn, cfg)?;
+
+    let all: Vec<_> = CORE_TABLES.iter().chain(LAZY_TABLES).copied().collect();
+    if (!first_run && version < SCHEMA_VERSION)
+        || reprocess::needs_migration(&conn)?
+        || !schema_registry::pending_upgrades(&conn, &all)?.is_empty()
+    {
+        taken.extend(snapshots::guard(&path, &root, &cfg.snapshots, RiskyOp::Migration)?);
+    }
+    migrate(&mut conn)?;
+    if !first_run && version < SCHEMA_VERSION {
+        log::info!("migrated database from version {version} to {SCHEMA_VERSION}");
+    }
+    // Lazy tables are only upgraded if they exist; the others are created on
+    // first use, already at their current version.
+    let registry = SchemaRegistry::new(&path);
+    for def in CORE_TABLES {
+        match registry.ensure(def)? {
+            Ensured::Present => {}
+            Ensured::Created => log::debug!("created table {}", def.name),
+            Ensured::Upgraded { from, to } => log::info!("upgraded table {} from version {from} to {to}", def.name),
+        }
+    }
+    for (name, from) in registry.upgrade_existing(LAZY_TABLES)? {
+        log::info!("upgraded table {name} from version {from}");
+    }
+    for name in reprocess::migrate(&conn)? {
+        log::info!("added columns for {}, reprocessing existing rows", name);
+    }
+    for name in purged {
+        // Validated by the config loader.
+        let Some(event) = event_table(name) else { continue };
+        if schema_registry::table_exists(&conn, event.table)? {
+            let n = conn.execute(&format!("DELETE FROM {}", event.table), [])?;
+            log::info!("purged {} rows from {} on restart", n, event.table);
+        }
+    }
+    for m in &taken {
+        snapshots::record(&conn, &root, m)?;
     }
-    log::info!("Database ready at {}", path.display());
+    log::info!(
+        "Database ready at {} (SQLite {}, page_size {:?})",
+        path.display(), report.version, page
+    );
     Ok(conn)
 }
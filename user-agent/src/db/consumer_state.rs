@@ -0,0 +1,82 @@
+// src/db/consumer_state.rs
+//! Durable ring consumer progress and coverage-gap accounting.
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// Last ring position whose events are known to be committed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoredPosition {
+    pub position:  u64,
+    /// Data-area size of the ring when the position was recorded.
+    pub ring_size: u64,
+}
+
+pub fn load_position(conn: &Connection, ring: &str) -> rusqlite::Result<Option<StoredPosition>> {
+    conn.query_row(
+        "SELECT position, ring_size FROM consumer_state WHERE ring = ?1",
+        [ring],
+        |r| Ok(StoredPosition { position: r.get::<_, i64>(0)? as u64, ring_size: r.get::<_, i64>(1)? as u64 }),
+    )
+    .optional()
+}
+
+pub fn store_position(conn: &Connection, ring: &str, pos: StoredPosition) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO consumer_state (ring, position, ring_size, updated_at) \
+         VALUES (?1, ?2, ?3, ?4) \
+         ON CONFLICT(ring) DO UPDATE SET \
+            position = excluded.position, \
+            ring_size = excluded.ring_size, \
+            updated_at = excluded.updated_at",
+        params![ring, pos.position as i64, pos.ring_size as i64, chrono::Utc::now().timestamp_micros()],
+    )?;
+    Ok(())
+}
+
+/// Moves the stored position after a flush, keeping the ring size recorded
+/// at startup (0 if the ring was never reconciled).
+pub fn advance_position(conn: &Connection, ring: &str, position: u64) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO consumer_state (ring, position, ring_size, updated_at) \
+         VALUES (?1, ?2, 0, ?3) \
+         ON CONFLICT(ring) DO UPDATE SET \
+            position = excluded.position, \
+            updated_at = excluded.updated_at",
+        params![ring, position as i64, chrono::Utc::now().timestamp_micros()],
+    )?;
+    Ok(())
+}
+
+/// Telemetry the agent knows it may have missed. `bytes` is `None` when the
+/// size of the gap cannot be known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageGap {
+    /// UNIX microseconds.
+    pub ts:    i64,
+    pub ring:  String,
+    pub kind:  String,
+    pub bytes: Option<u64>,
+}
+
+pub fn record_gap(conn: &Connection, ring: &str, kind: &str, bytes: Option<u64>) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO coverage_gaps (ts, ring, kind, bytes) VALUES (?1, ?2, ?3, ?4)",
+        params![chrono::Utc::now().timestamp_micros(), ring, kind, bytes.map(|b| b as i64)],
+    )?;
+    Ok(())
+}
+
+pub fn coverage_gaps(conn: &Connection, ring: &str) -> rusqlite::Result<Vec<CoverageGap>> {
+    let mut stmt = conn.prepare(
+        "SELECT ts, ring, kind, bytes FROM coverage_gaps WHERE ring = ?1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map([ring], |r| {
+        Ok(CoverageGap {
+            ts:    r.get(0)?,
+            ring:  r.get(1)?,
+            kind:  r.get(2)?,
+            bytes: r.get::<_, Option<i64>>(3)?.map(|b| b as u64),
+        })
+    })?;
+    rows.collect()
+}
@@ -1,77 +1,268 @@
 // src/db/db_writer.rs
 
-use rusqlite::Connection;
-use std::time::{Duration, Instant};
+use rusqlite::{Connection, Transaction};
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
 use thiserror::Error;
-use metrics::{histogram, counter};
-use crate::db::batch_inserts::BatchInsert; 
-
-/// A high-performance, batched writer for SQLite.
-/// Performs all DB work synchronously to avoid holding &Connection across .await.
-pub struct DbWriter<T> {
-    pub conn: Connection,
-    pub rx: tokio::sync::mpsc::Receiver<T>,
-    pub flush_interval_ms: u64,
-    pub batch_size: usize,
+use tokio::sync::{watch, Notify};
+use metrics::counter;
+use crate::config::model::DatabaseConfig;
+use crate::db::{
+    batch_inserts::BatchInsert,
+    codec::Codec,
+    ops_journal::{self, Actor, Entry, Subsystem},
+    preflight::CapabilityReport,
+};
+use crate::util::{Jitter, RetryPolicy};
+
+/// Flush acknowledgement for a ring-fed writer: after every successful flush
+/// the ring position of the last flushed event is stored in `consumer_state`
+/// and published on the watch channel.
+#[derive(Clone)]
+pub struct FlushAck {
+    pub ring: &'static str,
+    tx:       watch::Sender<Option<u64>>,
+}
+
+impl FlushAck {
+    pub fn new(ring: &'static str) -> (Self, watch::Receiver<Option<u64>>) {
+        let (tx, rx) = watch::channel(None);
+        (Self { ring, tx }, rx)
+    }
+
+    /// Announces that everything up to `pos` is committed.
+    pub(crate) fn publish(&self, pos: u64) {
+        self.tx.send_replace(Some(pos));
+    }
+}
+
+/// Writers whose last flush was forced by a full batch rather than the timer.
+static SATURATED_WRITERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Woken when the last saturated writer catches up.
+static PRESSURE_EASED: Notify = Notify::const_new();
+
+/// Correlation id of the ops journal entry for the current pressure episode.
+static PRESSURE_EPISODE: Mutex<Option<String>> = Mutex::new(None);
+
+/// `true` while any writer is flushing full batches; background jobs back
+/// off so they do not compete with live telemetry.
+pub fn under_pressure() -> bool {
+    SATURATED_WRITERS.load(Ordering::Relaxed) > 0
+}
+
+/// Resolves once [`under_pressure`] is `false`.
+pub async fn pressure_eased() {
+    loop {
+        // Registered before the check, so a wake in between is not lost.
+        let eased = PRESSURE_EASED.notified();
+        if !under_pressure() {
+            return;
+        }
+        eased.await;
+    }
+}
+
+/// Failed flushes tried again on exit before the rows are given up.
+const EXIT_ATTEMPTS: u32 = 3;
+
+/// Retry schedule of a writer whose flushes fail. The rows stay buffered
+/// and the flush is tried again after `database.flush_interval_ms`,
+/// doubling up to `database.flush_retry_max_ms`.
+pub struct FlushRetry {
+    policy:   RetryPolicy,
+    /// Consecutive failed flushes.
+    failures: u32,
+    due:      Option<tokio::time::Instant>,
+}
+
+impl FlushRetry {
+    pub(crate) fn new(cfg: &DatabaseConfig) -> Self {
+        let policy = RetryPolicy::new("db_flush", Duration::from_millis(cfg.flush_interval_ms))
+            .max_delay(Duration::from_millis(cfg.flush_retry_max_ms))
+            .jitter(Jitter::None);
+        Self { policy, failures: 0, due: None }
+    }
+
+    /// `false` while waiting for a retry.
+    pub(crate) fn ready(&self) -> bool {
+        self.due.is_none_or(|due| tokio::time::Instant::now() >= due)
+    }
+
+    /// Resolves when the pending retry is due; never if nothing failed.
+    pub(crate) async fn due(&self) {
+        match self.due {
+            Some(due) => tokio::time::sleep_until(due).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Schedules the next attempt and returns how long it is away.
+    pub(crate) fn failed(&mut self) -> Duration {
+        self.failures += 1;
+        let delay = self.policy.delay(self.failures, 0.0);
+        self.due = Some(tokio::time::Instant::now() + delay);
+        delay
+    }
+
+    pub(crate) fn succeeded(&mut self) {
+        self.failures = 0;
+        self.due = None;
+    }
+
+    /// Whether the exit path should keep retrying.
+    pub(crate) fn retry_on_exit(&self) -> bool {
+        self.failures > 0 && self.failures < EXIT_ATTEMPTS
+    }
+}
+
+/// Drops the oldest rows of `buffer` beyond `max`, counting them per table
+/// (named by `table`) in `db_events_dropped_total`.
+pub(crate) fn shed_oldest<T>(buffer: &mut Vec<T>, max: usize, table: impl Fn(&T) -> &'static str) {
+    if buffer.len() <= max {
+        return;
+    }
+    let mut dropped: BTreeMap<&'static str, u64> = BTreeMap::new();
+    for ev in buffer.drain(..buffer.len() - max) {
+        *dropped.entry(table(&ev)).or_default() += 1;
+    }
+    for (table, count) in dropped {
+        counter!("db_events_dropped_total", "table" => table).increment(count);
+        log::error!("dropped {} rows for {} that could not be stored ({} still pending)", count, table, max);
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum DbError {
     #[error("SQLite error: {0}")]
     Sql(#[from] rusqlite::Error),
+
+    #[error("SQLite runtime unsupported:\n{0}")]
+    Unsupported(Box<CapabilityReport>),
+
+    #[error("invalid database configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("safety snapshot before {0} failed: {1}")]
+    Snapshot(&'static str, String),
+
+    #[error("database schema version {found} is newer than this agent's ({known})")]
+    TooNew { found: u32, known: u32 },
 }
 
-impl<T> DbWriter<T>
-where
-    T: Send + 'static + BatchInsert<T>,
-{
-    pub async fn run(mut self) {
-        let mut buffer = Vec::with_capacity(self.batch_size);
-        let mut interval = tokio::time::interval(Duration::from_millis(self.flush_interval_ms));
-
-        loop {
-            tokio::select! {
-                maybe = self.rx.recv() => match maybe {
-                    Some(ev) => {
-                        buffer.push(ev);
-                        if buffer.len() >= self.batch_size {
-                            let _ = self.flush_sync(&mut buffer);
-                        }
-                    }
-                    None => {
-                        let _ = self.flush_sync(&mut buffer);
-                        break;
-                    }
-                },
-                _ = interval.tick() => {
-                    let _ = self.flush_sync(&mut buffer);
-                }
-            }
-        }
+/// Moves a writer in or out of the saturated set; the first writer in and
+/// the last one out are journaled on `conn`. `table` names what the writer
+/// stores.
+pub(crate) fn note_saturation(
+    conn: &Connection,
+    current: &mut bool,
+    saturated: bool,
+    table: &str,
+    batch_size: usize,
+) {
+    if saturated == *current {
+        return;
     }
-
-    fn flush_sync(&mut self, buffer: &mut Vec<T>) -> Result<(), DbError> {
-        let batch_count = buffer.len() as f64;
-        if batch_count == 0.0 {
-            return Ok(());
+    *current = saturated;
+    if saturated {
+        if SATURATED_WRITERS.fetch_add(1, Ordering::Relaxed) == 0 {
+            let correlation = ops_journal::new_correlation();
+            *PRESSURE_EPISODE.lock().unwrap() = Some(correlation.clone());
+            journal(conn, &correlation, "writers under pressure: background jobs back off", table, batch_size);
         }
+    } else if SATURATED_WRITERS.fetch_sub(1, Ordering::Relaxed) == 1 {
+        PRESSURE_EASED.notify_waiters();
+        if let Some(correlation) = PRESSURE_EPISODE.lock().unwrap().take() {
+            journal(conn, &correlation, "writer pressure eased", table, batch_size);
+        }
+    }
+}
 
-        let start = Instant::now();
-        let sql = T::insert_sql();
-        let mut stmt = self.conn.prepare_cached(sql)?;
+/// Records a pressure transition seen by the writer of `table`.
+fn journal(conn: &Connection, correlation: &str, summary: &str, table: &str, batch_size: usize) {
+    let detail = serde_json::json!({ "table": table, "batch_size": batch_size });
+    let entry = Entry::new(Subsystem::Pressure, Actor::Automatic, correlation, summary, detail);
+    if let Err(e) = ops_journal::record(conn, &entry) {
+        log::warn!("ops journal: cannot record '{}': {}", summary, e);
+    }
+}
 
-        for rec in buffer.drain(..) {
-            T::bind_and_execute(&mut stmt, &rec)?;
+/// Writes `rows` in a savepoint of `tx`. A failing row sends the batch down
+/// the row-by-row path instead of losing it; [`BatchInsert::complete`] runs
+/// on the rowids of what was stored. `rows` may hold the events or references
+/// to them. Returns how many rows were dropped.
+pub(crate) fn insert_batch<T: BatchInsert<T> + Debug, R: Borrow<T>>(
+    tx: &mut Transaction<'_>,
+    rows: &[R],
+    codec: &mut Codec,
+) -> rusqlite::Result<u64> {
+    let mut ids = Vec::with_capacity(rows.len());
+    let batch = tx.savepoint()?;
+    let failed = match insert_rows::<T, R>(&batch, rows, codec, &mut ids)? {
+        None => {
+            batch.commit()?;
+            0
         }
+        Some((idx, e)) => {
+            drop(batch);
+            ids.clear();
+            log::warn!(
+                "batch insert into {} failed at row {}: {}; retrying rows individually",
+                T::schema().name, idx, e
+            );
+            insert_individually::<T, R>(tx, rows, codec, &mut ids)?
+        }
+    };
+    T::complete(tx, &ids)?;
+    Ok(failed)
+}
 
-        // Record metrics
-        let elapsed = start.elapsed().as_secs_f64();
-        histogram!("db_flush_duration_seconds").record(elapsed);
-        histogram!("db_flush_batch_size").record(batch_count);
-        counter!("db_flush_batches_total").increment(1);
-
-        Ok(())
+/// Inserts `rows` on `conn`, stopping at the first failing row, and appends
+/// the rowid of each stored one to `ids`. Returns the failing row's index and
+/// error; preparing the statement is the only hard error.
+fn insert_rows<T: BatchInsert<T>, R: Borrow<T>>(
+    conn: &Connection,
+    rows: &[R],
+    codec: &mut Codec,
+    ids: &mut Vec<i64>,
+) -> rusqlite::Result<Option<(usize, rusqlite::Error)>> {
+    let mut stmt = conn.prepare_cached(T::insert_sql())?;
+    for (idx, rec) in rows.iter().enumerate() {
+        if let Err(e) = T::bind_and_execute(&mut stmt, rec.borrow(), codec) {
+            return Ok(Some((idx, e)));
+        }
+        ids.push(conn.last_insert_rowid());
     }
+    Ok(None)
 }
 
-
+/// Inserts each of `rows` in its own savepoint, logging and skipping the
+/// ones that fail; the rowids of the others go to `ids`. Returns how many
+/// failed.
+fn insert_individually<T: BatchInsert<T> + Debug, R: Borrow<T>>(
+    tx: &mut Transaction<'_>,
+    rows: &[R],
+    codec: &mut Codec,
+    ids: &mut Vec<i64>,
+) -> rusqlite::Result<u64> {
+    let mut failed = 0;
+    for rec in rows {
+        let row = tx.savepoint()?;
+        match insert_rows::<T, R>(&row, std::slice::from_ref(rec), codec, ids)? {
+            None => row.commit()?,
+            Some((_, e)) => {
+                log::error!("dropping row for {}: {}: {:?}", T::schema().name, e, rec.borrow());
+                failed += 1;
+            }
+        }
+    }
+    Ok(failed)
+}
@@ -0,0 +1,229 @@
+// src/db/event_types.rs
+//! Registration metadata of the stored event types.
+//!
+//! Each table is declared once with [`declare_event_type!`]: the column list
+//! in binding order and, per column, the proto field it is filled from. The
+//! writers' INSERT statements and the schema description are both generated
+//! from it, so the proto ↔ column mapping is never maintained by hand.
+//! So is the [`TableDef`] that creates the table (see `db::schema_registry`).
+
+use crate::db::schema_registry::{schema_version, TableDef};
+
+/// One column of an event table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub name:  &'static str,
+    /// Proto field the value comes from; `None` for columns filled from the
+    /// envelope (`ts`, `sensor_guid`, `event_uid`, `seq`, `count`).
+    pub field: Option<&'static str>,
+}
+
+/// A proto message stored in its own table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventType {
+    /// Message name in `events.proto`.
+    pub message:    &'static str,
+    pub table:      &'static str,
+    /// In the order `bind_and_execute` binds them.
+    pub columns:    &'static [Column],
+    pub insert_sql: &'static str,
+    /// Table creation, generated from the same column list.
+    pub schema:     TableDef,
+}
+
+impl EventType {
+    /// Columns filled from `field`.
+    pub fn columns_of<'a>(&'a self, field: &'a str) -> impl Iterator<Item = &'static str> + 'a {
+        self.columns.iter().filter(move |c| c.field == Some(field)).map(|c| c.name)
+    }
+}
+
+/// `NAME: "Message" => "table" { column "SQL TYPE", column "SQL TYPE": field, ... }`
+/// followed by `indexes { idx_name(column, ...), ... }` and, once the table
+/// changed, `upgrades { version => "sql", ... }`. A bare column is not filled
+/// from a proto field. Parameters are named after the columns and bound
+/// positionally; `id INTEGER PRIMARY KEY` is implied.
+macro_rules! declare_event_type {
+    ($(#[$meta:meta])* $name:ident: $message:literal => $table:literal {
+        $first:ident $first_ty:literal $(: $first_field:ident)?
+        $(, $col:ident $ty:literal $(: $field:ident)?)*
+    } indexes { $($idx:ident($($idx_col:ident),+)),* }
+    $(upgrades { $($version:literal => $upgrade:literal),* })?) => {
+        $(#[$meta])*
+        pub const $name: EventType = EventType {
+            message: $message,
+            table:   $table,
+            columns: &[
+                Column { name: stringify!($first), field: declare_event_type!(@field $($first_field)?) },
+                $(Column { name: stringify!($col), field: declare_event_type!(@field $($field)?) },)*
+            ],
+            insert_sql: concat!(
+                "INSERT INTO ", $table, " (", stringify!($first), $(", ", stringify!($col),)*
+                ") VALUES (:", stringify!($first), $(", :", stringify!($col),)* ")"
+            ),
+            schema: TableDef {
+                name:     $table,
+                version:  schema_version(&[$($(($version, $upgrade)),*)?]),
+                ddl: concat!(
+                    "CREATE TABLE IF NOT EXISTS ", $table, " (id INTEGER PRIMARY KEY, ",
+                    stringify!($first), " ", $first_ty, $(", ", stringify!($col), " ", $ty,)* ");",
+                    $("\nCREATE INDEX IF NOT EXISTS ", stringify!($idx), " ON ", $table,
+                      "(", stringify!($($idx_col),+), ");",)*
+                ),
+                upgrades: &[$($(($version, $upgrade)),*)?],
+            },
+        };
+    };
+    (@field) => { None };
+    (@field $field:ident) => { Some(stringify!($field)) };
+}
+
+declare_event_type! {
+    /// A row with a `count` above 1 stands for that many events merged by
+    /// `comms::coalesce`: `ts` is the first one's, `last_ts` the last one's.
+    /// `raw_path` and `raw_new_path` hold what the sensor sent when
+    /// `intel::paths` rewrote the path, NULL otherwise.
+    FS_EVENTS: "FileEvent" => "fs_events" {
+        ts "INTEGER NOT NULL", sensor_guid "TEXT", op "TEXT NOT NULL": op, path "TEXT NOT NULL": path,
+        new_path "TEXT": new_path, pid "INTEGER": pid, exe_path "TEXT": exe_path, size "INTEGER": size,
+        sha256 "TEXT": sha256, result "INTEGER": success, event_uid "INTEGER", seq "INTEGER",
+        count "INTEGER NOT NULL DEFAULT 1", last_ts "INTEGER", raw_path "TEXT": raw_path,
+        raw_new_path "TEXT": raw_new_path
+    } indexes { idx_fs_events_ts(ts), idx_fs_events_pid(pid) }
+    upgrades {
+        2 => "ALTER TABLE fs_events ADD COLUMN seq INTEGER;",
+        // `op` was stored as its number and `result` as 'true'/'false' text;
+        // the table is rebuilt for the INTEGER affinity of `result`.
+        3 => "CREATE TABLE fs_events_v3 (id INTEGER PRIMARY KEY, ts INTEGER NOT NULL, sensor_guid TEXT,
+                  op TEXT NOT NULL, path TEXT NOT NULL, new_path TEXT, pid INTEGER, exe_path TEXT,
+                  size INTEGER, sha256 TEXT, result INTEGER, event_uid INTEGER, seq INTEGER);
+              INSERT INTO fs_events_v3
+              SELECT id, ts, sensor_guid,
+                     CASE WHEN op = '0' THEN 'CREATE' WHEN op = '1' THEN 'WRITE'
+                          WHEN op = '2' THEN 'DELETE' WHEN op = '3' THEN 'RENAME'
+                          WHEN op GLOB '[0-9]*' OR op GLOB '-[0-9]*' THEN 'UNKNOWN(' || op || ')'
+                          ELSE op END,
+                     path, new_path, pid, exe_path, size, sha256,
+                     CASE result WHEN 'true' THEN 1 WHEN 'false' THEN 0 ELSE result END,
+                     event_uid, seq
+              FROM fs_events;
+              DROP TABLE fs_events;
+              ALTER TABLE fs_events_v3 RENAME TO fs_events;",
+        4 => "ALTER TABLE fs_events ADD COLUMN count INTEGER NOT NULL DEFAULT 1;
+              ALTER TABLE fs_events ADD COLUMN last_ts INTEGER;",
+        5 => "ALTER TABLE fs_events ADD COLUMN raw_path TEXT;
+              ALTER TABLE fs_events ADD COLUMN raw_new_path TEXT;"
+    }
+}
+
+declare_event_type! {
+    NETWORK_EVENTS: "NetworkEvent" => "network_events" {
+        ts "INTEGER NOT NULL", sensor_guid "TEXT", direction "TEXT NOT NULL": direction,
+        proto "TEXT NOT NULL": proto, src_ip "TEXT NOT NULL": src_ip, src_port "INTEGER": src_port,
+        dst_ip "TEXT NOT NULL": dst_ip, dst_port "INTEGER": dst_port, pid "INTEGER": pid,
+        exe_path "TEXT": exe_path, bytes "INTEGER": bytes, verdict "TEXT": blocked,
+        event_uid "INTEGER", seq "INTEGER", dst_hostname "TEXT": dst_hostname
+    } indexes { idx_net_events_ts(ts), idx_net_events_pid(pid) }
+    upgrades {
+        2 => "ALTER TABLE network_events ADD COLUMN seq INTEGER;",
+        3 => "UPDATE network_events SET verdict = CASE verdict WHEN 'true' THEN 'block' ELSE 'allow' END;",
+        4 => "ALTER TABLE network_events ADD COLUMN dst_hostname TEXT;"
+    }
+}
+
+declare_event_type! {
+    /// `user_sid` and `user_name` are extracted from the payload.
+    ETW_EVENTS: "EtwEvent" => "etw_events" {
+        ts "INTEGER NOT NULL", sensor_guid "TEXT", provider_guid "TEXT NOT NULL": provider_guid,
+        event_id "INTEGER NOT NULL": event_id, level "INTEGER": level, pid "INTEGER": pid,
+        tid "INTEGER": tid, json_payload "TEXT": json_payload, event_uid "INTEGER",
+        user_sid "TEXT": json_payload, user_name "TEXT": json_payload, seq "INTEGER"
+    } indexes {
+        idx_etw_events_ts(ts), idx_etw_events_pid(pid), idx_etw_events_provider(provider_guid),
+        idx_etw_events_event_id(event_id)
+    }
+    upgrades { 2 => "ALTER TABLE etw_events ADD COLUMN seq INTEGER;" }
+}
+
+declare_event_type! {
+    /// `image_path_norm` is the normalized `image_path`; `exit_code` is NULL
+    /// for creations. `parent_*` left empty by the driver are completed from
+    /// the parent's stored creation (see `BatchInsert::complete`).
+    /// `truncated` holds the `TRUNCATED_*` bits of the fields the driver cut.
+    /// `user_sid`, `session_id` and `elevated` are NULL on exits, the SID
+    /// also when the driver could not read the token.
+    PROCESS_EVENTS: "ProcessEvent" => "process_events" {
+        ts "INTEGER NOT NULL", sensor_guid "TEXT", pid "INTEGER NOT NULL": pid, ppid "INTEGER": ppid,
+        image_path "TEXT": image_path, cmdline "TEXT": cmdline, event_uid "INTEGER",
+        creator_pid "INTEGER": creator_pid, creator_tid "INTEGER": creator_tid,
+        image_path_norm "TEXT": image_path, event_type "TEXT NOT NULL DEFAULT 'CREATE'": event_type,
+        exit_code "INTEGER": exit_code, parent_image_path "TEXT": parent_image_path,
+        parent_cmdline "TEXT": parent_cmdline, seq "INTEGER",
+        truncated "INTEGER NOT NULL DEFAULT 0": truncated, user_sid "TEXT": user_sid,
+        session_id "INTEGER": session_id, elevated "INTEGER": elevated
+    } indexes { idx_proc_events_ts(ts), idx_proc_events_pid(pid) }
+    upgrades {
+        2 => "ALTER TABLE process_events ADD COLUMN event_type TEXT NOT NULL DEFAULT 'CREATE';
+              ALTER TABLE process_events ADD COLUMN exit_code INTEGER;",
+        3 => "ALTER TABLE process_events ADD COLUMN parent_image_path TEXT;
+              ALTER TABLE process_events ADD COLUMN parent_cmdline TEXT;",
+        4 => "ALTER TABLE process_events ADD COLUMN seq INTEGER;",
+        5 => "ALTER TABLE process_events ADD COLUMN truncated INTEGER NOT NULL DEFAULT 0;",
+        6 => "ALTER TABLE process_events ADD COLUMN user_sid TEXT;
+              ALTER TABLE process_events ADD COLUMN session_id INTEGER;
+              ALTER TABLE process_events ADD COLUMN elevated INTEGER;"
+    }
+}
+
+declare_event_type! {
+    /// Files the directory scanner found new or changed; `hash` and `sha256`
+    /// are hex and `matches` one per line.
+    SCAN_RESULTS: "ScanResult" => "scan_results" {
+        ts "INTEGER NOT NULL", sensor_guid "TEXT", file_path "TEXT NOT NULL": file_path,
+        size "INTEGER": size, hash "TEXT": hash, mtime "INTEGER": mtime, risk_group "TEXT": risk_group,
+        rule_id "TEXT": rule_id, matches "TEXT": matches, severity "TEXT": severity, event_uid "INTEGER",
+        sha256 "TEXT": sha256, seq "INTEGER"
+    } indexes { idx_scan_results_ts(ts), idx_scan_results_path(file_path) }
+    upgrades {
+        2 => "ALTER TABLE scan_results ADD COLUMN sha256 TEXT;",
+        3 => "ALTER TABLE scan_results ADD COLUMN seq INTEGER;"
+    }
+}
+
+declare_event_type! {
+    /// `image_base` is hex: kernel addresses do not fit an SQLite integer.
+    IMAGE_LOAD_EVENTS: "ImageLoadEvent" => "image_load_events" {
+        ts "INTEGER NOT NULL", sensor_guid "TEXT", pid "INTEGER": pid, image_base "TEXT": image_base,
+        image_size "INTEGER": image_size, full_image_name "TEXT": full_image_name,
+        is_kernel_module "INTEGER": is_kernel_module, event_uid "INTEGER", seq "INTEGER"
+    } indexes { idx_image_load_events_ts(ts), idx_image_load_events_pid(pid) }
+    upgrades { 2 => "ALTER TABLE image_load_events ADD COLUMN seq INTEGER;" }
+}
+
+declare_event_type! {
+    /// Handles to protected processes asking for memory access;
+    /// `operation` is the `Operation` name.
+    OBJECT_OP_EVENTS: "ObjectOpEvent" => "object_op_events" {
+        ts "INTEGER NOT NULL", sensor_guid "TEXT", source_pid "INTEGER": source_pid,
+        target_pid "INTEGER": target_pid, desired_access "INTEGER": desired_access,
+        operation "TEXT NOT NULL": operation, event_uid "INTEGER", seq "INTEGER"
+    } indexes {
+        idx_object_op_events_ts(ts), idx_object_op_events_source(source_pid),
+        idx_object_op_events_target(target_pid)
+    }
+}
+
+/// Every stored event type.
+pub const EVENT_TYPES: &[EventType] =
+    &[FS_EVENTS, NETWORK_EVENTS, ETW_EVENTS, PROCESS_EVENTS, SCAN_RESULTS, IMAGE_LOAD_EVENTS, OBJECT_OP_EVENTS];
+
+/// Registration of `message`, if it is stored.
+pub fn event_type(message: &str) -> Option<&'static EventType> {
+    EVENT_TYPES.iter().find(|t| t.message == message)
+}
+
+/// Registration of the table `name`, also given without its `_events`
+/// suffix (`process` for `process_events`) as the config does.
+pub fn event_table(name: &str) -> Option<&'static EventType> {
+    EVENT_TYPES.iter().find(|t| t.table == name || t.table.strip_suffix("_events") == Some(name))
+}
@@ -0,0 +1,435 @@
+// src/db/hub.rs
+//! One writer for every event table.
+//!
+//! Producers send [`AnyEvent`]s into a single channel; [`DbWriterHub`] owns
+//! the only writing connection, splits each flush by table and commits all
+//! of it, plus the ring positions, in one transaction. Producers that want a
+//! typed sender (the listeners' [`Buses`](crate::comms::listeners::Buses))
+//! get a [`DbSender`], which converts on the way in. The same writer stores
+//! the events of a single table for [`spawn_writer`](crate::db::spawn_writer).
+
+use std::{collections::HashSet, fmt::Debug, marker::PhantomData, sync::Arc, time::{Duration, Instant}};
+
+use metrics::{counter, histogram};
+use rusqlite::{Connection, Transaction};
+use tokio::sync::mpsc::{self, error::{SendError, TrySendError}};
+
+use shared::events::{EtwEvent, FileEvent, ImageLoadEvent, NetworkEvent, ObjectOpEvent, ProcessEvent, ScanResult};
+
+use crate::comms::{RingPosition, WrappedEvent};
+use crate::db::{
+    batch_inserts::BatchInsert,
+    codec::Codec,
+    consumer_state::advance_position,
+    db_writer::{insert_batch, note_saturation, shed_oldest, DbError, FlushAck, FlushRetry},
+    overflow::Overflow,
+    schema_registry::{ensure_for, TableDef},
+};
+use crate::heartbeat::Stats;
+use crate::status::AgentStats;
+use crate::util::Shutdown;
+
+macro_rules! any_event {
+    ($($variant:ident($payload:ty) => $field:ident, $ring:literal;)*) => {
+        /// An event for any of the tables the hub writes.
+        #[derive(Debug, Clone)]
+        pub enum AnyEvent {
+            $($variant(WrappedEvent<$payload>),)*
+        }
+
+        $(
+            impl From<WrappedEvent<$payload>> for AnyEvent {
+                fn from(ev: WrappedEvent<$payload>) -> Self {
+                    AnyEvent::$variant(ev)
+                }
+            }
+        )*
+
+        impl AnyEvent {
+            /// Ring events of this kind are read from, as named in
+            /// [`FlushAck::ring`].
+            pub fn ring(&self) -> &'static str {
+                match self {
+                    $(AnyEvent::$variant(_) => $ring,)*
+                }
+            }
+
+            /// Table the event is stored in.
+            pub fn schema(&self) -> &'static TableDef {
+                match self {
+                    $(AnyEvent::$variant(_) => <WrappedEvent<$payload> as BatchInsert<_>>::schema(),)*
+                }
+            }
+        }
+
+        impl RingPosition for AnyEvent {
+            fn ring_pos(&self) -> Option<u64> {
+                match self {
+                    $(AnyEvent::$variant(ev) => ev.ring_pos,)*
+                }
+            }
+        }
+
+        impl Stored for AnyEvent {
+            fn ring(&self) -> &'static str {
+                AnyEvent::ring(self)
+            }
+
+            fn schema(&self) -> &'static TableDef {
+                AnyEvent::schema(self)
+            }
+
+            fn insert(
+                rows: &[Self],
+                tx: &mut Transaction<'_>,
+                codec: &mut Codec,
+            ) -> rusqlite::Result<Vec<(&'static str, u64)>> {
+                let mut tables = Tables::default();
+                for ev in rows {
+                    tables.push(ev);
+                }
+                tables.insert(tx, codec)
+            }
+        }
+
+        $(
+            impl Stored for WrappedEvent<$payload> {
+                fn ring(&self) -> &'static str {
+                    $ring
+                }
+
+                fn schema(&self) -> &'static TableDef {
+                    <Self as BatchInsert<_>>::schema()
+                }
+
+                fn insert(
+                    rows: &[Self],
+                    tx: &mut Transaction<'_>,
+                    codec: &mut Codec,
+                ) -> rusqlite::Result<Vec<(&'static str, u64)>> {
+                    let failures = insert_batch::<Self, _>(tx, rows, codec)?;
+                    let name = <Self as BatchInsert<_>>::schema().name;
+                    Ok(if failures > 0 { vec![(name, failures)] } else { Vec::new() })
+                }
+            }
+        )*
+
+        /// A flush split by table, each part in arrival order.
+        #[derive(Default)]
+        struct Tables<'a> {
+            $($field: Vec<&'a WrappedEvent<$payload>>,)*
+        }
+
+        impl<'a> Tables<'a> {
+            fn push(&mut self, ev: &'a AnyEvent) {
+                match ev {
+                    $(AnyEvent::$variant(ev) => self.$field.push(ev),)*
+                }
+            }
+
+            /// Inserts every part on `tx`. Returns the rows dropped per
+            /// table.
+            fn insert(
+                &self,
+                tx: &mut Transaction<'_>,
+                codec: &mut Codec,
+            ) -> rusqlite::Result<Vec<(&'static str, u64)>> {
+                let mut failed = Vec::new();
+                $(
+                    if !self.$field.is_empty() {
+                        let failures = insert_batch::<WrappedEvent<$payload>, _>(tx, &self.$field, codec)?;
+                        if failures > 0 {
+                            failed.push((<WrappedEvent<$payload> as BatchInsert<_>>::schema().name, failures));
+                        }
+                    }
+                )*
+                Ok(failed)
+            }
+        }
+    };
+}
+
+/// What a [`DbWriterHub`] stores: [`AnyEvent`]s, or the events of a single
+/// table.
+pub trait Stored: RingPosition + Debug + Send + 'static {
+    /// Ring the event is read from, as named in [`FlushAck::ring`].
+    fn ring(&self) -> &'static str;
+
+    /// Table the event is stored in.
+    fn schema(&self) -> &'static TableDef;
+
+    /// Inserts `rows` on `tx`, table by table. Returns the rows dropped per
+    /// table.
+    fn insert(rows: &[Self], tx: &mut Transaction<'_>, codec: &mut Codec) -> rusqlite::Result<Vec<(&'static str, u64)>>
+    where
+        Self: Sized;
+}
+
+any_event! {
+    Process(ProcessEvent)   => process, "process";
+    File(FileEvent)         => file,    "file";
+    Net(NetworkEvent)       => net,     "network";
+    Etw(EtwEvent)           => etw,     "etw";
+    Scan(ScanResult)        => scan,    "scan";
+    Image(ImageLoadEvent)   => image,   "image";
+    ObjectOp(ObjectOpEvent) => object,  "object";
+}
+
+/// Typed sending half for the hub, or for a writer of `E` alone.
+pub enum DbSender<E: Clone> {
+    /// Straight to a writer of `E` (see [`spawn_writer`](crate::db::spawn_writer)).
+    Typed(mpsc::Sender<WrappedEvent<E>>),
+    /// Into a [`DbWriterHub`], spilling to the overflow file, if any, what
+    /// [`try_send`](Self::try_send) has no room for.
+    Hub(mpsc::Sender<AnyEvent>, Option<Arc<Overflow>>, PhantomData<fn(E)>),
+}
+
+impl<E: Clone> Clone for DbSender<E> {
+    fn clone(&self) -> Self {
+        match self {
+            DbSender::Typed(tx) => DbSender::Typed(tx.clone()),
+            DbSender::Hub(tx, overflow, _) => DbSender::Hub(tx.clone(), overflow.clone(), PhantomData),
+        }
+    }
+}
+
+impl<E: Clone> From<mpsc::Sender<WrappedEvent<E>>> for DbSender<E> {
+    fn from(tx: mpsc::Sender<WrappedEvent<E>>) -> Self {
+        DbSender::Typed(tx)
+    }
+}
+
+impl<E: Clone> From<mpsc::Sender<AnyEvent>> for DbSender<E> {
+    fn from(tx: mpsc::Sender<AnyEvent>) -> Self {
+        DbSender::Hub(tx, None, PhantomData)
+    }
+}
+
+/// The receiving writer is gone; the event was not stored.
+#[derive(Debug)]
+pub struct Closed;
+
+impl<E: Clone> DbSender<E>
+where
+    WrappedEvent<E>: Into<AnyEvent>,
+{
+    /// Spills to `overflow` what the hub has no room for. The file is
+    /// replayed into the hub, so typed senders are left as they are.
+    pub fn with_overflow(self, overflow: Arc<Overflow>) -> Self {
+        match self {
+            DbSender::Hub(tx, _, _) => DbSender::Hub(tx, Some(overflow), PhantomData),
+            typed => typed,
+        }
+    }
+
+    pub async fn send(&self, ev: WrappedEvent<E>) -> Result<(), Closed> {
+        match self {
+            DbSender::Typed(tx) => tx.send(ev).await.map_err(|SendError(_)| Closed),
+            DbSender::Hub(tx, _, _) => tx.send(ev.into()).await.map_err(|SendError(_)| Closed),
+        }
+    }
+
+    /// [`send`](Self::send) from outside the runtime.
+    pub fn blocking_send(&self, ev: WrappedEvent<E>) -> Result<(), Closed> {
+        match self {
+            DbSender::Typed(tx) => tx.blocking_send(ev).map_err(|SendError(_)| Closed),
+            DbSender::Hub(tx, _, _) => tx.blocking_send(ev.into()).map_err(|SendError(_)| Closed),
+        }
+    }
+
+    /// Queues `ev` without waiting. If the channel is full the event is
+    /// shed, counted in `events_shed_total`, and spilled to the overflow
+    /// file when there is one.
+    pub fn try_send(&self, ev: WrappedEvent<E>) -> Result<(), Closed> {
+        let (shed, overflow) = match self {
+            DbSender::Typed(tx) => match tx.try_send(ev) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(ev)) => (ev.into(), None),
+                Err(TrySendError::Closed(_)) => return Err(Closed),
+            },
+            DbSender::Hub(tx, overflow, _) => match tx.try_send(ev.into()) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(ev)) => (ev, overflow.as_ref()),
+                Err(TrySendError::Closed(_)) => return Err(Closed),
+            },
+        };
+        counter!("events_shed_total", "payload" => shed.ring()).increment(1);
+        if let Some(overflow) = overflow {
+            overflow.spill(shed);
+        }
+        Ok(())
+    }
+}
+
+/// Batched writer for every event table over a single connection; with `E`
+/// a `WrappedEvent`, for that table alone.
+pub struct DbWriterHub<E = AnyEvent> {
+    pub conn: Connection,
+    pub rx: mpsc::Receiver<E>,
+    /// Names the writer in the flush failures: `hub`, or the table it
+    /// stores.
+    pub label: &'static str,
+    pub flush_interval_ms: u64,
+    pub batch_size: usize,
+    /// One per ring whose position is persisted after each flush.
+    pub acks: Vec<FlushAck>,
+    pub codec: Codec,
+    /// Counted among the saturated writers (see `db_writer::under_pressure`).
+    pub saturated: bool,
+    /// Tables of the batch that saturated the hub, named in the journal.
+    pub saturated_by: String,
+    /// Tables already ensured; each is created on its first flush.
+    pub ready: HashSet<&'static str>,
+    /// Set while flushes fail; the buffer is kept until one commits.
+    pub retry: FlushRetry,
+    /// Rows buffered, failed flushes included, before the oldest are dropped.
+    pub pending_max_rows: usize,
+    /// Once triggered, the hub stores what is queued and returns; stop the
+    /// producers first.
+    pub shutdown: Shutdown,
+}
+
+impl<E: Stored> DbWriterHub<E> {
+    pub async fn run(&mut self) {
+        let mut buffer = Vec::with_capacity(self.batch_size);
+        let mut interval = tokio::time::interval(Duration::from_millis(self.flush_interval_ms));
+
+        loop {
+            tokio::select! {
+                maybe = self.rx.recv() => match maybe {
+                    Some(ev) => {
+                        buffer.push(ev);
+                        if buffer.len() >= self.batch_size && self.retry.ready() {
+                            self.set_saturated(true, &buffer);
+                            self.flush(&mut buffer);
+                        }
+                        shed_oldest(&mut buffer, self.pending_max_rows, |ev| ev.schema().name);
+                    }
+                    None => {
+                        self.flush_on_exit(&mut buffer).await;
+                        self.set_saturated(false, &buffer);
+                        break;
+                    }
+                },
+                _ = interval.tick(), if self.retry.ready() => {
+                    self.set_saturated(false, &buffer);
+                    self.flush(&mut buffer);
+                }
+                _ = self.retry.due(), if !self.retry.ready() => self.flush(&mut buffer),
+                _ = self.shutdown.triggered() => {
+                    while let Ok(ev) = self.rx.try_recv() {
+                        buffer.push(ev);
+                        if buffer.len() >= self.batch_size && self.retry.ready() {
+                            self.flush(&mut buffer);
+                        }
+                        shed_oldest(&mut buffer, self.pending_max_rows, |ev| ev.schema().name);
+                    }
+                    self.flush_on_exit(&mut buffer).await;
+                    self.set_saturated(false, &buffer);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Journaled with the tables of the batch that saturated the hub.
+    fn set_saturated(&mut self, saturated: bool, buffer: &[E]) {
+        if saturated && !self.saturated {
+            let mut tables: Vec<_> = buffer.iter().map(|ev| ev.schema().name).collect();
+            tables.sort_unstable();
+            tables.dedup();
+            self.saturated_by = tables.join(",");
+        }
+        note_saturation(&self.conn, &mut self.saturated, saturated, &self.saturated_by, self.batch_size);
+    }
+
+    /// Flushes `buffer`, keeping it for a retry if the commit fails.
+    fn flush(&mut self, buffer: &mut Vec<E>) {
+        match self.flush_sync(buffer) {
+            Ok(()) => self.retry.succeeded(),
+            Err(e) => {
+                let delay = self.retry.failed();
+                counter!("db_flush_failures_total", "table" => self.label).increment(1);
+                log::warn!("{}: cannot flush {} rows, retrying in {:?}: {}", self.label, buffer.len(), delay, e);
+                let error = format!("{}: cannot flush {} rows: {}", self.label, buffer.len(), e);
+                AgentStats::global().record_error(error.clone());
+                Stats::global().set_error(error);
+            }
+        }
+    }
+
+    /// Last flush: retried a few times, then what is left is dropped.
+    async fn flush_on_exit(&mut self, buffer: &mut Vec<E>) {
+        self.flush(buffer);
+        while !buffer.is_empty() && self.retry.retry_on_exit() {
+            self.retry.due().await;
+            self.flush(buffer);
+        }
+        shed_oldest(buffer, 0, |ev| ev.schema().name);
+    }
+
+    fn flush_sync(&mut self, buffer: &mut Vec<E>) -> Result<(), DbError> {
+        let batch_count = buffer.len() as f64;
+        if batch_count == 0.0 {
+            return Ok(());
+        }
+
+        for ev in buffer.iter() {
+            let schema = ev.schema();
+            if !self.ready.contains(schema.name) {
+                ensure_for(&self.conn, schema)?;
+                self.ready.insert(schema.name);
+            }
+        }
+
+        let start = Instant::now();
+        // Last position per acknowledged ring, by index into `acks`.
+        let mut end_pos: Vec<(usize, u64)> = Vec::new();
+        for (i, ack) in self.acks.iter().enumerate() {
+            let last = buffer.iter().rev().filter(|ev| ev.ring() == ack.ring).find_map(RingPosition::ring_pos);
+            if let Some(pos) = last {
+                end_pos.push((i, pos));
+            }
+        }
+        let mut rows: Vec<(&'static str, u64)> = Vec::new();
+        for ev in buffer.iter() {
+            match rows.iter_mut().find(|(table, _)| *table == ev.schema().name) {
+                Some((_, n)) => *n += 1,
+                None => rows.push((ev.schema().name, 1)),
+            }
+        }
+
+        // Rows leave the buffer only once committed.
+        let failed = self.commit_batch(buffer, &end_pos)?;
+        buffer.clear();
+        for &(i, pos) in &end_pos {
+            self.acks[i].publish(pos);
+        }
+
+        let elapsed = start.elapsed().as_secs_f64();
+        histogram!("db_flush_duration_seconds").record(elapsed);
+        histogram!("db_flush_batch_size").record(batch_count);
+        counter!("db_flush_batches_total").increment(1);
+        for (table, n) in rows {
+            let dropped = failed.iter().find(|(t, _)| *t == table).map_or(0, |(_, f)| *f);
+            Stats::global().add_processed(table, n - dropped);
+        }
+        for (table, rows) in failed {
+            counter!("db_flush_rows_failed_total", "table" => table).increment(rows);
+        }
+
+        Ok(())
+    }
+
+    /// Writes every table's rows and the ring positions in one transaction.
+    /// Returns the rows dropped per table.
+    fn commit_batch(&mut self, rows: &[E], end_pos: &[(usize, u64)]) -> Result<Vec<(&'static str, u64)>, DbError> {
+        let mut tx = self.conn.transaction()?;
+        let failed = E::insert(rows, &mut tx, &mut self.codec)?;
+        for &(i, pos) in end_pos {
+            advance_position(&tx, self.acks[i].ring, pos)?;
+        }
+        tx.commit()?;
+        Ok(failed)
+    }
+}
@@ -1,41 +1,322 @@
 // src/db/maintenance.rs
-//! Periodic TTL cleanup & WAL checkpoints.
+//! Periodic TTL cleanup, alert retention, WAL checkpoints, the daily
+//! maintenance hour and compression backfill.
 
-use std::{path::PathBuf, time::Duration};
-use rusqlite::Connection;
-use tokio::runtime::Runtime;
-use crate::config::model::DatabaseConfig;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use chrono::{Local, NaiveDate, Timelike};
+use metrics::{counter, gauge};
+use rusqlite::{params, Connection};
+use tokio::{runtime::Runtime, task::{self, JoinHandle}};
+use crate::config::model::{DatabaseConfig, Keep, PerSeverity};
+use crate::db::codec::{backfill_chunk, Codec};
+use crate::db::event_types::{event_table, EVENT_TYPES};
+use crate::db::schema_registry::table_exists;
+use crate::idle::{IdleGate, Task};
+use crate::intel::Severity;
+use crate::util::Shutdown;
 
-pub fn spawn_ttl_cleanup(rt: &Runtime, db_path: PathBuf, cfg: &DatabaseConfig) {
-    if cfg.ttl_seconds == 0 { return; }          // disabled
-    let ttl = cfg.ttl_seconds as i64;
-    rt.spawn(async move {
-        let mut ticker = tokio::time::interval(Duration::from_secs(60)); // every minute
+/// Rows compressed per backfill transaction.
+const BACKFILL_CHUNK: usize = 500;
+/// Pause between backfill transactions so writers are not starved.
+const BACKFILL_PAUSE: Duration = Duration::from_millis(200);
+/// How often the WAL size is checked against `journal_size_limit`.
+const WAL_SIZE_CHECK: Duration = Duration::from_secs(5);
+
+/// Rows deleted by TTL cleanup and alert retention since the last VACUUM
+/// this process ran.
+static DELETED_SINCE_VACUUM: AtomicU64 = AtomicU64::new(0);
+
+/// How long each event table keeps rows: its `[database.retention]` entry,
+/// else `ttl_seconds`. Tables kept forever, or by a TTL of 0, are left out.
+pub fn event_ttls(cfg: &DatabaseConfig) -> BTreeMap<&'static str, Duration> {
+    let default = Duration::from_secs(cfg.ttl_seconds);
+    EVENT_TYPES
+        .iter()
+        .filter_map(|event| {
+            let keep = cfg.retention.events.iter().find(|(name, _)| event_table(name) == Some(event)).map(|(_, k)| *k);
+            match keep {
+                Some(Keep::For(ttl)) => Some((event.table, ttl)),
+                Some(Keep::Forever)  => None,
+                None if default.is_zero() => None,
+                None => Some((event.table, default)),
+            }
+        })
+        .collect()
+}
+
+/// Periodic TTL and alert retention, every `cleanup_interval_seconds`;
+/// `None` when both are off.
+pub fn spawn_ttl_cleanup(rt: &Runtime, db_path: PathBuf, cfg: &DatabaseConfig, shutdown: Shutdown) -> Option<JoinHandle<()>> {
+    let ttls   = event_ttls(cfg);
+    let alerts = cfg.retention.alerts.clone();
+    if ttls.is_empty() && !alerts.expires() { return None; }  // disabled
+    let period = Duration::from_secs(cfg.cleanup_interval_seconds.max(1));
+    Some(rt.spawn(async move {
+        let mut ticker = tokio::time::interval(period);
         loop {
-            ticker.tick().await;
-            if let Ok(conn) = Connection::open(&db_path) {
-                let cutoff = chrono::Utc::now().timestamp() - ttl;
-                let _ = conn.execute("DELETE FROM fs_events       WHERE ts < ?1", [cutoff]);
-                let _ = conn.execute("DELETE FROM network_events  WHERE ts < ?1", [cutoff]);
-                let _ = conn.execute("DELETE FROM etw_events      WHERE ts < ?1", [cutoff]);
-                let _ = conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);");
-                log::debug!("TTL cleanup removed events before {}", cutoff);
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => return,
             }
+            // Multi-table DELETEs: off the runtime workers.
+            let (db_path, ttls, alerts) = (db_path.clone(), ttls.clone(), alerts.clone());
+            if let Err(e) = task::spawn_blocking(move || cleanup(&db_path, &ttls, &alerts)).await {
+                log::warn!("TTL cleanup task failed: {}", e);
+            }
+        }
+    }))
+}
+
+/// One pass of [`spawn_ttl_cleanup`].
+fn cleanup(db_path: &Path, ttls: &BTreeMap<&'static str, Duration>, alerts: &PerSeverity<Keep>) {
+    let Ok(conn) = Connection::open(db_path) else { return };
+    // The hub keeps writing to the same file.
+    let _ = conn.busy_timeout(Duration::from_millis(1_000));
+    if !ttls.is_empty() {
+        match purge_events(&conn, ttls, chrono::Utc::now().timestamp_micros()) {
+            Ok(removed) => log_purge(&removed),
+            Err(e) => log::warn!("TTL cleanup failed: {}", e),
         }
-    });
+    }
+    match purge_alerts(&conn, alerts, chrono::Utc::now().timestamp_micros()) {
+        Ok(0)  => {}
+        Ok(n)  => log::debug!("alert retention removed {} alerts", n),
+        Err(e) => log::warn!("alert retention failed: {}", e),
+    }
+    let _ = conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);");
+}
+
+/// Deletes events older than their table's TTL in `ttls` (see
+/// [`event_ttls`]) from the tables that exist; `now` is in UNIX microseconds
+/// like the `ts` columns. Returns the deleted rows per table and counts them
+/// in `deleted_rows_total{table}`.
+pub fn purge_events(
+    conn: &Connection,
+    ttls: &BTreeMap<&'static str, Duration>,
+    now: i64,
+) -> rusqlite::Result<Vec<(&'static str, usize)>> {
+    let mut removed = Vec::new();
+    for (&table, ttl) in ttls {
+        if !table_exists(conn, table)? {
+            continue;
+        }
+        let cutoff = now - ttl.as_micros() as i64;
+        let n = conn.execute(&format!("DELETE FROM {table} WHERE ts < ?1"), [cutoff])?;
+        if n > 0 {
+            counter!("deleted_rows_total", "table" => table).increment(n as u64);
+            DELETED_SINCE_VACUUM.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        removed.push((table, n));
+    }
+    Ok(removed)
+}
+
+fn log_purge(removed: &[(&'static str, usize)]) {
+    let total: usize = removed.iter().map(|(_, n)| n).sum();
+    if total == 0 {
+        log::debug!("TTL cleanup removed nothing");
+        return;
+    }
+    let per_table: Vec<_> = removed
+        .iter()
+        .filter(|(_, n)| *n > 0)
+        .map(|(table, n)| format!("{table} {n}"))
+        .collect();
+    log::info!("TTL cleanup removed {} rows ({})", total, per_table.join(", "));
+}
+
+/// Deletes alerts older than the retention of their severity; `now` is in
+/// UNIX microseconds like `alerts.ts`. Rows with an unrecognised severity
+/// follow `default`. Returns the number of deleted alerts.
+pub fn purge_alerts(conn: &Connection, retention: &PerSeverity<Keep>, now: i64) -> rusqlite::Result<usize> {
+    let cutoff = |keep: Option<&Keep>| match keep {
+        Some(Keep::For(d)) => Some(now - d.as_micros() as i64),
+        _ => None,
+    };
+
+    let mut removed = 0;
+    for sev in Severity::ALL {
+        if let Some(before) = cutoff(retention.get(sev)) {
+            removed += conn.execute(
+                "DELETE FROM alerts WHERE severity = ?1 AND ts < ?2",
+                params![sev.as_str(), before],
+            )?;
+        }
+    }
+    if let Some(before) = cutoff(retention.default.as_ref()) {
+        let known = Severity::ALL.map(|s| format!("'{s}'")).join(",");
+        removed += conn.execute(
+            &format!("DELETE FROM alerts WHERE severity NOT IN ({known}) AND ts < ?1"),
+            [before],
+        )?;
+    }
+    DELETED_SINCE_VACUUM.fetch_add(removed as u64, Ordering::Relaxed);
+    Ok(removed)
+}
+
+/// Sizes one WAL check found, before any checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalCheck {
+    pub wal_bytes:    u64,
+    pub db_bytes:     u64,
+    /// Whether the WAL was over the limit and checkpointed.
+    pub checkpointed: bool,
+}
+
+/// `<db>-wal`, where SQLite keeps the write-ahead log of `db_path`.
+pub fn wal_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.as_os_str().to_owned();
+    name.push("-wal");
+    PathBuf::from(name)
 }
 
-pub fn spawn_wal_maintenance(rt: &Runtime, db_path: PathBuf, cfg: &DatabaseConfig) {
+fn file_len(path: &Path) -> u64 {
+    fs::metadata(path).map_or(0, |m| m.len())
+}
+
+fn checkpoint(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+}
+
+/// Publishes `db_wal_size_bytes` and `db_file_size_bytes` and, when the WAL
+/// is over `limit` bytes (0: no limit), checkpoints it at once.
+pub fn check_wal(conn: &Connection, db_path: &Path, limit: u64) -> rusqlite::Result<WalCheck> {
+    let (wal_bytes, db_bytes) = (file_len(&wal_path(db_path)), file_len(db_path));
+    gauge!("db_wal_size_bytes").set(wal_bytes as f64);
+    gauge!("db_file_size_bytes").set(db_bytes as f64);
+    let checkpointed = limit > 0 && wal_bytes > limit;
+    if checkpointed {
+        checkpoint(conn)?;
+        counter!("db_wal_size_checkpoints_total").increment(1);
+        log::debug!("WAL of {} bytes over the {} byte limit, checkpointed", wal_bytes, limit);
+    }
+    Ok(WalCheck { wal_bytes, db_bytes, checkpointed })
+}
+
+/// Work of the maintenance hour: `PRAGMA optimize`, then VACUUM if
+/// `deleted` rows reach `vacuum_after` (0: never). Returns whether it
+/// vacuumed.
+pub fn maintenance_window(conn: &Connection, deleted: u64, vacuum_after: u64) -> rusqlite::Result<bool> {
+    conn.execute_batch("PRAGMA optimize;")?;
+    if vacuum_after == 0 || deleted < vacuum_after {
+        return Ok(false);
+    }
+    conn.execute_batch("VACUUM;")?;
+    Ok(true)
+}
+
+/// Checks the WAL size every [`WAL_SIZE_CHECK`] until `shutdown`. Runs apart
+/// from the timed checkpoints so an idle deferral does not hold it back.
+async fn watch_wal(db_path: PathBuf, limit: u64, shutdown: Shutdown) {
+    let mut ticker = tokio::time::interval(WAL_SIZE_CHECK);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.triggered() => return,
+        }
+        if let Ok(conn) = Connection::open(&db_path) {
+            let _ = conn.busy_timeout(Duration::from_millis(1_000));
+            if let Err(e) = check_wal(&conn, &db_path, limit) {
+                log::warn!("WAL size check failed: {}", e);
+            }
+        }
+    }
+}
+
+/// Periodic `wal_checkpoint(TRUNCATE)` and, once a day at
+/// `maintenance_hour`, [`maintenance_window`], both deferred while the user
+/// is active. A WAL over `journal_size_limit` is checkpointed at once.
+pub fn spawn_wal_maintenance(
+    rt: &Runtime,
+    db_path: PathBuf,
+    cfg: &DatabaseConfig,
+    idle: IdleGate,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
     let period = Duration::from_secs(cfg.checkpoint_seconds);
+    let (limit, hour, vacuum_after) = (cfg.journal_size_limit, cfg.maintenance_hour, cfg.vacuum_after_deleted_rows);
     rt.spawn(async move {
+        let watcher = tokio::spawn(watch_wal(db_path.clone(), limit, shutdown.clone()));
         let mut ticker = tokio::time::interval(period);
+        let mut last_window: Option<NaiveDate> = None;
         loop {
-            ticker.tick().await;
-            if let Ok(conn) = Connection::open(&db_path) {
-                if let Err(e) = conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);") {
-                    log::warn!("WAL checkpoint failed: {}", e);
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            if !idle.wait(Task::WalCheckpoint, &shutdown).await {
+                break;
+            }
+            let Ok(conn) = Connection::open(&db_path) else { continue };
+            let _ = conn.busy_timeout(Duration::from_millis(1_000));
+            if let Err(e) = checkpoint(&conn) {
+                log::warn!("WAL checkpoint failed: {}", e);
+            }
+
+            let now = Local::now();
+            if hour != Some(now.hour()) || last_window == Some(now.date_naive()) {
+                continue;
+            }
+            last_window = Some(now.date_naive());
+            let deleted = DELETED_SINCE_VACUUM.load(Ordering::Relaxed);
+            let started = Instant::now();
+            match maintenance_window(&conn, deleted, vacuum_after) {
+                Ok(true) => {
+                    DELETED_SINCE_VACUUM.fetch_sub(deleted, Ordering::Relaxed);
+                    log::info!("VACUUM after {} deleted rows took {:?}", deleted, started.elapsed());
+                }
+                Ok(false) => log::debug!("PRAGMA optimize done, {} rows deleted since the last VACUUM", deleted),
+                Err(e) => log::warn!("database maintenance failed: {}", e),
+            }
+        }
+        let _ = watcher.await;
+    })
+}
+
+/// Compresses values of `compress_columns` written before compression was
+/// enabled, in bounded chunks run only while the user is idle. Ends once
+/// every column has been walked; `None` when compression is off.
+pub fn spawn_compression_backfill(
+    rt: &Runtime,
+    db_path: PathBuf,
+    cfg: &DatabaseConfig,
+    idle: IdleGate,
+    shutdown: Shutdown,
+) -> Option<JoinHandle<()>> {
+    let Ok(mut codec) = Codec::new(cfg) else { return None };
+    codec.columns().next()?;   // disabled without columns
+    Some(rt.spawn(async move {
+        let conn = match Connection::open(&db_path) {
+            Ok(c) => c,
+            Err(e) => { log::warn!("compression backfill: {}", e); return; }
+        };
+        let _ = conn.busy_timeout(Duration::from_millis(1_000));
+        let columns: Vec<String> = codec.columns().map(String::from).collect();
+        for column in columns {
+            let (mut after, mut saved) = (0, 0u64);
+            loop {
+                if !idle.wait(Task::CompressionBackfill, &shutdown).await {
+                    return;
+                }
+                match backfill_chunk(&conn, &mut codec, &column, after, BACKFILL_CHUNK) {
+                    Ok(step) => {
+                        saved += step.bytes_saved;
+                        match step.last_id {
+                            Some(id) => after = id,
+                            None => break,
+                        }
+                    }
+                    Err(e) => { log::warn!("compression backfill of {}: {}", column, e); break; }
                 }
+                tokio::time::sleep(BACKFILL_PAUSE).await;
             }
+            log::info!("compression backfill of {} done, {} bytes saved", column, saved);
         }
-    });
+    }))
 }
@@ -1,43 +1,134 @@
 // src/db/mod.rs
 //! Public façade for DB helpers (re-exports plus spawn_writer).
 
+pub mod agent_status;
+pub mod captures;
 pub mod connection;
+pub mod consumer_state;
 pub mod maintenance;
+pub mod ops_journal;
+pub mod overflow;
 pub mod db_writer;
+pub mod hub;
 pub mod batch_inserts;
+pub mod codec;
+pub mod event_types;
+pub mod preflight;
+pub mod probe_results;
+pub mod process_tree;
+pub mod quarantine;
+pub mod query;
+pub mod reprocess;
+pub mod scan_cache;
+pub mod scan_reports;
+pub mod schema_registry;
+pub mod snapshots;
 
 // src/db/mod.rs
 
+use std::sync::Arc;
 use rusqlite::Connection;
-use tokio::{runtime::Runtime, sync::mpsc as async_mpsc};
+use tokio::{runtime::Runtime, sync::{mpsc as async_mpsc, Mutex}, task::JoinHandle};
 
 use crate::config::model::DatabaseConfig;
-use crate::db::db_writer::DbWriter;
+use crate::db::codec::Codec;
+use crate::db::db_writer::{FlushAck, FlushRetry};
+use crate::db::hub::{AnyEvent, DbWriterHub, Stored};
 use crate::db::batch_inserts::BatchInsert;
+use crate::util::{Shutdown, Supervisor};
 
-/// Arranca un writer de SQLite para cualquier `T` que implemente:
-///   - `BatchInsert<T>` (tiene el SQL y el bind_and_execute)
-///   - `Send + Clone + 'static` (para poder moverse al task de Tokio)
+/// Arranca un writer de SQLite para los eventos `T` de una sola tabla: el
+/// bucle del [`DbWriterHub`], con `T` en lugar de [`AnyEvent`].
+///
+/// Al activarse `shutdown` guarda lo que quede en `rx` y termina.
 pub fn spawn_writer<T>(
     rt: &Runtime,
     conn: Connection,
     rx: async_mpsc::Receiver<T>,
     cfg: &DatabaseConfig,
-)
+    shutdown: &Shutdown,
+) -> JoinHandle<()>
 where
-    T: BatchInsert<T> + Send + Clone + 'static,
+    T: BatchInsert<T> + Stored,
 {
-    let flush_ms = cfg.flush_interval_ms;
-    let batch_sz = cfg.batch_size;
-
-    rt.spawn(async move {
-        DbWriter::<T> {
-            conn,
-            rx,
-            flush_interval_ms: flush_ms,
-            batch_size:        batch_sz,
-        }
-            .run()
-            .await;
-    });
-}
\ No newline at end of file
+    spawn_ring_writer(rt, conn, rx, cfg, None, shutdown)
+}
+
+/// Como [`spawn_writer`], persistiendo además la posición del ring tras cada
+/// flush (ver [`FlushAck`]).
+pub fn spawn_ring_writer<T>(
+    rt: &Runtime,
+    conn: Connection,
+    rx: async_mpsc::Receiver<T>,
+    cfg: &DatabaseConfig,
+    ack: Option<FlushAck>,
+    shutdown: &Shutdown,
+) -> JoinHandle<()>
+where
+    T: BatchInsert<T> + Stored,
+{
+    let table = <T as BatchInsert<T>>::schema().name;
+    let writer = writer(conn, rx, table, cfg, ack.into_iter().collect(), shutdown);
+    supervise(rt, format!("db_writer_{table}"), writer)
+}
+
+/// Arranca el [`DbWriterHub`]: un único writer, sobre `conn`, para todas las
+/// tablas de eventos. Los productores envían [`AnyEvent`]s a `rx`, o
+/// `WrappedEvent<E>`s a través de un [`DbSender`](hub::DbSender). Tras cada
+/// flush persiste la posición de los rings de `acks`.
+///
+/// Al activarse `shutdown` guarda lo que quede en `rx` y termina.
+pub fn spawn_hub(
+    rt: &Runtime,
+    conn: Connection,
+    rx: async_mpsc::Receiver<AnyEvent>,
+    cfg: &DatabaseConfig,
+    acks: Vec<FlushAck>,
+    shutdown: &Shutdown,
+) -> JoinHandle<()> {
+    let hub = writer(conn, rx, "hub", cfg, acks, shutdown);
+    supervise(rt, "db_hub".into(), hub)
+}
+
+fn writer<E>(
+    conn: Connection,
+    rx: async_mpsc::Receiver<E>,
+    label: &'static str,
+    cfg: &DatabaseConfig,
+    acks: Vec<FlushAck>,
+    shutdown: &Shutdown,
+) -> DbWriterHub<E> {
+    DbWriterHub {
+        conn,
+        rx,
+        label,
+        flush_interval_ms: cfg.flush_interval_ms,
+        batch_size:        cfg.batch_size,
+        acks,
+        codec:             codec(cfg),
+        saturated:         false,
+        saturated_by:      String::new(),
+        ready:             Default::default(),
+        retry:             FlushRetry::new(cfg),
+        pending_max_rows:  cfg.pending_max_rows,
+        shutdown:          shutdown.clone(),
+    }
+}
+
+/// Tras un panic se relanza el mismo writer: conexión y canal siguen ahí,
+/// sólo se pierde el lote en memoria.
+fn supervise<E: Stored>(rt: &Runtime, name: String, writer: DbWriterHub<E>) -> JoinHandle<()> {
+    let writer = Arc::new(Mutex::new(writer));
+    rt.spawn(Supervisor::new(name).run(move || {
+        let writer = writer.clone();
+        async move { writer.lock().await.run().await }
+    }))
+}
+
+fn codec(cfg: &DatabaseConfig) -> Codec {
+    // `init_database` already rejected invalid column lists.
+    Codec::new(cfg).unwrap_or_else(|e| {
+        log::error!("{}; storing text uncompressed", e);
+        Codec::disabled()
+    })
+}
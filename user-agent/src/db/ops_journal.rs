@@ -0,0 +1,428 @@
+// src/db/ops_journal.rs
+//! Journal of operational changes that can alter the event flow.
+//!
+//! Config applies (with the prevention and retention switches inside them),
+//! watchdog restarts and writer pressure transitions each leave an entry
+//! saying who did what. Entries caused by the same operation share a
+//! correlation id. [`volume_drops`] lines the journal up with the metrics
+//! history so a drop in event volume shows what changed just before it.
+
+use std::{
+    fmt,
+    path::PathBuf,
+    time::Duration,
+};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+use crate::config::{
+    canonical::{diff, render_diff, Change, Fingerprint, KeyDiff},
+    model::DatabaseConfig,
+};
+use crate::db::connection::open_db_connection;
+use crate::db::schema_registry::{ensure_for, table_exists, TableDef};
+use crate::metrics_history::Snapshot;
+
+/// `detail` is a JSON object whose shape depends on `subsystem`.
+pub const OPS_JOURNAL_TABLE: TableDef = TableDef {
+    name:     "ops_journal",
+    version:  1,
+    ddl: "\
+CREATE TABLE IF NOT EXISTS ops_journal (
+    id             INTEGER PRIMARY KEY,
+    ts             INTEGER NOT NULL,
+    subsystem      TEXT    NOT NULL,
+    actor          TEXT    NOT NULL,
+    correlation_id TEXT    NOT NULL,
+    summary        TEXT    NOT NULL,
+    detail         TEXT    NOT NULL DEFAULT '{}'
+);
+CREATE INDEX IF NOT EXISTS idx_ops_journal_ts ON ops_journal(ts);
+CREATE INDEX IF NOT EXISTS idx_ops_journal_correlation ON ops_journal(correlation_id);",
+    upgrades: &[],
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Config,
+    /// `[actions]`: whether the agent acts on alerts.
+    Prevention,
+    /// `database.ttl_seconds` and `[database.retention]`.
+    Retention,
+    Watchdog,
+    /// DB writers flushing full batches; background jobs back off.
+    Pressure,
+}
+
+impl Subsystem {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Config     => "config",
+            Self::Prevention => "prevention",
+            Self::Retention  => "retention",
+            Self::Watchdog   => "watchdog",
+            Self::Pressure   => "pressure",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "config"     => Self::Config,
+            "prevention" => Self::Prevention,
+            "retention"  => Self::Retention,
+            "watchdog"   => Self::Watchdog,
+            "pressure"   => Self::Pressure,
+            _ => return None,
+        })
+    }
+}
+
+/// Who made a change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Actor {
+    /// `gladix-cli` on the host.
+    Cli,
+    /// A config file edited by other means, picked up when the service started.
+    Service,
+    /// The agent on its own: watchdog, writer pressure.
+    Automatic,
+    /// A remote peer, by address.
+    Grpc(String),
+}
+
+impl Actor {
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "cli"     => Self::Cli,
+            "service" => Self::Service,
+            "automatic" => Self::Automatic,
+            other => Self::Grpc(other.strip_prefix("grpc:").unwrap_or(other).to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cli       => f.write_str("cli"),
+            Self::Service   => f.write_str("service"),
+            Self::Automatic => f.write_str("automatic"),
+            Self::Grpc(peer) => write!(f, "grpc:{peer}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Row id; 0 until recorded.
+    pub id:          i64,
+    /// UNIX microseconds.
+    pub ts:          i64,
+    pub subsystem:   Subsystem,
+    pub actor:       Actor,
+    pub correlation: String,
+    pub summary:     String,
+    pub detail:      Value,
+}
+
+impl Entry {
+    /// An entry stamped now.
+    pub fn new(subsystem: Subsystem, actor: Actor, correlation: &str, summary: impl Into<String>, detail: Value) -> Self {
+        Self {
+            id: 0,
+            ts: Utc::now().timestamp_micros(),
+            subsystem,
+            actor,
+            correlation: correlation.to_owned(),
+            summary: summary.into(),
+            detail,
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ts = DateTime::from_timestamp_micros(self.ts).unwrap_or_default();
+        write!(
+            f,
+            "{}  {:<10} {:<10} {}  {}",
+            ts.format("%Y-%m-%dT%H:%M:%SZ"), self.subsystem.as_str(), self.actor, self.correlation, self.summary,
+        )
+    }
+}
+
+pub fn new_correlation() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Stores `e` and returns its row id.
+pub fn record(conn: &Connection, e: &Entry) -> rusqlite::Result<i64> {
+    ensure_for(conn, &OPS_JOURNAL_TABLE)?;
+    conn.execute(
+        "INSERT INTO ops_journal (ts, subsystem, actor, correlation_id, summary, detail) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![e.ts, e.subsystem.as_str(), e.actor.to_string(), e.correlation, e.summary, e.detail.to_string()],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn query(conn: &Connection, sql: &str, param: impl rusqlite::Params) -> rusqlite::Result<Vec<Entry>> {
+    if !table_exists(conn, OPS_JOURNAL_TABLE.name)? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(param, |r| {
+        let subsystem: String = r.get(2)?;
+        let actor: String = r.get(3)?;
+        let detail: String = r.get(6)?;
+        Ok(Entry {
+            id:          r.get(0)?,
+            ts:          r.get(1)?,
+            // Rows come from `record`; an unknown name means a newer agent.
+            subsystem:   Subsystem::parse(&subsystem).unwrap_or(Subsystem::Config),
+            actor:       Actor::parse(&actor),
+            correlation: r.get(4)?,
+            summary:     r.get(5)?,
+            detail:      serde_json::from_str(&detail).unwrap_or(Value::Null),
+        })
+    })?;
+    rows.collect()
+}
+
+/// Entries at or after `ts` (UNIX microseconds), oldest first.
+pub fn since(conn: &Connection, ts: i64) -> rusqlite::Result<Vec<Entry>> {
+    query(
+        conn,
+        "SELECT id, ts, subsystem, actor, correlation_id, summary, detail FROM ops_journal \
+         WHERE ts >= ?1 ORDER BY ts, id",
+        [ts],
+    )
+}
+
+/// Entries sharing one correlation id, oldest first.
+pub fn correlated(conn: &Connection, correlation: &str) -> rusqlite::Result<Vec<Entry>> {
+    query(
+        conn,
+        "SELECT id, ts, subsystem, actor, correlation_id, summary, detail FROM ops_journal \
+         WHERE correlation_id = ?1 ORDER BY ts, id",
+        [correlation],
+    )
+}
+
+/// Canonical config of the newest config entry.
+fn last_config(conn: &Connection) -> rusqlite::Result<Option<Value>> {
+    let last = query(
+        conn,
+        "SELECT id, ts, subsystem, actor, correlation_id, summary, detail FROM ops_journal \
+         WHERE subsystem = ?1 ORDER BY ts DESC, id DESC LIMIT 1",
+        [Subsystem::Config.as_str()],
+    )?;
+    Ok(last.into_iter().next().and_then(|e| e.detail.get("config").cloned()))
+}
+
+fn is_prevention(key: &str) -> bool {
+    key.starts_with("actions.")
+}
+
+fn is_retention(key: &str) -> bool {
+    key == "database.ttl_seconds" || key.starts_with("database.retention.")
+}
+
+fn change_json(d: &KeyDiff) -> Value {
+    let (from, to) = match &d.change {
+        Change::Added(v)                 => (Value::Null, v.clone()),
+        Change::Removed(v)               => (v.clone(), Value::Null),
+        Change::Changed { local, other } => (local.clone(), other.clone()),
+    };
+    json!({ "key": d.key, "from": from, "to": to })
+}
+
+fn keys_summary(keys: &[&KeyDiff]) -> String {
+    const SHOWN: usize = 3;
+    let names: Vec<&str> = keys.iter().take(SHOWN).map(|d| d.key.as_str()).collect();
+    let more = keys.len().saturating_sub(SHOWN);
+    let plural = if keys.len() == 1 { "" } else { "s" };
+    let tail = if more > 0 { format!(" and {more} more") } else { String::new() };
+    format!("{} key{plural} changed: {}{tail}", keys.len(), names.join(", "))
+}
+
+/// Records `current`, a canonical config (see
+/// [`canonicalize`](crate::config::canonical::canonicalize)), as applied by `actor`
+/// when it differs from the config of the last config entry: one config entry with the diff, plus prevention
+/// and retention entries for the keys that touch them, all under one
+/// correlation id, which is returned. `None` when nothing changed.
+pub fn record_config(conn: &Connection, current: &Value, actor: Actor) -> rusqlite::Result<Option<String>> {
+    let previous = last_config(conn)?;
+    if previous.as_ref() == Some(current) {
+        return Ok(None);
+    }
+    let correlation = new_correlation();
+    let digest = Fingerprint::of(current).digest;
+
+    let Some(previous) = previous else {
+        let summary = format!("initial config {}", &digest[..digest.len().min(12)]);
+        let detail = json!({ "fingerprint": digest, "config": current });
+        record(conn, &Entry::new(Subsystem::Config, actor, &correlation, summary, detail))?;
+        return Ok(Some(correlation));
+    };
+
+    let diffs = diff(&previous, current);
+    let keys: Vec<&KeyDiff> = diffs.iter().flat_map(|s| &s.changes).collect();
+    let detail = json!({
+        "fingerprint": digest,
+        "diff": render_diff(&diffs),
+        "changes": keys.iter().map(|d| change_json(d)).collect::<Vec<_>>(),
+        "config": current,
+    });
+    record(conn, &Entry::new(Subsystem::Config, actor.clone(), &correlation, keys_summary(&keys), detail))?;
+
+    for (subsystem, wanted) in [(Subsystem::Prevention, is_prevention as fn(&str) -> bool), (Subsystem::Retention, is_retention)] {
+        let touched: Vec<&KeyDiff> = keys.iter().copied().filter(|d| wanted(&d.key)).collect();
+        if touched.is_empty() {
+            continue;
+        }
+        let summary = match current.pointer("/actions/enabled") {
+            Some(Value::Bool(on)) if subsystem == Subsystem::Prevention && touched.iter().any(|d| d.key == "actions.enabled") => {
+                if *on { "prevention enabled".to_owned() } else { "prevention disabled".to_owned() }
+            }
+            _ => keys_summary(&touched),
+        };
+        let detail = json!({ "changes": touched.iter().map(|d| change_json(d)).collect::<Vec<_>>() });
+        record(conn, &Entry::new(subsystem, actor.clone(), &correlation, summary, detail))?;
+    }
+    Ok(Some(correlation))
+}
+
+/// Records entries from places that hold no connection of their own, one
+/// short-lived connection per entry. Failures are logged, never returned:
+/// the journal must not take down what it describes.
+#[derive(Debug, Clone, Default)]
+pub struct Journal {
+    db: Option<(PathBuf, DatabaseConfig)>,
+}
+
+impl Journal {
+    pub fn new(db_path: PathBuf, cfg: &DatabaseConfig) -> Self {
+        Self { db: Some((db_path, cfg.clone())) }
+    }
+
+    /// Records nothing.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, e: &Entry) {
+        let Some((path, cfg)) = &self.db else { return };
+        if let Err(err) = open_db_connection(path, cfg).and_then(|conn| record(&conn, e)) {
+            log::warn!("ops journal: cannot record '{}': {}", e.summary, err);
+        }
+    }
+
+    /// [`record_config`] on a short-lived connection; the correlation id
+    /// when something changed.
+    pub fn record_config(&self, current: &Value, actor: Actor) -> Option<String> {
+        let (path, cfg) = self.db.as_ref()?;
+        match open_db_connection(path, cfg).and_then(|conn| record_config(&conn, current, actor)) {
+            Ok(id) => id,
+            Err(err) => {
+                log::warn!("ops journal: cannot record the config: {}", err);
+                None
+            }
+        }
+    }
+}
+
+/// A drop in event volume between two metrics snapshots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolumeDrop {
+    /// Snapshot at which the lower rate was observed.
+    pub at:        DateTime<Utc>,
+    /// Events per second over the previous interval.
+    pub before:    f64,
+    pub after:     f64,
+    /// Highest `ring_fill_ratio` at `at`, if sampled.
+    pub ring_fill: Option<f64>,
+    /// Newest journal entry in the lookback window before `at`.
+    pub cause:     Option<Entry>,
+}
+
+impl fmt::Display for VolumeDrop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pct = (1.0 - self.after / self.before) * 100.0;
+        write!(
+            f,
+            "{}  volume dropped {pct:.0}% ({:.1} -> {:.1} events/s)",
+            self.at.format("%Y-%m-%dT%H:%M:%SZ"), self.before, self.after,
+        )?;
+        if let Some(fill) = self.ring_fill {
+            write!(f, ", ring fill {fill:.2}")?;
+        }
+        match &self.cause {
+            Some(e) => {
+                let after = Duration::from_secs(((self.at.timestamp_micros() - e.ts).max(0) / 1_000_000) as u64);
+                write!(
+                    f,
+                    "; {} after {} by {}: {} [{}]",
+                    humantime::format_duration(after), e.subsystem.as_str(), e.actor, e.summary, e.correlation,
+                )
+            }
+            None => f.write_str("; no journal entry before it"),
+        }
+    }
+}
+
+/// Sum of every series of `metric` in `snap`.
+fn total(snap: &Snapshot, metric: &str) -> Option<f64> {
+    let series = snap.series();
+    let values: Vec<f64> = series
+        .iter()
+        .filter(|(k, _)| k.split('{').next() == Some(metric))
+        .map(|(_, v)| *v)
+        .collect();
+    (!values.is_empty()).then(|| values.iter().sum())
+}
+
+fn max_of(snap: &Snapshot, metric: &str) -> Option<f64> {
+    snap.series()
+        .into_iter()
+        .filter(|(k, _)| k.split('{').next() == Some(metric))
+        .map(|(_, v)| v)
+        .reduce(f64::max)
+}
+
+/// Intervals of `snaps` (oldest first) whose `events_received_total` rate
+/// fell by at least `min_drop` (0..1) from the interval before, each with
+/// the newest of `entries` recorded within `lookback` before it.
+pub fn volume_drops(snaps: &[Snapshot], entries: &[Entry], min_drop: f64, lookback: Duration) -> Vec<VolumeDrop> {
+    // (end of interval, events/s); counter resets start over.
+    let mut rates = Vec::new();
+    for pair in snaps.windows(2) {
+        let secs = (pair[1].ts - pair[0].ts).num_milliseconds() as f64 / 1000.0;
+        let counts = (total(&pair[0], "events_received_total"), total(&pair[1], "events_received_total"));
+        if let (Some(a), Some(b)) = counts
+            && secs > 0.0
+            && b >= a
+        {
+            rates.push((&pair[1], (b - a) / secs));
+            continue;
+        }
+        rates.clear();
+    }
+
+    let lookback = lookback.as_micros() as i64;
+    rates
+        .windows(2)
+        .filter(|w| w[0].1 > 0.0 && w[1].1 <= w[0].1 * (1.0 - min_drop))
+        .map(|w| {
+            let at = w[1].0.ts;
+            let end = at.timestamp_micros();
+            let cause = entries
+                .iter()
+                .filter(|e| e.ts <= end && e.ts >= end - lookback)
+                .max_by_key(|e| (e.ts, e.id))
+                .cloned();
+            VolumeDrop { at, before: w[0].1, after: w[1].1, ring_fill: max_of(w[1].0, "ring_fill_ratio"), cause }
+        })
+        .collect()
+}
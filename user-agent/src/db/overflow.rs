@@ -0,0 +1,204 @@
+// src/db/overflow.rs
+//! Events the hub had no room for, kept on disk until it has.
+//!
+//! Listeners do not wait on the writer: a full channel would stall the ring
+//! consumer and the driver would drop events where nobody sees it. What
+//! [`DbSender::try_send`](super::hub::DbSender::try_send) cannot queue is
+//! appended here as a `BaseEvent` behind its length, the tap's framing, up
+//! to a size cap past which events are dropped and counted.
+//!
+//! [`spawn_replay`] feeds the file back into the hub once its channel is at
+//! most half full. Replayed events carry no ring position: the ring was
+//! acknowledged past them, so the file is their only copy and is replayed
+//! after a restart as well.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    iter,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use metrics::{counter, gauge};
+use tokio::{runtime::Runtime, sync::mpsc, task::{self, JoinHandle}};
+
+use shared::events::{base_event::Payload, BaseEvent};
+
+use crate::comms::{tap::{frame, read_event}, WrappedEvent};
+use crate::db::hub::AnyEvent;
+use crate::util::Shutdown;
+
+/// File name, relative to the agent directory.
+pub const FILE: &str = "overflow.bin";
+
+/// How often [`spawn_replay`] looks at the file and the channel.
+pub const REPLAY_PERIOD: Duration = Duration::from_secs(1);
+
+/// Bounded, append-only spill file.
+pub struct Overflow {
+    path:      PathBuf,
+    max_bytes: u64,
+    state:     Mutex<State>,
+}
+
+struct State {
+    file: File,
+    len:  u64,
+}
+
+impl Overflow {
+    /// Opens or creates the file at `path`; what a previous run left in it
+    /// is replayed like new spills.
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        gauge!("overflow_bytes").set(len as f64);
+        Ok(Self { path, max_bytes, state: Mutex::new(State { file, len }) })
+    }
+
+    /// Bytes waiting to be replayed.
+    pub fn len(&self) -> u64 {
+        self.state.lock().unwrap().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends `ev`. Returns `false`, counting it in
+    /// `overflow_dropped_total`, if it does not fit or cannot be written.
+    pub fn spill(&self, ev: AnyEvent) -> bool {
+        let kind = ev.ring();
+        let frame = frame(&ev.into());
+        let mut state = self.state.lock().unwrap();
+        if state.len + frame.len() as u64 > self.max_bytes {
+            counter!("overflow_dropped_total", "payload" => kind).increment(1);
+            return false;
+        }
+        if let Err(e) = state.file.write_all(&frame) {
+            log::warn!("cannot spill to {}: {}", self.path.display(), e);
+            counter!("overflow_dropped_total", "payload" => kind).increment(1);
+            return false;
+        }
+        state.len += frame.len() as u64;
+        gauge!("overflow_bytes").set(state.len as f64);
+        counter!("overflow_spilled_total", "payload" => kind).increment(1);
+        true
+    }
+
+    /// Empties the file and returns its events, oldest first. Reading stops
+    /// at a frame that does not decode, e.g. one cut short by a crash.
+    pub fn take(&self) -> io::Result<Vec<AnyEvent>> {
+        let mut buf = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.len == 0 {
+                return Ok(Vec::new());
+            }
+            File::open(&self.path)?.read_to_end(&mut buf)?;
+            state.file.set_len(0)?;
+            state.len = 0;
+            gauge!("overflow_bytes").set(0.0);
+        }
+
+        let mut events = Vec::new();
+        let mut rest = buf.as_slice();
+        loop {
+            match read_event(&mut rest) {
+                Ok(Some(ev)) => events.extend(from_base(ev)),
+                Ok(None) => break,
+                Err(e) => {
+                    log::warn!("{} ends with an unreadable frame: {}", self.path.display(), e);
+                    break;
+                }
+            }
+        }
+        Ok(events)
+    }
+}
+
+impl From<AnyEvent> for BaseEvent {
+    fn from(ev: AnyEvent) -> Self {
+        match ev {
+            AnyEvent::Process(ev)  => ev.into(),
+            AnyEvent::File(ev)     => ev.into(),
+            AnyEvent::Net(ev)      => ev.into(),
+            AnyEvent::Etw(ev)      => ev.into(),
+            AnyEvent::Scan(ev)     => ev.into(),
+            AnyEvent::Image(ev)    => ev.into(),
+            AnyEvent::ObjectOp(ev) => ev.into(),
+        }
+    }
+}
+
+/// Inverse of the conversion above, without a ring position.
+fn from_base(ev: BaseEvent) -> Option<AnyEvent> {
+    fn wrap<E: Clone>(ts: prost_types::Timestamp, sensor_guid: String, seq: u64, payload: E) -> WrappedEvent<E> {
+        WrappedEvent { ts, sensor_guid, payload, ring_pos: None, seq: (seq != 0).then_some(seq), enrichment: None, coalesced: None }
+    }
+    let (ts, guid, seq) = (ev.ts.unwrap_or_default(), ev.sensor_guid, ev.seq);
+    Some(match ev.payload? {
+        Payload::ProcessEvent(p)   => AnyEvent::Process(wrap(ts, guid, seq, p)),
+        Payload::FileEvent(p)      => AnyEvent::File(wrap(ts, guid, seq, p)),
+        Payload::NetworkEvent(p)   => AnyEvent::Net(wrap(ts, guid, seq, p)),
+        Payload::EtwEvent(p)       => AnyEvent::Etw(wrap(ts, guid, seq, p)),
+        Payload::ScanResult(p)     => AnyEvent::Scan(wrap(ts, guid, seq, p)),
+        Payload::ImageLoadEvent(p) => AnyEvent::Image(wrap(ts, guid, seq, p)),
+        Payload::ObjectOpEvent(p)  => AnyEvent::ObjectOp(wrap(ts, guid, seq, p)),
+    })
+}
+
+/// Every [`REPLAY_PERIOD`], moves the events of `overflow` into `tx` if it
+/// is at most half full, until `shutdown`. Events not sent by then go back
+/// to the file.
+pub fn spawn_replay(
+    rt: &Runtime,
+    overflow: Arc<Overflow>,
+    tx: mpsc::Sender<AnyEvent>,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    rt.spawn(async move {
+        let mut tick = tokio::time::interval(REPLAY_PERIOD);
+        loop {
+            tokio::select! {
+                _ = tick.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            if overflow.is_empty() || tx.capacity() < tx.max_capacity() / 2 {
+                continue;
+            }
+            let taken = {
+                let overflow = Arc::clone(&overflow);
+                task::spawn_blocking(move || overflow.take()).await
+            };
+            let events = match taken {
+                Ok(Ok(events)) => events,
+                Ok(Err(e)) => {
+                    log::warn!("cannot read the overflow file: {}", e);
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            log::info!("replaying {} shed events", events.len());
+
+            let mut events = events.into_iter();
+            while let Some(ev) = events.next() {
+                let permit = tokio::select! {
+                    permit = tx.reserve() => permit,
+                    _ = shutdown.triggered() => {
+                        for ev in iter::once(ev).chain(events) {
+                            overflow.spill(ev);
+                        }
+                        return;
+                    }
+                };
+                // The hub is gone; nothing would store them.
+                let Ok(permit) = permit else { return };
+                counter!("overflow_replayed_total", "payload" => ev.ring()).increment(1);
+                permit.send(ev);
+            }
+        }
+    })
+}
@@ -0,0 +1,127 @@
+// src/db/preflight.rs
+//! Pre-flight check of the SQLite runtime the agent is linked against.
+//!
+//! Bundled and system SQLite builds differ in version and compile options, and
+//! some filesystems (network shares) refuse WAL. Rather than failing deep inside
+//! a writer, `init_database` runs [`check`] and refuses to start with a
+//! [`CapabilityReport`] that says exactly what is missing.
+
+use std::fmt;
+use rusqlite::Connection;
+
+/// Oldest SQLite release the agent is tested against (UPSERT, window functions).
+pub const MIN_VERSION: (u32, u32, u32) = (3, 25, 0);
+
+/// Features a given configuration needs from SQLite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Requirements {
+    pub min_version: (u32, u32, u32),
+    pub wal:         bool,
+    /// Needed by JSON-shaped storage (ETW payload mapping, unified layouts).
+    pub json1:       bool,
+}
+
+impl Default for Requirements {
+    fn default() -> Self {
+        Self { min_version: MIN_VERSION, wal: true, json1: false }
+    }
+}
+
+/// What the linked SQLite can actually do on this database file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapabilityReport {
+    pub version:         String,
+    pub compile_options: Vec<String>,
+    /// `Some(true)` when WAL was enabled on the target file, `None` if not tried.
+    pub wal:             Option<bool>,
+    pub json1:           bool,
+    pub page_size:       u32,
+    /// Human-readable unmet requirements; empty means the check passed.
+    pub missing:         Vec<String>,
+}
+
+impl CapabilityReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+impl fmt::Display for CapabilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "SQLite capability report")?;
+        writeln!(f, "  version         : {}", self.version)?;
+        writeln!(f, "  WAL             : {}", match self.wal {
+            Some(true)  => "enabled",
+            Some(false) => "REFUSED",
+            None        => "not checked",
+        })?;
+        writeln!(f, "  JSON1           : {}", if self.json1 { "available" } else { "unavailable" })?;
+        writeln!(f, "  page_size       : {}", self.page_size)?;
+        writeln!(f, "  compile options : {}", self.compile_options.join(", "))?;
+        if self.missing.is_empty() {
+            write!(f, "  status          : OK")
+        } else {
+            write!(f, "  status          : UNSUPPORTED ({})", self.missing.join("; "))
+        }
+    }
+}
+
+/// Parses `"3.45.1"` into `(3, 45, 1)`. Missing components default to zero.
+pub fn parse_version(s: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = s.trim().split('.').map(|p| p.parse::<u32>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+/// JSON functions are built in since 3.38 unless compiled out with
+/// `OMIT_JSON`; older releases need `ENABLE_JSON1`.
+pub fn json1_from_options(version: (u32, u32, u32), options: &[String]) -> bool {
+    let has = |name: &str| options.iter().any(|o| o == name);
+    if has("OMIT_JSON") {
+        return false;
+    }
+    version >= (3, 38, 0) || has("ENABLE_JSON1")
+}
+
+/// Evaluates the linked SQLite against `req`. When `req.wal` is set this
+/// switches the connection to WAL, so it must run before any writer opens
+/// the file.
+pub fn check(conn: &Connection, req: &Requirements) -> rusqlite::Result<CapabilityReport> {
+    let version: String = conn.query_row("SELECT sqlite_version()", [], |r| r.get(0))?;
+    let compile_options = {
+        let mut stmt = conn.prepare("PRAGMA compile_options")?;
+        let rows = stmt.query_map([], |r| r.get::<_, String>(0))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    let page_size: u32 = conn.query_row("PRAGMA page_size", [], |r| r.get(0))?;
+
+    let mut missing = Vec::new();
+    let parsed = parse_version(&version).unwrap_or((0, 0, 0));
+    if parsed < req.min_version {
+        let (a, b, c) = req.min_version;
+        missing.push(format!("SQLite {version} is older than required {a}.{b}.{c}"));
+    }
+
+    let wal = if req.wal {
+        let mode: String = conn
+            .pragma_update_and_check(None, "journal_mode", "WAL", |r| r.get(0))?;
+        let ok = mode.eq_ignore_ascii_case("wal");
+        if !ok {
+            missing.push(format!("WAL refused by the target filesystem (journal_mode={mode})"));
+        }
+        Some(ok)
+    } else {
+        None
+    };
+
+    // Trust the runtime over compile flags: the function must actually exist.
+    let json1 = json1_from_options(parsed, &compile_options)
+        || conn.query_row("SELECT json('{}')", [], |_| Ok(())).is_ok();
+    if req.json1 && !json1 {
+        missing.push("JSON1 functions are not available".into());
+    }
+
+    Ok(CapabilityReport { version, compile_options, wal, json1, page_size, missing })
+}
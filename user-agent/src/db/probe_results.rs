@@ -0,0 +1,65 @@
+// src/db/probe_results.rs
+//! Persistence of live probe outcomes.
+
+use std::time::Duration;
+use rusqlite::{params, Connection};
+use crate::db::schema_registry::{ensure_for, table_exists, TableDef};
+use crate::probe::{ProbeResult, Sensor};
+
+/// One row per sensor and run; `latency_us` is NULL on failure.
+pub const PROBE_RESULTS_TABLE: TableDef = TableDef {
+    name:     "probe_results",
+    version:  1,
+    ddl: "\
+CREATE TABLE IF NOT EXISTS probe_results (
+    id         INTEGER PRIMARY KEY,
+    ts         INTEGER NOT NULL,
+    sensor     TEXT    NOT NULL,
+    marker     TEXT    NOT NULL,
+    success    BOOLEAN NOT NULL,
+    latency_us INTEGER,
+    error      TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_probe_results_sensor_ts ON probe_results(sensor, ts);",
+    upgrades: &[],
+};
+
+pub fn record_results(conn: &Connection, results: &[ProbeResult]) -> rusqlite::Result<()> {
+    ensure_for(conn, &PROBE_RESULTS_TABLE)?;
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO probe_results (ts, sensor, marker, success, latency_us, error) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    for r in results {
+        stmt.execute(params![
+            r.ts,
+            r.sensor.as_str(),
+            &r.marker,
+            r.success(),
+            r.latency.map(|d| d.as_micros() as i64),
+            r.error.as_deref(),
+        ])?;
+    }
+    Ok(())
+}
+
+/// Most recent results for `sensor`, newest first.
+pub fn recent_results(conn: &Connection, sensor: Sensor, limit: usize) -> rusqlite::Result<Vec<ProbeResult>> {
+    if !table_exists(conn, PROBE_RESULTS_TABLE.name)? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT ts, marker, latency_us, error FROM probe_results \
+         WHERE sensor = ?1 ORDER BY id DESC LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![sensor.as_str(), limit as i64], |r| {
+        Ok(ProbeResult {
+            ts:      r.get(0)?,
+            sensor,
+            marker:  r.get(1)?,
+            latency: r.get::<_, Option<i64>>(2)?.map(|us| Duration::from_micros(us as u64)),
+            error:   r.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
@@ -0,0 +1,136 @@
+// src/db/process_tree.rs
+//! Process ancestry and descendants rebuilt from stored `process_events`.
+//!
+//! PIDs are reused, so a pid names a process only at a point in time: the
+//! one whose creation is the latest at or before it. A parent is the
+//! creation of `ppid` latest before its child's; a child, a creation naming
+//! the pid as `ppid` after it and before the pid's next creation. Each step
+//! moves strictly backwards (ancestors) or forwards (descendants) in
+//! `(ts, id)` order, so a chain that loops through a reused pid ends, and
+//! depths are capped as well. A parent started before the agent has no
+//! creation stored: the chain ends at the child, whose `ppid` still says
+//! which pid it was.
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+
+use crate::db::{codec::StoredText, schema_registry::table_exists};
+
+/// Ancestors followed at most; deeper chains are cut.
+pub const MAX_ANCESTORS: u32 = 64;
+
+/// Stored creation of a process.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProcessNode {
+    /// Row id of the creation in `process_events`.
+    pub id:         i64,
+    /// Creation time, in microseconds.
+    pub ts:         i64,
+    pub pid:        u32,
+    pub ppid:       u32,
+    pub image_path: String,
+    pub cmdline:    String,
+    /// Steps from the process asked about: 0 for itself, 1 for its parent
+    /// or a child.
+    pub depth:      u32,
+}
+
+const COLUMNS: &str = "e.id, e.ts, e.pid, e.ppid, e.image_path, e.cmdline";
+
+/// Creation of `?1` latest at or before `?2`.
+const CREATION: &str = "
+SELECT id FROM process_events
+WHERE pid = ?1 AND event_type = 'CREATE' AND ts <= ?2
+ORDER BY ts DESC, id DESC LIMIT 1";
+
+const ANCESTORS: &str = "
+WITH RECURSIVE chain(id, depth) AS (
+    SELECT (SELECT id FROM process_events
+            WHERE pid = ?1 AND event_type = 'CREATE' AND ts <= ?2
+            ORDER BY ts DESC, id DESC LIMIT 1), 0
+    UNION ALL
+    SELECT (SELECT p.id FROM process_events p
+            WHERE p.pid = c.ppid AND p.event_type = 'CREATE'
+              AND (p.ts < c.ts OR (p.ts = c.ts AND p.id < c.id))
+            ORDER BY p.ts DESC, p.id DESC LIMIT 1), chain.depth + 1
+    FROM chain JOIN process_events c ON c.id = chain.id
+    WHERE chain.depth < ?3
+)
+SELECT e.id, e.ts, e.pid, e.ppid, e.image_path, e.cmdline, chain.depth
+FROM chain JOIN process_events e ON e.id = chain.id
+ORDER BY chain.depth";
+
+/// Depth-first: the queue is ordered by depth, deepest first, and siblings
+/// come out in creation order.
+const DESCENDANTS: &str = "
+WITH RECURSIVE tree(id, ts, depth) AS (
+    SELECT id, ts, 0 FROM process_events
+    WHERE id = (SELECT id FROM process_events
+                WHERE pid = ?1 AND event_type = 'CREATE' AND ts <= ?2
+                ORDER BY ts DESC, id DESC LIMIT 1)
+    UNION ALL
+    SELECT k.id, k.ts, tree.depth + 1
+    FROM tree
+    JOIN process_events n ON n.id = tree.id
+    JOIN process_events k ON k.ppid = n.pid AND k.event_type = 'CREATE'
+        AND (k.ts > n.ts OR (k.ts = n.ts AND k.id > n.id))
+    WHERE tree.depth < ?3
+      AND NOT EXISTS (
+          SELECT 1 FROM process_events r
+          WHERE r.pid = n.pid AND r.event_type = 'CREATE'
+            AND (r.ts > n.ts OR (r.ts = n.ts AND r.id > n.id))
+            AND (r.ts < k.ts OR (r.ts = k.ts AND r.id < k.id)))
+    ORDER BY 3 DESC, 2 ASC
+)
+SELECT e.id, e.ts, e.pid, e.ppid, e.image_path, e.cmdline, tree.depth
+FROM tree JOIN process_events e ON e.id = tree.id";
+
+fn node(r: &Row<'_>) -> rusqlite::Result<ProcessNode> {
+    Ok(ProcessNode {
+        id:         r.get(0)?,
+        ts:         r.get(1)?,
+        pid:        r.get(2)?,
+        ppid:       r.get::<_, Option<u32>>(3)?.unwrap_or_default(),
+        image_path: r.get::<_, Option<StoredText>>(4)?.map(|t| t.0).unwrap_or_default(),
+        cmdline:    r.get::<_, Option<StoredText>>(5)?.map(|t| t.0).unwrap_or_default(),
+        depth:      r.get(6)?,
+    })
+}
+
+/// The process `pid` named at `ts` (microseconds), if its creation is
+/// stored.
+pub fn creation(conn: &Connection, pid: u32, ts: i64) -> rusqlite::Result<Option<ProcessNode>> {
+    if !table_exists(conn, "process_events")? {
+        return Ok(None);
+    }
+    let sql = format!("SELECT {COLUMNS}, 0 FROM process_events e WHERE e.id = ({CREATION})");
+    conn.prepare_cached(&sql)?.query_row(params![pid, ts], node).optional()
+}
+
+/// The process `pid` named at `ts` followed by its parent, grandparent and
+/// so on, as far as creations are stored.
+pub fn ancestors(conn: &Connection, pid: u32, ts: i64) -> rusqlite::Result<Vec<ProcessNode>> {
+    if !table_exists(conn, "process_events")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare_cached(ANCESTORS)?;
+    let rows = stmt.query_map(params![pid, ts, MAX_ANCESTORS], node)?;
+    rows.collect()
+}
+
+/// The process `pid` named at `ts` and what it started, down to
+/// `max_depth` levels, depth-first.
+pub fn descendants(conn: &Connection, pid: u32, ts: i64, max_depth: u32) -> rusqlite::Result<Vec<ProcessNode>> {
+    if !table_exists(conn, "process_events")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare_cached(DESCENDANTS)?;
+    let rows = stmt.query_map(params![pid, ts, max_depth], node)?;
+    rows.collect()
+}
+
+/// Image of the parent of a process created at `ts` by `ppid`, for rules
+/// over the parent when the event did not carry it.
+pub fn parent_image(conn: &Connection, ppid: u32, ts: i64) -> rusqlite::Result<Option<String>> {
+    Ok(creation(conn, ppid, ts)?.map(|p| p.image_path).filter(|i| !i.is_empty()))
+}
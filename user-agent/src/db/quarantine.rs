@@ -0,0 +1,93 @@
+// src/db/quarantine.rs
+//! Persistence of the files sealed by the quarantine action.
+
+use rusqlite::{params, Connection};
+use crate::actions::quarantine::{QuarantineRecord, QuarantineStatus};
+use crate::db::schema_registry::{ensure_for, table_exists, TableDef};
+
+/// One row per request, with `container_id`, `sha256` and `size` NULL
+/// unless the file was sealed. A restore updates the row of its container.
+pub const QUARANTINE_TABLE: TableDef = TableDef {
+    name:     "quarantine",
+    version:  1,
+    ddl: "\
+CREATE TABLE IF NOT EXISTS quarantine (
+    id           INTEGER PRIMARY KEY,
+    ts           INTEGER NOT NULL,
+    alert_id     INTEGER,
+    path         TEXT    NOT NULL,
+    reason       TEXT    NOT NULL,
+    status       TEXT    NOT NULL,
+    container_id TEXT,
+    sha256       TEXT,
+    size         INTEGER,
+    modified     INTEGER,
+    error        TEXT,
+    restored_at  INTEGER
+);
+CREATE INDEX IF NOT EXISTS idx_quarantine_alert ON quarantine(alert_id);
+CREATE INDEX IF NOT EXISTS idx_quarantine_container ON quarantine(container_id);",
+    upgrades: &[],
+};
+
+/// Stores `r` and returns its row id.
+pub fn record_quarantine(conn: &Connection, r: &QuarantineRecord) -> rusqlite::Result<i64> {
+    ensure_for(conn, &QUARANTINE_TABLE)?;
+    conn.execute(
+        "INSERT INTO quarantine (ts, alert_id, path, reason, status, container_id, sha256, size, modified, error) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            r.ts,
+            r.alert_id,
+            r.path,
+            r.reason,
+            r.status.as_str(),
+            r.id.as_deref(),
+            r.sha256.as_deref(),
+            r.size.map(|s| s as i64),
+            r.modified,
+            r.error.as_deref(),
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Marks the row of container `id` restored at `ts` (UNIX microseconds);
+/// returns whether there was one.
+pub fn mark_restored(conn: &Connection, id: &str, ts: i64) -> rusqlite::Result<bool> {
+    if !table_exists(conn, QUARANTINE_TABLE.name)? {
+        return Ok(false);
+    }
+    let changed = conn.execute(
+        "UPDATE quarantine SET status = ?1, restored_at = ?2 WHERE container_id = ?3",
+        params![QuarantineStatus::Restored.as_str(), ts, id],
+    )?;
+    Ok(changed > 0)
+}
+
+/// Quarantine requests made for `alert_id`, oldest first.
+pub fn quarantine_for_alert(conn: &Connection, alert_id: i64) -> rusqlite::Result<Vec<QuarantineRecord>> {
+    if !table_exists(conn, QUARANTINE_TABLE.name)? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT ts, path, reason, status, container_id, sha256, size, modified, error FROM quarantine \
+         WHERE alert_id = ?1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map([alert_id], |r| {
+        let status: String = r.get(3)?;
+        Ok(QuarantineRecord {
+            ts:       r.get(0)?,
+            alert_id: Some(alert_id),
+            path:     r.get(1)?,
+            reason:   r.get(2)?,
+            status:   QuarantineStatus::parse(&status).unwrap_or(QuarantineStatus::Failed),
+            id:       r.get(4)?,
+            sha256:   r.get(5)?,
+            size:     r.get::<_, Option<i64>>(6)?.map(|s| s as u64),
+            modified: r.get(7)?,
+            error:    r.get(8)?,
+        })
+    })?;
+    rows.collect()
+}
@@ -0,0 +1,373 @@
+// src/db/query.rs
+//! Read-only queries over stored telemetry, for `gladix-cli query`.
+//!
+//! Every filter becomes a bound parameter of one `SELECT`, newest rows first;
+//! `tree` walks parents and children through [`process_tree`](super::process_tree).
+//! Stored values are turned back into what the event said: compressed text
+//! is decoded, hashes are hex and `ts` is rendered as RFC 3339.
+//! `fs_events.op` is stored as its proto name and `result` as 0/1.
+
+use std::path::Path;
+use chrono::{DateTime, SecondsFormat};
+use rusqlite::{params_from_iter, types::Value, Connection, OpenFlags, Row};
+use serde::Serialize;
+
+use shared::events::file_event::Operation;
+
+use crate::db::{
+    codec::StoredText,
+    process_tree::{ancestors, descendants, ProcessNode},
+    schema_registry::table_exists,
+};
+
+/// Rows returned when no `--limit` is given.
+pub const DEFAULT_LIMIT: usize = 100;
+
+/// Levels of descendants shown when no `--depth` is given.
+pub const DEFAULT_TREE_DEPTH: u32 = 8;
+
+/// Opens the database at `path` without write access, so a query never
+/// competes with the agent's writers for the lock.
+pub fn open_read_only(path: &Path) -> rusqlite::Result<Connection> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+    Ok(conn)
+}
+
+/// Filters of `query processes`.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessQuery {
+    /// Lower bound on `ts`, in microseconds.
+    pub since:    Option<i64>,
+    /// Case-insensitive substring of the normalized image path.
+    pub image:    Option<String>,
+    pub pid:      Option<u32>,
+    /// SID the process ran as, in any case.
+    pub user:     Option<String>,
+    /// Only creations with (`true`) or without an elevated token.
+    pub elevated: Option<bool>,
+    pub limit:    Option<usize>,
+}
+
+/// Filters of `query files`.
+#[derive(Debug, Clone, Default)]
+pub struct FileQuery {
+    pub since:         Option<i64>,
+    /// Case-insensitive substring of `path`.
+    pub path_contains: Option<String>,
+    pub op:            Option<Operation>,
+    pub pid:           Option<u32>,
+    pub limit:         Option<usize>,
+}
+
+/// Filters of `query net`.
+#[derive(Debug, Clone, Default)]
+pub struct NetQuery {
+    pub since:    Option<i64>,
+    pub dst_ip:   Option<String>,
+    pub dst_port: Option<u16>,
+    pub pid:      Option<u32>,
+    pub limit:    Option<usize>,
+}
+
+/// What `query tree` shows.
+#[derive(Debug, Clone, Default)]
+pub struct TreeQuery {
+    pub pid:   u32,
+    /// When the pid is resolved, in microseconds; now if unset.
+    pub at:    Option<i64>,
+    /// Levels of descendants; [`DEFAULT_TREE_DEPTH`] if unset.
+    pub depth: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProcessRow {
+    pub ts:         String,
+    pub event_type: String,
+    pub pid:        i64,
+    pub ppid:       Option<i64>,
+    pub image_path: Option<String>,
+    pub cmdline:    Option<String>,
+    pub exit_code:  Option<i64>,
+    pub user_sid:   Option<String>,
+    pub session_id: Option<i64>,
+    pub elevated:   Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileRow {
+    pub ts:       String,
+    pub op:       String,
+    pub path:     String,
+    pub new_path: Option<String>,
+    pub pid:      Option<i64>,
+    pub exe_path: Option<String>,
+    pub size:     Option<i64>,
+    pub sha256:   Option<String>,
+    pub result:   Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NetRow {
+    pub ts:        String,
+    pub direction: String,
+    pub proto:     String,
+    pub src_ip:    String,
+    pub src_port:  Option<i64>,
+    pub dst_ip:    String,
+    pub dst_port:  Option<i64>,
+    pub pid:       Option<i64>,
+    pub exe_path:  Option<String>,
+    pub verdict:   Option<String>,
+}
+
+/// One process of `query tree`: the ancestors of the pid, oldest first,
+/// then the pid and its descendants depth-first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TreeRow {
+    /// Indentation: 0 for the oldest ancestor found.
+    pub level:      usize,
+    pub ts:         String,
+    pub pid:        u32,
+    pub ppid:       u32,
+    pub image_path: String,
+    pub cmdline:    String,
+}
+
+/// Rows printable as a table.
+pub trait Tabular: Serialize {
+    const HEADERS: &'static [&'static str];
+    fn cells(&self) -> Vec<String>;
+}
+
+fn opt<T: ToString>(v: &Option<T>) -> String {
+    v.as_ref().map_or_else(|| "-".into(), T::to_string)
+}
+
+impl Tabular for ProcessRow {
+    const HEADERS: &'static [&'static str] = &["ts", "type", "pid", "ppid", "user", "image", "cmdline"];
+    fn cells(&self) -> Vec<String> {
+        let kind = match self.exit_code {
+            Some(code) => format!("{} ({code})", self.event_type),
+            None => self.event_type.clone(),
+        };
+        let user = match (&self.user_sid, self.elevated) {
+            (Some(sid), Some(true)) => format!("{sid} (elevated)"),
+            (sid, _) => opt(sid),
+        };
+        vec![
+            self.ts.clone(), kind, self.pid.to_string(), opt(&self.ppid), user, opt(&self.image_path),
+            opt(&self.cmdline),
+        ]
+    }
+}
+
+impl Tabular for FileRow {
+    const HEADERS: &'static [&'static str] = &["ts", "op", "pid", "path", "new_path", "exe"];
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.ts.clone(), self.op.clone(), opt(&self.pid), self.path.clone(),
+            opt(&self.new_path.as_ref().filter(|p| !p.is_empty())), opt(&self.exe_path),
+        ]
+    }
+}
+
+impl Tabular for TreeRow {
+    const HEADERS: &'static [&'static str] = &["ts", "pid", "ppid", "image", "cmdline"];
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.ts.clone(), format!("{}{}", "  ".repeat(self.level), self.pid), self.ppid.to_string(),
+            self.image_path.clone(), self.cmdline.clone(),
+        ]
+    }
+}
+
+impl Tabular for NetRow {
+    const HEADERS: &'static [&'static str] = &["ts", "dir", "proto", "source", "destination", "pid", "exe"];
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.ts.clone(), self.direction.clone(), self.proto.clone(),
+            format!("{}:{}", self.src_ip, opt(&self.src_port)), format!("{}:{}", self.dst_ip, opt(&self.dst_port)),
+            opt(&self.pid), opt(&self.exe_path),
+        ]
+    }
+}
+
+/// `rows` as left-aligned columns under [`Tabular::HEADERS`].
+pub fn render_table<R: Tabular>(rows: &[R]) -> String {
+    let cells: Vec<Vec<String>> = rows.iter().map(Tabular::cells).collect();
+    let mut widths: Vec<usize> = R::HEADERS.iter().map(|h| h.len()).collect();
+    for row in &cells {
+        for (w, c) in widths.iter_mut().zip(row) {
+            *w = (*w).max(c.chars().count());
+        }
+    }
+    let mut out = String::new();
+    let headers = R::HEADERS.iter().map(|h| h.to_string());
+    for row in std::iter::once(headers.collect::<Vec<_>>()).chain(cells) {
+        let line: Vec<String> = row.iter().zip(&widths).map(|(c, w)| format!("{c:<w$}")).collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// `WHERE` clauses and their parameters, in order.
+#[derive(Default)]
+struct Filters {
+    clauses: Vec<&'static str>,
+    params:  Vec<Value>,
+}
+
+impl Filters {
+    fn add(&mut self, clause: &'static str, value: Option<impl Into<Value>>) {
+        if let Some(v) = value {
+            self.clauses.push(clause);
+            self.params.push(v.into());
+        }
+    }
+
+    /// Selects `columns` of `table` with the filters, newest first, mapping
+    /// rows with `map`. A table the agent has not created yet has no rows.
+    fn run<T>(
+        mut self,
+        conn: &Connection,
+        table: &str,
+        columns: &str,
+        limit: Option<usize>,
+        map: impl Fn(&Row<'_>) -> rusqlite::Result<T>,
+    ) -> rusqlite::Result<Vec<T>> {
+        if !table_exists(conn, table)? {
+            return Ok(Vec::new());
+        }
+        let mut sql = format!("SELECT {columns} FROM {table}");
+        if !self.clauses.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&self.clauses.join(" AND "));
+        }
+        sql.push_str(" ORDER BY ts DESC, rowid DESC LIMIT ?");
+        self.params.push(Value::Integer(limit.unwrap_or(DEFAULT_LIMIT) as i64));
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(self.params), map)?;
+        rows.collect()
+    }
+}
+
+fn rfc3339(micros: i64) -> String {
+    DateTime::from_timestamp_micros(micros).unwrap_or_default().to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn text(row: &Row<'_>, idx: usize) -> rusqlite::Result<Option<String>> {
+    Ok(row.get::<_, Option<StoredText>>(idx)?.map(|t| t.0))
+}
+
+/// Process creations and exits matching `q`.
+pub fn processes(conn: &Connection, q: &ProcessQuery) -> rusqlite::Result<Vec<ProcessRow>> {
+    let mut f = Filters::default();
+    f.add("ts >= ?", q.since);
+    f.add("instr(image_path_norm, ?) > 0", q.image.as_ref().map(|s| s.to_lowercase()));
+    f.add("pid = ?", q.pid);
+    // Stored as the driver formats them, with an upper-case `S`.
+    f.add("user_sid = ?", q.user.as_ref().map(|s| s.to_uppercase()));
+    f.add("elevated = ?", q.elevated);
+    f.run(
+        conn,
+        "process_events",
+        "ts, event_type, pid, ppid, image_path, cmdline, exit_code, user_sid, session_id, elevated",
+        q.limit,
+        |r| Ok(ProcessRow {
+            ts:         rfc3339(r.get(0)?),
+            event_type: r.get(1)?,
+            pid:        r.get(2)?,
+            ppid:       r.get(3)?,
+            image_path: text(r, 4)?,
+            cmdline:    text(r, 5)?,
+            exit_code:  r.get(6)?,
+            user_sid:   r.get(7)?,
+            session_id: r.get(8)?,
+            elevated:   r.get(9)?,
+        }),
+    )
+}
+
+/// File operations matching `q`.
+pub fn files(conn: &Connection, q: &FileQuery) -> rusqlite::Result<Vec<FileRow>> {
+    let mut f = Filters::default();
+    f.add("ts >= ?", q.since);
+    f.add("instr(lower(path), ?) > 0", q.path_contains.as_ref().map(|s| s.to_lowercase()));
+    f.add("op = ?", q.op.map(|op| op.as_str_name().to_owned()));
+    f.add("pid = ?", q.pid);
+    f.run(
+        conn,
+        "fs_events",
+        "ts, op, path, new_path, pid, exe_path, size, sha256, result",
+        q.limit,
+        |r| Ok(FileRow {
+            ts:       rfc3339(r.get(0)?),
+            op:       r.get(1)?,
+            path:     r.get(2)?,
+            new_path: r.get(3)?,
+            pid:      r.get(4)?,
+            exe_path: r.get(5)?,
+            size:     r.get(6)?,
+            sha256:   r.get::<_, Option<Vec<u8>>>(7)?.filter(|h| !h.is_empty()).map(hex::encode),
+            result:   r.get(8)?,
+        }),
+    )
+}
+
+/// Network connections matching `q`.
+pub fn network(conn: &Connection, q: &NetQuery) -> rusqlite::Result<Vec<NetRow>> {
+    let mut f = Filters::default();
+    f.add("ts >= ?", q.since);
+    f.add("dst_ip = ?", q.dst_ip.clone());
+    f.add("dst_port = ?", q.dst_port);
+    f.add("pid = ?", q.pid);
+    f.run(
+        conn,
+        "network_events",
+        "ts, direction, proto, src_ip, src_port, dst_ip, dst_port, pid, exe_path, verdict",
+        q.limit,
+        |r| Ok(NetRow {
+            ts:        rfc3339(r.get(0)?),
+            direction: r.get(1)?,
+            proto:     r.get(2)?,
+            src_ip:    r.get(3)?,
+            src_port:  r.get(4)?,
+            dst_ip:    r.get(5)?,
+            dst_port:  r.get(6)?,
+            pid:       r.get(7)?,
+            exe_path:  r.get(8)?,
+            verdict:   r.get(9)?,
+        }),
+    )
+}
+
+/// Ancestry and descendants of `q.pid` as it was at `q.at`; empty when no
+/// creation of the pid is stored by then.
+pub fn tree(conn: &Connection, q: &TreeQuery) -> rusqlite::Result<Vec<TreeRow>> {
+    let at = q.at.unwrap_or_else(|| chrono::Utc::now().timestamp_micros());
+    let mut up = ancestors(conn, q.pid, at)?;
+    up.reverse();
+    // The pid itself closes the ancestry and opens the descendants.
+    up.pop();
+    let down = descendants(conn, q.pid, at, q.depth.unwrap_or(DEFAULT_TREE_DEPTH))?;
+    let top = up.len();
+    let row = |level: usize, n: ProcessNode| TreeRow {
+        level,
+        ts:         rfc3339(n.ts),
+        pid:        n.pid,
+        ppid:       n.ppid,
+        image_path: n.image_path,
+        cmdline:    n.cmdline,
+    };
+    Ok(up.into_iter().enumerate().map(|(i, n)| row(i, n))
+        .chain(down.into_iter().map(|n| row(top + n.depth as usize, n)))
+        .collect())
+}
+
+/// `--op` as given on the command line: the proto name in any case
+/// (`Write`) or its number.
+pub fn parse_op(s: &str) -> Option<Operation> {
+    Operation::from_str_name(&s.to_uppercase()).or_else(|| s.parse::<i32>().ok().and_then(|n| Operation::try_from(n).ok()))
+}
@@ -0,0 +1,326 @@
+// src/db/reprocess.rs
+//! Re-runs enrichments over rows written before they shipped.
+//!
+//! Each enrichment registers a [`Backfill`]. Jobs live in `reprocess_jobs`
+//! and are queued by `gladix-cli reprocess` or by [`migrate`] when it adds
+//! an enrichment's columns to an existing database. The background task walks
+//! matching rows by id in bounded chunks; every chunk commits its updates and
+//! the job cursor together, so an interrupted job resumes where it stopped.
+
+use std::{path::PathBuf, time::Duration};
+use metrics::counter;
+use rusqlite::{params, Connection, OptionalExtension};
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+use crate::db::{db_writer::under_pressure, schema_registry::{table_exists, TableDef}};
+use crate::idle::{IdleGate, Task};
+use crate::intel::enrich::{PATH_NORMALIZATION, SID_RESOLUTION};
+use crate::util::Shutdown;
+
+/// Rows enriched per transaction.
+pub const REPROCESS_CHUNK: usize = 500;
+/// Pause between chunks so live writers keep priority.
+const REPROCESS_PAUSE: Duration = Duration::from_millis(250);
+/// Back-off while a writer is flushing full batches.
+const PRESSURE_BACKOFF: Duration = Duration::from_secs(5);
+/// How often the task looks for queued jobs.
+const POLL_PERIOD: Duration = Duration::from_secs(30);
+
+/// An enrichment that can be applied to historical rows.
+#[derive(Debug)]
+pub struct Backfill {
+    /// Name used by `gladix-cli reprocess <name>`.
+    pub name:    &'static str,
+    pub table:   &'static str,
+    /// Columns the enrichment fills, with their SQL type.
+    pub columns: &'static [(&'static str, &'static str)],
+    /// SQL predicate selecting rows the enrichment has not processed yet.
+    pub pending: &'static str,
+    /// Computes and UPDATEs the new columns of the given rows; runs inside
+    /// the chunk transaction. Returns the number of rows updated.
+    pub apply:   fn(&Connection, &[i64]) -> rusqlite::Result<usize>,
+}
+
+pub const BACKFILLS: &[&Backfill] = &[&PATH_NORMALIZATION, &SID_RESOLUTION];
+
+pub fn backfill(name: &str) -> Option<&'static Backfill> {
+    BACKFILLS.iter().copied().find(|b| b.name == name)
+}
+
+/// `cursor` is the highest row id walked; `since` limits the job to rows
+/// with `ts >= since`.
+pub const JOBS_TABLE: TableDef = TableDef {
+    name:     "reprocess_jobs",
+    version:  1,
+    ddl: "\
+CREATE TABLE IF NOT EXISTS reprocess_jobs (
+    id         INTEGER PRIMARY KEY,
+    enrichment TEXT    NOT NULL,
+    since      INTEGER,
+    state      TEXT    NOT NULL,
+    cursor     INTEGER NOT NULL DEFAULT 0,
+    processed  INTEGER NOT NULL DEFAULT 0,
+    updated    INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    error      TEXT
+);",
+    upgrades: &[],
+};
+
+/// Creates `reprocess_jobs` and adds missing enrichment columns to an
+/// existing database, queueing a job for every enrichment whose columns were
+/// added. Returns the names of those enrichments.
+pub fn migrate(conn: &Connection) -> rusqlite::Result<Vec<&'static str>> {
+    conn.execute_batch(JOBS_TABLE.ddl)?;
+    let mut queued = Vec::new();
+    for b in BACKFILLS {
+        let missing = missing_columns(conn, b)?;
+        for (col, ty) in &missing {
+            conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {col} {ty}", b.table))?;
+        }
+        if !missing.is_empty() {
+            enqueue(conn, b, None)?;
+            queued.push(b.name);
+        }
+    }
+    Ok(queued)
+}
+
+/// `true` when [`migrate`] would alter a table.
+pub fn needs_migration(conn: &Connection) -> rusqlite::Result<bool> {
+    for b in BACKFILLS {
+        if !missing_columns(conn, b)?.is_empty() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn missing_columns(conn: &Connection, b: &Backfill) -> rusqlite::Result<Vec<(&'static str, &'static str)>> {
+    let existing: Vec<String> = conn
+        .prepare(&format!("PRAGMA table_info({})", b.table))?
+        .query_map([], |r| r.get(1))?
+        .collect::<rusqlite::Result<_>>()?;
+    // A missing table has no columns to add: the schema creates it whole.
+    if existing.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(b.columns.iter().copied().filter(|(c, _)| !existing.iter().any(|e| e == c)).collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+impl JobState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobState::Pending => "pending",
+            JobState::Running => "running",
+            JobState::Done    => "done",
+            JobState::Failed  => "failed",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "pending" => JobState::Pending,
+            "running" => JobState::Running,
+            "done"    => JobState::Done,
+            _         => JobState::Failed,
+        }
+    }
+}
+
+/// A row of `reprocess_jobs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub id:         i64,
+    pub enrichment: String,
+    /// Only rows with `ts >= since` (UNIX microseconds).
+    pub since:      Option<i64>,
+    pub state:      JobState,
+    /// Highest row id walked so far.
+    pub cursor:     i64,
+    pub processed:  u64,
+    pub updated:    u64,
+    pub error:      Option<String>,
+}
+
+const JOB_COLUMNS: &str = "id, enrichment, since, state, cursor, processed, updated, error";
+
+fn job_from_row(r: &rusqlite::Row<'_>) -> rusqlite::Result<Job> {
+    Ok(Job {
+        id:         r.get(0)?,
+        enrichment: r.get(1)?,
+        since:      r.get(2)?,
+        state:      JobState::parse(&r.get::<_, String>(3)?),
+        cursor:     r.get(4)?,
+        processed:  r.get::<_, i64>(5)? as u64,
+        updated:    r.get::<_, i64>(6)? as u64,
+        error:      r.get(7)?,
+    })
+}
+
+/// Queues a job, or returns the id of an unfinished one for the same
+/// enrichment and `since`.
+pub fn enqueue(conn: &Connection, backfill: &Backfill, since: Option<i64>) -> rusqlite::Result<i64> {
+    let open: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM reprocess_jobs \
+             WHERE enrichment = ?1 AND since IS ?2 AND state IN ('pending', 'running')",
+            params![backfill.name, since],
+            |r| r.get(0),
+        )
+        .optional()?;
+    if let Some(id) = open {
+        return Ok(id);
+    }
+    let now = chrono::Utc::now().timestamp_micros();
+    conn.execute(
+        "INSERT INTO reprocess_jobs (enrichment, since, state, created_at, updated_at) \
+         VALUES (?1, ?2, 'pending', ?3, ?3)",
+        params![backfill.name, since, now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// All jobs, newest first.
+pub fn jobs(conn: &Connection) -> rusqlite::Result<Vec<Job>> {
+    let mut stmt = conn.prepare(&format!("SELECT {JOB_COLUMNS} FROM reprocess_jobs ORDER BY id DESC"))?;
+    let rows = stmt.query_map([], job_from_row)?;
+    rows.collect()
+}
+
+/// Oldest unfinished job; a `running` job is one interrupted by a restart.
+pub fn next_job(conn: &Connection) -> rusqlite::Result<Option<Job>> {
+    conn.query_row(
+        &format!(
+            "SELECT {JOB_COLUMNS} FROM reprocess_jobs \
+             WHERE state IN ('pending', 'running') ORDER BY id LIMIT 1"
+        ),
+        [],
+        job_from_row,
+    )
+    .optional()
+}
+
+fn set_state(conn: &Connection, job: &mut Job, state: JobState, error: Option<String>) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE reprocess_jobs SET state = ?1, error = ?2, updated_at = ?3 WHERE id = ?4",
+        params![state.as_str(), &error, chrono::Utc::now().timestamp_micros(), job.id],
+    )?;
+    job.state = state;
+    job.error = error;
+    Ok(())
+}
+
+/// Enriches up to `chunk` pending rows after the job cursor and records the
+/// progress in the same transaction. Returns `false` once nothing is left.
+pub fn run_chunk(conn: &Connection, backfill: &Backfill, job: &mut Job, chunk: usize) -> rusqlite::Result<bool> {
+    // A table not created yet has no rows to enrich.
+    if !table_exists(conn, backfill.table)? {
+        return Ok(false);
+    }
+    let ids: Vec<i64> = {
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT id FROM {} WHERE id > ?1 AND ({}) AND (?2 IS NULL OR ts >= ?2) \
+             ORDER BY id LIMIT ?3",
+            backfill.table, backfill.pending
+        ))?;
+        stmt.query_map(params![job.cursor, job.since, chunk as i64], |r| r.get(0))?
+            .collect::<rusqlite::Result<_>>()?
+    };
+    let Some(&last) = ids.last() else { return Ok(false) };
+
+    let tx = conn.unchecked_transaction()?;
+    let updated = (backfill.apply)(&tx, &ids)?;
+    tx.execute(
+        "UPDATE reprocess_jobs SET cursor = ?1, processed = processed + ?2, \
+         updated = updated + ?3, updated_at = ?4 WHERE id = ?5",
+        params![last, ids.len() as i64, updated as i64, chrono::Utc::now().timestamp_micros(), job.id],
+    )?;
+    tx.commit()?;
+
+    job.cursor = last;
+    job.processed += ids.len() as u64;
+    job.updated += updated as u64;
+    counter!("reprocess_rows_total", "enrichment" => backfill.name).increment(updated as u64);
+    Ok(true)
+}
+
+/// Parses `--since`: an RFC 3339 timestamp or a duration before `now`
+/// (`7d`, `12h`). Returns UNIX microseconds.
+pub fn parse_since(s: &str, now: chrono::DateTime<chrono::Utc>) -> Option<i64> {
+    if let Ok(ts) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Some(ts.timestamp_micros());
+    }
+    let ago = humantime::parse_duration(s).ok()?;
+    Some(now.timestamp_micros() - ago.as_micros() as i64)
+}
+
+/// Picks up queued jobs and runs them one at a time, backing off while any
+/// writer is under pressure and starting jobs only while the user is idle.
+pub fn spawn_reprocessor(rt: &Runtime, db_path: PathBuf, idle: IdleGate, shutdown: Shutdown) -> JoinHandle<()> {
+    rt.spawn(async move {
+        let mut ticker = tokio::time::interval(POLL_PERIOD);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => return,
+            }
+            let mut conn = match Connection::open(&db_path) {
+                Ok(c) => c,
+                Err(e) => { log::warn!("reprocess: {}", e); continue; }
+            };
+            let _ = conn.busy_timeout(Duration::from_millis(1_000));
+            while let Ok(Some(job)) = next_job(&conn) {
+                if !idle.wait(Task::Reprocess, &shutdown).await {
+                    return;
+                }
+                if let Err(e) = run_job(&mut conn, job, &shutdown).await {
+                    log::warn!("reprocess: {}", e);
+                    break;
+                }
+            }
+        }
+    })
+}
+
+/// Runs `job` to completion, or until `shutdown`, which leaves it running
+/// for the next start to resume. Enrichment errors end the job as failed;
+/// an error is returned only when the job state itself cannot be recorded.
+async fn run_job(conn: &mut Connection, mut job: Job, shutdown: &Shutdown) -> rusqlite::Result<()> {
+    let Some(backfill) = backfill(&job.enrichment) else {
+        let error = format!("unknown enrichment {}", job.enrichment);
+        return set_state(conn, &mut job, JobState::Failed, Some(error));
+    };
+    set_state(conn, &mut job, JobState::Running, None)?;
+    log::info!("reprocess {} started after row {}", job.enrichment, job.cursor);
+    loop {
+        let pause = if under_pressure() {
+            PRESSURE_BACKOFF
+        } else {
+            match run_chunk(conn, backfill, &mut job, REPROCESS_CHUNK) {
+                Ok(true) => REPROCESS_PAUSE,
+                Ok(false) => {
+                    log::info!("reprocess {} done, {} rows updated", job.enrichment, job.updated);
+                    return set_state(conn, &mut job, JobState::Done, None);
+                }
+                Err(e) => {
+                    log::warn!("reprocess {} failed: {}", job.enrichment, e);
+                    return set_state(conn, &mut job, JobState::Failed, Some(e.to_string()));
+                }
+            }
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(pause) => {}
+            _ = shutdown.triggered() => return Ok(()),
+        }
+    }
+}
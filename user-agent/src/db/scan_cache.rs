@@ -0,0 +1,94 @@
+// src/db/scan_cache.rs
+//! The directory scanner's cache: what each file hashed to when last seen.
+//!
+//! Rows are written per pass for the entries that changed only, see
+//! [`crate::scanner::cache::PersistentCache`].
+
+use std::{collections::HashMap, path::PathBuf};
+use rusqlite::{params, Connection};
+use crate::db::schema_registry::{ensure_for, table_exists, TableDef};
+use crate::scanner::cache::FileCacheEntry;
+
+/// One row per file or `path:stream`. `mtime` is the file's, `last_seen`
+/// the time the row was last written, both in seconds since the epoch.
+/// `hash` is the XxHash64, stored as its signed bit pattern. `retry_after`
+/// is in seconds since the epoch too, set while a file is being written.
+pub const SCAN_CACHE_TABLE: TableDef = TableDef {
+    name:     "scan_cache",
+    version:  2,
+    ddl: "\
+CREATE TABLE IF NOT EXISTS scan_cache (
+    path        TEXT    PRIMARY KEY,
+    hash        INTEGER NOT NULL,
+    mtime       INTEGER NOT NULL,
+    scan_result TEXT,
+    size        INTEGER,
+    sha256      TEXT,
+    last_seen   INTEGER NOT NULL,
+    retry_after INTEGER
+);",
+    upgrades: &[(2, "ALTER TABLE scan_cache ADD COLUMN retry_after INTEGER;")],
+};
+
+/// Every entry; empty before the first pass stored one.
+pub fn load_cache(conn: &Connection) -> rusqlite::Result<HashMap<PathBuf, FileCacheEntry>> {
+    if !table_exists(conn, SCAN_CACHE_TABLE.name)? {
+        return Ok(HashMap::new());
+    }
+    let mut stmt = conn.prepare("SELECT path, hash, mtime, scan_result, size, sha256, retry_after FROM scan_cache")?;
+    let rows = stmt.query_map([], |r| {
+        Ok((
+            PathBuf::from(r.get::<_, String>(0)?),
+            FileCacheEntry {
+                hash:        r.get::<_, i64>(1)? as u64,
+                timestamp:   r.get::<_, i64>(2)? as u64,
+                scan_result: r.get(3)?,
+                size:        r.get::<_, Option<i64>>(4)?.map(|s| s as u64),
+                sha256:      r.get(5)?,
+                retry_after: r.get::<_, Option<i64>>(6)?.map(|t| t as u64),
+            },
+        ))
+    })?;
+    rows.collect()
+}
+
+/// Inserts or replaces `entries` in one transaction.
+pub fn upsert_entries(conn: &Connection, entries: &[(PathBuf, FileCacheEntry)]) -> rusqlite::Result<()> {
+    ensure_for(conn, &SCAN_CACHE_TABLE)?;
+    let now = chrono::Utc::now().timestamp();
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO scan_cache (path, hash, mtime, scan_result, size, sha256, last_seen, retry_after) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )?;
+        for (path, e) in entries {
+            stmt.execute(params![
+                path.to_string_lossy(),
+                e.hash as i64,
+                e.timestamp as i64,
+                e.scan_result,
+                e.size.map(|s| s as i64),
+                e.sha256,
+                now,
+                e.retry_after.map(|t| t as i64),
+            ])?;
+        }
+    }
+    tx.commit()
+}
+
+/// Removes the rows of `paths`, e.g. files pruned since the last pass.
+pub fn delete_entries(conn: &Connection, paths: &[PathBuf]) -> rusqlite::Result<()> {
+    if paths.is_empty() || !table_exists(conn, SCAN_CACHE_TABLE.name)? {
+        return Ok(());
+    }
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut stmt = tx.prepare("DELETE FROM scan_cache WHERE path = ?1")?;
+        for path in paths {
+            stmt.execute([path.to_string_lossy()])?;
+        }
+    }
+    tx.commit()
+}
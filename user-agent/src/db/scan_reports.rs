@@ -0,0 +1,172 @@
+// src/db/scan_reports.rs
+//! Persistence of scan change reports and the baselines they compare against.
+
+use std::path::PathBuf;
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::db::schema_registry::{ensure_for, table_exists, TableDef};
+use crate::reports::{render::{Rendered, Report}, FileState, Snapshot};
+
+/// One row per generated report; `delivered_to` lists the channels that
+/// accepted it, comma-separated, and is NULL until delivery.
+pub const SCAN_REPORTS_TABLE: TableDef = TableDef {
+    name:     "scan_reports",
+    version:  1,
+    ddl: "\
+CREATE TABLE IF NOT EXISTS scan_reports (
+    id           INTEGER PRIMARY KEY,
+    ts           INTEGER NOT NULL,
+    risk         TEXT    NOT NULL,
+    since        INTEGER,
+    baseline     BOOLEAN NOT NULL,
+    added        INTEGER NOT NULL,
+    removed      INTEGER NOT NULL,
+    changed      INTEGER NOT NULL,
+    subject      TEXT    NOT NULL,
+    body_json    TEXT    NOT NULL,
+    body_text    TEXT    NOT NULL,
+    body_html    TEXT    NOT NULL,
+    delivered_to TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_scan_reports_risk_ts ON scan_reports(risk, ts);",
+    upgrades: &[],
+};
+
+/// State of each group at its last report. `hash` is NULL for placeholders
+/// that were not read.
+pub const SCAN_BASELINES_TABLE: TableDef = TableDef {
+    name:     "scan_baselines",
+    version:  1,
+    ddl: "\
+CREATE TABLE IF NOT EXISTS scan_baselines (
+    risk TEXT    NOT NULL,
+    path TEXT    NOT NULL,
+    hash INTEGER,
+    size INTEGER,
+    PRIMARY KEY (risk, path)
+);",
+    upgrades: &[],
+};
+
+/// A report as stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredReport {
+    pub id:           i64,
+    pub ts:           i64,
+    pub risk:         String,
+    pub since:        Option<i64>,
+    pub baseline:     bool,
+    pub added:        usize,
+    pub removed:      usize,
+    pub changed:      usize,
+    pub body:         Rendered,
+    pub delivered_to: Vec<String>,
+}
+
+/// Time of the last report for `risk`; `None` before the first.
+pub fn last_report_ts(conn: &Connection, risk: &str) -> rusqlite::Result<Option<i64>> {
+    if !table_exists(conn, SCAN_REPORTS_TABLE.name)? {
+        return Ok(None);
+    }
+    conn.query_row("SELECT MAX(ts) FROM scan_reports WHERE risk = ?1", [risk], |r| r.get(0))
+        .optional()
+        .map(Option::flatten)
+}
+
+/// State recorded with the last report for `risk`; empty before the first.
+pub fn load_baseline(conn: &Connection, risk: &str) -> rusqlite::Result<Snapshot> {
+    if !table_exists(conn, SCAN_BASELINES_TABLE.name)? {
+        return Ok(Snapshot::new());
+    }
+    let mut stmt = conn.prepare("SELECT path, hash, size FROM scan_baselines WHERE risk = ?1")?;
+    let rows = stmt.query_map([risk], |r| {
+        Ok((
+            PathBuf::from(r.get::<_, String>(0)?),
+            FileState {
+                hash: r.get::<_, Option<i64>>(1)?.map(|h| h as u64),
+                size: r.get::<_, Option<i64>>(2)?.map(|s| s as u64),
+            },
+        ))
+    })?;
+    rows.collect()
+}
+
+/// Stores `report` and makes `state` the baseline of its group, in one
+/// transaction. Returns the report's row id.
+pub fn store_report(conn: &Connection, report: &Report, body: &Rendered, state: &Snapshot) -> rusqlite::Result<i64> {
+    ensure_for(conn, &SCAN_REPORTS_TABLE)?;
+    ensure_for(conn, &SCAN_BASELINES_TABLE)?;
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM scan_baselines WHERE risk = ?1", [&report.risk])?;
+    {
+        let mut stmt = tx.prepare("INSERT INTO scan_baselines (risk, path, hash, size) VALUES (?1, ?2, ?3, ?4)")?;
+        for (path, s) in state {
+            stmt.execute(params![
+                &report.risk,
+                path.to_string_lossy(),
+                s.hash.map(|h| h as i64),
+                s.size.map(|v| v as i64),
+            ])?;
+        }
+    }
+    tx.execute(
+        "INSERT INTO scan_reports \
+         (ts, risk, since, baseline, added, removed, changed, subject, body_json, body_text, body_html) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            report.generated,
+            &report.risk,
+            report.since,
+            report.baseline,
+            report.added.total as i64,
+            report.removed.total as i64,
+            report.changed.total as i64,
+            &body.subject,
+            &body.json,
+            &body.text,
+            &body.html,
+        ],
+    )?;
+    let id = tx.last_insert_rowid();
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Records the channels report `id` was delivered to.
+pub fn mark_delivered(conn: &Connection, id: i64, channels: &[String]) -> rusqlite::Result<()> {
+    conn.execute("UPDATE scan_reports SET delivered_to = ?2 WHERE id = ?1", params![id, channels.join(",")])?;
+    Ok(())
+}
+
+/// Most recent reports for `risk`, newest first.
+pub fn recent_reports(conn: &Connection, risk: &str, limit: usize) -> rusqlite::Result<Vec<StoredReport>> {
+    if !table_exists(conn, SCAN_REPORTS_TABLE.name)? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT id, ts, since, baseline, added, removed, changed, subject, body_json, body_text, body_html, \
+         delivered_to FROM scan_reports WHERE risk = ?1 ORDER BY ts DESC, id DESC LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![risk, limit as i64], |r| {
+        Ok(StoredReport {
+            id:       r.get(0)?,
+            ts:       r.get(1)?,
+            risk:     risk.to_owned(),
+            since:    r.get(2)?,
+            baseline: r.get(3)?,
+            added:    r.get::<_, i64>(4)? as usize,
+            removed:  r.get::<_, i64>(5)? as usize,
+            changed:  r.get::<_, i64>(6)? as usize,
+            body: Rendered {
+                subject: r.get(7)?,
+                json:    r.get(8)?,
+                text:    r.get(9)?,
+                html:    r.get(10)?,
+            },
+            delivered_to: r
+                .get::<_, Option<String>>(11)?
+                .map(|s| s.split(',').filter(|c| !c.is_empty()).map(str::to_owned).collect())
+                .unwrap_or_default(),
+        })
+    })?;
+    rows.collect()
+}
@@ -0,0 +1,249 @@
+// src/db/schema_registry.rs
+//! Versioned table creation.
+//!
+//! Every table outside `resources/migrations` is declared as a [`TableDef`].
+//! [`CORE_TABLES`] are ensured by `init_database` on every start; the others
+//! are ensured by their owner right before the first write, so a fresh
+//! install only holds the tables of the features it actually runs.
+//!
+//! [`SchemaRegistry::ensure`] works on a dedicated connection inside a
+//! `BEGIN IMMEDIATE` transaction, which holds the database write lock while
+//! it looks at and changes the schema: concurrent callers queue on the busy
+//! timeout and the later ones find the table already there. Creations and
+//! upgrades are appended to `schema_migrations`, the version of a table being
+//! the highest recorded for it. Tables that predate the registry have no
+//! history and count as version 1.
+
+use std::{path::{Path, PathBuf}, time::Duration};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use crate::db::{
+    agent_status::AGENT_STATUS_TABLE,
+    captures::CAPTURES_TABLE,
+    event_types::{ETW_EVENTS, FS_EVENTS, NETWORK_EVENTS, PROCESS_EVENTS},
+    probe_results::PROBE_RESULTS_TABLE,
+    quarantine::QUARANTINE_TABLE,
+    reprocess::JOBS_TABLE,
+    scan_cache::SCAN_CACHE_TABLE,
+    scan_reports::{SCAN_BASELINES_TABLE, SCAN_REPORTS_TABLE},
+    snapshots::SNAPSHOTS_TABLE,
+};
+
+/// How long [`SchemaRegistry::ensure`] waits for another writer's lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const MIGRATIONS_DDL: &str = "\
+CREATE TABLE IF NOT EXISTS schema_migrations (
+    id         INTEGER PRIMARY KEY,
+    ts         INTEGER NOT NULL,
+    table_name TEXT    NOT NULL,
+    version    INTEGER NOT NULL,
+    action     TEXT    NOT NULL
+);";
+
+/// A table and its indexes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableDef {
+    pub name:     &'static str,
+    /// Version `ddl` creates; the last entry of `upgrades`, or 1.
+    pub version:  u32,
+    /// `CREATE TABLE IF NOT EXISTS` followed by `CREATE INDEX IF NOT EXISTS`
+    /// statements, at `version`.
+    pub ddl:      &'static str,
+    /// `(version, sql)` steps, ascending, that bring a table created at an
+    /// older version up to date.
+    pub upgrades: &'static [(u32, &'static str)],
+}
+
+/// Version reached by `upgrades`: the last step's, or 1 without any.
+pub const fn schema_version(upgrades: &[(u32, &str)]) -> u32 {
+    match upgrades.last() {
+        Some((version, _)) => *version,
+        None => 1,
+    }
+}
+
+/// Ensured by `init_database` on every start.
+pub const CORE_TABLES: &[&TableDef] = &[&PROCESS_EVENTS.schema, &SNAPSHOTS_TABLE, &JOBS_TABLE];
+
+/// Ensured by their owner on first use.
+pub const LAZY_TABLES: &[&TableDef] = &[
+    &FS_EVENTS.schema,
+    &NETWORK_EVENTS.schema,
+    &ETW_EVENTS.schema,
+    &PROBE_RESULTS_TABLE,
+    &AGENT_STATUS_TABLE,
+    &CAPTURES_TABLE,
+    &QUARANTINE_TABLE,
+    &SCAN_REPORTS_TABLE,
+    &SCAN_BASELINES_TABLE,
+    &SCAN_CACHE_TABLE,
+];
+
+/// What [`SchemaRegistry::ensure`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ensured {
+    /// Already at the declared version.
+    Present,
+    Created,
+    Upgraded { from: u32, to: u32 },
+}
+
+/// One row of `schema_migrations`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    pub ts:      i64,
+    pub table:   String,
+    pub version: u32,
+    /// `create`, `adopt` (found without history) or `upgrade`.
+    pub action:  String,
+}
+
+/// Schema changes of the database at `path`.
+#[derive(Debug, Clone)]
+pub struct SchemaRegistry {
+    path: PathBuf,
+}
+
+impl SchemaRegistry {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Registry of the file `conn` is open on; `None` for in-memory databases.
+    pub fn of(conn: &Connection) -> Option<Self> {
+        conn.path().filter(|p| !p.is_empty()).map(Self::new)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Creates `def` or brings it up to its declared version. Idempotent and
+    /// safe to call from several tasks at once.
+    pub fn ensure(&self, def: &TableDef) -> rusqlite::Result<Ensured> {
+        let mut conn = Connection::open(&self.path)?;
+        conn.busy_timeout(LOCK_TIMEOUT)?;
+        ensure_on(&mut conn, def)
+    }
+
+    /// Upgrades those of `defs` that exist, leaving missing ones to be
+    /// created at their current version on first use. Returns the upgraded
+    /// tables with the version they came from.
+    pub fn upgrade_existing(&self, defs: &[&TableDef]) -> rusqlite::Result<Vec<(&'static str, u32)>> {
+        let mut conn = Connection::open(&self.path)?;
+        conn.busy_timeout(LOCK_TIMEOUT)?;
+        let mut upgraded = Vec::new();
+        for def in defs {
+            if !table_exists(&conn, def.name)? {
+                continue;
+            }
+            if let Ensured::Upgraded { from, .. } = ensure_on(&mut conn, def)? {
+                upgraded.push((def.name, from));
+            }
+        }
+        Ok(upgraded)
+    }
+}
+
+/// [`SchemaRegistry::ensure`] on an open connection, which must not be in a
+/// transaction. Used directly for in-memory databases.
+pub fn ensure_on(conn: &mut Connection, def: &TableDef) -> rusqlite::Result<Ensured> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    tx.execute_batch(MIGRATIONS_DDL)?;
+
+    let outcome = if !table_exists(&tx, def.name)? {
+        tx.execute_batch(def.ddl)?;
+        record(&tx, def.name, def.version, "create")?;
+        Ensured::Created
+    } else {
+        let from = match recorded_version(&tx, def.name)? {
+            Some(v) => v,
+            None => {
+                record(&tx, def.name, 1, "adopt")?;
+                1
+            }
+        };
+        for (version, sql) in def.upgrades.iter().filter(|(v, _)| *v > from) {
+            tx.execute_batch(sql)?;
+            record(&tx, def.name, *version, "upgrade")?;
+        }
+        // Indexes missing from an older or interrupted creation.
+        tx.execute_batch(def.ddl)?;
+        if from < def.version {
+            Ensured::Upgraded { from, to: def.version }
+        } else {
+            Ensured::Present
+        }
+    };
+    tx.commit()?;
+    Ok(outcome)
+}
+
+/// Ensures `def` before a write through `conn`. A table that already exists
+/// was versioned at startup and costs one lookup.
+pub fn ensure_for(conn: &Connection, def: &TableDef) -> rusqlite::Result<Ensured> {
+    if table_exists(conn, def.name)? {
+        return Ok(Ensured::Present);
+    }
+    let outcome = match SchemaRegistry::of(conn) {
+        Some(registry) => registry.ensure(def)?,
+        None => {
+            // In-memory databases are private to `conn`: nothing to lock.
+            conn.execute_batch(MIGRATIONS_DDL)?;
+            conn.execute_batch(def.ddl)?;
+            record(conn, def.name, def.version, "create")?;
+            Ensured::Created
+        }
+    };
+    if outcome == Ensured::Created {
+        log::info!("created table {} (version {})", def.name, def.version);
+    }
+    Ok(outcome)
+}
+
+/// Tables among `defs` that exist below their declared version.
+pub fn pending_upgrades(conn: &Connection, defs: &[&TableDef]) -> rusqlite::Result<Vec<&'static str>> {
+    let history = table_exists(conn, "schema_migrations")?;
+    let mut pending = Vec::new();
+    for def in defs {
+        if !table_exists(conn, def.name)? {
+            continue;
+        }
+        let version = if history { recorded_version(conn, def.name)?.unwrap_or(1) } else { 1 };
+        if version < def.version {
+            pending.push(def.name);
+        }
+    }
+    Ok(pending)
+}
+
+/// `schema_migrations`, oldest first; empty before the first creation.
+pub fn history(conn: &Connection) -> rusqlite::Result<Vec<Migration>> {
+    if !table_exists(conn, "schema_migrations")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare("SELECT ts, table_name, version, action FROM schema_migrations ORDER BY id")?;
+    let rows = stmt.query_map([], |r| {
+        Ok(Migration { ts: r.get(0)?, table: r.get(1)?, version: r.get(2)?, action: r.get(3)? })
+    })?;
+    rows.collect()
+}
+
+pub fn table_exists(conn: &Connection, table: &str) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1", [table], |_| Ok(()))
+        .optional()
+        .map(|r| r.is_some())
+}
+
+fn recorded_version(conn: &Connection, table: &str) -> rusqlite::Result<Option<u32>> {
+    conn.query_row("SELECT MAX(version) FROM schema_migrations WHERE table_name = ?1", [table], |r| r.get(0))
+}
+
+fn record(conn: &Connection, table: &str, version: u32, action: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO schema_migrations (ts, table_name, version, action) VALUES (?1, ?2, ?3, ?4)",
+        params![chrono::Utc::now().timestamp_micros(), table, version, action],
+    )?;
+    Ok(())
+}
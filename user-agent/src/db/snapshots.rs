@@ -0,0 +1,303 @@
+// src/db/snapshots.rs
+//! Safety exports taken before risky operations.
+//!
+//! A risky operation calls [`guard`] first. When `[database.snapshots]` is
+//! enabled this exports the most recent telemetry (bounded by days and size)
+//! as JSON lines plus a provenance manifest into a timestamped directory,
+//! prunes old snapshots and, unless `skip_on_failure` is set, refuses to let
+//! the operation run when the export fails. All tables are read inside one
+//! transaction, so the export is a consistent snapshot even with writers
+//! running.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use rusqlite::{params, types::ValueRef, Connection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::config::model::SnapshotConfig;
+use crate::db::{
+    codec::decode,
+    db_writer::DbError,
+    schema_registry::{table_exists, TableDef},
+};
+
+/// Operations that call [`guard`] before touching the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiskyOp {
+    /// `purge_on_restart` deleting the file or emptying tables.
+    Purge,
+    /// Enrichment columns added by `reprocess::migrate`, or table upgrades
+    /// applied by `schema_registry`.
+    Migration,
+    /// `VACUUM` applying a new `page_size`.
+    LayoutConversion,
+}
+
+impl RiskyOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RiskyOp::Purge            => "purge",
+            RiskyOp::Migration        => "migration",
+            RiskyOp::LayoutConversion => "layout-conversion",
+        }
+    }
+}
+
+/// Tables exported, newest rows first.
+pub const EXPORTED: &[&str] = &["alerts", "process_events", "fs_events", "network_events", "etw_events"];
+
+const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("SQLite error: {0}")]
+    Sql(#[from] rusqlite::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// One exported table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedFile {
+    pub table:  String,
+    pub file:   String,
+    pub rows:   u64,
+    pub bytes:  u64,
+    /// Oldest `ts` exported (UNIX microseconds).
+    pub oldest: Option<i64>,
+    pub sha256: String,
+}
+
+/// `manifest.json` of a snapshot directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Directory name.
+    pub name:           String,
+    pub operation:      String,
+    /// UNIX microseconds.
+    pub created_at:     i64,
+    pub agent_version:  String,
+    pub sqlite_version: String,
+    pub source:         PathBuf,
+    /// Start of the requested window (UNIX microseconds).
+    pub since:          i64,
+    /// `true` when `max_mb` cut the export short of `since`.
+    pub truncated:      bool,
+    pub files:          Vec<ExportedFile>,
+}
+
+impl Manifest {
+    pub fn rows(&self) -> u64 {
+        self.files.iter().map(|f| f.rows).sum()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.bytes).sum()
+    }
+}
+
+/// Snapshot directory for `cfg` under `exe_dir`.
+pub fn snapshot_root(exe_dir: &Path, cfg: &SnapshotConfig) -> PathBuf {
+    exe_dir.join(&cfg.dir)
+}
+
+/// Takes a snapshot before `op` if enabled. Returns the manifest, or `None`
+/// when snapshots are disabled, the database does not exist yet, or the
+/// snapshot failed and `skip_on_failure` allows going on.
+pub fn guard(db: &Path, root: &Path, cfg: &SnapshotConfig, op: RiskyOp) -> Result<Option<Manifest>, DbError> {
+    if !cfg.enabled || !db.exists() {
+        return Ok(None);
+    }
+    match before(db, root, cfg, op) {
+        Ok(m) => {
+            log::info!("safety snapshot {} taken before {} ({} rows)", m.name, op.as_str(), m.rows());
+            Ok(Some(m))
+        }
+        Err(e) if cfg.skip_on_failure => {
+            log::warn!("safety snapshot before {} failed, continuing: {}", op.as_str(), e);
+            Ok(None)
+        }
+        Err(e) => Err(DbError::Snapshot(op.as_str(), e.to_string())),
+    }
+}
+
+/// Exports the window configured in `cfg` from `db` into a new directory
+/// under `root` and prunes old snapshots.
+pub fn before(db: &Path, root: &Path, cfg: &SnapshotConfig, op: RiskyOp) -> Result<Manifest, SnapshotError> {
+    let now = chrono::Utc::now();
+    let name = format!("{}-{}", now.format("%Y%m%dT%H%M%S%6fZ"), op.as_str());
+    let since = now.timestamp_micros() - cfg.max_days as i64 * 86_400_000_000;
+
+    fs::create_dir_all(root)?;
+    // Written under a temporary name so a crash never leaves a directory
+    // that looks complete.
+    let partial = root.join(format!(".{name}.partial"));
+    let _ = fs::remove_dir_all(&partial);
+    fs::create_dir(&partial)?;
+
+    let conn = Connection::open(db)?;
+    conn.busy_timeout(std::time::Duration::from_millis(1_000))?;
+    let mut manifest = Manifest {
+        name:           name.clone(),
+        operation:      op.as_str().into(),
+        created_at:     now.timestamp_micros(),
+        agent_version:  env!("CARGO_PKG_VERSION").into(),
+        sqlite_version: rusqlite::version().into(),
+        source:         db.to_path_buf(),
+        since,
+        truncated:      false,
+        files:          Vec::new(),
+    };
+
+    let tx = conn.unchecked_transaction()?;
+    let tables: Vec<&str> = EXPORTED.iter().copied().filter(|t| table_exists(&tx, t).unwrap_or(false)).collect();
+    let mut budget = cfg.max_mb * 1024 * 1024;
+    for (i, table) in tables.iter().enumerate() {
+        // Unused share of earlier tables is passed on to later ones.
+        let share = budget / (tables.len() - i) as u64;
+        let (file, cut) = export_table(&tx, table, since, share, &partial)?;
+        budget -= file.bytes;
+        manifest.truncated |= cut;
+        manifest.files.push(file);
+    }
+    tx.finish()?;
+
+    fs::write(partial.join(MANIFEST), serde_json::to_vec_pretty(&manifest)?)?;
+    fs::rename(&partial, root.join(&name))?;
+    prune(root, cfg.keep)?;
+    Ok(manifest)
+}
+
+/// Writes rows of `table` with `ts >= since`, newest first, until `budget`
+/// bytes. Returns the file entry and whether the budget cut it short.
+fn export_table(
+    conn: &Connection,
+    table: &str,
+    since: i64,
+    budget: u64,
+    dir: &Path,
+) -> Result<(ExportedFile, bool), SnapshotError> {
+    let file_name = format!("{table}.jsonl");
+    let mut out = BufWriter::new(File::create(dir.join(&file_name))?);
+    let mut hasher = Sha256::new();
+    let mut entry = ExportedFile {
+        table:  table.into(),
+        file:   file_name,
+        rows:   0,
+        bytes:  0,
+        oldest: None,
+        sha256: String::new(),
+    };
+    let mut cut = false;
+
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {table} WHERE ts >= ?1 ORDER BY ts DESC, id DESC"))?;
+    let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let mut rows = stmt.query(params![since])?;
+    while let Some(row) = rows.next()? {
+        let mut obj = serde_json::Map::with_capacity(names.len());
+        for (i, name) in names.iter().enumerate() {
+            obj.insert(name.clone(), to_json(row.get_ref(i)?));
+        }
+        let mut line = serde_json::to_vec(&obj)?;
+        line.push(b'\n');
+        if entry.bytes + line.len() as u64 > budget {
+            cut = true;
+            break;
+        }
+        out.write_all(&line)?;
+        hasher.update(&line);
+        entry.bytes += line.len() as u64;
+        entry.rows += 1;
+        entry.oldest = obj.get("ts").and_then(|v| v.as_i64());
+    }
+    out.flush()?;
+    entry.sha256 = hex::encode(hasher.finalize());
+    Ok((entry, cut))
+}
+
+/// Compressed text is exported decoded; other BLOBs as hex.
+fn to_json(value: ValueRef<'_>) -> serde_json::Value {
+    match value {
+        ValueRef::Null       => serde_json::Value::Null,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f)    => f.into(),
+        ValueRef::Text(t)    => String::from_utf8_lossy(t).into(),
+        ValueRef::Blob(b)    => decode(b).unwrap_or_else(|_| hex::encode(b)).into(),
+    }
+}
+
+/// Removes the oldest snapshots beyond `keep`. Only directories holding a
+/// manifest are considered.
+pub fn prune(root: &Path, keep: usize) -> io::Result<Vec<String>> {
+    let mut names: Vec<String> = list(root)?.into_iter().map(|m| m.name).collect();
+    names.sort();
+    let excess = names.len().saturating_sub(keep);
+    for name in &names[..excess] {
+        fs::remove_dir_all(root.join(name))?;
+    }
+    Ok(names.drain(..excess).collect())
+}
+
+/// Manifests of the snapshots under `root`, newest first.
+pub fn list(root: &Path) -> io::Result<Vec<Manifest>> {
+    let mut out = Vec::new();
+    let entries = match fs::read_dir(root) {
+        Ok(e) => e,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(out),
+        Err(e) => return Err(e),
+    };
+    for entry in entries.flatten() {
+        if let Ok(m) = read_manifest(&entry.path()) {
+            out.push(m);
+        }
+    }
+    out.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(out)
+}
+
+pub fn read_manifest(dir: &Path) -> Result<Manifest, SnapshotError> {
+    Ok(serde_json::from_slice(&fs::read(dir.join(MANIFEST))?)?)
+}
+
+/// Records a snapshot in `safety_snapshots`. Called once the operation's
+/// database is open, so snapshots taken before a purge are recorded in the
+/// new file.
+pub fn record(conn: &Connection, root: &Path, m: &Manifest) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO safety_snapshots (ts, operation, dir, rows, bytes, truncated) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            m.created_at,
+            &m.operation,
+            root.join(&m.name).to_string_lossy(),
+            m.rows() as i64,
+            m.bytes() as i64,
+            m.truncated,
+        ],
+    )?;
+    Ok(())
+}
+
+/// `dir` holds the JSON lines and `manifest.json`.
+pub const SNAPSHOTS_TABLE: TableDef = TableDef {
+    name:     "safety_snapshots",
+    version:  1,
+    ddl: "\
+CREATE TABLE IF NOT EXISTS safety_snapshots (
+    id        INTEGER PRIMARY KEY,
+    ts        INTEGER NOT NULL,
+    operation TEXT    NOT NULL,
+    dir       TEXT    NOT NULL,
+    rows      INTEGER NOT NULL,
+    bytes     INTEGER NOT NULL,
+    truncated BOOLEAN NOT NULL
+);",
+    upgrades: &[],
+};
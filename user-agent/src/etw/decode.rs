@@ -0,0 +1,317 @@
+// src/etw/decode.rs
+//! Event properties as JSON.
+//!
+//! TDH describes the top-level properties of an event (`TRACE_EVENT_INFO`):
+//! name, input type, and a length and count that are either fixed or taken
+//! from an earlier property. [`render`] walks the user data with that
+//! description. Nested structures and types it does not know end the walk;
+//! the bytes left are reported as `_undecoded`.
+
+use std::{fmt, str::FromStr};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde_json::{json, Map, Value};
+
+/// `TDH_INTYPE` values understood by [`render`].
+pub mod in_type {
+    pub const UNICODE_STRING: u16 = 1;
+    pub const ANSI_STRING: u16 = 2;
+    pub const INT8: u16 = 3;
+    pub const UINT8: u16 = 4;
+    pub const INT16: u16 = 5;
+    pub const UINT16: u16 = 6;
+    pub const INT32: u16 = 7;
+    pub const UINT32: u16 = 8;
+    pub const INT64: u16 = 9;
+    pub const UINT64: u16 = 10;
+    pub const FLOAT: u16 = 11;
+    pub const DOUBLE: u16 = 12;
+    pub const BOOLEAN: u16 = 13;
+    pub const BINARY: u16 = 14;
+    pub const GUID: u16 = 15;
+    pub const POINTER: u16 = 16;
+    pub const FILETIME: u16 = 17;
+    pub const SYSTEMTIME: u16 = 18;
+    pub const SID: u16 = 19;
+    pub const HEXINT32: u16 = 20;
+    pub const HEXINT64: u16 = 21;
+}
+
+/// `EVENT_PROPERTY_INFO.Flags`.
+const PROPERTY_STRUCT: u32 = 0x1;
+const PROPERTY_PARAM_LENGTH: u32 = 0x2;
+const PROPERTY_PARAM_COUNT: u32 = 0x4;
+const PROPERTY_PARAM_FIXED_COUNT: u32 = 0x20;
+
+/// FILETIME ticks (100 ns) between 1601-01-01 and the UNIX epoch.
+const FILETIME_UNIX_EPOCH: i64 = 116_444_736_000_000_000;
+
+/// Provider or activity GUID, laid out as the Win32 `GUID`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Guid {
+    pub data1: u32,
+    pub data2: u16,
+    pub data3: u16,
+    pub data4: [u8; 8],
+}
+
+impl Guid {
+    pub fn from_bytes(b: &[u8; 16]) -> Self {
+        let mut data4 = [0; 8];
+        data4.copy_from_slice(&b[8..]);
+        Self {
+            data1: u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            data2: u16::from_le_bytes([b[4], b[5]]),
+            data3: u16::from_le_bytes([b[6], b[7]]),
+            data4,
+        }
+    }
+}
+
+impl FromStr for Guid {
+    type Err = String;
+
+    /// `22fb2cd6-0e7b-422b-a0c7-2fad1fd0e716`, with or without braces.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let bad = || format!("'{s}' is not a GUID");
+        let inner = s.trim().trim_start_matches('{').trim_end_matches('}');
+        let parts: Vec<&str> = inner.split('-').collect();
+        let lens = [8, 4, 4, 4, 12];
+        if parts.len() != 5 || parts.iter().zip(lens).any(|(p, n)| p.len() != n || !p.bytes().all(|b| b.is_ascii_hexdigit())) {
+            return Err(bad());
+        }
+        let tail = format!("{}{}", parts[3], parts[4]);
+        let mut data4 = [0; 8];
+        for (i, byte) in data4.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&tail[i * 2..i * 2 + 2], 16).map_err(|_| bad())?;
+        }
+        Ok(Self {
+            data1: u32::from_str_radix(parts[0], 16).map_err(|_| bad())?,
+            data2: u16::from_str_radix(parts[1], 16).map_err(|_| bad())?,
+            data3: u16::from_str_radix(parts[2], 16).map_err(|_| bad())?,
+            data4,
+        })
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let d = &self.data4;
+        write!(
+            f,
+            "{{{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}}}",
+            self.data1, self.data2, self.data3, d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7]
+        )
+    }
+}
+
+/// Length or element count of a property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Size {
+    Fixed(u16),
+    /// The value of the property at this index.
+    FromProperty(u16),
+}
+
+/// One top-level property of an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    pub name:    String,
+    /// A [`in_type`] value; `None` for nested structures.
+    pub in_type: Option<u16>,
+    /// Bytes for binary data, characters for strings; `Fixed(0)` is the
+    /// natural size of the type, or up to the terminator for strings.
+    pub length:  Size,
+    /// `None` for a single value, otherwise the elements of an array.
+    pub count:   Option<Size>,
+}
+
+impl Property {
+    /// Single value of `in_type`.
+    pub fn scalar(name: &str, in_type: u16) -> Self {
+        Self { name: name.into(), in_type: Some(in_type), length: Size::Fixed(0), count: None }
+    }
+}
+
+/// The top-level properties of a `TRACE_EVENT_INFO` returned by
+/// `TdhGetEventInformation`, or `None` if `info` is cut short.
+pub fn parse_event_info(info: &[u8]) -> Option<Vec<Property>> {
+    let u16_at = |off: usize| info.get(off..off + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let u32_at = |off: usize| info.get(off..off + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    // TopLevelPropertyCount, then EventPropertyInfoArray.
+    let count = u32_at(104)? as usize;
+    (0..count)
+        .map(|i| {
+            let base = 112 + i * 24;
+            let flags = u32_at(base)?;
+            let name = utf16_until_nul(info.get(u32_at(base + 4)? as usize..)?);
+            let (count_or_index, length_or_index) = (u16_at(base + 16)?, u16_at(base + 18)?);
+            let length = if flags & PROPERTY_PARAM_LENGTH != 0 {
+                Size::FromProperty(length_or_index)
+            } else {
+                Size::Fixed(length_or_index)
+            };
+            let count = if flags & PROPERTY_PARAM_COUNT != 0 {
+                Some(Size::FromProperty(count_or_index))
+            } else if count_or_index != 1 || flags & PROPERTY_PARAM_FIXED_COUNT != 0 {
+                Some(Size::Fixed(count_or_index))
+            } else {
+                None
+            };
+            let in_type = (flags & PROPERTY_STRUCT == 0).then(|| u16_at(base + 8)).flatten();
+            Some(Property { name, in_type, length, count })
+        })
+        .collect()
+}
+
+fn utf16_until_nul(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&u| u != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// `FILETIME` ticks as a UTC time; `None` when out of range.
+pub fn filetime(ticks: i64) -> Option<DateTime<Utc>> {
+    let since_epoch = ticks.checked_sub(FILETIME_UNIX_EPOCH)?;
+    Utc.timestamp_opt(since_epoch.div_euclid(10_000_000), (since_epoch.rem_euclid(10_000_000) * 100) as u32).single()
+}
+
+/// Reads values off the user data.
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos:  usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N).map(|b| b.try_into().expect("N bytes taken"))
+    }
+
+    fn left(&self) -> usize {
+        self.data.len() - self.pos
+    }
+}
+
+/// The properties of one event as a JSON object, from its user data.
+/// `pointer_size` is 4 or 8, after the process that logged the event.
+pub fn render(props: &[Property], data: &[u8], pointer_size: usize) -> String {
+    let mut out = Map::new();
+    // Integer value of each property, for lengths and counts given by one.
+    let mut ints: Vec<Option<u64>> = Vec::with_capacity(props.len());
+    let mut cur = Cursor { data, pos: 0 };
+    for prop in props {
+        let resolve = |size: Size| match size {
+            Size::Fixed(n) => Some(n as usize),
+            Size::FromProperty(i) => ints.get(i as usize).copied().flatten().map(|n| n as usize),
+        };
+        let (Some(in_type), Some(length)) = (prop.in_type, resolve(prop.length)) else { break };
+        let start = cur.pos;
+        let value = match prop.count {
+            None => value(&mut cur, in_type, length, pointer_size),
+            Some(count) => resolve(count).and_then(|n| {
+                (0..n).map(|_| value(&mut cur, in_type, length, pointer_size)).collect::<Option<Vec<_>>>().map(Value::Array)
+            }),
+        };
+        let Some(value) = value else {
+            // Part of a value is no value: its bytes count as undecoded.
+            cur.pos = start;
+            break;
+        };
+        ints.push(value.as_u64());
+        out.insert(prop.name.clone(), value);
+    }
+    if cur.left() > 0 {
+        out.insert("_undecoded".into(), json!(cur.left()));
+    }
+    Value::Object(out).to_string()
+}
+
+/// One value of `in_type`; `None` if the data runs out or the type is not
+/// known.
+fn value(cur: &mut Cursor<'_>, in_type: u16, length: usize, pointer_size: usize) -> Option<Value> {
+    use in_type::*;
+    Some(match in_type {
+        UNICODE_STRING => {
+            let units: Vec<u16> = if length > 0 {
+                cur.take(length * 2)?.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect()
+            } else {
+                let mut units = Vec::new();
+                loop {
+                    match cur.array::<2>() {
+                        Some(u) if u16::from_le_bytes(u) != 0 => units.push(u16::from_le_bytes(u)),
+                        Some(_) => break,
+                        None if units.is_empty() => return None,
+                        None => break,
+                    }
+                }
+                units
+            };
+            let end = units.iter().position(|&u| u == 0).unwrap_or(units.len());
+            json!(String::from_utf16_lossy(&units[..end]))
+        }
+        ANSI_STRING => {
+            let bytes = if length > 0 {
+                cur.take(length)?
+            } else {
+                let rest = cur.data.get(cur.pos..)?;
+                let n = rest.iter().position(|&b| b == 0).map_or(rest.len(), |n| n + 1);
+                if n == 0 {
+                    return None;
+                }
+                cur.take(n)?
+            };
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            json!(String::from_utf8_lossy(&bytes[..end]))
+        }
+        INT8 => json!(i8::from_le_bytes(cur.array()?)),
+        UINT8 => json!(u8::from_le_bytes(cur.array()?)),
+        INT16 => json!(i16::from_le_bytes(cur.array()?)),
+        UINT16 => json!(u16::from_le_bytes(cur.array()?)),
+        INT32 => json!(i32::from_le_bytes(cur.array()?)),
+        UINT32 => json!(u32::from_le_bytes(cur.array()?)),
+        INT64 => json!(i64::from_le_bytes(cur.array()?)),
+        UINT64 => json!(u64::from_le_bytes(cur.array()?)),
+        FLOAT => json!(f32::from_le_bytes(cur.array()?)),
+        DOUBLE => json!(f64::from_le_bytes(cur.array()?)),
+        BOOLEAN => json!(u32::from_le_bytes(cur.array()?) != 0),
+        BINARY => json!(hex::encode(cur.take(length)?)),
+        GUID => json!(Guid::from_bytes(&cur.array()?).to_string()),
+        POINTER => match pointer_size {
+            4 => json!(format!("0x{:x}", u32::from_le_bytes(cur.array()?))),
+            _ => json!(format!("0x{:x}", u64::from_le_bytes(cur.array()?))),
+        },
+        FILETIME => {
+            let ticks = i64::from_le_bytes(cur.array()?);
+            filetime(ticks).map_or(json!(ticks), |t| json!(t.to_rfc3339()))
+        }
+        SYSTEMTIME => {
+            let b: [u8; 16] = cur.array()?;
+            let f = |i: usize| u16::from_le_bytes([b[i * 2], b[i * 2 + 1]]) as u32;
+            // Year, month, day of week, day, hour, minute, second, ms.
+            NaiveDate::from_ymd_opt(f(0) as i32, f(1), f(3))
+                .and_then(|d| d.and_hms_milli_opt(f(4), f(5), f(6), f(7)))
+                .map_or(Value::Null, |t| json!(t.and_utc().to_rfc3339()))
+        }
+        SID => {
+            let head: [u8; 8] = cur.array()?;
+            let authority = head[2..8].iter().fold(0u64, |acc, &b| acc << 8 | b as u64);
+            let mut sid = format!("S-{}-{}", head[0], authority);
+            for _ in 0..head[1] {
+                sid.push_str(&format!("-{}", u32::from_le_bytes(cur.array()?)));
+            }
+            json!(sid)
+        }
+        HEXINT32 => json!(format!("0x{:x}", u32::from_le_bytes(cur.array()?))),
+        HEXINT64 => json!(format!("0x{:x}", u64::from_le_bytes(cur.array()?))),
+        _ => return None,
+    })
+}
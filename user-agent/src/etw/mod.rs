@@ -0,0 +1,123 @@
+// src/etw/mod.rs
+//! User-mode ETW consumer: a real-time trace session over the providers of
+//! `[[etw.providers]]`, published as [`EtwEvent`]s on the ETW buses.
+//!
+//! Starting a session needs administrator rights; without them, or off
+//! Windows, the listener logs a warning, marks the `Etw` component degraded
+//! when it reports to a health table, and ends; the agent runs without ETW
+//! events. A session of the same name left by an earlier run is stopped
+//! and started again.
+
+pub mod decode;
+mod sessions;
+
+use std::{sync::Arc, time::{Instant, SystemTime}};
+use async_trait::async_trait;
+use metrics::counter;
+use shared::events::EtwEvent;
+use tokio::{sync::mpsc::{self, error::TrySendError}, task};
+
+pub use decode::{Guid, Property, Size};
+
+use crate::comms::{listeners::Listener, WrappedEvent};
+use crate::config::model::EtwConfig;
+use crate::health::{Component, HealthRegistry};
+use crate::status::AgentStats;
+use crate::util::Shutdown;
+
+/// One event as delivered by the session, before decoding.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub provider:     Guid,
+    pub event_id:     u16,
+    pub level:        u8,
+    pub pid:          u32,
+    pub tid:          u32,
+    /// `FILETIME` ticks.
+    pub timestamp:    i64,
+    /// 4 for events logged by 32-bit processes.
+    pub pointer_size: usize,
+    /// As described by TDH; empty when the event has no schema.
+    pub properties:   Arc<[Property]>,
+    pub data:         Vec<u8>,
+}
+
+impl Record {
+    pub fn to_event(&self) -> EtwEvent {
+        EtwEvent {
+            provider_guid: self.provider.to_string(),
+            event_id:      self.event_id as u32,
+            level:         self.level as u32,
+            pid:           self.pid,
+            tid:           self.tid,
+            json_payload:  decode::render(&self.properties, &self.data, self.pointer_size),
+        }
+    }
+
+    pub fn time(&self) -> SystemTime {
+        decode::filetime(self.timestamp).map_or_else(SystemTime::now, SystemTime::from)
+    }
+}
+
+/// Publishes the events of the `[etw]` session.
+pub struct EtwListener {
+    cfg:         EtwConfig,
+    sensor_guid: String,
+    health:      Option<HealthRegistry>,
+}
+
+impl EtwListener {
+    pub fn new(cfg: EtwConfig, sensor_guid: impl Into<String>) -> Self {
+        Self { cfg, sensor_guid: sensor_guid.into(), health: None }
+    }
+
+    /// Marks [`Component::Etw`] degraded in `health` when the session
+    /// cannot be started.
+    pub fn reporting(mut self, health: HealthRegistry) -> Self {
+        self.health = Some(health);
+        self
+    }
+}
+
+#[async_trait]
+impl Listener<EtwEvent> for EtwListener {
+    fn name(&self) -> &'static str {
+        "etw"
+    }
+
+    async fn ingest(self: Arc<Self>, tx: mpsc::Sender<WrappedEvent<EtwEvent>>, shutdown: Shutdown) {
+        let this = self.clone();
+        // ProcessTrace blocks until the session stops.
+        let run = task::spawn_blocking(move || {
+            let sink = |record: Record| {
+                counter!("events_received_total", "type" => "etw").increment(1);
+                AgentStats::global().record_events("etw", 1, Instant::now());
+                let wrapped = WrappedEvent {
+                    ts:          record.time().into(),
+                    sensor_guid: this.sensor_guid.clone(),
+                    payload:     record.to_event(),
+                    ring_pos:    None,
+                    seq:         None,
+                    enrichment:  None,
+                    coalesced:   None,
+                };
+                // Never stall the session: a full channel drops the event.
+                match tx.try_send(wrapped) {
+                    Ok(()) | Err(TrySendError::Closed(_)) => {}
+                    Err(TrySendError::Full(_)) => counter!("etw_events_dropped_total").increment(1),
+                }
+            };
+            sessions::run(&this.cfg, sink, &shutdown)
+        });
+        match run.await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                log::warn!("ETW session '{}' unavailable, ETW events are not recorded: {}", self.cfg.session, e);
+                if let Some(health) = &self.health {
+                    health.mark_degraded(Component::Etw, e.to_string());
+                }
+            }
+            Err(e) => log::error!("ETW session '{}' failed: {}", self.cfg.session, e),
+        }
+    }
+}
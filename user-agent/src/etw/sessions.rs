@@ -1,11 +1,388 @@
-//! ETW consumer for real-time system event monitoring.
+// src/etw/sessions.rs
+//! The trace session: `StartTraceW` in real-time mode, `EnableTraceEx2` for
+//! each provider, then `OpenTraceW` and `ProcessTrace` on the calling thread
+//! until the session is stopped. A helper thread stops it on shutdown.
 //!
-//! This module sets up and runs an Event Tracing for Windows (ETW) session,
-//! subscribing to selected system providers to receive process, network,
-//! and image load events.
-//!
-//! Key responsibilities:
-//! - Subscribe to ETW providers of interest.
-//! - Parse and dispatch received events to the detection engine.
-//! - Run within a shared thread pool for efficiency.
-//! - Handle buffer overruns and session restarts if needed.
+//! Event schemas come from `TdhGetEventInformation`, cached per provider,
+//! event id and version. Off Windows [`run`] fails with `Unsupported`.
+
+use std::io;
+
+use super::Record;
+use crate::config::model::EtwConfig;
+use crate::util::Shutdown;
+
+/// Runs the session of `cfg` until `shutdown`, passing each event to `sink`.
+/// Fails if the session cannot be started or no provider can be enabled.
+pub fn run(cfg: &EtwConfig, sink: impl FnMut(Record), shutdown: &Shutdown) -> io::Result<()> {
+    sys::run(cfg, sink, shutdown)
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use std::io;
+    use super::{EtwConfig, Record, Shutdown};
+
+    pub fn run(_cfg: &EtwConfig, _sink: impl FnMut(Record), _shutdown: &Shutdown) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "ETW is only available on Windows"))
+    }
+}
+
+/// Layouts are those of 64-bit Windows.
+#[cfg(windows)]
+mod sys {
+    use std::{
+        collections::HashMap,
+        ffi::c_void,
+        io,
+        iter::once,
+        mem::{size_of, zeroed},
+        ptr, slice,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+    use super::{EtwConfig, Record, Shutdown};
+    use crate::etw::decode::{self, in_type, Guid, Property};
+
+    const ERROR_SUCCESS: u32 = 0;
+    const ERROR_INSUFFICIENT_BUFFER: u32 = 122;
+    const ERROR_ALREADY_EXISTS: u32 = 183;
+    /// What `ProcessTrace` returns once the session is stopped.
+    const ERROR_CANCELLED: u32 = 1223;
+
+    const WNODE_FLAG_TRACED_GUID: u32 = 0x0002_0000;
+    const EVENT_TRACE_REAL_TIME_MODE: u32 = 0x0000_0100;
+    const EVENT_TRACE_CONTROL_STOP: u32 = 1;
+    const EVENT_CONTROL_CODE_ENABLE_PROVIDER: u32 = 1;
+    const PROCESS_TRACE_MODE_REAL_TIME: u32 = 0x0000_0100;
+    const PROCESS_TRACE_MODE_EVENT_RECORD: u32 = 0x1000_0000;
+    const INVALID_PROCESSTRACE_HANDLE: u64 = u64::MAX;
+    const EVENT_HEADER_FLAG_STRING_ONLY: u16 = 0x0004;
+    const EVENT_HEADER_FLAG_32_BIT_HEADER: u16 = 0x0020;
+    /// Room for the session name, in UTF-16 units with the terminator.
+    const MAX_NAME: usize = 1024;
+
+    #[repr(C)]
+    struct WnodeHeader {
+        buffer_size:        u32,
+        provider_id:        u32,
+        historical_context: u64,
+        time_stamp:         i64,
+        guid:               Guid,
+        client_context:     u32,
+        flags:              u32,
+    }
+
+    /// `EVENT_TRACE_PROPERTIES`.
+    #[repr(C)]
+    struct TraceProperties {
+        wnode:                  WnodeHeader,
+        buffer_size:            u32,
+        minimum_buffers:        u32,
+        maximum_buffers:        u32,
+        maximum_file_size:      u32,
+        log_file_mode:          u32,
+        flush_timer:            u32,
+        enable_flags:           u32,
+        age_limit:              i32,
+        number_of_buffers:      u32,
+        free_buffers:           u32,
+        events_lost:            u32,
+        buffers_written:        u32,
+        log_buffers_lost:       u32,
+        real_time_buffers_lost: u32,
+        logger_thread_id:       *mut c_void,
+        log_file_name_offset:   u32,
+        logger_name_offset:     u32,
+    }
+
+    /// The properties followed by the room ETW writes the session name to.
+    #[repr(C)]
+    struct PropertiesBuffer {
+        props: TraceProperties,
+        name:  [u16; MAX_NAME],
+    }
+
+    impl PropertiesBuffer {
+        fn new() -> Box<Self> {
+            // SAFETY: all zeroes is a valid value of these C structs.
+            let mut buf: Box<Self> = Box::new(unsafe { zeroed() });
+            buf.props.wnode.buffer_size = size_of::<Self>() as u32;
+            buf.props.wnode.flags = WNODE_FLAG_TRACED_GUID;
+            // QueryPerformanceCounter timestamps, converted on delivery.
+            buf.props.wnode.client_context = 1;
+            buf.props.log_file_mode = EVENT_TRACE_REAL_TIME_MODE;
+            buf.props.logger_name_offset = size_of::<TraceProperties>() as u32;
+            buf
+        }
+    }
+
+    /// `EVENT_TRACE_LOGFILEW`; the members the consumer does not read are
+    /// opaque.
+    #[repr(C)]
+    struct EventTraceLogfile {
+        log_file_name:         *mut u16,
+        logger_name:           *mut u16,
+        current_time:          i64,
+        buffers_read:          u32,
+        process_trace_mode:    u32,
+        /// `EVENT_TRACE`.
+        current_event:         [u64; 11],
+        /// `TRACE_LOGFILE_HEADER`.
+        logfile_header:        [u64; 35],
+        buffer_callback:       *const c_void,
+        buffer_size:           u32,
+        filled:                u32,
+        events_lost:           u32,
+        event_record_callback: Option<unsafe extern "system" fn(*const EventRecord)>,
+        is_kernel_trace:       u32,
+        context:               *mut c_void,
+    }
+
+    #[repr(C)]
+    struct EventDescriptor {
+        id:      u16,
+        version: u8,
+        channel: u8,
+        level:   u8,
+        opcode:  u8,
+        task:    u16,
+        keyword: u64,
+    }
+
+    #[repr(C)]
+    struct EventHeader {
+        size:           u16,
+        header_type:    u16,
+        flags:          u16,
+        event_property: u16,
+        thread_id:      u32,
+        process_id:     u32,
+        /// FILETIME once delivered.
+        time_stamp:     i64,
+        provider_id:    Guid,
+        descriptor:     EventDescriptor,
+        processor_time: u64,
+        activity_id:    Guid,
+    }
+
+    /// `EVENT_RECORD`.
+    #[repr(C)]
+    struct EventRecord {
+        header:              EventHeader,
+        buffer_context:      u32,
+        extended_data_count: u16,
+        user_data_length:    u16,
+        extended_data:       *const c_void,
+        user_data:           *const u8,
+        user_context:        *mut c_void,
+    }
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        fn StartTraceW(handle: *mut u64, name: *const u16, props: *mut TraceProperties) -> u32;
+        fn ControlTraceW(handle: u64, name: *const u16, props: *mut TraceProperties, code: u32) -> u32;
+        fn EnableTraceEx2(
+            handle: u64,
+            provider: *const Guid,
+            code: u32,
+            level: u8,
+            match_any: u64,
+            match_all: u64,
+            timeout: u32,
+            params: *const c_void,
+        ) -> u32;
+        fn OpenTraceW(logfile: *mut EventTraceLogfile) -> u64;
+        fn ProcessTrace(handles: *const u64, count: u32, start: *const c_void, end: *const c_void) -> u32;
+        fn CloseTrace(handle: u64) -> u32;
+    }
+
+    #[link(name = "tdh")]
+    unsafe extern "system" {
+        fn TdhGetEventInformation(
+            record: *const EventRecord,
+            context_count: u32,
+            context: *const c_void,
+            info: *mut u8,
+            size: *mut u32,
+        ) -> u32;
+    }
+
+    fn os_error(status: u32) -> io::Error {
+        io::Error::from_raw_os_error(status as i32)
+    }
+
+    /// What the callback reaches through `EVENT_RECORD.UserContext`.
+    struct Context<'a> {
+        sink:    &'a mut dyn FnMut(Record),
+        schemas: HashMap<(Guid, u16, u8), Arc<[Property]>>,
+        message: Arc<[Property]>,
+    }
+
+    pub fn run(cfg: &EtwConfig, mut sink: impl FnMut(Record), shutdown: &Shutdown) -> io::Result<()> {
+        if cfg.session.len() >= MAX_NAME {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "session name too long"));
+        }
+        let name: Vec<u16> = cfg.session.encode_utf16().chain(once(0)).collect();
+        let control = start(&name, &cfg.session)?;
+
+        let mut enabled = 0;
+        for provider in &cfg.providers {
+            // Checked by the config loader.
+            let Ok(guid) = provider.guid.parse::<Guid>() else { continue };
+            // SAFETY: `guid` outlives the call; no enable parameters.
+            let status = unsafe {
+                EnableTraceEx2(
+                    control,
+                    &guid,
+                    EVENT_CONTROL_CODE_ENABLE_PROVIDER,
+                    provider.level,
+                    provider.keywords,
+                    0,
+                    0,
+                    ptr::null(),
+                )
+            };
+            match status {
+                ERROR_SUCCESS => enabled += 1,
+                s => log::warn!("ETW provider {} not enabled: {}", provider.guid, os_error(s)),
+            }
+        }
+        if enabled == 0 {
+            stop(&name);
+            return Err(io::Error::other("no provider could be enabled"));
+        }
+        log::info!("ETW session '{}' started with {} of {} providers", cfg.session, enabled, cfg.providers.len());
+
+        let mut ctx = Context {
+            sink:    &mut sink,
+            schemas: HashMap::new(),
+            message: Arc::from([Property::scalar("message", in_type::UNICODE_STRING)]),
+        };
+        // SAFETY: all zeroes is a valid value of this C struct.
+        let mut logfile: EventTraceLogfile = unsafe { zeroed() };
+        logfile.logger_name = name.as_ptr().cast_mut();
+        logfile.process_trace_mode = PROCESS_TRACE_MODE_REAL_TIME | PROCESS_TRACE_MODE_EVENT_RECORD;
+        logfile.event_record_callback = Some(on_event);
+        logfile.context = (&mut ctx as *mut Context<'_>).cast();
+        // SAFETY: `logfile` and the name it points to outlive the trace.
+        let trace = unsafe { OpenTraceW(&mut logfile) };
+        if trace == INVALID_PROCESSTRACE_HANDLE {
+            let e = io::Error::last_os_error();
+            stop(&name);
+            return Err(e);
+        }
+
+        // Stopping the session ends ProcessTrace.
+        let done = Arc::new(AtomicBool::new(false));
+        let stopper = {
+            let (done, shutdown, name) = (done.clone(), shutdown.clone(), name.clone());
+            thread::spawn(move || {
+                while !shutdown.wait_timeout(Duration::from_millis(500)) {
+                    if done.load(Ordering::Relaxed) {
+                        return;
+                    }
+                }
+                stop(&name);
+            })
+        };
+        // SAFETY: one valid handle; events are delivered on this thread while
+        // `ctx` is alive.
+        let status = unsafe { ProcessTrace(&trace, 1, ptr::null(), ptr::null()) };
+        done.store(true, Ordering::Relaxed);
+        // SAFETY: handle from OpenTraceW, closed once.
+        unsafe { CloseTrace(trace) };
+        // Stopped from outside or failed: the session may still exist.
+        stop(&name);
+        let _ = stopper.join();
+        match status {
+            ERROR_SUCCESS | ERROR_CANCELLED => {
+                log::info!("ETW session '{}' stopped", cfg.session);
+                Ok(())
+            }
+            s => Err(os_error(s)),
+        }
+    }
+
+    /// Starts the session `name`, replacing one left with that name.
+    fn start(name: &[u16], display: &str) -> io::Result<u64> {
+        let mut restarted = false;
+        loop {
+            let mut props = PropertiesBuffer::new();
+            let mut handle = 0;
+            // SAFETY: `name` is NUL-terminated; `props` has room for it.
+            let status = unsafe { StartTraceW(&mut handle, name.as_ptr(), &mut props.props) };
+            match status {
+                ERROR_SUCCESS => return Ok(handle),
+                ERROR_ALREADY_EXISTS if !restarted => {
+                    log::warn!("ETW session '{}' already exists; restarting it", display);
+                    stop(name);
+                    restarted = true;
+                }
+                s => return Err(os_error(s)),
+            }
+        }
+    }
+
+    /// Stops the session `name`; nothing happens if there is none.
+    fn stop(name: &[u16]) {
+        let mut props = PropertiesBuffer::new();
+        // SAFETY: as in `start`.
+        unsafe { ControlTraceW(0, name.as_ptr(), &mut props.props, EVENT_TRACE_CONTROL_STOP) };
+    }
+
+    unsafe extern "system" fn on_event(record: *const EventRecord) {
+        // SAFETY: ETW passes a record valid for this call, whose context is
+        // the `Context` that `run` keeps alive around ProcessTrace.
+        let (record, ctx) = unsafe { (&*record, &mut *(*record).user_context.cast::<Context<'_>>()) };
+        let h = &record.header;
+        let data = match record.user_data.is_null() {
+            true => &[][..],
+            // SAFETY: `user_data_length` bytes at `user_data`, for this call.
+            false => unsafe { slice::from_raw_parts(record.user_data, record.user_data_length as usize) },
+        };
+        let properties = if h.flags & EVENT_HEADER_FLAG_STRING_ONLY != 0 {
+            ctx.message.clone()
+        } else if record.extended_data_count > 0 {
+            // TraceLogging events carry their own schema, all with id 0.
+            schema(record)
+        } else {
+            ctx.schemas
+                .entry((h.provider_id, h.descriptor.id, h.descriptor.version))
+                .or_insert_with(|| schema(record))
+                .clone()
+        };
+        (ctx.sink)(Record {
+            provider: h.provider_id,
+            event_id: h.descriptor.id,
+            level: h.descriptor.level,
+            pid: h.process_id,
+            tid: h.thread_id,
+            timestamp: h.time_stamp,
+            pointer_size: if h.flags & EVENT_HEADER_FLAG_32_BIT_HEADER != 0 { 4 } else { 8 },
+            properties,
+            data: data.to_vec(),
+        });
+    }
+
+    /// The top-level properties TDH knows for `record`; none if it does not.
+    fn schema(record: &EventRecord) -> Arc<[Property]> {
+        let mut size = 0u32;
+        // SAFETY: size query with no buffer.
+        let status = unsafe { TdhGetEventInformation(record, 0, ptr::null(), ptr::null_mut(), &mut size) };
+        if status != ERROR_INSUFFICIENT_BUFFER {
+            return Arc::from([]);
+        }
+        let mut info = vec![0u8; size as usize];
+        // SAFETY: `info` holds `size` bytes.
+        let status = unsafe { TdhGetEventInformation(record, 0, ptr::null(), info.as_mut_ptr(), &mut size) };
+        if status != ERROR_SUCCESS {
+            return Arc::from([]);
+        }
+        decode::parse_event_info(&info).map_or_else(|| Arc::from([]), Arc::from)
+    }
+}
@@ -0,0 +1,391 @@
+// src/eventlog.rs
+//! Windows Event Log sink, for SOCs that collect the Application log rather
+//! than files next to the executable.
+//!
+//! With `[logging] eventlog = true`, [`EventLog`] is chained into the fern
+//! dispatch: records at WARN and above become events of the `Gladix`
+//! source, with an event id per [`Category`] taken from the record's target.
+//! Detection alerts are written by [`report_alert`] instead, with their rule
+//! id, severity, pid and path as separate insertion strings; the WARN line
+//! the engine logs for each (target [`ALERT_TARGET`]) is not written twice.
+//!
+//! The source is registered on first use under
+//! `HKLM\SYSTEM\CurrentControlSet\Services\EventLog\Application\Gladix`,
+//! with the .NET message file that renders an event as its first insertion
+//! string. That needs administrator rights once; without them
+//! [`EventLog::open`] fails and the agent keeps its file and stdout output.
+//! Off Windows it always fails with `ErrorKind::Unsupported`.
+
+use std::{
+    io,
+    sync::{Arc, OnceLock},
+};
+use log::{Level, Log, Metadata, Record};
+
+use crate::intel::{alerts::Alert, severity::Severity};
+
+/// Event source name, that of the service.
+pub const SOURCE: &str = "Gladix";
+
+/// Target of the line logged for each alert, which [`report_alert`] writes.
+pub const ALERT_TARGET: &str = "agent::intel::alerts";
+
+/// Event type, as `ReportEventW` takes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    Error       = 0x0001,
+    Warning     = 0x0002,
+    Information = 0x0004,
+}
+
+/// What an event is about; each has its event id and is passed as the event
+/// category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    /// Start, stop, components and the single-instance lock.
+    Service   = 1,
+    /// Shared rings with the driver and the driver connection.
+    Ring      = 2,
+    Database  = 3,
+    Detection = 4,
+    Other     = 5,
+}
+
+/// Targets and their category; the first that matches wins.
+const CATEGORIES: &[(&str, Category)] = &[
+    ("agent::run", Category::Service),
+    ("agent::health", Category::Service),
+    ("agent::util::instance", Category::Service),
+    ("agent::comms::memory_ring", Category::Ring),
+    ("agent::comms::ring_event", Category::Ring),
+    ("agent::comms::listeners", Category::Ring),
+    ("agent::comms::progress", Category::Ring),
+    ("agent::comms::ioctl", Category::Ring),
+    ("agent::db", Category::Database),
+    ("agent::intel", Category::Detection),
+];
+
+impl Category {
+    /// Category of records logged under `target`, a module path. `main.rs`
+    /// logs under the crate name, as service lifecycle.
+    pub fn of(target: &str) -> Self {
+        if target == "agent" {
+            return Self::Service;
+        }
+        CATEGORIES
+            .iter()
+            .find(|(module, _)| target.strip_prefix(module).is_some_and(|rest| rest.is_empty() || rest.starts_with("::")))
+            .map_or(Self::Other, |(_, category)| *category)
+    }
+
+    /// Event id of the category's events.
+    pub const fn event_id(self) -> u32 {
+        match self {
+            Self::Service   => 1000,
+            Self::Ring      => 2000,
+            Self::Database  => 3000,
+            Self::Detection => 4000,
+            Self::Other     => 9000,
+        }
+    }
+}
+
+/// One event as handed to `ReportEventW`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventRecord {
+    pub kind:     EventType,
+    pub category: Category,
+    pub id:       u32,
+    /// Insertion strings; the first is the whole message.
+    pub strings:  Vec<String>,
+}
+
+impl EventRecord {
+    /// Event of a log record, or `None` below WARN and for alert lines.
+    pub fn from_log(record: &Record) -> Option<Self> {
+        let kind = match record.level() {
+            Level::Error => EventType::Error,
+            Level::Warn => EventType::Warning,
+            _ => return None,
+        };
+        if record.target() == ALERT_TARGET {
+            return None;
+        }
+        let category = Category::of(record.target());
+        Some(Self {
+            kind,
+            category,
+            id: category.event_id(),
+            strings: vec![record.args().to_string(), record.target().to_string()],
+        })
+    }
+
+    /// Event of `alert`: the message, then rule id, severity, pid, parent pid
+    /// and file, empty when unknown.
+    pub fn from_alert(alert: &Alert) -> Self {
+        let kind = match alert.severity {
+            Severity::High | Severity::Critical => EventType::Error,
+            Severity::Medium | Severity::Low => EventType::Warning,
+            Severity::Info => EventType::Information,
+        };
+        Self {
+            kind,
+            category: Category::Detection,
+            id: Category::Detection.event_id(),
+            strings: vec![
+                format!("{}: {}", alert.rule_id, alert.message),
+                alert.rule_id.clone(),
+                alert.severity.to_string(),
+                alert.pid.to_string(),
+                alert.ppid.map(|p| p.to_string()).unwrap_or_default(),
+                alert.file.clone().unwrap_or_default(),
+            ],
+        }
+    }
+}
+
+/// Where events go: `ReportEventW`, or a recorder in tests.
+pub trait EventSink: Send + Sync {
+    fn report(&self, event: &EventRecord) -> io::Result<()>;
+}
+
+/// The Event Log as a `log::Log`. Clones share the sink.
+#[derive(Clone)]
+pub struct EventLog {
+    sink: Arc<dyn EventSink>,
+}
+
+impl EventLog {
+    pub fn new(sink: impl EventSink + 'static) -> Self {
+        Self { sink: Arc::new(sink) }
+    }
+
+    /// Registers the [`SOURCE`] if it is not yet and opens it.
+    pub fn open() -> io::Result<Self> {
+        sys::register_source(SOURCE)?;
+        Ok(Self::new(sys::Source::open(SOURCE)?))
+    }
+
+    pub fn alert(&self, alert: &Alert) {
+        self.write(&EventRecord::from_alert(alert));
+    }
+
+    fn write(&self, event: &EventRecord) {
+        // Dropped on failure: logging it would come back here.
+        let _ = self.sink.report(event);
+    }
+}
+
+impl Log for EventLog {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(event) = EventRecord::from_log(record) {
+            self.write(&event);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// The log alerts go to, once logging is set up with it.
+static ALERTS: OnceLock<EventLog> = OnceLock::new();
+
+/// Makes [`report_alert`] write to `log`. Only the first call counts.
+pub fn install(log: EventLog) {
+    let _ = ALERTS.set(log);
+}
+
+/// Writes `alert` to the Event Log, if it is in use.
+pub fn report_alert(alert: &Alert) {
+    if let Some(log) = ALERTS.get() {
+        log.alert(alert);
+    }
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use std::io;
+    use super::{EventRecord, EventSink};
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, "the Event Log is only available on Windows")
+    }
+
+    pub fn register_source(_name: &str) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub struct Source;
+
+    impl Source {
+        pub fn open(_name: &str) -> io::Result<Self> {
+            Err(unsupported())
+        }
+    }
+
+    impl EventSink for Source {
+        fn report(&self, _event: &EventRecord) -> io::Result<()> {
+            Err(unsupported())
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{ffi::c_void, io, ptr};
+    use super::{EventRecord, EventSink};
+
+    const HKEY_LOCAL_MACHINE: isize = 0x8000_0002_u32 as i32 as isize;
+    const KEY_QUERY_VALUE: u32 = 0x0001;
+    const KEY_SET_VALUE: u32 = 0x0002;
+    const REG_EXPAND_SZ: u32 = 2;
+    const REG_DWORD: u32 = 4;
+    const ERROR_FILE_NOT_FOUND: i32 = 2;
+    /// Error, warning and information events.
+    const TYPES_SUPPORTED: u32 = 0x7;
+    /// Has a message for every event id that is its first insertion string.
+    const MESSAGE_FILE: &str = r"%SystemRoot%\Microsoft.NET\Framework64\v4.0.30319\EventLogMessages.dll";
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        fn RegCreateKeyExW(
+            key: isize,
+            subkey: *const u16,
+            reserved: u32,
+            class: *const u16,
+            options: u32,
+            access: u32,
+            security: *const c_void,
+            result: *mut isize,
+            disposition: *mut u32,
+        ) -> i32;
+        fn RegOpenKeyExW(key: isize, subkey: *const u16, options: u32, access: u32, result: *mut isize) -> i32;
+        fn RegSetValueExW(key: isize, name: *const u16, reserved: u32, kind: u32, data: *const u8, len: u32) -> i32;
+        fn RegCloseKey(key: isize) -> i32;
+        fn RegisterEventSourceW(server: *const u16, source: *const u16) -> isize;
+        fn DeregisterEventSource(source: isize) -> i32;
+        fn ReportEventW(
+            source: isize,
+            kind: u16,
+            category: u16,
+            id: u32,
+            sid: *const c_void,
+            num_strings: u16,
+            data_size: u32,
+            strings: *const *const u16,
+            data: *const c_void,
+        ) -> i32;
+    }
+
+    /// Registry key closed on drop.
+    struct Key(isize);
+
+    impl Drop for Key {
+        fn drop(&mut self) {
+            // SAFETY: key opened by this module and closed once.
+            unsafe { RegCloseKey(self.0) };
+        }
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn check(status: i32) -> io::Result<()> {
+        match status {
+            0 => Ok(()),
+            e => Err(io::Error::from_raw_os_error(e)),
+        }
+    }
+
+    fn set_value(key: &Key, name: &str, kind: u32, data: &[u8]) -> io::Result<()> {
+        // SAFETY: `data` holds `data.len()` bytes of a value of type `kind`.
+        check(unsafe { RegSetValueExW(key.0, wide(name).as_ptr(), 0, kind, data.as_ptr(), data.len() as u32) })
+    }
+
+    /// Creates the source's key unless it exists, which only reading needs.
+    pub fn register_source(name: &str) -> io::Result<()> {
+        let path = wide(&format!(r"SYSTEM\CurrentControlSet\Services\EventLog\Application\{name}"));
+        let mut handle = 0isize;
+        // SAFETY: NUL-terminated string; `handle` receives the key.
+        match unsafe { RegOpenKeyExW(HKEY_LOCAL_MACHINE, path.as_ptr(), 0, KEY_QUERY_VALUE, &mut handle) } {
+            0 => {
+                drop(Key(handle));
+                return Ok(());
+            }
+            ERROR_FILE_NOT_FOUND => {}
+            status => check(status)?,
+        }
+        // SAFETY: as above.
+        check(unsafe {
+            RegCreateKeyExW(
+                HKEY_LOCAL_MACHINE,
+                path.as_ptr(),
+                0,
+                ptr::null(),
+                0,
+                KEY_SET_VALUE,
+                ptr::null(),
+                &mut handle,
+                ptr::null_mut(),
+            )
+        })?;
+        let key = Key(handle);
+        let file: Vec<u8> = wide(MESSAGE_FILE).iter().flat_map(|c| c.to_le_bytes()).collect();
+        set_value(&key, "EventMessageFile", REG_EXPAND_SZ, &file)?;
+        set_value(&key, "TypesSupported", REG_DWORD, &TYPES_SUPPORTED.to_le_bytes())
+    }
+
+    /// Registered source handle, deregistered on drop.
+    pub struct Source(isize);
+
+    // SAFETY: `ReportEventW` may be called on one handle from any thread.
+    unsafe impl Send for Source {}
+    unsafe impl Sync for Source {}
+
+    impl Source {
+        pub fn open(name: &str) -> io::Result<Self> {
+            // SAFETY: NUL-terminated string; null is the local computer.
+            match unsafe { RegisterEventSourceW(ptr::null(), wide(name).as_ptr()) } {
+                0 => Err(io::Error::last_os_error()),
+                handle => Ok(Self(handle)),
+            }
+        }
+    }
+
+    impl Drop for Source {
+        fn drop(&mut self) {
+            // SAFETY: handle registered by `open` and deregistered once.
+            unsafe { DeregisterEventSource(self.0) };
+        }
+    }
+
+    impl EventSink for Source {
+        fn report(&self, event: &EventRecord) -> io::Result<()> {
+            let strings: Vec<Vec<u16>> = event.strings.iter().map(|s| wide(s)).collect();
+            let ptrs: Vec<*const u16> = strings.iter().map(|s| s.as_ptr()).collect();
+            // SAFETY: `ptrs` points at NUL-terminated strings that outlive
+            // the call.
+            let ok = unsafe {
+                ReportEventW(
+                    self.0,
+                    event.kind as u16,
+                    event.category as u16,
+                    event.id,
+                    ptr::null(),
+                    ptrs.len() as u16,
+                    0,
+                    ptrs.as_ptr(),
+                    ptr::null(),
+                )
+            };
+            match ok {
+                0 => Err(io::Error::last_os_error()),
+                _ => Ok(()),
+            }
+        }
+    }
+}
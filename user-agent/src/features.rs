@@ -0,0 +1,68 @@
+// src/features.rs
+//! Cargo features of this build: combination guards and reporting.
+//!
+//! | Feature          | Default | Effect                                        |
+//! |------------------|---------|-----------------------------------------------|
+//! | `bundled-sqlite` | yes     | SQLite compiled in; otherwise the system lib  |
+//! | `driver-tests`   | no      | Tests that need the driver loaded             |
+//!
+//! Every feature is listed in [`KNOWN`]. `cargo xtask check-matrix` builds
+//! each one alone, with and without defaults, so a combination that cannot
+//! work must be rejected below with `compile_error!` rather than surface as
+//! a link error on a user's machine.
+
+// ─── Guards ───────────────────────────────────────────────────────────────
+// Without the bundled build the agent links the system SQLite, which is not
+// shipped with Windows.
+#[cfg(all(windows, not(feature = "bundled-sqlite")))]
+compile_error!(
+    "the agent needs SQLite on Windows: keep the default `bundled-sqlite` feature \
+     when building with --no-default-features"
+);
+
+/// Every feature declared in `Cargo.toml`, with a one-line description.
+pub const KNOWN: &[(&str, &str)] = &[
+    ("bundled-sqlite", "SQLite compiled into the agent instead of the system library"),
+    ("driver-tests", "integration tests that talk to a loaded driver"),
+];
+
+/// What this build was compiled with, as recorded by the build script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version:  &'static str,
+    pub target:   &'static str,
+    pub profile:  &'static str,
+    pub features: Vec<&'static str>,
+}
+
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version:  env!("CARGO_PKG_VERSION"),
+        target:   env!("GLADIX_TARGET"),
+        profile:  env!("GLADIX_PROFILE"),
+        features: enabled(),
+    }
+}
+
+/// Enabled features, sorted.
+pub fn enabled() -> Vec<&'static str> {
+    env!("GLADIX_FEATURES").split(',').filter(|f| !f.is_empty()).collect()
+}
+
+pub fn is_enabled(feature: &str) -> bool {
+    enabled().contains(&feature)
+}
+
+/// `--features-help` text: every known feature and whether it is compiled in.
+pub fn features_help() -> String {
+    let info = build_info();
+    let mut out = format!(
+        "agent {} ({}, {} build)\n\nfeatures:\n",
+        info.version, info.target, info.profile
+    );
+    for (name, about) in KNOWN {
+        let mark = if info.features.contains(name) { "+" } else { "-" };
+        out.push_str(&format!("  {mark} {name:<16} {about}\n"));
+    }
+    out
+}
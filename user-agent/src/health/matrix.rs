@@ -0,0 +1,104 @@
+// src/health/matrix.rs
+//! Static criticality matrix for agent components.
+//!
+//! | Component        | Criticality | Retryable |
+//! |------------------|-------------|-----------|
+//! | `RingConsumer`   | critical    | no        |
+//! | `DbWriter(..)`   | critical    | no        |
+//! | `Scanner`        | optional    | yes       |
+//! | `Etw`            | optional    | yes       |
+//! | `Metrics`        | optional    | yes       |
+//! | `Grpc`           | optional    | yes       |
+//! | `Sinks`          | optional    | yes       |
+//! | `Detection`      | optional    | yes       |
+//! | `Probe`          | optional    | yes       |
+//! | `Sensor(..)`     | optional    | no        |
+//!
+//! Kernel telemetry collection and its persistence are the reason the agent
+//! exists, so losing either aborts startup. Everything else can be lost
+//! temporarily without losing events. `Sensor` entries are not started but
+//! reported by the live probe when telemetry stops flowing.
+
+use std::fmt;
+
+/// A startable unit of the agent pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Component {
+    /// Shared-memory ring reader feeding the event buses.
+    RingConsumer,
+    /// Batched SQLite writer for one event table.
+    DbWriter(&'static str),
+    /// Scheduled directory scanner.
+    Scanner,
+    /// User-mode ETW consumer.
+    Etw,
+    /// Prometheus exporter and, if enabled, Windows performance counters.
+    Metrics,
+    /// gRPC server for the UI.
+    Grpc,
+    /// Secondary outputs (exports, forwarders).
+    Sinks,
+    /// Detection engine consuming the intel buses.
+    Detection,
+    /// Live verification probe.
+    Probe,
+    /// End-to-end telemetry path of one sensor, as verified by the probe.
+    Sensor(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Criticality {
+    /// Failure aborts the agent with a crash report.
+    Critical,
+    /// Failure leaves the agent running with the component degraded.
+    Optional,
+}
+
+/// How startup treats a failure of one component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    pub criticality: Criticality,
+    /// Whether the watchdog should periodically retry a failed start.
+    pub retryable:   bool,
+}
+
+/// Look up the policy of `component` in the static matrix.
+pub const fn policy(component: Component) -> Policy {
+    match component {
+        Component::RingConsumer | Component::DbWriter(_) => Policy {
+            criticality: Criticality::Critical,
+            retryable:   false,
+        },
+        Component::Scanner
+        | Component::Etw
+        | Component::Metrics
+        | Component::Grpc
+        | Component::Sinks
+        | Component::Detection
+        | Component::Probe => Policy {
+            criticality: Criticality::Optional,
+            retryable:   true,
+        },
+        Component::Sensor(_) => Policy {
+            criticality: Criticality::Optional,
+            retryable:   false,
+        },
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Component::RingConsumer => f.write_str("ring_consumer"),
+            Component::DbWriter(table) => write!(f, "db_writer[{table}]"),
+            Component::Scanner => f.write_str("scanner"),
+            Component::Etw => f.write_str("etw"),
+            Component::Metrics => f.write_str("metrics"),
+            Component::Grpc => f.write_str("grpc"),
+            Component::Sinks => f.write_str("sinks"),
+            Component::Detection => f.write_str("detection"),
+            Component::Probe => f.write_str("probe"),
+            Component::Sensor(name) => write!(f, "sensor[{name}]"),
+        }
+    }
+}
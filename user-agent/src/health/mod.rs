@@ -0,0 +1,18 @@
+// src/health/mod.rs
+//! Component health tracking and the startup degradation policy.
+//!
+//! Every long-running piece of the agent is a [`Component`]. The static
+//! [`matrix`] decides whether a component is critical (its failure aborts the
+//! agent) or optional (the agent keeps running in a degraded state). Startup
+//! results land in a shared [`HealthRegistry`]; optional components that failed
+//! and are retryable are handed to the [`watchdog`] which keeps retrying them.
+
+pub mod matrix;
+pub mod registry;
+pub mod startup;
+pub mod watchdog;
+
+pub use matrix::{policy, Component, Criticality, Policy};
+pub use registry::{ComponentState, HealthRegistry};
+pub use startup::{CrashReport, Startup, StartupReport};
+pub use watchdog::spawn_watchdog;
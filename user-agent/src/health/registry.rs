@@ -0,0 +1,113 @@
+// src/health/registry.rs
+//! Shared, thread-safe view of every component's current state.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+use chrono::{DateTime, Utc};
+use metrics::gauge;
+
+use super::matrix::Component;
+
+/// Current state of a single component.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentState {
+    /// Registered but not started yet.
+    Pending,
+    /// Started successfully (or recovered).
+    Healthy,
+    /// Failed to start or crashed; the agent keeps running without it.
+    Degraded {
+        error:    String,
+        since:    DateTime<Utc>,
+        attempts: u32,
+    },
+}
+
+impl ComponentState {
+    /// Numeric form for metrics: 0 pending, 1 healthy, 2 degraded.
+    pub fn as_number(&self) -> f64 {
+        match self {
+            ComponentState::Pending         => 0.0,
+            ComponentState::Healthy         => 1.0,
+            ComponentState::Degraded { .. } => 2.0,
+        }
+    }
+}
+
+fn publish(component: Component, state: &ComponentState) {
+    gauge!("component_health", "component" => component.to_string()).set(state.as_number());
+}
+
+/// Cloneable handle to the health table. Cheap to pass into tasks.
+#[derive(Debug, Clone, Default)]
+pub struct HealthRegistry {
+    inner: Arc<RwLock<BTreeMap<Component, ComponentState>>>,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, component: Component) {
+        let mut map = self.inner.write().unwrap();
+        publish(component, map.entry(component).or_insert(ComponentState::Pending));
+    }
+
+    pub fn mark_healthy(&self, component: Component) {
+        publish(component, &ComponentState::Healthy);
+        self.inner
+            .write()
+            .unwrap()
+            .insert(component, ComponentState::Healthy);
+    }
+
+    /// Records a failure, keeping the original `since` and bumping `attempts`
+    /// when the component was already degraded.
+    pub fn mark_degraded(&self, component: Component, error: impl Into<String>) {
+        let error = error.into();
+        let mut map = self.inner.write().unwrap();
+        let next = match map.get(&component) {
+            Some(ComponentState::Degraded { since, attempts, .. }) => ComponentState::Degraded {
+                error,
+                since:    *since,
+                attempts: attempts + 1,
+            },
+            _ => ComponentState::Degraded { error, since: Utc::now(), attempts: 1 },
+        };
+        publish(component, &next);
+        map.insert(component, next);
+    }
+
+    pub fn state(&self, component: Component) -> Option<ComponentState> {
+        self.inner.read().unwrap().get(&component).cloned()
+    }
+
+    /// Components currently degraded, in stable order.
+    pub fn degraded(&self) -> Vec<Component> {
+        self.inner
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, s)| matches!(s, ComponentState::Degraded { .. }))
+            .map(|(c, _)| *c)
+            .collect()
+    }
+
+    /// `true` when no component is degraded.
+    pub fn is_healthy(&self) -> bool {
+        self.degraded().is_empty()
+    }
+
+    /// Full table copy for status reporting.
+    pub fn snapshot(&self) -> Vec<(Component, ComponentState)> {
+        self.inner
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(c, s)| (*c, s.clone()))
+            .collect()
+    }
+}
@@ -0,0 +1,153 @@
+// src/health/startup.rs
+//! Start every component, classify failures and decide whether to go on.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+use chrono::{DateTime, Utc};
+
+use super::{
+    matrix::{policy, Component, Criticality},
+    registry::HealthRegistry,
+};
+
+/// Fallible start routine of a component. `FnMut` so the watchdog can call it
+/// again after a failure.
+pub type StartFn = Box<dyn FnMut() -> anyhow::Result<()> + Send + 'static>;
+
+/// Collects component start routines and runs them in registration order.
+pub struct Startup {
+    health:     HealthRegistry,
+    components: Vec<(Component, StartFn)>,
+}
+
+impl Startup {
+    pub fn new(health: HealthRegistry) -> Self {
+        Self { health, components: Vec::new() }
+    }
+
+    /// Registers `component` with its start routine.
+    pub fn component<F>(mut self, component: Component, start: F) -> Self
+    where
+        F: FnMut() -> anyhow::Result<()> + Send + 'static,
+    {
+        self.health.register(component);
+        self.components.push((component, Box::new(start)));
+        self
+    }
+
+    /// Attempts every component, even after a critical failure, so the crash
+    /// report lists the complete picture. Returns `Err` only when at least one
+    /// critical component failed.
+    pub fn run(self) -> Result<StartupReport, CrashReport> {
+        let Startup { health, components } = self;
+        let mut retry = Vec::new();
+        let mut crash: Option<CrashReport> = None;
+
+        for (component, mut start) in components {
+            match start() {
+                Ok(()) => {
+                    health.mark_healthy(component);
+                    log::info!("component '{}' started", component);
+                }
+                Err(e) => {
+                    let error = format!("{e:#}");
+                    health.mark_degraded(component, error.clone());
+                    let p = policy(component);
+                    match p.criticality {
+                        Criticality::Critical => {
+                            log::error!("critical component '{}' failed to start: {}", component, error);
+                            crash.get_or_insert_with(|| CrashReport::new(component, error));
+                        }
+                        Criticality::Optional => {
+                            log::error!(
+                                "!!! DEGRADED: component '{}' failed to start: {} (retry={})",
+                                component, error, p.retryable
+                            );
+                            if p.retryable {
+                                retry.push((component, start));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        match crash {
+            Some(mut report) => {
+                report.degraded = health.degraded();
+                Err(report)
+            }
+            None => {
+                let degraded = health.degraded();
+                if !degraded.is_empty() {
+                    log::warn!("agent running DEGRADED; affected components: {:?}", degraded);
+                }
+                Ok(StartupReport { health, retry })
+            }
+        }
+    }
+}
+
+/// Outcome of a startup that did not hit a critical failure.
+pub struct StartupReport {
+    pub health: HealthRegistry,
+    pub(crate) retry: Vec<(Component, StartFn)>,
+}
+
+impl StartupReport {
+    /// Components the watchdog will keep retrying.
+    pub fn retrying(&self) -> Vec<Component> {
+        self.retry.iter().map(|(c, _)| *c).collect()
+    }
+}
+
+/// Structured description of a fatal startup failure.
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    pub ts:        DateTime<Utc>,
+    pub component: Component,
+    pub error:     String,
+    /// Every component that was degraded when startup gave up.
+    pub degraded:  Vec<Component>,
+}
+
+impl CrashReport {
+    pub fn new(component: Component, error: impl Into<String>) -> Self {
+        Self { ts: Utc::now(), component, error: error.into(), degraded: Vec::new() }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "ts":        self.ts.to_rfc3339(),
+            "component": self.component.to_string(),
+            "error":     self.error,
+            "degraded":  self.degraded.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+            "pid":       std::process::id(),
+            "version":   env!("CARGO_PKG_VERSION"),
+        })
+    }
+
+    /// Writes `crash_report.json` into `dir`, replacing any previous report.
+    pub fn write_to(&self, dir: &Path) -> std::io::Result<PathBuf> {
+        let path = dir.join("crash_report.json");
+        let text = serde_json::to_string_pretty(&self.to_json())
+            .map_err(std::io::Error::other)?;
+        fs::write(&path, text)?;
+        Ok(path)
+    }
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}][CRASH][{}] {} (degraded: {:?})",
+            self.ts.to_rfc3339(),
+            self.component,
+            self.error,
+            self.degraded.iter().map(|c| c.to_string()).collect::<Vec<_>>()
+        )
+    }
+}
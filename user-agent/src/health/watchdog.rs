@@ -0,0 +1,82 @@
+// src/health/watchdog.rs
+//! Backoff retry of optional components that failed to start.
+
+use std::thread::{self, JoinHandle};
+
+use serde_json::json;
+
+use super::{registry::ComponentState, startup::StartupReport};
+use crate::db::ops_journal::{new_correlation, Actor, Entry, Journal, Subsystem};
+use crate::util::retry::{retry_blocking, RetryError, RetryPolicy};
+
+/// Spawns a thread that retries every retryable degraded component under
+/// `policy` (one retry loop per component, so their jitter spreads restarts
+/// apart), marking it healthy once its start routine succeeds. The thread
+/// exits when every component recovered or the policy gave up or was
+/// cancelled; `None` means there was nothing to do.
+///
+/// Each component's retries and their outcome go to `journal` under one
+/// correlation id.
+pub fn spawn_watchdog(report: StartupReport, policy: RetryPolicy, journal: Journal) -> Option<JoinHandle<()>> {
+    let StartupReport { health, retry } = report;
+    if retry.is_empty() {
+        return None;
+    }
+
+    let handle = thread::Builder::new()
+        .name("watchdog".into())
+        .spawn(move || {
+            thread::scope(|scope| {
+                for (component, mut start) in retry {
+                    let (health, policy, journal) = (&health, &policy, &journal);
+                    scope.spawn(move || {
+                        let correlation = new_correlation();
+                        let note = |summary: String, detail| {
+                            journal.record(&Entry::new(Subsystem::Watchdog, Actor::Automatic, &correlation, summary, detail));
+                        };
+                        let error = match health.state(component) {
+                            Some(ComponentState::Degraded { error, .. }) => Some(error),
+                            _ => None,
+                        };
+                        note(
+                            format!("retrying '{component}' after a failed start"),
+                            json!({ "component": component.to_string(), "error": error }),
+                        );
+                        // The failed start at boot counts as the first attempt, so the
+                        // first call here only schedules the initial backoff.
+                        let mut first = true;
+                        let outcome = retry_blocking(policy, || {
+                            if std::mem::take(&mut first) {
+                                anyhow::bail!("initial start failed");
+                            }
+                            start().inspect_err(|e| {
+                                health.mark_degraded(component, format!("{e:#}"));
+                                log::error!("!!! DEGRADED: component '{}' retry failed: {:#}", component, e);
+                            })
+                        });
+                        match outcome.result {
+                            Ok(()) => {
+                                health.mark_healthy(component);
+                                log::warn!("component '{}' recovered after {} attempts", component, outcome.attempts);
+                                note(
+                                    format!("'{component}' restarted after {} attempts", outcome.attempts),
+                                    json!({ "component": component.to_string(), "attempts": outcome.attempts }),
+                                );
+                            }
+                            Err(RetryError::Cancelled { .. }) => {}
+                            Err(e) => {
+                                log::error!("watchdog: giving up on '{}': {:#}", component, e);
+                                note(
+                                    format!("gave up on '{component}'"),
+                                    json!({ "component": component.to_string(), "error": format!("{e:#}") }),
+                                );
+                            }
+                        }
+                    });
+                }
+            });
+            log::info!("watchdog: finished");
+        })
+        .expect("failed to spawn watchdog thread");
+    Some(handle)
+}
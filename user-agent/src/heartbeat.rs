@@ -0,0 +1,170 @@
+// src/heartbeat.rs
+//! Agent heartbeat: every `[heartbeat].interval_ms` a row in `agent_status`
+//! with what the agent did since the previous one.
+//!
+//! The counts come from [`Stats`], which the ring listeners and the database
+//! writers bump as they go; the heartbeat takes them and starts them over.
+//! The Prometheus registry is not read, so the row is there whether or not
+//! the exporter is. The newest row is what `GetStatus` answers with.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use rusqlite::Connection;
+use shared::constants::VersionInfo;
+use tokio::{runtime::Runtime, task::JoinHandle, time};
+
+use crate::config::model::HeartbeatConfig;
+use crate::db::agent_status::{record_status, AgentStatus};
+use crate::db::maintenance::wal_path;
+use crate::util::{Shutdown, Supervisor};
+
+static GLOBAL: LazyLock<Stats> = LazyLock::new(Stats::new);
+
+/// Counters shared by whoever reports into the heartbeat. Clones share them.
+#[derive(Debug, Clone)]
+pub struct Stats {
+    started:      Instant,
+    ring_dropped: Arc<AtomicU64>,
+    /// Rows stored, per table.
+    processed:    Arc<Mutex<BTreeMap<&'static str, Arc<AtomicU64>>>>,
+    last_error:   Arc<Mutex<Option<String>>>,
+    /// Kept across heartbeats.
+    driver:       Arc<Mutex<Option<VersionInfo>>>,
+    /// Supervised tasks given up on; kept across heartbeats.
+    failed_tasks: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            started:      Instant::now(),
+            ring_dropped: Default::default(),
+            processed:    Default::default(),
+            last_error:   Default::default(),
+            driver:       Default::default(),
+            failed_tasks: Default::default(),
+        }
+    }
+
+    /// The agent's counters; uptime counts from the first call.
+    pub fn global() -> &'static Stats {
+        &GLOBAL
+    }
+
+    pub fn add_dropped(&self, n: u64) {
+        self.ring_dropped.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn add_processed(&self, table: &'static str, n: u64) {
+        if n == 0 {
+            return;
+        }
+        let counter = self.processed.lock().unwrap().entry(table).or_default().clone();
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Kept until the next heartbeat; a later error replaces it.
+    pub fn set_error(&self, error: impl Into<String>) {
+        *self.last_error.lock().unwrap() = Some(error.into());
+    }
+
+    /// The driver's answer to the version handshake.
+    pub fn set_driver(&self, version: VersionInfo) {
+        *self.driver.lock().unwrap() = Some(version);
+    }
+
+    /// A supervised task panicked too often and is no longer restarted.
+    pub fn add_failed_task(&self, task: &str) {
+        self.failed_tasks.lock().unwrap().insert(task.to_owned());
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// A heartbeat for the database at `db_path`, with the counts since the
+    /// previous one; the counters start over.
+    pub fn take(&self, db_path: &Path) -> AgentStatus {
+        let processed = self
+            .processed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(table, n)| (table.to_string(), n.swap(0, Ordering::Relaxed)))
+            .collect();
+        AgentStatus {
+            ts:            chrono::Utc::now().timestamp_micros(),
+            version:       env!("CARGO_PKG_VERSION").into(),
+            uptime_secs:   self.uptime().as_secs_f64(),
+            ring_dropped:  self.ring_dropped.swap(0, Ordering::Relaxed),
+            processed,
+            db_size_bytes: db_size(db_path),
+            last_error:    self.last_error.lock().unwrap().take(),
+            driver:        *self.driver.lock().unwrap(),
+            failed_tasks:  self.failed_tasks.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+}
+
+/// Database file plus its WAL, which holds what a checkpoint has not moved
+/// yet.
+pub fn db_size(db_path: &Path) -> u64 {
+    [db_path, wal_path(db_path).as_path()]
+        .iter()
+        .filter_map(|p| fs::metadata(p).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Writes a heartbeat taken from `stats` every `cfg.interval_ms`, the first
+/// right away, until `shutdown`.
+pub fn spawn_heartbeat(
+    rt: &Runtime,
+    db_path: PathBuf,
+    cfg: &HeartbeatConfig,
+    stats: Stats,
+    shutdown: &Shutdown,
+) -> Option<JoinHandle<()>> {
+    if !cfg.enabled {
+        log::info!("heartbeat disabled");
+        return None;
+    }
+    let period = Duration::from_millis(cfg.interval_ms);
+    let shutdown = shutdown.clone();
+    let supervisor = Supervisor::new("heartbeat").cancel_on(shutdown.clone());
+    Some(rt.spawn(supervisor.run(move || {
+        let (db_path, stats, shutdown) = (db_path.clone(), stats.clone(), shutdown.clone());
+        async move {
+            let mut ticker = time::interval(period);
+            ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = shutdown.triggered() => break,
+                }
+                let status = stats.take(&db_path);
+                let stored = Connection::open(&db_path).and_then(|conn| {
+                    let _ = conn.busy_timeout(Duration::from_millis(1_000));
+                    record_status(&conn, &status)
+                });
+                if let Err(e) = stored {
+                    log::warn!("cannot store heartbeat: {}", e);
+                }
+            }
+        }
+    })))
+}
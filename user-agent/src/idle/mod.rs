@@ -0,0 +1,264 @@
+// src/idle/mod.rs
+//! User-activity awareness for heavy background work.
+//!
+//! A monitor thread samples an [`IdleProvider`] (input idle time, session
+//! lock, AC/battery) into an [`IdleGate`]. Scheduled scanner passes and DB
+//! maintenance call [`IdleGate::wait_blocking`] / [`IdleGate::wait`] before
+//! running: while the user is active or on battery they are deferred, they
+//! resume as soon as the user goes idle or locks the session, and they run
+//! anyway once `max_deferral_hours` has passed. Current deferrals are listed
+//! by [`IdleGate::deferrals`] and published as `scheduling_deferred{task}`.
+
+pub mod system;
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use metrics::gauge;
+use tokio::sync::watch;
+
+use crate::config::model::SchedulingConfig;
+use crate::util::Shutdown;
+
+pub use system::SystemIdle;
+
+/// How often the monitor samples the provider.
+pub const SAMPLE_PERIOD: Duration = Duration::from_secs(5);
+/// Longest wait between two decisions, so `max_deferral_hours` is honoured
+/// without a state change.
+const RECHECK: Duration = Duration::from_secs(60);
+
+/// Snapshot of the interactive session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleState {
+    /// Time since the last keyboard or mouse input.
+    pub idle_for:   Duration,
+    pub locked:     bool,
+    pub on_battery: bool,
+}
+
+impl IdleState {
+    /// Used when nothing is known (no session, unsupported platform): never
+    /// defers anything.
+    pub const UNKNOWN: IdleState = IdleState { idle_for: Duration::MAX, locked: false, on_battery: false };
+}
+
+/// Source of [`IdleState`] samples.
+pub trait IdleProvider: Send {
+    fn sample(&mut self) -> IdleState;
+}
+
+/// Work that may be deferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Task {
+    Scanner,
+    WalCheckpoint,
+    CompressionBackfill,
+    Reprocess,
+}
+
+impl Task {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Task::Scanner             => "scanner",
+            Task::WalCheckpoint       => "wal_checkpoint",
+            Task::CompressionBackfill => "compression_backfill",
+            Task::Reprocess           => "reprocess",
+        }
+    }
+
+    /// Opt-in flag of `[scheduling.tasks]`.
+    fn enabled(self, cfg: &SchedulingConfig) -> bool {
+        match self {
+            Task::Scanner             => cfg.tasks.scanner,
+            Task::WalCheckpoint       => cfg.tasks.wal_checkpoint,
+            Task::CompressionBackfill => cfg.tasks.compression_backfill,
+            Task::Reprocess           => cfg.tasks.reprocess,
+        }
+    }
+}
+
+/// Outcome of one scheduling check, with the reason logged and shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Run(String),
+    Defer(String),
+}
+
+/// Decides whether `task`, already deferred for `deferred_for`, may run now.
+pub fn decide(cfg: &SchedulingConfig, task: Task, state: &IdleState, deferred_for: Duration) -> Decision {
+    if !cfg.respect_user_activity || !task.enabled(cfg) {
+        return Decision::Run("not deferrable".into());
+    }
+    if deferred_for >= max_deferral(cfg) {
+        return Decision::Run(format!("deferred for {}, the maximum", fmt_secs(deferred_for)));
+    }
+    if state.on_battery {
+        return Decision::Defer("on battery".into());
+    }
+    if state.locked {
+        return Decision::Run("session locked".into());
+    }
+    if state.idle_for >= Duration::from_secs(cfg.idle_threshold_secs) {
+        return Decision::Run(format!("user idle for {}", fmt_secs(state.idle_for)));
+    }
+    Decision::Defer(format!("user active {} ago", fmt_secs(state.idle_for)))
+}
+
+fn max_deferral(cfg: &SchedulingConfig) -> Duration {
+    Duration::try_from_secs_f64(cfg.max_deferral_hours * 3600.0).unwrap_or(Duration::MAX)
+}
+
+fn fmt_secs(d: Duration) -> String {
+    if d == Duration::MAX {
+        return "ever".into();
+    }
+    humantime::format_duration(Duration::from_secs(d.as_secs())).to_string()
+}
+
+/// A task currently waiting for an idle period.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deferred {
+    pub task:   Task,
+    pub since:  Instant,
+    pub reason: String,
+    /// When it runs regardless of activity.
+    pub until:  Instant,
+}
+
+struct Inner {
+    cfg:       SchedulingConfig,
+    state:     Mutex<IdleState>,
+    changed:   Condvar,
+    tx:        watch::Sender<IdleState>,
+    deferrals: Mutex<BTreeMap<Task, Deferred>>,
+}
+
+/// Latest [`IdleState`] plus the deferral bookkeeping; cheap to clone.
+#[derive(Clone)]
+pub struct IdleGate {
+    inner: Arc<Inner>,
+}
+
+impl IdleGate {
+    pub fn new(cfg: SchedulingConfig) -> Self {
+        let (tx, _) = watch::channel(IdleState::UNKNOWN);
+        Self {
+            inner: Arc::new(Inner {
+                cfg,
+                state:     Mutex::new(IdleState::UNKNOWN),
+                changed:   Condvar::new(),
+                tx,
+                deferrals: Mutex::default(),
+            }),
+        }
+    }
+
+    pub fn state(&self) -> IdleState {
+        *self.inner.state.lock().unwrap()
+    }
+
+    /// Stores a new sample and wakes waiting tasks if it changed.
+    pub fn update(&self, state: IdleState) {
+        let mut current = self.inner.state.lock().unwrap();
+        if *current != state {
+            *current = state;
+            self.inner.changed.notify_all();
+            self.inner.tx.send_replace(state);
+        }
+    }
+
+    /// Tasks currently deferred, for status output.
+    pub fn deferrals(&self) -> Vec<Deferred> {
+        self.inner.deferrals.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Applies `decision` to the bookkeeping. Returns `true` to run.
+    fn record(&self, task: Task, since: Instant, decision: Decision) -> bool {
+        let mut deferrals = self.inner.deferrals.lock().unwrap();
+        match decision {
+            Decision::Run(reason) => {
+                if deferrals.remove(&task).is_some() {
+                    log::info!("{}: resuming after {}: {}", task.as_str(), fmt_secs(since.elapsed()), reason);
+                    gauge!("scheduling_deferred", "task" => task.as_str()).set(0.0);
+                }
+                true
+            }
+            Decision::Defer(reason) => {
+                let previous = deferrals.get(&task).map(|d| d.reason.as_str());
+                if previous != Some(reason.as_str()) {
+                    log::info!("{}: deferred: {}", task.as_str(), reason);
+                }
+                let until = since.checked_add(max_deferral(&self.inner.cfg)).unwrap_or(since);
+                deferrals.insert(task, Deferred { task, since, reason, until });
+                gauge!("scheduling_deferred", "task" => task.as_str()).set(1.0);
+                false
+            }
+        }
+    }
+
+    /// Blocks until `task` may run. Returns `false` if shutdown came first.
+    pub fn wait_blocking(&self, task: Task, shutdown: &Shutdown) -> bool {
+        let since = Instant::now();
+        loop {
+            if shutdown.is_triggered() {
+                self.inner.deferrals.lock().unwrap().remove(&task);
+                return false;
+            }
+            let state = self.state();
+            if self.record(task, since, decide(&self.inner.cfg, task, &state, since.elapsed())) {
+                return true;
+            }
+            // Short slices so a stop request is noticed; state changes wake
+            // the wait at once.
+            let guard = self.inner.state.lock().unwrap();
+            let _ = self
+                .inner
+                .changed
+                .wait_timeout_while(guard, Duration::from_secs(1), |s| *s == state)
+                .unwrap();
+        }
+    }
+
+    /// Async counterpart of [`wait_blocking`](Self::wait_blocking).
+    pub async fn wait(&self, task: Task, shutdown: &Shutdown) -> bool {
+        let since = Instant::now();
+        let mut rx = self.inner.tx.subscribe();
+        loop {
+            if shutdown.is_triggered() {
+                self.inner.deferrals.lock().unwrap().remove(&task);
+                return false;
+            }
+            let state = self.state();
+            if self.record(task, since, decide(&self.inner.cfg, task, &state, since.elapsed())) {
+                return true;
+            }
+            tokio::select! {
+                _ = rx.changed() => {}
+                _ = shutdown.triggered() => {}
+                _ = tokio::time::sleep(RECHECK) => {}
+            }
+        }
+    }
+}
+
+/// Samples `provider` every `period` into `gate` until shutdown.
+pub fn spawn_monitor(
+    mut provider: impl IdleProvider + 'static,
+    gate: IdleGate,
+    period: Duration,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    thread::Builder::new()
+        .name("idle-monitor".into())
+        .spawn(move || loop {
+            gate.update(provider.sample());
+            if shutdown.wait_timeout(period) {
+                break;
+            }
+        })
+        .expect("failed to spawn idle monitor thread")
+}
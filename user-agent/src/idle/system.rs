@@ -0,0 +1,143 @@
+// src/idle/system.rs
+//! [`IdleProvider`] for the active console session.
+//!
+//! The service runs in session 0, where `GetLastInputInfo` only sees its own
+//! (absent) input, so idle time and lock state are read from the console
+//! session with `WTSQuerySessionInformationW(WTSSessionInfoEx)`; the local
+//! `GetLastInputInfo` is the fallback when the session reports no input time
+//! (console mode). Power comes from `GetSystemPowerStatus`. Off Windows every
+//! sample is [`IdleState::UNKNOWN`].
+
+use super::{IdleProvider, IdleState};
+
+#[derive(Debug, Default)]
+pub struct SystemIdle;
+
+impl IdleProvider for SystemIdle {
+    fn sample(&mut self) -> IdleState {
+        sys::sample()
+    }
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use super::IdleState;
+
+    pub fn sample() -> IdleState {
+        IdleState::UNKNOWN
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{ffi::c_void, time::Duration};
+    use super::IdleState;
+
+    const WTS_SESSION_INFO_EX: u32 = 25;
+    const WTS_SESSIONSTATE_LOCK: i32 = 0;
+
+    #[repr(C)]
+    struct WtsInfoExLevel1 {
+        session_id:       u32,
+        session_state:    i32,
+        session_flags:    i32,
+        win_station_name: [u16; 33],
+        user_name:        [u16; 21],
+        domain_name:      [u16; 18],
+        logon_time:       i64,
+        connect_time:     i64,
+        disconnect_time:  i64,
+        last_input_time:  i64,
+        current_time:     i64,
+        counters:         [u32; 6],
+    }
+
+    #[repr(C)]
+    struct WtsInfoEx {
+        level: u32,
+        data:  WtsInfoExLevel1,
+    }
+
+    #[repr(C)]
+    struct LastInputInfo {
+        size: u32,
+        time: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SystemPowerStatus {
+        ac_line_status:         u8,
+        battery_flag:           u8,
+        battery_life_percent:   u8,
+        system_status_flag:     u8,
+        battery_life_time:      u32,
+        battery_full_life_time: u32,
+    }
+
+    #[link(name = "wtsapi32")]
+    unsafe extern "system" {
+        fn WTSQuerySessionInformationW(server: isize, session: u32, class: u32, buf: *mut *mut c_void, len: *mut u32) -> i32;
+        fn WTSFreeMemory(mem: *mut c_void);
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn WTSGetActiveConsoleSessionId() -> u32;
+        fn GetTickCount() -> u32;
+        fn GetSystemPowerStatus(status: *mut SystemPowerStatus) -> i32;
+    }
+
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn GetLastInputInfo(info: *mut LastInputInfo) -> i32;
+    }
+
+    /// `(locked, idle)` of the console session; `None` without one.
+    fn console_session() -> Option<(bool, Option<Duration>)> {
+        // SAFETY: plain query; the buffer is freed below.
+        unsafe {
+            let session = WTSGetActiveConsoleSessionId();
+            if session == u32::MAX {
+                return None;
+            }
+            let (mut buf, mut len) = (std::ptr::null_mut(), 0u32);
+            if WTSQuerySessionInformationW(0, session, WTS_SESSION_INFO_EX, &mut buf, &mut len) == 0 {
+                return None;
+            }
+            let info = &*(buf as *const WtsInfoEx);
+            let locked = info.data.session_flags == WTS_SESSIONSTATE_LOCK;
+            // FILETIME ticks of 100 ns; zero when the session does not track input.
+            let idle = (info.data.last_input_time > 0)
+                .then(|| Duration::from_nanos((info.data.current_time - info.data.last_input_time).max(0) as u64 * 100));
+            WTSFreeMemory(buf);
+            Some((locked, idle))
+        }
+    }
+
+    fn local_idle() -> Option<Duration> {
+        let mut info = LastInputInfo { size: size_of::<LastInputInfo>() as u32, time: 0 };
+        // SAFETY: `info` is valid and sized.
+        if unsafe { GetLastInputInfo(&mut info) } == 0 {
+            return None;
+        }
+        // SAFETY: no arguments.
+        let now = unsafe { GetTickCount() };
+        Some(Duration::from_millis(now.wrapping_sub(info.time) as u64))
+    }
+
+    fn on_battery() -> bool {
+        let mut status = SystemPowerStatus::default();
+        // SAFETY: out-pointer valid for the call. ACLineStatus 0 is offline.
+        unsafe { GetSystemPowerStatus(&mut status) != 0 && status.ac_line_status == 0 }
+    }
+
+    pub fn sample() -> IdleState {
+        let (locked, idle) = console_session().unwrap_or((false, None));
+        IdleState {
+            idle_for:   idle.or_else(local_idle).unwrap_or(Duration::MAX),
+            locked,
+            on_battery: on_battery(),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+// src/intel/alerts.rs
+//! Alert persistence and the asynchronous context capture attached to it.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use rusqlite::{params, Connection, OptionalExtension};
+use tokio::task::{self, JoinHandle};
+
+use crate::actions::Actions;
+use super::{
+    context::{gather_context, ContextRef, ContextWindow, Trigger},
+    notify::{self, NotificationRouter},
+    recent::RecentEvents,
+    severity::Severity,
+};
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    /// UNIX microseconds.
+    pub ts:       i64,
+    pub rule_id:  String,
+    pub severity: Severity,
+    pub pid:      u32,
+    pub ppid:     Option<u32>,
+    pub message:  String,
+    /// File the alert is about, if any; what the quarantine action seals.
+    pub file:     Option<String>,
+}
+
+impl Alert {
+    pub fn trigger(&self) -> Trigger {
+        Trigger { pid: self.pid, ppid: self.ppid, ts: self.ts }
+    }
+}
+
+/// Inserts `alert` without context and returns its row id.
+pub fn insert_alert(conn: &Connection, alert: &Alert) -> rusqlite::Result<i64> {
+    conn.execute(
+        "INSERT INTO alerts (ts, rule_id, severity, pid, ppid, message) \
+         VALUES (?1,?2,?3,?4,?5,?6)",
+        params![
+            alert.ts,
+            &alert.rule_id,
+            alert.severity.as_str(),
+            alert.pid as i64,
+            alert.ppid.map(|p| p as i64),
+            &alert.message,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn store_context(conn: &Connection, alert_id: i64, refs: &[ContextRef]) -> rusqlite::Result<()> {
+    let json = serde_json::to_string(refs)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute(
+        "UPDATE alerts SET context_event_ids = ?1 WHERE id = ?2",
+        params![json, alert_id],
+    )?;
+    Ok(())
+}
+
+/// The alert stored as `alert_id`, without its file, which is not kept.
+pub fn load_alert(conn: &Connection, alert_id: i64) -> rusqlite::Result<Option<Alert>> {
+    conn.query_row(
+        "SELECT ts, rule_id, severity, pid, ppid, message FROM alerts WHERE id = ?1",
+        [alert_id],
+        |r| {
+            let severity: String = r.get(2)?;
+            Ok(Alert {
+                ts:       r.get(0)?,
+                rule_id:  r.get(1)?,
+                severity: severity.parse().unwrap_or(Severity::Info),
+                pid:      r.get::<_, i64>(3)? as u32,
+                ppid:     r.get::<_, Option<i64>>(4)?.map(|p| p as u32),
+                message:  r.get(5)?,
+                file:     None,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Context stored on `alert_id`; empty while the capture is still running.
+pub fn load_context(conn: &Connection, alert_id: i64) -> rusqlite::Result<Vec<ContextRef>> {
+    let json: Option<String> = conn
+        .query_row(
+            "SELECT context_event_ids FROM alerts WHERE id = ?1",
+            [alert_id],
+            |r| r.get(0),
+        )
+        .optional()?
+        .flatten();
+    match json {
+        Some(j) => serde_json::from_str(&j)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Gathers the context of `alert` off the async executor and stores it on
+/// the alert row. Must be called from within the Tokio runtime; resolves to
+/// the number of captured references.
+pub fn capture_context(
+    db_path: PathBuf,
+    recent: RecentEvents,
+    alert_id: i64,
+    alert: &Alert,
+    win: ContextWindow,
+) -> JoinHandle<rusqlite::Result<usize>> {
+    let trigger = alert.trigger();
+    task::spawn_blocking(move || {
+        let conn = Connection::open(&db_path)?;
+        conn.busy_timeout(std::time::Duration::from_millis(1_000))?;
+        let refs = gather_context(&recent, Some(&conn), &trigger, &win)?;
+        store_context(&conn, alert_id, &refs)?;
+        log::debug!("alert {}: captured {} context events", alert_id, refs.len());
+        Ok(refs.len())
+    })
+}
+
+/// One line per context event, oldest first, for the alert detail view.
+pub fn render_context(conn: &Connection, alert_id: i64) -> rusqlite::Result<Vec<String>> {
+    let Some(trigger_ts) = conn
+        .query_row("SELECT ts FROM alerts WHERE id = ?1", [alert_id], |r| r.get::<_, i64>(0))
+        .optional()?
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut lines = Vec::new();
+    for c in load_context(conn, alert_id)? {
+        let delta_ms = (c.ts - trigger_ts) as f64 / 1_000.0;
+        lines.push(format!(
+            "{:+10.1}ms  pid {:<6} {:<15} uid {:016x}",
+            delta_ms, c.pid, c.table, c.event_uid
+        ));
+    }
+    Ok(lines)
+}
+
+/// Where the detection engine and the analytics hand their alerts: each is
+/// stored, routed to the notification channels of its severity, its action
+/// run, and its context captured from `recent` once the row exists. Cheap to
+/// clone into every producer.
+#[derive(Clone)]
+pub struct AlertSink {
+    db_path: PathBuf,
+    actions: Actions,
+    recent:  RecentEvents,
+    window:  ContextWindow,
+    /// The router and the outbox its channels are written to.
+    notify:  Option<(Arc<Mutex<NotificationRouter>>, PathBuf)>,
+}
+
+impl AlertSink {
+    /// A sink whose context comes from the database alone until
+    /// [`with_recent`](Self::with_recent) gives it a fed cache.
+    pub fn new(db_path: PathBuf, actions: Actions) -> Self {
+        let recent = RecentEvents::new(Default::default());
+        Self { db_path, actions, recent, window: ContextWindow::default(), notify: None }
+    }
+
+    pub fn with_recent(mut self, recent: RecentEvents) -> Self {
+        self.recent = recent;
+        self
+    }
+
+    pub fn with_window(mut self, window: ContextWindow) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Routes every stored alert through `router`, writing it to the
+    /// channels' outboxes under `outbox`.
+    pub fn routed(mut self, router: NotificationRouter, outbox: PathBuf) -> Self {
+        self.notify = Some((Arc::new(Mutex::new(router)), outbox));
+        self
+    }
+
+    /// Stores, routes and runs the action of `alert` on the blocking pool,
+    /// then starts the context capture without waiting for it. Errors are
+    /// logged.
+    pub async fn store(&self, alert: Alert) {
+        let (db_path, actions, rule) = (self.db_path.clone(), self.actions.clone(), alert.rule_id.clone());
+        let notify = self.notify.clone();
+        let stored = task::spawn_blocking(move || {
+            let conn = Connection::open(&db_path)?;
+            conn.busy_timeout(Duration::from_millis(1_000))?;
+            let id = insert_alert(&conn, &alert)?;
+            if let Some((router, outbox)) = &notify {
+                let channels: Vec<String> = router
+                    .lock()
+                    .unwrap()
+                    .route(alert.severity, Instant::now())
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                for channel in channels {
+                    if let Err(e) = notify::deliver(outbox, &channel, id, &alert) {
+                        log::warn!("alert {}: not delivered to {}: {}", id, channel, e);
+                    }
+                }
+            }
+            actions.on_alert(&conn, id, &alert)?;
+            Ok::<_, rusqlite::Error>((id, alert))
+        })
+        .await;
+        match stored {
+            Ok(Ok((id, alert))) => {
+                let capture = capture_context(self.db_path.clone(), self.recent.clone(), id, &alert, self.window);
+                task::spawn(async move {
+                    if let Ok(Err(e)) = capture.await {
+                        log::warn!("alert {}: context not captured: {}", id, e);
+                    }
+                });
+            }
+            Ok(Err(e)) => log::warn!("cannot store {} alert: {}", rule, e),
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,19 @@
+// src/intel/analytics/mod.rs
+//! Built-in analytics that run on the intel buses independently of rules.
+
+pub mod parent_spoofing;
+pub mod write_execute;
+
+pub use parent_spoofing::{spawn_parent_spoofing, ParentSpoofing};
+pub use write_execute::{spawn_write_execute, WriteExecute};
+
+/// Allow-list match on an image path: entries with a directory match the
+/// full path, bare names match the file name in any directory. `entries`
+/// must be lower-cased.
+pub(crate) fn image_matches(entries: &[String], image_path: &str) -> bool {
+    let path = image_path.to_lowercase();
+    let name = path.rsplit(['\\', '/']).next().unwrap_or(&path);
+    entries.iter().any(|e| {
+        if e.contains(['\\', '/']) { *e == path } else { e == name }
+    })
+}
@@ -0,0 +1,98 @@
+// src/intel/analytics/parent_spoofing.rs
+//! Parent-PID spoofing: the declared parent of a new process is not the
+//! process that created it.
+//!
+//! The kernel reports both `ppid` (chosen by the caller, see
+//! `PROC_THREAD_ATTRIBUTE_PARENT_PROCESS`) and `creator_pid` (the thread that
+//! issued the call). Legitimate brokers such as services.exe, WMI and the
+//! AppInfo service create processes on behalf of others, so creators on the
+//! allow-list are ignored.
+
+use metrics::counter;
+use tokio::{runtime::Runtime, task::JoinHandle};
+use shared::events::ProcessEvent;
+
+use crate::comms::{intel_bus::Subscription, WrappedEvent};
+use crate::config::model::ParentSpoofingConfig;
+use crate::intel::{alerts::{Alert, AlertSink}, process_table::ProcessTable, severity::Severity};
+use super::image_matches;
+
+pub const RULE_ID: &str = "builtin.parent_pid_spoofing";
+
+#[derive(Debug, Clone)]
+pub struct ParentSpoofing {
+    /// Lower-cased full paths or bare file names.
+    brokers: Vec<String>,
+}
+
+impl ParentSpoofing {
+    pub fn new(cfg: &ParentSpoofingConfig) -> Self {
+        Self { brokers: cfg.brokers.iter().map(|b| b.to_lowercase()).collect() }
+    }
+
+    /// Entries with a directory match the full path, bare names match the
+    /// file name in any directory.
+    pub fn is_broker(&self, image_path: &str) -> bool {
+        image_matches(&self.brokers, image_path)
+    }
+
+    /// Alert for `ev` if its parent looks spoofed. An unknown creator (started
+    /// before the agent) cannot be allow-listed and lowers the severity.
+    pub fn check(&self, ev: &WrappedEvent<ProcessEvent>, table: &ProcessTable) -> Option<Alert> {
+        let p = &ev.payload;
+        if p.creator_pid == 0 || p.creator_pid == p.ppid {
+            return None;
+        }
+        let creator = table.image_path(p.creator_pid);
+        if creator.as_deref().is_some_and(|c| self.is_broker(c)) {
+            return None;
+        }
+
+        let parent = table.image_path(p.ppid);
+        Some(Alert {
+            ts:       ev.ts_micros(),
+            rule_id:  RULE_ID.into(),
+            severity: if creator.is_some() { Severity::High } else { Severity::Medium },
+            pid:      p.pid,
+            ppid:     Some(p.ppid),
+            message:  format!(
+                "{} (pid {}) declares parent {} (pid {}) but was created by {} (pid {}, tid {})",
+                p.image_path,
+                p.pid,
+                parent.as_deref().unwrap_or("?"),
+                p.ppid,
+                creator.as_deref().unwrap_or("?"),
+                p.creator_pid,
+                p.creator_tid,
+            ),
+            file:     Some(p.image_path.clone()),
+        })
+    }
+}
+
+/// Keeps `table` up to date from the process bus and stores an alert for
+/// every spoofed parent, then runs its configured action. Recording happens
+/// before the check so a creator seen just before its child is always known.
+/// Exits are skipped: a creator that quit right after spawning a child is
+/// still resolved.
+pub fn spawn_parent_spoofing(
+    rt: &Runtime,
+    mut rx: Subscription<ProcessEvent>,
+    table: ProcessTable,
+    analytic: ParentSpoofing,
+    sink: AlertSink,
+) -> JoinHandle<()> {
+    rt.spawn(async move {
+        while let Some(ev) = rx.recv().await {
+            if ev.payload.is_exit() {
+                continue;
+            }
+            table.record(&ev.payload, ev.ts_micros());
+            let Some(alert) = analytic.check(&ev, &table) else { continue };
+
+            counter!("alerts_raised_total", "rule" => RULE_ID).increment(1);
+            log::warn!("{}: {}", RULE_ID, alert.message);
+            sink.store(alert).await;
+        }
+    })
+}
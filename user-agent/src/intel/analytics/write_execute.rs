@@ -0,0 +1,228 @@
+// src/intel/analytics/write_execute.rs
+//! Write-then-execute: a process starts from a file that another process
+//! wrote moments earlier, the typical dropper pattern.
+//!
+//! File events fill a bounded map of recent writes keyed by normalised path
+//! (renames move the entry, deletes drop it). Each process creation looks its
+//! image up in that map; a write by a different process within the window
+//! raises one alert linking both events. Writes by allow-listed installers
+//! are ignored, and severity goes up when the image sits in a user-writable
+//! directory and when its hash has no known reputation.
+
+use std::collections::{HashMap, VecDeque};
+use metrics::{counter, gauge};
+use tokio::{runtime::Runtime, task::JoinHandle};
+use shared::events::{file_event::Operation, FileEvent, ProcessEvent};
+
+use crate::comms::{intel_bus::Subscription, WrappedEvent};
+use crate::config::model::{PathClass, WriteExecuteConfig};
+use crate::intel::{alerts::{Alert, AlertSink}, enrich::normalize_path, severity::Severity};
+use crate::probe::is_probe_event;
+use super::image_matches;
+
+pub const RULE_ID: &str = "builtin.write_then_execute";
+
+/// Last write seen for a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentWrite {
+    pub pid:       u32,
+    /// Image of the writing process.
+    pub exe_path:  String,
+    /// UNIX microseconds.
+    pub ts:        i64,
+    /// Lower-case hex; `None` when the sensor did not hash the file.
+    pub sha256:    Option<String>,
+    pub event_uid: i64,
+}
+
+/// Class of a normalised path (see [`normalize_path`]).
+pub fn classify(path: &str) -> PathClass {
+    if path.starts_with(r"\\") {
+        return PathClass::UserWritable;
+    }
+    // Drop "c:" or "\device\harddiskvolumeN" to get the rooted remainder.
+    let rest = match path.strip_prefix(r"\device\") {
+        Some(dev) => dev.find('\\').map_or("", |i| &dev[i..]),
+        None => path.get(1..2).filter(|c| *c == ":").map_or(path, |_| &path[2..]),
+    };
+    let under = |dir: &str| rest.strip_prefix(dir).is_some_and(|r| r.starts_with('\\'));
+    if under(r"\users") || under(r"\programdata") || under(r"\windows\temp") {
+        PathClass::UserWritable
+    } else if under(r"\windows") {
+        PathClass::System
+    } else if under(r"\program files") || under(r"\program files (x86)") {
+        PathClass::ProgramFiles
+    } else {
+        PathClass::Other
+    }
+}
+
+#[derive(Debug)]
+pub struct WriteExecute {
+    window:       i64,
+    max:          usize,
+    classes:      Vec<PathClass>,
+    /// Lower-cased full paths or bare file names.
+    installers:   Vec<String>,
+    known_hashes: Vec<String>,
+    writes:       HashMap<String, (RecentWrite, u64)>,
+    /// Write order as (path, tick); stale after a path is written again.
+    order:        VecDeque<(String, u64)>,
+    tick:         u64,
+}
+
+impl WriteExecute {
+    pub fn new(cfg: &WriteExecuteConfig) -> Self {
+        Self {
+            window:       cfg.window_secs.saturating_mul(1_000_000) as i64,
+            max:          cfg.max_tracked.max(1),
+            classes:      cfg.path_classes.clone(),
+            installers:   cfg.installers.iter().map(|i| i.to_lowercase()).collect(),
+            known_hashes: cfg.known_hashes.iter().map(|h| h.to_lowercase()).collect(),
+            writes:       HashMap::new(),
+            order:        VecDeque::new(),
+            tick:         0,
+        }
+    }
+
+    /// Number of paths currently remembered.
+    pub fn tracked(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn recent_write(&self, path: &str) -> Option<&RecentWrite> {
+        self.writes.get(&normalize_path(path)).map(|(w, _)| w)
+    }
+
+    /// Updates the write map from a file event.
+    pub fn on_file(&mut self, ev: &WrappedEvent<FileEvent>) {
+        let f = &ev.payload;
+        if !f.success {
+            return;
+        }
+        match Operation::try_from(f.op) {
+            Ok(Operation::Create | Operation::Write) => {
+                let write = RecentWrite {
+                    pid:       f.pid,
+                    exe_path:  f.exe_path.clone(),
+                    ts:        ev.ts_micros(),
+                    sha256:    (!f.sha256.is_empty()).then(|| hex::encode(&f.sha256)),
+                    event_uid: ev.event_uid(),
+                };
+                self.insert(normalize_path(&f.path), write);
+            }
+            Ok(Operation::Rename) => {
+                if let Some((write, _)) = self.writes.remove(&normalize_path(&f.path)) {
+                    self.insert(normalize_path(&f.new_path), write);
+                }
+            }
+            Ok(Operation::Delete) => {
+                self.writes.remove(&normalize_path(&f.path));
+            }
+            Err(_) => {}
+        }
+        gauge!("write_execute_tracked").set(self.writes.len() as f64);
+    }
+
+    fn insert(&mut self, path: String, write: RecentWrite) {
+        self.tick += 1;
+        self.order.push_back((path.clone(), self.tick));
+        self.writes.insert(path, (write, self.tick));
+        while self.writes.len() > self.max {
+            let Some((old, tick)) = self.order.pop_front() else { break };
+            if self.writes.get(&old).is_some_and(|(_, t)| *t == tick) {
+                self.writes.remove(&old);
+                counter!("write_execute_evictions_total").increment(1);
+            }
+        }
+        // Drop stale order entries left behind by rewrites and renames.
+        if self.order.len() > 2 * self.max {
+            let writes = &self.writes;
+            self.order.retain(|(p, tick)| writes.get(p).is_some_and(|(_, t)| t == tick));
+        }
+    }
+
+    /// Alert for `ev` if its image was written by another process within the
+    /// window. The write is consumed, so each dropped file alerts once.
+    pub fn check(&mut self, ev: &WrappedEvent<ProcessEvent>) -> Option<Alert> {
+        let p = &ev.payload;
+        let path = normalize_path(&p.image_path);
+        let class = classify(&path);
+        if !self.classes.contains(&class) {
+            return None;
+        }
+        let (write, _) = self.writes.get(&path)?;
+        let delta = ev.ts_micros() - write.ts;
+        if !(0..=self.window).contains(&delta) || write.pid == p.pid || image_matches(&self.installers, &write.exe_path) {
+            return None;
+        }
+        let (write, _) = self.writes.remove(&path)?;
+
+        let known = write.sha256.as_ref().is_some_and(|h| self.known_hashes.contains(h));
+        // One step up for each aggravating factor.
+        let severity = match u8::from(class == PathClass::UserWritable) + u8::from(!known) {
+            0 => Severity::Medium,
+            1 => Severity::High,
+            _ => Severity::Critical,
+        };
+        Some(Alert {
+            ts:       ev.ts_micros(),
+            rule_id:  RULE_ID.into(),
+            severity,
+            pid:      p.pid,
+            ppid:     Some(p.ppid),
+            message:  format!(
+                "{} (pid {}) started {:.1}s after {} (pid {}) wrote it; sha256 {}; write event {:016x}, process event {:016x}",
+                p.image_path,
+                p.pid,
+                delta as f64 / 1_000_000.0,
+                if write.exe_path.is_empty() { "?" } else { &write.exe_path },
+                write.pid,
+                write.sha256.as_deref().unwrap_or("unknown"),
+                write.event_uid,
+                ev.event_uid(),
+            ),
+            file:     Some(p.image_path.clone()),
+        })
+    }
+}
+
+/// Follows the file and process buses and stores an alert for every
+/// write-then-execute sequence, then runs its configured action. Probe
+/// traffic and process exits are ignored.
+pub fn spawn_write_execute(
+    rt: &Runtime,
+    mut files: Subscription<FileEvent>,
+    mut processes: Subscription<ProcessEvent>,
+    mut analytic: WriteExecute,
+    sink: AlertSink,
+) -> JoinHandle<()> {
+    rt.spawn(async move {
+        let mut files_open = true;
+        loop {
+            // Drain file events first so a write is known before the process
+            // that runs it.
+            let alert = tokio::select! {
+                biased;
+                ev = files.recv(), if files_open => {
+                    match ev {
+                        Some(ev) if is_probe_event(&ev.payload) => {}
+                        Some(ev) => analytic.on_file(&ev),
+                        None => files_open = false,
+                    }
+                    continue;
+                }
+                ev = processes.recv() => match ev {
+                    Some(ev) if is_probe_event(&ev.payload) || ev.payload.is_exit() => continue,
+                    Some(ev) => analytic.check(&ev),
+                    None => break,
+                },
+            };
+            let Some(alert) = alert else { continue };
+
+            counter!("alerts_raised_total", "rule" => RULE_ID).increment(1);
+            log::warn!("{}: {}", RULE_ID, alert.message);
+            sink.store(alert).await;
+        }
+    })
+}
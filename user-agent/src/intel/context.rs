@@ -0,0 +1,115 @@
+// src/intel/context.rs
+//! Context snippet around an alert: the sibling events of the triggering
+//! process and its parent within ±T, up to N per event type.
+//!
+//! The in-memory [`RecentEvents`] cache answers first; the database is only
+//! queried when the window reaches further back than the cache retains.
+
+use std::{collections::HashSet, time::Duration};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use super::recent::{EventKind, RecentEvents};
+use crate::db::schema_registry::table_exists;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ContextWindow {
+    /// Maximum events kept per event type and per process (N).
+    pub per_type: usize,
+    /// Half-width of the time window around the trigger (T).
+    pub window:   Duration,
+}
+
+impl Default for ContextWindow {
+    fn default() -> Self {
+        Self { per_type: 20, window: Duration::from_secs(30) }
+    }
+}
+
+/// What raised the alert.
+#[derive(Debug, Clone, Copy)]
+pub struct Trigger {
+    pub pid:  u32,
+    pub ppid: Option<u32>,
+    /// UNIX microseconds.
+    pub ts:   i64,
+}
+
+/// Reference to a stored event; enough to fetch it back for rendering.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextRef {
+    pub table:     String,
+    pub event_uid: i64,
+    pub ts:        i64,
+    pub pid:       u32,
+}
+
+/// Collects the context of `trigger`, ordered chronologically. `conn` may be
+/// `None` to answer from the cache alone.
+pub fn gather_context(
+    recent: &RecentEvents,
+    conn: Option<&Connection>,
+    trigger: &Trigger,
+    win: &ContextWindow,
+) -> rusqlite::Result<Vec<ContextRef>> {
+    let half = win.window.as_micros() as i64;
+    let (from, to) = (trigger.ts.saturating_sub(half), trigger.ts.saturating_add(half));
+
+    let mut pids = vec![trigger.pid];
+    if let Some(ppid) = trigger.ppid.filter(|p| *p != trigger.pid) {
+        pids.push(ppid);
+    }
+
+    let mut out = Vec::new();
+    for kind in EventKind::ALL {
+        for &pid in &pids {
+            let mut found: Vec<ContextRef> = recent
+                .window(kind, pid, from, to)
+                .into_iter()
+                .map(|e| ContextRef { table: kind.table().into(), event_uid: e.event_uid, ts: e.ts, pid })
+                .collect();
+
+            let cache_covers = recent.oldest(kind, pid).is_some_and(|oldest| oldest <= from);
+            if let Some(conn) = conn.filter(|_| found.len() < win.per_type && !cache_covers) {
+                let seen: HashSet<i64> = found.iter().map(|c| c.event_uid).collect();
+                for r in query_window(conn, kind, pid, (from, to), trigger.ts, win.per_type)? {
+                    if !seen.contains(&r.event_uid) {
+                        found.push(r);
+                    }
+                }
+            }
+
+            // Keep the N closest to the trigger.
+            found.sort_by_key(|c| (c.ts - trigger.ts).abs());
+            found.truncate(win.per_type);
+            out.extend(found);
+        }
+    }
+    out.sort_by_key(|c| (c.ts, c.event_uid));
+    Ok(out)
+}
+
+fn query_window(
+    conn: &Connection,
+    kind: EventKind,
+    pid: u32,
+    (from, to): (i64, i64),
+    around: i64,
+    limit: usize,
+) -> rusqlite::Result<Vec<ContextRef>> {
+    // Event tables are created by their writer on the first event.
+    if !table_exists(conn, kind.table())? {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        "SELECT event_uid, ts FROM {} \
+         WHERE pid = ?1 AND ts BETWEEN ?2 AND ?3 AND event_uid IS NOT NULL \
+         ORDER BY ABS(ts - ?4) LIMIT ?5",
+        kind.table()
+    );
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let rows = stmt.query_map(params![pid as i64, from, to, around, limit as i64], |r| {
+        Ok(ContextRef { table: kind.table().into(), event_uid: r.get(0)?, ts: r.get(1)?, pid })
+    })?;
+    rows.collect()
+}
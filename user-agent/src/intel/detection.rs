@@ -0,0 +1,370 @@
+// src/intel/detection.rs
+//! Configurable detection rules from `[detection]`, matched against every
+//! process creation, file operation and network connection on the intel
+//! buses.
+//!
+//! Process rules take regular expressions over the image path, command line
+//! and parent image, file rules a path glob and a set of operations, network rules CIDR
+//! ranges and ports. Each match stores one alert with rule id
+//! `detection.<id>`, so `[actions.rules]` can attach a response to it. The
+//! task re-reads `config.toml` every `reload_secs` and swaps in the new rules
+//! when the file changed and still loads; otherwise the old rules stay.
+
+use std::{
+    fs,
+    net::IpAddr,
+    path::PathBuf,
+    time::Duration,
+};
+use ipnet::IpNet;
+use metrics::counter;
+use regex::{Regex, RegexBuilder};
+use rusqlite::Connection;
+use tokio::{runtime::Runtime, task::JoinHandle};
+use shared::events::{file_event::Operation, FileEvent, NetworkEvent, ProcessEvent};
+
+use crate::comms::{intel_bus::Subscription, WrappedEvent};
+use crate::config::{
+    loader::parse,
+    model::{ConfigError, DetectionConfig},
+};
+use crate::db::{process_tree::parent_image, query::open_read_only};
+use crate::eventlog::{self, ALERT_TARGET};
+use crate::intel::{alerts::{Alert, AlertSink}, severity::{Fields, Severity, SeverityExpr}};
+use crate::probe::is_probe_event;
+use crate::util::Shutdown;
+
+/// Prefix of the rule ids of alerts raised here.
+pub const RULE_PREFIX: &str = "detection.";
+
+/// Id and severity shared by every kind of rule.
+#[derive(Debug)]
+struct Head {
+    rule_id:       String,
+    severity:      Severity,
+    severity_when: Option<SeverityExpr>,
+}
+
+impl Head {
+    fn new(kind: &str, id: &str, severity: Severity, when: &Option<String>) -> Result<Self, ConfigError> {
+        let severity_when = when
+            .as_deref()
+            .map(|w| w.parse().map_err(|e| invalid(kind, id, "severity_when", format!("{e}"))))
+            .transpose()?;
+        Ok(Self { rule_id: format!("{RULE_PREFIX}{id}"), severity, severity_when })
+    }
+
+    fn alert(&self, ev: &dyn Fields, ts: i64, pid: u32, ppid: Option<u32>, message: String, file: &str) -> Alert {
+        let severity = self.severity_when.as_ref().map_or(self.severity, |e| e.evaluate(ev, self.severity));
+        let file = (!file.is_empty()).then(|| file.to_string());
+        Alert { ts, rule_id: self.rule_id.clone(), severity, pid, ppid, message, file }
+    }
+}
+
+fn invalid(kind: &str, id: &str, key: &str, reason: String) -> ConfigError {
+    ConfigError::InvalidValue(format!("detection.{kind}[{id}].{key}"), reason)
+}
+
+fn regex(kind: &str, id: &str, key: &str, pattern: &str) -> Result<Regex, ConfigError> {
+    Regex::new(pattern).map_err(|e| invalid(kind, id, key, e.to_string()))
+}
+
+/// `*` matches any run of characters (path separators included), `?` one.
+pub(crate) fn glob(pattern: &str) -> Result<Regex, regex::Error> {
+    let mut re = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    re.push('$');
+    RegexBuilder::new(&re).case_insensitive(true).build()
+}
+
+#[derive(Debug)]
+struct ProcessMatcher {
+    head:    Head,
+    image:   Option<Regex>,
+    cmdline: Option<Regex>,
+    parent:  Option<Regex>,
+}
+
+#[derive(Debug)]
+struct FileMatcher {
+    head: Head,
+    path: Regex,
+    /// Empty matches every operation.
+    ops:  Vec<Operation>,
+}
+
+#[derive(Debug)]
+struct NetworkMatcher {
+    head:  Head,
+    dst:   Vec<IpNet>,
+    ports: Vec<u16>,
+}
+
+/// Compiled `[detection]` rules.
+#[derive(Debug, Default)]
+pub struct Detection {
+    process: Vec<ProcessMatcher>,
+    file:    Vec<FileMatcher>,
+    network: Vec<NetworkMatcher>,
+}
+
+impl Detection {
+    /// Compiles every rule; the first that does not compile (bad regex,
+    /// glob, operation or range, duplicate id) is reported.
+    pub fn compile(cfg: &DetectionConfig) -> Result<Self, ConfigError> {
+        let mut ids = std::collections::HashSet::new();
+        let mut unique = |kind: &str, id: &str| {
+            if id.is_empty() {
+                return Err(ConfigError::InvalidValue(format!("detection.{kind}"), "rule without an id".into()));
+            }
+            if !ids.insert(id.to_owned()) {
+                return Err(invalid(kind, id, "id", "used by another rule".into()));
+            }
+            Ok(())
+        };
+
+        let mut out = Detection::default();
+        for r in &cfg.process {
+            unique("process", &r.id)?;
+            if r.image.is_none() && r.cmdline.is_none() && r.parent_image.is_none() {
+                return Err(invalid("process", &r.id, "image", "none of image, cmdline or parent_image is set".into()));
+            }
+            out.process.push(ProcessMatcher {
+                head:    Head::new("process", &r.id, r.severity, &r.severity_when)?,
+                image:   r.image.as_deref().map(|p| regex("process", &r.id, "image", p)).transpose()?,
+                cmdline: r.cmdline.as_deref().map(|p| regex("process", &r.id, "cmdline", p)).transpose()?,
+                parent:  r.parent_image.as_deref().map(|p| regex("process", &r.id, "parent_image", p)).transpose()?,
+            });
+        }
+        for r in &cfg.file {
+            unique("file", &r.id)?;
+            let ops = r
+                .ops
+                .iter()
+                .map(|op| {
+                    Operation::from_str_name(&op.to_uppercase())
+                        .ok_or_else(|| invalid("file", &r.id, "ops", format!("unknown operation '{op}'")))
+                })
+                .collect::<Result<_, _>>()?;
+            out.file.push(FileMatcher {
+                head: Head::new("file", &r.id, r.severity, &r.severity_when)?,
+                path: glob(&r.path).map_err(|e| invalid("file", &r.id, "path", e.to_string()))?,
+                ops,
+            });
+        }
+        for r in &cfg.network {
+            unique("network", &r.id)?;
+            let dst = r
+                .dst
+                .iter()
+                .map(|d| {
+                    d.parse::<IpNet>()
+                        .or_else(|_| d.parse::<IpAddr>().map(IpNet::from))
+                        .map_err(|_| invalid("network", &r.id, "dst", format!("'{d}' is not an address or range")))
+                })
+                .collect::<Result<_, _>>()?;
+            out.network.push(NetworkMatcher {
+                head:  Head::new("network", &r.id, r.severity, &r.severity_when)?,
+                dst,
+                ports: r.ports.clone(),
+            });
+        }
+        Ok(out)
+    }
+
+    /// Number of rules.
+    pub fn len(&self) -> usize {
+        self.process.len() + self.file.len() + self.network.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether a process rule looks at the parent image, which the event
+    /// may lack (see [`with_parent_image`]).
+    pub fn needs_parent(&self) -> bool {
+        self.process.iter().any(|m| m.parent.is_some())
+    }
+
+    /// Alerts for a process creation, one per matching rule. `parent_image`
+    /// rules match on `parent_image_path`; an empty one matches none.
+    pub fn check_process(&self, ev: &WrappedEvent<ProcessEvent>) -> Vec<Alert> {
+        let p = &ev.payload;
+        self.process
+            .iter()
+            .filter(|m| m.image.as_ref().is_none_or(|re| re.is_match(&p.image_path)))
+            .filter(|m| m.cmdline.as_ref().is_none_or(|re| re.is_match(&p.cmdline)))
+            .filter(|m| m.parent.as_ref().is_none_or(|re| !p.parent_image_path.is_empty() && re.is_match(&p.parent_image_path)))
+            .map(|m| {
+                let message = format!("{} (pid {}) started: {}", p.image_path, p.pid, p.cmdline);
+                m.head.alert(p, ev.ts_micros(), p.pid, Some(p.ppid), message, &p.image_path)
+            })
+            .collect()
+    }
+
+    /// Alerts for a file operation, one per matching rule. A rename matches
+    /// on either name.
+    pub fn check_file(&self, ev: &WrappedEvent<FileEvent>) -> Vec<Alert> {
+        let f = &ev.payload;
+        let Ok(op) = Operation::try_from(f.op) else { return Vec::new() };
+        self.file
+            .iter()
+            .filter(|m| m.ops.is_empty() || m.ops.contains(&op))
+            .filter(|m| m.path.is_match(&f.path) || (!f.new_path.is_empty() && m.path.is_match(&f.new_path)))
+            .map(|m| {
+                let target = if f.new_path.is_empty() { f.path.clone() } else { format!("{} -> {}", f.path, f.new_path) };
+                let message = format!("{} {} by {} (pid {})", op.as_str_name(), target, f.exe_path, f.pid);
+                // A renamed file is found under its new name.
+                let file = if f.new_path.is_empty() { &f.path } else { &f.new_path };
+                m.head.alert(f, ev.ts_micros(), f.pid, None, message, file)
+            })
+            .collect()
+    }
+
+    /// Alerts for a network connection, one per matching rule.
+    pub fn check_network(&self, ev: &WrappedEvent<NetworkEvent>) -> Vec<Alert> {
+        let n = &ev.payload;
+        let dst = n.dst_ip.parse::<IpAddr>().ok();
+        self.network
+            .iter()
+            .filter(|m| m.dst.is_empty() || dst.is_some_and(|ip| m.dst.iter().any(|net| net.contains(&ip))))
+            .filter(|m| m.ports.is_empty() || u16::try_from(n.dst_port).is_ok_and(|p| m.ports.contains(&p)))
+            .map(|m| {
+                let message = format!(
+                    "{} (pid {}) connected to {}:{} over {}",
+                    n.exe_path, n.pid, n.dst_ip, n.dst_port, n.proto,
+                );
+                m.head.alert(n, ev.ts_micros(), n.pid, None, message, &n.exe_path)
+            })
+            .collect()
+    }
+}
+
+/// `ev` with `parent_image_path` filled from the parent's stored creation
+/// when the driver left it empty. Unchanged when the parent was not stored
+/// (started before the agent, or not flushed yet).
+pub fn with_parent_image(conn: &Connection, ev: &mut WrappedEvent<ProcessEvent>) {
+    let ts = ev.ts_micros();
+    let p = &mut ev.payload;
+    if !p.parent_image_path.is_empty() {
+        return;
+    }
+    match parent_image(conn, p.ppid, ts) {
+        Ok(Some(image)) => p.parent_image_path = image,
+        Ok(None) => {}
+        Err(e) => log::debug!("detection: parent of pid {} not looked up: {}", p.pid, e),
+    }
+}
+
+/// Where the rules are re-read from.
+#[derive(Debug, Clone)]
+pub struct RuleSource {
+    pub config: PathBuf,
+    pub period: Duration,
+}
+
+/// Rules from `config.toml` as it is now, or `None` when it is unchanged
+/// since `last` or does not load.
+fn reload(source: &RuleSource, last: &mut String) -> Option<Detection> {
+    let text = match fs::read_to_string(&source.config) {
+        Ok(t) if t == *last => return None,
+        Ok(t) => t,
+        Err(e) => {
+            log::warn!("detection: cannot read {}: {}", source.config.display(), e);
+            return None;
+        }
+    };
+    *last = text;
+    match parse(last) {
+        Ok(cfg) => Detection::compile(&cfg.detection).ok(),
+        Err(e) => {
+            log::warn!("detection: {} not reloaded, keeping the current rules: {}", source.config.display(), e);
+            None
+        }
+    }
+}
+
+/// The buses the rules are matched against, best subscribed with
+/// [`TokioBuses::subscribe_with_replay`](crate::comms::intel_bus::TokioBuses::subscribe_with_replay)
+/// so a restarted engine also sees the events just before it.
+pub struct DetectionBuses {
+    pub process: Subscription<ProcessEvent>,
+    pub file:    Subscription<FileEvent>,
+    pub network: Subscription<NetworkEvent>,
+}
+
+/// Follows `buses` and stores an alert for every rule match, then runs the
+/// configured action. Probe traffic and process exits are ignored. With a
+/// `source`, rule changes in it are picked up without a restart. Ends once
+/// every bus is closed or on `shutdown`.
+pub fn spawn_detection(
+    rt: &Runtime,
+    buses: DetectionBuses,
+    mut rules: Detection,
+    source: Option<RuleSource>,
+    db_path: PathBuf,
+    sink: AlertSink,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    rt.spawn(async move {
+        let DetectionBuses { process: mut processes, file: mut files, mut network } = buses;
+        let (mut procs_open, mut files_open, mut net_open) = (true, true, true);
+        let mut last = source.as_ref().and_then(|s| fs::read_to_string(&s.config).ok()).unwrap_or_default();
+        // Opened on the first rule over a parent image.
+        let mut reader: Option<Option<Connection>> = None;
+        let mut ticker = tokio::time::interval(source.as_ref().map_or(Duration::from_secs(3_600), |s| s.period));
+        ticker.tick().await;
+
+        while procs_open || files_open || net_open {
+            let alerts = tokio::select! {
+                _ = shutdown.triggered() => break,
+                _ = ticker.tick(), if source.is_some() => {
+                    if let Some(new) = source.as_ref().and_then(|s| reload(s, &mut last)) {
+                        log::info!("detection: reloaded {} rules", new.len());
+                        rules = new;
+                    }
+                    continue;
+                }
+                ev = processes.recv(), if procs_open => match ev {
+                    Some(ev) if is_probe_event(&ev.payload) || ev.payload.is_exit() => continue,
+                    Some(mut ev) => {
+                        if rules.needs_parent() {
+                            let conn = reader.get_or_insert_with(|| open_read_only(&db_path).ok());
+                            if let Some(conn) = conn {
+                                with_parent_image(conn, &mut ev);
+                            }
+                        }
+                        rules.check_process(&ev)
+                    }
+                    None => { procs_open = false; continue; }
+                },
+                ev = files.recv(), if files_open => match ev {
+                    Some(ev) if is_probe_event(&ev.payload) => continue,
+                    Some(ev) => rules.check_file(&ev),
+                    None => { files_open = false; continue; }
+                },
+                ev = network.recv(), if net_open => match ev {
+                    Some(ev) => rules.check_network(&ev),
+                    None => { net_open = false; continue; }
+                },
+            };
+            for alert in alerts {
+                store(alert, &sink).await;
+            }
+        }
+    })
+}
+
+async fn store(alert: Alert, sink: &AlertSink) {
+    counter!("alerts_raised_total", "rule" => alert.rule_id.clone()).increment(1);
+    log::warn!(target: ALERT_TARGET, "{}: {}", alert.rule_id, alert.message);
+    eventlog::report_alert(&alert);
+    sink.store(alert).await;
+}
@@ -0,0 +1,334 @@
+// src/intel/dns.rs
+//! Host names of the remote addresses of network events.
+//!
+//! Network events carry raw IPs. [`DnsNames`] fills `dst_hostname` from a
+//! cache of reverse lookups as the event is read, and never waits for one:
+//! an address not in the cache is queued for a PTR lookup and the event goes
+//! on without a name, so later events to the same address get it. Lookups
+//! run in the background, at most `dns.lookups_per_sec` started per second
+//! and each given [`LOOKUP_TIMEOUT`]; those over the budget are dropped and
+//! asked again by a later event. Names are kept `dns.ttl_secs`, addresses
+//! without one `dns.negative_ttl_secs`, the least recently used going first
+//! once `dns.cache_entries` are held.
+//!
+//! Counted in `dns_cache_lookups_total{result}` (`hit`, `negative`, `miss`)
+//! and `dns_lookups_total{result}` (`name`, `no_name`, `failed`,
+//! `timed_out`, `over_budget`, `queue_full`).
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use async_trait::async_trait;
+use metrics::counter;
+use shared::events::NetworkEvent;
+use tokio::{sync::mpsc, task::{self, JoinHandle}, time::timeout};
+
+use crate::comms::{rate_limit::TokenBucket, WrappedEvent};
+use crate::config::model::{DnsConfig, PayloadLimit};
+use crate::intel::enrich::{filled, Enricher};
+
+/// How long one reverse lookup may take.
+pub const LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+/// Addresses waiting for a lookup; more are dropped until there is room.
+const QUEUE: usize = 256;
+
+/// Answers reverse lookups.
+#[async_trait]
+pub trait Resolver: Send + Sync + 'static {
+    /// Name of `ip` from its PTR record; `None` when it has none.
+    async fn reverse(&self, ip: IpAddr) -> io::Result<Option<String>>;
+}
+
+/// The resolver of this machine (`GetNameInfoW`), on the blocking pool.
+/// Off Windows every lookup fails with `ErrorKind::Unsupported`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+#[async_trait]
+impl Resolver for SystemResolver {
+    async fn reverse(&self, ip: IpAddr) -> io::Result<Option<String>> {
+        task::spawn_blocking(move || sys::reverse(ip)).await.map_err(io::Error::other)?
+    }
+}
+
+/// What the cache knows of an address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Known {
+    Name(String),
+    /// Looked up without finding a name.
+    NoName,
+    /// Never looked up, or the answer expired.
+    Unknown,
+}
+
+/// Reverse lookups answered, by address, least recently used first out.
+/// Callers pass the current time, so expiry can be driven by a fake clock.
+#[derive(Debug)]
+pub struct DnsCache {
+    /// Name (or none), expiry and the tick of the last use.
+    entries:      HashMap<IpAddr, (Option<String>, Instant, u64)>,
+    /// Use order as (address, tick); stale after an address is used again.
+    order:        VecDeque<(IpAddr, u64)>,
+    tick:         u64,
+    max:          usize,
+    ttl:          Duration,
+    negative_ttl: Duration,
+}
+
+impl DnsCache {
+    pub fn new(config: &DnsConfig) -> Self {
+        Self {
+            entries:      HashMap::new(),
+            order:        VecDeque::new(),
+            tick:         0,
+            max:          config.cache_entries,
+            ttl:          Duration::from_secs(config.ttl_secs),
+            negative_ttl: Duration::from_secs(config.negative_ttl_secs),
+        }
+    }
+
+    pub fn get(&mut self, ip: IpAddr, now: Instant) -> Known {
+        let Some((name, expires, tick)) = self.entries.get_mut(&ip) else { return Known::Unknown };
+        if *expires <= now {
+            self.entries.remove(&ip);
+            return Known::Unknown;
+        }
+        self.tick += 1;
+        *tick = self.tick;
+        let known = name.clone().map_or(Known::NoName, Known::Name);
+        self.order.push_back((ip, self.tick));
+        self.trim();
+        known
+    }
+
+    /// Records the answer for `ip`: a name, or `None` when it has none.
+    pub fn insert(&mut self, ip: IpAddr, name: Option<String>, now: Instant) {
+        if self.max == 0 {
+            return;
+        }
+        let expires = now + if name.is_some() { self.ttl } else { self.negative_ttl };
+        self.tick += 1;
+        self.order.push_back((ip, self.tick));
+        self.entries.insert(ip, (name, expires, self.tick));
+        while self.entries.len() > self.max {
+            let Some((old, tick)) = self.order.pop_front() else { break };
+            if self.entries.get(&old).is_some_and(|(_, _, t)| *t == tick) {
+                self.entries.remove(&old);
+            }
+        }
+        self.trim();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drops order entries left behind by hits.
+    fn trim(&mut self) {
+        if self.order.len() > 2 * self.max {
+            let entries = &self.entries;
+            self.order.retain(|(ip, tick)| entries.get(ip).is_some_and(|(_, _, t)| t == tick));
+        }
+    }
+}
+
+/// Fills `NetworkEvent::dst_hostname` from the cache and queues lookups for
+/// addresses it does not know. Clones share the cache and the queue.
+#[derive(Debug, Clone)]
+pub struct DnsNames {
+    cache:   Arc<Mutex<DnsCache>>,
+    /// Addresses queued or being looked up, not to be queued again.
+    pending: Arc<Mutex<HashSet<IpAddr>>>,
+    lookups: mpsc::Sender<IpAddr>,
+}
+
+impl DnsNames {
+    /// Starts the lookups on the current runtime. The task ends once every
+    /// clone of the returned enricher is dropped.
+    pub fn spawn(config: &DnsConfig, resolver: Arc<dyn Resolver>) -> (Self, JoinHandle<()>) {
+        let (lookups, rx) = mpsc::channel(QUEUE);
+        let names = Self {
+            cache:   Arc::new(Mutex::new(DnsCache::new(config))),
+            pending: Arc::new(Mutex::new(HashSet::new())),
+            lookups,
+        };
+        let limit = PayloadLimit { per_sec: config.lookups_per_sec, burst: config.lookups_per_sec };
+        let budget = TokenBucket::new(limit, Instant::now());
+        let handle = tokio::spawn(look_up(rx, names.cache.clone(), names.pending.clone(), resolver, budget));
+        (names, handle)
+    }
+
+    /// Records a name learned otherwise, such as from a forward resolution.
+    pub fn record(&self, ip: IpAddr, name: &str) {
+        self.cache.lock().unwrap().insert(ip, Some(name.to_owned()), Instant::now());
+    }
+
+    /// What the cache knows of `ip` now.
+    pub fn known(&self, ip: IpAddr) -> Known {
+        self.cache.lock().unwrap().get(ip, Instant::now())
+    }
+
+    fn queue(&self, ip: IpAddr) {
+        if !self.pending.lock().unwrap().insert(ip) {
+            return;
+        }
+        if self.lookups.try_send(ip).is_err() {
+            self.pending.lock().unwrap().remove(&ip);
+            counter!("dns_lookups_total", "result" => "queue_full").increment(1);
+        }
+    }
+}
+
+/// Loopback and the like have no name worth asking for.
+fn worth_naming(ip: IpAddr) -> bool {
+    !(ip.is_unspecified() || ip.is_loopback() || ip.is_multicast())
+}
+
+impl Enricher<NetworkEvent> for DnsNames {
+    fn enrich(&self, ev: &mut WrappedEvent<NetworkEvent>) {
+        if !ev.payload.dst_hostname.is_empty() {
+            return;
+        }
+        let Some(ip) = ev.payload.dst_ip.parse().ok().filter(|ip| worth_naming(*ip)) else { return };
+        match self.known(ip) {
+            Known::Name(name) => {
+                counter!("dns_cache_lookups_total", "result" => "hit").increment(1);
+                ev.payload.dst_hostname = name;
+                filled(ev, "dst_hostname", "dns_cache");
+            }
+            Known::NoName => counter!("dns_cache_lookups_total", "result" => "negative").increment(1),
+            Known::Unknown => {
+                counter!("dns_cache_lookups_total", "result" => "miss").increment(1);
+                self.queue(ip);
+            }
+        }
+    }
+}
+
+/// Looks up the queued addresses within `budget`, each in a task of its own.
+async fn look_up(
+    mut rx: mpsc::Receiver<IpAddr>,
+    cache: Arc<Mutex<DnsCache>>,
+    pending: Arc<Mutex<HashSet<IpAddr>>>,
+    resolver: Arc<dyn Resolver>,
+    mut budget: TokenBucket,
+) {
+    while let Some(ip) = rx.recv().await {
+        if !budget.take(Instant::now()) {
+            pending.lock().unwrap().remove(&ip);
+            counter!("dns_lookups_total", "result" => "over_budget").increment(1);
+            continue;
+        }
+        let (cache, pending, resolver) = (cache.clone(), pending.clone(), resolver.clone());
+        tokio::spawn(async move {
+            let (name, result) = match timeout(LOOKUP_TIMEOUT, resolver.reverse(ip)).await {
+                Ok(Ok(Some(name))) => (Some(name), "name"),
+                Ok(Ok(None)) => (None, "no_name"),
+                Ok(Err(e)) => {
+                    log::debug!("reverse lookup of {} failed: {}", ip, e);
+                    (None, "failed")
+                }
+                Err(_) => (None, "timed_out"),
+            };
+            counter!("dns_lookups_total", "result" => result).increment(1);
+            cache.lock().unwrap().insert(ip, name, Instant::now());
+            pending.lock().unwrap().remove(&ip);
+        });
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{ffi::c_void, io, mem::size_of, net::IpAddr, sync::Once};
+
+    const AF_INET: u16 = 2;
+    const AF_INET6: u16 = 23;
+    /// Fail rather than return the address as text.
+    const NI_NAMEREQD: i32 = 0x04;
+    const NI_MAXHOST: usize = 1025;
+    const WSAHOST_NOT_FOUND: i32 = 11001;
+    const WSANO_DATA: i32 = 11004;
+
+    #[repr(C)]
+    struct SockaddrIn {
+        family: u16,
+        port:   u16,
+        addr:   [u8; 4],
+        zero:   [u8; 8],
+    }
+
+    #[repr(C)]
+    struct SockaddrIn6 {
+        family:   u16,
+        port:     u16,
+        flowinfo: u32,
+        addr:     [u8; 16],
+        scope_id: u32,
+    }
+
+    #[link(name = "ws2_32")]
+    unsafe extern "system" {
+        fn WSAStartup(version: u16, data: *mut u8) -> i32;
+        fn GetNameInfoW(
+            addr: *const c_void,
+            addr_len: i32,
+            host: *mut u16,
+            host_len: u32,
+            serv: *mut u16,
+            serv_len: u32,
+            flags: i32,
+        ) -> i32;
+    }
+
+    /// Name of `ip` from its PTR record.
+    pub fn reverse(ip: IpAddr) -> io::Result<Option<String>> {
+        static STARTED: Once = Once::new();
+        STARTED.call_once(|| {
+            // WSADATA, larger than any layout of it; never read.
+            let mut data = [0u8; 512];
+            // SAFETY: the buffer outlives the call. Winsock 2.2.
+            unsafe { WSAStartup(0x0202, data.as_mut_ptr()) };
+        });
+        let mut host = vec![0u16; NI_MAXHOST];
+        let mut lookup = |addr: *const c_void, len: usize| {
+            // SAFETY: `addr` points at a sockaddr of `len` bytes; `host` is
+            // NI_MAXHOST characters and no service is asked for.
+            unsafe { GetNameInfoW(addr, len as i32, host.as_mut_ptr(), NI_MAXHOST as u32, std::ptr::null_mut(), 0, NI_NAMEREQD) }
+        };
+        let status = match ip {
+            IpAddr::V4(v4) => {
+                let addr = SockaddrIn { family: AF_INET, port: 0, addr: v4.octets(), zero: [0; 8] };
+                lookup(&addr as *const _ as *const c_void, size_of::<SockaddrIn>())
+            }
+            IpAddr::V6(v6) => {
+                let addr = SockaddrIn6 { family: AF_INET6, port: 0, flowinfo: 0, addr: v6.octets(), scope_id: 0 };
+                lookup(&addr as *const _ as *const c_void, size_of::<SockaddrIn6>())
+            }
+        };
+        match status {
+            0 => {
+                let len = host.iter().position(|&c| c == 0).unwrap_or(host.len());
+                Ok(Some(String::from_utf16_lossy(&host[..len])))
+            }
+            WSAHOST_NOT_FOUND | WSANO_DATA => Ok(None),
+            code => Err(io::Error::from_raw_os_error(code)),
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use std::{io, net::IpAddr};
+
+    pub fn reverse(_ip: IpAddr) -> io::Result<Option<String>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "reverse lookups are only available on Windows"))
+    }
+}
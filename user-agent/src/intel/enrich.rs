@@ -0,0 +1,210 @@
+// src/intel/enrich.rs
+//! Enrichments of telemetry.
+//!
+//! Per event, an [`Enricher`] fills payload fields the sensor left empty as
+//! the event is read, such as the image path of a file or network event
+//! that only carries a pid ([`ExePath`]). The SHA-256 of written files is
+//! filled in later, on the way to the database, as reading them can block
+//! ([`file_hash`](crate::intel::file_hash)).
+//!
+//! Per row, columns computed from stored telemetry: normalised image paths
+//! and the account behind ETW user SIDs. The writers fill these columns for
+//! new events; each enrichment also exposes a [`Backfill`] so rows written
+//! before it shipped get upgraded by the reprocessor.
+
+use std::collections::BTreeMap;
+use metrics::counter;
+use prost::Message;
+use rusqlite::{params, Connection};
+use shared::events::{FileEvent, NetworkEvent};
+
+use crate::comms::{HasPid, WrappedEvent};
+use crate::db::{codec::StoredText, reprocess::Backfill};
+use crate::intel::process_table::ProcessTable;
+
+/// Fills in fields of events of type `E` after they are read and before
+/// anything else sees them (see `RingListener::enriched`).
+pub trait Enricher<E: Clone>: Send + Sync + 'static {
+    fn enrich(&self, ev: &mut WrappedEvent<E>);
+}
+
+/// Records that `field` of `ev` was filled in from `source`.
+pub(crate) fn filled<E: Clone>(ev: &mut WrappedEvent<E>, field: &'static str, source: &'static str) {
+    ev.enrichment.get_or_insert_with(BTreeMap::new).insert(field, source);
+}
+
+/// Payloads naming the image of the process they are attributed to.
+pub trait HasExePath: HasPid {
+    fn exe_path_mut(&mut self) -> &mut String;
+}
+
+impl HasExePath for FileEvent    { fn exe_path_mut(&mut self) -> &mut String { &mut self.exe_path } }
+impl HasExePath for NetworkEvent { fn exe_path_mut(&mut self) -> &mut String { &mut self.exe_path } }
+
+/// Fills an empty `exe_path` with the image of the event's pid: from the
+/// processes seen created, or else from the process if it is still running.
+/// Counted in `process_cache_lookups_total{result}` (`hit`, `live`, `miss`).
+#[derive(Debug, Clone)]
+pub struct ExePath {
+    processes: ProcessTable,
+}
+
+impl ExePath {
+    pub fn new(processes: ProcessTable) -> Self {
+        Self { processes }
+    }
+}
+
+impl<E: HasExePath + Message + Clone> Enricher<E> for ExePath {
+    fn enrich(&self, ev: &mut WrappedEvent<E>) {
+        let pid = ev.payload.pid();
+        if pid == 0 || !ev.payload.exe_path_mut().is_empty() {
+            return;
+        }
+        // A process created after the event has reused the pid.
+        let cached = self.processes.get(pid).filter(|p| p.ts <= ev.ts_micros() && !p.image_path.is_empty());
+        let (path, source) = if let Some(process) = cached {
+            counter!("process_cache_lookups_total", "result" => "hit").increment(1);
+            (process.image_path, "process_cache")
+        } else if let Some(path) = sys::image_path(pid) {
+            counter!("process_cache_lookups_total", "result" => "live").increment(1);
+            (path, "live_process")
+        } else {
+            counter!("process_cache_lookups_total", "result" => "miss").increment(1);
+            return;
+        };
+        *ev.payload.exe_path_mut() = path;
+        filled(ev, "exe_path", source);
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{ffi::c_void, os::windows::ffi::OsStringExt};
+
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    /// Long enough for `\\?\` paths.
+    const MAX_PATH_CHARS: usize = 32_768;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn QueryFullProcessImageNameW(process: *mut c_void, flags: u32, name: *mut u16, len: *mut u32) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    /// Win32 path of the image of running process `pid`.
+    pub fn image_path(pid: u32) -> Option<String> {
+        // SAFETY: plain call; a null handle is checked below.
+        let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+        if process.is_null() {
+            return None;
+        }
+        let mut name = vec![0u16; MAX_PATH_CHARS];
+        let mut len = name.len() as u32;
+        // SAFETY: valid handle; `len` is the buffer size in characters. The
+        // handle is closed once.
+        let ok = unsafe { QueryFullProcessImageNameW(process, 0, name.as_mut_ptr(), &mut len) } != 0;
+        unsafe { CloseHandle(process) };
+        ok.then(|| std::ffi::OsString::from_wide(&name[..len as usize]).to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(not(windows))]
+mod sys {
+    pub fn image_path(pid: u32) -> Option<String> {
+        std::fs::read_link(format!("/proc/{pid}/exe")).ok().map(|p| p.to_string_lossy().into_owned())
+    }
+}
+
+/// `\SystemRoot` as seen in kernel image paths.
+const SYSTEM_ROOT: &str = r"c:\windows";
+
+/// Lower-cased DOS form of a kernel or Win32 path: `\??\` and `\\?\`
+/// prefixes are dropped, `\SystemRoot` is expanded and `/` becomes `\`.
+/// `\Device\HarddiskVolumeN` paths are kept as they are. An alternate data
+/// stream suffix (`file.txt:payload`) survives unchanged apart from case.
+pub fn normalize_path(path: &str) -> String {
+    let path = path.trim().replace('/', "\\");
+    let lower = path.to_lowercase();
+    let rest = [r"\??\", r"\\?\"]
+        .iter()
+        .find_map(|p| lower.strip_prefix(p))
+        .unwrap_or(&lower);
+    match rest.strip_prefix(r"\systemroot") {
+        Some(tail) if tail.is_empty() || tail.starts_with('\\') => format!("{SYSTEM_ROOT}{tail}"),
+        _ => rest.to_owned(),
+    }
+}
+
+/// Payload fields that carry the SID of the acting user, most specific first.
+const SID_FIELDS: &[&str] = &["UserSid", "SubjectUserSid", "TargetUserSid", "Sid", "UserID"];
+
+/// First user SID found in an ETW JSON payload.
+pub fn extract_sid(json_payload: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(json_payload).ok()?;
+    SID_FIELDS
+        .iter()
+        .filter_map(|f| value.get(f)?.as_str())
+        .find(|s| s.starts_with("S-1-"))
+        .map(str::to_owned)
+}
+
+/// Account name for well-known SIDs. Domain and local accounts need the
+/// host's LSA and stay unresolved.
+pub fn resolve_sid(sid: &str) -> Option<&'static str> {
+    Some(match sid {
+        "S-1-5-18" => r"NT AUTHORITY\SYSTEM",
+        "S-1-5-19" => r"NT AUTHORITY\LOCAL SERVICE",
+        "S-1-5-20" => r"NT AUTHORITY\NETWORK SERVICE",
+        "S-1-5-7"  => r"NT AUTHORITY\ANONYMOUS LOGON",
+        "S-1-5-32-544" => r"BUILTIN\Administrators",
+        "S-1-5-32-545" => r"BUILTIN\Users",
+        "S-1-1-0"  => "Everyone",
+        _ => return None,
+    })
+}
+
+/// `process_events.image_path` → `image_path_norm`.
+pub const PATH_NORMALIZATION: Backfill = Backfill {
+    name:    "path-normalization",
+    table:   "process_events",
+    columns: &[("image_path_norm", "TEXT")],
+    pending: "image_path_norm IS NULL AND image_path IS NOT NULL",
+    apply:   backfill_paths,
+};
+
+fn backfill_paths(conn: &Connection, ids: &[i64]) -> rusqlite::Result<usize> {
+    let mut read = conn.prepare_cached("SELECT image_path FROM process_events WHERE id = ?1")?;
+    let mut write = conn.prepare_cached("UPDATE process_events SET image_path_norm = ?1 WHERE id = ?2")?;
+    let mut updated = 0;
+    for &id in ids {
+        let StoredText(path) = read.query_row([id], |r| r.get(0))?;
+        updated += write.execute(params![normalize_path(&path), id])?;
+    }
+    Ok(updated)
+}
+
+/// `etw_events.json_payload` → `user_sid` and `user_name`. Events without a
+/// SID get an empty `user_sid` so they are not selected again.
+pub const SID_RESOLUTION: Backfill = Backfill {
+    name:    "sid-resolution",
+    table:   "etw_events",
+    columns: &[("user_sid", "TEXT"), ("user_name", "TEXT")],
+    pending: "user_sid IS NULL",
+    apply:   backfill_sids,
+};
+
+fn backfill_sids(conn: &Connection, ids: &[i64]) -> rusqlite::Result<usize> {
+    let mut read = conn.prepare_cached("SELECT json_payload FROM etw_events WHERE id = ?1")?;
+    let mut write = conn.prepare_cached(
+        "UPDATE etw_events SET user_sid = ?1, user_name = ?2 WHERE id = ?3",
+    )?;
+    let mut updated = 0;
+    for &id in ids {
+        let payload: Option<StoredText> = read.query_row([id], |r| r.get(0))?;
+        let sid = payload.and_then(|StoredText(p)| extract_sid(&p)).unwrap_or_default();
+        updated += write.execute(params![&sid, resolve_sid(&sid), id])?;
+    }
+    Ok(updated)
+}
@@ -0,0 +1,210 @@
+// src/intel/file_hash.rs
+//! SHA-256 of small files, filled into file events before they are stored.
+//!
+//! The sensor reports what happened to a file, not what is in it, so hash
+//! rules had nothing to match on. For a successful create, write or rename
+//! whose file is still there and at most `file_hash.max_bytes`, a
+//! [`FileHasher`] reads the file on the blocking pool and fills
+//! `FileEvent::sha256`. Events it cannot hash go on unchanged and are
+//! counted in `file_hash_skipped_total{reason}`. Digests are remembered by
+//! path, modification time and length, so a file written again without
+//! changing is not read again.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use futures::StreamExt;
+use metrics::counter;
+use shared::events::{file_event::Operation, FileEvent};
+use tokio::{sync::mpsc, task::{self, JoinHandle}};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::comms::WrappedEvent;
+use crate::config::model::FileHashConfig;
+use crate::db::hub::DbSender;
+use crate::intel::enrich::filled;
+use crate::scanner::hash::compute_sha256;
+
+/// A file as last seen: path, modification time and length.
+type Version = (String, SystemTime, u64);
+
+/// Why an event was left without a hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Skip {
+    /// Gone before it could be read.
+    Missing,
+    /// Locked or denied.
+    Unreadable,
+    NotAFile,
+    TooBig,
+    /// Written again while it was read.
+    Changed,
+}
+
+impl Skip {
+    fn as_str(self) -> &'static str {
+        match self {
+            Skip::Missing => "missing",
+            Skip::Unreadable => "unreadable",
+            Skip::NotAFile => "not_a_file",
+            Skip::TooBig => "too_big",
+            Skip::Changed => "changed",
+        }
+    }
+
+    fn io(e: &io::Error) -> Self {
+        if e.kind() == io::ErrorKind::NotFound { Skip::Missing } else { Skip::Unreadable }
+    }
+}
+
+/// Least recently used digests, by file version.
+#[derive(Debug)]
+struct Digests {
+    entries: HashMap<Version, ([u8; 32], u64)>,
+    /// Use order as (version, tick); stale after a version is used again.
+    order:   VecDeque<(Version, u64)>,
+    tick:    u64,
+    max:     usize,
+}
+
+impl Digests {
+    fn get(&mut self, version: &Version) -> Option<[u8; 32]> {
+        self.tick += 1;
+        let (digest, tick) = self.entries.get_mut(version)?;
+        *tick = self.tick;
+        self.order.push_back((version.clone(), self.tick));
+        let digest = *digest;
+        self.trim();
+        Some(digest)
+    }
+
+    fn insert(&mut self, version: Version, digest: [u8; 32]) {
+        if self.max == 0 {
+            return;
+        }
+        self.tick += 1;
+        self.order.push_back((version.clone(), self.tick));
+        self.entries.insert(version, (digest, self.tick));
+        while self.entries.len() > self.max {
+            let Some((old, tick)) = self.order.pop_front() else { break };
+            if self.entries.get(&old).is_some_and(|(_, t)| *t == tick) {
+                self.entries.remove(&old);
+            }
+        }
+        self.trim();
+    }
+
+    /// Drops order entries left behind by hits.
+    fn trim(&mut self) {
+        if self.order.len() > 2 * self.max {
+            let entries = &self.entries;
+            self.order.retain(|(v, tick)| entries.get(v).is_some_and(|(_, t)| t == tick));
+        }
+    }
+}
+
+/// Fills `FileEvent::sha256` from the file on disk. Clones share the
+/// remembered digests.
+#[derive(Debug, Clone)]
+pub struct FileHasher {
+    max_bytes: u64,
+    threads:   usize,
+    digests:   Arc<Mutex<Digests>>,
+}
+
+impl FileHasher {
+    pub fn new(config: &FileHashConfig) -> Self {
+        let digests = Digests { entries: HashMap::new(), order: VecDeque::new(), tick: 0, max: config.cache_entries };
+        Self { max_bytes: config.max_bytes, threads: config.threads.max(1), digests: Arc::new(Mutex::new(digests)) }
+    }
+
+    /// Hashes the file `ev` left behind, if it is one to hash; otherwise,
+    /// or when it cannot be read, `ev` is left as it was.
+    pub async fn enrich(&self, ev: &mut WrappedEvent<FileEvent>) {
+        let Some(path) = written(&ev.payload) else { return };
+        match self.digest(path.to_owned()).await {
+            Ok((digest, source)) => {
+                ev.payload.sha256 = digest.to_vec();
+                filled(ev, "sha256", source);
+            }
+            Err(skip) => counter!("file_hash_skipped_total", "reason" => skip.as_str()).increment(1),
+        }
+    }
+
+    /// The digest of the file at `path`, with where it came from.
+    async fn digest(&self, path: String) -> Result<([u8; 32], &'static str), Skip> {
+        let version = self.version(path)?;
+        if let Some(digest) = self.digests.lock().unwrap().get(&version) {
+            return Ok((digest, "hash_cache"));
+        }
+        let file = PathBuf::from(&version.0);
+        let digest = task::spawn_blocking(move || compute_sha256(&file))
+            .await
+            .map_err(|_| Skip::Unreadable)?
+            .map_err(|e| Skip::io(&e))?;
+        // Read across a write: the digest is of neither version.
+        if self.version(version.0.clone())? != version {
+            return Err(Skip::Changed);
+        }
+        self.digests.lock().unwrap().insert(version, digest);
+        Ok((digest, "file_contents"))
+    }
+
+    fn version(&self, path: String) -> Result<Version, Skip> {
+        let meta = fs::metadata(&path).map_err(|e| Skip::io(&e))?;
+        if !meta.is_file() {
+            return Err(Skip::NotAFile);
+        }
+        if meta.len() > self.max_bytes {
+            return Err(Skip::TooBig);
+        }
+        let modified = meta.modified().map_err(|_| Skip::Unreadable)?;
+        Ok((path, modified, meta.len()))
+    }
+}
+
+/// Where the file written by `ev` now is; `None` for events not to hash.
+fn written(ev: &FileEvent) -> Option<&str> {
+    if !ev.success || !ev.sha256.is_empty() {
+        return None;
+    }
+    match Operation::try_from(ev.op) {
+        Ok(Operation::Create | Operation::Write) => Some(&ev.path),
+        Ok(Operation::Rename) => Some(&ev.new_path),
+        _ => None,
+    }
+}
+
+/// Runs `hasher` in front of `db_tx`; returns the sender to store through
+/// instead. Up to `file_hash.threads` files are read at once and events
+/// leave in the order they came. The task ends once every clone of the
+/// returned sender is dropped.
+pub fn spawn_file_hasher(
+    hasher: FileHasher,
+    db_tx: DbSender<FileEvent>,
+    capacity: usize,
+) -> (DbSender<FileEvent>, JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel::<WrappedEvent<FileEvent>>(capacity);
+    let threads = hasher.threads;
+    let handle = tokio::spawn(async move {
+        let mut hashed = ReceiverStream::new(rx)
+            .map(|mut ev| {
+                let hasher = hasher.clone();
+                async move {
+                    hasher.enrich(&mut ev).await;
+                    ev
+                }
+            })
+            .buffered(threads);
+        while let Some(ev) = hashed.next().await {
+            if db_tx.try_send(ev).is_err() {
+                break;
+            }
+        }
+    });
+    (tx.into(), handle)
+}
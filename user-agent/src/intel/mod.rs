@@ -0,0 +1,27 @@
+// src/intel/mod.rs
+//! Detection-side state: recent-event caches, alert context capture,
+//! severity and notification routing.
+
+pub mod alerts;
+pub mod analytics;
+pub mod context;
+pub mod detection;
+pub mod dns;
+pub mod enrich;
+pub mod file_hash;
+pub mod notify;
+pub mod paths;
+pub mod process_table;
+pub mod recent;
+pub mod severity;
+
+pub use alerts::{capture_context, insert_alert, load_alert, load_context, render_context, Alert, AlertSink};
+pub use context::{gather_context, ContextRef, ContextWindow, Trigger};
+pub use detection::{spawn_detection, Detection, DetectionBuses, RuleSource};
+pub use dns::{DnsNames, Resolver, SystemResolver};
+pub use file_hash::{spawn_file_hasher, FileHasher};
+pub use notify::NotificationRouter;
+pub use paths::{PathNormalizer, SystemVolumes, Volumes};
+pub use process_table::{spawn_recorder, ProcessInfo, ProcessTable};
+pub use recent::{spawn_feeder, EventKind, RecentConfig, RecentEvent, RecentEvents};
+pub use severity::{Fields, Severity, SeverityError, SeverityExpr};
@@ -0,0 +1,93 @@
+// src/intel/notify.rs
+//! Routes alerts to notification channels by severity.
+//!
+//! Each `[[notification]]` channel accepts a severity range and may cap how
+//! many notifications of each severity it receives per period, so a burst of
+//! low-severity alerts cannot crowd out the criticals on the same channel.
+//! A routed alert is written to `<DIR>/<channel>/` as one `.json` file, for
+//! the channel's forwarder to pick up, as reports are.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs, io,
+    path::Path,
+    time::Instant,
+};
+use metrics::counter;
+
+use crate::config::model::NotificationChannel;
+use super::{alerts::Alert, severity::Severity};
+
+/// Outbox of alert notifications, next to the executable.
+pub const DIR: &str = "notifications";
+
+pub struct NotificationRouter {
+    channels: Vec<NotificationChannel>,
+    /// Send times inside the current window, per (channel, severity).
+    sent:       HashMap<(usize, Severity), VecDeque<Instant>>,
+    suppressed: HashMap<(usize, Severity), u64>,
+}
+
+impl NotificationRouter {
+    pub fn new(channels: Vec<NotificationChannel>) -> Self {
+        Self { channels, sent: HashMap::new(), suppressed: HashMap::new() }
+    }
+
+    /// Channels that should be notified of an alert of `severity` raised at
+    /// `now`. Channels over their limit for that severity are skipped.
+    pub fn route(&mut self, severity: Severity, now: Instant) -> Vec<&str> {
+        let mut out = Vec::new();
+        for (idx, ch) in self.channels.iter().enumerate() {
+            if !ch.accepts(severity) {
+                continue;
+            }
+            if let Some(limit) = ch.rate_limit.get(severity) {
+                let window = self.sent.entry((idx, severity)).or_default();
+                while window.front().is_some_and(|t| now.duration_since(*t) >= limit.per) {
+                    window.pop_front();
+                }
+                if window.len() >= limit.count as usize {
+                    *self.suppressed.entry((idx, severity)).or_default() += 1;
+                    counter!(
+                        "notifications_rate_limited_total",
+                        "channel" => ch.name.clone(),
+                        "severity" => severity.as_str()
+                    )
+                    .increment(1);
+                    continue;
+                }
+                window.push_back(now);
+            }
+            out.push(ch.name.as_str());
+        }
+        out
+    }
+
+    /// Notifications of `severity` dropped on `channel` by rate limiting.
+    pub fn suppressed(&self, channel: &str, severity: Severity) -> u64 {
+        self.channels
+            .iter()
+            .position(|c| c.name == channel)
+            .and_then(|idx| self.suppressed.get(&(idx, severity)))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// Writes alert `alert_id` to the outbox of `channel` under `dir`.
+pub fn deliver(dir: &Path, channel: &str, alert_id: i64, alert: &Alert) -> io::Result<()> {
+    let dir = dir.join(channel);
+    fs::create_dir_all(&dir)?;
+    let body = serde_json::json!({
+        "id":       alert_id,
+        "ts":       alert.ts,
+        "rule_id":  alert.rule_id,
+        "severity": alert.severity,
+        "pid":      alert.pid,
+        "ppid":     alert.ppid,
+        "message":  alert.message,
+        "file":     alert.file,
+    });
+    let stem = chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f");
+    fs::write(dir.join(format!("{stem}-{alert_id}.json")), body.to_string())
+}
@@ -0,0 +1,251 @@
+// src/intel/paths.rs
+//! Canonical paths for file events.
+//!
+//! The file sensor reports NT paths (`\Device\HarddiskVolume3\Users\...`),
+//! and the target of a rename may come as a bare final component or as a
+//! path relative to the volume, so the same file could reach dedup and the
+//! path globs of detections in several forms. [`PathNormalizer`] rewrites
+//! `path` and `new_path` into one:
+//!
+//! - the volume device becomes the drive letter it is mounted on, as
+//!   `QueryDosDeviceW` maps them, cached per volume for [`VOLUME_TTL`];
+//! - a rename target is completed from the directory or volume of `path`;
+//! - 8.3 short names are expanded with `GetLongPathNameW` while the file is
+//!   there;
+//! - the rest is [`normalize_path`]: lower case, `\` separators, no `\??\`
+//!   or `\\?\` prefix.
+//!
+//! What the sensor sent is kept in `raw_path` and `raw_new_path` when it
+//! differed. A volume no drive letter maps to, such as a VHD dismounted
+//! since, keeps its device name and is counted in
+//! `path_unmapped_volumes_total`.
+
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use metrics::counter;
+use shared::events::FileEvent;
+
+use crate::comms::WrappedEvent;
+use crate::intel::enrich::{normalize_path, Enricher};
+
+/// How long a volume's drive letter, or its lack of one, is trusted.
+/// Volumes come and go with mounted VHDs and removable drives.
+pub const VOLUME_TTL: Duration = Duration::from_secs(60);
+
+/// NT namespace prefix of volume devices, lower case.
+const DEVICE_PREFIX: &str = r"\device\";
+
+/// Where volumes are mounted.
+pub trait Volumes: Send + Sync + 'static {
+    /// Drive (`"C:"`) of each volume device (`\Device\HarddiskVolume3`)
+    /// that has one.
+    fn dos_devices(&self) -> Vec<(String, String)>;
+
+    /// Long form of `path` if it names an existing file through 8.3 short
+    /// names.
+    fn long_path(&self, path: &str) -> Option<String>;
+}
+
+/// The volumes and files of this host.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemVolumes;
+
+impl Volumes for SystemVolumes {
+    fn dos_devices(&self) -> Vec<(String, String)> {
+        sys::dos_devices()
+    }
+
+    fn long_path(&self, path: &str) -> Option<String> {
+        sys::long_path(path)
+    }
+}
+
+/// Rewrites the paths of file events; see the module documentation.
+pub struct PathNormalizer {
+    volumes: Arc<dyn Volumes>,
+    /// Drive of each lower-case volume device, and when it was looked up.
+    drives:  Mutex<HashMap<String, (Option<String>, Instant)>>,
+}
+
+impl PathNormalizer {
+    pub fn new(volumes: Arc<dyn Volumes>) -> Self {
+        Self { volumes, drives: Mutex::default() }
+    }
+
+    /// Canonical form of `path`.
+    pub fn normalize(&self, path: &str) -> String {
+        let path = path.trim().replace('/', "\\");
+        let path = strip_prefix(&path);
+        let path = match self.translate(path) {
+            Some(translated) => translated,
+            None => path.to_owned(),
+        };
+        let path = match has_short_names(&path).then(|| self.volumes.long_path(&path)).flatten() {
+            Some(long) => long,
+            None => path,
+        };
+        normalize_path(&path)
+    }
+
+    /// Canonical form of the rename target `new_path` of `path`.
+    pub fn normalize_target(&self, path: &str, new_path: &str) -> String {
+        self.normalize(&complete_target(path, new_path))
+    }
+
+    /// `path` with its volume device replaced by its drive letter. `None`
+    /// when it does not start with a device or the device has no letter.
+    fn translate(&self, path: &str) -> Option<String> {
+        let (device, rest) = split_device(path)?;
+        match self.drive(device) {
+            Some(drive) => Some(format!("{drive}{rest}")),
+            None => {
+                counter!("path_unmapped_volumes_total").increment(1);
+                None
+            }
+        }
+    }
+
+    /// Drive letter of `device`, from the cache or, past [`VOLUME_TTL`],
+    /// from a fresh look at every volume.
+    fn drive(&self, device: &str) -> Option<String> {
+        let key = device.to_lowercase();
+        let now = Instant::now();
+        let mut drives = self.drives.lock().unwrap();
+        if let Some((drive, _)) = drives.get(&key).filter(|(_, at)| now.duration_since(*at) < VOLUME_TTL) {
+            return drive.clone();
+        }
+        for (device, drive) in self.volumes.dos_devices() {
+            drives.insert(device.to_lowercase(), (Some(drive), now));
+        }
+        drives.entry(key).or_insert((None, now)).0.clone()
+    }
+}
+
+impl Enricher<FileEvent> for PathNormalizer {
+    fn enrich(&self, ev: &mut WrappedEvent<FileEvent>) {
+        let f = &mut ev.payload;
+        if !f.new_path.is_empty() {
+            let new_path = self.normalize_target(&f.path, &f.new_path);
+            if new_path != f.new_path {
+                f.raw_new_path = mem::replace(&mut f.new_path, new_path);
+            }
+        }
+        let path = self.normalize(&f.path);
+        if path != f.path {
+            f.raw_path = mem::replace(&mut f.path, path);
+        }
+    }
+}
+
+/// `path` without a `\??\` or `\\?\` prefix.
+fn strip_prefix(path: &str) -> &str {
+    [r"\??\", r"\\?\"].iter().find_map(|p| path.strip_prefix(p)).unwrap_or(path)
+}
+
+/// Whether a component of `path` may be an 8.3 short name. Only drive paths
+/// are looked up.
+fn has_short_names(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && path.contains('~')
+}
+
+/// A rename target as a full path: a bare name goes in the directory of
+/// `path`, a path relative to the volume (`\Users\a\b.txt`) on the volume
+/// of `path`. Anything else is already complete.
+fn complete_target(path: &str, new_path: &str) -> String {
+    let target = new_path.trim().replace('/', "\\");
+    let path = strip_prefix(path.trim()).replace('/', "\\");
+    if !target.contains('\\') && volume_of(&target).is_none() {
+        return match path.rfind('\\') {
+            Some(i) => format!("{}\\{target}", &path[..i]),
+            None => target,
+        };
+    }
+    let relative = target.starts_with('\\')
+        && !target.starts_with(r"\\")
+        && !target.starts_with(r"\??\")
+        && split_device(&target).is_none();
+    match volume_of(&path).filter(|_| relative) {
+        Some(volume) => format!("{volume}{target}"),
+        None => target,
+    }
+}
+
+/// `C:` or `\Device\HarddiskVolume3` at the start of `path`.
+fn volume_of(path: &str) -> Option<&str> {
+    let bytes = path.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return Some(&path[..2]);
+    }
+    split_device(path).map(|(device, _)| device)
+}
+
+/// The volume device `path` starts with, and the rest of it.
+fn split_device(path: &str) -> Option<(&str, &str)> {
+    if !path.get(..DEVICE_PREFIX.len())?.eq_ignore_ascii_case(DEVICE_PREFIX) {
+        return None;
+    }
+    let end = path[DEVICE_PREFIX.len()..].find('\\').map_or(path.len(), |i| i + DEVICE_PREFIX.len());
+    Some(path.split_at(end))
+}
+
+#[cfg(windows)]
+mod sys {
+    /// Long enough for `\\?\` paths.
+    const MAX_PATH_CHARS: usize = 32_768;
+    /// A device name and its older mappings, NUL-separated.
+    const MAX_TARGET_CHARS: usize = 1_024;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetLogicalDrives() -> u32;
+        fn QueryDosDeviceW(name: *const u16, target: *mut u16, max: u32) -> u32;
+        fn GetLongPathNameW(short: *const u16, long: *mut u16, len: u32) -> u32;
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain([0]).collect()
+    }
+
+    /// Device of each drive letter in use.
+    pub fn dos_devices() -> Vec<(String, String)> {
+        // SAFETY: no arguments.
+        let drives = unsafe { GetLogicalDrives() };
+        (0..26u8)
+            .filter(|i| drives & (1 << i) != 0)
+            .filter_map(|i| {
+                let drive = format!("{}:", (b'A' + i) as char);
+                let mut target = vec![0u16; MAX_TARGET_CHARS];
+                // SAFETY: NUL-terminated name; `max` is the buffer size in
+                // characters.
+                let n = unsafe { QueryDosDeviceW(wide(&drive).as_ptr(), target.as_mut_ptr(), target.len() as u32) };
+                // The first string is the current mapping.
+                let end = target[..n as usize].iter().position(|&c| c == 0)?;
+                (end > 0).then(|| (String::from_utf16_lossy(&target[..end]), drive))
+            })
+            .collect()
+    }
+
+    pub fn long_path(path: &str) -> Option<String> {
+        let mut long = vec![0u16; MAX_PATH_CHARS];
+        // SAFETY: NUL-terminated input; `len` is the buffer size in
+        // characters. A result that does not fit is reported as its size.
+        let n = unsafe { GetLongPathNameW(wide(path).as_ptr(), long.as_mut_ptr(), long.len() as u32) } as usize;
+        (n != 0 && n < long.len()).then(|| String::from_utf16_lossy(&long[..n]))
+    }
+}
+
+#[cfg(not(windows))]
+mod sys {
+    pub fn dos_devices() -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    pub fn long_path(_path: &str) -> Option<String> {
+        None
+    }
+}
@@ -0,0 +1,108 @@
+// src/intel/process_table.rs
+//! Bounded pid → process map built from process-create events, used to
+//! enrich events that only carry a pid (e.g. the creator of a process).
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use shared::events::ProcessEvent;
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+use crate::comms::intel_bus::Subscription;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub image_path: String,
+    pub ppid:       u32,
+    /// Creation time, UNIX microseconds.
+    pub ts:         i64,
+}
+
+/// Cloneable handle; the oldest entry is evicted once `max` is reached.
+#[derive(Debug, Clone)]
+pub struct ProcessTable {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    map:   HashMap<u32, ProcessInfo>,
+    /// Insertion order as (pid, ts); stale after pid reuse.
+    order: VecDeque<(u32, i64)>,
+    max:   usize,
+}
+
+impl ProcessTable {
+    pub fn new(max: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                map:   HashMap::new(),
+                order: VecDeque::new(),
+                max:   max.max(1),
+            })),
+        }
+    }
+
+    /// Records a creation; a reused pid replaces the previous process. A
+    /// creation already recorded, by another feeder of the same table, is
+    /// left alone.
+    pub fn record(&self, ev: &ProcessEvent, ts: i64) {
+        let mut g = self.inner.lock().unwrap();
+        if g.map.get(&ev.pid).is_some_and(|i| i.ts == ts) {
+            return;
+        }
+        let info = ProcessInfo { image_path: ev.image_path.clone(), ppid: ev.ppid, ts };
+        g.map.insert(ev.pid, info);
+        g.order.push_back((ev.pid, ts));
+        while g.map.len() > g.max {
+            let Some((pid, ts)) = g.order.pop_front() else { break };
+            if g.map.get(&pid).is_some_and(|i| i.ts == ts) {
+                g.map.remove(&pid);
+            }
+        }
+        // Drop stale order entries left behind by pid reuse.
+        if g.order.len() > 2 * g.max {
+            let Inner { map, order, .. } = &mut *g;
+            order.retain(|(pid, ts)| map.get(pid).is_some_and(|i| i.ts == *ts));
+        }
+    }
+
+    pub fn get(&self, pid: u32) -> Option<ProcessInfo> {
+        self.inner.lock().unwrap().map.get(&pid).cloned()
+    }
+
+    pub fn image_path(&self, pid: u32) -> Option<String> {
+        self.get(pid).map(|i| i.image_path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for ProcessTable {
+    fn default() -> Self {
+        Self::new(16_384)
+    }
+}
+
+/// Records the creations on an intel bus subscription in `table`
+/// until the bus closes.
+pub fn spawn_recorder(
+    rt: &Runtime,
+    mut rx: Subscription<ProcessEvent>,
+    table: ProcessTable,
+) -> JoinHandle<()> {
+    rt.spawn(async move {
+        while let Some(ev) = rx.recv().await {
+            if !ev.payload.is_exit() {
+                table.record(&ev.payload, ev.ts_micros());
+            }
+        }
+    })
+}
@@ -0,0 +1,161 @@
+// src/intel/recent.rs
+//! Bounded per-process cache of the most recent events of each type.
+//!
+//! Only references are kept (kind, pid, timestamp, `event_uid`), never
+//! payload copies. Memory is capped twice: at most `per_pid` entries per
+//! (kind, pid) buffer and at most `max_pids` buffers, evicting the buffer
+//! that was created first once the limit is reached.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use prost::Message;
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+use crate::comms::{intel_bus::Subscription, HasPid};
+use crate::probe::is_probe_event;
+use super::severity::Fields;
+
+/// Event families kept by the cache, one per telemetry table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EventKind {
+    Process,
+    File,
+    Network,
+    Etw,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 4] = [Self::Process, Self::File, Self::Network, Self::Etw];
+
+    /// Table holding events of this kind.
+    pub const fn table(self) -> &'static str {
+        match self {
+            Self::Process => "process_events",
+            Self::File    => "fs_events",
+            Self::Network => "network_events",
+            Self::Etw     => "etw_events",
+        }
+    }
+
+    pub fn from_table(table: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.table() == table)
+    }
+}
+
+/// Reference to an event seen on the intel bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecentEvent {
+    pub kind:      EventKind,
+    pub pid:       u32,
+    /// UNIX microseconds.
+    pub ts:        i64,
+    pub event_uid: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecentConfig {
+    pub per_pid:  usize,
+    pub max_pids: usize,
+}
+
+impl Default for RecentConfig {
+    fn default() -> Self {
+        Self { per_pid: 64, max_pids: 4_096 }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    buffers: HashMap<(EventKind, u32), VecDeque<RecentEvent>>,
+    /// Creation order of `buffers`, used for eviction.
+    order:   VecDeque<(EventKind, u32)>,
+}
+
+/// Cheaply clonable handle to the shared cache.
+#[derive(Clone)]
+pub struct RecentEvents {
+    cfg:   RecentConfig,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl RecentEvents {
+    pub fn new(cfg: RecentConfig) -> Self {
+        Self { cfg, inner: Arc::new(Mutex::new(Inner::default())) }
+    }
+
+    pub fn record(&self, ev: RecentEvent) {
+        let mut inner = self.inner.lock().unwrap();
+        let key = (ev.kind, ev.pid);
+        if !inner.buffers.contains_key(&key) {
+            while inner.order.len() >= self.cfg.max_pids {
+                match inner.order.pop_front() {
+                    Some(old) => { inner.buffers.remove(&old); }
+                    None => break,
+                }
+            }
+            inner.order.push_back(key);
+        }
+        let buf = inner.buffers.entry(key).or_default();
+        if buf.len() >= self.cfg.per_pid {
+            buf.pop_front();
+        }
+        buf.push_back(ev);
+    }
+
+    /// Cached events of `kind` for `pid` with `from <= ts <= to`, oldest first.
+    pub fn window(&self, kind: EventKind, pid: u32, from: i64, to: i64) -> Vec<RecentEvent> {
+        let inner = self.inner.lock().unwrap();
+        inner.buffers
+            .get(&(kind, pid))
+            .map(|b| b.iter().filter(|e| e.ts >= from && e.ts <= to).copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Timestamp of the oldest cached event for (kind, pid); anything older
+    /// has to come from the database.
+    pub fn oldest(&self, kind: EventKind, pid: u32) -> Option<i64> {
+        let inner = self.inner.lock().unwrap();
+        inner.buffers.get(&(kind, pid)).and_then(|b| b.front()).map(|e| e.ts)
+    }
+
+    /// Number of (kind, pid) buffers currently held.
+    pub fn buffers(&self) -> usize {
+        self.inner.lock().unwrap().buffers.len()
+    }
+
+    /// Total number of cached references.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().buffers.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Feeds `recent` from an intel bus subscription until the bus closes.
+pub fn spawn_feeder<E>(
+    rt: &Runtime,
+    mut rx: Subscription<E>,
+    recent: RecentEvents,
+    kind: EventKind,
+) -> JoinHandle<()>
+where
+    E: Message + HasPid + Fields + Clone + Send + 'static,
+{
+    rt.spawn(async move {
+        while let Some(ev) = rx.recv().await {
+            // Live probe traffic must never end up as alert context.
+            if !is_probe_event(&ev.payload) {
+                recent.record(RecentEvent {
+                    kind,
+                    pid:       ev.payload.pid(),
+                    ts:        ev.ts_micros(),
+                    event_uid: ev.event_uid(),
+                });
+            }
+        }
+    })
+}
@@ -0,0 +1,382 @@
+// src/intel/severity.rs
+//! Alert severity and the expressions rules use to compute it at match time.
+//!
+//! A rule either has a static severity or an expression made of clauses that
+//! are tried in order; the first whose condition holds wins:
+//!
+//! ```text
+//! critical when image_path ends_with "lsass.exe";
+//! high     when path starts_with "C:\Windows\" and not path contains "\Temp\";
+//! default  medium
+//! ```
+//!
+//! Operators: `==`, `!=`, `contains`, `starts_with`, `ends_with` (ASCII
+//! case-insensitive, as Windows paths are) and `<`, `<=`, `>`, `>=` on
+//! numbers; conditions combine with `and`, `or`, `not` and parentheses.
+
+use std::{collections::HashMap, fmt, str::FromStr};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use shared::events::{EtwEvent, FileEvent, NetworkEvent, ProcessEvent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 5] = [Self::Info, Self::Low, Self::Medium, Self::High, Self::Critical];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Info     => "info",
+            Self::Low      => "low",
+            Self::Medium   => "medium",
+            Self::High     => "high",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Severity {
+    type Err = SeverityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|v| v.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| SeverityError::UnknownSeverity(s.to_string()))
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SeverityError {
+    #[error("unknown severity '{0}'")]
+    UnknownSeverity(String),
+
+    #[error("severity expression: {0}")]
+    Syntax(String),
+}
+
+/// Named fields of an event, as seen by severity expressions.
+pub trait Fields {
+    fn field(&self, name: &str) -> Option<String>;
+}
+
+impl Fields for HashMap<String, String> {
+    fn field(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+}
+
+impl Fields for ProcessEvent {
+    fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "pid"         => Some(self.pid.to_string()),
+            "ppid"        => Some(self.ppid.to_string()),
+            "image_path"  => Some(self.image_path.clone()),
+            "cmdline"     => Some(self.cmdline.clone()),
+            "creator_pid" => Some(self.creator_pid.to_string()),
+            "creator_tid" => Some(self.creator_tid.to_string()),
+            "parent_image_path" => Some(self.parent_image_path.clone()),
+            "parent_cmdline"    => Some(self.parent_cmdline.clone()),
+            "user_sid"    => Some(self.user_sid.clone()),
+            "session_id"  => Some(self.session_id.to_string()),
+            "elevated"    => Some(self.elevated.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl Fields for FileEvent {
+    fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "pid"      => Some(self.pid.to_string()),
+            "path"     => Some(self.path.clone()),
+            "new_path" => Some(self.new_path.clone()),
+            "raw_path" => Some(self.raw_path.clone()),
+            "raw_new_path" => Some(self.raw_new_path.clone()),
+            "exe_path" => Some(self.exe_path.clone()),
+            "size"     => Some(self.size.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl Fields for NetworkEvent {
+    fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "pid"      => Some(self.pid.to_string()),
+            "proto"    => Some(self.proto.clone()),
+            "src_ip"   => Some(self.src_ip.clone()),
+            "src_port" => Some(self.src_port.to_string()),
+            "dst_ip"   => Some(self.dst_ip.clone()),
+            "dst_port" => Some(self.dst_port.to_string()),
+            "exe_path" => Some(self.exe_path.clone()),
+            "bytes"    => Some(self.bytes.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl Fields for EtwEvent {
+    fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "pid"           => Some(self.pid.to_string()),
+            "tid"           => Some(self.tid.to_string()),
+            "provider_guid" => Some(self.provider_guid.clone()),
+            "event_id"      => Some(self.event_id.to_string()),
+            "level"         => Some(self.level.to_string()),
+            "json_payload"  => Some(self.json_payload.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op { Eq, Ne, Contains, StartsWith, EndsWith, Lt, Le, Gt, Ge }
+
+#[derive(Debug, Clone, PartialEq)]
+enum Cond {
+    Cmp { field: String, op: Op, value: String },
+    Not(Box<Cond>),
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
+}
+
+impl Cond {
+    fn eval(&self, ev: &dyn Fields) -> bool {
+        match self {
+            Cond::Not(c)    => !c.eval(ev),
+            Cond::And(a, b) => a.eval(ev) && b.eval(ev),
+            Cond::Or(a, b)  => a.eval(ev) || b.eval(ev),
+            Cond::Cmp { field, op, value } => {
+                let Some(actual) = ev.field(field) else { return false };
+                let (a, v) = (actual.to_ascii_lowercase(), value.to_ascii_lowercase());
+                let num = || Some((actual.parse::<f64>().ok()?, value.parse::<f64>().ok()?));
+                match op {
+                    Op::Eq         => a == v,
+                    Op::Ne         => a != v,
+                    Op::Contains   => a.contains(&v),
+                    Op::StartsWith => a.starts_with(&v),
+                    Op::EndsWith   => a.ends_with(&v),
+                    Op::Lt => num().is_some_and(|(a, v)| a < v),
+                    Op::Le => num().is_some_and(|(a, v)| a <= v),
+                    Op::Gt => num().is_some_and(|(a, v)| a > v),
+                    Op::Ge => num().is_some_and(|(a, v)| a >= v),
+                }
+            }
+        }
+    }
+}
+
+/// Compiled severity expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeverityExpr {
+    clauses: Vec<(Severity, Cond)>,
+    default: Option<Severity>,
+}
+
+impl SeverityExpr {
+    /// Severity for `ev`; `fallback` is the rule's static severity, used
+    /// when no clause matches and the expression has no `default`.
+    pub fn evaluate(&self, ev: &dyn Fields, fallback: Severity) -> Severity {
+        self.clauses
+            .iter()
+            .find(|(_, c)| c.eval(ev))
+            .map(|(s, _)| *s)
+            .or(self.default)
+            .unwrap_or(fallback)
+    }
+}
+
+impl FromStr for SeverityExpr {
+    type Err = SeverityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut p = Parser { toks: tokenize(s)?, pos: 0 };
+        let mut expr = SeverityExpr { clauses: Vec::new(), default: None };
+        loop {
+            if p.peek().is_none() {
+                break;
+            }
+            if p.eat_word("default") {
+                expr.default = Some(p.severity()?);
+            } else {
+                let sev = p.severity()?;
+                if !p.eat_word("when") {
+                    return Err(p.error("expected 'when'"));
+                }
+                expr.clauses.push((sev, p.or()?));
+            }
+            if !p.eat(&Tok::Semi) {
+                break;
+            }
+        }
+        match p.peek() {
+            None => Ok(expr),
+            Some(t) => Err(SeverityError::Syntax(format!("unexpected {t:?}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    Str(String),
+    Sym(&'static str),
+    Semi,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Tok>, SeverityError> {
+    let mut out = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => { chars.next(); }
+            ';' => { chars.next(); out.push(Tok::Semi); }
+            '(' => { chars.next(); out.push(Tok::Open); }
+            ')' => { chars.next(); out.push(Tok::Close); }
+            '"' | '\'' => {
+                chars.next();
+                let mut lit = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(ch) => lit.push(ch),
+                        None => return Err(SeverityError::Syntax("unterminated string".into())),
+                    }
+                }
+                out.push(Tok::Str(lit));
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let eq = chars.next_if_eq(&'=').is_some();
+                out.push(Tok::Sym(match (c, eq) {
+                    ('=', true)  => "==",
+                    ('!', true)  => "!=",
+                    ('<', true)  => "<=",
+                    ('>', true)  => ">=",
+                    ('<', false) => "<",
+                    ('>', false) => ">",
+                    _ => return Err(SeverityError::Syntax(format!("unexpected '{c}'"))),
+                }));
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' => {
+                let mut word = String::new();
+                while let Some(ch) = chars.next_if(|ch| ch.is_alphanumeric() || matches!(ch, '_' | '.' | '-')) {
+                    word.push(ch);
+                }
+                out.push(Tok::Word(word));
+            }
+            other => return Err(SeverityError::Syntax(format!("unexpected '{other}'"))),
+        }
+    }
+    Ok(out)
+}
+
+struct Parser {
+    toks: Vec<Tok>,
+    pos:  usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let t = self.toks.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn eat(&mut self, t: &Tok) -> bool {
+        let hit = self.peek() == Some(t);
+        if hit {
+            self.pos += 1;
+        }
+        hit
+    }
+
+    fn eat_word(&mut self, w: &str) -> bool {
+        let hit = matches!(self.peek(), Some(Tok::Word(x)) if x.eq_ignore_ascii_case(w));
+        if hit {
+            self.pos += 1;
+        }
+        hit
+    }
+
+    fn error(&self, msg: &str) -> SeverityError {
+        SeverityError::Syntax(format!("{msg} at token {}", self.pos + 1))
+    }
+
+    fn severity(&mut self) -> Result<Severity, SeverityError> {
+        match self.next() {
+            Some(Tok::Word(w)) => w.parse(),
+            _ => Err(self.error("expected a severity")),
+        }
+    }
+
+    fn or(&mut self) -> Result<Cond, SeverityError> {
+        let mut lhs = self.and()?;
+        while self.eat_word("or") {
+            lhs = Cond::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Cond, SeverityError> {
+        let mut lhs = self.atom()?;
+        while self.eat_word("and") {
+            lhs = Cond::And(Box::new(lhs), Box::new(self.atom()?));
+        }
+        Ok(lhs)
+    }
+
+    fn atom(&mut self) -> Result<Cond, SeverityError> {
+        if self.eat_word("not") {
+            return Ok(Cond::Not(Box::new(self.atom()?)));
+        }
+        if self.eat(&Tok::Open) {
+            let inner = self.or()?;
+            if !self.eat(&Tok::Close) {
+                return Err(self.error("expected ')'"));
+            }
+            return Ok(inner);
+        }
+        let Some(Tok::Word(field)) = self.next() else {
+            return Err(self.error("expected a field name"));
+        };
+        let op = match self.next() {
+            Some(Tok::Sym("==")) => Op::Eq,
+            Some(Tok::Sym("!=")) => Op::Ne,
+            Some(Tok::Sym("<"))  => Op::Lt,
+            Some(Tok::Sym("<=")) => Op::Le,
+            Some(Tok::Sym(">"))  => Op::Gt,
+            Some(Tok::Sym(">=")) => Op::Ge,
+            Some(Tok::Word(w)) if w == "contains"    => Op::Contains,
+            Some(Tok::Word(w)) if w == "starts_with" => Op::StartsWith,
+            Some(Tok::Word(w)) if w == "ends_with"   => Op::EndsWith,
+            _ => return Err(self.error("expected an operator")),
+        };
+        let value = match self.next() {
+            Some(Tok::Str(v) | Tok::Word(v)) => v,
+            _ => return Err(self.error("expected a value")),
+        };
+        Ok(Cond::Cmp { field, op, value })
+    }
+}
@@ -3,7 +3,25 @@
 // Public library entry point.  Re-export everything for both `main.rs` and
 // integration tests.
 
+pub mod actions;
+pub mod admin;
 pub mod config;
 pub mod db;
+pub mod etw;
+pub mod eventlog;
+pub mod features;
+pub mod heartbeat;
+pub mod health;
+pub mod idle;
+pub mod intel;
+pub mod metrics_exporter;
+pub mod metrics_history;
+pub mod perfcounters;
+pub mod policy;
 pub mod comms;
-pub mod scanner;
\ No newline at end of file
+pub mod probe;
+pub mod reports;
+pub mod run;
+pub mod scanner;
+pub mod status;
+pub mod util;
\ No newline at end of file
@@ -1,39 +1,26 @@
 // src/main.rs
 
-//! Agent entry‑point: Windows service or console fallback.
+//! Agent entry‑point: Windows service or console mode.
 //!
-//! **Refactored** to leverage the new [`Config::load()`] API that returns a fully‑validated
-//! runtime configuration.  All bespoke glue for reading TOML, converting risk groups, and
-//! validating paths has been removed.
+//! ```text
+//! agent [--foreground] [--config <path>] [--log-level <level>] [--force]
+//! agent install | uninstall | status
+//! ```
 //!
-//! Execution flow
-//! ————————————————————————————————————————————————————————————————————————
-//! 1. `Config::load()` merges embedded defaults → optional file → env vars → CLI.
-//! 2. Structured logging initialised from `cfg.logging`.
-//! 3. SQLite opened; async writers & maintenance tasks spawned.
-//! 4. Windows SCM registration (or console fallback).
-//! 5. Directory scanner launched in blocking thread.
-//! 6. Graceful shutdown via service control or Ctrl‑C.
-
-mod comms;
-mod config;
-mod db;
-mod scanner;
+//! Started by the SCM, the agent runs as the `Gladix` service. Otherwise, or
+//! with `--foreground`, it runs in this console until Ctrl‑C. Both run
+//! [`run_agent`]; only the stop signal and the status reporting differ.
+//! The subcommands register and remove the services instead (see [`admin`]).
 
 use chrono::Local;
-use fern::Dispatch;
-use log::LevelFilter;
+use clap::Parser;
 use std::{
     ffi::OsString,
-    path::{Path, PathBuf},
-    process,
-    sync::mpsc,
+    path::PathBuf,
+    process::ExitCode,
+    sync::{mpsc, OnceLock},
     thread,
-    time::Duration,
 };
-use std::sync::Arc;
-use tokio::runtime::Runtime;
-use tokio::sync::{broadcast, mpsc as async_mpsc};
 use windows_service::{
     define_windows_service,
     service::{
@@ -44,160 +31,58 @@ use windows_service::{
     service_dispatcher::start,
 };
 
-use crate::comms::WrappedEvent;
-use crate::config::{load, Config};
-use shared::events::{ProcessEvent};
-use db::{
-    connection::{init_database},
-    maintenance::{spawn_ttl_cleanup, spawn_wal_maintenance},
-    spawn_writer,
-};
-use metrics_exporter_prometheus::PrometheusBuilder;
-use scanner::run_scanner;
-use crate::comms::listeners::{Buses, Listener, RingListener};
-use crate::comms::memory_ring::MemoryRing;
-
-const SERVICE_NAME: &str = "Gladix";
+use agent::admin::{self, AGENT_SERVICE};
+use agent::run::{run_agent, Phase, RunOptions, STOP_TIMEOUT};
 
 define_windows_service!(ffi_service_main, service_main);
 
-macro_rules! fatal {
-    ($ctx:expr, $($arg:tt)+) => {{
-        eprintln!(
-            "[{}][ERROR][{}] {}",
-            Local::now().to_rfc3339(),
-            $ctx,
-            format!($($arg)+)
-        );
-        std::process::exit(1);
-    }};
+/// Gladix endpoint agent.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<admin::Command>,
+    /// Run in this console instead of as a service; Ctrl-C stops it.
+    #[arg(long)]
+    foreground: bool,
+    /// Config file [default: config.toml next to the executable].
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+    /// Overrides `[logging] level`.
+    #[arg(long, value_name = "LEVEL", value_parser = ["error", "warn", "info", "debug", "trace"])]
+    log_level: Option<String>,
+    /// Console mode: replace the lock file of an agent that has exited.
+    #[arg(long)]
+    force: bool,
 }
 
-/// Returns the directory that contains the running executable.
-fn exe_dir() -> PathBuf {
-    std::env::current_exe()
-        .expect("Cannot determine exe path")
-        .parent()
-        .expect("Executable must live in some directory")
-        .to_path_buf()
-}
+/// Parsed in `main`, read again by `service_main`.
+static OPTIONS: OnceLock<RunOptions> = OnceLock::new();
 
-/// Initialise structured logging according to `cfg.logging`.
-fn setup_logging(cfg: &Config, exe_dir: &Path) -> Result<(), fern::InitError> {
-    let level = match cfg.logging.level.to_uppercase().as_str() {
-        "ERROR" => LevelFilter::Error,
-        "WARN"  => LevelFilter::Warn,
-        "DEBUG" => LevelFilter::Debug,
-        "TRACE" => LevelFilter::Trace,
-        _        => LevelFilter::Info,
-    };
-
-    let log_path = cfg
-        .logging
-        .enable
-        .then(|| exe_dir.join(cfg.logging.file.as_deref().unwrap_or("agent.log")));
-
-    let mut dispatch = Dispatch::new()
-        .format(|out, msg, record| {
-            out.finish(format_args!(
-                "[{}][{:5}][{}][pid={}][tid={:?}] {}",
-                Local::now().to_rfc3339(),
-                record.level(),
-                record.target(),
-                process::id(),
-                thread::current().id(),
-                msg
-            ))
-        })
-        .level(level)
-        .chain(std::io::stdout());
-
-    if let Some(path) = log_path {
-        dispatch = dispatch.chain(fern::log_file(path)?);
-    }
-
-    dispatch.apply()?;
-    Ok(())
+fn report_error(ctx: &str, e: &anyhow::Error) {
+    eprintln!("[{}][ERROR][{}] {:#}", Local::now().to_rfc3339(), ctx, e);
 }
 
-fn run_service() {
-    // ────────────────────────────────────────────────────────────────────
-    // 1 ▸ Context & configuration
-    // ────────────────────────────────────────────────────────────────────
-    let exe_dir = exe_dir();
-
-    // Loader merges defaults → exe_dir/config.toml → env (APP__) → CLI (None here)
-    let cfg = load(&exe_dir.join("config.toml"))
-        .unwrap_or_else(|e| fatal!("config", "{}", e));
-
-    // ────────────────────────────────────────────────────────────────────
-    // 2 ▸ Logging
-    // ────────────────────────────────────────────────────────────────────
-    setup_logging(&cfg, &exe_dir).expect("Logging setup failed");
-    log::info!("Service bootstrap initiated");
-
-    // ────────────────────────────────────────────────────────────────────
-    // 3 ▸ Prometheus metrics
-    // ────────────────────────────────────────────────────────────────────
-    let _recorder = PrometheusBuilder::new().install();
-
-    // ────────────────────────────────────────────────────────────────────
-    // 4 ▸ Database initialisation
-    // ────────────────────────────────────────────────────────────────────
-    let db_cfg  = &cfg.database;
-    let db_path = db::connection::db_path(&exe_dir, db_cfg);
-
-    let db_conn_process = init_database(&exe_dir, db_cfg).unwrap_or_else(|e| fatal!("database", "{e}"));
-
-    // ────────────────────────────────────────────────────────────────────
-    // 5 ▸ Tokio runtime & async DB writers
-    // ────────────────────────────────────────────────────────────────────
-    let rt = Runtime::new().expect("Tokio runtime failed");
-    let (process_db_tx, process_db_rx) =
-        async_mpsc::channel::<WrappedEvent<ProcessEvent>>(10_000);
-    spawn_writer(&rt, db_conn_process, process_db_rx, db_cfg);
-
-    // 5a ▸ MemoryRing listener para ProcessEvent
-    let process_ring = MemoryRing::open(r"\\Gladix\process_ring")
-        .unwrap_or_else(|e| fatal!("ring", "process_ring: {}", e));
-    let process_listener = Arc::new(RingListener::<ProcessEvent>::new(
-        "process",
-        process_ring,
-        "7119d098-3100-4fc2-ba48-52b1fabdb4b8",
-    ));
-
-    // Creamos también el canal de broadcast para intel
-    let (process_intel_tx, _) =
-        broadcast::channel::<WrappedEvent<ProcessEvent>>(1_024);
-
-    // Ahora sí conectamos los _mismos_ senders al Buses…
-    let process_buses = Buses {
-        db_tx:    process_db_tx.clone(),
-        intel_tx: process_intel_tx.clone(),
-    };
-
-    process_listener.spawn(process_buses);
-
-    // Background DB‑maintenance tasks
-    spawn_ttl_cleanup(&rt, db_path.clone(), db_cfg);
-    spawn_wal_maintenance(&rt, db_path.clone(), db_cfg);
-
-    // ────────────────────────────────────────────────────────────────────
-    // 6 ▸ Windows SCM integration
-    // ────────────────────────────────────────────────────────────────────
+/// Runs under the SCM, reporting each phase as the service status.
+fn run_as_service(mut opts: RunOptions) {
+    // Restarted by the SCM after a crash: a stale lock file is expected.
+    opts.take_over_stale = true;
     let (svc_tx, svc_rx) = mpsc::sync_channel(1);
-    let status_handle = service_control_handler::register(
-        SERVICE_NAME,
+    let status_handle = match service_control_handler::register(
+        AGENT_SERVICE,
         move |ctrl| match ctrl {
             ServiceControl::Stop | ServiceControl::Shutdown => {
                 log::warn!("Stop requested via SCM");
-                let _ = svc_tx.send(());
+                let _ = svc_tx.try_send(());
                 ServiceControlHandlerResult::NoError
             }
             ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
             _ => ServiceControlHandlerResult::NotImplemented,
         },
-    ).unwrap();
+    ) {
+        Ok(handle) => handle,
+        Err(e) => return report_error("service", &e.into()),
+    };
 
     let mut status = ServiceStatus {
         service_type: ServiceType::OWN_PROCESS,
@@ -205,44 +90,85 @@ fn run_service() {
         controls_accepted: ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
         exit_code: ServiceExitCode::Win32(0),
         checkpoint: 0,
-        wait_hint: Duration::from_secs(30),
+        wait_hint: std::time::Duration::from_secs(30),
         process_id: None,
     };
-    status_handle.set_service_status(status.clone()).unwrap();
-
-    // ────────────────────────────────────────────────────────────────────
-    // 7 ▸ Scanner thread
-    // ────────────────────────────────────────────────────────────────────
-    status.current_state = ServiceState::Running;
-    status_handle.set_service_status(status.clone()).unwrap();
-
-    let groups = cfg.scanner.clone(); // already runtime‑ready `RiskGroup`s
-    log::info!("Service running with {} scanner groups", groups.len());
-
-    let cache_path = exe_dir.join("persistent_cache.json");
-    thread::spawn(move || run_scanner(groups, cache_path));
-
-    // ────────────────────────────────────────────────────────────────────
-    // 8 ▸ Shutdown
-    // ────────────────────────────────────────────────────────────────────
-    let _ = svc_rx.recv();
-    log::warn!("Shutdown initiated");
+    let set = |status: &ServiceStatus| {
+        if let Err(e) = status_handle.set_service_status(status.clone()) {
+            log::warn!("cannot report service status {:?}: {}", status.current_state, e);
+        }
+    };
+    set(&status);
+
+    let result = run_agent(&opts, svc_rx, |phase| {
+        match phase {
+            Phase::Running => status.current_state = ServiceState::Running,
+            Phase::Stopping => {
+                status.current_state = ServiceState::StopPending;
+                status.wait_hint = STOP_TIMEOUT;
+            }
+        }
+        set(&status);
+    });
+    if let Err(e) = &result {
+        report_error("agent", e);
+        status.exit_code = ServiceExitCode::ServiceSpecific(1);
+    }
     status.current_state = ServiceState::Stopped;
-    status_handle.set_service_status(status).unwrap();
-    log::info!("Service stopped cleanly");
+    set(&status);
+}
+
+/// Runs in the console until Ctrl-C.
+fn run_as_console(opts: RunOptions) -> ExitCode {
+    let (tx, rx) = mpsc::channel();
+    let ctrl_c = thread::Builder::new().name("ctrl-c".into()).spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        if rt.block_on(tokio::signal::ctrl_c()).is_ok() {
+            log::warn!("Ctrl-C received");
+            let _ = tx.send(());
+        }
+        std::io::Result::Ok(())
+    });
+    if let Err(e) = ctrl_c {
+        report_error("console", &anyhow::Error::from(e).context("Ctrl-C handler"));
+        return ExitCode::FAILURE;
+    }
+    match run_agent(&opts, rx, |_| {}) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            report_error("agent", &e);
+            ExitCode::FAILURE
+        }
+    }
 }
 
 fn service_main(_args: Vec<OsString>) {
-    run_service();
+    run_as_service(OPTIONS.get().cloned().unwrap_or_else(RunOptions::installed));
 }
 
-fn main() {
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Some(command) = &cli.command {
+        return admin::run(command, cli.config.as_deref());
+    }
+    let mut opts = RunOptions::installed();
+    if let Some(config) = cli.config {
+        opts.config = config;
+    }
+    opts.log_level = cli.log_level;
+    opts.take_over_stale = cli.force;
+    if cli.foreground {
+        return run_as_console(opts);
+    }
+
+    let _ = OPTIONS.set(opts.clone());
     // When not launched by the SCM we fall back to console mode.
-    if start(SERVICE_NAME, ffi_service_main).is_err() {
+    if start(AGENT_SERVICE, ffi_service_main).is_err() {
         eprintln!(
             "[{}][ERROR][main] Not a service; falling back to console.",
             Local::now().to_rfc3339()
         );
-        run_service();
+        return run_as_console(opts);
     }
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,51 @@
+// src/metrics_exporter.rs
+//! Prometheus recorder and its exposition, as `[metrics]` asks for.
+//!
+//! With `listen` the text is served over HTTP on `listen_addr`, or pushed to
+//! `push_gateway_url` every [`PUSH_INTERVAL`] when that is set. An endpoint
+//! that cannot be set up (address in use, bad URL) is logged and the agent
+//! keeps the recorder alone: the metrics history still gets its text.
+
+use std::time::Duration;
+use metrics::gauge;
+use metrics_exporter_prometheus::{ExporterFuture, PrometheusBuilder, PrometheusRecorder};
+
+use crate::config::model::MetricsConfig;
+
+/// How often the push gateway receives the exposition.
+pub const PUSH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The recorder and, when an endpoint is up, the future serving it. Must be
+/// called inside a Tokio runtime; the future is spawned by the caller.
+pub fn build(cfg: &MetricsConfig) -> (PrometheusRecorder, Option<ExporterFuture>) {
+    if !cfg.listen {
+        return (PrometheusBuilder::new().build_recorder(), None);
+    }
+    let (endpoint, built) = match &cfg.push_gateway_url {
+        Some(url) => (
+            url.clone(),
+            PrometheusBuilder::new()
+                .with_push_gateway(url, PUSH_INTERVAL, None, None, false)
+                .and_then(PrometheusBuilder::build),
+        ),
+        None => (
+            cfg.listen_addr.to_string(),
+            PrometheusBuilder::new().with_http_listener(cfg.listen_addr).build(),
+        ),
+    };
+    match built {
+        Ok((recorder, exporter)) => {
+            log::info!("metrics exposed on {}", endpoint);
+            (recorder, Some(exporter))
+        }
+        Err(e) => {
+            log::error!("metrics endpoint {} unavailable: {}", endpoint, e);
+            (PrometheusBuilder::new().build_recorder(), None)
+        }
+    }
+}
+
+/// Gauges set once at startup, after the recorder is installed.
+pub fn record_agent_info() {
+    gauge!("agent_info", "version" => env!("CARGO_PKG_VERSION")).set(1.0);
+}
@@ -0,0 +1,269 @@
+// src/metrics_history.rs
+//! Recent history of every metric, for support bundles and crash reports.
+//!
+//! A sampler renders the Prometheus exposition text every
+//! `interval_secs` into a bounded in-memory ring (`keep` snapshots and
+//! `max_kb` of text, oldest dropped first). This works without the HTTP
+//! listener: the text comes straight from the recorder handle. Each sample
+//! is also mirrored to `<dir>/<timestamp>.prom` plus `latest.prom`, pruned to
+//! the same bounds, so `gladix-cli metrics dump` and `support-bundle` can
+//! read it from another process.
+//!
+//! A bundle is the last `bundle_files` snapshots as `.prom` files and, when
+//! `csv_series` is set, `metrics.csv` with those series over the window.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::config::model::MetricsHistoryConfig;
+use crate::util::Shutdown;
+
+/// Mirror directory, relative to the agent directory.
+pub const DIR: &str = "metrics";
+/// Bundle directory written next to `crash_report.json`.
+pub const CRASH_DIR: &str = "crash_metrics";
+/// File name of the newest mirrored snapshot.
+pub const LATEST: &str = "latest.prom";
+const FILE_TS: &str = "%Y%m%dT%H%M%SZ";
+
+/// One rendering of the exposition text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub ts:   DateTime<Utc>,
+    pub text: String,
+}
+
+impl Snapshot {
+    pub fn new(text: String) -> Self {
+        Self { ts: Utc::now(), text }
+    }
+
+    /// `name{labels}` → value of every sample line.
+    pub fn series(&self) -> BTreeMap<String, f64> {
+        parse_exposition(&self.text)
+    }
+
+    fn file_name(&self) -> String {
+        format!("{}.prom", self.ts.format(FILE_TS))
+    }
+}
+
+/// Sample lines of Prometheus exposition text, keyed by `name{labels}`.
+/// Comments, blank lines and unparsable values are skipped.
+pub fn parse_exposition(text: &str) -> BTreeMap<String, f64> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| {
+            // Labels may contain spaces; the value follows the last '}' or
+            // the first space.
+            let split = l.rfind('}').map_or_else(|| l.find(' '), |i| Some(i + 1))?;
+            let (key, rest) = l.split_at(split);
+            let value = rest.split_whitespace().next()?.parse().ok()?;
+            Some((key.to_owned(), value))
+        })
+        .collect()
+}
+
+/// Metric name of a series key.
+fn metric_name(key: &str) -> &str {
+    key.split('{').next().unwrap_or(key)
+}
+
+type Source = Box<dyn Fn() -> String + Send + Sync>;
+
+struct Inner {
+    snapshots: VecDeque<Snapshot>,
+    bytes:     usize,
+}
+
+/// Bounded snapshot ring; cheap to clone.
+#[derive(Clone)]
+pub struct MetricsHistory {
+    keep:   usize,
+    max:    usize,
+    inner:  Arc<Mutex<Inner>>,
+    source: Arc<Mutex<Option<Source>>>,
+}
+
+impl MetricsHistory {
+    pub fn new(cfg: &MetricsHistoryConfig) -> Self {
+        Self {
+            keep:   cfg.keep.max(1),
+            max:    cfg.max_kb.saturating_mul(1024),
+            inner:  Arc::new(Mutex::new(Inner { snapshots: VecDeque::new(), bytes: 0 })),
+            source: Arc::default(),
+        }
+    }
+
+    /// Sets what [`sample`](Self::sample) renders, once the recorder exists.
+    pub fn set_source(&self, render: impl Fn() -> String + Send + Sync + 'static) {
+        *self.source.lock().unwrap() = Some(Box::new(render));
+    }
+
+    /// Renders and stores a snapshot now; `None` before a source is set.
+    pub fn sample(&self) -> Option<Snapshot> {
+        let text = (self.source.lock().unwrap().as_ref()?)();
+        let snap = Snapshot::new(text);
+        self.push(snap.clone());
+        Some(snap)
+    }
+
+    pub fn push(&self, snap: Snapshot) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.bytes += snap.text.len();
+        inner.snapshots.push_back(snap);
+        // Always keep the newest, even if it alone exceeds `max_kb`.
+        while inner.snapshots.len() > 1 && (inner.snapshots.len() > self.keep || inner.bytes > self.max) {
+            let Some(old) = inner.snapshots.pop_front() else { break };
+            inner.bytes -= old.text.len();
+        }
+    }
+
+    /// Oldest first.
+    pub fn snapshots(&self) -> Vec<Snapshot> {
+        self.inner.lock().unwrap().snapshots.iter().cloned().collect()
+    }
+
+    pub fn latest(&self) -> Option<Snapshot> {
+        self.inner.lock().unwrap().snapshots.back().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Text bytes currently held.
+    pub fn bytes(&self) -> usize {
+        self.inner.lock().unwrap().bytes
+    }
+
+    /// Rebuilds a history from a mirror directory.
+    pub fn load_dir(dir: &Path, cfg: &MetricsHistoryConfig) -> io::Result<Self> {
+        let history = Self::new(cfg);
+        for (ts, path) in mirrored(dir)? {
+            history.push(Snapshot { ts, text: fs::read_to_string(path)? });
+        }
+        Ok(history)
+    }
+
+    /// Mirrors `snap` into `dir` and prunes files the ring no longer holds.
+    pub fn persist(&self, dir: &Path, snap: &Snapshot) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(snap.file_name()), &snap.text)?;
+        let tmp = dir.join("latest.prom.tmp");
+        fs::write(&tmp, &snap.text)?;
+        fs::rename(&tmp, dir.join(LATEST))?;
+
+        // File names only have whole seconds.
+        let oldest = self.inner.lock().unwrap().snapshots.front().map(|s| s.ts.timestamp());
+        for (ts, path) in mirrored(dir)? {
+            if oldest.is_some_and(|o| ts.timestamp() < o) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `ts` plus one column per series of the selected metrics, one row per
+    /// snapshot; a series missing from a snapshot is left empty.
+    pub fn to_csv(&self, metrics: &[String]) -> String {
+        let snaps: Vec<_> = self.snapshots().iter().map(|s| (s.ts, s.series())).collect();
+        let columns: BTreeSet<&str> = snaps
+            .iter()
+            .flat_map(|(_, series)| series.keys())
+            .filter(|k| metrics.iter().any(|m| m == metric_name(k)))
+            .map(String::as_str)
+            .collect();
+
+        let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+        let mut out = std::iter::once("ts".to_owned())
+            .chain(columns.iter().map(|c| quote(c)))
+            .collect::<Vec<_>>()
+            .join(",");
+        out.push('\n');
+        for (ts, series) in &snaps {
+            out.push_str(&ts.to_rfc3339());
+            for c in &columns {
+                out.push(',');
+                if let Some(v) = series.get(*c) {
+                    out.push_str(&v.to_string());
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Writes the `recent` newest snapshots and, if `csv_series` is not
+    /// empty, `metrics.csv` into `out`. Returns the files written.
+    pub fn write_bundle(&self, out: &Path, recent: usize, csv_series: &[String]) -> io::Result<Vec<PathBuf>> {
+        fs::create_dir_all(out)?;
+        let snaps = self.snapshots();
+        let mut written = Vec::new();
+        for snap in &snaps[snaps.len().saturating_sub(recent)..] {
+            let path = out.join(format!("metrics-{}", snap.file_name()));
+            fs::write(&path, &snap.text)?;
+            written.push(path);
+        }
+        if !csv_series.is_empty() && !snaps.is_empty() {
+            let path = out.join("metrics.csv");
+            fs::write(&path, self.to_csv(csv_series))?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+/// Timestamped snapshot files in `dir`, oldest first.
+fn mirrored(dir: &Path) -> io::Result<Vec<(DateTime<Utc>, PathBuf)>> {
+    let mut files = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(files),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let Some(stem) = path.file_name().and_then(|n| n.to_str()?.strip_suffix(".prom")) else { continue };
+        if let Ok(ts) = NaiveDateTime::parse_from_str(stem, FILE_TS) {
+            files.push((ts.and_utc(), path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Samples `history` every `period` until shutdown, mirroring each snapshot
+/// into `dir` when given.
+pub fn spawn_sampler(
+    history: MetricsHistory,
+    period: Duration,
+    dir: Option<PathBuf>,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    thread::Builder::new()
+        .name("metrics-history".into())
+        .spawn(move || loop {
+            if let (Some(snap), Some(dir)) = (history.sample(), &dir)
+                && let Err(e) = history.persist(dir, &snap)
+            {
+                log::warn!("cannot mirror metrics snapshot to {}: {}", dir.display(), e);
+            }
+            if shutdown.wait_timeout(period) {
+                break;
+            }
+        })
+        .expect("failed to spawn metrics history thread")
+}
@@ -0,0 +1,230 @@
+// src/perfcounters/adapter.rs
+//! Mirrors selected `metrics` series into performance counters.
+//!
+//! [`PerfRecorder`] wraps the Prometheus recorder: every handle it registers
+//! still feeds Prometheus, and handles whose name appears in [`COUNTERS`]
+//! also update a [`Slot`] that pushes the new value to a [`PerfSink`].
+//! Instrumentation keeps using the `metrics` macros only.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use metrics::{
+    Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+    SharedString, Unit,
+};
+
+/// Counter sets declared in `resources/perfcounters.man`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CounterSet {
+    /// One instance per ring / event type.
+    Pipeline,
+    /// Single instance.
+    Agent,
+    /// One instance per health component.
+    Components,
+}
+
+impl CounterSet {
+    pub const ALL: [CounterSet; 3] = [CounterSet::Pipeline, CounterSet::Agent, CounterSet::Components];
+
+    pub fn is_multi_instance(self) -> bool {
+        !matches!(self, CounterSet::Agent)
+    }
+}
+
+/// Instance name of single-instance sets.
+pub const SINGLE_INSTANCE: &str = "_Total";
+
+/// How metric updates turn into the raw counter value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// Monotonic total; series sharing a slot are summed.
+    Total,
+    /// Like `Total`, displayed by consumers as a per-second rate.
+    Rate,
+    /// Current value multiplied by the factor (ratios as per-mille).
+    Gauge(f64),
+    /// Last histogram sample multiplied by the factor (seconds as µs).
+    Last(f64),
+}
+
+/// One performance counter fed by a metric.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CounterDef {
+    pub set:     CounterSet,
+    /// Counter id within the set, as in the manifest.
+    pub id:      u32,
+    pub metric:  &'static str,
+    /// Label whose value names the instance in multi-instance sets.
+    pub label:   Option<&'static str>,
+    pub kind:    Kind,
+}
+
+const fn def(set: CounterSet, id: u32, metric: &'static str, label: Option<&'static str>, kind: Kind) -> CounterDef {
+    CounterDef { set, id, metric, label, kind }
+}
+
+pub const COUNTERS: &[CounterDef] = &[
+    def(CounterSet::Pipeline,   1, "events_received_total",         Some("type"),      Kind::Rate),
+    def(CounterSet::Pipeline,   2, "ring_fill_ratio",               Some("ring"),      Kind::Gauge(1_000.0)),
+    def(CounterSet::Pipeline,   3, "ring_coverage_gap_bytes_total", Some("ring"),      Kind::Total),
+    def(CounterSet::Agent,      1, "db_flush_duration_seconds",     None,              Kind::Last(1_000_000.0)),
+    def(CounterSet::Agent,      2, "db_flush_batches_total",        None,              Kind::Total),
+    def(CounterSet::Agent,      3, "alerts_raised_total",           None,              Kind::Total),
+    def(CounterSet::Agent,      4, "probe_failures_total",          None,              Kind::Total),
+    def(CounterSet::Components, 1, "component_health",              Some("component"), Kind::Gauge(1.0)),
+];
+
+/// Destination of counter values: PerfLib on Windows, a mock in tests.
+pub trait PerfSink: Send + Sync {
+    fn set(&self, set: CounterSet, instance: &str, counter: u32, value: u64);
+}
+
+/// Current value of one counter instance.
+pub struct Slot {
+    sink:     Arc<dyn PerfSink>,
+    set:      CounterSet,
+    instance: String,
+    id:       u32,
+    kind:     Kind,
+    value:    Mutex<f64>,
+}
+
+impl Slot {
+    fn update(&self, f: impl FnOnce(&mut f64)) {
+        let mut v = self.value.lock().unwrap();
+        f(&mut v);
+        let raw = match self.kind {
+            Kind::Total | Kind::Rate => *v,
+            Kind::Gauge(factor) | Kind::Last(factor) => *v * factor,
+        };
+        self.sink.set(self.set, &self.instance, self.id, raw.max(0.0).round() as u64);
+    }
+}
+
+/// Maps metric keys to shared slots.
+pub struct PerfAdapter {
+    sink:  Arc<dyn PerfSink>,
+    slots: Mutex<HashMap<(CounterSet, String, u32), Arc<Slot>>>,
+}
+
+impl PerfAdapter {
+    pub fn new(sink: Arc<dyn PerfSink>) -> Self {
+        Self { sink, slots: Mutex::default() }
+    }
+
+    /// Slot fed by `key`, if it maps to a counter. Keys that differ only in
+    /// labels other than the instance label share a slot.
+    pub fn slot(&self, key: &Key) -> Option<Arc<Slot>> {
+        let def = COUNTERS.iter().find(|d| d.metric == key.name())?;
+        let instance = match def.label {
+            Some(label) => key.labels().find(|l| l.key() == label)?.value().to_owned(),
+            None => SINGLE_INSTANCE.to_owned(),
+        };
+        let mut slots = self.slots.lock().unwrap();
+        let slot = slots.entry((def.set, instance.clone(), def.id)).or_insert_with(|| {
+            Arc::new(Slot {
+                sink: self.sink.clone(),
+                set: def.set,
+                instance,
+                id: def.id,
+                kind: def.kind,
+                value: Mutex::new(0.0),
+            })
+        });
+        Some(slot.clone())
+    }
+}
+
+/// A recorder that also feeds performance counters.
+pub struct PerfRecorder<R> {
+    inner:   R,
+    adapter: PerfAdapter,
+}
+
+impl<R: Recorder> PerfRecorder<R> {
+    pub fn new(inner: R, sink: Arc<dyn PerfSink>) -> Self {
+        Self { inner, adapter: PerfAdapter::new(sink) }
+    }
+}
+
+struct Tee<H> {
+    inner: H,
+    slot:  Arc<Slot>,
+}
+
+impl CounterFn for Tee<Counter> {
+    fn increment(&self, value: u64) {
+        self.inner.increment(value);
+        self.slot.update(|v| *v += value as f64);
+    }
+
+    fn absolute(&self, value: u64) {
+        self.inner.absolute(value);
+        self.slot.update(|v| *v = v.max(value as f64));
+    }
+}
+
+impl GaugeFn for Tee<Gauge> {
+    fn increment(&self, value: f64) {
+        self.inner.increment(value);
+        self.slot.update(|v| *v += value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.inner.decrement(value);
+        self.slot.update(|v| *v -= value);
+    }
+
+    fn set(&self, value: f64) {
+        self.inner.set(value);
+        self.slot.update(|v| *v = value);
+    }
+}
+
+impl HistogramFn for Tee<Histogram> {
+    fn record(&self, value: f64) {
+        self.inner.record(value);
+        self.slot.update(|v| *v = value);
+    }
+}
+
+impl<R: Recorder> Recorder for PerfRecorder<R> {
+    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner.describe_counter(key, unit, description)
+    }
+
+    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner.describe_gauge(key, unit, description)
+    }
+
+    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner.describe_histogram(key, unit, description)
+    }
+
+    fn register_counter(&self, key: &Key, metadata: &Metadata<'_>) -> Counter {
+        let inner = self.inner.register_counter(key, metadata);
+        match self.adapter.slot(key) {
+            Some(slot) => Counter::from_arc(Arc::new(Tee { inner, slot })),
+            None => inner,
+        }
+    }
+
+    fn register_gauge(&self, key: &Key, metadata: &Metadata<'_>) -> Gauge {
+        let inner = self.inner.register_gauge(key, metadata);
+        match self.adapter.slot(key) {
+            Some(slot) => Gauge::from_arc(Arc::new(Tee { inner, slot })),
+            None => inner,
+        }
+    }
+
+    fn register_histogram(&self, key: &Key, metadata: &Metadata<'_>) -> Histogram {
+        let inner = self.inner.register_histogram(key, metadata);
+        match self.adapter.slot(key) {
+            Some(slot) => Histogram::from_arc(Arc::new(Tee { inner, slot })),
+            None => inner,
+        }
+    }
+}
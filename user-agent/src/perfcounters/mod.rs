@@ -0,0 +1,33 @@
+// src/perfcounters/mod.rs
+//! Windows performance counters mirroring a core subset of the Prometheus
+//! metrics, for monitoring that cannot scrape HTTP (`[metrics] perfcounters`).
+//!
+//! [`adapter`] maps `metrics` series to counters behind the [`PerfSink`]
+//! trait; [`perflib`] is the PerfLib V2 implementation and manages the
+//! counter manifest registration.
+
+pub mod adapter;
+pub mod perflib;
+
+use std::{path::Path, sync::Arc};
+
+pub use adapter::{CounterSet, PerfRecorder, PerfSink, COUNTERS};
+pub use perflib::{install, uninstall, PerfLib};
+
+/// Starts the provider, registering the manifest first if the counter sets
+/// are not known yet. `None` when counters cannot be published, e.g. the
+/// first run is not elevated; Prometheus keeps working either way.
+pub fn start(exe_dir: &Path) -> Option<Arc<dyn PerfSink>> {
+    let provider = PerfLib::start().or_else(|first| {
+        log::info!("performance counters not registered ({}), installing manifest", first);
+        install(exe_dir)?;
+        PerfLib::start()
+    });
+    match provider {
+        Ok(p) => Some(Arc::new(p)),
+        Err(e) => {
+            log::warn!("performance counters unavailable: {}", e);
+            None
+        }
+    }
+}
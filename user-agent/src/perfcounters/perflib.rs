@@ -0,0 +1,254 @@
+// src/perfcounters/perflib.rs
+//! PerfLib V2 provider for the counter sets in `resources/perfcounters.man`.
+//!
+//! The manifest is registered with the `lodctr /m` equivalent
+//! (`LoadPerfCounterTextStringsW`), which needs administrator rights; the
+//! provider itself then runs unprivileged. Off Windows every call fails with
+//! `Unsupported`.
+
+use std::{io, path::Path};
+
+use super::adapter::{CounterSet, Kind, PerfSink, COUNTERS};
+
+/// Manifest shipped next to the executable by [`install`].
+pub const MANIFEST: &str = include_str!("../../resources/perfcounters.man");
+pub const MANIFEST_FILE: &str = "gladix-perfcounters.man";
+
+/// `(id, is_rate)` of the counters of `set`, as declared in the manifest.
+fn counters(set: CounterSet) -> Vec<(u32, bool)> {
+    COUNTERS.iter().filter(|d| d.set == set).map(|d| (d.id, d.kind == Kind::Rate)).collect()
+}
+
+/// Writes the manifest to `exe_dir` and registers it.
+pub fn install(exe_dir: &Path) -> io::Result<()> {
+    let manifest = exe_dir.join(MANIFEST_FILE);
+    std::fs::write(&manifest, MANIFEST)?;
+    sys::load(&format!("lodctr /m:\"{}\" \"{}\"", manifest.display(), exe_dir.display()))
+}
+
+/// Removes the registration made by [`install`].
+pub fn uninstall(exe_dir: &Path) -> io::Result<()> {
+    let manifest = exe_dir.join(MANIFEST_FILE);
+    if !manifest.exists() {
+        std::fs::write(&manifest, MANIFEST)?;
+    }
+    sys::unload(&format!("unlodctr /m:\"{}\"", manifest.display()))?;
+    let _ = std::fs::remove_file(manifest);
+    Ok(())
+}
+
+/// Running provider; stopped on drop.
+pub struct PerfLib {
+    inner: sys::Provider,
+}
+
+impl PerfLib {
+    pub fn start() -> io::Result<Self> {
+        Ok(Self { inner: sys::Provider::start(&CounterSet::ALL.map(|s| (s, counters(s))))? })
+    }
+}
+
+impl PerfSink for PerfLib {
+    fn set(&self, set: CounterSet, instance: &str, counter: u32, value: u64) {
+        if let Err(e) = self.inner.set(set, instance, counter, value) {
+            log::debug!("perf counter {:?}/{}/{}: {}", set, instance, counter, e);
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use std::io;
+    use super::CounterSet;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, "performance counters need Windows")
+    }
+
+    pub fn load(_cmdline: &str) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn unload(_cmdline: &str) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub struct Provider;
+
+    impl Provider {
+        pub fn start(_sets: &[(CounterSet, Vec<(u32, bool)>)]) -> io::Result<Self> {
+            Err(unsupported())
+        }
+
+        pub fn set(&self, _set: CounterSet, _instance: &str, _counter: u32, _value: u64) -> io::Result<()> {
+            Err(unsupported())
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{
+        collections::HashMap,
+        ffi::c_void,
+        io,
+        sync::Mutex,
+    };
+    use super::CounterSet;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Guid(u32, u16, u16, [u8; 8]);
+
+    // Must match resources/perfcounters.man.
+    const PROVIDER: Guid = Guid(0x5d5b3a0e, 0x6f2a, 0x4c47, [0x9f, 0x3e, 0x1a, 0x8c, 0x0b, 0x6e, 0x2d, 0x41]);
+    fn set_guid(set: CounterSet) -> Guid {
+        match set {
+            CounterSet::Pipeline   => Guid(0x5d5b3a0e, 0x6f2a, 0x4c47, [0x9f, 0x3e, 0x1a, 0x8c, 0x0b, 0x6e, 0x2d, 0x42]),
+            CounterSet::Agent      => Guid(0x5d5b3a0e, 0x6f2a, 0x4c47, [0x9f, 0x3e, 0x1a, 0x8c, 0x0b, 0x6e, 0x2d, 0x43]),
+            CounterSet::Components => Guid(0x5d5b3a0e, 0x6f2a, 0x4c47, [0x9f, 0x3e, 0x1a, 0x8c, 0x0b, 0x6e, 0x2d, 0x44]),
+        }
+    }
+
+    const PERF_COUNTERSET_SINGLE_INSTANCE: u32 = 0;
+    const PERF_COUNTERSET_MULTI_INSTANCES: u32 = 2;
+    const PERF_COUNTER_LARGE_RAWCOUNT: u32 = 0x0001_0100;
+    const PERF_COUNTER_BULK_COUNT: u32 = 0x1041_0500;
+    const PERF_DETAIL_NOVICE: u32 = 100;
+
+    #[repr(C)]
+    struct CounterSetInfo {
+        counter_set: Guid,
+        provider:    Guid,
+        counters:    u32,
+        instance:    u32,
+    }
+
+    #[repr(C)]
+    struct CounterInfo {
+        id:           u32,
+        kind:         u32,
+        attrib:       u64,
+        size:         u32,
+        detail_level: u32,
+        scale:        i32,
+        offset:       u32,
+    }
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        fn PerfStartProviderEx(provider: *const Guid, context: *const c_void, handle: *mut isize) -> u32;
+        fn PerfStopProvider(handle: isize) -> u32;
+        fn PerfSetCounterSetInfo(handle: isize, template: *const u8, size: u32) -> u32;
+        fn PerfCreateInstance(handle: isize, set: *const Guid, name: *const u16, id: u32) -> *mut c_void;
+        fn PerfSetULongLongCounterValue(handle: isize, instance: *mut c_void, counter: u32, value: u64) -> u32;
+    }
+
+    #[link(name = "loadperf")]
+    unsafe extern "system" {
+        fn LoadPerfCounterTextStringsW(cmdline: *const u16, quiet: i32) -> u32;
+        fn UnloadPerfCounterTextStringsW(cmdline: *const u16, quiet: i32) -> u32;
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn check(status: u32) -> io::Result<()> {
+        match status {
+            0 => Ok(()),
+            e => Err(io::Error::from_raw_os_error(e as i32)),
+        }
+    }
+
+    pub fn load(cmdline: &str) -> io::Result<()> {
+        // SAFETY: NUL-terminated command line.
+        check(unsafe { LoadPerfCounterTextStringsW(wide(cmdline).as_ptr(), 1) })
+    }
+
+    pub fn unload(cmdline: &str) -> io::Result<()> {
+        // SAFETY: NUL-terminated command line.
+        check(unsafe { UnloadPerfCounterTextStringsW(wide(cmdline).as_ptr(), 1) })
+    }
+
+    pub struct Provider {
+        handle:    isize,
+        instances: Mutex<HashMap<(CounterSet, String), usize>>,
+    }
+
+    // SAFETY: PerfLib handles and instance blocks may be used from any thread.
+    unsafe impl Send for Provider {}
+    unsafe impl Sync for Provider {}
+
+    impl Provider {
+        pub fn start(sets: &[(CounterSet, Vec<(u32, bool)>)]) -> io::Result<Self> {
+            let mut handle = 0isize;
+            // SAFETY: out-pointer valid for the call.
+            check(unsafe { PerfStartProviderEx(&PROVIDER, std::ptr::null(), &mut handle) })?;
+            let provider = Self { handle, instances: Mutex::default() };
+            for (set, counters) in sets {
+                provider.register(*set, counters)?;
+            }
+            Ok(provider)
+        }
+
+        /// Every counter is 8 bytes; rates are bulk counts, the rest raw counts.
+        fn register(&self, set: CounterSet, counters: &[(u32, bool)]) -> io::Result<()> {
+            let info = CounterSetInfo {
+                counter_set: set_guid(set),
+                provider:    PROVIDER,
+                counters:    counters.len() as u32,
+                instance:    if set.is_multi_instance() { PERF_COUNTERSET_MULTI_INSTANCES } else { PERF_COUNTERSET_SINGLE_INSTANCE },
+            };
+            let mut template = Vec::new();
+            // SAFETY: plain-old-data structs copied byte for byte.
+            unsafe {
+                template.extend_from_slice(std::slice::from_raw_parts(
+                    &info as *const _ as *const u8, size_of::<CounterSetInfo>(),
+                ));
+                for (i, &(id, rate)) in counters.iter().enumerate() {
+                    let counter = CounterInfo {
+                        id,
+                        kind: if rate { PERF_COUNTER_BULK_COUNT } else { PERF_COUNTER_LARGE_RAWCOUNT },
+                        attrib: 0,
+                        size: 8,
+                        detail_level: PERF_DETAIL_NOVICE,
+                        scale: 0,
+                        offset: i as u32 * 8,
+                    };
+                    template.extend_from_slice(std::slice::from_raw_parts(
+                        &counter as *const _ as *const u8, size_of::<CounterInfo>(),
+                    ));
+                }
+            }
+            // SAFETY: template is laid out as PERF_COUNTERSET_INFO + counters.
+            check(unsafe { PerfSetCounterSetInfo(self.handle, template.as_ptr(), template.len() as u32) })
+        }
+
+        pub fn set(&self, set: CounterSet, instance: &str, counter: u32, value: u64) -> io::Result<()> {
+            let mut instances = self.instances.lock().unwrap();
+            let next_id = instances.len() as u32;
+            let block = match instances.get(&(set, instance.to_owned())) {
+                Some(&b) => b as *mut c_void,
+                None => {
+                    // SAFETY: NUL-terminated name, registered set.
+                    let b = unsafe { PerfCreateInstance(self.handle, &set_guid(set), wide(instance).as_ptr(), next_id) };
+                    if b.is_null() {
+                        return Err(io::Error::last_os_error());
+                    }
+                    instances.insert((set, instance.to_owned()), b as usize);
+                    b
+                }
+            };
+            // SAFETY: `block` belongs to this provider until it stops.
+            check(unsafe { PerfSetULongLongCounterValue(self.handle, block, counter, value) })
+        }
+    }
+
+    impl Drop for Provider {
+        fn drop(&mut self) {
+            // SAFETY: stops the provider started in `start`; frees instances.
+            unsafe { PerfStopProvider(self.handle) };
+        }
+    }
+}
@@ -0,0 +1,250 @@
+// src/policy.rs
+//! Network policy from `[[network_policy]]`: whether a connection is allowed
+//! or blocked.
+//!
+//! A rule matches the remote address against a CIDR range, the remote port
+//! against a range and the process path against a glob; the first matching
+//! rule decides, and connections no rule matches are allowed. The compiled
+//! rules are sent to the driver with `IOCTL_GLADIX_SET_NET_POLICY` for the
+//! WFP classify path. That path cannot block yet, so the agent applies the
+//! same rules to the network events it reads: the stored `blocked` flag and
+//! `verdict` say what the policy decided.
+
+use std::{collections::HashSet, net::IpAddr, ops::RangeInclusive};
+use ipnet::IpNet;
+use metrics::counter;
+use regex::Regex;
+use shared::{
+    constants::{
+        NetRule, NET_ACTION_ALLOW, NET_ACTION_BLOCK, NET_FAMILY_V4, NET_FAMILY_V6, NET_POLICY_MAX_RULES,
+        NET_RULE_PROCESS_LEN,
+    },
+    events::NetworkEvent,
+};
+
+use crate::comms::ioctl::Driver;
+use crate::config::model::{ConfigError, NetPolicyRule, PolicyAction};
+use crate::intel::detection::glob;
+
+/// What the policy decided for a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Block,
+}
+
+impl Verdict {
+    /// The verdict recorded by an event's `blocked` flag.
+    pub fn of(blocked: bool) -> Self {
+        if blocked { Verdict::Block } else { Verdict::Allow }
+    }
+
+    /// As stored in `network_events.verdict`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Verdict::Allow => "allow",
+            Verdict::Block => "block",
+        }
+    }
+}
+
+impl From<PolicyAction> for Verdict {
+    fn from(action: PolicyAction) -> Self {
+        match action {
+            PolicyAction::Allow => Verdict::Allow,
+            PolicyAction::Block => Verdict::Block,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    id:      String,
+    verdict: Verdict,
+    /// Any address when `None`.
+    dst:     Option<IpNet>,
+    ports:   RangeInclusive<u16>,
+    /// The lowercase glob and its compiled form; any process when `None`.
+    process: Option<(String, Regex)>,
+}
+
+impl Rule {
+    fn matches(&self, dst: Option<IpAddr>, port: Option<u16>, process: &str) -> bool {
+        self.dst.is_none_or(|net| dst.is_some_and(|ip| net.contains(&ip)))
+            && port.is_some_and(|p| self.ports.contains(&p))
+            && self.process.as_ref().is_none_or(|(_, re)| re.is_match(process))
+    }
+
+    /// Whether every connection `other` matches is matched by this rule too.
+    fn covers(&self, other: &Rule) -> bool {
+        let dst = match (self.dst, other.dst) {
+            (None, _) => true,
+            (Some(a), Some(b)) => a.contains(&b),
+            (Some(_), None) => false,
+        };
+        let ports = self.ports.start() <= other.ports.start() && other.ports.end() <= self.ports.end();
+        let process = match (&self.process, &other.process) {
+            (None, _) => true,
+            (Some((a, _)), Some((b, _))) => a == b,
+            (Some(_), None) => false,
+        };
+        dst && ports && process
+    }
+
+    fn to_driver(&self) -> NetRule {
+        let mut rule = NetRule {
+            action: match self.verdict {
+                Verdict::Allow => NET_ACTION_ALLOW,
+                Verdict::Block => NET_ACTION_BLOCK,
+            },
+            port_lo: *self.ports.start(),
+            port_hi: *self.ports.end(),
+            ..NetRule::ANY
+        };
+        match self.dst {
+            Some(IpNet::V4(net)) => {
+                rule.family = NET_FAMILY_V4;
+                rule.addr[..4].copy_from_slice(&net.network().octets());
+                rule.prefix = net.prefix_len();
+            }
+            Some(IpNet::V6(net)) => {
+                rule.family = NET_FAMILY_V6;
+                rule.addr = net.network().octets();
+                rule.prefix = net.prefix_len();
+            }
+            None => {}
+        }
+        if let Some((pattern, _)) = &self.process {
+            // Length checked by `compile`.
+            rule.process[..pattern.len()].copy_from_slice(pattern.as_bytes());
+            rule.process_len = pattern.len() as u8;
+        }
+        rule
+    }
+}
+
+fn invalid(id: &str, key: &str, reason: String) -> ConfigError {
+    ConfigError::InvalidValue(format!("network_policy[{id}].{key}"), reason)
+}
+
+/// `445` or `8000-8100`.
+fn port_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let port = |p: &str| p.trim().parse::<u16>().map_err(|_| format!("'{text}' is not a port or range"));
+    let (lo, hi) = match text.split_once('-') {
+        Some((lo, hi)) => (port(lo)?, port(hi)?),
+        None => (port(text)?, port(text)?),
+    };
+    if lo > hi {
+        return Err(format!("range '{text}' starts above its end"));
+    }
+    Ok(lo..=hi)
+}
+
+/// Compiled `[[network_policy]]` rules, in order.
+#[derive(Debug, Default)]
+pub struct NetPolicy {
+    rules: Vec<Rule>,
+}
+
+impl NetPolicy {
+    /// Compiles every rule; the first that does not compile (bad range,
+    /// port range or glob, duplicate id) or that an earlier rule overlaps
+    /// entirely, so that it never decides anything, is reported.
+    pub fn compile(rules: &[NetPolicyRule]) -> Result<Self, ConfigError> {
+        if rules.len() > NET_POLICY_MAX_RULES {
+            return Err(ConfigError::InvalidValue(
+                "network_policy".into(),
+                format!("{} rules; the driver takes at most {NET_POLICY_MAX_RULES}", rules.len()),
+            ));
+        }
+        let mut ids = HashSet::new();
+        let mut out = NetPolicy::default();
+        for r in rules {
+            if r.id.is_empty() {
+                return Err(ConfigError::InvalidValue("network_policy".into(), "rule without an id".into()));
+            }
+            if !ids.insert(r.id.as_str()) {
+                return Err(invalid(&r.id, "id", "used by another rule".into()));
+            }
+            let dst = r
+                .dst
+                .as_deref()
+                .map(|d| {
+                    d.parse::<IpNet>()
+                        .or_else(|_| d.parse::<IpAddr>().map(IpNet::from))
+                        .map(|net| net.trunc())
+                        .map_err(|_| invalid(&r.id, "dst", format!("'{d}' is not an address or range")))
+                })
+                .transpose()?;
+            let ports = match r.ports.as_deref() {
+                Some(p) => port_range(p).map_err(|e| invalid(&r.id, "ports", e))?,
+                None => 0..=u16::MAX,
+            };
+            let process = r
+                .process
+                .as_deref()
+                .map(|p| {
+                    let pattern = p.to_lowercase();
+                    if pattern.len() > NET_RULE_PROCESS_LEN {
+                        return Err(invalid(&r.id, "process", format!("longer than {NET_RULE_PROCESS_LEN} bytes")));
+                    }
+                    let re = glob(&pattern).map_err(|e| invalid(&r.id, "process", e.to_string()))?;
+                    Ok((pattern, re))
+                })
+                .transpose()?;
+            let rule = Rule { id: r.id.clone(), verdict: r.action.into(), dst, ports, process };
+            if let Some(earlier) = out.rules.iter().find(|e| e.covers(&rule)) {
+                return Err(invalid(
+                    &r.id,
+                    "id",
+                    format!("overlapped by rule '{}' above it, so it never matches", earlier.id),
+                ));
+            }
+            out.rules.push(rule);
+        }
+        Ok(out)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The decision for `ev`, and the id of the rule that made it.
+    pub fn evaluate(&self, ev: &NetworkEvent) -> (Verdict, Option<&str>) {
+        let dst = ev.dst_ip.parse::<IpAddr>().ok();
+        let port = u16::try_from(ev.dst_port).ok();
+        self.rules
+            .iter()
+            .find(|r| r.matches(dst, port, &ev.exe_path))
+            .map_or((Verdict::Allow, None), |r| (r.verdict, Some(r.id.as_str())))
+    }
+
+    /// Marks `ev` blocked when the policy blocks it; a connection the driver
+    /// already blocked stays blocked.
+    pub fn apply(&self, ev: &mut NetworkEvent) -> Verdict {
+        let (verdict, _) = self.evaluate(ev);
+        ev.blocked |= verdict == Verdict::Block;
+        let verdict = Verdict::of(ev.blocked);
+        counter!("network_policy_verdicts_total", "verdict" => verdict.as_str()).increment(1);
+        verdict
+    }
+
+    /// The rules as the driver takes them.
+    pub fn driver_rules(&self) -> Vec<NetRule> {
+        self.rules.iter().map(Rule::to_driver).collect()
+    }
+
+    /// Sends the rules to the driver. Never fails: without the driver the
+    /// policy is still applied to the stored events.
+    pub fn push_to_driver(&self) {
+        match Driver::open().and_then(|d| d.set_net_policy(&self.driver_rules())) {
+            Ok(()) => log::info!("network policy of {} rules sent to the driver", self.len()),
+            Err(e) if self.is_empty() => log::debug!("empty network policy not sent to the driver: {}", e),
+            Err(e) => log::warn!("network policy not sent to the driver: {}", e),
+        }
+    }
+}
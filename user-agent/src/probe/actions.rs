@@ -0,0 +1,54 @@
+// src/probe/actions.rs
+//! The benign actions each sensor is expected to observe.
+
+use std::{
+    fs,
+    io::{self, Write},
+    net::ToSocketAddrs,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use super::{Marker, Sensor};
+
+/// Performs the probe action of a sensor. Implemented by [`SystemActions`]
+/// on hosts and by scripted fakes in tests.
+pub trait ProbeActions: Send + Sync {
+    fn trigger(&self, sensor: Sensor, marker: &Marker) -> io::Result<()>;
+}
+
+pub struct SystemActions {
+    /// No-op marker helper (`gladix-probe-marker.exe`).
+    pub helper:   PathBuf,
+    /// Directory watched by the file sensor.
+    pub temp_dir: PathBuf,
+}
+
+impl ProbeActions for SystemActions {
+    fn trigger(&self, sensor: Sensor, marker: &Marker) -> io::Result<()> {
+        match sensor {
+            Sensor::Process => {
+                let status = Command::new(&self.helper)
+                    .arg(marker.as_str())
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()?;
+                if !status.success() {
+                    return Err(io::Error::other(format!("marker helper exited with {status}")));
+                }
+                Ok(())
+            }
+            Sensor::File => {
+                let path = self.temp_dir.join(format!("{marker}.tmp"));
+                fs::File::create(&path)?.write_all(marker.as_str().as_bytes())?;
+                fs::remove_file(&path)
+            }
+            Sensor::Dns => {
+                // Only the query matters; the answer (loopback or none) is irrelevant.
+                let _ = (marker.host_name().as_str(), 0).to_socket_addrs();
+                Ok(())
+            }
+        }
+    }
+}
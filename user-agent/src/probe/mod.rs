@@ -0,0 +1,135 @@
+// src/probe/mod.rs
+//! Live verification probe.
+//!
+//! Heartbeats prove that tasks are alive, not that telemetry flows. The probe
+//! periodically performs one benign action per sensor — spawning the marker
+//! helper, creating and deleting a marker file, resolving a marker host name —
+//! and waits for the matching event on the intel broadcast. Every action
+//! carries a unique [`Marker`]; events containing [`MARKER_PREFIX`] are probe
+//! traffic and are kept out of detection state (see [`is_probe_event`]).
+
+pub mod actions;
+pub mod runner;
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use twox_hash::XxHash64;
+
+use crate::intel::Fields;
+
+pub use actions::{ProbeActions, SystemActions};
+pub use runner::{spawn_probe, ProbeTaps, Prober};
+
+/// Common prefix of every marker; also the helper's file name stem.
+pub const MARKER_PREFIX: &str = "gladix-probe-";
+
+/// Sensor paths exercised by the probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Sensor {
+    Process,
+    File,
+    Dns,
+}
+
+impl Sensor {
+    pub const ALL: [Sensor; 3] = [Sensor::Process, Sensor::File, Sensor::Dns];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Sensor::Process => "process",
+            Sensor::File    => "file",
+            Sensor::Dns     => "dns",
+        }
+    }
+
+    /// Whether `ev`, delivered on this sensor's bus, is the probe's own
+    /// event for `marker`.
+    pub fn matches(self, marker: &Marker, ev: &dyn Fields) -> bool {
+        let field = match self {
+            Sensor::Process => "cmdline",
+            Sensor::File    => "path",
+            // DNS-Client query events carry the name in the ETW payload.
+            Sensor::Dns     => "json_payload",
+        };
+        ev.field(field).is_some_and(|v| v.to_ascii_lowercase().contains(marker.as_str()))
+    }
+}
+
+impl fmt::Display for Sensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Unique tag of one probe action, `gladix-probe-` followed by a GUID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Marker(String);
+
+impl Marker {
+    pub fn new() -> Self {
+        static SEQ: AtomicU64 = AtomicU64::new(0);
+        let seq   = SEQ.fetch_add(1, Ordering::Relaxed);
+        let nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let seed  = [nanos.to_le_bytes(), seq.to_le_bytes()].concat();
+        let hi = XxHash64::oneshot(std::process::id() as u64, &seed);
+        let lo = XxHash64::oneshot(hi, &seed);
+        Marker(format!(
+            "{MARKER_PREFIX}{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            hi >> 32,
+            (hi >> 16) & 0xffff,
+            hi & 0xffff,
+            lo >> 48,
+            lo & 0xffff_ffff_ffff,
+        ))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Host name resolved by the DNS probe; `.localhost` never leaves the box.
+    pub fn host_name(&self) -> String {
+        format!("{}.localhost", self.0)
+    }
+}
+
+impl Default for Marker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// `true` for events caused by the probe itself.
+pub fn is_probe_event(ev: &dyn Fields) -> bool {
+    ["cmdline", "image_path", "path", "new_path", "exe_path", "json_payload"]
+        .into_iter()
+        .filter_map(|f| ev.field(f))
+        .any(|v| v.to_ascii_lowercase().contains(MARKER_PREFIX))
+}
+
+/// Outcome of probing one sensor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeResult {
+    /// UNIX microseconds when the action was performed.
+    pub ts:      i64,
+    pub sensor:  Sensor,
+    pub marker:  String,
+    /// Action to event latency; `None` when the event never arrived.
+    pub latency: Option<Duration>,
+    pub error:   Option<String>,
+}
+
+impl ProbeResult {
+    pub fn success(&self) -> bool {
+        self.latency.is_some()
+    }
+}
@@ -0,0 +1,199 @@
+// src/probe/runner.rs
+//! Probe scheduling, event matching on the intel buses and health reporting.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use futures::FutureExt;
+use metrics::{counter, histogram};
+use rusqlite::Connection;
+use tokio::{
+    runtime::Runtime,
+    sync::broadcast,
+    task::{self, JoinHandle},
+    time::{self, Instant},
+};
+use shared::events::{EtwEvent, FileEvent, ProcessEvent};
+
+use crate::comms::WrappedEvent;
+use crate::config::model::ProbeConfig;
+use crate::db::probe_results::record_results;
+use crate::health::{Component, HealthRegistry};
+use crate::intel::Fields;
+use super::{actions::ProbeActions, Marker, ProbeResult, Sensor};
+
+/// Intel broadcasts the probe listens on. Sensors without a bus are not
+/// probed.
+#[derive(Clone, Default)]
+pub struct ProbeTaps {
+    pub process: Option<broadcast::Sender<WrappedEvent<ProcessEvent>>>,
+    pub file:    Option<broadcast::Sender<WrappedEvent<FileEvent>>>,
+    /// ETW bus carrying DNS-Client query events.
+    pub dns:     Option<broadcast::Sender<WrappedEvent<EtwEvent>>>,
+}
+
+impl ProbeTaps {
+    pub fn sensors(&self) -> Vec<Sensor> {
+        Sensor::ALL
+            .into_iter()
+            .filter(|s| match s {
+                Sensor::Process => self.process.is_some(),
+                Sensor::File    => self.file.is_some(),
+                Sensor::Dns     => self.dns.is_some(),
+            })
+            .collect()
+    }
+}
+
+pub struct Prober {
+    actions:   Arc<dyn ProbeActions>,
+    taps:      ProbeTaps,
+    health:    HealthRegistry,
+    timeout:   Duration,
+    threshold: u32,
+    failures:  HashMap<Sensor, u32>,
+}
+
+impl Prober {
+    pub fn new(cfg: &ProbeConfig, actions: Arc<dyn ProbeActions>, taps: ProbeTaps, health: HealthRegistry) -> Self {
+        for s in taps.sensors() {
+            health.register(Component::Sensor(s.as_str()));
+        }
+        Self {
+            actions,
+            taps,
+            health,
+            timeout:   cfg.timeout,
+            threshold: cfg.failure_threshold.max(1),
+            failures:  HashMap::new(),
+        }
+    }
+
+    /// Probes every tapped sensor once, in order, and updates their health.
+    pub async fn run_once(&mut self) -> Vec<ProbeResult> {
+        let mut results = Vec::new();
+        for sensor in self.taps.sensors() {
+            let result = self.probe(sensor).await;
+            self.report(&result);
+            results.push(result);
+        }
+        results
+    }
+
+    /// Consecutive failures of `sensor` so far.
+    pub fn failures(&self, sensor: Sensor) -> u32 {
+        self.failures.get(&sensor).copied().unwrap_or(0)
+    }
+
+    async fn probe(&self, sensor: Sensor) -> ProbeResult {
+        let marker = Marker::new();
+        let ts     = chrono::Utc::now().timestamp_micros();
+        let mut result = ProbeResult { ts, sensor, marker: marker.to_string(), latency: None, error: None };
+
+        // Subscribe before acting so the event cannot slip past.
+        let waiter = match sensor {
+            Sensor::Process => self.taps.process.as_ref().map(|t| wait_for(t.subscribe(), sensor, marker.clone()).boxed()),
+            Sensor::File    => self.taps.file.as_ref().map(|t| wait_for(t.subscribe(), sensor, marker.clone()).boxed()),
+            Sensor::Dns     => self.taps.dns.as_ref().map(|t| wait_for(t.subscribe(), sensor, marker.clone()).boxed()),
+        };
+        let Some(waiter) = waiter else {
+            result.error = Some("no intel bus".into());
+            return result;
+        };
+
+        let started  = Instant::now();
+        let deadline = started + self.timeout;
+        let actions  = self.actions.clone();
+        let action   = task::spawn_blocking({
+            let marker = marker.clone();
+            move || actions.trigger(sensor, &marker)
+        });
+        match action.await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                result.error = Some(format!("action failed: {e}"));
+                return result;
+            }
+            Err(e) => {
+                result.error = Some(format!("action panicked: {e}"));
+                return result;
+            }
+        }
+
+        match time::timeout_at(deadline, waiter).await {
+            Ok(Ok(())) => result.latency = Some(started.elapsed()),
+            Ok(Err(e)) => result.error = Some(e),
+            Err(_)     => result.error = Some(format!("no event within {}", humantime::format_duration(self.timeout))),
+        }
+        result
+    }
+
+    fn report(&mut self, r: &ProbeResult) {
+        let name      = r.sensor.as_str();
+        let component = Component::Sensor(name);
+        let failures  = self.failures.entry(r.sensor).or_default();
+        match r.latency {
+            Some(latency) => {
+                *failures = 0;
+                histogram!("probe_latency_seconds", "sensor" => name).record(latency.as_secs_f64());
+                self.health.mark_healthy(component);
+            }
+            None => {
+                *failures += 1;
+                counter!("probe_failures_total", "sensor" => name).increment(1);
+                let error = r.error.as_deref().unwrap_or("unknown");
+                log::warn!("probe of {} sensor failed ({} in a row): {}", name, failures, error);
+                if *failures >= self.threshold {
+                    self.health.mark_degraded(
+                        component,
+                        format!("live probe failed {} times in a row: {}", failures, error),
+                    );
+                }
+            }
+        }
+    }
+}
+
+async fn wait_for<E: Fields + Clone>(
+    mut rx: broadcast::Receiver<WrappedEvent<E>>,
+    sensor: Sensor,
+    marker: Marker,
+) -> Result<(), String> {
+    loop {
+        match rx.recv().await {
+            Ok(ev) if sensor.matches(&marker, &ev.payload) => return Ok(()),
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return Err("intel bus closed".into()),
+        }
+    }
+}
+
+/// Runs the probe every `cfg.interval` and stores the outcomes in
+/// `probe_results`. Returns `None` when the probe is disabled.
+pub fn spawn_probe(rt: &Runtime, db_path: PathBuf, cfg: &ProbeConfig, mut prober: Prober) -> Option<JoinHandle<()>> {
+    if !cfg.enabled {
+        log::info!("live probe disabled");
+        return None;
+    }
+    let period = cfg.interval;
+    Some(rt.spawn(async move {
+        // First run after one period so sensors have time to come up.
+        let mut ticker = time::interval_at(Instant::now() + period, period);
+        loop {
+            ticker.tick().await;
+            let results = prober.run_once().await;
+            match Connection::open(&db_path) {
+                Ok(conn) => {
+                    let _ = conn.busy_timeout(Duration::from_millis(1_000));
+                    if let Err(e) = record_results(&conn, &results) {
+                        log::warn!("cannot store probe results: {}", e);
+                    }
+                }
+                Err(e) => log::warn!("cannot store probe results: {}", e),
+            }
+        }
+    }))
+}
@@ -0,0 +1,102 @@
+// src/reports/diff.rs
+//! Executable changes between two states of the scan cache.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+use serde::Serialize;
+
+use crate::scanner::cache::{FileCacheEntry, SKIPPED_OFFLINE, UNSTABLE};
+
+/// What the scanner last knew about one file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileState {
+    /// `None` for cloud placeholders that were not read and files that
+    /// were still being written.
+    pub hash: Option<u64>,
+    /// `None` in caches written before sizes were recorded.
+    pub size: Option<u64>,
+}
+
+/// Files of one group, sorted by path.
+pub type Snapshot = BTreeMap<PathBuf, FileState>;
+
+/// Entries of `cache` under any of `dirs`.
+pub fn snapshot(cache: &HashMap<PathBuf, FileCacheEntry>, dirs: &[PathBuf]) -> Snapshot {
+    cache
+        .iter()
+        .filter(|(path, _)| dirs.iter().any(|d| path.starts_with(d)))
+        .map(|(path, e)| {
+            let unread = matches!(e.scan_result.as_deref(), Some(SKIPPED_OFFLINE | UNSTABLE));
+            (path.clone(), FileState { hash: (!unread).then_some(e.hash), size: e.size })
+        })
+        .collect()
+}
+
+/// One added, removed or changed file. Hashes are hex; the old side is empty
+/// for additions and the new side for removals.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub path:       String,
+    pub old_hash:   Option<String>,
+    pub new_hash:   Option<String>,
+    pub old_size:   Option<u64>,
+    pub new_size:   Option<u64>,
+    /// `new_size - old_size`, when both are known.
+    pub size_delta: Option<i64>,
+}
+
+impl Change {
+    fn new(path: &Path, old: Option<&FileState>, new: Option<&FileState>) -> Self {
+        let old_size = old.and_then(|s| s.size);
+        let new_size = new.and_then(|s| s.size);
+        Self {
+            path:       path.to_string_lossy().into_owned(),
+            old_hash:   old.and_then(|s| s.hash).map(hex_hash),
+            new_hash:   new.and_then(|s| s.hash).map(hex_hash),
+            old_size,
+            new_size,
+            size_delta: old_size.zip(new_size).map(|(o, n)| n as i64 - o as i64),
+        }
+    }
+}
+
+fn hex_hash(h: u64) -> String {
+    format!("{h:016x}")
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diff {
+    pub added:   Vec<Change>,
+    pub removed: Vec<Change>,
+    pub changed: Vec<Change>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Changes from `old` to `new`, each list sorted by path. A file counts as
+/// changed only when both hashes are known and differ: a placeholder that
+/// was not read says nothing about its content.
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Diff {
+    let mut out = Diff::default();
+    for (path, before) in old {
+        match new.get(path) {
+            None => out.removed.push(Change::new(path, Some(before), None)),
+            Some(after) if matches!((before.hash, after.hash), (Some(a), Some(b)) if a != b) => {
+                out.changed.push(Change::new(path, Some(before), Some(after)));
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, after) in new {
+        if !old.contains_key(path) {
+            out.added.push(Change::new(path, None, Some(after)));
+        }
+    }
+    out
+}
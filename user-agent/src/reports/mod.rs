@@ -0,0 +1,157 @@
+// src/reports/mod.rs
+//! Periodic reports of executable changes per scanner group.
+//!
+//! Each `[[reports.group]]` compares the scanner's persistent cache with the
+//! state recorded at its previous report, without rescanning anything. The
+//! first run only records that state. Reports are stored in `scan_reports`
+//! and handed to a [`ReportSink`] once per configured notification channel.
+
+pub mod diff;
+pub mod render;
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use rusqlite::Connection;
+
+use crate::config::model::{ReportGroup, ReportsConfig, RiskGroup};
+use crate::db::scan_cache::load_cache;
+use crate::db::scan_reports::{last_report_ts, load_baseline, mark_delivered, store_report};
+use crate::scanner::cache::FileCacheEntry;
+use crate::util::Shutdown;
+
+pub use diff::{diff, snapshot, Change, Diff, FileState, Snapshot};
+pub use render::{render, Rendered, Report};
+
+/// How often the scheduler looks for due groups.
+const CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Delivers a report to a notification channel. Implemented by
+/// [`OutboxSink`] and by fakes in tests.
+pub trait ReportSink: Send + Sync {
+    fn deliver(&self, channel: &str, report: &Rendered) -> io::Result<()>;
+}
+
+/// Writes each report to `<dir>/<channel>/` as `.json`, `.txt` and `.html`
+/// files, for the channel's forwarder to pick up.
+pub struct OutboxSink {
+    pub dir: PathBuf,
+}
+
+impl ReportSink for OutboxSink {
+    fn deliver(&self, channel: &str, report: &Rendered) -> io::Result<()> {
+        let dir = self.dir.join(channel);
+        fs::create_dir_all(&dir)?;
+        let stem = chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f").to_string();
+        fs::write(dir.join(format!("{stem}.json")), &report.json)?;
+        fs::write(dir.join(format!("{stem}.txt")), &report.text)?;
+        fs::write(dir.join(format!("{stem}.html")), &report.html)?;
+        Ok(())
+    }
+}
+
+/// Whether a group last reported at `last` is due at `now` (microseconds).
+pub fn is_due(last: Option<i64>, interval: Duration, now: i64) -> bool {
+    last.is_none_or(|t| now.saturating_sub(t) >= interval.as_micros() as i64)
+}
+
+/// Builds and stores the report of `group` over `dirs` at `now`, moving its
+/// baseline to the current cache state. Returns the row id and bodies.
+pub fn generate(
+    conn: &Connection,
+    group: &ReportGroup,
+    dirs: &[PathBuf],
+    cache: &HashMap<PathBuf, FileCacheEntry>,
+    max_entries: usize,
+    now: i64,
+) -> rusqlite::Result<(i64, Rendered)> {
+    let risk = group.risk.as_str();
+    let since = last_report_ts(conn, risk)?;
+    let current = snapshot(cache, dirs);
+    let changes = match since {
+        Some(_) => diff(&load_baseline(conn, risk)?, &current),
+        None => Diff::default(),
+    };
+    let report = Report::new(risk, now, since, current.len(), changes, max_entries);
+    let body = render(&report);
+    let id = store_report(conn, &report, &body, &current)?;
+    Ok((id, body))
+}
+
+/// Generates and delivers the reports of every due group. Channels that fail
+/// are logged and left out of `delivered_to`. Returns the new report ids.
+pub fn run_due(
+    conn: &Connection,
+    cfg: &ReportsConfig,
+    scanner: &[RiskGroup],
+    cache: &HashMap<PathBuf, FileCacheEntry>,
+    sink: &dyn ReportSink,
+    now: i64,
+) -> rusqlite::Result<Vec<i64>> {
+    let mut ids = Vec::new();
+    for group in &cfg.groups {
+        if !is_due(last_report_ts(conn, group.risk.as_str())?, group.interval, now) {
+            continue;
+        }
+        // The loader only accepts groups that match a scanner group.
+        let dirs: Vec<PathBuf> = scanner
+            .iter()
+            .filter(|s| s.risk == group.risk)
+            .flat_map(|s| s.directories.iter().cloned())
+            .collect();
+        let (id, body) = generate(conn, group, &dirs, cache, cfg.max_entries, now)?;
+        log::info!("{}", body.subject);
+
+        let mut delivered = Vec::new();
+        for channel in &group.channels {
+            match sink.deliver(channel, &body) {
+                Ok(())  => delivered.push(channel.clone()),
+                Err(e)  => log::warn!("report {} not delivered to {}: {}", id, channel, e),
+            }
+        }
+        mark_delivered(conn, id, &delivered)?;
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+fn any_due(conn: &Connection, cfg: &ReportsConfig, now: i64) -> rusqlite::Result<bool> {
+    for group in &cfg.groups {
+        if is_due(last_report_ts(conn, group.risk.as_str())?, group.interval, now) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Checks for due groups every hour until `shutdown`, reading the scanner
+/// cache from `db_path` as of its last save.
+pub fn run_reports(
+    cfg: ReportsConfig,
+    scanner: Vec<RiskGroup>,
+    db_path: PathBuf,
+    sink: Arc<dyn ReportSink>,
+    shutdown: Shutdown,
+) -> rusqlite::Result<()> {
+    let conn = Connection::open(&db_path)?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    loop {
+        let now = chrono::Utc::now().timestamp_micros();
+        let result = any_due(&conn, &cfg, now).and_then(|due| {
+            if !due {
+                return Ok(Vec::new());
+            }
+            run_due(&conn, &cfg, &scanner, &load_cache(&conn)?, sink.as_ref(), now)
+        });
+        if let Err(e) = result {
+            log::error!("scan reports failed: {}", e);
+        }
+        if shutdown.wait_timeout(CHECK_INTERVAL) {
+            return Ok(());
+        }
+    }
+}
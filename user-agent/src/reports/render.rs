@@ -0,0 +1,180 @@
+// src/reports/render.rs
+//! JSON, text and HTML bodies of a change report.
+
+use std::fmt::Write;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::diff::{Change, Diff};
+
+/// Up to `max_entries` changes of one kind and how many were left out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Section {
+    pub total:   usize,
+    pub entries: Vec<Change>,
+    pub omitted: usize,
+}
+
+impl Section {
+    fn new(mut changes: Vec<Change>, max_entries: usize) -> Self {
+        let total = changes.len();
+        changes.truncate(max_entries);
+        Self { total, omitted: total - changes.len(), entries: changes }
+    }
+}
+
+/// Structured report; also the JSON body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub risk:      String,
+    /// Microseconds since the epoch, like the other timestamps in the database.
+    pub generated: i64,
+    /// Time of the previous report; `None` on the first run.
+    pub since:     Option<i64>,
+    /// First run: the current state is recorded and nothing is compared.
+    pub baseline:  bool,
+    /// Executables known in the group now.
+    pub files:     usize,
+    pub added:     Section,
+    pub removed:   Section,
+    pub changed:   Section,
+}
+
+impl Report {
+    pub fn new(risk: &str, generated: i64, since: Option<i64>, files: usize, diff: Diff, max_entries: usize) -> Self {
+        Self {
+            risk: risk.to_owned(),
+            generated,
+            since,
+            baseline: since.is_none(),
+            files,
+            added:   Section::new(diff.added, max_entries),
+            removed: Section::new(diff.removed, max_entries),
+            changed: Section::new(diff.changed, max_entries),
+        }
+    }
+
+    pub fn subject(&self) -> String {
+        if self.baseline {
+            format!("[gladix] {} scan baseline: {} executables", self.risk, self.files)
+        } else {
+            format!(
+                "[gladix] {} scan changes: {} added, {} removed, {} changed",
+                self.risk, self.added.total, self.removed.total, self.changed.total
+            )
+        }
+    }
+
+    fn sections(&self) -> [(&'static str, &Section); 3] {
+        [("Added", &self.added), ("Removed", &self.removed), ("Changed", &self.changed)]
+    }
+
+    fn period(&self) -> String {
+        match self.since {
+            Some(since) => format!("{} to {}", timestamp(since), timestamp(self.generated)),
+            None => format!("baseline taken {}", timestamp(self.generated)),
+        }
+    }
+}
+
+/// All bodies of one report, as stored and delivered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendered {
+    pub subject: String,
+    pub json:    String,
+    pub text:    String,
+    pub html:    String,
+}
+
+pub fn render(report: &Report) -> Rendered {
+    Rendered {
+        subject: report.subject(),
+        json:    serde_json::to_string_pretty(report).expect("report serializes"),
+        text:    text(report),
+        html:    html(report),
+    }
+}
+
+fn timestamp(us: i64) -> String {
+    DateTime::<Utc>::from_timestamp_micros(us)
+        .map_or_else(|| us.to_string(), |t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+}
+
+/// `old -> new` hashes and the size delta, omitting the unknown parts.
+fn details(c: &Change) -> String {
+    let mut out = match (&c.old_hash, &c.new_hash) {
+        (Some(o), Some(n)) => format!("{o} -> {n}"),
+        (Some(h), None) | (None, Some(h)) => h.clone(),
+        (None, None) => "not read (offline)".into(),
+    };
+    match (c.size_delta, c.old_size, c.new_size) {
+        (Some(d), _, _) => { let _ = write!(out, ", {d:+} bytes"); }
+        (None, _, Some(s)) | (None, Some(s), None) => { let _ = write!(out, ", {s} bytes"); }
+        (None, None, None) => {}
+    }
+    out
+}
+
+fn text(report: &Report) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", report.subject());
+    let _ = writeln!(out, "Period: {}", report.period());
+    if report.baseline {
+        let _ = writeln!(out, "\nRecorded {} executables; changes are reported from the next run.", report.files);
+        return out;
+    }
+    for (title, section) in report.sections() {
+        let _ = writeln!(out, "\n{title} ({})", section.total);
+        for c in &section.entries {
+            let _ = writeln!(out, "  {}  [{}]", c.path, details(c));
+        }
+        if section.omitted > 0 {
+            let _ = writeln!(out, "  ... and {} more", section.omitted);
+        }
+    }
+    out
+}
+
+fn html(report: &Report) -> String {
+    let mut out = String::new();
+    let _ = write!(out, "<html><body><h2>{}</h2><p>{}</p>", escape(&report.subject()), escape(&report.period()));
+    if report.baseline {
+        let _ = write!(
+            out,
+            "<p>Recorded {} executables; changes are reported from the next run.</p></body></html>",
+            report.files
+        );
+        return out;
+    }
+    for (title, section) in report.sections() {
+        let _ = write!(out, "<h3>{title} ({})</h3>", section.total);
+        if section.entries.is_empty() {
+            continue;
+        }
+        out.push_str("<table><tr><th>Path</th><th>Details</th></tr>");
+        for c in &section.entries {
+            let _ = write!(out, "<tr><td>{}</td><td>{}</td></tr>", escape(&c.path), escape(&details(c)));
+        }
+        out.push_str("</table>");
+        if section.omitted > 0 {
+            let _ = write!(out, "<p>... and {} more</p>", section.omitted);
+        }
+    }
+    out.push_str("</body></html>");
+    out
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
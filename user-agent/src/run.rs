@@ -0,0 +1,833 @@
+// src/run.rs
+//! The agent itself, whichever way it was started: the Windows service and
+//! console mode (`main.rs`) only differ in where the stop comes from.
+//!
+//! Execution flow
+//! ————————————————————————————————————————————————————————————————————————
+//! 1. `config::load()` reads and validates the config file.
+//! 2. Structured logging initialised from `cfg.logging`.
+//! 3. Tokio runtime, event buses and analytics.
+//! 4. Components started (see `health::matrix`); a critical one failing
+//!    ends the run with a crash report.
+//! 5. On stop, producers are joined first, then the writers.
+
+use anyhow::{anyhow, Context};
+use chrono::Local;
+use fern::Dispatch;
+use log::LevelFilter;
+use std::{
+    path::{Path, PathBuf},
+    process,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use tokio::sync::{broadcast, mpsc as async_mpsc};
+
+use crate::comms::WrappedEvent;
+use crate::config::{canonical::canonicalize, load, provision::{load_or_create_sensor_guid, SENSOR_GUID_FILE}, Config};
+use shared::constants::{ring_path, user_object_path, FILE_RING, IMAGE_RING, NETWORK_RING, OBJECT_RING, PROCESS_RING};
+use shared::events::{EtwEvent, FileEvent, ImageLoadEvent, NetworkEvent, ObjectOpEvent, ProcessEvent, ScanResult};
+use crate::db::{
+    self,
+    connection::{init_database, open_db_connection},
+    db_writer::FlushAck,
+    hub::{AnyEvent, DbSender},
+    overflow::{self, spawn_replay, Overflow},
+    maintenance::{spawn_compression_backfill, spawn_ttl_cleanup, spawn_wal_maintenance},
+    ops_journal::{self, Actor, Journal},
+    reprocess::spawn_reprocessor,
+    spawn_hub,
+};
+use crate::scanner::{cache::{self, PersistentCache}, run_scanner, Schedule};
+use crate::actions::Actions;
+use crate::comms::export::{spawn_exporter, ExportTarget};
+use crate::comms::forwarder::{spawn_forwarder, ForwardTarget};
+use crate::etw::EtwListener;
+use crate::eventlog::{self, EventLog};
+use crate::comms::grpc::{self, ConfigServer, StatusServer};
+use crate::comms::driver_params::{ring_size_registry, set_ring_size_registry};
+use crate::comms::ioctl::{check_driver, protect_agent};
+use crate::comms::listeners::{count_truncated, Buses, Listener, RingListener};
+use crate::comms::rate_limit::process_image;
+use crate::policy::NetPolicy;
+use crate::comms::intel_bus::TokioBuses;
+use crate::comms::memory_ring::MemoryRing;
+use crate::comms::ring_event::{RingWait, RING_EVENT};
+use crate::comms::progress::{reconcile_optional, reconcile_ring};
+use crate::comms::tap::{self, spawn_event_tap, TapSources};
+use crate::comms::coalesce::{spawn_coalescer, Coalescer};
+use crate::health::{spawn_watchdog, Component, HealthRegistry, Startup};
+use crate::heartbeat::{spawn_heartbeat, Stats};
+use crate::status::AgentStats;
+use crate::idle::{spawn_monitor, IdleGate, SystemIdle, SAMPLE_PERIOD};
+use crate::intel::{
+    analytics::{spawn_parent_spoofing, spawn_write_execute, ParentSpoofing, WriteExecute},
+    enrich::ExePath,
+    spawn_detection, spawn_file_hasher, FileHasher, PathNormalizer, SystemVolumes, DnsNames, SystemResolver, spawn_feeder, spawn_recorder, Detection, DetectionBuses, EventKind, ProcessTable, RecentConfig,
+    notify, AlertSink, NotificationRouter, RecentEvents, RuleSource,
+};
+use crate::metrics_history::{spawn_sampler, MetricsHistory};
+use crate::perfcounters::PerfRecorder;
+use crate::probe::{spawn_probe, ProbeTaps, Prober, SystemActions};
+use crate::reports::{run_reports, OutboxSink, ReportSink};
+use crate::util::{instance, InstanceGuard, RetryPolicy, Shutdown, Tasks};
+use crate::{metrics_exporter, metrics_history, perfcounters};
+
+/// First and longest wait of the watchdog retrying optional components that
+/// failed to start.
+const WATCHDOG_INITIAL: Duration = Duration::from_secs(5);
+const WATCHDOG_MAX: Duration = Duration::from_secs(300);
+/// How long a stop waits for tasks to finish.
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the agent is run.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Config file; the detection rules and `SetConfig` follow it too.
+    pub config:          PathBuf,
+    /// Directory of the database, logs and state files the config names
+    /// relative to it.
+    pub dir:             PathBuf,
+    /// Overrides `[logging] level`.
+    pub log_level:       Option<String>,
+    /// Replace a lock file left by an agent that has exited (always for
+    /// the service, `--force` in console mode).
+    pub take_over_stale: bool,
+}
+
+impl RunOptions {
+    /// `config.toml` and its data next to the executable, as installed.
+    pub fn installed() -> Self {
+        let dir = exe_dir();
+        Self { config: dir.join("config.toml"), dir, log_level: None, take_over_stale: false }
+    }
+}
+
+/// Progress reported to whoever started the agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Every component was started, some possibly degraded.
+    Running,
+    /// A stop was received; tasks are being joined for up to
+    /// [`STOP_TIMEOUT`].
+    Stopping,
+}
+
+/// Returns the directory that contains the running executable.
+pub fn exe_dir() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Initialise structured logging according to `cfg.logging`.
+fn setup_logging(cfg: &Config, dir: &Path) -> Result<(), fern::InitError> {
+    let level = match cfg.logging.level.to_uppercase().as_str() {
+        "ERROR" => LevelFilter::Error,
+        "WARN"  => LevelFilter::Warn,
+        "DEBUG" => LevelFilter::Debug,
+        "TRACE" => LevelFilter::Trace,
+        _        => LevelFilter::Info,
+    };
+
+    let log_path = cfg
+        .logging
+        .enable
+        .then(|| dir.join(cfg.logging.file.as_deref().unwrap_or("agent.log")));
+
+    let mut dispatch = Dispatch::new()
+        .format(|out, msg, record| {
+            out.finish(format_args!(
+                "[{}][{:5}][{}][pid={}][tid={:?}] {}",
+                Local::now().to_rfc3339(),
+                record.level(),
+                record.target(),
+                process::id(),
+                thread::current().id(),
+                msg
+            ))
+        })
+        .level(level)
+        .chain(std::io::stdout());
+
+    if let Some(path) = log_path {
+        dispatch = dispatch.chain(fern::log_file(path)?);
+    }
+
+    // Without the rights to register the source, file and stdout only.
+    let mut eventlog_error = None;
+    if cfg.logging.eventlog {
+        match EventLog::open() {
+            Ok(eventlog) => {
+                eventlog::install(eventlog.clone());
+                dispatch = dispatch.chain(Box::new(eventlog) as Box<dyn log::Log>);
+            }
+            Err(e) => eventlog_error = Some(e),
+        }
+    }
+
+    dispatch.apply()?;
+    if let Some(e) = eventlog_error {
+        log::warn!("Windows Event Log unavailable, logging to file and stdout only: {}", e);
+    }
+    Ok(())
+}
+
+/// Runs the agent until `stop` receives or its sender is dropped. Fails
+/// before the agent runs: bad config, another agent running, or a critical
+/// component that did not start (its crash report is written to `opts.dir`).
+pub fn run_agent(opts: &RunOptions, stop: mpsc::Receiver<()>, mut on_phase: impl FnMut(Phase)) -> anyhow::Result<()> {
+    // ────────────────────────────────────────────────────────────────────
+    // 1 ▸ Context & configuration
+    // ────────────────────────────────────────────────────────────────────
+    let dir = opts.dir.clone();
+    // Heartbeat uptime counts from here.
+    let stats = Stats::global().clone();
+
+    let mut cfg = load(&opts.config).with_context(|| format!("config {}", opts.config.display()))?;
+    if let Some(level) = &opts.log_level {
+        cfg.logging.level = level.clone();
+    }
+
+    // A second agent would share the rings' tail and the database.
+    let lock = instance::lock_path(&db::connection::db_path(&dir, &cfg.database));
+    let _instance = InstanceGuard::acquire(instance::MUTEX_NAME, &lock, opts.take_over_stale).context("instance")?;
+
+    // ────────────────────────────────────────────────────────────────────
+    // 2 ▸ Logging
+    // ────────────────────────────────────────────────────────────────────
+    // Only the first agent of a process installs its logger.
+    if let Err(e) = setup_logging(&cfg, &dir) {
+        eprintln!("[{}][WARN][logging] {}", Local::now().to_rfc3339(), e);
+    }
+    log::info!("Agent bootstrap initiated");
+
+    // ────────────────────────────────────────────────────────────────────
+    // 3 ▸ Tokio runtime & event buses
+    // ────────────────────────────────────────────────────────────────────
+    let rt = Arc::new(Runtime::new().context("tokio runtime")?);
+    let db_cfg  = cfg.database.clone();
+    let db_path = db::connection::db_path(&dir, &db_cfg);
+
+    // One writer, over one connection, stores every event type; the typed
+    // buses convert into `AnyEvent` on the way in. Listeners shed what does
+    // not fit, into `overflow.bin` unless it is disabled.
+    let (db_tx, db_rx) = async_mpsc::channel::<AnyEvent>(db_cfg.channel_capacity);
+    let overflow = (db_cfg.overflow_max_kb > 0)
+        .then(|| Overflow::open(dir.join(overflow::FILE), db_cfg.overflow_max_kb * 1024))
+        .transpose()
+        .unwrap_or_else(|e| {
+            log::warn!("overflow file unavailable, shed events are dropped: {}", e);
+            None
+        })
+        .map(Arc::new);
+    // One per event type, so a function rather than a closure.
+    fn hub_sender<E: Clone>(tx: &async_mpsc::Sender<AnyEvent>, overflow: Option<&Arc<Overflow>>) -> DbSender<E>
+    where
+        WrappedEvent<E>: Into<AnyEvent>,
+    {
+        match overflow {
+            Some(overflow) => DbSender::from(tx.clone()).with_overflow(overflow.clone()),
+            None           => DbSender::from(tx.clone()),
+        }
+    }
+
+    let (process_intel_tx, _) =
+        broadcast::channel::<WrappedEvent<ProcessEvent>>(1_024);
+    let process_buses = Buses {
+        db_tx:    hub_sender(&db_tx, overflow.as_ref()),
+        intel_tx: process_intel_tx.clone(),
+    };
+
+    // Images mapped into processes and drivers loaded, from the image ring.
+    let (image_intel_tx, _) =
+        broadcast::channel::<WrappedEvent<ImageLoadEvent>>(1_024);
+    let image_buses = Buses {
+        db_tx:    hub_sender(&db_tx, overflow.as_ref()),
+        intel_tx: image_intel_tx.clone(),
+    };
+
+    // Memory access to protected processes, from the object ring.
+    let (object_intel_tx, _) =
+        broadcast::channel::<WrappedEvent<ObjectOpEvent>>(1_024);
+    let object_buses = Buses {
+        db_tx:    hub_sender(&db_tx, overflow.as_ref()),
+        intel_tx: object_intel_tx.clone(),
+    };
+
+    // Files the scanner found new or changed; no analytic subscribes yet.
+    let (scan_intel_tx, _) =
+        broadcast::channel::<WrappedEvent<ScanResult>>(1_024);
+    let scan_buses = Buses {
+        db_tx:    db_tx.clone().into(),
+        intel_tx: scan_intel_tx.clone(),
+    };
+
+    // Intel consumers read through these, which keep the last events for
+    // the ones that subscribe late.
+    let process_bus = TokioBuses::spawn(&rt, "process", &process_intel_tx, 1_024, &cfg.communications);
+
+    // Recent-event references used to attach context to alerts, fed by
+    // each bus below as it is created.
+    let recent = RecentEvents::new(RecentConfig::default());
+    spawn_feeder(&rt, process_bus.subscribe(), recent.clone(), EventKind::Process);
+
+    // Responses to alerts, off unless `actions.enabled`.
+    let actions = Actions::new(&cfg.actions, &dir);
+    if cfg.actions.enabled {
+        log::info!("actions enabled for {} rules", cfg.actions.rules.len());
+    }
+    // Every alert is stored through this, which routes it to the
+    // `[[notification]]` channels of its severity and captures its context.
+    let alerts = AlertSink::new(db_path.clone(), actions)
+        .with_recent(recent.clone())
+        .routed(NotificationRouter::new(cfg.notifications.clone()), dir.join(notify::DIR));
+
+    // pid → image enrichment and the creator/parent mismatch analytic.
+    let processes = ProcessTable::default();
+    spawn_recorder(&rt, process_bus.subscribe(), processes.clone());
+    if cfg.analytics.parent_spoofing.enabled {
+        spawn_parent_spoofing(
+            &rt,
+            process_bus.subscribe(),
+            processes.clone(),
+            ParentSpoofing::new(&cfg.analytics.parent_spoofing),
+            alerts.clone(),
+        );
+    }
+
+    // Files created and written, from the file ring. Paths are normalized by
+    // the listener; on the way to the hub a burst of writes to one file is
+    // coalesced into one row, then hashed once.
+    let (file_intel_tx, _) =
+        broadcast::channel::<WrappedEvent<FileEvent>>(1_024);
+    let file_buses = {
+        let _guard = rt.enter();
+        let mut db_tx = hub_sender(&db_tx, overflow.as_ref());
+        if cfg.file_hash.enabled {
+            db_tx = spawn_file_hasher(FileHasher::new(&cfg.file_hash), db_tx, db_cfg.channel_capacity).0;
+        }
+        if db_cfg.coalesce_window_ms > 0 {
+            let window = Duration::from_millis(db_cfg.coalesce_window_ms);
+            db_tx = spawn_coalescer(Coalescer::files(window), db_tx, db_cfg.channel_capacity).0;
+        }
+        Buses { db_tx, intel_tx: file_intel_tx.clone() }
+    };
+    let file_bus = TokioBuses::spawn(&rt, "file", &file_intel_tx, 1_024, &cfg.communications);
+    spawn_feeder(&rt, file_bus.subscribe(), recent.clone(), EventKind::File);
+    if cfg.analytics.write_execute.enabled {
+        spawn_write_execute(
+            &rt,
+            file_bus.subscribe(),
+            process_bus.subscribe(),
+            WriteExecute::new(&cfg.analytics.write_execute),
+            alerts.clone(),
+        );
+    }
+
+    // Connections from the network ring, judged by `[[network_policy]]`.
+    let (net_intel_tx, _) =
+        broadcast::channel::<WrappedEvent<NetworkEvent>>(1_024);
+    let net_buses = Buses {
+        db_tx:    hub_sender(&db_tx, overflow.as_ref()),
+        intel_tx: net_intel_tx.clone(),
+    };
+    let net_bus = TokioBuses::spawn(&rt, "network", &net_intel_tx, 1_024, &cfg.communications);
+    spawn_feeder(&rt, net_bus.subscribe(), recent.clone(), EventKind::Network);
+
+    // Events of the `[etw]` trace session; its listener starts below.
+    let (etw_intel_tx, _) =
+        broadcast::channel::<WrappedEvent<EtwEvent>>(1_024);
+    let etw_bus = TokioBuses::spawn(&rt, "etw", &etw_intel_tx, 1_024, &cfg.communications);
+    spawn_feeder(&rt, etw_bus.subscribe(), recent, EventKind::Etw);
+    // Already compiled once by the config loader.
+    let net_policy = Arc::new(NetPolicy::compile(&cfg.network_policy).context("config")?);
+    if !net_policy.is_empty() {
+        log::info!("network policy with {} rules", net_policy.len());
+    }
+
+    // Stop token for producers, retry loops and deferrable work; user
+    // activity gates scheduled scans and DB maintenance. Writers have their
+    // own token, triggered once the producers are done (see 6).
+    let shutdown = Shutdown::new();
+    let drain    = Shutdown::new();
+    let (tasks, writers) = (Tasks::new(), Tasks::new());
+    let idle = IdleGate::new(cfg.scheduling.clone());
+    if cfg.scheduling.respect_user_activity {
+        spawn_monitor(SystemIdle, idle.clone(), SAMPLE_PERIOD, shutdown.clone());
+    }
+
+    // Metrics history survives restarts through its mirror directory.
+    let history_cfg = cfg.metrics.history.clone();
+    let history_dir = dir.join(metrics_history::DIR);
+    let history = MetricsHistory::load_dir(&history_dir, &history_cfg).unwrap_or_else(|e| {
+        log::warn!("cannot read metrics history from {}: {}", history_dir.display(), e);
+        MetricsHistory::new(&history_cfg)
+    });
+    if history_cfg.enabled {
+        spawn_sampler(
+            history.clone(),
+            Duration::from_secs(history_cfg.interval_secs.max(1)),
+            Some(history_dir),
+            shutdown.clone(),
+        );
+    }
+
+    // Written by `gladix-cli setup`, or generated here on first start.
+    let sensor_guid = match load_or_create_sensor_guid(&dir) {
+        Ok(guid) => guid,
+        Err(e) => {
+            let guid = uuid::Uuid::new_v4().hyphenated().to_string();
+            log::warn!("cannot keep {}: {}; using {} until restart", SENSOR_GUID_FILE, e, guid);
+            guid
+        }
+    };
+    log::info!("sensor GUID {}", sensor_guid);
+
+    // Decoded events for local tools; the ring itself has one consumer.
+    let sources = TapSources {
+        process: Some(process_intel_tx.clone()),
+        file:    Some(file_intel_tx.clone()),
+        network: Some(net_intel_tx.clone()),
+        etw:     Some(etw_intel_tx.clone()),
+        scan:    Some(scan_intel_tx.clone()),
+        image:   Some(image_intel_tx.clone()),
+        object:  Some(object_intel_tx.clone()),
+    };
+    if cfg.communications.tap
+        && let Err(e) = spawn_event_tap(&rt, tap::PIPE_NAME, sources.clone(), shutdown.clone())
+    {
+        log::warn!("event tap unavailable on {}: {}", tap::PIPE_NAME, e);
+    }
+    if cfg.export.enable {
+        let target = ExportTarget::new(&dir, &cfg.export);
+        if let Err(e) = spawn_exporter(&rt, target, sources.clone(), shutdown.clone()) {
+            log::warn!("event export unavailable on {}: {}", cfg.export.path, e);
+        }
+    }
+    if cfg.forwarder.enabled {
+        match ForwardTarget::new(&dir, &cfg.forwarder) {
+            Ok(target) => {
+                if let Err(e) = spawn_forwarder(&rt, target, sources, shutdown.clone()) {
+                    log::warn!("event forwarding unavailable on {}: {}", cfg.forwarder.spool_path, e);
+                }
+            }
+            Err(e) => log::warn!("event forwarding to {} unavailable: {:#}", cfg.forwarder.endpoint, e),
+        }
+    }
+
+    // ────────────────────────────────────────────────────────────────────
+    // 4 ▸ Components (see `health::matrix` for what may fail)
+    // ────────────────────────────────────────────────────────────────────
+    let health = HealthRegistry::new();
+    AgentStats::global().set_health(health.clone());
+    // Scanner groups as running; `SetConfig` changes them in place.
+    let schedule = Schedule::new(cfg.scanner.clone());
+    let startup = Startup::new(health.clone())
+        .component(Component::Metrics, {
+            let rt           = rt.clone();
+            let exe_dir      = exe_dir();
+            let metrics_cfg  = cfg.metrics.clone();
+            let history      = history.clone();
+            move || {
+                let _guard = rt.enter();
+                let (prometheus, exporter) = metrics_exporter::build(&metrics_cfg);
+                let handle = prometheus.handle();
+                let upkeep = exporter.is_none();
+                history.set_source(move || {
+                    // Without the exporter nobody else runs upkeep.
+                    if upkeep {
+                        handle.run_upkeep();
+                    }
+                    handle.render()
+                });
+                let installed = match metrics_cfg.perfcounters.then(|| perfcounters::start(&exe_dir)).flatten() {
+                    Some(sink) => metrics::set_global_recorder(PerfRecorder::new(prometheus, sink)).is_ok(),
+                    None       => metrics::set_global_recorder(prometheus).is_ok(),
+                };
+                anyhow::ensure!(installed, "metrics recorder already installed");
+                metrics_exporter::record_agent_info();
+                if let Some(exporter) = exporter {
+                    rt.spawn(exporter);
+                }
+                Ok(())
+            }
+        })
+        .component(Component::DbWriter("events"), {
+            let rt      = rt.clone();
+            let dir     = dir.clone();
+            let db_cfg  = db_cfg.clone();
+            let db_path = db_path.clone();
+            let (idle, shutdown, drain) = (idle.clone(), shutdown.clone(), drain.clone());
+            let (tasks, writers) = (tasks.clone(), writers.clone());
+            let mut rx  = Some(db_rx);
+            let replay  = overflow.clone().map(|overflow| (overflow, db_tx.clone()));
+            let applied = canonicalize(&cfg);
+            let (heartbeat, stats) = (cfg.heartbeat.clone(), stats.clone());
+            move || {
+                let conn = init_database(&dir, &db_cfg).context("database")?;
+                // Edits made without gladix-cli show up here, on the next start.
+                match ops_journal::record_config(&conn, &applied, Actor::Service) {
+                    Ok(Some(id)) => log::info!("config change recorded in the ops journal ({})", id),
+                    Ok(None)     => {}
+                    Err(e)       => log::warn!("ops journal: cannot record the config: {}", e),
+                }
+                let rx = rx.take().context("event writer already running")?;
+                let acks = ["process", "image", "object", "file", "network"].map(|ring| FlushAck::new(ring).0).to_vec();
+                writers.push(spawn_hub(&rt, conn, rx, &db_cfg, acks, &drain));
+                // Shed events go back in as the hub catches up.
+                if let Some((overflow, tx)) = &replay {
+                    tasks.push(spawn_replay(&rt, overflow.clone(), tx.clone(), shutdown.clone()));
+                }
+
+                // Background DB‑maintenance tasks
+                if let Some(ttl) = spawn_ttl_cleanup(&rt, db_path.clone(), &db_cfg, shutdown.clone()) {
+                    tasks.push(ttl);
+                }
+                tasks.push(spawn_wal_maintenance(&rt, db_path.clone(), &db_cfg, idle.clone(), shutdown.clone()));
+                if let Some(backfill) = spawn_compression_backfill(&rt, db_path.clone(), &db_cfg, idle.clone(), shutdown.clone()) {
+                    tasks.push(backfill);
+                }
+                tasks.push(spawn_reprocessor(&rt, db_path.clone(), idle.clone(), shutdown.clone()));
+                if let Some(handle) = spawn_heartbeat(&rt, db_path.clone(), &heartbeat, stats.clone(), &shutdown) {
+                    tasks.push(handle);
+                }
+                Ok(())
+            }
+        })
+        .component(Component::RingConsumer, {
+            let rt      = rt.clone();
+            let db_cfg  = db_cfg.clone();
+            let limits  = cfg.limits.clone();
+            let replay  = cfg.ring.replay;
+            let lag_warn_samples = cfg.ring.lag_warn_samples;
+            let bursts = (cfg.ring.max_events_per_burst, cfg.ring.blocking_decode_bytes);
+            let ring_size = cfg.ring.size_bytes;
+            let db_path = db_path.clone();
+            let sensor_guid = sensor_guid.clone();
+            let exe_path = Arc::new(ExePath::new(processes.clone()));
+            let dns_cfg = cfg.dns.clone();
+            let (tasks, shutdown) = (tasks.clone(), shutdown.clone());
+            move || {
+                match ring_size {
+                    Some(bytes) => match set_ring_size_registry(bytes) {
+                        Ok(size) => log::info!("driver rings of {} bytes from the next driver start", size),
+                        Err(e) => log::warn!("cannot set the driver ring size: {}", e),
+                    },
+                    None => {
+                        if let Ok(Some(bytes)) = ring_size_registry() {
+                            log::debug!("driver ring size asked in the registry: {} bytes", bytes);
+                        }
+                    }
+                }
+                let reported = check_driver().context("driver")?;
+                let _guard = rt.enter();
+                // One event for every ring; without it the consumers poll.
+                let wait = RingWait::open(&user_object_path(RING_EVENT), &shutdown);
+                let path = ring_path(PROCESS_RING);
+                let ring = MemoryRing::open_with_policy(&path, replay)
+                    .with_context(|| format!("opening {path}"))?
+                    .woken_by(wait.clone());
+                if let Some(stats) = reported.and_then(|d| d.ring) {
+                    ring.expect_size(stats.size).with_context(|| format!("mapping {path}"))?;
+                }
+                let conn = open_db_connection(&db_path, &db_cfg).context("database")?;
+                reconcile_ring(&conn, "process", &ring).context("consumer_state")?;
+                let listener = Arc::new(
+                    RingListener::<ProcessEvent>::new("process", ring, sensor_guid.clone())
+                        .limited(&limits, Some(process_image))
+                        .judged(Arc::new(count_truncated))
+                        .lag_warned_after(lag_warn_samples)
+                        .bursts(bursts.0, bursts.1),
+                );
+                for handle in listener.spawn(process_buses.clone(), &shutdown) {
+                    tasks.push(handle);
+                }
+
+                // Drivers without image load reporting do not map this ring.
+                let path = ring_path(IMAGE_RING);
+                match MemoryRing::open_with_policy(&path, replay) {
+                    Ok(ring) => {
+                        let ring = ring.woken_by(wait.clone());
+                        reconcile_optional(&conn, "image", &ring);
+                        let listener = Arc::new(
+                            RingListener::<ImageLoadEvent>::new("image", ring, sensor_guid.clone())
+                                .limited(&limits, None)
+                                .lag_warned_after(lag_warn_samples)
+                                .bursts(bursts.0, bursts.1),
+                        );
+                        for handle in listener.spawn(image_buses.clone(), &shutdown) {
+                            tasks.push(handle);
+                        }
+                    }
+                    Err(e) => log::warn!("{} unavailable, image loads are not recorded: {}", path, e),
+                }
+
+                protect_agent();
+                let path = ring_path(OBJECT_RING);
+                match MemoryRing::open_with_policy(&path, replay) {
+                    Ok(ring) => {
+                        let ring = ring.woken_by(wait.clone());
+                        reconcile_optional(&conn, "object", &ring);
+                        let listener = Arc::new(
+                            RingListener::<ObjectOpEvent>::new("object", ring, sensor_guid.clone())
+                                .lag_warned_after(lag_warn_samples)
+                                .bursts(bursts.0, bursts.1),
+                        );
+                        for handle in listener.spawn(object_buses.clone(), &shutdown) {
+                            tasks.push(handle);
+                        }
+                    }
+                    Err(e) => log::warn!("{} unavailable, handle access is not recorded: {}", path, e),
+                }
+
+                // Only drivers built with the minifilter map this ring.
+                let path = ring_path(FILE_RING);
+                match MemoryRing::open_with_policy(&path, replay) {
+                    Ok(ring) => {
+                        let ring = ring.woken_by(wait.clone());
+                        reconcile_optional(&conn, "file", &ring);
+                        let listener = Arc::new(
+                            RingListener::<FileEvent>::new("file", ring, sensor_guid.clone())
+                                .enriched(Arc::new(PathNormalizer::new(Arc::new(SystemVolumes))))
+                                .lag_warned_after(lag_warn_samples)
+                                .bursts(bursts.0, bursts.1),
+                        );
+                        for handle in listener.spawn(file_buses.clone(), &shutdown) {
+                            tasks.push(handle);
+                        }
+                    }
+                    Err(e) => log::debug!("{} unavailable, file activity is not recorded: {}", path, e),
+                }
+
+                net_policy.push_to_driver();
+                let path = ring_path(NETWORK_RING);
+                match MemoryRing::open_with_policy(&path, replay) {
+                    Ok(ring) => {
+                        let ring = ring.woken_by(wait);
+                        reconcile_optional(&conn, "network", &ring);
+                        let policy = net_policy.clone();
+                        let judge = Arc::new(move |ev: &mut NetworkEvent| {
+                            policy.apply(ev);
+                        });
+                        let mut listener = RingListener::<NetworkEvent>::new("network", ring, sensor_guid.clone())
+                            .enriched(exe_path.clone());
+                        if dns_cfg.enabled {
+                            // The lookup task ends once the listener drops its names.
+                            let (names, _lookups) = DnsNames::spawn(&dns_cfg, Arc::new(SystemResolver));
+                            listener = listener.enriched(Arc::new(names));
+                        }
+                        let listener = Arc::new(
+                            listener
+                                .judged(judge)
+                                .lag_warned_after(lag_warn_samples)
+                                .bursts(bursts.0, bursts.1),
+                        );
+                        for handle in listener.spawn(net_buses.clone(), &shutdown) {
+                            tasks.push(handle);
+                        }
+                    }
+                    Err(e) => log::debug!("{} unavailable, connections are not recorded: {}", path, e),
+                }
+                Ok(())
+            }
+        })
+        // The `[etw]` trace session, which needs administrator rights.
+        .component(Component::Etw, {
+            let rt    = rt.clone();
+            let etw   = cfg.etw.clone();
+            let buses = Buses { db_tx: hub_sender(&db_tx, overflow.as_ref()), intel_tx: etw_intel_tx.clone() };
+            let (sensor_guid, health) = (sensor_guid.clone(), health.clone());
+            let (tasks, shutdown) = (tasks.clone(), shutdown.clone());
+            move || {
+                if !etw.enabled {
+                    log::info!("ETW disabled");
+                    return Ok(());
+                }
+                let listener = Arc::new(EtwListener::new(etw.clone(), sensor_guid.clone()).reporting(health.clone()));
+                let _guard = rt.enter();
+                for handle in listener.spawn(buses.clone(), &shutdown) {
+                    tasks.push(handle);
+                }
+                Ok(())
+            }
+        })
+        // Rules from `[detection]`, re-read from config.toml as it changes.
+        .component(Component::Detection, {
+            let rt        = rt.clone();
+            let detection = cfg.detection.clone();
+            let config    = opts.config.clone();
+            let buses     = (process_bus.clone(), file_bus.clone(), net_bus.clone());
+            let (db_path, alerts) = (db_path.clone(), alerts.clone());
+            let (tasks, shutdown) = (tasks.clone(), shutdown.clone());
+            move || {
+                if !detection.enabled {
+                    log::info!("Detection disabled");
+                    return Ok(());
+                }
+                let rules = Detection::compile(&detection).context("detection rules")?;
+                log::info!("detection enabled with {} rules", rules.len());
+                let (process, file, network) = &buses;
+                tasks.push(spawn_detection(
+                    &rt,
+                    DetectionBuses {
+                        process: process.subscribe_with_replay(),
+                        file:    file.subscribe_with_replay(),
+                        network: network.subscribe_with_replay(),
+                    },
+                    rules,
+                    Some(RuleSource {
+                        config: config.clone(),
+                        period: Duration::from_secs(detection.reload_secs),
+                    }),
+                    db_path.clone(),
+                    alerts.clone(),
+                    shutdown.clone(),
+                ));
+                Ok(())
+            }
+        })
+        .component(Component::Scanner, {
+            let rt         = rt.clone();
+            let schedule   = schedule.clone();
+            let scanning   = cfg.scanning.clone();
+            let legacy     = dir.join(cache::LEGACY_FILE);
+            let db_cfg     = db_cfg.clone();
+            let db_path    = db_path.clone();
+            let (idle, shutdown) = (idle.clone(), shutdown.clone());
+            let tasks      = tasks.clone();
+            let buses      = scan_buses.clone();
+            move || {
+                log::info!("Starting scanner with {} groups", schedule.groups().len());
+                let conn = open_db_connection(&db_path, &db_cfg).context("scan cache")?;
+                cache::migrate_legacy(&conn, &legacy);
+                // Unreadable, the scanner would start empty and rehash every file.
+                let store = PersistentCache::open(conn).context("scan cache unreadable")?;
+                let (schedule, buses) = (schedule.clone(), buses.clone());
+                let (idle, shutdown, scanning) = (idle.clone(), shutdown.clone(), scanning.clone());
+                tasks.push(rt.spawn(run_scanner(schedule, store, buses, idle, shutdown, scanning)));
+                Ok(())
+            }
+        })
+        .component(Component::Grpc, {
+            let rt       = rt.clone();
+            let comms    = cfg.communications.clone();
+            let config   = opts.config.clone();
+            let schedule = schedule.clone();
+            let db_cfg   = db_cfg.clone();
+            let journal  = Journal::new(db_path.clone(), &db_cfg);
+            let db_path  = db_path.clone();
+            let (tasks, shutdown) = (tasks.clone(), shutdown.clone());
+            move || {
+                let addr = grpc::listen_addr(&comms)?;
+                let listener = {
+                    let _guard = rt.enter();
+                    let bound = std::net::TcpListener::bind(addr).with_context(|| format!("gRPC on {addr}"))?;
+                    bound.set_nonblocking(true)?;
+                    tokio::net::TcpListener::from_std(bound)?
+                };
+                log::info!("config and status services listening on {}", addr);
+                let server = ConfigServer::new(config.clone(), schedule.clone(), db_cfg.clone(), journal.clone())
+                    .with_status(db_path.clone());
+                let status = StatusServer::new(AgentStats::global().clone()).with_db(db_path.clone());
+                let shutdown = shutdown.clone();
+                tasks.push(rt.spawn(async move {
+                    if let Err(e) = grpc::serve(listener, server, status, shutdown).await {
+                        log::error!("config service stopped: {:#}", e);
+                    }
+                }));
+                Ok(())
+            }
+        })
+        .component(Component::Sinks, {
+            let reports    = cfg.reports.clone();
+            let groups     = cfg.scanner.clone();
+            let sink: Arc<dyn ReportSink> = Arc::new(OutboxSink { dir: dir.join("reports") });
+            let db_path    = db_path.clone();
+            let shutdown   = shutdown.clone();
+            move || {
+                if !reports.enabled || reports.groups.is_empty() {
+                    log::info!("Scan reports disabled");
+                    return Ok(());
+                }
+                let (reports, groups) = (reports.clone(), groups.clone());
+                let (sink, db_path, shutdown) = (sink.clone(), db_path.clone(), shutdown.clone());
+                thread::Builder::new()
+                    .name("reports".into())
+                    .spawn(move || {
+                        if let Err(e) = run_reports(reports, groups, db_path, sink, shutdown) {
+                            log::error!("scan reports stopped: {}", e);
+                        }
+                    })?;
+                Ok(())
+            }
+        })
+        .component(Component::Probe, {
+            let rt      = rt.clone();
+            let health  = health.clone();
+            let probe   = cfg.probe.clone();
+            let actions = Arc::new(SystemActions {
+                helper:   exe_dir().join(&cfg.probe.helper),
+                temp_dir: cfg.probe.temp_dir.clone().unwrap_or_else(std::env::temp_dir),
+            });
+            let taps = ProbeTaps { process: Some(process_intel_tx.clone()), ..ProbeTaps::default() };
+            let db_path = db_path.clone();
+            move || {
+                let prober = Prober::new(&probe, actions.clone(), taps.clone(), health.clone());
+                spawn_probe(&rt, db_path.clone(), &probe, prober);
+                Ok(())
+            }
+        });
+
+    let report = match startup.run() {
+        Ok(report) => report,
+        Err(crash) => {
+            match crash.write_to(&dir) {
+                Ok(path) => log::error!("crash report written to {}", path.display()),
+                Err(e)   => log::error!("cannot write crash report: {}", e),
+            }
+            history.sample();
+            let crash_dir = dir.join(metrics_history::CRASH_DIR);
+            if let Err(e) = history.write_bundle(&crash_dir, history_cfg.bundle_files, &history_cfg.csv_series) {
+                log::error!("cannot write crash metrics to {}: {}", crash_dir.display(), e);
+            }
+            shutdown.trigger();
+            drain.trigger();
+            return Err(anyhow!("{crash}"));
+        }
+    };
+    spawn_watchdog(
+        report,
+        RetryPolicy::new("watchdog", WATCHDOG_INITIAL)
+            .max_delay(WATCHDOG_MAX)
+            .cancel_on(shutdown.clone()),
+        Journal::new(db_path.clone(), &db_cfg),
+    );
+
+    on_phase(Phase::Running);
+    log::info!("Agent running (degraded: {:?})", health.degraded());
+
+    // ────────────────────────────────────────────────────────────────────
+    // 5 ▸ Shutdown
+    // ────────────────────────────────────────────────────────────────────
+    let _ = stop.recv();
+    log::warn!("Shutdown initiated");
+    on_phase(Phase::Stopping);
+    shutdown.trigger();
+    let pending = rt.block_on(async {
+        let deadline = tokio::time::Instant::now() + STOP_TIMEOUT;
+        // Producers first, so the writers store everything they forwarded.
+        let producers = tasks.join(deadline).await;
+        drain.trigger();
+        producers + writers.join(deadline).await
+    });
+    if pending > 0 {
+        log::warn!("{} task(s) still running after {:?}", pending, STOP_TIMEOUT);
+    }
+    log::info!("Agent stopped cleanly");
+    Ok(())
+}
@@ -0,0 +1,246 @@
+// src/scanner/async_engine.rs
+
+//! Scanner core on the shared Tokio runtime.
+//!
+//! No threads of its own: readdir and hashing run on `spawn_blocking`, a
+//! semaphore bounds the files in flight, and idle deferral, writer pressure
+//! and shutdown are awaited instead of slept on. The read budget and YARA
+//! rules are shared by every group; a throttled read or a rule scan blocks
+//! its `spawn_blocking` thread, never the runtime.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    pin::pin,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use futures::{stream, Stream, StreamExt};
+use tokio::{sync::Semaphore, task::{self, JoinSet}};
+
+use super::cache::{prune_missing, FileCacheEntry, PersistentCache};
+use super::jobs::manual_pass;
+use super::rules::Rules;
+use super::schedule::Schedule;
+use super::scheduler::{publishing_options, ListOptions, Tree};
+use super::throttle::ReadThrottle;
+use super::worker::{process_file, PassSummary, ScanOptions};
+use crate::comms::listeners::Buses;
+use crate::config::model::ScanningConfig;
+use crate::db::db_writer::{pressure_eased, under_pressure};
+use crate::idle::{IdleGate, Task};
+use crate::util::Shutdown;
+use shared::events::ScanResult;
+
+type Cache = Arc<Mutex<HashMap<PathBuf, FileCacheEntry>>>;
+
+/// Files under `root` as `opts` allow, one `read_dir` per blocking call.
+pub fn walk(root: PathBuf, opts: Arc<ListOptions>) -> impl Stream<Item = PathBuf> {
+    stream::unfold(Some(Tree::new(root, opts)), |tree| async move {
+        let mut tree = tree?;
+        let (tree, files) = task::spawn_blocking(move || {
+            let files = tree.next_dir();
+            (tree, files)
+        })
+        .await
+        .ok()?;
+        Some((stream::iter(files?), Some(tree)))
+    })
+    .flatten()
+}
+
+/// One pass over `dirs`, at most `limit` files in flight. Returns `None`
+/// if `shutdown` interrupted it; the cache then keeps the entries of
+/// directories not fully listed.
+pub async fn scan_pass(
+    dirs: &[PathBuf],
+    cache: &Cache,
+    opts: &Arc<ScanOptions>,
+    limit: &Arc<Semaphore>,
+    shutdown: &Shutdown,
+) -> Option<PassSummary> {
+    let started = Instant::now();
+    let mut summary = PassSummary::default();
+    if let Some(rules) = &opts.rules {
+        let rules = Arc::clone(rules);
+        let _ = task::spawn_blocking(move || rules.refresh()).await;
+    }
+    for dir in dirs {
+        let exists = {
+            let dir = dir.clone();
+            task::spawn_blocking(move || dir.exists()).await.unwrap_or(false)
+        };
+        if !exists {
+            log::warn!("Skipping non-existent dir: {:?}", dir);
+            continue;
+        }
+        log::info!("Scanning {:?}", dir);
+
+        let mut listed = HashSet::new();
+        let mut tasks = JoinSet::new();
+        let mut files = pin!(walk(dir.clone(), Arc::clone(&opts.listing)));
+        let mut cancelled = false;
+        while let Some(path) = files.next().await {
+            if under_pressure() {
+                tokio::select! {
+                    _ = pressure_eased() => {}
+                    _ = shutdown.triggered() => { cancelled = true; break; }
+                }
+            }
+            let permit = tokio::select! {
+                p = limit.clone().acquire_owned() => p.expect("scan semaphore is never closed"),
+                _ = shutdown.triggered() => { cancelled = true; break; }
+            };
+            listed.insert(path.clone());
+            let (cache, opts) = (Arc::clone(cache), Arc::clone(opts));
+            tasks.spawn_blocking(move || {
+                // Errors are skipped; the file is counted anyway.
+                let hashed = process_file(&path, &cache, &opts).unwrap_or(0);
+                drop(permit);
+                hashed
+            });
+        }
+        // Blocking tasks cannot be aborted; let the started ones finish.
+        while let Some(hashed) = tasks.join_next().await {
+            summary.bytes += hashed.unwrap_or(0);
+        }
+        if cancelled {
+            return None;
+        }
+        summary.files += listed.len() as u64;
+        log::debug!("Found {} candidates in {:?}", listed.len(), dir);
+
+        let pruned = prune_missing(&mut cache.lock().unwrap(), dir, &listed);
+        if pruned > 0 {
+            log::debug!("Dropped {} cache entries no longer in {:?}", pruned, dir);
+        }
+    }
+    summary.elapsed = started.elapsed();
+    Some(summary)
+}
+
+/// Runs the scanner: one task per group sharing `scanning.concurrency`
+/// hashing slots, following `schedule`. Groups without an interval are
+/// manual-only and wait until they are given one; one more task runs the
+/// scans queued on `schedule.jobs()`.
+/// Returns once `shutdown` is triggered and every group task has stopped.
+pub async fn run_scanner(
+    schedule: Schedule,
+    store: PersistentCache,
+    buses: Buses<ScanResult>,
+    idle: IdleGate,
+    shutdown: Shutdown,
+    scanning: ScanningConfig,
+) {
+    let concurrency = scanning.concurrency;
+    let (store, loaded) = task::spawn_blocking(move || {
+        let mut store = store;
+        let loaded = store.load();
+        (store, loaded)
+    })
+    .await
+    .expect("loading the scan cache does not panic");
+    let cache: Cache = Arc::new(Mutex::new(loaded));
+    let store = Arc::new(Mutex::new(store));
+    let limit = Arc::new(Semaphore::new(concurrency.max(1)));
+    let throttle = ReadThrottle::new(scanning.read_bytes_per_sec).map(Arc::new);
+    let rules = match scanning.rules_dir {
+        Some(dir) => Some(Arc::new(
+            task::spawn_blocking(move || Rules::load(dir)).await.expect("loading YARA rules does not panic"),
+        )),
+        None => None,
+    };
+
+    let groups = schedule.groups();
+    log::info!("Scheduling {} group(s) on the async engine ({} slots)", groups.len(), concurrency.max(1));
+    log::info!("SHA-256 backend: {:?}", super::hash::sha256_backend());
+
+    let mut passes = JoinSet::new();
+    for group in groups {
+        let opts = Arc::new(ScanOptions {
+            throttle: throttle.clone(),
+            rules: rules.clone().filter(|_| group.yara),
+            ..publishing_options(&group, &buses)
+        });
+        let (cache, limit, store) = (Arc::clone(&cache), Arc::clone(&limit), Arc::clone(&store));
+        let (schedule, idle, shutdown) = (schedule.clone(), idle.clone(), shutdown.clone());
+        let risk = group.risk;
+        passes.spawn(async move {
+            match group.interval {
+                Some(interval) => log::info!("[{:?}] Task starting (interval={}s)", risk, interval.as_secs()),
+                None => {
+                    log::info!("[{:?}] No interval, waiting for one", risk);
+                    if !schedule.wait_next(risk, Instant::now(), &shutdown).await {
+                        return;
+                    }
+                }
+            }
+            loop {
+                // Scheduled passes yield to an active user; see `idle`.
+                if !idle.wait(Task::Scanner, &shutdown).await {
+                    break;
+                }
+                log::info!("[{:?}] Starting scan pass", risk);
+                let dirs = schedule.group(risk).map(|g| g.directories).unwrap_or_default();
+                let Some(summary) = scan_pass(&dirs, &cache, &opts, &limit, &shutdown).await else {
+                    break;
+                };
+                summary.report(risk);
+                save(&store, &cache).await;
+                log::info!("[{:?}] Next pass due in {:?}", risk, schedule.interval(risk));
+
+                if !schedule.wait_next(risk, Instant::now(), &shutdown).await {
+                    break;
+                }
+            }
+            log::info!("[{:?}] Scanner task stopped", risk);
+        });
+    }
+    passes.spawn({
+        let (cache, limit, store) = (Arc::clone(&cache), Arc::clone(&limit), Arc::clone(&store));
+        let (schedule, shutdown) = (schedule.clone(), shutdown.clone());
+        async move {
+            let jobs = schedule.jobs().clone();
+            let commands = jobs.commands();
+            let mut commands = commands.lock().await;
+            loop {
+                let command = tokio::select! {
+                    received = commands.recv() => match received {
+                        Some(command) => command,
+                        None => break,
+                    },
+                    _ = shutdown.triggered() => break,
+                };
+                let (dirs, opts) = match manual_pass(&schedule, &command.target, &buses, throttle.clone(), rules.as_ref()) {
+                    Ok(pass) => pass,
+                    Err(e) => {
+                        jobs.finished(command.id, Err(e));
+                        continue;
+                    }
+                };
+                log::info!("Scan job {} starting: {:?}", command.id, command.target);
+                jobs.started(command.id);
+                let Some(summary) = scan_pass(&dirs, &cache, &Arc::new(opts), &limit, &shutdown).await else {
+                    jobs.finished(command.id, Err("interrupted by shutdown".into()));
+                    break;
+                };
+                save(&store, &cache).await;
+                jobs.finished(command.id, Ok(summary));
+            }
+        }
+    });
+    while passes.join_next().await.is_some() {}
+    // Keeps what interrupted passes hashed.
+    save(&store, &cache).await;
+}
+
+/// Writes what changed since the last save, off the runtime threads.
+async fn save(store: &Arc<Mutex<PersistentCache>>, cache: &Cache) {
+    let (store, cache) = (Arc::clone(store), Arc::clone(cache));
+    let saved = task::spawn_blocking(move || store.lock().unwrap().save(&cache)).await;
+    match saved {
+        Ok(Ok(n)) => log::info!("Cache saved ({} rows changed)", n),
+        Ok(Err(e)) => log::error!("Cannot save the scan cache: {}", e),
+        Err(_) => {}
+    }
+}
@@ -1,113 +1,173 @@
 // src/scanner/cache.rs
 
-//! Persistent file‐scan cache with HMAC integrity checks.
+//! File-scan cache, persisted in the `scan_cache` table of the agent database.
+//!
+//! The scanner works on an in-memory map; [`PersistentCache`] remembers what
+//! it last wrote so a save after each pass only touches the rows that pass
+//! changed. Caches from older versions lived in a signed JSON file, imported
+//! once by [`migrate_legacy`].
 
 use std::{
-    collections::{BTreeMap, HashMap},
-    fs, fs::File,
-    io::{BufReader, Write},
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
     path::{Path, PathBuf},
+    sync::Mutex,
 };
-use hmac::{Hmac, Mac};
+use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
-use sha2::{digest::KeyInit, Sha256};
 
-// HMAC-SHA256 type alias and fixed key for cache signing
-type HmacSha256 = Hmac<Sha256>;
-static HMAC_KEY: &[u8] = b"super_secret_key";
+use super::streams::split_stream;
+use crate::db::scan_cache::{delete_entries, load_cache, upsert_entries};
 
-/// Represents a cached scan result for a file.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// File the cache was kept in before the database, next to the agent.
+pub const LEGACY_FILE: &str = "persistent_cache.json";
+
+/// `scan_result` of placeholders whose content was not read; `hash` is 0.
+pub const SKIPPED_OFFLINE: &str = "skipped_offline";
+
+/// `scan_result` of files that kept changing while they were hashed, or
+/// that a writer held without sharing; `hash` is 0 and `retry_after` set.
+pub const UNSTABLE: &str = "unstable";
+
+/// Represents a cached scan result for a file or a `path:stream` entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FileCacheEntry {
     pub hash: u64,
     pub timestamp: u64,
     pub scan_result: Option<String>,
+    /// Bytes hashed; absent in caches written before sizes were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Hex SHA-256, for groups hashing with it; absent in older caches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Seconds since the epoch before which the file is not hashed again;
+    /// only set on [`UNSTABLE`] entries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
 }
 
-/// Wrapper that holds the serialized cache and its signature.
-/// The BTreeMap ensures consistent ordering before signing.
-#[derive(Serialize, Deserialize)]
-struct CacheWrapper {
+/// Layout of [`LEGACY_FILE`]. The signature used a key compiled into the
+/// agent, so it proved nothing and is not checked.
+#[derive(Deserialize)]
+struct LegacyCache {
     data: BTreeMap<String, FileCacheEntry>,
-    signature: String,
 }
 
-/// Convert PathBuf-keyed cache into a string-keyed, sorted map for JSON serialization.
-/// Sorting ensures deterministic signature computation.
-fn convert_cache_to_string_keys(
-    cache: &HashMap<PathBuf, FileCacheEntry>,
-) -> BTreeMap<String, FileCacheEntry> {
-    let mut m = BTreeMap::new();
-    for (p, e) in cache {
-        m.insert(p.to_string_lossy().into_owned(), e.clone());
-    }
-    m
+/// Drops entries under `dir` whose file was not listed in the last pass, so
+/// the cache mirrors what is on disk. Stream entries follow their file.
+/// Returns how many were removed.
+pub fn prune_missing(
+    cache: &mut HashMap<PathBuf, FileCacheEntry>,
+    dir: &Path,
+    listed: &HashSet<PathBuf>,
+) -> usize {
+    let before = cache.len();
+    cache.retain(|path, _| {
+        if !path.starts_with(dir) {
+            return true;
+        }
+        let key = path.to_string_lossy();
+        let (file, _) = split_stream(&key);
+        listed.contains(path) || listed.contains(Path::new(file))
+    });
+    before - cache.len()
 }
 
-/// Reverse operation: reconstruct PathBuf-keyed cache from the string-keyed map.
-fn convert_cache_from_string_keys(
-    map: BTreeMap<String, FileCacheEntry>,
-) -> HashMap<PathBuf, FileCacheEntry> {
-    map.into_iter()
-        .map(|(s, e)| (PathBuf::from(s), e))
-        .collect()
+/// The scanner's side of `scan_cache`.
+pub struct PersistentCache {
+    conn:   Connection,
+    /// Entries as last loaded or saved.
+    saved:  HashMap<PathBuf, FileCacheEntry>,
+    /// Read by [`open`](Self::open), handed out by the first
+    /// [`load`](Self::load).
+    opened: Option<HashMap<PathBuf, FileCacheEntry>>,
 }
 
-/// Generate an HMAC signature over JSON data.
-/// Protects against tampering of cache file contents.
-fn compute_signature(data: &str) -> String {
-    let mut mac = HmacSha256::new_from_slice(HMAC_KEY).unwrap();
-    mac.update(data.as_bytes());
-    hex::encode(mac.finalize().into_bytes())
-}
+impl PersistentCache {
+    pub fn new(conn: Connection) -> Self {
+        Self { conn, saved: HashMap::new(), opened: None }
+    }
 
-/// Load cache from disk, verifying the HMAC before trusting data.
-/// Falls back to empty cache on any I/O/parse/signature error.
-pub fn load_persistent_cache<P: AsRef<Path>>(path: P) -> HashMap<PathBuf, FileCacheEntry> {
-    if let Ok(f) = File::open(&path) {
-        // Deserialize wrapper containing sorted data and signature
-        if let Ok(wrapper) = serde_json::from_reader::<_, CacheWrapper>(BufReader::new(f)) {
-            // Re-serialize only the data portion for signature comparison
-            if let Ok(json_data) = serde_json::to_string_pretty(&wrapper.data) {
-                if compute_signature(&json_data) == wrapper.signature {
-                    log::info!("Loaded cache from {:?}", path.as_ref());
-                    return convert_cache_from_string_keys(wrapper.data);
-                } else {
-                    log::warn!("Signature mismatch for {:?}; starting fresh", path.as_ref());
-                }
+    /// Reads the stored cache now, so a cache that cannot be read fails the
+    /// scanner's start rather than its first pass.
+    pub fn open(conn: Connection) -> rusqlite::Result<Self> {
+        let cache = load_cache(&conn)?;
+        Ok(Self { conn, saved: HashMap::new(), opened: Some(cache) })
+    }
+
+    /// The stored cache; empty, with the error logged, if it cannot be read.
+    pub fn load(&mut self) -> HashMap<PathBuf, FileCacheEntry> {
+        let cache = match self.opened.take() {
+            Some(cache) => Ok(cache),
+            None => load_cache(&self.conn),
+        };
+        match cache {
+            Ok(cache) => {
+                log::info!("Loaded {} cache entries", cache.len());
+                self.saved = cache.clone();
+                cache
+            }
+            Err(e) => {
+                log::error!("Cannot read the scan cache, starting empty: {}", e);
+                HashMap::new()
             }
         }
     }
-    // Any failure -> empty cache + log info
-    log::info!( "No valid cache at {:?}; using empty", path.as_ref());
-    HashMap::new()
+
+    /// Writes the entries of `cache` added or changed since the last save
+    /// and deletes those it no longer holds. Only the comparison runs under
+    /// the lock. Returns the number of rows touched; after an error the
+    /// same changes are retried by the next save.
+    pub fn save(&mut self, cache: &Mutex<HashMap<PathBuf, FileCacheEntry>>) -> rusqlite::Result<usize> {
+        let (changed, removed): (Vec<_>, Vec<_>) = {
+            let cache = cache.lock().unwrap();
+            (
+                cache
+                    .iter()
+                    .filter(|(path, entry)| self.saved.get(*path) != Some(*entry))
+                    .map(|(path, entry)| (path.clone(), entry.clone()))
+                    .collect(),
+                self.saved.keys().filter(|path| !cache.contains_key(*path)).cloned().collect(),
+            )
+        };
+        upsert_entries(&self.conn, &changed)?;
+        delete_entries(&self.conn, &removed)?;
+
+        let touched = changed.len() + removed.len();
+        for path in &removed {
+            self.saved.remove(path);
+        }
+        self.saved.extend(changed);
+        Ok(touched)
+    }
 }
 
-/// Save cache to disk with HMAC signature.
-/// Uses pretty JSON for readability; signature computed on sorted data.
-pub fn save_persistent_cache<P: AsRef<Path>>(path: P, cache: &HashMap<PathBuf, FileCacheEntry>) {
-    // Prepare sorted string-keyed map
-    let sorted = convert_cache_to_string_keys(cache);
-    // Serialize map and sign it
-    if let Ok(json_data) = serde_json::to_string_pretty(&sorted) {
-        let signature = compute_signature(&json_data);
-        let wrapper = CacheWrapper { data: sorted, signature };
-        // Final wrapper serialization
-        if let Ok(serialized) = serde_json::to_string_pretty(&wrapper) {
-            match fs::File::create(&path) {
-                Ok(mut f) => {
-                    // Write atomically; errors logged but not fatal
-                    if let Err(e) = f.write_all(serialized.as_bytes()) {
-                        log::error!( "Failed write {:?}: {}", path.as_ref(), e);
-                    } else {
-                        log::info!( "Saved cache to {:?}", path.as_ref());
-                    }
-                }
-                Err(e) => {
-                    // Directory missing or permissions issue
-                    log::error!( "Cannot create {:?}: {}", path.as_ref(), e);
-                }
+/// Imports the JSON cache at `path` into `scan_cache` and renames it to
+/// `<path>.migrated`, so this happens once. A file that cannot be parsed is
+/// renamed as well; after a database error it is kept for the next start.
+/// Returns the number of entries imported.
+pub fn migrate_legacy(conn: &Connection, path: &Path) -> usize {
+    let Ok(text) = fs::read_to_string(path) else { return 0 };
+    let imported = match serde_json::from_str::<LegacyCache>(&text) {
+        Ok(legacy) => {
+            let entries: Vec<_> = legacy.data.into_iter().map(|(p, e)| (PathBuf::from(p), e)).collect();
+            if let Err(e) = upsert_entries(conn, &entries) {
+                log::error!("Cannot import {:?} into the database: {}", path, e);
+                return 0;
             }
+            log::info!("Imported {} entries from {:?}", entries.len(), path);
+            entries.len()
+        }
+        Err(e) => {
+            log::warn!("Ignoring unreadable cache {:?}: {}", path, e);
+            0
         }
+    };
+    let mut done = path.as_os_str().to_owned();
+    done.push(".migrated");
+    if let Err(e) = fs::rename(path, &done) {
+        log::warn!("Cannot rename {:?}: {}", path, e);
     }
+    imported
 }
@@ -5,14 +5,83 @@
 //! Fast file hashing and extension‐based filtering.
 //!
 //! **Responsibilities:**
-//! - Compute `XxHash64` of file contents.
+//! - Compute `XxHash64` and SHA-256 of file contents, reading in
+//!   [`CHUNK`]-sized pieces so memory use does not grow with the file,
+//!   optionally within a shared [`ReadThrottle`] budget.
+//! - Open files for reading without locking out their writers, see
+//!   [`open_shared`].
 //! - Detect executable files by extension.
+//!
+//! Both digests are architecture independent: `sha2` selects SHA-NI, the
+//! ARMv8 SHA2 extension or its portable code at runtime, and always yields
+//! the same bytes. [`sha256_backend`] only reports which one is in use.
 
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::hash::Hasher;
+use std::io::{self, Read};
 use std::path::Path;
+use sha2::{Digest, Sha256};
 use twox_hash::XxHash64;
 
+use super::throttle::ReadThrottle;
+use crate::config::model::HashAlgorithm;
+
+/// Bytes read from a file at a time.
+pub const CHUNK: usize = 64 * 1024;
+
+/// Opens `path` for reading while letting others read, write, rename or
+/// delete it, so hashing a file never makes its writer fail. A writer that
+/// did not share the file still makes this fail, see
+/// [`is_sharing_violation`].
+pub fn open_shared(path: &Path) -> io::Result<File> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_SHARE_READ: u32 = 0x1;
+        const FILE_SHARE_WRITE: u32 = 0x2;
+        const FILE_SHARE_DELETE: u32 = 0x4;
+        std::fs::OpenOptions::new()
+            .read(true)
+            .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE)
+            .open(path)
+    }
+    #[cfg(not(windows))]
+    {
+        File::open(path)
+    }
+}
+
+/// `ERROR_SHARING_VIOLATION`: another process has the file open without
+/// sharing it. Never the case off Windows.
+pub fn is_sharing_violation(e: &io::Error) -> bool {
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    cfg!(windows) && e.raw_os_error() == Some(ERROR_SHARING_VIOLATION)
+}
+
+/// SHA-256 implementation available on the running CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sha256Backend {
+    /// x86 SHA extensions.
+    ShaNi,
+    /// ARMv8 cryptography extension.
+    ArmSha2,
+    /// Portable software implementation.
+    Portable,
+}
+
+/// Detects the SHA-256 backend; reports `Portable` on CPUs without support.
+pub fn sha256_backend() -> Sha256Backend {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if std::arch::is_x86_feature_detected!("sha") && std::arch::is_x86_feature_detected!("sse4.1") {
+        return Sha256Backend::ShaNi;
+    }
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("sha2") {
+        return Sha256Backend::ArmSha2;
+    }
+    Sha256Backend::Portable
+}
+
 /// Returns `true` if `path` has an extension in `exts`.
 pub fn is_executable_file(path: &Path, exts: &[String]) -> bool {
     let result = path
@@ -25,13 +94,52 @@ pub fn is_executable_file(path: &Path, exts: &[String]) -> bool {
     result
 }
 
+/// Digests of one file; each is `None` unless its algorithm was asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Digests {
+    pub xxh64:  Option<u64>,
+    pub sha256: Option<[u8; 32]>,
+}
+
+/// Hashes `path` with `algorithm` in a single pass.
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> io::Result<Digests> {
+    hash_file_throttled(path, algorithm, None)
+}
+
+/// [`hash_file`] charging every chunk read to `throttle`.
+pub fn hash_file_throttled(path: &Path, algorithm: HashAlgorithm, throttle: Option<&ReadThrottle>) -> io::Result<Digests> {
+    let mut xxh = algorithm.xxh64().then(|| XxHash64::with_seed(0));
+    let mut sha = algorithm.sha256().then(Sha256::new);
+    let mut file = open_shared(path)?;
+    let mut buf = vec![0u8; CHUNK];
+    loop {
+        let n = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(t) = throttle {
+            t.take(n);
+        }
+        if let Some(h) = &mut xxh {
+            h.write(&buf[..n]);
+        }
+        if let Some(h) = &mut sha {
+            h.update(&buf[..n]);
+        }
+    }
+    Ok(Digests { xxh64: xxh.map(|h| h.finish()), sha256: sha.map(|h| h.finalize().into()) })
+}
+
 /// Compute and return the `XxHash64` of a file’s contents.
-pub fn compute_file_hash(path: &Path) -> std::io::Result<u64> {
-    let f = File::open(path)?;
-    let mut buf = Vec::new();
-    let mut rdr = BufReader::new(f);
-    rdr.read_to_end(&mut buf)?;
-    let hash = XxHash64::oneshot(0, &buf);
+pub fn compute_file_hash(path: &Path) -> io::Result<u64> {
+    let hash = hash_file(path, HashAlgorithm::Xxh64)?.xxh64.expect("xxh64 requested");
     log::debug!( "compute_file_hash: {:?} → {}", path, hash);
     Ok(hash)
 }
+
+/// Compute and return the SHA-256 of a file’s contents.
+pub fn compute_sha256(path: &Path) -> io::Result<[u8; 32]> {
+    Ok(hash_file(path, HashAlgorithm::Sha256)?.sha256.expect("sha256 requested"))
+}
@@ -0,0 +1,204 @@
+// src/scanner/jobs.rs
+
+//! Scans asked for outside the schedule.
+//!
+//! `TriggerScan` queues a [`ScanCommand`] on the [`ScanJobs`] of the running
+//! [`Schedule`](super::Schedule). The engine holding the receiving end runs
+//! them one at a time next to its group loops, on the same hashing slots,
+//! and records how each went. Manual passes do not wait for
+//! the user to be idle: someone asked for them.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use shared::events::ScanResult;
+use thiserror::Error;
+use tokio::sync::{mpsc, Mutex as AsyncMutex};
+
+use super::rules::Rules;
+use super::schedule::Schedule;
+use super::scheduler::{publishing_options, EXTENSIONS, MAX_FILE_SIZE};
+use super::throttle::ReadThrottle;
+use super::worker::{PassSummary, ScanEvents, ScanOptions};
+use crate::comms::listeners::Buses;
+use crate::config::model::{DirectoryRisk, HashAlgorithm};
+
+/// Commands waiting for the engine, beyond which a trigger is refused.
+pub const QUEUE_LEN: usize = 32;
+
+/// Jobs whose status is kept; the oldest finished ones are forgotten first.
+pub const KEPT_JOBS: usize = 256;
+
+/// `risk_group` of the results of a scan of explicit paths.
+pub const MANUAL_GROUP: &str = "Manual";
+
+/// What a manual scan covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanTarget {
+    /// The directories of a group, with its settings.
+    Group(DirectoryRisk),
+    /// These paths, without exclusions or a depth limit, hashed with
+    /// XxHash64.
+    Paths(Vec<PathBuf>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanCommand {
+    pub id:     u64,
+    pub target: ScanTarget,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// How far a job got.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobStatus {
+    pub state: JobState,
+    /// Files listed and looked at, hashed or not; 0 until done.
+    pub files: u64,
+    /// Bytes read to hash them.
+    pub bytes: u64,
+    /// Why a failed job failed.
+    pub error: Option<String>,
+}
+
+impl JobStatus {
+    fn queued() -> Self {
+        Self { state: JobState::Queued, files: 0, bytes: 0, error: None }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TriggerError {
+    #[error("{QUEUE_LEN} scans are already waiting")]
+    Full,
+}
+
+/// Queue of manual scans and the status of the recent ones. Clones share
+/// them.
+#[derive(Clone)]
+pub struct ScanJobs {
+    tx:       mpsc::Sender<ScanCommand>,
+    /// Locked by the engine running the commands for as long as it runs.
+    commands: Arc<AsyncMutex<mpsc::Receiver<ScanCommand>>>,
+    jobs:     Arc<Mutex<BTreeMap<u64, JobStatus>>>,
+    last_id:  Arc<AtomicU64>,
+}
+
+impl Default for ScanJobs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScanJobs {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_LEN);
+        Self {
+            tx,
+            commands: Arc::new(AsyncMutex::new(rx)),
+            jobs:     Default::default(),
+            last_id:  Default::default(),
+        }
+    }
+
+    /// Queues a scan of `target` and returns its job id.
+    pub fn trigger(&self, target: ScanTarget) -> Result<u64, TriggerError> {
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        // Known before the engine can pick it up.
+        self.jobs.lock().unwrap().insert(id, JobStatus::queued());
+        if self.tx.try_send(ScanCommand { id, target }).is_err() {
+            self.jobs.lock().unwrap().remove(&id);
+            return Err(TriggerError::Full);
+        }
+        Ok(id)
+    }
+
+    /// `None` for an unknown id or one forgotten after [`KEPT_JOBS`].
+    pub fn status(&self, id: u64) -> Option<JobStatus> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    /// The receiving end; one engine holds it at a time.
+    pub fn commands(&self) -> Arc<AsyncMutex<mpsc::Receiver<ScanCommand>>> {
+        Arc::clone(&self.commands)
+    }
+
+    pub fn started(&self, id: u64) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            job.state = JobState::Running;
+        }
+    }
+
+    /// Records the outcome of job `id` and logs it.
+    pub fn finished(&self, id: u64, outcome: Result<PassSummary, String>) {
+        let status = match outcome {
+            Ok(summary) => {
+                log::info!(
+                    "Scan job {} done: {} files, {} bytes hashed in {:.1}s",
+                    id, summary.files, summary.bytes, summary.elapsed.as_secs_f64(),
+                );
+                JobStatus { state: JobState::Done, files: summary.files, bytes: summary.bytes, error: None }
+            }
+            Err(e) => {
+                log::warn!("Scan job {} failed: {}", id, e);
+                JobStatus { state: JobState::Failed, error: Some(e), ..JobStatus::queued() }
+            }
+        };
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.insert(id, status);
+        while jobs.len() > KEPT_JOBS {
+            let oldest = jobs.iter().find(|(_, j)| matches!(j.state, JobState::Done | JobState::Failed)).map(|(id, _)| *id);
+            match oldest {
+                Some(oldest) => jobs.remove(&oldest),
+                None => break,
+            };
+        }
+    }
+}
+
+/// Directories and options of a manual scan of `target`, publishing on
+/// `buses` within `throttle`; a group with `yara` also runs `rules`.
+/// Fails if the group is not in `schedule`.
+pub fn manual_pass(
+    schedule: &Schedule,
+    target: &ScanTarget,
+    buses: &Buses<ScanResult>,
+    throttle: Option<Arc<ReadThrottle>>,
+    rules: Option<&Arc<Rules>>,
+) -> Result<(Vec<PathBuf>, ScanOptions), String> {
+    let (dirs, opts) = match target {
+        ScanTarget::Group(risk) => {
+            let group = schedule.group(*risk).ok_or_else(|| format!("no [[scanner]] group {}", risk.as_str()))?;
+            let rules = rules.filter(|_| group.yara).cloned();
+            (group.directories.clone(), ScanOptions { rules, ..publishing_options(&group, buses) })
+        }
+        ScanTarget::Paths(paths) => (paths.clone(), path_options(buses)),
+    };
+    Ok((dirs, ScanOptions { throttle, ..opts }))
+}
+
+/// Options of a scan of explicit paths.
+fn path_options(buses: &Buses<ScanResult>) -> ScanOptions {
+    ScanOptions {
+        max_size: MAX_FILE_SIZE,
+        exts: EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+        hydrate_placeholders: false,
+        hash: HashAlgorithm::Xxh64,
+        listing: Default::default(),
+        events: Some(ScanEvents { buses: buses.clone(), risk_group: MANUAL_GROUP.into() }),
+        throttle: None,
+        rules: None,
+    }
+}
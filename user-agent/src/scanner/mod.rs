@@ -1,8 +1,15 @@
 
+pub mod async_engine;
 pub mod cache;
 pub mod hash;
+pub mod jobs;
+pub mod streams;
+pub mod throttle;
+pub mod rules;
 pub mod worker;
 pub mod scheduler;
+pub mod schedule;
 
 
-pub use scheduler::run_scanner;
+pub use async_engine::run_scanner;
+pub use schedule::Schedule;
@@ -0,0 +1,234 @@
+// src/scanner/rules.rs
+
+//! YARA rules run on the files a scan finds new or changed.
+//!
+//! Every `.yar` and `.yara` file of `[scanning] rules_dir` is compiled at
+//! startup, each in a namespace of its own so rule names may repeat across
+//! files. A file that does not compile is logged and left out; the rest
+//! still load. [`Rules::refresh`] runs before each pass and compiles again
+//! when a rule file was added, removed or modified, so rules follow the
+//! directory without a restart.
+//!
+//! A match becomes a [`RuleMatch`]: the rule, the patterns that matched and
+//! the severity from the rule's `severity` metadata (`low`, `medium`,
+//! `high` or `critical`), [`DEFAULT_SEVERITY`] when it has none.
+//!
+//! Matching needs the `yara` feature (`yara-x`). Built without it, the
+//! directory is still listed but nothing is compiled and groups with
+//! `yara = true` only hash.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use metrics::gauge;
+use shared::events::scan_result::Severity;
+
+/// Extensions of the rule files read from the directory.
+pub const RULE_EXTENSIONS: &[&str] = &["yar", "yara"];
+
+/// Severity of a rule without a `severity` of its own.
+pub const DEFAULT_SEVERITY: Severity = Severity::Medium;
+
+/// One rule matching a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleMatch {
+    /// `namespace.rule`, the namespace being the rule file's stem.
+    pub rule_id:  String,
+    /// Identifiers of the patterns found, such as `$mz`.
+    pub patterns: Vec<String>,
+    pub severity: Severity,
+}
+
+/// Severity named by a rule's `severity` metadata, case aside.
+pub fn severity(meta: Option<&str>) -> Severity {
+    meta.and_then(|s| Severity::from_str_name(&s.to_ascii_uppercase())).unwrap_or(DEFAULT_SEVERITY)
+}
+
+/// A rule file as listed: path, modification time and length.
+type Listing = Vec<(PathBuf, SystemTime, u64)>;
+
+struct Loaded {
+    listing:  Listing,
+    compiled: Option<Arc<engine::Compiled>>,
+    /// Files compiled and files left out.
+    counts:   (usize, usize),
+}
+
+/// The rules of one directory as last compiled. Shared by every group
+/// running them; scans in flight keep the rules they started with.
+pub struct Rules {
+    dir:    PathBuf,
+    loaded: Mutex<Loaded>,
+}
+
+impl std::fmt::Debug for Rules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (files, failed) = self.counts();
+        f.debug_struct("Rules").field("dir", &self.dir).field("files", &files).field("failed", &failed).finish()
+    }
+}
+
+impl Rules {
+    /// Compiles the rule files of `dir`. A directory that cannot be listed
+    /// loads no rules and is tried again on [`refresh`](Self::refresh).
+    pub fn load(dir: impl Into<PathBuf>) -> Self {
+        let rules = Self {
+            dir:    dir.into(),
+            loaded: Mutex::new(Loaded { listing: Vec::new(), compiled: None, counts: (0, 0) }),
+        };
+        rules.compile(rules.list());
+        rules
+    }
+
+    /// Compiles again if the rule files changed since the last time.
+    /// Returns whether they did.
+    pub fn refresh(&self) -> bool {
+        let listing = self.list();
+        if listing == self.loaded.lock().unwrap().listing {
+            return false;
+        }
+        log::info!("YARA rules in {} changed, compiling again", self.dir.display());
+        self.compile(listing);
+        true
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Rule files compiled and rule files left out by the last compilation.
+    pub fn counts(&self) -> (usize, usize) {
+        self.loaded.lock().unwrap().counts
+    }
+
+    /// Whether any rules compiled; files need not be read otherwise.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.lock().unwrap().compiled.is_some()
+    }
+
+    /// Rules matching `data`, the content of a file.
+    pub fn scan(&self, data: &[u8]) -> io::Result<Vec<RuleMatch>> {
+        let compiled = self.loaded.lock().unwrap().compiled.clone();
+        match compiled {
+            Some(compiled) => engine::scan(&compiled, data),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn list(&self) -> Listing {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::warn!("Cannot list YARA rules in {}: {}", self.dir.display(), e);
+                return Vec::new();
+            }
+        };
+        let mut listing: Listing = entries
+            .filter_map(Result::ok)
+            .map(|e| e.path())
+            .filter(|p| {
+                p.extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| RULE_EXTENSIONS.iter().any(|r| e.eq_ignore_ascii_case(r)))
+            })
+            .filter_map(|p| {
+                let meta = fs::metadata(&p).ok().filter(|m| m.is_file())?;
+                Some((p, meta.modified().ok()?, meta.len()))
+            })
+            .collect();
+        listing.sort();
+        listing
+    }
+
+    fn compile(&self, listing: Listing) {
+        let files: Vec<_> = listing.iter().map(|(p, _, _)| p.clone()).collect();
+        let (compiled, loaded) = engine::compile(&files);
+        let counts = (loaded, files.len() - loaded);
+        log::info!("Loaded YARA rules from {} of {} file(s) in {}", counts.0, files.len(), self.dir.display());
+        gauge!("yara_rule_files", "state" => "loaded").set(counts.0 as f64);
+        gauge!("yara_rule_files", "state" => "failed").set(counts.1 as f64);
+        *self.loaded.lock().unwrap() = Loaded { listing, compiled: compiled.map(Arc::new), counts };
+    }
+}
+
+#[cfg(feature = "yara")]
+mod engine {
+    use std::{fs, io, path::{Path, PathBuf}};
+    use yara_x::{Compiler, MetaValue, Scanner};
+
+    use super::{severity, RuleMatch};
+
+    pub type Compiled = yara_x::Rules;
+
+    /// Namespace of the rules of `file`: its stem.
+    fn namespace(file: &Path) -> String {
+        file.file_stem().map_or_else(|| "rules".into(), |s| s.to_string_lossy().into_owned())
+    }
+
+    /// Rules of `files`, with how many of them compiled.
+    pub fn compile(files: &[PathBuf]) -> (Option<Compiled>, usize) {
+        let mut compiler = Compiler::new();
+        let mut loaded = 0;
+        for file in files {
+            let source = match fs::read_to_string(file) {
+                Ok(source) => source,
+                Err(e) => {
+                    log::warn!("Cannot read YARA rules {}: {}", file.display(), e);
+                    continue;
+                }
+            };
+            // A source that fails leaves the compiler as it was.
+            compiler.new_namespace(&namespace(file));
+            match compiler.add_source(source.as_str()) {
+                Ok(_) => loaded += 1,
+                Err(e) => log::warn!("YARA rules {} skipped: {}", file.display(), e),
+            }
+        }
+        (Some(compiler.build()), loaded)
+    }
+
+    pub fn scan(rules: &Compiled, data: &[u8]) -> io::Result<Vec<RuleMatch>> {
+        let mut scanner = Scanner::new(rules);
+        let results = scanner.scan(data).map_err(io::Error::other)?;
+        Ok(results
+            .matching_rules()
+            .map(|rule| RuleMatch {
+                rule_id:  format!("{}.{}", rule.namespace(), rule.identifier()),
+                patterns: rule
+                    .patterns()
+                    .filter(|p| p.matches().next().is_some())
+                    .map(|p| p.identifier().to_owned())
+                    .collect(),
+                severity: severity(rule.metadata().find_map(|(key, value)| match value {
+                    MetaValue::String(s) if key == "severity" => Some(s),
+                    _ => None,
+                })),
+            })
+            .collect())
+    }
+}
+
+#[cfg(not(feature = "yara"))]
+mod engine {
+    use std::{io, path::PathBuf, sync::Once};
+
+    use super::RuleMatch;
+
+    /// Never built: there is no engine to compile for.
+    pub enum Compiled {}
+
+    pub fn compile(files: &[PathBuf]) -> (Option<Compiled>, usize) {
+        static WARNED: Once = Once::new();
+        if !files.is_empty() {
+            WARNED.call_once(|| log::warn!("Built without the `yara` feature; YARA rules are not loaded"));
+        }
+        (None, 0)
+    }
+
+    pub fn scan(rules: &Compiled, _data: &[u8]) -> io::Result<Vec<RuleMatch>> {
+        match *rules {}
+    }
+}
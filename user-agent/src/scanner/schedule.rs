@@ -0,0 +1,77 @@
+// src/scanner/schedule.rs
+
+//! Scanner groups as the running engine sees them.
+//!
+//! The engine reads a group's directories from the [`Schedule`] before each
+//! pass and waits on it between passes, so a change published with
+//! [`Schedule::set`] (e.g. by `comms::grpc`) applies to the wait already in
+//! progress: the next pass is due one new interval after the last one ended.
+//! Scans asked for in between go through [`Schedule::jobs`].
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+use super::jobs::ScanJobs;
+use crate::config::model::{DirectoryRisk, RiskGroup};
+use crate::util::Shutdown;
+
+/// Shared, updatable list of scanner groups.
+#[derive(Clone)]
+pub struct Schedule {
+    tx:   Arc<watch::Sender<Vec<RiskGroup>>>,
+    jobs: ScanJobs,
+}
+
+impl Schedule {
+    pub fn new(groups: Vec<RiskGroup>) -> Self {
+        Self { tx: Arc::new(watch::channel(groups).0), jobs: ScanJobs::new() }
+    }
+
+    /// Manual scans, run by the engine following this schedule.
+    pub fn jobs(&self) -> &ScanJobs {
+        &self.jobs
+    }
+
+    /// Current groups, in config order.
+    pub fn groups(&self) -> Vec<RiskGroup> {
+        self.tx.borrow().clone()
+    }
+
+    /// Replaces the groups. Engines only run the risks they started with.
+    pub fn set(&self, groups: Vec<RiskGroup>) {
+        self.tx.send_replace(groups);
+    }
+
+    pub fn group(&self, risk: DirectoryRisk) -> Option<RiskGroup> {
+        self.tx.borrow().iter().find(|g| g.risk == risk).cloned()
+    }
+
+    /// Current interval of `risk`; `None` while it is manual-only.
+    pub fn interval(&self, risk: DirectoryRisk) -> Option<Duration> {
+        self.tx.borrow().iter().find(|g| g.risk == risk).and_then(|g| g.interval)
+    }
+
+    /// Waits until the next pass of `risk` is due, one interval after
+    /// `since`. Returns `false` if `shutdown` came first.
+    pub async fn wait_next(&self, risk: DirectoryRisk, since: Instant, shutdown: &Shutdown) -> bool {
+        let mut rx = self.tx.subscribe();
+        loop {
+            let due = rx.borrow_and_update().iter().find(|g| g.risk == risk).and_then(|g| g.interval).map(|i| since + i);
+            let sleep = async {
+                match due {
+                    Some(at) => tokio::time::sleep_until(at.into()).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = sleep => return true,
+                // The sender lives in `self`, so this only resolves on a change.
+                _ = rx.changed() => {}
+                _ = shutdown.triggered() => return false,
+            }
+        }
+    }
+}
@@ -1,100 +1,125 @@
 // src/scanner/scheduler.rs
 
-//! Task scheduler & directory scanner.
+//! What a group scans: the directory walk and the options of its passes.
 
-use super::cache::{load_persistent_cache, save_persistent_cache};
-use super::worker::process_files;
+use super::worker::{ScanEvents, ScanOptions};
+use crate::comms::listeners::Buses;
 use crate::config::model::RiskGroup;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use shared::events::ScanResult;
 use std::{
+    collections::HashSet,
     fs,
     path::PathBuf,
-    sync::{Arc, Mutex},
-    thread,
-    time::Duration,
+    sync::Arc,
 };
 
-/// Recursively list all files under a directory.
-/// Logs count to aid debugging of deep directory trees.
-fn list_files(dir: &std::path::Path) -> Vec<PathBuf> {
-    let mut out = Vec::new();
-    if let Ok(entries) = fs::read_dir(dir) {
+/// How a group's directories are walked: what is excluded, whether links
+/// are entered and how deep.
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    exclude: GlobSet,
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+}
+
+impl ListOptions {
+    /// Options of `group`. Patterns that do not compile, which
+    /// `Config::validate` rejects, are left out.
+    pub fn new(group: &RiskGroup) -> Self {
+        let mut set = GlobSetBuilder::new();
+        for pattern in &group.exclude {
+            // Paths are matched as Windows compares them.
+            match GlobBuilder::new(pattern).case_insensitive(cfg!(windows)).build() {
+                Ok(glob) => {
+                    set.add(glob);
+                }
+                Err(e) => log::warn!("[{:?}] ignoring exclude pattern: {}", group.risk, e),
+            }
+        }
+        Self {
+            exclude: set.build().unwrap_or_default(),
+            follow_symlinks: group.follow_symlinks,
+            max_depth: group.max_depth,
+        }
+    }
+}
+
+/// Directories still to list under one root.
+pub struct Tree {
+    opts: Arc<ListOptions>,
+    /// Directories to list with their depth below the root.
+    pending: Vec<(PathBuf, usize)>,
+    /// Canonical paths of the directories queued, so following links
+    /// cannot loop. Only kept when links are followed.
+    seen: HashSet<PathBuf>,
+}
+
+impl Tree {
+    pub fn new(root: PathBuf, opts: Arc<ListOptions>) -> Self {
+        let mut tree = Self { opts, pending: Vec::new(), seen: HashSet::new() };
+        if tree.first_visit(&root) {
+            tree.pending.push((root, 0));
+        }
+        tree
+    }
+
+    /// Lists the next directory: queues its subdirectories and returns its
+    /// files. `None` once every directory was listed.
+    pub fn next_dir(&mut self) -> Option<Vec<PathBuf>> {
+        let (dir, depth) = self.pending.pop()?;
+        let mut files = Vec::new();
+        let Ok(entries) = fs::read_dir(&dir) else { return Some(files) };
         for e in entries.flatten() {
             let p = e.path();
+            if self.opts.exclude.is_match(&p) {
+                continue;
+            }
+            // Symbolic links and junctions; cloud placeholders are not links.
+            let link = e.file_type().is_ok_and(|t| t.is_symlink());
+            if link && !self.opts.follow_symlinks {
+                continue;
+            }
             if p.is_dir() {
-                // Recurse into subdirectories
-                out.extend(list_files(&p));
+                if self.opts.max_depth.is_none_or(|max| depth < max) && self.first_visit(&p) {
+                    self.pending.push((p, depth + 1));
+                }
             } else {
-                out.push(p);
+                files.push(p);
             }
         }
+        Some(files)
     }
 
-    log::debug!( "list_files: {:?} → {} entries", dir, out.len());
-    out
+    fn first_visit(&mut self, dir: &std::path::Path) -> bool {
+        !self.opts.follow_symlinks || fs::canonicalize(dir).map_or(true, |c| self.seen.insert(c))
+    }
 }
 
-/// Launches one thread per risk group to perform scheduled scans.
-/// Each thread:
-/// 1. Logs start of scan pass.
-/// 2. Lists files in each directory, skipping missing ones.
-/// 3. Delegates to worker pool for concurrent file processing.
-/// 4. Saves updated cache and sleeps until next interval.
-pub fn run_scanner(groups: Vec<RiskGroup>, cache_path: PathBuf) {
-    // Shared cache loaded once and passed to all threads
-    let cache = Arc::new(Mutex::new(load_persistent_cache(&cache_path)));
-    // Extensions to consider executable—immutable shared state
-    let exts = Arc::new(vec!["exe".into(), "dll".into(), "sys".into(), "ocx".into()]);
-    // Maximum file size to process (50 MB)
-    let max_size = 50 * 1024 * 1024;
-
-    log::info!( "Scheduling {} group(s)", groups.len());
-
-    for group in groups {
-        let cache_cloned = Arc::clone(&cache);
-        let exts_cloned = Arc::clone(&exts);
-        let cache_file = cache_path.clone();
-        // Capture directories and scan interval ahead of thread loop
-        let dirs: Vec<PathBuf> = group.directories.into_iter().collect();
-        let secs = group
-            .interval
-            .expect("scheduled_interval must be set")
-            .as_secs();
-
-        thread::spawn(move || {
-            log::info!( "Thread for {:?} starting (interval={}s)", group.risk, secs);
+/// Extensions considered executable, the only files hashed.
+pub const EXTENSIONS: [&str; 4] = ["exe", "dll", "sys", "ocx"];
 
-            loop {
-                log::info!( "[{:?}] Starting scan pass", group.risk);
+/// Files and streams above this size are not hashed (50 MB).
+pub const MAX_FILE_SIZE: u64 = 50 * 1024 * 1024;
 
-                for dir in &dirs {
-                    if !dir.exists() {
-                        // Warn and skip directories that may have been removed
-                        log::warn!("Skipping non-existent dir: {:?}", dir);
-                        continue;
-                    }
-                    log::info!("Scanning {:?}", dir);
-
-                    // Collect candidate files (expensive I/O)
-                    let files = list_files(dir);
-                    log::debug!( "Found {} candidates in {:?}", files.len(), dir);
-
-                    // Parallel processing; ignores errors inside
-                    process_files(files, Arc::clone(&cache_cloned), max_size, Arc::clone(&exts_cloned));
-                }
-
-                // Persist updated cache after each pass
-                save_persistent_cache(&cache_file, &*cache_cloned.lock().unwrap());
-                log::info!( "[{:?}] Cache written to {:?}", group.risk, cache_file);
-                log::debug!( "[{:?}] Sleeping for {}s", group.risk, secs);
-
-                // Sleep until next scheduled scan iteration
-                thread::sleep(Duration::from_secs(secs));
-            }
-        });
+/// Limits applied to every group.
+pub fn group_options(group: &RiskGroup) -> ScanOptions {
+    ScanOptions {
+        max_size: MAX_FILE_SIZE,
+        exts: EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+        hydrate_placeholders: group.hydrate_placeholders,
+        hash: group.hash,
+        listing: Arc::new(ListOptions::new(group)),
+        events: None,
+        throttle: None,
+        rules: None,
     }
+}
 
-    // Main thread idles to keep process alive; caches are saved by worker threads
-    loop {
-        thread::sleep(Duration::from_secs(600));
+/// [`group_options`] publishing the group's new or changed files on `buses`.
+pub fn publishing_options(group: &RiskGroup, buses: &Buses<ScanResult>) -> ScanOptions {
+    ScanOptions {
+        events: Some(ScanEvents { buses: buses.clone(), risk_group: format!("{:?}", group.risk) }),
+        ..group_options(group)
     }
 }
@@ -0,0 +1,162 @@
+// src/scanner/streams.rs
+
+//! NTFS alternate data streams and cloud placeholder detection.
+//!
+//! Streams are addressed with the Win32 `path:stream` form, which is also
+//! the cache key and the event path the scanner records for them.
+//! Placeholders (OneDrive and other cloud files) are recognised from their
+//! attributes so the scanner can skip them instead of triggering a download.
+
+use std::{
+    fs::Metadata,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+pub const FILE_ATTRIBUTE_SPARSE_FILE: u32           = 0x0000_0200;
+pub const FILE_ATTRIBUTE_OFFLINE: u32               = 0x0000_1000;
+pub const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32        = 0x0004_0000;
+pub const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x0040_0000;
+
+/// Reading the content of a file with any of these flags fetches it from
+/// remote storage. Sparse files alone are local and are read normally.
+const PLACEHOLDER_MASK: u32 =
+    FILE_ATTRIBUTE_OFFLINE | FILE_ATTRIBUTE_RECALL_ON_OPEN | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS;
+
+pub fn is_placeholder(attributes: u32) -> bool {
+    attributes & PLACEHOLDER_MASK != 0
+}
+
+/// Win32 file attributes; always 0 off Windows.
+pub fn file_attributes(meta: &Metadata) -> u32 {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        meta.file_attributes()
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = meta;
+        0
+    }
+}
+
+/// A named data stream of a file; the unnamed default stream is not listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamInfo {
+    /// Name without the leading `:` and the `:$DATA` type.
+    pub name: String,
+    pub size: u64,
+}
+
+/// `path:name`, the path Win32 APIs accept to open a stream.
+pub fn stream_path(path: &Path, name: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(":");
+    s.push(name);
+    PathBuf::from(s)
+}
+
+/// Splits `C:\dir\file.txt:payload` into the file path and stream name. A
+/// drive letter colon is not a stream separator.
+pub fn split_stream(path: &str) -> (&str, Option<&str>) {
+    let file_start = path.rfind(['\\', '/']).map_or(0, |i| i + 1);
+    let name = &path[file_start..];
+    let drive = name.len() >= 2 && file_start == 0 && name.as_bytes()[1] == b':';
+    match name.find(':') {
+        Some(i) if !(drive && i == 1) => (&path[..file_start + i], Some(&name[i + 1..])),
+        _ => (path, None),
+    }
+}
+
+/// Named streams look executable when their name has one of `exts` or the
+/// content starts with an `MZ` header.
+pub fn looks_executable(stream: &Path, name: &str, exts: &[String]) -> bool {
+    let by_name = Path::new(name)
+        .extension()
+        .is_some_and(|e| exts.iter().any(|x| x.eq_ignore_ascii_case(&e.to_string_lossy())));
+    if by_name {
+        return true;
+    }
+    let mut magic = [0u8; 2];
+    std::fs::File::open(stream)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .is_ok_and(|_| &magic == b"MZ")
+}
+
+/// Named data streams of `path`. Empty off Windows and on volumes without
+/// stream support.
+pub fn alternate_streams(path: &Path) -> io::Result<Vec<StreamInfo>> {
+    #[cfg(windows)]
+    {
+        sys::find_streams(path)
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = path;
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::StreamInfo;
+    use std::{ffi::c_void, io, os::windows::ffi::OsStrExt, path::Path};
+
+    const FIND_STREAM_INFO_STANDARD: i32 = 0;
+    const INVALID_HANDLE_VALUE: isize = -1;
+    const ERROR_HANDLE_EOF: i32 = 38;
+    const MAX_PATH: usize = 260;
+
+    #[repr(C)]
+    struct Win32FindStreamData {
+        stream_size: i64,
+        stream_name: [u16; MAX_PATH + 36],
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn FindFirstStreamW(name: *const u16, level: i32, data: *mut c_void, flags: u32) -> isize;
+        fn FindNextStreamW(handle: isize, data: *mut c_void) -> i32;
+        fn FindClose(handle: isize) -> i32;
+    }
+
+    fn to_info(data: &Win32FindStreamData) -> Option<StreamInfo> {
+        let len = data.stream_name.iter().position(|&c| c == 0).unwrap_or(data.stream_name.len());
+        let raw = String::from_utf16_lossy(&data.stream_name[..len]);
+        // ":name:$DATA"; the default stream is "::$DATA".
+        let name = raw.strip_prefix(':')?.strip_suffix(":$DATA")?;
+        (!name.is_empty()).then(|| StreamInfo { name: name.to_owned(), size: data.stream_size as u64 })
+    }
+
+    pub fn find_streams(path: &Path) -> io::Result<Vec<StreamInfo>> {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut data = Win32FindStreamData { stream_size: 0, stream_name: [0; MAX_PATH + 36] };
+        let ptr = &mut data as *mut Win32FindStreamData as *mut c_void;
+
+        // SAFETY: `wide` is NUL-terminated and `data` outlives every call.
+        let handle = unsafe { FindFirstStreamW(wide.as_ptr(), FIND_STREAM_INFO_STANDARD, ptr, 0) };
+        if handle == INVALID_HANDLE_VALUE {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(ERROR_HANDLE_EOF) => Ok(Vec::new()),
+                _ => Err(err),
+            };
+        }
+        let mut out = Vec::new();
+        loop {
+            out.extend(to_info(&data));
+            // SAFETY: `handle` is a live find handle.
+            if unsafe { FindNextStreamW(handle, ptr) } == 0 {
+                break;
+            }
+        }
+        let err = io::Error::last_os_error();
+        // SAFETY: closed exactly once.
+        unsafe { FindClose(handle) };
+        match err.raw_os_error() {
+            Some(ERROR_HANDLE_EOF) => Ok(out),
+            _ => Err(err),
+        }
+    }
+}
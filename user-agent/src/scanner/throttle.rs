@@ -0,0 +1,56 @@
+// src/scanner/throttle.rs
+
+//! Read budget shared by every hashing worker.
+//!
+//! A token bucket refilled at `[scanning] read_bytes_per_sec`, holding at
+//! most one second of reads. A worker takes the bytes of each chunk after
+//! reading it; when that leaves the bucket in debt it sleeps until the debt
+//! is repaid, so the workers together average the configured rate however
+//! many there are.
+
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes that can be read without waiting; negative while in debt.
+    tokens: f64,
+    last:   Instant,
+}
+
+#[derive(Debug)]
+pub struct ReadThrottle {
+    rate:   f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl ReadThrottle {
+    /// `None` when `bytes_per_sec` is 0, which means unlimited.
+    pub fn new(bytes_per_sec: u64) -> Option<Self> {
+        (bytes_per_sec > 0).then(|| Self {
+            rate:   bytes_per_sec as f64,
+            bucket: Mutex::new(Bucket { tokens: bytes_per_sec as f64, last: Instant::now() }),
+        })
+    }
+
+    /// How long whoever just read `bytes` must wait, charging them to the
+    /// bucket as of `now`.
+    pub fn charge(&self, bytes: usize, now: Instant) -> Duration {
+        let mut b = self.bucket.lock().unwrap();
+        let refill = now.saturating_duration_since(b.last).as_secs_f64() * self.rate;
+        b.tokens = (b.tokens + refill).min(self.rate) - bytes as f64;
+        b.last = b.last.max(now);
+        if b.tokens >= 0.0 { Duration::ZERO } else { Duration::from_secs_f64(-b.tokens / self.rate) }
+    }
+
+    /// Charges `bytes` and sleeps for as long as that put the bucket in debt.
+    pub fn take(&self, bytes: usize) {
+        let wait = self.charge(bytes, Instant::now());
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
@@ -1,102 +1,384 @@
 // src/scanner/worker.rs
 
-//! Concurrent file‐processing engine.
+//! What a pass does with each file: hashing, caching and publishing it.
 
-use super::cache::FileCacheEntry;
-use super::hash::{compute_file_hash, is_executable_file};
+use super::cache::{FileCacheEntry, SKIPPED_OFFLINE, UNSTABLE};
+use super::hash::{hash_file_throttled, is_executable_file, is_sharing_violation, Digests};
+use super::rules::{RuleMatch, Rules};
+use super::scheduler::ListOptions;
+use super::streams::{
+    alternate_streams, file_attributes, is_placeholder, looks_executable, stream_path, StreamInfo,
+};
+use super::throttle::ReadThrottle;
+use crate::comms::{listeners::Buses, WrappedEvent};
+use crate::config::model::{DirectoryRisk, HashAlgorithm};
+use crate::status::AgentStats;
+use metrics::{counter, histogram};
+use shared::events::ScanResult;
 use std::{
     collections::HashMap,
+    fmt,
     fs,
-    io::ErrorKind,
+    io::{self, ErrorKind},
+    ops::AddAssign,
     path::{Path, PathBuf},
-    sync::{mpsc, Arc, Mutex},
+    sync::{Arc, Mutex},
     thread,
-    time::UNIX_EPOCH,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-/// Checks file metadata and content hash to decide whether to process a file.
-/// - Skips files larger than `max_size` or non-executable based on extension blacklist/whitelist.
-/// - Uses timestamp + hash comparison to avoid reprocessing unchanged files.
-fn process_file(
-    path: &Path,
-    cache: &Arc<Mutex<HashMap<PathBuf, FileCacheEntry>>>,
-    max_size: u64,
-    exts: &[String],
-) -> std::io::Result<()> {
-    let meta = fs::metadata(path)?;
+/// `sensor_guid` of the events the scanner publishes.
+pub const SCANNER_SENSOR: &str = "scanner";
 
-    // Skip based on size or file type to minimize unnecessary I/O and hashing.
-    if meta.len() > max_size || !is_executable_file(path, exts) {
-        log::debug!( "Ignored {:?} (size={}, exe={})", path, meta.len(), is_executable_file(path, exts));
-        return Ok(());
+/// Reads of a file made before it is taken as still being written.
+pub const STABLE_ATTEMPTS: u32 = 3;
+
+/// Wait between those reads.
+pub const STABLE_DELAY: Duration = Duration::from_millis(200);
+
+/// How long an [`UNSTABLE`] file is left alone; shorter than any sensible
+/// scan interval, so the next pass hashes it again.
+pub const UNSTABLE_RETRY: Duration = Duration::from_secs(30);
+
+/// Where a group announces the files it finds new or changed.
+#[derive(Clone)]
+pub struct ScanEvents {
+    pub buses: Buses<ScanResult>,
+    /// `risk_group` of every event, the group's risk level.
+    pub risk_group: String,
+}
+
+impl ScanEvents {
+    /// Publishes one file, once per rule in `matches` if any; waits while
+    /// the writer's queue is full. Must not be called from async code
+    /// (workers and `spawn_blocking` are fine).
+    pub fn publish(&self, path: &Path, size: u64, hash: u64, sha256: Option<[u8; 32]>, mtime: u64, matches: &[RuleMatch]) {
+        let file = ScanResult {
+            file_path:  path.to_string_lossy().into_owned(),
+            size,
+            hash,
+            mtime,
+            risk_group: self.risk_group.clone(),
+            sha256:     sha256.map(Vec::from).unwrap_or_default(),
+            ..ScanResult::default()
+        };
+        if matches.is_empty() {
+            return self.send(path, file);
+        }
+        for m in matches {
+            counter!("yara_matches_total", "severity" => m.severity.as_str_name()).increment(1);
+            let result = ScanResult {
+                rule_id:  m.rule_id.clone(),
+                matches:  m.patterns.clone(),
+                severity: m.severity as i32,
+                ..file.clone()
+            };
+            self.send(path, result);
+        }
+    }
+
+    fn send(&self, path: &Path, payload: ScanResult) {
+        let event = WrappedEvent {
+            ts:          SystemTime::now().into(),
+            sensor_guid: SCANNER_SENSOR.into(),
+            payload,
+            ring_pos:    None,
+            seq:         None,
+            enrichment:  None,
+            coalesced:   None,
+        };
+        // Having no analytics subscribed is fine.
+        let _ = self.buses.intel_tx.send(event.clone());
+        if self.buses.db_tx.blocking_send(event).is_err() {
+            log::debug!("Scan results writer gone, {:?} not stored", path);
+        }
     }
+}
 
-    // Compute modification time in seconds since epoch for cache comparison.
-    let mtime = meta
-        .modified()?
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?
-        .as_secs();
+impl fmt::Debug for ScanEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScanEvents").field("risk_group", &self.risk_group).finish_non_exhaustive()
+    }
+}
+
+/// Per-group limits shared by all workers.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// Files and streams above this size are not hashed.
+    pub max_size: u64,
+    /// Extensions treated as executable.
+    pub exts: Vec<String>,
+    /// Read cloud placeholders, downloading their content.
+    pub hydrate_placeholders: bool,
+    /// Digests computed for each file.
+    pub hash: HashAlgorithm,
+    /// How the group's directories are listed.
+    pub listing: Arc<ListOptions>,
+    /// Announces new or changed files; `None` only updates the cache.
+    pub events: Option<ScanEvents>,
+    /// Read budget shared with every other group; `None` reads as fast as
+    /// the disk allows.
+    pub throttle: Option<Arc<ReadThrottle>>,
+    /// YARA rules run on new or changed files before they are announced;
+    /// `None` unless the group sets `yara`.
+    pub rules: Option<Arc<Rules>>,
+}
+
+/// What the scanner needs to know about a file before reading it.
+#[derive(Debug, Clone)]
+pub struct FileFacts {
+    pub len: u64,
+    /// Modification time in seconds since the epoch.
+    pub mtime: u64,
+    /// Win32 attributes (see [`super::streams`]).
+    pub attributes: u32,
+    pub streams: Vec<StreamInfo>,
+}
+
+impl FileFacts {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let meta = fs::metadata(path)?;
+        let mtime = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?
+            .as_secs();
+        let attributes = file_attributes(&meta);
+        // Enumerating streams only touches metadata, even on placeholders.
+        let streams = alternate_streams(path).unwrap_or_else(|e| {
+            log::debug!("Cannot list streams of {:?}: {}", path, e);
+            Vec::new()
+        });
+        Ok(Self { len: meta.len(), mtime, attributes, streams })
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Size and modification time, the latter to the precision of the file
+/// system: a second write within the same second still shows.
+fn stat(path: &Path) -> io::Result<(u64, SystemTime)> {
+    let meta = fs::metadata(path)?;
+    Ok((meta.len(), meta.modified()?))
+}
+
+/// Hashes `path` as `opts` say, again while its size or modification time
+/// changes during the read. `None`, counted in
+/// `scanner_unstable_total{reason}`, if it still did after
+/// [`STABLE_ATTEMPTS`] reads or a writer holds it without sharing it.
+fn stable_digests(path: &Path, opts: &ScanOptions) -> io::Result<Option<Digests>> {
+    for attempt in 1..=STABLE_ATTEMPTS {
+        if attempt > 1 {
+            thread::sleep(STABLE_DELAY);
+        }
+        let before = stat(path)?;
+        let digests = match hash_file_throttled(path, opts.hash, opts.throttle.as_deref()) {
+            Ok(digests) => digests,
+            Err(e) if is_sharing_violation(&e) => {
+                counter!("scanner_unstable_total", "reason" => "sharing_violation").increment(1);
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        };
+        if stat(path)? == before {
+            return Ok(Some(digests));
+        }
+    }
+    counter!("scanner_unstable_total", "reason" => "changing").increment(1);
+    Ok(None)
+}
 
+/// Hashes `path` as `opts` say and, unless the cache already holds the same
+/// timestamp and digests, records it and announces it on `opts.events`.
+/// A file still being written is recorded as [`UNSTABLE`] instead, to be
+/// hashed again after [`UNSTABLE_RETRY`]. Returns the bytes hashed.
+fn hash_and_cache(
+    path: &Path,
+    mtime: u64,
+    size: u64,
+    cache: &Arc<Mutex<HashMap<PathBuf, FileCacheEntry>>>,
+    opts: &ScanOptions,
+) -> std::io::Result<u64> {
     // Hashing can be expensive; only do if size/type checks pass.
-    let hash = compute_file_hash(path)?;
+    let Some(digests) = stable_digests(path, opts)? else {
+        log::debug!("{:?} is being written, hashed again in {:?}", path, UNSTABLE_RETRY);
+        cache.lock().unwrap().insert(
+            path.to_owned(),
+            FileCacheEntry {
+                hash:        0,
+                timestamp:   mtime,
+                scan_result: Some(UNSTABLE.into()),
+                size:        Some(size),
+                sha256:      None,
+                retry_after: Some(now_secs() + UNSTABLE_RETRY.as_secs()),
+            },
+        );
+        return Ok(0);
+    };
+    // Groups hashing with SHA-256 only keep 0 as their XxHash64.
+    let hash = digests.xxh64.unwrap_or(0);
+    let sha256 = digests.sha256.map(hex::encode);
 
     // Lock cache to check prior processed entry (timestamp+hash match means skip).
     let mut lock = cache.lock().unwrap();
     if let Some(entry) = lock.get(path) {
-        if entry.timestamp == mtime && entry.hash == hash {
+        if entry.timestamp == mtime
+            && entry.hash == hash
+            && entry.size == Some(size)
+            && entry.sha256 == sha256
+            && entry.retry_after.is_none()
+        {
             // File unchanged since last scan: skip further processing.
-            return Ok(());
+            return Ok(size);
         }
     }
 
     // Record new cache entry with the scan result placeholder.
     lock.insert(
         path.to_owned(),
-        FileCacheEntry { hash, timestamp: mtime, scan_result: Some("Processed".into()) },
+        FileCacheEntry {
+            hash,
+            timestamp: mtime,
+            scan_result: Some("Processed".into()),
+            size: Some(size),
+            sha256,
+            retry_after: None,
+        },
     );
+    drop(lock);
     log::debug!( "Processed {:?} (hash={})", path, hash);
-    Ok(())
-}
-
-/// Distributes file paths to a pool of worker threads for concurrent processing.
-/// - Uses up to 4 threads or number of files, whichever is smaller.
-/// - Workers pull from a shared, synchronized receiver until channel closes.
-pub fn process_files(
-    paths: Vec<PathBuf>,
-    cache: Arc<Mutex<HashMap<PathBuf, FileCacheEntry>>>,
-    max_size: u64,
-    exts: Arc<Vec<String>>,
-) {
-    // Channel for sending file paths to worker threads.
-    let (tx, rx) = mpsc::channel::<PathBuf>();
-    let rx = Arc::new(Mutex::new(rx));  // Mutex ensures only one thread at a time reads
-
-    // Spawn a bounded number of worker threads for parallel processing.
-    let workers = (0..std::cmp::min(4, paths.len()))
-        .map(|_| {
-            let rx_clone = Arc::clone(&rx);
-            let cache_clone = Arc::clone(&cache);
-            let exts_clone = Arc::clone(&exts);
-            thread::spawn(move || {
-                // Each worker loops until channel is closed and empty.
-                while let Ok(path) = rx_clone.lock().unwrap().recv() {
-                    // Errors inside process_file are intentionally ignored here,
-                    // but in a real implementation, consider logging or handling.
-                    let _ = process_file(&path, &cache_clone, max_size, &exts_clone);
-                }
-            })
-        })
-        .collect::<Vec<_>>();
-
-    // Feed all file paths into the channel, then close it to signal completion.
-    for path in paths {
-        let _ = tx.send(path);
+    if let Some(events) = &opts.events {
+        let matches = match &opts.rules {
+            Some(rules) if rules.is_loaded() => match_rules(rules, path, opts.throttle.as_deref()),
+            _ => Vec::new(),
+        };
+        events.publish(path, size, hash, digests.sha256, mtime, &matches);
+    }
+    Ok(size)
+}
+
+/// Rules matching the content of `path`, read again within `throttle`;
+/// none when it cannot be read or scanned.
+fn match_rules(rules: &Rules, path: &Path, throttle: Option<&ReadThrottle>) -> Vec<RuleMatch> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            log::debug!("Cannot read {:?} for YARA: {}", path, e);
+            return Vec::new();
+        }
+    };
+    if let Some(t) = throttle {
+        t.take(data.len());
+    }
+    rules.scan(&data).unwrap_or_else(|e| {
+        log::warn!("YARA scan of {:?} failed: {}", path, e);
+        Vec::new()
+    })
+}
+
+/// Checks file metadata and content hash to decide whether to process a file.
+/// - Skips cloud placeholders unless `hydrate_placeholders` is set, leaving a
+///   `skipped_offline` marker in the cache so coverage reports can count them.
+/// - Skips files larger than `max_size` or non-executable based on extension blacklist/whitelist.
+/// - Hashes named streams that look executable as separate `path:stream` entries.
+/// - Uses timestamp + hash comparison to avoid reprocessing unchanged files;
+///   new or changed ones are published on `opts.events`.
+///
+/// Returns the bytes hashed, streams included.
+pub fn scan_file(
+    path: &Path,
+    facts: &FileFacts,
+    cache: &Arc<Mutex<HashMap<PathBuf, FileCacheEntry>>>,
+    opts: &ScanOptions,
+) -> std::io::Result<u64> {
+    // Reading a placeholder's content (streams included) downloads it.
+    if is_placeholder(facts.attributes) && !opts.hydrate_placeholders {
+        if is_executable_file(path, &opts.exts) {
+            log::debug!("Skipped offline {:?} (attributes={:#x})", path, facts.attributes);
+            cache.lock().unwrap().insert(
+                path.to_owned(),
+                FileCacheEntry {
+                    hash:        0,
+                    timestamp:   facts.mtime,
+                    scan_result: Some(SKIPPED_OFFLINE.into()),
+                    size:        Some(facts.len),
+                    sha256:      None,
+                    retry_after: None,
+                },
+            );
+        }
+        return Ok(0);
     }
-    drop(tx);  // Closing the sending side causes workers to exit when done.
 
-    // Wait for all workers to finish before returning.
-    for handle in workers {
-        let _ = handle.join();
+    // Payloads hidden in alternate streams of any file, executable or not.
+    let mut hashed = 0;
+    for stream in facts.streams.iter().filter(|s| s.size <= opts.max_size) {
+        let spath = stream_path(path, &stream.name);
+        if looks_executable(&spath, &stream.name, &opts.exts) {
+            match hash_and_cache(&spath, facts.mtime, stream.size, cache, opts) {
+                Ok(n) => hashed += n,
+                Err(e) => log::debug!("Cannot hash stream {:?}: {}", spath, e),
+            }
+        }
+    }
+
+    // Skip based on size or file type to minimize unnecessary I/O and hashing.
+    if facts.len > opts.max_size || !is_executable_file(path, &opts.exts) {
+        log::debug!( "Ignored {:?} (size={}, exe={})", path, facts.len, is_executable_file(path, &opts.exts));
+        return Ok(hashed);
+    }
+    Ok(hashed + hash_and_cache(path, facts.mtime, facts.len, cache, opts)?)
+}
+
+/// Reads the metadata of `path` and scans it; the unit of work of both
+/// engines. A file found being written is left alone until its
+/// `retry_after`. Returns the bytes hashed.
+pub fn process_file(
+    path: &Path,
+    cache: &Arc<Mutex<HashMap<PathBuf, FileCacheEntry>>>,
+    opts: &ScanOptions,
+) -> std::io::Result<u64> {
+    let retry_after = cache.lock().unwrap().get(path).and_then(|e| e.retry_after);
+    if retry_after.is_some_and(|t| now_secs() < t) {
+        log::debug!("Left {:?} alone until it is written", path);
+        return Ok(0);
+    }
+    scan_file(path, &FileFacts::read(path)?, cache, opts)
+}
+
+/// What one scan pass did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PassSummary {
+    /// Files listed and looked at, hashed or not.
+    pub files:   u64,
+    /// Bytes read to hash them, streams included.
+    pub bytes:   u64,
+    pub elapsed: Duration,
+}
+
+impl AddAssign for PassSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+        self.elapsed += other.elapsed;
+    }
+}
+
+impl PassSummary {
+    /// Logs the pass of group `risk`, counts it in `scanner_files_total{group}`,
+    /// `scanner_bytes_hashed_total{group}` and `scanner_pass_seconds{group}`,
+    /// and keeps it as the group's last pass in [`AgentStats`].
+    pub fn report(&self, risk: DirectoryRisk) {
+        let group = format!("{risk:?}");
+        counter!("scanner_files_total", "group" => group.clone()).increment(self.files);
+        counter!("scanner_bytes_hashed_total", "group" => group.clone()).increment(self.bytes);
+        histogram!("scanner_pass_seconds", "group" => group).record(self.elapsed.as_secs_f64());
+        log::info!(
+            "[{:?}] Scan pass done: {} files, {} bytes hashed in {:.1}s",
+            risk, self.files, self.bytes, self.elapsed.as_secs_f64(),
+        );
+        AgentStats::global().set_pass(risk, self);
     }
 }
@@ -0,0 +1,253 @@
+// src/status.rs
+//! Live state of the agent for `status.StatusService`.
+//!
+//! [`AgentStats`] is fed as things happen: the ring consumers count the
+//! events they read and hand over each ring header they sample, the
+//! database writers and listeners record their errors, the driver check
+//! records what answered and the scanner records each pass. Unlike the
+//! heartbeat's [`Stats`](crate::heartbeat::Stats), nothing is reset by
+//! reading it: [`AgentStats::snapshot`] can be taken as often as the UI
+//! asks. Event rates come from [`SlidingCounter`]s, not from the database.
+//! Component states are read from the [`HealthRegistry`] of the run.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use shared::{
+    constants::VersionInfo,
+    ring::RingStats,
+    status::{
+        component_health, driver_status::State, AgentError, ComponentHealth, DriverStatus, EventRate, RingStatus,
+        ScanPass, StatusSnapshot,
+    },
+};
+
+use crate::comms::memory_ring::backlog_bytes;
+use crate::config::model::DirectoryRisk;
+use crate::health::{ComponentState, HealthRegistry};
+use crate::heartbeat::db_size;
+use crate::scanner::worker::PassSummary;
+use crate::util::SlidingCounter;
+
+/// Errors a snapshot carries.
+pub const LAST_ERRORS: usize = 10;
+
+static GLOBAL: LazyLock<AgentStats> = LazyLock::new(AgentStats::new);
+
+/// How the driver answered the last check.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DriverState {
+    #[default]
+    Unknown,
+    Connected,
+    /// Answered with a protocol the agent does not speak.
+    Incompatible,
+    /// The control device did not answer.
+    Unavailable,
+}
+
+/// Events of one type: since the start, and per minute and hour.
+#[derive(Debug)]
+struct EventCounts {
+    total:   AtomicU64,
+    /// Last minute in seconds, last hour in minutes.
+    windows: Mutex<(SlidingCounter, SlidingCounter)>,
+}
+
+impl EventCounts {
+    fn new(at: Instant) -> Self {
+        Self {
+            total:   AtomicU64::new(0),
+            windows: Mutex::new((
+                SlidingCounter::new(Duration::from_secs(60), 60, at),
+                SlidingCounter::new(Duration::from_secs(3_600), 60, at),
+            )),
+        }
+    }
+}
+
+/// Counters and last values behind `GetStatus`. Clones share them.
+#[derive(Debug, Clone)]
+pub struct AgentStats {
+    started: Instant,
+    driver:  Arc<Mutex<(DriverState, Option<VersionInfo>)>>,
+    /// Last header read, per ring.
+    rings:   Arc<Mutex<BTreeMap<&'static str, RingStats>>>,
+    events:  Arc<Mutex<BTreeMap<&'static str, Arc<EventCounts>>>>,
+    /// Newest last, at most [`LAST_ERRORS`].
+    errors:  Arc<Mutex<VecDeque<AgentError>>>,
+    /// Last scheduled pass, per risk group.
+    scans:   Arc<Mutex<BTreeMap<&'static str, ScanPass>>>,
+    /// Supervised tasks given up on.
+    failed:  Arc<Mutex<BTreeSet<String>>>,
+    /// Set once the components are registered.
+    health:  Arc<Mutex<Option<HealthRegistry>>>,
+}
+
+impl Default for AgentStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AgentStats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            driver:  Default::default(),
+            rings:   Default::default(),
+            events:  Default::default(),
+            errors:  Default::default(),
+            scans:   Default::default(),
+            failed:  Default::default(),
+            health:  Default::default(),
+        }
+    }
+
+    /// The agent's stats; uptime counts from the first call.
+    pub fn global() -> &'static AgentStats {
+        &GLOBAL
+    }
+
+    /// Counts `n` events of `event_type` read at `at`.
+    pub fn record_events(&self, event_type: &'static str, n: u64, at: Instant) {
+        let counts = self.events.lock().unwrap().entry(event_type).or_insert_with(|| Arc::new(EventCounts::new(at))).clone();
+        counts.total.fetch_add(n, Ordering::Relaxed);
+        let mut windows = counts.windows.lock().unwrap();
+        windows.0.add(n, at);
+        windows.1.add(n, at);
+    }
+
+    /// The header of ring `name` as last sampled.
+    pub fn set_ring(&self, name: &'static str, stats: RingStats) {
+        self.rings.lock().unwrap().insert(name, stats);
+    }
+
+    /// Keeps `error` among the last [`LAST_ERRORS`].
+    pub fn record_error(&self, error: impl Into<String>) {
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() == LAST_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(AgentError { timestamp_us: chrono::Utc::now().timestamp_micros(), message: error.into() });
+    }
+
+    /// The outcome of the last driver check, with the version it answered.
+    pub fn set_driver(&self, state: DriverState, version: Option<VersionInfo>) {
+        *self.driver.lock().unwrap() = (state, version);
+    }
+
+    /// A scheduled pass of group `risk` just finished.
+    pub fn set_pass(&self, risk: DirectoryRisk, summary: &PassSummary) {
+        let pass = ScanPass {
+            risk_group:   risk.as_str().into(),
+            finished_us:  chrono::Utc::now().timestamp_micros(),
+            files:        summary.files,
+            bytes_hashed: summary.bytes,
+            seconds:      summary.elapsed.as_secs_f64(),
+        };
+        self.scans.lock().unwrap().insert(risk.as_str(), pass);
+    }
+
+    /// A supervised task panicked too often and is no longer restarted.
+    pub fn add_failed_task(&self, task: &str) {
+        self.failed.lock().unwrap().insert(task.to_owned());
+    }
+
+    /// Where the component states of the snapshots come from.
+    pub fn set_health(&self, health: HealthRegistry) {
+        *self.health.lock().unwrap() = Some(health);
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// The agent now; the database size is read from `db_path` when given.
+    pub fn snapshot(&self, db_path: Option<&Path>) -> StatusSnapshot {
+        let now = Instant::now();
+        let (state, version) = *self.driver.lock().unwrap();
+        let state = match state {
+            DriverState::Unknown => State::Unknown,
+            DriverState::Connected => State::Connected,
+            DriverState::Incompatible => State::Incompatible,
+            DriverState::Unavailable => State::Unavailable,
+        };
+        let rings = self
+            .rings
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, s)| RingStatus {
+                name:             name.to_string(),
+                backlog_bytes:    backlog_bytes(s.head, s.tail, s.size as u64),
+                size_bytes:       s.size as u64,
+                dropped:          s.dropped as u64,
+                dropped_full:     s.dropped_full as u64,
+                dropped_oversize: s.dropped_oversize as u64,
+                max_frame_bytes:  s.max_observed_len as u64,
+            })
+            .collect();
+        let events = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(event_type, counts)| {
+                let windows = counts.windows.lock().unwrap();
+                EventRate {
+                    event_type:  event_type.to_string(),
+                    last_minute: windows.0.total(now),
+                    last_hour:   windows.1.total(now),
+                    total:       counts.total.load(Ordering::Relaxed),
+                }
+            })
+            .collect();
+        let components = self
+            .health
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|health| health.snapshot().into_iter().map(|(c, state)| health_of(c.to_string(), state)).collect())
+            .unwrap_or_default();
+        StatusSnapshot {
+            timestamp_us:   chrono::Utc::now().timestamp_micros(),
+            version:        env!("CARGO_PKG_VERSION").into(),
+            uptime_seconds: self.uptime().as_secs_f64(),
+            driver:         Some(DriverStatus {
+                state:    state.into(),
+                protocol: version.map_or(0, |v| v.protocol),
+                version:  version.map(|v| v.driver_version()).unwrap_or_default(),
+            }),
+            rings,
+            events,
+            db_size_bytes:  db_path.map_or(0, db_size),
+            last_errors:    self.errors.lock().unwrap().iter().cloned().collect(),
+            scans:          self.scans.lock().unwrap().values().cloned().collect(),
+            failed_tasks:   self.failed.lock().unwrap().iter().cloned().collect(),
+            components,
+        }
+    }
+}
+
+fn health_of(component: String, state: ComponentState) -> ComponentHealth {
+    match state {
+        ComponentState::Pending => ComponentHealth { component, ..Default::default() },
+        ComponentState::Healthy => {
+            ComponentHealth { component, state: component_health::State::Healthy.into(), ..Default::default() }
+        }
+        ComponentState::Degraded { error, since, attempts } => ComponentHealth {
+            component,
+            state: component_health::State::Degraded.into(),
+            error,
+            since_us: since.timestamp_micros(),
+            attempts,
+        },
+    }
+}
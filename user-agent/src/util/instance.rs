@@ -0,0 +1,201 @@
+// src/util/instance.rs
+//! One agent per machine.
+//!
+//! Two agents would both map the rings and advance their `tail`, stealing
+//! each other's frames, and fight over the WAL of the same database. The
+//! agent therefore holds the named mutex [`MUTEX_NAME`] while it runs and
+//! records its PID in a lock file next to the database, so that whoever is
+//! refused can see which process holds it.
+//!
+//! The mutex goes away with the process that holds it; the lock file does
+//! not. A lock file left by a process that no longer exists is stale: the
+//! service takes it over, a console run only with `--force`.
+
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+use thiserror::Error;
+
+/// Mutex held by the running agent, across sessions.
+pub const MUTEX_NAME: &str = r"Global\GladixAgent";
+
+#[derive(Debug, Error)]
+pub enum InstanceError {
+    #[error("another agent is running{}; see {}", owner_text(*.owner), .lock.display())]
+    Running { owner: Option<u32>, lock: PathBuf },
+    #[error("{} names pid {pid}, which has exited; remove it or run with --force", .lock.display())]
+    Stale { pid: u32, lock: PathBuf },
+    #[error("cannot create mutex {name}: {source}")]
+    Mutex { name: String, source: io::Error },
+    #[error("cannot write {}: {source}", .lock.display())]
+    Lock { lock: PathBuf, source: io::Error },
+}
+
+fn owner_text(owner: Option<u32>) -> String {
+    owner.map(|pid| format!(" (pid {pid})")).unwrap_or_default()
+}
+
+/// The lock file of the database at `db`: `<db>.lock`.
+pub fn lock_path(db: &Path) -> PathBuf {
+    let mut name = OsString::from(db.as_os_str());
+    name.push(".lock");
+    name.into()
+}
+
+/// The PID recorded in `lock`, if it holds one.
+pub fn lock_owner(lock: &Path) -> Option<u32> {
+    fs::read_to_string(lock).ok()?.trim().parse().ok()
+}
+
+/// Held while this process is the agent; released on drop.
+#[derive(Debug)]
+pub struct InstanceGuard {
+    _mutex: sys::NamedMutex,
+    lock:   PathBuf,
+}
+
+impl InstanceGuard {
+    /// Takes the mutex `name` and writes this process' PID to `lock`. Fails
+    /// while another guard holds `name`, or while `lock` names a process
+    /// that is still running. A lock naming an exited process is replaced
+    /// only when `take_over_stale` is set.
+    pub fn acquire(name: &str, lock: &Path, take_over_stale: bool) -> Result<Self, InstanceError> {
+        let mutex = match sys::NamedMutex::create(name) {
+            Ok(Some(mutex)) => mutex,
+            Ok(None) => return Err(InstanceError::Running { owner: lock_owner(lock), lock: lock.to_path_buf() }),
+            Err(source) => return Err(InstanceError::Mutex { name: name.to_owned(), source }),
+        };
+        match lock_owner(lock) {
+            Some(pid) if pid == process::id() => {}
+            // An agent that does not see this mutex, e.g. in another namespace.
+            Some(pid) if sys::pid_alive(pid) => {
+                return Err(InstanceError::Running { owner: Some(pid), lock: lock.to_path_buf() });
+            }
+            Some(pid) if !take_over_stale => return Err(InstanceError::Stale { pid, lock: lock.to_path_buf() }),
+            Some(pid) => log::warn!("{} left by pid {}, which has exited; taking it over", lock.display(), pid),
+            None => {}
+        }
+        fs::write(lock, process::id().to_string())
+            .map_err(|source| InstanceError::Lock { lock: lock.to_path_buf(), source })?;
+        Ok(Self { _mutex: mutex, lock: lock.to_path_buf() })
+    }
+
+    pub fn lock(&self) -> &Path {
+        &self.lock
+    }
+}
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        // Only our own record; the mutex is released after this.
+        if lock_owner(&self.lock) == Some(process::id()) {
+            let _ = fs::remove_file(&self.lock);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{ffi::c_void, io, iter::once};
+
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    const ERROR_ALREADY_EXISTS: i32 = 183;
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const STILL_ACTIVE: u32 = 259;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn CreateMutexW(attributes: *const c_void, initial_owner: i32, name: *const u16) -> *mut c_void;
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn GetExitCodeProcess(process: *mut c_void, code: *mut u32) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    /// Mutex handle; the object goes away with the last handle.
+    #[derive(Debug)]
+    pub struct NamedMutex(*mut c_void);
+
+    // SAFETY: the handle is only closed, once, on drop.
+    unsafe impl Send for NamedMutex {}
+    unsafe impl Sync for NamedMutex {}
+
+    impl NamedMutex {
+        /// `None` when the mutex already exists.
+        pub fn create(name: &str) -> io::Result<Option<Self>> {
+            let wide: Vec<u16> = name.encode_utf16().chain(once(0)).collect();
+            // SAFETY: `wide` is NUL-terminated and outlives the call.
+            let handle = unsafe { CreateMutexW(std::ptr::null(), 0, wide.as_ptr()) };
+            let err = io::Error::last_os_error();
+            if handle.is_null() {
+                // Created by an agent running as another user (the service).
+                return match err.raw_os_error() {
+                    Some(ERROR_ACCESS_DENIED) => Ok(None),
+                    _ => Err(err),
+                };
+            }
+            let mutex = Self(handle);
+            if err.raw_os_error() == Some(ERROR_ALREADY_EXISTS) {
+                return Ok(None);
+            }
+            Ok(Some(mutex))
+        }
+    }
+
+    impl Drop for NamedMutex {
+        fn drop(&mut self) {
+            // SAFETY: handle from `CreateMutexW`, closed once.
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    pub fn pid_alive(pid: u32) -> bool {
+        // SAFETY: plain call; a null handle is checked below.
+        let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+        if process.is_null() {
+            // A process we may not query still exists.
+            return io::Error::last_os_error().raw_os_error() == Some(ERROR_ACCESS_DENIED);
+        }
+        let mut code = 0;
+        // SAFETY: valid handle and out pointer; the handle is closed once.
+        let ok = unsafe { GetExitCodeProcess(process, &mut code) } != 0;
+        unsafe { CloseHandle(process) };
+        ok && code == STILL_ACTIVE
+    }
+}
+
+/// Without named kernel objects the mutex only excludes guards of this
+/// process, which is what the tests exercise.
+#[cfg(not(windows))]
+mod sys {
+    use std::{
+        collections::HashSet,
+        io,
+        path::Path,
+        sync::{LazyLock, Mutex},
+    };
+
+    static HELD: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Default::default);
+
+    #[derive(Debug)]
+    pub struct NamedMutex(String);
+
+    impl NamedMutex {
+        pub fn create(name: &str) -> io::Result<Option<Self>> {
+            let mut held = HELD.lock().unwrap_or_else(|e| e.into_inner());
+            Ok(held.insert(name.to_owned()).then(|| Self(name.to_owned())))
+        }
+    }
+
+    impl Drop for NamedMutex {
+        fn drop(&mut self) {
+            HELD.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.0);
+        }
+    }
+
+    pub fn pid_alive(pid: u32) -> bool {
+        Path::new(&format!("/proc/{pid}")).exists()
+    }
+}
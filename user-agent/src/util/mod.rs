@@ -0,0 +1,14 @@
+// src/util/mod.rs
+//! Small building blocks shared by several subsystems.
+
+pub mod instance;
+pub mod retry;
+pub mod shutdown;
+pub mod supervisor;
+pub mod window;
+
+pub use instance::{InstanceError, InstanceGuard};
+pub use retry::{retry_async, retry_blocking, Jitter, Outcome, RetryError, RetryPolicy};
+pub use shutdown::{Shutdown, Tasks};
+pub use supervisor::{spawn_supervised, Supervisor};
+pub use window::SlidingCounter;
@@ -0,0 +1,348 @@
+// src/util/retry.rs
+//! Exponential backoff with jitter for every reconnect / restart path.
+//!
+//! A [`RetryPolicy`] describes the schedule and carries the call-site label
+//! and the [`Shutdown`] token; [`retry_async`] and [`retry_blocking`] run an
+//! operation under it and return an [`Outcome`]. Each run is reported as
+//!
+//! - `retry_attempts_total{site}`
+//! - `retry_outcomes_total{site, outcome}` with `ok`, `gave_up` or `cancelled`
+//! - `retry_elapsed_seconds{site}`
+//!
+//! Time and randomness come from a [`Clock`] so tests can replace both.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use futures::future::BoxFuture;
+use metrics::{counter, histogram};
+
+use super::shutdown::Shutdown;
+
+/// How the computed delay is randomised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jitter {
+    None,
+    /// Uniform in `[0, delay)`.
+    Full,
+    /// Uniform in `[delay / 2, delay)`.
+    Equal,
+}
+
+/// Time source of the retry loops.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    /// Uniform sample in `[0, 1)` used for jitter.
+    fn random(&self) -> f64;
+    fn sleep(&self, d: Duration) -> BoxFuture<'static, ()>;
+    /// Blocks for `d`. Returns `false` when `shutdown` fired first.
+    fn sleep_blocking(&self, d: Duration, shutdown: &Shutdown) -> bool;
+}
+
+/// Wall clock with an xorshift jitter source.
+pub struct SystemClock {
+    state: AtomicU64,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        Self { state: AtomicU64::new(seed | 1) }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn random(&self) -> f64 {
+        let mut x = self.state.load(Ordering::Relaxed);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state.store(x, Ordering::Relaxed);
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn sleep(&self, d: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(d))
+    }
+
+    fn sleep_blocking(&self, d: Duration, shutdown: &Shutdown) -> bool {
+        !shutdown.wait_timeout(d)
+    }
+}
+
+/// Retry schedule of one call site.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    site:            &'static str,
+    initial:         Duration,
+    multiplier:      f64,
+    max_delay:       Duration,
+    max_attempts:    Option<u32>,
+    deadline:        Option<Duration>,
+    jitter:          Jitter,
+    attempt_timeout: Option<Duration>,
+    shutdown:        Option<Shutdown>,
+    clock:           Arc<dyn Clock>,
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("site", &self.site)
+            .field("initial", &self.initial)
+            .field("multiplier", &self.multiplier)
+            .field("max_delay", &self.max_delay)
+            .field("max_attempts", &self.max_attempts)
+            .field("deadline", &self.deadline)
+            .field("jitter", &self.jitter)
+            .field("attempt_timeout", &self.attempt_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RetryPolicy {
+    /// Doubling delays from `initial`, capped at one minute, with equal
+    /// jitter and no attempt or time limit.
+    pub fn new(site: &'static str, initial: Duration) -> Self {
+        Self {
+            site,
+            initial,
+            multiplier:      2.0,
+            max_delay:       Duration::from_secs(60).max(initial),
+            max_attempts:    None,
+            deadline:        None,
+            jitter:          Jitter::Equal,
+            attempt_timeout: None,
+            shutdown:        None,
+            clock:           Arc::new(SystemClock::default()),
+        }
+    }
+
+    pub fn multiplier(mut self, m: f64) -> Self {
+        self.multiplier = m.max(1.0);
+        self
+    }
+
+    pub fn max_delay(mut self, d: Duration) -> Self {
+        self.max_delay = d;
+        self
+    }
+
+    /// Total attempts, the first one included.
+    pub fn max_attempts(mut self, n: u32) -> Self {
+        self.max_attempts = Some(n.max(1));
+        self
+    }
+
+    /// Gives up instead of waiting past `d` from the first attempt.
+    pub fn deadline(mut self, d: Duration) -> Self {
+        self.deadline = Some(d);
+        self
+    }
+
+    pub fn jitter(mut self, j: Jitter) -> Self {
+        self.jitter = j;
+        self
+    }
+
+    /// Abandons an attempt after `d`. Only honoured by [`retry_async`].
+    pub fn attempt_timeout(mut self, d: Duration) -> Self {
+        self.attempt_timeout = Some(d);
+        self
+    }
+
+    /// Stops retrying, and abandons a running async attempt, once `s` fires.
+    pub fn cancel_on(mut self, s: Shutdown) -> Self {
+        self.shutdown = Some(s);
+        self
+    }
+
+    pub fn clock(mut self, c: Arc<dyn Clock>) -> Self {
+        self.clock = c;
+        self
+    }
+
+    pub fn site(&self) -> &'static str {
+        self.site
+    }
+
+    pub(crate) fn time(&self) -> &dyn Clock {
+        &*self.clock
+    }
+
+    pub(crate) fn stop(&self) -> Option<&Shutdown> {
+        self.shutdown.as_ref()
+    }
+
+    /// Wait before retry number `retry` (1 after the first failure), with
+    /// `unit` in `[0, 1)` as the jitter sample.
+    pub fn delay(&self, retry: u32, unit: f64) -> Duration {
+        let exp = self.multiplier.powi(retry.saturating_sub(1).min(i32::MAX as u32) as i32);
+        let base = self.initial.as_secs_f64() * exp;
+        let base = Duration::try_from_secs_f64(base).unwrap_or(self.max_delay).min(self.max_delay);
+        match self.jitter {
+            Jitter::None  => base,
+            Jitter::Full  => base.mul_f64(unit),
+            Jitter::Equal => base / 2 + (base / 2).mul_f64(unit),
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        self.shutdown.as_ref().is_some_and(Shutdown::is_triggered)
+    }
+
+    /// Delay before the next attempt, or `None` when the attempt budget or
+    /// deadline is spent.
+    fn next_delay(&self, attempts: u32, start: Instant) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| attempts >= max) {
+            return None;
+        }
+        let delay = self.delay(attempts, self.clock.random());
+        match self.deadline {
+            Some(limit) if self.clock.now() + delay >= start + limit => None,
+            _ => Some(delay),
+        }
+    }
+}
+
+/// Why a retried operation did not succeed. `last` is `None` when the final
+/// attempt timed out or was abandoned.
+#[derive(Debug)]
+pub enum RetryError<E> {
+    /// Attempts or deadline exhausted.
+    GaveUp { last: Option<E> },
+    /// Shutdown requested.
+    Cancelled { last: Option<E> },
+}
+
+impl<E> RetryError<E> {
+    pub fn last(&self) -> Option<&E> {
+        match self {
+            RetryError::GaveUp { last } | RetryError::Cancelled { last } => last.as_ref(),
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for RetryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self {
+            RetryError::GaveUp { .. }    => "gave up",
+            RetryError::Cancelled { .. } => "cancelled",
+        };
+        match self.last() {
+            Some(e) => write!(f, "{what}: {e}"),
+            None    => f.write_str(what),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for RetryError<E> {}
+
+/// Result of a retried operation.
+#[derive(Debug)]
+pub struct Outcome<T, E> {
+    pub result:   Result<T, RetryError<E>>,
+    pub attempts: u32,
+    pub elapsed:  Duration,
+}
+
+impl<T, E> Outcome<T, E> {
+    fn finish(policy: &RetryPolicy, result: Result<T, RetryError<E>>, attempts: u32, start: Instant) -> Self {
+        let elapsed = policy.clock.now().saturating_duration_since(start);
+        let outcome = match &result {
+            Ok(_)                             => "ok",
+            Err(RetryError::GaveUp { .. })    => "gave_up",
+            Err(RetryError::Cancelled { .. }) => "cancelled",
+        };
+        counter!("retry_attempts_total", "site" => policy.site).increment(attempts as u64);
+        counter!("retry_outcomes_total", "site" => policy.site, "outcome" => outcome).increment(1);
+        histogram!("retry_elapsed_seconds", "site" => policy.site).record(elapsed.as_secs_f64());
+        Self { result, attempts, elapsed }
+    }
+
+    pub fn into_result(self) -> Result<T, RetryError<E>> {
+        self.result
+    }
+}
+
+/// Runs `op` until it succeeds, the policy gives up or shutdown is requested.
+pub fn retry_blocking<T, E>(policy: &RetryPolicy, mut op: impl FnMut() -> Result<T, E>) -> Outcome<T, E> {
+    let start = policy.clock.now();
+    let never = Shutdown::new();
+    let shutdown = policy.shutdown.as_ref().unwrap_or(&never);
+    let mut attempts = 0;
+    let mut last = None;
+    loop {
+        if policy.cancelled() {
+            return Outcome::finish(policy, Err(RetryError::Cancelled { last }), attempts, start);
+        }
+        attempts += 1;
+        match op() {
+            Ok(v) => return Outcome::finish(policy, Ok(v), attempts, start),
+            Err(e) => last = Some(e),
+        }
+        let Some(delay) = policy.next_delay(attempts, start) else {
+            return Outcome::finish(policy, Err(RetryError::GaveUp { last }), attempts, start);
+        };
+        if !policy.clock.sleep_blocking(delay, shutdown) {
+            return Outcome::finish(policy, Err(RetryError::Cancelled { last }), attempts, start);
+        }
+    }
+}
+
+/// Async counterpart of [`retry_blocking`]. A running attempt is dropped when
+/// it exceeds the per-attempt timeout or shutdown is requested.
+pub async fn retry_async<T, E, F, Fut>(policy: &RetryPolicy, mut op: F) -> Outcome<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let start = policy.clock.now();
+    let never = Shutdown::new();
+    let shutdown = policy.shutdown.as_ref().unwrap_or(&never);
+    let mut attempts = 0;
+    let mut last = None;
+    loop {
+        if policy.cancelled() {
+            return Outcome::finish(policy, Err(RetryError::Cancelled { last }), attempts, start);
+        }
+        attempts += 1;
+        let timeout = async {
+            match policy.attempt_timeout {
+                Some(d) => policy.clock.sleep(d).await,
+                None    => futures::future::pending().await,
+            }
+        };
+        tokio::select! {
+            biased;
+            r = op() => match r {
+                Ok(v) => return Outcome::finish(policy, Ok(v), attempts, start),
+                Err(e) => last = Some(e),
+            },
+            _ = shutdown.triggered() => {
+                return Outcome::finish(policy, Err(RetryError::Cancelled { last: None }), attempts, start);
+            }
+            _ = timeout => last = None,
+        }
+        let Some(delay) = policy.next_delay(attempts, start) else {
+            return Outcome::finish(policy, Err(RetryError::GaveUp { last }), attempts, start);
+        };
+        tokio::select! {
+            biased;
+            _ = shutdown.triggered() => {
+                return Outcome::finish(policy, Err(RetryError::Cancelled { last }), attempts, start);
+            }
+            _ = policy.clock.sleep(delay) => {}
+        }
+    }
+}
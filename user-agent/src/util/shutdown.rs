@@ -0,0 +1,87 @@
+// src/util/shutdown.rs
+//! Process-wide stop request shared by threads and tasks.
+
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+use tokio::{sync::watch, task::JoinHandle, time::Instant};
+
+/// Cloneable stop token. Triggering it wakes every blocking and async waiter;
+/// it cannot be reset.
+#[derive(Clone)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    flag: Mutex<bool>,
+    cond: Condvar,
+    tx:   watch::Sender<bool>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (tx, _) = watch::channel(false);
+        Self { inner: Arc::new(Inner { flag: Mutex::new(false), cond: Condvar::new(), tx }) }
+    }
+
+    pub fn trigger(&self) {
+        *self.inner.flag.lock().unwrap() = true;
+        self.inner.cond.notify_all();
+        self.inner.tx.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.inner.flag.lock().unwrap()
+    }
+
+    /// Blocks for up to `timeout`. Returns `true` if shutdown was requested.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let flag = self.inner.flag.lock().unwrap();
+        let (flag, _) = self.inner.cond.wait_timeout_while(flag, timeout, |stop| !*stop).unwrap();
+        *flag
+    }
+
+    /// Resolves once shutdown is requested.
+    pub async fn triggered(&self) {
+        let mut rx = self.inner.tx.subscribe();
+        // The sender lives in `self`, so the channel never closes here.
+        let _ = rx.wait_for(|stop| *stop).await;
+    }
+}
+
+/// Handles of tasks the service waits for before reporting itself stopped.
+#[derive(Clone, Default)]
+pub struct Tasks {
+    handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Tasks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, handle: JoinHandle<()>) {
+        self.handles.lock().unwrap().push(handle);
+    }
+
+    /// Waits for every task pushed so far until `deadline`. Returns how many
+    /// were still running; those are left detached.
+    pub async fn join(&self, deadline: Instant) -> usize {
+        let handles = std::mem::take(&mut *self.handles.lock().unwrap());
+        let mut pending = 0;
+        for handle in handles {
+            if tokio::time::timeout_at(deadline, handle).await.is_err() {
+                pending += 1;
+            }
+        }
+        pending
+    }
+}
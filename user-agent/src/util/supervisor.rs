@@ -0,0 +1,180 @@
+// src/util/supervisor.rs
+//! Restarts of long-running tasks and threads that panic.
+//!
+//! A panic only ends the task it happened in: the service goes on running
+//! around a dead pipeline. [`Supervisor::run`] starts a task made by a
+//! factory and, when it panics, logs the payload, counts
+//! `task_restarts_total{task}` and starts a new one after the backoff of its
+//! [`RetryPolicy`], doubling from [`FIRST_RESTART`] up to a minute. After
+//! [`MAX_FAILURES`] panics within [`FAILURE_WINDOW`] it gives up and lists
+//! the task among the failed ones of the heartbeat and of `GetStatus`.
+//! [`Supervisor::run_blocking`] does the same for std threads. A task that
+//! returns is not started again.
+
+use std::{
+    any::Any,
+    collections::VecDeque,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+use metrics::counter;
+use tokio::task::JoinHandle;
+
+use super::{retry::RetryPolicy, shutdown::Shutdown};
+use crate::heartbeat::Stats;
+use crate::status::AgentStats;
+
+/// Panics within [`FAILURE_WINDOW`] after which a task is given up on.
+pub const MAX_FAILURES: usize = 5;
+pub const FAILURE_WINDOW: Duration = Duration::from_secs(600);
+/// Wait before the first restart.
+pub const FIRST_RESTART: Duration = Duration::from_secs(1);
+
+/// How one task is restarted.
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    name:         String,
+    policy:       RetryPolicy,
+    max_failures: usize,
+    window:       Duration,
+}
+
+/// Aborts the running incarnation when the supervisor itself is dropped.
+struct Incarnation(JoinHandle<()>);
+
+impl Drop for Incarnation {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl Supervisor {
+    /// `name` labels the logs, the metric and the failed-task list.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name:         name.into(),
+            policy:       RetryPolicy::new("supervisor", FIRST_RESTART),
+            max_failures: MAX_FAILURES,
+            window:       FAILURE_WINDOW,
+        }
+    }
+
+    /// Restart delays; the policy's clock and shutdown token are used too.
+    pub fn policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Gives up on the `n`-th panic within `window`.
+    pub fn give_up_after(mut self, n: usize, window: Duration) -> Self {
+        self.max_failures = n.max(1);
+        self.window = window;
+        self
+    }
+
+    /// Starts nothing more once `s` fires.
+    pub fn cancel_on(mut self, s: Shutdown) -> Self {
+        self.policy = self.policy.cancel_on(s);
+        self
+    }
+
+    /// Runs tasks made by `factory`, each spawned on the current runtime,
+    /// until one returns, the supervisor gives up or shutdown is requested.
+    pub async fn run<F, Fut>(self, mut factory: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let never = Shutdown::new();
+        let shutdown = self.policy.stop().unwrap_or(&never);
+        let mut failures = VecDeque::new();
+        loop {
+            let mut task = Incarnation(tokio::spawn(factory()));
+            let payload = match (&mut task.0).await {
+                Ok(()) => return,
+                Err(e) if e.is_panic() => e.into_panic(),
+                // Aborted, or its runtime is going away.
+                Err(_) => return,
+            };
+            let Some(delay) = self.failed(&mut failures, payload) else { return };
+            if shutdown.is_triggered() {
+                return;
+            }
+            tokio::select! {
+                biased;
+                _ = shutdown.triggered() => return,
+                _ = self.policy.time().sleep(delay) => {}
+            }
+            counter!("task_restarts_total", "task" => self.name.clone()).increment(1);
+        }
+    }
+
+    /// [`run`](Self::run) for the body of a std thread, called again after
+    /// each panic.
+    pub fn run_blocking(self, mut body: impl FnMut()) {
+        let never = Shutdown::new();
+        let shutdown = self.policy.stop().unwrap_or(&never);
+        let mut failures = VecDeque::new();
+        loop {
+            let payload = match panic::catch_unwind(AssertUnwindSafe(&mut body)) {
+                Ok(()) => return,
+                Err(payload) => payload,
+            };
+            let Some(delay) = self.failed(&mut failures, payload) else { return };
+            if shutdown.is_triggered() || !self.policy.time().sleep_blocking(delay, shutdown) {
+                return;
+            }
+            counter!("task_restarts_total", "task" => self.name.clone()).increment(1);
+        }
+    }
+
+    /// Records a panic. Returns the wait before the next start, or `None`
+    /// when the task is given up on.
+    fn failed(&self, failures: &mut VecDeque<Instant>, payload: Box<dyn Any + Send>) -> Option<Duration> {
+        let clock = self.policy.time();
+        let now = clock.now();
+        while failures.front().is_some_and(|&at| now.saturating_duration_since(at) >= self.window) {
+            failures.pop_front();
+        }
+        failures.push_back(now);
+        let message = panic_message(&*payload);
+        if failures.len() >= self.max_failures {
+            log::error!(
+                "task '{}' panicked {} times within {:?}, not restarting it: {}",
+                self.name, failures.len(), self.window, message,
+            );
+            let error = format!("task '{}' given up after {} panics: {}", self.name, failures.len(), message);
+            AgentStats::global().record_error(error.clone());
+            AgentStats::global().add_failed_task(&self.name);
+            Stats::global().set_error(error);
+            Stats::global().add_failed_task(&self.name);
+            return None;
+        }
+        let delay = self.policy.delay(failures.len() as u32, clock.random());
+        log::error!("task '{}' panicked, restarting in {:?}: {}", self.name, delay, message);
+        let error = format!("task '{}' panicked: {}", self.name, message);
+        AgentStats::global().record_error(error.clone());
+        Stats::global().set_error(error);
+        Some(delay)
+    }
+}
+
+/// Spawns `factory`'s tasks under a [`Supervisor`] with the default
+/// schedule; see [`Supervisor::run`].
+pub fn spawn_supervised<F, Fut>(name: impl Into<String>, factory: F) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(Supervisor::new(name).run(factory))
+}
+
+/// The text a panic was raised with, as `panic!` and `unwrap` leave it.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
@@ -0,0 +1,64 @@
+// src/util/window.rs
+//! Event counts over a trailing window of time, for rates such as "in the
+//! last minute" without keeping every event.
+//!
+//! The window is cut into buckets; a count lands in the bucket of its
+//! instant and leaves the window with it, so [`SlidingCounter::total`]
+//! covers between `span - span / buckets` and `span`. Time is passed in, so
+//! tests can pick it.
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct SlidingCounter {
+    origin: Instant,
+    width:  Duration,
+    /// Bucket number since `origin` and its count; bucket `n` lives in slot
+    /// `n % len`.
+    slots:  Vec<(u64, u64)>,
+}
+
+impl SlidingCounter {
+    /// A window of `span` in `buckets` buckets, starting at `origin`.
+    /// Earlier instants count as `origin`.
+    pub fn new(span: Duration, buckets: usize, origin: Instant) -> Self {
+        let buckets = buckets.max(1);
+        let width = (span / buckets as u32).max(Duration::from_nanos(1));
+        Self { origin, width, slots: vec![(0, 0); buckets] }
+    }
+
+    pub fn span(&self) -> Duration {
+        self.width * self.slots.len() as u32
+    }
+
+    fn bucket(&self, at: Instant) -> u64 {
+        (at.saturating_duration_since(self.origin).as_nanos() / self.width.as_nanos()) as u64
+    }
+
+    /// Counts `n` at `at`. A bucket that left the window starts over.
+    pub fn add(&mut self, n: u64, at: Instant) {
+        let bucket = self.bucket(at);
+        let len = self.slots.len() as u64;
+        let slot = &mut self.slots[(bucket % len) as usize];
+        if slot.0 != bucket {
+            // Counts older than the window are gone; late ones for a bucket
+            // already reused are dropped.
+            if slot.0 > bucket {
+                return;
+            }
+            *slot = (bucket, 0);
+        }
+        slot.1 += n;
+    }
+
+    /// Counts in the window ending at `at`.
+    pub fn total(&self, at: Instant) -> u64 {
+        let newest = self.bucket(at);
+        let len = self.slots.len() as u64;
+        self.slots
+            .iter()
+            .filter(|(bucket, _)| *bucket <= newest && newest - bucket < len)
+            .map(|(_, n)| n)
+            .sum()
+    }
+}
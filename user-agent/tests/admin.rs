@@ -0,0 +1,256 @@
+// tests/admin.rs
+//
+// `install`, `uninstall` and `status` against a service manager kept in
+// memory: arguments and their defaults, each step acting only when the
+// services are not already as asked, and a failed step reported without
+// stopping the others.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use clap::Parser;
+
+use agent::admin::{
+    install, install_action, remove_action, status, uninstall, Command, InstallAction, InstallArgs, Installed,
+    Outcome, RemoveAction, ServiceKind, ServiceManager, ServiceSpec, State, AGENT_SERVICE, RESTART_DELAYS,
+};
+use shared::constants::DRIVER_SERVICE;
+
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+fn parse(args: &[&str]) -> Command {
+    Cli::try_parse_from(std::iter::once("agent").chain(args.iter().copied())).unwrap().command
+}
+
+/// Services by name, the calls made, and the calls that fail.
+#[derive(Default)]
+struct Scm {
+    services:  RefCell<BTreeMap<String, Installed>>,
+    calls:     RefCell<Vec<String>>,
+    failing:   Vec<&'static str>,
+    ring_size: RefCell<Option<u32>>,
+    device:    bool,
+}
+
+impl Scm {
+    fn call(&self, call: String) -> io::Result<()> {
+        let failed = self.failing.iter().any(|f| call.starts_with(f));
+        self.calls.borrow_mut().push(call.clone());
+        if failed { Err(io::Error::other(format!("{call} refused"))) } else { Ok(()) }
+    }
+
+    fn with(self, name: &str, state: State, command: &str) -> Self {
+        self.services.borrow_mut().insert(name.into(), Installed { state, command: command.into() });
+        self
+    }
+
+    fn calls(&self) -> Vec<String> {
+        self.calls.borrow().clone()
+    }
+}
+
+impl ServiceManager for Scm {
+    fn query(&self, name: &str) -> io::Result<Option<Installed>> {
+        self.call(format!("query {name}"))?;
+        Ok(self.services.borrow().get(name).cloned())
+    }
+
+    fn create(&self, spec: &ServiceSpec) -> io::Result<()> {
+        self.call(format!("create {}", spec.name))?;
+        let command = spec.binary.to_string_lossy().into_owned();
+        self.services.borrow_mut().insert(spec.name.into(), Installed { state: State::Stopped, command });
+        Ok(())
+    }
+
+    fn reconfigure(&self, spec: &ServiceSpec) -> io::Result<()> {
+        self.call(format!("reconfigure {}", spec.name))?;
+        self.services.borrow_mut().get_mut(spec.name).unwrap().command = spec.binary.to_string_lossy().into_owned();
+        Ok(())
+    }
+
+    fn set_recovery(&self, name: &str, delays: &[Duration], _reset: Duration) -> io::Result<()> {
+        self.call(format!("recovery {name} {}", delays.len()))
+    }
+
+    fn stop(&self, name: &str) -> io::Result<()> {
+        self.call(format!("stop {name}"))?;
+        self.services.borrow_mut().get_mut(name).unwrap().state = State::Stopped;
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.call(format!("delete {name}"))?;
+        self.services.borrow_mut().remove(name);
+        Ok(())
+    }
+
+    fn set_ring_size(&self, bytes: u32) -> io::Result<u32> {
+        self.call(format!("ring {bytes}"))?;
+        *self.ring_size.borrow_mut() = Some(bytes);
+        Ok(bytes)
+    }
+
+    fn device_present(&self) -> bool {
+        self.device
+    }
+}
+
+fn exe() -> PathBuf {
+    std::path::absolute("gladix/agent.exe").unwrap()
+}
+
+fn outcomes(report: &agent::admin::Report) -> Vec<(&str, &str)> {
+    report
+        .steps
+        .iter()
+        .map(|s| {
+            let kind = match s.outcome {
+                Outcome::Done(_) => "done",
+                Outcome::Unchanged(_) => "unchanged",
+                Outcome::Failed(_) => "failed",
+            };
+            (s.name.as_str(), kind)
+        })
+        .collect()
+}
+
+#[test]
+fn subcommands_parse_with_their_defaults() {
+    assert_eq!(parse(&["status"]), Command::Status);
+    assert_eq!(parse(&["uninstall"]), Command::Uninstall);
+    assert_eq!(parse(&["install"]), Command::Install(InstallArgs::default()));
+    let Command::Install(args) = parse(&["install", "--driver", r"D:\drv\gladix.sys", "--ring-size", "4194304"]) else {
+        panic!("not install");
+    };
+    assert_eq!((args.driver.as_deref(), args.agent, args.ring_size), (Some(Path::new(r"D:\drv\gladix.sys")), None, Some(4 << 20)));
+    assert!(Cli::try_parse_from(["agent", "install", "--ring-size", "big"]).is_err());
+    assert!(Cli::try_parse_from(["agent", "reinstall"]).is_err());
+
+    // Defaults next to the executable; the flag wins over the config.
+    let plan = InstallArgs::default().plan(&exe(), None, Some(1 << 20));
+    assert_eq!(plan.driver.binary, exe().with_file_name("gladix.sys"));
+    assert_eq!((plan.driver.name, plan.driver.kind), (DRIVER_SERVICE, ServiceKind::Driver));
+    assert_eq!((plan.agent.name, &plan.agent.binary, plan.agent.args.len()), (AGENT_SERVICE, &exe(), 0));
+    assert_eq!(plan.ring_size, Some(1 << 20));
+    let plan = InstallArgs { ring_size: Some(4096), ..InstallArgs::default() }.plan(&exe(), Some(Path::new("agent.toml")), None);
+    assert_eq!(plan.ring_size, Some(4096));
+    assert_eq!(plan.agent.args, ["--config".into(), std::path::absolute("agent.toml").unwrap().into_os_string()]);
+}
+
+#[test]
+fn steps_act_only_when_the_service_differs() {
+    let spec = InstallArgs::default().plan(&exe(), None, None).agent;
+    let at = |state, command: String| Installed { state, command };
+    let path = exe().to_string_lossy().into_owned();
+
+    assert_eq!(install_action(None, &spec), InstallAction::Create);
+    for command in [path.clone(), path.to_uppercase(), format!("\"{path}\" --config x.toml"), format!(r"\??\{path}")] {
+        assert_eq!(install_action(Some(&at(State::Running, command.clone())), &spec), InstallAction::Keep, "{command}");
+    }
+    for command in [format!("{path}.old"), r"C:\elsewhere\agent.exe".into()] {
+        assert_eq!(install_action(Some(&at(State::Stopped, command.clone())), &spec), InstallAction::Reconfigure, "{command}");
+    }
+
+    assert_eq!(remove_action(None), RemoveAction::Nothing);
+    assert_eq!(remove_action(Some(&at(State::Stopped, path.clone()))), RemoveAction::Delete);
+    for state in [State::Running, State::Starting, State::Stopping, State::Paused] {
+        assert_eq!(remove_action(Some(&at(state, path.clone()))), RemoveAction::StopAndDelete);
+    }
+}
+
+#[test]
+fn install_twice_changes_nothing_the_second_time() {
+    let scm = Scm::default();
+    let plan = InstallArgs::default().plan(&exe(), None, Some(1 << 20));
+    let first = install(&scm, &plan);
+    assert_eq!(first.failed(), 0, "{first}");
+    assert_eq!(outcomes(&first), [
+        ("edr_driver service", "done"),
+        ("ring size", "done"),
+        ("Gladix service", "done"),
+        ("Gladix recovery", "done"),
+    ]);
+    assert!(scm.calls().contains(&format!("recovery {AGENT_SERVICE} {}", RESTART_DELAYS.len())));
+
+    scm.calls.borrow_mut().clear();
+    let second = install(&scm, &plan);
+    assert_eq!(outcomes(&second)[0], ("edr_driver service", "unchanged"));
+    assert_eq!(outcomes(&second)[2], ("Gladix service", "unchanged"));
+    assert!(!scm.calls().iter().any(|c| c.starts_with("create")), "{:?}", scm.calls());
+
+    // Moved elsewhere: pointed at the new binary.
+    let moved = InstallArgs { agent: Some(r"C:\Program Files\Gladix\agent.exe".into()), ..InstallArgs::default() };
+    let third = install(&scm, &moved.plan(&exe(), None, None));
+    assert_eq!(outcomes(&third)[1..3], [("ring size", "unchanged"), ("Gladix service", "done")]);
+    assert!(scm.calls().contains(&"reconfigure Gladix".to_owned()));
+}
+
+#[test]
+fn a_failed_step_does_not_stop_the_others() {
+    let scm = Scm { failing: vec!["create edr_driver"], ..Scm::default() };
+    let report = install(&scm, &InstallArgs::default().plan(&exe(), None, Some(1 << 20)));
+    assert_eq!(outcomes(&report), [
+        ("edr_driver service", "failed"),
+        ("ring size", "done"),
+        ("Gladix service", "done"),
+        ("Gladix recovery", "done"),
+    ]);
+    let text = report.to_string();
+    assert!(text.contains("FAILED  edr_driver service: create edr_driver refused"), "{text}");
+    assert!(text.ends_with("3 done, 0 unchanged, 1 failed"), "{text}");
+
+    // No agent service, no recovery to set on it.
+    let scm = Scm { failing: vec!["create Gladix"], ..Scm::default() };
+    let report = install(&scm, &InstallArgs::default().plan(&exe(), None, None));
+    assert_eq!(outcomes(&report).last(), Some(&("Gladix service", "failed")));
+}
+
+#[test]
+fn uninstall_stops_what_runs_and_checks_the_device_link() {
+    let scm = Scm::default()
+        .with(AGENT_SERVICE, State::Running, r"C:\Gladix\agent.exe")
+        .with(DRIVER_SERVICE, State::Stopped, r"\??\C:\Gladix\gladix.sys");
+    let report = uninstall(&scm);
+    assert_eq!(report.failed(), 0, "{report}");
+    assert_eq!(scm.calls(), ["query Gladix", "stop Gladix", "delete Gladix", "query edr_driver", "delete edr_driver"]);
+    assert!(scm.services.borrow().is_empty());
+
+    // Again: nothing left to do.
+    let again = uninstall(&scm);
+    assert_eq!(outcomes(&again), [
+        ("Gladix service", "unchanged"),
+        ("edr_driver service", "unchanged"),
+        ("device link", "unchanged"),
+    ]);
+
+    // A driver that would not stop leaves its device behind; the agent is
+    // still removed.
+    let scm = Scm { failing: vec!["stop edr_driver"], device: true, ..Scm::default() }
+        .with(AGENT_SERVICE, State::Stopped, "agent.exe")
+        .with(DRIVER_SERVICE, State::Running, "gladix.sys");
+    let report = uninstall(&scm);
+    assert_eq!(outcomes(&report), [
+        ("Gladix service", "done"),
+        ("edr_driver service", "failed"),
+        ("device link", "failed"),
+    ]);
+}
+
+#[test]
+fn status_lists_both_services() {
+    let scm = Scm::default().with(DRIVER_SERVICE, State::Running, r"\??\C:\Gladix\gladix.sys");
+    let report = status(&scm);
+    assert_eq!(report.failed(), 0);
+    let text = report.to_string();
+    assert!(text.contains(r"edr_driver: running (\??\C:\Gladix\gladix.sys)"), "{text}");
+    assert!(text.contains("Gladix: not installed"), "{text}");
+    assert!(scm.calls().iter().all(|c| c.starts_with("query")));
+}
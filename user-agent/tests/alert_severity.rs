@@ -0,0 +1,203 @@
+// tests/alert_severity.rs
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use rusqlite::Connection;
+use tempfile::tempdir;
+use shared::events::ProcessEvent;
+
+use agent::{
+    actions::Actions,
+    config::{
+        load,
+        model::{Keep, NotificationChannel, PerSeverity, RateLimit},
+    },
+    db::{connection::init_database, maintenance::purge_alerts},
+    intel::{insert_alert, Alert, AlertSink, NotificationRouter, Severity, SeverityError, SeverityExpr},
+};
+
+const DAY: i64 = 86_400 * 1_000_000;
+
+fn fields(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+fn alert(ts: i64, severity: Severity) -> Alert {
+    Alert {
+        ts,
+        rule_id: "test.rule".into(),
+        severity,
+        pid: 42,
+        ppid: None,
+        message: String::new(),
+        file: None,
+    }
+}
+
+fn severities(conn: &Connection) -> Vec<(String, i64)> {
+    let mut stmt = conn.prepare("SELECT severity, ts FROM alerts ORDER BY id").unwrap();
+    stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap()
+}
+
+#[test]
+fn severity_expression_is_evaluated_per_event() {
+    let expr: SeverityExpr = r#"
+        critical when image_path ends_with "lsass.exe" or cmdline contains "-enc";
+        high     when image_path starts_with 'C:\Windows\' and not ppid == 4;
+        low      when pid < 100
+    "#
+    .parse()
+    .unwrap();
+
+    let proc_ev = |pid: u32, ppid: u32, image: &str, cmd: &str| ProcessEvent {
+        pid,
+        ppid,
+        image_path: image.into(),
+        cmdline: cmd.into(),
+        ..ProcessEvent::default()
+    };
+
+    let fallback = Severity::Medium;
+    assert_eq!(expr.evaluate(&proc_ev(500, 1, r"C:\Windows\System32\LSASS.EXE", ""), fallback), Severity::Critical);
+    assert_eq!(expr.evaluate(&proc_ev(500, 1, r"D:\x.exe", "powershell -ENC aQBlAHgA"), fallback), Severity::Critical);
+    assert_eq!(expr.evaluate(&proc_ev(500, 1, r"c:\windows\notepad.exe", ""), fallback), Severity::High);
+    assert_eq!(expr.evaluate(&proc_ev(500, 4, r"C:\Windows\notepad.exe", ""), fallback), Severity::Medium);
+    assert_eq!(expr.evaluate(&proc_ev(50, 4, r"C:\Windows\notepad.exe", ""), fallback), Severity::Low);
+
+    // An explicit default beats the rule's static severity; unknown fields never match.
+    let expr: SeverityExpr = "high when (size >= 1000000 or path contains 'secret'); default info"
+        .parse()
+        .unwrap();
+    assert_eq!(expr.evaluate(&fields(&[("size", "5000000")]), Severity::Critical), Severity::High);
+    assert_eq!(expr.evaluate(&fields(&[("path", "a/Secret.txt")]), Severity::Critical), Severity::High);
+    assert_eq!(expr.evaluate(&fields(&[("size", "10")]), Severity::Critical), Severity::Info);
+    assert_eq!(expr.evaluate(&fields(&[]), Severity::Critical), Severity::Info);
+}
+
+#[test]
+fn malformed_severity_expressions_are_rejected() {
+    assert_eq!(
+        "urgent when pid == 1".parse::<SeverityExpr>(),
+        Err(SeverityError::UnknownSeverity("urgent".into()))
+    );
+    for bad in ["high pid == 1", "high when pid", "high when (pid == 1", "high when path == 'x", "default"] {
+        assert!(matches!(bad.parse::<SeverityExpr>(), Err(SeverityError::Syntax(_))), "{bad}");
+    }
+}
+
+#[test]
+fn retention_keeps_criticals_while_expiring_infos() {
+    let dir = tempdir().unwrap();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut cfg = load(&root.join("config.toml")).unwrap().database;
+    cfg.path = "telemetry.db".into();
+    cfg.purge_on_restart = false.into();
+    let conn = init_database(dir.path(), &cfg).unwrap();
+
+    let now = 1_000 * DAY;
+    for sev in Severity::ALL {
+        insert_alert(&conn, &alert(now - 60 * DAY, sev)).unwrap();
+        insert_alert(&conn, &alert(now - 10 * DAY, sev)).unwrap();
+        insert_alert(&conn, &alert(now - DAY, sev)).unwrap();
+    }
+    conn.execute(
+        "INSERT INTO alerts (ts, rule_id, severity) VALUES (?1, 'legacy', 'warning')",
+        [now - 60 * DAY],
+    )
+    .unwrap();
+
+    // The shipped config: info 7d, critical forever, everything else 30d.
+    let retention = &cfg.retention.alerts;
+    assert_eq!(retention.keep(Severity::Critical), Keep::Forever);
+    assert_eq!(retention.keep(Severity::Info), Keep::For(Duration::from_secs(7 * 86_400)));
+    assert_eq!(retention.keep(Severity::High), Keep::For(Duration::from_secs(30 * 86_400)));
+
+    let removed = purge_alerts(&conn, retention, now).unwrap();
+    // Two infos, one each of low/medium/high and the unknown severity.
+    assert_eq!(removed, 6);
+
+    let left = severities(&conn);
+    let count = |s: &str| left.iter().filter(|(sev, _)| sev == s).count();
+    assert_eq!(count("critical"), 3);
+    assert_eq!(count("high"), 2);
+    assert_eq!(count("info"), 1);
+    assert_eq!(count("warning"), 0);
+
+    // Nothing configured means nothing expires.
+    assert!(!PerSeverity::<Keep>::default().expires());
+    assert_eq!(purge_alerts(&conn, &PerSeverity::default(), now + 1_000 * DAY).unwrap(), 0);
+}
+
+#[test]
+fn notification_rate_limits_apply_per_severity_band() {
+    let limit = |s: &str| RateLimit::try_from(s.to_string()).unwrap();
+    let pager = NotificationChannel {
+        name: "pager".into(),
+        min_severity: Severity::High,
+        max_severity: Severity::Critical,
+        rate_limit: PerSeverity { high: Some(limit("2/1m")), ..PerSeverity::default() },
+    };
+    let siem = NotificationChannel {
+        name: "siem".into(),
+        min_severity: Severity::Info,
+        max_severity: Severity::Medium,
+        rate_limit: PerSeverity { default: Some(limit("1/10s")), ..PerSeverity::default() },
+    };
+    let mut router = NotificationRouter::new(vec![pager, siem]);
+    let t0 = Instant::now();
+
+    // Bands route to the channels whose range contains them.
+    assert_eq!(router.route(Severity::Info, t0), vec!["siem"]);
+    assert_eq!(router.route(Severity::High, t0), vec!["pager"]);
+    assert_eq!(router.route(Severity::High, t0), vec!["pager"]);
+
+    // High is capped on the pager; criticals on the same channel are not.
+    assert!(router.route(Severity::High, t0).is_empty());
+    for _ in 0..10 {
+        assert_eq!(router.route(Severity::Critical, t0), vec!["pager"]);
+    }
+    assert_eq!(router.suppressed("pager", Severity::High), 1);
+    assert_eq!(router.suppressed("pager", Severity::Critical), 0);
+
+    // `default` limits each severity separately.
+    assert!(router.route(Severity::Info, t0).is_empty());
+    assert_eq!(router.route(Severity::Low, t0), vec!["siem"]);
+
+    // Windows slide.
+    assert_eq!(router.route(Severity::Info, t0 + Duration::from_secs(10)), vec!["siem"]);
+    assert_eq!(router.route(Severity::High, t0 + Duration::from_secs(60)), vec!["pager"]);
+
+    for bad in ["0/1m", "5", "x/1m", "5/soon"] {
+        assert!(RateLimit::try_from(bad.to_string()).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn stored_alerts_are_written_to_their_channels() {
+    let dir = tempdir().unwrap();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut cfg = load(&root.join("config.toml")).unwrap();
+    cfg.database.path = "telemetry.db".into();
+    drop(init_database(dir.path(), &cfg.database).unwrap());
+    let outbox = dir.path().join("notifications");
+    let sink = AlertSink::new(dir.path().join("telemetry.db"), Actions::disabled())
+        .routed(NotificationRouter::new(cfg.notifications), outbox.clone());
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    for severity in [Severity::Info, Severity::Medium, Severity::Critical] {
+        rt.block_on(sink.store(alert(DAY, severity)));
+    }
+
+    // The shipped channels: siem from low, pager from high.
+    let count = |channel: &str| std::fs::read_dir(outbox.join(channel)).map_or(0, |d| d.count());
+    assert_eq!((count("siem"), count("pager")), (2, 1));
+    let pager = std::fs::read_dir(outbox.join("pager")).unwrap().next().unwrap().unwrap().path();
+    let body: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(pager).unwrap()).unwrap();
+    assert_eq!((body["severity"].as_str(), body["rule_id"].as_str()), (Some("critical"), Some("test.rule")));
+}
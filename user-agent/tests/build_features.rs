@@ -0,0 +1,29 @@
+// tests/build_features.rs
+
+use agent::features::{build_info, enabled, features_help, is_enabled, KNOWN};
+
+#[test]
+fn reported_features_match_enabled_cfgs() {
+    assert_eq!(is_enabled("bundled-sqlite"), cfg!(feature = "bundled-sqlite"));
+
+    let features = enabled();
+    let mut sorted = features.clone();
+    sorted.sort();
+    assert_eq!(features, sorted);
+    for f in &features {
+        assert!(KNOWN.iter().any(|(k, _)| k == f), "undocumented feature {f}");
+    }
+}
+
+#[test]
+fn build_info_describes_this_build() {
+    let info = build_info();
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert!(!info.target.is_empty());
+    assert_eq!(info.profile, if cfg!(debug_assertions) { "debug" } else { "release" });
+
+    let help = features_help();
+    let mark = if cfg!(feature = "bundled-sqlite") { "+" } else { "-" };
+    assert!(help.contains(&format!("{mark} bundled-sqlite")), "{help}");
+    assert!(help.contains(info.target));
+}
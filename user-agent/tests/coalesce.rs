@@ -0,0 +1,115 @@
+// tests/coalesce.rs
+//
+// File event bursts in front of the writer: events of one process, path and
+// operation within the window become one row with their count, first and
+// last timestamps; anything else stays a row of its own.
+
+mod common;
+
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use prost_types::Timestamp;
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::mpsc};
+use shared::events::{file_event::Operation, FileEvent};
+
+use agent::{
+    comms::{
+        coalesce::{spawn_coalescer, Coalescer},
+        Coalesced, WrappedEvent,
+    },
+    db::{hub::AnyEvent, spawn_hub},
+    util::Shutdown,
+};
+use common::{from_micros, wrap};
+
+const WINDOW: Duration = Duration::from_secs(2);
+
+fn write(path: &str, size: u64, micros: i64) -> WrappedEvent<FileEvent> {
+    wrap(from_micros(micros), FileEvent {
+        op: Operation::Write as i32,
+        path: path.into(),
+        pid: 300,
+        size,
+        success: true,
+        ..FileEvent::default()
+    })
+}
+
+#[test]
+fn a_window_merges_its_key_until_it_closes() {
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+    let mut c = Coalescer::files(WINDOW);
+
+    assert!(c.push(write(r"C:\src\main.rs", 10, 1_000), at(0)).is_empty());
+    assert!(c.push(write(r"C:\src\lib.rs", 1, 1_100), at(100)).is_empty());
+    assert!(c.push(write(r"C:\src\main.rs", 20, 1_500), at(500)).is_empty());
+    let mut other = write(r"C:\src\main.rs", 5, 1_600);
+    other.payload.op = Operation::Delete as i32;
+    assert!(c.push(other, at(600)).is_empty());
+    assert_eq!(c.len(), 3);
+
+    assert!(c.expired(at(1_999)).is_empty());
+    let closed = c.expired(at(2_050));
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].payload.size, 30);
+    assert_eq!(closed[0].ts, Timestamp { seconds: 0, nanos: 1_000_000 });
+    assert_eq!(closed[0].coalesced, Some(Coalesced { count: 2, last_ts: Timestamp { seconds: 0, nanos: 1_500_000 } }));
+
+    // A write after the window opens a new one.
+    assert!(c.push(write(r"C:\src\main.rs", 7, 3_000), at(2_100)).is_empty());
+    let rest = c.drain();
+    let sizes: Vec<_> = rest.iter().map(|ev| (ev.payload.path.as_str(), ev.payload.size, ev.coalesced)).collect();
+    assert_eq!(sizes, [(r"C:\src\lib.rs", 1, None), (r"C:\src\main.rs", 5, None), (r"C:\src\main.rs", 7, None)]);
+    assert!(c.is_empty());
+}
+
+#[test]
+fn a_burst_is_stored_as_one_row_with_its_count() {
+    let dir = tempdir().unwrap();
+    let (conn, mut db_cfg) = common::database(dir.path());
+    db_cfg.flush_interval_ms = 50;
+    let rt = Runtime::new().unwrap();
+    let (hub_tx, hub_rx) = mpsc::channel::<AnyEvent>(1_024);
+    let hub = spawn_hub(&rt, conn, hub_rx, &db_cfg, Vec::new(), &Shutdown::new());
+    let (tx, stage) = {
+        let _guard = rt.enter();
+        spawn_coalescer(Coalescer::files(WINDOW), hub_tx.into(), 1_024)
+    };
+
+    // 100 writes to one file within 50 ms, then one write to each of 3 others.
+    for i in 0..100 {
+        tx.blocking_send(write(r"C:\build\out.obj", 4_096, 1_000 + i * 500)).unwrap();
+        if i % 20 == 0 {
+            sleep(Duration::from_millis(10));
+        }
+    }
+    for path in [r"C:\a.txt", r"C:\b.txt", r"C:\c.txt"] {
+        tx.blocking_send(write(path, 1, 60_000)).unwrap();
+    }
+    // Stopping the producers closes the open windows.
+    drop(tx);
+    rt.block_on(async {
+        stage.await.unwrap();
+        hub.await.unwrap();
+    });
+
+    let conn = Connection::open(dir.path().join("telemetry.db")).unwrap();
+    let rows: Vec<(String, i64, i64, i64, Option<i64>)> = conn
+        .prepare("SELECT path, size, count, ts, last_ts FROM fs_events ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(rows, [
+        (r"C:\build\out.obj".to_string(), 409_600, 100, 1_000, Some(50_500)),
+        (r"C:\a.txt".to_string(), 1, 1, 60_000, None),
+        (r"C:\b.txt".to_string(), 1, 1, 60_000, None),
+        (r"C:\c.txt".to_string(), 1, 1, 60_000, None),
+    ]);
+}
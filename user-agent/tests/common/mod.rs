@@ -0,0 +1,96 @@
+// tests/common/mod.rs
+//
+// Fixtures shared by the integration tests: the database settings of the
+// shipped config.toml, a database created with them, events as a listener
+// wraps them, a ring file mapped with a fresh header and a blocking scanner
+// pass. Each test binary uses a subset.
+
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use memmap2::{MmapMut, MmapOptions};
+use prost_types::Timestamp;
+use rusqlite::Connection;
+use tokio::sync::Semaphore;
+
+use agent::{
+    comms::WrappedEvent,
+    config::{load, model::{DatabaseConfig, ScanningConfig}},
+    db::connection::init_database,
+    scanner::{async_engine, cache::FileCacheEntry, worker::{PassSummary, ScanOptions}},
+    util::Shutdown,
+};
+use shared::ring::{self, RingHeader};
+
+/// `[database]` of the shipped config, on `telemetry.db`, kept across
+/// restarts.
+pub fn db_cfg() -> DatabaseConfig {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut cfg = load(&root.join("config.toml")).unwrap().database;
+    cfg.path = "telemetry.db".into();
+    cfg.purge_on_restart = false.into();
+    cfg
+}
+
+/// A database in `dir` created with [`db_cfg`], and that config.
+pub fn database(dir: &Path) -> (Connection, DatabaseConfig) {
+    let cfg = db_cfg();
+    (init_database(dir, &cfg).unwrap(), cfg)
+}
+
+/// `payload` stamped `ts` by sensor `test`, not read from a ring, not
+/// enriched and not coalesced. Tests needing more set it with `..wrap(..)`.
+pub fn wrap<E: Clone>(ts: impl Into<Timestamp>, payload: E) -> WrappedEvent<E> {
+    WrappedEvent {
+        ts:          ts.into(),
+        sensor_guid: "test".into(),
+        payload,
+        ring_pos:    None,
+        seq:         None,
+        enrichment:  None,
+        coalesced:   None,
+    }
+}
+
+/// `seconds` after the epoch, as an event timestamp.
+pub fn seconds(seconds: i64) -> Timestamp {
+    Timestamp { seconds, nanos: 0 }
+}
+
+/// `micros` after the epoch, as an event timestamp.
+pub fn from_micros(micros: i64) -> Timestamp {
+    Timestamp { seconds: micros / 1_000_000, nanos: (micros % 1_000_000) as i32 * 1_000 }
+}
+
+/// A ring file at `path` with `size` bytes of data, mapped, its header
+/// written and nothing in it.
+pub fn empty_ring(path: &Path, size: usize) -> MmapMut {
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path).unwrap();
+    file.set_len((ring::HEADER_SIZE + size) as u64).unwrap();
+    let mut mmap = unsafe { MmapOptions::new().map_mut(&file).unwrap() };
+    unsafe { (mmap.as_mut_ptr() as *mut RingHeader).write(RingHeader::new()) };
+    mmap
+}
+
+/// The header at the start of a mapped ring.
+pub fn header(mmap: &MmapMut) -> &RingHeader {
+    unsafe { &*(mmap.as_ptr() as *const RingHeader) }
+}
+
+/// One scanner pass over `dirs` on a runtime of its own, with the default
+/// `[scanning] concurrency`.
+pub fn scan_pass(
+    dirs: &[PathBuf],
+    cache: &Arc<Mutex<HashMap<PathBuf, FileCacheEntry>>>,
+    opts: &Arc<ScanOptions>,
+) -> PassSummary {
+    let rt = tokio::runtime::Builder::new_multi_thread().worker_threads(2).build().unwrap();
+    let limit = Arc::new(Semaphore::new(ScanningConfig::default().concurrency));
+    rt.block_on(async_engine::scan_pass(dirs, cache, opts, &limit, &Shutdown::new())).expect("never shut down")
+}
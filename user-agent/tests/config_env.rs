@@ -0,0 +1,142 @@
+// tests/config_env.rs
+//
+// `GLADIX_` variables override the TOML before validation, typed like the
+// value they replace, and can stand in for a missing config.toml.
+
+use std::path::PathBuf;
+use agent::config::{
+    load,
+    loader::parse_with,
+    model::{ConfigError, DirectoryRisk},
+};
+
+const BASE: &str = r#"
+[logging]
+enable = false
+
+[database]
+path               = "telemetry.db"
+purge_on_restart   = false
+synchronous        = "NORMAL"
+journal_size_limit = 20000000
+checkpoint_seconds = 30
+ttl_seconds        = 3600
+flush_interval_ms  = 250
+batch_size         = 1000
+
+[[scanner]]
+risk     = "High"
+dirs     = ["C:\\Downloads"]
+interval = "60s"
+"#;
+
+fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+fn rejected(pairs: &[(&str, &str)]) -> (String, String) {
+    match parse_with(BASE, vars(pairs)) {
+        Err(ConfigError::InvalidValue(field, reason)) => (field, reason),
+        other => panic!("expected InvalidValue, got {other:?}"),
+    }
+}
+
+#[test]
+fn variables_override_the_file() {
+    let config = parse_with(
+        BASE,
+        vars(&[
+            ("GLADIX_DATABASE__FLUSH_INTERVAL_MS", "100"),
+            ("GLADIX_LOGGING__LEVEL", "DEBUG"),
+            ("GLADIX_LOGGING__ENABLE", "1"),
+            ("GLADIX_DATABASE__SYNCHRONOUS", "full"),
+            ("GLADIX_SCANNER__0__DIRS", r"C:\Users;D:\Shares"),
+            ("PATH", "/usr/bin"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(config.database.flush_interval_ms, 100);
+    assert_eq!(config.logging.level, "DEBUG");
+    assert!(config.logging.enable);
+    // Validation runs on the result and normalizes it as for the file.
+    assert_eq!(config.database.synchronous, "FULL");
+    assert_eq!(config.scanner[0].directories, [PathBuf::from(r"C:\Users"), PathBuf::from(r"D:\Shares")]);
+    assert_eq!(config.database.batch_size, 1000);
+}
+
+#[test]
+fn keys_and_groups_missing_from_the_file_are_added() {
+    let config = parse_with(
+        BASE,
+        vars(&[
+            ("GLADIX_DATABASE__CHANNEL_CAPACITY", "50"),
+            ("GLADIX_SCANNER__1__RISK", "Low"),
+            ("GLADIX_SCANNER__1__DIRS", r"E:\Archive;"),
+            ("GLADIX_SCANNER__1__INTERVAL", "1h"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(config.database.channel_capacity, 50);
+    assert_eq!(config.scanner.len(), 2);
+    assert_eq!(config.scanner[1].risk, DirectoryRisk::Low);
+    assert_eq!(config.scanner[1].directories, [PathBuf::from(r"E:\Archive")]);
+}
+
+#[test]
+fn a_value_of_the_wrong_type_names_key_and_variable() {
+    let (field, reason) = rejected(&[("GLADIX_DATABASE__FLUSH_INTERVAL_MS", "soon")]);
+    assert_eq!(field, "database.flush_interval_ms");
+    assert!(reason.contains("'soon' is not an integer"), "{reason}");
+    assert!(reason.contains("GLADIX_DATABASE__FLUSH_INTERVAL_MS"), "{reason}");
+
+    let (field, _) = rejected(&[("GLADIX_LOGGING__ENABLE", "maybe")]);
+    assert_eq!(field, "logging.enable");
+    let (field, _) = rejected(&[("GLADIX_SCANNER__5__RISK", "Low")]);
+    assert_eq!(field, "scanner[5].risk");
+    let (field, _) = rejected(&[("GLADIX_DATABASE__", "x")]);
+    assert_eq!(field, "database.");
+}
+
+#[test]
+fn variables_without_a_section_are_not_overrides() {
+    let config = parse_with(BASE, vars(&[("GLADIX_DATABASE", "x"), ("GLADIX_PROFILE", "debug")])).unwrap();
+    assert_eq!(config.database.path, "telemetry.db");
+}
+
+#[test]
+fn overridden_values_are_still_validated() {
+    let (field, _) = rejected(&[("GLADIX_DATABASE__FLUSH_INTERVAL_MS", "1")]);
+    assert_eq!(field, "database.flush_interval_ms");
+}
+
+#[test]
+fn the_environment_can_replace_a_missing_file() {
+    let missing = tempfile::tempdir().unwrap().path().join("config.toml");
+    assert!(matches!(load(&missing), Err(ConfigError::Io(_))));
+
+    let set = [
+        ("GLADIX_DATABASE__PATH", "telemetry.db"),
+        ("GLADIX_DATABASE__PURGE_ON_RESTART", "false"),
+        ("GLADIX_DATABASE__SYNCHRONOUS", "NORMAL"),
+        ("GLADIX_DATABASE__JOURNAL_SIZE_LIMIT", "20000000"),
+        ("GLADIX_DATABASE__CHECKPOINT_SECONDS", "30"),
+        ("GLADIX_DATABASE__TTL_SECONDS", "3600"),
+        ("GLADIX_DATABASE__FLUSH_INTERVAL_MS", "250"),
+        ("GLADIX_DATABASE__BATCH_SIZE", "1000"),
+        ("GLADIX_SCANNER__0__RISK", "High"),
+        ("GLADIX_SCANNER__0__DIRS", r"C:\Downloads;"),
+    ];
+    // The only test here touching the process environment.
+    for (name, value) in set {
+        unsafe { std::env::set_var(name, value) };
+    }
+    let loaded = load(&missing);
+    for (name, _) in set {
+        unsafe { std::env::remove_var(name) };
+    }
+
+    let config = loaded.unwrap();
+    assert_eq!(config.logging.level, "INFO");
+    assert_eq!(config.database.path, "telemetry.db");
+    assert_eq!(config.scanner[0].directories, [PathBuf::from(r"C:\Downloads")]);
+}
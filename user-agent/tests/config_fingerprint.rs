@@ -0,0 +1,135 @@
+// tests/config_fingerprint.rs
+
+use std::{fs, path::Path};
+use tempfile::tempdir;
+
+use agent::config::{
+    canonical::{canonicalize, diff, fingerprint, render_diff, Change},
+    load,
+    metadata::Reload,
+    Config,
+};
+
+const BASE: &str = r#"
+[logging]
+enable = true
+file   = "logs/agent.log"
+level  = "INFO"
+
+[database]
+path               = "telemetry.db"
+purge_on_restart   = false
+synchronous        = "NORMAL"
+journal_size_limit = 20000000
+checkpoint_seconds = 30
+ttl_seconds        = 3600
+flush_interval_ms  = 250
+batch_size         = 1000
+
+[[scanner]]
+risk     = "High"
+dirs     = ["C:\\Downloads"]
+interval = "60s"
+
+[[scanner]]
+risk = "Low"
+dirs = ["C:\\Manual"]
+"#;
+
+/// Same settings, keys and scanner groups shuffled, host paths different.
+const SHUFFLED: &str = r#"
+[[scanner]]
+dirs = ["C:\\Manual"]
+risk = "Low"
+
+[database]
+batch_size         = 1000
+flush_interval_ms  = 250
+ttl_seconds        = 3600
+checkpoint_seconds = 30
+journal_size_limit = 20000000
+synchronous        = "NORMAL"
+purge_on_restart   = false
+path               = "D:\\gladix\\other.db"
+
+[[scanner]]
+interval = "1m"
+dirs     = ["C:\\Downloads"]
+risk     = "High"
+
+[logging]
+level  = "INFO"
+file   = "E:\\logs\\agent.log"
+enable = true
+"#;
+
+fn cfg(dir: &Path, name: &str, text: &str) -> Config {
+    let path = dir.join(name);
+    fs::write(&path, text).unwrap();
+    load(&path).unwrap()
+}
+
+#[test]
+fn fingerprint_ignores_ordering_and_volatile_keys() {
+    let dir = tempdir().unwrap();
+    let a = fingerprint(&cfg(dir.path(), "a.toml", BASE));
+    let b = fingerprint(&cfg(dir.path(), "b.toml", SHUFFLED));
+    assert_eq!(a, b);
+    assert_eq!(a.sections.keys().collect::<Vec<_>>(), vec!["actions", "analytics", "communications", "database", "detection", "dns", "etw", "export", "file_hash", "forwarder", "heartbeat", "limits", "logging", "metrics", "network_policy", "notification", "probe", "reports", "ring", "scanner", "scanning", "scheduling"]);
+}
+
+#[test]
+fn fingerprint_detects_real_changes() {
+    let dir = tempdir().unwrap();
+    let a = fingerprint(&cfg(dir.path(), "a.toml", BASE));
+    let b = fingerprint(&cfg(dir.path(), "b.toml", &BASE.replace("ttl_seconds        = 3600", "ttl_seconds = 7200")));
+    assert_ne!(a.digest, b.digest);
+    assert_ne!(a.sections["database"], b.sections["database"]);
+    assert_eq!(a.sections["scanner"], b.sections["scanner"]);
+    assert_eq!(a.sections["logging"], b.sections["logging"]);
+
+    // Default-filled: spelling out a default changes nothing.
+    let c = fingerprint(&cfg(dir.path(), "c.toml", &BASE.replace("level  = \"INFO\"", "")));
+    assert_eq!(a, c);
+}
+
+#[test]
+fn diff_groups_changes_by_section() {
+    let dir = tempdir().unwrap();
+    let local = canonicalize(&cfg(dir.path(), "a.toml", BASE));
+    let other_text = BASE
+        .replace("level  = \"INFO\"", "level  = \"DEBUG\"")
+        .replace("batch_size         = 1000", "batch_size = 1000\ncache_kb = 8192");
+    let other = canonicalize(&cfg(dir.path(), "b.toml", &other_text));
+
+    let diffs = diff(&local, &other);
+    assert_eq!(diffs.iter().map(|d| d.section.as_str()).collect::<Vec<_>>(), vec!["database", "logging"]);
+
+    let db = &diffs[0].changes;
+    assert_eq!(db.len(), 1);
+    assert_eq!(db[0].key, "database.cache_kb");
+    assert_eq!(db[0].change, Change::Changed { local: serde_json::Value::Null, other: 8192.into() });
+    assert_eq!(db[0].reload, Reload::Restart);
+    assert_eq!(diffs[1].changes[0].key, "logging.level");
+
+    let text = render_diff(&diffs);
+    assert_eq!(
+        text,
+        "[database]\n  ~ database.cache_kb: null -> 8192  (restart)\n\
+         [logging]\n  ~ logging.level: \"INFO\" -> \"DEBUG\"  (restart)\n"
+    );
+    assert!(diff(&local, &local).is_empty());
+}
+
+#[test]
+fn diff_reports_added_and_removed_scanner_groups() {
+    let dir = tempdir().unwrap();
+    let local = canonicalize(&cfg(dir.path(), "a.toml", BASE));
+    let extra = format!("{BASE}\n[[scanner]]\nrisk = \"Special\"\ndirs = [\"C:\\\\Special\"]\n");
+    let other = canonicalize(&cfg(dir.path(), "b.toml", &extra));
+
+    let diffs = diff(&local, &other);
+    assert_eq!(diffs.len(), 1);
+    assert_eq!(diffs[0].section, "scanner");
+    assert!(diffs[0].changes.iter().all(|c| matches!(c.change, Change::Added(_))));
+}
@@ -0,0 +1,212 @@
+// tests/config_service.rs
+//
+// The agent's config gRPC service, driven through the generated client as
+// in shared/tests/config_grpc.rs but against `comms::grpc::ConfigServer`:
+// reads report the running scanner, writes land in config.toml and in the
+// schedule the scanner follows.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::net::TcpListener;
+use tonic::transport::Channel;
+use shared::config::{
+    config_service_client::ConfigServiceClient, ConfigUpdate, DescribeSchemaRequest, DriverVersion,
+    GetConfigRequest, GetStatusRequest, ProcessConfig, ScannerConfig, SetConfigRequest,
+};
+use shared::constants::VersionInfo;
+
+use agent::{
+    comms::{grpc::{self, ConfigServer, StatusServer}, listeners::Buses},
+    config::{load, model::{CommunicationsConfig, ScanningConfig, SchedulingConfig}},
+    db::{
+        agent_status::{record_status, AgentStatus},
+        connection::init_database,
+        ops_journal::{self, Actor, Journal},
+        scan_cache::load_cache,
+    },
+    idle::IdleGate,
+    scanner::{async_engine, cache::PersistentCache, Schedule},
+    status::AgentStats,
+    util::Shutdown,
+};
+
+/// The repo's config.toml in `dir`, its first (High) group scanning
+/// `scanned` hourly.
+fn config_in(dir: &Path, scanned: &Path) -> PathBuf {
+    let template = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml")).unwrap();
+    let high = "dirs     = [\"C:\\\\Users\\\\Noel\\\\Downloads\", \"C:\\\\Programs\"]\ninterval = \"60s\"";
+    assert!(template.contains(high));
+    let text = template.replacen(high, &format!("dirs     = ['{}']\ninterval = \"1h\"", scanned.display()), 1);
+    let path = dir.join("config.toml");
+    fs::write(&path, text).unwrap();
+    path
+}
+
+async fn connect(server: ConfigServer, shutdown: &Shutdown) -> ConfigServiceClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(grpc::serve(listener, server, StatusServer::new(AgentStats::new()), shutdown.clone()));
+    ConfigServiceClient::connect(format!("http://{addr}")).await.unwrap()
+}
+
+fn scanner(interval_seconds: u32, paths: Vec<String>) -> SetConfigRequest {
+    SetConfigRequest {
+        config: Some(ConfigUpdate {
+            scanner: Some(ScannerConfig { enabled: true, interval_seconds, recursive: true, paths, ..Default::default() }),
+            ..Default::default()
+        }),
+    }
+}
+
+async fn wait_for_file(conn: &Connection, file: &Path) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !load_cache(conn).unwrap().contains_key(file) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("{} never scanned", file.display()));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn set_config_reschedules_the_running_scanner() {
+    let dir = tempdir().unwrap();
+    let (first, second) = (dir.path().join("first"), dir.path().join("second"));
+    for (root, file) in [(&first, "a.exe"), (&second, "b.exe")] {
+        fs::create_dir_all(root).unwrap();
+        fs::write(root.join(file), file).unwrap();
+    }
+    let config = config_in(dir.path(), &first);
+    let cfg = load(&config).unwrap();
+    let db = init_database(dir.path(), &cfg.database).unwrap();
+    let db_path = dir.path().join("telemetry.db");
+
+    let schedule = Schedule::new(cfg.scanner.clone());
+    let shutdown = Shutdown::new();
+    let idle = IdleGate::new(SchedulingConfig { respect_user_activity: false, ..SchedulingConfig::default() });
+    let store = PersistentCache::new(Connection::open(&db_path).unwrap());
+    let engine = tokio::spawn(async_engine::run_scanner(
+        schedule.clone(), store, Buses::new(16, 16), idle, shutdown.clone(),
+        ScanningConfig { concurrency: 2, ..ScanningConfig::default() },
+    ));
+
+    let server = ConfigServer::new(config.clone(), schedule.clone(), cfg.database.clone(), Journal::new(db_path.clone(), &cfg.database));
+    let mut client = connect(server, &shutdown).await;
+
+    let got = client.get_config(GetConfigRequest {}).await.unwrap().into_inner().scanner.unwrap();
+    assert!(got.enabled);
+    assert_eq!(got.interval_seconds, 3600);
+    assert_eq!(got.paths, [first.display().to_string()]);
+    assert_eq!(got.file_extensions, ".exe,.dll,.sys,.ocx");
+
+    // The first pass runs at start; the next one is an hour away.
+    wait_for_file(&db, &first.join("a.exe")).await;
+
+    let reply = client.set_config(scanner(1, vec![second.display().to_string()])).await.unwrap().into_inner();
+    assert!(reply.success, "{}", reply.message);
+    assert_eq!(schedule.interval(cfg.scanner[0].risk), Some(Duration::from_secs(1)));
+    // Due one second after the first pass, not an hour.
+    wait_for_file(&db, &second.join("b.exe")).await;
+
+    let got = client.get_config(GetConfigRequest {}).await.unwrap().into_inner().scanner.unwrap();
+    assert_eq!((got.interval_seconds, got.paths), (1, vec![second.display().to_string()]));
+
+    // Persisted, with the comments around it.
+    let reloaded = load(&config).unwrap();
+    assert_eq!(reloaded.scanner[0].interval, Some(Duration::from_secs(1)));
+    assert_eq!(reloaded.scanner[0].directories, [second]);
+    assert!(fs::read_to_string(&config).unwrap().contains("# High-risk scan every 60s\n[[scanner]]"));
+
+    let journal = ops_journal::since(&db, 0).unwrap();
+    assert!(journal.iter().any(|e| matches!(&e.actor, Actor::Grpc(peer) if peer.starts_with("127.0.0.1:"))), "{journal:?}");
+
+    shutdown.trigger();
+    tokio::time::timeout(Duration::from_secs(5), engine).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn invalid_changes_are_refused_and_leave_the_file_alone() {
+    let dir = tempdir().unwrap();
+    let config = config_in(dir.path(), dir.path());
+    let before = fs::read_to_string(&config).unwrap();
+    let cfg = load(&config).unwrap();
+    let schedule = Schedule::new(cfg.scanner.clone());
+    let shutdown = Shutdown::new();
+    let mut client = connect(ConfigServer::new(config.clone(), schedule.clone(), cfg.database.clone(), Journal::disabled()), &shutdown).await;
+
+    let reply = client.set_config(scanner(0, vec![])).await.unwrap().into_inner();
+    assert!(!reply.success);
+    assert!(reply.message.contains("must be positive"), "{}", reply.message);
+
+    let mut flat = scanner(60, vec![]);
+    flat.config.as_mut().unwrap().scanner.as_mut().unwrap().recursive = false;
+    assert!(!client.set_config(flat).await.unwrap().into_inner().success);
+
+    let mut pdf = scanner(60, vec![]);
+    pdf.config.as_mut().unwrap().scanner.as_mut().unwrap().file_extensions = ".pdf".into();
+    assert!(!client.set_config(pdf).await.unwrap().into_inner().success);
+
+    let mut process = scanner(60, vec![]);
+    process.config.as_mut().unwrap().process = Some(ProcessConfig { enabled: true, ..Default::default() });
+    let reply = client.set_config(process).await.unwrap().into_inner();
+    assert_eq!((reply.success, reply.message.as_str()), (false, "only scanner settings can be changed"));
+
+    assert_eq!(fs::read_to_string(&config).unwrap(), before);
+    assert_eq!(schedule.interval(cfg.scanner[0].risk), Some(Duration::from_secs(3600)));
+
+    let schema = client.describe_schema(DescribeSchemaRequest { event_type: "ProcessEvent".into() }).await.unwrap().into_inner();
+    assert_eq!(schema.events.len(), 1);
+    shutdown.trigger();
+}
+
+#[tokio::test]
+async fn get_status_serves_the_latest_heartbeat() {
+    let dir = tempdir().unwrap();
+    let config = config_in(dir.path(), dir.path());
+    let cfg = load(&config).unwrap();
+    let db_path = dir.path().join("telemetry.db");
+    let conn = Connection::open(&db_path).unwrap();
+    let server = ConfigServer::new(config.clone(), Schedule::new(cfg.scanner.clone()), cfg.database.clone(), Journal::disabled())
+        .with_status(db_path.clone());
+    let shutdown = Shutdown::new();
+    let mut client = connect(server, &shutdown).await;
+
+    let none = client.get_status(GetStatusRequest {}).await.unwrap_err();
+    assert_eq!(none.code(), tonic::Code::NotFound);
+
+    let beat = |uptime_secs, last_error: Option<&str>| AgentStatus {
+        ts: 1_700_000_000_000_000,
+        version: "1.2.3".into(),
+        uptime_secs,
+        ring_dropped: 4,
+        processed: BTreeMap::from([("process_events".to_string(), 12)]),
+        db_size_bytes: 8192,
+        last_error: last_error.map(String::from),
+        driver: Some(VersionInfo { protocol: 2 << 16, major: 0, minor: 4, patch: 2, build_time: 1_760_000_000, ..Default::default() }),
+        failed_tasks: vec!["process_ingest".into()],
+    };
+    record_status(&conn, &beat(60.0, Some("decode error"))).unwrap();
+    record_status(&conn, &beat(120.5, None)).unwrap();
+
+    let status = client.get_status(GetStatusRequest {}).await.unwrap().into_inner();
+    assert_eq!((status.version.as_str(), status.uptime_seconds, status.ring_dropped), ("1.2.3", 120.5, 4));
+    assert_eq!((status.processed.get("process_events"), status.db_size_bytes, status.last_error.as_str()), (Some(&12), 8192, ""));
+    assert_eq!(status.driver, Some(DriverVersion { protocol: 2 << 16, version: "0.4.2".into(), build_time: 1_760_000_000 }));
+    assert_eq!(status.failed_tasks, ["process_ingest"]);
+    shutdown.trigger();
+}
+
+#[test]
+fn remote_binds_need_allow_remote() {
+    let comms = |bind: &str, allow_remote| CommunicationsConfig { grpc_bind: bind.into(), allow_remote, ..Default::default() };
+    assert_eq!(grpc::listen_addr(&comms("0.0.0.0:50051", false)).unwrap().to_string(), "127.0.0.1:50051");
+    assert_eq!(grpc::listen_addr(&comms("0.0.0.0:50051", true)).unwrap().to_string(), "0.0.0.0:50051");
+    assert_eq!(grpc::listen_addr(&comms("[::1]:6000", false)).unwrap().to_string(), "[::1]:6000");
+    assert_eq!(grpc::listen_addr(&CommunicationsConfig::default()).unwrap().to_string(), "127.0.0.1:50051");
+}
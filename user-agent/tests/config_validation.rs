@@ -0,0 +1,244 @@
+// tests/config_validation.rs
+//
+// Out-of-range database and scanner values are refused at load time, each
+// naming the offending key.
+
+use agent::config::{loader::parse, model::ConfigError, Config};
+
+const BASE: &str = r#"
+[logging]
+enable = false
+
+[database]
+path               = "telemetry.db"
+purge_on_restart   = false
+synchronous        = "NORMAL"
+journal_size_limit = 20000000
+checkpoint_seconds = 30
+ttl_seconds        = 3600
+flush_interval_ms  = 250
+batch_size         = 1000
+
+[[scanner]]
+risk     = "High"
+dirs     = ["C:\\Downloads"]
+interval = "60s"
+"#;
+
+fn with(from: &str, to: &str) -> String {
+    assert!(BASE.contains(from), "{from}");
+    BASE.replace(from, to)
+}
+
+fn rejected(text: &str) -> (String, String) {
+    match parse(text) {
+        Err(ConfigError::InvalidValue(field, reason)) => (field, reason),
+        other => panic!("expected InvalidValue, got {other:?}"),
+    }
+}
+
+#[test]
+fn base_fixture_loads() {
+    let cfg: Config = parse(BASE).unwrap();
+    assert_eq!(cfg.database.synchronous, "NORMAL");
+}
+
+#[test]
+fn synchronous_is_normalized_or_rejected() {
+    for (given, stored) in [("full", "FULL"), ("Normal", "NORMAL"), (" off ", "OFF")] {
+        let cfg = parse(&with(r#""NORMAL""#, &format!("{given:?}"))).unwrap();
+        assert_eq!(cfg.database.synchronous, stored);
+    }
+    let (field, reason) = rejected(&with(r#""NORMAL""#, r#""SUPERFAST""#));
+    assert_eq!(field, "database.synchronous");
+    assert_eq!(reason, "'SUPERFAST' is not one of FULL, NORMAL, OFF");
+}
+
+#[test]
+fn writer_ranges_are_enforced() {
+    for (from, to, key) in [
+        ("flush_interval_ms  = 250", "flush_interval_ms = 0", "database.flush_interval_ms"),
+        ("flush_interval_ms  = 250", "flush_interval_ms = 9", "database.flush_interval_ms"),
+        ("batch_size         = 1000", "batch_size = 0", "database.batch_size"),
+        ("batch_size         = 1000", "batch_size = 100001", "database.batch_size"),
+        ("checkpoint_seconds = 30", "checkpoint_seconds = 0", "database.checkpoint_seconds"),
+        ("ttl_seconds        = 3600", "ttl_seconds = 10", "database.ttl_seconds"),
+        ("batch_size         = 1000", "batch_size = 1000\nmaintenance_hour = 24", "database.maintenance_hour"),
+    ] {
+        assert_eq!(rejected(&with(from, to)).0, key, "{to}");
+    }
+
+    // The bounds themselves, and a TTL of 0 (off), are fine.
+    let edges = with("flush_interval_ms  = 250", "flush_interval_ms = 10")
+        .replace("batch_size         = 1000", "batch_size = 100000")
+        .replace("ttl_seconds        = 3600", "ttl_seconds = 0\nmaintenance_hour = 23");
+    parse(&edges).unwrap();
+}
+
+#[test]
+fn scanner_groups_need_dirs_and_a_positive_interval() {
+    let (field, reason) = rejected(&with(r#"dirs     = ["C:\\Downloads"]"#, "dirs = []"));
+    assert_eq!((field.as_str(), reason.as_str()), ("scanner.high.dirs", "no directories"));
+
+    let (field, _) = rejected(&with(r#"interval = "60s""#, r#"interval = "0s""#));
+    assert_eq!(field, "scanner.high.interval");
+
+    assert_eq!(
+        parse(&with("synchronous        = \"NORMAL\"", "synchronous = \"fast\"")).unwrap_err().to_string(),
+        "database.synchronous: 'fast' is not one of FULL, NORMAL, OFF"
+    );
+}
+
+#[test]
+fn yara_groups_need_a_rules_dir() {
+    let yara = with(r#"interval = "60s""#, "interval = \"60s\"\nyara     = true");
+    let (field, reason) = rejected(&yara);
+    assert_eq!((field.as_str(), reason.as_str()), ("scanner.high.yara", "needs scanning.rules_dir"));
+
+    let cfg = parse(&format!("{yara}\n[scanning]\nrules_dir = \"C:\\\\rules\"\n")).unwrap();
+    assert!(cfg.scanner[0].yara);
+    assert_eq!(cfg.scanning.rules_dir.as_deref(), Some(std::path::Path::new(r"C:\rules")));
+}
+
+#[test]
+fn limits_name_ring_payloads_with_positive_rates() {
+    let cfg = parse(&format!("{BASE}\n[limits]\nprocess = {{ per_sec = 100, burst = 500 }}\n")).unwrap();
+    assert_eq!(cfg.limits.payloads["process"].burst, 500);
+    assert_eq!(cfg.limits.new_images, 256);
+
+    let (field, _) = rejected(&format!("{BASE}\n[limits]\nnetwork = {{ per_sec = 100, burst = 500 }}\n"));
+    assert_eq!(field, "limits.network");
+    let (field, reason) = rejected(&format!("{BASE}\n[limits]\nimage = {{ per_sec = 0, burst = 500 }}\n"));
+    assert_eq!((field.as_str(), reason.as_str()), ("limits.image", "per_sec and burst must be positive"));
+}
+
+#[test]
+fn an_enabled_export_needs_a_path_and_a_rotation_size() {
+    let cfg = parse(&format!("{BASE}\n[export]\nenable = true\n")).unwrap();
+    assert_eq!((cfg.export.path.as_str(), cfg.export.rotate_mb), ("events.ndjson", 100));
+    // Not checked while disabled.
+    parse(&format!("{BASE}\n[export]\nrotate_mb = 0\n")).unwrap();
+
+    let (field, _) = rejected(&format!("{BASE}\n[export]\nenable = true\npath = \" \"\n"));
+    assert_eq!(field, "export.path");
+    let (field, reason) = rejected(&format!("{BASE}\n[export]\nenable = true\nrotate_mb = 0\n"));
+    assert_eq!((field.as_str(), reason.as_str()), ("export.rotate_mb", "must be positive"));
+}
+
+#[test]
+fn etw_providers_need_a_guid_and_a_level() {
+    let provider = "[[etw.providers]]\nguid = \"{1c95126e-7eea-49a9-a3fe-a378b03ddb4d}\"\nkeywords = 0x10\n";
+    let cfg = parse(&format!("{BASE}\n[etw]\nenabled = true\n{provider}")).unwrap();
+    assert_eq!((cfg.etw.session.as_str(), cfg.etw.providers[0].level, cfg.etw.providers[0].keywords), ("GladixAgent", 4, 0x10));
+
+    let (field, reason) = rejected(&format!("{BASE}\n[etw]\nenabled = true\n"));
+    assert_eq!((field.as_str(), reason.as_str()), ("etw.providers", "enabled without providers"));
+    let (field, _) = rejected(&format!("{BASE}\n[[etw.providers]]\nguid = \"1c95126e-7eea\"\n"));
+    assert_eq!(field, "etw.providers[0].guid");
+    let (field, _) = rejected(&format!("{BASE}\n{provider}level = 9\n"));
+    assert_eq!(field, "etw.providers[0].level");
+}
+
+#[test]
+fn heartbeat_defaults_to_a_minute_and_needs_a_positive_interval() {
+    let cfg = parse(BASE).unwrap();
+    assert_eq!((cfg.heartbeat.enabled, cfg.heartbeat.interval_ms), (true, 60_000));
+
+    let (field, reason) = rejected(&format!("{BASE}\n[heartbeat]\ninterval_ms = 0\n"));
+    assert_eq!((field.as_str(), reason.as_str()), ("heartbeat.interval_ms", "must be positive"));
+    assert!(parse(&format!("{BASE}\n[heartbeat]\nenabled = false\ninterval_ms = 0\n")).is_ok());
+}
+
+#[test]
+fn ring_bursts_default_to_512_events_and_need_a_positive_cap() {
+    let cfg = parse(BASE).unwrap();
+    assert_eq!((cfg.ring.max_events_per_burst, cfg.ring.blocking_decode_bytes), (512, 65_536));
+
+    let (field, reason) = rejected(&format!("{BASE}\n[ring]\nmax_events_per_burst = 0\n"));
+    assert_eq!((field.as_str(), reason.as_str()), ("ring.max_events_per_burst", "must be positive"));
+}
+
+#[test]
+fn file_hashes_default_to_files_up_to_10_mib_on_two_threads() {
+    let cfg = parse(BASE).unwrap();
+    assert_eq!((cfg.file_hash.enabled, cfg.file_hash.max_bytes, cfg.file_hash.threads), (true, 10 << 20, 2));
+
+    let (field, reason) = rejected(&format!("{BASE}\n[file_hash]\nthreads = 0\n"));
+    assert_eq!((field.as_str(), reason.as_str()), ("file_hash.threads", "must be positive"));
+    assert!(parse(&format!("{BASE}\n[file_hash]\nenabled = false\nthreads = 0\n")).is_ok());
+}
+
+#[test]
+fn purge_on_restart_is_a_bool_or_a_list_of_event_tables() {
+    use agent::config::model::PurgeOnRestart;
+
+    let cfg = parse(&with("purge_on_restart   = false", "purge_on_restart = true")).unwrap();
+    assert!(cfg.database.purge_on_restart.whole_file());
+    let cfg = parse(BASE).unwrap();
+    assert_eq!(cfg.database.purge_on_restart, PurgeOnRestart::All(false));
+
+    let cfg = parse(&with("purge_on_restart   = false", r#"purge_on_restart = ["etw", "network_events"]"#)).unwrap();
+    assert!(!cfg.database.purge_on_restart.whole_file());
+    assert_eq!(cfg.database.purge_on_restart.tables(), ["etw", "network_events"]);
+
+    let (field, reason) = rejected(&with("purge_on_restart   = false", r#"purge_on_restart = ["etw", "alerts"]"#));
+    assert_eq!(field, "database.purge_on_restart");
+    assert!(reason.starts_with("unknown event table 'alerts'"), "{reason}");
+}
+
+#[test]
+fn retention_overrides_name_event_tables_and_parse_durations() {
+    use agent::config::model::Keep;
+    use std::time::Duration;
+
+    let retention = "\n[database.retention]\nprocess = \"30d\"\netw_events = \"6h\"\nfs = \"forever\"\n";
+    let cfg = parse(&format!("{BASE}{retention}")).unwrap();
+    let events = &cfg.database.retention.events;
+    assert_eq!(events["process"], Keep::For(Duration::from_secs(30 * 86_400)));
+    assert_eq!(events["etw_events"], Keep::For(Duration::from_secs(6 * 3_600)));
+    assert_eq!(events["fs"], Keep::Forever);
+    assert!(cfg.database.retention.alerts.default.is_none());
+
+    let (field, _) = rejected(&format!("{BASE}\n[database.retention]\nregistry = \"1d\"\n"));
+    assert_eq!(field, "database.retention.registry");
+    match parse(&format!("{BASE}\n[database.retention]\netw = \"six hours\"\n")) {
+        Err(ConfigError::Toml(e)) => assert!(e.to_string().contains("invalid retention 'six hours'"), "{e}"),
+        other => panic!("expected a TOML error, got {other:?}"),
+    }
+}
+
+#[test]
+fn dns_names_are_on_with_a_bounded_cache_and_lookup_rate() {
+    let cfg = parse(BASE).unwrap();
+    assert_eq!((cfg.dns.enabled, cfg.dns.cache_entries, cfg.dns.lookups_per_sec), (true, 4096, 10));
+
+    let (field, reason) = rejected(&format!("{BASE}\n[dns]\nlookups_per_sec = 0\n"));
+    assert_eq!((field.as_str(), reason.as_str()), ("dns.lookups_per_sec", "must be positive"));
+    let (field, _) = rejected(&format!("{BASE}\n[dns]\ncache_entries = 0\n"));
+    assert_eq!(field, "dns.cache_entries");
+    assert!(parse(&format!("{BASE}\n[dns]\nenabled = false\nlookups_per_sec = 0\n")).is_ok());
+}
+
+#[test]
+fn forwarding_is_off_and_needs_a_url_known_types_and_paired_certificates() {
+    let cfg = parse(BASE).unwrap();
+    assert!(!cfg.forwarder.enabled);
+    assert_eq!((cfg.forwarder.types.len(), cfg.forwarder.batch_size), (7, 500));
+    parse(&format!("{BASE}\n[forwarder]\nbatch_size = 0\n")).unwrap();
+
+    let on = |rest: &str| format!("{BASE}\n[forwarder]\nenabled = true\nendpoint = \"https://siem:4317\"\n{rest}");
+    assert!(parse(&on("types = [\"process\", \"network\"]\n")).is_ok());
+    let (field, _) = rejected(&format!("{BASE}\n[forwarder]\nenabled = true\nendpoint = \"siem:4317\"\n"));
+    assert_eq!(field, "forwarder.endpoint");
+    let (field, reason) = rejected(&on("types = [\"registry\"]\n"));
+    assert_eq!(field, "forwarder.types");
+    assert!(reason.starts_with("unknown payload 'registry'"), "{reason}");
+    let (field, reason) = rejected(&on("spool_mb = 0\n"));
+    assert_eq!((field.as_str(), reason.as_str()), ("forwarder.spool_mb", "must be positive"));
+    let (field, _) = rejected(&on("client_cert = \"agent.pem\"\n"));
+    assert_eq!(field, "forwarder.client_key");
+    let (field, _) = rejected(&format!(
+        "{BASE}\n[forwarder]\nenabled = true\nendpoint = \"http://siem:4317\"\nca_cert = \"ca.pem\"\n"
+    ));
+    assert_eq!(field, "forwarder.endpoint");
+}
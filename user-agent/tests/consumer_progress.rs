@@ -0,0 +1,182 @@
+// tests/consumer_progress.rs
+
+mod common;
+
+use std::{
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicU64, Ordering}, Arc},
+    time::Duration,
+};
+use memmap2::MmapOptions;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use prost::Message;
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::mpsc};
+use shared::{events::ProcessEvent, ring::{self, RingHeader}};
+
+use agent::{
+    comms::{
+        listeners::{Buses, Listener, RingListener},
+        memory_ring::MemoryRing,
+        progress::{reconcile, reconcile_optional, reconcile_ring, Reconcile},
+        WrappedEvent,
+    },
+    db::{
+        connection::init_database,
+        consumer_state::{coverage_gaps, load_position, StoredPosition},
+        db_writer::FlushAck,
+        spawn_ring_writer,
+    },
+    util::Shutdown,
+};
+
+const RING_SIZE: usize = 4_096;
+
+fn frame(pid: u32) -> Vec<u8> {
+    ProcessEvent { pid, ppid: 1, image_path: "C:\\a.exe".into(), ..ProcessEvent::default() }.encode_to_vec()
+}
+
+/// The fake driver's frame numbers.
+static SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// Fake driver: appends frames at `tail` and publishes the new tail.
+fn produce(file: &File, frames: &[Vec<u8>]) -> u64 {
+    let mut mmap = unsafe { MmapOptions::new().map_mut(file).unwrap() };
+    let header = mmap.as_ptr() as *const RingHeader;
+    let mut tail = unsafe { (*header).tail.load(Ordering::Acquire) } as usize;
+    for f in frames {
+        let off = ring::HEADER_SIZE + tail;
+        tail += ring::write_frame(&mut mmap[off..], SEQ.fetch_add(1, Ordering::Relaxed), 0, f).unwrap();
+    }
+    unsafe { (*header).tail.store(tail as u64, Ordering::Release) };
+    mmap.flush().unwrap();
+    tail as u64
+}
+
+/// Moves the header directly, as a consumer that crashed mid-batch or a
+/// reloaded driver would leave it.
+fn set_header(file: &File, head: u64, tail: u64) {
+    let mmap = unsafe { MmapOptions::new().map_mut(file).unwrap() };
+    let header = mmap.as_ptr() as *const RingHeader;
+    unsafe {
+        (*header).head.store(head, Ordering::Release);
+        (*header).tail.store(tail, Ordering::Release);
+    }
+    mmap.flush().unwrap();
+}
+
+fn ring_file(dir: &Path) -> (PathBuf, File) {
+    let path = dir.join("process_ring");
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+    file.set_len((ring::HEADER_SIZE + RING_SIZE) as u64).unwrap();
+    let mut mmap = unsafe { MmapOptions::new().map_mut(&file).unwrap() };
+    unsafe { (mmap.as_mut_ptr() as *mut RingHeader).write(RingHeader::new()) };
+    mmap.flush().unwrap();
+    (path, file)
+}
+
+fn stored(position: u64) -> Option<StoredPosition> {
+    Some(StoredPosition { position, ring_size: RING_SIZE as u64 })
+}
+
+#[test]
+fn reconcile_orderings() {
+    let size = RING_SIZE as u64;
+    assert_eq!(reconcile(None, 64, 128, size), Reconcile::FirstRun);
+    assert_eq!(reconcile(stored(64), 64, 128, size), Reconcile::Clean);
+    assert_eq!(reconcile(stored(64), 96, 128, size), Reconcile::Gap { bytes: 32 });
+    // Consumer wrapped after the last flush.
+    assert_eq!(reconcile(stored(size - 16), 24, 40, size), Reconcile::Gap { bytes: 40 });
+    // Driver reset the header, or came back with a different ring.
+    assert_eq!(reconcile(stored(512), 0, 0, size), Reconcile::DriverReloaded { recorded: 512 });
+    assert_eq!(
+        reconcile(Some(StoredPosition { position: 64, ring_size: 8_192 }), 64, 64, size),
+        Reconcile::DriverReloaded { recorded: 64 }
+    );
+}
+
+#[test]
+fn flushed_position_survives_restart_and_crash_gap_is_exact() {
+    let dir = tempdir().unwrap();
+    let (ring_path, file) = ring_file(dir.path());
+    let (conn, mut cfg) = common::database(dir.path());
+    cfg.flush_interval_ms = 20;
+    let tail = produce(&file, &[frame(1), frame(2), frame(3)]);
+
+    // 1st run: consume and flush everything.
+    let ring = MemoryRing::open(&ring_path).unwrap();
+    assert_eq!(reconcile_ring(&conn, "process", &ring).unwrap(), Reconcile::FirstRun);
+    {
+        let rt = Runtime::new().unwrap();
+        let (db_tx, db_rx) = mpsc::channel::<WrappedEvent<ProcessEvent>>(64);
+        let (ack, mut acked) = FlushAck::new("process");
+        spawn_ring_writer(&rt, init_database(dir.path(), &cfg).unwrap(), db_rx, &cfg, Some(ack), &Shutdown::new());
+
+        let listener = Arc::new(RingListener::<ProcessEvent>::new("process", ring, "guid"));
+        let buses = Buses { db_tx: db_tx.into(), intel_tx: tokio::sync::broadcast::channel(16).0 };
+        let _guard = rt.enter();
+        listener.spawn(buses, &Shutdown::new());
+
+        rt.block_on(async {
+            tokio::time::timeout(Duration::from_secs(5), acked.wait_for(|p| *p == Some(tail)))
+                .await
+                .expect("flush ack")
+                .unwrap();
+        });
+    }
+    assert_eq!(load_position(&conn, "process").unwrap(), stored(tail));
+    let rows: i64 = conn.query_row("SELECT COUNT(*) FROM process_events", [], |r| r.get(0)).unwrap();
+    assert_eq!(rows, 3);
+
+    // Crash: two more frames consumed (head advanced) but never flushed.
+    let new_tail = produce(&file, &[frame(4), frame(5)]);
+    set_header(&file, new_tail, new_tail);
+
+    let ring = MemoryRing::open(&ring_path).unwrap();
+    let outcome = reconcile_ring(&conn, "process", &ring).unwrap();
+    assert_eq!(outcome, Reconcile::Gap { bytes: new_tail - tail });
+    let gaps = coverage_gaps(&conn, "process").unwrap();
+    assert_eq!(gaps.len(), 1);
+    assert_eq!((gaps[0].kind.as_str(), gaps[0].bytes), ("uncommitted", Some(new_tail - tail)));
+
+    // Clean restart right after: no new gap.
+    let ring = MemoryRing::open(&ring_path).unwrap();
+    assert_eq!(reconcile_ring(&conn, "process", &ring).unwrap(), Reconcile::Clean);
+    assert_eq!(coverage_gaps(&conn, "process").unwrap().len(), 1);
+}
+
+#[test]
+fn driver_reload_is_reconciled() {
+    let dir = tempdir().unwrap();
+    let (ring_path, file) = ring_file(dir.path());
+    let (conn, _) = common::database(dir.path());
+    let tail = produce(&file, &[frame(1), frame(2)]);
+    set_header(&file, tail, tail);
+
+    let ring = MemoryRing::open(&ring_path).unwrap();
+    reconcile_ring(&conn, "process", &ring).unwrap();
+
+    // Driver restarts with an empty ring.
+    set_header(&file, 0, 0);
+    let ring = MemoryRing::open(&ring_path).unwrap();
+    assert_eq!(reconcile_ring(&conn, "process", &ring).unwrap(), Reconcile::DriverReloaded { recorded: tail });
+    assert_eq!(load_position(&conn, "process").unwrap(), stored(0));
+
+    let gaps = coverage_gaps(&conn, "process").unwrap();
+    assert_eq!((gaps[0].kind.as_str(), gaps[0].bytes), ("driver_reloaded", None));
+}
+
+#[test]
+fn optional_rings_survive_an_unreadable_position() {
+    let dir = tempdir().unwrap();
+    let (ring_path, _file) = ring_file(dir.path());
+    let ring = MemoryRing::open(&ring_path).unwrap();
+    // No consumer_state table: every read fails.
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+
+    let recorder = PrometheusBuilder::new().build_recorder();
+    metrics::with_local_recorder(&recorder, || reconcile_optional(&conn, "image", &ring));
+    let text = recorder.handle().render();
+    assert!(text.contains(r#"ring_reconcile_errors_total{ring="image"} 1"#), "{text}");
+}
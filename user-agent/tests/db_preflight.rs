@@ -0,0 +1,110 @@
+// tests/db_preflight.rs
+
+mod common;
+
+use std::path::Path;
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+use agent::{
+    config::model::DatabaseConfig,
+    db::{
+        connection::{apply_page_size, init_database, PageSizeOutcome},
+        db_writer::DbError,
+        preflight::{check, json1_from_options, parse_version, Requirements},
+    },
+};
+
+fn db_cfg(page_size: Option<u32>) -> DatabaseConfig {
+    let mut cfg = common::db_cfg();
+    cfg.page_size = page_size;
+    cfg
+}
+
+fn page_size(path: &Path) -> u32 {
+    Connection::open(path)
+        .unwrap()
+        .query_row("PRAGMA page_size", [], |r| r.get(0))
+        .unwrap()
+}
+
+#[test]
+fn version_parsing() {
+    assert_eq!(parse_version("3.45.1"), Some((3, 45, 1)));
+    assert_eq!(parse_version("3.8"), Some((3, 8, 0)));
+    assert_eq!(parse_version(" 3.38.0\n"), Some((3, 38, 0)));
+    assert_eq!(parse_version("three"), None);
+    assert_eq!(parse_version(""), None);
+}
+
+#[test]
+fn json1_detection_from_compile_options() {
+    let opts = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert!(json1_from_options((3, 45, 0), &opts(&["THREADSAFE=1"])));
+    assert!(!json1_from_options((3, 45, 0), &opts(&["OMIT_JSON"])));
+    assert!(json1_from_options((3, 31, 1), &opts(&["ENABLE_JSON1"])));
+    assert!(!json1_from_options((3, 31, 1), &opts(&["ENABLE_FTS5"])));
+}
+
+#[test]
+fn bundled_sqlite_passes_preflight() {
+    let dir  = tempdir().unwrap();
+    let conn = Connection::open(dir.path().join("x.db")).unwrap();
+    let report = check(&conn, &Requirements { json1: true, ..Requirements::default() }).unwrap();
+    assert!(report.is_ok(), "{report}");
+    assert_eq!(report.wal, Some(true));
+    assert!(!report.compile_options.is_empty());
+}
+
+#[test]
+fn unmet_version_is_reported() {
+    let dir  = tempdir().unwrap();
+    let conn = Connection::open(dir.path().join("x.db")).unwrap();
+    let req  = Requirements { min_version: (99, 0, 0), ..Requirements::default() };
+    let report = check(&conn, &req).unwrap();
+    assert!(!report.is_ok());
+    assert!(report.missing[0].contains("99.0.0"), "{report}");
+}
+
+#[test]
+fn page_size_applied_on_new_database() {
+    let dir = tempdir().unwrap();
+    let cfg = db_cfg(Some(16_384));
+    drop(init_database(dir.path(), &cfg).unwrap());
+    assert_eq!(page_size(&dir.path().join("telemetry.db")), 16_384);
+}
+
+#[test]
+fn page_size_on_existing_database_needs_vacuum() {
+    let dir  = tempdir().unwrap();
+    let file = dir.path().join("telemetry.db");
+
+    // Existing DB created with the default page size and some data.
+    drop(init_database(dir.path(), &db_cfg(None)).unwrap());
+    let before = page_size(&file);
+    assert_ne!(before, 8_192);
+
+    let conn = Connection::open(&file).unwrap();
+    assert_eq!(apply_page_size(&conn, Some(8_192), false).unwrap(), PageSizeOutcome::Vacuumed);
+    drop(conn);
+    assert_eq!(page_size(&file), 8_192);
+
+    let conn = Connection::open(&file).unwrap();
+    assert_eq!(apply_page_size(&conn, Some(8_192), false).unwrap(), PageSizeOutcome::Unchanged);
+    assert_eq!(apply_page_size(&conn, None, false).unwrap(), PageSizeOutcome::Default);
+
+    // Re-opening through init_database keeps the WAL + page size combination.
+    drop(conn);
+    drop(init_database(dir.path(), &db_cfg(Some(8_192))).unwrap());
+    assert_eq!(page_size(&file), 8_192);
+}
+
+#[test]
+fn invalid_page_size_is_rejected() {
+    let dir  = tempdir().unwrap();
+    let conn = Connection::open(dir.path().join("x.db")).unwrap();
+    assert!(matches!(
+        apply_page_size(&conn, Some(3_000), true),
+        Err(DbError::InvalidConfig(_))
+    ));
+}
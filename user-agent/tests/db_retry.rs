@@ -0,0 +1,77 @@
+// tests/db_retry.rs
+//
+// A writer whose flush fails keeps the rows and tries again: another
+// connection holds the write lock past the busy timeout, and once it lets
+// go every event lands.
+
+mod common;
+
+use std::{path::PathBuf, thread::sleep, time::{Duration, SystemTime}};
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::mpsc};
+use shared::events::{FileEvent, file_event::Operation as FileOperation};
+
+use agent::{
+    config::load,
+    db::{
+        connection::{db_path, init_database},
+        event_types::FS_EVENTS,
+        hub::AnyEvent,
+        schema_registry::ensure_for,
+        spawn_hub,
+    },
+    util::Shutdown,
+};
+use common::wrap;
+
+const EVENTS: usize = 50;
+
+fn file_event(i: usize) -> AnyEvent {
+    wrap(SystemTime::now(), FileEvent {
+        op:       FileOperation::Create as i32,
+        path:     format!("C:\\temp\\{i}.txt"),
+        pid:      1,
+        success:  true,
+        ..Default::default()
+    })
+    .into()
+}
+
+#[test]
+fn rows_survive_a_locked_database() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut db_cfg = load(&root.join("config.toml")).unwrap().database;
+    db_cfg.flush_interval_ms = 50;
+    db_cfg.flush_retry_max_ms = 400;
+    db_cfg.batch_size = 10;
+
+    let dir = tempdir().unwrap();
+    let conn = init_database(dir.path(), &db_cfg).unwrap();
+    ensure_for(&conn, &FS_EVENTS.schema).unwrap();
+    let file = db_path(dir.path(), &db_cfg);
+
+    // Holds the write lock well past the writer's 1s busy timeout.
+    let blocker = Connection::open(&file).unwrap();
+    blocker.execute_batch("BEGIN IMMEDIATE").unwrap();
+
+    let rt = Runtime::new().unwrap();
+    let shutdown = Shutdown::new();
+    let (tx, rx) = mpsc::channel::<AnyEvent>(EVENTS);
+    let hub = spawn_hub(&rt, conn, rx, &db_cfg, Vec::new(), &shutdown);
+    for i in 0..EVENTS {
+        tx.blocking_send(file_event(i)).unwrap();
+    }
+
+    sleep(Duration::from_millis(2500));
+    blocker.execute_batch("COMMIT").unwrap();
+
+    // Stored by a retry, not by the exit path.
+    sleep(Duration::from_millis(2000));
+    let count = || blocker.query_row("SELECT COUNT(*) FROM fs_events", [], |r| r.get::<_, i64>(0)).unwrap();
+    assert_eq!(count(), EVENTS as i64);
+
+    drop(tx);
+    rt.block_on(hub).unwrap();
+    assert_eq!(count(), EVENTS as i64);
+}
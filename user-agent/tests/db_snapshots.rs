@@ -0,0 +1,117 @@
+// tests/db_snapshots.rs
+
+mod common;
+
+use std::{fs, path::Path};
+use rusqlite::{params, Connection};
+use tempfile::tempdir;
+
+use agent::{
+    config::model::{DatabaseConfig, SnapshotConfig},
+    db::{
+        connection::init_database,
+        db_writer::DbError,
+        snapshots::{before, list, read_manifest, RiskyOp},
+    },
+};
+
+fn db_cfg() -> DatabaseConfig {
+    let mut cfg = common::db_cfg();
+    cfg.snapshots = SnapshotConfig { enabled: true, ..SnapshotConfig::default() };
+    cfg
+}
+
+/// Five recent alerts and one from two weeks ago.
+fn populate(dir: &Path, cfg: &DatabaseConfig) {
+    let conn = init_database(dir, cfg).unwrap();
+    let now = chrono::Utc::now().timestamp_micros();
+    for i in 0..5 {
+        conn.execute(
+            "INSERT INTO alerts (ts, rule_id, severity, message) VALUES (?1, 'r', 'high', ?2)",
+            params![now - i, format!("alert {i}")],
+        )
+        .unwrap();
+    }
+    conn.execute(
+        "INSERT INTO alerts (ts, rule_id, severity) VALUES (?1, 'old', 'low')",
+        [now - 14 * 86_400_000_000],
+    )
+    .unwrap();
+}
+
+fn alert_count(path: &Path) -> i64 {
+    Connection::open(path).unwrap().query_row("SELECT COUNT(*) FROM alerts", [], |r| r.get(0)).unwrap()
+}
+
+#[test]
+fn purge_takes_a_bounded_snapshot_first() {
+    let dir = tempdir().unwrap();
+    let mut cfg = db_cfg();
+    populate(dir.path(), &cfg);
+
+    cfg.purge_on_restart = true.into();
+    let conn = init_database(dir.path(), &cfg).unwrap();
+    let (op, rows, snap_dir): (String, i64, String) = conn
+        .query_row("SELECT operation, rows, dir FROM safety_snapshots", [], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap();
+    assert_eq!((op.as_str(), rows), ("purge", 5), "the two-week-old alert is outside max_days");
+    assert_eq!(alert_count(&dir.path().join("telemetry.db")), 0);
+
+    let manifest = read_manifest(Path::new(&snap_dir)).unwrap();
+    assert!(!manifest.truncated);
+    let alerts = manifest.files.iter().find(|f| f.table == "alerts").unwrap();
+    let text = fs::read_to_string(Path::new(&snap_dir).join(&alerts.file)).unwrap();
+    let first: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+    assert_eq!(first["message"], "alert 0", "newest first");
+    assert_eq!(list(&dir.path().join("snapshots")).unwrap(), vec![manifest]);
+}
+
+#[test]
+fn size_cap_truncates_and_old_snapshots_are_pruned() {
+    let dir = tempdir().unwrap();
+    let cfg = db_cfg();
+    populate(dir.path(), &cfg);
+    let db = dir.path().join("telemetry.db");
+    let root = dir.path().join("snapshots");
+
+    let tiny = SnapshotConfig { max_mb: 0, keep: 2, ..cfg.snapshots.clone() };
+    let m = before(&db, &root, &tiny, RiskyOp::Migration).unwrap();
+    assert!(m.truncated);
+    assert_eq!(m.rows(), 0);
+
+    before(&db, &root, &tiny, RiskyOp::Migration).unwrap();
+    let last = before(&db, &root, &tiny, RiskyOp::LayoutConversion).unwrap();
+    let kept = list(&root).unwrap();
+    assert_eq!(kept.len(), 2);
+    assert_eq!(kept[0], last);
+    assert!(kept.iter().all(|k| k.name != m.name), "oldest pruned");
+}
+
+#[test]
+fn failed_snapshot_blocks_the_operation_unless_skipping_is_allowed() {
+    let dir = tempdir().unwrap();
+    let mut cfg = db_cfg();
+    populate(dir.path(), &cfg);
+    // A file where the snapshot directory should be makes the export fail.
+    fs::write(dir.path().join("snapshots"), "not a directory").unwrap();
+
+    cfg.purge_on_restart = true.into();
+    let err = init_database(dir.path(), &cfg).unwrap_err();
+    assert!(matches!(err, DbError::Snapshot("purge", _)), "{err}");
+    assert_eq!(alert_count(&dir.path().join("telemetry.db")), 6, "purge did not run");
+
+    cfg.snapshots.skip_on_failure = true;
+    init_database(dir.path(), &cfg).unwrap();
+    assert_eq!(alert_count(&dir.path().join("telemetry.db")), 0);
+}
+
+#[test]
+fn disabled_snapshots_do_nothing() {
+    let dir = tempdir().unwrap();
+    let mut cfg = db_cfg();
+    populate(dir.path(), &cfg);
+    cfg.snapshots.enabled = false;
+    cfg.purge_on_restart = true.into();
+    init_database(dir.path(), &cfg).unwrap();
+    assert!(!dir.path().join("snapshots").exists());
+}
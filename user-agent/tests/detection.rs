@@ -0,0 +1,231 @@
+// tests/detection.rs
+//
+// `[detection]` rules: process, file and network matches become alert rows,
+// bad rules fail the config load, and rule edits apply without a restart.
+
+mod common;
+
+use std::{fs, path::{Path, PathBuf}, time::Duration};
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::broadcast};
+use shared::events::{file_event::Operation, FileEvent, NetworkEvent, ProcessEvent};
+
+use agent::{
+    actions::Actions,
+    comms::WrappedEvent,
+    config::{loader::parse, model::{ConfigError, DetectionConfig}},
+    db::{event_types::PROCESS_EVENTS, schema_registry::ensure_for},
+    intel::{detection::with_parent_image, spawn_detection, AlertSink, Detection, DetectionBuses, RuleSource, Severity},
+    util::Shutdown,
+};
+use common::{seconds, wrap};
+
+const RULES: &str = r#"
+[[detection.process]]
+id       = "encoded_powershell"
+severity = "high"
+image    = '(?i)\\powershell\.exe$'
+cmdline  = '(?i)\s-enc\s'
+
+[[detection.file]]
+id   = "startup_write"
+path = '*\Start Menu\Programs\Startup\*'
+ops  = ["write", "RENAME"]
+
+[[detection.network]]
+id            = "tor_ports"
+severity      = "low"
+severity_when = 'critical when exe_path ends_with "powershell.exe"'
+dst           = ["93.184.216.0/24", "10.1.2.3"]
+ports         = [9001, 9030]
+"#;
+
+fn shipped() -> String {
+    fs::read_to_string(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("config.toml")).unwrap()
+}
+
+fn rules(toml: &str) -> Detection {
+    Detection::compile(&parse(&format!("{}{toml}", shipped())).unwrap().detection).unwrap()
+}
+
+fn process(pid: u32, image: &str, cmdline: &str) -> WrappedEvent<ProcessEvent> {
+    wrap(seconds(100), ProcessEvent { pid, ppid: 4, image_path: image.into(), cmdline: cmdline.into(), ..Default::default() })
+}
+
+fn file(op: Operation, path: &str, new_path: &str) -> WrappedEvent<FileEvent> {
+    wrap(seconds(100), FileEvent { op: op as i32, path: path.into(), new_path: new_path.into(), pid: 7, success: true, ..Default::default() })
+}
+
+fn net(dst_ip: &str, dst_port: u32, exe: &str) -> WrappedEvent<NetworkEvent> {
+    wrap(seconds(100), NetworkEvent { dst_ip: dst_ip.into(), dst_port, exe_path: exe.into(), pid: 9, proto: "TCP".into(), ..Default::default() })
+}
+
+const STARTUP: &str = r"C:\Users\bob\AppData\Roaming\Microsoft\Windows\Start Menu\Programs\Startup\run.lnk";
+
+#[test]
+fn rules_match_their_events() {
+    let r = rules(RULES);
+    assert_eq!(r.len(), 3);
+
+    let hits = r.check_process(&process(10, r"C:\Windows\System32\WindowsPowerShell\v1.0\PowerShell.exe", "powershell -enc SQBFAFgA"));
+    assert_eq!(hits.len(), 1);
+    assert_eq!((hits[0].rule_id.as_str(), hits[0].severity, hits[0].ppid), ("detection.encoded_powershell", Severity::High, Some(4)));
+    assert!(r.check_process(&process(11, r"C:\Windows\System32\WindowsPowerShell\v1.0\powershell.exe", "powershell -nop")).is_empty());
+    assert!(r.check_process(&process(12, r"C:\tools\pwsh.exe", "pwsh -enc AAAA")).is_empty());
+
+    assert_eq!(r.check_file(&file(Operation::Write, &STARTUP.to_uppercase(), ""))[0].rule_id, "detection.startup_write");
+    assert_eq!(r.check_file(&file(Operation::Rename, r"C:\Users\bob\Downloads\run.lnk", STARTUP)).len(), 1, "renamed into the folder");
+    assert!(r.check_file(&file(Operation::Delete, STARTUP, "")).is_empty(), "operation not listed");
+    assert!(r.check_file(&file(Operation::Write, r"C:\Users\bob\Start Menu\x.lnk", "")).is_empty());
+
+    let hits = r.check_network(&net("93.184.216.34", 9001, r"C:\tools\curl.exe"));
+    assert_eq!((hits.len(), hits[0].severity), (1, Severity::Low));
+    assert_eq!(r.check_network(&net("10.1.2.3", 9030, r"C:\x\PowerShell.exe"))[0].severity, Severity::Critical);
+    assert!(r.check_network(&net("93.184.217.1", 9001, "")).is_empty(), "outside the range");
+    assert!(r.check_network(&net("93.184.216.34", 443, "")).is_empty(), "other port");
+    assert!(r.check_network(&net("not an address", 9001, "")).is_empty());
+}
+
+#[test]
+fn parent_image_comes_from_the_event_or_the_stored_parent() {
+    let r = rules(r#"
+[[detection.process]]
+id           = "office_child"
+parent_image = '(?i)\\winword\.exe$'
+"#);
+    assert!(r.needs_parent());
+    let mut child = process(20, r"C:\Windows\System32\cmd.exe", "cmd /c whoami");
+    child.payload.ppid = 10;
+    assert!(r.check_process(&child).is_empty(), "parent unknown");
+
+    let mut carried = child.clone();
+    carried.payload.parent_image_path = r"C:\Office\WINWORD.EXE".into();
+    assert_eq!(r.check_process(&carried).len(), 1);
+
+    // The driver left it empty: the parent's stored creation has it.
+    let conn = Connection::open_in_memory().unwrap();
+    ensure_for(&conn, &PROCESS_EVENTS.schema).unwrap();
+    conn.execute(
+        "INSERT INTO process_events (ts, pid, ppid, image_path, event_type) VALUES (?1, 10, 4, ?2, 'CREATE')",
+        (50 * 1_000_000, r"C:\Office\winword.exe"),
+    ).unwrap();
+    with_parent_image(&conn, &mut child);
+    assert_eq!(child.payload.parent_image_path, r"C:\Office\winword.exe");
+    assert_eq!(r.check_process(&child)[0].rule_id, "detection.office_child");
+    assert!(!rules(RULES).needs_parent());
+}
+
+#[test]
+fn bad_rules_fail_the_config_load() {
+    let err = |toml: &str| match parse(&format!("{}{toml}", shipped())) {
+        Err(ConfigError::InvalidValue(field, _)) => field,
+        other => panic!("{toml}: {other:?}"),
+    };
+    assert_eq!(err("[[detection.process]]\nid = \"a\"\ncmdline = '(unclosed'\n"), "detection.process[a].cmdline");
+    assert_eq!(err("[[detection.process]]\nid = \"a\"\n"), "detection.process[a].image");
+    assert_eq!(err("[[detection.process]]\nid = \"a\"\nparent_image = '['\n"), "detection.process[a].parent_image");
+    assert_eq!(err("[[detection.file]]\nid = \"f\"\npath = '*'\nops = [\"Truncate\"]\n"), "detection.file[f].ops");
+    assert_eq!(err("[[detection.network]]\nid = \"n\"\ndst = [\"10.0.0.0/33\"]\n"), "detection.network[n].dst");
+    assert_eq!(err("[[detection.network]]\nid = \"n\"\nseverity_when = 'loud when x == 1'\n"), "detection.network[n].severity_when");
+    assert_eq!(err("[[detection.network]]\nid = \"n\"\n[[detection.file]]\nid = \"n\"\npath = '*'\n"), "detection.network[n].id");
+
+    // Nothing configured: the engine runs with no rules.
+    assert!(Detection::compile(&DetectionConfig::default()).unwrap().is_empty());
+}
+
+fn alerts(db: &Path) -> Vec<(String, i64, String)> {
+    Connection::open(db)
+        .unwrap()
+        .prepare("SELECT rule_id, pid, severity FROM alerts ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap()
+}
+
+#[test]
+fn matches_on_the_buses_are_stored() {
+    let rt  = Runtime::new().unwrap();
+    let dir = tempdir().unwrap();
+    let db  = dir.path().join(common::db_cfg().path);
+    drop(common::database(dir.path()));
+
+    let (proc_tx, _) = broadcast::channel(64);
+    let (file_tx, _) = broadcast::channel(64);
+    let (net_tx, _)  = broadcast::channel(64);
+    let buses = DetectionBuses {
+        process: proc_tx.subscribe().into(),
+        file:    file_tx.subscribe().into(),
+        network: net_tx.subscribe().into(),
+    };
+    let sink = AlertSink::new(db.clone(), Actions::disabled());
+    let task = spawn_detection(&rt, buses, rules(RULES), None, db.clone(), sink, Shutdown::new());
+
+    assert!(proc_tx.send(process(10, r"C:\Windows\System32\WindowsPowerShell\v1.0\powershell.exe", "powershell -enc AAAA")).is_ok());
+    assert!(proc_tx.send(process(11, r"C:\Windows\notepad.exe", "notepad -enc x")).is_ok());
+    let mut exit = process(10, r"C:\Windows\System32\WindowsPowerShell\v1.0\powershell.exe", "powershell -enc AAAA");
+    exit.payload.event_type = shared::events::process_event::EventType::Exit as i32;
+    assert!(proc_tx.send(exit).is_ok());
+    drop(proc_tx);
+    assert!(file_tx.send(file(Operation::Write, STARTUP, "")).is_ok());
+    drop(file_tx);
+    assert!(net_tx.send(net("93.184.216.34", 9030, "")).is_ok());
+    assert!(net_tx.send(net("93.184.216.34", 80, "")).is_ok());
+    drop(net_tx);
+    rt.block_on(async { tokio::time::timeout(Duration::from_secs(5), task).await })
+        .expect("detection ends when every bus closes")
+        .unwrap();
+
+    let mut rows = alerts(&db);
+    rows.sort();
+    assert_eq!(rows, [
+        ("detection.encoded_powershell".to_string(), 10, "high".to_string()),
+        ("detection.startup_write".to_string(), 7, "medium".to_string()),
+        ("detection.tor_ports".to_string(), 9, "low".to_string()),
+    ]);
+}
+
+#[test]
+fn edited_rules_apply_without_a_restart() {
+    let rt  = Runtime::new().unwrap();
+    let dir = tempdir().unwrap();
+    let db  = dir.path().join(common::db_cfg().path);
+    drop(common::database(dir.path()));
+    let config = dir.path().join("config.toml");
+    let notepad = "[[detection.process]]\nid = \"notepad\"\nimage = '(?i)notepad'\n";
+    fs::write(&config, shipped()).unwrap();
+
+    let (proc_tx, _) = broadcast::channel(64);
+    let (_file_tx, file_rx) = broadcast::channel(1);
+    let (_net_tx, net_rx) = broadcast::channel(1);
+    let buses = DetectionBuses { process: proc_tx.subscribe().into(), file: file_rx.into(), network: net_rx.into() };
+    let source = RuleSource { config: config.clone(), period: Duration::from_millis(20) };
+    let shutdown = Shutdown::new();
+    let sink = AlertSink::new(db.clone(), Actions::disabled());
+    let task = spawn_detection(&rt, buses, Detection::default(), Some(source), db.clone(), sink, shutdown.clone());
+    let settle = || std::thread::sleep(Duration::from_millis(300));
+
+    assert!(proc_tx.send(process(1, r"C:\Windows\notepad.exe", "")).is_ok());
+    fs::write(&config, format!("{}{notepad}", shipped())).unwrap();
+    settle();
+    assert!(proc_tx.send(process(2, r"C:\Windows\notepad.exe", "")).is_ok());
+    settle();
+    // A broken edit leaves the rules in place.
+    fs::write(&config, format!("{}{notepad}[[detection.process]]\nid = \"x\"\nimage = '('\n", shipped())).unwrap();
+    settle();
+    assert!(proc_tx.send(process(3, r"C:\Windows\notepad.exe", "")).is_ok());
+    settle();
+    fs::write(&config, shipped()).unwrap();
+    settle();
+    assert!(proc_tx.send(process(4, r"C:\Windows\notepad.exe", "")).is_ok());
+    settle();
+    shutdown.trigger();
+    rt.block_on(async { tokio::time::timeout(Duration::from_secs(5), task).await })
+        .expect("detection stops on shutdown")
+        .unwrap();
+
+    let pids: Vec<_> = alerts(&db).into_iter().map(|(_, pid, _)| pid).collect();
+    assert_eq!(pids, [2, 3]);
+}
@@ -0,0 +1,204 @@
+// tests/dns.rs
+//
+// Remote host names on network events: the reverse lookup cache with its
+// TTLs and size cap, the enricher that fills names from it without waiting
+// and queues the addresses it does not know, and the lookups kept within
+// their per-second budget and timeout, against a resolver with canned
+// answers.
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    io,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use async_trait::async_trait;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use prost_types::Timestamp;
+use tokio::runtime;
+
+use agent::comms::WrappedEvent;
+use agent::config::model::DnsConfig;
+use agent::intel::{
+    dns::{DnsCache, Known, LOOKUP_TIMEOUT},
+    enrich::Enricher,
+    DnsNames, Resolver,
+};
+use shared::events::NetworkEvent;
+use common::wrap;
+
+/// Canned answers by address; addresses without one fail. Counts calls.
+#[derive(Default)]
+struct Canned {
+    names: HashMap<IpAddr, Option<&'static str>>,
+    calls: Mutex<Vec<IpAddr>>,
+    delay: Option<Duration>,
+}
+
+#[async_trait]
+impl Resolver for Canned {
+    async fn reverse(&self, ip: IpAddr) -> io::Result<Option<String>> {
+        self.calls.lock().unwrap().push(ip);
+        if let Some(delay) = self.delay {
+            tokio::time::sleep(delay).await;
+        }
+        match self.names.get(&ip) {
+            Some(name) => Ok(name.map(str::to_owned)),
+            None => Err(io::Error::other("SERVFAIL")),
+        }
+    }
+}
+
+fn ip(text: &str) -> IpAddr {
+    text.parse().unwrap()
+}
+
+fn config() -> DnsConfig {
+    DnsConfig { cache_entries: 3, ttl_secs: 60, negative_ttl_secs: 10, lookups_per_sec: 100, ..DnsConfig::default() }
+}
+
+fn connection(dst_ip: &str) -> WrappedEvent<NetworkEvent> {
+    wrap(Timestamp::default(), NetworkEvent { dst_ip: dst_ip.into(), dst_port: 443, ..NetworkEvent::default() })
+}
+
+fn enriched(names: &DnsNames, dst_ip: &str) -> WrappedEvent<NetworkEvent> {
+    let mut ev = connection(dst_ip);
+    names.enrich(&mut ev);
+    ev
+}
+
+/// Waits for the background lookups to leave `ip` with an answer.
+async fn answered(names: &DnsNames, ip: IpAddr) -> Known {
+    for _ in 0..200 {
+        match names.known(ip) {
+            Known::Unknown => tokio::time::sleep(Duration::from_millis(10)).await,
+            known => return known,
+        }
+    }
+    panic!("{ip} never answered");
+}
+
+#[test]
+fn names_and_their_absence_expire_after_their_own_ttl() {
+    let mut cache = DnsCache::new(&config());
+    let t0 = Instant::now();
+    cache.insert(ip("10.0.0.1"), Some("fs01.corp".into()), t0);
+    cache.insert(ip("10.0.0.2"), None, t0);
+
+    assert_eq!(cache.get(ip("10.0.0.1"), t0), Known::Name("fs01.corp".into()));
+    assert_eq!(cache.get(ip("10.0.0.2"), t0), Known::NoName);
+    assert_eq!(cache.get(ip("10.0.0.3"), t0), Known::Unknown);
+
+    let later = t0 + Duration::from_secs(10);
+    assert_eq!(cache.get(ip("10.0.0.2"), later), Known::Unknown, "negative TTL");
+    assert_eq!(cache.get(ip("10.0.0.1"), later), Known::Name("fs01.corp".into()));
+    assert_eq!(cache.get(ip("10.0.0.1"), t0 + Duration::from_secs(60)), Known::Unknown, "TTL");
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn the_least_recently_used_address_goes_first() {
+    let mut cache = DnsCache::new(&config());
+    let t0 = Instant::now();
+    for (n, last) in ["1", "2", "3"].iter().enumerate() {
+        cache.insert(ip(&format!("10.0.0.{last}")), Some(format!("host{n}")), t0);
+    }
+    // .1 used again, so .2 is now the oldest.
+    for _ in 0..10 {
+        assert_eq!(cache.get(ip("10.0.0.1"), t0), Known::Name("host0".into()));
+    }
+    cache.insert(ip("10.0.0.4"), None, t0);
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.get(ip("10.0.0.2"), t0), Known::Unknown);
+    for kept in ["10.0.0.1", "10.0.0.3", "10.0.0.4"] {
+        assert_ne!(cache.get(ip(kept), t0), Known::Unknown, "{kept}");
+    }
+
+    let mut none = DnsCache::new(&DnsConfig { cache_entries: 0, ..config() });
+    none.insert(ip("10.0.0.1"), Some("host".into()), t0);
+    assert!(none.is_empty());
+}
+
+#[tokio::test]
+async fn events_go_on_without_a_name_and_later_ones_get_it() {
+    let resolver = Arc::new(Canned {
+        names: HashMap::from([(ip("10.0.0.1"), Some("fs01.corp")), (ip("10.0.0.2"), None)]),
+        ..Canned::default()
+    });
+    let (names, _lookups) = DnsNames::spawn(&config(), resolver.clone());
+
+    // Not known yet: no waiting, no name, and one lookup however many
+    // events ask before it is answered.
+    for _ in 0..3 {
+        assert_eq!(enriched(&names, "10.0.0.1").payload.dst_hostname, "");
+    }
+    assert_eq!(answered(&names, ip("10.0.0.1")).await, Known::Name("fs01.corp".into()));
+    let ev = enriched(&names, "10.0.0.1");
+    assert_eq!(ev.payload.dst_hostname, "fs01.corp");
+    assert_eq!(ev.enrichment.unwrap()["dst_hostname"], "dns_cache");
+
+    // No PTR record, and a failed lookup: remembered as nameless.
+    enriched(&names, "10.0.0.2");
+    enriched(&names, "10.0.0.9");
+    assert_eq!(answered(&names, ip("10.0.0.2")).await, Known::NoName);
+    assert_eq!(answered(&names, ip("10.0.0.9")).await, Known::NoName);
+    assert_eq!(enriched(&names, "10.0.0.2").payload.dst_hostname, "");
+
+    // Loopback, unspecified and multicast are not looked up; a name the
+    // sensor or a resolution gave is kept.
+    for dst_ip in ["127.0.0.1", "::1", "0.0.0.0", "224.0.0.251", "not an ip"] {
+        assert!(enriched(&names, dst_ip).enrichment.is_none(), "{dst_ip}");
+    }
+    names.record(ip("10.0.0.5"), "printer.corp");
+    assert_eq!(enriched(&names, "10.0.0.5").payload.dst_hostname, "printer.corp");
+    let mut ev = connection("10.0.0.1");
+    ev.payload.dst_hostname = "given".into();
+    names.enrich(&mut ev);
+    assert_eq!((ev.payload.dst_hostname.as_str(), ev.enrichment), ("given", None));
+
+    let calls = resolver.calls.lock().unwrap().clone();
+    assert_eq!(calls, [ip("10.0.0.1"), ip("10.0.0.2"), ip("10.0.0.9")]);
+}
+
+#[test]
+fn lookups_stay_within_their_budget_and_timeout() {
+    let resolver = Arc::new(Canned {
+        names: (1..=5).map(|n| (ip(&format!("10.0.1.{n}")), Some("host"))).collect(),
+        ..Canned::default()
+    });
+    let rt = runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let recorder = PrometheusBuilder::new().build_recorder();
+    metrics::with_local_recorder(&recorder, || {
+        rt.block_on(async {
+            let budget = DnsConfig { lookups_per_sec: 2, ..config() };
+            let (names, _lookups) = DnsNames::spawn(&budget, resolver.clone());
+            for n in 1..=5 {
+                enriched(&names, &format!("10.0.1.{n}"));
+            }
+            answered(&names, ip("10.0.1.1")).await;
+            answered(&names, ip("10.0.1.2")).await;
+            // Dropped over the budget, so asked again by a later event.
+            assert_eq!(names.known(ip("10.0.1.5")), Known::Unknown);
+
+            let slow = Arc::new(Canned { delay: Some(LOOKUP_TIMEOUT * 2), ..Canned::default() });
+            let (names, _lookups) = DnsNames::spawn(&config(), slow);
+            enriched(&names, "10.0.2.1");
+            tokio::time::sleep(LOOKUP_TIMEOUT).await;
+            assert_eq!(answered(&names, ip("10.0.2.1")).await, Known::NoName);
+        })
+    });
+
+    assert_eq!(resolver.calls.lock().unwrap().len(), 2);
+    let text = recorder.handle().render();
+    for line in [
+        "dns_lookups_total{result=\"name\"} 2",
+        "dns_lookups_total{result=\"over_budget\"} 3",
+        "dns_lookups_total{result=\"timed_out\"} 1",
+        "dns_cache_lookups_total{result=\"miss\"} 6",
+    ] {
+        assert!(text.contains(line), "{line} not in {text}");
+    }
+}
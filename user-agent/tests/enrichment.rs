@@ -0,0 +1,154 @@
+// tests/enrichment.rs
+//
+// File and network events that only carry a pid get the image path of
+// their process: from the creations recorded off the process intel bus, or
+// from the running process itself. Paths the sensor reported are kept, and
+// a process created after the event (pid reuse) is not taken for its owner.
+
+mod common;
+
+use std::{
+    path::PathBuf,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use prost::Message;
+use rusqlite::Connection;
+use tempfile::NamedTempFile;
+use tokio::{runtime::Runtime, sync::{broadcast, mpsc}, time::timeout};
+
+use agent::comms::{
+    listeners::{Buses, Listener, RingListener},
+    memory_ring::MemoryRing,
+    WrappedEvent,
+};
+use agent::config::load;
+use agent::db::{connection::{db_path, init_database}, spawn_writer};
+use agent::intel::{
+    enrich::{Enricher, ExePath},
+    spawn_recorder, ProcessTable,
+};
+use agent::util::Shutdown;
+use shared::events::{file_event::Operation, FileEvent, NetworkEvent, ProcessEvent};
+use shared::ring::{self, RingHeader};
+use common::{from_micros, wrap};
+
+const WRITER_PID: u32 = 4242;
+/// No process has this pid, so the live lookup finds nothing.
+const GONE_PID: u32 = 0x7fff_fff0;
+
+fn now_micros() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as u64
+}
+
+fn creation(pid: u32, image_path: &str) -> ProcessEvent {
+    ProcessEvent { pid, ppid: 4, image_path: image_path.into(), ..ProcessEvent::default() }
+}
+
+fn connection(pid: u32, exe_path: &str) -> NetworkEvent {
+    NetworkEvent { pid, exe_path: exe_path.into(), dst_ip: "10.0.0.2".into(), dst_port: 445, ..NetworkEvent::default() }
+}
+
+/// A ring holding one frame with `payload` written at `ts`.
+fn ring_with(payload: &[u8], ts: u64) -> NamedTempFile {
+    let tmp = NamedTempFile::new().unwrap();
+    let mut mmap = common::empty_ring(tmp.path(), 4096);
+    let (header, data) = mmap.split_at_mut(ring::HEADER_SIZE);
+    let header = unsafe { &*(header.as_ptr() as *const RingHeader) };
+    assert!(ring::push(header, data, 1, ts, payload));
+    assert_ne!(header.tail.load(Ordering::Acquire), 0);
+    tmp
+}
+
+#[test]
+fn a_file_event_is_stored_with_the_image_of_its_process() {
+    let exe_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut db_cfg = load(&exe_dir.join("config.toml")).unwrap().database;
+    let tmp = NamedTempFile::new().unwrap();
+    db_cfg.path = tmp.path().file_name().unwrap().to_string_lossy().into_owned();
+    db_cfg.purge_on_restart = true.into();
+    let conn = init_database(&exe_dir, &db_cfg).unwrap();
+    let db_path = db_path(&exe_dir, &db_cfg);
+
+    let written = now_micros();
+    let file = FileEvent {
+        op: Operation::Write as i32,
+        path: r"C:\Users\Public\payload.dll".into(),
+        pid: WRITER_PID,
+        exe_path: String::new(),
+        ..FileEvent::default()
+    };
+    let ring_file = ring_with(&file.encode_to_vec(), written);
+
+    let rt = Runtime::new().unwrap();
+    let shutdown = Shutdown::new();
+    let enriched = rt.block_on(async {
+        // The writer's creation reaches the table before its file event.
+        let table = ProcessTable::default();
+        let (process_tx, _) = broadcast::channel::<WrappedEvent<ProcessEvent>>(8);
+        spawn_recorder(&rt, process_tx.subscribe().into(), table.clone());
+        process_tx.send(wrap(from_micros(written as i64 - 1_000_000), creation(WRITER_PID, r"C:\Tools\writer.exe"))).unwrap();
+        timeout(Duration::from_secs(5), async {
+            while table.is_empty() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("creation never recorded");
+
+        let (db_tx, db_rx) = mpsc::channel::<WrappedEvent<FileEvent>>(8);
+        spawn_writer(&rt, conn, db_rx, &db_cfg, &shutdown);
+        let (intel_tx, mut intel_rx) = broadcast::channel::<WrappedEvent<FileEvent>>(8);
+        let listener = Arc::new(
+            RingListener::new("file", MemoryRing::open(ring_file.path()).unwrap(), "SENSOR")
+                .enriched(Arc::new(ExePath::new(table))),
+        );
+        listener.spawn(Buses::<FileEvent> { db_tx: db_tx.into(), intel_tx }, &shutdown);
+        timeout(Duration::from_secs(5), intel_rx.recv()).await.expect("no event").unwrap()
+    });
+    assert_eq!(enriched.payload.exe_path, r"C:\Tools\writer.exe");
+    assert_eq!(enriched.enrichment.unwrap()["exe_path"], "process_cache");
+
+    std::thread::sleep(Duration::from_millis(db_cfg.flush_interval_ms + 200));
+    let stored: (i64, String) = Connection::open(&db_path)
+        .unwrap()
+        .query_row("SELECT pid, exe_path FROM fs_events", [], |r| Ok((r.get(0)?, r.get(1)?)))
+        .expect("one row in fs_events");
+    assert_eq!(stored, (WRITER_PID as i64, r"C:\Tools\writer.exe".to_string()));
+    shutdown.trigger();
+}
+
+#[test]
+fn reported_paths_are_kept_and_a_later_process_is_not_the_owner() {
+    let table = ProcessTable::default();
+    let exe_path = ExePath::new(table.clone());
+    let at = 1_700_000_000_000_000;
+
+    let mut reported = wrap(from_micros(at), connection(WRITER_PID, r"C:\Sensor\said.exe"));
+    table.record(&creation(WRITER_PID, r"C:\Tools\writer.exe"), at - 10);
+    exe_path.enrich(&mut reported);
+    assert_eq!(reported.payload.exe_path, r"C:\Sensor\said.exe");
+    assert!(reported.enrichment.is_none());
+
+    // Created after the connection: the pid belonged to another process.
+    table.record(&creation(GONE_PID, r"C:\Tools\later.exe"), at + 10);
+    let mut reused = wrap(from_micros(at), connection(GONE_PID, ""));
+    exe_path.enrich(&mut reused);
+    assert_eq!(reused.payload.exe_path, "");
+    assert!(reused.enrichment.is_none());
+
+    let mut kernel = wrap(from_micros(at), connection(0, ""));
+    exe_path.enrich(&mut kernel);
+    assert_eq!(kernel.payload.exe_path, "");
+}
+
+#[test]
+fn a_running_process_missing_from_the_table_is_looked_up() {
+    let exe_path = ExePath::new(ProcessTable::default());
+    let mut ev = wrap(from_micros(now_micros() as i64), connection(std::process::id(), ""));
+    exe_path.enrich(&mut ev);
+
+    let expected = std::env::current_exe().unwrap();
+    assert_eq!(PathBuf::from(&ev.payload.exe_path).file_name(), expected.file_name());
+    assert_eq!(ev.enrichment.unwrap()["exe_path"], "live_process");
+}
@@ -0,0 +1,215 @@
+// tests/etw_decode.rs
+//
+// Records as an ETW session delivers them, with the property layout TDH
+// gives for their event, come out as EtwEvents whose payload is the
+// properties as JSON. Values the layout cannot account for are reported,
+// not guessed.
+
+use std::sync::Arc;
+use serde_json::{json, Value};
+
+use agent::etw::{
+    decode::{in_type::*, parse_event_info, render},
+    Guid, Property, Record, Size,
+};
+
+const DNS_CLIENT: &str = "{1c95126e-7eea-49a9-a3fe-a378b03ddb4d}";
+const KERNEL_PROCESS: &str = "{22fb2cd6-0e7b-422b-a0c7-2fad1fd0e716}";
+
+/// Microsoft-Windows-DNS-Client 3008, a completed query.
+const DNS_3008: &str = "6500780061006d0070006c0065002e0063006f006d00000001000000000000400000000000000000390033002e0031003800\
+                        34002e003200310036002e00330034003b000000";
+
+/// Microsoft-Windows-Kernel-Process 1, a process start.
+const PROCESS_START: &str = "921000008772bf141a5edd012c03000001000000000000005c004400650076006900630065005c00480061007200640064\
+                             00690073006b0056006f006c0075006d00650033005c00570069006e0064006f00770073005c0053007900730074\
+                             0065006d00330032005c006e006f00740065007000610064002e006500780065000000b1a203003d2c1e5f0000\
+                             0000";
+
+fn record(provider: &str, event_id: u16, properties: Vec<Property>, data: Vec<u8>) -> Record {
+    Record {
+        provider: provider.parse().unwrap(),
+        event_id,
+        level: 4,
+        pid: 4242,
+        tid: 77,
+        timestamp: 134_367_030_001_234_567,
+        pointer_size: 8,
+        properties: Arc::from(properties),
+        data,
+    }
+}
+
+fn payload(record: &Record) -> Value {
+    serde_json::from_str(&record.to_event().json_payload).unwrap()
+}
+
+#[test]
+fn guids_parse_with_or_without_braces() {
+    let guid: Guid = KERNEL_PROCESS.to_uppercase().parse().unwrap();
+    assert_eq!(guid.to_string(), KERNEL_PROCESS);
+    assert_eq!(KERNEL_PROCESS.trim_matches(['{', '}']).parse::<Guid>().unwrap(), guid);
+    assert!("22fb2cd6-0e7b-422b-a0c7".parse::<Guid>().is_err());
+    assert!("{22fb2cd6-0e7b-422b-a0c7-2fad1fd0e71g}".parse::<Guid>().is_err());
+}
+
+#[test]
+fn dns_query_renders_every_property() {
+    let props = vec![
+        Property::scalar("QueryName", UNICODE_STRING),
+        Property::scalar("QueryType", UINT32),
+        Property::scalar("QueryOptions", UINT64),
+        Property::scalar("QueryStatus", UINT32),
+        Property::scalar("QueryResults", UNICODE_STRING),
+    ];
+    let rec = record(DNS_CLIENT, 3008, props, hex::decode(DNS_3008).unwrap());
+    let ev = rec.to_event();
+    assert_eq!((ev.provider_guid.as_str(), ev.event_id, ev.level, ev.pid, ev.tid), (DNS_CLIENT, 3008, 4, 4242, 77));
+    assert_eq!(
+        payload(&rec),
+        json!({
+            "QueryName": "example.com",
+            "QueryType": 1,
+            "QueryOptions": 0x4000_0000u64,
+            "QueryStatus": 0,
+            "QueryResults": "93.184.216.34;",
+        })
+    );
+}
+
+#[test]
+fn process_start_renders_times_and_empty_strings() {
+    let props = vec![
+        Property::scalar("ProcessID", UINT32),
+        Property::scalar("CreateTime", FILETIME),
+        Property::scalar("ParentProcessID", UINT32),
+        Property::scalar("SessionID", UINT32),
+        Property::scalar("Flags", UINT32),
+        Property::scalar("ImageName", UNICODE_STRING),
+        Property::scalar("ImageChecksum", HEXINT32),
+        Property::scalar("TimeDateStamp", HEXINT32),
+        Property::scalar("PackageFullName", UNICODE_STRING),
+        Property::scalar("PackageRelativeAppId", UNICODE_STRING),
+    ];
+    let rec = record(KERNEL_PROCESS, 1, props, hex::decode(PROCESS_START).unwrap());
+    assert_eq!(
+        payload(&rec),
+        json!({
+            "ProcessID": 4242,
+            "CreateTime": "2026-10-17T09:30:00.123456700+00:00",
+            "ParentProcessID": 812,
+            "SessionID": 1,
+            "Flags": 0,
+            "ImageName": "\\Device\\HarddiskVolume3\\Windows\\System32\\notepad.exe",
+            "ImageChecksum": "0x3a2b1",
+            "TimeDateStamp": "0x5f1e2c3d",
+            "PackageFullName": "",
+            "PackageRelativeAppId": "",
+        })
+    );
+    let ts: chrono::DateTime<chrono::Utc> = rec.time().into();
+    assert_eq!(ts.to_rfc3339(), "2026-10-17T09:30:00.123456700+00:00");
+}
+
+#[test]
+fn lengths_and_counts_come_from_earlier_properties() {
+    let mut data = vec![3, 0, 0, 0, 0xde, 0xad, 0xbe];
+    // S-1-5-18
+    data.extend([1, 1, 0, 0, 0, 0, 0, 5, 18, 0, 0, 0]);
+    data.extend(hex::decode("d62cfb227b0e2b42a0c72fad1fd0e716").unwrap());
+    data.extend([1, 0, 0, 0]);
+    data.extend(0x7ffe_0000u32.to_le_bytes());
+    data.extend([2, 0, 0xbb, 0x01, 0x50, 0x00]);
+    let props = vec![
+        Property::scalar("Size", UINT32),
+        Property { length: Size::FromProperty(0), ..Property::scalar("Data", BINARY) },
+        Property::scalar("User", SID),
+        Property::scalar("Activity", GUID),
+        Property::scalar("Elevated", BOOLEAN),
+        Property::scalar("Address", POINTER),
+        Property::scalar("PortCount", UINT16),
+        Property { count: Some(Size::FromProperty(6)), ..Property::scalar("Ports", UINT16) },
+    ];
+    // Logged by a 32-bit process.
+    let rec = Record { pointer_size: 4, ..record(DNS_CLIENT, 9, props, data) };
+    assert_eq!(
+        payload(&rec),
+        json!({
+            "Size": 3,
+            "Data": "deadbe",
+            "User": "S-1-5-18",
+            "Activity": KERNEL_PROCESS,
+            "Elevated": true,
+            "Address": "0x7ffe0000",
+            "PortCount": 2,
+            "Ports": [443, 80],
+        })
+    );
+}
+
+#[test]
+fn what_cannot_be_decoded_is_counted_not_guessed() {
+    let data = hex::decode(DNS_3008).unwrap();
+    // Cut inside QueryOptions: the value is left out whole.
+    let props = vec![
+        Property::scalar("QueryName", UNICODE_STRING),
+        Property::scalar("QueryType", UINT32),
+        Property::scalar("QueryOptions", UINT64),
+    ];
+    let cut = record(DNS_CLIENT, 3008, props, data[..32].to_vec());
+    assert_eq!(payload(&cut), json!({ "QueryName": "example.com", "QueryType": 1, "_undecoded": 4 }));
+
+    // A nested structure ends the walk.
+    let props = vec![
+        Property::scalar("QueryName", UNICODE_STRING),
+        Property { in_type: None, ..Property::scalar("Nested", UINT32) },
+    ];
+    let nested = record(DNS_CLIENT, 3008, props, data.clone());
+    assert_eq!(payload(&nested), json!({ "QueryName": "example.com", "_undecoded": data.len() - 24 }));
+
+    // No schema at all.
+    assert_eq!(render(&[], &[1, 2, 3], 8), r#"{"_undecoded":3}"#);
+    assert_eq!(render(&[], &[], 8), "{}");
+}
+
+/// `TRACE_EVENT_INFO` as TdhGetEventInformation fills it: a fixed header,
+/// one `EVENT_PROPERTY_INFO` per top-level property, then the names.
+fn event_info(props: &[(&str, u32, u16, u16, u16)]) -> Vec<u8> {
+    let mut info = vec![0u8; 112 + props.len() * 24];
+    info[100..104].copy_from_slice(&(props.len() as u32).to_le_bytes());
+    info[104..108].copy_from_slice(&(props.len() as u32).to_le_bytes());
+    for (i, (name, flags, in_type, count, length)) in props.iter().enumerate() {
+        let base = 112 + i * 24;
+        let name_offset = info.len() as u32;
+        info.extend(name.encode_utf16().chain([0]).flat_map(u16::to_le_bytes));
+        info[base..base + 4].copy_from_slice(&flags.to_le_bytes());
+        info[base + 4..base + 8].copy_from_slice(&name_offset.to_le_bytes());
+        info[base + 8..base + 10].copy_from_slice(&in_type.to_le_bytes());
+        info[base + 16..base + 18].copy_from_slice(&count.to_le_bytes());
+        info[base + 18..base + 20].copy_from_slice(&length.to_le_bytes());
+    }
+    info
+}
+
+#[test]
+fn tdh_event_info_is_read_into_properties() {
+    let info = event_info(&[
+        ("Size", 0, UINT32, 1, 4),
+        ("Data", 0x2, BINARY, 1, 0),
+        ("Ports", 0x4, UINT16, 0, 2),
+        ("Pair", 0, UINT8, 2, 1),
+        ("Header", 0x1, 0, 1, 0),
+    ]);
+    let props = parse_event_info(&info).unwrap();
+    assert_eq!(
+        props,
+        [
+            Property { length: Size::Fixed(4), ..Property::scalar("Size", UINT32) },
+            Property { length: Size::FromProperty(0), ..Property::scalar("Data", BINARY) },
+            Property { count: Some(Size::FromProperty(0)), length: Size::Fixed(2), ..Property::scalar("Ports", UINT16) },
+            Property { count: Some(Size::Fixed(2)), length: Size::Fixed(1), ..Property::scalar("Pair", UINT8) },
+            Property { in_type: None, ..Property::scalar("Header", 0) },
+        ]
+    );
+    assert!(parse_event_info(&info[..120]).is_none());
+}
@@ -0,0 +1,190 @@
+// tests/event_tests.rs
+
+mod common;
+
+use std::{path::PathBuf, thread::sleep, time::{Duration, SystemTime}};
+use prost::Message;
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::mpsc};
+use shared::events::{
+    base_event::Payload,
+    process_event::EventType,
+    BaseEvent,
+    ProcessEvent,
+};
+
+use agent::{
+    comms::WrappedEvent,
+    config::load,
+    db::{connection::{db_path, init_database}, spawn_writer},
+    util::Shutdown,
+};
+use common::wrap;
+
+/// `STATUS_ACCESS_VIOLATION`, as `PsGetProcessExitStatus` reports it.
+const ACCESS_VIOLATION: i32 = 0xC000_0005_u32 as i32;
+
+#[test]
+fn exit_event_round_trips_into_sqlite() {
+    let dir = tempdir().unwrap();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut cfg = load(&root.join("config.toml")).unwrap().database;
+    cfg.path = "telemetry.db".into();
+    cfg.flush_interval_ms = 20;
+
+    // As the driver would send it.
+    let exit = ProcessEvent {
+        pid:        4242,
+        event_type: EventType::Exit as i32,
+        exit_code:  ACCESS_VIOLATION,
+        ..ProcessEvent::default()
+    };
+    let sent = BaseEvent {
+        ts:          Some(SystemTime::now().into()),
+        sensor_guid: "PROC".into(),
+        seq:         0,
+        payload:     Some(Payload::ProcessEvent(exit)),
+        forward_seq: 0,
+    };
+    let received = BaseEvent::decode(sent.encode_to_vec().as_slice()).unwrap();
+    let Some(Payload::ProcessEvent(payload)) = received.payload else { panic!("not a process event") };
+    assert!(payload.is_exit());
+
+    let create = ProcessEvent {
+        pid:        4242,
+        ppid:       4,
+        image_path: r"C:\Tools\a.exe".into(),
+        user_sid:   "S-1-5-21-1004336348-1177238915-682003330-1001".into(),
+        session_id: 1,
+        elevated:   true,
+        ..ProcessEvent::default()
+    };
+    assert!(!create.is_exit());
+    // The driver could not read the token: no SID, session 0.
+    let tokenless = ProcessEvent { pid: 4343, ppid: 4, ..ProcessEvent::default() };
+
+    let rt = Runtime::new().unwrap();
+    let (tx, rx) = mpsc::channel(4);
+    spawn_writer(&rt, init_database(dir.path(), &cfg).unwrap(), rx, &cfg, &Shutdown::new());
+    for payload in [create, tokenless, payload] {
+        tx.blocking_send(WrappedEvent { sensor_guid: received.sensor_guid.clone(), ..wrap(received.ts.unwrap(), payload) })
+            .unwrap();
+    }
+    drop(tx);
+    sleep(Duration::from_millis(200));
+
+    let conn = Connection::open(db_path(dir.path(), &cfg)).unwrap();
+    let rows: Vec<(i64, String, Option<i64>)> = conn
+        .prepare("SELECT pid, event_type, exit_code FROM process_events ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(rows, [
+        (4242, "CREATE".to_owned(), None),
+        (4343, "CREATE".to_owned(), None),
+        (4242, "EXIT".to_owned(), Some(ACCESS_VIOLATION as i64)),
+    ]);
+
+    let tokens: Vec<(Option<String>, Option<i64>, Option<bool>)> = conn
+        .prepare("SELECT user_sid, session_id, elevated FROM process_events ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(tokens, [
+        (Some("S-1-5-21-1004336348-1177238915-682003330-1001".to_owned()), Some(1), Some(true)),
+        (None, Some(0), Some(false)),
+        (None, None, None),
+    ]);
+}
+
+#[test]
+fn existing_process_tables_gain_the_exit_columns() {
+    let dir = tempdir().unwrap();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut cfg = load(&root.join("config.toml")).unwrap().database;
+    cfg.path = "telemetry.db".into();
+    cfg.purge_on_restart = false.into();
+
+    // Layout written before exit events existed.
+    let conn = Connection::open(db_path(dir.path(), &cfg)).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE process_events (id INTEGER PRIMARY KEY, ts INTEGER NOT NULL, sensor_guid TEXT,
+             pid INTEGER NOT NULL, ppid INTEGER, image_path TEXT, cmdline TEXT, event_uid INTEGER,
+             creator_pid INTEGER, creator_tid INTEGER, image_path_norm TEXT);
+         INSERT INTO process_events (ts, pid) VALUES (1, 10);",
+    )
+    .unwrap();
+    drop(conn);
+
+    let conn = init_database(dir.path(), &cfg).unwrap();
+    let row: (String, Option<i64>, Option<String>) = conn
+        .query_row("SELECT event_type, exit_code, parent_cmdline FROM process_events", [], |r| {
+            Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+        })
+        .unwrap();
+    assert_eq!(row, ("CREATE".to_owned(), None, None));
+}
+
+#[test]
+fn parent_context_is_decoded_and_completed_from_history() {
+    let dir = tempdir().unwrap();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut cfg = load(&root.join("config.toml")).unwrap().database;
+    cfg.path = "telemetry.db".into();
+    cfg.flush_interval_ms = 20;
+
+    let create = |pid, ppid, image: &str, cmdline: &str| ProcessEvent {
+        pid,
+        ppid,
+        image_path: image.into(),
+        cmdline: cmdline.into(),
+        ..ProcessEvent::default()
+    };
+    let word = create(100, 4, r"C:\Office\WINWORD.EXE", "WINWORD.EXE /n report.docm");
+    // The driver found the parent's image; its command line is left to the agent.
+    let shell = ProcessEvent {
+        parent_image_path: r"\Device\HarddiskVolume3\Office\WINWORD.EXE".into(),
+        ..create(200, 100, r"C:\Windows\powershell.exe", "powershell -enc AAAA")
+    };
+    let sent = BaseEvent { payload: Some(Payload::ProcessEvent(shell.clone())), ..BaseEvent::default() };
+    let Some(Payload::ProcessEvent(received)) = BaseEvent::decode(sent.encode_to_vec().as_slice()).unwrap().payload else {
+        panic!("not a process event")
+    };
+    assert_eq!(received, shell);
+    // Parent gone before the driver looked it up.
+    let child = create(300, 200, r"C:\Windows\cmd.exe", "cmd /c whoami");
+    // Parent started before the agent.
+    let orphan = create(400, 999, r"C:\Windows
+otepad.exe", "notepad");
+
+    let rt = Runtime::new().unwrap();
+    let (tx, rx) = mpsc::channel(8);
+    spawn_writer(&rt, init_database(dir.path(), &cfg).unwrap(), rx, &cfg, &Shutdown::new());
+    for payload in [word, received, child, orphan] {
+        tx.blocking_send(wrap(SystemTime::now(), payload))
+            .unwrap();
+    }
+    drop(tx);
+    sleep(Duration::from_millis(200));
+
+    let conn = Connection::open(db_path(dir.path(), &cfg)).unwrap();
+    let rows: Vec<(i64, Option<String>, Option<String>)> = conn
+        .prepare("SELECT pid, parent_image_path, parent_cmdline FROM process_events ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let some = |s: &str| Some(s.to_owned());
+    assert_eq!(rows, [
+        (100, some(""), some("")),
+        (200, some(r"\Device\HarddiskVolume3\Office\WINWORD.EXE"), some("WINWORD.EXE /n report.docm")),
+        (300, some(r"C:\Windows\powershell.exe"), some("powershell -enc AAAA")),
+        (400, some(""), some("")),
+    ]);
+}
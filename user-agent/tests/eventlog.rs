@@ -0,0 +1,107 @@
+// tests/eventlog.rs
+//
+// Windows Event Log sink: which events a log record or an alert would be
+// written as, through a sink that records them instead of calling
+// `ReportEventW`.
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+use log::{Level, Log, Record};
+
+use agent::{
+    eventlog::{Category, EventLog, EventRecord, EventSink, EventType, ALERT_TARGET},
+    intel::{alerts::Alert, severity::Severity},
+};
+
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<EventRecord>>>);
+
+impl EventSink for Recorder {
+    fn report(&self, event: &EventRecord) -> io::Result<()> {
+        self.0.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+}
+
+fn log(eventlog: &EventLog, level: Level, target: &str, msg: &str) {
+    eventlog.log(&Record::builder().level(level).target(target).args(format_args!("{msg}")).build());
+}
+
+#[test]
+fn warnings_and_errors_are_written_with_their_category() {
+    let recorder = Recorder::default();
+    let eventlog = EventLog::new(recorder.clone());
+
+    log(&eventlog, Level::Warn, "agent::db::db_writer", "flush failed: disk I/O error");
+    log(&eventlog, Level::Error, "agent::comms::memory_ring", "ring header corrupt");
+    log(&eventlog, Level::Warn, "agent", "Stop requested via SCM");
+    log(&eventlog, Level::Warn, "agent::scanner::worker", "cannot read C:\\x.exe");
+    // Below WARN, and the line logged for each alert, are not written.
+    log(&eventlog, Level::Info, "agent::run", "Agent bootstrap initiated");
+    log(&eventlog, Level::Debug, "agent::db", "checkpoint");
+    log(&eventlog, Level::Warn, ALERT_TARGET, "rule-1: suspicious");
+
+    let events = recorder.0.lock().unwrap();
+    let written: Vec<_> = events.iter().map(|e| (e.kind, e.category, e.id, e.strings[0].as_str())).collect();
+    assert_eq!(written, [
+        (EventType::Warning, Category::Database, 3000, "flush failed: disk I/O error"),
+        (EventType::Error, Category::Ring, 2000, "ring header corrupt"),
+        (EventType::Warning, Category::Service, 1000, "Stop requested via SCM"),
+        (EventType::Warning, Category::Other, 9000, "cannot read C:\\x.exe"),
+    ]);
+    assert_eq!(events[0].strings[1], "agent::db::db_writer");
+}
+
+#[test]
+fn categories_follow_module_boundaries() {
+    assert_eq!(Category::of("agent::run"), Category::Service);
+    assert_eq!(Category::of("agent::comms::listeners"), Category::Ring);
+    assert_eq!(Category::of("agent::intel::detection"), Category::Detection);
+    assert_eq!(Category::of("agent::dbx"), Category::Other);
+    assert_eq!(Category::of("agent::runner"), Category::Other);
+    assert_eq!(Category::of("tonic::transport"), Category::Other);
+}
+
+#[test]
+fn alerts_carry_structured_insertion_strings() {
+    let recorder = Recorder::default();
+    let eventlog = EventLog::new(recorder.clone());
+    let alert = |severity, ppid, file: Option<&str>| Alert {
+        ts: 1_000_000,
+        rule_id: "write-then-exec".into(),
+        severity,
+        pid: 4242,
+        ppid,
+        message: "dropped and ran payload.exe".into(),
+        file: file.map(Into::into),
+    };
+    eventlog.alert(&alert(Severity::Critical, Some(1), Some(r"C:\Users\a\payload.exe")));
+    eventlog.alert(&alert(Severity::Medium, None, None));
+    eventlog.alert(&alert(Severity::Info, None, None));
+
+    let events = recorder.0.lock().unwrap();
+    assert_eq!(events[0], EventRecord {
+        kind:     EventType::Error,
+        category: Category::Detection,
+        id:       4000,
+        strings:  vec![
+            "write-then-exec: dropped and ran payload.exe".into(),
+            "write-then-exec".into(),
+            "critical".into(),
+            "4242".into(),
+            "1".into(),
+            r"C:\Users\a\payload.exe".into(),
+        ],
+    });
+    assert_eq!(events[1].kind, EventType::Warning);
+    assert_eq!(events[1].strings[4..], ["", ""]);
+    assert_eq!(events[2].kind, EventType::Information);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn there_is_no_event_log_off_windows() {
+    assert_eq!(EventLog::open().err().map(|e| e.kind()), Some(io::ErrorKind::Unsupported));
+}
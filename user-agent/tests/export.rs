@@ -0,0 +1,215 @@
+// tests/export.rs
+//
+// Events published on the intel buses end up in the export file, one JSON
+// object per line that parses back into the serde model; the file rotates
+// past its size keeping only the newest copies. Every variant of that model
+// also survives the trip through its protobuf form.
+
+mod common;
+
+use std::{
+    fs,
+    path::Path,
+    thread::sleep,
+    time::{Duration, Instant, SystemTime},
+};
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::Value;
+use shared::events::{
+    file_event::Operation as FileOperation, process_event::EventType, scan_result::Severity as ScanSeverity,
+    BaseEvent, EtwEvent, FileEvent, ImageLoadEvent, NetworkEvent, ObjectOpEvent, ProcessEvent, ScanResult,
+};
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::broadcast};
+
+use agent::{
+    comms::{
+        events::{self, Event},
+        export::{spawn_exporter, ExportTarget},
+        tap::TapSources,
+        WrappedEvent,
+    },
+    util::Shutdown,
+};
+use common::wrap;
+
+fn ts() -> DateTime<Utc> {
+    Utc.timestamp_opt(1_760_000_000, 123_456_000).unwrap()
+}
+
+fn every_variant() -> Vec<Event> {
+    let guid = || "EXPORT".to_owned();
+    vec![
+        Event::File(events::FileEvent {
+            ts: ts(), sensor_guid: guid(), seq: Some(7),
+            op: events::FileOperation::Rename,
+            path: "C:\\a.txt".into(), new_path: Some("C:\\b.txt".into()),
+            pid: 4, exe_path: "C:\\x.exe".into(), size: 12, sha256: vec![1; 32], success: true,
+            raw_path: None, raw_new_path: None,
+        }),
+        Event::Network(events::NetworkEvent {
+            ts: ts(), sensor_guid: guid(), seq: None,
+            direction: events::Direction::Inbound, proto: "TCP".into(),
+            src_ip: "10.0.0.1".into(), src_port: 445, dst_ip: "10.0.0.2".into(), dst_port: 50_000,
+            pid: 4, exe_path: "System".into(), bytes: 1_500, blocked: true, dst_hostname: "fs01.corp".into(),
+        }),
+        Event::Process(events::ProcessEvent {
+            ts: ts(), sensor_guid: guid(), seq: Some(1),
+            event_type: events::ProcessEventType::Exit,
+            pid: 10, ppid: 2, image_path: "C:\\p.exe".into(), cmdline: "p -x".into(),
+            creator_pid: 3, creator_tid: 30, exit_code: -1,
+            parent_image_path: "C:\\q.exe".into(), parent_cmdline: "q".into(), truncated: 2,
+            user_sid: "S-1-5-18".into(), session_id: 1, elevated: true,
+        }),
+        Event::Scan(events::ScanResult {
+            ts: ts(), sensor_guid: guid(), seq: None,
+            rule_id: "Eicar".into(), file_path: "C:\\eicar.com".into(), matches: vec!["$a".into(), "$b".into()],
+            severity: events::Severity::Critical,
+            size: 68, hash: 0xfeed, mtime: 1_700_000_000, risk_group: "high".into(), sha256: vec![2; 32],
+        }),
+        Event::Etw(events::EtwEvent {
+            ts: ts(), sensor_guid: guid(), seq: Some(9),
+            provider_guid: "{22fb2cd6-0e7b-422b-a0c7-2fad1fd0e716}".into(),
+            event_id: 1, level: 4, pid: 8, tid: 80, json_payload: "{\"k\":1}".into(),
+        }),
+        Event::Image(events::ImageLoadEvent {
+            ts: ts(), sensor_guid: guid(), seq: Some(2),
+            pid: 8, image_base: 0x7ff0_0000_0000, image_size: 0x1000,
+            full_image_name: "C:\\Windows\\System32\\ntdll.dll".into(), is_kernel_module: false,
+        }),
+        Event::ObjectOp(events::ObjectOpEvent {
+            ts: ts(), sensor_guid: guid(), seq: Some(4),
+            source_pid: 8, target_pid: 10, desired_access: 0x1010,
+            operation: events::ObjectOperation::HandleDuplicate,
+        }),
+    ]
+}
+
+#[test]
+fn every_variant_survives_the_protobuf_round_trip() {
+    for event in every_variant() {
+        let before = serde_json::to_value(&event).unwrap();
+        let back = Event::try_from(BaseEvent::from(event)).unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), before);
+    }
+}
+
+#[test]
+fn unknown_enum_values_are_rejected() {
+    let Event::File(file) = every_variant().remove(0) else { unreachable!() };
+    let mut proto = BaseEvent::from(Event::File(file));
+    if let Some(shared::events::base_event::Payload::FileEvent(f)) = &mut proto.payload {
+        f.op = 99;
+    }
+    assert!(Event::try_from(proto).is_err());
+    assert!(Event::try_from(BaseEvent::default()).is_err());
+}
+
+fn numbered<E: Clone>(payload: E, seq: u64) -> WrappedEvent<E> {
+    WrappedEvent { seq: Some(seq), ..wrap(SystemTime::now(), payload) }
+}
+
+/// Lines of `path` once there are `n` of them.
+fn wait_for_lines(path: &Path, n: usize) -> Vec<String> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let text = fs::read_to_string(path).unwrap_or_default();
+        let lines: Vec<String> = text.lines().map(str::to_owned).collect();
+        if lines.len() >= n || Instant::now() > deadline {
+            return lines;
+        }
+        sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn bus_events_are_written_as_ndjson() {
+    let dir = tempdir().unwrap();
+    let rt = Runtime::new().unwrap();
+    let shutdown = Shutdown::new();
+    let (process, _) = broadcast::channel(16);
+    let (file, _) = broadcast::channel(16);
+    let (network, _) = broadcast::channel(16);
+    let (etw, _) = broadcast::channel(16);
+    let (scan, _) = broadcast::channel(16);
+    let (image, _) = broadcast::channel(16);
+    let (object, _) = broadcast::channel(16);
+    let sources = TapSources {
+        process: Some(process.clone()),
+        file:    Some(file.clone()),
+        network: Some(network.clone()),
+        etw:     Some(etw.clone()),
+        scan:    Some(scan.clone()),
+        image:   Some(image.clone()),
+        object:  Some(object.clone()),
+    };
+    let target = ExportTarget { path: dir.path().join("out/events.ndjson"), rotate_bytes: u64::MAX, keep: 1 };
+    let writer = spawn_exporter(&rt, target.clone(), sources, shutdown.clone()).unwrap();
+
+    for seq in 1..=3 {
+        process.send(numbered(ProcessEvent { pid: seq as u32, event_type: EventType::Create as i32, ..Default::default() }, seq)).unwrap();
+        file.send(numbered(FileEvent { op: FileOperation::Write as i32, path: "C:\\f".into(), ..Default::default() }, seq)).unwrap();
+        network.send(numbered(NetworkEvent { proto: "UDP".into(), ..Default::default() }, seq)).unwrap();
+        etw.send(numbered(EtwEvent { event_id: 5, ..Default::default() }, seq)).unwrap();
+        scan.send(numbered(ScanResult { severity: ScanSeverity::High as i32, ..Default::default() }, seq)).unwrap();
+        image.send(numbered(ImageLoadEvent { full_image_name: "a.dll".into(), ..Default::default() }, seq)).unwrap();
+        object.send(numbered(ObjectOpEvent { source_pid: 8, target_pid: 10, ..Default::default() }, seq)).unwrap();
+    }
+
+    let lines = wait_for_lines(&target.path, 21);
+    assert_eq!(lines.len(), 21);
+    let mut kinds = lines
+        .iter()
+        .map(|line| {
+            let event: Event = serde_json::from_str(line).unwrap();
+            let value: Value = serde_json::to_value(&event).unwrap();
+            value["type"].as_str().unwrap().to_owned()
+        })
+        .collect::<Vec<_>>();
+    kinds.sort();
+    kinds.dedup();
+    assert_eq!(kinds, ["Etw", "File", "Image", "Network", "ObjectOp", "Process", "Scan"]);
+    let process_seqs: Vec<u64> = lines
+        .iter()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|v| v["type"] == "Process")
+        .map(|v| v["payload"]["seq"].as_u64().unwrap())
+        .collect();
+    assert_eq!(process_seqs, [1, 2, 3]);
+
+    shutdown.trigger();
+    rt.block_on(writer).unwrap();
+}
+
+#[test]
+fn the_file_rotates_keeping_the_newest_copies() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("events.ndjson");
+    let rt = Runtime::new().unwrap();
+    let shutdown = Shutdown::new();
+    let (process, _) = broadcast::channel(64);
+    let sources = TapSources { process: Some(process.clone()), ..TapSources::default() };
+    // Each line is well over 64 bytes: one line per file.
+    let target = ExportTarget { path: path.clone(), rotate_bytes: 64, keep: 2 };
+    let writer = spawn_exporter(&rt, target, sources, shutdown.clone()).unwrap();
+
+    for seq in 1..=5 {
+        process.send(numbered(ProcessEvent { pid: seq as u32, image_path: "C:\\p.exe".into(), ..Default::default() }, seq)).unwrap();
+    }
+    let numbered = |n: usize| dir.path().join(format!("events.ndjson.{n}"));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !fs::read_to_string(numbered(1)).unwrap_or_default().contains("\"pid\":5") && Instant::now() < deadline {
+        sleep(Duration::from_millis(50));
+    }
+    shutdown.trigger();
+    rt.block_on(writer).unwrap();
+
+    let pid = |p: &Path| -> u64 {
+        let v: Value = serde_json::from_str(fs::read_to_string(p).unwrap().trim()).unwrap();
+        v["payload"]["pid"].as_u64().unwrap()
+    };
+    assert_eq!(pid(&numbered(1)), 5);
+    assert_eq!(pid(&numbered(2)), 4);
+    assert!(!numbered(3).exists());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+}
@@ -0,0 +1,212 @@
+// tests/file_hash.rs
+//
+// SHA-256 of written files filled into file events: created, written and
+// renamed files are hashed, files over the cap or already gone pass through
+// unchanged with a reason counted, a file seen again at the same
+// modification time is not read again, and the digest reaches the stored
+// row.
+
+mod common;
+
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use tempfile::tempdir;
+use tokio::{runtime::{self, Runtime}, sync::mpsc};
+use shared::events::{file_event::Operation, FileEvent};
+
+use agent::{
+    comms::WrappedEvent,
+    config::{load, model::FileHashConfig},
+    db::{connection::init_database, hub::AnyEvent, spawn_hub},
+    intel::{spawn_file_hasher, FileHasher},
+    util::Shutdown,
+};
+use common::{seconds, wrap};
+
+fn event(op: Operation, path: &Path) -> WrappedEvent<FileEvent> {
+    wrap(seconds(1), FileEvent {
+        op: op as i32,
+        path: path.to_string_lossy().into_owned(),
+        pid: 300,
+        success: true,
+        ..FileEvent::default()
+    })
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+/// Where the digest of `ev` came from, if it was filled in.
+fn source(ev: &WrappedEvent<FileEvent>) -> Option<&'static str> {
+    ev.enrichment.as_ref().and_then(|e| e.get("sha256").copied())
+}
+
+/// Sets the modification time of `path` to `at`.
+fn touch(path: &Path, at: SystemTime) {
+    File::options().write(true).open(path).unwrap().set_modified(at).unwrap();
+}
+
+#[tokio::test]
+async fn created_written_and_renamed_files_are_hashed() {
+    let dir = tempdir().unwrap();
+    let hasher = FileHasher::new(&FileHashConfig::default());
+    let a = dir.path().join("a.exe");
+    fs::write(&a, b"MZ first").unwrap();
+
+    let mut created = event(Operation::Create, &a);
+    hasher.enrich(&mut created).await;
+    assert_eq!(created.payload.sha256, sha256(b"MZ first"));
+    assert_eq!(source(&created), Some("file_contents"));
+
+    // A rename is hashed where the file went.
+    let b = dir.path().join("b.exe");
+    fs::rename(&a, &b).unwrap();
+    fs::write(&b, b"MZ second, longer").unwrap();
+    let mut renamed = event(Operation::Rename, &a);
+    renamed.payload.new_path = b.to_string_lossy().into_owned();
+    hasher.enrich(&mut renamed).await;
+    assert_eq!(renamed.payload.sha256, sha256(b"MZ second, longer"));
+
+    // Deletes, failed operations and events already carrying a hash are
+    // left alone.
+    let mut deleted = event(Operation::Delete, &b);
+    let mut failed = event(Operation::Write, &b);
+    failed.payload.success = false;
+    let mut known = event(Operation::Write, &b);
+    known.payload.sha256 = vec![7; 32];
+    for ev in [&mut deleted, &mut failed, &mut known] {
+        let before = ev.payload.sha256.clone();
+        hasher.enrich(ev).await;
+        assert_eq!(ev.payload.sha256, before);
+        assert_eq!(source(ev), None);
+    }
+}
+
+#[test]
+fn files_that_cannot_be_hashed_pass_through_with_a_reason() {
+    let dir = tempdir().unwrap();
+    let big = dir.path().join("big.bin");
+    fs::write(&big, vec![0u8; 2_048]).unwrap();
+    let small = dir.path().join("small.bin");
+    fs::write(&small, vec![0u8; 1_024]).unwrap();
+    let hasher = FileHasher::new(&FileHashConfig { max_bytes: 1_024, ..FileHashConfig::default() });
+
+    let rt = runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let events = metrics::with_local_recorder(&recorder, || {
+        rt.block_on(async {
+            let mut events = vec![
+                event(Operation::Write, &big),
+                event(Operation::Create, &dir.path().join("gone.tmp")),
+                event(Operation::Create, dir.path()),
+                event(Operation::Write, &small),
+            ];
+            for ev in &mut events {
+                hasher.enrich(ev).await;
+            }
+            events
+        })
+    });
+
+    let hashed: Vec<_> = events.iter().map(|ev| !ev.payload.sha256.is_empty()).collect();
+    assert_eq!(hashed, [false, false, false, true], "the cap is inclusive");
+    assert!(events[..3].iter().all(|ev| ev.enrichment.is_none()));
+    let text = recorder.handle().render();
+    for reason in ["too_big", "missing", "not_a_file"] {
+        let line = format!("file_hash_skipped_total{{reason=\"{reason}\"}} 1");
+        assert!(text.contains(&line), "{line} not in {text}");
+    }
+}
+
+#[tokio::test]
+async fn a_file_is_read_again_only_when_its_modification_time_changes() {
+    let dir = tempdir().unwrap();
+    let hasher = FileHasher::new(&FileHashConfig { cache_entries: 2, ..FileHashConfig::default() });
+    let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let path = dir.path().join("doc.txt");
+    fs::write(&path, b"version 1").unwrap();
+    touch(&path, at);
+
+    let hash = |p: &PathBuf| {
+        let hasher = hasher.clone();
+        let mut ev = event(Operation::Write, p);
+        async move {
+            hasher.enrich(&mut ev).await;
+            (ev.payload.sha256.clone(), source(&ev).unwrap())
+        }
+    };
+    assert_eq!(hash(&path).await, (sha256(b"version 1"), "file_contents"));
+    assert_eq!(hash(&path).await, (sha256(b"version 1"), "hash_cache"));
+
+    // Same path, time and length: taken as unchanged, the old digest
+    // stands.
+    fs::write(&path, b"version 2").unwrap();
+    touch(&path, at);
+    assert_eq!(hash(&path).await, (sha256(b"version 1"), "hash_cache"));
+    touch(&path, at + Duration::from_secs(1));
+    assert_eq!(hash(&path).await, (sha256(b"version 2"), "file_contents"));
+
+    // Two digests fit: the least recently used one goes first.
+    let other = dir.path().join("other.txt");
+    let third = dir.path().join("third.txt");
+    fs::write(&other, b"other").unwrap();
+    fs::write(&third, b"third").unwrap();
+    assert_eq!(hash(&other).await.1, "file_contents");
+    assert_eq!(hash(&path).await.1, "hash_cache");
+    assert_eq!(hash(&third).await.1, "file_contents");
+    assert_eq!(hash(&path).await.1, "hash_cache");
+    assert_eq!(hash(&other).await.1, "file_contents");
+}
+
+#[test]
+fn the_digest_is_stored_with_the_event() {
+    let dir = tempdir().unwrap();
+    let mut db_cfg = load(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("config.toml")).unwrap().database;
+    db_cfg.path = "telemetry.db".into();
+    db_cfg.flush_interval_ms = 50;
+    let conn = init_database(dir.path(), &db_cfg).unwrap();
+    let rt = Runtime::new().unwrap();
+    let (hub_tx, hub_rx) = mpsc::channel::<AnyEvent>(64);
+    let hub = spawn_hub(&rt, conn, hub_rx, &db_cfg, Vec::new(), &Shutdown::new());
+    let (tx, stage) = {
+        let _guard = rt.enter();
+        spawn_file_hasher(FileHasher::new(&FileHashConfig::default()), hub_tx.into(), 64)
+    };
+
+    // Interleaved hashed and unhashed events keep their order.
+    let mut expected = Vec::new();
+    for i in 0..10u8 {
+        let path = dir.path().join(format!("f{i}.bin"));
+        if i % 3 == 0 {
+            tx.blocking_send(event(Operation::Delete, &path)).unwrap();
+            expected.push((path.to_string_lossy().into_owned(), Vec::new()));
+        } else {
+            let data = vec![i; 1_000 * i as usize];
+            fs::write(&path, &data).unwrap();
+            tx.blocking_send(event(Operation::Write, &path)).unwrap();
+            expected.push((path.to_string_lossy().into_owned(), sha256(&data)));
+        }
+    }
+    drop(tx);
+    rt.block_on(async {
+        stage.await.unwrap();
+        hub.await.unwrap();
+    });
+
+    let conn = Connection::open(dir.path().join("telemetry.db")).unwrap();
+    let rows: Vec<(String, Vec<u8>)> = conn
+        .prepare("SELECT path, sha256 FROM fs_events ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(rows, expected);
+}
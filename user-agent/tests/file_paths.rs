@@ -0,0 +1,153 @@
+// tests/file_paths.rs
+//
+// File event paths reach dedup and detections in one form: volume devices
+// become drive letters, looked up once per volume; rename targets are
+// completed from the source path; volumes without a letter pass through
+// and are counted. What the sensor sent is stored next to it.
+
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use rusqlite::{types::Value, Connection};
+
+use agent::{
+    comms::WrappedEvent,
+    db::{batch_inserts::BatchInsert, codec::Codec, schema_registry::ensure_for},
+    intel::{enrich::Enricher, PathNormalizer, SystemVolumes, Volumes},
+};
+use shared::events::{file_event::Operation, FileEvent};
+use common::{seconds, wrap};
+
+/// One volume on `C:`; counts how often the mappings are read.
+#[derive(Default)]
+struct OneVolume {
+    lookups: AtomicUsize,
+}
+
+impl Volumes for OneVolume {
+    fn dos_devices(&self) -> Vec<(String, String)> {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        vec![(r"\Device\HarddiskVolume3".into(), "C:".into())]
+    }
+
+    fn long_path(&self, _path: &str) -> Option<String> {
+        None
+    }
+}
+
+fn rename(path: &str, new_path: &str) -> WrappedEvent<FileEvent> {
+    wrap(seconds(1), FileEvent { op: Operation::Rename as i32, path: path.into(), new_path: new_path.into(), ..Default::default() })
+}
+
+fn normalized(normalizer: &PathNormalizer, mut ev: WrappedEvent<FileEvent>) -> FileEvent {
+    normalizer.enrich(&mut ev);
+    ev.payload
+}
+
+#[test]
+fn volume_devices_become_drive_letters_looked_up_once() {
+    let volumes = Arc::new(OneVolume::default());
+    let normalizer = PathNormalizer::new(volumes.clone());
+
+    let ev = normalized(&normalizer, wrap(seconds(1), FileEvent { path: r"\Device\HarddiskVolume3\Users\A\Doc.TXT".into(), ..Default::default() }));
+    assert_eq!(ev.path, r"c:\users\a\doc.txt");
+    assert_eq!(ev.raw_path, r"\Device\HarddiskVolume3\Users\A\Doc.TXT");
+    assert_eq!(normalizer.normalize(r"\device\harddiskvolume3\Windows\x.dll"), r"c:\windows\x.dll");
+    assert_eq!(volumes.lookups.load(Ordering::SeqCst), 1);
+
+    // Already canonical: nothing to keep.
+    let ev = normalized(&normalizer, wrap(seconds(1), FileEvent { path: r"c:\users\a\doc.txt".into(), ..Default::default() }));
+    assert_eq!((ev.path.as_str(), ev.raw_path.as_str()), (r"c:\users\a\doc.txt", ""));
+}
+
+#[test]
+fn rename_targets_are_completed_from_the_source() {
+    let normalizer = PathNormalizer::new(Arc::new(OneVolume::default()));
+    let source = r"\Device\HarddiskVolume3\Users\A\draft.docx";
+
+    let bare = normalized(&normalizer, rename(source, "Final.docx"));
+    assert_eq!((bare.path.as_str(), bare.new_path.as_str()), (r"c:\users\a\draft.docx", r"c:\users\a\final.docx"));
+    assert_eq!((bare.raw_path.as_str(), bare.raw_new_path.as_str()), (source, "Final.docx"));
+
+    let on_volume = normalized(&normalizer, rename(source, r"\Temp\final.docx"));
+    assert_eq!(on_volume.new_path, r"c:\temp\final.docx");
+
+    let full = normalized(&normalizer, rename(r"C:\a\b.txt", r"\??\D:\c.txt"));
+    assert_eq!((full.new_path.as_str(), full.raw_new_path.as_str()), (r"d:\c.txt", r"\??\D:\c.txt"));
+}
+
+#[test]
+fn unmapped_volumes_pass_through_and_are_counted() {
+    let normalizer = PathNormalizer::new(Arc::new(OneVolume::default()));
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let ev = metrics::with_local_recorder(&recorder, || {
+        normalized(&normalizer, wrap(seconds(1), FileEvent { path: r"\Device\HarddiskVolume9\Data\X.bin".into(), ..Default::default() }))
+    });
+    assert_eq!(ev.path, r"\device\harddiskvolume9\data\x.bin");
+    let text = recorder.handle().render();
+    assert!(text.contains("path_unmapped_volumes_total 1"), "{text}");
+}
+
+#[test]
+fn short_names_of_missing_files_are_kept() {
+    let normalizer = PathNormalizer::new(Arc::new(SystemVolumes));
+    assert_eq!(normalizer.normalize(r"C:\NOPE~1\GONE~1.TXT"), r"c:\nope~1\gone~1.txt");
+}
+
+#[test]
+fn raw_paths_are_stored_when_they_differ() {
+    let conn = Connection::open_in_memory().unwrap();
+    ensure_for(&conn, <WrappedEvent<FileEvent>>::schema()).unwrap();
+    let mut stmt = conn.prepare(<WrappedEvent<FileEvent>>::insert_sql()).unwrap();
+    let normalizer = PathNormalizer::new(Arc::new(OneVolume::default()));
+    for ev in [rename(r"\Device\HarddiskVolume3\a.txt", "b.txt"), rename(r"c:\a.txt", r"c:\b.txt")] {
+        let mut ev = ev;
+        normalizer.enrich(&mut ev);
+        <WrappedEvent<FileEvent>>::bind_and_execute(&mut stmt, &ev, &mut Codec::disabled()).unwrap();
+    }
+    drop(stmt);
+
+    let mut stmt = conn.prepare("SELECT path, new_path, raw_path, raw_new_path FROM fs_events ORDER BY id").unwrap();
+    let rows: Vec<Vec<Value>> = stmt
+        .query_map([], |r| (0..4).map(|i| r.get(i)).collect())
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let text = |s: &str| Value::Text(s.into());
+    assert_eq!(rows, [
+        [text(r"c:\a.txt"), text(r"c:\b.txt"), text(r"\Device\HarddiskVolume3\a.txt"), text("b.txt")],
+        [text(r"c:\a.txt"), text(r"c:\b.txt"), Value::Null, Value::Null],
+    ]);
+}
+
+#[cfg(windows)]
+#[test]
+fn short_names_of_existing_files_are_expanded() {
+    use std::os::windows::ffi::OsStrExt;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetShortPathNameW(long: *const u16, short: *mut u16, len: u32) -> u32;
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let long = dir.path().join("a rather long file name.txt");
+    std::fs::write(&long, b"x").unwrap();
+    let wide: Vec<u16> = long.as_os_str().encode_wide().chain([0]).collect();
+    let mut short = vec![0u16; 1_024];
+    // SAFETY: NUL-terminated input; `len` is the buffer size in characters.
+    let n = unsafe { GetShortPathNameW(wide.as_ptr(), short.as_mut_ptr(), short.len() as u32) } as usize;
+    let short = String::from_utf16_lossy(&short[..n]);
+    if n == 0 || !short.contains('~') {
+        // 8.3 names are disabled on this volume.
+        return;
+    }
+    let normalizer = PathNormalizer::new(Arc::new(SystemVolumes));
+    let expected = normalizer.normalize(&long.to_string_lossy());
+    assert_eq!(normalizer.normalize(&short), expected);
+    assert!(expected.ends_with(r"\a rather long file name.txt"), "{expected}");
+}
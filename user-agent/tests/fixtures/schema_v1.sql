@@ -1,61 +1,5 @@
-
--- Enable WAL mode and tuning
-PRAGMA journal_mode = WAL;
-PRAGMA synchronous = NORMAL;
-PRAGMA journal_size_limit = 52428800;  -- ~50MB
-
--- Filesystem events table
-CREATE TABLE IF NOT EXISTS fs_events (
-    id          INTEGER PRIMARY KEY,
-    ts          INTEGER NOT NULL,
-    sensor_guid TEXT,
-    op          TEXT    NOT NULL,
-    path        TEXT    NOT NULL,
-    new_path    TEXT,
-    pid         INTEGER,
-    exe_path    TEXT,
-    size        INTEGER,
-    sha256      TEXT,
-    result      TEXT
-);
-CREATE INDEX IF NOT EXISTS idx_fs_events_ts  ON fs_events(ts);
-CREATE INDEX IF NOT EXISTS idx_fs_events_pid ON fs_events(pid);
-
--- Network events table
-CREATE TABLE IF NOT EXISTS network_events (
-    id          INTEGER PRIMARY KEY,
-    ts          INTEGER NOT NULL,
-    sensor_guid TEXT,
-    direction   TEXT    NOT NULL,
-    proto       TEXT    NOT NULL,
-    src_ip      TEXT    NOT NULL,
-    src_port    INTEGER,
-    dst_ip      TEXT    NOT NULL,
-    dst_port    INTEGER,
-    pid         INTEGER,
-    exe_path    TEXT,
-    bytes       INTEGER,
-    verdict     TEXT,
-    rule_id     TEXT
-);
-CREATE INDEX IF NOT EXISTS idx_net_events_ts  ON network_events(ts);
-CREATE INDEX IF NOT EXISTS idx_net_events_pid ON network_events(pid);
-
--- ETW events table
-CREATE TABLE IF NOT EXISTS etw_events (
-    id            INTEGER PRIMARY KEY,
-    ts            INTEGER NOT NULL,
-    sensor_guid   TEXT,
-    provider_guid TEXT    NOT NULL,
-    event_id      INTEGER NOT NULL,
-    level         INTEGER,
-    pid           INTEGER,
-    tid           INTEGER,
-    json_payload  TEXT
-);
-CREATE INDEX IF NOT EXISTS idx_etw_events_ts         ON etw_events(ts);
-CREATE INDEX IF NOT EXISTS idx_etw_events_provider   ON etw_events(provider_guid);
-CREATE INDEX IF NOT EXISTS idx_etw_events_event_id   ON etw_events(event_id);
+-- A database at schema version 1, as written before alerts and consumer
+-- state existed, with one audited config change.
 
 -- Configuration tables (scanner, process, fs, network, etw)
 CREATE TABLE IF NOT EXISTS scanner_config (
@@ -108,4 +52,10 @@ CREATE TABLE IF NOT EXISTS config_audit (
     old_config  TEXT    NOT NULL,
     new_config  TEXT    NOT NULL
 );
-CREATE INDEX IF NOT EXISTS idx_config_audit_time ON config_audit(changed_at);
\ No newline at end of file
+CREATE INDEX IF NOT EXISTS idx_config_audit_time ON config_audit(changed_at);
+
+INSERT INTO scanner_config (id, interval_seconds, paths) VALUES (1, 300, '["C:\\Users"]');
+INSERT INTO config_audit (sensor_type, changed_at, actor, old_config, new_config)
+    VALUES ('scanner', 1700000000, 'cli', '{}', '{"interval_seconds":300}');
+
+PRAGMA user_version = 1;
@@ -0,0 +1,262 @@
+// tests/forwarder.rs
+//
+// Events forwarded to a collector served in-process: a collector killed in
+// the middle of a stream and started again still ends up with every
+// forwarded event exactly once, events spooled while it was down are sent
+// by the next run with their numbering carried on, and the spool itself
+// keeps its cap, its acknowledgements and its readable frames.
+
+mod common;
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use prost_types::Timestamp;
+use shared::events::{
+    base_event::Payload,
+    event_collector_server::{EventCollector, EventCollectorServer},
+    BaseEvent, FileEvent, NetworkEvent, ProcessEvent, StreamAck,
+};
+use tempfile::tempdir;
+use tokio::{net::TcpListener, runtime::Runtime, sync::broadcast};
+use tonic::{
+    codec::CompressionEncoding,
+    transport::{server::TcpIncoming, Endpoint, Server},
+    Request, Response, Status, Streaming,
+};
+
+use agent::{
+    comms::{
+        forwarder::{spawn_forwarder, ForwardTarget, Spool},
+        tap::{frame, TapSources},
+        WrappedEvent,
+    },
+    util::{retry::RetryPolicy, Shutdown},
+};
+use common::wrap;
+
+/// What every incarnation of the collector stored, by `forward_seq`.
+#[derive(Default)]
+struct Stored {
+    events:   BTreeMap<u64, BaseEvent>,
+    /// Events received, duplicates included.
+    received: usize,
+}
+
+struct Collector {
+    stored: Arc<Mutex<Stored>>,
+    /// Stops answering, mid-stream, once this many events are stored.
+    stall_at: Option<usize>,
+}
+
+#[tonic::async_trait]
+impl EventCollector for Collector {
+    async fn stream_events(&self, request: Request<Streaming<BaseEvent>>) -> Result<Response<StreamAck>, Status> {
+        let mut events = request.into_inner();
+        let mut received = 0;
+        while let Some(ev) = events.message().await? {
+            received += 1;
+            let count = {
+                let mut stored = self.stored.lock().unwrap();
+                stored.received += 1;
+                stored.events.entry(ev.forward_seq).or_insert(ev);
+                stored.events.len()
+            };
+            if self.stall_at.is_some_and(|n| count >= n) {
+                std::future::pending::<()>().await;
+            }
+        }
+        Ok(Response::new(StreamAck { received }))
+    }
+}
+
+/// Serves a collector on `addr` from a runtime of its own, so dropping that
+/// runtime kills it with its connections.
+fn serve(addr: SocketAddr, collector: Collector) -> Runtime {
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        let service = EventCollectorServer::new(collector).accept_compressed(CompressionEncoding::Gzip);
+        tokio::spawn(Server::builder().add_service(service).serve_with_incoming(incoming));
+    });
+    rt
+}
+
+/// A local address nothing listens on.
+fn free_addr() -> SocketAddr {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+fn target(addr: SocketAddr, spool: &Path, types: &[&str]) -> ForwardTarget {
+    ForwardTarget {
+        endpoint:    Endpoint::from_shared(format!("http://{addr}")).unwrap(),
+        types:       types.iter().map(|t| t.to_string()).collect(),
+        batch_size:  10,
+        spool_path:  spool.to_owned(),
+        spool_bytes: 1 << 20,
+        retry:       RetryPolicy::new("forwarder", Duration::from_millis(10))
+            .max_delay(Duration::from_millis(100))
+            .attempt_timeout(Duration::from_secs(5)),
+        flush_every: Duration::from_millis(20),
+    }
+}
+
+fn process(pid: u32) -> WrappedEvent<ProcessEvent> {
+    wrap(Timestamp::default(), ProcessEvent { pid, ..ProcessEvent::default() })
+}
+
+fn connection(port: u32) -> WrappedEvent<NetworkEvent> {
+    wrap(Timestamp::default(), NetworkEvent { dst_ip: "10.0.0.1".into(), dst_port: port, ..NetworkEvent::default() })
+}
+
+/// Which event this is: `("process", pid)` or `("network", port)`.
+fn key(ev: &BaseEvent) -> (&'static str, u32) {
+    match &ev.payload {
+        Some(Payload::ProcessEvent(p)) => ("process", p.pid),
+        Some(Payload::NetworkEvent(n)) => ("network", n.dst_port),
+        other => panic!("not forwarded: {other:?}"),
+    }
+}
+
+fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !done() {
+        assert!(start.elapsed() < Duration::from_secs(20), "timed out waiting for {what}");
+        sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn a_collector_killed_mid_stream_gets_every_event_exactly_once() {
+    let dir = tempdir().unwrap();
+    let spool = dir.path().join("forward.spool");
+    let addr = free_addr();
+    let stored = Arc::new(Mutex::new(Stored::default()));
+    let first = serve(addr, Collector { stored: stored.clone(), stall_at: Some(15) });
+
+    let (process_tx, _) = broadcast::channel(256);
+    let (network_tx, _) = broadcast::channel(256);
+    let (file_tx, _) = broadcast::channel::<WrappedEvent<FileEvent>>(256);
+    let sources = TapSources {
+        process: Some(process_tx.clone()),
+        network: Some(network_tx.clone()),
+        file: Some(file_tx.clone()),
+        ..TapSources::default()
+    };
+    let rt = Runtime::new().unwrap();
+    let shutdown = Shutdown::new();
+    let forwarder =
+        spawn_forwarder(&rt, target(addr, &spool, &["process", "network"]), sources, shutdown.clone()).unwrap();
+
+    // File events are not in the include-list: their bus is not even read.
+    assert_eq!(file_tx.receiver_count(), 0);
+    for n in 1..=50 {
+        process_tx.send(process(n)).unwrap();
+        network_tx.send(connection(n)).unwrap();
+    }
+
+    // The collector stops answering in the middle of a batch and is killed.
+    wait_for("the collector to stall", || stored.lock().unwrap().events.len() >= 15);
+    drop(first);
+    let _second = serve(addr, Collector { stored: stored.clone(), stall_at: None });
+    wait_for("every event", || stored.lock().unwrap().events.len() >= 100);
+    wait_for("the spool to drain", || fs::metadata(&spool).unwrap().len() == 0);
+
+    let stored = stored.lock().unwrap();
+    assert_eq!(stored.events.keys().copied().collect::<Vec<_>>(), (1..=100).collect::<Vec<u64>>());
+    let mut keys: Vec<_> = stored.events.values().map(key).collect();
+    keys.sort();
+    let mut expected: Vec<_> = (1..=50).flat_map(|n| [("network", n), ("process", n)]).collect();
+    expected.sort();
+    assert_eq!(keys, expected, "each forwarded event once");
+    assert!(stored.received > 100, "the interrupted batch was sent again");
+    assert_eq!(fs::read(dir.path().join("forward.spool.acked")).unwrap(), 100u64.to_le_bytes());
+
+    shutdown.trigger();
+    rt.block_on(forwarder).unwrap();
+}
+
+#[test]
+fn events_spooled_while_the_collector_is_down_are_sent_by_the_next_run() {
+    let dir = tempdir().unwrap();
+    let spool = dir.path().join("forward.spool");
+    let addr = free_addr();
+    let (process_tx, _) = broadcast::channel(256);
+    let sources = || TapSources { process: Some(process_tx.clone()), ..TapSources::default() };
+    let rt = Runtime::new().unwrap();
+
+    let shutdown = Shutdown::new();
+    let forwarder = spawn_forwarder(&rt, target(addr, &spool, &["process"]), sources(), shutdown.clone()).unwrap();
+    let mut spooled = 0;
+    for pid in 1..=20 {
+        let mut ev = BaseEvent::from(process(pid));
+        ev.forward_seq = pid as u64;
+        spooled += frame(&ev).len() as u64;
+        process_tx.send(process(pid)).unwrap();
+    }
+    wait_for("the spool", || fs::metadata(&spool).unwrap().len() == spooled);
+    shutdown.trigger();
+    rt.block_on(forwarder).unwrap();
+
+    let stored = Arc::new(Mutex::new(Stored::default()));
+    let _collector = serve(addr, Collector { stored: stored.clone(), stall_at: None });
+    let shutdown = Shutdown::new();
+    let forwarder = spawn_forwarder(&rt, target(addr, &spool, &["process"]), sources(), shutdown.clone()).unwrap();
+    for pid in 21..=25 {
+        process_tx.send(process(pid)).unwrap();
+    }
+    wait_for("every event", || stored.lock().unwrap().events.len() >= 25);
+
+    let stored = stored.lock().unwrap();
+    let by_seq: Vec<_> = stored.events.iter().map(|(&seq, ev)| (seq, key(ev).1)).collect();
+    assert_eq!(by_seq, (1..=25).map(|n| (n as u64, n)).collect::<Vec<_>>());
+    assert_eq!(stored.received, 25);
+    shutdown.trigger();
+    rt.block_on(forwarder).unwrap();
+}
+
+#[test]
+fn the_spool_keeps_its_cap_acknowledgements_and_readable_frames() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("forward.spool");
+    let event = |pid| BaseEvent::from(process(pid));
+    let size = frame(&BaseEvent { forward_seq: 1, ..event(1) }).len() as u64;
+
+    let spool = Spool::open(&path, size * 3).unwrap();
+    for pid in 1..=4 {
+        assert_eq!(spool.push(event(pid), "process"), pid <= 3, "{pid}");
+    }
+    let batch = spool.peek(2).unwrap();
+    assert_eq!(batch.events.iter().map(|e| e.forward_seq).collect::<Vec<_>>(), [1, 2]);
+    spool.ack(&batch).unwrap();
+    assert_eq!((spool.pending(), spool.len()), (1, size));
+    // Room again for what was acknowledged.
+    assert!(spool.push(event(5), "process"));
+    drop(spool);
+
+    // A crash in the middle of a frame: the torn frame goes, the rest stays.
+    let whole = fs::metadata(&path).unwrap().len();
+    fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[0x20, 0x00, 0x00, 0x00, 0x08]).unwrap();
+    let spool = Spool::open(&path, size * 3).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().len(), whole);
+    let batch = spool.peek(10).unwrap();
+    assert_eq!(batch.events.iter().map(|e| e.forward_seq).collect::<Vec<_>>(), [3, 4]);
+    assert!(spool.push(event(6), "process"));
+    assert_eq!(spool.peek(10).unwrap().events.last().unwrap().forward_seq, 5);
+
+    spool.ack(&spool.peek(10).unwrap()).unwrap();
+    assert!(spool.is_empty());
+    assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+    drop(spool);
+    let spool = Spool::open(&path, size * 3).unwrap();
+    assert!(spool.push(event(7), "process"));
+    assert_eq!(spool.peek(1).unwrap().events[0].forward_seq, 6, "numbering survives an empty spool");
+}
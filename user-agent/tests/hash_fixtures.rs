@@ -0,0 +1,118 @@
+// tests/hash_fixtures.rs
+//
+// Digests recorded on x86_64; every target must reproduce them byte for byte.
+// Large files are hashed in chunks, never read whole.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs::{self, File},
+    io::{BufWriter, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use sha2::{Digest, Sha256};
+use tempfile::tempdir;
+
+use agent::{
+    config::model::HashAlgorithm,
+    scanner::{
+        cache::FileCacheEntry,
+        hash::{compute_file_hash, compute_sha256, hash_file, sha256_backend, Sha256Backend, CHUNK},
+    },
+};
+
+/// Records the largest single allocation of this test binary.
+struct Largest;
+
+static LARGEST: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Largest {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LARGEST.fetch_max(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LARGEST.fetch_max(new_size, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: Largest = Largest;
+
+const FIXTURES: [(&[u8], u64, &str); 3] = [
+    (b"", 0xef46db3751d8e999, "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+    (b"abc", 0x44bc2cf5ad770999, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+    (b"MZ\x90\x00gladix", 0xd4c0aa538f4dee7b, "50c6f97f6adae32c1a71c1377345271214f608957388c578514d171de57364ce"),
+];
+
+#[test]
+fn digests_match_x86_fixtures() {
+    let dir = tempdir().unwrap();
+    for (i, (data, xxh, sha)) in FIXTURES.iter().enumerate() {
+        let path = dir.path().join(format!("f{i}.bin"));
+        fs::write(&path, data).unwrap();
+        assert_eq!(compute_file_hash(&path).unwrap(), *xxh, "xxh64 of fixture {i}");
+        assert_eq!(hex::encode(compute_sha256(&path).unwrap()), *sha, "sha256 of fixture {i}");
+        let both = hash_file(&path, HashAlgorithm::Both).unwrap();
+        assert_eq!((both.xxh64, both.sha256.map(hex::encode)), (Some(*xxh), Some(sha.to_string())));
+        assert_eq!(hash_file(&path, HashAlgorithm::Xxh64).unwrap().sha256, None);
+    }
+}
+
+#[test]
+fn sha_backend_matches_architecture() {
+    let backend = sha256_backend();
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    assert_ne!(backend, Sha256Backend::ShaNi);
+    #[cfg(not(target_arch = "aarch64"))]
+    assert_ne!(backend, Sha256Backend::ArmSha2);
+    let _ = backend;
+}
+
+#[test]
+fn large_files_are_streamed() {
+    const SIZE: usize = 100 << 20;
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("large.bin");
+
+    // Pseudo-random content, hashed with sha2 directly while it is written.
+    let mut expected = Sha256::new();
+    let mut out = BufWriter::new(File::create(&path).unwrap());
+    let mut block = vec![0u8; 1 << 20];
+    let mut x = 0x9e37_79b9_7f4a_7c15_u64;
+    for _ in 0..SIZE / block.len() {
+        for b in block.iter_mut() {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            *b = x as u8;
+        }
+        expected.update(&block);
+        out.write_all(&block).unwrap();
+    }
+    out.into_inner().unwrap().sync_all().unwrap();
+    let expected: [u8; 32] = expected.finalize().into();
+    drop(block);
+
+    LARGEST.store(0, Ordering::Relaxed);
+    let digests = hash_file(&path, HashAlgorithm::Both).unwrap();
+    let largest = LARGEST.load(Ordering::Relaxed);
+    assert_eq!(digests.sha256, Some(expected));
+    assert!(digests.xxh64.is_some());
+    // Other tests allocate too, but nothing close to the file size.
+    assert!(largest < 4 * CHUNK, "largest allocation while hashing: {largest} bytes");
+}
+
+#[test]
+fn caches_without_sha256_still_load() {
+    let old: FileCacheEntry =
+        serde_json::from_str(r#"{"hash":1,"timestamp":2,"scan_result":"Processed","size":3}"#).unwrap();
+    assert_eq!(old.sha256, None);
+    // And entries without one are written as before.
+    assert!(!serde_json::to_string(&old).unwrap().contains("sha256"));
+}
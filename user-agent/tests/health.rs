@@ -0,0 +1,126 @@
+// tests/health.rs
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use anyhow::bail;
+use tempfile::tempdir;
+
+use agent::{
+    db::ops_journal::Journal,
+    health::{policy, spawn_watchdog, Component, ComponentState, Criticality, HealthRegistry, Startup},
+    util::RetryPolicy,
+};
+
+const OPTIONAL: [Component; 6] = [
+    Component::Scanner,
+    Component::Etw,
+    Component::Metrics,
+    Component::Grpc,
+    Component::Sinks,
+    Component::Detection,
+];
+
+/// Start routine that fails while `broken` is set and counts its calls.
+fn injectable(broken: Arc<AtomicBool>, calls: Arc<AtomicUsize>) -> impl FnMut() -> anyhow::Result<()> + Send + 'static {
+    move || {
+        calls.fetch_add(1, Ordering::SeqCst);
+        if broken.load(Ordering::SeqCst) {
+            bail!("injected failure");
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn matrix_marks_only_pipeline_core_as_critical() {
+    assert_eq!(policy(Component::RingConsumer).criticality, Criticality::Critical);
+    assert_eq!(policy(Component::DbWriter("process_events")).criticality, Criticality::Critical);
+    for c in OPTIONAL {
+        let p = policy(c);
+        assert_eq!(p.criticality, Criticality::Optional, "{c}");
+        assert!(p.retryable, "{c}");
+    }
+}
+
+#[test]
+fn each_optional_failure_keeps_the_agent_running() {
+    for failing in OPTIONAL {
+        let health = HealthRegistry::new();
+        let mut startup = Startup::new(health.clone())
+            .component(Component::RingConsumer, || Ok(()))
+            .component(Component::DbWriter("process_events"), || Ok(()));
+        for c in OPTIONAL {
+            startup = if c == failing {
+                startup.component(c, || bail!("injected failure"))
+            } else {
+                startup.component(c, || Ok(()))
+            };
+        }
+
+        let report = startup.run().unwrap_or_else(|c| panic!("{failing} must not be fatal: {c}"));
+        assert_eq!(report.retrying(), vec![failing]);
+        assert_eq!(health.degraded(), vec![failing]);
+        assert_eq!(health.state(Component::RingConsumer), Some(ComponentState::Healthy));
+    }
+}
+
+#[test]
+fn watchdog_recovers_component_once_failure_clears() {
+    let broken = Arc::new(AtomicBool::new(true));
+    let calls  = Arc::new(AtomicUsize::new(0));
+    let health = HealthRegistry::new();
+
+    let report = Startup::new(health.clone())
+        .component(Component::RingConsumer, || Ok(()))
+        .component(Component::Metrics, injectable(broken.clone(), calls.clone()))
+        .run()
+        .expect("metrics failure is not fatal");
+    assert_eq!(health.degraded(), vec![Component::Metrics]);
+
+    let handle = spawn_watchdog(report, RetryPolicy::new("watchdog", Duration::from_millis(20)), Journal::disabled())
+        .expect("watchdog needed");
+
+    // Still failing: attempts keep growing.
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while calls.load(Ordering::SeqCst) < 3 && Instant::now() < deadline {
+        sleep(Duration::from_millis(10));
+    }
+    match health.state(Component::Metrics) {
+        Some(ComponentState::Degraded { attempts, .. }) => assert!(attempts >= 2),
+        other => panic!("expected degraded, got {other:?}"),
+    }
+
+    broken.store(false, Ordering::SeqCst);
+    handle.join().unwrap();
+    assert!(health.is_healthy());
+    assert_eq!(health.state(Component::Metrics), Some(ComponentState::Healthy));
+}
+
+#[test]
+fn critical_failure_produces_crash_report() {
+    let health = HealthRegistry::new();
+    let crash = Startup::new(health.clone())
+        .component(Component::Metrics, || bail!("port in use"))
+        .component(Component::RingConsumer, || bail!("section missing"))
+        .component(Component::Scanner, || Ok(()))
+        .run()
+        .err()
+        .expect("ring consumer failure must abort");
+
+    assert_eq!(crash.component, Component::RingConsumer);
+    assert!(crash.error.contains("section missing"));
+    assert_eq!(crash.degraded, vec![Component::RingConsumer, Component::Metrics]);
+
+    let dir  = tempdir().unwrap();
+    let path = crash.write_to(dir.path()).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    assert_eq!(json["component"], "ring_consumer");
+    assert_eq!(json["degraded"].as_array().unwrap().len(), 2);
+}
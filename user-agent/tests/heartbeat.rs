@@ -0,0 +1,79 @@
+// tests/heartbeat.rs
+//
+// The heartbeat writes an `agent_status` row per interval with what was
+// reported into its `Stats` since the row before, and the uptime grows from
+// row to row. The driver version from the handshake is in every row.
+
+use std::{fs, thread, time::Duration};
+use tempfile::tempdir;
+use tokio::runtime::Runtime;
+use rusqlite::Connection;
+use shared::constants::{protocol_version, VersionInfo};
+
+use agent::{
+    config::model::HeartbeatConfig,
+    db::agent_status::{latest_status, recent_status},
+    heartbeat::{spawn_heartbeat, Stats},
+    util::Shutdown,
+};
+
+#[test]
+fn heartbeats_report_counts_since_the_previous_one() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("telemetry.db");
+    fs::write(&db_path, []).unwrap();
+    let rt = Runtime::new().unwrap();
+    let shutdown = Shutdown::new();
+
+    let stats = Stats::new();
+    stats.add_dropped(7);
+    stats.add_processed("process_events", 40);
+    stats.add_processed("process_events", 2);
+    stats.add_processed("fs_events", 5);
+    stats.set_error("cannot flush 3 rows: database is locked");
+    let driver = VersionInfo {
+        protocol: protocol_version(2, 1),
+        major: 0,
+        minor: 4,
+        patch: 2,
+        build_time: 1_760_000_000,
+        ..VersionInfo::default()
+    };
+    stats.set_driver(driver);
+
+    let cfg = HeartbeatConfig { enabled: true, interval_ms: 50 };
+    let task = spawn_heartbeat(&rt, db_path.clone(), &cfg, stats.clone(), &shutdown).unwrap();
+    thread::sleep(Duration::from_millis(300));
+    shutdown.trigger();
+    rt.block_on(task).unwrap();
+
+    let conn = Connection::open(&db_path).unwrap();
+    let mut rows = recent_status(&conn, 100).unwrap();
+    rows.reverse();
+    assert!(rows.len() >= 2, "{} heartbeats", rows.len());
+    assert!(rows.windows(2).all(|w| w[1].uptime_secs > w[0].uptime_secs), "{rows:?}");
+    assert!(rows.iter().all(|r| r.version == env!("CARGO_PKG_VERSION") && r.driver == Some(driver)));
+    // Taken before the row is written: the first sees the empty file.
+    assert_eq!(rows[0].db_size_bytes, 0);
+    assert!(rows[1].db_size_bytes > 0);
+
+    let first = &rows[0];
+    assert_eq!(first.ring_dropped, 7);
+    assert_eq!(first.processed.get("process_events"), Some(&42));
+    assert_eq!(first.processed.get("fs_events"), Some(&5));
+    assert_eq!(first.last_error.as_deref(), Some("cannot flush 3 rows: database is locked"));
+
+    let second = &rows[1];
+    assert_eq!((second.ring_dropped, second.processed.get("process_events"), second.last_error.as_deref()), (0, Some(&0), None));
+    assert_eq!(latest_status(&conn).unwrap().as_ref(), rows.last());
+}
+
+#[test]
+fn disabled_heartbeat_writes_nothing() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("telemetry.db");
+    let rt = Runtime::new().unwrap();
+    let cfg = HeartbeatConfig { enabled: false, ..HeartbeatConfig::default() };
+    assert!(spawn_heartbeat(&rt, db_path.clone(), &cfg, Stats::new(), &Shutdown::new()).is_none());
+    assert_eq!(latest_status(&Connection::open(&db_path).unwrap()).unwrap(), None);
+}
@@ -0,0 +1,145 @@
+// tests/idle.rs
+
+use std::{
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use agent::{
+    config::model::SchedulingConfig,
+    idle::{decide, spawn_monitor, Decision, IdleGate, IdleProvider, IdleState, Task},
+    util::Shutdown,
+};
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+fn active() -> IdleState {
+    IdleState { idle_for: Duration::from_secs(10), locked: false, on_battery: false }
+}
+
+fn idle() -> IdleState {
+    IdleState { idle_for: Duration::from_secs(600), ..active() }
+}
+
+fn runs(d: Decision) -> bool {
+    matches!(d, Decision::Run(_))
+}
+
+/// Replays states sent by the test, repeating the last one.
+struct MockIdle {
+    rx:   mpsc::Receiver<IdleState>,
+    last: IdleState,
+}
+
+impl IdleProvider for MockIdle {
+    fn sample(&mut self) -> IdleState {
+        if let Ok(s) = self.rx.try_recv() {
+            self.last = s;
+        }
+        self.last
+    }
+}
+
+#[test]
+fn active_user_defers_until_idle_or_lock() {
+    let cfg = SchedulingConfig::default();
+    assert_eq!(
+        decide(&cfg, Task::Scanner, &active(), Duration::ZERO),
+        Decision::Defer("user active 10s ago".into())
+    );
+    assert!(runs(decide(&cfg, Task::Scanner, &idle(), Duration::ZERO)));
+    assert!(runs(decide(&cfg, Task::Scanner, &IdleState { locked: true, ..active() }, Duration::ZERO)));
+    assert_eq!(
+        decide(&cfg, Task::Scanner, &IdleState { on_battery: true, ..idle() }, Duration::ZERO),
+        Decision::Defer("on battery".into())
+    );
+    assert!(runs(decide(&cfg, Task::Scanner, &IdleState::UNKNOWN, Duration::ZERO)));
+}
+
+#[test]
+fn max_deferral_forces_a_run() {
+    let cfg = SchedulingConfig { max_deferral_hours: 2.0, ..SchedulingConfig::default() };
+    assert!(!runs(decide(&cfg, Task::Scanner, &active(), HOUR)));
+    assert_eq!(
+        decide(&cfg, Task::Scanner, &active(), 2 * HOUR),
+        Decision::Run("deferred for 2h, the maximum".into())
+    );
+}
+
+#[test]
+fn opt_outs_never_defer() {
+    let mut cfg = SchedulingConfig::default();
+    cfg.tasks.wal_checkpoint = false;
+    assert!(runs(decide(&cfg, Task::WalCheckpoint, &active(), Duration::ZERO)));
+    assert!(!runs(decide(&cfg, Task::Reprocess, &active(), Duration::ZERO)));
+
+    cfg.respect_user_activity = false;
+    assert!(runs(decide(&cfg, Task::Reprocess, &active(), Duration::ZERO)));
+}
+
+#[test]
+fn deferred_task_resumes_as_soon_as_the_user_goes_idle() {
+    let gate = IdleGate::new(SchedulingConfig::default());
+    let shutdown = Shutdown::new();
+    let (tx, rx) = mpsc::channel();
+    tx.send(active()).unwrap();
+    let monitor = spawn_monitor(MockIdle { rx, last: IdleState::UNKNOWN }, gate.clone(), Duration::from_millis(5), shutdown.clone());
+    while gate.state() != active() {
+        thread::yield_now();
+    }
+
+    let waiter = {
+        let (gate, shutdown) = (gate.clone(), shutdown.clone());
+        thread::spawn(move || gate.wait_blocking(Task::Scanner, &shutdown))
+    };
+    while gate.deferrals().is_empty() {
+        thread::yield_now();
+    }
+    let deferred = &gate.deferrals()[0];
+    assert_eq!((deferred.task, deferred.reason.as_str()), (Task::Scanner, "user active 10s ago"));
+    assert_eq!(deferred.until - deferred.since, 4 * HOUR);
+
+    tx.send(idle()).unwrap();
+    assert!(waiter.join().unwrap());
+    assert!(gate.deferrals().is_empty());
+
+    shutdown.trigger();
+    monitor.join().unwrap();
+}
+
+#[test]
+fn shutdown_releases_deferred_tasks() {
+    let gate = IdleGate::new(SchedulingConfig::default());
+    gate.update(active());
+    let shutdown = Shutdown::new();
+
+    let waiter = {
+        let (gate, shutdown) = (gate.clone(), shutdown.clone());
+        thread::spawn(move || gate.wait_blocking(Task::Scanner, &shutdown))
+    };
+    while gate.deferrals().is_empty() {
+        thread::yield_now();
+    }
+    shutdown.trigger();
+    assert!(!waiter.join().unwrap());
+
+    let rt = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+    assert!(!rt.block_on(gate.wait(Task::WalCheckpoint, &shutdown)));
+}
+
+#[tokio::test]
+async fn async_wait_wakes_on_state_change() {
+    let gate = IdleGate::new(SchedulingConfig::default());
+    gate.update(active());
+    let shutdown = Shutdown::new();
+    let waiter = {
+        let (gate, shutdown) = (gate.clone(), shutdown.clone());
+        tokio::spawn(async move { gate.wait(Task::CompressionBackfill, &shutdown).await })
+    };
+    while gate.deferrals().is_empty() {
+        tokio::task::yield_now().await;
+    }
+    gate.update(IdleState { locked: true, ..active() });
+    assert!(waiter.await.unwrap());
+}
@@ -0,0 +1,58 @@
+// tests/instance_guard.rs
+//
+// A second agent is refused while the first holds the guard and gets it once
+// the first lets go; the lock file names the holder while it runs. A lock
+// file left by an exited process is only taken over when asked to.
+
+use std::{fs, path::Path, process};
+use tempfile::tempdir;
+
+use agent::util::{
+    instance::{lock_owner, lock_path},
+    InstanceError, InstanceGuard,
+};
+
+/// Far above any PID the system hands out.
+const DEAD_PID: u32 = 4_000_000_000;
+
+#[test]
+fn lock_file_sits_next_to_the_database() {
+    assert_eq!(lock_path(Path::new("C:/gladix/telemetry.db")), Path::new("C:/gladix/telemetry.db.lock"));
+}
+
+#[test]
+fn second_instance_is_refused_until_the_first_releases() {
+    let dir = tempdir().unwrap();
+    let lock = dir.path().join("telemetry.db.lock");
+    let name = "Local\\GladixTestTwice";
+
+    let first = InstanceGuard::acquire(name, &lock, false).unwrap();
+    assert_eq!(lock_owner(&lock), Some(process::id()));
+
+    match InstanceGuard::acquire(name, &lock, true) {
+        Err(InstanceError::Running { owner, .. }) => assert_eq!(owner, Some(process::id())),
+        other => panic!("second acquisition: {other:?}"),
+    }
+
+    drop(first);
+    assert!(!lock.exists());
+    let second = InstanceGuard::acquire(name, &lock, false).unwrap();
+    assert_eq!(second.lock(), lock);
+}
+
+#[test]
+fn stale_lock_needs_force() {
+    let dir = tempdir().unwrap();
+    let lock = dir.path().join("telemetry.db.lock");
+    let name = "Local\\GladixTestStale";
+    fs::write(&lock, DEAD_PID.to_string()).unwrap();
+
+    match InstanceGuard::acquire(name, &lock, false) {
+        Err(e @ InstanceError::Stale { pid: DEAD_PID, .. }) => assert!(e.to_string().contains("--force")),
+        other => panic!("stale lock without force: {other:?}"),
+    }
+    assert_eq!(lock_owner(&lock), Some(DEAD_PID));
+
+    let _guard = InstanceGuard::acquire(name, &lock, true).unwrap();
+    assert_eq!(lock_owner(&lock), Some(process::id()));
+}
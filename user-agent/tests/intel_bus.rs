@@ -0,0 +1,131 @@
+// tests/intel_bus.rs
+//
+// Intel buses with a replay buffer: a subscriber that attaches after events
+// flowed gets the buffered ones, then the live ones, in order and once
+// each; the buffer is bounded by count and age.
+
+mod common;
+
+use std::time::{Duration, Instant};
+use tokio::{runtime::Runtime, sync::broadcast};
+use shared::events::ProcessEvent;
+
+use agent::{
+    comms::{
+        intel_bus::{RecentBuffer, Subscription, TokioBuses},
+        WrappedEvent,
+    },
+    config::model::CommunicationsConfig,
+};
+use common::{seconds, wrap};
+
+fn event(seq: u64) -> WrappedEvent<ProcessEvent> {
+    WrappedEvent { seq: Some(seq), ..wrap(seconds(seq as i64), ProcessEvent { pid: seq as u32, ..ProcessEvent::default() }) }
+}
+
+fn seqs(events: impl IntoIterator<Item = WrappedEvent<ProcessEvent>>) -> Vec<u64> {
+    events.into_iter().map(|ev| ev.seq.unwrap()).collect()
+}
+
+/// Receives `n` events from `sub`, failing after a second without one.
+async fn take(sub: &mut Subscription<ProcessEvent>, n: usize) -> Vec<u64> {
+    let mut got = Vec::new();
+    while got.len() < n {
+        let ev = tokio::time::timeout(Duration::from_secs(1), sub.recv()).await.expect("no event").expect("bus closed");
+        got.push(ev.seq.unwrap());
+    }
+    got
+}
+
+#[test]
+fn a_late_subscriber_gets_the_buffer_then_live_events() {
+    let rt = Runtime::new().unwrap();
+    let (input, _) = broadcast::channel(1_024);
+    let cfg = CommunicationsConfig { intel_replay_events: 64, ..CommunicationsConfig::default() };
+    let bus = TokioBuses::spawn(&rt, "process", &input, 1_024, &cfg);
+    let mut early = bus.subscribe();
+
+    rt.block_on(async {
+        for seq in 1..=100 {
+            input.send(event(seq)).unwrap();
+        }
+        // The early subscriber has seen them all, so the buffer holds them.
+        assert_eq!(take(&mut early, 100).await, (1..=100).collect::<Vec<_>>());
+        assert_eq!(bus.recent_len(), 64);
+
+        let mut late = bus.subscribe_with_replay();
+        let mut live_only = bus.subscribe();
+        for seq in 101..=120 {
+            input.send(event(seq)).unwrap();
+        }
+        assert_eq!(take(&mut late, 84).await, (37..=120).collect::<Vec<_>>());
+        assert_eq!(take(&mut live_only, 20).await, (101..=120).collect::<Vec<_>>());
+
+        // The producers are gone: the bus closes once the buffer is read.
+        drop(input);
+        assert!(late.recv().await.is_none());
+        let mut after = bus.subscribe_with_replay();
+        assert_eq!(take(&mut after, 64).await, (57..=120).collect::<Vec<_>>());
+        assert!(after.recv().await.is_none());
+    });
+}
+
+#[test]
+fn live_events_already_replayed_are_skipped() {
+    let rt = Runtime::new().unwrap();
+    let (input, _) = broadcast::channel(64);
+    let bus = TokioBuses::spawn(&rt, "process", &input, 64, &CommunicationsConfig::default());
+
+    rt.block_on(async {
+        let mut early = bus.subscribe();
+        for seq in 1..=3 {
+            input.send(event(seq)).unwrap();
+        }
+        take(&mut early, 3).await;
+        let mut late = bus.subscribe_with_replay();
+        // A producer sending 3 again, then the next event; then a restarted
+        // sequence, which is not taken for a repeat.
+        for seq in [3, 4, 1] {
+            input.send(event(seq)).unwrap();
+        }
+        assert_eq!(take(&mut late, 5).await, [1, 2, 3, 4, 1]);
+    });
+}
+
+#[test]
+fn a_lagging_subscriber_skips_what_it_lost() {
+    let rt = Runtime::new().unwrap();
+    let (tx, rx) = broadcast::channel(4);
+    let mut sub = Subscription::live("process", rx);
+    for seq in 1..=10 {
+        tx.send(event(seq)).unwrap();
+    }
+    drop(tx);
+    let got = rt.block_on(async {
+        let mut got = Vec::new();
+        while let Some(ev) = sub.recv().await {
+            got.push(ev.seq.unwrap());
+        }
+        got
+    });
+    assert_eq!(got, [7, 8, 9, 10]);
+}
+
+#[test]
+fn the_buffer_is_bounded_by_count_and_age() {
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut buffer = RecentBuffer::new(3, Duration::from_secs(60));
+    for seq in 1..=4 {
+        buffer.push(event(seq), at(seq * 10));
+    }
+    assert_eq!(seqs(buffer.events(at(40))), [2, 3, 4]);
+    // 2 was kept at 20s, 3 at 30s.
+    assert_eq!(seqs(buffer.events(at(85))), [3, 4]);
+    assert_eq!(seqs(buffer.events(at(101))), Vec::<u64>::new());
+    assert!(buffer.is_empty());
+
+    let mut none = RecentBuffer::new(0, Duration::from_secs(60));
+    none.push(event(1), start);
+    assert!(none.is_empty());
+}
@@ -0,0 +1,195 @@
+// tests/intel_context.rs
+
+mod common;
+
+use std::{path::PathBuf, time::Duration};
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::broadcast};
+use shared::events::{FileEvent, ProcessEvent};
+
+use agent::{
+    actions::Actions,
+    comms::WrappedEvent,
+    config::load,
+    db::{connection::init_database, event_types::FS_EVENTS, schema_registry::ensure_for},
+    intel::{
+        capture_context, gather_context, insert_alert, load_alert, load_context, render_context, spawn_feeder,
+        Alert, AlertSink, ContextWindow, EventKind, RecentConfig, RecentEvent, RecentEvents, Severity,
+        Trigger,
+    },
+};
+use common::{seconds, wrap};
+
+const SEC: i64 = 1_000_000;
+
+fn ev(kind: EventKind, pid: u32, ts: i64, uid: i64) -> RecentEvent {
+    RecentEvent { kind, pid, ts, event_uid: uid }
+}
+
+fn window(per_type: usize, secs: u64) -> ContextWindow {
+    ContextWindow { per_type, window: Duration::from_secs(secs) }
+}
+
+/// Scripted stream: pid 200 (child of 100) writes a file every second, its
+/// parent spawns processes, and unrelated pid 999 is noisy throughout.
+fn scripted(recent: &RecentEvents) {
+    for i in 0..60 {
+        let ts = 1_000 * SEC + i * SEC;
+        recent.record(ev(EventKind::File, 200, ts, 10_000 + i));
+        recent.record(ev(EventKind::File, 999, ts, 90_000 + i));
+        if i % 10 == 0 {
+            recent.record(ev(EventKind::Process, 100, ts, 20_000 + i));
+        }
+    }
+}
+
+#[test]
+fn context_is_bounded_around_trigger() {
+    let recent = RecentEvents::new(RecentConfig::default());
+    scripted(&recent);
+
+    let trigger = Trigger { pid: 200, ppid: Some(100), ts: 1_030 * SEC };
+    let refs = gather_context(&recent, None, &trigger, &window(5, 10)).unwrap();
+
+    let files: Vec<_> = refs.iter().filter(|r| r.table == "fs_events").collect();
+    assert_eq!(files.len(), 5);
+    assert!(files.iter().all(|r| r.pid == 200 && (r.ts - trigger.ts).abs() <= 2 * SEC));
+
+    let procs: Vec<_> = refs.iter().filter(|r| r.table == "process_events").collect();
+    assert_eq!(procs.iter().map(|r| r.event_uid).collect::<Vec<_>>(), vec![20_020, 20_030, 20_040]);
+
+    assert!(refs.iter().all(|r| r.pid != 999), "unrelated pid leaked into context");
+    assert!(refs.windows(2).all(|w| w[0].ts <= w[1].ts), "not chronological");
+}
+
+#[test]
+fn cache_respects_memory_bounds() {
+    let recent = RecentEvents::new(RecentConfig { per_pid: 8, max_pids: 4 });
+    for pid in 0..10 {
+        for i in 0..20 {
+            recent.record(ev(EventKind::Etw, pid, i, i64::from(pid) * 100 + i));
+        }
+    }
+    assert_eq!(recent.buffers(), 4);
+    assert_eq!(recent.len(), 32);
+    // First pids were evicted, the latest keeps only its newest entries.
+    assert!(recent.window(EventKind::Etw, 0, 0, 100).is_empty());
+    assert_eq!(recent.oldest(EventKind::Etw, 9), Some(12));
+}
+
+#[test]
+fn older_events_come_from_database() {
+    let dir = tempdir().unwrap();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut cfg = load(&root.join("config.toml")).unwrap().database;
+    cfg.path = "telemetry.db".into();
+    let conn = init_database(dir.path(), &cfg).unwrap();
+    ensure_for(&conn, &FS_EVENTS.schema).unwrap();
+
+    // Already flushed and evicted from memory.
+    for i in 0..5 {
+        conn.execute(
+            "INSERT INTO fs_events (ts, op, path, pid, event_uid) VALUES (?1, 'CREATE', 'x', 200, ?2)",
+            (1_000 * SEC + i * SEC, 500 + i),
+        ).unwrap();
+    }
+    let recent = RecentEvents::new(RecentConfig::default());
+    recent.record(ev(EventKind::File, 200, 1_006 * SEC, 506));
+
+    let trigger = Trigger { pid: 200, ppid: None, ts: 1_006 * SEC };
+    let refs = gather_context(&recent, Some(&conn), &trigger, &window(10, 10)).unwrap();
+    let uids: Vec<_> = refs.iter().map(|r| r.event_uid).collect();
+    assert_eq!(uids, vec![500, 501, 502, 503, 504, 506]);
+}
+
+#[test]
+fn alert_gets_context_from_live_bus() {
+    let dir = tempdir().unwrap();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut cfg = load(&root.join("config.toml")).unwrap().database;
+    cfg.path = "telemetry.db".into();
+    let conn = init_database(dir.path(), &cfg).unwrap();
+    let db_file = dir.path().join("telemetry.db");
+
+    let rt = Runtime::new().unwrap();
+    let recent = RecentEvents::new(RecentConfig::default());
+    let (proc_tx, _) = broadcast::channel::<WrappedEvent<ProcessEvent>>(64);
+    let (file_tx, _) = broadcast::channel::<WrappedEvent<FileEvent>>(64);
+    spawn_feeder(&rt, proc_tx.subscribe().into(), recent.clone(), EventKind::Process);
+    spawn_feeder(&rt, file_tx.subscribe().into(), recent.clone(), EventKind::File);
+
+    let spawn = wrap(seconds(2_000), ProcessEvent { pid: 300, ppid: 4, image_path: "evil.exe".into(), ..ProcessEvent::default() });
+    let write = wrap(seconds(2_001), FileEvent { pid: 300, path: "C:\\x".into(), ..Default::default() });
+    let (spawn_uid, write_uid) = (spawn.event_uid(), write.event_uid());
+    assert!(proc_tx.send(spawn).is_ok());
+    assert!(file_tx.send(write).is_ok());
+    while recent.len() < 2 {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    let alert = Alert {
+        ts: 2_001 * SEC,
+        rule_id: "test.rule".into(),
+        severity: Severity::High,
+        pid: 300,
+        ppid: Some(4),
+        message: "scripted".into(),
+        file: None,
+    };
+    let id = insert_alert(&conn, &alert).unwrap();
+    assert!(load_context(&conn, id).unwrap().is_empty());
+
+    let n = rt
+        .block_on(async { capture_context(db_file, recent, id, &alert, window(20, 30)).await })
+        .unwrap()
+        .unwrap();
+    assert_eq!(n, 2);
+
+    let refs = load_context(&Connection::open(dir.path().join("telemetry.db")).unwrap(), id).unwrap();
+    assert_eq!(refs.iter().map(|r| r.event_uid).collect::<Vec<_>>(), vec![spawn_uid, write_uid]);
+    let lines = render_context(&conn, id).unwrap();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("process_events") && lines[1].contains("fs_events"), "{lines:?}");
+}
+
+#[test]
+fn stored_alerts_get_their_context() {
+    let dir = tempdir().unwrap();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut cfg = load(&root.join("config.toml")).unwrap().database;
+    cfg.path = "telemetry.db".into();
+    let conn = init_database(dir.path(), &cfg).unwrap();
+
+    let rt = Runtime::new().unwrap();
+    let recent = RecentEvents::new(RecentConfig::default());
+    let spawn = wrap(seconds(3_000), ProcessEvent { pid: 400, ppid: 4, image_path: "evil.exe".into(), ..ProcessEvent::default() });
+    recent.record(RecentEvent { kind: EventKind::Process, pid: 400, ts: spawn.ts_micros(), event_uid: spawn.event_uid() });
+    let sink = AlertSink::new(dir.path().join("telemetry.db"), Actions::disabled())
+        .with_recent(recent)
+        .with_window(window(20, 30));
+
+    let alert = Alert {
+        ts: 3_000 * SEC,
+        rule_id: "test.rule".into(),
+        severity: Severity::Critical,
+        pid: 400,
+        ppid: Some(4),
+        message: "stored".into(),
+        file: None,
+    };
+    rt.block_on(sink.store(alert));
+    let id: i64 = conn.query_row("SELECT id FROM alerts WHERE rule_id = 'test.rule'", [], |r| r.get(0)).unwrap();
+    let stored = load_alert(&conn, id).unwrap().unwrap();
+    assert_eq!((stored.severity, stored.pid, stored.message.as_str()), (Severity::Critical, 400, "stored"));
+
+    // Captured after the store returns.
+    for _ in 0..200 {
+        if !load_context(&conn, id).unwrap().is_empty() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let refs = load_context(&conn, id).unwrap();
+    assert_eq!(refs.iter().map(|r| r.event_uid).collect::<Vec<_>>(), vec![spawn.event_uid()]);
+}
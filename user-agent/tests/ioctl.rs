@@ -0,0 +1,80 @@
+// tests/ioctl.rs
+//
+// The `live` tests need the driver loaded:
+//   cargo test --features driver-tests --test ioctl
+
+#[cfg(any(not(windows), feature = "driver-tests"))]
+use agent::comms::ioctl::Driver;
+use agent::comms::ioctl::{compatibility, ensure_compatible, Compatibility};
+use shared::constants::{protocol_version, VersionInfo, DRIVER_PROTOCOL_VERSION};
+
+#[test]
+fn only_a_different_major_is_incompatible() {
+    use Compatibility::*;
+    let v = protocol_version;
+    let matrix = [
+        (v(2, 0), v(2, 0), Same),
+        (v(2, 0), v(2, 3), MinorDiffers),
+        (v(2, 3), v(2, 0), MinorDiffers),
+        (v(2, 0), v(1, 0), MajorDiffers),
+        (v(2, 0), v(3, 0), MajorDiffers),
+        (v(2, 5), v(3, 5), MajorDiffers),
+        // Drivers from before the major/minor split reply 1.
+        (v(2, 0), 1, MajorDiffers),
+        (v(0, 1), 1, Same),
+    ];
+    for (agent, driver, expected) in matrix {
+        assert_eq!(compatibility(agent, driver), expected, "agent {agent:#x}, driver {driver:#x}");
+    }
+
+    let driver = |protocol| VersionInfo { protocol, major: 0, minor: 3, patch: 7, ..VersionInfo::default() };
+    assert_eq!(ensure_compatible(&driver(DRIVER_PROTOCOL_VERSION)).unwrap(), Same);
+    assert_eq!(ensure_compatible(&driver(DRIVER_PROTOCOL_VERSION + 1)).unwrap(), MinorDiffers);
+    // Drivers from before the drop reasons.
+    assert_eq!(ensure_compatible(&driver(protocol_version(2, 0))).unwrap(), MinorDiffers);
+    let err = ensure_compatible(&driver(1)).unwrap_err().to_string();
+    assert!(err.starts_with("driver 0.3.7 speaks protocol 0.1, agent "), "{err}");
+    assert!(err.contains(&format!("{} speaks 2.1", env!("CARGO_PKG_VERSION"))), "{err}");
+}
+
+#[cfg(not(windows))]
+#[test]
+fn opening_the_driver_is_unsupported_off_windows() {
+    assert_eq!(Driver::open().unwrap_err().kind(), std::io::ErrorKind::Unsupported);
+}
+
+#[cfg(feature = "driver-tests")]
+mod live {
+    use super::*;
+
+    fn driver() -> Driver {
+        Driver::open().expect("driver-tests needs the Gladix driver loaded")
+    }
+
+    #[test]
+    fn driver_answers_ping_and_version() {
+        let d = driver();
+        d.ping().unwrap();
+        assert_eq!(compatibility(DRIVER_PROTOCOL_VERSION, d.version().unwrap().protocol), Compatibility::Same);
+    }
+
+    #[test]
+    fn ring_stats_are_consistent_or_not_ready() {
+        match driver().ring_stats() {
+            Ok(stats) => assert!(stats.tail <= stats.head && stats.head - stats.tail <= stats.size as u64, "{stats:?}"),
+            // ERROR_NOT_READY: no ring allocated yet.
+            Err(e) => assert_eq!(e.raw_os_error(), Some(21), "{e}"),
+        }
+    }
+
+    #[test]
+    fn unknown_codes_and_short_buffers_are_rejected() {
+        let d = driver();
+        // ERROR_INVALID_FUNCTION from STATUS_INVALID_DEVICE_REQUEST.
+        let err = d.call(shared::constants::ctl_code(0x22, 0xfff, 0, 0), &[], &mut [0; 8]).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(1), "{err}");
+        // ERROR_INSUFFICIENT_BUFFER from STATUS_BUFFER_TOO_SMALL.
+        let err = d.call(shared::constants::IOCTL_GLADIX_GET_VERSION, &[], &mut [0; 4]).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(122), "{err}");
+    }
+}
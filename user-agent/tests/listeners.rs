@@ -3,9 +3,8 @@
 use tokio::time::timeout;
 use std::{
     fs::{File, OpenOptions},
-    mem::size_of,
-    sync::{Arc, atomic::{AtomicUsize, Ordering}},
-    time::{Duration},
+    sync::{Arc, atomic::Ordering},
+    time::{Duration, SystemTime},
 };
 use std::path::PathBuf;
 use tempfile::NamedTempFile;
@@ -13,37 +12,47 @@ use memmap2::MmapOptions;
 use prost::Message;
 use tokio::{runtime::Runtime, sync::{mpsc, broadcast}};
 use rusqlite::Connection;
+use metrics_exporter_prometheus::PrometheusBuilder;
 
 
 use agent::config::{load, model::Config as AppConfig};
 use agent::db::{connection::{init_database, db_path}, spawn_writer};
 use agent::comms::{
     WrappedEvent,
-    memory_ring::MemoryRing,
-    listeners::{Buses, RingListener, Listener},
+    memory_ring::{DropMonitor, MemoryRing},
+    listeners::{count_truncated, Buses, RingListener, Listener},
 };
-use shared::events::{ProcessEvent, NetworkEvent, network_event::Direction};
+use agent::util::Shutdown;
+use shared::events::{
+    FileEvent, ImageLoadEvent, ObjectOpEvent, ProcessEvent, NetworkEvent, file_event::Operation,
+    network_event::Direction, object_op_event::Operation as ObjectOperation,
+};
+use shared::constants::{PROCESS_CMDLINE_MAX, TRUNCATED_CMDLINE, TRUNCATED_PARENT_IMAGE_PATH};
+use shared::ring::{self, RingHeader, RingStats};
 /// Simula que un driver escribe **solo** el payload serializado en el ring.
 fn push_raw_event(file: &File, buf: &[u8]) {
-    let header_bytes = 2 * size_of::<AtomicUsize>();
-    let data_and_len = 4 + buf.len();
-    let pad = (8 - (data_and_len % 8)) % 8;
-    let record_size = data_and_len + pad;
+    push_event_at(file, 0, buf);
+}
+
+/// Como `push_raw_event`, con la hora `ts` (µs desde 1970) en el frame.
+fn push_event_at(file: &File, ts: u64, buf: &[u8]) {
+    let header_bytes = ring::HEADER_SIZE;
+    let record_size = ring::frame_len(buf.len());
     let buf_size = record_size * 2;
     let total_size = header_bytes + buf_size;
 
     file.set_len(total_size as u64).unwrap();
     let mut mmap = unsafe { MmapOptions::new().map_mut(file).unwrap() };
 
-    // length prefix + payload
-    let off = header_bytes;
-    mmap[off..off+4].copy_from_slice(&(buf.len() as u32).to_le_bytes());
-    mmap[off+4..off+4+buf.len()].copy_from_slice(buf);
+    // magic + longitud + CRC + número + hora (0: sin hora) + payload
+    ring::write_frame(&mut mmap[header_bytes..], 1, ts, buf).unwrap();
 
     // tail = record_size, head = 0
-    let ptr = mmap.as_mut_ptr() as *mut AtomicUsize;
-    let tail_ptr = unsafe { ptr.add(1) };
-    unsafe { (*tail_ptr).store(record_size, Ordering::Release) };
+    let header = mmap.as_mut_ptr() as *mut RingHeader;
+    unsafe {
+        header.write(RingHeader::new());
+        (*header).tail.store(record_size as u64, Ordering::Release);
+    }
 
     mmap.flush().unwrap();
 }
@@ -60,6 +69,7 @@ async fn test_process_event_listener_reads_and_forwards() {
         ppid: 1,
         image_path: "C:\\foo.exe".to_string(),
         cmdline: "foo".to_string(),
+        ..ProcessEvent::default()
     };
     let mut buf = Vec::new();
     proc.encode(&mut buf).unwrap();
@@ -75,9 +85,9 @@ async fn test_process_event_listener_reads_and_forwards() {
     let (db_tx, mut db_rx) = mpsc::channel::<WrappedEvent<ProcessEvent>>(8);
     let (intel_tx, _)      = broadcast::channel::<WrappedEvent<ProcessEvent>>(8);
     let mut intel_rx       = intel_tx.subscribe();
-    let buses = Buses::<ProcessEvent> { db_tx, intel_tx };
+    let buses = Buses::<ProcessEvent> { db_tx: db_tx.into(), intel_tx };
 
-    listener.spawn(buses);
+    listener.spawn(buses, &Shutdown::new());
 
     // 6) Verificamos que lleguen los wrapped.payload
     let got_db = timeout(Duration::from_secs(1), db_rx.recv())
@@ -91,6 +101,36 @@ async fn test_process_event_listener_reads_and_forwards() {
     assert_eq!(got_intel.payload, proc);
 }
 
+/// Events carry the time the driver wrote in the frame, however late they
+/// are read; only frames without one are stamped at read time.
+#[tokio::test]
+async fn events_keep_the_driver_timestamp() {
+    async fn read_at(ts: u64) -> WrappedEvent<ProcessEvent> {
+        let tmp  = NamedTempFile::new().unwrap();
+        let file = OpenOptions::new().read(true).write(true).open(tmp.path()).unwrap();
+        push_event_at(&file, ts, &ProcessEvent { pid: 7, ..ProcessEvent::default() }.encode_to_vec());
+
+        let listener = Arc::new(RingListener::new("process", MemoryRing::open(tmp.path()).unwrap(), "SENSOR"));
+        let (db_tx, mut db_rx) = mpsc::channel::<WrappedEvent<ProcessEvent>>(8);
+        let (intel_tx, _)      = broadcast::channel::<WrappedEvent<ProcessEvent>>(8);
+        listener.spawn(Buses::<ProcessEvent> { db_tx: db_tx.into(), intel_tx }, &Shutdown::new());
+        timeout(Duration::from_secs(1), db_rx.recv())
+            .await.expect("timeout waiting for db")
+            .expect("db channel closed")
+    }
+
+    // 2024-05-01T10:00:00.123456Z
+    let written = 1_714_557_600_123_456;
+    let got = read_at(written).await;
+    assert_eq!((got.ts.seconds, got.ts.nanos), (1_714_557_600, 123_456_000));
+    assert_eq!(got.payload.pid, 7);
+
+    let before = SystemTime::now();
+    let got = read_at(0).await;
+    let stamped = SystemTime::try_from(got.ts).unwrap();
+    assert!(stamped >= before && stamped <= SystemTime::now());
+}
+
 #[tokio::test]
 async fn test_network_event_listener_reads_and_forwards() {
     let tmp  = NamedTempFile::new().unwrap();
@@ -107,6 +147,7 @@ async fn test_network_event_listener_reads_and_forwards() {
         exe_path:  "C:\\net.exe".to_string(),
         bytes:     1024,
         blocked:   false,
+        dst_hostname: String::new(),
     };
     let mut buf = Vec::new();
     net.encode(&mut buf).unwrap();
@@ -119,9 +160,9 @@ async fn test_network_event_listener_reads_and_forwards() {
     let (db_tx, mut db_rx) = mpsc::channel::<WrappedEvent<NetworkEvent>>(8);
     let (intel_tx, _)      = broadcast::channel::<WrappedEvent<NetworkEvent>>(8);
     let mut intel_rx       = intel_tx.subscribe();
-    let buses = Buses::<NetworkEvent> { db_tx, intel_tx };
+    let buses = Buses::<NetworkEvent> { db_tx: db_tx.into(), intel_tx };
 
-    listener.spawn(buses);
+    listener.spawn(buses, &Shutdown::new());
 
     let got_db = timeout(Duration::from_secs(1), db_rx.recv())
         .await.expect("timeout waiting for db")
@@ -134,6 +175,133 @@ async fn test_network_event_listener_reads_and_forwards() {
     assert_eq!(got_intel.payload, net);
 }
 
+/// Frame as the minifilter's pre-create callback encodes it by hand
+/// (`kernel-driver/tests/file_event.rs` checks the same bytes).
+#[tokio::test]
+async fn file_event_from_minifilter_frame_decodes() {
+    let tmp  = NamedTempFile::new().unwrap();
+    let file = OpenOptions::new().read(true).write(true).open(tmp.path()).unwrap();
+
+    let mut buf = vec![0x12, 29];
+    buf.extend_from_slice(br"\Device\HarddiskVolume3\t.txt");
+    buf.extend_from_slice(&[0x20, 0x92, 0x21]);
+    push_raw_event(&file, &buf);
+
+    let ring     = MemoryRing::open(tmp.path()).unwrap();
+    let listener = Arc::new(RingListener::new("file", ring, "SENSOR"));
+
+    let (db_tx, mut db_rx) = mpsc::channel::<WrappedEvent<FileEvent>>(8);
+    let (intel_tx, _)      = broadcast::channel::<WrappedEvent<FileEvent>>(8);
+    let buses = Buses::<FileEvent> { db_tx: db_tx.into(), intel_tx };
+
+    listener.spawn(buses, &Shutdown::new());
+
+    let got = timeout(Duration::from_secs(1), db_rx.recv())
+        .await.expect("timeout waiting for db")
+        .expect("db channel closed");
+    assert_eq!(got.payload, FileEvent {
+        op:   Operation::Create as i32,
+        path: r"\Device\HarddiskVolume3\t.txt".to_string(),
+        pid:  4242,
+        ..FileEvent::default()
+    });
+}
+
+/// Frame as the driver's load-image routine encodes it by hand
+/// (`kernel-driver/tests/image_event.rs` checks the same bytes).
+#[tokio::test]
+async fn image_load_event_from_driver_frame_decodes() {
+    let tmp  = NamedTempFile::new().unwrap();
+    let file = OpenOptions::new().read(true).write(true).open(tmp.path()).unwrap();
+
+    let mut buf = vec![0x08, 0x92, 0x21];
+    buf.extend_from_slice(&[0x10, 0x80, 0x80, 0x80, 0x80, 0xa0, 0xff, 0x1f]);
+    buf.extend_from_slice(&[0x18, 0x80, 0x80, 0x7e]);
+    buf.extend_from_slice(&[0x22, 27]);
+    buf.extend_from_slice(br"\Windows\System32\ntdll.dll");
+    push_raw_event(&file, &buf);
+
+    let ring     = MemoryRing::open(tmp.path()).unwrap();
+    let listener = Arc::new(RingListener::new("image", ring, "SENSOR"));
+
+    let (db_tx, mut db_rx) = mpsc::channel::<WrappedEvent<ImageLoadEvent>>(8);
+    let (intel_tx, _)      = broadcast::channel::<WrappedEvent<ImageLoadEvent>>(8);
+    let buses = Buses::<ImageLoadEvent> { db_tx: db_tx.into(), intel_tx };
+
+    listener.spawn(buses, &Shutdown::new());
+
+    let got = timeout(Duration::from_secs(1), db_rx.recv())
+        .await.expect("timeout waiting for db")
+        .expect("db channel closed");
+    assert_eq!(got.payload, ImageLoadEvent {
+        pid:              4242,
+        image_base:       0x7ffa_0000_0000,
+        image_size:       0x1f_8000,
+        full_image_name:  r"\Windows\System32\ntdll.dll".to_string(),
+        is_kernel_module: false,
+    });
+}
+
+/// Exit frame as the driver's process routine encodes it by hand
+/// (`kernel-driver/tests/process_event.rs` checks the same bytes), then the
+/// strings the driver cut counted by field.
+#[test]
+fn process_frames_decode_and_cut_strings_are_counted() {
+    let mut buf = vec![0x08, 7, 0x38, 1, 0x40];
+    buf.extend_from_slice(&[0x85, 0x80, 0x80, 0x80, 0xfc, 0xff, 0xff, 0xff, 0xff, 0x01]);
+    let exit = ProcessEvent::decode(&*buf).unwrap();
+    assert_eq!((exit.pid, exit.is_exit(), exit.exit_code, exit.truncated), (7, true, -1_073_741_819, 0));
+
+    let recorder = PrometheusBuilder::new().build_recorder();
+    metrics::with_local_recorder(&recorder, || {
+        let cut = ProcessEvent {
+            pid: 8,
+            cmdline: "A".repeat(PROCESS_CMDLINE_MAX / 2),
+            truncated: TRUNCATED_CMDLINE | TRUNCATED_PARENT_IMAGE_PATH,
+            ..ProcessEvent::default()
+        };
+        let mut cut = ProcessEvent::decode(&*cut.encode_to_vec()).unwrap();
+        count_truncated(&mut cut);
+        cut.truncated = TRUNCATED_CMDLINE;
+        count_truncated(&mut cut);
+        count_truncated(&mut exit.clone());
+    });
+    let text = recorder.handle().render();
+    assert!(text.contains("events_truncated_total{field=\"cmdline\"} 2"), "{text}");
+    assert!(text.contains("events_truncated_total{field=\"parent_image_path\"} 1"), "{text}");
+    assert!(!text.contains("field=\"image_path\""), "{text}");
+}
+
+/// Frame as the driver's handle callback encodes it by hand
+/// (`kernel-driver/tests/object_event.rs` checks the same bytes).
+#[tokio::test]
+async fn object_op_event_from_driver_frame_decodes() {
+    let tmp  = NamedTempFile::new().unwrap();
+    let file = OpenOptions::new().read(true).write(true).open(tmp.path()).unwrap();
+
+    let buf = [0x08, 0x92, 0x21, 0x10, 0xb9, 0x0a, 0x18, 0xff, 0xff, 0x7f, 0x20, 0x01];
+    push_raw_event(&file, &buf);
+
+    let ring     = MemoryRing::open(tmp.path()).unwrap();
+    let listener = Arc::new(RingListener::new("object", ring, "SENSOR"));
+
+    let (db_tx, mut db_rx) = mpsc::channel::<WrappedEvent<ObjectOpEvent>>(8);
+    let (intel_tx, _)      = broadcast::channel::<WrappedEvent<ObjectOpEvent>>(8);
+    let buses = Buses::<ObjectOpEvent> { db_tx: db_tx.into(), intel_tx };
+
+    listener.spawn(buses, &Shutdown::new());
+
+    let got = timeout(Duration::from_secs(1), db_rx.recv())
+        .await.expect("timeout waiting for db")
+        .expect("db channel closed");
+    assert_eq!(got.payload, ObjectOpEvent {
+        source_pid:     4242,
+        target_pid:     1337,
+        desired_access: 0x1f_ffff,
+        operation:      ObjectOperation::HandleDuplicate as i32,
+    });
+}
+
 #[test]
 fn network_event_listener_to_db_e2e() {
     let exe_dir: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -143,7 +311,7 @@ fn network_event_listener_to_db_e2e() {
     let mut db_cfg = cfg.database.clone();
     let tmp = NamedTempFile::new().unwrap();
     db_cfg.path = tmp.path().file_name().unwrap().to_string_lossy().into_owned();
-    db_cfg.purge_on_restart = true;
+    db_cfg.purge_on_restart = true.into();
 
     let conn    = init_database(&exe_dir, &db_cfg).expect("init database");
     let db_path = db_path(&exe_dir, &db_cfg);
@@ -152,7 +320,7 @@ fn network_event_listener_to_db_e2e() {
     rt.block_on(async {
         // 1) lanzamos writer
         let (db_tx, db_rx) = mpsc::channel::<WrappedEvent<NetworkEvent>>(1);
-        spawn_writer(&rt, conn, db_rx, &db_cfg);
+        spawn_writer(&rt, conn, db_rx, &db_cfg, &Shutdown::new());
 
         // 2) simulamos driver ring
         let tmp_ring = NamedTempFile::new().unwrap();
@@ -169,6 +337,7 @@ fn network_event_listener_to_db_e2e() {
             exe_path:  "C:\\Windows\\svchost.exe".to_string(),
             bytes:     2048,
             blocked:   false,
+            dst_hostname: String::new(),
         };
         let mut buf = Vec::new();
         net.encode(&mut buf).unwrap();
@@ -178,8 +347,8 @@ fn network_event_listener_to_db_e2e() {
         let ring = MemoryRing::open(tmp_ring.path()).unwrap();
         let listener = Arc::new(RingListener::new("network", ring, "TEST-NET"));
         let (_intel_tx, _) = broadcast::channel::<WrappedEvent<NetworkEvent>>(8);
-        let buses = Buses::<NetworkEvent> { db_tx, intel_tx: _intel_tx };
-        listener.spawn(buses);
+        let buses = Buses::<NetworkEvent> { db_tx: db_tx.into(), intel_tx: _intel_tx };
+        listener.spawn(buses, &Shutdown::new());
     });
 
     // dejamos que corran listener+writer
@@ -219,4 +388,89 @@ fn network_event_listener_to_db_e2e() {
     for row in rows {
         println!("{:?}", row.unwrap());
     }
-}
\ No newline at end of file
+}
+/// Frame captured from the driver's ALE connect callout
+/// (`kernel-driver/tests/net_event.rs` checks the encoder produces it).
+#[test]
+fn wfp_connect_frame_is_stored_in_network_events() {
+    let exe_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let cfg: AppConfig = load(&exe_dir.join("config.toml")).expect("failed to load config.toml");
+
+    let mut db_cfg = cfg.database.clone();
+    let tmp = NamedTempFile::new().unwrap();
+    db_cfg.path = tmp.path().file_name().unwrap().to_string_lossy().into_owned();
+    db_cfg.purge_on_restart = true.into();
+    let conn    = init_database(&exe_dir, &db_cfg).expect("init database");
+    let db_path = db_path(&exe_dir, &db_cfg);
+
+    // The ring holds the payload; the helper frames it again.
+    let frame   = std::fs::read(exe_dir.join("tests/fixtures/wfp_connect_v4.frame")).unwrap();
+    let found   = ring::check_frame(&frame).expect("fixture is a whole frame");
+    let tmp_ring = NamedTempFile::new().unwrap();
+    let ring_f   = OpenOptions::new().read(true).write(true).open(tmp_ring.path()).unwrap();
+    push_raw_event(&ring_f, &frame[found.payload]);
+
+    let rt = Runtime::new().unwrap();
+    let shutdown = Shutdown::new();
+    rt.block_on(async {
+        let (db_tx, db_rx) = mpsc::channel::<WrappedEvent<NetworkEvent>>(8);
+        spawn_writer(&rt, conn, db_rx, &db_cfg, &shutdown);
+
+        let ring     = MemoryRing::open(tmp_ring.path()).unwrap();
+        let listener = Arc::new(RingListener::new("network", ring, "SENSOR"));
+        let (intel_tx, _) = broadcast::channel::<WrappedEvent<NetworkEvent>>(8);
+        listener.spawn(Buses::<NetworkEvent> { db_tx: db_tx.into(), intel_tx }, &shutdown);
+    });
+    std::thread::sleep(Duration::from_millis(db_cfg.flush_interval_ms as u64 + 100));
+
+    let conn = Connection::open(&db_path).unwrap();
+    let row = conn
+        .query_row(
+            "SELECT direction, proto, src_ip, src_port, dst_ip, dst_port, pid, exe_path, bytes, verdict \
+             FROM network_events",
+            [],
+            |r| Ok((
+                r.get::<_, String>(0)?, r.get::<_, String>(1)?,
+                r.get::<_, String>(2)?, r.get::<_, i64>(3)?,
+                r.get::<_, String>(4)?, r.get::<_, i64>(5)?,
+                r.get::<_, i64>(6)?, r.get::<_, String>(7)?,
+                r.get::<_, i64>(8)?, r.get::<_, String>(9)?,
+            )),
+        )
+        .expect("one row in network_events");
+    assert_eq!(row, (
+        "OUTBOUND".to_string(), "TCP".to_string(),
+        "10.0.0.1".to_string(), 49752,
+        "93.184.216.34".to_string(), 443,
+        4242, r"\device\harddiskvolume3\windows\system32\curl.exe".to_string(),
+        0, "allow".to_string(),
+    ));
+    shutdown.trigger();
+}
+
+#[test]
+fn ring_stats_report_driver_drops() {
+    let tmp  = NamedTempFile::new().unwrap();
+    let file = OpenOptions::new().read(true).write(true).open(tmp.path()).unwrap();
+    file.set_len((ring::HEADER_SIZE + 4096) as u64).unwrap();
+    let set_dropped = |n: u32| {
+        let mut mmap = unsafe { MmapOptions::new().map_mut(&file).unwrap() };
+        let header = mmap.as_mut_ptr() as *mut RingHeader;
+        unsafe {
+            (*header).version = ring::VERSION;
+            (*header).head.store(64, Ordering::Release);
+            (*header).tail.store(128, Ordering::Release);
+            (*header).dropped.store(n, Ordering::Release);
+        }
+    };
+    set_dropped(7);
+
+    let ring = MemoryRing::open(tmp.path()).unwrap();
+    assert_eq!(ring.stats(), RingStats { head: 64, tail: 128, dropped: 7, size: 4096, ..RingStats::default() });
+
+    let drops = DropMonitor::default();
+    assert_eq!(drops.observe("process", &ring.stats()), 7);
+    assert_eq!(drops.observe("process", &ring.stats()), 0, "no growth, no warning");
+    set_dropped(10);
+    assert_eq!(drops.observe("process", &ring.stats()), 3);
+}
@@ -0,0 +1,211 @@
+// tests/live_probe.rs
+
+mod common;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::broadcast};
+use shared::events::{EtwEvent, FileEvent, ProcessEvent};
+
+use agent::{
+    config::{load, model::ProbeConfig},
+    db::{
+        connection::init_database,
+        probe_results::{record_results, recent_results},
+    },
+    health::{Component, ComponentState, HealthRegistry},
+    intel::{spawn_feeder, EventKind, RecentConfig, RecentEvents},
+    probe::{is_probe_event, Marker, ProbeActions, ProbeTaps, Prober, Sensor},
+};
+use common::{seconds, wrap};
+
+/// What the fake pipeline does after a probe action.
+#[derive(Clone, Copy)]
+enum Step {
+    /// Deliver the marker event after the given delay.
+    Deliver(Duration),
+    /// Deliver an event for some other marker only.
+    Unrelated,
+    /// Nothing reaches the intel bus.
+    Drop,
+}
+
+fn process_event(marker: &str) -> ProcessEvent {
+    ProcessEvent {
+        pid: 4242,
+        ppid: 1,
+        image_path: r"C:\Program Files\Gladix\gladix-probe-marker.exe".into(),
+        cmdline: format!(r#""C:\Program Files\Gladix\gladix-probe-marker.exe" {marker}"#),
+        ..ProcessEvent::default()
+    }
+}
+
+fn file_event(marker: &str) -> FileEvent {
+    FileEvent { path: format!(r"C:\Windows\Temp\{marker}.tmp"), pid: 7, ..FileEvent::default() }
+}
+
+fn dns_event(marker: &str) -> EtwEvent {
+    EtwEvent {
+        provider_guid: "{1c95126e-7eea-49a9-a3fe-a378b03ddb4d}".into(),
+        event_id: 3008,
+        pid: 7,
+        json_payload: format!(r#"{{"QueryName":"{marker}.localhost","QueryStatus":"0"}}"#),
+        ..EtwEvent::default()
+    }
+}
+
+/// Fake sensors: each action consumes the next scripted step of its sensor.
+struct Scripted {
+    taps:   ProbeTaps,
+    script: Mutex<HashMap<Sensor, VecDeque<Step>>>,
+}
+
+impl Scripted {
+    fn new(taps: ProbeTaps, script: &[(Sensor, &[Step])]) -> Arc<Self> {
+        let script = script.iter().map(|(s, steps)| (*s, steps.iter().copied().collect())).collect();
+        Arc::new(Self { taps, script: Mutex::new(script) })
+    }
+
+    fn emit(&self, sensor: Sensor, marker: &str) {
+        let _ = match sensor {
+            Sensor::Process => self.taps.process.as_ref().unwrap().send(wrap(seconds(1_700_000_000), process_event(marker))).is_ok(),
+            Sensor::File    => self.taps.file.as_ref().unwrap().send(wrap(seconds(1_700_000_000), file_event(marker))).is_ok(),
+            Sensor::Dns     => self.taps.dns.as_ref().unwrap().send(wrap(seconds(1_700_000_000), dns_event(marker))).is_ok(),
+        };
+    }
+}
+
+impl ProbeActions for Scripted {
+    fn trigger(&self, sensor: Sensor, marker: &Marker) -> io::Result<()> {
+        let step = self.script.lock().unwrap().get_mut(&sensor).and_then(|q| q.pop_front());
+        match step.unwrap_or(Step::Drop) {
+            Step::Deliver(delay) => {
+                let (taps, marker) = (self.taps.clone(), marker.to_string());
+                let me = Scripted { taps, script: Mutex::default() };
+                thread::spawn(move || {
+                    thread::sleep(delay);
+                    me.emit(sensor, &marker);
+                });
+            }
+            Step::Unrelated => self.emit(sensor, Marker::new().as_str()),
+            Step::Drop => {}
+        }
+        Ok(())
+    }
+}
+
+fn taps() -> ProbeTaps {
+    ProbeTaps {
+        process: Some(broadcast::channel(64).0),
+        file:    Some(broadcast::channel(64).0),
+        dns:     Some(broadcast::channel(64).0),
+    }
+}
+
+fn probe_cfg() -> ProbeConfig {
+    ProbeConfig {
+        timeout: Duration::from_millis(300),
+        failure_threshold: 2,
+        ..ProbeConfig::default()
+    }
+}
+
+const SOON: Step = Step::Deliver(Duration::from_millis(20));
+
+#[test]
+fn every_sensor_observed_is_recorded_with_latency() {
+    let rt     = Runtime::new().unwrap();
+    let taps   = taps();
+    let health = HealthRegistry::new();
+    let fake   = Scripted::new(taps.clone(), &[
+        (Sensor::Process, &[SOON]),
+        (Sensor::File,    &[SOON]),
+        (Sensor::Dns,     &[SOON]),
+    ]);
+    let mut prober = Prober::new(&probe_cfg(), fake, taps, health.clone());
+    assert_eq!(health.state(Component::Sensor("dns")), Some(ComponentState::Pending));
+
+    let results = rt.block_on(prober.run_once());
+    assert_eq!(results.iter().map(|r| r.sensor).collect::<Vec<_>>(), Sensor::ALL);
+    for r in &results {
+        assert!(r.success(), "{r:?}");
+        assert!(r.latency.unwrap() >= Duration::from_millis(20));
+        assert!(r.marker.starts_with("gladix-probe-"));
+        assert_eq!(health.state(Component::Sensor(r.sensor.as_str())), Some(ComponentState::Healthy));
+    }
+
+    let dir  = tempdir().unwrap();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut db = load(&root.join("config.toml")).unwrap().database;
+    db.path = "telemetry.db".into();
+    let conn = init_database(dir.path(), &db).unwrap();
+    record_results(&conn, &results).unwrap();
+    let stored = recent_results(&conn, Sensor::File, 10).unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].marker, results[1].marker);
+    assert!(stored[0].success());
+}
+
+#[test]
+fn consecutive_failures_degrade_the_sensor_until_it_recovers() {
+    let rt     = Runtime::new().unwrap();
+    let taps   = ProbeTaps { process: Some(broadcast::channel(64).0), ..ProbeTaps::default() };
+    let health = HealthRegistry::new();
+    let late   = Step::Deliver(Duration::from_millis(600));
+    let fake   = Scripted::new(taps.clone(), &[
+        (Sensor::Process, &[Step::Unrelated, late, Step::Drop, SOON]),
+    ]);
+    let mut prober = Prober::new(&probe_cfg(), fake, taps, health.clone());
+    let sensor = Component::Sensor("process");
+
+    // Someone else's marker does not count; one failure is tolerated.
+    let r = rt.block_on(prober.run_once()).remove(0);
+    assert!(!r.success());
+    assert!(r.error.unwrap().contains("no event within"));
+    assert_eq!(prober.failures(Sensor::Process), 1);
+    assert_eq!(health.state(sensor), Some(ComponentState::Pending));
+
+    // Arriving after the timeout is a failure too: threshold reached.
+    assert!(!rt.block_on(prober.run_once())[0].success());
+    match health.state(sensor) {
+        Some(ComponentState::Degraded { error, attempts: 1, .. }) => assert!(error.contains("2 times"), "{error}"),
+        other => panic!("expected degraded, got {other:?}"),
+    }
+
+    assert!(!rt.block_on(prober.run_once())[0].success());
+    assert!(matches!(health.state(sensor), Some(ComponentState::Degraded { attempts: 2, .. })));
+
+    // Telemetry flows again.
+    assert!(rt.block_on(prober.run_once())[0].success());
+    assert_eq!(prober.failures(Sensor::Process), 0);
+    assert_eq!(health.state(sensor), Some(ComponentState::Healthy));
+}
+
+#[test]
+fn probe_events_are_kept_out_of_detection_state() {
+    let marker = Marker::new();
+    assert_ne!(marker, Marker::new());
+    assert!(is_probe_event(&process_event(marker.as_str())));
+    assert!(is_probe_event(&file_event(marker.as_str())));
+    assert!(is_probe_event(&dns_event(marker.as_str())));
+    assert!(!is_probe_event(&ProcessEvent { pid: 1, ppid: 0, image_path: "cmd.exe".into(), cmdline: "cmd /c dir".into(), ..ProcessEvent::default() }));
+
+    let rt = Runtime::new().unwrap();
+    let (tx, _) = broadcast::channel(16);
+    let recent = RecentEvents::new(RecentConfig::default());
+    let feeder = spawn_feeder(&rt, tx.subscribe().into(), recent.clone(), EventKind::Process);
+    assert!(tx.send(wrap(seconds(1_700_000_000), process_event(marker.as_str()))).is_ok());
+    assert!(tx.send(wrap(seconds(1_700_000_000), ProcessEvent { pid: 9, ppid: 1, image_path: "cmd.exe".into(), cmdline: String::new(), ..ProcessEvent::default() })).is_ok());
+    drop(tx);
+    rt.block_on(feeder).unwrap();
+
+    assert_eq!(recent.len(), 1);
+    assert!(recent.window(EventKind::Process, 4242, i64::MIN, i64::MAX).is_empty());
+}
@@ -0,0 +1,245 @@
+// tests/memdump.rs
+
+mod common;
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+};
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+use agent::{
+    actions::{Actions, Capture, CaptureStatus, Dumper, Response},
+    config::model::{ActionsConfig, DumpType, MemdumpConfig, RuleAction},
+    db::captures::captures_for_alert,
+    intel::{insert_alert, Alert, Severity},
+};
+
+const RULE: &str = "builtin.parent_pid_spoofing";
+const IMAGE: &str = r"C:\Users\bob\AppData\Local\Temp\injected.exe";
+
+/// Dumps a spawned helper process with synthetic contents; reports the
+/// process as gone once it exits.
+struct Helper {
+    child: Mutex<Child>,
+    bytes: Vec<u8>,
+}
+
+impl Helper {
+    fn spawn(bytes: Vec<u8>) -> Arc<Self> {
+        let mut cmd = if cfg!(windows) {
+            let mut c = Command::new("ping");
+            c.args(["-n", "60", "127.0.0.1"]);
+            c
+        } else {
+            let mut c = Command::new("sleep");
+            c.arg("60");
+            c
+        };
+        let child = cmd.stdout(Stdio::null()).spawn().expect("helper process");
+        Arc::new(Self { child: Mutex::new(child), bytes })
+    }
+
+    fn pid(&self) -> u32 {
+        self.child.lock().unwrap().id()
+    }
+
+    fn kill(&self) {
+        let mut child = self.child.lock().unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
+
+impl Drop for Helper {
+    fn drop(&mut self) {
+        let _ = self.child.get_mut().unwrap().kill();
+    }
+}
+
+impl Dumper for Helper {
+    fn image_path(&self, pid: u32) -> io::Result<String> {
+        let mut child = self.child.lock().unwrap();
+        if pid != child.id() || child.try_wait()?.is_some() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "process exited"));
+        }
+        Ok(IMAGE.into())
+    }
+
+    fn write_dump(&self, _pid: u32, _kind: DumpType, mut out: &File) -> io::Result<()> {
+        out.write_all(&self.bytes)
+    }
+}
+
+/// `len` bytes zstd cannot shrink.
+fn noise(len: usize) -> Vec<u8> {
+    let mut x = 0x2545_f491_4f6c_dd1d_u64;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect()
+}
+
+fn actions_cfg(memdump: MemdumpConfig) -> ActionsConfig {
+    ActionsConfig {
+        enabled: true,
+        rules: BTreeMap::from([(RULE.to_string(), RuleAction::Memdump)]),
+        memdump,
+        ..ActionsConfig::default()
+    }
+}
+
+/// Stores an alert on `pid` and returns it with its id.
+fn alert(conn: &Connection, rule: &str, pid: u32) -> (i64, Alert) {
+    let alert = Alert {
+        ts:       1,
+        rule_id:  rule.into(),
+        severity: Severity::Critical,
+        pid,
+        ppid:     None,
+        message:  "suspected injection".into(),
+        file:     None,
+    };
+    (insert_alert(conn, &alert).unwrap(), alert)
+}
+
+/// What the rule's memdump action recorded, if it has one.
+fn captured(actions: &Actions, conn: &Connection, id: i64, a: &Alert) -> Option<Capture> {
+    match actions.on_alert(conn, id, a).unwrap()? {
+        Response::Capture(capture) => Some(capture),
+        other => panic!("expected a capture, got {other:?}"),
+    }
+}
+
+fn dumps(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir).map_or_else(|_| Vec::new(), |d| d.map(|e| e.unwrap().path()).collect())
+}
+
+#[test]
+fn dump_is_written_and_recorded_for_the_alert() {
+    let dir = tempdir().unwrap();
+    let (conn, _) = common::database(dir.path());
+    let helper = Helper::spawn(noise(4096));
+    let actions = Actions::with_dumper(&actions_cfg(MemdumpConfig::default()), dir.path(), helper.clone());
+
+    let (id, a) = alert(&conn, RULE, helper.pid());
+    let capture = captured(&actions, &conn, id, &a).expect("rule declares memdump");
+    assert_eq!(capture.status, CaptureStatus::Captured, "{:?}", capture.error);
+    let path = capture.path.clone().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), helper.bytes);
+    assert_eq!(path.parent().unwrap(), dir.path().join("captures"));
+    assert!(path.file_name().unwrap().to_str().unwrap().ends_with(&format!("-alert{id}-pid{}-injected.dmp", helper.pid())));
+    assert_eq!((capture.size, capture.truncated), (Some(4096), false));
+
+    assert_eq!(captures_for_alert(&conn, id).unwrap(), vec![capture]);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(dir.path().join("captures")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
+
+#[test]
+fn captures_are_rate_limited_per_hour() {
+    let dir = tempdir().unwrap();
+    let (conn, _) = common::database(dir.path());
+    let helper = Helper::spawn(noise(128));
+    let cfg = actions_cfg(MemdumpConfig { per_hour: 2, ..MemdumpConfig::default() });
+    let actions = Actions::with_dumper(&cfg, dir.path(), helper.clone());
+
+    let statuses: Vec<_> = (0..3)
+        .map(|_| {
+            let (id, a) = alert(&conn, RULE, helper.pid());
+            captured(&actions, &conn, id, &a).unwrap().status
+        })
+        .collect();
+    assert_eq!(statuses, [CaptureStatus::Captured, CaptureStatus::Captured, CaptureStatus::RateLimited]);
+    assert_eq!(dumps(&dir.path().join("captures")).len(), 2);
+}
+
+#[test]
+fn oversized_dumps_are_cut_after_compression() {
+    let dir = tempdir().unwrap();
+    let (conn, _) = common::database(dir.path());
+
+    // Incompressible 3 MiB against a 1 MiB cap.
+    let helper = Helper::spawn(noise(3 << 20));
+    let cfg = actions_cfg(MemdumpConfig { max_mb: 1, ..MemdumpConfig::default() });
+    let actions = Actions::with_dumper(&cfg, dir.path(), helper.clone());
+    let (id, a) = alert(&conn, RULE, helper.pid());
+    let capture = captured(&actions, &conn, id, &a).unwrap();
+    assert_eq!((capture.status, capture.truncated, capture.size), (CaptureStatus::Captured, true, Some(1 << 20)));
+    assert_eq!(fs::metadata(capture.path.unwrap()).unwrap().len(), 1 << 20);
+
+    // The same size compresses below the cap and is kept whole.
+    let helper = Helper::spawn(vec![0x5a; 3 << 20]);
+    let cfg = actions_cfg(MemdumpConfig { max_mb: 1, compress: true, ..MemdumpConfig::default() });
+    let actions = Actions::with_dumper(&cfg, &dir.path().join("zst"), helper.clone());
+    let (id, a) = alert(&conn, RULE, helper.pid());
+    let capture = captured(&actions, &conn, id, &a).unwrap();
+    assert!(capture.compressed && !capture.truncated, "{capture:?}");
+    let path = capture.path.unwrap();
+    assert!(path.to_str().unwrap().ends_with(".dmp.zst"));
+    assert_eq!(zstd::decode_all(File::open(&path).unwrap()).unwrap(), helper.bytes);
+    assert_eq!(dumps(path.parent().unwrap()).len(), 1, "raw dump removed");
+}
+
+#[test]
+fn refusals_and_failures_are_recorded_not_retried() {
+    let dir = tempdir().unwrap();
+    let (conn, _) = common::database(dir.path());
+    let helper = Helper::spawn(noise(64));
+
+    // Master switch off, or no action for the rule: nothing happens.
+    let off = ActionsConfig { enabled: false, ..actions_cfg(MemdumpConfig::default()) };
+    let (id, a) = alert(&conn, RULE, helper.pid());
+    assert!(captured(&Actions::with_dumper(&off, dir.path(), helper.clone()), &conn, id, &a).is_none());
+    let actions = Actions::with_dumper(&actions_cfg(MemdumpConfig::default()), dir.path(), helper.clone());
+    let (id, a) = alert(&conn, "builtin.write_then_execute", helper.pid());
+    assert!(captured(&actions, &conn, id, &a).is_none());
+    assert!(captures_for_alert(&conn, id).unwrap().is_empty());
+
+    // The agent itself and denylisted images.
+    let (id, a) = alert(&conn, RULE, std::process::id());
+    assert_eq!(captured(&actions, &conn, id, &a).unwrap().status, CaptureStatus::Refused);
+    let deny = actions_cfg(MemdumpConfig { denylist: vec!["INJECTED.exe".into()], ..MemdumpConfig::default() });
+    let (id, a) = alert(&conn, RULE, helper.pid());
+    let refused = captured(&Actions::with_dumper(&deny, dir.path(), helper.clone()), &conn, id, &a).unwrap();
+    assert_eq!((refused.status, refused.image.as_deref()), (CaptureStatus::Refused, Some(IMAGE)));
+
+    // Gone before the dump: one failed row, no file.
+    helper.kill();
+    let (id, a) = alert(&conn, RULE, helper.pid());
+    actions.on_alert(&conn, id, &a).unwrap();
+    let rows = captures_for_alert(&conn, id).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].status, CaptureStatus::Failed);
+    assert!(rows[0].error.as_deref().unwrap().contains("process exited"), "{:?}", rows[0].error);
+    assert!(dumps(&dir.path().join("captures")).is_empty());
+}
+
+#[cfg(windows)]
+#[test]
+#[ignore = "needs an elevated shell: the captures directory is restricted to Administrators"]
+fn system_dumper_captures_a_live_process() {
+    let dir = tempdir().unwrap();
+    let (conn, _) = common::database(dir.path());
+    let helper = Helper::spawn(Vec::new());
+    let actions = Actions::new(&actions_cfg(MemdumpConfig::default()), dir.path());
+    let (id, a) = alert(&conn, RULE, helper.pid());
+    let capture = captured(&actions, &conn, id, &a).unwrap();
+    assert_eq!(capture.status, CaptureStatus::Captured, "{:?}", capture.error);
+    assert!(capture.image.unwrap().to_lowercase().ends_with("ping.exe"));
+    assert!(capture.size.unwrap() > 0);
+}
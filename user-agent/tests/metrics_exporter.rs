@@ -0,0 +1,64 @@
+// tests/metrics_exporter.rs
+//
+// The exporter serves the exposition on the configured address, and a busy
+// address leaves the recorder working without an endpoint.
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::runtime::Runtime;
+
+use agent::{config::model::MetricsConfig, metrics_exporter};
+
+/// A port nothing listens on right now.
+fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+fn get(addr: SocketAddr) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    write!(stream, "GET /metrics HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+#[test]
+fn http_listener_serves_the_exposition() {
+    let cfg = MetricsConfig { listen_addr: free_addr(), ..MetricsConfig::default() };
+    let rt = Runtime::new().unwrap();
+    let _guard = rt.enter();
+
+    let (recorder, exporter) = metrics_exporter::build(&cfg);
+    rt.spawn(exporter.expect("listener is up"));
+    metrics::with_local_recorder(&recorder, metrics_exporter::record_agent_info);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let response = loop {
+        match get(cfg.listen_addr) {
+            Ok(response) => break response,
+            Err(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+            Err(e) => panic!("scrape failed: {e}"),
+        }
+    };
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    let version = env!("CARGO_PKG_VERSION");
+    assert!(response.contains(&format!("agent_info{{version=\"{version}\"}} 1")), "{response}");
+}
+
+#[test]
+fn busy_address_keeps_the_recorder() {
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let cfg = MetricsConfig { listen_addr: taken.local_addr().unwrap(), ..MetricsConfig::default() };
+    let rt = Runtime::new().unwrap();
+    let _guard = rt.enter();
+
+    let (recorder, exporter) = metrics_exporter::build(&cfg);
+    assert!(exporter.is_none());
+    metrics::with_local_recorder(&recorder, metrics_exporter::record_agent_info);
+    assert!(recorder.handle().render().contains("agent_info"));
+}
@@ -0,0 +1,130 @@
+// tests/metrics_history.rs
+
+use std::fs;
+use chrono::{Duration as ChronoDuration, Utc};
+use metrics::counter;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tempfile::tempdir;
+
+use agent::{
+    config::model::MetricsHistoryConfig,
+    metrics_history::{parse_exposition, MetricsHistory, Snapshot, LATEST},
+};
+
+fn cfg(keep: usize, max_kb: usize) -> MetricsHistoryConfig {
+    MetricsHistoryConfig { keep, max_kb, ..MetricsHistoryConfig::default() }
+}
+
+/// Snapshot `mins_ago` minutes old with one counter value.
+fn snap(mins_ago: i64, events: u64) -> Snapshot {
+    Snapshot {
+        ts:   Utc::now() - ChronoDuration::minutes(mins_ago),
+        text: format!(
+            "# TYPE events_received_total counter\n\
+             events_received_total{{type=\"process\"}} {events}\n\
+             ring_fill_ratio{{ring=\"process\"}} 0.25\n\
+             db_flush_batches_total 9\n"
+        ),
+    }
+}
+
+#[test]
+fn exposition_lines_are_parsed_by_series() {
+    let series = parse_exposition(
+        "# HELP x y\n\
+         up 1\n\
+         lat{quantile=\"0.5\",path=\"a b\"} 0.002\n\
+         bad{x=\"1\"} NaNx\n\n",
+    );
+    assert_eq!(series.len(), 2);
+    assert_eq!(series["up"], 1.0);
+    assert_eq!(series["lat{quantile=\"0.5\",path=\"a b\"}"], 0.002);
+}
+
+#[test]
+fn sampling_works_without_an_http_listener() {
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    metrics::with_local_recorder(&recorder, || {
+        counter!("events_received_total", "type" => "process").increment(3);
+    });
+
+    let history = MetricsHistory::new(&MetricsHistoryConfig::default());
+    assert!(history.sample().is_none(), "no source yet");
+    history.set_source(move || handle.render());
+    let taken = history.sample().unwrap();
+    assert_eq!(taken.series()["events_received_total{type=\"process\"}"], 3.0);
+    assert_eq!(history.latest(), Some(taken));
+}
+
+#[test]
+fn ring_is_bounded_by_count_and_size() {
+    let history = MetricsHistory::new(&cfg(3, 1024));
+    for i in 0..5 {
+        history.push(snap(10 - i, i as u64));
+    }
+    assert_eq!(history.len(), 3);
+    let kept: Vec<_> = history.snapshots().iter().map(|s| s.series()["db_flush_batches_total"]).collect();
+    assert_eq!(kept, [9.0; 3]);
+    assert_eq!(history.snapshots()[0].series()["events_received_total{type=\"process\"}"], 2.0);
+
+    // 1 KiB holds only a few of these; the newest always stays.
+    let small = MetricsHistory::new(&cfg(100, 1));
+    for i in 0..20 {
+        small.push(snap(20 - i, i as u64));
+    }
+    assert!(small.len() > 1 && small.len() < 20, "{}", small.len());
+    assert!(small.bytes() <= 1024);
+
+    let tiny = MetricsHistory::new(&cfg(100, 0));
+    tiny.push(snap(0, 1));
+    assert_eq!(tiny.len(), 1);
+}
+
+#[test]
+fn mirror_survives_a_restart_and_is_pruned() {
+    let dir = tempdir().unwrap();
+    let history = MetricsHistory::new(&cfg(2, 1024));
+    for i in 0..3 {
+        let s = snap(30 - i * 10, i as u64);
+        history.push(s.clone());
+        history.persist(dir.path(), &s).unwrap();
+    }
+    let prom: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|n| n.ends_with(".prom") && n != LATEST)
+        .collect();
+    assert_eq!(prom.len(), 2, "{prom:?}");
+    assert_eq!(fs::read_to_string(dir.path().join(LATEST)).unwrap(), snap(0, 2).text);
+
+    let reloaded = MetricsHistory::load_dir(dir.path(), &cfg(2, 1024)).unwrap();
+    let values: Vec<_> = reloaded.snapshots().iter().map(|s| s.series()["events_received_total{type=\"process\"}"]).collect();
+    assert_eq!(values, [1.0, 2.0]);
+    assert!(MetricsHistory::load_dir(&dir.path().join("missing"), &cfg(2, 1024)).unwrap().is_empty());
+}
+
+#[test]
+fn bundle_has_recent_snapshots_and_selected_series() {
+    let history = MetricsHistory::new(&MetricsHistoryConfig::default());
+    for i in 0..4 {
+        history.push(snap(40 - i * 10, i as u64 * 5));
+    }
+    let out = tempdir().unwrap();
+    let files = history
+        .write_bundle(out.path(), 2, &["events_received_total".into(), "ring_fill_ratio".into()])
+        .unwrap();
+    assert_eq!(files.len(), 3);
+    assert!(files[1].file_name().unwrap().to_str().unwrap().starts_with("metrics-"));
+    assert_eq!(fs::read_to_string(&files[1]).unwrap(), history.latest().unwrap().text);
+
+    let csv = fs::read_to_string(out.path().join("metrics.csv")).unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines[0], r#"ts,"events_received_total{type=""process""}","ring_fill_ratio{ring=""process""}""#);
+    assert_eq!(lines.len(), 5, "header and one row per snapshot");
+    assert!(lines[4].ends_with(",15,0.25"), "{}", lines[4]);
+
+    // No series selected: text files only.
+    let out = tempdir().unwrap();
+    assert_eq!(history.write_bundle(out.path(), 10, &[]).unwrap().len(), 4);
+}
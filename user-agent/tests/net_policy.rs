@@ -0,0 +1,248 @@
+// tests/net_policy.rs
+//
+// `[[network_policy]]` rules: what fails to compile, which rule decides a
+// connection, what the driver is sent, and the verdict a blocked connection
+// read from the network ring is stored with.
+
+use std::{
+    fs::OpenOptions,
+    path::PathBuf,
+    sync::Arc,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use memmap2::MmapOptions;
+use prost::Message;
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::{broadcast, mpsc}};
+
+use agent::{
+    comms::{
+        listeners::{Buses, Listener, RingListener},
+        memory_ring::MemoryRing,
+        WrappedEvent,
+    },
+    config::{load, loader::parse, model::{ConfigError, NetPolicyRule, PolicyAction}},
+    db::{connection::{db_path, init_database}, spawn_writer},
+    policy::{NetPolicy, Verdict},
+    util::Shutdown,
+};
+use shared::{
+    constants::{NET_ACTION_ALLOW, NET_ACTION_BLOCK, NET_FAMILY_ANY, NET_FAMILY_V4, NET_FAMILY_V6},
+    events::NetworkEvent,
+    ring::{self, RingHeader},
+};
+
+fn rule(id: &str, action: PolicyAction, dst: Option<&str>, ports: Option<&str>, process: Option<&str>) -> NetPolicyRule {
+    NetPolicyRule {
+        id: id.into(),
+        action,
+        dst: dst.map(Into::into),
+        ports: ports.map(Into::into),
+        process: process.map(Into::into),
+    }
+}
+
+fn connection(dst: &str, port: u32, exe: &str) -> NetworkEvent {
+    NetworkEvent {
+        proto: "TCP".into(),
+        src_ip: "10.0.0.5".into(),
+        src_port: 50_000,
+        dst_ip: dst.into(),
+        dst_port: port,
+        pid: 4242,
+        exe_path: exe.into(),
+        ..Default::default()
+    }
+}
+
+fn rejected(rules: &[NetPolicyRule]) -> (String, String) {
+    match NetPolicy::compile(rules) {
+        Err(ConfigError::InvalidValue(field, reason)) => (field, reason),
+        other => panic!("expected InvalidValue, got {other:?}"),
+    }
+}
+
+#[test]
+fn bad_rules_are_reported_with_their_key() {
+    use PolicyAction::Block;
+    let (field, reason) = rejected(&[rule("a", Block, Some("10.0.0.0/33"), None, None)]);
+    assert_eq!((field.as_str(), reason.as_str()), ("network_policy[a].dst", "'10.0.0.0/33' is not an address or range"));
+
+    let (field, reason) = rejected(&[rule("a", Block, None, Some("http"), None)]);
+    assert_eq!((field.as_str(), reason.as_str()), ("network_policy[a].ports", "'http' is not a port or range"));
+    let (_, reason) = rejected(&[rule("a", Block, None, Some("9000-8000"), None)]);
+    assert_eq!(reason, "range '9000-8000' starts above its end");
+    let (field, _) = rejected(&[rule("a", Block, None, Some("1-70000"), None)]);
+    assert_eq!(field, "network_policy[a].ports");
+
+    let (field, _) = rejected(&[rule("a", Block, None, None, Some(&"x".repeat(121)))]);
+    assert_eq!(field, "network_policy[a].process");
+
+    let (field, reason) = rejected(&[rule("a", Block, None, Some("445"), None), rule("a", Block, None, Some("80"), None)]);
+    assert_eq!((field.as_str(), reason.as_str()), ("network_policy[a].id", "used by another rule"));
+    let (field, _) = rejected(&[rule("", Block, None, None, None)]);
+    assert_eq!(field, "network_policy");
+}
+
+#[test]
+fn rules_overlapped_by_an_earlier_one_are_refused() {
+    use PolicyAction::{Allow, Block};
+    let (field, reason) = rejected(&[
+        rule("lan", Block, Some("10.0.0.0/8"), Some("1-1024"), None),
+        rule("smb", Allow, Some("10.1.0.0/16"), Some("445"), None),
+    ]);
+    assert_eq!(field, "network_policy[smb].id");
+    assert_eq!(reason, "overlapped by rule 'lan' above it, so it never matches");
+
+    // A rule for any process covers one for a single process.
+    let (field, _) = rejected(&[
+        rule("all", Block, None, Some("445"), None),
+        rule("tool", Allow, None, Some("445"), Some(r"*\psexec.exe")),
+    ]);
+    assert_eq!(field, "network_policy[tool].id");
+
+    // Exceptions above the general rule are what the order is for, and
+    // ranges that only intersect are fine.
+    let policy = NetPolicy::compile(&[
+        rule("tool", Allow, None, Some("445"), Some(r"*\psexec.exe")),
+        rule("dc", Allow, Some("10.1.2.3"), Some("445"), None),
+        rule("smb", Block, Some("10.0.0.0/8"), Some("139-445"), None),
+        rule("high", Block, Some("10.0.0.0/8"), Some("400-500"), None),
+    ])
+    .unwrap();
+    assert_eq!(policy.len(), 4);
+}
+
+#[test]
+fn the_first_matching_rule_decides() {
+    use PolicyAction::{Allow, Block};
+    let policy = NetPolicy::compile(&[
+        rule("tool", Allow, None, None, Some(r"*\Tools\*.exe")),
+        rule("dc", Allow, Some("10.1.2.3"), Some("445"), None),
+        rule("smb", Block, Some("10.0.0.0/8"), Some("139-445"), None),
+        rule("v6", Block, Some("2001:db8::/32"), None, None),
+    ])
+    .unwrap();
+    let decide = |dst, port, exe| policy.evaluate(&connection(dst, port, exe));
+
+    assert_eq!(decide("10.9.9.9", 445, r"C:\Windows\explorer.exe"), (Verdict::Block, Some("smb")));
+    assert_eq!(decide("10.9.9.9", 139, r"C:\Windows\explorer.exe"), (Verdict::Block, Some("smb")));
+    assert_eq!(decide("10.1.2.3", 445, r"C:\Windows\explorer.exe"), (Verdict::Allow, Some("dc")));
+    assert_eq!(decide("10.9.9.9", 445, r"c:\tools\PsExec.exe"), (Verdict::Allow, Some("tool")));
+    assert_eq!(decide("10.9.9.9", 8080, r"C:\Windows\explorer.exe"), (Verdict::Allow, None));
+    assert_eq!(decide("192.168.1.1", 445, r"C:\Windows\explorer.exe"), (Verdict::Allow, None));
+    assert_eq!(decide("2001:db8::1", 80, ""), (Verdict::Block, Some("v6")));
+    assert_eq!(decide("not an address", 445, ""), (Verdict::Allow, None));
+
+    let mut ev = connection("10.9.9.9", 445, "");
+    assert_eq!(policy.apply(&mut ev), Verdict::Block);
+    assert!(ev.blocked);
+    // What the driver blocked stays blocked.
+    let mut ev = NetworkEvent { blocked: true, ..connection("192.168.1.1", 80, "") };
+    assert_eq!(policy.apply(&mut ev), Verdict::Block);
+}
+
+#[test]
+fn the_driver_gets_the_compiled_rules() {
+    use PolicyAction::{Allow, Block};
+    let policy = NetPolicy::compile(&[
+        rule("tool", Allow, None, None, Some(r"*\Tools\*.exe")),
+        rule("smb", Block, Some("10.1.2.3/8"), Some("139-445"), None),
+        rule("v6", Block, Some("2001:db8::/32"), Some("80"), None),
+    ])
+    .unwrap();
+    let rules = policy.driver_rules();
+
+    assert_eq!((rules[0].action, rules[0].family, rules[0].port_lo, rules[0].port_hi), (NET_ACTION_ALLOW, NET_FAMILY_ANY, 0, 65_535));
+    assert_eq!(&rules[0].process[..rules[0].process_len as usize], br"*\tools\*.exe");
+
+    // The host bits are dropped.
+    assert_eq!((rules[1].action, rules[1].family, rules[1].prefix), (NET_ACTION_BLOCK, NET_FAMILY_V4, 8));
+    assert_eq!(rules[1].addr[..4], [10, 0, 0, 0]);
+    assert_eq!((rules[1].port_lo, rules[1].port_hi, rules[1].process_len), (139, 445, 0));
+
+    assert_eq!((rules[2].family, rules[2].prefix, rules[2].port_lo, rules[2].port_hi), (NET_FAMILY_V6, 32, 80, 80));
+    assert_eq!(rules[2].addr[..4], [0x20, 0x01, 0x0d, 0xb8]);
+}
+
+#[test]
+fn rules_are_read_and_checked_with_the_config() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let base = std::fs::read_to_string(root.join("config.toml")).unwrap();
+
+    let cfg = parse(&format!("{base}\n[[network_policy]]\nid = \"smb\"\naction = \"block\"\ndst = \"10.0.0.0/8\"\nports = \"445\"\n")).unwrap();
+    assert_eq!(cfg.network_policy, [rule("smb", PolicyAction::Block, Some("10.0.0.0/8"), Some("445"), None)]);
+
+    let bad = format!("{base}\n[[network_policy]]\nid = \"smb\"\naction = \"block\"\ndst = \"10.0.0/8\"\n");
+    match parse(&bad) {
+        Err(ConfigError::InvalidValue(field, _)) => assert_eq!(field, "network_policy[smb].dst"),
+        other => panic!("expected InvalidValue, got {other:?}"),
+    }
+    assert!(parse(&format!("{base}\n[[network_policy]]\nid = \"x\"\naction = \"deny\"\n")).is_err());
+}
+
+#[test]
+fn blocked_connections_are_stored_with_the_block_verdict() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut db_cfg = load(&root.join("config.toml")).unwrap().database;
+    db_cfg.flush_interval_ms = 50;
+    let dir = tempdir().unwrap();
+    let conn = init_database(dir.path(), &db_cfg).unwrap();
+
+    // Two connections in the ring: one the policy blocks, one it allows.
+    let payloads: Vec<Vec<u8>> = [connection("10.9.9.9", 445, ""), connection("93.184.216.34", 443, "")]
+        .iter()
+        .map(Message::encode_to_vec)
+        .collect();
+    let ring_path = dir.path().join("network_ring");
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&ring_path).unwrap();
+    file.set_len((ring::HEADER_SIZE + 1_024) as u64).unwrap();
+    let mut mmap = unsafe { MmapOptions::new().map_mut(&file).unwrap() };
+    unsafe { (mmap.as_mut_ptr() as *mut RingHeader).write(RingHeader::new()) };
+    {
+        let (header, data) = mmap.split_at_mut(ring::HEADER_SIZE);
+        let header = unsafe { &*(header.as_ptr() as *const RingHeader) };
+        for (seq, payload) in (1..).zip(&payloads) {
+            assert!(ring::push(header, data, seq, 0, payload));
+        }
+    }
+
+    let policy = Arc::new(
+        NetPolicy::compile(&[rule("smb", PolicyAction::Block, Some("10.0.0.0/8"), Some("445"), None)]).unwrap(),
+    );
+    let rt = Runtime::new().unwrap();
+    let shutdown = Shutdown::new();
+    let (intel_tx, mut intel_rx) = broadcast::channel::<WrappedEvent<NetworkEvent>>(8);
+    rt.block_on(async {
+        let (db_tx, db_rx) = mpsc::channel::<WrappedEvent<NetworkEvent>>(8);
+        spawn_writer(&rt, conn, db_rx, &db_cfg, &shutdown);
+        let ring = MemoryRing::open(&ring_path).unwrap();
+        let listener = Arc::new(RingListener::new("network", ring, "POLICY").judged(Arc::new(
+            move |ev: &mut NetworkEvent| {
+                policy.apply(ev);
+            },
+        )));
+        listener.spawn(Buses { db_tx: db_tx.into(), intel_tx }, &shutdown);
+    });
+
+    let db = Connection::open(db_path(dir.path(), &db_cfg)).unwrap();
+    // Empty until the writer has created the table.
+    let verdicts = || -> Vec<(String, String)> {
+        let Ok(mut stmt) = db.prepare("SELECT dst_ip, verdict FROM network_events ORDER BY id") else {
+            return Vec::new();
+        };
+        stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?))).unwrap().collect::<Result<_, _>>().unwrap()
+    };
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while verdicts().len() < 2 && Instant::now() < deadline {
+        sleep(Duration::from_millis(50));
+    }
+    assert_eq!(verdicts(), [("10.9.9.9".into(), "block".into()), ("93.184.216.34".into(), "allow".into())]);
+
+    // Detections on the intel bus see the same verdict.
+    assert!(intel_rx.try_recv().unwrap().payload.blocked);
+    assert!(!intel_rx.try_recv().unwrap().payload.blocked);
+    shutdown.trigger();
+}
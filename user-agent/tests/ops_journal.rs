@@ -0,0 +1,187 @@
+// tests/ops_journal.rs
+//
+// Each subsystem leaves its ops journal entries under one correlation id,
+// and volume drops in the metrics history point back at them.
+
+mod common;
+
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::sleep,
+    time::{Duration, Instant, SystemTime},
+};
+use anyhow::bail;
+use chrono::{TimeZone, Utc};
+use rusqlite::Connection;
+use serde_json::json;
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::mpsc};
+
+use agent::{
+    config::canonical::canonicalize,
+    db::{
+        connection::init_database,
+        ops_journal::{self, record, since, volume_drops, Actor, Entry, Journal, Subsystem},
+        spawn_writer,
+    },
+    health::{spawn_watchdog, Component, HealthRegistry, Startup},
+    metrics_history::Snapshot,
+    util::{RetryPolicy, Shutdown, Tasks},
+};
+use shared::events::ScanResult;
+use common::wrap;
+
+fn all(path: &Path) -> Vec<Entry> {
+    since(&Connection::open(path).unwrap(), 0).unwrap()
+}
+
+#[test]
+fn config_applies_share_one_correlation() {
+    let conn = Connection::open_in_memory().unwrap();
+    let cfg = agent::config::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml")).unwrap();
+    let mut applied = canonicalize(&cfg);
+
+    let first = ops_journal::record_config(&conn, &applied, Actor::Service).unwrap().unwrap();
+    assert!(ops_journal::record_config(&conn, &applied, Actor::Service).unwrap().is_none(), "unchanged");
+
+    applied["actions"]["enabled"] = json!(true);
+    applied["database"]["ttl_seconds"] = json!(86_400);
+    applied["database"]["batch_size"] = json!(7);
+    let id = ops_journal::record_config(&conn, &applied, Actor::Cli).unwrap().unwrap();
+    assert_ne!(id, first);
+
+    let entries = ops_journal::correlated(&conn, &id).unwrap();
+    let kinds: Vec<_> = entries.iter().map(|e| (e.subsystem, e.actor.clone())).collect();
+    assert_eq!(kinds, [
+        (Subsystem::Config, Actor::Cli),
+        (Subsystem::Prevention, Actor::Cli),
+        (Subsystem::Retention, Actor::Cli),
+    ]);
+    assert_eq!(entries[0].summary, "3 keys changed: actions.enabled, database.batch_size, database.ttl_seconds");
+    assert!(entries[0].detail["diff"].as_str().unwrap().contains("~ actions.enabled: false -> true"));
+    assert_eq!(entries[1].summary, "prevention enabled");
+    assert_eq!(entries[2].detail["changes"], json!([{ "key": "database.ttl_seconds", "from": cfg.database.ttl_seconds, "to": 86_400 }]));
+
+    let initial = ops_journal::correlated(&conn, &first).unwrap();
+    assert_eq!(initial.len(), 1);
+    assert!(initial[0].summary.starts_with("initial config "), "{}", initial[0].summary);
+    assert_eq!(since(&conn, 0).unwrap().len(), 4);
+}
+
+#[test]
+fn watchdog_restarts_are_journaled() {
+    let dir = tempdir().unwrap();
+    let db_cfg = common::db_cfg();
+    let path = dir.path().join(&db_cfg.path);
+    drop(init_database(dir.path(), &db_cfg).unwrap());
+
+    let broken = Arc::new(AtomicBool::new(true));
+    let health = HealthRegistry::new();
+    let report = Startup::new(health.clone())
+        .component(Component::Metrics, {
+            let broken = broken.clone();
+            move || if broken.load(Ordering::SeqCst) { bail!("port in use") } else { Ok(()) }
+        })
+        .run()
+        .unwrap();
+    let handle = spawn_watchdog(
+        report,
+        RetryPolicy::new("watchdog", Duration::from_millis(20)),
+        Journal::new(path.clone(), &db_cfg),
+    )
+    .unwrap();
+    sleep(Duration::from_millis(50));
+    broken.store(false, Ordering::SeqCst);
+    handle.join().unwrap();
+
+    let entries = all(&path);
+    assert_eq!(entries.len(), 2, "{entries:#?}");
+    assert!(entries.iter().all(|e| e.subsystem == Subsystem::Watchdog && e.actor == Actor::Automatic));
+    assert_eq!(entries[0].correlation, entries[1].correlation);
+    assert_eq!(entries[0].detail["error"], "port in use");
+    assert!(entries[1].summary.starts_with("'metrics' restarted after "), "{}", entries[1].summary);
+}
+
+#[test]
+fn writer_pressure_episodes_are_journaled() {
+    let dir = tempdir().unwrap();
+    let mut db_cfg = common::db_cfg();
+    let path = dir.path().join(&db_cfg.path);
+    db_cfg.batch_size = 2;
+    db_cfg.flush_interval_ms = 50;
+    let conn = init_database(dir.path(), &db_cfg).unwrap();
+
+    let rt = Runtime::new().unwrap();
+    let (tx, rx) = mpsc::channel(64);
+    let drain = Shutdown::new();
+    let writers = Tasks::new();
+    writers.push(spawn_writer(&rt, conn, rx, &db_cfg, &drain));
+    for i in 0..10 {
+        tx.blocking_send(wrap(SystemTime::now(), ScanResult { file_path: format!("{i}.exe"), ..Default::default() }))
+        .unwrap();
+    }
+
+    // The next timer flush ends the episode.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while all(&path).len() < 2 && Instant::now() < deadline {
+        sleep(Duration::from_millis(20));
+    }
+    drain.trigger();
+    assert_eq!(rt.block_on(writers.join(tokio::time::Instant::now() + Duration::from_secs(5))), 0);
+
+    let entries = all(&path);
+    let summaries: Vec<_> = entries.iter().map(|e| e.summary.as_str()).collect();
+    assert_eq!(summaries, ["writers under pressure: background jobs back off", "writer pressure eased"]);
+    assert!(entries.iter().all(|e| e.subsystem == Subsystem::Pressure && e.actor == Actor::Automatic));
+    assert_eq!(entries[0].correlation, entries[1].correlation);
+    assert_eq!(entries[0].detail["table"], "scan_results");
+}
+
+fn snapshot(secs: i64, events: f64, fill: f64) -> Snapshot {
+    Snapshot {
+        ts:   Utc.timestamp_opt(1_700_000_000 + secs, 0).unwrap(),
+        text: format!(
+            "events_received_total{{type=\"process\"}} {events}\n\
+             events_received_total{{type=\"file\"}} 0\n\
+             ring_fill_ratio{{ring=\"process\"}} {fill}\n"
+        ),
+    }
+}
+
+#[test]
+fn volume_drops_point_at_the_change_before_them() {
+    // 10 events/s until 120s, then 1 event/s.
+    let snaps = [snapshot(0, 0.0, 0.1), snapshot(60, 600.0, 0.1), snapshot(120, 1200.0, 0.2), snapshot(180, 1260.0, 0.9)];
+    let entry = |secs: i64, summary: &str| {
+        let mut e = Entry::new(Subsystem::Config, Actor::Cli, "c-1", summary, json!({}));
+        e.ts = (1_700_000_000 + secs) * 1_000_000;
+        e
+    };
+    let entries = [entry(10, "too early"), entry(100, "1 key changed: filters.process"), entry(200, "after")];
+
+    let drops = volume_drops(&snaps, &entries, 0.5, Duration::from_secs(5 * 60));
+    assert_eq!(drops.len(), 1, "{drops:#?}");
+    let d = &drops[0];
+    assert_eq!((d.at, d.before, d.after, d.ring_fill), (snaps[3].ts, 10.0, 1.0, Some(0.9)));
+    assert_eq!(d.cause.as_ref().unwrap().summary, "1 key changed: filters.process");
+    assert_eq!(
+        d.to_string(),
+        "2023-11-14T22:16:20Z  volume dropped 90% (10.0 -> 1.0 events/s), ring fill 0.90; \
+         1m 20s after config by cli: 1 key changed: filters.process [c-1]"
+    );
+
+    // Nothing in the lookback window; a counter reset is not a drop.
+    let drops = volume_drops(&snaps, &entries[..1], 0.5, Duration::from_secs(60));
+    assert!(drops[0].cause.is_none());
+    let reset = [snapshot(0, 0.0, 0.0), snapshot(60, 600.0, 0.0), snapshot(120, 5.0, 0.0), snapshot(180, 10.0, 0.0)];
+    assert!(volume_drops(&reset, &entries, 0.5, Duration::from_secs(60)).is_empty());
+
+    // Persisted entries come back as recorded.
+    let conn = Connection::open_in_memory().unwrap();
+    let id = record(&conn, &entries[1]).unwrap();
+    assert_eq!(since(&conn, 0).unwrap(), [Entry { id, ..entries[1].clone() }]);
+}
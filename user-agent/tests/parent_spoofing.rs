@@ -0,0 +1,150 @@
+// tests/parent_spoofing.rs
+
+mod common;
+
+use std::{path::PathBuf, time::Duration};
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::broadcast};
+use shared::events::ProcessEvent;
+
+use agent::{
+    actions::Actions,
+    comms::WrappedEvent,
+    config::{load, model::ParentSpoofingConfig},
+    db::connection::init_database,
+    intel::{
+        analytics::{parent_spoofing::RULE_ID, spawn_parent_spoofing, ParentSpoofing},
+        AlertSink, ProcessTable, Severity,
+    },
+};
+use common::{seconds, wrap};
+
+fn spawn(secs: i64, pid: u32, ppid: u32, creator: u32, image: &str) -> WrappedEvent<ProcessEvent> {
+    wrap(seconds(secs), ProcessEvent {
+        pid,
+        ppid,
+        image_path: image.into(),
+        cmdline: String::new(),
+        creator_pid: creator,
+        creator_tid: creator * 10,
+        ..ProcessEvent::default()
+    })
+}
+
+/// Table as it would look after boot: explorer, services and an attacker.
+fn table(events: &[WrappedEvent<ProcessEvent>]) -> ProcessTable {
+    let table = ProcessTable::new(64);
+    for ev in events {
+        table.record(&ev.payload, ev.ts_micros());
+    }
+    table
+}
+
+fn boot() -> Vec<WrappedEvent<ProcessEvent>> {
+    vec![
+        spawn(1, 600, 500, 500, r"C:\Windows\System32\services.exe"),
+        spawn(2, 900, 800, 800, r"C:\Windows\explorer.exe"),
+        spawn(3, 1200, 900, 900, r"C:\Users\bob\Downloads\loader.exe"),
+    ]
+}
+
+#[test]
+fn creator_image_is_resolved_through_the_pid_map() {
+    let t = table(&boot());
+    assert_eq!(t.image_path(600).as_deref(), Some(r"C:\Windows\System32\services.exe"));
+    assert_eq!(t.get(1200).unwrap().ppid, 900);
+    assert!(t.image_path(4).is_none());
+
+    // Bounded: the oldest process is evicted first, pid reuse replaces.
+    let small = ProcessTable::new(2);
+    for ev in boot() {
+        small.record(&ev.payload, ev.ts_micros());
+    }
+    assert_eq!(small.len(), 2);
+    assert!(small.get(600).is_none());
+    small.record(&spawn(9, 900, 1, 1, "reused.exe").payload, 9_000_000);
+    assert_eq!(small.image_path(900).as_deref(), Some("reused.exe"));
+}
+
+#[test]
+fn brokered_creations_are_allow_listed() {
+    let analytic = ParentSpoofing::new(&ParentSpoofingConfig::default());
+    let t = table(&boot());
+
+    // Same parent and creator, or no creator reported (old driver).
+    assert!(analytic.check(&spawn(10, 2000, 900, 900, "notepad.exe"), &t).is_none());
+    assert!(analytic.check(&spawn(10, 2001, 900, 0, "notepad.exe"), &t).is_none());
+    // services.exe starting a service on behalf of the SCM client.
+    assert!(analytic.check(&spawn(10, 2002, 900, 600, "svc.exe"), &t).is_none());
+
+    assert!(analytic.is_broker(r"C:\WINDOWS\system32\SVCHOST.EXE"));
+    let strict = ParentSpoofing::new(&ParentSpoofingConfig {
+        enabled: true,
+        brokers: vec![r"C:\Windows\System32\services.exe".into()],
+    });
+    assert!(strict.is_broker(r"c:\windows\system32\services.exe"));
+    assert!(!strict.is_broker(r"C:\Temp\services.exe"));
+}
+
+#[test]
+fn spoofed_parent_raises_one_alert() {
+    let analytic = ParentSpoofing::new(&ParentSpoofingConfig::default());
+    let t = table(&boot());
+
+    // loader.exe creates a child that claims explorer.exe as its parent.
+    let alert = analytic
+        .check(&spawn(10, 3000, 900, 1200, r"C:\Windows\System32\rundll32.exe"), &t)
+        .expect("spoofing must be flagged");
+    assert_eq!(alert.rule_id, RULE_ID);
+    assert_eq!(alert.severity, Severity::High);
+    assert_eq!((alert.pid, alert.ppid), (3000, Some(900)));
+    assert!(alert.message.contains("explorer.exe") && alert.message.contains("loader.exe"), "{}", alert.message);
+
+    // Creator unknown to the agent: still flagged, lower confidence.
+    let alert = analytic.check(&spawn(10, 3001, 900, 4444, "x.exe"), &t).unwrap();
+    assert_eq!(alert.severity, Severity::Medium);
+}
+
+#[test]
+fn analytic_task_stores_exactly_one_alert_per_spoofed_process() {
+    let rt   = Runtime::new().unwrap();
+    let dir  = tempdir().unwrap();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut db = load(&root.join("config.toml")).unwrap().database;
+    db.path = "telemetry.db".into();
+    drop(init_database(dir.path(), &db).unwrap());
+
+    let (tx, _) = broadcast::channel(64);
+    let table = ProcessTable::default();
+    let task = spawn_parent_spoofing(
+        &rt,
+        tx.subscribe().into(),
+        table.clone(),
+        ParentSpoofing::new(&ParentSpoofingConfig::default()),
+        AlertSink::new(dir.path().join("telemetry.db"), Actions::disabled()),
+    );
+
+    let mut stream = boot();
+    stream.push(spawn(10, 3000, 900, 1200, "rundll32.exe"));    // spoofed
+    stream.push(spawn(11, 3001, 900, 600, "svc.exe"));          // brokered
+    stream.push(spawn(12, 3002, 1200, 1200, "child.exe"));      // plain
+    for ev in stream {
+        assert!(tx.send(ev).is_ok());
+    }
+    drop(tx);
+    rt.block_on(async { tokio::time::timeout(Duration::from_secs(5), task).await })
+        .expect("analytic ends when the bus closes")
+        .unwrap();
+
+    assert_eq!(table.len(), 6);
+    let conn = Connection::open(dir.path().join("telemetry.db")).unwrap();
+    let rows: Vec<(String, i64, String)> = conn
+        .prepare("SELECT rule_id, pid, severity FROM alerts")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap();
+    assert_eq!(rows, vec![(RULE_ID.to_string(), 3000, "high".to_string())]);
+}
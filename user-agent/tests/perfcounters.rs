@@ -0,0 +1,90 @@
+// tests/perfcounters.rs
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use metrics::{counter, gauge, histogram, with_local_recorder, NoopRecorder};
+
+use agent::{
+    health::{Component, HealthRegistry},
+    perfcounters::{adapter::Kind, perflib::MANIFEST, CounterSet, PerfRecorder, PerfSink, COUNTERS},
+};
+
+#[derive(Default)]
+struct MockSink(Mutex<HashMap<(CounterSet, String, u32), u64>>);
+
+impl PerfSink for MockSink {
+    fn set(&self, set: CounterSet, instance: &str, counter: u32, value: u64) {
+        self.0.lock().unwrap().insert((set, instance.to_owned(), counter), value);
+    }
+}
+
+impl MockSink {
+    fn get(&self, set: CounterSet, instance: &str, counter: u32) -> Option<u64> {
+        self.0.lock().unwrap().get(&(set, instance.to_owned(), counter)).copied()
+    }
+}
+
+fn recorder() -> (PerfRecorder<NoopRecorder>, Arc<MockSink>) {
+    let sink = Arc::new(MockSink::default());
+    (PerfRecorder::new(NoopRecorder, sink.clone()), sink)
+}
+
+#[test]
+fn metrics_map_to_counter_instances() {
+    let (recorder, sink) = recorder();
+    with_local_recorder(&recorder, || {
+        counter!("events_received_total", "type" => "process").increment(3);
+        counter!("events_received_total", "type" => "process").increment(2);
+        counter!("events_received_total", "type" => "fs").increment(1);
+        gauge!("ring_fill_ratio", "ring" => "process").set(0.25);
+        histogram!("db_flush_duration_seconds").record(0.0125);
+        // Different rules share the single-instance alert counter.
+        counter!("alerts_raised_total", "rule" => "a").increment(1);
+        counter!("alerts_raised_total", "rule" => "b").increment(4);
+        counter!("db_compress_bytes_saved_total").increment(100);
+    });
+
+    assert_eq!(sink.get(CounterSet::Pipeline, "process", 1), Some(5));
+    assert_eq!(sink.get(CounterSet::Pipeline, "fs", 1), Some(1));
+    assert_eq!(sink.get(CounterSet::Pipeline, "process", 2), Some(250), "per mille");
+    assert_eq!(sink.get(CounterSet::Agent, "_Total", 1), Some(12_500), "microseconds");
+    assert_eq!(sink.get(CounterSet::Agent, "_Total", 3), Some(5));
+    assert_eq!(sink.0.lock().unwrap().len(), 5, "unmapped metrics are not published");
+}
+
+#[test]
+fn component_health_is_numeric() {
+    let (recorder, sink) = recorder();
+    with_local_recorder(&recorder, || {
+        let health = HealthRegistry::new();
+        health.register(Component::Scanner);
+        assert_eq!(sink.get(CounterSet::Components, "scanner", 1), Some(0));
+        health.mark_degraded(Component::Scanner, "boom");
+        assert_eq!(sink.get(CounterSet::Components, "scanner", 1), Some(2));
+        health.mark_healthy(Component::Scanner);
+        assert_eq!(sink.get(CounterSet::Components, "scanner", 1), Some(1));
+    });
+}
+
+#[test]
+fn manifest_declares_every_counter() {
+    for set in CounterSet::ALL {
+        let uri = format!("uri=\"Gladix.{set:?}\"");
+        let start = MANIFEST.find(&uri).unwrap_or_else(|| panic!("{set:?} missing"));
+        let block = &MANIFEST[start..start + MANIFEST[start..].find("</counterSet>").unwrap()];
+        let instances = if set.is_multi_instance() { "multiple" } else { "single" };
+        assert!(block.contains(&format!("instances=\"{instances}\"")), "{set:?}");
+
+        let defs: Vec<_> = COUNTERS.iter().filter(|d| d.set == set).collect();
+        assert_eq!(block.matches("<counter ").count(), defs.len(), "{set:?}");
+        for (i, d) in defs.iter().enumerate() {
+            assert_eq!(d.id as usize, i + 1, "{set:?} ids are contiguous");
+            let decl = &block[block.find(&format!("<counter id=\"{}\"", d.id)).unwrap()..];
+            let decl = &decl[..decl.find("/>").unwrap()];
+            let ty = if d.kind == Kind::Rate { "perf_counter_bulk_count" } else { "perf_counter_large_rawcount" };
+            assert!(decl.contains(ty), "{} should be {ty}", d.metric);
+        }
+    }
+}
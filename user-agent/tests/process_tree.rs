@@ -0,0 +1,129 @@
+// tests/process_tree.rs
+//
+// Ancestry and descendants rebuilt from stored process creations: a pid is
+// the process whose creation is the latest before the time asked about, a
+// parent missing from the database ends the chain, and chains looping
+// through a reused pid end.
+
+mod common;
+
+use rusqlite::Connection;
+
+use agent::{
+    comms::WrappedEvent,
+    db::{
+        batch_inserts::BatchInsert,
+        codec::Codec,
+        process_tree::{ancestors, creation, descendants, ProcessNode},
+        query::{self, render_table, TreeQuery},
+        schema_registry::ensure_for,
+    },
+};
+use shared::events::ProcessEvent;
+use common::{seconds, wrap};
+
+const SEC: i64 = 1_000_000;
+
+/// Stores the creation of `pid` by `ppid` at `secs`.
+fn spawn(conn: &Connection, secs: i64, pid: u32, ppid: u32, image: &str) {
+    ensure_for(conn, <WrappedEvent<ProcessEvent>>::schema()).unwrap();
+    let ev = wrap(seconds(secs), ProcessEvent { pid, ppid, image_path: image.into(), cmdline: image.into(), ..Default::default() });
+    let mut stmt = conn.prepare(<WrappedEvent<ProcessEvent>>::insert_sql()).unwrap();
+    <WrappedEvent<ProcessEvent>>::bind_and_execute(&mut stmt, &ev, &mut Codec::disabled()).unwrap();
+}
+
+/// explorer (100) → cmd (200) → powershell (300); pid 4 was never stored.
+fn chain() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    spawn(&conn, 10, 100, 4, "explorer.exe");
+    spawn(&conn, 20, 200, 100, "cmd.exe");
+    spawn(&conn, 30, 300, 200, "powershell.exe");
+    conn
+}
+
+fn pids_and_depths(nodes: &[ProcessNode]) -> Vec<(u32, &str, u32)> {
+    nodes.iter().map(|n| (n.pid, n.image_path.as_str(), n.depth)).collect()
+}
+
+#[test]
+fn three_level_chain_is_followed_both_ways() {
+    let conn = chain();
+    assert_eq!(
+        pids_and_depths(&ancestors(&conn, 300, 35 * SEC).unwrap()),
+        [(300, "powershell.exe", 0), (200, "cmd.exe", 1), (100, "explorer.exe", 2)],
+    );
+    assert_eq!(
+        pids_and_depths(&descendants(&conn, 100, 35 * SEC, 8).unwrap()),
+        [(100, "explorer.exe", 0), (200, "cmd.exe", 1), (300, "powershell.exe", 2)],
+    );
+    assert_eq!(pids_and_depths(&descendants(&conn, 100, 35 * SEC, 1).unwrap()).len(), 2);
+
+    // The chain ends at explorer, whose parent was not stored.
+    let top = ancestors(&conn, 100, 35 * SEC).unwrap();
+    assert_eq!((top.len(), top[0].ppid), (1, 4));
+    // Nothing is known of a pid before its creation.
+    assert!(ancestors(&conn, 300, 25 * SEC).unwrap().is_empty());
+    assert_eq!(creation(&conn, 4, 35 * SEC).unwrap(), None);
+}
+
+#[test]
+fn reused_pids_resolve_by_time() {
+    let conn = chain();
+    // cmd exits and its pid comes back as notepad, which starts 400.
+    spawn(&conn, 50, 200, 100, "notepad.exe");
+    spawn(&conn, 60, 400, 200, "calc.exe");
+
+    assert_eq!(
+        pids_and_depths(&ancestors(&conn, 400, 65 * SEC).unwrap()),
+        [(400, "calc.exe", 0), (200, "notepad.exe", 1), (100, "explorer.exe", 2)],
+    );
+    assert_eq!(
+        pids_and_depths(&ancestors(&conn, 300, 65 * SEC).unwrap()),
+        [(300, "powershell.exe", 0), (200, "cmd.exe", 1), (100, "explorer.exe", 2)],
+    );
+    assert_eq!(
+        pids_and_depths(&descendants(&conn, 100, 65 * SEC, 8).unwrap()),
+        [
+            (100, "explorer.exe", 0),
+            (200, "cmd.exe", 1),
+            (300, "powershell.exe", 2),
+            (200, "notepad.exe", 1),
+            (400, "calc.exe", 2),
+        ],
+    );
+    assert_eq!(creation(&conn, 200, 40 * SEC).unwrap().unwrap().image_path, "cmd.exe");
+    assert_eq!(creation(&conn, 200, 50 * SEC).unwrap().unwrap().image_path, "notepad.exe");
+}
+
+#[test]
+fn loops_through_reused_pids_end() {
+    let conn = Connection::open_in_memory().unwrap();
+    // 500 names 600 as parent, and a later 600 names 500.
+    spawn(&conn, 70, 500, 600, "a.exe");
+    spawn(&conn, 80, 600, 500, "b.exe");
+    // A process that is its own parent, as pid 0 reports.
+    spawn(&conn, 90, 700, 700, "idle");
+
+    assert_eq!(pids_and_depths(&ancestors(&conn, 600, 95 * SEC).unwrap()), [(600, "b.exe", 0), (500, "a.exe", 1)]);
+    assert_eq!(pids_and_depths(&descendants(&conn, 500, 95 * SEC, 100).unwrap()), [(500, "a.exe", 0), (600, "b.exe", 1)]);
+    assert_eq!(pids_and_depths(&ancestors(&conn, 700, 95 * SEC).unwrap()), [(700, "idle", 0)]);
+    assert_eq!(pids_and_depths(&descendants(&conn, 700, 95 * SEC, 100).unwrap()), [(700, "idle", 0)]);
+}
+
+#[test]
+fn query_tree_indents_ancestors_then_descendants() {
+    let conn = chain();
+    spawn(&conn, 40, 310, 200, "conhost.exe");
+    let rows = query::tree(&conn, &TreeQuery { pid: 200, at: Some(45 * SEC), depth: None }).unwrap();
+    let shown: Vec<_> = rows.iter().map(|r| (r.level, r.pid)).collect();
+    assert_eq!(shown, [(0, 100), (1, 200), (2, 300), (2, 310)]);
+    assert_eq!(rows[0].ts, "1970-01-01T00:00:10.000000Z");
+
+    let table = render_table(&rows);
+    let lines: Vec<_> = table.lines().collect();
+    assert!(lines[0].starts_with("ts") && lines[3].contains("    300"), "{table}");
+    assert!(query::tree(&conn, &TreeQuery { pid: 999, ..Default::default() }).unwrap().is_empty());
+    assert!(query::tree(&Connection::open_in_memory().unwrap(), &TreeQuery { pid: 1, ..Default::default() })
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,194 @@
+// tests/provisioning.rs
+//
+// Unattended setup against the fixture profiles in tests/fixtures/profiles.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use tempfile::tempdir;
+
+use agent::config::{
+    load,
+    model::DirectoryRisk,
+    provision::{
+        apply, expand, load_or_create_sensor_guid, plan, read_sensor_guid, suggested, Host, Outcome, Profile, ProvisionError, Target,
+        BASELINE_FILE, SENSOR_GUID_FILE, TEMPLATE,
+    },
+};
+
+struct FakeHost {
+    vars: HashMap<&'static str, String>,
+    free: Option<u64>,
+}
+
+impl Host for FakeHost {
+    fn var(&self, name: &str) -> Option<String> {
+        self.vars.get(name).cloned()
+    }
+
+    fn free_bytes(&self, _dir: &Path) -> Option<u64> {
+        self.free
+    }
+}
+
+fn host(root: &Path) -> FakeHost {
+    let vars = [
+        ("PROGRAMDATA", "ProgramData"),
+        ("USERPROFILE", "Users/ana"),
+        ("PUBLIC", "Users/Public"),
+        ("PROGRAMFILES", "Program Files"),
+        ("SYSTEMROOT", "Windows"),
+        ("TEMP", "Windows/Temp"),
+    ]
+    .map(|(k, v)| (k, root.join(v).to_string_lossy().into_owned()));
+    FakeHost { vars: HashMap::from(vars), free: Some(10 << 30) }
+}
+
+fn fixture(name: &str) -> Profile {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    Profile::read(&root.join("tests/fixtures/profiles").join(name)).unwrap()
+}
+
+fn outcomes(steps: &[agent::config::provision::Step]) -> Vec<(Target, &Outcome)> {
+    steps.iter().map(|s| (s.target, &s.outcome)).collect()
+}
+
+#[test]
+fn workstation_profile_on_a_fresh_host() {
+    let dir = tempdir().unwrap();
+    let host = host(dir.path());
+    let config = dir.path().join("config.toml");
+    let profile = fixture("workstation.toml");
+
+    let first = plan(&profile, &config, &host).unwrap();
+    let steps = apply(&first).unwrap();
+    assert_eq!(outcomes(&steps), [
+        (Target::Config, &Outcome::Created),
+        (Target::DatabaseDir, &Outcome::Created),
+        (Target::SensorGuid, &Outcome::Created),
+        (Target::Baseline, &Outcome::Created),
+    ]);
+
+    let cfg = load(&config).unwrap();
+    assert!(!cfg.metrics.listen);
+    assert!(!cfg.actions.enabled);
+    assert_eq!(cfg.database.path, dir.path().join("ProgramData/Gladix/telemetry.db").to_string_lossy());
+    assert_eq!(cfg.scanner.len(), 3);
+    assert_eq!(cfg.scanner[0].risk, DirectoryRisk::High);
+    assert_eq!(cfg.scanner[0].directories[1], dir.path().join("Users/Public"));
+    assert_eq!(cfg.scanner[2].interval, None);
+
+    // The template's other settings and comments come along.
+    let text = fs::read_to_string(&config).unwrap();
+    assert!(text.contains("# Master switch for every action below"));
+    assert!(text.contains("# ─── Scanner: use an array of tables! ─────────────────────"));
+    assert!(!text.contains("Noel"));
+    assert_eq!(read_sensor_guid(dir.path()), Some(first.sensor_guid.clone()));
+    assert!(dir.path().join(BASELINE_FILE).is_file());
+
+    // Second run: nothing to do, same GUID.
+    let second = plan(&profile, &config, &host).unwrap();
+    assert!(second.is_noop(), "{}", second.report());
+    assert_eq!(second.sensor_guid, first.sensor_guid);
+    apply(&second).unwrap();
+    assert_eq!(fs::read_to_string(&config).unwrap(), text);
+}
+
+#[test]
+fn server_profile_over_an_existing_config() {
+    let dir = tempdir().unwrap();
+    let host = host(dir.path());
+    let config = dir.path().join("config.toml");
+    fs::write(&config, TEMPLATE).unwrap();
+    let profile = fixture("server.json");
+
+    let first = plan(&profile, &config, &host).unwrap();
+    let Outcome::Changed(detail) = &first.steps[0].outcome else { panic!("{}", first.report()) };
+    assert!(detail.contains("~ database.path: \"telemetry.db\" -> \"data/telemetry.db\""), "{detail}");
+    assert!(detail.contains("~ actions.enabled: false -> true"), "{detail}");
+    apply(&first).unwrap();
+    assert!(dir.path().join("data").is_dir());
+    // Pinned GUIDs are stored in canonical form.
+    assert_eq!(read_sensor_guid(dir.path()).unwrap(), "3f2504e0-4f89-11d3-9a0c-0305e82c3301");
+
+    let cfg = load(&config).unwrap();
+    assert!(cfg.actions.enabled);
+    assert!(cfg.scanner[0].hydrate_placeholders);
+    assert_eq!(cfg.scanner[0].directories, [PathBuf::from(format!("{}\\Drop", host.vars["PROGRAMDATA"]))]);
+    // Comments on changed values survive.
+    let text = fs::read_to_string(&config).unwrap();
+    let line = text.lines().find(|l| l.contains("# Master switch")).unwrap();
+    assert!(line.starts_with("enabled = true "), "{line}");
+
+    assert!(plan(&profile, &config, &host).unwrap().is_noop());
+
+    // Only what the profile changes is reported.
+    let relaxed = Profile { prevention: false, grpc_bind: "127.0.0.1:50051".into(), ..profile };
+    let third = plan(&relaxed, &config, &host).unwrap();
+    let Outcome::Changed(detail) = &third.steps[0].outcome else { panic!("{}", third.report()) };
+    assert_eq!(
+        detail.as_str(),
+        "[actions]\n  ~ actions.enabled: true -> false  (restart)\n\
+         [communications]\n  ~ communications.allow_remote: true -> false  (restart)\n  ~ communications.grpc_bind: \"0.0.0.0:50051\" -> \"127.0.0.1:50051\"  (restart)\n"
+    );
+    assert!(third.steps[1..].iter().all(|s| s.outcome == Outcome::Unchanged));
+}
+
+#[test]
+fn placeholders() {
+    let host = FakeHost { vars: HashMap::from([("PROGRAMDATA", r"C:\ProgramData".to_owned())]), free: None };
+    assert_eq!(expand(r"%PROGRAMDATA%\Gladix", &host).unwrap(), r"C:\ProgramData\Gladix");
+    assert_eq!(expand("100%% local", &host).unwrap(), "100% local");
+    assert!(matches!(expand("%APPDATA%", &host), Err(ProvisionError::UnknownPlaceholder(_, v)) if v == "APPDATA"));
+    assert!(matches!(expand("50%", &host), Err(ProvisionError::Unterminated(_))));
+
+    // Suggestions leave out what this host cannot resolve.
+    let profile = suggested(&host);
+    assert_eq!(profile.database, r"C:\ProgramData\Gladix\telemetry.db");
+    assert_eq!(profile.scanner[1].directories, [r"C:\ProgramData"]);
+    assert!(profile.scanner[0].directories.is_empty());
+}
+
+#[test]
+fn bad_profiles_write_nothing() {
+    let dir = tempdir().unwrap();
+    let config = dir.path().join("config.toml");
+    let mut host = host(dir.path());
+
+    host.free = Some(100 << 20);
+    let err = plan(&fixture("server.json"), &config, &host).unwrap_err();
+    assert!(matches!(err, ProvisionError::LowDiskSpace { free_mb: 100, min_mb: 2048, .. }), "{err}");
+
+    host.free = None;
+    let mut bad_risk = fixture("server.json");
+    bad_risk.scanner[0].risk = "Critical".into();
+    assert!(matches!(plan(&bad_risk, &config, &host), Err(ProvisionError::Config(_))));
+
+    let bad_guid = Profile { sensor_guid: Some("not-a-guid".into()), ..fixture("server.json") };
+    assert!(matches!(plan(&bad_guid, &config, &host), Err(ProvisionError::InvalidGuid(_))));
+
+    let unknown = dir.path().join("typo.toml");
+    fs::write(&unknown, "prevntion = true\n").unwrap();
+    assert!(matches!(Profile::read(&unknown), Err(ProvisionError::Profile(_))));
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn hosts_set_up_by_hand_get_a_kept_guid() {
+    let dir = tempdir().unwrap();
+    let first = load_or_create_sensor_guid(dir.path()).unwrap();
+    assert_eq!(read_sensor_guid(dir.path()), Some(first.clone()));
+    assert_eq!(load_or_create_sensor_guid(dir.path()).unwrap(), first, "generated once");
+
+    // One written by setup, in any case, is used as it is.
+    fs::write(dir.path().join(SENSOR_GUID_FILE), "3F2504E0-4F89-11D3-9A0C-0305E82C3301\r\n").unwrap();
+    assert_eq!(load_or_create_sensor_guid(dir.path()).unwrap(), "3f2504e0-4f89-11d3-9a0c-0305e82c3301");
+
+    // An unreadable one is replaced rather than stamped on events.
+    fs::write(dir.path().join(SENSOR_GUID_FILE), "00000000\u{2011}0000").unwrap();
+    let fresh = load_or_create_sensor_guid(dir.path()).unwrap();
+    assert!(fresh.is_ascii() && fresh.len() == 36, "{fresh}");
+    assert_eq!(read_sensor_guid(dir.path()), Some(fresh));
+}
@@ -0,0 +1,177 @@
+// tests/quarantine.rs
+//
+// Quarantine containers: round trips through the store, the pinned v1
+// fixture in tests/fixtures/quarantine, tamper detection and exports.
+
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use sha2::{Digest, Sha256};
+use tempfile::tempdir;
+
+use agent::actions::quarantine::{
+    container::{self, ContainerError},
+    Protector, Quarantine, QuarantineError, WrapKey, EXTENSION, MASTER_KEY_FILE,
+};
+
+/// Stands in for DPAPI: reversible, but never stores the key as is.
+struct Flip;
+
+impl Protector for Flip {
+    fn protect(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(data.iter().map(|b| !b).collect())
+    }
+
+    fn unprotect(&self, blob: &[u8]) -> io::Result<Vec<u8>> {
+        self.protect(blob)
+    }
+}
+
+fn store(root: &Path) -> Quarantine {
+    Quarantine::new(root.join("quarantine"), Arc::new(Flip))
+}
+
+fn fixture() -> Vec<u8> {
+    fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/quarantine/v1.gqf")).unwrap()
+}
+
+/// Key the fixture was sealed with.
+fn fixture_key() -> WrapKey {
+    WrapKey::from_bytes(&(0u8..32).collect::<Vec<_>>()).unwrap()
+}
+
+#[test]
+fn quarantine_and_restore() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("downloads/tool.exe");
+    fs::create_dir_all(file.parent().unwrap()).unwrap();
+    fs::write(&file, b"MZ not really a program").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+    }
+    let q = store(dir.path());
+
+    let header = q.quarantine(&file, Some(7)).unwrap();
+    assert!(!file.exists());
+    assert_eq!(header.alert_id, Some(7));
+    assert_eq!(header.sha256, hex::encode(Sha256::digest(b"MZ not really a program")));
+    let sealed = fs::read(q.path_of(&header.id).unwrap()).unwrap();
+    assert!(!sealed.windows(6).any(|w| w == b"really"), "content is stored encrypted");
+    assert_ne!(fs::read(q.dir().join(MASTER_KEY_FILE)).unwrap().len(), 0);
+    assert_eq!(q.list().unwrap(), std::slice::from_ref(&header));
+
+    // A file that took its place is not overwritten.
+    fs::write(&file, b"new").unwrap();
+    assert!(matches!(q.restore(&header.id), Err(QuarantineError::Exists(_))));
+    fs::remove_file(&file).unwrap();
+
+    assert_eq!(q.restore(&header.id).unwrap(), file);
+    assert_eq!(fs::read(&file).unwrap(), b"MZ not really a program");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+    assert!(q.list().unwrap().is_empty());
+    assert!(matches!(q.restore(&header.id), Err(QuarantineError::NotFound(_))));
+    assert!(matches!(q.restore("../master"), Err(QuarantineError::NotFound(_))));
+}
+
+#[test]
+fn v1_fixture_still_opens() {
+    let bytes = fixture();
+    let (header, content) = container::open(&bytes, &fixture_key()).unwrap();
+    assert_eq!(header.id, "0f6e3c2a-8d1b-4e7f-9a2c-5b4d3e2f1a0b");
+    assert_eq!(header.original_path, r"C:\Users\ana\Downloads\invoice.exe");
+    assert_eq!((header.alert_id, header.acl.as_slice()), (Some(42), &[1, 0, 4, 0x80][..]));
+    assert_eq!(content, b"MZ\x90\x00 quarantine fixture payload\n");
+    assert_eq!(container::peek(&bytes).unwrap(), header);
+
+    let other = WrapKey::generate();
+    assert!(matches!(container::open(&bytes, &other), Err(ContainerError::KeyUnwrap)));
+}
+
+#[test]
+fn every_corrupt_byte_is_detected() {
+    let bytes = fixture();
+    let key = fixture_key();
+    for i in 0..bytes.len() {
+        let mut bad = bytes.clone();
+        bad[i] ^= 0x01;
+        assert!(container::open(&bad, &key).is_err(), "flipped byte {i} went unnoticed");
+    }
+
+    // Each region reports itself.
+    let flipped = |i: usize| {
+        let mut bad = bytes.clone();
+        bad[i] ^= 0x01;
+        container::open(&bad, &key).unwrap_err()
+    };
+    let len = bytes.len();
+    assert!(matches!(flipped(0), ContainerError::BadMagic));
+    assert!(matches!(flipped(8), ContainerError::Version(0)));
+    assert!(matches!(flipped(len - 40), ContainerError::Content));
+    assert!(matches!(flipped(len - 1), ContainerError::Hmac));
+    assert!(matches!(container::open(&bytes[..len - 1], &key), Err(ContainerError::Truncated("HMAC"))));
+    assert!(matches!(container::open(&bytes[..100], &key), Err(ContainerError::Truncated("header"))));
+    let longer = [bytes.as_slice(), b"x"].concat();
+    assert!(matches!(container::open(&longer, &key), Err(ContainerError::TrailingBytes(1))));
+}
+
+#[test]
+fn corrupt_containers_are_not_restored() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("a.exe");
+    fs::write(&file, b"payload").unwrap();
+    let q = store(dir.path());
+    let header = q.quarantine(&file, None).unwrap();
+
+    let path = q.path_of(&header.id).unwrap();
+    let mut bytes = fs::read(&path).unwrap();
+    let n = bytes.len();
+    bytes[n - 40] ^= 0x80;
+    fs::write(&path, &bytes).unwrap();
+
+    let err = q.restore(&header.id).unwrap_err();
+    assert!(matches!(err, QuarantineError::Container { source: ContainerError::Content, .. }), "{err}");
+    assert!(!file.exists());
+    assert!(path.exists());
+}
+
+#[test]
+fn exports_with_metadata() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("b.dll");
+    fs::write(&file, b"library").unwrap();
+    let q = store(dir.path());
+    let header = q.quarantine(&file, Some(3)).unwrap();
+    let out = dir.path().join("upload");
+
+    // As stored: only this host's master key opens it.
+    let plain = q.export(&header.id, &out, None).unwrap();
+    assert_eq!(fs::read(&plain.container).unwrap(), fs::read(q.path_of(&header.id).unwrap()).unwrap());
+    let meta: serde_json::Value = serde_json::from_slice(&fs::read(&plain.metadata).unwrap()).unwrap();
+    assert_eq!(meta["key"], "master");
+    assert_eq!(meta["id"], header.id.as_str());
+    assert_eq!(meta["alert_id"], 3);
+    assert_eq!(meta["format_version"], container::VERSION);
+    assert_eq!(meta["container_sha256"], hex::encode(Sha256::digest(fs::read(&plain.container).unwrap())));
+
+    // Re-wrapped for an analysis server holding the transport key.
+    let transport = WrapKey::generate();
+    let shipped = q.export(&header.id, &out, Some(&transport)).unwrap();
+    assert_eq!(shipped.container.extension().unwrap(), EXTENSION);
+    let bytes = fs::read(&shipped.container).unwrap();
+    let (opened, content) = container::open(&bytes, &transport).unwrap();
+    assert_eq!((opened, content.as_slice()), (header.clone(), &b"library"[..]));
+    let meta: serde_json::Value = serde_json::from_slice(&fs::read(&shipped.metadata).unwrap()).unwrap();
+    assert_eq!(meta["key"], "transport");
+
+    // The quarantined copy is untouched.
+    assert_eq!(q.restore(&header.id).unwrap(), file);
+}
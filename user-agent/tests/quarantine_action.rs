@@ -0,0 +1,149 @@
+// tests/quarantine_action.rs
+//
+// Quarantine as a rule action: the file an alert names is sealed, moved or
+// copied per `[actions.quarantine]`, and the outcome recorded against the
+// alert; restoring marks the row.
+
+mod common;
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use tempfile::tempdir;
+
+use agent::{
+    actions::{Actions, Protector, Quarantine, QuarantineRecord, QuarantineStatus, Response, SystemDumper},
+    config::model::{ActionsConfig, QuarantineConfig, QuarantineMode, RuleAction},
+    db::quarantine::{mark_restored, quarantine_for_alert},
+    intel::{insert_alert, Alert, Severity},
+};
+
+const RULE: &str = "detection.startup_folder_write";
+
+/// Stands in for DPAPI.
+struct Flip;
+
+impl Protector for Flip {
+    fn protect(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(data.iter().map(|b| !b).collect())
+    }
+
+    fn unprotect(&self, blob: &[u8]) -> io::Result<Vec<u8>> {
+        self.protect(blob)
+    }
+}
+
+fn store(root: &Path, cfg: &QuarantineConfig) -> Quarantine {
+    Quarantine::with_config(root.join("quarantine"), Arc::new(Flip), cfg)
+}
+
+fn dropped(dir: &Path, content: &[u8]) -> PathBuf {
+    let file = dir.join("startup/run.exe");
+    fs::create_dir_all(file.parent().unwrap()).unwrap();
+    fs::write(&file, content).unwrap();
+    file
+}
+
+fn actions(dir: &Path, quarantine: QuarantineConfig) -> Actions {
+    let cfg = ActionsConfig {
+        enabled: true,
+        rules: BTreeMap::from([(RULE.to_string(), RuleAction::Quarantine)]),
+        quarantine,
+        ..ActionsConfig::default()
+    };
+    Actions::with_backends(&cfg, dir, Arc::new(SystemDumper), Arc::new(Flip))
+}
+
+/// Stores an alert about `file` and returns it with its id.
+fn alert(conn: &Connection, file: Option<&Path>) -> (i64, Alert) {
+    let alert = Alert {
+        ts:       1,
+        rule_id:  RULE.into(),
+        severity: Severity::High,
+        pid:      4242,
+        ppid:     None,
+        message:  "write to a startup folder".into(),
+        file:     file.map(|f| f.to_string_lossy().into_owned()),
+    };
+    (insert_alert(conn, &alert).unwrap(), alert)
+}
+
+fn quarantined(actions: &Actions, conn: &Connection, id: i64, a: &Alert) -> Option<QuarantineRecord> {
+    match actions.on_alert(conn, id, a).unwrap()? {
+        Response::Quarantine(record) => Some(record),
+        other => panic!("expected a quarantine, got {other:?}"),
+    }
+}
+
+#[test]
+fn the_alerted_file_is_moved_and_recorded() {
+    let dir = tempdir().unwrap();
+    let (conn, _) = common::database(dir.path());
+    let file = dropped(dir.path(), b"MZ payload");
+    let actions = actions(dir.path(), QuarantineConfig::default());
+
+    let (id, a) = alert(&conn, Some(&file));
+    let record = quarantined(&actions, &conn, id, &a).expect("rule declares quarantine");
+    assert_eq!(record.status, QuarantineStatus::Quarantined, "{:?}", record.error);
+    assert!(!file.exists());
+    assert_eq!((record.alert_id, record.reason.as_str()), (Some(id), RULE));
+    assert_eq!(record.sha256.as_deref(), Some(hex::encode(Sha256::digest(b"MZ payload")).as_str()));
+    assert_eq!(record.size, Some(10));
+    let container = record.id.clone().unwrap();
+    assert!(dir.path().join("quarantine").join(format!("{container}.gqf")).exists());
+
+    assert_eq!(quarantine_for_alert(&conn, id).unwrap(), vec![record]);
+    assert!(mark_restored(&conn, &container, 99).unwrap());
+    assert_eq!(quarantine_for_alert(&conn, id).unwrap()[0].status, QuarantineStatus::Restored);
+    assert!(!mark_restored(&conn, "no-such-container", 99).unwrap());
+}
+
+#[test]
+fn copy_mode_leaves_the_original() {
+    let dir = tempdir().unwrap();
+    let file = dropped(dir.path(), b"MZ payload");
+    let q = store(dir.path(), &QuarantineConfig { mode: QuarantineMode::Copy, ..QuarantineConfig::default() });
+
+    let record = q.quarantine_file(&file, Some(3), RULE);
+    assert_eq!(record.status, QuarantineStatus::Copied);
+    assert_eq!(record.error, None);
+    assert_eq!(fs::read(&file).unwrap(), b"MZ payload");
+
+    // The same content in place: restoring only drops the container.
+    let container = record.id.unwrap();
+    assert_eq!(q.restore(&container).unwrap(), file);
+    assert!(q.list().unwrap().is_empty());
+}
+
+#[test]
+fn files_above_the_limit_are_refused() {
+    let dir = tempdir().unwrap();
+    let file = dropped(dir.path(), &vec![0x90; (1 << 20) + 1]);
+    let q = store(dir.path(), &QuarantineConfig { max_mb: 1, ..QuarantineConfig::default() });
+
+    let record = q.quarantine_file(&file, None, "manual");
+    assert_eq!((record.status, record.size, record.id), (QuarantineStatus::TooLarge, Some((1 << 20) + 1), None));
+    assert!(file.exists());
+    assert!(q.list().unwrap().is_empty());
+
+    let missing = q.quarantine_file(&dir.path().join("gone.exe"), None, "manual");
+    assert_eq!(missing.status, QuarantineStatus::Failed);
+    assert!(missing.error.is_some());
+}
+
+#[test]
+fn alerts_without_a_file_quarantine_nothing() {
+    let dir = tempdir().unwrap();
+    let (conn, _) = common::database(dir.path());
+    let actions = actions(dir.path(), QuarantineConfig::default());
+
+    let (id, a) = alert(&conn, None);
+    assert_eq!(actions.on_alert(&conn, id, &a).unwrap(), None);
+    assert!(quarantine_for_alert(&conn, id).unwrap().is_empty());
+}
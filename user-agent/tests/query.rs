@@ -0,0 +1,168 @@
+// tests/query.rs
+//
+// `gladix-cli query`: rows written by the event writers come back filtered
+// in SQL, newest first, with decoded text and RFC 3339 timestamps.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use prost::Message;
+use rusqlite::Connection;
+use tempfile::tempdir;
+use shared::events::{file_event::Operation, network_event::Direction, FileEvent, NetworkEvent, ProcessEvent};
+
+use agent::{
+    comms::WrappedEvent,
+    config::load,
+    db::{
+        batch_inserts::BatchInsert,
+        codec::Codec,
+        connection::init_database,
+        query::{self, FileQuery, NetQuery, ProcessQuery},
+        schema_registry::ensure_for,
+    },
+};
+use common::{seconds, wrap};
+
+/// 2024-05-01T12:00:00Z.
+const T0: i64 = 1_714_564_800;
+/// The account the seeded processes ran as.
+const USER: &str = "S-1-5-21-1004336348-1177238915-682003330-1001";
+
+/// Writes `events` the way the agent's writer does.
+fn insert<E: Message + Clone>(conn: &Connection, codec: &mut Codec, events: &[WrappedEvent<E>])
+where
+    WrappedEvent<E>: BatchInsert<WrappedEvent<E>>,
+{
+    ensure_for(conn, <WrappedEvent<E>>::schema()).unwrap();
+    let mut stmt = conn.prepare(<WrappedEvent<E>>::insert_sql()).unwrap();
+    for ev in events {
+        <WrappedEvent<E>>::bind_and_execute(&mut stmt, ev, codec).unwrap();
+    }
+}
+
+/// A database seeded with a few events of each kind; returns its path.
+fn seeded(dir: &Path) -> PathBuf {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut cfg = load(&root.join("config.toml")).unwrap().database;
+    cfg.path = "telemetry.db".into();
+    // Long command lines are stored compressed.
+    cfg.compress_columns = vec!["process_events.cmdline".into()];
+    cfg.compress_threshold = 64;
+    let conn = init_database(dir, &cfg).unwrap();
+    let mut codec = Codec::new(&cfg).unwrap();
+
+    let proc = |pid: u32, image: &str, cmdline: String| ProcessEvent {
+        pid, ppid: 4, image_path: image.into(), cmdline, user_sid: USER.into(), session_id: 1, ..Default::default()
+    };
+    insert(&conn, &mut codec, &[
+        wrap(seconds(T0), proc(100, r"C:\Windows\System32\notepad.exe", "notepad.exe a.txt".into())),
+        wrap(seconds(T0 + 60), ProcessEvent {
+            elevated: true,
+            ..proc(200, r"C:\Windows\System32\cmd.exe", "cmd.exe /c dir".into())
+        }),
+        wrap(seconds(T0 + 120), proc(300, r"\??\C:\Windows\System32\NOTEPAD.EXE", format!("notepad.exe {}", "b".repeat(200)))),
+    ]);
+
+    let file = |op: Operation, path: &str| FileEvent { op: op as i32, path: path.into(), pid: 100, ..Default::default() };
+    insert(&conn, &mut codec, &[
+        wrap(seconds(T0), file(Operation::Create, r"C:\Users\a\AppData\Local\Temp\x.tmp")),
+        wrap(seconds(T0 + 1), file(Operation::Write, r"C:\Users\a\AppData\Local\Temp\x.tmp")),
+        wrap(seconds(T0 + 2), file(Operation::Write, r"C:\Users\a\Documents\report.docx")),
+    ]);
+
+    let net = |dst_port: u32, pid: u32| NetworkEvent {
+        direction: Direction::Outbound as i32, proto: "TCP".into(), src_ip: "10.0.0.1".into(), src_port: 50000,
+        dst_ip: "93.184.216.34".into(), dst_port, pid, ..Default::default()
+    };
+    insert(&conn, &mut codec, &(0..5).map(|i| wrap(seconds(T0 + i), net(if i % 2 == 0 { 443 } else { 80 }, 100 + i as u32))).collect::<Vec<_>>());
+    dir.join("telemetry.db")
+}
+
+#[test]
+fn processes_match_the_image_in_any_case() {
+    let dir = tempdir().unwrap();
+    let conn = query::open_read_only(&seeded(dir.path())).unwrap();
+
+    let rows = query::processes(&conn, &ProcessQuery { image: Some("Notepad".into()), ..Default::default() }).unwrap();
+    let pids: Vec<_> = rows.iter().map(|r| r.pid).collect();
+    assert_eq!(pids, [300, 100], "newest first");
+    assert_eq!(rows[0].ts, "2024-05-01T12:02:00.000000Z");
+    assert_eq!(rows[0].event_type, "CREATE");
+    // Decoded, not the stored zstd blob.
+    assert_eq!(rows[0].cmdline.as_deref(), Some(format!("notepad.exe {}", "b".repeat(200)).as_str()));
+
+    let since = ProcessQuery { since: Some((T0 + 60) * 1_000_000), ..Default::default() };
+    let pids: Vec<_> = query::processes(&conn, &since).unwrap().iter().map(|r| r.pid).collect();
+    assert_eq!(pids, [300, 200]);
+}
+
+#[test]
+fn processes_filter_on_the_user_and_elevation() {
+    let dir = tempdir().unwrap();
+    let conn = query::open_read_only(&seeded(dir.path())).unwrap();
+    let pids = |q: ProcessQuery| query::processes(&conn, &q).unwrap().iter().map(|r| r.pid).collect::<Vec<_>>();
+
+    assert_eq!(pids(ProcessQuery { user: Some(USER.to_lowercase()), ..Default::default() }), [300, 200, 100]);
+    assert!(pids(ProcessQuery { user: Some("S-1-5-18".into()), ..Default::default() }).is_empty());
+    assert_eq!(pids(ProcessQuery { elevated: Some(true), ..Default::default() }), [200]);
+    assert_eq!(pids(ProcessQuery { elevated: Some(false), ..Default::default() }), [300, 100]);
+
+    let row = &query::processes(&conn, &ProcessQuery { pid: Some(200), ..Default::default() }).unwrap()[0];
+    assert_eq!((row.user_sid.as_deref(), row.session_id, row.elevated), (Some(USER), Some(1), Some(true)));
+}
+
+#[test]
+fn files_filter_on_path_and_operation() {
+    let dir = tempdir().unwrap();
+    let conn = query::open_read_only(&seeded(dir.path())).unwrap();
+
+    let q = FileQuery { path_contains: Some("TEMP".into()), op: query::parse_op("Write"), ..Default::default() };
+    let rows = query::files(&conn, &q).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].op, "WRITE");
+    assert_eq!(rows[0].path, r"C:\Users\a\AppData\Local\Temp\x.tmp");
+
+    let temp = FileQuery { path_contains: Some("temp".into()), ..Default::default() };
+    let ops: Vec<_> = query::files(&conn, &temp).unwrap().into_iter().map(|r| r.op).collect();
+    assert_eq!(ops, ["WRITE", "CREATE"]);
+
+    assert_eq!(query::parse_op("delete"), Some(Operation::Delete));
+    assert_eq!(query::parse_op("3"), Some(Operation::Rename));
+    assert_eq!(query::parse_op("Truncate"), None);
+}
+
+#[test]
+fn net_filters_on_port_and_honours_the_limit() {
+    let dir = tempdir().unwrap();
+    let conn = query::open_read_only(&seeded(dir.path())).unwrap();
+
+    let q = NetQuery { dst_port: Some(443), limit: Some(2), ..Default::default() };
+    let rows = query::network(&conn, &q).unwrap();
+    let pids: Vec<_> = rows.iter().map(|r| r.pid).collect();
+    assert_eq!(pids, [Some(104), Some(102)]);
+    assert_eq!(rows[0].direction, "OUTBOUND");
+    assert_eq!(rows[0].verdict.as_deref(), Some("allow"));
+
+    let json = serde_json::to_value(&rows).unwrap();
+    assert_eq!(json[1]["ts"], "2024-05-01T12:00:02.000000Z");
+    assert_eq!(json[1]["dst_port"], 443);
+
+    let table = query::render_table(&rows);
+    let lines: Vec<_> = table.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("ts "), "{table}");
+    assert!(lines[1].contains("93.184.216.34:443"), "{table}");
+}
+
+#[test]
+fn tables_not_created_yet_have_no_rows() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("empty.db");
+    Connection::open(&path).unwrap().execute_batch("CREATE TABLE t (x)").unwrap();
+    let conn = query::open_read_only(&path).unwrap();
+
+    assert!(query::processes(&conn, &ProcessQuery::default()).unwrap().is_empty());
+    // Read-only: nothing can be written through the query connection.
+    assert!(conn.execute_batch("CREATE TABLE u (x)").is_err());
+}
@@ -0,0 +1,90 @@
+// tests/rate_limit.rs
+//
+// Token buckets of the ring consumers, driven by a fake clock: a burst is
+// let through whole, then the sustained rate holds, and a process image not
+// seen recently passes an empty bucket once.
+
+use std::time::{Duration, Instant};
+
+use agent::{
+    comms::rate_limit::{process_image, RateLimiter, RecentKeys, TokenBucket},
+    config::model::PayloadLimit,
+};
+use shared::events::{process_event::EventType, ProcessEvent};
+
+const LIMIT: PayloadLimit = PayloadLimit { per_sec: 10, burst: 5 };
+
+/// Events kept out of `n` arriving every `every` from `start`.
+fn kept(bucket: &mut TokenBucket, start: Instant, every: Duration, n: u32) -> u32 {
+    (0..n).filter(|i| bucket.take(start + every * *i)).count() as u32
+}
+
+#[test]
+fn burst_then_steady_rate() {
+    let t0 = Instant::now();
+    let mut bucket = TokenBucket::new(LIMIT, t0);
+    // The whole burst at once, nothing more.
+    assert_eq!(kept(&mut bucket, t0, Duration::ZERO, 8), 5);
+
+    // 20/s offered for 10s: 10/s kept.
+    let start = t0 + Duration::from_millis(50);
+    assert_eq!(kept(&mut bucket, start, Duration::from_millis(50), 200), 100);
+
+    // Idle time refills up to the burst only.
+    let later = start + Duration::from_secs(60);
+    assert_eq!(kept(&mut bucket, later, Duration::ZERO, 50), 5);
+}
+
+#[test]
+fn a_clock_going_backwards_adds_nothing() {
+    let t0 = Instant::now() + Duration::from_secs(10);
+    let mut bucket = TokenBucket::new(PayloadLimit { per_sec: 10, burst: 1 }, t0);
+    assert!(bucket.take(t0));
+    assert!(!bucket.take(t0 - Duration::from_secs(5)));
+    assert!(!bucket.take(t0 + Duration::from_millis(50)));
+    assert!(bucket.take(t0 + Duration::from_millis(100)));
+}
+
+#[test]
+fn recent_keys_forget_the_least_recently_seen() {
+    let mut recent = RecentKeys::new(2);
+    assert!(recent.first_seen("a"));
+    assert!(recent.first_seen("b"));
+    assert!(!recent.first_seen("a"));
+    // "b" is now the oldest and makes room for "c".
+    assert!(recent.first_seen("c"));
+    assert!(!recent.first_seen("a"));
+    assert!(recent.first_seen("b"));
+    // Repeats do not grow without bound nor evict live keys.
+    for _ in 0..100 {
+        assert!(!recent.first_seen("b"));
+    }
+    assert!(!recent.first_seen("a"));
+}
+
+#[test]
+fn new_images_pass_an_empty_bucket() {
+    let t0 = Instant::now();
+    let mut limiter = RateLimiter::new("process", PayloadLimit { per_sec: 1, burst: 1 }, 8, t0);
+    assert!(limiter.admit(Some("C:\\a.exe"), t0));
+    // A fork bomb of one image is limited...
+    assert!(!limiter.admit(Some("C:\\a.exe"), t0));
+    assert!(!limiter.admit(None, t0));
+    // ...but the first start of another image is kept.
+    assert!(limiter.admit(Some("C:\\b.exe"), t0));
+    assert!(!limiter.admit(Some("C:\\b.exe"), t0));
+
+    let mut strict = RateLimiter::new("process", PayloadLimit { per_sec: 1, burst: 1 }, 0, t0);
+    assert!(strict.admit(Some("C:\\a.exe"), t0));
+    assert!(!strict.admit(Some("C:\\b.exe"), t0));
+    assert!(strict.admit(Some("C:\\b.exe"), t0 + Duration::from_secs(1)));
+}
+
+#[test]
+fn only_creations_have_a_bypass_key() {
+    let create = ProcessEvent { image_path: "C:\\a.exe".into(), ..Default::default() };
+    assert_eq!(process_image(&create), Some("C:\\a.exe"));
+    let exit = ProcessEvent { event_type: EventType::Exit as i32, ..create.clone() };
+    assert_eq!(process_image(&exit), None);
+    assert_eq!(process_image(&ProcessEvent::default()), None);
+}
@@ -0,0 +1,169 @@
+// tests/reprocess.rs
+
+mod common;
+
+use std::path::Path;
+use chrono::{TimeZone, Utc};
+use rusqlite::{params, Connection};
+use tempfile::tempdir;
+
+use agent::{
+    config::model::DatabaseConfig,
+    db::{
+        connection::init_database,
+        event_types::ETW_EVENTS,
+        schema_registry::ensure_for,
+        reprocess::{backfill, enqueue, jobs, migrate, next_job, parse_since, run_chunk, JobState},
+    },
+    intel::enrich::{extract_sid, normalize_path, resolve_sid},
+};
+
+fn db_cfg() -> DatabaseConfig {
+    let mut cfg = common::db_cfg();
+    cfg.compress_columns = vec!["etw_events.json_payload".into()];
+    cfg.compress_threshold = 64;
+    cfg
+}
+
+/// Ten legacy process rows plus two written by an enrichment-aware agent.
+fn fixture(dir: &Path) -> Connection {
+    let conn = init_database(dir, &db_cfg()).unwrap();
+    for i in 0..10 {
+        conn.execute(
+            "INSERT INTO process_events (ts, pid, image_path) VALUES (?1, ?2, ?3)",
+            params![i, i, format!(r"\??\C:\Tools\App{i}.EXE")],
+        )
+        .unwrap();
+    }
+    for i in 10..12 {
+        conn.execute(
+            "INSERT INTO process_events (ts, pid, image_path, image_path_norm) VALUES (?1, ?2, 'X', 'kept')",
+            params![i, i],
+        )
+        .unwrap();
+    }
+    conn
+}
+
+fn norm_column(conn: &Connection) -> Vec<Option<String>> {
+    let mut stmt = conn.prepare("SELECT image_path_norm FROM process_events ORDER BY id").unwrap();
+    stmt.query_map([], |r| r.get(0)).unwrap().map(Result::unwrap).collect()
+}
+
+#[test]
+fn enrichments() {
+    assert_eq!(normalize_path(r"\??\C:\Windows\System32\CMD.EXE"), r"c:\windows\system32\cmd.exe");
+    assert_eq!(normalize_path(r"\\?\C:/Temp/a.exe"), r"c:\temp\a.exe");
+    assert_eq!(normalize_path(r"\SystemRoot\System32\smss.exe"), r"c:\windows\system32\smss.exe");
+    assert_eq!(normalize_path(r"\SystemRootX\a"), r"\systemrootx\a");
+
+    assert_eq!(extract_sid(r#"{"SubjectUserSid":"S-1-5-18","Sid":"S-1-5-20"}"#).as_deref(), Some("S-1-5-18"));
+    assert_eq!(extract_sid(r#"{"Sid":"not-a-sid"}"#), None);
+    assert_eq!(extract_sid("garbage"), None);
+    assert_eq!(resolve_sid("S-1-5-18"), Some(r"NT AUTHORITY\SYSTEM"));
+    assert_eq!(resolve_sid("S-1-5-21-1-2-3-1001"), None);
+}
+
+#[test]
+fn resumes_after_interruption_and_is_idempotent() {
+    let dir = tempdir().unwrap();
+    let conn = fixture(dir.path());
+    let paths = backfill("path-normalization").unwrap();
+
+    let id = enqueue(&conn, paths, None).unwrap();
+    assert_eq!(enqueue(&conn, paths, None).unwrap(), id, "open job is reused");
+    let mut job = next_job(&conn).unwrap().unwrap();
+    assert!(run_chunk(&conn, paths, &mut job, 4).unwrap());
+    drop(conn);
+
+    // Agent restart: the job is picked up from the stored cursor.
+    let conn = Connection::open(dir.path().join("telemetry.db")).unwrap();
+    let mut job = next_job(&conn).unwrap().unwrap();
+    assert_eq!((job.id, job.cursor, job.processed), (id, 4, 4));
+    while run_chunk(&conn, paths, &mut job, 4).unwrap() {}
+    assert_eq!((job.processed, job.updated), (10, 10));
+
+    let norms = norm_column(&conn);
+    assert_eq!(norms[0].as_deref(), Some(r"c:\tools\app0.exe"));
+    assert_eq!(norms[9].as_deref(), Some(r"c:\tools\app9.exe"));
+    assert_eq!(norms[10].as_deref(), Some("kept"), "enriched rows are not touched");
+
+    // A second job finds nothing left to do.
+    conn.execute("UPDATE reprocess_jobs SET state = 'done'", []).unwrap();
+    enqueue(&conn, paths, None).unwrap();
+    let mut again = next_job(&conn).unwrap().unwrap();
+    assert!(!run_chunk(&conn, paths, &mut again, 4).unwrap());
+    assert_eq!(again.processed, 0);
+    assert_eq!(norm_column(&conn), norms);
+}
+
+#[test]
+fn since_limits_rows() {
+    let dir = tempdir().unwrap();
+    let conn = fixture(dir.path());
+    let paths = backfill("path-normalization").unwrap();
+
+    enqueue(&conn, paths, Some(7)).unwrap();
+    let mut job = next_job(&conn).unwrap().unwrap();
+    while run_chunk(&conn, paths, &mut job, 100).unwrap() {}
+    let norms = norm_column(&conn);
+    assert!(norms[..7].iter().all(Option::is_none));
+    assert!(norms[7..10].iter().all(Option::is_some));
+
+    let now = Utc.timestamp_opt(1_000_000, 0).unwrap();
+    assert_eq!(parse_since("1h", now), Some((1_000_000 - 3_600) * 1_000_000));
+    assert_eq!(parse_since("1970-01-01T00:00:01Z", now), Some(1_000_000));
+    assert_eq!(parse_since("yesterday", now), None);
+}
+
+#[test]
+fn sid_resolution_reads_compressed_payloads() {
+    let dir = tempdir().unwrap();
+    let conn = init_database(dir.path(), &db_cfg()).unwrap();
+    ensure_for(&conn, &ETW_EVENTS.schema).unwrap();
+    let big = format!(r#"{{"UserSid":"S-1-5-18","Pad":"{}"}}"#, "a".repeat(200));
+    let blob = agent::db::codec::Codec::new(&db_cfg()).unwrap().encode("etw_events.json_payload", &big);
+    conn.execute(
+        "INSERT INTO etw_events (ts, provider_guid, event_id, json_payload) VALUES (1, 'p', 1, ?1)",
+        [blob],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO etw_events (ts, provider_guid, event_id, json_payload) VALUES (2, 'p', 1, '{}')",
+        [],
+    )
+    .unwrap();
+
+    let sids = backfill("sid-resolution").unwrap();
+    enqueue(&conn, sids, None).unwrap();
+    let mut job = next_job(&conn).unwrap().unwrap();
+    while run_chunk(&conn, sids, &mut job, 10).unwrap() {}
+
+    let rows: Vec<(String, Option<String>)> = conn
+        .prepare("SELECT user_sid, user_name FROM etw_events ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(rows, vec![
+        ("S-1-5-18".into(), Some(r"NT AUTHORITY\SYSTEM".into())),
+        (String::new(), None),
+    ]);
+}
+
+#[test]
+fn migration_adds_columns_and_queues_jobs() {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "CREATE TABLE process_events (id INTEGER PRIMARY KEY, ts INTEGER, image_path TEXT);
+         CREATE TABLE etw_events (id INTEGER PRIMARY KEY, ts INTEGER, json_payload TEXT, user_sid TEXT, user_name TEXT);",
+    )
+    .unwrap();
+
+    assert_eq!(migrate(&conn).unwrap(), vec!["path-normalization"]);
+    assert!(migrate(&conn).unwrap().is_empty());
+    let queued = jobs(&conn).unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!((queued[0].enrichment.as_str(), queued[0].state), ("path-normalization", JobState::Pending));
+}
@@ -0,0 +1,122 @@
+// tests/retention.rs
+//
+// Event tables expire on their own `[database.retention]` TTL, falling back
+// to `ttl_seconds`, and `purge_on_restart` can empty only some of them.
+
+mod common;
+
+use std::{path::Path, time::Duration};
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+use agent::{
+    config::model::{DatabaseConfig, Keep, PurgeOnRestart},
+    db::{
+        connection::init_database,
+        event_types::EVENT_TYPES,
+        maintenance::{event_ttls, purge_events},
+        schema_registry::ensure_for,
+    },
+};
+
+const HOUR: i64 = 3_600 * 1_000_000;
+const DAY: i64 = 24 * HOUR;
+
+fn db_cfg() -> DatabaseConfig {
+    let mut cfg = common::db_cfg();
+    cfg.ttl_seconds = 86_400;
+    cfg
+}
+
+/// A row with only the columns that cannot be NULL.
+fn insert(conn: &Connection, table: &str, ts: i64) {
+    let sql = match table {
+        "process_events" => "INSERT INTO process_events (ts, pid) VALUES (?1, 10)",
+        "etw_events"     => "INSERT INTO etw_events (ts, provider_guid, event_id) VALUES (?1, 'p', 1)",
+        "network_events" => "INSERT INTO network_events (ts, direction, proto, src_ip, dst_ip) VALUES (?1, 'OUTBOUND', 'TCP', '10.0.0.1', '10.0.0.2')",
+        "scan_results"   => "INSERT INTO scan_results (ts, file_path) VALUES (?1, 'C:\\a')",
+        other => panic!("no row for {other}"),
+    };
+    conn.execute(sql, [ts]).unwrap();
+}
+
+/// Every event table, lazy ones included.
+fn open(dir: &Path, cfg: &DatabaseConfig) -> Connection {
+    let conn = init_database(dir, cfg).unwrap();
+    for event in EVENT_TYPES {
+        ensure_for(&conn, &event.schema).unwrap();
+    }
+    conn
+}
+
+fn count(conn: &Connection, table: &str) -> i64 {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0)).unwrap()
+}
+
+#[test]
+fn each_table_expires_on_its_own_ttl() {
+    let dir = tempdir().unwrap();
+    let mut cfg = db_cfg();
+    cfg.retention.events.insert("process".into(), Keep::For(Duration::from_secs(30 * 86_400)));
+    cfg.retention.events.insert("etw_events".into(), Keep::For(Duration::from_secs(6 * 3_600)));
+    cfg.retention.events.insert("scan_results".into(), Keep::Forever);
+    let conn = open(dir.path(), &cfg);
+
+    let ttls = event_ttls(&cfg);
+    assert_eq!(ttls["process_events"], Duration::from_secs(30 * 86_400));
+    assert_eq!(ttls["etw_events"], Duration::from_secs(6 * 3_600));
+    assert_eq!(ttls["network_events"], Duration::from_secs(86_400));
+    assert!(!ttls.contains_key("scan_results"));
+
+    let now = 1_000 * DAY;
+    for table in ["process_events", "etw_events", "network_events", "scan_results"] {
+        for age in [HOUR, 12 * HOUR, 10 * DAY, 60 * DAY] {
+            insert(&conn, table, now - age);
+        }
+    }
+
+    let removed = purge_events(&conn, &ttls, now).unwrap();
+    let removed_from = |table: &str| removed.iter().find(|(t, _)| *t == table).map(|(_, n)| *n);
+    assert_eq!(removed_from("process_events"), Some(1));
+    assert_eq!(removed_from("etw_events"), Some(3));
+    assert_eq!(removed_from("network_events"), Some(2));
+    assert_eq!(removed_from("scan_results"), None);
+
+    assert_eq!(count(&conn, "process_events"), 3);
+    assert_eq!(count(&conn, "etw_events"), 1);
+    assert_eq!(count(&conn, "network_events"), 2);
+    assert_eq!(count(&conn, "scan_results"), 4);
+}
+
+#[test]
+fn without_a_global_ttl_only_overridden_tables_expire() {
+    let mut cfg = db_cfg();
+    cfg.ttl_seconds = 0;
+    assert!(event_ttls(&cfg).is_empty());
+
+    cfg.retention.events.insert("etw".into(), Keep::For(Duration::from_secs(6 * 3_600)));
+    assert_eq!(event_ttls(&cfg).into_iter().collect::<Vec<_>>(), [("etw_events", Duration::from_secs(6 * 3_600))]);
+}
+
+#[test]
+fn purge_on_restart_empties_only_the_listed_tables() {
+    let dir = tempdir().unwrap();
+    let mut cfg = db_cfg();
+    let conn = open(dir.path(), &cfg);
+    for table in ["process_events", "etw_events", "network_events"] {
+        insert(&conn, table, DAY);
+    }
+    drop(conn);
+
+    cfg.purge_on_restart = PurgeOnRestart::Tables(vec!["etw".into(), "network_events".into()]);
+    let conn = open(dir.path(), &cfg);
+    assert_eq!(count(&conn, "process_events"), 1);
+    assert_eq!(count(&conn, "etw_events"), 0);
+    assert_eq!(count(&conn, "network_events"), 0);
+    drop(conn);
+
+    // `true` still starts from an empty file.
+    cfg.purge_on_restart = true.into();
+    let conn = open(dir.path(), &cfg);
+    assert_eq!(count(&conn, "process_events"), 0);
+}
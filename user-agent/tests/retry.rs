@@ -0,0 +1,238 @@
+// tests/retry.rs
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use futures::future::BoxFuture;
+
+use agent::util::{
+    retry::Clock, retry_async, retry_blocking, Jitter, RetryError, RetryPolicy, Shutdown,
+};
+
+/// Clock that advances only when slept on and replays fixed jitter samples.
+struct MockClock {
+    now:     Mutex<Instant>,
+    slept:   Mutex<Vec<Duration>>,
+    samples: Mutex<Vec<f64>>,
+    /// Fired once this many sleeps happened.
+    stop_after: Option<(usize, Shutdown)>,
+}
+
+impl MockClock {
+    fn new(samples: &[f64]) -> Arc<Self> {
+        Self::stopping(samples, None)
+    }
+
+    fn stopping(samples: &[f64], stop_after: Option<(usize, Shutdown)>) -> Arc<Self> {
+        Arc::new(Self {
+            now:        Mutex::new(Instant::now()),
+            slept:      Mutex::default(),
+            samples:    Mutex::new(samples.iter().rev().copied().collect()),
+            stop_after,
+        })
+    }
+
+    fn advance(&self, d: Duration) {
+        *self.now.lock().unwrap() += d;
+        let mut slept = self.slept.lock().unwrap();
+        slept.push(d);
+        if let Some((n, shutdown)) = &self.stop_after
+            && slept.len() >= *n
+        {
+            shutdown.trigger();
+        }
+    }
+
+    fn slept(&self) -> Vec<Duration> {
+        self.slept.lock().unwrap().clone()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn random(&self) -> f64 {
+        self.samples.lock().unwrap().pop().unwrap_or(0.5)
+    }
+
+    fn sleep(&self, d: Duration) -> BoxFuture<'static, ()> {
+        self.advance(d);
+        Box::pin(async {})
+    }
+
+    fn sleep_blocking(&self, d: Duration, shutdown: &Shutdown) -> bool {
+        self.advance(d);
+        !shutdown.is_triggered()
+    }
+}
+
+fn ms(v: u64) -> Duration {
+    Duration::from_millis(v)
+}
+
+fn policy(clock: Arc<MockClock>) -> RetryPolicy {
+    RetryPolicy::new("test", ms(100)).max_delay(ms(1_000)).clock(clock)
+}
+
+#[test]
+fn delays_grow_geometrically_up_to_the_cap() {
+    let clock = MockClock::new(&[]);
+    let p = policy(clock.clone()).jitter(Jitter::None).max_attempts(7);
+    let out = retry_blocking(&p, || Err::<(), _>("down"));
+
+    assert!(matches!(out.result, Err(RetryError::GaveUp { last: Some("down") })));
+    assert_eq!(out.attempts, 7);
+    assert_eq!(clock.slept(), [100, 200, 400, 800, 1_000, 1_000].map(ms));
+    assert_eq!(out.elapsed, ms(3_500));
+}
+
+#[test]
+fn jitter_stays_within_bounds() {
+    let p = RetryPolicy::new("test", ms(100)).multiplier(3.0);
+    for retry in 1..6 {
+        let base = ms(100).mul_f64(3f64.powi(retry as i32 - 1)).min(ms(60_000));
+        for unit in [0.0, 0.25, 0.5, 0.999_999] {
+            let full = p.clone().jitter(Jitter::Full).delay(retry, unit);
+            assert!(full < base || base.is_zero(), "full {full:?} < {base:?}");
+            let equal = p.clone().jitter(Jitter::Equal).delay(retry, unit);
+            assert!(equal >= base / 2 && equal < base, "equal {equal:?} in [{:?}, {base:?})", base / 2);
+        }
+    }
+
+    let clock = MockClock::new(&[0.0, 0.5, 0.999]);
+    let p = policy(clock.clone()).jitter(Jitter::Full).max_attempts(4);
+    retry_blocking(&p, || Err::<(), _>(()));
+    let slept = clock.slept();
+    assert_eq!(slept[..2], [ms(0), ms(100)]);
+    assert!(slept[2] > ms(399) && slept[2] < ms(400));
+}
+
+#[test]
+fn success_stops_retrying() {
+    let clock = MockClock::new(&[]);
+    let p = policy(clock.clone()).jitter(Jitter::None);
+    let mut calls = 0;
+    let out = retry_blocking(&p, || {
+        calls += 1;
+        if calls < 3 { Err("not yet") } else { Ok(calls) }
+    });
+    assert_eq!(out.result.unwrap(), 3);
+    assert_eq!(out.attempts, 3);
+    assert_eq!(clock.slept(), [ms(100), ms(200)]);
+}
+
+#[test]
+fn deadline_is_never_overshot() {
+    let clock = MockClock::new(&[]);
+    let p = policy(clock.clone()).jitter(Jitter::None).deadline(ms(1_000));
+    let out = retry_blocking(&p, || Err::<(), _>("down"));
+
+    // 100 + 200 + 400 = 700; another 800 would end past the deadline.
+    assert!(matches!(out.result, Err(RetryError::GaveUp { .. })));
+    assert_eq!(out.attempts, 4);
+    assert_eq!(out.elapsed, ms(700));
+}
+
+#[test]
+fn shutdown_cancels_blocking_retries() {
+    let shutdown = Shutdown::new();
+    let clock = MockClock::stopping(&[], Some((2, shutdown.clone())));
+    let p = policy(clock.clone()).cancel_on(shutdown.clone());
+    let out = retry_blocking(&p, || Err::<(), _>("down"));
+
+    assert!(matches!(out.result, Err(RetryError::Cancelled { last: Some("down") })));
+    assert_eq!(out.attempts, 2);
+
+    // Already stopped: no attempt at all.
+    let out = retry_blocking(&p, || Ok::<_, ()>(()));
+    assert!(matches!(out.result, Err(RetryError::Cancelled { last: None })));
+    assert_eq!(out.attempts, 0);
+}
+
+#[tokio::test]
+async fn async_retries_follow_the_same_schedule() {
+    let clock = MockClock::new(&[]);
+    let p = policy(clock.clone()).jitter(Jitter::None).max_attempts(4);
+    let mut calls = 0;
+    let out = retry_async(&p, || {
+        calls += 1;
+        let n = calls;
+        async move { if n < 4 { Err("down") } else { Ok(n) } }
+    })
+    .await;
+    assert_eq!(out.result.unwrap(), 4);
+    assert_eq!(clock.slept(), [100, 200, 400].map(ms));
+}
+
+#[tokio::test]
+async fn hung_attempts_time_out() {
+    let clock = MockClock::new(&[]);
+    let p = policy(clock.clone())
+        .jitter(Jitter::None)
+        .max_attempts(2)
+        .attempt_timeout(ms(50));
+    let out = retry_async(&p, futures::future::pending::<Result<(), ()>>).await;
+
+    assert!(matches!(out.result, Err(RetryError::GaveUp { last: None })));
+    assert_eq!(out.attempts, 2);
+    assert_eq!(clock.slept(), [ms(50), ms(100), ms(50)]);
+}
+
+#[tokio::test]
+async fn shutdown_abandons_a_running_attempt() {
+    let shutdown = Shutdown::new();
+    let p = policy(MockClock::new(&[])).cancel_on(shutdown.clone());
+    let stopper = {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move { shutdown.trigger() })
+    };
+    let out = retry_async(&p, futures::future::pending::<Result<(), ()>>).await;
+    stopper.await.unwrap();
+
+    assert!(matches!(out.result, Err(RetryError::Cancelled { last: None })));
+    assert_eq!(out.attempts, 1);
+}
+
+/// Modules whose reconnect and restart paths must go through `util::retry`.
+const GUARDED: &[&str] = &["comms", "etw", "health", "db", "probe"];
+/// Sleeps that pace work rather than retry it.
+const ALLOWED: &[&str] = &["db/maintenance.rs", "db/reprocess.rs"];
+
+fn rust_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            rust_files(&path, out);
+        } else if path.extension().is_some_and(|e| e == "rs") {
+            out.push(path);
+        }
+    }
+}
+
+#[test]
+fn no_ad_hoc_sleep_loops() {
+    let src = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut offenders = Vec::new();
+    for module in GUARDED {
+        let mut files = Vec::new();
+        rust_files(&src.join(module), &mut files);
+        for file in files {
+            let rel = file.strip_prefix(&src).unwrap().to_string_lossy().replace('\\', "/");
+            if ALLOWED.contains(&rel.as_str()) {
+                continue;
+            }
+            for (i, line) in fs::read_to_string(&file).unwrap().lines().enumerate() {
+                let code = line.split("//").next().unwrap();
+                if code.contains("sleep(") {
+                    offenders.push(format!("{rel}:{}: {}", i + 1, line.trim()));
+                }
+            }
+        }
+    }
+    assert!(offenders.is_empty(), "use util::retry instead of sleeping in a loop:\n{}", offenders.join("\n"));
+}
@@ -0,0 +1,120 @@
+// tests/ring_event.rs
+//
+// Ring consumers sleep on the driver's event instead of spinning: a frame
+// followed by a set is read at once, one whose signal the driver held back
+// is read after the wait timeout, and a missing event leaves the consumer
+// polling. The event is one the test creates itself.
+
+use std::{
+    fs::OpenOptions,
+    path::Path,
+    time::{Duration, Instant},
+};
+use memmap2::{MmapMut, MmapOptions};
+use tempfile::tempdir;
+use tokio::time::{sleep, timeout};
+
+use agent::comms::{
+    memory_ring::MemoryRing,
+    ring_event::{RingEvent, RingWait},
+};
+use agent::util::Shutdown;
+use shared::ring::{self, RingHeader};
+
+const DATA: usize = 4096;
+
+/// An empty ring file, its mapping for the producer side and the reader.
+fn empty_ring(path: &Path) -> MmapMut {
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path).unwrap();
+    file.set_len((ring::HEADER_SIZE + DATA) as u64).unwrap();
+    let mut mmap = unsafe { MmapOptions::new().map_mut(&file).unwrap() };
+    unsafe { (mmap.as_mut_ptr() as *mut RingHeader).write(RingHeader::new()) };
+    mmap
+}
+
+fn push(mmap: &mut MmapMut, seq: u64, payload: &[u8]) {
+    let (header, data) = mmap.split_at_mut(ring::HEADER_SIZE);
+    let header = unsafe { &*(header.as_ptr() as *const RingHeader) };
+    assert!(ring::push(header, data, seq, 0, payload));
+}
+
+#[test]
+fn an_event_wakes_one_wait_and_resets() {
+    let event = RingEvent::create(None).unwrap();
+    assert!(!event.wait(Duration::from_millis(20)));
+    event.set().unwrap();
+    assert!(event.wait(Duration::from_millis(20)));
+    assert!(!event.wait(Duration::from_millis(20)));
+}
+
+#[test]
+fn a_named_event_is_opened_and_a_missing_one_is_not() {
+    let created = RingEvent::create(Some(r"Local\GladixTestRingEvent")).unwrap();
+    let opened = RingEvent::open(r"Local\GladixTestRingEvent").unwrap();
+    created.set().unwrap();
+    assert!(opened.wait(Duration::from_millis(100)));
+    assert!(RingEvent::open(r"Local\GladixTestNoSuchEvent").is_err());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn a_consumer_sleeps_until_the_event_is_set() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("ring");
+    let mut mmap = empty_ring(&path);
+    let shutdown = Shutdown::new();
+    let event = RingEvent::create(Some(r"Local\GladixTestRingWake")).unwrap();
+    let opened = RingEvent::open(r"Local\GladixTestRingWake").unwrap();
+    // A timeout far beyond the test: only the set can wake the consumer.
+    let wait = RingWait::event(opened, Duration::from_secs(30), &shutdown);
+    assert!(matches!(wait, RingWait::Event(_)));
+    let reader = MemoryRing::open(&path).unwrap().woken_by(wait);
+
+    let pop = tokio::spawn(async move { reader.pop_frame().await.map(|f| f.data) });
+    sleep(Duration::from_millis(100)).await;
+    push(&mut mmap, 1, b"first");
+    sleep(Duration::from_millis(100)).await;
+    assert!(!pop.is_finished(), "read before the event was set");
+
+    event.set().unwrap();
+    let data = timeout(Duration::from_secs(5), pop).await.expect("not woken by the event").unwrap();
+    assert_eq!(data.as_deref(), Some(&b"first"[..]));
+    shutdown.trigger();
+    event.set().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn a_frame_without_a_signal_is_read_after_the_wait_timeout() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("ring");
+    let mut mmap = empty_ring(&path);
+    let shutdown = Shutdown::new();
+    let wait = RingWait::event(RingEvent::create(None).unwrap(), Duration::from_millis(50), &shutdown);
+    let reader = MemoryRing::open(&path).unwrap().woken_by(wait);
+
+    let pop = tokio::spawn(async move { reader.pop_frame().await.map(|f| f.data) });
+    sleep(Duration::from_millis(20)).await;
+    let pushed = Instant::now();
+    push(&mut mmap, 1, b"held back");
+    let data = timeout(Duration::from_secs(5), pop).await.expect("never read").unwrap();
+    assert_eq!(data.as_deref(), Some(&b"held back"[..]));
+    assert!(pushed.elapsed() < Duration::from_secs(1));
+    shutdown.trigger();
+}
+
+#[tokio::test]
+async fn without_the_event_the_consumer_polls() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("ring");
+    let mut mmap = empty_ring(&path);
+    let shutdown = Shutdown::new();
+    let wait = RingWait::open(r"Local\GladixTestNoSuchEvent", &shutdown);
+    assert!(matches!(wait, RingWait::Poll));
+    let reader = MemoryRing::open(&path).unwrap().woken_by(wait);
+
+    push(&mut mmap, 1, b"one");
+    push(&mut mmap, 2, b"two");
+    for expected in [&b"one"[..], b"two"] {
+        let frame = timeout(Duration::from_secs(5), reader.pop_frame()).await.unwrap().unwrap();
+        assert_eq!(frame.data, expected);
+    }
+}
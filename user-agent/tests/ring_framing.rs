@@ -0,0 +1,145 @@
+// tests/ring_framing.rs
+//
+// Every ring frame carries a magic, a CRC, the producer's frame number and
+// its time: the reader drops a damaged frame and resynchronizes on the next
+// one instead of losing the rest, and refuses rings written with another
+// framing.
+
+mod common;
+
+use std::{
+    fs::File,
+    path::Path,
+    sync::atomic::Ordering,
+    time::Duration,
+};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use prost::Message;
+use tempfile::tempdir;
+use tokio::{runtime::Builder, time::timeout};
+
+use agent::comms::memory_ring::{MemoryRing, RingOpenError};
+use shared::{events::ProcessEvent, ring};
+
+const RING_SIZE: usize = 512;
+
+fn payload(pid: u32) -> Vec<u8> {
+    ProcessEvent { pid, image_path: format!(r"C:\bin\{pid}.exe"), ..Default::default() }.encode_to_vec()
+}
+
+/// A ring holding `payloads` from offset `start`, with `damage` applied to
+/// the data area before the tail is published. Returns the frame offsets.
+fn ring_with(path: &Path, start: usize, payloads: &[Vec<u8>], damage: impl FnOnce(&mut [u8], &[usize])) -> Vec<usize> {
+    let mut mmap = common::empty_ring(path, RING_SIZE);
+    let mut offsets = Vec::new();
+    let mut tail = start;
+    for (seq, p) in (1..).zip(payloads) {
+        if tail + ring::frame_len(p.len()) > RING_SIZE {
+            tail = 0;
+        }
+        offsets.push(tail);
+        tail += ring::write_frame(&mut mmap[ring::HEADER_SIZE + tail..], seq, 0, p).unwrap();
+    }
+    damage(&mut mmap[ring::HEADER_SIZE..], &offsets);
+    let header = common::header(&mmap);
+    header.head.store(start as u64, Ordering::Release);
+    header.tail.store(tail as u64, Ordering::Release);
+    mmap.flush().unwrap();
+    offsets
+}
+
+/// Pops until the ring is drained; returns the pids and the metrics text.
+fn drain(path: &Path) -> (Vec<u32>, String) {
+    let ring = MemoryRing::open(path).unwrap();
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let pids = metrics::with_local_recorder(&recorder, || {
+        let rt = Builder::new_current_thread().enable_time().build().unwrap();
+        rt.block_on(async {
+            let mut pids = Vec::new();
+            while ring.head() != ring.tail() {
+                let frame = timeout(Duration::from_secs(1), ring.pop()).await;
+                match frame {
+                    Ok(Some(bytes)) => pids.push(ProcessEvent::decode(&*bytes).unwrap().pid),
+                    // The last frame was damaged: the reader is waiting again.
+                    Err(_) => break,
+                    Ok(None) => unreachable!(),
+                }
+            }
+            pids
+        })
+    });
+    assert_eq!(ring.head(), ring.tail(), "everything consumed");
+    (pids, recorder.handle().render())
+}
+
+#[test]
+fn only_the_damaged_frame_is_lost() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("process_ring");
+    let payloads: Vec<_> = (1..=4).map(payload).collect();
+    let second = ring::frame_len(payloads[1].len());
+
+    // Magic, length (made huge), CRC, number, time and payload of the second
+    // frame.
+    for at in [0, 5, 7, 12, 20, ring::FRAME_PREFIX + 3] {
+        ring_with(&path, 0, &payloads, |data, offsets| data[offsets[1] + at] ^= 0x80);
+        let (pids, text) = drain(&path);
+        assert_eq!(pids, [1, 3, 4], "damage at byte {at}");
+        assert!(text.contains(&format!("ring_skipped_bytes_total {second}")), "{text}");
+    }
+}
+
+#[test]
+fn damage_in_the_last_frame_skips_to_the_tail() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("process_ring");
+    let payloads: Vec<_> = (1..=3).map(payload).collect();
+
+    ring_with(&path, 0, &payloads, |data, offsets| data[offsets[2] + ring::FRAME_PREFIX] ^= 0xff);
+    assert_eq!(drain(&path).0, [1, 2]);
+}
+
+#[test]
+fn padding_before_the_wrap_is_not_damage() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("process_ring");
+    let payloads: Vec<_> = (1..=3).map(payload).collect();
+    // The second frame does not fit before the end and starts at 0.
+    let start = RING_SIZE - ring::frame_len(payloads[0].len()) - ring::FRAME_ALIGN;
+
+    let offsets = ring_with(&path, start, &payloads, |_, _| {});
+    assert_eq!(offsets[1], 0);
+    let (pids, text) = drain(&path);
+    assert_eq!(pids, [1, 2, 3]);
+    assert!(!text.contains("ring_skipped_bytes_total"), "{text}");
+}
+
+#[test]
+fn rings_with_another_framing_are_refused() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("process_ring");
+    let file = File::create(&path).unwrap();
+    // A header from before frames were versioned: all zeroes.
+    file.set_len((ring::HEADER_SIZE + RING_SIZE) as u64).unwrap();
+
+    let err = MemoryRing::open(&path).err().expect("old framing refused");
+    assert!(matches!(err, RingOpenError::BadVersion { found: 0 }), "{err:?}");
+    assert!(err.to_string().contains("version 0"), "{err}");
+}
+
+#[test]
+fn frames_are_checked_with_crc32() {
+    assert_eq!(ring::crc32(b"123456789"), 0xCBF4_3926);
+
+    let mut frame = [0xAA; 40];
+    assert_eq!(ring::write_frame(&mut frame, 7, 9, b"abc"), Some(32));
+    assert_eq!(frame[..2], *b"GX");
+    assert_eq!(frame[2..6], 3u32.to_le_bytes());
+    assert_eq!(frame[10..18], 7u64.to_le_bytes());
+    assert_eq!(frame[18..26], 9u64.to_le_bytes());
+    assert_eq!(frame[6..10], ring::crc32(&frame[10..29]).to_le_bytes());
+    assert_eq!(ring::check_frame(&frame), Some(ring::Frame { seq: 7, ts: 9, payload: 26..29 }));
+    assert_eq!(frame[32], 0xAA, "nothing past the frame");
+    // Cut short: the payload is not all there.
+    assert_eq!(ring::check_frame(&frame[..28]), None);
+}
@@ -0,0 +1,117 @@
+// tests/ring_gaps.rs
+//
+// The driver numbers every frame it tries to push, so numbers missing
+// between two frames read are events lost on the way. The consumer counts
+// them and the numbers are stored with the rows.
+
+mod common;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use prost::Message;
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::{runtime::Builder, sync::{broadcast, mpsc}};
+
+use agent::{
+    comms::{
+        listeners::{Buses, Listener, RingListener},
+        memory_ring::{GapMonitor, MemoryRing},
+        WrappedEvent,
+    },
+    config::load,
+    db::{connection::{db_path, init_database}, spawn_writer},
+    util::Shutdown,
+};
+use shared::{events::ProcessEvent, ring};
+
+const RING_SIZE: usize = 4_096;
+
+/// A ring holding one process event per number in `seqs`, pid = number.
+fn ring_with(path: &Path, seqs: &[u64]) {
+    let mut mmap = common::empty_ring(path, RING_SIZE);
+    let mut tail = 0;
+    for &seq in seqs {
+        let payload = ProcessEvent { pid: seq as u32, image_path: r"C:\a.exe".into(), ..Default::default() };
+        tail += ring::write_frame(&mut mmap[ring::HEADER_SIZE + tail..], seq, 0, &payload.encode_to_vec()).unwrap();
+    }
+    common::header(&mmap).tail.store(tail as u64, Ordering::Release);
+    mmap.flush().unwrap();
+}
+
+#[test]
+fn gaps_are_counted_and_restarts_are_not() {
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let missing: Vec<u64> = metrics::with_local_recorder(&recorder, || {
+        let gaps = GapMonitor::default();
+        // A driver reload starts again from 1.
+        [1, 2, 3, 6, 7, 1, 2, 10].into_iter().map(|seq| gaps.observe("process", seq)).collect()
+    });
+    assert_eq!(missing, [0, 0, 0, 2, 0, 0, 0, 7]);
+    let text = recorder.handle().render();
+    assert!(text.contains("ring_gap_events_total{ring=\"process\"} 9"), "{text}");
+}
+
+#[test]
+fn stored_rows_keep_the_frame_numbers() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut db_cfg = load(&root.join("config.toml")).unwrap().database;
+    db_cfg.flush_interval_ms = 20;
+    let dir = tempdir().unwrap();
+    let conn = init_database(dir.path(), &db_cfg).unwrap();
+    let ring_path = dir.path().join("process_ring");
+    // 3 and 4 lost, then a frame from before 5: a restarted counter.
+    let seqs = [1, 2, 5, 3, 9];
+    ring_with(&ring_path, &seqs);
+
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let rt = Builder::new_current_thread().enable_all().build().unwrap();
+    let shutdown = Shutdown::new();
+    let stored: Vec<(i64, i64)> = metrics::with_local_recorder(&recorder, || {
+        let (db_tx, db_rx) = mpsc::channel::<WrappedEvent<ProcessEvent>>(16);
+        let writer = spawn_writer(&rt, conn, db_rx, &db_cfg, &shutdown);
+        let listener = Arc::new(RingListener::new("process", MemoryRing::open(&ring_path).unwrap(), "SENSOR"));
+        let (intel_tx, _) = broadcast::channel(16);
+        let tasks = {
+            let _guard = rt.enter();
+            listener.spawn(Buses { db_tx: db_tx.into(), intel_tx }, &shutdown)
+        };
+
+        let file = db_path(dir.path(), &db_cfg);
+        rt.block_on(async {
+            let count = || {
+                Connection::open(&file)
+                    .and_then(|c| c.query_row("SELECT COUNT(*) FROM process_events", [], |r| r.get::<_, usize>(0)))
+                    .unwrap_or(0)
+            };
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while count() < seqs.len() {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("every frame stored");
+            shutdown.trigger();
+            for task in tasks {
+                task.await.unwrap();
+            }
+            writer.await.unwrap();
+        });
+        Connection::open(&file)
+            .unwrap()
+            .prepare("SELECT pid, seq FROM process_events ORDER BY id")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    });
+
+    assert_eq!(stored, seqs.map(|s| (s as i64, s as i64)));
+    let text = recorder.handle().render();
+    assert!(text.contains("ring_gap_events_total{ring=\"process\"} 7"), "{text}");
+}
@@ -0,0 +1,152 @@
+// tests/ring_lag.rs
+//
+// How far the consumer is behind the driver, read from a mapped ring whose
+// header is set by hand: the backlog is the circular distance from the read
+// offset to the write offset, also once the driver has wrapped, and a ring
+// that stays nearly full is warned about once. Drops are reported by reason.
+
+use std::{
+    fs::{self, OpenOptions},
+    path::Path,
+    sync::atomic::Ordering,
+};
+use memmap2::{MmapMut, MmapOptions};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tempfile::tempdir;
+
+use agent::comms::memory_ring::{backlog_bytes, LagMonitor, MemoryRing};
+use shared::ring::{self, RingHeader, RingStats};
+
+const SIZE: u64 = 4_096;
+
+/// A ring file of `SIZE` data bytes; the map writes its header.
+fn ring_file(path: &Path) -> MmapMut {
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path).unwrap();
+    file.set_len(ring::HEADER_SIZE as u64 + SIZE).unwrap();
+    let mut mmap = unsafe { MmapOptions::new().map_mut(&file).unwrap() };
+    unsafe { (mmap.as_mut_ptr() as *mut RingHeader).write(RingHeader::new()) };
+    mmap
+}
+
+fn set(mmap: &MmapMut, head: u64, tail: u64, dropped: u32) {
+    let header = unsafe { &*(mmap.as_ptr() as *const RingHeader) };
+    header.head.store(head, Ordering::Release);
+    header.tail.store(tail, Ordering::Release);
+    header.dropped.store(dropped, Ordering::Relaxed);
+}
+
+#[test]
+fn the_backlog_is_the_distance_from_head_to_tail() {
+    assert_eq!(backlog_bytes(0, 0, SIZE), 0);
+    assert_eq!(backlog_bytes(128, 1_152, SIZE), 1_024);
+    // The driver wrapped: unread from head to the end, then up to tail.
+    assert_eq!(backlog_bytes(3_000, 200, SIZE), 1_296);
+    assert_eq!(backlog_bytes(SIZE - 8, 0, SIZE), 8);
+    assert_eq!(backlog_bytes(10, 20, 0), 0);
+}
+
+#[test]
+fn a_mapped_header_is_read_without_touching_it() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("ring");
+    let mmap = ring_file(&path);
+    let ring = MemoryRing::open(&path).unwrap();
+
+    set(&mmap, 1_024, 3_072, 0);
+    assert_eq!(ring.backlog(), 2_048);
+    assert_eq!(ring.fill_ratio(), 0.5);
+
+    set(&mmap, 3_584, 1_536, 17);
+    assert_eq!(ring.backlog(), 2_048);
+    assert_eq!(ring.dropped(), 17);
+    // Sampling leaves the read offset to `pop_frame`.
+    assert!(!LagMonitor::new(0).sample("test", &ring));
+    assert_eq!(ring.head(), 3_584);
+    assert_eq!(ring.tail(), 1_536);
+}
+
+#[test]
+fn a_ring_that_stays_nearly_full_is_warned_about_once() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("ring");
+    let mmap = ring_file(&path);
+    let ring = MemoryRing::open(&path).unwrap();
+    let lag = LagMonitor::new(2);
+
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let warned: Vec<bool> = metrics::with_local_recorder(&recorder, || {
+        // 90% used, across the wrap.
+        set(&mmap, 2_048, 1_638, 0);
+        let mut warned: Vec<bool> = (0..5).map(|_| lag.sample("process", &ring)).collect();
+        // Caught up, then behind again: a new run of samples.
+        set(&mmap, 2_048, 2_048, 0);
+        warned.push(lag.sample("process", &ring));
+        set(&mmap, 0, 4_000, 0);
+        warned.extend((0..3).map(|_| lag.sample("process", &ring)));
+        warned
+    });
+    assert_eq!(warned, [false, false, true, false, false, false, false, false, true]);
+
+    let text = recorder.handle().render();
+    assert!(text.contains("ring_backlog_bytes{ring=\"process\"} 4000"), "{text}");
+    assert!(text.contains("ring_utilization_ratio{ring=\"process\"} 0.9765625"), "{text}");
+}
+
+#[test]
+fn drops_are_reported_by_reason() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("ring");
+    let mmap = ring_file(&path);
+    let ring = MemoryRing::open(&path).unwrap();
+
+    // What `ring::push` leaves after a full ring and an oversize frame.
+    let mut data = vec![0u8; 256];
+    let header = RingHeader::new();
+    header.head.store(64, Ordering::Release);
+    header.tail.store(32, Ordering::Release);
+    assert!(!ring::push(&header, &mut data, 1, 0, &[0; 8]));
+    assert!(!ring::push(&header, &mut data, 2, 0, &[0; 300]));
+    assert!(!ring::push(&header, &mut data, 3, 0, &[0; 230]));
+    let reasons = |h: &RingHeader| {
+        [&h.dropped, &h.dropped_full, &h.dropped_oversize, &h.max_observed_len].map(|c| c.load(Ordering::Relaxed))
+    };
+    assert_eq!(reasons(&header), [3, 1, 2, ring::frame_len(300) as u32]);
+
+    let mapped = unsafe { &*(mmap.as_ptr() as *const RingHeader) };
+    mapped.dropped.store(5, Ordering::Relaxed);
+    mapped.dropped_full.store(4, Ordering::Relaxed);
+    mapped.dropped_oversize.store(1, Ordering::Relaxed);
+    mapped.max_observed_len.store(6_000, Ordering::Relaxed);
+    let stats = ring.stats();
+    assert_eq!(
+        (stats.dropped, stats.dropped_full, stats.dropped_oversize, stats.max_observed_len, stats.size),
+        (5, 4, 1, 6_000, SIZE as u32)
+    );
+
+    let recorder = PrometheusBuilder::new().build_recorder();
+    metrics::with_local_recorder(&recorder, || LagMonitor::new(0).sample("image", &ring));
+    let text = recorder.handle().render();
+    for line in ["ring_dropped_full{ring=\"image\"} 4", "ring_dropped_oversize{ring=\"image\"} 1", "ring_max_frame_bytes{ring=\"image\"} 6000"] {
+        assert!(text.contains(line), "{line} not in {text}");
+    }
+}
+
+#[test]
+fn a_version_3_ring_is_read_with_its_shorter_header() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("ring");
+    drop(ring_file(&path));
+    let mut bytes = fs::read(&path).unwrap();
+    bytes[20..24].copy_from_slice(&3u32.to_le_bytes());
+    // Data the longer header would have taken for drop reasons.
+    bytes[24..40].fill(0xEE);
+    fs::write(&path, &bytes).unwrap();
+
+    let ring = MemoryRing::open(&path).unwrap();
+    assert_eq!(ring.capacity(), SIZE + (ring::HEADER_SIZE - ring::LEGACY_HEADER_SIZE) as u64);
+    assert_eq!(ring.stats(), RingStats { size: ring.capacity() as u32, ..RingStats::default() });
+
+    bytes[20..24].copy_from_slice(&2u32.to_le_bytes());
+    fs::write(&path, &bytes).unwrap();
+    assert!(MemoryRing::open(&path).is_err());
+}
@@ -0,0 +1,90 @@
+// tests/ring_open.rs
+//
+// Opening a ring fails with a reason the caller can act on: a section the
+// driver never created, one the agent may not open, a view too small for the
+// header or for the data the driver reports, and a header of an unknown
+// framing. Headers are checked on synthetic buffers before anything large is
+// mapped.
+
+use std::{fs::File, io, path::Path};
+use tempfile::tempdir;
+
+use agent::comms::memory_ring::{data_offset, MemoryRing, RingOpenError};
+use shared::ring::{self, RingHeader};
+
+/// The bytes of a header of framing `version`.
+fn header(version: u32) -> Vec<u8> {
+    let header = RingHeader { version, ..RingHeader::new() };
+    let bytes = unsafe { std::slice::from_raw_parts(&header as *const RingHeader as *const u8, ring::HEADER_SIZE) };
+    bytes.to_vec()
+}
+
+/// A file of `len` bytes starting with a current header.
+fn ring_file(path: &Path, len: u64) {
+    std::fs::write(path, header(ring::VERSION)).unwrap();
+    File::options().write(true).open(path).unwrap().set_len(len).unwrap();
+}
+
+#[test]
+fn headers_give_the_data_offset_of_their_framing() {
+    let view = 1 << 16;
+    assert_eq!(data_offset(&header(ring::VERSION), view).unwrap(), ring::HEADER_SIZE);
+    assert_eq!(data_offset(&header(3), view).unwrap(), ring::LEGACY_HEADER_SIZE);
+
+    for version in [0, 2, ring::VERSION + 1, u32::MAX] {
+        match data_offset(&header(version), view) {
+            Err(RingOpenError::BadVersion { found }) => assert_eq!(found, version),
+            other => panic!("version {version}: {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn views_without_room_past_the_header_are_refused() {
+    let expected = ring::HEADER_SIZE as u64 + 1;
+    for (bytes, view) in [(ring::HEADER_SIZE, ring::HEADER_SIZE as u64), (ring::HEADER_SIZE, 8), (12, 1 << 16)] {
+        match data_offset(&header(ring::VERSION)[..bytes], view) {
+            Err(RingOpenError::SizeMismatch { expected: e, got }) => {
+                assert_eq!((e, got), (expected, view.min(bytes as u64)))
+            }
+            other => panic!("{bytes} header bytes in a view of {view}: {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn io_errors_name_the_missing_or_forbidden_section() {
+    let missing = RingOpenError::from(io::Error::from(io::ErrorKind::NotFound));
+    assert!(matches!(missing, RingOpenError::SectionMissing));
+    let denied = RingOpenError::from(io::Error::from(io::ErrorKind::PermissionDenied));
+    assert!(matches!(denied, RingOpenError::AccessDenied));
+    assert!(denied.to_string().contains("elevated"), "{denied}");
+    let other = RingOpenError::from(io::Error::other("mapping failed"));
+    assert_eq!(other.to_string(), "mapping failed");
+
+    let dir = tempdir().unwrap();
+    let err = MemoryRing::open(dir.path().join("process_ring")).err().expect("no section");
+    assert!(matches!(err, RingOpenError::SectionMissing), "{err:?}");
+}
+
+#[test]
+fn the_view_is_checked_against_the_file_and_the_driver() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("process_ring");
+
+    ring_file(&path, ring::HEADER_SIZE as u64);
+    match MemoryRing::open(&path) {
+        Err(RingOpenError::SizeMismatch { expected, got }) => {
+            assert_eq!((expected, got), (ring::HEADER_SIZE as u64 + 1, ring::HEADER_SIZE as u64))
+        }
+        other => panic!("{:?}", other.err()),
+    }
+
+    let size = 1 << 16;
+    ring_file(&path, (ring::HEADER_SIZE + size) as u64);
+    let reader = MemoryRing::open(&path).unwrap();
+    assert_eq!((reader.capacity(), reader.mapped_len()), (size as u64, ring::HEADER_SIZE + size));
+    reader.expect_size(size as u32).unwrap();
+    let err = reader.expect_size(2 * size as u32).unwrap_err();
+    assert_eq!(err.to_string(), format!("ring view of {} bytes, {} needed", ring::HEADER_SIZE + size, ring::HEADER_SIZE + 2 * size));
+}
@@ -0,0 +1,137 @@
+// tests/ring_replay.rs
+//
+// What the consumer reads of the events the driver wrote before it
+// attached: everything, nothing, or only what is younger than a maximum
+// age going by the time in each frame.
+
+mod common;
+
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use memmap2::MmapMut;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use prost::Message;
+use tempfile::tempdir;
+use tokio::{runtime::Builder, time::timeout};
+
+use agent::{
+    comms::memory_ring::{frame_time, MemoryRing},
+    config::model::ReplayPolicy,
+};
+use shared::{events::ProcessEvent, ring::{self, RingHeader}};
+
+const RING_SIZE: usize = 1_024;
+
+fn micros(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).unwrap().as_micros() as u64
+}
+
+/// A mapped ring holding one frame per time in `ts`, pid = frame number.
+fn ring_with(path: &Path, ts: &[u64]) -> MmapMut {
+    let mut mmap = common::empty_ring(path, RING_SIZE);
+    for (seq, &ts) in (1..).zip(ts) {
+        push(&mut mmap, seq, ts);
+    }
+    mmap
+}
+
+/// Appends frame `seq` as the driver does.
+fn push(mmap: &mut MmapMut, seq: u64, ts: u64) {
+    let (header, data) = mmap.split_at_mut(ring::HEADER_SIZE);
+    let header = unsafe { &*(header.as_ptr() as *const RingHeader) };
+    let payload = ProcessEvent { pid: seq as u32, ..Default::default() }.encode_to_vec();
+    assert!(ring::push(header, data, seq, ts, &payload));
+}
+
+/// Pids and times of the frames read until the ring stays empty, and the
+/// metrics text.
+fn drain(ring: &MemoryRing) -> (Vec<(u32, Option<SystemTime>)>, String) {
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let read = metrics::with_local_recorder(&recorder, || {
+        let rt = Builder::new_current_thread().enable_time().build().unwrap();
+        rt.block_on(async {
+            let mut read = Vec::new();
+            while let Ok(Some(frame)) = timeout(Duration::from_millis(200), ring.pop_frame()).await {
+                read.push((ProcessEvent::decode(&*frame.data).unwrap().pid, frame.ts));
+            }
+            read
+        })
+    });
+    (read, recorder.handle().render())
+}
+
+fn pids(read: &[(u32, Option<SystemTime>)]) -> Vec<u32> {
+    read.iter().map(|(pid, _)| *pid).collect()
+}
+
+#[test]
+fn the_whole_backlog_is_read_by_default() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("process_ring");
+    let hour_ago = micros(SystemTime::now() - Duration::from_secs(3_600));
+    let _mmap = ring_with(&path, &[hour_ago, 0, hour_ago + 1]);
+
+    let ring = MemoryRing::open_with_policy(&path, ReplayPolicy::FromTail).unwrap();
+    let (read, _) = drain(&ring);
+    assert_eq!(read, [(1, frame_time(hour_ago)), (2, None), (3, frame_time(hour_ago + 1))]);
+}
+
+#[test]
+fn skipping_the_backlog_reads_only_new_frames() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("process_ring");
+    let now = micros(SystemTime::now());
+    let mut mmap = ring_with(&path, &[now, now, now]);
+
+    let ring = MemoryRing::open_with_policy(&path, ReplayPolicy::SkipBacklog).unwrap();
+    // Untouched until the first read, so the stored position still matches.
+    assert_eq!((ring.head(), ring.tail()), (0, 3 * 32));
+    assert_eq!(drain(&ring).0, []);
+    assert_eq!(ring.head(), ring.tail());
+
+    push(&mut mmap, 4, now);
+    push(&mut mmap, 5, now);
+    assert_eq!(pids(&drain(&ring).0), [4, 5]);
+}
+
+#[test]
+fn frames_older_than_the_max_age_are_discarded_until_a_young_one() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("process_ring");
+    let now = SystemTime::now();
+    let old = micros(now - Duration::from_secs(60));
+    let young = micros(now - Duration::from_secs(5));
+    // The old frame after the young one is no longer backlog.
+    let mut mmap = ring_with(&path, &[old, old, young, old]);
+
+    let ring = MemoryRing::open_with_policy(&path, ReplayPolicy::MaxAge(Duration::from_secs(30))).unwrap();
+    let (read, text) = drain(&ring);
+    assert_eq!(pids(&read), [3, 4]);
+    assert!(text.contains("ring_replay_skipped_total 2"), "{text}");
+
+    push(&mut mmap, 5, old);
+    assert_eq!(pids(&drain(&ring).0), [5]);
+}
+
+#[test]
+fn frames_without_a_time_end_the_backlog() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("process_ring");
+    let old = micros(SystemTime::now() - Duration::from_secs(60));
+    let _mmap = ring_with(&path, &[old, 0, old]);
+
+    let ring = MemoryRing::open_with_policy(&path, ReplayPolicy::MaxAge(Duration::from_secs(30))).unwrap();
+    assert_eq!(pids(&drain(&ring).0), [2, 3]);
+}
+
+#[test]
+fn policies_are_read_from_config_text() {
+    let policy = |s: &str| ReplayPolicy::try_from(s.to_owned());
+    assert_eq!(policy("all"), Ok(ReplayPolicy::FromTail));
+    assert_eq!(policy("NONE"), Ok(ReplayPolicy::SkipBacklog));
+    assert_eq!(policy("30s"), Ok(ReplayPolicy::MaxAge(Duration::from_secs(30))));
+    assert!(policy("soon").unwrap_err().contains("invalid replay 'soon'"));
+    assert_eq!(String::from(ReplayPolicy::MaxAge(Duration::from_secs(90))), "1m 30s");
+}
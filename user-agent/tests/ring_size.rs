@@ -0,0 +1,81 @@
+// tests/ring_size.rs
+//
+// Rings are as large as the driver negotiated at load, not a size built into
+// the agent: the reader takes the data area from the view and checks it
+// against what the driver reports. The size asked of the driver is rounded
+// to pages and kept within bounds, and `[ring] size_bytes` outside them is
+// refused.
+
+use std::{fs::OpenOptions, path::Path, sync::atomic::Ordering};
+use memmap2::{MmapMut, MmapOptions};
+use tempfile::tempdir;
+use tokio::runtime::Builder;
+
+use agent::comms::{driver_params::parameters_key, memory_ring::MemoryRing};
+use agent::config::loader::parse;
+use shared::{
+    constants::{ring_size, RING_SIZE_MAX, RING_SIZE_MIN},
+    ring::{self, RingHeader},
+};
+
+/// A ring of `size` data bytes at `path`, as the driver maps it.
+fn ring_of(path: &Path, size: usize) -> MmapMut {
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path).unwrap();
+    file.set_len((ring::HEADER_SIZE + size) as u64).unwrap();
+    let mut mmap = unsafe { MmapOptions::new().map_mut(&file).unwrap() };
+    unsafe { (mmap.as_mut_ptr() as *mut RingHeader).write(RingHeader::new()) };
+    mmap
+}
+
+#[test]
+fn requested_sizes_become_whole_pages_within_bounds() {
+    assert_eq!(ring_size(4 << 20), 4 << 20);
+    assert_eq!(ring_size(190_000), 47 * 4096);
+    assert_eq!(ring_size(1), RING_SIZE_MIN);
+    assert_eq!(ring_size(u32::MAX), RING_SIZE_MAX);
+    assert_eq!(parameters_key(), r"SYSTEM\CurrentControlSet\Services\edr_driver\Parameters");
+}
+
+#[test]
+fn reader_follows_a_ring_larger_than_the_default() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("process_ring");
+    let size = ring_size(190_000) as usize;
+    let mut mmap = ring_of(&path, size);
+    let reader = MemoryRing::open(&path).unwrap();
+    assert_eq!(reader.capacity(), size as u64);
+    assert_eq!(reader.stats().size, size as u32);
+    reader.expect_size(size as u32).unwrap();
+    assert!(reader.expect_size(size as u32 + 4096).is_err());
+
+    // Frames across the whole area and past its end, well beyond 64 KiB.
+    let (header, data) = mmap.split_at_mut(ring::HEADER_SIZE);
+    let header = unsafe { &*(header.as_ptr() as *const RingHeader) };
+    let rt = Builder::new_current_thread().build().unwrap();
+    let payload = vec![0x5a; 4_000];
+    let mut seq = 0;
+    for _ in 0..3 {
+        let mut batch = 0;
+        while ring::push(header, data, seq + 1, 0, &payload) {
+            seq += 1;
+            batch += 1;
+        }
+        assert!(batch * payload.len() > 40_000);
+        for _ in 0..batch {
+            let got = rt.block_on(reader.pop_frame()).unwrap();
+            assert_eq!(got.data, payload);
+        }
+        assert_eq!(reader.head(), header.tail.load(Ordering::Acquire));
+    }
+    assert_eq!(header.dropped.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+fn configured_sizes_must_be_within_bounds() {
+    let base = std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml")).unwrap();
+    let with = |bytes: u32| parse(&base.replacen("[ring]\n", &format!("[ring]\nsize_bytes = {bytes}\n"), 1));
+    assert_eq!(with(4 << 20).unwrap().ring.size_bytes, Some(4 << 20));
+    assert!(with(RING_SIZE_MIN - 1).unwrap_err().to_string().contains("ring.size_bytes"));
+    assert!(with(RING_SIZE_MAX + 1).is_err());
+    assert_eq!(parse(&base).unwrap().ring.size_bytes, None);
+}
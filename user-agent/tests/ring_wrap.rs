@@ -0,0 +1,160 @@
+// tests/ring_wrap.rs
+//
+// The producer side of the ring (`shared::ring::push`) against the reader,
+// over thousands of frames of random size that keep wrapping the end of the
+// data area: every accepted frame comes out whole and in order, and only
+// frames that did not fit are counted as dropped, and missed in the frame
+// numbers.
+
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    path::Path,
+    sync::atomic::Ordering,
+};
+use memmap2::MmapOptions;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tempfile::tempdir;
+use tokio::runtime::Builder;
+
+use agent::comms::memory_ring::{GapMonitor, MemoryRing};
+use shared::ring::{self, RingHeader};
+
+/// xorshift64*, so every run sees the same sequence.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+struct Outcome {
+    delivered: usize,
+    dropped:   u32,
+    wraps:     usize,
+}
+
+/// Runs `messages` pushes interleaved with reads on a ring of `size` data
+/// bytes, checking every frame read against what was pushed.
+fn exchange(path: &Path, size: usize, messages: usize, seed: u64) -> Outcome {
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path).unwrap();
+    file.set_len((ring::HEADER_SIZE + size) as u64).unwrap();
+    let mut mmap = unsafe { MmapOptions::new().map_mut(&file).unwrap() };
+    unsafe { (mmap.as_mut_ptr() as *mut RingHeader).write(RingHeader::new()) };
+    let reader = MemoryRing::open(path).unwrap();
+
+    let (header, data) = mmap.split_at_mut(ring::HEADER_SIZE);
+    let header = unsafe { &*(header.as_ptr() as *const RingHeader) };
+    let rt = Builder::new_current_thread().build().unwrap();
+    let mut rng = Rng(seed);
+    let mut pending = VecDeque::new();
+    let (mut delivered, mut wraps, mut refused) = (0, 0, 0);
+    let gaps = GapMonitor::default();
+    let (mut first, mut last, mut missing) = (None, 0, 0);
+    let mut check = |(seq, payload): (u64, Vec<u8>), i| {
+        let got = rt.block_on(reader.pop_frame()).unwrap();
+        assert_eq!((got.seq, got.data), (seq, payload), "message {i}");
+        missing += gaps.observe("wrap", seq);
+        first.get_or_insert(seq);
+        last = seq;
+    };
+
+    for i in 0..messages {
+        // Up to a third of the ring, so frames of every size meet the end.
+        let mut payload = vec![0u8; rng.below(size / 3)];
+        payload.iter_mut().for_each(|b| *b = rng.next() as u8);
+        if i % 7 == 0 {
+            // Bytes that look like the start of a frame.
+            payload.splice(0..0, *b"GX");
+        }
+        let before = header.tail.load(Ordering::Relaxed);
+        let seq = i as u64 + 1;
+        if ring::push(header, data, seq, 0, &payload) {
+            wraps += usize::from(header.tail.load(Ordering::Relaxed) < before);
+            pending.push_back((seq, payload));
+        } else {
+            refused += 1;
+        }
+
+        // Read a few, sometimes everything, sometimes nothing.
+        let reads = if rng.below(4) == 0 { pending.len() } else { rng.below(3) };
+        for _ in 0..reads.min(pending.len()) {
+            check(pending.pop_front().unwrap(), i);
+            delivered += 1;
+        }
+    }
+    while let Some(expected) = pending.pop_front() {
+        check(expected, messages);
+        delivered += 1;
+    }
+    assert_eq!(reader.head(), reader.tail(), "nothing left behind");
+    let dropped = header.dropped.load(Ordering::Relaxed);
+    assert_eq!(dropped, refused);
+    // Every number between the first and last frame read is either read or
+    // a dropped push.
+    assert_eq!(missing, last + 1 - first.unwrap() - delivered as u64);
+    Outcome { delivered, dropped, wraps }
+}
+
+#[test]
+fn frames_survive_the_wrap() {
+    let dir = tempdir().unwrap();
+    let recorder = PrometheusBuilder::new().build_recorder();
+    metrics::with_local_recorder(&recorder, || {
+        for (size, seed) in [(256, 1), (1_000, 2), (4_096, 3), (4_096, 0x9E37_79B9_7F4A_7C15)] {
+            let out = exchange(&dir.path().join("ring"), size, 5_000, seed);
+            assert!(out.wraps > 100, "size {size}: only {} wraps", out.wraps);
+            assert!(out.delivered > 2_500, "size {size}: {} delivered, {} dropped", out.delivered, out.dropped);
+        }
+    });
+    // Padding before the wrap is never taken for damage.
+    let text = recorder.handle().render();
+    assert!(!text.contains("ring_skipped_bytes_total"), "{text}");
+}
+
+#[test]
+fn the_tail_never_catches_up_with_the_head() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("ring");
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+    file.set_len((ring::HEADER_SIZE + 128) as u64).unwrap();
+    let mut mmap = unsafe { MmapOptions::new().map_mut(&file).unwrap() };
+    let (header, data) = mmap.split_at_mut(ring::HEADER_SIZE);
+    let header = unsafe { &mut *(header.as_mut_ptr() as *mut RingHeader) };
+    *header = RingHeader::new();
+
+    // 4 frames of 32 bytes would fill it exactly: the last is refused.
+    let payload = [7u8; 6];
+    assert_eq!(ring::frame_len(payload.len()), 32);
+    for seq in 1..=3 {
+        assert!(ring::push(header, data, seq, 0, &payload));
+    }
+    assert!(!ring::push(header, data, 4, 0, &payload));
+    assert_eq!((header.tail.load(Ordering::Relaxed), header.dropped.load(Ordering::Relaxed)), (96, 1));
+
+    // Once the first two are read, the next ends exactly at the end and the
+    // tail goes back to 0; one that does not fit before the end starts at 0.
+    header.head.store(64, Ordering::Relaxed);
+    assert!(ring::push(header, data, 5, 0, &payload));
+    assert_eq!(header.tail.load(Ordering::Relaxed), 0);
+    assert!(ring::push(header, data, 6, 0, &payload));
+    assert_eq!(header.tail.load(Ordering::Relaxed), 32);
+    assert!(!ring::push(header, data, 7, 0, &payload), "would end on the head");
+
+    // The 24 bytes left before the end are zeroed: padding to the reader.
+    header.head.store(72, Ordering::Relaxed);
+    header.tail.store(104, Ordering::Relaxed);
+    data[104..].fill(0xAA);
+    assert!(ring::push(header, data, 8, 0, &payload));
+    assert_eq!(header.tail.load(Ordering::Relaxed), 32);
+    assert_eq!(data[104..], [0; 24]);
+    assert_eq!(ring::check_frame(&data[..32]), Some(ring::Frame { seq: 8, ts: 0, payload: 26..32 }));
+}
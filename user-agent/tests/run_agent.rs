@@ -0,0 +1,74 @@
+// tests/run_agent.rs
+//
+// `run_agent` is what both the service and console mode run: with its stop
+// already signalled it starts every component, stops them again and
+// returns, and a config it cannot load is an error rather than a panic.
+//
+// Off Windows the ring names are plain relative paths, so the process ring
+// is a file in the working directory.
+
+use std::{env, fs, fs::OpenOptions, path::Path, sync::mpsc};
+use memmap2::MmapOptions;
+use tempfile::tempdir;
+
+use agent::run::{run_agent, Phase, RunOptions};
+use shared::constants::{ring_path, PROCESS_RING};
+use shared::ring::{self, RingHeader};
+
+/// The shipped config without listeners or the probe.
+fn config(dir: &Path) -> std::path::PathBuf {
+    let shipped = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml")).unwrap();
+    let text = shipped
+        .replace("grpc_bind = \"0.0.0.0:50051\"", "grpc_bind = \"127.0.0.1:0\"")
+        .replacen("listen       = true", "listen       = false", 1)
+        .replacen("[probe]\nenabled           = true", "[probe]\nenabled           = false", 1);
+    let path = dir.join("config.toml");
+    fs::write(&path, text).unwrap();
+    path
+}
+
+/// Creates the process ring in the working directory.
+fn process_ring() {
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true)
+        .open(ring_path(PROCESS_RING))
+        .unwrap();
+    file.set_len((ring::HEADER_SIZE + 64 * 1024) as u64).unwrap();
+    let mut mmap = unsafe { MmapOptions::new().map_mut(&file).unwrap() };
+    unsafe { (mmap.as_mut_ptr() as *mut RingHeader).write(RingHeader::new()) };
+    mmap.flush().unwrap();
+}
+
+#[test]
+fn cancelled_run_starts_and_stops_cleanly() {
+    let dir = tempdir().unwrap();
+    env::set_current_dir(dir.path()).unwrap();
+    process_ring();
+    let opts = RunOptions {
+        config:          config(dir.path()),
+        dir:             dir.path().to_path_buf(),
+        log_level:       Some("warn".into()),
+        take_over_stale: false,
+    };
+
+    let (stop, stopped) = mpsc::channel::<()>();
+    stop.send(()).unwrap();
+    let mut phases = Vec::new();
+    run_agent(&opts, stopped, |p| phases.push(p)).unwrap();
+
+    assert_eq!(phases, [Phase::Running, Phase::Stopping]);
+    assert!(dir.path().join("telemetry.db").is_file());
+}
+
+#[test]
+fn unreadable_config_is_an_error() {
+    let dir = tempdir().unwrap();
+    let opts = RunOptions {
+        config:          dir.path().join("missing.toml"),
+        dir:             dir.path().to_path_buf(),
+        log_level:       None,
+        take_over_stale: false,
+    };
+    let (_stop, stopped) = mpsc::channel();
+    let err = run_agent(&opts, stopped, |_| panic!("never started")).unwrap_err();
+    assert!(format!("{err:#}").contains("missing.toml"), "{err:#}");
+}
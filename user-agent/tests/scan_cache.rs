@@ -0,0 +1,129 @@
+// tests/scan_cache.rs
+//
+// The scanner cache round-trips through `scan_cache`, saves only write what
+// changed, a cache that cannot be read fails opening, and a JSON cache from
+// an older version is imported once.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+use agent::{
+    db::scan_cache::load_cache,
+    scanner::cache::{migrate_legacy, FileCacheEntry, PersistentCache, LEGACY_FILE, SKIPPED_OFFLINE, UNSTABLE},
+};
+
+fn entry(hash: u64, size: Option<u64>) -> FileCacheEntry {
+    FileCacheEntry {
+        hash,
+        timestamp: 1_760_000_000,
+        scan_result: Some("Processed".into()),
+        size,
+        sha256: None,
+        retry_after: None,
+    }
+}
+
+fn last_seen(conn: &Connection, path: &str) -> i64 {
+    conn.query_row("SELECT last_seen FROM scan_cache WHERE path = ?1", [path], |r| r.get(0)).unwrap()
+}
+
+#[test]
+fn entries_round_trip_and_saves_are_incremental() {
+    let dir = tempdir().unwrap();
+    let db = dir.path().join("agent.db");
+    let mut cache = HashMap::from([
+        // Above i64::MAX: stored as its bit pattern.
+        (PathBuf::from("/pf/a.exe"), entry(u64::MAX - 1, Some(10))),
+        (PathBuf::from("/pf/a.exe:payload"), FileCacheEntry { sha256: Some("ab".repeat(32)), ..entry(2, Some(3)) }),
+        (PathBuf::from("/pf/cloud.exe"), FileCacheEntry { scan_result: Some(SKIPPED_OFFLINE.into()), ..entry(0, Some(9)) }),
+        (PathBuf::from("/pf/old.dll"), entry(4, None)),
+        (
+            PathBuf::from("/pf/setup.exe"),
+            FileCacheEntry { scan_result: Some(UNSTABLE.into()), retry_after: Some(1_760_000_030), ..entry(0, Some(7)) },
+        ),
+    ]);
+
+    let mut store = PersistentCache::new(Connection::open(&db).unwrap());
+    assert!(store.load().is_empty());
+    assert_eq!(store.save(&Mutex::new(cache.clone())).unwrap(), 5);
+    let conn = Connection::open(&db).unwrap();
+    assert_eq!(load_cache(&conn).unwrap(), cache);
+
+    // Nothing changed: nothing written.
+    conn.execute("UPDATE scan_cache SET last_seen = 0", []).unwrap();
+    assert_eq!(store.save(&Mutex::new(cache.clone())).unwrap(), 0);
+    assert_eq!(last_seen(&conn, "/pf/a.exe"), 0);
+
+    cache.insert("/pf/a.exe".into(), entry(5, Some(11)));
+    cache.remove(Path::new("/pf/old.dll"));
+    assert_eq!(store.save(&Mutex::new(cache.clone())).unwrap(), 2);
+    assert_eq!(load_cache(&conn).unwrap(), cache);
+    assert!(last_seen(&conn, "/pf/a.exe") > 0);
+    assert_eq!(last_seen(&conn, "/pf/cloud.exe"), 0);
+
+    // A new store starts from what is stored.
+    let mut reopened = PersistentCache::new(Connection::open(&db).unwrap());
+    assert_eq!(reopened.load(), cache);
+    assert_eq!(reopened.save(&Mutex::new(cache)).unwrap(), 0);
+}
+
+#[test]
+fn opening_reads_the_cache_up_front() {
+    let dir = tempdir().unwrap();
+    let db = dir.path().join("agent.db");
+    let cache = HashMap::from([(PathBuf::from("/pf/a.exe"), entry(1, Some(10)))]);
+    PersistentCache::new(Connection::open(&db).unwrap()).save(&Mutex::new(cache.clone())).unwrap();
+
+    let mut store = PersistentCache::open(Connection::open(&db).unwrap()).unwrap();
+    // Read already; what changes afterwards waits for the next start.
+    Connection::open(&db).unwrap().execute("DELETE FROM scan_cache", []).unwrap();
+    assert_eq!(store.load(), cache);
+
+    // Left by something else: the scanner does not start on it.
+    let conn = Connection::open(dir.path().join("other.db")).unwrap();
+    conn.execute_batch("CREATE TABLE scan_cache (path TEXT PRIMARY KEY);").unwrap();
+    assert!(PersistentCache::open(conn).is_err());
+}
+
+#[test]
+fn legacy_json_is_imported_once() {
+    let dir = tempdir().unwrap();
+    let legacy = dir.path().join(LEGACY_FILE);
+    fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/persistent_cache.json"), &legacy).unwrap();
+    let conn = Connection::open(dir.path().join("agent.db")).unwrap();
+
+    assert_eq!(migrate_legacy(&conn, &legacy), 4);
+    assert!(!legacy.exists());
+    assert!(dir.path().join(format!("{LEGACY_FILE}.migrated")).exists());
+
+    let cache = load_cache(&conn).unwrap();
+    assert_eq!(cache.len(), 4);
+    let tool = &cache[Path::new(r"C:\Program Files\Tool\tool.exe")];
+    assert_eq!((tool.hash, tool.size), (18_364_758_544_493_064_720, Some(40_960)));
+    let stream = &cache[Path::new(r"C:\Program Files\Tool\tool.exe:payload")];
+    assert_eq!(stream.sha256.as_deref(), Some("9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"));
+    assert_eq!(cache[Path::new(r"C:\Users\a\OneDrive\setup.exe")].scan_result.as_deref(), Some(SKIPPED_OFFLINE));
+    assert_eq!(cache[Path::new(r"C:\Windows\old.dll")].size, None);
+
+    // Already migrated.
+    assert_eq!(migrate_legacy(&conn, &legacy), 0);
+    assert_eq!(load_cache(&conn).unwrap().len(), 4);
+}
+
+#[test]
+fn unreadable_legacy_file_is_set_aside() {
+    let dir = tempdir().unwrap();
+    let legacy = dir.path().join(LEGACY_FILE);
+    fs::write(&legacy, "{ not json").unwrap();
+    let conn = Connection::open_in_memory().unwrap();
+
+    assert_eq!(migrate_legacy(&conn, &legacy), 0);
+    assert!(!legacy.exists());
+    assert!(load_cache(&conn).unwrap().is_empty());
+}
@@ -0,0 +1,165 @@
+// tests/scan_events.rs
+//
+// New or changed files found by the scanner end up in `scan_results`.
+
+mod common;
+
+use std::{fs, path::Path, sync::Arc, thread, time::Duration};
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::{
+    runtime::Runtime,
+    sync::{broadcast, mpsc},
+};
+
+use agent::{
+    comms::{listeners::Buses, WrappedEvent},
+    config::model::{DirectoryRisk, HashAlgorithm, RiskGroup, ScanningConfig, SchedulingConfig},
+    db::{connection::init_database, spawn_writer},
+    idle::IdleGate,
+    db::scan_cache::load_cache,
+    scanner::{cache::PersistentCache, run_scanner, scheduler, worker::SCANNER_SENSOR, Schedule},
+    util::{Shutdown, Tasks},
+};
+use shared::events::ScanResult;
+
+fn group(root: &Path) -> RiskGroup {
+    RiskGroup {
+        risk:        DirectoryRisk::High,
+        directories: vec![root.to_owned()],
+        interval:    Some(Duration::from_secs(3600)),
+        hydrate_placeholders: false,
+        hash:        HashAlgorithm::Xxh64,
+        exclude:     vec![],
+        follow_symlinks: false,
+        max_depth:   None,
+        yara:        false,
+    }
+}
+
+#[test]
+fn scanned_executables_are_stored() {
+    let dir = tempdir().unwrap();
+    let root = dir.path().join("drop");
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::write(root.join("a.exe"), "alpha").unwrap();
+    fs::write(root.join("sub/b.exe"), "bravo!").unwrap();
+    fs::write(root.join("notes.txt"), "not executable").unwrap();
+
+    let mut db_cfg = agent::config::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml"))
+        .unwrap()
+        .database;
+    db_cfg.path = "telemetry.db".into();
+    let conn = init_database(dir.path(), &db_cfg).unwrap();
+
+    let rt = Runtime::new().unwrap();
+    let (db_tx, rx) = mpsc::channel::<WrappedEvent<ScanResult>>(16);
+    let (intel_tx, _) = broadcast::channel(16);
+    let drain = Shutdown::new();
+    let writers = Tasks::new();
+    writers.push(spawn_writer(&rt, conn, rx, &db_cfg, &drain));
+
+    let db_path = dir.path().join("telemetry.db");
+    let idle = IdleGate::new(SchedulingConfig { respect_user_activity: false, ..SchedulingConfig::default() });
+    let shutdown = Shutdown::new();
+    let scanner = {
+        let (groups, shutdown) = (vec![group(&root)], shutdown.clone());
+        let store = PersistentCache::new(Connection::open(&db_path).unwrap());
+        let buses = Buses { db_tx: db_tx.into(), intel_tx };
+        rt.spawn(run_scanner(Schedule::new(groups), store, buses, idle, shutdown, ScanningConfig::default()))
+    };
+    let conn = Connection::open(&db_path).unwrap();
+    while load_cache(&conn).unwrap().is_empty() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    shutdown.trigger();
+    rt.block_on(scanner).unwrap();
+    drain.trigger();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    assert_eq!(rt.block_on(writers.join(deadline)), 0);
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT file_path, size, hash, mtime, risk_group, sensor_guid, severity
+             FROM scan_results ORDER BY file_path",
+        )
+        .unwrap();
+    let rows: Vec<(String, i64, String, i64, String, String, String)> = stmt
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?, r.get(6)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+
+    let cache = load_cache(&conn).unwrap();
+    let expected: Vec<_> = [root.join("a.exe"), root.join("sub/b.exe")]
+        .iter()
+        .map(|p| {
+            let entry = &cache[p];
+            (
+                p.to_string_lossy().into_owned(),
+                entry.size.unwrap() as i64,
+                format!("{:016x}", entry.hash),
+                entry.timestamp as i64,
+                "High".to_owned(),
+                SCANNER_SENSOR.to_owned(),
+                "LOW".to_owned(),
+            )
+        })
+        .collect();
+    assert_eq!(rows, expected);
+    assert_eq!((rows[0].1, rows[1].1), (5, 6));
+}
+
+#[test]
+fn only_new_or_changed_files_are_published() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.exe"), "alpha").unwrap();
+    fs::write(dir.path().join("b.dll"), "bravo").unwrap();
+
+    let (db_tx, _db_rx) = mpsc::channel::<WrappedEvent<ScanResult>>(16);
+    let (intel_tx, mut intel) = broadcast::channel(16);
+    let opts = Arc::new(scheduler::publishing_options(&group(dir.path()), &Buses { db_tx: db_tx.into(), intel_tx }));
+    let cache = Default::default();
+    let dirs = [dir.path().to_owned()];
+    let mut published = || {
+        let mut paths = Vec::new();
+        while let Ok(ev) = intel.try_recv() {
+            paths.push(Path::new(&ev.payload.file_path).file_name().unwrap().to_owned());
+        }
+        paths.sort();
+        paths
+    };
+
+    common::scan_pass(&dirs, &cache, &opts);
+    assert_eq!(published(), ["a.exe", "b.dll"]);
+
+    common::scan_pass(&dirs, &cache, &opts);
+    assert!(published().is_empty());
+
+    fs::write(dir.path().join("b.dll"), "bravo, changed").unwrap();
+    common::scan_pass(&dirs, &cache, &opts);
+    assert_eq!(published(), ["b.dll"]);
+}
+
+#[test]
+fn sha256_groups_publish_the_digest() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.exe"), "abc").unwrap();
+
+    let (db_tx, _db_rx) = mpsc::channel::<WrappedEvent<ScanResult>>(16);
+    let (intel_tx, mut intel) = broadcast::channel(16);
+    let sha_group = RiskGroup { hash: HashAlgorithm::Sha256, ..group(dir.path()) };
+    let opts = Arc::new(scheduler::publishing_options(&sha_group, &Buses { db_tx: db_tx.into(), intel_tx }));
+    let cache = Default::default();
+    common::scan_pass(&[dir.path().to_owned()], &cache, &opts);
+
+    let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    let ev = intel.try_recv().unwrap();
+    assert_eq!((hex::encode(&ev.payload.sha256), ev.payload.hash), (abc.to_owned(), 0));
+    let entry = cache.lock().unwrap()[&dir.path().join("a.exe")].clone();
+    assert_eq!((entry.sha256.as_deref(), entry.hash), (Some(abc), 0));
+
+    // Unchanged on the next pass, with the same digest.
+    common::scan_pass(&[dir.path().to_owned()], &cache, &opts);
+    assert!(intel.try_recv().is_err());
+}
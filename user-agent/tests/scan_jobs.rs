@@ -0,0 +1,187 @@
+// tests/scan_jobs.rs
+//
+// Scans triggered over gRPC: `TriggerScan` queues a pass over a manual-only
+// group or explicit paths, the running engine picks it up next to its group
+// tasks, and `GetScanStatus` follows the job until its files are in the
+// scan cache and `scan_results`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::{
+    net::TcpListener,
+    runtime::Runtime,
+    sync::{broadcast, mpsc},
+};
+use tonic::{transport::Channel, Code};
+use shared::{
+    config::{
+        config_service_client::ConfigServiceClient, get_scan_status_response::State, GetScanStatusRequest,
+        GetScanStatusResponse, TriggerScanRequest,
+    },
+    events::ScanResult,
+};
+
+use agent::{
+    comms::{grpc::{self, ConfigServer, StatusServer}, listeners::Buses, WrappedEvent},
+    config::{load, model::{DirectoryRisk, HashAlgorithm, RiskGroup, ScanningConfig, SchedulingConfig}},
+    db::{connection::init_database, ops_journal::Journal, scan_cache::load_cache, spawn_writer},
+    idle::IdleGate,
+    scanner::{
+        cache::PersistentCache,
+        jobs::{JobState, ScanJobs, ScanTarget, TriggerError, MANUAL_GROUP, QUEUE_LEN},
+        run_scanner, Schedule,
+    },
+    status::AgentStats,
+    util::{Shutdown, Tasks},
+};
+
+/// A manual-only group over `root`.
+fn low(root: &Path) -> RiskGroup {
+    RiskGroup {
+        risk:        DirectoryRisk::Low,
+        directories: vec![root.to_owned()],
+        interval:    None,
+        hydrate_placeholders: false,
+        hash:        HashAlgorithm::Xxh64,
+        exclude:     vec![],
+        follow_symlinks: false,
+        max_depth:   None,
+        yara:        false,
+    }
+}
+
+fn trigger(risk: &str, paths: &[&Path]) -> TriggerScanRequest {
+    TriggerScanRequest { risk: risk.into(), paths: paths.iter().map(|p| p.display().to_string()).collect() }
+}
+
+async fn connect(server: ConfigServer, shutdown: &Shutdown) -> ConfigServiceClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(grpc::serve(listener, server, StatusServer::new(AgentStats::new()), shutdown.clone()));
+    ConfigServiceClient::connect(format!("http://{addr}")).await.unwrap()
+}
+
+/// Polls job `job_id` until it is neither queued nor running.
+async fn finished(client: &mut ConfigServiceClient<Channel>, job_id: u64) -> GetScanStatusResponse {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let status = client.get_scan_status(GetScanStatusRequest { job_id }).await.unwrap().into_inner();
+            if !matches!(status.state(), State::Queued | State::Running) {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("scan job {job_id} never finished"))
+}
+
+#[test]
+fn triggered_scans_run_on_the_engine_and_are_stored() {
+    let dir = tempdir().unwrap();
+    let (manual, other) = (dir.path().join("manual"), dir.path().join("other"));
+    fs::create_dir_all(manual.join("sub")).unwrap();
+    fs::create_dir_all(&other).unwrap();
+    fs::write(manual.join("a.exe"), "alpha").unwrap();
+    fs::write(manual.join("sub/b.dll"), "bravo!").unwrap();
+    fs::write(manual.join("notes.txt"), "not executable").unwrap();
+    fs::write(other.join("c.sys"), "charlie").unwrap();
+
+    let config = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("config.toml");
+    let mut db_cfg = load(&config).unwrap().database;
+    db_cfg.path = "telemetry.db".into();
+    let conn = init_database(dir.path(), &db_cfg).unwrap();
+    let db_path = dir.path().join("telemetry.db");
+
+    let rt = Runtime::new().unwrap();
+    let (db_tx, rx) = mpsc::channel::<WrappedEvent<ScanResult>>(16);
+    let (intel_tx, _) = broadcast::channel(16);
+    let drain = Shutdown::new();
+    let writers = Tasks::new();
+    writers.push(spawn_writer(&rt, conn, rx, &db_cfg, &drain));
+
+    // No group due: only triggered scans run.
+    let schedule = Schedule::new(vec![low(&manual)]);
+    let shutdown = Shutdown::new();
+    let scanner = {
+        let (schedule, shutdown) = (schedule.clone(), shutdown.clone());
+        let store = PersistentCache::new(Connection::open(&db_path).unwrap());
+        let buses = Buses { db_tx: db_tx.into(), intel_tx };
+        let idle = IdleGate::new(SchedulingConfig { respect_user_activity: false, ..SchedulingConfig::default() });
+        rt.spawn(run_scanner(schedule, store, buses, idle, shutdown, ScanningConfig::default()))
+    };
+
+    let server = ConfigServer::new(config, schedule, db_cfg.clone(), Journal::disabled());
+    rt.block_on(async {
+        let mut client = connect(server, &shutdown).await;
+
+        let group = client.trigger_scan(trigger("low", &[])).await.unwrap().into_inner().job_id;
+        let status = finished(&mut client, group).await;
+        assert_eq!((status.state(), status.files, status.bytes_hashed, status.error.as_str()), (State::Done, 3, 11, ""));
+
+        let paths = client.trigger_scan(trigger("", &[&other])).await.unwrap().into_inner().job_id;
+        assert!(paths > group);
+        let status = finished(&mut client, paths).await;
+        assert_eq!((status.state(), status.files), (State::Done, 1));
+
+        let refused = [
+            trigger("", &[]),
+            trigger("low", &[&other]),
+            trigger("urgent", &[]),
+            // No Medium group to take the directories from.
+            trigger("medium", &[]),
+        ];
+        for request in refused {
+            let err = client.trigger_scan(request.clone()).await.unwrap_err();
+            assert_eq!(err.code(), Code::InvalidArgument, "{request:?}: {err}");
+        }
+        let err = client.get_scan_status(GetScanStatusRequest { job_id: 999 }).await.unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+    });
+
+    shutdown.trigger();
+    rt.block_on(scanner).unwrap();
+    drain.trigger();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    assert_eq!(rt.block_on(writers.join(deadline)), 0);
+
+    let conn = Connection::open(&db_path).unwrap();
+    let cache = load_cache(&conn).unwrap();
+    for file in [manual.join("a.exe"), manual.join("sub/b.dll"), other.join("c.sys")] {
+        assert!(cache.contains_key(&file), "{} not cached", file.display());
+    }
+    let rows: Vec<(String, String)> = conn
+        .prepare("SELECT file_path, risk_group FROM scan_results ORDER BY file_path")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let name = |p: PathBuf| p.to_string_lossy().into_owned();
+    assert_eq!(rows, [
+        (name(manual.join("a.exe")), "Low".to_string()),
+        (name(manual.join("sub/b.dll")), "Low".to_string()),
+        (name(other.join("c.sys")), MANUAL_GROUP.to_string()),
+    ]);
+}
+
+#[test]
+fn triggers_beyond_the_queue_are_refused() {
+    // No engine takes the commands.
+    let jobs = ScanJobs::new();
+    let ids: Vec<u64> = (0..QUEUE_LEN).map(|_| jobs.trigger(ScanTarget::Group(DirectoryRisk::Low)).unwrap()).collect();
+    assert_eq!(ids, (1..=QUEUE_LEN as u64).collect::<Vec<_>>());
+    assert_eq!(jobs.trigger(ScanTarget::Paths(vec![])), Err(TriggerError::Full));
+    assert_eq!(jobs.status(1).map(|s| s.state), Some(JobState::Queued));
+    assert_eq!(jobs.status(QUEUE_LEN as u64 + 1), None);
+
+    jobs.started(1);
+    jobs.finished(1, Err("no [[scanner]] group low".into()));
+    let failed = jobs.status(1).unwrap();
+    assert_eq!((failed.state, failed.error.as_deref()), (JobState::Failed, Some("no [[scanner]] group low")));
+}
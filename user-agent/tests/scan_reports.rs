@@ -0,0 +1,235 @@
+// tests/scan_reports.rs
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+use agent::{
+    config::{load, model::{ConfigError, DirectoryRisk, HashAlgorithm, ReportGroup, ReportsConfig, RiskGroup}},
+    db::scan_reports::{load_baseline, recent_reports, SCAN_BASELINES_TABLE, SCAN_REPORTS_TABLE},
+    db::schema_registry::table_exists,
+    reports::{diff, is_due, run_due, snapshot, Rendered, ReportSink},
+    scanner::cache::{prune_missing, FileCacheEntry, PersistentCache, SKIPPED_OFFLINE},
+};
+
+const DAY: i64 = 24 * 3600 * 1_000_000;
+const T0: i64 = 1_760_000_000_000_000;
+
+#[derive(Default)]
+struct Recorder {
+    sent:    Mutex<Vec<(String, Rendered)>>,
+    failing: HashSet<String>,
+}
+
+impl ReportSink for Recorder {
+    fn deliver(&self, channel: &str, report: &Rendered) -> io::Result<()> {
+        if self.failing.contains(channel) {
+            return Err(io::Error::other("unreachable"));
+        }
+        self.sent.lock().unwrap().push((channel.to_owned(), report.clone()));
+        Ok(())
+    }
+}
+
+fn entry(hash: u64, size: u64) -> FileCacheEntry {
+    FileCacheEntry {
+        hash,
+        timestamp: 1,
+        scan_result: Some("Processed".into()),
+        size: Some(size),
+        sha256: None,
+        retry_after: None,
+    }
+}
+
+fn cache(entries: &[(&str, FileCacheEntry)]) -> HashMap<PathBuf, FileCacheEntry> {
+    entries.iter().map(|(p, e)| (PathBuf::from(p), e.clone())).collect()
+}
+
+fn scanner() -> Vec<RiskGroup> {
+    vec![RiskGroup {
+        risk:        DirectoryRisk::High,
+        directories: vec![PathBuf::from("/pf")],
+        interval:    Some(Duration::from_secs(60)),
+        hydrate_placeholders: false,
+        hash:        HashAlgorithm::Xxh64,
+        exclude:     vec![],
+        follow_symlinks: false,
+        max_depth:   None,
+        yara:        false,
+    }]
+}
+
+fn reports(max_entries: usize) -> ReportsConfig {
+    ReportsConfig {
+        enabled: true,
+        max_entries,
+        groups: vec![ReportGroup {
+            risk:     DirectoryRisk::High,
+            interval: Duration::from_secs(7 * 24 * 3600),
+            channels: vec!["pager".into(), "siem".into()],
+        }],
+    }
+}
+
+#[test]
+fn first_run_records_a_baseline_only() {
+    let conn = Connection::open_in_memory().unwrap();
+    let sink = Recorder::default();
+    let state = cache(&[("/pf/a.exe", entry(1, 10)), ("/pf/b.dll", entry(2, 20)), ("/other/c.exe", entry(3, 30))]);
+
+    let ids = run_due(&conn, &reports(50), &scanner(), &state, &sink, T0).unwrap();
+    assert_eq!(ids.len(), 1);
+    assert!(table_exists(&conn, SCAN_REPORTS_TABLE.name).unwrap());
+    assert!(table_exists(&conn, SCAN_BASELINES_TABLE.name).unwrap());
+    assert_eq!(load_baseline(&conn, "high").unwrap().len(), 2);
+
+    let stored = &recent_reports(&conn, "high", 10).unwrap()[0];
+    assert!(stored.baseline);
+    assert_eq!((stored.since, stored.added, stored.removed, stored.changed), (None, 0, 0, 0));
+    assert!(stored.body.text.contains("Recorded 2 executables"));
+    assert_eq!(stored.delivered_to, ["pager", "siem"]);
+    assert_eq!(sink.sent.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn second_run_reports_known_changes() {
+    let conn = Connection::open_in_memory().unwrap();
+    let sink = Recorder { failing: HashSet::from(["siem".to_owned()]), ..Recorder::default() };
+    let before = cache(&[
+        ("/pf/kept.exe", entry(1, 100)),
+        ("/pf/patched.dll", entry(2, 200)),
+        ("/pf/gone.exe", entry(3, 300)),
+        ("/pf/cloud.exe", FileCacheEntry { hash: 0, scan_result: Some(SKIPPED_OFFLINE.into()), ..entry(0, 50) }),
+    ]);
+    let after = cache(&[
+        ("/pf/kept.exe", entry(1, 100)),
+        ("/pf/patched.dll", entry(0xabc, 260)),
+        ("/pf/new.exe", entry(4, 400)),
+        ("/pf/cloud.exe", entry(5, 50)),
+    ]);
+    run_due(&conn, &reports(50), &scanner(), &before, &sink, T0).unwrap();
+
+    // Not due before the interval has passed.
+    assert!(run_due(&conn, &reports(50), &scanner(), &after, &sink, T0 + 6 * DAY).unwrap().is_empty());
+    assert_eq!(run_due(&conn, &reports(50), &scanner(), &after, &sink, T0 + 7 * DAY).unwrap().len(), 1);
+
+    let stored = &recent_reports(&conn, "high", 10).unwrap()[0];
+    assert!(!stored.baseline);
+    assert_eq!(stored.since, Some(T0));
+    assert_eq!((stored.added, stored.removed, stored.changed), (1, 1, 1));
+    assert_eq!(stored.delivered_to, ["pager"]);
+
+    let json: serde_json::Value = serde_json::from_str(&stored.body.json).unwrap();
+    assert_eq!(json["added"]["entries"][0]["path"], "/pf/new.exe");
+    assert_eq!(json["removed"]["entries"][0]["path"], "/pf/gone.exe");
+    let changed = &json["changed"]["entries"][0];
+    assert_eq!(changed["path"], "/pf/patched.dll");
+    assert_eq!(changed["old_hash"], "0000000000000002");
+    assert_eq!(changed["new_hash"], "0000000000000abc");
+    assert_eq!(changed["size_delta"], 60);
+    assert!(stored.body.text.contains("0000000000000002 -> 0000000000000abc, +60 bytes"));
+    assert!(stored.body.html.contains("<td>/pf/patched.dll</td>"));
+
+    // The baseline moved to the reported state.
+    assert_eq!(load_baseline(&conn, "high").unwrap(), snapshot(&after, &[PathBuf::from("/pf")]));
+}
+
+#[test]
+fn large_reports_are_truncated() {
+    let conn = Connection::open_in_memory().unwrap();
+    let sink = Recorder::default();
+    run_due(&conn, &reports(3), &scanner(), &HashMap::new(), &sink, T0).unwrap();
+    let many: Vec<(String, FileCacheEntry)> =
+        (0..10).map(|i| (format!("/pf/tool{i:02}.exe"), entry(i, 1))).collect();
+    let state = many.iter().map(|(p, e)| (PathBuf::from(p), e.clone())).collect();
+    run_due(&conn, &reports(3), &scanner(), &state, &sink, T0 + 7 * DAY).unwrap();
+
+    let stored = &recent_reports(&conn, "high", 1).unwrap()[0];
+    assert_eq!(stored.added, 10);
+    let json: serde_json::Value = serde_json::from_str(&stored.body.json).unwrap();
+    assert_eq!(json["added"]["entries"].as_array().unwrap().len(), 3);
+    assert_eq!(json["added"]["omitted"], 7);
+    assert!(stored.body.text.contains("... and 7 more"));
+    assert!(stored.body.html.contains("... and 7 more"));
+}
+
+#[test]
+fn diff_ignores_unread_placeholders_and_escapes_html() {
+    let old = snapshot(
+        &cache(&[("/pf/a.exe", FileCacheEntry { scan_result: Some(SKIPPED_OFFLINE.into()), ..entry(0, 1) })]),
+        &[PathBuf::from("/pf")],
+    );
+    let new = snapshot(&cache(&[("/pf/a.exe", entry(7, 1))]), &[PathBuf::from("/pf")]);
+    assert!(diff(&old, &new).is_empty());
+
+    let conn = Connection::open_in_memory().unwrap();
+    let sink = Recorder::default();
+    let mut cfg = reports(50);
+    cfg.groups[0].channels = vec!["siem".into()];
+    run_due(&conn, &cfg, &scanner(), &HashMap::new(), &sink, T0).unwrap();
+    run_due(&conn, &cfg, &scanner(), &cache(&[("/pf/<b>&.exe", entry(1, 1))]), &sink, T0 + 8 * DAY).unwrap();
+    let html = &sink.sent.lock().unwrap()[1].1.html;
+    assert!(html.contains("/pf/&lt;b&gt;&amp;.exe"));
+}
+
+#[test]
+fn due_scheduling() {
+    let week = Duration::from_secs(7 * 24 * 3600);
+    assert!(is_due(None, week, T0));
+    assert!(!is_due(Some(T0), week, T0 + 7 * DAY - 1));
+    assert!(is_due(Some(T0), week, T0 + 7 * DAY));
+}
+
+#[test]
+fn pruning_drops_deleted_files_and_their_streams() {
+    let mut state = cache(&[
+        ("/pf/kept.exe", entry(1, 1)),
+        ("/pf/kept.exe:payload", entry(2, 1)),
+        ("/pf/gone.exe", entry(3, 1)),
+        ("/pf/gone.txt:tool.exe", entry(4, 1)),
+        ("/elsewhere/gone.exe", entry(5, 1)),
+    ]);
+    let listed = HashSet::from([PathBuf::from("/pf/kept.exe")]);
+    assert_eq!(prune_missing(&mut state, Path::new("/pf"), &listed), 2);
+    let mut left: Vec<_> = state.keys().cloned().collect();
+    left.sort();
+    assert_eq!(left, [PathBuf::from("/elsewhere/gone.exe"), "/pf/kept.exe".into(), "/pf/kept.exe:payload".into()]);
+
+    // Sizes survive a save and load; entries without them still parse.
+    let mut store = PersistentCache::new(Connection::open_in_memory().unwrap());
+    store.save(&Mutex::new(state)).unwrap();
+    assert_eq!(store.load()[Path::new("/pf/kept.exe")].size, Some(1));
+    let legacy: FileCacheEntry =
+        serde_json::from_str(r#"{"hash":1,"timestamp":1,"scan_result":"Processed"}"#).unwrap();
+    assert_eq!(legacy.size, None);
+}
+
+#[test]
+fn report_groups_are_validated() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let base = fs::read_to_string(root.join("config.toml")).unwrap();
+    let dir = tempdir().unwrap();
+    let with = |extra: &str| {
+        let path = dir.path().join("config.toml");
+        fs::write(&path, format!("{base}\n{extra}")).unwrap();
+        load(&path)
+    };
+
+    let cfg = with("[[reports.group]]\nrisk = \"High\"\ninterval = \"weekly\"\nchannels = [\"siem\"]\n").unwrap();
+    assert_eq!(cfg.reports.groups[0].interval, Duration::from_secs(7 * 24 * 3600));
+    assert_eq!(cfg.reports.max_entries, 50);
+
+    let err = with("[[reports.group]]\nrisk = \"High\"\ninterval = \"daily\"\nchannels = [\"email\"]\n").unwrap_err();
+    assert!(matches!(err, ConfigError::InvalidReport(_, ref m) if m.contains("'email'")), "{err}");
+    assert!(matches!(
+        with("[[reports.group]]\nrisk = \"High\"\ninterval = \"fortnightly\"\n"),
+        Err(ConfigError::InvalidDuration(..))
+    ));
+}
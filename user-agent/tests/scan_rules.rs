@@ -0,0 +1,207 @@
+// tests/scan_rules.rs
+//
+// YARA rules in scanner groups: rule files are listed and compiled again
+// when they change, severities come from rule metadata, and a file matching
+// a rule is stored in `scan_results` with the rule while the others are
+// stored as before. Matching needs the engine:
+//
+//   cargo test --features yara --test scan_rules
+
+mod common;
+
+use std::{fs, path::Path, sync::Arc};
+use tempfile::tempdir;
+use tokio::sync::{broadcast, mpsc};
+
+use agent::{
+    comms::{listeners::Buses, WrappedEvent},
+    config::model::{DirectoryRisk, HashAlgorithm, RiskGroup},
+    scanner::{
+        rules::{severity, Rules, DEFAULT_SEVERITY},
+        scheduler,
+        worker::ScanOptions,
+    },
+};
+use shared::events::{scan_result::Severity, ScanResult};
+
+/// Matches files holding the marker; `high` in its metadata.
+const MARKER_RULE: &str = r#"
+rule gladix_marker {
+    meta:
+        severity = "high"
+    strings:
+        $marker = "GLADIX-TEST-MARKER"
+    condition:
+        $marker
+}
+"#;
+
+fn group(root: &Path) -> RiskGroup {
+    RiskGroup {
+        risk:        DirectoryRisk::High,
+        directories: vec![root.to_owned()],
+        interval:    None,
+        hydrate_placeholders: false,
+        hash:        HashAlgorithm::Xxh64,
+        exclude:     vec![],
+        follow_symlinks: false,
+        max_depth:   None,
+        yara:        true,
+    }
+}
+
+/// One pass over `root` running `rules`; the results published, by file
+/// name.
+fn scan(root: &Path, rules: Arc<Rules>) -> Vec<(String, ScanResult)> {
+    let (db_tx, _db_rx) = mpsc::channel::<WrappedEvent<ScanResult>>(16);
+    let (intel_tx, mut intel) = broadcast::channel(16);
+    let opts = ScanOptions {
+        rules: Some(rules),
+        ..scheduler::publishing_options(&group(root), &Buses { db_tx: db_tx.into(), intel_tx })
+    };
+    common::scan_pass(&[root.to_owned()], &Default::default(), &Arc::new(opts));
+    let mut published = Vec::new();
+    while let Ok(ev) = intel.try_recv() {
+        let name = Path::new(&ev.payload.file_path).file_name().unwrap().to_string_lossy().into_owned();
+        published.push((name, ev.payload));
+    }
+    published.sort_by(|a, b| a.0.cmp(&b.0));
+    published
+}
+
+#[test]
+fn severity_comes_from_rule_metadata() {
+    assert_eq!(severity(Some("critical")), Severity::Critical);
+    assert_eq!(severity(Some("High")), Severity::High);
+    assert_eq!(severity(Some("LOW")), Severity::Low);
+    assert_eq!(severity(Some("urgent")), DEFAULT_SEVERITY);
+    assert_eq!(severity(None), Severity::Medium);
+}
+
+#[test]
+fn rule_files_are_compiled_again_when_they_change() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("marker.yar"), MARKER_RULE).unwrap();
+    fs::write(dir.path().join("README.txt"), "not a rule").unwrap();
+    let rules = Rules::load(dir.path());
+    let (compiled, failed) = rules.counts();
+    assert_eq!(compiled + failed, 1);
+    assert!(!rules.refresh());
+
+    fs::write(dir.path().join("more.yara"), "rule empty_file { condition: filesize == 0 }").unwrap();
+    assert!(rules.refresh());
+    let (compiled, failed) = rules.counts();
+    assert_eq!(compiled + failed, 2);
+    assert!(!rules.refresh());
+
+    fs::remove_file(dir.path().join("more.yara")).unwrap();
+    assert!(rules.refresh());
+    let (compiled, failed) = rules.counts();
+    assert_eq!(compiled + failed, 1);
+}
+
+#[cfg(not(feature = "yara"))]
+#[test]
+fn without_the_engine_groups_only_hash() {
+    let rules_dir = tempdir().unwrap();
+    fs::write(rules_dir.path().join("marker.yar"), MARKER_RULE).unwrap();
+    let rules = Arc::new(Rules::load(rules_dir.path()));
+    assert!(!rules.is_loaded());
+    assert_eq!(rules.counts(), (0, 1));
+
+    let root = tempdir().unwrap();
+    fs::write(root.path().join("a.exe"), "MZ GLADIX-TEST-MARKER").unwrap();
+    let published = scan(root.path(), rules);
+    assert_eq!(published.len(), 1);
+    let result = &published[0].1;
+    assert_eq!((result.rule_id.as_str(), result.matches.len(), result.severity()), ("", 0, Severity::Low));
+}
+
+#[cfg(feature = "yara")]
+#[test]
+fn a_matching_file_is_published_with_its_rule() {
+    let rules_dir = tempdir().unwrap();
+    fs::write(rules_dir.path().join("marker.yar"), MARKER_RULE).unwrap();
+    // Does not compile; the other files still load.
+    fs::write(rules_dir.path().join("broken.yar"), "rule broken { condition: $missing }").unwrap();
+    fs::write(rules_dir.path().join("plain.yar"), "rule plain_mz { strings: $mz = \"MZ\" condition: $mz at 0 }")
+        .unwrap();
+    let rules = Arc::new(Rules::load(rules_dir.path()));
+    assert_eq!(rules.counts(), (2, 1));
+
+    let root = tempdir().unwrap();
+    fs::write(root.path().join("dropper.exe"), "MZ...GLADIX-TEST-MARKER...").unwrap();
+    fs::write(root.path().join("other.exe"), "PE without the marker").unwrap();
+    let published = scan(root.path(), rules);
+
+    let summary: Vec<_> = published
+        .iter()
+        .map(|(name, r)| (name.as_str(), r.rule_id.as_str(), r.matches.clone(), r.severity()))
+        .collect();
+    assert_eq!(summary, [
+        ("dropper.exe", "marker.gladix_marker", vec!["$marker".to_owned()], Severity::High),
+        ("dropper.exe", "plain.plain_mz", vec!["$mz".to_owned()], Severity::Medium),
+        ("other.exe", "", vec![], Severity::Low),
+    ]);
+    // Each match carries the file's digest as the plain result would.
+    assert_eq!(published[0].1.hash, published[1].1.hash);
+    assert_ne!(published[0].1.hash, 0);
+}
+
+#[cfg(feature = "yara")]
+#[test]
+fn matches_are_stored_in_scan_results() {
+    use std::time::Duration;
+    use rusqlite::Connection;
+    use tokio::runtime::Runtime;
+    use agent::{db::{connection::init_database, spawn_writer}, util::{Shutdown, Tasks}};
+
+    let dir = tempdir().unwrap();
+    let rules_dir = dir.path().join("rules");
+    let root = dir.path().join("drop");
+    fs::create_dir_all(&rules_dir).unwrap();
+    fs::create_dir_all(&root).unwrap();
+    fs::write(rules_dir.join("marker.yar"), MARKER_RULE).unwrap();
+    fs::write(root.join("dropper.exe"), "MZ GLADIX-TEST-MARKER").unwrap();
+    fs::write(root.join("clean.exe"), "MZ clean").unwrap();
+
+    let mut db_cfg = agent::config::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml"))
+        .unwrap()
+        .database;
+    db_cfg.path = "telemetry.db".into();
+    let conn = init_database(dir.path(), &db_cfg).unwrap();
+    let rt = Runtime::new().unwrap();
+    let (db_tx, rx) = mpsc::channel::<WrappedEvent<ScanResult>>(16);
+    let (intel_tx, _) = broadcast::channel(16);
+    let drain = Shutdown::new();
+    let writers = Tasks::new();
+    writers.push(spawn_writer(&rt, conn, rx, &db_cfg, &drain));
+
+    let opts = ScanOptions {
+        rules: Some(Arc::new(Rules::load(&rules_dir))),
+        ..scheduler::publishing_options(&group(&root), &Buses { db_tx: db_tx.into(), intel_tx })
+    };
+    common::scan_pass(&[root.clone()], &Default::default(), &Arc::new(opts));
+    drain.trigger();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    assert_eq!(rt.block_on(writers.join(deadline)), 0);
+
+    let conn = Connection::open(dir.path().join("telemetry.db")).unwrap();
+    let rows: Vec<(String, String, String, String)> = conn
+        .prepare("SELECT file_path, rule_id, matches, severity FROM scan_results WHERE rule_id != '' ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(rows, [(
+        root.join("dropper.exe").to_string_lossy().into_owned(),
+        "marker.gladix_marker".to_owned(),
+        "$marker".to_owned(),
+        "HIGH".to_owned(),
+    )]);
+    let clean: i64 = conn
+        .query_row("SELECT count(*) FROM scan_results WHERE file_path LIKE '%clean.exe' AND rule_id = ''", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(clean, 1);
+}
@@ -0,0 +1,88 @@
+// tests/scan_throttle.rs
+//
+// The read budget shared by scan workers: a bucket holding a second of
+// reads, after which readers wait out their debt. A throttled pass takes
+// longer and leaves the same cache behind.
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tempfile::tempdir;
+
+use agent::{
+    config::model::HashAlgorithm,
+    scanner::{
+        cache::FileCacheEntry,
+        throttle::ReadThrottle,
+        worker::ScanOptions,
+    },
+};
+
+type Cache = Arc<Mutex<HashMap<PathBuf, FileCacheEntry>>>;
+
+const KIB: u64 = 1024;
+
+#[test]
+fn debt_is_waited_out_at_the_configured_rate() {
+    assert!(ReadThrottle::new(0).is_none());
+
+    let throttle = ReadThrottle::new(100 * KIB).unwrap();
+    let t0 = Instant::now();
+    // A full second of reads is free, the next 50 KiB cost half a second.
+    assert_eq!(throttle.charge(100 * KIB as usize, t0), Duration::ZERO);
+    assert_eq!(throttle.charge(50 * KIB as usize, t0), Duration::from_millis(500));
+    // A quarter of a second later the debt is down to 25 KiB.
+    assert_eq!(throttle.charge(0, t0 + Duration::from_millis(250)), Duration::from_millis(250));
+    // Idle time refills at most a second's worth.
+    let later = t0 + Duration::from_secs(10);
+    assert_eq!(throttle.charge(100 * KIB as usize, later), Duration::ZERO);
+    assert!(throttle.charge(1, later) > Duration::ZERO);
+}
+
+#[test]
+fn throttled_pass_is_slower_and_caches_the_same() {
+    let dir = tempdir().unwrap();
+    for i in 0..8 {
+        fs::write(dir.path().join(format!("f{i}.exe")), vec![i as u8; 64 * KIB as usize]).unwrap();
+    }
+    let dirs = [dir.path().to_owned()];
+    let pass = |throttle: Option<ReadThrottle>| {
+        let opts = Arc::new(ScanOptions {
+            max_size: 1 << 20,
+            exts: vec!["exe".into()],
+            hydrate_placeholders: false,
+            hash: HashAlgorithm::Both,
+            listing: Default::default(),
+            events: None,
+            throttle: throttle.map(Arc::new),
+            rules: None,
+        });
+        let cache: Cache = Default::default();
+        let summary = common::scan_pass(&dirs, &cache, &opts);
+        let cache = Arc::try_unwrap(cache).unwrap().into_inner().unwrap();
+        (summary, cache)
+    };
+
+    let (fast, unthrottled) = pass(None);
+    // 512 KiB at 256 KiB/s: the first second's worth is free, the rest waits.
+    let (slow, throttled) = pass(ReadThrottle::new(256 * KIB));
+
+    assert_eq!((fast.files, fast.bytes), (8, 512 * KIB));
+    assert_eq!((slow.files, slow.bytes), (fast.files, fast.bytes));
+    assert!(slow.elapsed >= Duration::from_millis(900), "{:?}", slow.elapsed);
+    assert!(fast.elapsed < slow.elapsed, "{:?} vs {:?}", fast.elapsed, slow.elapsed);
+
+    let entries = |c: &HashMap<PathBuf, FileCacheEntry>| {
+        let mut v: Vec<_> = c.iter().map(|(p, e)| (p.clone(), e.hash, e.sha256.clone(), e.size)).collect();
+        v.sort();
+        v
+    };
+    assert_eq!(entries(&throttled), entries(&unthrottled));
+    assert_eq!(throttled.len(), 8);
+}
@@ -0,0 +1,85 @@
+// tests/scan_unstable.rs
+//
+// A file hashed while another thread keeps appending to it is recorded as
+// unstable instead of with the hash of a partial file, is left alone until
+// its retry time, and is hashed normally once the writing stops.
+
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tempfile::tempdir;
+
+use agent::config::model::HashAlgorithm;
+use agent::scanner::{
+    cache::UNSTABLE,
+    hash::compute_file_hash,
+    worker::{process_file, ScanOptions, UNSTABLE_RETRY},
+};
+
+fn opts() -> ScanOptions {
+    ScanOptions {
+        max_size:             u64::MAX,
+        exts:                 vec!["exe".into()],
+        hydrate_placeholders: false,
+        hash:                 HashAlgorithm::Xxh64,
+        listing:              Default::default(),
+        events:               None,
+        throttle:             None,
+        rules:                None,
+    }
+}
+
+#[test]
+fn a_file_being_written_is_retried_until_it_settles() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("setup.exe");
+    fs::write(&path, vec![0x4d; 1 << 20]).unwrap();
+    let cache = Arc::new(Mutex::new(HashMap::new()));
+
+    let writing = Arc::new(AtomicBool::new(true));
+    let writer = thread::spawn({
+        let (path, writing) = (path.clone(), writing.clone());
+        move || {
+            let mut file = OpenOptions::new().append(true).open(path).unwrap();
+            while writing.load(Ordering::Relaxed) {
+                file.write_all(&[0x5a; 64]).unwrap();
+            }
+        }
+    });
+
+    let recorder = PrometheusBuilder::new().build_recorder();
+    metrics::with_local_recorder(&recorder, || {
+        assert_eq!(process_file(&path, &cache, &opts()).unwrap(), 0);
+    });
+    writing.store(false, Ordering::Relaxed);
+    writer.join().unwrap();
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let unstable = cache.lock().unwrap()[&path].clone();
+    assert_eq!((unstable.scan_result.as_deref(), unstable.hash, unstable.sha256.as_deref()), (Some(UNSTABLE), 0, None));
+    let retry_after = unstable.retry_after.expect("retry time set");
+    assert!(retry_after > now && retry_after <= now + UNSTABLE_RETRY.as_secs(), "{retry_after} vs {now}");
+    let text = recorder.handle().render();
+    assert!(text.contains("scanner_unstable_total{reason=\"changing\"} 1"), "{text}");
+
+    // Settled, but not due yet: left alone.
+    assert_eq!(process_file(&path, &cache, &opts()).unwrap(), 0);
+    assert_eq!(cache.lock().unwrap()[&path], unstable);
+
+    // Due: hashed like any other file.
+    cache.lock().unwrap().get_mut(&path).unwrap().retry_after = Some(now - 1);
+    let len = fs::metadata(&path).unwrap().len();
+    assert_eq!(process_file(&path, &cache, &opts()).unwrap(), len);
+    let settled = cache.lock().unwrap()[&path].clone();
+    assert_eq!(settled.scan_result.as_deref(), Some("Processed"));
+    assert_eq!((settled.hash, settled.size, settled.retry_after), (compute_file_hash(&path).unwrap(), Some(len), None));
+}
@@ -0,0 +1,232 @@
+// tests/scanner_engines.rs
+//
+// The scanner engine over fixture trees: what a pass caches, what the walk
+// lists, and that the running engine saves its cache and stops on shutdown.
+
+mod common;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+use agent::{
+    comms::listeners::Buses,
+    config::model::{DirectoryRisk, HashAlgorithm, RiskGroup, ScanningConfig, SchedulingConfig},
+    db::scan_cache::load_cache,
+    idle::IdleGate,
+    scanner::{
+        async_engine,
+        cache::{FileCacheEntry, PersistentCache},
+        scheduler::{self, ListOptions},
+        worker::ScanOptions,
+        Schedule,
+    },
+    util::Shutdown,
+};
+
+type Cache = Arc<Mutex<HashMap<PathBuf, FileCacheEntry>>>;
+type Seen = BTreeMap<PathBuf, (u64, u64, Option<String>, Option<u64>)>;
+
+fn opts() -> Arc<ScanOptions> {
+    Arc::new(ScanOptions {
+        max_size: 1024,
+        exts: vec!["exe".into(), "dll".into(), "sys".into(), "ocx".into()],
+        hydrate_placeholders: false,
+        hash: HashAlgorithm::Xxh64,
+        listing: Default::default(),
+        events: None,
+        throttle: None,
+        rules: None,
+    })
+}
+
+fn seen(cache: &HashMap<PathBuf, FileCacheEntry>) -> Seen {
+    cache
+        .iter()
+        .map(|(p, e)| (p.clone(), (e.hash, e.timestamp, e.scan_result.clone(), e.size)))
+        .collect()
+}
+
+fn fixture(root: &Path) {
+    fs::create_dir_all(root.join("sub/deep")).unwrap();
+    fs::write(root.join("a.exe"), "alpha").unwrap();
+    fs::write(root.join("sub/b.dll"), "bravo").unwrap();
+    fs::write(root.join("sub/deep/c.sys"), "charlie").unwrap();
+    fs::write(root.join("notes.txt"), "not executable").unwrap();
+    fs::write(root.join("big.exe"), vec![0u8; 2048]).unwrap();
+    // Off Windows this stands in for an alternate stream.
+    fs::write(root.join("notes.txt:tool.exe"), "stream").unwrap();
+}
+
+#[test]
+fn passes_follow_changes_to_fixture_trees() {
+    let dir = tempdir().unwrap();
+    let root = dir.path().join("pf");
+    fixture(&root);
+    let dirs = vec![root.clone(), dir.path().join("missing")];
+    let cache: Cache = Default::default();
+
+    let summary = common::scan_pass(&dirs, &cache, &opts());
+    assert_eq!(summary.files, 6);
+    let first = seen(&cache.lock().unwrap());
+    let names: Vec<_> = first.keys().map(|p| p.strip_prefix(&root).unwrap().to_owned()).collect();
+    assert_eq!(
+        names,
+        [PathBuf::from("a.exe"), "notes.txt:tool.exe".into(), "sub/b.dll".into(), "sub/deep/c.sys".into()]
+    );
+
+    // Deleted, modified and new files.
+    fs::remove_file(root.join("a.exe")).unwrap();
+    fs::write(root.join("sub/b.dll"), "bravo, patched").unwrap();
+    fs::write(root.join("sub/e.ocx"), "echo").unwrap();
+    common::scan_pass(&dirs, &cache, &opts());
+    let second = seen(&cache.lock().unwrap());
+    assert!(!second.contains_key(&root.join("a.exe")));
+    assert!(second.contains_key(&root.join("sub/e.ocx")));
+    assert_ne!(second[&root.join("sub/b.dll")], first[&root.join("sub/b.dll")]);
+    assert_eq!(second[&root.join("sub/b.dll")].3, Some(14));
+}
+
+/// What `walk` lists under `root`, sorted.
+fn listing(root: &Path, opts: ListOptions) -> Vec<PathBuf> {
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let mut walked: Vec<PathBuf> = rt.block_on(async {
+        use futures::StreamExt;
+        async_engine::walk(root.to_owned(), Arc::new(opts)).collect().await
+    });
+    walked.sort();
+    walked
+}
+
+#[test]
+fn walk_lists_every_file() {
+    let dir = tempdir().unwrap();
+    fixture(dir.path());
+    let names: Vec<_> = listing(dir.path(), ListOptions::default())
+        .iter()
+        .map(|p| p.strip_prefix(dir.path()).unwrap().to_owned())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            PathBuf::from("a.exe"),
+            "big.exe".into(),
+            "notes.txt".into(),
+            "notes.txt:tool.exe".into(),
+            "sub/b.dll".into(),
+            "sub/deep/c.sys".into(),
+        ]
+    );
+}
+
+#[test]
+fn listing_honors_exclusions_links_and_depth() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    fixture(root);
+    fs::create_dir_all(root.join("app/node_modules/pkg")).unwrap();
+    fs::write(root.join("app/node_modules/pkg/x.dll"), "excluded").unwrap();
+    fs::write(root.join("app/y.dll"), "kept").unwrap();
+    // A link back to the root: endless unless links are skipped or tracked.
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(root, root.join("sub/loop")).unwrap();
+
+    let group = |exclude: &[&str], follow_symlinks, max_depth| ListOptions::new(&RiskGroup {
+        risk:        DirectoryRisk::High,
+        directories: vec![],
+        interval:    None,
+        hydrate_placeholders: false,
+        hash:        HashAlgorithm::Xxh64,
+        exclude:     exclude.iter().map(|p| p.to_string()).collect(),
+        follow_symlinks,
+        max_depth,
+        yara:        false,
+    });
+    let names = |paths: Vec<PathBuf>| -> Vec<PathBuf> {
+        paths.iter().map(|p| p.strip_prefix(root).unwrap().to_owned()).collect()
+    };
+
+    let listed = listing(root, group(&["**/node_modules", "**/*.txt"], false, None));
+    assert_eq!(
+        names(listed),
+        [
+            PathBuf::from("a.exe"),
+            "app/y.dll".into(),
+            "big.exe".into(),
+            "notes.txt:tool.exe".into(),
+            "sub/b.dll".into(),
+            "sub/deep/c.sys".into(),
+        ]
+    );
+
+    // Only the root and its direct subdirectories.
+    let listed = listing(root, group(&["**/node_modules"], false, Some(1)));
+    assert!(listed.contains(&root.join("sub/b.dll")));
+    assert!(!listed.contains(&root.join("sub/deep/c.sys")));
+
+    // Followed links are listed once.
+    #[cfg(unix)]
+    {
+        let listed = listing(root, group(&["**/node_modules"], true, None));
+        assert_eq!(listed.len(), 7);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn async_scanner_saves_and_stops_on_shutdown() {
+    let dir = tempdir().unwrap();
+    let root = dir.path().join("pf");
+    fixture(&root);
+    let db_path = dir.path().join("agent.db");
+    let groups = vec![
+        RiskGroup {
+            risk:        DirectoryRisk::High,
+            directories: vec![root.clone()],
+            interval:    Some(Duration::from_secs(3600)),
+            hydrate_placeholders: false,
+            hash:        HashAlgorithm::Xxh64,
+            exclude:     vec![],
+            follow_symlinks: false,
+            max_depth:   None,
+            yara:        false,
+        },
+        // Manual-only groups wait for an interval.
+        RiskGroup { risk: DirectoryRisk::Low, directories: vec![], interval: None, hydrate_placeholders: false, hash: HashAlgorithm::Xxh64,
+                    exclude: vec![], follow_symlinks: false, max_depth: None, yara: false },
+    ];
+    let idle = IdleGate::new(SchedulingConfig { respect_user_activity: false, ..SchedulingConfig::default() });
+    let shutdown = Shutdown::new();
+    let buses = Buses::new(16, 16);
+    let store = PersistentCache::new(Connection::open(&db_path).unwrap());
+    let scanner = tokio::spawn(async_engine::run_scanner(Schedule::new(groups), store, buses, idle, shutdown.clone(),
+        ScanningConfig { concurrency: 2, ..ScanningConfig::default() }));
+
+    let conn = Connection::open(&db_path).unwrap();
+    while load_cache(&conn).unwrap().is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    shutdown.trigger();
+    tokio::time::timeout(Duration::from_secs(5), scanner).await.unwrap().unwrap();
+
+    let fresh: Cache = Default::default();
+    let limit = Arc::new(tokio::sync::Semaphore::new(2));
+    let opts = Arc::new(scheduler::group_options(&RiskGroup {
+        risk:        DirectoryRisk::High,
+        directories: vec![],
+        interval:    None,
+        hydrate_placeholders: false,
+        hash:        HashAlgorithm::Xxh64,
+        exclude:     vec![],
+        follow_symlinks: false,
+        max_depth:   None,
+        yara:        false,
+    }));
+    assert!(async_engine::scan_pass(&[root], &fresh, &opts, &limit, &Shutdown::new()).await.is_some());
+    assert_eq!(seen(&load_cache(&conn).unwrap()), seen(&fresh.lock().unwrap()));
+}
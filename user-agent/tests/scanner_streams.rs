@@ -0,0 +1,116 @@
+// tests/scanner_streams.rs
+//
+// Off Windows, "file:stream" is an ordinary file name, which stands in for
+// the stream so the worker logic runs everywhere.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use tempfile::tempdir;
+
+use agent::config::model::HashAlgorithm;
+use agent::scanner::{
+    cache::SKIPPED_OFFLINE,
+    streams::{
+        is_placeholder, split_stream, stream_path, StreamInfo, FILE_ATTRIBUTE_OFFLINE,
+        FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS, FILE_ATTRIBUTE_SPARSE_FILE,
+    },
+    worker::{scan_file, FileFacts, ScanOptions},
+};
+
+fn opts(hydrate: bool) -> ScanOptions {
+    ScanOptions { max_size: 1024, exts: vec!["exe".into(), "dll".into()], hydrate_placeholders: hydrate, hash: HashAlgorithm::Xxh64, listing: Default::default(), events: None, throttle: None, rules: None }
+}
+
+fn facts(path: &Path, attributes: u32, streams: Vec<StreamInfo>) -> FileFacts {
+    FileFacts { len: fs::metadata(path).unwrap().len(), mtime: 1, attributes, streams }
+}
+
+fn stream(name: &str, size: u64) -> StreamInfo {
+    StreamInfo { name: name.into(), size }
+}
+
+#[test]
+fn stream_paths() {
+    assert_eq!(split_stream(r"C:\dir\file.txt:payload"), (r"C:\dir\file.txt", Some("payload")));
+    assert_eq!(split_stream(r"C:\dir\file.txt"), (r"C:\dir\file.txt", None));
+    assert_eq!(split_stream(r"C:\dir"), (r"C:\dir", None));
+    assert_eq!(split_stream("notes.txt:x.exe"), ("notes.txt", Some("x.exe")));
+    assert_eq!(stream_path(Path::new("a.txt"), "b.exe"), Path::new("a.txt:b.exe"));
+}
+
+#[test]
+fn placeholder_flags() {
+    assert!(is_placeholder(FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS));
+    assert!(is_placeholder(FILE_ATTRIBUTE_OFFLINE | FILE_ATTRIBUTE_SPARSE_FILE));
+    assert!(!is_placeholder(FILE_ATTRIBUTE_SPARSE_FILE));
+    assert!(!is_placeholder(0));
+}
+
+#[test]
+fn executable_streams_get_own_cache_entries() {
+    let dir = tempdir().unwrap();
+    let host = dir.path().join("notes.txt");
+    fs::write(&host, "hello").unwrap();
+    fs::write(stream_path(&host, "tool.exe"), "anything").unwrap();
+    fs::write(stream_path(&host, "blob"), b"MZ\x90\x00").unwrap();
+    fs::write(stream_path(&host, "Zone.Identifier"), "[ZoneTransfer]").unwrap();
+    fs::write(stream_path(&host, "big.exe"), vec![0u8; 2048]).unwrap();
+
+    let cache = Arc::new(Mutex::new(HashMap::new()));
+    let streams = vec![stream("tool.exe", 8), stream("blob", 4), stream("Zone.Identifier", 14), stream("big.exe", 2048)];
+    scan_file(&host, &facts(&host, 0, streams), &cache, &opts(false)).unwrap();
+
+    let cache = cache.lock().unwrap();
+    let mut keys: Vec<_> = cache.keys().map(|k| k.file_name().unwrap().to_string_lossy().into_owned()).collect();
+    keys.sort();
+    // The host itself is not executable, the zone marker is not a PE and
+    // big.exe exceeds the size cap.
+    assert_eq!(keys, vec!["notes.txt:blob", "notes.txt:tool.exe"]);
+}
+
+#[test]
+fn placeholders_are_skipped_unless_hydration_is_enabled() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("setup.exe");
+    fs::write(&file, b"MZ").unwrap();
+    fs::write(stream_path(&file, "x.exe"), b"MZ").unwrap();
+    let streams = vec![stream("x.exe", 2)];
+
+    let cache = Arc::new(Mutex::new(HashMap::new()));
+    let offline = facts(&file, FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS, streams.clone());
+    scan_file(&file, &offline, &cache, &opts(false)).unwrap();
+    {
+        let cache = cache.lock().unwrap();
+        assert_eq!(cache.len(), 1, "streams of a placeholder are not read either");
+        assert_eq!(cache[&file].scan_result.as_deref(), Some(SKIPPED_OFFLINE));
+        assert_eq!(cache[&file].hash, 0);
+    }
+
+    scan_file(&file, &offline, &cache, &opts(true)).unwrap();
+    let cache = cache.lock().unwrap();
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache[&file].scan_result.as_deref(), Some("Processed"));
+    assert_ne!(cache[&file].hash, 0);
+}
+
+#[cfg(windows)]
+#[test]
+fn enumerates_ntfs_streams() {
+    use agent::scanner::streams::alternate_streams;
+
+    let dir = tempdir().unwrap();
+    let host = dir.path().join("notes.txt");
+    fs::write(&host, "hello").unwrap();
+    assert!(alternate_streams(&host).unwrap().is_empty());
+
+    fs::write(stream_path(&host, "payload.exe"), b"MZ\x90\x00").unwrap();
+    assert_eq!(alternate_streams(&host).unwrap(), vec![stream("payload.exe", 4)]);
+
+    let cache = Arc::new(Mutex::new(HashMap::new()));
+    scan_file(&host, &FileFacts::read(&host).unwrap(), &cache, &opts(false)).unwrap();
+    assert!(cache.lock().unwrap().contains_key(&stream_path(&host, "payload.exe")));
+}
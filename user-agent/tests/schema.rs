@@ -0,0 +1,93 @@
+// tests/schema.rs
+
+mod common;
+
+use tempfile::tempdir;
+
+use agent::{
+    comms::schema::{describe_schema, render_text, to_json},
+    db::{connection::init_database, event_types::EVENT_TYPES, schema_registry::ensure_for},
+};
+use common::db_cfg;
+
+#[test]
+fn process_event_matches_the_known_schema() {
+    let mut cfg = db_cfg();
+    cfg.compress_columns.clear();
+    let schema = describe_schema(&cfg, Some("ProcessEvent")).unwrap();
+    assert_eq!(schema.events.len(), 1);
+    let ev = &schema.events[0];
+    assert_eq!(ev.table, "process_events");
+
+    let fields: Vec<_> = ev
+        .fields
+        .iter()
+        .map(|f| (f.number, f.name.as_str(), f.r#type.as_str(), f.columns.join(",")))
+        .collect();
+    assert_eq!(fields, vec![
+        (1, "pid",         "uint32", "pid".to_owned()),
+        (2, "ppid",        "uint32", "ppid".to_owned()),
+        (3, "image_path",  "string", "image_path,image_path_norm".to_owned()),
+        (4, "cmdline",     "string", "cmdline".to_owned()),
+        (5, "creator_pid", "uint32", "creator_pid".to_owned()),
+        (6, "creator_tid", "uint32", "creator_tid".to_owned()),
+        (7, "event_type",  "ProcessEvent.EventType", "event_type".to_owned()),
+        (8, "exit_code",   "int32",  "exit_code".to_owned()),
+        (9, "parent_image_path", "string", "parent_image_path".to_owned()),
+        (10, "parent_cmdline",   "string", "parent_cmdline".to_owned()),
+        (11, "truncated",        "uint32", "truncated".to_owned()),
+        (12, "user_sid",         "string", "user_sid".to_owned()),
+        (13, "session_id",       "uint32", "session_id".to_owned()),
+        (14, "elevated",         "bool",   "elevated".to_owned()),
+    ]);
+    assert!(ev.fields.iter().all(|f| f.annotations.is_empty() && !f.repeated));
+    assert_eq!(ev.derived_columns, ["ts", "sensor_guid", "event_uid", "seq"]);
+    let values: Vec<_> = ev.enums[0].values.iter().map(|v| (v.name.as_str(), v.number)).collect();
+    assert_eq!(values, [("CREATE", 0), ("EXIT", 1)]);
+}
+
+#[test]
+fn enums_and_repeated_fields_are_described() {
+    let schema = describe_schema(&db_cfg(), None).unwrap();
+    let names: Vec<_> = schema.events.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, ["FileEvent", "NetworkEvent", "ProcessEvent", "ScanResult", "EtwEvent", "ImageLoadEvent", "ObjectOpEvent"]);
+
+    let file = &schema.events[0];
+    let op = file.fields.iter().find(|f| f.name == "op").unwrap();
+    assert_eq!(op.r#type, "FileEvent.Operation");
+    let values: Vec<_> = file.enums[0].values.iter().map(|v| (v.name.as_str(), v.number)).collect();
+    assert_eq!(values, [("CREATE", 0), ("WRITE", 1), ("DELETE", 2), ("RENAME", 3)]);
+
+    let scan = &schema.events[3];
+    assert_eq!(scan.table, "scan_results");
+    assert!(scan.fields.iter().find(|f| f.name == "matches").unwrap().repeated);
+    assert!(!render_text(&schema).contains("(not stored)"));
+    assert!(describe_schema(&db_cfg(), Some("Nope")).is_err());
+}
+
+#[test]
+fn compression_config_is_annotated() {
+    let mut cfg = db_cfg();
+    cfg.compress_columns = vec!["process_events.cmdline".into()];
+    cfg.compress_threshold = 256;
+    let schema = describe_schema(&cfg, Some("ProcessEvent")).unwrap();
+
+    let cmdline = schema.events[0].fields.iter().find(|f| f.name == "cmdline").unwrap();
+    assert_eq!(cmdline.annotations, ["cmdline: compressed from 256 bytes"]);
+    let image = schema.events[0].fields.iter().find(|f| f.name == "image_path").unwrap();
+    assert!(image.annotations.is_empty());
+
+    let json = to_json(&schema);
+    assert_eq!(json[0]["fields"][3]["annotations"][0], "cmdline: compressed from 256 bytes");
+}
+
+#[test]
+fn registrations_match_the_database() {
+    let dir = tempdir().unwrap();
+    let conn = init_database(dir.path(), &db_cfg()).unwrap();
+    for t in EVENT_TYPES {
+        ensure_for(&conn, &t.schema).unwrap();
+        let stmt = conn.prepare(t.insert_sql).unwrap_or_else(|e| panic!("{}: {e}", t.table));
+        assert_eq!(stmt.parameter_count(), t.columns.len(), "{}", t.table);
+    }
+}
@@ -0,0 +1,157 @@
+// tests/schema_registry.rs
+
+mod common;
+
+use std::{sync::{Arc, Barrier}, thread};
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+use agent::db::{
+    connection::{db_path, init_database},
+    event_types::{ETW_EVENTS, FS_EVENTS, NETWORK_EVENTS},
+    schema_registry::{
+        ensure_for, history, pending_upgrades, table_exists, Ensured, SchemaRegistry, TableDef,
+        CORE_TABLES, LAZY_TABLES,
+    },
+};
+use common::db_cfg;
+
+fn actions(conn: &Connection, table: &str) -> Vec<(u32, String)> {
+    history(conn)
+        .unwrap()
+        .into_iter()
+        .filter(|m| m.table == table)
+        .map(|m| (m.version, m.action))
+        .collect()
+}
+
+fn indexes(conn: &Connection, table: &str) -> Vec<String> {
+    conn.prepare("SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = ?1 ORDER BY name")
+        .unwrap()
+        .query_map([table], |r| r.get(0))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+}
+
+const WIDGETS_V1: TableDef = TableDef {
+    name:     "widgets",
+    version:  1,
+    ddl:      "CREATE TABLE IF NOT EXISTS widgets (id INTEGER PRIMARY KEY, ts INTEGER NOT NULL);",
+    upgrades: &[],
+};
+
+const WIDGETS_V2: TableDef = TableDef {
+    name:     "widgets",
+    version:  2,
+    ddl: "CREATE TABLE IF NOT EXISTS widgets (id INTEGER PRIMARY KEY, ts INTEGER NOT NULL, colour TEXT);
+          CREATE INDEX IF NOT EXISTS idx_widgets_colour ON widgets(colour);",
+    upgrades: &[(2, "ALTER TABLE widgets ADD COLUMN colour TEXT")],
+};
+
+const GADGETS_V2: TableDef = TableDef {
+    name:     "gadgets",
+    version:  2,
+    ddl:      "CREATE TABLE IF NOT EXISTS gadgets (id INTEGER PRIMARY KEY, ts INTEGER NOT NULL, colour TEXT);",
+    upgrades: &[(2, "ALTER TABLE gadgets ADD COLUMN colour TEXT")],
+};
+
+#[test]
+fn startup_creates_core_tables_only() {
+    let dir = tempdir().unwrap();
+    let conn = init_database(dir.path(), &db_cfg()).unwrap();
+
+    for def in CORE_TABLES {
+        assert!(table_exists(&conn, def.name).unwrap(), "{}", def.name);
+        assert_eq!(actions(&conn, def.name), [(def.version, "create".to_owned())]);
+    }
+    for def in LAZY_TABLES {
+        assert!(!table_exists(&conn, def.name).unwrap(), "{}", def.name);
+    }
+
+    assert_eq!(ensure_for(&conn, &FS_EVENTS.schema).unwrap(), Ensured::Created);
+    assert_eq!(ensure_for(&conn, &FS_EVENTS.schema).unwrap(), Ensured::Present);
+    assert_eq!(indexes(&conn, "fs_events"), ["idx_fs_events_pid", "idx_fs_events_ts"]);
+    let stmt = conn.prepare(FS_EVENTS.insert_sql).unwrap();
+    assert_eq!(stmt.parameter_count(), FS_EVENTS.columns.len());
+}
+
+#[test]
+fn concurrent_ensure_creates_the_table_once() {
+    let dir = tempdir().unwrap();
+    let cfg = db_cfg();
+    drop(init_database(dir.path(), &cfg).unwrap());
+    let path = db_path(dir.path(), &cfg);
+
+    let barrier = Arc::new(Barrier::new(8));
+    let outcomes: Vec<Ensured> = (0..8)
+        .map(|_| {
+            let (barrier, registry) = (barrier.clone(), SchemaRegistry::new(&path));
+            thread::spawn(move || {
+                barrier.wait();
+                registry.ensure(&NETWORK_EVENTS.schema).unwrap()
+            })
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|h| h.join().unwrap())
+        .collect();
+
+    assert_eq!(outcomes.iter().filter(|o| **o == Ensured::Created).count(), 1);
+    assert_eq!(outcomes.iter().filter(|o| **o == Ensured::Present).count(), 7);
+    let conn = Connection::open(&path).unwrap();
+    assert_eq!(actions(&conn, "network_events"), [(4, "create".to_owned())]);
+}
+
+#[test]
+fn restart_completes_a_partially_created_table() {
+    let dir = tempdir().unwrap();
+    let cfg = db_cfg();
+    let conn = init_database(dir.path(), &cfg).unwrap();
+    ensure_for(&conn, &NETWORK_EVENTS.schema).unwrap();
+    // An interrupted creation: the version 1 table without its indexes or
+    // history.
+    let create_only = ETW_EVENTS.schema.ddl.split(';').next().unwrap().replace(", seq INTEGER", "");
+    conn.execute_batch(&create_only).unwrap();
+    assert!(indexes(&conn, "etw_events").is_empty());
+    drop(conn);
+
+    let conn = init_database(dir.path(), &cfg).unwrap();
+    assert_eq!(indexes(&conn, "etw_events").len(), 4);
+    assert_eq!(actions(&conn, "etw_events"), [(1, "adopt".to_owned()), (2, "upgrade".to_owned())]);
+    assert_eq!(actions(&conn, "network_events"), [(4, "create".to_owned())]);
+    assert!(!table_exists(&conn, "fs_events").unwrap());
+    assert_eq!(ensure_for(&conn, &ETW_EVENTS.schema).unwrap(), Ensured::Present);
+}
+
+#[test]
+fn migration_skips_lazy_tables_that_were_never_created() {
+    let dir = tempdir().unwrap();
+    let cfg = db_cfg();
+    drop(init_database(dir.path(), &cfg).unwrap());
+    let registry = SchemaRegistry::new(db_path(dir.path(), &cfg));
+    assert_eq!(registry.ensure(&WIDGETS_V1).unwrap(), Ensured::Created);
+
+    // A later release: widgets gains a column, gadgets is new at version 2.
+    let conn = Connection::open(registry.path()).unwrap();
+    assert_eq!(pending_upgrades(&conn, &[&WIDGETS_V2, &GADGETS_V2]).unwrap(), ["widgets"]);
+    assert_eq!(registry.upgrade_existing(&[&WIDGETS_V2, &GADGETS_V2]).unwrap(), [("widgets", 1)]);
+    assert!(pending_upgrades(&conn, &[&WIDGETS_V2, &GADGETS_V2]).unwrap().is_empty());
+    assert!(!table_exists(&conn, "gadgets").unwrap());
+    assert_eq!(indexes(&conn, "widgets"), ["idx_widgets_colour"]);
+    assert_eq!(actions(&conn, "widgets"), [(1, "create".to_owned()), (2, "upgrade".to_owned())]);
+
+    // Created on first use at the current version, without replaying upgrades.
+    assert_eq!(registry.ensure(&GADGETS_V2).unwrap(), Ensured::Created);
+    assert_eq!(registry.ensure(&GADGETS_V2).unwrap(), Ensured::Present);
+    assert_eq!(actions(&conn, "gadgets"), [(2, "create".to_owned())]);
+    conn.execute("INSERT INTO gadgets (ts, colour) VALUES (1, 'red')", []).unwrap();
+}
+
+#[test]
+fn in_memory_databases_are_ensured_in_place() {
+    let conn = Connection::open_in_memory().unwrap();
+    assert!(SchemaRegistry::of(&conn).is_none());
+    assert_eq!(ensure_for(&conn, &WIDGETS_V2).unwrap(), Ensured::Created);
+    assert_eq!(actions(&conn, "widgets"), [(2, "create".to_owned())]);
+}
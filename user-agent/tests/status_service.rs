@@ -0,0 +1,199 @@
+// tests/status_service.rs
+//
+// StatusService served in-process: events read from a ring, errors, the
+// driver check, scan passes and component health show up in GetStatus, and
+// Watch streams snapshots until the agent stops.
+
+mod common;
+
+use std::{
+    path::PathBuf,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+use prost::Message;
+use tempfile::NamedTempFile;
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, mpsc},
+    time::timeout,
+};
+use tonic::transport::Channel;
+use shared::{
+    constants::VersionInfo,
+    events::ProcessEvent,
+    ring,
+    status::{
+        component_health, driver_status::State, status_service_client::StatusServiceClient, GetStatusRequest,
+        StatusSnapshot, WatchRequest,
+    },
+};
+
+use agent::{
+    comms::{
+        grpc::{self, ConfigServer, StatusServer},
+        listeners::{Buses, Listener, RingListener},
+        memory_ring::MemoryRing,
+        WrappedEvent,
+    },
+    config::{load, model::DirectoryRisk},
+    db::ops_journal::Journal,
+    health::{Component, HealthRegistry},
+    scanner::{worker::PassSummary, Schedule},
+    status::{AgentStats, DriverState, LAST_ERRORS},
+    util::Shutdown,
+};
+
+/// Serves `status` next to a config service on a free local port.
+async fn connect(status: StatusServer, shutdown: &Shutdown) -> StatusServiceClient<Channel> {
+    // Only read: no SetConfig is sent.
+    let config = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/config.toml"));
+    let cfg = load(&config).unwrap();
+    let server = ConfigServer::new(config, Schedule::new(cfg.scanner.clone()), cfg.database.clone(), Journal::disabled());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(grpc::serve(listener, server, status, shutdown.clone()));
+    StatusServiceClient::connect(format!("http://{addr}")).await.unwrap()
+}
+
+/// A ring file holding `events` as frames 1.., with the drop counters a
+/// driver would have left.
+fn ring_with(events: &[ProcessEvent]) -> NamedTempFile {
+    let payloads: Vec<_> = events.iter().map(Message::encode_to_vec).collect();
+    let used: usize = payloads.iter().map(|p| ring::frame_len(p.len())).sum();
+    let tmp = NamedTempFile::new().unwrap();
+    let mut mmap = common::empty_ring(tmp.path(), used * 2);
+    let mut tail = 0;
+    for (seq, payload) in payloads.iter().enumerate() {
+        tail += ring::write_frame(&mut mmap[ring::HEADER_SIZE + tail..], seq as u64 + 1, 0, payload).unwrap();
+    }
+    let header = common::header(&mmap);
+    header.tail.store(tail as u64, Ordering::Release);
+    header.dropped.store(5, Ordering::Relaxed);
+    header.dropped_full.store(3, Ordering::Relaxed);
+    header.dropped_oversize.store(2, Ordering::Relaxed);
+    header.max_observed_len.store(70_000, Ordering::Relaxed);
+    mmap.flush().unwrap();
+    tmp
+}
+
+/// Asks until `ready` holds, for at most five seconds.
+async fn status_when(
+    client: &mut StatusServiceClient<Channel>,
+    ready: impl Fn(&StatusSnapshot) -> bool,
+) -> StatusSnapshot {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let status = client.get_status(GetStatusRequest {}).await.unwrap().into_inner();
+        if ready(&status) || Instant::now() > deadline {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[tokio::test]
+async fn get_status_reflects_what_the_agent_did() {
+    // The listener reports into the global stats, which no other test here
+    // uses.
+    let stats = AgentStats::global();
+    let events: Vec<_> = (1..=7).map(|pid| ProcessEvent { pid, ..ProcessEvent::default() }).collect();
+    let ring_file = ring_with(&events);
+    let listener = Arc::new(RingListener::new("process", MemoryRing::open(ring_file.path()).unwrap(), "SENSOR"));
+    let (db_tx, mut db_rx) = mpsc::channel::<WrappedEvent<ProcessEvent>>(16);
+    let (intel_tx, _) = broadcast::channel(16);
+    let shutdown = Shutdown::new();
+    listener.spawn(Buses { db_tx: db_tx.into(), intel_tx }, &shutdown);
+    for _ in &events {
+        timeout(Duration::from_secs(1), db_rx.recv()).await.expect("timeout waiting for db").unwrap();
+    }
+
+    let version = VersionInfo { protocol: 2 << 16 | 1, major: 0, minor: 3, patch: 1, ..VersionInfo::default() };
+    stats.set_driver(DriverState::Connected, Some(version));
+    for i in 0..12 {
+        stats.record_error(format!("cannot flush rows: error {i}"));
+    }
+    let pass = PassSummary { files: 120, bytes: 4_096, elapsed: Duration::from_millis(1_500) };
+    stats.set_pass(DirectoryRisk::High, &pass);
+
+    let db = NamedTempFile::new().unwrap();
+    std::fs::write(db.path(), vec![0u8; 8_192]).unwrap();
+    let mut client = connect(StatusServer::new(stats.clone()).with_db(db.path().to_path_buf()), &shutdown).await;
+    let status = status_when(&mut client, |s| !s.rings.is_empty()).await;
+
+    let rates: Vec<_> = status.events.iter().map(|e| (e.event_type.as_str(), e.last_minute, e.last_hour, e.total)).collect();
+    assert_eq!(rates, [("process", 7, 7, 7)]);
+
+    let ring = &status.rings[0];
+    assert_eq!(ring.name, "process");
+    assert_eq!((ring.dropped, ring.dropped_full, ring.dropped_oversize), (5, 3, 2));
+    assert_eq!(ring.max_frame_bytes, 70_000);
+    assert!(ring.size_bytes > 0 && ring.backlog_bytes <= ring.size_bytes);
+
+    let driver = status.driver.unwrap();
+    assert_eq!(driver.state(), State::Connected);
+    assert_eq!((driver.protocol, driver.version.as_str()), (version.protocol, "0.3.1"));
+
+    // The oldest two errors made room for the newest.
+    assert_eq!(status.last_errors.len(), LAST_ERRORS);
+    assert_eq!(status.last_errors[0].message, "cannot flush rows: error 2");
+    assert_eq!(status.last_errors[9].message, "cannot flush rows: error 11");
+    assert!(status.last_errors[0].timestamp_us > 0);
+
+    assert_eq!(status.scans.len(), 1);
+    let scan = &status.scans[0];
+    assert_eq!((scan.risk_group.as_str(), scan.files, scan.bytes_hashed, scan.seconds), ("high", 120, 4_096, 1.5));
+
+    assert_eq!(status.db_size_bytes, 8_192);
+    assert!(status.uptime_seconds > 0.0);
+    assert_eq!(status.version, env!("CARGO_PKG_VERSION"));
+    shutdown.trigger();
+}
+
+#[tokio::test]
+async fn watch_streams_snapshots_until_shutdown() {
+    let stats = AgentStats::new();
+    let shutdown = Shutdown::new();
+    let mut client = connect(StatusServer::new(stats.clone()), &shutdown).await;
+    let mut snapshots = client.watch(WatchRequest { interval_seconds: 1 }).await.unwrap().into_inner();
+
+    // The first one comes right away.
+    let first = timeout(Duration::from_millis(500), snapshots.message()).await.unwrap().unwrap().unwrap();
+    assert!(first.events.is_empty());
+    assert_eq!(first.driver.unwrap().state(), State::Unknown);
+    assert_eq!(first.db_size_bytes, 0);
+
+    stats.record_events("file", 3, Instant::now());
+    stats.set_driver(DriverState::Unavailable, None);
+    let second = timeout(Duration::from_secs(2), snapshots.message()).await.unwrap().unwrap().unwrap();
+    assert_eq!(second.events[0].event_type, "file");
+    assert_eq!(second.events[0].last_minute, 3);
+    assert_eq!(second.driver.unwrap().state(), State::Unavailable);
+    assert!(second.timestamp_us >= first.timestamp_us);
+
+    // Stopping the agent ends the stream instead of waiting on the client.
+    shutdown.trigger();
+    let end = timeout(Duration::from_secs(2), snapshots.message()).await.expect("stream still open");
+    assert!(matches!(end, Ok(None)));
+}
+
+#[test]
+fn components_show_their_health() {
+    let stats = AgentStats::new();
+    assert!(stats.snapshot(None).components.is_empty());
+
+    let health = HealthRegistry::new();
+    stats.set_health(health.clone());
+    health.register(Component::Grpc);
+    health.mark_healthy(Component::Metrics);
+    health.mark_degraded(Component::Scanner, "scan cache unreadable");
+    health.mark_degraded(Component::Scanner, "scan cache unreadable");
+
+    let components = stats.snapshot(None).components;
+    let states: Vec<_> = components.iter().map(|c| (c.component.as_str(), c.state(), c.attempts)).collect();
+    use component_health::State::*;
+    assert_eq!(states, [("scanner", Degraded, 2), ("metrics", Healthy, 0), ("grpc", Pending, 0)]);
+    assert_eq!(components[0].error, "scan cache unreadable");
+    assert!(components[0].since_us > 0);
+    assert_eq!((components[1].error.as_str(), components[1].since_us), ("", 0));
+}
@@ -0,0 +1,133 @@
+// tests/stored_values.rs
+//
+// What each event writer leaves in its columns: enums as their proto names,
+// booleans as INTEGER 0/1, and `fs_events` written by older agents (`op` as
+// its number, `result` as 'true'/'false') rewritten by the table upgrade.
+
+mod common;
+
+use rusqlite::{types::Value, Connection};
+
+use agent::{
+    comms::WrappedEvent,
+    db::{
+        batch_inserts::BatchInsert,
+        codec::Codec,
+        event_types::FS_EVENTS,
+        schema_registry::{ensure_for, ensure_on, Ensured},
+    },
+};
+use shared::events::{
+    file_event::Operation, network_event::Direction, object_op_event::Operation as ObjectOperation,
+    process_event::EventType, scan_result::Severity, FileEvent, ImageLoadEvent, NetworkEvent, ObjectOpEvent,
+    ProcessEvent, ScanResult,
+};
+use common::{seconds, wrap};
+
+fn insert<E: Clone>(conn: &Connection, events: &[E])
+where
+    WrappedEvent<E>: BatchInsert<WrappedEvent<E>>,
+{
+    ensure_for(conn, <WrappedEvent<E>>::schema()).unwrap();
+    let mut stmt = conn.prepare(<WrappedEvent<E>>::insert_sql()).unwrap();
+    for ev in events {
+        <WrappedEvent<E>>::bind_and_execute(&mut stmt, &wrap(seconds(1), ev.clone()), &mut Codec::disabled()).unwrap();
+    }
+}
+
+/// `columns` of every row of `table`, as stored.
+fn stored(conn: &Connection, table: &str, columns: &str) -> Vec<Vec<Value>> {
+    let mut stmt = conn.prepare(&format!("SELECT {columns} FROM {table} ORDER BY id")).unwrap();
+    let n = stmt.column_count();
+    stmt.query_map([], |r| (0..n).map(|i| r.get(i)).collect())
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.into())
+}
+
+#[test]
+fn file_operations_are_stored_by_name_and_results_as_integers() {
+    let conn = Connection::open_in_memory().unwrap();
+    let file = |op: i32, success: bool| FileEvent { op, path: "C:\\f".into(), success, ..Default::default() };
+    insert(&conn, &[
+        file(Operation::Create as i32, true),
+        file(Operation::Write as i32, false),
+        file(Operation::Delete as i32, true),
+        file(Operation::Rename as i32, true),
+        file(42, false),
+    ]);
+    assert_eq!(stored(&conn, "fs_events", "op, result"), [
+        [text("CREATE"), Value::Integer(1)],
+        [text("WRITE"), Value::Integer(0)],
+        [text("DELETE"), Value::Integer(1)],
+        [text("RENAME"), Value::Integer(1)],
+        [text("UNKNOWN(42)"), Value::Integer(0)],
+    ]);
+}
+
+#[test]
+fn other_event_types_keep_their_representations() {
+    let conn = Connection::open_in_memory().unwrap();
+    let net = |direction: Direction, blocked: bool| NetworkEvent { direction: direction as i32, blocked, ..Default::default() };
+    insert(&conn, &[net(Direction::Outbound, true), net(Direction::Inbound, false)]);
+    assert_eq!(stored(&conn, "network_events", "direction, verdict"), [
+        [text("OUTBOUND"), text("block")],
+        [text("INBOUND"), text("allow")],
+    ]);
+
+    let exit = ProcessEvent { pid: 8, event_type: EventType::Exit as i32, exit_code: 3, ..Default::default() };
+    insert(&conn, &[ProcessEvent { pid: 8, truncated: 2, ..Default::default() }, exit]);
+    assert_eq!(stored(&conn, "process_events", "event_type, exit_code, truncated"), [
+        [text("CREATE"), Value::Null, Value::Integer(2)],
+        [text("EXIT"), Value::Integer(3), Value::Integer(0)],
+    ]);
+
+    let scan = |severity: i32| ScanResult { severity, ..Default::default() };
+    insert(&conn, &[scan(Severity::Critical as i32), scan(9)]);
+    assert_eq!(stored(&conn, "scan_results", "severity"), [[text("CRITICAL")], [text("9")]]);
+
+    let image = |is_kernel_module: bool| ImageLoadEvent { is_kernel_module, ..Default::default() };
+    insert(&conn, &[image(true), image(false)]);
+    assert_eq!(stored(&conn, "image_load_events", "is_kernel_module"), [[Value::Integer(1)], [Value::Integer(0)]]);
+
+    let object = |operation: i32| ObjectOpEvent { source_pid: 7, target_pid: 8, desired_access: 0x10, operation };
+    insert(&conn, &[object(ObjectOperation::HandleDuplicate as i32), object(5)]);
+    assert_eq!(stored(&conn, "object_op_events", "source_pid, target_pid, desired_access, operation"), [
+        [Value::Integer(7), Value::Integer(8), Value::Integer(0x10), text("HANDLE_DUPLICATE")],
+        [Value::Integer(7), Value::Integer(8), Value::Integer(0x10), text("UNKNOWN(5)")],
+    ]);
+}
+
+#[test]
+fn upgrade_rewrites_file_events_of_older_agents() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    // `fs_events` as first created, before `seq` and the stored names.
+    conn.execute_batch(
+        "CREATE TABLE fs_events (id INTEGER PRIMARY KEY, ts INTEGER NOT NULL, sensor_guid TEXT,
+             op TEXT NOT NULL, path TEXT NOT NULL, new_path TEXT, pid INTEGER, exe_path TEXT,
+             size INTEGER, sha256 TEXT, result TEXT, event_uid INTEGER);
+         INSERT INTO fs_events (ts, op, path, result) VALUES
+             (1, '0', 'a', 'true'), (2, '3', 'b', 'false'), (3, '7', 'c', 'true'), (4, 'WRITE', 'd', NULL);",
+    )
+    .unwrap();
+
+    assert_eq!(ensure_on(&mut conn, &FS_EVENTS.schema).unwrap(), Ensured::Upgraded { from: 1, to: FS_EVENTS.schema.version });
+    assert_eq!(stored(&conn, "fs_events", "ts, op, path, result"), [
+        [Value::Integer(1), text("CREATE"), text("a"), Value::Integer(1)],
+        [Value::Integer(2), text("RENAME"), text("b"), Value::Integer(0)],
+        [Value::Integer(3), text("UNKNOWN(7)"), text("c"), Value::Integer(1)],
+        [Value::Integer(4), text("WRITE"), text("d"), Value::Null],
+    ]);
+    let indexes: i64 = conn
+        .query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND tbl_name = 'fs_events'", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(indexes, 2);
+
+    // New rows go into the rebuilt table as before.
+    insert(&conn, &[FileEvent { op: Operation::Delete as i32, path: "e".into(), success: true, ..Default::default() }]);
+    assert_eq!(stored(&conn, "fs_events", "op, result").last().unwrap(), &[text("DELETE"), Value::Integer(1)]);
+}
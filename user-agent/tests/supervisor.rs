@@ -0,0 +1,170 @@
+// tests/supervisor.rs
+//
+// Tasks and threads that panic are started again after growing delays,
+// counted in `task_restarts_total`, and given up on, flagged in the status
+// and the heartbeat, once they panic too often within the window. Time is a
+// clock that only moves when slept on.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use futures::future::BoxFuture;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tempfile::tempdir;
+use tokio::runtime;
+
+use agent::heartbeat::Stats;
+use agent::status::AgentStats;
+use agent::util::{retry::Clock, Jitter, RetryPolicy, Shutdown, Supervisor};
+
+/// Clock that advances only when slept on.
+struct MockClock {
+    now:   Mutex<Instant>,
+    slept: Mutex<Vec<Duration>>,
+}
+
+impl MockClock {
+    fn new() -> Arc<Self> {
+        Arc::new(Self { now: Mutex::new(Instant::now()), slept: Mutex::default() })
+    }
+
+    fn advance(&self, d: Duration) {
+        *self.now.lock().unwrap() += d;
+        self.slept.lock().unwrap().push(d);
+    }
+
+    fn slept(&self) -> Vec<Duration> {
+        self.slept.lock().unwrap().clone()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn random(&self) -> f64 {
+        0.5
+    }
+
+    fn sleep(&self, d: Duration) -> BoxFuture<'static, ()> {
+        self.advance(d);
+        Box::pin(async {})
+    }
+
+    fn sleep_blocking(&self, d: Duration, shutdown: &Shutdown) -> bool {
+        self.advance(d);
+        !shutdown.is_triggered()
+    }
+}
+
+fn secs(s: u64) -> Duration {
+    Duration::from_secs(s)
+}
+
+fn supervisor(name: &str, clock: Arc<MockClock>) -> Supervisor {
+    Supervisor::new(name).policy(RetryPolicy::new("supervisor", secs(1)).jitter(Jitter::None).clock(clock))
+}
+
+/// A body that panics on its first `panics` runs and then returns; counts
+/// its runs.
+fn flaky(panics: usize) -> (Arc<AtomicUsize>, impl FnMut() -> usize) {
+    let runs = Arc::new(AtomicUsize::new(0));
+    let body = {
+        let runs = runs.clone();
+        move || {
+            let run = runs.fetch_add(1, Ordering::SeqCst) + 1;
+            if run <= panics {
+                panic!("run {run} failed");
+            }
+            run
+        }
+    };
+    (runs, body)
+}
+
+#[test]
+fn a_task_that_panics_twice_is_restarted_twice_with_growing_delays() {
+    let clock = MockClock::new();
+    let (runs, body) = flaky(2);
+    let body = Arc::new(Mutex::new(body));
+    let rt = runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let recorder = PrometheusBuilder::new().build_recorder();
+    metrics::with_local_recorder(&recorder, || {
+        rt.block_on(supervisor("flaky_task", clock.clone()).run(move || {
+            let body = body.clone();
+            async move {
+                // Poisoned by the panics before.
+                body.lock().unwrap_or_else(|e| e.into_inner())();
+            }
+        }));
+    });
+
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+    assert_eq!(clock.slept(), [secs(1), secs(2)]);
+    let text = recorder.handle().render();
+    assert!(text.contains("task_restarts_total{task=\"flaky_task\"} 2"), "{text}");
+    assert!(!AgentStats::global().snapshot(None).failed_tasks.contains(&"flaky_task".to_owned()));
+}
+
+#[test]
+fn too_many_panics_within_the_window_give_the_task_up() {
+    let clock = MockClock::new();
+    let (runs, body) = flaky(usize::MAX);
+    let body = Mutex::new(body);
+    supervisor("always_panics", clock.clone()).give_up_after(3, secs(600)).run_blocking(|| {
+        body.lock().unwrap_or_else(|e| e.into_inner())();
+    });
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+    assert_eq!(clock.slept(), [secs(1), secs(2)]);
+
+    let status = AgentStats::global().snapshot(None);
+    assert!(status.failed_tasks.contains(&"always_panics".to_owned()), "{:?}", status.failed_tasks);
+    assert!(status.last_errors.iter().any(|e| e.message.contains("'always_panics' given up after 3 panics: run 3 failed")));
+    let dir = tempdir().unwrap();
+    let beat = Stats::global().take(&dir.path().join("telemetry.db"));
+    assert!(beat.failed_tasks.contains(&"always_panics".to_owned()));
+    // Kept for the next heartbeat too.
+    assert!(Stats::global().take(&dir.path().join("telemetry.db")).failed_tasks.contains(&"always_panics".to_owned()));
+}
+
+#[test]
+fn panics_spread_wider_than_the_window_keep_being_restarted() {
+    // One second, then two: the first panic of each pair is out of the
+    // window by the time the third comes.
+    let clock = MockClock::new();
+    let (runs, mut body) = flaky(6);
+    supervisor("spread_out", clock.clone()).give_up_after(3, Duration::from_millis(1_500)).run_blocking(|| {
+        body();
+    });
+    assert_eq!(runs.load(Ordering::SeqCst), 7);
+    assert_eq!(clock.slept(), [secs(1), secs(2), secs(1), secs(2), secs(1), secs(2)]);
+    assert!(!AgentStats::global().snapshot(None).failed_tasks.contains(&"spread_out".to_owned()));
+}
+
+#[test]
+fn nothing_is_restarted_once_shutdown_is_requested() {
+    let clock = MockClock::new();
+    let shutdown = Shutdown::new();
+    shutdown.trigger();
+    let (runs, mut body) = flaky(usize::MAX);
+    supervisor("stopping", clock.clone()).cancel_on(shutdown.clone()).run_blocking(|| {
+        body();
+    });
+    assert_eq!((runs.load(Ordering::SeqCst), clock.slept()), (1, vec![]));
+
+    let rt = runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let (runs, body) = flaky(usize::MAX);
+    let body = Arc::new(Mutex::new(body));
+    rt.block_on(supervisor("stopping_task", clock.clone()).cancel_on(shutdown).run(move || {
+        let body = body.clone();
+        async move {
+            body.lock().unwrap_or_else(|e| e.into_inner())();
+        }
+    }));
+    assert_eq!((runs.load(Ordering::SeqCst), clock.slept()), (1, vec![]));
+}
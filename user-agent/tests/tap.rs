@@ -0,0 +1,103 @@
+// tests/tap.rs
+//
+// Events published on the intel buses reach a tap client as BaseEvents.
+// Off Windows the tap serves a Unix socket, here inside a temp dir.
+
+mod common;
+
+use std::{
+    io::Cursor,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::broadcast};
+
+use agent::{
+    comms::{
+        tap::{frame, read_event, spawn_event_tap, TapClient, TapSources},
+        WrappedEvent,
+    },
+    util::Shutdown,
+};
+use shared::events::{base_event::Payload, BaseEvent, FileEvent, ProcessEvent, ScanResult};
+use common::wrap;
+
+#[test]
+fn client_decodes_events_from_every_bus() {
+    let dir = tempdir().unwrap();
+    let name = dir.path().join("tap.sock");
+    let name = name.to_str().unwrap();
+
+    let rt = Runtime::new().unwrap();
+    let (process_tx, _) = broadcast::channel::<WrappedEvent<ProcessEvent>>(64);
+    let (file_tx, _) = broadcast::channel::<WrappedEvent<FileEvent>>(64);
+    let (scan_tx, _) = broadcast::channel::<WrappedEvent<ScanResult>>(64);
+    let sources = TapSources {
+        process: Some(process_tx.clone()),
+        file:    Some(file_tx.clone()),
+        scan:    Some(scan_tx.clone()),
+        ..TapSources::default()
+    };
+    let shutdown = Shutdown::new();
+    let server = spawn_event_tap(&rt, name, sources, shutdown.clone()).unwrap();
+
+    let mut client = TapClient::connect(name).unwrap();
+    let (got_tx, got_rx) = mpsc::channel();
+    let reader = thread::spawn(move || {
+        while let Some(ev) = client.recv().unwrap() {
+            got_tx.send(ev).unwrap();
+        }
+    });
+
+    // Frames are only fanned out once the server has picked the client up.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let _ = process_tx.send(wrap(SystemTime::now(), ProcessEvent { pid: 1, ..Default::default() }));
+        if got_rx.recv_timeout(Duration::from_millis(20)).is_ok() {
+            break;
+        }
+        assert!(Instant::now() < deadline, "client never received the warm-up event");
+    }
+
+    file_tx.send(wrap(SystemTime::now(), FileEvent { path: r"C:\t.txt".into(), pid: 7, ..Default::default() })).unwrap();
+    let received: Vec<BaseEvent> = got_rx
+        .iter()
+        .filter(|ev| !matches!(&ev.payload, Some(Payload::ProcessEvent(p)) if p.pid == 1))
+        .take(1)
+        .collect();
+    let ev = &received[0];
+    assert_eq!(ev.sensor_guid, "test");
+    assert!(ev.ts.is_some());
+    match &ev.payload {
+        Some(Payload::FileEvent(f)) => assert_eq!((f.path.as_str(), f.pid), (r"C:\t.txt", 7)),
+        other => panic!("unexpected payload {other:?}"),
+    }
+
+    scan_tx.send(wrap(SystemTime::now(), ScanResult { file_path: "a.exe".into(), ..Default::default() })).unwrap();
+    let scan = got_rx.iter().find(|ev| matches!(ev.payload, Some(Payload::ScanResult(_)))).unwrap();
+    assert!(matches!(scan.payload, Some(Payload::ScanResult(ref s)) if s.file_path == "a.exe"));
+
+    // Shutting down closes the stream; the client sees a clean end.
+    shutdown.trigger();
+    rt.block_on(server).unwrap();
+    reader.join().unwrap();
+}
+
+#[test]
+fn frames_round_trip_and_bad_lengths_are_refused() {
+    let ev: BaseEvent = wrap(SystemTime::now(), ProcessEvent { pid: 42, image_path: r"C:\x.exe".into(), ..Default::default() }).into();
+    let mut stream = frame(&ev);
+    stream.extend_from_slice(&frame(&BaseEvent::default()));
+
+    let mut r = Cursor::new(stream);
+    assert_eq!(read_event(&mut r).unwrap(), Some(ev));
+    assert_eq!(read_event(&mut r).unwrap(), Some(BaseEvent::default()));
+    assert_eq!(read_event(&mut r).unwrap(), None);
+
+    let huge = (u32::MAX).to_le_bytes();
+    assert_eq!(read_event(&mut Cursor::new(huge)).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    let cut = [8, 0, 0, 0, 0x08];
+    assert_eq!(read_event(&mut Cursor::new(cut)).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+}
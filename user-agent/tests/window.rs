@@ -0,0 +1,75 @@
+// tests/window.rs
+//
+// Sliding-window counter: counts leave the window bucket by bucket, late
+// counts for a reused bucket are dropped and instants before the origin
+// count as the origin.
+
+use std::time::{Duration, Instant};
+
+use agent::util::SlidingCounter;
+
+#[test]
+fn counts_leave_the_window_bucket_by_bucket() {
+    let start = Instant::now();
+    let at = |secs: u64| start + Duration::from_secs(secs);
+    let mut minute = SlidingCounter::new(Duration::from_secs(60), 60, start);
+    assert_eq!(minute.span(), Duration::from_secs(60));
+
+    minute.add(3, at(0));
+    minute.add(2, at(30));
+    minute.add(1, at(59));
+    assert_eq!(minute.total(at(59)), 6);
+    // The bucket of second 0 is gone at 60; the one of second 30 at 90.
+    assert_eq!(minute.total(at(60)), 3);
+    assert_eq!(minute.total(at(89)), 3);
+    assert_eq!(minute.total(at(90)), 1);
+    assert_eq!(minute.total(at(119)), 0);
+
+    // A bucket reused after a full turn starts over.
+    minute.add(5, at(120));
+    assert_eq!(minute.total(at(120)), 5);
+    assert_eq!(minute.total(at(10_000)), 0);
+}
+
+#[test]
+fn coarse_buckets_keep_a_whole_bucket() {
+    let start = Instant::now();
+    let at = |secs: u64| start + Duration::from_secs(secs);
+    let mut hour = SlidingCounter::new(Duration::from_secs(3_600), 60, start);
+    hour.add(1, at(0));
+    hour.add(1, at(59));
+    hour.add(1, at(60));
+    assert_eq!(hour.total(at(3_599)), 3);
+    // Both counts of the first minute leave together.
+    assert_eq!(hour.total(at(3_600)), 1);
+    assert_eq!(hour.total(at(3_660)), 0);
+}
+
+#[test]
+fn late_and_early_counts() {
+    let start = Instant::now() + Duration::from_secs(10);
+    let at = |secs: u64| start + Duration::from_secs(secs);
+    let mut window = SlidingCounter::new(Duration::from_secs(10), 10, start);
+
+    // Before the origin: counted at the origin.
+    window.add(4, start - Duration::from_secs(5));
+    assert_eq!(window.total(at(0)), 4);
+
+    // A count for second 3 after second 13 took its slot is dropped.
+    window.add(1, at(13));
+    window.add(7, at(3));
+    assert_eq!(window.total(at(13)), 1);
+    // Within the window, late counts land in their bucket.
+    window.add(2, at(12));
+    assert_eq!(window.total(at(13)), 3);
+    assert_eq!(window.total(at(22)), 1);
+}
+
+#[test]
+fn a_single_bucket_window() {
+    let start = Instant::now();
+    let mut window = SlidingCounter::new(Duration::from_secs(1), 0, start);
+    window.add(2, start + Duration::from_millis(999));
+    assert_eq!(window.total(start + Duration::from_millis(999)), 2);
+    assert_eq!(window.total(start + Duration::from_secs(1)), 0);
+}
@@ -0,0 +1,184 @@
+// tests/write_execute.rs
+
+mod common;
+
+use std::{path::PathBuf, time::Duration};
+use rusqlite::Connection;
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::broadcast};
+use shared::events::{file_event::Operation, FileEvent, ProcessEvent};
+
+use agent::{
+    actions::Actions,
+    comms::WrappedEvent,
+    config::{load, model::{PathClass, WriteExecuteConfig}},
+    db::connection::init_database,
+    intel::{
+        analytics::{
+            spawn_write_execute,
+            write_execute::{classify, RULE_ID},
+            WriteExecute,
+        },
+        AlertSink, Severity,
+    },
+};
+use common::{seconds, wrap};
+
+const DROP: &str = r"C:\Users\bob\AppData\Local\Temp\payload.exe";
+
+fn file(secs: i64, op: Operation, pid: u32, path: &str, new_path: &str) -> WrappedEvent<FileEvent> {
+    wrap(seconds(secs), FileEvent {
+        op: op as i32,
+        path: path.into(),
+        new_path: new_path.into(),
+        pid,
+        exe_path: r"C:\Users\bob\Downloads\loader.exe".into(),
+        size: 4096,
+        sha256: Vec::new(),
+        success: true,
+        ..FileEvent::default()
+    })
+}
+
+fn write(secs: i64, pid: u32, path: &str) -> WrappedEvent<FileEvent> {
+    file(secs, Operation::Write, pid, path, "")
+}
+
+fn exec(secs: i64, pid: u32, image: &str) -> WrappedEvent<ProcessEvent> {
+    wrap(seconds(secs), ProcessEvent {
+        pid,
+        ppid: 1200,
+        image_path: image.into(),
+        cmdline: String::new(),
+        creator_pid: 1200,
+        creator_tid: 12000,
+        ..ProcessEvent::default()
+    })
+}
+
+#[test]
+fn execution_inside_the_window_alerts_once() {
+    let mut a = WriteExecute::new(&WriteExecuteConfig::default());
+    a.on_file(&write(100, 1200, DROP));
+
+    let alert = a.check(&exec(130, 3000, &DROP.to_uppercase())).expect("dropped file executed");
+    assert_eq!(alert.rule_id, RULE_ID);
+    assert_eq!((alert.pid, alert.ppid), (3000, Some(1200)));
+    assert_eq!(alert.severity, Severity::Critical, "user-writable and no hash");
+    assert!(alert.message.contains("loader.exe (pid 1200)") && alert.message.contains("30.0s"), "{}", alert.message);
+
+    // Consumed: a second run of the same file does not alert again.
+    assert!(a.check(&exec(131, 3001, DROP)).is_none());
+}
+
+#[test]
+fn execution_outside_the_window_or_by_the_writer_is_ignored() {
+    let mut a = WriteExecute::new(&WriteExecuteConfig { window_secs: 10, ..WriteExecuteConfig::default() });
+    a.on_file(&write(100, 1200, DROP));
+    assert!(a.check(&exec(111, 3000, DROP)).is_none(), "11s after the write");
+    assert!(a.check(&exec(99, 3000, DROP)).is_none(), "before the write");
+
+    // The writer itself (pid reused for the image it wrote).
+    assert!(a.check(&exec(105, 1200, DROP)).is_none());
+    assert!(a.check(&exec(105, 3000, DROP)).is_some());
+
+    // Deleted before it ran.
+    a.on_file(&write(200, 1200, DROP));
+    a.on_file(&file(201, Operation::Delete, 1200, DROP, ""));
+    assert!(a.check(&exec(202, 3000, DROP)).is_none());
+}
+
+#[test]
+fn rename_moves_the_write_to_the_new_path() {
+    let mut a = WriteExecute::new(&WriteExecuteConfig::default());
+    let staged = r"C:\Users\bob\AppData\Local\Temp\payload.tmp";
+    a.on_file(&write(100, 1200, staged));
+    a.on_file(&file(101, Operation::Rename, 1200, staged, DROP));
+
+    assert!(a.recent_write(staged).is_none());
+    assert_eq!(a.recent_write(DROP).unwrap().ts, 100_000_000, "original write time kept");
+    assert!(a.check(&exec(102, 3000, DROP)).is_some());
+}
+
+#[test]
+fn installers_path_classes_and_known_hashes() {
+    let cfg = WriteExecuteConfig {
+        known_hashes: vec!["AB".repeat(32)],
+        path_classes: vec![PathClass::UserWritable, PathClass::ProgramFiles],
+        ..WriteExecuteConfig::default()
+    };
+    let mut a = WriteExecute::new(&cfg);
+
+    let mut msi = write(100, 700, DROP);
+    msi.payload.exe_path = r"C:\Windows\System32\msiexec.exe".into();
+    a.on_file(&msi);
+    assert!(a.check(&exec(101, 3000, DROP)).is_none(), "installer writes are allow-listed");
+
+    let app = r"C:\Program Files\App\app.exe";
+    let mut known = write(100, 1200, app);
+    known.payload.sha256 = vec![0xab; 32];
+    a.on_file(&known);
+    assert_eq!(a.check(&exec(101, 3000, app)).unwrap().severity, Severity::Medium);
+
+    a.on_file(&write(100, 1200, r"C:\Tools\x.exe"));
+    assert!(a.check(&exec(101, 3000, r"C:\Tools\x.exe")).is_none(), "class not watched");
+
+    assert_eq!(classify(r"\\server\share\x.exe"), PathClass::UserWritable);
+    assert_eq!(classify(r"\device\harddiskvolume3\programdata\x.exe"), PathClass::UserWritable);
+    assert_eq!(classify(r"c:\windows\temp\x.exe"), PathClass::UserWritable);
+    assert_eq!(classify(r"c:\windows\system32\x.exe"), PathClass::System);
+    assert_eq!(classify(r"c:\program files (x86)\x.exe"), PathClass::ProgramFiles);
+    assert_eq!(classify(r"c:\usersx\x.exe"), PathClass::Other);
+}
+
+#[test]
+fn tracked_writes_are_bounded_least_recent_first() {
+    let mut a = WriteExecute::new(&WriteExecuteConfig { max_tracked: 2, ..WriteExecuteConfig::default() });
+    a.on_file(&write(100, 1200, r"C:\Users\bob\a.exe"));
+    a.on_file(&write(101, 1200, r"C:\Users\bob\b.exe"));
+    a.on_file(&write(102, 1200, r"C:\Users\bob\a.exe"));
+    a.on_file(&write(103, 1200, r"C:\Users\bob\c.exe"));
+
+    assert_eq!(a.tracked(), 2);
+    assert!(a.recent_write(r"C:\Users\bob\b.exe").is_none(), "b was least recently written");
+    assert!(a.recent_write(r"C:\Users\bob\a.exe").is_some());
+}
+
+#[test]
+fn analytic_task_stores_the_alert() {
+    let rt   = Runtime::new().unwrap();
+    let dir  = tempdir().unwrap();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut db = load(&root.join("config.toml")).unwrap().database;
+    db.path = "telemetry.db".into();
+    drop(init_database(dir.path(), &db).unwrap());
+
+    let (file_tx, _) = broadcast::channel(64);
+    let (proc_tx, _) = broadcast::channel(64);
+    let task = spawn_write_execute(
+        &rt,
+        file_tx.subscribe().into(),
+        proc_tx.subscribe().into(),
+        WriteExecute::new(&WriteExecuteConfig::default()),
+        AlertSink::new(dir.path().join("telemetry.db"), Actions::disabled()),
+    );
+
+    assert!(file_tx.send(write(100, 1200, DROP)).is_ok());
+    drop(file_tx);
+    assert!(proc_tx.send(exec(101, 3000, DROP)).is_ok());
+    assert!(proc_tx.send(exec(102, 3001, r"C:\Users\bob\other.exe")).is_ok());
+    drop(proc_tx);
+    rt.block_on(async { tokio::time::timeout(Duration::from_secs(5), task).await })
+        .expect("analytic ends when the process bus closes")
+        .unwrap();
+
+    let conn = Connection::open(dir.path().join("telemetry.db")).unwrap();
+    let rows: Vec<(String, i64, String)> = conn
+        .prepare("SELECT rule_id, pid, severity FROM alerts")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap();
+    assert_eq!(rows, vec![(RULE_ID.to_string(), 3000, "critical".to_string())]);
+}
@@ -0,0 +1,4 @@
+// src/lib.rs
+//! Repository automation. Only the feature matrix lives here for now.
+
+pub mod matrix;
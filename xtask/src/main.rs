@@ -0,0 +1,105 @@
+// src/main.rs
+//! `cargo xtask <command>`
+//!
+//! ```text
+//! cargo xtask check-matrix [--member <name>] [--dry-run]
+//! ```
+//!
+//! `check-matrix` runs `cargo check --all-targets` for every feature
+//! combination of every member listed in `xtask/matrix.toml` and fails if
+//! any combination does not build.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+use anyhow::{bail, Context, Result};
+
+use xtask::matrix::{combos, unknown_features, Features, MatrixFile};
+
+const USAGE: &str = "usage: cargo xtask check-matrix [--member <name>] [--dry-run]";
+
+fn repo_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().expect("xtask lives in the repo").to_path_buf()
+}
+
+fn check_matrix(member_filter: Option<&str>, dry_run: bool) -> Result<bool> {
+    let root = repo_root();
+    let matrix = MatrixFile::parse(&fs::read_to_string(root.join("xtask/matrix.toml"))?)?;
+    if let Some(m) = member_filter.filter(|m| !matrix.members.contains_key(*m)) {
+        bail!("unknown member '{m}'");
+    }
+
+    let mut failed = Vec::new();
+    for (name, spec) in &matrix.members {
+        if member_filter.is_some_and(|m| m != name) {
+            continue;
+        }
+        if let Some(reason) = &spec.skip {
+            println!("{name}: skipped ({reason})");
+            continue;
+        }
+        let manifest = root.join(name).join("Cargo.toml");
+        let features = Features::from_manifest(&fs::read_to_string(&manifest)
+            .with_context(|| format!("reading {}", manifest.display()))?)?;
+        let unknown = unknown_features(&features, &spec.bundles);
+        if !unknown.is_empty() {
+            bail!("{name}: bundles name undeclared features {unknown:?}");
+        }
+
+        for combo in combos(&features, &spec.bundles) {
+            let mut cmd = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".into()));
+            cmd.args(["check", "--all-targets", "--quiet", "--manifest-path"])
+                .arg(&manifest)
+                .args(combo.cargo_args());
+            if dry_run {
+                println!("{name} [{}]: {:?}", combo.label, cmd);
+                continue;
+            }
+            let ok = cmd.status().with_context(|| format!("running cargo for {name}"))?.success();
+            println!("{name} [{}]: {}", combo.label, if ok { "ok" } else { "FAILED" });
+            if !ok {
+                failed.push(format!("{name} [{}] {}", combo.label, combo.cargo_args().join(" ")));
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        eprintln!("\n{} combination(s) failed:", failed.len());
+        for f in &failed {
+            eprintln!("  {f}");
+        }
+    }
+    Ok(failed.is_empty())
+}
+
+fn run(args: Vec<String>) -> Result<bool> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["check-matrix", rest @ ..] => {
+            let (mut member, mut dry_run) = (None, false);
+            let mut it = rest.iter();
+            while let Some(a) = it.next() {
+                match *a {
+                    "--dry-run" => dry_run = true,
+                    "--member"  => member = Some(*it.next().context("--member needs a name")?),
+                    _ => bail!("{USAGE}"),
+                }
+            }
+            check_matrix(member, dry_run)
+        }
+        _ => bail!("{USAGE}"),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{e:#}");
+            ExitCode::from(2)
+        }
+    }
+}
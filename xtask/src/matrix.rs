@@ -0,0 +1,108 @@
+// src/matrix.rs
+//! Feature combinations to build for each member crate.
+
+use std::collections::{BTreeMap, BTreeSet};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// `xtask/matrix.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MatrixFile {
+    #[serde(default)]
+    pub members: BTreeMap<String, MemberSpec>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemberSpec {
+    /// Known-good feature sets, built on top of `--no-default-features`.
+    #[serde(default)]
+    pub bundles: Vec<Vec<String>>,
+    /// Reason the member is not built by the matrix.
+    #[serde(default)]
+    pub skip: Option<String>,
+}
+
+impl MatrixFile {
+    pub fn parse(text: &str) -> Result<Self> {
+        toml::from_str(text).context("parsing matrix.toml")
+    }
+}
+
+/// The `[features]` table of a member manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Features {
+    pub default:  Vec<String>,
+    /// Every feature except `default`.
+    pub optional: BTreeSet<String>,
+}
+
+impl Features {
+    pub fn from_manifest(text: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Manifest {
+            #[serde(default)]
+            features: BTreeMap<String, Vec<String>>,
+        }
+        let mut table = toml::from_str::<Manifest>(text).context("parsing Cargo.toml")?.features;
+        let default = table.remove("default").unwrap_or_default();
+        Ok(Features { default, optional: table.into_keys().collect() })
+    }
+}
+
+/// One `cargo check` of a member.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Combo {
+    pub label:      String,
+    pub no_default: bool,
+    pub features:   Vec<String>,
+}
+
+impl Combo {
+    pub fn cargo_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.no_default {
+            args.push("--no-default-features".into());
+        }
+        if !self.features.is_empty() {
+            args.push("--features".into());
+            args.push(self.features.join(","));
+        }
+        args
+    }
+}
+
+/// Default, no-default, each optional feature alone, then `bundles`.
+/// Duplicate combinations are dropped, keeping the first label.
+pub fn combos(features: &Features, bundles: &[Vec<String>]) -> Vec<Combo> {
+    let mut out = vec![Combo { label: "default".into(), no_default: false, features: Vec::new() }];
+    if !features.default.is_empty() {
+        out.push(Combo { label: "no-default".into(), no_default: true, features: Vec::new() });
+    }
+    for f in &features.optional {
+        out.push(Combo { label: f.clone(), no_default: true, features: vec![f.clone()] });
+    }
+    for b in bundles {
+        let mut set: Vec<String> = b.clone();
+        set.sort();
+        set.dedup();
+        out.push(Combo { label: format!("bundle[{}]", set.join("+")), no_default: true, features: set });
+    }
+
+    let mut seen = BTreeSet::new();
+    out.retain(|c| seen.insert((c.no_default, c.features.clone())));
+    out
+}
+
+/// Features named by `bundles` that the manifest does not declare.
+pub fn unknown_features(features: &Features, bundles: &[Vec<String>]) -> Vec<String> {
+    bundles
+        .iter()
+        .flatten()
+        .filter(|f| !features.optional.contains(*f))
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
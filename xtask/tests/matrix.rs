@@ -0,0 +1,63 @@
+// tests/matrix.rs
+
+use std::{fs, path::Path};
+use xtask::matrix::{combos, unknown_features, Features, MatrixFile};
+
+const MANIFEST: &str = r#"
+[package]
+name = "demo"
+
+[features]
+default = ["bundled"]
+bundled = []
+tracing = []
+cipher  = ["bundled"]
+"#;
+
+fn labels(features: &Features, bundles: &[Vec<String>]) -> Vec<String> {
+    combos(features, bundles).into_iter().map(|c| c.label).collect()
+}
+
+#[test]
+fn matrix_covers_default_no_default_and_each_feature() {
+    let f = Features::from_manifest(MANIFEST).unwrap();
+    assert_eq!(f.default, vec!["bundled"]);
+
+    let bundles = vec![
+        vec!["tracing".to_string(), "bundled".to_string()],
+        vec!["bundled".to_string()],           // same as the single feature
+    ];
+    assert_eq!(
+        labels(&f, &bundles),
+        ["default", "no-default", "bundled", "cipher", "tracing", "bundle[bundled+tracing]"]
+    );
+
+    let all = combos(&f, &bundles);
+    assert!(all[0].cargo_args().is_empty());
+    assert_eq!(all[1].cargo_args(), ["--no-default-features"]);
+    assert_eq!(all[5].cargo_args(), ["--no-default-features", "--features", "bundled,tracing"]);
+}
+
+#[test]
+fn crate_without_features_is_built_once() {
+    let f = Features::from_manifest("[package]\nname = \"x\"\n").unwrap();
+    assert_eq!(labels(&f, &[]), ["default"]);
+}
+
+#[test]
+fn bundles_must_name_declared_features() {
+    let f = Features::from_manifest(MANIFEST).unwrap();
+    assert_eq!(unknown_features(&f, &[vec!["yara".into(), "tracing".into()]]), ["yara"]);
+}
+
+#[test]
+fn repository_matrix_is_consistent() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let matrix = MatrixFile::parse(&fs::read_to_string(root.join("xtask/matrix.toml")).unwrap()).unwrap();
+    assert!(matrix.members.contains_key("user-agent"));
+    for (name, spec) in &matrix.members {
+        let manifest = fs::read_to_string(root.join(name).join("Cargo.toml")).unwrap();
+        let f = Features::from_manifest(&manifest).unwrap();
+        assert!(unknown_features(&f, &spec.bundles).is_empty(), "{name}");
+    }
+}